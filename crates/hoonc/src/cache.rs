@@ -0,0 +1,136 @@
+//! Content-addressed cache of build outputs
+//!
+//! The hoonc kernel keeps its own caches of parsed and compiled files, keyed by the hash of each
+//! file and its dependencies, but only in its checkpoint, and booting it to find out nothing
+//! changed is most of the time a rebuild takes. This cache sits in front of it: a build is keyed
+//! by the compiler, the entry file and every file in the dependency directory, and a build whose
+//! key was seen before writes the jam it produced then without booting the kernel at all.
+//!
+//! Every dependency file is in the key because the kernel hashes the whole directory into the
+//! kernels it builds.
+
+use std::path::PathBuf;
+
+use blake3::{Hash, Hasher};
+use tokio::fs;
+use tracing::debug;
+
+use crate::{Error, Sources, HOON_TXT, KERNEL_JAM};
+
+/// A directory of output jams, named by their build keys
+#[derive(Debug, Clone)]
+pub struct BuildCache {
+    dir: PathBuf,
+}
+
+impl BuildCache {
+    pub fn new(dir: PathBuf) -> Self {
+        BuildCache { dir }
+    }
+
+    fn path(&self, key: &Hash) -> PathBuf {
+        self.dir.join(format!("{}.jam", key.to_hex()))
+    }
+
+    /// The output of a build with `key`, if there was one
+    pub async fn get(&self, key: &Hash) -> Option<Vec<u8>> {
+        let jam = fs::read(self.path(key)).await.ok()?;
+        debug!("Build cache hit: {}", key);
+        Some(jam)
+    }
+
+    /// Keeps the output of a build with `key`
+    pub async fn put(&self, key: &Hash, jam: &[u8]) -> Result<(), Error> {
+        fs::create_dir_all(&self.dir).await?;
+        // Written aside and renamed, so a build that's interrupted leaves no partial entry
+        let path = self.path(key);
+        let partial = path.with_extension("partial");
+        fs::write(&partial, jam).await?;
+        fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+}
+
+/// The key of building `sources`, with or without file hash injection
+pub fn build_key(sources: &Sources, arbitrary: bool) -> Hash {
+    let mut hasher = Hasher::new();
+    hasher.update(blake3::hash(KERNEL_JAM).as_bytes());
+    hasher.update(blake3::hash(HOON_TXT).as_bytes());
+    hasher.update(&[arbitrary as u8]);
+    update_field(&mut hasher, sources.entry_path.as_bytes());
+    update_field(&mut hasher, &sources.entry);
+    let mut files: Vec<&(String, Vec<u8>)> = sources.files.iter().collect();
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    for (path, contents) in files {
+        update_field(&mut hasher, path.as_bytes());
+        update_field(&mut hasher, contents);
+    }
+    hasher.finalize()
+}
+
+/// Length-prefixed, so that moving bytes between fields changes the key
+fn update_field(hasher: &mut Hasher, field: &[u8]) {
+    hasher.update(&(field.len() as u64).to_le_bytes());
+    hasher.update(field);
+}
+
+/// The default cache, in the hoonc data directory
+pub async fn default_cache() -> BuildCache {
+    BuildCache::new(crate::hoonc_data_dir().await.join("cache"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sources(files: &[(&str, &str)]) -> Sources {
+        Sources {
+            entry_path: "/app/main.hoon".into(),
+            entry: b"%main".to_vec(),
+            files: files
+                .iter()
+                .map(|(path, contents)| (path.to_string(), contents.as_bytes().to_vec()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_build_key() {
+        let key = build_key(
+            &sources(&[("/lib/a.hoon", "a"), ("/lib/b.hoon", "b")]),
+            false,
+        );
+        // The order files are read in doesn't matter
+        assert_eq!(
+            key,
+            build_key(
+                &sources(&[("/lib/b.hoon", "b"), ("/lib/a.hoon", "a")]),
+                false
+            )
+        );
+        assert_ne!(
+            key,
+            build_key(
+                &sources(&[("/lib/a.hoon", "a"), ("/lib/b.hoon", "c")]),
+                false
+            )
+        );
+        assert_ne!(
+            key,
+            build_key(
+                &sources(&[("/lib/a.hoon", "a"), ("/lib/b.hoon", "b")]),
+                true
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_build_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = BuildCache::new(dir.path().join("cache"));
+        let key = build_key(&sources(&[]), false);
+        assert_eq!(cache.get(&key).await, None);
+        cache.put(&key, b"jam").await.unwrap();
+        assert_eq!(cache.get(&key).await.as_deref(), Some(&b"jam"[..]));
+    }
+}
@@ -0,0 +1,320 @@
+//! Compiler errors with source spans
+//!
+//! The kernel reports a failed build by slogging its trace: a `/lib/foo.hoon:<[12 5].[12 9]>`
+//! spot for every hint it was compiling under, with messages like `-find.foo` among them, or a
+//! `syntax error at [12 5] in /lib/foo.hoon` with the line and a caret. [`SlogLayer`] keeps the
+//! slogs of a build and leaves the spots out of the log, and [`report`] turns them into one
+//! error per failed file, at the innermost spot, printed with the source excerpt or as JSON.
+
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde_json::json;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::Sources;
+
+static SLOGS: Mutex<Slogs> = Mutex::new(Slogs {
+    lines: Vec::new(),
+    excerpt: 0,
+});
+
+struct Slogs {
+    lines: Vec<String>,
+    /// Lines of a syntax error's excerpt still to come
+    excerpt: usize,
+}
+
+/// Keeps the kernel's slogs for [`take`], logging all but the spots of traces
+pub struct SlogLayer;
+
+impl<S: Subscriber> Layer<S> for SlogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        let line = message.0;
+        let mut slogs = SLOGS.lock().unwrap_or_else(|e| e.into_inner());
+        let hidden = if slogs.excerpt > 0 {
+            slogs.excerpt -= 1;
+            true
+        } else if syntax_error(&line).is_some() {
+            slogs.excerpt = 2;
+            false
+        } else {
+            spot(&line).is_some()
+        };
+        if !hidden {
+            eprintln!("{}", line);
+        }
+        slogs.lines.push(line);
+    }
+}
+
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        }
+    }
+}
+
+/// Drains the slogs kept since the last call
+pub fn take() -> Vec<String> {
+    std::mem::take(&mut SLOGS.lock().unwrap_or_else(|e| e.into_inner()).lines)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    /// The file's path as the kernel names it
+    pub file: String,
+    pub span: Span,
+}
+
+/// `syntax error at [line column] in path`
+fn syntax_error(line: &str) -> Option<(String, Span)> {
+    let rest = line.strip_prefix("syntax error at [")?;
+    let (position, file) = rest.split_once("] in ")?;
+    let (line, column) = position.split_once(' ')?;
+    let (line, column) = (line.parse().ok()?, column.parse().ok()?);
+    let span = Span {
+        line,
+        column,
+        end_line: line,
+        end_column: column,
+    };
+    Some((file.trim().to_string(), span))
+}
+
+/// `path:<[line column].[line column]>`
+fn spot(line: &str) -> Option<(String, Span)> {
+    let (file, rest) = line.trim().split_once(":<[")?;
+    let (start, end) = rest.strip_suffix("]>")?.split_once("].[")?;
+    let pair = |s: &str| -> Option<(usize, usize)> {
+        let (a, b) = s.split_once(' ')?;
+        Some((a.parse().ok()?, b.parse().ok()?))
+    };
+    let ((line, column), (end_line, end_column)) = (pair(start)?, pair(end)?);
+    let span = Span {
+        line,
+        column,
+        end_line,
+        end_column,
+    };
+    Some((file.to_string(), span))
+}
+
+/// The errors in the slogs of a failed build
+pub fn from_slogs(lines: &[String]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut spots: Vec<(String, Span)> = Vec::new();
+    let mut messages: Vec<&str> = Vec::new();
+    for line in lines {
+        if let Some((file, span)) = syntax_error(line) {
+            diagnostics.push(Diagnostic {
+                message: "syntax error".into(),
+                file,
+                span,
+            });
+        } else if let Some(spot) = spot(line) {
+            spots.push(spot);
+        } else if line.contains("build-cache-miss") {
+            // A new file: nothing before it is part of its trace
+            spots.clear();
+            messages.clear();
+        } else if line.trim_matches('"').starts_with("compile-target: failed") {
+            // Spans nest, so the narrowest is where the error is
+            let innermost = spots.iter().min_by_key(|(_, span)| {
+                (
+                    span.end_line.saturating_sub(span.line),
+                    span.end_column.abs_diff(span.column),
+                )
+            });
+            if let Some((file, span)) = innermost {
+                diagnostics.push(Diagnostic {
+                    message: if messages.is_empty() {
+                        "build failed".into()
+                    } else {
+                        messages.join(" ")
+                    },
+                    file: file.clone(),
+                    span: *span,
+                });
+            }
+            spots.clear();
+            messages.clear();
+        } else if !line.starts_with(['[', '"']) && !line.is_empty() {
+            // Progress like `[%grabbing-dep /lib/foo.hoon]` or `"node ... is eval"` isn't part of
+            // the trace
+            messages.push(line);
+        }
+    }
+    diagnostics
+}
+
+/// The contents of the file the kernel calls `file`, and where it is on disk: in the first of
+/// `roots` that has it, like in the build
+fn source<'a>(file: &str, sources: &'a Sources, roots: &[PathBuf]) -> Option<(String, &'a [u8])> {
+    if file == sources.entry_path {
+        return Some((file.to_string(), &sources.entry));
+    }
+    let (path, contents) = sources.files.iter().find(|(path, _)| path == file)?;
+    let in_root = |root: &PathBuf| root.join(path.trim_start_matches('/'));
+    let on_disk = roots.iter().map(in_root).find(|path| path.exists());
+    let on_disk = on_disk.map_or_else(|| path.clone(), |path| path.display().to_string());
+    Some((on_disk, contents))
+}
+
+/// `diagnostic` with its source excerpt and carets under the span, like rustc's errors
+pub fn render(diagnostic: &Diagnostic, sources: &Sources, roots: &[PathBuf]) -> String {
+    let Span { line, column, .. } = diagnostic.span;
+    let mut out = format!("error: {}\n", diagnostic.message);
+    let Some((path, contents)) = source(&diagnostic.file, sources, roots) else {
+        let _ = writeln!(out, "  --> {}:{}:{}", diagnostic.file, line, column);
+        return out;
+    };
+    let _ = writeln!(out, "  --> {}:{}:{}", path, line, column);
+    let text = String::from_utf8_lossy(contents);
+    let Some(excerpt) = text.lines().nth(line.saturating_sub(1)) else {
+        return out;
+    };
+    let width = if diagnostic.span.end_line == line {
+        diagnostic.span.end_column.saturating_sub(column).max(1)
+    } else {
+        excerpt
+            .chars()
+            .count()
+            .saturating_sub(column.saturating_sub(1))
+            .max(1)
+    };
+    let gutter = " ".repeat(line.to_string().len());
+    let _ = writeln!(out, "{} |", gutter);
+    let _ = writeln!(out, "{} | {}", line, excerpt);
+    let _ = writeln!(
+        out,
+        "{} | {}{}",
+        gutter,
+        " ".repeat(column.saturating_sub(1)),
+        "^".repeat(width)
+    );
+    out
+}
+
+/// `diagnostic` as one line of JSON
+pub fn to_json(diagnostic: &Diagnostic, sources: &Sources, roots: &[PathBuf]) -> String {
+    let path = source(&diagnostic.file, sources, roots)
+        .map(|(path, _)| path)
+        .unwrap_or_else(|| diagnostic.file.clone());
+    let Span {
+        line,
+        column,
+        end_line,
+        end_column,
+    } = diagnostic.span;
+    json!({
+        "severity": "error",
+        "message": diagnostic.message,
+        "file": path,
+        "line": line,
+        "column": column,
+        "end_line": end_line,
+        "end_column": end_column,
+    })
+    .to_string()
+}
+
+/// Prints the errors of a failed build, to stderr or as JSON lines on stdout
+pub fn report(lines: &[String], sources: &Sources, roots: &[PathBuf], json: bool) {
+    for diagnostic in from_slogs(lines) {
+        if json {
+            println!("{}", to_json(&diagnostic, sources, roots));
+        } else {
+            eprintln!("{}", render(&diagnostic, sources, roots));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_from_slogs() {
+        let slogs = lines(&[
+            "[%build-cache-miss /lib/ok.hoon]", "[%build-cache-miss /lib/foo.hoon]",
+            "/lib/foo.hoon:<[1 1].[4 3]>", "-find.bar", "/lib/foo.hoon:<[2 3].[2 8]>",
+            "[%grabbing-dep /lib/ok.hoon]", "\"compile-target: failed: /lib/foo.hoon\"",
+            "syntax error at [3 5] in /lib/baz.hoon", "  =/  x", "----^",
+        ]);
+        assert_eq!(
+            from_slogs(&slogs),
+            vec![
+                Diagnostic {
+                    message: "-find.bar".into(),
+                    file: "/lib/foo.hoon".into(),
+                    span: Span {
+                        line: 2,
+                        column: 3,
+                        end_line: 2,
+                        end_column: 8
+                    },
+                },
+                Diagnostic {
+                    message: "syntax error".into(),
+                    file: "/lib/baz.hoon".into(),
+                    span: Span {
+                        line: 3,
+                        column: 5,
+                        end_line: 3,
+                        end_column: 5
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let sources = Sources {
+            entry_path: "/app/main.hoon".into(),
+            entry: b"|=  a=@\n  (bar a)\n".to_vec(),
+            files: vec![],
+        };
+        let diagnostic = Diagnostic {
+            message: "-find.bar".into(),
+            file: "/app/main.hoon".into(),
+            span: Span {
+                line: 2,
+                column: 3,
+                end_line: 2,
+                end_column: 10,
+            },
+        };
+        assert_eq!(
+            render(&diagnostic, &sources, &[PathBuf::from("/hoon")]),
+            "error: -find.bar\n  --> /app/main.hoon:2:3\n  |\n2 |   (bar a)\n  |   ^^^^^^^\n"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&to_json(&diagnostic, &sources, &[PathBuf::from("/hoon")]))
+                .unwrap();
+        assert_eq!(json["line"], 2);
+        assert_eq!(json["file"], "/app/main.hoon");
+    }
+}
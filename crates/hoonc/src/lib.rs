@@ -12,18 +12,34 @@ use nockapp::{system_data_dir, AtomExt, Noun, NounExt};
 use nockvm::interpreter::{self, Context};
 use nockvm::noun::{Atom, D, T};
 use nockvm_macros::tas;
-use tokio::fs::{self, File};
-use tokio::io::AsyncReadExt;
-use tracing::{debug, info, instrument, trace};
+use tokio::fs;
+use tracing::{debug, error, info, instrument, trace};
 use walkdir::{DirEntry, WalkDir};
 
+pub mod cache;
+pub mod diagnostics;
+pub mod manifest;
+pub mod provenance;
+
 pub const OUT_JAM_NAME: &str = "out.jam";
 
+/// How often `--watch` checks the sources for changes
+pub const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
 pub type Error = Box<dyn std::error::Error>;
 
 static KERNEL_JAM: &[u8] = include_bytes!("../bootstrap/hoonc.jam");
 static HOON_TXT: &[u8] = include_bytes!("../hoon/hoon-138.hoon");
 
+/// The files a build reads: the entry, and every source file in the library roots by its path in
+/// its root
+#[derive(Debug, Clone)]
+pub struct Sources {
+    pub entry_path: String,
+    pub entry: Vec<u8>,
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
 #[derive(Parser, Debug)]
 #[command(about = "Tests various poke types for the kernel", author = "zorp", version, color = ColorChoice::Auto)]
 pub struct ChooCli {
@@ -46,8 +62,50 @@ pub struct ChooCli {
     )]
     pub arbitrary: bool,
 
+    #[arg(
+        long = "lib",
+        value_name = "DIR",
+        help = "Another root to look for library files in, after the dependency directory; can be given more than once"
+    )]
+    pub libs: Vec<std::path::PathBuf>,
+
+    #[arg(
+        long,
+        help = "Manifest of library dependencies, hoonc.toml in the current directory if it has one"
+    )]
+    pub manifest: Option<std::path::PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "RECORD",
+        help = "Rebuild from the sources and check the result, and the jam at --output if given, against a build record (<jam>.json)",
+        conflicts_with = "watch"
+    )]
+    pub verify: Option<std::path::PathBuf>,
+
     #[arg(long, help = "Output file path", default_value = None)]
     pub output: Option<std::path::PathBuf>,
+
+    #[arg(
+        long,
+        help = "Always run the compiler, without reading or writing the build cache",
+        default_value = "false"
+    )]
+    pub no_cache: bool,
+
+    #[arg(
+        long,
+        help = "Rebuild the output whenever the entry or a file in the dependency directory changes",
+        default_value = "false"
+    )]
+    pub watch: bool,
+
+    #[arg(
+        long,
+        help = "Print the errors of a failed build as JSON, one object per line on stdout",
+        default_value = "false"
+    )]
+    pub json_diagnostics: bool,
 }
 
 pub async fn hoonc_data_dir() -> PathBuf {
@@ -201,6 +259,210 @@ pub async fn initialize_hoonc_(
 ) -> Result<(nockapp::NockApp, PathBuf), Error> {
     debug!("Dependencies directory: {:?}", deps_dir);
     debug!("Entry file: {:?}", entry);
+    let sources = read_sources(&entry, &deps_dir).await?;
+    initialize_with_sources(&sources, arbitrary, out, boot_cli).await
+}
+
+/// Builds the entry of the command line with the build cache, unless `--no-cache`, and returns
+/// the path it was written to
+pub async fn build(cli: &ChooCli) -> Result<PathBuf, Error> {
+    let roots = library_roots(cli)?;
+    let sources = read_roots(&cli.entry, &roots).await?;
+    build_sources(cli, &sources, &roots).await
+}
+
+async fn build_sources(
+    cli: &ChooCli,
+    sources: &Sources,
+    roots: &[PathBuf],
+) -> Result<PathBuf, Error> {
+    let cache = cache::default_cache().await;
+    let key = cache::build_key(sources, cli.arbitrary);
+    if !cli.no_cache {
+        if let Some(jam) = cache.get(&key).await {
+            let out_path = PathBuf::from(out_path_string(&cli.output));
+            fs::write(&out_path, &jam).await?;
+            write_provenance(sources, cli.arbitrary, &jam, &out_path).await?;
+            println!(
+                "choo: nothing changed, output written from the build cache to {}",
+                out_path.display()
+            );
+            return Ok(out_path);
+        }
+    }
+    // Only this build's slogs are its errors
+    diagnostics::take();
+    let (nockapp, out_path) =
+        initialize_with_sources(sources, cli.arbitrary, cli.output.clone(), cli.boot.clone())
+            .await?;
+    let jam = match run_build(nockapp, Some(out_path.clone())).await {
+        Ok(jam) => jam,
+        Err(e) => {
+            let slogs = diagnostics::take();
+            diagnostics::report(&slogs, sources, roots, cli.json_diagnostics);
+            return Err(e);
+        }
+    };
+    if !cli.no_cache {
+        cache.put(&key, &jam).await?;
+    }
+    write_provenance(sources, cli.arbitrary, &jam, &out_path).await?;
+    Ok(out_path)
+}
+
+async fn write_provenance(
+    sources: &Sources,
+    arbitrary: bool,
+    jam: &[u8],
+    out_path: &Path,
+) -> Result<(), Error> {
+    let record = provenance::Provenance::new(sources, arbitrary, jam);
+    record
+        .write(&provenance::Provenance::sidecar_path(out_path))
+        .await
+}
+
+/// Rebuilds the entry of the command line without the build cache and checks that it, and the
+/// jam at `--output` if given, match the build record at `record_path`
+pub async fn verify(cli: &ChooCli, record_path: &Path) -> Result<(), Error> {
+    let record = provenance::Provenance::read(record_path).await?;
+    let roots = library_roots(cli)?;
+    let sources = read_roots(&cli.entry, &roots).await?;
+    let local = provenance::Provenance::new(&sources, record.arbitrary, &[]);
+    let differences = record.differences(&local);
+    if !differences.is_empty() {
+        for difference in &differences {
+            println!("verify: {}", difference);
+        }
+        return Err("the sources or compiler differ from the build record".into());
+    }
+    if let Some(jam_path) = &cli.output {
+        let jam = fs::read(jam_path).await?;
+        if blake3::hash(&jam).to_hex().as_str() != record.jam {
+            return Err(format!("{} isn't the jam in the build record", jam_path.display()).into());
+        }
+    }
+    let dir = tempfile::tempdir()?;
+    let out = Some(dir.path().join(OUT_JAM_NAME));
+    let (nockapp, out_path) =
+        initialize_with_sources(&sources, record.arbitrary, out, cli.boot.clone()).await?;
+    let jam = run_build(nockapp, Some(out_path)).await?;
+    let hash = blake3::hash(&jam).to_hex();
+    if hash.as_str() != record.jam {
+        return Err(format!(
+            "rebuilt jam {} doesn't match the build record's {}",
+            hash, record.jam
+        )
+        .into());
+    }
+    println!("verify: rebuilt {} from matching sources", record.jam);
+    Ok(())
+}
+
+/// Builds the entry of the command line, then again whenever the entry or a file in the
+/// dependency directory changes. A failed build is logged, and the next change tries again.
+pub async fn watch(mut cli: ChooCli) -> Result<(), Error> {
+    info!(
+        "Watching {:?} and {:?} for changes",
+        cli.entry, cli.directory
+    );
+    let mut built = None;
+    let mut ticks = tokio::time::interval(WATCH_INTERVAL);
+    loop {
+        ticks.tick().await;
+        let read = async {
+            let roots = library_roots(&cli)?;
+            let sources = read_roots(&cli.entry, &roots).await?;
+            Ok::<_, Error>((sources, roots))
+        };
+        let (sources, roots) = match read.await {
+            Ok(read) => read,
+            Err(e) => {
+                // Most likely a file in the middle of being saved
+                debug!("Failed to read sources: {}", e);
+                continue;
+            }
+        };
+        let key = cache::build_key(&sources, cli.arbitrary);
+        if built == Some(key) {
+            continue;
+        }
+        built = Some(key);
+        match build_sources(&cli, &sources, &roots).await {
+            Ok(out_path) => println!("choo: built {}", out_path.display()),
+            Err(e) => error!("Build failed: {}", e),
+        }
+        // Only the first build starts from a new data directory
+        cli.boot.new = false;
+    }
+}
+
+/// The roots the command line builds from: the dependency directory, then each `--lib`, then
+/// the dependencies in the manifest
+pub fn library_roots(cli: &ChooCli) -> Result<Vec<PathBuf>, Error> {
+    let mut roots = vec![cli.directory.clone()];
+    roots.extend(cli.libs.iter().cloned());
+    let manifest = match &cli.manifest {
+        Some(path) => Some(path.clone()),
+        None => Some(PathBuf::from(manifest::MANIFEST_NAME)).filter(|path| path.exists()),
+    };
+    if let Some(path) = manifest {
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        roots.extend(manifest::Manifest::read(&path)?.library_roots(dir)?);
+    }
+    Ok(roots)
+}
+
+/// Reads the entry and the source files in `deps_dir`
+pub async fn read_sources(entry: &Path, deps_dir: &Path) -> Result<Sources, Error> {
+    read_roots(entry, &[deps_dir.to_path_buf()]).await
+}
+
+/// Reads the entry and the source files in `roots`. A file in more than one root is read from
+/// the first.
+pub async fn read_roots(entry: &Path, roots: &[PathBuf]) -> Result<Sources, Error> {
+    let contents = fs::read(entry).await?;
+    let entry_path = canonicalize_and_string(entry);
+    let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    for root in roots {
+        let directory = canonicalize_and_string(root);
+        let walker = WalkDir::new(&directory).follow_links(true).into_iter();
+        for entry_result in walker.filter_entry(is_valid_file_or_dir) {
+            let entry = entry_result?;
+            if entry.metadata()?.is_file() {
+                let path_str = entry
+                    .path()
+                    .to_str()
+                    .expect("Failed to convert path to string")
+                    .strip_prefix(&directory)
+                    .expect("Failed to strip prefix");
+                if !seen.insert(path_str.to_string()) {
+                    debug!(
+                        "{:?} in {:?} is shadowed by an earlier root",
+                        path_str, root
+                    );
+                    continue;
+                }
+                debug!("Path: {:?}", path_str);
+                let contents = fs::read(entry.path()).await?;
+                files.push((path_str.to_string(), contents));
+            }
+        }
+    }
+    Ok(Sources {
+        entry_path,
+        entry: contents,
+        files,
+    })
+}
+
+async fn initialize_with_sources(
+    sources: &Sources,
+    arbitrary: bool,
+    out: Option<std::path::PathBuf>,
+    boot_cli: BootCli,
+) -> Result<(nockapp::NockApp, PathBuf), Error> {
     let data_dir = system_data_dir();
     let mut nockapp = boot::setup(
         KERNEL_JAM,
@@ -231,63 +493,18 @@ pub async fn initialize_hoonc_(
     // We do a raw poke here to ensure boot is done before we start the build poke.
     let _boot_result = nockapp.poke(OnePunchWire::Poke.to_wire(), slab).await?;
     let mut slab = NounSlab::new();
-    let entry_contents = {
-        let mut contents_vec: Vec<u8> = vec![];
-        let mut file = File::open(&entry).await?;
-        file.read_to_end(&mut contents_vec).await?;
-        Atom::from_value(&mut slab, contents_vec)?.as_noun()
-    };
-
-    let entry_string = canonicalize_and_string(&entry);
-    let entry_path = Atom::from_value(&mut slab, entry_string)?.as_noun();
+    let entry_contents = Atom::from_value(&mut slab, &sources.entry[..])?.as_noun();
+    let entry_path = Atom::from_value(&mut slab, sources.entry_path.as_str())?.as_noun();
 
     let mut directory_noun = D(0);
-    let directory = canonicalize_and_string(&deps_dir);
-
-    let walker = WalkDir::new(&directory).follow_links(true).into_iter();
-    for entry_result in walker.filter_entry(is_valid_file_or_dir) {
-        let entry = entry_result?;
-        let is_file = entry.metadata()?.is_file();
-        if is_file {
-            let path_str = entry
-                .path()
-                .to_str()
-                .expect("Failed to convert path to string")
-                .strip_prefix(&directory)
-                .expect("Failed to strip prefix");
-            debug!("Path: {:?}", path_str);
-            let path_cord = Atom::from_value(&mut slab, path_str)?.as_noun();
-
-            let contents = {
-                let mut contents_vec: Vec<u8> = vec![];
-                let mut file = File::open(entry.path()).await?;
-                file.read_to_end(&mut contents_vec).await?;
-                Atom::from_value(&mut slab, contents_vec)?.as_noun()
-            };
-
-            let entry_cell = T(&mut slab, &[path_cord, contents]);
-            directory_noun = T(&mut slab, &[entry_cell, directory_noun]);
-        }
+    for (path_str, contents) in &sources.files {
+        let path_cord = Atom::from_value(&mut slab, path_str.as_str())?.as_noun();
+        let contents = Atom::from_value(&mut slab, &contents[..])?.as_noun();
+        let entry_cell = T(&mut slab, &[path_cord, contents]);
+        directory_noun = T(&mut slab, &[entry_cell, directory_noun]);
     }
 
-    let out_path_string = if let Some(path) = &out {
-        let parent = if path.is_dir() {
-            path
-        } else {
-            path.parent().unwrap_or_else(|| Path::new("."))
-        };
-        let filename = if path.is_dir() {
-            OsStr::new(OUT_JAM_NAME)
-        } else {
-            path.file_name().unwrap_or_else(|| OsStr::new(OUT_JAM_NAME))
-        };
-        info!("Filename: {:?}", filename);
-        let parent_canonical = canonicalize_and_string(parent);
-        format!("{}/{}", parent_canonical, filename.to_string_lossy())
-    } else {
-        let parent_dir = current_dir().expect("Failed to get current directory");
-        format!("{}/{}", canonicalize_and_string(&parent_dir), OUT_JAM_NAME)
-    };
+    let out_path_string = out_path_string(&out);
     debug!("Output path: {:?}", out_path_string);
     let out_path = Atom::from_value(&mut slab, out_path_string.clone())?.as_noun();
 
@@ -313,18 +530,34 @@ pub async fn initialize_hoonc_(
     Ok((nockapp, out_path_string.into()))
 }
 
+/// Where a build with `--output` `out` writes its jam
+fn out_path_string(out: &Option<std::path::PathBuf>) -> String {
+    if let Some(path) = out {
+        let parent = if path.is_dir() {
+            path
+        } else {
+            path.parent().unwrap_or_else(|| Path::new("."))
+        };
+        let filename = if path.is_dir() {
+            OsStr::new(OUT_JAM_NAME)
+        } else {
+            path.file_name().unwrap_or_else(|| OsStr::new(OUT_JAM_NAME))
+        };
+        info!("Filename: {:?}", filename);
+        let parent_canonical = canonicalize_and_string(parent);
+        format!("{}/{}", parent_canonical, filename.to_string_lossy())
+    } else {
+        let parent_dir = current_dir().expect("Failed to get current directory");
+        format!("{}/{}", canonicalize_and_string(&parent_dir), OUT_JAM_NAME)
+    }
+}
+
 pub fn is_valid_file_or_dir(entry: &DirEntry) -> bool {
-    let is_dir = entry
-        .metadata()
-        .unwrap_or_else(|_| {
-            panic!(
-                "Panicked at {}:{} (git sha: {:?})",
-                file!(),
-                line!(),
-                option_env!("GIT_SHA")
-            )
-        })
-        .is_dir();
+    // A file removed since the directory was listed, like an editor's swap file, is skipped
+    let Ok(metadata) = entry.metadata() else {
+        return false;
+    };
+    let is_dir = metadata.is_dir();
 
     let is_valid = entry
         .file_name()
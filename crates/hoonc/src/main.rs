@@ -8,14 +8,23 @@ use nockvm::mem::{AllocationError, NewStackError};
 async fn main() -> Result<(), Error> {
     let cli = ChooCli::parse();
 
-    boot::init_default_tracing(&cli.boot.clone());
+    boot::init_tracing_with_slogs(&cli.boot.clone(), diagnostics::SlogLayer);
     // use tracing_subscriber::layer::SubscriberExt;
     // tracing::subscriber::set_global_default(
     //     tracing_subscriber::registry().with(tracing_tracy::TracyLayer::default()),
     // );
     let result = std::panic::AssertUnwindSafe(async {
-        let (mut nockapp, _) = initialize_hoonc(cli).await?;
-        nockapp.run().await?;
+        if let Some(record) = &cli.verify {
+            // Operators script verification, so a mismatch is a failing exit
+            if let Err(e) = verify(&cli, record).await {
+                println!("verify: {}", e);
+                std::process::exit(1);
+            }
+        } else if cli.watch {
+            watch(cli).await?;
+        } else {
+            build(&cli).await?;
+        }
         Ok::<(), Error>(())
     })
     .catch_unwind()
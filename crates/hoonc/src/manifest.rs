@@ -0,0 +1,154 @@
+//! Library dependencies in `hoonc.toml`
+//!
+//! A manifest names the Hoon libraries a kernel shares with others, instead of vendoring copies
+//! of them into its own dependency directory:
+//!
+//! ```toml
+//! [package]
+//! name = "wallet"
+//! version = "0.2.0"
+//!
+//! [dependencies]
+//! common = { path = "../common", version = "1.0.0" }
+//! ```
+//!
+//! A dependency's `path` is a directory with a manifest of its own, whose `[package]` gives its
+//! version and, as `root`, the directory its files are in (`hoon` unless said otherwise). A pinned
+//! `version` has to be the one the dependency's manifest gives. Dependencies of dependencies
+//! aren't followed: a kernel names every library it uses.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+/// The manifest hoonc reads from the current directory
+pub const MANIFEST_NAME: &str = "hoonc.toml";
+
+#[derive(Debug, Error)]
+pub enum ManifestError {
+    #[error("Failed to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Invalid manifest {0}: {1}")]
+    Toml(PathBuf, toml::de::Error),
+    #[error("Dependency {name} is pinned to {pinned}, but {path} is {found}")]
+    Version {
+        name: String,
+        pinned: String,
+        path: PathBuf,
+        found: String,
+    },
+    #[error("Dependency {name} is pinned to {pinned}, but {path} gives no version")]
+    NoVersion {
+        name: String,
+        pinned: String,
+        path: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub package: Option<Package>,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Package {
+    pub name: String,
+    pub version: Option<String>,
+    /// The directory of the package's files, relative to its manifest
+    #[serde(default = "default_root")]
+    pub root: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Dependency {
+    /// The directory of the dependency's manifest, relative to this one
+    pub path: PathBuf,
+    pub version: Option<String>,
+}
+
+fn default_root() -> PathBuf {
+    PathBuf::from("hoon")
+}
+
+impl Manifest {
+    pub fn read(path: &Path) -> Result<Self, ManifestError> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| ManifestError::Io(path.into(), e))?;
+        toml::from_str(&contents).map_err(|e| ManifestError::Toml(path.into(), e))
+    }
+
+    /// The roots of the dependencies of the manifest in `dir`, in the order of their names
+    pub fn library_roots(&self, dir: &Path) -> Result<Vec<PathBuf>, ManifestError> {
+        let mut roots = Vec::new();
+        for (name, dependency) in &self.dependencies {
+            let dep_dir = dir.join(&dependency.path);
+            let path = dep_dir.join(MANIFEST_NAME);
+            let package = Manifest::read(&path)?.package;
+            if let Some(pinned) = &dependency.version {
+                let found = package.as_ref().and_then(|package| package.version.clone());
+                match found {
+                    Some(found) if &found == pinned => {}
+                    Some(found) => {
+                        return Err(ManifestError::Version {
+                            name: name.clone(),
+                            pinned: pinned.clone(),
+                            path,
+                            found,
+                        })
+                    }
+                    None => {
+                        return Err(ManifestError::NoVersion {
+                            name: name.clone(),
+                            pinned: pinned.clone(),
+                            path,
+                        })
+                    }
+                }
+            }
+            let root = package.map_or_else(default_root, |package| package.root);
+            roots.push(dep_dir.join(root));
+        }
+        Ok(roots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_library_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let common = dir.path().join("common");
+        std::fs::create_dir_all(&common).unwrap();
+        std::fs::write(
+            common.join(MANIFEST_NAME),
+            "[package]\nname = \"common\"\nversion = \"1.0.0\"\nroot = \"src\"\n",
+        )
+        .unwrap();
+
+        let manifest: Manifest = toml::from_str(
+            "[package]\nname = \"app\"\n\n[dependencies]\ncommon = { path = \"common\", version = \"1.0.0\" }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            manifest.library_roots(dir.path()).unwrap(),
+            vec![common.join("src")]
+        );
+
+        let manifest: Manifest =
+            toml::from_str("[dependencies]\ncommon = { path = \"common\", version = \"2.0.0\" }\n")
+                .unwrap();
+        assert!(matches!(
+            manifest.library_roots(dir.path()),
+            Err(ManifestError::Version { .. })
+        ));
+    }
+}
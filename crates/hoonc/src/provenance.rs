@@ -0,0 +1,183 @@
+//! Build records for reproducing a kernel jam
+//!
+//! Next to every jam it writes, hoonc writes `<jam>.json`: the hash of the jam, the compiler it
+//! was built with, and the hash of the entry and of every file in the library roots. Anyone with
+//! the same sources and compiler can rebuild the jam and check it against the record with
+//! `--verify`. The entry's path is in the record too, since the kernel compiles source paths into
+//! the jam: a rebuild from another path gives a different jam.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Sources, HOON_TXT, KERNEL_JAM};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// BLAKE3 of the jam
+    pub jam: String,
+    pub compiler: Compiler,
+    pub arbitrary: bool,
+    pub entry: Input,
+    /// The files in the library roots, by path
+    pub inputs: Vec<Input>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Compiler {
+    pub version: String,
+    pub git_sha: Option<String>,
+    /// BLAKE3 of the compiler kernel
+    pub kernel: String,
+    /// BLAKE3 of the hoon.hoon it compiles with
+    pub hoon: String,
+}
+
+impl Compiler {
+    pub fn current() -> Self {
+        Compiler {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_sha: option_env!("GIT_SHA").map(str::to_string),
+            kernel: blake3::hash(KERNEL_JAM).to_hex().to_string(),
+            hoon: blake3::hash(HOON_TXT).to_hex().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Input {
+    pub path: String,
+    pub blake3: String,
+}
+
+impl Input {
+    fn new(path: &str, contents: &[u8]) -> Self {
+        Input {
+            path: path.to_string(),
+            blake3: blake3::hash(contents).to_hex().to_string(),
+        }
+    }
+}
+
+impl Provenance {
+    pub fn new(sources: &Sources, arbitrary: bool, jam: &[u8]) -> Self {
+        let mut inputs: Vec<Input> = sources
+            .files
+            .iter()
+            .map(|(path, contents)| Input::new(path, contents))
+            .collect();
+        inputs.sort_by(|a, b| a.path.cmp(&b.path));
+        Provenance {
+            jam: blake3::hash(jam).to_hex().to_string(),
+            compiler: Compiler::current(),
+            arbitrary,
+            entry: Input::new(&sources.entry_path, &sources.entry),
+            inputs,
+        }
+    }
+
+    /// The record of the jam at `out`
+    pub fn sidecar_path(out: &Path) -> PathBuf {
+        let mut path = out.as_os_str().to_owned();
+        path.push(".json");
+        PathBuf::from(path)
+    }
+
+    pub async fn read(path: &Path) -> Result<Self, Error> {
+        Ok(serde_json::from_slice(&tokio::fs::read(path).await?)?)
+    }
+
+    pub async fn write(&self, path: &Path) -> Result<(), Error> {
+        tokio::fs::write(path, serde_json::to_vec_pretty(self)?).await?;
+        Ok(())
+    }
+
+    /// How a build of `local` would differ from this one, before the jam
+    pub fn differences(&self, local: &Provenance) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.compiler != local.compiler {
+            differences.push(format!(
+                "built with hoonc {} (kernel {}), this is hoonc {} (kernel {})",
+                self.compiler.version,
+                self.compiler.kernel,
+                local.compiler.version,
+                local.compiler.kernel
+            ));
+        }
+        if self.entry.path != local.entry.path {
+            differences.push(format!(
+                "built from {}, not {}",
+                self.entry.path, local.entry.path
+            ));
+        } else if self.entry.blake3 != local.entry.blake3 {
+            differences.push(format!("{} changed", self.entry.path));
+        }
+        for input in &self.inputs {
+            match local.inputs.iter().find(|local| local.path == input.path) {
+                None => differences.push(format!("{} is missing", input.path)),
+                Some(local) if local.blake3 != input.blake3 => {
+                    differences.push(format!("{} changed", input.path))
+                }
+                Some(_) => {}
+            }
+        }
+        for local in &local.inputs {
+            if !self.inputs.iter().any(|input| input.path == local.path) {
+                differences.push(format!("{} wasn't in the build", local.path));
+            }
+        }
+        differences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sources(files: &[(&str, &str)]) -> Sources {
+        Sources {
+            entry_path: "/app/main.hoon".into(),
+            entry: b"%main".to_vec(),
+            files: files
+                .iter()
+                .map(|(path, contents)| (path.to_string(), contents.as_bytes().to_vec()))
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provenance() {
+        let built = Provenance::new(
+            &sources(&[("/lib/b.hoon", "b"), ("/lib/a.hoon", "a")]),
+            false,
+            b"jam",
+        );
+        assert_eq!(built.inputs[0].path, "/lib/a.hoon");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = Provenance::sidecar_path(&dir.path().join("out.jam"));
+        assert!(path.ends_with("out.jam.json"));
+        built.write(&path).await.unwrap();
+        assert_eq!(Provenance::read(&path).await.unwrap(), built);
+
+        let same = Provenance::new(
+            &sources(&[("/lib/a.hoon", "a"), ("/lib/b.hoon", "b")]),
+            false,
+            b"",
+        );
+        assert!(built.differences(&same).is_empty());
+        let changed = Provenance::new(
+            &sources(&[("/lib/a.hoon", "A"), ("/lib/c.hoon", "c")]),
+            false,
+            b"",
+        );
+        assert_eq!(
+            built.differences(&changed),
+            vec![
+                "/lib/a.hoon changed".to_string(),
+                "/lib/b.hoon is missing".into(),
+                "/lib/c.hoon wasn't in the build".into(),
+            ]
+        );
+    }
+}
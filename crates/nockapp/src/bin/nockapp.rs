@@ -0,0 +1,123 @@
+use std::io::{stdin, stdout};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use nockapp::kernel::debugger::{self, Debugger};
+use nockapp::kernel::form::{Kernel, StackConfig};
+use nockapp::noun::slab::NockJammer;
+use nockapp::repl::{self, History, NpcConnection};
+use nockapp::save::{SaveableCheckpoint, Saver};
+
+/// Tools for running NockApps
+#[derive(Parser, Debug)]
+#[command(name = "nockapp", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Poke and peek a running NockApp over its NPC socket
+    Repl {
+        /// The NPC socket to connect to
+        #[arg(long, short, default_value = ".socket/nockchain_npc.sock")]
+        socket: PathBuf,
+        /// Where to keep the history, by default ~/.nockapp_repl_history
+        #[arg(long)]
+        history: Option<PathBuf>,
+        /// Don't keep a history file
+        #[arg(long, conflicts_with = "history")]
+        no_history: bool,
+        /// Seconds to wait for a reply
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+    },
+    /// Step through a NockApp's event log, peeking its kernel at any event
+    Debug {
+        /// The kernel jam
+        kernel: PathBuf,
+        /// The event log directory, like <data dir>/checkpoints/events
+        events: PathBuf,
+        /// The checkpoint to start from, by default a freshly booted kernel
+        #[arg(long)]
+        from: Option<PathBuf>,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Repl {
+            socket,
+            history,
+            no_history,
+            timeout,
+        } => {
+            let timeout = Duration::from_secs(timeout.max(1));
+            let mut connection = match NpcConnection::connect(&socket, timeout) {
+                Ok(connection) => connection,
+                Err(e) => {
+                    eprintln!("couldn't connect to {}: {}", socket.display(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let file = if no_history {
+                None
+            } else {
+                history.or_else(History::default_path)
+            };
+            let mut history = History::load(file);
+            println!("connected to {}; type help for commands", socket.display());
+            if let Err(e) = repl::run(&mut connection, &mut history, stdin().lock(), stdout()) {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+            ExitCode::SUCCESS
+        }
+        Command::Debug {
+            kernel,
+            events,
+            from,
+        } => {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .expect("could not start a runtime");
+            match runtime.block_on(debug(kernel, events, from)) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+    }
+}
+
+async fn debug(
+    kernel: PathBuf,
+    events: PathBuf,
+    from: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let jam = std::fs::read(&kernel)?;
+    let checkpoint = match &from {
+        Some(path) => Some(Saver::<NockJammer>::load_file::<SaveableCheckpoint>(path, None).await?),
+        None => None,
+    };
+    // Without the app's jets the kernel runs slower, but the same
+    let kernel: Kernel<SaveableCheckpoint> = Kernel::load_with_stack_config(
+        &jam,
+        checkpoint,
+        &[],
+        StackConfig::autodetect(),
+        Vec::new(),
+        false,
+    )
+    .await?;
+    let mut debugger = Debugger::new(kernel, &events).await?;
+    debugger::run(&mut debugger, std::io::BufReader::new(stdin()), stdout()).await?;
+    Ok(())
+}
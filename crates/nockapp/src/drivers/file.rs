@@ -1,10 +1,13 @@
-use nockvm::noun::{IndirectAtom, Noun, D, NO, T, YES};
+use std::path::{PathBuf, MAIN_SEPARATOR_STR};
+
+use nockvm::noun::{Atom, IndirectAtom, Noun, D, NO, T, YES};
 use nockvm_macros::tas;
 use tracing::{debug, error};
 
 use crate::nockapp::driver::{make_driver, IODriverFn};
 use crate::nockapp::wire::{Wire, WireRepr};
-use crate::noun::slab::NounSlab;
+use crate::noun::path::{Fields, PathError};
+use crate::noun::pool::SlabPool;
 use crate::noun::FromAtom;
 use crate::AtomExt;
 
@@ -40,6 +43,7 @@ impl Wire for FileWire {
 ///  `[%file %write path=@t contents=@ success=?]`
 pub fn file() -> IODriverFn {
     make_driver(|handle| async move {
+        let pool = SlabPool::default();
         loop {
             let effect_res = handle.next_effect().await;
             let slab = match effect_res {
@@ -50,35 +54,21 @@ pub fn file() -> IODriverFn {
                 }
             };
 
-            let Ok(effect_cell) = unsafe { slab.root() }.as_cell() else {
-                continue;
-            };
-
-            if !unsafe { effect_cell.head().raw_equals(&D(tas!(b"file"))) } {
-                continue;
-            }
-
-            let Ok(file_cell) = effect_cell.tail().as_cell() else {
-                continue;
-            };
-
-            let (operation, path_atom) = match file_cell.head().as_direct() {
-                Ok(tag) if tag.data() == tas!(b"read") => ("read", file_cell.tail().as_atom().ok()),
-                Ok(tag) if tag.data() == tas!(b"write") => {
-                    let Ok(write_cell) = file_cell.tail().as_cell() else {
-                        continue;
-                    };
-                    ("write", write_cell.head().as_atom().ok())
+            let file_effect = match FileEffect::from_noun(unsafe { *slab.root() }) {
+                Ok(Some(file_effect)) => file_effect,
+                Ok(None) => continue,
+                Err(e) => {
+                    debug!("file driver: malformed effect: {}", e);
+                    continue;
                 }
-                _ => continue,
             };
 
-            match (operation, path_atom) {
-                ("read", Some(path_atom)) => {
-                    let path = String::from_utf8(Vec::from(path_atom.as_ne_bytes()))?;
+            match file_effect {
+                FileEffect::Read { path: path_atom } => {
+                    let path = native_path(&String::from_utf8(Vec::from(path_atom.as_ne_bytes()))?);
                     match tokio::fs::read(&path).await {
                         Ok(contents) => {
-                            let mut poke_slab = NounSlab::new();
+                            let mut poke_slab = pool.rent();
                             let contents_atom = unsafe {
                                 IndirectAtom::new_raw_bytes_ref(&mut poke_slab, &contents)
                                     .normalize_as_atom()
@@ -93,7 +83,7 @@ pub fn file() -> IODriverFn {
                             handle.poke(wire, poke_slab).await?;
                         }
                         Err(_) => {
-                            let mut poke_slab = NounSlab::new();
+                            let mut poke_slab = pool.rent();
                             let poke_noun =
                                 T(&mut poke_slab, &[D(tas!(b"file")), D(tas!(b"read")), D(0)]);
                             poke_slab.set_root(poke_noun);
@@ -102,22 +92,23 @@ pub fn file() -> IODriverFn {
                         }
                     }
                 }
-                ("write", Some(path_atom)) => {
-                    let Ok(write_cell) = file_cell.tail().as_cell() else {
-                        continue;
-                    };
-                    let Ok(contents_atom) = write_cell.tail().as_atom() else {
-                        continue;
-                    };
-                    let path = path_atom.into_string()?;
+                FileEffect::Write {
+                    path: path_atom,
+                    contents: contents_atom,
+                } => {
+                    let path = native_path(&path_atom.into_string()?);
                     let contents = contents_atom.as_ne_bytes();
-                    debug!("file driver: writing {} bytes to: {}", contents.len(), path);
+                    debug!(
+                        "file driver: writing {} bytes to: {}",
+                        contents.len(),
+                        path.display()
+                    );
 
                     // Create parent directories if they don't exist
-                    if let Some(parent) = std::path::Path::new(&path).parent() {
+                    if let Some(parent) = path.parent() {
                         if let Err(e) = tokio::fs::create_dir_all(parent).await {
                             error!("file driver: error creating directories: {}", e);
-                            let mut poke_slab = NounSlab::new();
+                            let mut poke_slab = pool.rent();
                             let poke_noun = T(
                                 &mut poke_slab,
                                 &[
@@ -137,7 +128,7 @@ pub fn file() -> IODriverFn {
 
                     match tokio::fs::write(&path, contents).await {
                         Ok(_) => {
-                            let mut poke_slab = NounSlab::new();
+                            let mut poke_slab = pool.rent();
                             let poke_noun = T(
                                 &mut poke_slab,
                                 &[
@@ -154,7 +145,7 @@ pub fn file() -> IODriverFn {
                         }
                         Err(e) => {
                             error!("file driver: error writing to path: {}", e);
-                            let mut poke_slab = NounSlab::new();
+                            let mut poke_slab = pool.rent();
                             let poke_noun = T(
                                 &mut poke_slab,
                                 &[
@@ -171,8 +162,55 @@ pub fn file() -> IODriverFn {
                         }
                     }
                 }
-                _ => continue,
             }
+            pool.recycle(slab);
         }
     })
 }
+
+/// A kernel's path, which separates components with `/`, as the platform spells it
+fn native_path(path: &str) -> PathBuf {
+    PathBuf::from(path.replace('/', MAIN_SEPARATOR_STR))
+}
+
+enum FileEffect {
+    Read { path: Atom },
+    Write { path: Atom, contents: Atom },
+}
+
+impl FileEffect {
+    /// Returns `None` for effects that aren't for this driver.
+    fn from_noun(effect: Noun) -> Result<Option<Self>, PathError> {
+        let mut fields = Fields::new(effect).named("effect");
+        if fields.tag(b"file").is_err() {
+            return Ok(None);
+        }
+        match fields.next::<u64>("operation")? {
+            tas!(b"read") => Ok(Some(FileEffect::Read {
+                path: fields.last("path")?,
+            })),
+            tas!(b"write") => {
+                let path = fields.next("path")?;
+                let contents = fields.last("contents")?;
+                Ok(Some(FileEffect::Write { path, contents }))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn test_native_path() {
+        assert_eq!(
+            native_path("out/blocks/1.jam"),
+            Path::new("out").join("blocks").join("1.jam")
+        );
+        assert_eq!(native_path("a.txt"), Path::new("a.txt"));
+    }
+}
@@ -0,0 +1,332 @@
+//! A gRPC server for the `nockapp.v1.NockApp` service in `proto/nockapp.proto`, and whatever
+//! services the app adds to it.
+//!
+//! `Peek` takes a text path, as [`parse_path`] reads it, and answers with what the kernel said
+//! and the value jammed or rendered as JSON, like `%scry` over NPC. A path that doesn't parse is
+//! `INVALID_ARGUMENT`, a peek the kernel crashes on `INTERNAL`, and a value that isn't a `json`
+//! noun when JSON was asked for `FAILED_PRECONDITION`.
+//!
+//! The messages and the service in [`pb`] are written out the way `tonic-build` would generate
+//! them from the proto, so keep the two in step.
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::service::Routes;
+use tonic::transport::Server;
+use tonic::Status;
+use tracing::{debug, error, info};
+
+use crate::nockapp::driver::{make_driver, IODriverFn, NockAppHandle};
+use crate::nockapp::NockAppError;
+use crate::noun::json::print_json;
+use crate::noun::scry::{parse_path, Scry};
+use crate::noun::slab::NounSlab;
+use crate::utils::daemon;
+
+/// Serve the `NockApp` service and `routes` over gRPC on `addr`, or on the socket systemd passed
+/// in as `grpc`.
+pub fn grpc_server(addr: SocketAddr, routes: Routes) -> IODriverFn {
+    make_driver(move |handle| async move {
+        let listener = daemon::activated_or_bind_tcp("grpc", addr)
+            .await
+            .map_err(NockAppError::IoError)?;
+        let local_addr = listener.local_addr().map_err(NockAppError::IoError)?;
+        info!("Serving gRPC on {}", local_addr);
+        let routes = routes.add_service(pb::NockAppServer::new(Arc::new(handle)));
+        if let Err(e) = Server::builder()
+            .add_routes(routes)
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+        {
+            error!("gRPC server error: {}", e);
+        }
+        Ok(())
+    })
+}
+
+/// Answer a `Peek`
+pub async fn peek(
+    handle: &NockAppHandle,
+    request: pb::PeekRequest,
+) -> Result<pb::PeekResponse, Status> {
+    let encoding = pb::Encoding::try_from(request.encoding)
+        .map_err(|_| Status::invalid_argument("unknown encoding"))?;
+    let mut path_slab = NounSlab::new();
+    let path = parse_path(&mut path_slab, &request.path)
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+    path_slab.set_root(path);
+
+    let Some(mut peek_slab) = handle
+        .peek(path_slab)
+        .await
+        .map_err(|e| Status::unavailable(e.to_string()))?
+    else {
+        debug!("grpc: peek of {} failed", request.path);
+        return Err(Status::internal("peek failed"));
+    };
+    let scry = Scry::from_peek(unsafe { *peek_slab.root() })
+        .map_err(|e| Status::internal(e.to_string()))?;
+    let (found, value) = match scry {
+        Scry::Unknown => (pb::Found::Unknown, Vec::new()),
+        Scry::Empty => (pb::Found::Empty, Vec::new()),
+        Scry::Value(value) => {
+            let value = match encoding {
+                pb::Encoding::Jam => {
+                    peek_slab.set_root(value);
+                    peek_slab.jam().to_vec()
+                }
+                pb::Encoding::Json => print_json(value)
+                    .map_err(|e| Status::failed_precondition(e.to_string()))?
+                    .into_bytes(),
+            };
+            (pb::Found::Value, value)
+        }
+    };
+    Ok(pb::PeekResponse {
+        found: found as i32,
+        value,
+    })
+}
+
+/// The messages and server of `nockapp.v1`
+pub mod pb {
+    use tonic::codegen::{empty_body, http, Body, BoxFuture, Context, Poll, Service, StdError};
+    use tonic::server::{Grpc, NamedService, UnaryService};
+
+    use super::*;
+
+    /// How the value of a peek is encoded
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Encoding {
+        /// The jam of the value noun
+        Jam = 0,
+        /// The value as JSON text; the kernel must return a `json` noun
+        Json = 1,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PeekRequest {
+        #[prost(string, tag = "1")]
+        pub path: String,
+        #[prost(enumeration = "Encoding", tag = "2")]
+        pub encoding: i32,
+    }
+
+    /// What the kernel's `(unit (unit *))` said
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Found {
+        /// `~`: the kernel doesn't handle the path
+        Unknown = 0,
+        /// `[~ ~]`: the path is handled but has no value
+        Empty = 1,
+        /// `[~ ~ value]`
+        Value = 2,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PeekResponse {
+        #[prost(enumeration = "Found", tag = "1")]
+        pub found: i32,
+        /// The encoded value, empty unless `found` is [`Found::Value`]
+        #[prost(bytes = "vec", tag = "2")]
+        pub value: Vec<u8>,
+    }
+
+    /// The `nockapp.v1.NockApp` service, answering from a handle
+    #[derive(Clone)]
+    pub struct NockAppServer {
+        handle: Arc<NockAppHandle>,
+    }
+
+    impl NockAppServer {
+        pub fn new(handle: Arc<NockAppHandle>) -> Self {
+            NockAppServer { handle }
+        }
+    }
+
+    impl NamedService for NockAppServer {
+        const NAME: &'static str = "nockapp.v1.NockApp";
+    }
+
+    struct PeekSvc(Arc<NockAppHandle>);
+
+    impl UnaryService<PeekRequest> for PeekSvc {
+        type Response = PeekResponse;
+        type Future = BoxFuture<tonic::Response<PeekResponse>, Status>;
+
+        fn call(&mut self, request: tonic::Request<PeekRequest>) -> Self::Future {
+            let handle = self.0.clone();
+            Box::pin(async move {
+                peek(&handle, request.into_inner())
+                    .await
+                    .map(tonic::Response::new)
+            })
+        }
+    }
+
+    impl<B> Service<http::Request<B>> for NockAppServer
+    where
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<B>) -> Self::Future {
+            match request.uri().path() {
+                "/nockapp.v1.NockApp/Peek" => {
+                    let svc = PeekSvc(self.handle.clone());
+                    Box::pin(async move {
+                        let mut grpc = Grpc::new(tonic::codec::ProstCodec::default());
+                        Ok(grpc.unary(svc, request).await)
+                    })
+                }
+                _ => Box::pin(async move {
+                    let mut response = http::Response::new(empty_body());
+                    let headers = response.headers_mut();
+                    headers.insert(
+                        Status::GRPC_STATUS,
+                        (tonic::Code::Unimplemented as i32).into(),
+                    );
+                    headers.insert(
+                        http::header::CONTENT_TYPE,
+                        tonic::metadata::GRPC_CONTENT_TYPE,
+                    );
+                    Ok(response)
+                }),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::{D, T};
+    use nockvm_macros::tas;
+    use tokio::sync::{broadcast, mpsc, watch, Mutex};
+
+    use super::*;
+    use crate::metrics::NockAppMetrics;
+    use crate::nockapp::driver::IOAction;
+    use crate::NockAppExit;
+
+    /// A handle that answers `/value` with `[~ ~ [%o ~]]`, the `json` of `{}`, `/atom` with
+    /// `[~ ~ 5]`, `/empty` with `[~ ~]`, `/crash` by failing, and anything else with `~`
+    fn handle() -> NockAppHandle {
+        let (io_sender, mut io_receiver) = mpsc::channel(8);
+        let (effect_sender, effect_receiver) = broadcast::channel(8);
+        tokio::spawn(async move {
+            while let Some(action) = io_receiver.recv().await {
+                if let IOAction::Peek {
+                    path,
+                    result_channel,
+                    ..
+                } = action
+                {
+                    let head = unsafe { *path.root() }
+                        .as_cell()
+                        .ok()
+                        .and_then(|cell| cell.head().as_direct().ok())
+                        .map(|head| head.data());
+                    let mut slab = NounSlab::new();
+                    let root = match head {
+                        Some(tas!(b"value")) => T(&mut slab, &[D(0), D(0), D(tas!(b"o")), D(0)]),
+                        Some(tas!(b"atom")) => T(&mut slab, &[D(0), D(0), D(5)]),
+                        Some(tas!(b"empty")) => T(&mut slab, &[D(0), D(0)]),
+                        Some(tas!(b"crash")) => {
+                            let _ = result_channel.send(None);
+                            continue;
+                        }
+                        _ => D(0),
+                    };
+                    slab.set_root(root);
+                    let _ = result_channel.send(Some(slab));
+                }
+            }
+        });
+        NockAppHandle {
+            io_sender,
+            effect_sender: Arc::new(effect_sender),
+            effect_receiver: Mutex::new(effect_receiver),
+            metrics: Arc::new(
+                NockAppMetrics::register(gnort::global_metrics_registry())
+                    .expect("Failed to register metrics!"),
+            ),
+            exit: NockAppExit::new().0,
+            shutdown: Default::default(),
+            store: Default::default(),
+            state_changes: watch::channel(0).1,
+            wire_versions: Default::default(),
+        }
+    }
+
+    fn request(path: &str, encoding: pb::Encoding) -> pb::PeekRequest {
+        pb::PeekRequest {
+            path: path.to_string(),
+            encoding: encoding as i32,
+        }
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_peek() {
+        let handle = handle();
+
+        let response = peek(&handle, request("/value", pb::Encoding::Jam))
+            .await
+            .expect("peek");
+        assert_eq!(response.found, pb::Found::Value as i32);
+        let mut slab: NounSlab = NounSlab::new();
+        let value = T(&mut slab, &[D(tas!(b"o")), D(0)]);
+        slab.set_root(value);
+        assert_eq!(response.value, slab.jam().to_vec());
+
+        let response = peek(&handle, request("/value", pb::Encoding::Json))
+            .await
+            .expect("peek");
+        assert_eq!(response.value, b"{}");
+
+        let response = peek(&handle, request("/empty", pb::Encoding::Json))
+            .await
+            .expect("peek");
+        assert_eq!(response.found, pb::Found::Empty as i32);
+        assert!(response.value.is_empty());
+
+        let response = peek(&handle, request("/other", pb::Encoding::Jam))
+            .await
+            .expect("peek");
+        assert_eq!(response.found, pb::Found::Unknown as i32);
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_peek_errors() {
+        let handle = handle();
+        let code = |result: Result<pb::PeekResponse, Status>| result.expect_err("error").code();
+
+        let result = peek(&handle, request("value", pb::Encoding::Jam)).await;
+        assert_eq!(code(result), tonic::Code::InvalidArgument);
+        let result = peek(&handle, request("/Value", pb::Encoding::Jam)).await;
+        assert_eq!(code(result), tonic::Code::InvalidArgument);
+        let mut bad_encoding = request("/value", pb::Encoding::Jam);
+        bad_encoding.encoding = 7;
+        assert_eq!(
+            code(peek(&handle, bad_encoding).await),
+            tonic::Code::InvalidArgument
+        );
+        let result = peek(&handle, request("/crash", pb::Encoding::Jam)).await;
+        assert_eq!(code(result), tonic::Code::Internal);
+        let result = peek(&handle, request("/atom", pb::Encoding::Json)).await;
+        assert_eq!(code(result), tonic::Code::FailedPrecondition);
+        let result = peek(&handle, request("/atom", pb::Encoding::Jam)).await;
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,45 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use tokio::{fs, time};
+use tracing::{error, info, warn};
+
+use crate::nockapp::driver::{make_driver, IODriverFn, PokeResult};
+
+/// How often the hot-load driver checks its kernel file
+pub const HOT_LOAD_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Hot-load IO Driver
+///
+/// Watches a kernel jam, like the output of `hoonc --watch`, and upgrades the running kernel to
+/// it whenever it's rewritten. A jam that fails to load, like one read halfway through being
+/// written, leaves the old kernel running until the file changes again.
+pub fn hot_load(path: PathBuf, interval: Duration) -> IODriverFn {
+    make_driver(move |handle| async move {
+        let mut loaded = modified(&path).await;
+        let mut ticks = time::interval(interval);
+        loop {
+            ticks.tick().await;
+            let current = modified(&path).await;
+            if current.is_none() || current == loaded {
+                continue;
+            }
+            loaded = current;
+            let kernel = match fs::read(&path).await {
+                Ok(kernel) => kernel,
+                Err(e) => {
+                    error!("hot-load: failed to read {:?}: {}", path, e);
+                    continue;
+                }
+            };
+            match handle.upgrade(kernel).await? {
+                PokeResult::Ack => info!("hot-load: loaded the kernel in {:?}", path),
+                PokeResult::Nack => warn!("hot-load: the kernel in {:?} did not load", path),
+            }
+        }
+    })
+}
+
+async fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).await.ok()?.modified().ok()
+}
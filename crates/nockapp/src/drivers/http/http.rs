@@ -11,6 +11,7 @@ use axum::response::Response;
 use axum::routing::get;
 use axum::{serve, Router};
 use axum_server::tls_rustls::RustlsConfig;
+use futures::{Stream, StreamExt};
 use nockvm::noun::{Atom, D, T};
 use nockvm_macros::tas;
 use tokio::select;
@@ -19,11 +20,12 @@ use tower_http::services::ServeDir;
 use tracing::{debug, error, info, warn};
 
 use crate::drivers::http::acme::AcmeManager;
-use crate::nockapp::driver::{make_driver, IODriverFn, PokeResult};
+use crate::nockapp::driver::{make_driver, IODriverFn, NockAppHandle, PokeResult};
 use crate::nockapp::wire::{Wire, WireRepr};
 use crate::nockapp::NockAppError;
 use crate::noun::slab::NounSlab;
-use crate::{AtomExt, Bytes};
+use crate::utils::daemon;
+use crate::{AtomExt, Bytes, BytesMut};
 
 #[derive(Debug, thiserror::Error)]
 pub enum HttpError {
@@ -80,9 +82,145 @@ struct RequestMessage {
     method: Method,
     headers: HeaderMap,
     body: Option<axum::body::Bytes>,
+    /// The body is too big for one poke and follows in [`HttpMessage::BodyPart`]s
+    streamed: bool,
     resp: Responder,
 }
 
+#[derive(Debug)]
+enum BodyPart {
+    Data(axum::body::Bytes),
+    End,
+    /// The client went over a limit or stopped sending, and won't get a response
+    Drop,
+}
+
+#[derive(Debug)]
+enum HttpMessage {
+    Request(RequestMessage),
+    /// The next part of a streamed body. `ack` gets whether the kernel took it, and the next part
+    /// isn't read from the client until it has.
+    BodyPart {
+        id: u64,
+        part: BodyPart,
+        ack: oneshot::Sender<bool>,
+    },
+}
+
+/// Limits on request bodies, set with `HTTP_MAX_BODY_BYTES`, `HTTP_BODY_CHUNK_BYTES`,
+/// `HTTP_BODY_IDLE_TIMEOUT_SECS` and `HTTP_BODY_TIMEOUT_SECS`
+#[derive(Debug, Clone, Copy)]
+struct BodyLimits {
+    /// Bigger bodies get 413
+    max_bytes: u64,
+    /// Bodies up to this size are poked whole, and bigger ones in parts of this size
+    chunk_bytes: usize,
+    /// How long the client may go without sending any of the body
+    idle_timeout: Duration,
+    /// How long the whole body may take
+    timeout: Duration,
+}
+
+impl Default for BodyLimits {
+    fn default() -> Self {
+        BodyLimits {
+            max_bytes: 64 << 20,
+            chunk_bytes: 1 << 20,
+            idle_timeout: Duration::from_secs(30),
+            timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+impl BodyLimits {
+    fn from_env() -> Self {
+        let var = |name: &str| env::var(name).ok().and_then(|s| s.parse::<u64>().ok());
+        let default = BodyLimits::default();
+        BodyLimits {
+            max_bytes: var("HTTP_MAX_BODY_BYTES").unwrap_or(default.max_bytes),
+            chunk_bytes: var("HTTP_BODY_CHUNK_BYTES")
+                .map(|n| n.max(1) as usize)
+                .unwrap_or(default.chunk_bytes),
+            idle_timeout: var("HTTP_BODY_IDLE_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(default.idle_timeout),
+            timeout: var("HTTP_BODY_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(default.timeout),
+        }
+    }
+}
+
+/// Reads a request body in chunks of [`BodyLimits::chunk_bytes`], failing with the status to
+/// answer when it breaks a limit
+struct BodyReader<S> {
+    stream: S,
+    buffer: BytesMut,
+    total: u64,
+    ended: bool,
+    limits: BodyLimits,
+    deadline: tokio::time::Instant,
+}
+
+impl<S, E> BodyReader<S>
+where
+    S: Stream<Item = Result<axum::body::Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    fn new(stream: S, limits: BodyLimits) -> Self {
+        BodyReader {
+            stream,
+            buffer: BytesMut::new(),
+            total: 0,
+            ended: false,
+            limits,
+            deadline: tokio::time::Instant::now() + limits.timeout,
+        }
+    }
+
+    /// The next chunk of the body, shorter at its end, or `None` after it
+    async fn next_chunk(&mut self) -> Result<Option<axum::body::Bytes>, StatusCode> {
+        while !self.ended && self.buffer.len() < self.limits.chunk_bytes {
+            let wait = self.limits.idle_timeout.min(
+                self.deadline
+                    .saturating_duration_since(tokio::time::Instant::now()),
+            );
+            match tokio::time::timeout(wait, self.stream.next()).await {
+                Err(_) => {
+                    warn!("http: timed out reading a request body");
+                    return Err(StatusCode::REQUEST_TIMEOUT);
+                }
+                Ok(None) => self.ended = true,
+                Ok(Some(Err(e))) => {
+                    warn!("http: failed to read a request body: {}", e);
+                    return Err(StatusCode::BAD_REQUEST);
+                }
+                Ok(Some(Ok(data))) => {
+                    self.total += data.len() as u64;
+                    if self.total > self.limits.max_bytes {
+                        warn!(
+                            "http: request body over the limit of {} bytes",
+                            self.limits.max_bytes
+                        );
+                        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+                    }
+                    self.buffer.extend_from_slice(&data);
+                }
+            }
+        }
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        let len = self.buffer.len().min(self.limits.chunk_bytes);
+        Ok(Some(self.buffer.split_to(len).freeze()))
+    }
+
+    /// Whether all of the body has been returned
+    fn finished(&self) -> bool {
+        self.ended && self.buffer.is_empty()
+    }
+}
+
 struct ResponseBuilder {
     status_code: StatusCode,
     headers: Vec<(String, String)>,
@@ -149,8 +287,9 @@ impl CachedResponse {
 
 #[derive(Clone)]
 struct AppState {
-    sender: Arc<RwLock<tokio::sync::mpsc::Sender<RequestMessage>>>,
+    sender: Arc<RwLock<tokio::sync::mpsc::Sender<HttpMessage>>>,
     challenges: Option<Arc<RwLock<HashMap<String, String>>>>,
+    body_limits: BodyLimits,
 }
 
 /// ACME challenge handler for Let's Encrypt HTTP-01 validation
@@ -172,7 +311,8 @@ async fn acme_challenge_handler(
 /// HTTP IO driver with support for automatic HTTPS via Let's Encrypt
 pub fn http() -> IODriverFn {
     make_driver(move |handle| async move {
-        let (tx, mut rx) = tokio::sync::mpsc::channel::<RequestMessage>(10);
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<HttpMessage>(10);
+        let body_limits = BodyLimits::from_env();
 
         // Domain to bind to for HTTPS
         let domain = env::var("HTTPS_DOMAIN").unwrap_or_else(|_| "localhost".to_string());
@@ -196,6 +336,7 @@ pub fn http() -> IODriverFn {
                 AppState {
                     sender: Arc::new(RwLock::new(tx.clone())),
                     challenges: None,
+                    body_limits,
                 },
                 None,
             )
@@ -218,6 +359,7 @@ pub fn http() -> IODriverFn {
                 AppState {
                     sender: Arc::new(RwLock::new(tx.clone())),
                     challenges: Some(challenges),
+                    body_limits,
                 },
                 Some(acme_manager),
             )
@@ -266,7 +408,7 @@ pub fn http() -> IODriverFn {
 
         if is_local {
             // Local development: just run HTTP on port 8080
-            let http_listener = tokio::net::TcpListener::bind("127.0.0.1:8080")
+            let http_listener = daemon::activated_or_bind_tcp("http", "127.0.0.1:8080")
                 .await
                 .map_err(HttpError::BindError)?;
             let http_addr = http_listener
@@ -283,7 +425,7 @@ pub fn http() -> IODriverFn {
             // Production: Start HTTP server first for ACME challenges
             info!("Starting HTTP server for ACME challenges");
             let http_app = app.clone();
-            let http_listener = tokio::net::TcpListener::bind("0.0.0.0:80")
+            let http_listener = daemon::activated_or_bind_tcp("http", "0.0.0.0:80")
                 .await
                 .map_err(HttpError::BindError)?;
             let http_addr = http_listener
@@ -313,7 +455,7 @@ pub fn http() -> IODriverFn {
                         info!("Successfully got certificate, starting HTTPS server");
                         let rustls_config = RustlsConfig::from_config(Arc::new(tls_config));
 
-                        match tokio::net::TcpListener::bind("0.0.0.0:443").await {
+                        match daemon::activated_or_bind_tcp("https", "0.0.0.0:443").await {
                             Ok(https_listener) => {
                                 let https_addr = https_listener.local_addr().unwrap();
                                 info!("HTTPS server listening on {}", https_addr);
@@ -394,7 +536,7 @@ pub fn http() -> IODriverFn {
                         Some(msg) => msg,
                         None => {
                             warn!("HTTP request channel closed, recreating channel");
-                            let (new_tx, new_rx) = tokio::sync::mpsc::channel::<RequestMessage>(10);
+                            let (new_tx, new_rx) = tokio::sync::mpsc::channel::<HttpMessage>(10);
                             rx = new_rx;
 
                             // Update the sender in the existing shared app_state
@@ -402,6 +544,14 @@ pub fn http() -> IODriverFn {
                             continue;
                         }
                     };
+                    let msg = match msg {
+                        HttpMessage::Request(msg) => msg,
+                        HttpMessage::BodyPart { id, part, ack } => {
+                            let taken = poke_body_part(&handle, &channel_map, id, part).await;
+                            let _ = ack.send(taken);
+                            continue;
+                        }
+                    };
                     info!("Processing request {} {} with id: {}", msg.method, msg.uri, msg.id);
                     debug!("headers: {:?}", msg.headers);
                     if let Some(ref body) = msg.body {
@@ -461,7 +611,9 @@ pub fn http() -> IODriverFn {
                         }
 
                         let body: crate::Noun = {
-                            if let Some(bod) = msg.body {
+                            if msg.streamed {
+                                D(0)
+                            } else if let Some(bod) = msg.body {
                                 let ato = Atom::from_bytes(&mut slab, &bod).as_noun();
                                 let len: u64 = bod.len().try_into().map_err(|_| HttpError::BodyLengthConversion)?;
                                 T(&mut slab, &[D(0), D(len), ato])
@@ -470,10 +622,18 @@ pub fn http() -> IODriverFn {
                             }
                         };
 
-                        let poke = T(
-                            &mut slab,
-                            &[D(tas!(b"req")), id.as_noun(), uri.as_noun(), method.as_noun(), headers, body],
-                        );
+                        // A streamed body follows in %req-body pokes, ended by %req-end
+                        let poke = if msg.streamed {
+                            T(
+                                &mut slab,
+                                &[D(tas!(b"req-head")), id.as_noun(), uri.as_noun(), method.as_noun(), headers],
+                            )
+                        } else {
+                            T(
+                                &mut slab,
+                                &[D(tas!(b"req")), id.as_noun(), uri.as_noun(), method.as_noun(), headers, body],
+                            )
+                        };
                         debug!("poking kernel with request for {}", msg.uri);
                         slab.set_root(poke);
 
@@ -664,82 +824,177 @@ pub fn http() -> IODriverFn {
     })
 }
 
-async fn nockvm_handler(
-    method: Method,
-    headers: HeaderMap,
-    uri: Uri,
-    State(state): State<AppState>,
-    body: axum::body::Bytes,
-) -> Result<Response, StatusCode> {
-    debug!("Received request: {} {}", method, uri);
-    debug!("Headers: {:?}", headers);
-    debug!("Body length: {}", body.len());
-
-    let (mut resp_tx, mut resp_rx) = oneshot::channel::<Result<Response, StatusCode>>();
-    let opt_body: Option<axum::body::Bytes> = {
-        if body.is_empty() {
-            None
-        } else {
-            Some(body)
+/// Poke a part of a streamed body, returning whether the kernel took it. A nacked part fails the
+/// request with 400.
+async fn poke_body_part(
+    handle: &NockAppHandle,
+    channel_map: &RwLock<HashMap<u64, Responder>>,
+    id: u64,
+    part: BodyPart,
+) -> bool {
+    if !channel_map.read().await.contains_key(&id) {
+        return false;
+    }
+    let mut slab = NounSlab::new();
+    let id_atom = Atom::new(&mut slab, id).as_noun();
+    let poke = match &part {
+        BodyPart::Data(data) => {
+            let dat = Atom::from_bytes(&mut slab, data).as_noun();
+            T(
+                &mut slab,
+                &[D(tas!(b"req-body")), id_atom, D(data.len() as u64), dat],
+            )
+        }
+        BodyPart::End => T(&mut slab, &[D(tas!(b"req-end")), id_atom]),
+        BodyPart::Drop => {
+            channel_map.write().await.remove(&id);
+            T(&mut slab, &[D(tas!(b"req-drop")), id_atom])
         }
     };
+    slab.set_root(poke);
+    match handle.poke(HttpWire::Request.to_wire(), slab).await {
+        Ok(PokeResult::Ack) => true,
+        result => {
+            error!(
+                "Kernel didn't take part of the body of request {}: {:?}",
+                id, result
+            );
+            if let Some(resp_tx) = channel_map.write().await.remove(&id) {
+                let _ = resp_tx.send(Err(StatusCode::BAD_REQUEST));
+            }
+            false
+        }
+    }
+}
 
-    let request_id = get_id();
-
-    // Try to send the message, with a retry for closed channels
+/// Send a message to the driver loop, retrying while its channel is recreated
+async fn send_message(
+    state: &AppState,
+    mut make_msg: impl FnMut() -> HttpMessage,
+) -> Result<(), StatusCode> {
     let mut retry_count = 0;
     const MAX_RETRIES: usize = 3;
 
     loop {
-        let msg = RequestMessage {
-            id: request_id,
-            uri: uri.clone(),
-            method: method.clone(),
-            headers: headers.clone(),
-            body: opt_body.clone(),
-            resp: resp_tx,
-        };
-
         // Get the current sender from shared state (it might have been recreated)
         let send_result = {
             let sender_guard = state.sender.read().await;
-            sender_guard.send(msg).await
+            sender_guard.send(make_msg()).await
         };
 
         match send_result {
-            Ok(()) => break,
+            Ok(()) => return Ok(()),
             Err(e) => {
                 error!(
                     "Failed to send request (attempt {}): {}",
                     retry_count + 1,
                     e
                 );
+                retry_count += 1;
+                if retry_count >= MAX_RETRIES {
+                    error!("Max retries reached for closed channel, returning service unavailable");
+                    return Err(StatusCode::SERVICE_UNAVAILABLE);
+                }
+                warn!(
+                    "Channel closed, waiting for recreation (retry {}/{})",
+                    retry_count, MAX_RETRIES
+                );
+                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
 
-                // For channel closed errors, retry after a short delay
-                if matches!(e, tokio::sync::mpsc::error::SendError(_)) {
-                    retry_count += 1;
-                    if retry_count >= MAX_RETRIES {
-                        error!(
-                            "Max retries reached for closed channel, returning service unavailable"
-                        );
-                        return Err(StatusCode::SERVICE_UNAVAILABLE);
-                    }
+async fn nockvm_handler(
+    method: Method,
+    headers: HeaderMap,
+    uri: Uri,
+    State(state): State<AppState>,
+    body: Body,
+) -> Result<Response, StatusCode> {
+    debug!("Received request: {} {}", method, uri);
+    debug!("Headers: {:?}", headers);
 
-                    warn!(
-                        "Channel closed, waiting for recreation (retry {}/{})",
-                        retry_count, MAX_RETRIES
-                    );
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-
-                    // Need to create a new oneshot channel for the retry
-                    let (new_resp_tx, new_resp_rx) =
-                        oneshot::channel::<Result<Response, StatusCode>>();
-                    resp_tx = new_resp_tx;
-                    resp_rx = new_resp_rx;
-                    continue;
-                } else {
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
-                }
+    let limits = state.body_limits;
+    let content_length = headers
+        .get(axum::http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if content_length.is_some_and(|len| len > limits.max_bytes) {
+        warn!(
+            "http: request body of {:?} bytes over the limit of {}",
+            content_length, limits.max_bytes
+        );
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    // A body that fits in one chunk is poked with the request, and a bigger one in parts
+    let mut reader = BodyReader::new(body.into_data_stream(), limits);
+    let first = reader.next_chunk().await?;
+    let streamed = !reader.finished();
+    debug!("Body length: {}", first.as_ref().map_or(0, |b| b.len()));
+
+    let request_id = get_id();
+    let mut resp_rx = None;
+    send_message(&state, || {
+        // A retry needs a new oneshot channel
+        let (resp_tx, rx) = oneshot::channel::<Result<Response, StatusCode>>();
+        resp_rx = Some(rx);
+        HttpMessage::Request(RequestMessage {
+            id: request_id,
+            uri: uri.clone(),
+            method: method.clone(),
+            headers: headers.clone(),
+            body: if streamed { None } else { first.clone() },
+            streamed,
+            resp: resp_tx,
+        })
+    })
+    .await?;
+    let resp_rx = resp_rx.expect("a request was sent");
+
+    if streamed {
+        let mut next = first.map(BodyPart::Data);
+        loop {
+            let part = match next.take() {
+                Some(part) => part,
+                None => match reader.next_chunk().await {
+                    Ok(Some(data)) => BodyPart::Data(data),
+                    Ok(None) => BodyPart::End,
+                    Err(status) => {
+                        let (ack, _) = oneshot::channel();
+                        let _ = state
+                            .sender
+                            .read()
+                            .await
+                            .send(HttpMessage::BodyPart {
+                                id: request_id,
+                                part: BodyPart::Drop,
+                                ack,
+                            })
+                            .await;
+                        return Err(status);
+                    }
+                },
+            };
+            let end = matches!(part, BodyPart::End);
+            let (ack, taken) = oneshot::channel();
+            let sent = state
+                .sender
+                .read()
+                .await
+                .send(HttpMessage::BodyPart {
+                    id: request_id,
+                    part,
+                    ack,
+                })
+                .await;
+            if sent.is_err() {
+                return Err(StatusCode::SERVICE_UNAVAILABLE);
+            }
+            // Not taken means the request already has its response
+            if end || !taken.await.unwrap_or(false) {
+                break;
             }
         }
     }
@@ -774,3 +1029,50 @@ async fn favicon_handler() -> Response {
         .body(Body::from(svg))
         .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_bytes: u64, chunk_bytes: usize) -> BodyLimits {
+        BodyLimits {
+            max_bytes,
+            chunk_bytes,
+            idle_timeout: Duration::from_millis(50),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    fn body(
+        frames: &[&'static [u8]],
+    ) -> impl Stream<Item = Result<axum::body::Bytes, String>> + Unpin {
+        futures::stream::iter(
+            frames
+                .iter()
+                .map(|f| Ok(axum::body::Bytes::from_static(f)))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_body_reader() {
+        let mut small = BodyReader::new(body(&[b"ab", b"c"]), limits(100, 4));
+        assert_eq!(small.next_chunk().await, Ok(Some("abc".into())));
+        assert!(small.finished());
+
+        let mut big = BodyReader::new(body(&[b"abc", b"def", b"ghij"]), limits(100, 4));
+        assert_eq!(big.next_chunk().await, Ok(Some("abcd".into())));
+        assert!(!big.finished());
+        assert_eq!(big.next_chunk().await, Ok(Some("efgh".into())));
+        assert_eq!(big.next_chunk().await, Ok(Some("ij".into())));
+        assert_eq!(big.next_chunk().await, Ok(None));
+        assert!(big.finished());
+
+        let mut over = BodyReader::new(body(&[b"abc", b"def"]), limits(5, 4));
+        assert_eq!(over.next_chunk().await, Err(StatusCode::PAYLOAD_TOO_LARGE));
+
+        let stalled = body(&[b"ab"]).chain(futures::stream::pending());
+        let mut slow = BodyReader::new(stalled, limits(100, 4));
+        assert_eq!(slow.next_chunk().await, Err(StatusCode::REQUEST_TIMEOUT));
+    }
+}
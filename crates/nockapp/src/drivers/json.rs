@@ -0,0 +1,122 @@
+use nockvm::noun::{IndirectAtom, D, T};
+use nockvm_macros::tas;
+use tracing::{debug, error};
+
+use crate::nockapp::driver::{make_driver, IODriverFn};
+use crate::nockapp::wire::{Wire, WireRepr};
+use crate::noun::json::{parse_json, print_json};
+use crate::noun::slab::NounSlab;
+
+pub enum JsonWire {
+    Parse,
+    Print,
+}
+
+impl Wire for JsonWire {
+    const VERSION: u64 = 1;
+    const SOURCE: &'static str = "json";
+
+    fn to_wire(&self) -> WireRepr {
+        let tags = match self {
+            JsonWire::Parse => vec!["parse".into()],
+            JsonWire::Print => vec!["print".into()],
+        };
+        WireRepr::new(JsonWire::SOURCE, JsonWire::VERSION, tags)
+    }
+}
+
+/// JSON conversion driver
+///
+/// Lets a kernel hand JSON parsing and printing off to the runtime instead of doing it in Hoon.
+///
+/// ## Effects
+/// `[%json %parse id=@ text=@t]`
+/// results in poke
+/// `[%json %parse id=@ ~]` on parse error
+/// or
+/// `[%json %parse id=@ ~ =json]` on success
+///
+/// `[%json %print id=@ =json]`
+/// results in poke
+/// `[%json %print id=@ ~]` if the noun is not valid `json`
+/// or
+/// `[%json %print id=@ ~ text=@t]` on success
+pub fn json() -> IODriverFn {
+    make_driver(|handle| async move {
+        loop {
+            let effect_res = handle.next_effect().await;
+            let slab = match effect_res {
+                Ok(slab) => slab,
+                Err(e) => {
+                    error!("Error receiving effect: {:?}", e);
+                    continue;
+                }
+            };
+
+            let Ok(effect_cell) = unsafe { slab.root() }.as_cell() else {
+                continue;
+            };
+
+            if !unsafe { effect_cell.head().raw_equals(&D(tas!(b"json"))) } {
+                continue;
+            }
+
+            let Ok(json_cell) = effect_cell.tail().as_cell() else {
+                continue;
+            };
+            let Ok(args_cell) = json_cell.tail().as_cell() else {
+                continue;
+            };
+            let id = args_cell.head();
+
+            match json_cell.head().as_direct() {
+                Ok(tag) if tag.data() == tas!(b"parse") => {
+                    let Ok(text_atom) = args_cell.tail().as_atom() else {
+                        continue;
+                    };
+                    let text = String::from_utf8_lossy(text_atom.as_ne_bytes());
+                    let text = text.trim_end_matches('\0');
+                    let mut poke_slab = NounSlab::new();
+                    let id = poke_slab.copy_into(id);
+                    let result = match parse_json(&mut poke_slab, text) {
+                        Ok(json) => T(&mut poke_slab, &[D(0), json]),
+                        Err(e) => {
+                            debug!("json driver: parse failed: {}", e);
+                            D(0)
+                        }
+                    };
+                    let poke_noun = T(
+                        &mut poke_slab,
+                        &[D(tas!(b"json")), D(tas!(b"parse")), id, result],
+                    );
+                    poke_slab.set_root(poke_noun);
+                    handle.poke(JsonWire::Parse.to_wire(), poke_slab).await?;
+                }
+                Ok(tag) if tag.data() == tas!(b"print") => {
+                    let mut poke_slab = NounSlab::new();
+                    let id = poke_slab.copy_into(id);
+                    let result = match print_json(args_cell.tail()) {
+                        Ok(text) => {
+                            let text_atom = unsafe {
+                                IndirectAtom::new_raw_bytes_ref(&mut poke_slab, text.as_bytes())
+                                    .normalize_as_atom()
+                            };
+                            T(&mut poke_slab, &[D(0), text_atom.as_noun()])
+                        }
+                        Err(e) => {
+                            debug!("json driver: print failed: {}", e);
+                            D(0)
+                        }
+                    };
+                    let poke_noun = T(
+                        &mut poke_slab,
+                        &[D(tas!(b"json")), D(tas!(b"print")), id, result],
+                    );
+                    poke_slab.set_root(poke_noun);
+                    handle.poke(JsonWire::Print.to_wire(), poke_slab).await?;
+                }
+                _ => continue,
+            }
+        }
+    })
+}
@@ -0,0 +1,71 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{serve, Router};
+use nockvm::noun::Noun;
+use tokio::time;
+use tracing::{debug, error, info};
+
+use crate::nockapp::driver::*;
+use crate::nockapp::{prometheus, NockAppError};
+use crate::noun::slab::NounSlab;
+
+/// A gauge read out of the kernel state with a peek
+pub struct MetricsProbe {
+    /// Prometheus metric name
+    pub name: &'static str,
+    pub help: &'static str,
+    /// The peek path
+    pub path: NounSlab,
+    /// Turns the peek result into the gauge's value, or `None` to leave it as it was
+    pub read: fn(Noun) -> Option<f64>,
+}
+
+/// Serve the metrics recorded in [`prometheus`] at `/metrics` on `addr`, and refresh the gauges
+/// of `probes` by peeking the kernel every `interval`. Enables recording when it starts.
+pub fn metrics_server(
+    addr: SocketAddr,
+    probes: Vec<MetricsProbe>,
+    interval: Duration,
+) -> IODriverFn {
+    make_driver(move |handle| async move {
+        prometheus::enable();
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(NockAppError::IoError)?;
+        let local_addr = listener.local_addr().map_err(NockAppError::IoError)?;
+        info!("Serving metrics on http://{}/metrics", local_addr);
+        let app = Router::new().route("/metrics", get(metrics_handler));
+        tokio::spawn(async move {
+            if let Err(e) = serve(listener, app.into_make_service()).await {
+                error!("Metrics server error: {}", e);
+            }
+        });
+
+        // Borrowed mutably, since a shared borrow of a slab isn't Send
+        let mut probes = probes;
+        let mut refresh = time::interval(interval);
+        loop {
+            refresh.tick().await;
+            for probe in &mut probes {
+                let Some(result) = handle.peek(probe.path.clone()).await? else {
+                    debug!("Metrics probe {} peeked nothing", probe.name);
+                    continue;
+                };
+                if let Some(value) = (probe.read)(unsafe { *result.root() }) {
+                    prometheus::set_gauge(probe.name, probe.help, &[], value);
+                }
+            }
+        }
+    })
+}
+
+async fn metrics_handler() -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        prometheus::render(),
+    )
+}
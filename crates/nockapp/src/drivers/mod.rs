@@ -1,15 +1,30 @@
 pub mod exit;
 pub mod file;
+pub mod grpc;
+pub mod hot_load;
 pub mod http;
+pub mod json;
 pub mod markdown;
+pub mod metrics_server;
 pub mod npc;
 pub mod one_punch;
+pub mod recorder;
+pub mod router;
 pub mod timer;
+pub mod watch;
 
 pub use exit::exit as exit_driver;
 pub use file::file as file_driver;
+pub use grpc::grpc_server as grpc_driver;
+pub use hot_load::hot_load as hot_load_driver;
 pub use http::http::http as http_driver;
+pub use json::json as json_driver;
 pub use markdown::markdown as markdown_driver;
+pub use metrics_server::metrics_server as metrics_driver;
 pub use npc::{npc_client as npc_client_driver, npc_listener as npc_listener_driver};
-pub use one_punch::one_punch_man as one_punch_driver;
+pub use one_punch::{
+    one_punch_man as one_punch_driver, one_punch_secret as one_punch_secret_driver,
+};
+pub use recorder::recorder as recorder_driver;
+pub use router::Router;
 pub use timer::make_timer_driver as timer_driver;
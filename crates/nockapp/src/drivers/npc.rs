@@ -1,20 +1,29 @@
+use std::future::Future;
+use std::io;
 use std::sync::Arc;
+use std::time::Instant;
 
 use bytes::buf::BufMut;
-use nockvm::noun::{D, T};
+use clap::ValueEnum;
+use nockvm::noun::{Noun, D, T};
 use nockvm_macros::tas;
-use tokio::io::{split, AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
-use tokio::net::{UnixListener, UnixStream};
+use tokio::io::{split, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
 use tokio::select;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
 use tokio::task::JoinSet;
 use tokio::time::{sleep, timeout, Duration};
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
-use crate::nockapp::driver::{make_driver, IODriverFn, PokeResult, TaskJoinSet};
+use crate::drivers::watch::{WatchMode, Watches};
+use crate::nockapp::driver::{make_driver, IODriverFn, NockAppHandle, PokeResult, TaskJoinSet};
 use crate::nockapp::wire::{Wire, WireRepr};
 use crate::nockapp::NockAppError;
+use crate::noun::path::Fields;
+use crate::noun::scry::{parse_path, Scry, ScryEncoding};
 use crate::noun::slab::NounSlab;
+use crate::utils::ipc::{IpcListener, IpcStream};
+use crate::utils::make_tas;
+use crate::utils::rate::RateLimiter;
 use crate::Bytes;
 
 /// Timeout constants for npc driver operations
@@ -45,8 +54,161 @@ impl Wire for NpcWire {
     }
 }
 
+/// What a client over an [`NpcLimits`] rate or peek limit gets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LimitAction {
+    /// Hold the request until it fits, which stops reading from the client meanwhile
+    Delay,
+    /// Answer a poke, peek or scry with `[pid %nack 'npc: over limit']` instead of running it
+    Reject,
+    /// Close the connection
+    Disconnect,
+}
+
+/// Limits on the clients of [`npc_listener_with_limits`], each off when `None`.
+///
+/// Every message a client sends counts against the rates. A message over `max_message_bytes`
+/// closes its connection whatever `on_limit` says, since the stream can't be resynchronized
+/// without reading it.
+#[derive(Debug, Clone)]
+pub struct NpcLimits {
+    /// Connections open at once; more are closed as soon as they're accepted
+    pub max_connections: Option<usize>,
+    /// Bytes in one jammed message
+    pub max_message_bytes: Option<usize>,
+    /// Messages a second from each connection
+    pub client_rate: Option<u32>,
+    /// Messages a second from all connections together
+    pub global_rate: Option<u32>,
+    /// Peeks and scries waiting on the kernel at once, across connections
+    pub max_concurrent_peeks: Option<usize>,
+    pub on_limit: LimitAction,
+}
+
+impl Default for NpcLimits {
+    fn default() -> Self {
+        NpcLimits {
+            max_connections: None,
+            max_message_bytes: None,
+            client_rate: None,
+            global_rate: None,
+            max_concurrent_peeks: None,
+            on_limit: LimitAction::Delay,
+        }
+    }
+}
+
+/// A request that went over a limit under [`LimitAction::Reject`] or [`LimitAction::Disconnect`]
+struct Limited;
+
+/// The state the limits share across connections
+struct Limiter {
+    limits: NpcLimits,
+    global: Option<std::sync::Mutex<RateLimiter>>,
+    peeks: Option<Arc<Semaphore>>,
+}
+
+impl Limiter {
+    fn new(limits: NpcLimits) -> Self {
+        Limiter {
+            global: limits
+                .global_rate
+                .map(|rate| std::sync::Mutex::new(RateLimiter::new(rate))),
+            peeks: limits
+                .max_concurrent_peeks
+                .map(|max| Arc::new(Semaphore::new(max))),
+            limits,
+        }
+    }
+
+    /// Take a message's share of the connection's and the global rates, waiting for it under
+    /// [`LimitAction::Delay`]
+    async fn admit(&self, client: &mut Option<RateLimiter>) -> Result<(), Limited> {
+        loop {
+            let now = Instant::now();
+            let wait = {
+                let mut global = self
+                    .global
+                    .as_ref()
+                    .map(|global| global.lock().expect("npc rate limiter poisoned"));
+                let wait = client
+                    .as_mut()
+                    .and_then(|client| client.wait(now))
+                    .max(global.as_mut().and_then(|global| global.wait(now)));
+                if wait.is_none() {
+                    if let Some(client) = client.as_mut() {
+                        client.take();
+                    }
+                    if let Some(global) = global.as_mut() {
+                        global.take();
+                    }
+                }
+                wait
+            };
+            match wait {
+                None => return Ok(()),
+                Some(wait) if self.limits.on_limit == LimitAction::Delay => sleep(wait).await,
+                Some(_) => return Err(Limited),
+            }
+        }
+    }
+
+    /// A place among the peeks waiting on the kernel, if they're limited
+    async fn peek_permit(&self) -> Result<Option<OwnedSemaphorePermit>, Limited> {
+        let Some(peeks) = &self.peeks else {
+            return Ok(None);
+        };
+        let permit = if self.limits.on_limit == LimitAction::Delay {
+            peeks.clone().acquire_owned().await.ok()
+        } else {
+            peeks.clone().try_acquire_owned().ok()
+        };
+        permit.map(Some).ok_or(Limited)
+    }
+}
+
+/// Where [`npc_listener_with_limits`] takes connections from: an [`IpcListener`], or a
+/// [`TlsListener`](crate::utils::tls::TlsListener) for mutually authenticated TCP
+pub trait NpcTransport: Send + 'static {
+    /// An accepted connection, before [`NpcTransport::establish`]
+    type Connection: Send + 'static;
+    type Stream: AsyncRead + AsyncWrite + Send + 'static;
+
+    /// Wait for the next connection. Cancel safe, as it's raced in a `select!`.
+    fn accept(&mut self) -> impl Future<Output = io::Result<Self::Connection>> + Send;
+
+    /// Finish setting up a connection, like with a handshake. This runs in the connection's own
+    /// task, so a slow client doesn't hold up the others.
+    fn establish(
+        &self,
+        connection: Self::Connection,
+    ) -> impl Future<Output = io::Result<Self::Stream>> + Send + 'static;
+}
+
+impl NpcTransport for IpcListener {
+    type Connection = IpcStream;
+    type Stream = IpcStream;
+
+    fn accept(&mut self) -> impl Future<Output = io::Result<IpcStream>> + Send {
+        IpcListener::accept(self)
+    }
+
+    fn establish(
+        &self,
+        connection: IpcStream,
+    ) -> impl Future<Output = io::Result<IpcStream>> + Send + 'static {
+        std::future::ready(Ok(connection))
+    }
+}
+
 /// NPC Listener IO driver
-pub fn npc_listener(listener: UnixListener) -> IODriverFn {
+pub fn npc_listener(listener: IpcListener) -> IODriverFn {
+    npc_listener_with_limits(listener, NpcLimits::default())
+}
+
+/// NPC Listener IO driver, holding clients to `limits` so that none can starve the kernel
+pub fn npc_listener_with_limits<T: NpcTransport>(mut listener: T, limits: NpcLimits) -> IODriverFn {
+    let limiter = Arc::new(Limiter::new(limits));
     make_driver(move |mut handle| async move {
         let mut client_join_set = TaskJoinSet::new();
         loop {
@@ -54,10 +216,26 @@ pub fn npc_listener(listener: UnixListener) -> IODriverFn {
                 stream_res = listener.accept() => {
                     debug!("Accepted new connection");
                     match stream_res {
-                        Ok((stream, _)) => {
+                        Ok(connection) => {
+                            if let Some(max) = limiter.limits.max_connections {
+                                if client_join_set.len() >= max {
+                                    warn!("npc: closing a new connection, {} are already open", max);
+                                    continue;
+                                }
+                            }
                             let (my_handle, their_handle) = handle.dup();
                             handle = my_handle;
-                            let _ = client_join_set.spawn(npc_client(stream)(their_handle));
+                            let establish = listener.establish(connection);
+                            let limiter = limiter.clone();
+                            let _ = client_join_set.spawn(async move {
+                                match establish.await {
+                                    Ok(stream) => client(stream, limiter)(their_handle).await,
+                                    Err(e) => {
+                                        warn!("npc: couldn't set up a connection: {}", e);
+                                        Ok(())
+                                    }
+                                }
+                            });
                         },
                         Err(e) => {
                             error!("Error accepting connection: {:?}", e);
@@ -80,13 +258,27 @@ pub fn npc_listener(listener: UnixListener) -> IODriverFn {
     })
 }
 
-/// NPC Client IO driver
-pub fn npc_client(stream: UnixStream) -> IODriverFn {
+/// NPC Client IO driver, over a Unix socket or a named pipe
+pub fn npc_client<S>(stream: S) -> IODriverFn
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    client(stream, Arc::new(Limiter::new(NpcLimits::default())))
+}
+
+fn client<S>(stream: S, limiter: Arc<Limiter>) -> IODriverFn
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
     make_driver(move |handle| async move {
         let (stream_read, mut stream_write) = split(stream);
         let stream_read_arc = Arc::new(Mutex::new(stream_read));
+        let max_bytes = limiter.limits.max_message_bytes;
+        let mut client_rate = limiter.limits.client_rate.map(RateLimiter::new);
+        let mut watches = Watches::new();
+        let mut state_changes = handle.state_changes.clone();
         let mut read_message_join_set = JoinSet::new();
-        read_message_join_set.spawn(read_message(stream_read_arc.clone()));
+        read_message_join_set.spawn(read_message(stream_read_arc.clone(), max_bytes));
 
         'driver: loop {
             select! {
@@ -108,6 +300,34 @@ pub fn npc_client(stream: UnixStream) -> IODriverFn {
                             };
                             let directive_tag = directive_tag.data();
 
+                            let is_request = matches!(directive_tag, tas!(b"poke") | tas!(b"peek") | tas!(b"scry") | tas!(b"watch"));
+                            let admitted = match limiter.admit(&mut client_rate).await {
+                                Ok(()) if matches!(directive_tag, tas!(b"peek") | tas!(b"scry") | tas!(b"watch")) => limiter.peek_permit().await,
+                                Ok(()) => Ok(None),
+                                Err(limited) => Err(limited),
+                            };
+                            let _permit = match admitted {
+                                Ok(permit) => permit,
+                                Err(Limited) => {
+                                    debug!("npc_client: over limit, {:?}", limiter.limits.on_limit);
+                                    if limiter.limits.on_limit == LimitAction::Disconnect {
+                                        break 'driver;
+                                    }
+                                    if is_request {
+                                        match write_message(&mut stream_write, over_limit(pid)).await {
+                                            Ok(false) => break 'driver,
+                                            Err(NockAppError::IoError(e)) if e.kind() == std::io::ErrorKind::TimedOut => {
+                                                error!("npc_client: write timeout, closing connection to allow reconnect");
+                                                break 'driver;
+                                            },
+                                            Err(e) => return Err(e),
+                                            Ok(true) => {},
+                                        }
+                                    }
+                                    continue;
+                                }
+                            };
+
                             match directive_tag {
                                 tas!(b"poke") => {
                                     debug!("npc_client: poke");
@@ -159,6 +379,38 @@ pub fn npc_client(stream: UnixStream) -> IODriverFn {
                                         }
                                     }
                                 },
+                                tas!(b"scry") => {
+                                    debug!("npc_client: scry");
+                                    let response_slab = scry(&handle, pid, directive_cell.tail()).await?;
+                                    match write_message(&mut stream_write, response_slab).await {
+                                        Ok(false) => break 'driver,
+                                        Err(NockAppError::IoError(e)) if e.kind() == std::io::ErrorKind::TimedOut => {
+                                            error!("npc_client: write timeout, closing connection to allow reconnect");
+                                            break 'driver;
+                                        },
+                                        Err(e) => return Err(e),
+                                        Ok(true) => {}, // Success, continue
+                                    }
+                                },
+                                tas!(b"watch") => {
+                                    debug!("npc_client: watch");
+                                    // Changes from before the first result don't need a fact
+                                    state_changes.mark_unchanged();
+                                    let response_slab = watch(&handle, &mut watches, pid, directive_cell.tail()).await?;
+                                    match write_message(&mut stream_write, response_slab).await {
+                                        Ok(false) => break 'driver,
+                                        Err(NockAppError::IoError(e)) if e.kind() == std::io::ErrorKind::TimedOut => {
+                                            error!("npc_client: write timeout, closing connection to allow reconnect");
+                                            break 'driver;
+                                        },
+                                        Err(e) => return Err(e),
+                                        Ok(true) => {}, // Success, continue
+                                    }
+                                },
+                                tas!(b"leave") => {
+                                    debug!("npc_client: leave");
+                                    watches.leave(pid);
+                                },
                                 tas!(b"pack") | tas!(b"nack") | tas!(b"bind") => {
                                     debug!("npc_client: pack, nack, or bind");
                                     let tag = match directive_tag {
@@ -197,7 +449,26 @@ pub fn npc_client(stream: UnixStream) -> IODriverFn {
                             error!("{e:?}");
                         },
                         None => {
-                            read_message_join_set.spawn(read_message(stream_read_arc.clone()));
+                            read_message_join_set.spawn(read_message(stream_read_arc.clone(), max_bytes));
+                        }
+                    }
+                },
+                changed = state_changes.changed(), if !watches.is_empty() => {
+                    if changed.is_err() {
+                        // The app is gone, so nothing will change again
+                        watches = Watches::new();
+                        continue;
+                    }
+                    for (pid, mut fact) in watches.refresh(&handle).await? {
+                        fact.modify(|root| vec![D(pid), D(tas!(b"fact")), root]);
+                        match write_message(&mut stream_write, fact).await {
+                            Ok(false) => break 'driver,
+                            Err(NockAppError::IoError(e)) if e.kind() == std::io::ErrorKind::TimedOut => {
+                                error!("npc_client: write timeout, closing connection to allow reconnect");
+                                break 'driver;
+                            },
+                            Err(e) => return Err(e),
+                            Ok(true) => {}, // Success, continue
                         }
                     }
                 },
@@ -226,8 +497,99 @@ pub fn npc_client(stream: UnixStream) -> IODriverFn {
     })
 }
 
-async fn read_message(
-    stream_arc: Arc<Mutex<ReadHalf<UnixStream>>>,
+/// Answer `[%scry enc=?(%jam %json) path=@t]` with `[pid %bind result]`, where `result` is the
+/// peek result with its value encoded as requested, or with `[pid %nack err=@t]` if the request
+/// or the peek fails.
+async fn scry(handle: &NockAppHandle, pid: u64, request: Noun) -> Result<NounSlab, NockAppError> {
+    let error = match parse_scry(request) {
+        Ok((encoding, path_slab)) => match handle.peek(path_slab).await? {
+            Some(mut peek_slab) => {
+                // Encode into the peek slab, since a jam-encoded value still points into it
+                let encoded = Scry::from_peek(unsafe { *peek_slab.root() })
+                    .and_then(|scry| scry.encode(&mut peek_slab, encoding));
+                match encoded {
+                    Ok(result) => {
+                        let response = T(&mut peek_slab, &[D(pid), D(tas!(b"bind")), result]);
+                        peek_slab.set_root(response);
+                        return Ok(peek_slab);
+                    }
+                    Err(e) => e.to_string(),
+                }
+            }
+            None => "scry: peek failed".to_string(),
+        },
+        Err(e) => e,
+    };
+    debug!("npc: {}", error);
+    let mut response_slab = NounSlab::new();
+    let message = make_tas(&mut response_slab, &error).as_noun();
+    let response = T(&mut response_slab, &[D(pid), D(tas!(b"nack")), message]);
+    response_slab.set_root(response);
+    Ok(response_slab)
+}
+
+/// Answer `[%watch mode=?(%full %diff) path]` with `[pid %bind result]` and watch the path under
+/// `pid`, sending `[pid %fact fact]` when the result changes until `[pid %leave ~]`, or with
+/// `[pid %nack err=@t]` if the request or the peek fails. See [`crate::drivers::watch`].
+async fn watch(
+    handle: &NockAppHandle,
+    watches: &mut Watches,
+    pid: u64,
+    request: Noun,
+) -> Result<NounSlab, NockAppError> {
+    let mode = request.as_cell().ok().and_then(|cell| {
+        Some((
+            WatchMode::from_tag(cell.head().as_direct().ok()?.data())?,
+            cell.tail(),
+        ))
+    });
+    let error = match mode {
+        Some((mode, path)) => {
+            let mut path_slab = NounSlab::new();
+            path_slab.copy_into(path);
+            match watches.watch(handle, pid, mode, path_slab).await? {
+                Some(mut bind_slab) => {
+                    bind_slab.modify(|root| vec![D(pid), D(tas!(b"bind")), root]);
+                    return Ok(bind_slab);
+                }
+                None => "watch: peek failed",
+            }
+        }
+        None => "watch: mode must be %full or %diff",
+    };
+    debug!("npc: {}", error);
+    let mut response_slab = NounSlab::new();
+    let message = make_tas(&mut response_slab, error).as_noun();
+    let response = T(&mut response_slab, &[D(pid), D(tas!(b"nack")), message]);
+    response_slab.set_root(response);
+    Ok(response_slab)
+}
+
+/// `[pid %nack 'npc: over limit']`
+fn over_limit(pid: u64) -> NounSlab {
+    let mut slab = NounSlab::new();
+    let message = make_tas(&mut slab, "npc: over limit").as_noun();
+    let response = T(&mut slab, &[D(pid), D(tas!(b"nack")), message]);
+    slab.set_root(response);
+    slab
+}
+
+fn parse_scry(request: Noun) -> Result<(ScryEncoding, NounSlab), String> {
+    let mut fields = Fields::new(request);
+    let encoding: Noun = fields.next("enc").map_err(|e| e.to_string())?;
+    let path: String = fields.last("path").map_err(|e| e.to_string())?;
+    let encoding = ScryEncoding::from_tag(encoding)
+        .ok_or_else(|| "scry: encoding must be %jam or %json".to_string())?;
+    let mut path_slab = NounSlab::new();
+    let path = parse_path(&mut path_slab, &path).map_err(|e| e.to_string())?;
+    path_slab.set_root(path);
+    Ok((encoding, path_slab))
+}
+
+/// The next message, or `None` once the connection closes or sends one over `max_bytes`
+async fn read_message<S: AsyncRead>(
+    stream_arc: Arc<Mutex<ReadHalf<S>>>,
+    max_bytes: Option<usize>,
 ) -> Result<Option<NounSlab>, NockAppError> {
     let mut stream = stream_arc.lock_owned().await;
     let mut size_bytes = [0u8; 8];
@@ -251,6 +613,13 @@ async fn read_message(
     }
     let size = usize::from_le_bytes(size_bytes);
     debug!("Message size: {} bytes", size);
+    if let Some(max) = max_bytes.filter(|max| size > *max) {
+        warn!(
+            "npc: closing a connection that sent a {} byte message, over the {} byte limit",
+            size, max
+        );
+        return Ok(None);
+    }
     let mut buf = Vec::with_capacity(size).limit(size);
     while buf.remaining_mut() > 0 {
         debug!(
@@ -273,8 +642,8 @@ async fn read_message(
     Ok(Some(slab))
 }
 
-async fn write_message(
-    stream: &mut WriteHalf<UnixStream>,
+async fn write_message<S: AsyncWrite>(
+    stream: &mut WriteHalf<S>,
     msg_slab: NounSlab,
 ) -> Result<bool, NockAppError> {
     let msg_bytes = msg_slab.jam();
@@ -376,14 +745,15 @@ async fn write_message(
     }
 }
 
-#[cfg(test)]
+// The client side of these tests is a std Unix socket
+#[cfg(all(test, unix))]
 mod tests {
     use std::io::{Read, Write};
     use std::os::unix::net::UnixStream as StdUnixStream;
     use std::time::Duration;
 
     use tempfile::tempdir;
-    use tokio::net::UnixStream;
+    use tokio::net::{UnixListener, UnixStream};
     use tokio::sync::{broadcast, mpsc};
     use tokio::time::timeout;
     use tracing_test::traced_test;
@@ -567,7 +937,7 @@ mod tests {
         drop(client);
 
         let stream_arc = Arc::new(Mutex::new(split(server).0));
-        let result = read_message(stream_arc).await;
+        let result = read_message(stream_arc, None).await;
         assert!(result
             .unwrap_or_else(|err| {
                 panic!(
@@ -580,6 +950,17 @@ mod tests {
             .is_none());
     }
 
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_read_message_over_limit() {
+        let (server, mut client) = setup_socket_pair().await;
+        client.write_all(&(1usize << 40).to_le_bytes()).unwrap();
+
+        let stream_arc = Arc::new(Mutex::new(split(server).0));
+        let result = read_message(stream_arc, Some(1 << 20)).await;
+        assert!(result.expect("read").is_none());
+    }
+
     #[tokio::test]
     #[traced_test]
     #[cfg_attr(miri, ignore)]
@@ -594,7 +975,7 @@ mod tests {
             )
         });
         let socket_path = dir.path().join("test.sock");
-        let listener = UnixListener::bind(&socket_path).unwrap_or_else(|err| {
+        let listener = IpcListener::bind(&socket_path).unwrap_or_else(|err| {
             panic!(
                 "Panicked with {err:?} at {}:{} (git sha: {:?})",
                 file!(),
@@ -620,6 +1001,10 @@ mod tests {
             effect_receiver: Mutex::new(rx_effect),
             metrics: metrics,
             exit: tx_exit,
+            shutdown: Default::default(),
+            store: Default::default(),
+            state_changes: tokio::sync::watch::channel(0).1,
+            wire_versions: Default::default(),
         };
 
         // Spawn the listener driver
@@ -672,6 +1057,7 @@ mod tests {
             wire: _wire,
             poke: noun_slab,
             ack_channel: _,
+            span: _,
         }) = timeout(Duration::from_secs(1), rx_io.recv())
             .await
             .unwrap_or_else(|err| {
@@ -18,8 +18,18 @@ impl Wire for OnePunchWire {
 }
 
 pub fn one_punch_man(data: NounSlab, op: Operation) -> IODriverFn {
-    make_driver(|handle| async move {
+    punch(data, op, false)
+}
+
+/// Like [`one_punch_man`] poking `data`, but on a secret wire, for a poke that carries a secret
+pub fn one_punch_secret(data: NounSlab) -> IODriverFn {
+    punch(data, Operation::Poke, true)
+}
+
+fn punch(data: NounSlab, op: Operation, secret: bool) -> IODriverFn {
+    make_driver(move |handle| async move {
         let wire = OnePunchWire::Poke.to_wire();
+        let wire = if secret { wire.secret() } else { wire };
         let result = match op {
             Operation::Poke => Left(handle.poke(wire, data).await?),
             Operation::Peek => {
@@ -0,0 +1,306 @@
+//! Records every poke and effect to a rotating log on disk, for audits and forensics.
+//!
+//! [`recorder`] takes the pokes from [`NockApp::tap_pokes`](crate::NockApp::tap_pokes) and the
+//! effects its handle sees, and appends each to files `audit-<utc time>-<n>.log` in its directory as
+//! records `len: u32 | jam | blake3(jam)`, little-endian. The jammed nouns are:
+//!
+//! - `[%poke at=@ud acked=? wire cause]`
+//! - `[%effect at=@ud effect]`
+//!
+//! with times in Unix milliseconds. A new file is started once the current one passes
+//! [`RecorderConfig::max_file_bytes`], and the oldest files are deleted while the directory holds
+//! more than [`RecorderConfig::max_total_bytes`]. Records are synced to disk every
+//! [`SYNC_INTERVAL`] and on shutdown; [`read_records`] reads a file back, up to any torn record.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use nockvm::noun::{D, NO, T, YES};
+use nockvm_macros::tas;
+use tokio::sync::mpsc;
+use tracing::{debug, error, warn};
+
+use crate::nockapp::driver::{make_driver, IODriverFn, TappedPoke};
+use crate::nockapp::wire::wire_to_noun;
+use crate::nockapp::NockAppError;
+use crate::noun::slab::NounSlab;
+
+/// How often records are synced to disk
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+pub const DEFAULT_MAX_FILE_BYTES: u64 = 64 * 1024 * 1024;
+pub const DEFAULT_MAX_TOTAL_BYTES: u64 = 1024 * 1024 * 1024;
+/// How many pokes may wait for the recorder, for [`NockApp::tap_pokes`](crate::NockApp::tap_pokes)
+pub const TAP_CAPACITY: usize = 1024;
+
+const FILE_PREFIX: &str = "audit-";
+const FILE_SUFFIX: &str = ".log";
+const RECORD_HASH_LEN: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct RecorderConfig {
+    pub dir: PathBuf,
+    /// Start a new file once the current one is this big
+    pub max_file_bytes: u64,
+    /// Delete the oldest files while the directory holds more than this
+    pub max_total_bytes: u64,
+}
+
+impl RecorderConfig {
+    pub fn new(dir: PathBuf) -> Self {
+        RecorderConfig {
+            dir,
+            max_file_bytes: DEFAULT_MAX_FILE_BYTES,
+            max_total_bytes: DEFAULT_MAX_TOTAL_BYTES,
+        }
+    }
+
+    /// Keep at most `max_total_bytes`, in files of at most a sixteenth of that up to the default
+    pub fn with_max_total_bytes(mut self, max_total_bytes: u64) -> Self {
+        self.max_total_bytes = max_total_bytes;
+        self.max_file_bytes = (max_total_bytes / 16).clamp(1, DEFAULT_MAX_FILE_BYTES);
+        self
+    }
+}
+
+/// The files being recorded to
+pub struct AuditLog {
+    config: RecorderConfig,
+    file: BufWriter<File>,
+    file_len: u64,
+    /// Tells apart files started in the same microsecond
+    files_started: u64,
+}
+
+impl AuditLog {
+    /// Start a new file in the configured directory, creating it if needed
+    pub fn open(config: RecorderConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let file = create_file(&config.dir, 0)?;
+        Ok(AuditLog {
+            config,
+            file: BufWriter::new(file),
+            file_len: 0,
+            files_started: 1,
+        })
+    }
+
+    pub fn record_poke(&mut self, poke: &TappedPoke) -> io::Result<()> {
+        let mut slab: NounSlab = NounSlab::new();
+        let cause = slab.copy_into(unsafe { *poke.poke.root() });
+        let wire = wire_to_noun(&mut slab, &poke.wire);
+        let acked = if poke.acked { YES } else { NO };
+        let record = T(
+            &mut slab,
+            &[D(tas!(b"poke")), D(unix_millis(poke.at)), acked, wire, cause],
+        );
+        slab.set_root(record);
+        self.append(&slab.jam())
+    }
+
+    pub fn record_effect(&mut self, at: SystemTime, effect: &NounSlab) -> io::Result<()> {
+        let mut slab: NounSlab = NounSlab::new();
+        let effect = slab.copy_into(unsafe { *effect.root() });
+        let record = T(&mut slab, &[D(tas!(b"effect")), D(unix_millis(at)), effect]);
+        slab.set_root(record);
+        self.append(&slab.jam())
+    }
+
+    fn append(&mut self, jam: &[u8]) -> io::Result<()> {
+        if self.file_len >= self.config.max_file_bytes {
+            self.rotate()?;
+        }
+        let len = u32::try_from(jam.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "record too large"))?;
+        self.file.write_all(&len.to_le_bytes())?;
+        self.file.write_all(jam)?;
+        self.file.write_all(blake3::hash(jam).as_bytes())?;
+        self.file_len += (4 + jam.len() + RECORD_HASH_LEN) as u64;
+        Ok(())
+    }
+
+    /// Write out and sync what's been recorded
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.sync()?;
+        self.file = BufWriter::new(create_file(&self.config.dir, self.files_started)?);
+        self.file_len = 0;
+        self.files_started += 1;
+        let files = log_files(&self.config.dir)?;
+        let mut sizes = Vec::with_capacity(files.len());
+        for path in &files {
+            sizes.push(fs::metadata(path)?.len());
+        }
+        let mut total: u64 = sizes.iter().sum();
+        // The newest file is the one just started, which is never deleted
+        for (path, size) in files.iter().zip(sizes).take(files.len().saturating_sub(1)) {
+            if total <= self.config.max_total_bytes {
+                break;
+            }
+            debug!("Removing audit log {}", path.display());
+            fs::remove_file(path)?;
+            total -= size;
+        }
+        Ok(())
+    }
+}
+
+/// The audit log files in `dir`, oldest first
+pub fn log_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_log = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(FILE_PREFIX) && name.ends_with(FILE_SUFFIX));
+        if is_log {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The records in the audit log file at `path`, up to the first torn or corrupt one
+pub fn read_records(path: &Path) -> io::Result<Vec<NounSlab>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while let Some(len_bytes) = bytes.get(offset..offset + 4) {
+        let len = u32::from_le_bytes(len_bytes.try_into().expect("four bytes")) as usize;
+        let jam_end = offset + 4 + len;
+        let (Some(jam), Some(hash)) = (
+            bytes.get(offset + 4..jam_end),
+            bytes.get(jam_end..jam_end + RECORD_HASH_LEN),
+        ) else {
+            break;
+        };
+        if blake3::hash(jam).as_bytes() != hash {
+            warn!("Audit log {} is corrupt at byte {}", path.display(), offset);
+            break;
+        }
+        let mut slab: NounSlab = NounSlab::new();
+        let record = slab
+            .cue_into(Bytes::copy_from_slice(jam))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        slab.set_root(record);
+        records.push(slab);
+        offset = jam_end + RECORD_HASH_LEN;
+    }
+    Ok(records)
+}
+
+fn create_file(dir: &Path, seq: u64) -> io::Result<File> {
+    let name = format!(
+        "{}{}-{:06}{}",
+        FILE_PREFIX,
+        chrono::Utc::now().format("%Y%m%dT%H%M%S%.6fZ"),
+        seq % 1_000_000,
+        FILE_SUFFIX
+    );
+    OpenOptions::new()
+        .create_new(true)
+        .append(true)
+        .open(dir.join(name))
+}
+
+fn unix_millis(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Audit recorder driver
+///
+/// Records `pokes`, from [`NockApp::tap_pokes`](crate::NockApp::tap_pokes), and every effect to
+/// an [`AuditLog`] until the app shuts down. It doesn't poke.
+pub fn recorder(config: RecorderConfig, mut pokes: mpsc::Receiver<TappedPoke>) -> IODriverFn {
+    make_driver(move |handle| async move {
+        let mut log = AuditLog::open(config).map_err(NockAppError::IoError)?;
+        let _guard = handle.shutdown.guard();
+        let mut sync = tokio::time::interval(SYNC_INTERVAL);
+        sync.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            let recorded = tokio::select! {
+                effect = handle.next_effect() => match effect {
+                    Ok(effect) => log.record_effect(SystemTime::now(), &effect),
+                    Err(NockAppError::BroadcastRecvLaggedError(missed)) => {
+                        warn!("Audit recorder fell behind and missed {} effects", missed);
+                        continue;
+                    }
+                    Err(e) => {
+                        debug!("Audit recorder stopping: {}", e);
+                        break;
+                    }
+                },
+                Some(poke) = pokes.recv() => log.record_poke(&poke),
+                _ = sync.tick() => log.sync(),
+                _ = handle.shutdown.closing() => break,
+            };
+            if let Err(e) = recorded {
+                error!("Audit recorder could not write: {}", e);
+            }
+        }
+        log.sync().map_err(NockAppError::IoError)?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::Slots;
+
+    use super::*;
+    use crate::nockapp::wire::WireRepr;
+    use crate::NounExt;
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_audit_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = RecorderConfig {
+            dir: dir.path().to_path_buf(),
+            max_file_bytes: 1,
+            max_total_bytes: 200,
+        };
+        let mut log = AuditLog::open(config).unwrap();
+        let mut poke: NounSlab = NounSlab::new();
+        let cause = T(&mut poke, &[D(tas!(b"command")), D(7), D(0)]);
+        poke.set_root(cause);
+        let tapped = TappedPoke {
+            wire: WireRepr::no_tags("npc", 1),
+            poke: poke.clone(),
+            at: UNIX_EPOCH + Duration::from_millis(1_000),
+            acked: true,
+        };
+        log.record_poke(&tapped).unwrap();
+        log.sync().unwrap();
+        let first = log_files(dir.path()).unwrap();
+        assert_eq!(first.len(), 1);
+        let records = read_records(&first[0]).unwrap();
+        assert_eq!(records.len(), 1);
+        let record = unsafe { *records[0].root() };
+        assert!(record.slot(2).unwrap().eq_bytes(b"poke"));
+        assert_eq!(
+            record.slot(6).unwrap().as_atom().unwrap().as_u64().unwrap(),
+            1_000
+        );
+
+        // Every record after the first starts a new file, and old ones go past the limit
+        for _ in 0..20 {
+            log.record_effect(SystemTime::now(), &poke).unwrap();
+        }
+        log.sync().unwrap();
+        // Records are about 50 bytes, so a few files fit in 200
+        let files = log_files(dir.path()).unwrap();
+        assert!(files.len() > 1 && files.len() <= 5);
+        assert_ne!(files[0], first[0]);
+    }
+}
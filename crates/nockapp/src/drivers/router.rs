@@ -0,0 +1,189 @@
+//! Routing between kernels hosted in the same process.
+//!
+//! Each NockApp that should talk to the others gets a driver from one shared [`Router`], under a
+//! name. A kernel pokes another kernel by emitting the effect `[%route target=@tas poke=*]`. The
+//! router delivers `poke` to the kernel registered as `target` on the wire `/route/1/<source>`,
+//! so the receiving kernel knows which kernel sent it.
+//!
+//! ```ignore
+//! let router = Router::new();
+//! node.add_io_driver(router.driver("node")).await;
+//! wallet.add_io_driver(router.driver("wallet")).await;
+//! tokio::try_join!(node.run(), wallet.run())?;
+//! ```
+//!
+//! Every kernel keeps its own serf thread and state. The kernels share the jet code, the
+//! tokio runtime, and anything else the embedding binary sets up once.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use nockvm::noun::Noun;
+use tokio::sync::mpsc;
+use tracing::{debug, error, warn};
+
+use crate::nockapp::driver::{make_driver, IODriverFn, PokeResult};
+use crate::nockapp::wire::{Wire, WireRepr};
+use crate::nockapp::NockAppError;
+use crate::noun::path::{Fields, PathError};
+use crate::noun::slab::NounSlab;
+use crate::NounExt;
+
+pub struct RouterWire {
+    pub source: String,
+}
+
+impl Wire for RouterWire {
+    const VERSION: u64 = 1;
+    const SOURCE: &'static str = "route";
+
+    fn to_wire(&self) -> WireRepr {
+        WireRepr::new(
+            RouterWire::SOURCE,
+            RouterWire::VERSION,
+            vec![self.source.as_str().into()],
+        )
+    }
+}
+
+struct Routed {
+    source: String,
+    poke: NounSlab,
+}
+
+// Unbounded so two kernels poking each other can't deadlock on full queues
+type Inbox = mpsc::UnboundedSender<Routed>;
+
+/// Forwards `%route` effects between the kernels registered with it
+#[derive(Clone, Default)]
+pub struct Router {
+    routes: Arc<Mutex<HashMap<String, Inbox>>>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names of the kernels currently registered
+    pub fn kernels(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.lock().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Make the driver for the kernel called `name`.
+    ///
+    /// Registering a second driver under the same name replaces the first.
+    pub fn driver(&self, name: &str) -> IODriverFn {
+        let name = name.to_string();
+        let (inbox, mut pokes) = mpsc::unbounded_channel();
+        if self.lock().insert(name.clone(), inbox).is_some() {
+            warn!("router: replacing kernel registered as {}", name);
+        }
+        let router = self.clone();
+        make_driver(move |handle| async move {
+            loop {
+                tokio::select! {
+                    effect = handle.next_effect() => {
+                        match effect {
+                            Ok(slab) => router.route(&name, &slab),
+                            Err(NockAppError::BroadcastRecvClosedError) => break,
+                            Err(e) => error!("router: error receiving effect: {:?}", e),
+                        }
+                    }
+                    Some(routed) = pokes.recv() => {
+                        let wire = RouterWire { source: routed.source.clone() }.to_wire();
+                        if let PokeResult::Nack = handle.poke(wire, routed.poke).await? {
+                            warn!("router: {} nacked poke from {}", name, routed.source);
+                        }
+                    }
+                }
+            }
+            router.lock().remove(&name);
+            Ok(())
+        })
+    }
+
+    fn route(&self, source: &str, slab: &NounSlab) {
+        let (target, poke) = match parse_route(unsafe { *slab.root() }) {
+            Ok(Some(route)) => route,
+            Ok(None) => return,
+            Err(e) => {
+                debug!("router: malformed effect: {}", e);
+                return;
+            }
+        };
+        let routes = self.lock();
+        let Some(inbox) = routes.get(&target) else {
+            warn!(
+                "router: {} routed a poke to unknown kernel {}",
+                source, target
+            );
+            return;
+        };
+        let mut poke_slab = NounSlab::new();
+        poke_slab.copy_into(poke);
+        let routed = Routed {
+            source: source.to_string(),
+            poke: poke_slab,
+        };
+        if inbox.send(routed).is_err() {
+            warn!("router: kernel {} has stopped", target);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Inbox>> {
+        self.routes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Parse `[%route target=@tas poke=*]`, or `None` for any other effect.
+fn parse_route(effect: Noun) -> Result<Option<(String, Noun)>, PathError> {
+    let is_route = effect
+        .as_cell()
+        .map(|cell| cell.head().eq_bytes(b"route"))
+        .unwrap_or(false);
+    if !is_route {
+        return Ok(None);
+    }
+    let mut fields = Fields::new(effect);
+    fields.tag(b"route")?;
+    let target: String = fields.next("target")?;
+    let poke: Noun = fields.last("poke")?;
+    Ok(Some((target, poke)))
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::{D, T};
+    use nockvm_macros::tas;
+
+    use super::*;
+    use crate::noun::slab::slab_noun_equality;
+
+    #[test]
+    fn test_route_delivers_to_target() {
+        let router = Router::new();
+        let (inbox, mut pokes) = mpsc::unbounded_channel();
+        router.lock().insert("wallet".to_string(), inbox);
+
+        let mut slab: NounSlab = NounSlab::new();
+        let poke = T(&mut slab, &[D(tas!(b"balance")), D(7)]);
+        let effect = T(&mut slab, &[D(tas!(b"route")), D(tas!(b"wallet")), poke]);
+        slab.set_root(effect);
+        router.route("node", &slab);
+
+        let routed = pokes.try_recv().expect("routed poke");
+        assert_eq!(routed.source, "node");
+        assert!(slab_noun_equality(unsafe { routed.poke.root() }, &poke));
+
+        // Other effects and unknown targets are dropped
+        let other = T(&mut slab, &[D(tas!(b"file")), D(0)]);
+        slab.set_root(other);
+        router.route("node", &slab);
+        let unknown = T(&mut slab, &[D(tas!(b"route")), D(tas!(b"miner")), D(0)]);
+        slab.set_root(unknown);
+        router.route("node", &slab);
+        assert!(pokes.try_recv().is_err());
+    }
+}
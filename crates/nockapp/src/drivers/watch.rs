@@ -0,0 +1,232 @@
+//! Peek paths that clients watch, peeked again whenever the kernel's state may have changed.
+//!
+//! A driver keeps a [`Watches`] for its clients, calls [`Watches::watch`] when one asks to watch a
+//! path, and [`Watches::refresh`] each time [`NockAppHandle::state_changes`] ticks, sending the
+//! facts it returns. A fact is `[%full result]`, the new peek result, or `[%diff edits]`, the
+//! [`NounDiff`](crate::noun::diff::NounDiff) from the last result sent as a
+//! `(list [axis=@ new=*])` that [`patch`](crate::noun::diff::patch) applies. The NPC driver serves
+//! these as `[pid %watch mode path]`, see its module.
+use std::collections::BTreeMap;
+
+use nockvm::noun::{D, T};
+use nockvm_macros::tas;
+
+use crate::nockapp::driver::NockAppHandle;
+use crate::nockapp::NockAppError;
+use crate::noun::diff::diff;
+use crate::noun::slab::NounSlab;
+
+/// What a watcher is sent when its result changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchMode {
+    /// The whole new result
+    Full,
+    /// The edits from the last result
+    Diff,
+}
+
+impl WatchMode {
+    /// `%full` or `%diff`
+    pub fn from_tag(tag: u64) -> Option<Self> {
+        match tag {
+            tas!(b"full") => Some(WatchMode::Full),
+            tas!(b"diff") => Some(WatchMode::Diff),
+            _ => None,
+        }
+    }
+}
+
+struct Watch {
+    path: NounSlab,
+    mode: WatchMode,
+    last: NounSlab,
+}
+
+/// The paths a driver's clients watch, by an id the driver picks
+#[derive(Default)]
+pub struct Watches {
+    watches: BTreeMap<u64, Watch>,
+}
+
+impl Watches {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.watches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    /// Watch `path` under `id`, replacing what `id` watched, and return the current result, or
+    /// `None` without watching if the peek fails
+    pub async fn watch(
+        &mut self,
+        handle: &NockAppHandle,
+        id: u64,
+        mode: WatchMode,
+        path: NounSlab,
+    ) -> Result<Option<NounSlab>, NockAppError> {
+        let Some(result) = handle.peek(path.clone()).await? else {
+            return Ok(None);
+        };
+        self.watches.insert(
+            id,
+            Watch {
+                path,
+                mode,
+                last: result.clone(),
+            },
+        );
+        Ok(Some(result))
+    }
+
+    /// Stop watching under `id`, returning whether anything was
+    pub fn leave(&mut self, id: u64) -> bool {
+        self.watches.remove(&id).is_some()
+    }
+
+    /// Peek every watched path again, returning a fact for each whose result changed. A peek that
+    /// fails is skipped, to be tried again on the next change.
+    pub async fn refresh(
+        &mut self,
+        handle: &NockAppHandle,
+    ) -> Result<Vec<(u64, NounSlab)>, NockAppError> {
+        let mut facts = Vec::new();
+        for (id, watch) in self.watches.iter_mut() {
+            let Some(mut result) = handle.peek(watch.path.clone()).await? else {
+                continue;
+            };
+            let (old, new) = unsafe { (*watch.last.root(), *result.root()) };
+            let edits = diff(old, new);
+            if edits.is_empty() {
+                continue;
+            }
+            let mut fact = NounSlab::new();
+            let root = match watch.mode {
+                WatchMode::Full => {
+                    let value = fact.copy_into(new);
+                    T(&mut fact, &[D(tas!(b"full")), value])
+                }
+                WatchMode::Diff => {
+                    // The edits point into the result, so they're encoded there and copied over
+                    let list = edits.to_noun(&mut result);
+                    let list = fact.copy_into(list);
+                    T(&mut fact, &[D(tas!(b"diff")), list])
+                }
+            };
+            fact.set_root(root);
+            watch.last = result;
+            facts.push((*id, fact));
+        }
+        Ok(facts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::{broadcast, mpsc, watch, Mutex};
+
+    use super::*;
+    use crate::metrics::NockAppMetrics;
+    use crate::nockapp::driver::IOAction;
+    use crate::noun::diff::patch;
+    use crate::noun::slab::slab_equality;
+    use crate::NockAppExit;
+
+    /// A handle whose peeks are answered with `[state path]`
+    fn handle(state: watch::Receiver<u64>) -> NockAppHandle {
+        let (io_sender, mut io_receiver) = mpsc::channel(8);
+        let (effect_sender, effect_receiver) = broadcast::channel(8);
+        let answers = state.clone();
+        tokio::spawn(async move {
+            while let Some(action) = io_receiver.recv().await {
+                if let IOAction::Peek {
+                    path,
+                    result_channel,
+                    ..
+                } = action
+                {
+                    let mut slab = NounSlab::new();
+                    let path = slab.copy_into(unsafe { *path.root() });
+                    let root = T(&mut slab, &[D(*answers.borrow()), path]);
+                    slab.set_root(root);
+                    let _ = result_channel.send(Some(slab));
+                }
+            }
+        });
+        NockAppHandle {
+            io_sender,
+            effect_sender: Arc::new(effect_sender),
+            effect_receiver: Mutex::new(effect_receiver),
+            metrics: Arc::new(
+                NockAppMetrics::register(gnort::global_metrics_registry())
+                    .expect("Failed to register metrics!"),
+            ),
+            exit: NockAppExit::new().0,
+            shutdown: Default::default(),
+            store: Default::default(),
+            state_changes: state,
+            wire_versions: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_watches() {
+        let (state, state_changes) = watch::channel(1);
+        let handle = handle(state_changes);
+        let mut watches = Watches::new();
+        let path = |n: u64| NounSlab::from(D(n));
+
+        let full = watches
+            .watch(&handle, 1, WatchMode::Full, path(10))
+            .await
+            .unwrap()
+            .unwrap();
+        let old = watches
+            .watch(&handle, 2, WatchMode::Diff, path(20))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            unsafe {
+                full.root()
+                    .as_cell()
+                    .unwrap()
+                    .head()
+                    .as_direct()
+                    .unwrap()
+                    .data()
+            },
+            1
+        );
+        assert!(watches.refresh(&handle).await.unwrap().is_empty());
+
+        state.send_replace(2);
+        let facts = watches.refresh(&handle).await.unwrap();
+        assert_eq!(
+            facts.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        let fact = unsafe { *facts[1].1.root() }.as_cell().unwrap();
+        assert_eq!(fact.head().as_direct().unwrap().data(), tas!(b"diff"));
+        let mut patched = NounSlab::new();
+        let root = patch(&mut patched, unsafe { *old.root() }, fact.tail()).unwrap();
+        patched.set_root(root);
+        let mut expected = NounSlab::new();
+        let root = T(&mut expected, &[D(2), D(20)]);
+        expected.set_root(root);
+        assert!(slab_equality(&patched, &expected));
+
+        assert!(watches.leave(1));
+        assert!(!watches.leave(1));
+        state.send_replace(3);
+        assert_eq!(watches.refresh(&handle).await.unwrap().len(), 1);
+    }
+}
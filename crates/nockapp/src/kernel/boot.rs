@@ -1,24 +1,45 @@
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 use chrono;
-use clap::{arg, command, ColorChoice, Parser, ValueEnum};
+use clap::{arg, command, ColorChoice, Parser, Subcommand, ValueEnum};
 use nockvm::jets::hot::HotEntry;
+use nockvm::memo::{MemoConfig, MemoEviction};
 use nockvm::noun::Atom;
 use tokio::fs;
+use tracing::field::{Field, Visit};
 use tracing::{debug, info, Level};
+use tracing_subscriber::filter::Targets;
 use tracing_subscriber::fmt::format::Writer;
 use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
-use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::layer::{Identity, Layer, SubscriberExt};
 use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::util::SubscriberInitExt;
-use tracing_subscriber::{fmt, EnvFilter};
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
 
+use crate::dead_letter::{DeadLetterError, DeadLetters, DEAD_LETTERS_DIR};
+use crate::drivers::hot_load::HOT_LOAD_INTERVAL;
+use crate::drivers::recorder::{RecorderConfig, TAP_CAPACITY};
 use crate::export::ExportedState;
-use crate::kernel::form::Kernel;
+use crate::kernel::debugger::{self, Debugger};
+use crate::kernel::form::{Kernel, StackConfig, SERF_THREAD_STACK_SIZE};
+use crate::kernel::replica::Replica;
+use crate::noun::pretty::PrettyNoun;
 use crate::noun::slab::{Jammer, NounSlab};
-use crate::save::SaveableCheckpoint;
+use crate::quota::{PokeQuotas, SourceQuota};
+use crate::save::{
+    history_dir, list_history, CheckpointPolicy, Compression, DeltaPolicy, RetentionPolicy,
+    SaveableCheckpoint, Saver,
+};
+use crate::shutdown::DEFAULT_SHUTDOWN_DEADLINE;
+use crate::utils::daemon::{self, PidFile};
 use crate::utils::error::{CrownError, ExternalError};
-use crate::{default_data_dir, AtomExt, NockApp};
+use crate::utils::{
+    autodetect_nock_stack_size, NOCK_STACK_SIZE, NOCK_STACK_SIZE_HUGE, NOCK_STACK_SIZE_LARGE,
+    NOCK_STACK_SIZE_MEDIUM, NOCK_STACK_SIZE_SMALL, NOCK_STACK_SIZE_TINY,
+};
+use crate::{default_data_dir, observability, AtomExt, NockApp};
 
 const DEFAULT_SAVE_INTERVAL: u64 = 120000;
 const DEFAULT_LOG_FILTER: &str = "info,slogger=trace";
@@ -31,6 +52,32 @@ pub enum NockStackSize {
     Medium,
     Large,
     Huge,
+    /// The largest preset that fits in physical memory
+    Auto,
+}
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Text, minimal unless `RUST_LOG` is set and `MINIMAL_LOG_FORMAT` isn't
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl NockStackSize {
+    /// The size in 64-bit words
+    pub fn words(&self) -> usize {
+        match self {
+            NockStackSize::Tiny => NOCK_STACK_SIZE_TINY,
+            NockStackSize::Small => NOCK_STACK_SIZE_SMALL,
+            NockStackSize::Normal => NOCK_STACK_SIZE,
+            NockStackSize::Medium => NOCK_STACK_SIZE_MEDIUM,
+            NockStackSize::Large => NOCK_STACK_SIZE_LARGE,
+            NockStackSize::Huge => NOCK_STACK_SIZE_HUGE,
+            NockStackSize::Auto => autodetect_nock_stack_size(),
+        }
+    }
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -49,10 +96,249 @@ pub struct Cli {
     #[arg(
         long,
         default_value_t = DEFAULT_SAVE_INTERVAL,
-        help = "Set the save interval for checkpoints (in ms)"
+        help = "Set the save interval for checkpoints (in ms), or 0 to disable timed checkpoints"
     )]
     pub save_interval: u64,
 
+    #[arg(
+        long,
+        help = "Also checkpoint once this many events have been processed since the last checkpoint"
+    )]
+    pub checkpoint_every_events: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Exit without writing a final checkpoint",
+        default_value = "false"
+    )]
+    pub no_checkpoint_on_exit: bool,
+
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "Compress checkpoints with zstd at this level (1 to 22), or 0 to write them uncompressed"
+    )]
+    pub checkpoint_zstd_level: i32,
+
+    #[arg(
+        long,
+        help = "Write up to this many checkpoints as deltas against the last full one, 0 to always write full checkpoints. Deltas keep a copy of the last full checkpoint in memory",
+        default_value = "0"
+    )]
+    pub checkpoint_deltas: usize,
+
+    #[arg(
+        long,
+        help = "Keep at most this many past full checkpoints in the checkpoint history. The history is kept when this or either of the limits below is set"
+    )]
+    pub checkpoint_history: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Delete checkpoints from the history once they are this many hours old"
+    )]
+    pub checkpoint_history_max_age_hours: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Delete the oldest checkpoints from the history until it takes at most this many megabytes"
+    )]
+    pub checkpoint_history_max_mb: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Log every event next to the checkpoints and replay events since the last checkpoint on boot",
+        default_value = "false"
+    )]
+    pub event_log: bool,
+
+    #[arg(
+        long,
+        help = "Replay the event log in this directory, check the kernel state against the mugs recorded at each checkpoint, and exit. Fails at the first divergence.",
+        group = "replay_log"
+    )]
+    pub replay: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Step through the event log in this directory from a prompt, peeking the kernel state at any event, and exit",
+        group = "replay_log"
+    )]
+    pub debug: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Checkpoint file to start --replay or --debug from, such as one from the checkpoint history. Without it they start from a freshly booted kernel.",
+        requires = "replay_log"
+    )]
+    pub replay_from: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Interrupt any poke that runs longer than this many seconds and fail it with %poke-timeout"
+    )]
+    pub poke_timeout_secs: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Seconds to let a shutdown finish in-flight work and write its checkpoint before forcing it",
+        default_value_t = DEFAULT_SHUTDOWN_DEADLINE.as_secs()
+    )]
+    pub shutdown_timeout_secs: u64,
+
+    #[arg(
+        long,
+        help = "Write a crash dump with the failed poke, its stack trace and recent effects to the data directory whenever a poke bails",
+        default_value = "false"
+    )]
+    pub crash_dumps: bool,
+
+    #[arg(
+        long,
+        help = "Keep pokes from drivers that bail or time out in the data directory, for the dead-letters command to list, show, retry or remove",
+        default_value = "false"
+    )]
+    pub dead_letters: bool,
+
+    #[arg(
+        long,
+        help = "Profile kernel computations and write folded stacks for a flamegraph to this file on exit"
+    )]
+    pub profile: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Most results the interpreter's memo cache may hold. Unbounded by default"
+    )]
+    pub memo_cache_entries: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Once the memo cache is full, keep its entries and stop caching new results, instead of flushing it",
+        default_value = "false",
+        requires = "memo_cache_entries"
+    )]
+    pub memo_cache_retain: bool,
+
+    #[arg(
+        long,
+        help = "Compile formulas into bytecode once the kernel has called them this many times"
+    )]
+    pub bytecode_threshold: Option<u32>,
+
+    #[arg(
+        long,
+        help = "Check one in every N jet calls against the Nock it replaces, writing mismatches to the jet-mismatches directory"
+    )]
+    pub jet_shadow_check: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Count Nock steps, jet hits and loom allocations, reporting them as metrics after every poke and peek",
+        default_value = "false"
+    )]
+    pub nock_stats: bool,
+
+    #[arg(
+        long,
+        help = "Keep the kernel's loom in a persistent memory arena in the data directory, saving by flushing its dirty pages instead of writing checkpoints. Linux only, since it finds dirty pages in /proc/self/pagemap",
+        default_value = "false",
+        conflicts_with = "peek_replica_refresh_secs"
+    )]
+    pub pma: bool,
+
+    #[arg(
+        long,
+        help = "Serve peeks from IO drivers with a read-only replica of the kernel, reloaded from the checkpoints this often (in seconds)"
+    )]
+    pub peek_replica_refresh_secs: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Number of serfs the peek replica runs peeks on in parallel",
+        default_value = "1"
+    )]
+    pub peek_workers: usize,
+
+    #[arg(
+        long,
+        help = "Cache up to this many results of driver peeks, answering the same peek from the cache until the next poke",
+        conflicts_with = "peek_replica_refresh_secs"
+    )]
+    pub peek_cache_entries: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "SOURCE:MAX_BYTES:RATE",
+        help = "Nack pokes from a wire source over this size in bytes or rate a second, leaving either empty for no limit and with * as the source for all others, like npc:1048576:50. Repeatable"
+    )]
+    pub poke_quota: Vec<SourceQuota>,
+
+    #[arg(
+        long,
+        help = "Upgrade the running kernel to the jam at this path whenever it changes, like the output of hoonc --watch",
+        conflicts_with = "peek_replica_refresh_secs"
+    )]
+    pub hot_load: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Record every poke and effect, jammed and timestamped, to a rotating log in this directory"
+    )]
+    pub audit_log: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Delete the oldest audit log files past this many MiB",
+        default_value = "1024",
+        requires = "audit_log"
+    )]
+    pub audit_log_max_mb: u64,
+
+    #[arg(
+        long,
+        help = "Run in the background, detached from the terminal, printing the new process's id and exiting",
+        default_value = "false"
+    )]
+    pub detach: bool,
+
+    #[arg(
+        long,
+        help = "Append the output of a detached process to this file instead of discarding it",
+        requires = "detach"
+    )]
+    pub daemon_log: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Write the process id to this file while running, refusing to start if it names a running process"
+    )]
+    pub pid_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "OTEL_EXPORTER_OTLP_ENDPOINT",
+        help = "Export spans over OTLP (gRPC) to this collector, like http://localhost:4317 for Jaeger or Tempo"
+    )]
+    pub otlp_endpoint: Option<String>,
+
+    #[arg(
+        long,
+        env = "OTEL_SERVICE_NAME",
+        help = "Service name of exported spans",
+        default_value = "nockapp"
+    )]
+    pub otlp_service_name: String,
+
+    #[arg(
+        long,
+        env = "LOG_FORMAT",
+        help = "Write the log as text or as one JSON object per line",
+        value_enum,
+        default_value_t = LogFormat::Text
+    )]
+    pub log_format: LogFormat,
+
     #[arg(long, help = "Control colored output", value_enum, default_value_t = ColorChoice::Auto)]
     pub color: ColorChoice,
 
@@ -68,6 +354,13 @@ pub struct Cli {
     )]
     pub export_state_jam: Option<String>,
 
+    #[arg(
+        long,
+        help = "Path to a jam file in the ExportedState format to import. The imported state is saved as a checkpoint and the app exits without running.",
+        conflicts_with_all = ["state_jam", "export_state_jam"]
+    )]
+    pub import_state_jam: Option<String>,
+
     #[arg(
         long,
         help = "Nock stack size to use",
@@ -75,6 +368,36 @@ pub struct Cli {
         default_value_t = NockStackSize::Normal
     )]
     pub stack_size: NockStackSize,
+
+    #[arg(
+        long,
+        help = "Nock stack size in GiB, overriding --stack-size",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub nock_stack_gb: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Grow the nock stack when the kernel runs out of memory, up to this many GiB",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with = "pma"
+    )]
+    pub nock_stack_max_gb: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Native stack size of the serf thread in MiB",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub serf_thread_stack_mb: Option<u64>,
+
+    /// Checkpoint maintenance to run instead of booting, set by [`StateCommand::Checkpoints`]
+    #[arg(skip)]
+    pub checkpoints_command: Option<CheckpointsCommand>,
+
+    /// Dead letter maintenance to run instead of booting, set by [`StateCommand::DeadLetters`]
+    #[arg(skip)]
+    pub dead_letters_command: Option<DeadLettersCommand>,
 }
 
 /// Result of setting up a NockApp
@@ -83,17 +406,168 @@ pub enum SetupResult<J> {
     App(NockApp<J>),
     /// State was exported successfully
     ExportedState,
+    /// State was imported and checkpointed successfully
+    ImportedState,
+    /// A checkpoint maintenance command ran
+    CheckpointsCommand,
+    /// A dead letter maintenance command ran
+    DeadLettersCommand,
+    /// An event log was replayed without diverging
+    Replayed,
+    /// An event log was stepped through with the debugger
+    Debugged,
+}
+
+/// Offline state migration, for apps to offer as subcommands
+///
+/// Both commands load the app's checkpoint (or boot fresh), move the kernel state in or out as
+/// an ExportedState jam, and exit without running drivers.
+#[derive(Subcommand, Debug, Clone)]
+pub enum StateCommand {
+    /// Export the current kernel state to a jam file
+    ExportState { file: String },
+    /// Import kernel state from a jam file and save it as a checkpoint
+    ImportState { file: String },
+    /// List or delete checkpoints
+    Checkpoints {
+        #[command(subcommand)]
+        command: CheckpointsCommand,
+    },
+    /// List, show, retry or remove the pokes kept with --dead-letters
+    DeadLetters {
+        #[command(subcommand)]
+        command: DeadLettersCommand,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CheckpointsCommand {
+    /// List the live checkpoints and the checkpoint history
+    Ls,
+    /// Delete checkpoints from the history. The live checkpoints are never deleted.
+    Rm {
+        /// Event numbers of the checkpoints to delete
+        event_nums: Vec<u64>,
+        /// Delete every checkpoint in the history from before this event number
+        #[arg(long)]
+        before: Option<u64>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum DeadLettersCommand {
+    /// List the failed pokes, oldest first
+    Ls,
+    /// Print a failed poke's error, wire and cause
+    Show { id: String },
+    /// Poke these again, as soon as the running app looks or when it next starts
+    Retry {
+        ids: Vec<String>,
+        /// Retry every failed poke
+        #[arg(long, conflicts_with = "ids")]
+        all: bool,
+    },
+    /// Discard failed pokes
+    Rm {
+        ids: Vec<String>,
+        /// Discard every failed poke
+        #[arg(long, conflicts_with = "ids")]
+        all: bool,
+    },
+}
+
+impl StateCommand {
+    /// Set the boot flags that carry out this command.
+    pub fn apply(self, cli: &mut Cli) {
+        match self {
+            StateCommand::ExportState { file } => cli.export_state_jam = Some(file),
+            StateCommand::ImportState { file } => cli.import_state_jam = Some(file),
+            StateCommand::Checkpoints { command } => cli.checkpoints_command = Some(command),
+            StateCommand::DeadLetters { command } => cli.dead_letters_command = Some(command),
+        }
+    }
 }
 
 pub fn default_boot_cli(new: bool) -> Cli {
     Cli {
         save_interval: DEFAULT_SAVE_INTERVAL,
+        checkpoint_every_events: None,
+        no_checkpoint_on_exit: false,
+        checkpoint_zstd_level: 0,
+        checkpoint_deltas: 0,
+        checkpoint_history: None,
+        checkpoint_history_max_age_hours: None,
+        checkpoint_history_max_mb: None,
+        event_log: false,
+        replay: None,
+        debug: None,
+        replay_from: None,
+        poke_timeout_secs: None,
+        shutdown_timeout_secs: DEFAULT_SHUTDOWN_DEADLINE.as_secs(),
+        crash_dumps: false,
+        dead_letters: false,
+        profile: None,
+        memo_cache_entries: None,
+        memo_cache_retain: false,
+        bytecode_threshold: None,
+        jet_shadow_check: None,
+        nock_stats: false,
+        pma: false,
+        peek_replica_refresh_secs: None,
+        peek_workers: 1,
+        peek_cache_entries: None,
+        poke_quota: Vec::new(),
+        hot_load: None,
+        audit_log: None,
+        audit_log_max_mb: 1024,
+        detach: false,
+        daemon_log: None,
+        pid_file: None,
+        otlp_endpoint: None,
+        otlp_service_name: "nockapp".to_string(),
         new,
         trace: false,
+        log_format: LogFormat::Text,
         color: ColorChoice::Auto,
         state_jam: None,
         export_state_jam: None,
+        import_state_jam: None,
+        checkpoints_command: None,
+        dead_letters_command: None,
         stack_size: NockStackSize::Normal,
+        nock_stack_gb: None,
+        nock_stack_max_gb: None,
+        serf_thread_stack_mb: None,
+    }
+}
+
+impl Cli {
+    /// The stack sizes the kernel's serf runs with
+    pub fn stack_config(&self) -> StackConfig {
+        let nock_stack_size = match self.nock_stack_gb {
+            // 2^27 words of 8 bytes in a GiB
+            Some(gb) => (gb as usize) << 27,
+            None => self.stack_size.words(),
+        };
+        let thread_stack_size = match self.serf_thread_stack_mb {
+            Some(mb) => (mb as usize) << 20,
+            None => SERF_THREAD_STACK_SIZE,
+        };
+        StackConfig {
+            nock_stack_size,
+            thread_stack_size,
+        }
+    }
+
+    /// Which historical checkpoints are kept, for the `--checkpoint-history` flags
+    pub fn retention_policy(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            keep: self.checkpoint_history,
+            max_age: self
+                .checkpoint_history_max_age_hours
+                .map(|hours| Duration::from_secs(hours * 60 * 60)),
+            max_bytes: self.checkpoint_history_max_mb.map(|mb| mb * 1024 * 1024),
+        }
     }
 }
 
@@ -193,36 +667,155 @@ where
     }
 }
 
+/// Writes each event as a JSON object on one line, with its fields and the names of the spans
+/// it's in
+struct JsonFormatter;
+
+impl<S, N> FormatEvent<S, N> for JsonFormatter
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &tracing::Event<'_>,
+    ) -> std::fmt::Result {
+        let mut fields = JsonFields::default();
+        event.record(&mut fields);
+        let spans: Vec<&str> = ctx
+            .event_scope()
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+            .map(|span| span.name())
+            .collect();
+        let metadata = event.metadata();
+        let line = serde_json::json!({
+            "timestamp": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+            "level": metadata.level().to_string(),
+            "target": metadata.target(),
+            "fields": fields.0,
+            "spans": spans,
+        });
+        writeln!(writer, "{}", line)
+    }
+}
+
+#[derive(Default)]
+struct JsonFields(serde_json::Map<String, serde_json::Value>);
+
+impl JsonFields {
+    fn insert(&mut self, field: &Field, value: impl Into<serde_json::Value>) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+}
+
+impl Visit for JsonFields {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.insert(field, format!("{:?}", value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value);
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value);
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value);
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value);
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value);
+    }
+}
+
 /// Initialize tracing with appropriate configuration based on CLI arguments.
 pub fn init_default_tracing(cli: &Cli) {
-    let filter = EnvFilter::new(
-        std::env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_LOG_FILTER.to_string()),
-    );
+    init_tracing(cli, None::<Identity>);
+}
+
+/// Like [`init_default_tracing`], but with the kernel's slogs sent to `slog_layer` instead of the
+/// log
+pub fn init_tracing_with_slogs<L>(cli: &Cli, slog_layer: L)
+where
+    L: Layer<Registry> + Send + Sync + 'static,
+{
+    init_tracing(cli, Some(slog_layer));
+}
+
+fn init_tracing<L>(cli: &Cli, slog_layer: Option<L>)
+where
+    L: Layer<Registry> + Send + Sync + 'static,
+{
+    let directives = std::env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_LOG_FILTER.to_string());
+    let suffix = if slog_layer.is_some() {
+        ",slogger=off"
+    } else {
+        ""
+    };
+    let (filter, filter_handle) =
+        reload::Layer::new(EnvFilter::new(format!("{}{}", directives, suffix)));
+    let tracer = cli.otlp_endpoint.as_ref().and_then(|endpoint| {
+        match observability::otlp_tracer(endpoint, &cli.otlp_service_name) {
+            Ok(tracer) => Some(tracer),
+            Err(e) => {
+                eprintln!("Failed to export traces to {}: {}", endpoint, e);
+                None
+            }
+        }
+    });
     let use_ansi = cli.color == ColorChoice::Auto || cli.color == ColorChoice::Always;
 
     // Build and initialize the subscriber
     // If RUST_LOG is set and MINIMAL_LOG_FORMAT is unset, we will do production-grade logging.
     // Otherwise we will do more minimal logging suitable for an interactive terminal.
-    if std::env::var("MINIMAL_LOG_FORMAT").is_ok() || std::env::var("RUST_LOG").is_err() {
-        let fmt_layer = fmt::layer()
+    let minimal = std::env::var("MINIMAL_LOG_FORMAT").is_ok() || std::env::var("RUST_LOG").is_err();
+    let fmt_layer = match cli.log_format {
+        LogFormat::Json => fmt::layer()
+            .event_format(JsonFormatter)
+            .with_filter(filter)
+            .boxed(),
+        LogFormat::Text if minimal => fmt::layer()
             .with_ansi(use_ansi)
-            .event_format(MinimalFormatter);
+            .event_format(MinimalFormatter)
+            .with_filter(filter)
+            .boxed(),
+        LogFormat::Text => fmt::layer()
+            .with_ansi(use_ansi)
+            .with_target(true)
+            .with_level(true)
+            .with_filter(filter)
+            .boxed(),
+    };
 
-        tracing_subscriber::registry()
-            .with(fmt_layer)
-            .with(filter)
-            .init();
-    } else {
-        tracing_subscriber::registry()
-            .with(
-                fmt::layer()
-                    .with_ansi(use_ansi)
-                    .with_target(true)
-                    .with_level(true),
-            )
-            .with(filter)
-            .init();
+    let mut layers = Vec::new();
+    if let Some(slog_layer) = slog_layer {
+        layers.push(
+            slog_layer
+                .with_filter(Targets::new().with_target("slogger", Level::TRACE))
+                .boxed(),
+        );
+    }
+    layers.push(fmt_layer);
+    if let Some(tracer) = tracer {
+        // Spans are exported by the filter the node started with, whatever the log is changed to
+        layers.push(
+            tracing_opentelemetry::layer()
+                .with_tracer(tracer)
+                .with_filter(EnvFilter::new(&directives))
+                .boxed(),
+        );
     }
+    tracing_subscriber::registry().with(layers).init();
+    observability::set_log_filter_handle(filter_handle, suffix);
 }
 
 pub async fn setup<J: Jammer + Send + 'static>(
@@ -246,6 +839,16 @@ pub async fn setup<J: Jammer + Send + 'static>(
             info!("Exiting after successful state export");
             std::process::exit(0);
         }
+        SetupResult::ImportedState => {
+            info!("Exiting after successful state import");
+            std::process::exit(0);
+        }
+        SetupResult::CheckpointsCommand | SetupResult::DeadLettersCommand => std::process::exit(0),
+        SetupResult::Replayed => {
+            info!("Exiting after successful replay");
+            std::process::exit(0);
+        }
+        SetupResult::Debugged => std::process::exit(0),
     }
 }
 
@@ -258,6 +861,9 @@ pub async fn setup_<J: Jammer + Send + 'static>(
 ) -> Result<SetupResult<J>, Box<dyn std::error::Error>> {
     let nock_test_jets_env = std::env::var("NOCK_TEST_JETS").unwrap_or_default();
     let test_jets = parse_test_jets(nock_test_jets_env.as_str());
+    if cli.pma && !cfg!(target_os = "linux") {
+        return Err("--pma is only supported on Linux".into());
+    }
     let data_dir = if let Some(data_path) = data_dir.clone() {
         data_path.join(name)
     } else {
@@ -271,7 +877,59 @@ pub async fn setup_<J: Jammer + Send + 'static>(
         debug!("Created jams directory: {:?}", jams_dir);
     }
 
-    if pma_dir.exists() {
+    if let Some(command) = cli.checkpoints_command.clone() {
+        run_checkpoints_command(command, &jams_dir)?;
+        return Ok(SetupResult::CheckpointsCommand);
+    }
+
+    let dead_letters_dir = data_dir.join(DEAD_LETTERS_DIR);
+    if let Some(command) = cli.dead_letters_command.clone() {
+        run_dead_letters_command(command, &DeadLetters::new(dead_letters_dir))?;
+        return Ok(SetupResult::DeadLettersCommand);
+    }
+
+    if cli.replay.is_some() || cli.debug.is_some() {
+        let checkpoint = match &cli.replay_from {
+            Some(path) => Some(Saver::<J>::load_file::<SaveableCheckpoint>(path, None).await?),
+            None => None,
+        };
+        let kernel: Kernel<SaveableCheckpoint> = Kernel::load_with_stack_config(
+            jam,
+            checkpoint,
+            hot_state,
+            cli.stack_config(),
+            test_jets,
+            cli.trace,
+        )
+        .await?;
+        if let Some(log_dir) = cli.debug.clone() {
+            let mut debugger = Debugger::new(kernel, &log_dir).await?;
+            let input = std::io::BufReader::new(std::io::stdin());
+            debugger::run(&mut debugger, input, std::io::stdout()).await?;
+            return Ok(SetupResult::Debugged);
+        }
+        let log_dir = cli.replay.clone().expect("--replay or --debug is set");
+        let report = kernel.replay(log_dir.clone()).await?;
+        info!(
+            "Replayed events {} to {} from {:?}, {} recorded state mugs matched, final state mug {:08x}",
+            report.start + 1,
+            report.end,
+            log_dir,
+            report.checked,
+            report.state_mug
+        );
+        return Ok(SetupResult::Replayed);
+    }
+
+    if cli.detach {
+        if let Some(pid) = daemon::detach(cli.daemon_log.as_deref())? {
+            println!("{}", pid);
+            std::process::exit(0);
+        }
+    }
+    let pid_file = cli.pid_file.as_deref().map(PidFile::create).transpose()?;
+
+    if pma_dir.exists() && (cli.new || !cli.pma) {
         std::fs::remove_dir_all(&pma_dir)?;
         debug!("Deleted existing pma directory: {:?}", pma_dir);
     }
@@ -281,57 +939,339 @@ pub async fn setup_<J: Jammer + Send + 'static>(
         debug!("Deleted existing checkpoint directory: {:?}", jams_dir);
     }
 
+    let store_dir = data_dir.join("drivers");
+    if cli.new && store_dir.exists() {
+        std::fs::remove_dir_all(&store_dir)?;
+        debug!("Deleted existing driver store: {:?}", store_dir);
+    }
+
+    if cli.new && dead_letters_dir.exists() {
+        std::fs::remove_dir_all(&dead_letters_dir)?;
+        debug!("Deleted existing dead letters: {:?}", dead_letters_dir);
+    }
+
     info!("kernel: starting");
     debug!("kernel: pma directory: {:?}", pma_dir);
     debug!("kernel: snapshots directory: {:?}", jams_dir);
 
+    let stack_config = cli.stack_config();
+    debug!(
+        "kernel: nock stack of {} words, serf thread stack of {} bytes",
+        stack_config.nock_stack_size, stack_config.thread_stack_size
+    );
+    if cli.pma {
+        std::fs::create_dir_all(&pma_dir)?;
+    }
     let kernel_f = async |checkpoint| {
-        let kernel: Kernel<SaveableCheckpoint> = match cli.stack_size {
-            NockStackSize::Tiny => {
-                Kernel::load_with_hot_state_tiny(jam, checkpoint, hot_state, test_jets, cli.trace)
-                    .await?
-            }
-            NockStackSize::Small => {
-                Kernel::load_with_hot_state_small(jam, checkpoint, hot_state, test_jets, cli.trace)
-                    .await?
-            }
-            NockStackSize::Normal => {
-                Kernel::load_with_hot_state(jam, checkpoint, hot_state, test_jets, cli.trace)
-                    .await?
-            }
-            NockStackSize::Medium => {
-                Kernel::load_with_hot_state_medium(jam, checkpoint, hot_state, test_jets, cli.trace)
-                    .await?
-            }
-            NockStackSize::Large => {
-                Kernel::load_with_hot_state_large(jam, checkpoint, hot_state, test_jets, cli.trace)
-                    .await?
-            }
-            NockStackSize::Huge => {
-                Kernel::load_with_hot_state_huge(jam, checkpoint, hot_state, test_jets, cli.trace)
-                    .await?
-            }
+        let kernel: Kernel<SaveableCheckpoint> = if cli.pma {
+            Kernel::load_with_pma(
+                jam,
+                checkpoint,
+                hot_state,
+                stack_config,
+                pma_dir.join("loom"),
+                test_jets,
+                cli.trace,
+            )
+            .await?
+        } else {
+            Kernel::load_with_stack_config(
+                jam, checkpoint, hot_state, stack_config, test_jets, cli.trace,
+            )
+            .await?
         };
         let res: Result<Kernel<SaveableCheckpoint>, CrownError<ExternalError>> = Ok(kernel);
         res
     };
 
-    let save_interval = std::time::Duration::from_millis(cli.save_interval);
+    let save_interval = Duration::from_millis(cli.save_interval.max(1));
+
+    let mut app: NockApp<J> = NockApp::new(kernel_f, &jams_dir, save_interval).await?;
+    app.set_checkpoint_policy(CheckpointPolicy {
+        interval: (cli.save_interval > 0).then_some(save_interval),
+        every_events: cli.checkpoint_every_events,
+        on_shutdown: !cli.no_checkpoint_on_exit,
+        on_effect: true,
+    });
+    app.set_checkpoint_compression(Compression::from_level(cli.checkpoint_zstd_level))
+        .await;
+    app.set_checkpoint_deltas(DeltaPolicy::up_to(cli.checkpoint_deltas))
+        .await;
+    app.set_checkpoint_retention(cli.retention_policy()).await;
+
+    app.set_shutdown_deadline(Duration::from_secs(cli.shutdown_timeout_secs));
+    app.set_driver_store(store_dir);
+    app.set_data_dir(data_dir.clone());
 
-    let app: NockApp<J> = NockApp::new(kernel_f, &jams_dir, save_interval).await?;
+    if let Some(secs) = cli.poke_timeout_secs {
+        app.kernel
+            .set_poke_timeout(Some(Duration::from_secs(secs)))
+            .await?;
+    }
+
+    if let Some(gb) = cli.nock_stack_max_gb {
+        // 2^27 words of 8 bytes in a GiB
+        app.kernel
+            .set_loom_ceiling(Some((gb as usize) << 27))
+            .await?;
+        info!("Growing the nock stack up to {} GiB as needed", gb);
+    }
+
+    if cli.crash_dumps {
+        let crash_dir = data_dir.join("crashes");
+        app.kernel.set_crash_dumps(Some(crash_dir.clone())).await?;
+        info!("Writing crash dumps to {:?}", crash_dir);
+    }
+
+    if cli.dead_letters {
+        app.set_dead_letters(DeadLetters::new(dead_letters_dir.clone()));
+        info!("Keeping failed pokes in {:?}", dead_letters_dir);
+    }
+
+    if let Some(file) = cli.profile.clone() {
+        app.kernel.set_profile(Some(file.clone())).await?;
+        info!("Profiling kernel computations into {:?}", file);
+    }
+
+    if let Some(threshold) = cli.bytecode_threshold {
+        app.kernel.set_bytecode(Some(threshold)).await?;
+        info!("Compiling formulas into bytecode after {} calls", threshold);
+    }
+
+    if let Some(every) = cli.jet_shadow_check {
+        let mismatch_dir = data_dir.join("jet-mismatches");
+        app.kernel
+            .set_shadow_check(Some(every), mismatch_dir.clone())
+            .await?;
+        info!(
+            "Checking one in every {} jet calls against Nock, writing mismatches to {:?}",
+            every, mismatch_dir
+        );
+    }
+
+    if cli.nock_stats {
+        app.kernel.set_runtime_stats(true).await?;
+        info!("Counting Nock steps and jet hits");
+    }
+
+    if let Some(max_entries) = cli.memo_cache_entries {
+        let eviction = if cli.memo_cache_retain {
+            MemoEviction::Retain
+        } else {
+            MemoEviction::Flush
+        };
+        app.kernel
+            .set_memo_config(MemoConfig {
+                max_entries: Some(max_entries),
+                eviction,
+            })
+            .await?;
+        info!(
+            "Limiting the memo cache to {} entries ({:?} when full)",
+            max_entries, eviction
+        );
+    }
+
+    let events_dir = jams_dir.join("events");
+    if cli.event_log {
+        let replayed = app.kernel.attach_event_log(events_dir.clone()).await?;
+        info!(
+            "Logging events to {:?}, replayed {} since the last checkpoint",
+            events_dir, replayed
+        );
+    }
 
     if let Some(export_path) = cli.export_state_jam.clone() {
         export_kernel_state(&app.kernel, &export_path).await?;
         return Ok(SetupResult::ExportedState);
     }
 
+    if let Some(import_path) = cli.import_state_jam.clone() {
+        let mut app = app;
+        import_kernel_state(&app.kernel, &import_path).await?;
+        // Logged events belong to the replaced state. An attached log was reset by the import.
+        if !cli.event_log && events_dir.exists() {
+            std::fs::remove_dir_all(&events_dir)?;
+        }
+        let permit = app.save_mutex.clone().lock_owned().await;
+        app.save_f(async {}, permit).await?.await??;
+        info!("Saved imported kernel state to: {:?}", jams_dir);
+        return Ok(SetupResult::ImportedState);
+    }
+
     if let Some(import_path) = cli.state_jam.clone() {
         import_kernel_state(&app.kernel, &import_path).await?;
+        if !cli.event_log && events_dir.exists() {
+            std::fs::remove_dir_all(&events_dir)?;
+        }
+    }
+
+    if let Some(secs) = cli.peek_replica_refresh_secs {
+        let replica =
+            Replica::<J>::open_workers(jam, hot_state, jams_dir.clone(), cli.peek_workers);
+        let replica = Arc::new(replica.await?);
+        replica.spawn_refresh(Duration::from_secs(secs.max(1)));
+        info!(
+            "Serving peeks from a replica with {} workers at event {}, refreshed every {}s",
+            replica.workers(),
+            replica.event_num(),
+            secs.max(1)
+        );
+        app.set_peek_replica(replica);
+    }
+
+    if !cli.poke_quota.is_empty() {
+        app.set_poke_quotas(PokeQuotas::new(cli.poke_quota.clone()));
+        info!("Enforcing poke quotas: {:?}", cli.poke_quota);
+    }
+
+    if let Some(max_entries) = cli.peek_cache_entries {
+        app.set_peek_cache(max_entries);
+        info!("Caching up to {} peek results between pokes", max_entries);
+    }
+
+    if let Some(pid_file) = pid_file {
+        info!("Wrote the process id to {}", pid_file.path().display());
+        app.set_pid_file(pid_file);
+    }
+
+    if let Some(path) = cli.hot_load.clone() {
+        app.add_io_driver(crate::hot_load_driver(path.clone(), HOT_LOAD_INTERVAL))
+            .await;
+        info!("Hot-loading the kernel in {:?} when it changes", path);
+    }
+
+    if let Some(dir) = cli.audit_log.clone() {
+        let config = RecorderConfig::new(dir.clone())
+            .with_max_total_bytes(cli.audit_log_max_mb * 1024 * 1024);
+        let pokes = app.tap_pokes(TAP_CAPACITY);
+        app.add_io_driver(crate::recorder_driver(config, pokes))
+            .await;
+        info!("Recording pokes and effects to {:?}", dir);
     }
 
     Ok(SetupResult::App(app))
 }
 
+/// Print or delete checkpoints in `jams_dir` for a [`CheckpointsCommand`].
+fn run_checkpoints_command(
+    command: CheckpointsCommand,
+    jams_dir: &std::path::Path,
+) -> std::io::Result<()> {
+    let format_time = |time: std::time::SystemTime| {
+        chrono::DateTime::<chrono::Local>::from(time)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    };
+    match command {
+        CheckpointsCommand::Ls => {
+            for name in ["0.chkjam", "1.chkjam", "delta.chkjam"] {
+                let Ok(metadata) = std::fs::metadata(jams_dir.join(name)) else {
+                    continue;
+                };
+                println!(
+                    "live     {:<24} {:>12} bytes  {}",
+                    name,
+                    metadata.len(),
+                    format_time(metadata.modified()?)
+                );
+            }
+            for checkpoint in list_history(jams_dir)? {
+                println!(
+                    "history  event {:<18} {:>12} bytes  {}",
+                    checkpoint.event_num,
+                    checkpoint.size,
+                    format_time(checkpoint.modified)
+                );
+            }
+        }
+        CheckpointsCommand::Rm { event_nums, before } => {
+            let mut removed = 0;
+            for checkpoint in list_history(jams_dir)? {
+                let matches = event_nums.contains(&checkpoint.event_num)
+                    || before.is_some_and(|before| checkpoint.event_num < before);
+                if matches {
+                    std::fs::remove_file(&checkpoint.path)?;
+                    println!("removed event {}", checkpoint.event_num);
+                    removed += 1;
+                }
+            }
+            println!(
+                "removed {} checkpoints from {}",
+                removed,
+                history_dir(jams_dir).display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Print, retry or remove dead letters for a [`DeadLettersCommand`].
+fn run_dead_letters_command(
+    command: DeadLettersCommand,
+    dead_letters: &DeadLetters,
+) -> Result<(), DeadLetterError> {
+    let format_time = |millis: u64| {
+        chrono::DateTime::from_timestamp_millis(millis as i64)
+            .map(|time| {
+                time.with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_else(|| "?".to_string())
+    };
+    let all_ids = || -> Result<Vec<String>, DeadLetterError> {
+        Ok(dead_letters
+            .list()?
+            .into_iter()
+            .map(|entry| entry.id)
+            .collect())
+    };
+    match command {
+        DeadLettersCommand::Ls => {
+            for entry in dead_letters.list()? {
+                let error = entry.error.lines().next().unwrap_or_default();
+                println!(
+                    "{}  {:<10} {}  {}",
+                    entry.id,
+                    entry.source(),
+                    format_time(entry.failed_at),
+                    error
+                );
+            }
+            for id in dead_letters.retrying()? {
+                println!("{}  waiting to be retried", id);
+            }
+        }
+        DeadLettersCommand::Show { id } => {
+            let entry = dead_letters.get(&id)?;
+            println!("failed   {}", format_time(entry.failed_at));
+            println!("wire     {}", PrettyNoun::new(entry.wire()));
+            println!("cause    {}", PrettyNoun::new(entry.cause()));
+            println!("{}", entry.error);
+        }
+        DeadLettersCommand::Retry { ids, all } => {
+            let ids = if all { all_ids()? } else { ids };
+            for id in &ids {
+                dead_letters.retry(id)?;
+            }
+            println!("{} pokes will be retried", ids.len());
+        }
+        DeadLettersCommand::Rm { ids, all } => {
+            let ids = if all { all_ids()? } else { ids };
+            for id in &ids {
+                dead_letters.remove(id)?;
+            }
+            println!(
+                "removed {} pokes from {}",
+                ids.len(),
+                dead_letters.dir().display()
+            );
+        }
+    }
+    Ok(())
+}
+
 /// Exports the kernel state to a jam file at the specified path
 async fn export_kernel_state<C>(
     kernel: &Kernel<C>,
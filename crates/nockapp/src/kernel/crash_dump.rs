@@ -0,0 +1,147 @@
+//! Crash dumps for pokes the kernel bails on.
+//!
+//! When a poke crashes, the serf writes a directory `crash-<utc timestamp>-e<event_num>` holding
+//! everything needed to reproduce and read the crash:
+//!
+//! - `poke.jam`: the job `[event_num wire eny our now cause]`, in the event log's format, unless
+//!   the poke came on a secret wire
+//! - `goof.jam`: the `[mote tang]` the interpreter bailed with
+//! - `trace.txt`: the mote and the stack trace, one rendered tank per line
+//! - `effects-<event_num>.jam`: the effects of the most recent successful pokes
+//!
+//! The crashed poke is still sent through `+crud` afterwards, so dumping never changes what the
+//! kernel does.
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use bytes::Bytes;
+use nockvm::noun::Noun;
+
+use crate::noun::pretty::render_tank;
+use crate::noun::slab::{Jammer, NockJammer};
+use crate::{AtomExt, NounExt};
+
+/// How many pokes' worth of effects are kept for the next dump
+pub const DEFAULT_RECENT_EFFECTS: usize = 16;
+
+pub struct CrashDumper {
+    dir: PathBuf,
+    /// Jammed effect lists of the latest successful pokes, oldest first
+    recent_effects: VecDeque<(u64, Bytes)>,
+    max_recent: usize,
+}
+
+impl CrashDumper {
+    pub fn new(dir: PathBuf) -> Self {
+        CrashDumper {
+            dir,
+            recent_effects: VecDeque::new(),
+            max_recent: DEFAULT_RECENT_EFFECTS,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Remember the effects of successful event `event_num`, dropping the oldest beyond the limit.
+    pub fn record_effects(&mut self, event_num: u64, effects: Noun) {
+        if self.recent_effects.len() == self.max_recent {
+            self.recent_effects.pop_front();
+        }
+        self.recent_effects
+            .push_back((event_num, NockJammer::jam(effects)));
+    }
+
+    /// Write a dump for `job` failing as event `event_num` with `goof`, returning its directory.
+    /// A `job` of `None` leaves out `poke.jam`.
+    pub fn dump(&self, event_num: u64, job: Option<Noun>, goof: Noun) -> io::Result<PathBuf> {
+        let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ");
+        let path = self.dir.join(format!("crash-{}-e{}", timestamp, event_num));
+        fs::create_dir_all(&path)?;
+        if let Some(job) = job {
+            fs::write(path.join("poke.jam"), NockJammer::jam(job))?;
+        }
+        fs::write(path.join("goof.jam"), NockJammer::jam(goof))?;
+        let mut trace = render_goof(goof).join("\n");
+        trace.push('\n');
+        fs::write(path.join("trace.txt"), trace)?;
+        for (effects_num, effects) in &self.recent_effects {
+            fs::write(path.join(format!("effects-{}.jam", effects_num)), effects)?;
+        }
+        Ok(path)
+    }
+}
+
+/// Render a `[mote tang]` as the mote followed by one line per tank.
+///
+/// Tanks that aren't well formed are marked rather than dropped, so the line count still matches
+/// the trace.
+pub fn render_goof(goof: Noun) -> Vec<String> {
+    let Ok(cell) = goof.as_cell() else {
+        return vec!["malformed goof".to_string()];
+    };
+    let mote = cell
+        .head()
+        .as_atom()
+        .ok()
+        .and_then(|mote| mote.into_string().ok())
+        .unwrap_or_else(|| "unknown".to_string());
+    let mut lines = vec![format!("bail: %{}", mote)];
+    lines.extend(
+        cell.tail()
+            .list_iter()
+            .map(|tank| render_tank(tank).unwrap_or_else(|| "<malformed tank>".to_string())),
+    );
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::{D, T};
+    use nockvm_macros::tas;
+
+    use super::*;
+    use crate::noun::slab::{slab_noun_equality, NounSlab};
+
+    #[test]
+    fn test_dump_writes_poke_trace_and_effects() {
+        let temp = tempfile::tempdir().expect("tempdir");
+        let mut dumper = CrashDumper::new(temp.path().to_path_buf());
+        dumper.max_recent = 2;
+        for event_num in 1..=3 {
+            dumper.record_effects(event_num, D(event_num));
+        }
+
+        let mut slab: NounSlab = NounSlab::new();
+        let leaf = T(
+            &mut slab,
+            &[D(tas!(b"leaf")), D(b'h' as u64), D(b'i' as u64), D(0)],
+        );
+        let bad = T(&mut slab, &[D(tas!(b"nope")), D(0)]);
+        let tang = T(&mut slab, &[leaf, bad, D(0)]);
+        let goof = T(&mut slab, &[D(tas!(b"exit")), tang]);
+        let job = T(&mut slab, &[D(4), D(0), D(tas!(b"poke"))]);
+        let path = dumper.dump(4, Some(job), goof).expect("dump");
+
+        let name = path.file_name().and_then(|n| n.to_str()).expect("name");
+        assert!(name.starts_with("crash-") && name.ends_with("-e4"));
+        let trace = fs::read_to_string(path.join("trace.txt")).expect("trace");
+        assert_eq!(trace, "bail: %exit\nhi\n<malformed tank>\n");
+
+        let mut cued: NounSlab = NounSlab::new();
+        let poke = fs::read(path.join("poke.jam")).expect("poke.jam");
+        let root = cued.cue_into(Bytes::from(poke)).expect("cue");
+        assert!(slab_noun_equality(&root, &job));
+
+        // Only the last two pokes' effects are kept
+        assert!(!path.join("effects-1.jam").exists());
+        assert!(path.join("effects-2.jam").exists());
+        assert!(path.join("effects-3.jam").exists());
+
+        let secret = dumper.dump(5, None, goof).expect("dump");
+        assert!(!secret.join("poke.jam").exists());
+        assert!(secret.join("trace.txt").exists());
+    }
+}
@@ -0,0 +1,128 @@
+//! Deadlines for kernel computations.
+//!
+//! The interpreter can only be interrupted from outside the serf thread, through its
+//! [`NockCancelToken`]. A [`Deadline`] owns a small watchdog thread that cancels the running
+//! computation once the armed deadline passes. The interpreter checks for cancellation on every
+//! Nock 2 and 9, so any unbounded loop is interrupted, but a single long-running jet is not.
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use nockvm::interpreter::NockCancelToken;
+
+#[derive(Default)]
+struct State {
+    deadline: Option<Instant>,
+    fired: bool,
+    stop: bool,
+}
+
+pub(crate) struct Deadline {
+    shared: Arc<(Mutex<State>, Condvar)>,
+    watchdog: Option<JoinHandle<()>>,
+}
+
+impl Deadline {
+    pub(crate) fn new(cancel_token: NockCancelToken) -> std::io::Result<Self> {
+        let shared = Arc::new((Mutex::new(State::default()), Condvar::new()));
+        let watchdog_shared = shared.clone();
+        let watchdog = std::thread::Builder::new()
+            .name("serf-deadline".to_string())
+            .spawn(move || watch(&watchdog_shared, cancel_token))?;
+        Ok(Deadline {
+            shared,
+            watchdog: Some(watchdog),
+        })
+    }
+
+    /// Cancel the computation if it is still running `timeout` from now.
+    pub(crate) fn arm(&self, timeout: Duration) {
+        let mut state = lock(&self.shared.0);
+        state.deadline = Some(Instant::now() + timeout);
+        state.fired = false;
+        self.shared.1.notify_one();
+    }
+
+    /// Stop waiting, returning whether the deadline passed and interrupted the computation.
+    pub(crate) fn disarm(&self) -> bool {
+        let mut state = lock(&self.shared.0);
+        state.deadline = None;
+        std::mem::take(&mut state.fired)
+    }
+}
+
+impl Drop for Deadline {
+    fn drop(&mut self) {
+        lock(&self.shared.0).stop = true;
+        self.shared.1.notify_one();
+        if let Some(watchdog) = self.watchdog.take() {
+            let _ = watchdog.join();
+        }
+    }
+}
+
+fn watch(shared: &(Mutex<State>, Condvar), cancel_token: NockCancelToken) {
+    let (mutex, condvar) = shared;
+    let mut state = lock(mutex);
+    loop {
+        if state.stop {
+            return;
+        }
+        state = match state.deadline {
+            None => condvar.wait(state).unwrap_or_else(|e| e.into_inner()),
+            Some(deadline) => {
+                let now = Instant::now();
+                if now < deadline {
+                    condvar
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                } else {
+                    // Cancelling under the lock means the serf can't have disarmed and started
+                    // its next computation in between
+                    state.deadline = None;
+                    state.fired = cancel_token.cancel();
+                    state
+                }
+            }
+        };
+    }
+}
+
+fn lock(mutex: &Mutex<State>) -> MutexGuard<'_, State> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::interpreter::{interpret, Error, Mote};
+    use nockvm::jets::cold::Cold;
+    use nockvm::mem::NockStack;
+    use nockvm::noun::{D, T};
+
+    use super::*;
+    use crate::utils::create_context;
+
+    #[test]
+    fn test_deadline_interrupts_loop() {
+        let mut stack = NockStack::new(1 << 20, 0);
+        let cold = Cold::new(&mut stack);
+        let mut context = create_context(stack, &[], cold, None, vec![]);
+        let deadline = Deadline::new(context.cancel_token()).expect("start watchdog");
+
+        // [9 2 0 1] against a subject whose head is [9 2 0 1] calls itself forever
+        let formula = T(&mut context.stack, &[D(9), D(2), D(0), D(1)]);
+        let subject = T(&mut context.stack, &[formula, D(0)]);
+        deadline.arm(Duration::from_millis(50));
+        let res = interpret(&mut context, subject, formula);
+        assert!(deadline.disarm());
+        assert!(matches!(res, Err(Error::NonDeterministic(Mote::Intr, _))));
+
+        // The interpreter is usable again, and an unexpired deadline does nothing
+        deadline.arm(Duration::from_secs(60));
+        let quote = T(&mut context.stack, &[D(1), D(42)]);
+        let res = interpret(&mut context, D(0), quote).expect("interpret");
+        assert!(!deadline.disarm());
+        assert!(unsafe { res.raw_equals(&D(42)) });
+    }
+}
@@ -0,0 +1,486 @@
+//! Stepping a kernel through its event log, forwards and back, to find where its state went bad.
+//!
+//! A [`Debugger`] starts from a loaded kernel, usually one booted from a checkpoint, and the
+//! events logged after it. It pokes the logged jobs again to move forward, and to move back it
+//! imports the nearest state it kept on the way and replays from there, so going back never
+//! replays more than [`MARK_INTERVAL`] events past a kept state. At any event the kernel can be
+//! peeked, and [`Debugger::bisect`] finds the first event after which a peek stops answering what
+//! it should.
+//!
+//! [`run`] reads commands for it from a prompt:
+//!
+//! | Command                | Does                                                           |
+//! |------------------------|----------------------------------------------------------------|
+//! | `step [n]`, `back [n]` | runs the next `n` events, or goes back `n`, 1 by default       |
+//! | `goto <event>`         | moves to just after `event`                                    |
+//! | `event [event]`        | shows the wire and cause of `event`, by default the next one   |
+//! | `peek /a/b`, `peek <noun>` | peeks the kernel at the current event                      |
+//! | `bisect <path> <noun>` | finds the first event after which `path` doesn't answer `noun` |
+//! | `where`                | shows the current event                                        |
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use nockvm::noun::{Noun, Slots};
+use thiserror::Error;
+
+use crate::kernel::event_log::{self, EventLogError, LoggedEvent};
+use crate::kernel::form::{Kernel, LoadState};
+use crate::noun::literal::{parse_noun, LiteralError};
+use crate::noun::pretty::PrettyNoun;
+use crate::noun::scry::{parse_path, Scry, ScryError};
+use crate::noun::slab::{slab_equality, NounSlab};
+use crate::save::SaveableCheckpoint;
+use crate::CrownError;
+
+/// How many events apart the states kept for going back are
+pub const MARK_INTERVAL: usize = 100;
+/// How many states are kept besides the starting one
+const MAX_MARKS: usize = 8;
+/// How much of a cause `event` prints
+const MAX_SHOWN: usize = 2000;
+
+const HELP: &str = "\
+step [n]              run the next n events
+back [n]              go back n events
+goto <event>          move to just after event
+event [event]         show an event, by default the next one
+peek /a/b             peek a path of @ta segments
+peek <noun>           peek a path noun, like [%block 0x1.beef ~]
+bisect <path> <noun>  find the first event after which path doesn't answer noun
+where                 show the current event
+help                  show this
+quit                  leave";
+
+#[derive(Debug, Error)]
+pub enum DebugError {
+    #[error("debug: {0}")]
+    Io(#[from] io::Error),
+    #[error("debug: {0}")]
+    Kernel(#[from] CrownError),
+    #[error("debug: {0}")]
+    EventLog(#[from] EventLogError),
+    #[error("debug: the log's first event is {first}, but the kernel is at event {state}; start from a checkpoint taken there")]
+    Gap { first: u64, state: u64 },
+    #[error("debug: event {0} left the kernel at event {1}")]
+    Diverged(u64, u64),
+    #[error("debug: {0} at the first event, so there's nothing to bisect")]
+    BadStart(String),
+    #[error(transparent)]
+    Literal(#[from] LiteralError),
+    #[error(transparent)]
+    Scry(#[from] ScryError),
+    #[error("debug: {0}")]
+    Usage(String),
+}
+
+pub type Result<T, E = DebugError> = std::result::Result<T, E>;
+
+pub struct Debugger {
+    kernel: Kernel<SaveableCheckpoint>,
+    events: Vec<LoggedEvent>,
+    /// The event the kernel was at before any logged event
+    start: u64,
+    /// How many logged events have been run
+    at: usize,
+    /// States to go back to, by how many logged events had been run
+    marks: BTreeMap<usize, LoadState>,
+}
+
+impl Debugger {
+    /// Debug `kernel` with the events logged in `dir` after its current event
+    pub async fn new(kernel: Kernel<SaveableCheckpoint>, dir: &Path) -> Result<Self> {
+        let state = kernel.export().await?;
+        let start = state.event_num;
+        let events = event_log::read_since(dir, start)?;
+        if let Some(first) = events.first() {
+            if first.event_num != start + 1 {
+                return Err(DebugError::Gap {
+                    first: first.event_num,
+                    state: start,
+                });
+            }
+        }
+        Ok(Debugger {
+            kernel,
+            events,
+            start,
+            at: 0,
+            marks: BTreeMap::from([(0, state)]),
+        })
+    }
+
+    /// The event the kernel is at
+    pub fn position(&self) -> u64 {
+        self.start + self.at as u64
+    }
+
+    /// The first event, before any logged one
+    pub fn first(&self) -> u64 {
+        self.start
+    }
+
+    /// The last logged event
+    pub fn last(&self) -> u64 {
+        self.start + self.events.len() as u64
+    }
+
+    /// The logged event `event_num`, if there is one
+    pub fn event(&self, event_num: u64) -> Option<&LoggedEvent> {
+        let index = event_num.checked_sub(self.start + 1)?;
+        self.events.get(usize::try_from(index).ok()?)
+    }
+
+    /// Move to just after `event_num`, as far as the log goes
+    pub async fn goto(&mut self, event_num: u64) -> Result<()> {
+        let target = event_num.clamp(self.first(), self.last()) - self.start;
+        let target = target as usize;
+        if target < self.at {
+            let (&mark, state) = self
+                .marks
+                .range(..=target)
+                .next_back()
+                .expect("the starting state is always kept");
+            self.kernel.import(state.clone()).await?;
+            self.at = mark;
+        }
+        while self.at < target {
+            let event = &self.events[self.at];
+            self.kernel.poke_job(event.job.clone()).await?;
+            self.at += 1;
+            let position = self.kernel.export().await?;
+            if position.event_num != self.position() {
+                return Err(DebugError::Diverged(event.event_num, position.event_num));
+            }
+            if self.at % MARK_INTERVAL == 0 && !self.marks.contains_key(&self.at) {
+                self.marks.insert(self.at, position);
+                self.prune_marks();
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop the kept state furthest from the current event, if there are too many
+    fn prune_marks(&mut self) {
+        if self.marks.len() <= MAX_MARKS + 1 {
+            return;
+        }
+        let furthest = self
+            .marks
+            .keys()
+            .copied()
+            .filter(|&mark| mark != 0)
+            .max_by_key(|&mark| mark.abs_diff(self.at));
+        if let Some(furthest) = furthest {
+            self.marks.remove(&furthest);
+        }
+    }
+
+    /// Peek the kernel at the current event, returning the `(unit (unit *))` it answered
+    pub async fn peek(&self, path: NounSlab) -> Result<NounSlab> {
+        Ok(self.kernel.peek(path).await?)
+    }
+
+    /// Whether peeking `path` answers `value`
+    async fn answers(&self, path: &NounSlab, value: &NounSlab) -> Result<bool> {
+        let result = self.peek(path.clone()).await?;
+        let Scry::Value(answer) = Scry::from_peek(unsafe { *result.root() })? else {
+            return Ok(false);
+        };
+        let mut answer_slab = NounSlab::new();
+        answer_slab.copy_into(answer);
+        Ok(slab_equality(&answer_slab, value))
+    }
+
+    /// The first event after which peeking `path` no longer answers `value`, or `None` if it
+    /// still does after the last. Leaves the kernel at that event.
+    pub async fn bisect(&mut self, path: &NounSlab, value: &NounSlab) -> Result<Option<u64>> {
+        self.goto(self.first()).await?;
+        if !self.answers(path, value).await? {
+            return Err(DebugError::BadStart(
+                "the path doesn't answer that".to_string(),
+            ));
+        }
+        self.goto(self.last()).await?;
+        if self.answers(path, value).await? {
+            return Ok(None);
+        }
+        // Answers it after `good`, and doesn't after `bad`
+        let (mut good, mut bad) = (self.first(), self.last());
+        while bad - good > 1 {
+            let mid = good + (bad - good) / 2;
+            self.goto(mid).await?;
+            if self.answers(path, value).await? {
+                good = mid;
+            } else {
+                bad = mid;
+            }
+        }
+        self.goto(bad).await?;
+        Ok(Some(bad))
+    }
+}
+
+/// A parsed line of input
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Step(u64),
+    Back(u64),
+    Goto(u64),
+    Event(Option<u64>),
+    Peek(String),
+    Bisect(String, String),
+    Where,
+    Help,
+    Quit,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Option<Self>> {
+        let line = line.trim();
+        let (verb, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let count = |rest: &str| -> Result<u64> {
+            if rest.is_empty() {
+                return Ok(1);
+            }
+            number(rest)
+        };
+        let command = match verb {
+            "" => return Ok(None),
+            "step" | "s" => Command::Step(count(rest)?),
+            "back" | "b" => Command::Back(count(rest)?),
+            "goto" if !rest.is_empty() => Command::Goto(number(rest)?),
+            "event" if rest.is_empty() => Command::Event(None),
+            "event" => Command::Event(Some(number(rest)?)),
+            "peek" if !rest.is_empty() => Command::Peek(rest.to_string()),
+            "bisect" => {
+                let (path, value) = split_path(rest).ok_or_else(|| {
+                    DebugError::Usage("bisect needs a path and a noun".to_string())
+                })?;
+                Command::Bisect(path.to_string(), value.to_string())
+            }
+            "goto" | "peek" => {
+                return Err(DebugError::Usage(format!("{} needs an argument", verb)))
+            }
+            "where" => Command::Where,
+            "help" | "?" => Command::Help,
+            "quit" | "exit" => Command::Quit,
+            _ => {
+                return Err(DebugError::Usage(format!(
+                    "unknown command {:?}; try help",
+                    verb
+                )))
+            }
+        };
+        Ok(Some(command))
+    }
+}
+
+fn number(text: &str) -> Result<u64> {
+    text.replace('.', "")
+        .parse()
+        .map_err(|_| DebugError::Usage(format!("{:?} isn't a number", text)))
+}
+
+/// Split `/a/b noun` or `[%a %b ~] noun` into the path and the rest
+fn split_path(text: &str) -> Option<(&str, &str)> {
+    let end = if text.starts_with('/') {
+        text.find(char::is_whitespace)?
+    } else {
+        let mut depth = 0usize;
+        let mut end = None;
+        for (i, c) in text.char_indices() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth = depth.checked_sub(1)?,
+                c if c.is_whitespace() && depth == 0 => {
+                    end = Some(i);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        end?
+    };
+    let (path, rest) = text.split_at(end);
+    let rest = rest.trim();
+    (!rest.is_empty()).then_some((path, rest))
+}
+
+/// A path typed as `/a/b` or as a noun
+fn path_slab(text: &str) -> Result<NounSlab> {
+    let mut slab = NounSlab::new();
+    let path = if text.starts_with('/') {
+        parse_path(&mut slab, text)?
+    } else {
+        parse_noun(&mut slab, text)?
+    };
+    slab.set_root(path);
+    Ok(slab)
+}
+
+fn noun_slab(text: &str) -> Result<NounSlab> {
+    let mut slab = NounSlab::new();
+    let noun = parse_noun(&mut slab, text)?;
+    slab.set_root(noun);
+    Ok(slab)
+}
+
+/// Read commands from `input` until it ends or `quit`, writing replies to `output`.
+pub async fn run<R: BufRead, W: Write>(
+    debugger: &mut Debugger,
+    mut input: R,
+    mut output: W,
+) -> Result<()> {
+    writeln!(
+        output,
+        "at event {} of {}..{}; type help for commands",
+        debugger.position(),
+        debugger.first(),
+        debugger.last()
+    )?;
+    let mut line = String::new();
+    loop {
+        write!(output, "{}> ", debugger.position())?;
+        output.flush()?;
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let command = match Command::parse(&line) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(e) => {
+                writeln!(output, "{}", e)?;
+                continue;
+            }
+        };
+        if command == Command::Quit {
+            return Ok(());
+        }
+        match execute(debugger, command, &mut output).await {
+            Ok(()) => {}
+            Err(e @ DebugError::Io(_)) => return Err(e),
+            Err(e) => writeln!(output, "{}", e)?,
+        }
+    }
+}
+
+async fn execute<W: Write>(
+    debugger: &mut Debugger,
+    command: Command,
+    output: &mut W,
+) -> Result<()> {
+    match command {
+        Command::Step(n) => {
+            debugger.goto(debugger.position().saturating_add(n)).await?;
+            writeln!(output, "at event {}", debugger.position())?;
+        }
+        Command::Back(n) => {
+            debugger.goto(debugger.position().saturating_sub(n)).await?;
+            writeln!(output, "at event {}", debugger.position())?;
+        }
+        Command::Goto(event_num) => {
+            debugger.goto(event_num).await?;
+            writeln!(output, "at event {}", debugger.position())?;
+        }
+        Command::Event(event_num) => {
+            let event_num = event_num.unwrap_or(debugger.position() + 1);
+            let Some(event) = debugger.event(event_num) else {
+                writeln!(output, "no event {} in the log", event_num)?;
+                return Ok(());
+            };
+            show_event(event, output)?;
+        }
+        Command::Peek(path) => {
+            let result = debugger.peek(path_slab(&path)?).await?;
+            match Scry::from_peek(unsafe { *result.root() })? {
+                Scry::Unknown => writeln!(output, "~ (the kernel doesn't handle this path)")?,
+                Scry::Empty => writeln!(output, "[~ ~] (no value)")?,
+                Scry::Value(value) => writeln!(output, "{}", PrettyNoun::new(value))?,
+            }
+        }
+        Command::Bisect(path, value) => {
+            let (path, value) = (path_slab(&path)?, noun_slab(&value)?);
+            match debugger.bisect(&path, &value).await? {
+                Some(event_num) => {
+                    writeln!(
+                        output,
+                        "event {} is the first after which it doesn't",
+                        event_num
+                    )?;
+                    if let Some(event) = debugger.event(event_num) {
+                        show_event(event, output)?;
+                    }
+                }
+                None => writeln!(output, "it still does at the last event")?,
+            }
+        }
+        Command::Where => writeln!(
+            output,
+            "at event {} of {}..{}",
+            debugger.position(),
+            debugger.first(),
+            debugger.last()
+        )?,
+        Command::Help => writeln!(output, "{}", HELP)?,
+        Command::Quit => {}
+    }
+    Ok(())
+}
+
+/// Print the wire and cause of a job `[event_num wire eny our now cause]`
+fn show_event<W: Write>(event: &LoggedEvent, output: &mut W) -> Result<()> {
+    let job = unsafe { *event.job.root() };
+    let part = |axis: u64| -> Option<Noun> { job.slot(axis).ok() };
+    let (Some(wire), Some(cause)) = (part(6), part(63)) else {
+        writeln!(output, "event {}: malformed job", event.event_num)?;
+        return Ok(());
+    };
+    let mut cause = PrettyNoun::new(cause).to_string();
+    if cause.len() > MAX_SHOWN {
+        let cut = (0..=MAX_SHOWN)
+            .rev()
+            .find(|&i| cause.is_char_boundary(i))
+            .unwrap_or(0);
+        cause.truncate(cut);
+        cause.push_str("...");
+    }
+    writeln!(
+        output,
+        "event {} on {}",
+        event.event_num,
+        PrettyNoun::new(wire)
+    )?;
+    writeln!(output, "{}", cause)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(Command::parse(" ").unwrap(), None);
+        assert_eq!(Command::parse("step").unwrap(), Some(Command::Step(1)));
+        assert_eq!(
+            Command::parse("back 1.000").unwrap(),
+            Some(Command::Back(1000))
+        );
+        assert_eq!(Command::parse("goto 42").unwrap(), Some(Command::Goto(42)));
+        assert_eq!(Command::parse("event").unwrap(), Some(Command::Event(None)));
+        assert_eq!(
+            Command::parse("bisect /heavy-n/3 [1 2]").unwrap(),
+            Some(Command::Bisect("/heavy-n/3".into(), "[1 2]".into()))
+        );
+        assert_eq!(
+            Command::parse("bisect [%block 1 ~] ~").unwrap(),
+            Some(Command::Bisect("[%block 1 ~]".into(), "~".into()))
+        );
+        assert!(Command::parse("bisect /heavy").is_err());
+        assert!(Command::parse("goto").is_err());
+        assert!(Command::parse("step x").is_err());
+    }
+}
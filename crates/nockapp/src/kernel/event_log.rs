@@ -0,0 +1,422 @@
+//! Append-only log of the events the kernel has processed.
+//!
+//! Each poke is logged as the full job noun `[event_num wire eny our now cause]`, so replaying it
+//! reproduces the same entropy and timestamp the kernel originally saw. Events are appended
+//! after the kernel accepts them and before the poke is acknowledged, which makes the log
+//! consistent with acked state even if the process dies between checkpoints.
+//!
+//! The log is split into segments named after their first event. A new segment is started at
+//! every checkpoint, and only the newest [`DEFAULT_KEEP_SEGMENTS`] are kept, which covers rolling
+//! back to the previous checkpoint.
+//!
+//! Records are `len: u32 | event_num: u64 | jam | blake3(event_num | jam)`, all little-endian.
+//! A torn record at the end of the newest segment is truncated when the log is opened.
+//!
+//! At every checkpoint the serf also records the mug of the kernel state in `state-mugs`, one
+//! `<event_num> <mug in hex>` line per checkpoint. Replaying the log checks the state against
+//! these, so a jet or interpreter change that alters the result of real traffic is caught.
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use nockvm::noun::Noun;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::noun::slab::{CueError, Jammer, NockJammer, NounSlab};
+
+pub const DEFAULT_KEEP_SEGMENTS: usize = 3;
+
+const SEGMENT_PREFIX: &str = "events-";
+const SEGMENT_SUFFIX: &str = ".log";
+const RECORD_HEADER_LEN: usize = 12;
+const RECORD_HASH_LEN: usize = 32;
+const STATE_MUGS_FILE: &str = "state-mugs";
+
+#[derive(Debug, Error)]
+pub enum EventLogError {
+    #[error("event log io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("event log cue error: {0}")]
+    Cue(#[from] CueError),
+    #[error("event log {path} is corrupt at byte {offset}")]
+    Corrupt { path: PathBuf, offset: u64 },
+}
+
+pub type Result<T, E = EventLogError> = std::result::Result<T, E>;
+
+/// An event read back from the log
+pub struct LoggedEvent {
+    pub event_num: u64,
+    /// The poke job, `[event_num wire eny our now cause]`
+    pub job: NounSlab,
+}
+
+pub struct EventLog {
+    dir: PathBuf,
+    file: File,
+    keep_segments: usize,
+}
+
+impl EventLog {
+    /// Open the log in `dir` for appending, creating it if needed.
+    ///
+    /// `next_event` names the segment if a new one has to be created.
+    pub fn open(dir: &Path, next_event: u64) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let file = match segments(dir)?.pop() {
+            Some((_, path)) => {
+                let valid_len = scan_segment(&path, |_, _| Ok(()))?;
+                let file = OpenOptions::new().append(true).open(&path)?;
+                if file.metadata()?.len() > valid_len {
+                    warn!(
+                        "Truncating torn record at byte {} of event log {}",
+                        valid_len,
+                        path.display()
+                    );
+                    file.set_len(valid_len)?;
+                }
+                file
+            }
+            None => create_segment(dir, next_event)?,
+        };
+        Ok(EventLog {
+            dir: dir.to_path_buf(),
+            file,
+            keep_segments: DEFAULT_KEEP_SEGMENTS,
+        })
+    }
+
+    pub fn keep_segments(mut self, keep_segments: usize) -> Self {
+        self.keep_segments = keep_segments.max(1);
+        self
+    }
+
+    /// Append a jammed job for `event_num` and sync it to disk.
+    pub fn append(&mut self, event_num: u64, jam: &[u8]) -> Result<()> {
+        let len = u32::try_from(jam.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "event too large to log"))?;
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN + jam.len() + RECORD_HASH_LEN);
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(&event_num.to_le_bytes());
+        record.extend_from_slice(jam);
+        record.extend_from_slice(record_hash(event_num, jam).as_bytes());
+        self.file.write_all(&record)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Jam `job` and append it.
+    pub fn append_job(&mut self, event_num: u64, job: Noun) -> Result<()> {
+        self.append(event_num, &NockJammer::jam(job))
+    }
+
+    /// Delete every segment and start over at `next_event`, e.g. after the kernel state has been
+    /// replaced wholesale.
+    pub fn reset(&mut self, next_event: u64) -> Result<()> {
+        for (_, path) in segments(&self.dir)? {
+            fs::remove_file(path)?;
+        }
+        write_state_mugs(&self.dir, &BTreeMap::new())?;
+        self.file = create_segment(&self.dir, next_event)?;
+        Ok(())
+    }
+
+    /// Start a new segment at `next_event` and delete the oldest segments beyond the limit.
+    pub fn rotate(&mut self, next_event: u64) -> Result<()> {
+        let existing = segments(&self.dir)?;
+        if existing.last().map(|(start, _)| *start) == Some(next_event) {
+            return Ok(());
+        }
+        self.file = create_segment(&self.dir, next_event)?;
+        let mut all = segments(&self.dir)?;
+        let excess = all.len().saturating_sub(self.keep_segments);
+        for (start, path) in all.drain(..excess) {
+            debug!("Removing event log segment starting at event {}", start);
+            fs::remove_file(path)?;
+        }
+        // Mugs from before the oldest segment can't be reached by a replay anymore
+        if let Some((oldest, _)) = all.first() {
+            let mut mugs = read_state_mugs(&self.dir)?;
+            mugs.retain(|event_num, _| event_num + 1 >= *oldest);
+            write_state_mugs(&self.dir, &mugs)?;
+        }
+        Ok(())
+    }
+
+    /// Record the mug of the kernel state after `event_num`, for replays to check against.
+    pub fn record_state_mug(&mut self, event_num: u64, mug: u32) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(STATE_MUGS_FILE))?;
+        writeln!(file, "{} {:08x}", event_num, mug)?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// The state mugs recorded in the log in `dir`, by event number
+pub fn read_state_mugs(dir: &Path) -> Result<BTreeMap<u64, u32>> {
+    let mut mugs = BTreeMap::new();
+    let path = dir.join(STATE_MUGS_FILE);
+    if !path.exists() {
+        return Ok(mugs);
+    }
+    for line in BufReader::new(File::open(&path)?).lines() {
+        let line = line?;
+        let parsed = line.split_once(' ').and_then(|(event_num, mug)| {
+            Some((event_num.parse().ok()?, u32::from_str_radix(mug, 16).ok()?))
+        });
+        match parsed {
+            Some((event_num, mug)) => {
+                mugs.insert(event_num, mug);
+            }
+            // A torn last line from a crash mid-write
+            None => warn!("Ignoring malformed line in {}: {:?}", path.display(), line),
+        }
+    }
+    Ok(mugs)
+}
+
+fn write_state_mugs(dir: &Path, mugs: &BTreeMap<u64, u32>) -> Result<()> {
+    let mut contents = String::new();
+    for (event_num, mug) in mugs {
+        contents.push_str(&format!("{} {:08x}\n", event_num, mug));
+    }
+    fs::write(dir.join(STATE_MUGS_FILE), contents)?;
+    Ok(())
+}
+
+/// Read every logged event after `after`, in order.
+///
+/// Stops at the first gap in event numbers, since later events can't be applied without the
+/// missing ones. Only events since the last checkpoint are normally read, so they are collected
+/// rather than streamed.
+pub fn read_since(dir: &Path, after: u64) -> Result<Vec<LoggedEvent>> {
+    let mut events = Vec::new();
+    if !dir.exists() {
+        return Ok(events);
+    }
+    let mut next = after + 1;
+    for (_, path) in segments(dir)? {
+        let mut gap = false;
+        scan_segment(&path, |event_num, jam| {
+            if gap || event_num < next {
+                return Ok(());
+            }
+            if event_num > next {
+                warn!(
+                    "Event log skips from event {} to {}, not reading further",
+                    next - 1,
+                    event_num
+                );
+                gap = true;
+                return Ok(());
+            }
+            let mut job = NounSlab::new();
+            let root = job.cue_into(Bytes::copy_from_slice(jam))?;
+            job.set_root(root);
+            events.push(LoggedEvent { event_num, job });
+            next += 1;
+            Ok(())
+        })?;
+        if gap {
+            break;
+        }
+    }
+    Ok(events)
+}
+
+fn record_hash(event_num: u64, jam: &[u8]) -> blake3::Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&event_num.to_le_bytes());
+    hasher.update(jam);
+    hasher.finalize()
+}
+
+fn segment_path(dir: &Path, start: u64) -> PathBuf {
+    dir.join(format!("{}{:020}{}", SEGMENT_PREFIX, start, SEGMENT_SUFFIX))
+}
+
+fn create_segment(dir: &Path, start: u64) -> Result<File> {
+    Ok(OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(segment_path(dir, start))?)
+}
+
+/// Segments in `dir`, oldest first
+fn segments(dir: &Path) -> Result<Vec<(u64, PathBuf)>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let start = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(SEGMENT_PREFIX))
+            .and_then(|name| name.strip_suffix(SEGMENT_SUFFIX))
+            .and_then(|start| start.parse::<u64>().ok());
+        if let Some(start) = start {
+            segments.push((start, path));
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+/// Call `f` on each intact record of a segment and return the length of the intact prefix.
+///
+/// A short or mismatched record at the very end is a torn write and ends the scan. One followed
+/// by more data is corruption.
+fn scan_segment<F>(path: &Path, mut f: F) -> Result<u64>
+where
+    F: FnMut(u64, &[u8]) -> Result<()>,
+{
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut offset = 0u64;
+    let mut header = [0u8; RECORD_HEADER_LEN];
+    let mut hash = [0u8; RECORD_HASH_LEN];
+    let mut jam = Vec::new();
+    loop {
+        if !read_full(&mut reader, &mut header)? {
+            return Ok(offset);
+        }
+        let len = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+        let event_num = u64::from_le_bytes(header[4..12].try_into().unwrap());
+        let record_len = (RECORD_HEADER_LEN + len + RECORD_HASH_LEN) as u64;
+        let end = offset + record_len;
+        if end > file_len {
+            return Ok(offset);
+        }
+        jam.resize(len, 0);
+        reader.read_exact(&mut jam)?;
+        reader.read_exact(&mut hash)?;
+        if record_hash(event_num, &jam).as_bytes() != &hash {
+            if end == file_len {
+                return Ok(offset);
+            }
+            return Err(EventLogError::Corrupt {
+                path: path.to_path_buf(),
+                offset,
+            });
+        }
+        f(event_num, &jam)?;
+        offset = end;
+    }
+}
+
+/// Fill `buf`, returning `false` if the reader ends first.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => return Ok(false),
+            n => filled += n,
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::{D, T};
+
+    use super::*;
+    use crate::noun::slab::slab_noun_equality;
+
+    fn job(slab: &mut NounSlab, event_num: u64) -> Noun {
+        T(slab, &[D(event_num), D(0), D(event_num * 10)])
+    }
+
+    fn replayed(dir: &Path, after: u64) -> Vec<u64> {
+        read_since(dir, after)
+            .expect("read_since")
+            .into_iter()
+            .map(|event| {
+                let mut slab: NounSlab = NounSlab::new();
+                let expected = job(&mut slab, event.event_num);
+                assert!(slab_noun_equality(unsafe { event.job.root() }, &expected));
+                event.event_num
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_append_rotate_replay() {
+        let temp = tempfile::tempdir().expect("tempdir");
+        let mut slab: NounSlab = NounSlab::new();
+        let mut log = EventLog::open(temp.path(), 1)
+            .expect("open")
+            .keep_segments(2);
+        for event_num in 1..=3 {
+            log.append_job(event_num, job(&mut slab, event_num))
+                .expect("append");
+        }
+        log.rotate(4).expect("rotate");
+        for event_num in 4..=5 {
+            log.append_job(event_num, job(&mut slab, event_num))
+                .expect("append");
+        }
+        assert_eq!(replayed(temp.path(), 0), vec![1, 2, 3, 4, 5]);
+        assert_eq!(replayed(temp.path(), 3), vec![4, 5]);
+
+        log.rotate(6).expect("rotate");
+        assert_eq!(segments(temp.path()).expect("segments").len(), 2);
+        assert_eq!(replayed(temp.path(), 3), vec![4, 5]);
+        // Events 1-3 were dropped with their segment
+        assert!(replayed(temp.path(), 0).is_empty());
+    }
+
+    #[test]
+    fn test_state_mugs_follow_segments() {
+        let temp = tempfile::tempdir().expect("tempdir");
+        let mut log = EventLog::open(temp.path(), 1)
+            .expect("open")
+            .keep_segments(2);
+        log.record_state_mug(0, 0xdead).expect("record");
+        log.rotate(3).expect("rotate");
+        log.record_state_mug(2, 0xbeef).expect("record");
+        log.rotate(5).expect("rotate");
+        log.record_state_mug(4, 0x1234).expect("record");
+        let mugs = read_state_mugs(temp.path()).expect("read");
+        assert_eq!(
+            mugs.into_iter().collect::<Vec<_>>(),
+            vec![(2, 0xbeef), (4, 0x1234)]
+        );
+
+        log.reset(9).expect("reset");
+        assert!(read_state_mugs(temp.path()).expect("read").is_empty());
+    }
+
+    #[test]
+    fn test_torn_record_is_truncated() {
+        let temp = tempfile::tempdir().expect("tempdir");
+        let mut slab: NounSlab = NounSlab::new();
+        let mut log = EventLog::open(temp.path(), 1).expect("open");
+        log.append_job(1, job(&mut slab, 1)).expect("append");
+        log.append_job(2, job(&mut slab, 2)).expect("append");
+        drop(log);
+
+        let (_, path) = segments(temp.path())
+            .expect("segments")
+            .pop()
+            .expect("segment");
+        let len = fs::metadata(&path).expect("metadata").len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .expect("open segment")
+            .set_len(len - 5)
+            .expect("truncate");
+        assert_eq!(replayed(temp.path(), 0), vec![1]);
+
+        let mut log = EventLog::open(temp.path(), 1).expect("reopen");
+        log.append_job(2, job(&mut slab, 2)).expect("append");
+        assert_eq!(replayed(temp.path(), 0), vec![1, 2]);
+    }
+}
@@ -2,34 +2,45 @@
 use std::any::Any;
 use std::fs::File;
 use std::future::Future;
+use std::io::Write;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
 
 use blake3::{Hash, Hasher};
 use byteorder::{LittleEndian, WriteBytesExt};
-use nockvm::hamt::Hamt;
+use futures::future::Either;
+use nockvm::bytecode::Bytecode;
 use nockvm::interpreter::{self, interpret, Error, Mote, NockCancelToken};
 use nockvm::jets::cold::{Cold, Nounable};
 use nockvm::jets::hot::{HotEntry, URBIT_HOT_STATE};
 use nockvm::jets::nock::util::mook;
-use nockvm::mem::NockStack;
-use nockvm::mug::met3_usize;
+use nockvm::mem::{AllocationError, NockStack};
+use nockvm::memo::{MemoCache, MemoConfig};
+use nockvm::mug::{met3_usize, mug_u32};
 use nockvm::noun::{Atom, Cell, DirectAtom, IndirectAtom, Noun, Slots, D, T};
+use nockvm::profile::Profiler;
+use nockvm::shadow::{ShadowCheck, ShadowMismatch};
+use nockvm::stats::{self, RuntimeStats, StatsReport};
 use nockvm::trace::{path_to_cord, write_serf_trace_safe, TraceInfo};
 use nockvm_macros::tas;
 use tokio::sync::{mpsc, oneshot};
 use tokio::time::Duration;
-use tracing::{debug, warn};
+use tracing::{debug, error, info, info_span, warn};
 
+use crate::kernel::crash_dump::{render_goof, CrashDumper};
+use crate::kernel::deadline::Deadline;
+use crate::kernel::event_log::{self, EventLog};
 use crate::metrics::NockAppMetrics;
 use crate::nockapp::wire::{wire_to_noun, WireRepr};
-use crate::noun::slab::NounSlab;
+use crate::noun::slab::{Jammer, NockJammer, NounSlab};
 use crate::noun::slam;
 use crate::save::SaveableCheckpoint;
 use crate::utils::{
-    create_context, current_da, NOCK_STACK_SIZE, NOCK_STACK_SIZE_HUGE, NOCK_STACK_SIZE_LARGE,
-    NOCK_STACK_SIZE_MEDIUM, NOCK_STACK_SIZE_SMALL, NOCK_STACK_SIZE_TINY,
+    autodetect_nock_stack_size, create_context, current_da, NOCK_STACK_SIZE, NOCK_STACK_SIZE_HUGE,
+    NOCK_STACK_SIZE_LARGE, NOCK_STACK_SIZE_MEDIUM, NOCK_STACK_SIZE_SMALL, NOCK_STACK_SIZE_TINY,
 };
 use crate::{AtomExt, CrownError, NounExt, Result, ToBytesExt};
 
@@ -39,8 +50,46 @@ const PEEK_AXIS: u64 = 22;
 const POKE_AXIS: u64 = 23;
 
 const SERF_FINISHED_INTERVAL: Duration = Duration::from_millis(100);
-const SERF_THREAD_STACK_SIZE: usize = 8 * 1024 * 1024; // 8MB
+pub const SERF_THREAD_STACK_SIZE: usize = 8 * 1024 * 1024; // 8MB
+
+/// Sizes of the stacks a serf runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackConfig {
+    /// Size of the nock stack (the loom) kernel computations allocate on, in 64-bit words
+    pub nock_stack_size: usize,
+    /// Size of the serf thread's native stack, in bytes. The interpreter doesn't recurse on it,
+    /// but jets and (de)serialization of deep nouns do.
+    pub thread_stack_size: usize,
+}
+
+impl Default for StackConfig {
+    fn default() -> Self {
+        StackConfig {
+            nock_stack_size: NOCK_STACK_SIZE,
+            thread_stack_size: SERF_THREAD_STACK_SIZE,
+        }
+    }
+}
 
+impl StackConfig {
+    /// The default thread stack, with a nock stack sized to the machine's memory, see
+    /// [`autodetect_nock_stack_size`].
+    pub fn autodetect() -> Self {
+        StackConfig {
+            nock_stack_size: autodetect_nock_stack_size(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_nock_stack_size(nock_stack_size: usize) -> Self {
+        StackConfig {
+            nock_stack_size,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct LoadState {
     pub ker_hash: Hash,
     pub event_num: u64,
@@ -53,6 +102,10 @@ pub enum SerfAction<C> {
     Checkpoint {
         result: oneshot::Sender<C>,
     },
+    // Snapshot the persistent loom, returning the event number snapshotted
+    SnapshotPma {
+        result: oneshot::Sender<Result<u64>>,
+    },
     Import {
         state: LoadState,
         result: oneshot::Sender<Result<()>>,
@@ -68,10 +121,11 @@ pub enum SerfAction<C> {
     GetColdStateSlab {
         result: oneshot::Sender<NounSlab>,
     },
-    // Run a peek
+    // Run a peek in the span it was asked for in
     Peek {
         ovo: NounSlab,
         result: oneshot::Sender<Result<NounSlab>>,
+        span: tracing::Span,
     },
     // Run a poke
     //
@@ -80,6 +134,77 @@ pub enum SerfAction<C> {
         wire: WireRepr,
         cause: NounSlab,
         result: oneshot::Sender<Result<NounSlab>>,
+        span: tracing::Span,
+    },
+    // Swap in a new kernel, migrating the current state into it
+    Upgrade {
+        kernel: Vec<u8>,
+        result: oneshot::Sender<Result<NounSlab>>,
+    },
+    // Replay the event log in a directory past the current event, then log new events to it
+    AttachEventLog {
+        dir: PathBuf,
+        result: oneshot::Sender<Result<u64>>,
+    },
+    // Delete the event log and start it over after the current event
+    ResetEventLog {
+        result: oneshot::Sender<Result<()>>,
+    },
+    // Replay a whole event log, checking the state against the mugs recorded in it
+    Replay {
+        dir: PathBuf,
+        result: oneshot::Sender<Result<ReplayReport>>,
+    },
+    // Run a logged poke job again, without logging it
+    PokeJob {
+        job: NounSlab,
+        result: oneshot::Sender<Result<NounSlab>>,
+    },
+    // Interrupt pokes that run longer than the timeout, or never if `None`
+    SetPokeTimeout {
+        timeout: Option<Duration>,
+        result: oneshot::Sender<Result<()>>,
+    },
+    // Write a crash dump into the directory whenever a poke bails, or stop if `None`
+    SetCrashDumps {
+        dir: Option<PathBuf>,
+        result: oneshot::Sender<Result<()>>,
+    },
+    // Grow the loom up to this many words when it runs out of memory, or never if `None`
+    SetLoomCeiling {
+        words: Option<usize>,
+        result: oneshot::Sender<Result<()>>,
+    },
+    // Profile computations into the file, or stop if `None`
+    SetProfile {
+        file: Option<PathBuf>,
+        result: oneshot::Sender<Result<()>>,
+    },
+    // Limit the size of the memo cache and choose how it evicts
+    SetMemoConfig {
+        config: MemoConfig,
+        result: oneshot::Sender<Result<()>>,
+    },
+    // Compile formulas to bytecode once called this many times, or stop compiling if `None`
+    SetBytecode {
+        threshold: Option<u32>,
+        result: oneshot::Sender<Result<()>>,
+    },
+    // Check one in every `every` jet calls against its Nock, writing mismatches into the directory,
+    // or stop if `None`
+    SetShadowCheck {
+        every: Option<u64>,
+        dir: PathBuf,
+        result: oneshot::Sender<Result<()>>,
+    },
+    // Count Nock steps and jet hits, or stop counting
+    SetRuntimeStats {
+        enabled: bool,
+        result: oneshot::Sender<Result<()>>,
+    },
+    // Report the runtime statistics, or `None` if they aren't being counted
+    GetRuntimeStats {
+        result: oneshot::Sender<Option<StatsReport>>,
     },
     // Provide metrics
     ProvideMetrics {
@@ -96,6 +221,8 @@ pub struct SerfThread<C> {
     pub cancel_token: NockCancelToken,
     inhibit: Arc<AtomicBool>,
     pub event_number: Arc<AtomicU64>,
+    /// Whether the loom is a persistent memory arena, which is snapshotted instead of checkpointed
+    pma: bool,
 }
 
 impl<C: SerfCheckpoint + Send + 'static> SerfThread<C> {
@@ -107,6 +234,43 @@ impl<C: SerfCheckpoint + Send + 'static> SerfThread<C> {
         test_jets: Vec<NounSlab>,
         trace: bool,
     ) -> Result<Self> {
+        Self::with_stack_config(
+            kernel_bytes,
+            checkpoint,
+            constant_hot_state,
+            StackConfig::with_nock_stack_size(nock_stack_size),
+            test_jets,
+            trace,
+        )
+        .await
+    }
+
+    pub async fn with_stack_config(
+        kernel_bytes: Vec<u8>,
+        checkpoint: Option<C>,
+        constant_hot_state: Vec<HotEntry>,
+        stack_config: StackConfig,
+        test_jets: Vec<NounSlab>,
+        trace: bool,
+    ) -> Result<Self> {
+        Self::with_pma(
+            kernel_bytes, checkpoint, constant_hot_state, stack_config, None, test_jets, trace,
+        )
+        .await
+    }
+
+    /// Like [`SerfThread::with_stack_config`], but with the loom in the persistent memory arena at
+    /// `pma` if given, resuming from its last snapshot if it has one newer than `checkpoint`.
+    pub async fn with_pma(
+        kernel_bytes: Vec<u8>,
+        checkpoint: Option<C>,
+        constant_hot_state: Vec<HotEntry>,
+        stack_config: StackConfig,
+        pma: Option<PathBuf>,
+        test_jets: Vec<NounSlab>,
+        trace: bool,
+    ) -> Result<Self> {
+        let uses_pma = pma.is_some();
         let (action_sender, action_receiver) = mpsc::channel(1);
         let (event_number_sender, event_number_receiver) = oneshot::channel();
         let (cancel_token_sender, cancel_token_receiver) = oneshot::channel();
@@ -114,11 +278,23 @@ impl<C: SerfCheckpoint + Send + 'static> SerfThread<C> {
         let inhibit_clone = inhibit.clone();
         let handle = std::thread::Builder::new()
             .name("serf".to_string())
-            .stack_size(SERF_THREAD_STACK_SIZE)
+            .stack_size(stack_config.thread_stack_size)
             .spawn(move || {
-                let stack = NockStack::new(nock_stack_size, 0);
+                // Failing here drops the senders, which fails the load below
+                let (stack, restored) =
+                    match open_loom(stack_config.nock_stack_size, pma.as_deref()) {
+                        Ok(loom) => loom,
+                        Err(e) => {
+                            error!(
+                                "Could not allocate a nock stack of {} words: {}",
+                                stack_config.nock_stack_size, e
+                            );
+                            return;
+                        }
+                    };
                 let serf = Serf::new(
-                    stack, checkpoint, &kernel_bytes, &constant_hot_state, test_jets, trace,
+                    stack, checkpoint, restored, &kernel_bytes, &constant_hot_state, test_jets,
+                    trace,
                 );
                 event_number_sender
                     .send(serf.event_num.clone())
@@ -137,10 +313,46 @@ impl<C: SerfCheckpoint + Send + 'static> SerfThread<C> {
             action_sender,
             event_number,
             cancel_token,
+            pma: uses_pma,
         })
     }
 }
 
+/// Allocate the loom, in the persistent memory arena at `pma` if given. An arena with a snapshot
+/// is reopened, returning the snapshot's `[state event_num]` roots. One that can't be reopened is
+/// replaced by a new arena, and the kernel boots from its checkpoints instead.
+fn open_loom(
+    size: usize,
+    pma: Option<&Path>,
+) -> std::result::Result<(NockStack, Option<(Noun, u64)>), String> {
+    let Some(path) = pma else {
+        let (stack, _) = NockStack::new_(size, 0).map_err(|e| e.to_string())?;
+        return Ok((stack, None));
+    };
+    if path.exists() {
+        match NockStack::open_pma(path) {
+            Ok((stack, roots)) => {
+                let restored = match roots.as_slice() {
+                    [state, event_num] => event_num
+                        .as_atom()
+                        .ok()
+                        .and_then(|event_num| event_num.as_u64().ok())
+                        .map(|event_num| (*state, event_num)),
+                    _ => None,
+                };
+                if let Some((_, event_num)) = restored {
+                    info!("Resuming from {:?} at event {}", path, event_num);
+                    return Ok((stack, restored));
+                }
+                warn!("Not resuming from {:?}: unexpected snapshot roots", path);
+            }
+            Err(e) => warn!("Not resuming from {:?}: {}", path, e),
+        }
+    }
+    let stack = NockStack::new_pma(path, size, 0).map_err(|e| e.to_string())?;
+    Ok((stack, None))
+}
+
 impl<C> SerfThread<C> {
     pub(crate) fn provide_metrics(
         &mut self,
@@ -159,6 +371,170 @@ impl<C> SerfThread<C> {
         }
     }
 
+    pub(crate) fn upgrade(&self, kernel: Vec<u8>) -> impl Future<Output = Result<NounSlab>> {
+        let (result, result_fut) = oneshot::channel();
+        let action_sender = self.action_sender.clone();
+        async move {
+            action_sender
+                .send(SerfAction::Upgrade { kernel, result })
+                .await?;
+            result_fut.await?
+        }
+    }
+
+    pub(crate) fn attach_event_log(&self, dir: PathBuf) -> impl Future<Output = Result<u64>> {
+        let (result, result_fut) = oneshot::channel();
+        let action_sender = self.action_sender.clone();
+        async move {
+            action_sender
+                .send(SerfAction::AttachEventLog { dir, result })
+                .await?;
+            result_fut.await?
+        }
+    }
+
+    pub(crate) fn reset_event_log(&self) -> impl Future<Output = Result<()>> {
+        let (result, result_fut) = oneshot::channel();
+        let action_sender = self.action_sender.clone();
+        async move {
+            action_sender
+                .send(SerfAction::ResetEventLog { result })
+                .await?;
+            result_fut.await?
+        }
+    }
+
+    pub(crate) fn replay(&self, dir: PathBuf) -> impl Future<Output = Result<ReplayReport>> {
+        let (result, result_fut) = oneshot::channel();
+        let action_sender = self.action_sender.clone();
+        async move {
+            action_sender
+                .send(SerfAction::Replay { dir, result })
+                .await?;
+            result_fut.await?
+        }
+    }
+
+    pub(crate) fn poke_job(&self, job: NounSlab) -> impl Future<Output = Result<NounSlab>> {
+        let (result, result_fut) = oneshot::channel();
+        let action_sender = self.action_sender.clone();
+        async move {
+            action_sender
+                .send(SerfAction::PokeJob { job, result })
+                .await?;
+            result_fut.await?
+        }
+    }
+
+    pub(crate) fn set_poke_timeout(
+        &self,
+        timeout: Option<Duration>,
+    ) -> impl Future<Output = Result<()>> {
+        let (result, result_fut) = oneshot::channel();
+        let action_sender = self.action_sender.clone();
+        async move {
+            action_sender
+                .send(SerfAction::SetPokeTimeout { timeout, result })
+                .await?;
+            result_fut.await?
+        }
+    }
+
+    pub(crate) fn set_loom_ceiling(
+        &self,
+        words: Option<usize>,
+    ) -> impl Future<Output = Result<()>> {
+        let (result, result_fut) = oneshot::channel();
+        let action_sender = self.action_sender.clone();
+        async move {
+            action_sender
+                .send(SerfAction::SetLoomCeiling { words, result })
+                .await?;
+            result_fut.await?
+        }
+    }
+
+    pub(crate) fn set_crash_dumps(&self, dir: Option<PathBuf>) -> impl Future<Output = Result<()>> {
+        let (result, result_fut) = oneshot::channel();
+        let action_sender = self.action_sender.clone();
+        async move {
+            action_sender
+                .send(SerfAction::SetCrashDumps { dir, result })
+                .await?;
+            result_fut.await?
+        }
+    }
+
+    pub(crate) fn set_memo_config(&self, config: MemoConfig) -> impl Future<Output = Result<()>> {
+        let (result, result_fut) = oneshot::channel();
+        let action_sender = self.action_sender.clone();
+        async move {
+            action_sender
+                .send(SerfAction::SetMemoConfig { config, result })
+                .await?;
+            result_fut.await?
+        }
+    }
+
+    pub(crate) fn set_bytecode(&self, threshold: Option<u32>) -> impl Future<Output = Result<()>> {
+        let (result, result_fut) = oneshot::channel();
+        let action_sender = self.action_sender.clone();
+        async move {
+            action_sender
+                .send(SerfAction::SetBytecode { threshold, result })
+                .await?;
+            result_fut.await?
+        }
+    }
+
+    pub(crate) fn set_shadow_check(
+        &self,
+        every: Option<u64>,
+        dir: PathBuf,
+    ) -> impl Future<Output = Result<()>> {
+        let (result, result_fut) = oneshot::channel();
+        let action_sender = self.action_sender.clone();
+        async move {
+            action_sender
+                .send(SerfAction::SetShadowCheck { every, dir, result })
+                .await?;
+            result_fut.await?
+        }
+    }
+
+    pub(crate) fn set_runtime_stats(&self, enabled: bool) -> impl Future<Output = Result<()>> {
+        let (result, result_fut) = oneshot::channel();
+        let action_sender = self.action_sender.clone();
+        async move {
+            action_sender
+                .send(SerfAction::SetRuntimeStats { enabled, result })
+                .await?;
+            result_fut.await?
+        }
+    }
+
+    pub(crate) fn runtime_stats(&self) -> impl Future<Output = Result<Option<StatsReport>>> {
+        let (result, result_fut) = oneshot::channel();
+        let action_sender = self.action_sender.clone();
+        async move {
+            action_sender
+                .send(SerfAction::GetRuntimeStats { result })
+                .await?;
+            Ok(result_fut.await?)
+        }
+    }
+
+    pub(crate) fn set_profile(&self, file: Option<PathBuf>) -> impl Future<Output = Result<()>> {
+        let (result, result_fut) = oneshot::channel();
+        let action_sender = self.action_sender.clone();
+        async move {
+            action_sender
+                .send(SerfAction::SetProfile { file, result })
+                .await?;
+            result_fut.await?
+        }
+    }
+
     pub(crate) fn stop(&mut self) -> impl Future<Output = Result<()>> {
         let action_sender = self.action_sender.clone();
         let cancel_token = self.cancel_token.clone();
@@ -205,22 +581,32 @@ impl<C> SerfThread<C> {
     pub(crate) fn peek(&self, ovo: NounSlab) -> impl Future<Output = Result<NounSlab>> {
         let (result, result_fut) = oneshot::channel();
         let action_sender = self.action_sender.clone();
+        let span = tracing::Span::current();
         async move {
-            action_sender.send(SerfAction::Peek { ovo, result }).await?;
+            action_sender
+                .send(SerfAction::Peek { ovo, result, span })
+                .await?;
             result_fut.await?
         }
     }
 
+    /// The thread the serf runs on, to set its affinity or priority
+    pub fn thread(&self) -> Option<&std::thread::JoinHandle<()>> {
+        self.handle.as_ref()
+    }
+
     // We are very carefully ensuring that the future does not contain the &self reference, to allow spawning a task without lifetime issues
     pub fn poke(&self, wire: WireRepr, cause: NounSlab) -> impl Future<Output = Result<NounSlab>> {
         let (result, result_fut) = oneshot::channel();
         let action_sender = self.action_sender.clone();
+        let span = tracing::Span::current();
         async move {
             action_sender
                 .send(SerfAction::Poke {
                     wire,
                     cause,
                     result,
+                    span,
                 })
                 .await?;
             result_fut.await?
@@ -233,14 +619,18 @@ impl<C> SerfThread<C> {
             wire,
             cause,
             result,
+            span: tracing::Span::current(),
         })?;
         result_fut.blocking_recv()?
     }
 
     pub(crate) fn peek_sync(&self, ovo: NounSlab) -> Result<NounSlab> {
         let (result, result_fut) = oneshot::channel();
-        self.action_sender
-            .blocking_send(SerfAction::Peek { ovo, result })?;
+        self.action_sender.blocking_send(SerfAction::Peek {
+            ovo,
+            result,
+            span: tracing::Span::current(),
+        })?;
         result_fut.blocking_recv()?
     }
 
@@ -255,6 +645,17 @@ impl<C> SerfThread<C> {
         }
     }
 
+    pub(crate) fn snapshot_pma(&self) -> impl Future<Output = Result<u64>> {
+        let (result, result_fut) = oneshot::channel();
+        let action_sender = self.action_sender.clone();
+        async move {
+            action_sender
+                .send(SerfAction::SnapshotPma { result })
+                .await?;
+            result_fut.await?
+        }
+    }
+
     pub fn import(&self, state: LoadState) -> impl Future<Output = Result<()>> {
         let (result, result_fut) = oneshot::channel();
         let action_sender = self.action_sender.clone();
@@ -295,6 +696,9 @@ fn serf_loop<C: SerfCheckpoint>(
         let action_start = std::time::Instant::now();
         match action {
             SerfAction::Stop => {
+                if let Err(e) = serf.write_profile() {
+                    error!("Failed to write the profile: {}", e);
+                }
                 break;
             }
             SerfAction::Export { result } => {
@@ -328,7 +732,7 @@ fn serf_loop<C: SerfCheckpoint>(
                     }
                     Ok(arvo) => {
                         if serf.ker_hash != state.ker_hash {
-                            debug!(
+                            warn!(
                                 "Importing state from kernel hash {} into kernel hash {}",
                                 state.ker_hash, serf.ker_hash
                             );
@@ -337,6 +741,11 @@ fn serf_loop<C: SerfCheckpoint>(
                             serf.event_update(state.event_num, arvo);
                             serf.preserve_event_update_leftovers();
                         }
+                        if let Some(log) = &mut serf.event_log {
+                            if let Err(e) = log.reset(state.event_num + 1) {
+                                error!("Failed to reset event log after import: {}", e);
+                            }
+                        }
                         let _ = result.send(Ok(())).map_err(|err| {
                             debug!("Tried to send to dropped channel: {:?}", err);
                         });
@@ -385,6 +794,9 @@ fn serf_loop<C: SerfCheckpoint>(
             SerfAction::Checkpoint { result } => {
                 let metrics_checkpoint = serf.metrics.clone();
                 let checkpoint = create_checkpoint(&mut serf, &metrics_checkpoint);
+                if let Err(e) = serf.checkpoint_event_log() {
+                    error!("Failed to rotate event log: {}", e);
+                }
                 //result.send(checkpoint).expect("Could not send checkpoint");
                 if result.send(checkpoint).is_err() {
                     debug!(
@@ -398,7 +810,20 @@ fn serf_loop<C: SerfCheckpoint>(
                         .add_timing(&action_elapsed);
                 };
             }
-            SerfAction::Peek { ovo, result } => {
+            SerfAction::SnapshotPma { result } => {
+                let res = serf.snapshot_pma();
+                if result.send(res).is_err() {
+                    debug!("Snapshot receiver dropped before receiving result");
+                };
+                let action_elapsed = action_start.elapsed();
+                if let Some(nockapp_metrics) = &serf.metrics {
+                    nockapp_metrics
+                        .serf_loop_checkpoint
+                        .add_timing(&action_elapsed);
+                };
+            }
+            SerfAction::Peek { ovo, result, span } => {
+                let _span = info_span!(parent: &span, "serf_peek").entered();
                 if inhibit.load(Ordering::SeqCst) {
                     let _ = result
                         .send(Err(CrownError::Unknown("Serf stopping".to_string())))
@@ -407,8 +832,10 @@ fn serf_loop<C: SerfCheckpoint>(
                             e
                         });
                 } else {
-                    let ovo_noun = ovo.copy_to_stack(serf.stack());
-                    let noun_res = serf.peek(ovo_noun);
+                    let noun_res = serf.grow_on_oom(|serf| {
+                        let ovo_noun = ovo.clone().copy_to_stack(serf.stack());
+                        serf.peek(ovo_noun)
+                    });
                     let noun_slab_res = noun_res.map(|noun| {
                         let mut slab = NounSlab::new();
                         slab.copy_into(noun);
@@ -422,13 +849,18 @@ fn serf_loop<C: SerfCheckpoint>(
                 let action_elapsed = action_start.elapsed();
                 if let Some(nockapp_metrics) = &serf.metrics {
                     nockapp_metrics.serf_loop_peek.add_timing(&action_elapsed);
+                    record_memo_metrics(&serf.context, nockapp_metrics);
+                    record_runtime_metrics(&serf.context, nockapp_metrics);
                 };
             }
             SerfAction::Poke {
                 wire,
                 cause,
                 result,
+                span,
             } => {
+                let source = wire.source;
+                let _span = info_span!(parent: &span, "serf_poke", source = %source).entered();
                 if inhibit.load(Ordering::SeqCst) {
                     let _ = result
                         .send(Err(CrownError::Unknown("Serf stopping".to_string())))
@@ -437,8 +869,10 @@ fn serf_loop<C: SerfCheckpoint>(
                             e
                         });
                 } else {
-                    let cause_noun = cause.copy_to_stack(serf.stack());
-                    let noun_res = serf.poke(wire, cause_noun);
+                    let noun_res = serf.grow_on_oom(|serf| {
+                        let cause_noun = cause.clone().copy_to_stack(serf.stack());
+                        serf.poke(wire.clone(), cause_noun)
+                    });
                     let noun_slab_res = noun_res.map(|noun| {
                         let mut slab = NounSlab::new();
                         slab.copy_into(noun);
@@ -452,7 +886,110 @@ fn serf_loop<C: SerfCheckpoint>(
                 let action_elapsed = action_start.elapsed();
                 if let Some(nockapp_metrics) = &serf.metrics {
                     nockapp_metrics.serf_loop_poke.add_timing(&action_elapsed);
+                    record_memo_metrics(&serf.context, nockapp_metrics);
+                    record_runtime_metrics(&serf.context, nockapp_metrics);
+                };
+            }
+            SerfAction::Upgrade { kernel, result } => {
+                let effects = if inhibit.load(Ordering::SeqCst) {
+                    Err(CrownError::Unknown("Serf stopping".to_string()))
+                } else {
+                    serf.upgrade(&kernel).map(|noun| {
+                        let mut slab = NounSlab::new();
+                        slab.copy_into(noun);
+                        slab
+                    })
                 };
+                let _ = result.send(effects).inspect_err(|_| {
+                    debug!("Failed to send upgrade result from serf thread");
+                });
+            }
+            SerfAction::AttachEventLog { dir, result } => {
+                let replayed = serf.attach_event_log(dir);
+                let _ = result.send(replayed).inspect_err(|_| {
+                    debug!("Failed to send event log result from serf thread");
+                });
+            }
+            SerfAction::ResetEventLog { result } => {
+                let res = serf.reset_event_log();
+                let _ = result.send(res).inspect_err(|_| {
+                    debug!("Failed to send event log reset result from serf thread");
+                });
+            }
+            SerfAction::Replay { dir, result } => {
+                let report = serf.replay(&dir);
+                let _ = result.send(report).inspect_err(|_| {
+                    debug!("Failed to send replay result from serf thread");
+                });
+            }
+            SerfAction::PokeJob { job, result } => {
+                let effects = serf
+                    .grow_on_oom(|serf| {
+                        let job = job.clone().copy_to_stack(serf.stack());
+                        serf.do_poke(job)
+                    })
+                    .map(|noun| {
+                        let mut slab = NounSlab::new();
+                        slab.copy_into(noun);
+                        slab
+                    });
+                let _ = result.send(effects).inspect_err(|_| {
+                    debug!("Failed to send poke job result from serf thread");
+                });
+            }
+            SerfAction::SetLoomCeiling { words, result } => {
+                let res = serf.set_loom_ceiling(words);
+                let _ = result.send(res).inspect_err(|_| {
+                    debug!("Failed to send loom ceiling result from serf thread");
+                });
+            }
+            SerfAction::SetPokeTimeout { timeout, result } => {
+                let res = serf.set_poke_timeout(timeout);
+                let _ = result.send(res).inspect_err(|_| {
+                    debug!("Failed to send poke timeout result from serf thread");
+                });
+            }
+            SerfAction::SetCrashDumps { dir, result } => {
+                let res = serf.set_crash_dumps(dir);
+                let _ = result.send(res).inspect_err(|_| {
+                    debug!("Failed to send crash dump result from serf thread");
+                });
+            }
+            SerfAction::SetProfile { file, result } => {
+                let res = serf.set_profile(file);
+                let _ = result.send(res).inspect_err(|_| {
+                    debug!("Failed to send profile result from serf thread");
+                });
+            }
+            SerfAction::SetMemoConfig { config, result } => {
+                serf.context.memo_config = config;
+                let _ = result.send(Ok(())).inspect_err(|_| {
+                    debug!("Failed to send memo config result from serf thread");
+                });
+            }
+            SerfAction::SetBytecode { threshold, result } => {
+                serf.context.bytecode =
+                    threshold.map(|threshold| Bytecode::new(&mut serf.context.stack, threshold));
+                let _ = result.send(Ok(())).inspect_err(|_| {
+                    debug!("Failed to send bytecode result from serf thread");
+                });
+            }
+            SerfAction::SetShadowCheck { every, dir, result } => {
+                let res = serf.set_shadow_check(every, dir);
+                let _ = result.send(res).inspect_err(|_| {
+                    debug!("Failed to send shadow check result from serf thread");
+                });
+            }
+            SerfAction::SetRuntimeStats { enabled, result } => {
+                serf.context.stats = enabled.then(RuntimeStats::new);
+                let _ = result.send(Ok(())).inspect_err(|_| {
+                    debug!("Failed to send runtime stats result from serf thread");
+                });
+            }
+            SerfAction::GetRuntimeStats { result } => {
+                let _ = result.send(stats::report(&serf.context)).inspect_err(|_| {
+                    debug!("Failed to send runtime stats from serf thread");
+                });
             }
             SerfAction::ProvideMetrics { metrics, result } => {
                 serf.metrics = Some(metrics);
@@ -475,6 +1012,33 @@ fn serf_loop<C: SerfCheckpoint>(
     }
 }
 
+fn record_memo_metrics(context: &interpreter::Context, metrics: &NockAppMetrics) {
+    let stats = context.memo_stats;
+    metrics.memo_hits.swap(stats.hits as f64);
+    metrics.memo_misses.swap(stats.misses as f64);
+    metrics.memo_evictions.swap(stats.evictions as f64);
+    metrics.memo_entries.swap(context.cache.entries() as f64);
+}
+
+fn record_runtime_metrics(context: &interpreter::Context, metrics: &NockAppMetrics) {
+    let Some(report) = stats::report(context) else {
+        return;
+    };
+    metrics.nock_ops.swap(report.nock_ops as f64);
+    metrics.jet_calls.swap(report.jet_calls as f64);
+    metrics
+        .stack_allocations
+        .swap(report.stack.allocations as f64);
+    metrics
+        .stack_allocated_words
+        .swap(report.stack.allocated_words as f64);
+    metrics.stack_copies.swap(report.stack.copies as f64);
+    metrics
+        .stack_copied_words
+        .swap(report.stack.copied_words as f64);
+    metrics.max_stack_words.swap(report.max_stack_words as f64);
+}
+
 fn create_checkpoint<C: SerfCheckpoint>(
     serf: &mut Serf,
     metrics: &Option<Arc<NockAppMetrics>>,
@@ -526,11 +1090,31 @@ impl<C: SerfCheckpoint + 'static> Kernel<C> {
         hot_state: &[HotEntry],
         test_jets: Vec<NounSlab>,
         trace: bool,
+    ) -> Result<Self> {
+        Self::load_with_stack_config(
+            kernel,
+            checkpoint,
+            hot_state,
+            StackConfig::with_nock_stack_size(NOCK_STACK_SIZE),
+            test_jets,
+            trace,
+        )
+        .await
+    }
+
+    /// Like [`Kernel::load_with_hot_state`], but with the stack sizes in `stack_config`.
+    pub async fn load_with_stack_config(
+        kernel: &[u8],
+        checkpoint: Option<C>,
+        hot_state: &[HotEntry],
+        stack_config: StackConfig,
+        test_jets: Vec<NounSlab>,
+        trace: bool,
     ) -> Result<Self> {
         let kernel_vec = Vec::from(kernel);
         let hot_state_vec = Vec::from(hot_state);
-        let serf = SerfThread::new(
-            kernel_vec, checkpoint, hot_state_vec, NOCK_STACK_SIZE, test_jets, trace,
+        let serf = SerfThread::with_stack_config(
+            kernel_vec, checkpoint, hot_state_vec, stack_config, test_jets, trace,
         )
         .await?;
         Ok(Self { serf })
@@ -543,13 +1127,15 @@ impl<C: SerfCheckpoint + 'static> Kernel<C> {
         test_jets: Vec<NounSlab>,
         trace: bool,
     ) -> Result<Self> {
-        let kernel_vec = Vec::from(kernel);
-        let hot_state_vec = Vec::from(hot_state);
-        let serf = SerfThread::new(
-            kernel_vec, checkpoint, hot_state_vec, NOCK_STACK_SIZE_TINY, test_jets, trace,
+        Self::load_with_stack_config(
+            kernel,
+            checkpoint,
+            hot_state,
+            StackConfig::with_nock_stack_size(NOCK_STACK_SIZE_TINY),
+            test_jets,
+            trace,
         )
-        .await?;
-        Ok(Self { serf })
+        .await
     }
 
     pub async fn load_with_hot_state_small(
@@ -559,13 +1145,15 @@ impl<C: SerfCheckpoint + 'static> Kernel<C> {
         test_jets: Vec<NounSlab>,
         trace: bool,
     ) -> Result<Self> {
-        let kernel_vec = Vec::from(kernel);
-        let hot_state_vec = Vec::from(hot_state);
-        let serf = SerfThread::new(
-            kernel_vec, checkpoint, hot_state_vec, NOCK_STACK_SIZE_SMALL, test_jets, trace,
+        Self::load_with_stack_config(
+            kernel,
+            checkpoint,
+            hot_state,
+            StackConfig::with_nock_stack_size(NOCK_STACK_SIZE_SMALL),
+            test_jets,
+            trace,
         )
-        .await?;
-        Ok(Self { serf })
+        .await
     }
 
     pub async fn load_with_hot_state_medium(
@@ -575,13 +1163,15 @@ impl<C: SerfCheckpoint + 'static> Kernel<C> {
         test_jets: Vec<NounSlab>,
         trace: bool,
     ) -> Result<Self> {
-        let kernel_vec = Vec::from(kernel);
-        let hot_state_vec = Vec::from(hot_state);
-        let serf = SerfThread::new(
-            kernel_vec, checkpoint, hot_state_vec, NOCK_STACK_SIZE_MEDIUM, test_jets, trace,
+        Self::load_with_stack_config(
+            kernel,
+            checkpoint,
+            hot_state,
+            StackConfig::with_nock_stack_size(NOCK_STACK_SIZE_MEDIUM),
+            test_jets,
+            trace,
         )
-        .await?;
-        Ok(Self { serf })
+        .await
     }
 
     pub async fn load_with_hot_state_large(
@@ -591,13 +1181,15 @@ impl<C: SerfCheckpoint + 'static> Kernel<C> {
         test_jets: Vec<NounSlab>,
         trace: bool,
     ) -> Result<Self> {
-        let kernel_vec = Vec::from(kernel);
-        let hot_state_vec = Vec::from(hot_state);
-        let serf = SerfThread::new(
-            kernel_vec, checkpoint, hot_state_vec, NOCK_STACK_SIZE_LARGE, test_jets, trace,
+        Self::load_with_stack_config(
+            kernel,
+            checkpoint,
+            hot_state,
+            StackConfig::with_nock_stack_size(NOCK_STACK_SIZE_LARGE),
+            test_jets,
+            trace,
         )
-        .await?;
-        Ok(Self { serf })
+        .await
     }
 
     pub async fn load_with_hot_state_huge(
@@ -607,13 +1199,15 @@ impl<C: SerfCheckpoint + 'static> Kernel<C> {
         test_jets: Vec<NounSlab>,
         trace: bool,
     ) -> Result<Self> {
-        let kernel_vec = Vec::from(kernel);
-        let hot_state_vec = Vec::from(hot_state);
-        let serf = SerfThread::new(
-            kernel_vec, checkpoint, hot_state_vec, NOCK_STACK_SIZE_HUGE, test_jets, trace,
+        Self::load_with_stack_config(
+            kernel,
+            checkpoint,
+            hot_state,
+            StackConfig::with_nock_stack_size(NOCK_STACK_SIZE_HUGE),
+            test_jets,
+            trace,
         )
-        .await?;
-        Ok(Self { serf })
+        .await
     }
 
     /// Loads a kernel with default hot state.
@@ -640,6 +1234,66 @@ impl<C: SerfCheckpoint + 'static> Kernel<C> {
     pub fn checkpoint(&self) -> impl Future<Output = Result<C>> {
         self.serf.checkpoint()
     }
+
+    /// Like [`Kernel::load_with_stack_config`], but with the loom in a persistent memory arena at
+    /// `pma`, see [`nockvm::pma`]. If the arena has a snapshot at least as recent as `checkpoint`,
+    /// the kernel resumes from it without copying any state.
+    pub async fn load_with_pma(
+        kernel: &[u8],
+        checkpoint: Option<C>,
+        hot_state: &[HotEntry],
+        stack_config: StackConfig,
+        pma: PathBuf,
+        test_jets: Vec<NounSlab>,
+        trace: bool,
+    ) -> Result<Self> {
+        let serf = SerfThread::with_pma(
+            Vec::from(kernel),
+            checkpoint,
+            Vec::from(hot_state),
+            stack_config,
+            Some(pma),
+            test_jets,
+            trace,
+        )
+        .await?;
+        Ok(Self { serf })
+    }
+
+    /// Whether the kernel's loom is a persistent memory arena, saved with
+    /// [`Kernel::snapshot_pma`] rather than [`Kernel::checkpoint`].
+    pub fn uses_pma(&self) -> bool {
+        self.serf.pma
+    }
+
+    /// Flush the pages of the persistent loom dirtied since the last snapshot, returning the
+    /// event number it was taken at. Fails if the loom isn't persistent.
+    pub fn snapshot_pma(&self) -> impl Future<Output = Result<u64>> {
+        self.serf.snapshot_pma()
+    }
+
+    /// What [`NockApp`](crate::NockApp) saves: a snapshot of the persistent loom if there is one,
+    /// or else a checkpoint.
+    pub(crate) fn save_point(&self) -> impl Future<Output = Result<SavePoint<C>>> {
+        let save = if self.uses_pma() {
+            Either::Left(self.snapshot_pma())
+        } else {
+            Either::Right(self.checkpoint())
+        };
+        async move {
+            match save {
+                Either::Left(snapshot) => Ok(SavePoint::Snapshot(snapshot.await?)),
+                Either::Right(checkpoint) => Ok(SavePoint::Checkpoint(checkpoint.await?)),
+            }
+        }
+    }
+}
+
+/// A saved kernel state, see [`Kernel::save_point`]
+pub(crate) enum SavePoint<C> {
+    Checkpoint(C),
+    /// The event number the persistent loom was snapshotted at
+    Snapshot(u64),
 }
 
 impl<C> Kernel<C> {
@@ -666,6 +1320,115 @@ impl<C> Kernel<C> {
         self.serf.import(state)
     }
 
+    /// Swap in a new kernel, migrating the current state into it with a `%migrate` poke.
+    /// Returns the effects of the migration.
+    pub fn upgrade(&self, kernel: Vec<u8>) -> impl Future<Output = Result<NounSlab>> {
+        self.serf.upgrade(kernel)
+    }
+
+    /// Replay the event log in `dir` past the loaded checkpoint, then log every new event there.
+    /// Returns the number of events replayed.
+    pub fn attach_event_log(&self, dir: PathBuf) -> impl Future<Output = Result<u64>> {
+        self.serf.attach_event_log(dir)
+    }
+
+    /// Delete the event log, if there is one, and start it over after the current event.
+    pub fn reset_event_log(&self) -> impl Future<Output = Result<()>> {
+        self.serf.reset_event_log()
+    }
+
+    /// Replay every event in the log in `dir` on top of the current state, checking the state
+    /// against each mug recorded in the log. Fails with [`CrownError::ReplayDiverged`] at the
+    /// first mismatch. New events are not logged.
+    pub fn replay(&self, dir: PathBuf) -> impl Future<Output = Result<ReplayReport>> {
+        self.serf.replay(dir)
+    }
+
+    /// Run a logged job `[event_num wire eny our now cause]` again, returning its effects. The
+    /// event isn't logged, and its number should be the one after the current event.
+    pub fn poke_job(&self, job: NounSlab) -> impl Future<Output = Result<NounSlab>> {
+        self.serf.poke_job(job)
+    }
+
+    /// Interrupt whatever computation the kernel is running, returning whether there was one.
+    ///
+    /// The interpreter stops at its next Nock 2 or 9 and unwinds. An interrupted poke fails with
+    /// [`CrownError::Interrupted`] and leaves the kernel state as it was, and an interrupted peek
+    /// fails. Computations started afterwards run normally.
+    pub fn interrupt(&self) -> bool {
+        self.serf.cancel_token.cancel()
+    }
+
+    /// A token that interrupts the kernel's running computation, like [`Kernel::interrupt`], from
+    /// anywhere.
+    pub fn cancel_token(&self) -> NockCancelToken {
+        self.serf.cancel_token.clone()
+    }
+
+    /// Interrupt pokes that run for longer than `timeout` and fail them with
+    /// [`CrownError::PokeTimeout`]. `None` lets pokes run indefinitely, which is the default.
+    pub fn set_poke_timeout(&self, timeout: Option<Duration>) -> impl Future<Output = Result<()>> {
+        self.serf.set_poke_timeout(timeout)
+    }
+
+    /// Write a crash dump under `dir` for every poke the kernel bails on, see
+    /// [`crate::kernel::crash_dump`]. `None` turns crash dumps off, which is the default.
+    pub fn set_crash_dumps(&self, dir: Option<PathBuf>) -> impl Future<Output = Result<()>> {
+        self.serf.set_crash_dumps(dir)
+    }
+
+    /// Limit the size of the interpreter's `%memo` cache and choose what happens when it fills, see
+    /// [`nockvm::memo`]. The cache is unbounded by default. Its hits, misses, evictions and size
+    /// are reported through [`NockAppMetrics`] after every poke and peek.
+    pub fn set_memo_config(&self, config: MemoConfig) -> impl Future<Output = Result<()>> {
+        self.serf.set_memo_config(config)
+    }
+
+    /// Compile formulas the kernel calls at least `threshold` times into bytecode, see
+    /// [`nockvm::bytecode`]. `None` stops compiling and drops the compiled formulas, which is the
+    /// default.
+    pub fn set_bytecode(&self, threshold: Option<u32>) -> impl Future<Output = Result<()>> {
+        self.serf.set_bytecode(threshold)
+    }
+
+    /// Check one in every `every` jet calls against the Nock it replaces, see [`nockvm::shadow`].
+    /// Each mismatch is logged and written to `dir` as the jam of `[path subject jet nock]`,
+    /// where `nock` is `[%& product]` or `[%| mote]`. `None` stops checking, which is the default.
+    pub fn set_shadow_check(
+        &self,
+        every: Option<u64>,
+        dir: PathBuf,
+    ) -> impl Future<Output = Result<()>> {
+        self.serf.set_shadow_check(every, dir)
+    }
+
+    /// Let the loom grow up to `words` 64-bit words when a poke or peek runs out of memory, by
+    /// moving the kernel to a loom twice the size and running it again. `None`, the default,
+    /// means computations that run out of memory crash the serf. A persistent loom can't grow.
+    pub fn set_loom_ceiling(&self, words: Option<usize>) -> impl Future<Output = Result<()>> {
+        self.serf.set_loom_ceiling(words)
+    }
+
+    /// Profile kernel computations, writing folded stacks for a flamegraph to `file` when the
+    /// kernel stops or profiling is switched again, see [`nockvm::profile`]. `None` stops
+    /// profiling, which is the default.
+    pub fn set_profile(&self, file: Option<PathBuf>) -> impl Future<Output = Result<()>> {
+        self.serf.set_profile(file)
+    }
+
+    /// Count the Nock steps the kernel takes and its jet hits by path, see [`nockvm::stats`].
+    /// While counting, the totals and the loom's allocation counters are reported through
+    /// [`NockAppMetrics`] after every poke and peek. Off by default, since hot formulas aren't run
+    /// from bytecode while counting.
+    pub fn set_runtime_stats(&self, enabled: bool) -> impl Future<Output = Result<()>> {
+        self.serf.set_runtime_stats(enabled)
+    }
+
+    /// Poll the runtime statistics, or `None` if they aren't being counted
+    pub fn runtime_stats(&self) -> impl Future<Output = Result<Option<StatsReport>>> {
+        self.serf.runtime_stats()
+    }
+
     pub fn export(&self) -> impl Future<Output = Result<LoadState>> {
         self.serf.export()
     }
@@ -678,6 +1441,19 @@ impl<C> Kernel<C> {
     }
 }
 
+/// The outcome of a successful [`Kernel::replay`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// The event the replay started after
+    pub start: u64,
+    /// The last event replayed
+    pub end: u64,
+    /// How many recorded state mugs were checked
+    pub checked: usize,
+    /// The mug of the kernel state after the last event
+    pub state_mug: u32,
+}
+
 /// Represents the Serf, which maintains context and provides an interface to
 /// the Sword.
 pub struct Serf {
@@ -693,6 +1469,21 @@ pub struct Serf {
     pub event_num: Arc<AtomicU64>,
     /// A metrics
     pub metrics: Option<Arc<NockAppMetrics>>,
+    /// Where processed events are logged, if anywhere
+    pub event_log: Option<EventLog>,
+    /// How long a poke may run before it is interrupted
+    pub poke_timeout: Option<Duration>,
+    /// Watchdog enforcing `poke_timeout`, started the first time a timeout is set
+    deadline: Option<Deadline>,
+    /// Where crash dumps for failed pokes are written, if anywhere
+    crash_dumps: Option<CrashDumper>,
+    /// Where the profile is written, if profiling
+    profile_file: Option<PathBuf>,
+    /// The most words the loom may grow to when it runs out of memory, if it may grow
+    loom_ceiling: Option<usize>,
+    /// What the context was created with, to recreate it on a bigger loom
+    hot_state: Vec<HotEntry>,
+    test_jets: Vec<NounSlab>,
 }
 
 impl Serf {
@@ -702,6 +1493,8 @@ impl Serf {
     ///
     /// * `stack` - The Nock stack.
     /// * `checkpoint` - Optional checkpoint to restore from.
+    /// * `restored` - The `[state event_num]` of a persistent loom's snapshot, if the stack was
+    ///   reopened from one. It is used over `checkpoint` unless older.
     /// * `kernel_bytes` - Byte slice containing the kernel code.
     /// * `constant_hot_state` - Custom hot state entries.
     /// * `trace` - Bool indicating whether to enable nockvm tracing.
@@ -712,6 +1505,7 @@ impl Serf {
     fn new<C: SerfCheckpoint>(
         mut stack: NockStack,
         checkpoint: Option<C>,
+        restored: Option<(Noun, u64)>,
         kernel_bytes: &[u8],
         constant_hot_state: &[HotEntry],
         test_jets: Vec<NounSlab>,
@@ -723,29 +1517,44 @@ impl Serf {
         hasher.update(kernel_bytes);
         let ker_hash = hasher.finalize();
 
-        let (maybe_state, cold, event_num_raw) = if let Some(c) = checkpoint {
-            let saveable = c.load();
-
-            let checkpoint_noun = saveable.noun.copy_to_stack(&mut stack);
-            let checkpoint_cell = checkpoint_noun
-                .as_cell()
-                .expect("snapshot noun should be a cell");
-            let ker_state = checkpoint_cell.head();
-            let cold_noun = checkpoint_cell.tail();
-            let cold_vecs = Cold::from_noun(&mut stack, &cold_noun)
-                .expect("Could not load cold state from snapshot");
-            let cold = Cold::from_vecs(&mut stack, cold_vecs.0, cold_vecs.1, cold_vecs.2);
-            if saveable.ker_hash != ker_hash {
-                debug!(
-                    "Loading snapshot from kernel {} into kernel {}",
-                    saveable.ker_hash, ker_hash
-                );
-            }
-            (Some(ker_state), cold, saveable.event_num)
-        } else {
-            (None, Cold::new(&mut stack), 0)
+        let saveable = checkpoint.map(|c| c.load());
+        let restored = restored.filter(|(_, event_num)| {
+            saveable
+                .as_ref()
+                .is_none_or(|saveable| saveable.event_num <= *event_num)
+        });
+        let snapshot = match (restored, saveable) {
+            // Already on the stack
+            (Some((state, event_num)), _) => Some((state, event_num, None)),
+            (None, Some(saveable)) => Some((
+                saveable.noun.copy_to_stack(&mut stack),
+                saveable.event_num,
+                Some(saveable.ker_hash),
+            )),
+            (None, None) => None,
         };
 
+        let (maybe_state, cold, event_num_raw) =
+            if let Some((checkpoint_noun, event_num, saved_hash)) = snapshot {
+                let checkpoint_cell = checkpoint_noun
+                    .as_cell()
+                    .expect("snapshot noun should be a cell");
+                let ker_state = checkpoint_cell.head();
+                let cold_noun = checkpoint_cell.tail();
+                let cold_vecs = Cold::from_noun(&mut stack, &cold_noun)
+                    .expect("Could not load cold state from snapshot");
+                let cold = Cold::from_vecs(&mut stack, cold_vecs.0, cold_vecs.1, cold_vecs.2);
+                if let Some(saved_hash) = saved_hash.filter(|saved_hash| *saved_hash != ker_hash) {
+                    debug!(
+                        "Loading snapshot from kernel {} into kernel {}",
+                        saved_hash, ker_hash
+                    );
+                }
+                (Some(ker_state), cold, event_num)
+            } else {
+                (None, Cold::new(&mut stack), 0)
+            };
+
         let event_num = Arc::new(AtomicU64::new(event_num_raw));
 
         let trace_info = if trace {
@@ -761,7 +1570,7 @@ impl Serf {
             None
         };
 
-        let mut context = create_context(stack, &hot_state, cold, trace_info, test_jets);
+        let mut context = create_context(stack, &hot_state, cold, trace_info, test_jets.clone());
         let cancel_token = context.cancel_token();
 
         let mut arvo = {
@@ -800,6 +1609,14 @@ impl Serf {
             event_num,
             cancel_token,
             metrics: None,
+            event_log: None,
+            poke_timeout: None,
+            deadline: None,
+            crash_dumps: None,
+            profile_file: None,
+            loom_ceiling: None,
+            hot_state,
+            test_jets,
         };
 
         if let Some(kernel_state) = maybe_state {
@@ -896,19 +1713,264 @@ impl Serf {
     #[tracing::instrument(level = "info", skip_all)]
     pub fn do_poke(&mut self, job: Noun) -> Result<Noun> {
         match self.soft(job, POKE_AXIS, Some("poke".to_string())) {
-            Ok(res) => {
-                let cell = res.as_cell().expect("serf: poke: +slam returned atom");
-                let mut fec = cell.head();
-                let eve = self.event_num.load(Ordering::SeqCst);
+            Ok(res) => Ok(self.poke_commit(res)),
+            Err(goof) => self.poke_swap(job, goof, false),
+        }
+    }
 
-                unsafe {
-                    self.event_update(eve + 1, cell.tail());
-                    self.stack().preserve(&mut fec);
-                    self.preserve_event_update_leftovers();
+    /// Like [`Serf::do_poke`], but interrupts the poke if it runs past `poke_timeout`, and writes
+    /// no `poke.jam` into the crash dump if `secret`.
+    ///
+    /// A poke interrupted by the deadline or through the cancel token is dropped without running
+    /// `+crud` or advancing the event number.
+    fn do_poke_with_deadline(&mut self, job: Noun, secret: bool) -> Result<Noun> {
+        if let (Some(timeout), Some(deadline)) = (self.poke_timeout, &self.deadline) {
+            deadline.arm(timeout);
+        }
+        let res = self.soft(job, POKE_AXIS, Some("poke".to_string()));
+        let fired = self
+            .deadline
+            .as_ref()
+            .is_some_and(|deadline| deadline.disarm());
+        match res {
+            Ok(res) => Ok(self.poke_commit(res)),
+            Err(goof) if fired || is_interrupt(goof) => {
+                self.context.cache = MemoCache::new(&mut self.context.stack);
+                match self.poke_timeout {
+                    Some(timeout) if fired => {
+                        warn!("Poke interrupted after running for {:?}", timeout);
+                        Err(CrownError::PokeTimeout(timeout))
+                    }
+                    _ => {
+                        warn!("Poke interrupted");
+                        Err(CrownError::Interrupted)
+                    }
                 }
-                Ok(fec)
             }
-            Err(goof) => self.poke_swap(job, goof),
+            Err(goof) => self.poke_swap(job, goof, secret),
+        }
+    }
+
+    /// Advance to the kernel returned by a successful poke and return its effects.
+    fn poke_commit(&mut self, res: Noun) -> Noun {
+        let cell = res.as_cell().expect("serf: poke: +slam returned atom");
+        let mut fec = cell.head();
+        let eve = self.event_num.load(Ordering::SeqCst);
+
+        unsafe {
+            self.event_update(eve + 1, cell.tail());
+            self.stack().preserve(&mut fec);
+            self.preserve_event_update_leftovers();
+        }
+        if let Some(dumper) = &mut self.crash_dumps {
+            dumper.record_effects(eve + 1, fec);
+        }
+        fec
+    }
+
+    pub fn set_poke_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        if timeout.is_some() && self.deadline.is_none() {
+            self.deadline = Some(Deadline::new(self.cancel_token.clone())?);
+        }
+        self.poke_timeout = timeout;
+        Ok(())
+    }
+
+    pub fn set_crash_dumps(&mut self, dir: Option<PathBuf>) -> Result<()> {
+        self.crash_dumps = match dir {
+            Some(dir) => {
+                std::fs::create_dir_all(&dir)?;
+                Some(CrashDumper::new(dir))
+            }
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// Check one in every `every` jet calls against its Nock, writing mismatches into `dir`, or
+    /// stop if `None`.
+    pub fn set_shadow_check(&mut self, every: Option<u64>, dir: PathBuf) -> Result<()> {
+        let Some(every) = every else {
+            self.context.shadow = None;
+            return Ok(());
+        };
+        std::fs::create_dir_all(&dir)?;
+        let mut found = 0u64;
+        let report = move |stack: &mut NockStack, mismatch: &ShadowMismatch| {
+            found += 1;
+            let cord = path_to_cord(stack, mismatch.path);
+            let path = String::from_utf8_lossy(cord.as_ne_bytes())
+                .trim_end_matches('\0')
+                .to_string();
+            let nock = match mismatch.nock {
+                Ok(product) => T(stack, &[D(0), product]),
+                Err(Error::Deterministic(mote, _) | Error::NonDeterministic(mote, _)) => {
+                    T(stack, &[D(1), D(mote as u64)])
+                }
+                Err(_) => T(stack, &[D(1), D(tas!(b"fail"))]),
+            };
+            let record = T(
+                stack,
+                &[mismatch.path, mismatch.subject, mismatch.jet, nock],
+            );
+            let file = dir.join(format!("{}.jam", found));
+            match std::fs::write(&file, NockJammer::jam(record)) {
+                Ok(()) => error!(
+                    "Jet {} disagreed with its Nock, wrote {}",
+                    path,
+                    file.display()
+                ),
+                Err(e) => error!(
+                    "Jet {} disagreed with its Nock, failed to write {}: {}",
+                    path,
+                    file.display(),
+                    e
+                ),
+            }
+        };
+        self.context.shadow = Some(ShadowCheck::new(every, Box::new(report)));
+        Ok(())
+    }
+
+    /// Let the loom grow up to `words` when a poke or peek runs out of memory, or never if `None`.
+    pub fn set_loom_ceiling(&mut self, words: Option<usize>) -> Result<()> {
+        if words.is_some() && self.context.stack.pma().is_some() {
+            return Err(CrownError::Unknown(
+                "a persistent loom can't grow".to_string(),
+            ));
+        }
+        self.loom_ceiling = words;
+        Ok(())
+    }
+
+    /// Run `f`, and if the loom runs out of memory, move the kernel to a bigger loom and run it
+    /// again, until it succeeds or the loom reaches the ceiling.
+    fn grow_on_oom<T>(&mut self, mut f: impl FnMut(&mut Serf) -> T) -> T {
+        if self.loom_ceiling.is_none() {
+            return f(self);
+        }
+        loop {
+            match catch_unwind(AssertUnwindSafe(|| f(self))) {
+                Ok(res) => return res,
+                Err(payload) => {
+                    let oom = matches!(
+                        payload.downcast_ref::<AllocationError>(),
+                        Some(AllocationError::OutOfMemory(_))
+                    );
+                    if !oom || !self.grow_loom() {
+                        resume_unwind(payload);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Move the kernel to a loom twice the size, capped at the ceiling, abandoning whatever
+    /// computation ran out of memory. Returns `false` if the loom is already at the ceiling.
+    fn grow_loom(&mut self) -> bool {
+        let Some(ceiling) = self.loom_ceiling else {
+            return false;
+        };
+        let size = self.context.stack.size();
+        if size >= ceiling {
+            return false;
+        }
+        let new_size = size.saturating_mul(2).min(ceiling);
+        let mut stack = match NockStack::new_(new_size, 0) {
+            Ok((stack, _)) => stack,
+            Err(e) => {
+                error!("Could not grow the loom to {} words: {}", new_size, e);
+                return false;
+            }
+        };
+
+        // The kernel and cold states are senior to the abandoned computation, so are intact
+        let mut slab: NounSlab = NounSlab::new();
+        let cold_noun = self.context.cold.into_noun(&mut slab);
+        let state = T(&mut slab, &[self.arvo, cold_noun]);
+        let state = slab.copy_into(state);
+        slab.set_root(state);
+        let state = slab
+            .copy_to_stack(&mut stack)
+            .as_cell()
+            .expect("serf: grow: state should be a cell");
+        let cold_vecs = Cold::from_noun(&mut stack, &state.tail())
+            .expect("serf: grow: could not copy the cold state");
+        let cold = Cold::from_vecs(&mut stack, cold_vecs.0, cold_vecs.1, cold_vecs.2);
+
+        let mut context = create_context(
+            stack,
+            &self.hot_state,
+            cold,
+            self.context.trace_info.take(),
+            self.test_jets.clone(),
+        );
+        // Keep the cancel token handed out to the deadline and the kernel handle
+        context.running_status = self.context.running_status.clone();
+        context
+            .running_status
+            .store(NockCancelToken::RUNNING_IDLE, Ordering::SeqCst);
+        context.memo_config = self.context.memo_config;
+        context.memo_stats = self.context.memo_stats;
+        context.profiler = self.context.profiler.take();
+        context.shadow = self.context.shadow.take();
+        context.stats = self.context.stats.take();
+        context.bytecode = self
+            .context
+            .bytecode
+            .as_ref()
+            .map(|bytecode| Bytecode::new(&mut context.stack, bytecode.threshold));
+        self.context = context;
+        self.arvo = state.head();
+        if let Some(deadline) = &self.deadline {
+            deadline.disarm();
+        }
+        unsafe { self.preserve_event_update_leftovers() };
+        warn!(
+            "Loom ran out of memory, grew it from {} to {} words",
+            size, new_size
+        );
+        true
+    }
+
+    /// Write the profile so far, then profile into `file` from now on, or stop if `None`.
+    pub fn set_profile(&mut self, file: Option<PathBuf>) -> Result<()> {
+        self.write_profile()?;
+        self.context.profiler = file.as_ref().map(|_| Profiler::new());
+        self.profile_file = file;
+        Ok(())
+    }
+
+    /// Write the folded stacks profiled so far, if profiling.
+    pub fn write_profile(&self) -> Result<()> {
+        let (Some(profiler), Some(file)) = (&self.context.profiler, &self.profile_file) else {
+            return Ok(());
+        };
+        let mut out = std::io::BufWriter::new(File::create(file)?);
+        profiler.write_folded(&mut out)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Log the decoded trace of a bailed poke and write its crash dump, if enabled. A `job` of
+    /// `None` is left out of the dump.
+    fn dump_crash(&self, job: Option<Noun>, goof: Noun) {
+        let Some(dumper) = &self.crash_dumps else {
+            return;
+        };
+        let event_num = self.event_num.load(Ordering::SeqCst) + 1;
+        error!(
+            "Poke for event {} bailed:\n{}",
+            event_num,
+            render_goof(goof).join("\n")
+        );
+        match dumper.dump(event_num, job, goof) {
+            Ok(path) => error!("Wrote crash dump to {}", path.display()),
+            Err(e) => error!(
+                "Failed to write crash dump in {}: {}",
+                dumper.dir().display(),
+                e
+            ),
         }
     }
 
@@ -1028,9 +2090,10 @@ impl Serf {
     /// # Returns
     ///
     /// Result containing the new event or an error.
-    fn poke_swap(&mut self, job: Noun, goof: Noun) -> Result<Noun> {
+    fn poke_swap(&mut self, job: Noun, goof: Noun, secret: bool) -> Result<Noun> {
+        self.dump_crash((!secret).then_some(job), goof);
         let stack = &mut self.context.stack;
-        self.context.cache = Hamt::<Noun>::new(stack);
+        self.context.cache = MemoCache::new(stack);
         let job_cell = job.as_cell().expect("serf: poke: job not a cell");
         // job data is job without event_num
         let job_data = job_cell
@@ -1126,6 +2189,55 @@ impl Serf {
         src = wire.source
     ))]
     pub fn poke(&mut self, wire: WireRepr, cause: Noun) -> Result<Noun> {
+        let poke = self.poke_job(&wire, cause)?;
+
+        if self.event_log.is_none() {
+            return self.do_poke_with_deadline(poke, wire.secret);
+        }
+        if wire.secret {
+            return self.secret_poke(poke);
+        }
+        // The job doesn't survive the event update, so jam it beforehand
+        let jam = NockJammer::jam(poke);
+        let before = self.event_num.load(Ordering::SeqCst);
+        let res = self.do_poke_with_deadline(poke, false);
+        let after = self.event_num.load(Ordering::SeqCst);
+        if after > before {
+            if let Some(log) = &mut self.event_log {
+                if let Err(e) = log.append(after, &jam) {
+                    error!("Failed to log event {}: {}", after, e);
+                }
+            }
+        }
+        res
+    }
+
+    /// Runs a poke from a secret wire without logging it. The log can't be replayed past an event
+    /// it's missing, so like an upgrade, it starts over after this one.
+    fn secret_poke(&mut self, job: Noun) -> Result<Noun> {
+        let before = self.event_num.load(Ordering::SeqCst);
+        let res = self.do_poke_with_deadline(job, true);
+        let after = self.event_num.load(Ordering::SeqCst);
+        if after > before {
+            if let Err(e) = self.reset_event_log() {
+                error!("Failed to reset event log after event {}: {}", after, e);
+            }
+        }
+        res
+    }
+
+    /// Delete the event log and start it over after the current event, so it holds none of the
+    /// events before.
+    pub fn reset_event_log(&mut self) -> Result<()> {
+        let event_num = self.event_num.load(Ordering::SeqCst);
+        if let Some(log) = &mut self.event_log {
+            log.reset(event_num + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Builds the job `[event_num wire eny our now cause]` for the next event.
+    fn poke_job(&mut self, wire: &WireRepr, cause: Noun) -> Result<Noun> {
         let random_bytes = rand::random::<u64>();
         let bytes = random_bytes.as_bytes()?;
         let eny: Atom = Atom::from_bytes(&mut self.context.stack, &bytes);
@@ -1138,14 +2250,202 @@ impl Serf {
         };
 
         let event_num = D(self.event_num.load(Ordering::SeqCst) + 1);
-        let base_wire_noun = wire_to_noun(&mut self.context.stack, &wire);
+        let base_wire_noun = wire_to_noun(&mut self.context.stack, wire);
         let wire = T(&mut self.context.stack, &[D(tas!(b"poke")), base_wire_noun]);
-        let poke = T(
+        Ok(T(
             &mut self.context.stack,
             &[event_num, wire, eny.as_noun(), our.as_noun(), now.as_noun(), cause],
+        ))
+    }
+
+    /// Replaces the running kernel with a new one, handing it the current state.
+    ///
+    /// The new kernel is booted and poked with `[%migrate old-state]` on the `/upgrade` wire. If
+    /// the poke crashes, the old kernel stays in place untouched. The migration counts as an event
+    /// and is not written to the event log, so the log is reset to start after it.
+    ///
+    /// # Arguments
+    ///
+    /// * `kernel_bytes` - Byte slice containing the new kernel as a jammed noun.
+    ///
+    /// # Returns
+    ///
+    /// Result containing the effects of the migration poke or an error.
+    #[tracing::instrument(level = "info", skip_all)]
+    pub fn upgrade(&mut self, kernel_bytes: &[u8]) -> Result<Noun> {
+        let kernel_trap = Noun::cue_bytes_slice(&mut self.context.stack, kernel_bytes)?;
+        let fol = T(&mut self.context.stack, &[D(9), D(2), D(0), D(1)]);
+        let new_arvo = interpret(&mut self.context, kernel_trap, fol)?;
+        let old_state = self.arvo.slot(STATE_AXIS)?;
+        let cause = T(&mut self.context.stack, &[D(tas!(b"migrate")), old_state]);
+        let job = self.poke_job(&WireRepr::no_tags("upgrade", 1), cause)?;
+
+        let old_arvo = self.arvo;
+        self.arvo = new_arvo;
+        match self.soft(job, POKE_AXIS, Some("migrate".to_string())) {
+            Ok(res) => {
+                let cell = res.as_cell()?;
+                let mut fec = cell.head();
+                let eve = self.event_num.load(Ordering::SeqCst);
+
+                let mut hasher = Hasher::new();
+                hasher.update(kernel_bytes);
+                let ker_hash = hasher.finalize();
+                info!(
+                    "Upgraded kernel {} to {} at event {}",
+                    self.ker_hash,
+                    ker_hash,
+                    eve + 1
+                );
+                self.ker_hash = ker_hash;
+                unsafe {
+                    self.event_update(eve + 1, cell.tail());
+                    self.stack().preserve(&mut fec);
+                    self.preserve_event_update_leftovers();
+                }
+                if let Some(log) = &mut self.event_log {
+                    if let Err(e) = log.reset(eve + 2) {
+                        error!("Failed to reset event log after upgrade: {}", e);
+                    }
+                }
+                Ok(fec)
+            }
+            Err(goof) => {
+                self.arvo = old_arvo;
+                // Drop the migration's partial results from the memo cache
+                self.context.cache = MemoCache::new(&mut self.context.stack);
+                self.print_goof(goof);
+                Err(CrownError::UpgradeFailed)
+            }
+        }
+    }
+
+    /// Replays the events logged in `dir` past the current event, then logs new events there.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The event log directory.
+    ///
+    /// # Returns
+    ///
+    /// Result containing the number of events replayed or an error.
+    pub fn attach_event_log(&mut self, dir: PathBuf) -> Result<u64> {
+        let start = self.event_num.load(Ordering::SeqCst);
+        let events = event_log::read_since(&dir, start)?;
+        let mut replayed = 0;
+        for event in events {
+            let job = event.job.copy_to_stack(self.stack());
+            self.do_poke(job)?;
+            let event_num = self.event_num.load(Ordering::SeqCst);
+            if event_num != event.event_num {
+                warn!(
+                    "Replaying logged event {} left the kernel at event {}, not replaying further",
+                    event.event_num, event_num
+                );
+                break;
+            }
+            replayed += 1;
+        }
+        if replayed > 0 {
+            info!(
+                "Replayed {} logged events, from event {} to {}",
+                replayed,
+                start,
+                start + replayed
+            );
+        }
+        self.event_log = Some(EventLog::open(
+            &dir,
+            self.event_num.load(Ordering::SeqCst) + 1,
+        )?);
+        Ok(replayed)
+    }
+
+    /// Start a new event log segment at a checkpoint and record the mug of the checkpointed
+    /// state.
+    fn checkpoint_event_log(&mut self) -> Result<()> {
+        if self.event_log.is_none() {
+            return Ok(());
+        }
+        let event_num = self.event_num.load(Ordering::SeqCst);
+        let mug = self.state_mug()?;
+        if let Some(log) = &mut self.event_log {
+            log.rotate(event_num + 1)?;
+            log.record_state_mug(event_num, mug)?;
+        }
+        Ok(())
+    }
+
+    /// Snapshot the persistent loom with the kernel and cold states, returning the event number.
+    pub fn snapshot_pma(&mut self) -> Result<u64> {
+        let event_num = self.event_num.load(Ordering::SeqCst);
+        let ker_state = self.arvo.slot(STATE_AXIS)?;
+        let cold = self.context.cold;
+        // The roots are only needed in the snapshot, so their allocations don't stay on the loom
+        let snapshot = self.context.stack.snapshot_with(|stack| {
+            let cold = cold.into_noun(stack);
+            let state = T(stack, &[ker_state, cold]);
+            vec![state, Atom::new(stack, event_num).as_noun()]
+        })?;
+        debug!(
+            "Snapshotted event {} to the persistent loom, {} dirty pages ({} bytes)",
+            event_num, snapshot.dirty_pages, snapshot.bytes
         );
+        self.checkpoint_event_log()?;
+        Ok(event_num)
+    }
+
+    /// The mug of the kernel state, which replays compare against the recorded one
+    pub fn state_mug(&mut self) -> Result<u32> {
+        let state = self.arvo.slot(STATE_AXIS)?;
+        Ok(mug_u32(self.stack(), state))
+    }
 
-        self.do_poke(poke)
+    pub fn replay(&mut self, dir: &Path) -> Result<ReplayReport> {
+        let expected = event_log::read_state_mugs(dir)?;
+        let start = self.event_num.load(Ordering::SeqCst);
+        let mut checked = 0;
+        let mut check = |serf: &mut Serf, event_num: u64| -> Result<u32> {
+            let actual = serf.state_mug()?;
+            if let Some(&expected) = expected.get(&event_num) {
+                if actual != expected {
+                    return Err(CrownError::ReplayDiverged {
+                        event_num,
+                        expected,
+                        actual,
+                    });
+                }
+                checked += 1;
+            }
+            Ok(actual)
+        };
+        let mut state_mug = check(self, start)?;
+        let events = event_log::read_since(dir, start)?;
+        if events.is_empty() {
+            warn!(
+                "No events after event {} in {}, nothing to replay",
+                start,
+                dir.display()
+            );
+        }
+        for event in events {
+            let job = event.job.copy_to_stack(self.stack());
+            self.do_poke(job)?;
+            let event_num = self.event_num.load(Ordering::SeqCst);
+            if event_num != event.event_num {
+                return Err(CrownError::Unknown(format!(
+                    "replaying logged event {} left the kernel at event {}",
+                    event.event_num, event_num
+                )));
+            }
+            state_mug = check(self, event_num)?;
+        }
+        Ok(ReplayReport {
+            start,
+            end: self.event_num.load(Ordering::SeqCst),
+            checked,
+            state_mug,
+        })
     }
 
     /// Updates the Serf's state after an event.
@@ -1163,7 +2463,7 @@ impl Serf {
         self.arvo = new_arvo;
         self.event_num.store(new_event_num, Ordering::SeqCst);
 
-        self.context.cache = Hamt::new(&mut self.context.stack);
+        self.context.cache = MemoCache::new(&mut self.context.stack);
         self.context.scry_stack = D(0);
     }
 
@@ -1179,6 +2479,7 @@ impl Serf {
         stack.preserve(&mut self.context.test_jets);
         stack.preserve(&mut self.context.hot);
         stack.preserve(&mut self.context.cache);
+        stack.preserve(&mut self.context.bytecode);
         stack.preserve(&mut self.context.cold);
         stack.preserve(&mut self.arvo);
         stack.flip_top_frame(0);
@@ -1230,6 +2531,12 @@ fn slot(noun: Noun, axis: u64) -> Result<Noun> {
     Ok(noun.slot(axis)?)
 }
 
+/// Whether a `[mote tang]` goof is the interpreter being interrupted
+fn is_interrupt(goof: Noun) -> bool {
+    goof.as_cell()
+        .is_ok_and(|goof| unsafe { goof.head().raw_equals(&D(Mote::Intr as u64)) })
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
@@ -1,2 +1,7 @@
 pub mod boot;
+pub mod crash_dump;
+mod deadline;
+pub mod debugger;
+pub mod event_log;
 pub mod form;
+pub mod replica;
@@ -0,0 +1,195 @@
+//! Read-only replicas of a kernel for serving peeks.
+//!
+//! A [`Replica`] runs its own serfs, loaded from the checkpoints another NockApp writes, and only
+//! ever answers peeks. Heavy query traffic then queues on the replica instead of in front of the
+//! pokes on the primary. The replica polls the checkpoint directory and imports a newer
+//! checkpoint when one appears, so its answers lag the primary by up to one save interval plus
+//! one refresh interval.
+//!
+//! Nouns can't be shared between serf threads, so peeks run in parallel by giving the replica
+//! several worker serfs, each holding its own copy of the same checkpoint. A peek goes to the
+//! worker with the fewest peeks in flight. A refresh imports the new checkpoint into every
+//! worker, and until all are done some may still answer from the previous one.
+//!
+//! Peeks that must observe the effects of a poke just made have to go to the primary.
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::SystemTime;
+
+use nockvm::jets::hot::HotEntry;
+use tokio::sync::Mutex;
+use tokio::time::{interval, Duration, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+use crate::kernel::form::{Kernel, LoadState};
+use crate::noun::slab::{Jammer, NockJammer, NounSlab};
+use crate::save::{SaveableCheckpoint, Saver};
+use crate::Result;
+
+const CHECKPOINT_FILES: [&str; 3] = ["0.chkjam", "1.chkjam", "delta.chkjam"];
+
+/// Modification times of the checkpoint files, to skip reloading when nothing was saved
+type Stamp = [Option<SystemTime>; 3];
+
+struct Worker {
+    kernel: Kernel<SaveableCheckpoint>,
+    in_flight: AtomicUsize,
+}
+
+/// Counts a peek as in flight on its worker until dropped
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub struct Replica<J = NockJammer> {
+    workers: Vec<Worker>,
+    checkpoint_dir: PathBuf,
+    event_num: AtomicU64,
+    stamp: Mutex<Stamp>,
+    _phantom: std::marker::PhantomData<fn() -> J>,
+}
+
+impl<J: Jammer + 'static> Replica<J> {
+    /// Boot `kernel` from the newest checkpoint in `checkpoint_dir`, with a single worker.
+    ///
+    /// The directory doesn't have to hold a checkpoint yet. The replica then serves the freshly
+    /// booted state until the primary saves.
+    pub async fn open(
+        kernel: &[u8],
+        hot_state: &[HotEntry],
+        checkpoint_dir: PathBuf,
+    ) -> Result<Self> {
+        Self::open_workers(kernel, hot_state, checkpoint_dir, 1).await
+    }
+
+    /// Like [`Replica::open`], but with `workers` serfs answering peeks in parallel.
+    pub async fn open_workers(
+        kernel: &[u8],
+        hot_state: &[HotEntry],
+        checkpoint_dir: PathBuf,
+        workers: usize,
+    ) -> Result<Self> {
+        let stamp = checkpoint_stamp(&checkpoint_dir);
+        let checkpoint =
+            Saver::<J>::load_latest::<SaveableCheckpoint>(&checkpoint_dir, None).await?;
+        let event_num = checkpoint.as_ref().map_or(0, |c| c.event_num);
+        let loads = (0..workers.max(1)).map(|_| {
+            Kernel::load_with_hot_state(kernel, checkpoint.clone(), hot_state, Vec::new(), false)
+        });
+        let workers = futures::future::try_join_all(loads)
+            .await?
+            .into_iter()
+            .map(|kernel| Worker {
+                kernel,
+                in_flight: AtomicUsize::new(0),
+            })
+            .collect();
+        Ok(Replica {
+            workers,
+            checkpoint_dir,
+            event_num: AtomicU64::new(event_num),
+            stamp: Mutex::new(stamp),
+            _phantom: std::marker::PhantomData,
+        })
+    }
+
+    /// Peek the replicated state, like [`crate::NockApp::peek`].
+    pub async fn peek(&self, path: NounSlab) -> Result<NounSlab> {
+        let worker = self
+            .workers
+            .iter()
+            .min_by_key(|worker| worker.in_flight.load(Ordering::SeqCst))
+            .expect("replica has at least one worker");
+        worker.in_flight.fetch_add(1, Ordering::SeqCst);
+        let _in_flight = InFlight(&worker.in_flight);
+        worker.kernel.peek(path).await
+    }
+
+    /// How many serfs answer peeks
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn worker_kernels(&self) -> impl Iterator<Item = &Kernel<SaveableCheckpoint>> {
+        self.workers.iter().map(|worker| &worker.kernel)
+    }
+
+    /// The event number of the checkpoint the replica last loaded
+    pub fn event_num(&self) -> u64 {
+        self.event_num.load(Ordering::SeqCst)
+    }
+
+    /// Import the newest checkpoint if it is past the replicated state, returning whether it was.
+    pub async fn refresh(&self) -> Result<bool> {
+        let mut stamp = self.stamp.lock().await;
+        let current = checkpoint_stamp(&self.checkpoint_dir);
+        if current == *stamp {
+            return Ok(false);
+        }
+        let Some(checkpoint) =
+            Saver::<J>::load_latest::<SaveableCheckpoint>(&self.checkpoint_dir, None).await?
+        else {
+            return Ok(false);
+        };
+        *stamp = current;
+        if checkpoint.event_num <= self.event_num() {
+            return Ok(false);
+        }
+
+        // The checkpoint noun is `[kernel-state cold-state]`. The replica keeps its own cold
+        // state, which only affects jet matching.
+        let mut kernel_state = NounSlab::new();
+        let state = unsafe { *checkpoint.noun.root() }.as_cell()?.head();
+        let state = kernel_state.copy_into(state);
+        kernel_state.set_root(state);
+        let event_num = checkpoint.event_num;
+        let imports = self.workers.iter().map(|worker| {
+            worker.kernel.import(LoadState {
+                ker_hash: checkpoint.ker_hash,
+                event_num,
+                kernel_state: kernel_state.clone(),
+            })
+        });
+        futures::future::try_join_all(imports).await?;
+        self.event_num.store(event_num, Ordering::SeqCst);
+        debug!("Replica refreshed to event {}", event_num);
+        Ok(true)
+    }
+
+    /// Refresh every `period` in a background task, which stops once the replica is dropped.
+    pub fn spawn_refresh(self: &Arc<Self>, period: Duration) -> tokio::task::JoinHandle<()> {
+        let replica: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = interval(period);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let Some(replica) = replica.upgrade() else {
+                    break;
+                };
+                if let Err(e) = replica.refresh().await {
+                    warn!(
+                        "Replica failed to refresh from {}: {}",
+                        replica.checkpoint_dir.display(),
+                        e
+                    );
+                }
+            }
+            info!("Replica refresh stopped");
+        })
+    }
+}
+
+fn checkpoint_stamp(dir: &std::path::Path) -> Stamp {
+    CHECKPOINT_FILES.map(|name| {
+        std::fs::metadata(dir.join(name))
+            .and_then(|meta| meta.modified())
+            .ok()
+    })
+}
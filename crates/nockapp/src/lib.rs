@@ -17,6 +17,8 @@ pub mod kernel;
 pub mod nockapp;
 pub mod noun;
 pub mod observability;
+pub mod repl;
+pub mod settings;
 pub mod utils;
 
 use std::path::PathBuf;
@@ -0,0 +1,402 @@
+//! Pokes from drivers that failed, kept so they can be looked at and tried again.
+//!
+//! When a poke bails or times out, [`NockApp`](crate::NockApp) writes it to its dead-letter
+//! directory (`dead-letters` in the data directory when booted with [`crate::kernel::boot`] and
+//! `--dead-letters`) as `<id>.jam`, the jam of `[failed-at=@ud error=@t wire cause]` with the time
+//! in Unix milliseconds. Ids start with the UTC time, so they sort oldest first, and beyond the
+//! limit the oldest entries are dropped.
+//!
+//! Moving an entry into `retry/` asks the app to poke it again, which it checks for every
+//! [`RETRY_POLL_INTERVAL`] while running and so also when it next starts. A retry that fails is
+//! kept again as a new entry.
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+use nockvm::noun::{Noun, Slots, D, T};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::kernel::crash_dump::render_goof;
+use crate::nockapp::wire::{wire_to_noun, WireRepr, WireTag};
+use crate::noun::slab::{CueError, NounSlab};
+use crate::utils::ipc::sync_dir;
+use crate::utils::make_tas;
+use crate::{AtomExt, NounExt};
+
+/// Where [`crate::kernel::boot`] keeps dead letters, in the data directory
+pub const DEAD_LETTERS_DIR: &str = "dead-letters";
+/// How many failed pokes are kept by default
+pub const DEFAULT_MAX_DEAD_LETTERS: usize = 1000;
+/// How often a running app looks for entries to retry
+pub const RETRY_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const RETRY_DIR: &str = "retry";
+
+#[derive(Debug, Error)]
+pub enum DeadLetterError {
+    #[error("dead letters: no entry {0}")]
+    NotFound(String),
+    #[error("dead letters: bad entry id {0:?}")]
+    BadId(String),
+    #[error("dead letters: entry {0} is malformed")]
+    Malformed(String),
+    #[error("dead letters: {0}")]
+    Cue(#[from] CueError),
+    #[error("dead letters: {0}")]
+    Io(#[from] io::Error),
+}
+
+pub type Result<T, E = DeadLetterError> = std::result::Result<T, E>;
+
+/// A directory of failed pokes. Clones share it.
+#[derive(Debug, Clone)]
+pub struct DeadLetters {
+    dir: Arc<PathBuf>,
+    max_entries: usize,
+    /// Tells apart entries from the same millisecond
+    next: Arc<AtomicU64>,
+}
+
+/// A failed poke read back from the directory
+pub struct DeadLetter {
+    pub id: String,
+    /// Unix time in milliseconds
+    pub failed_at: u64,
+    pub error: String,
+    /// `[wire cause]`
+    pub poke: NounSlab,
+}
+
+impl DeadLetter {
+    pub fn wire(&self) -> Noun {
+        unsafe { self.poke.root() }
+            .slot(2)
+            .expect("dead letter poke is a cell")
+    }
+
+    pub fn cause(&self) -> Noun {
+        unsafe { self.poke.root() }
+            .slot(3)
+            .expect("dead letter poke is a cell")
+    }
+
+    /// The cause in a slab of its own, to poke again
+    pub fn cause_slab(&self) -> NounSlab {
+        let mut slab = NounSlab::new();
+        slab.copy_into(self.cause());
+        slab
+    }
+
+    /// The wire's source, or `?` if it doesn't have one
+    pub fn source(&self) -> String {
+        self.wire()
+            .list_iter()
+            .next()
+            .and_then(|source| source.as_atom().ok()?.into_string().ok())
+            .unwrap_or_else(|| "?".to_string())
+    }
+
+    /// The wire as a driver would send it, if it's `[source=@tas version=@ud tags... ~]`
+    pub fn wire_repr(&self) -> Option<WireRepr> {
+        let mut parts = self.wire().list_iter();
+        let source = parts.next()?.as_atom().ok()?.into_string().ok()?;
+        let version = parts.next()?.as_atom().ok()?.as_u64().ok()?;
+        // A tag noun is the same whether it was made from a number or a string that fits in one
+        let tags = parts
+            .map(|tag| {
+                let tag = tag.as_atom().ok()?;
+                match tag.as_direct() {
+                    Ok(direct) => Some(WireTag::Direct(direct.data())),
+                    Err(_) => tag.into_string().ok().map(WireTag::String),
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(WireRepr::new(intern(source), version, tags))
+    }
+}
+
+impl DeadLetters {
+    pub fn new(dir: PathBuf) -> Self {
+        DeadLetters {
+            dir: Arc::new(dir),
+            max_entries: DEFAULT_MAX_DEAD_LETTERS,
+            next: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Keep at most `max_entries`, dropping the oldest
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries.max(1);
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn retry_dir(&self) -> PathBuf {
+        self.dir.join(RETRY_DIR)
+    }
+
+    /// Keep `cause` on `wire`, which failed with `error`, returning its id
+    pub fn add(&self, wire: &WireRepr, cause: &NounSlab, error: &str) -> Result<String> {
+        let now = chrono::Utc::now();
+        let id = format!(
+            "{}-{:06}",
+            now.format("%Y%m%dT%H%M%S%.3fZ"),
+            self.next.fetch_add(1, Ordering::Relaxed) % 1_000_000
+        );
+        let mut slab: NounSlab = NounSlab::new();
+        let cause = slab.copy_into(unsafe { *cause.root() });
+        let wire = wire_to_noun(&mut slab, wire);
+        let error = make_tas(&mut slab, error).as_noun();
+        let failed_at = D(now.timestamp_millis().max(0) as u64);
+        let entry = T(&mut slab, &[failed_at, error, wire, cause]);
+        slab.set_root(entry);
+        self.write(&self.dir, &id, &slab.jam())?;
+        self.prune()?;
+        Ok(id)
+    }
+
+    /// Every entry not waiting to be retried, oldest first
+    pub fn list(&self) -> Result<Vec<DeadLetter>> {
+        ids(&self.dir)?
+            .into_iter()
+            .map(|id| self.read(&self.dir, &id))
+            .collect()
+    }
+
+    /// The ids waiting to be retried
+    pub fn retrying(&self) -> Result<Vec<String>> {
+        ids(&self.retry_dir())
+    }
+
+    pub fn get(&self, id: &str) -> Result<DeadLetter> {
+        check_id(id)?;
+        self.read(&self.dir, id)
+    }
+
+    /// Ask the app to poke entry `id` again
+    pub fn retry(&self, id: &str) -> Result<()> {
+        check_id(id)?;
+        let from = self.dir.join(format!("{}.jam", id));
+        if !from.exists() {
+            return Err(DeadLetterError::NotFound(id.to_string()));
+        }
+        std::fs::create_dir_all(self.retry_dir())?;
+        std::fs::rename(from, self.retry_dir().join(format!("{}.jam", id)))?;
+        sync_dir(&self.retry_dir())?;
+        Ok(())
+    }
+
+    /// Discard entry `id`, whether or not it's waiting to be retried
+    pub fn remove(&self, id: &str) -> Result<()> {
+        check_id(id)?;
+        for dir in [self.dir.to_path_buf(), self.retry_dir()] {
+            match std::fs::remove_file(dir.join(format!("{}.jam", id))) {
+                Ok(()) => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(DeadLetterError::NotFound(id.to_string()))
+    }
+
+    /// Take the entries waiting to be retried, with the wires to poke them on. Entries whose wire
+    /// can't be sent again are put back with the rest.
+    pub fn take_retries(&self) -> Result<Vec<(DeadLetter, WireRepr)>> {
+        let retry_dir = self.retry_dir();
+        let mut retries = Vec::new();
+        for id in ids(&retry_dir)? {
+            let path = retry_dir.join(format!("{}.jam", id));
+            let entry = self.read(&retry_dir, &id)?;
+            match entry.wire_repr() {
+                Some(wire) => {
+                    std::fs::remove_file(&path)?;
+                    retries.push((entry, wire));
+                }
+                None => {
+                    warn!("Dead letter {} has a wire that can't be sent again", id);
+                    std::fs::rename(&path, self.dir.join(format!("{}.jam", id)))?;
+                }
+            }
+        }
+        Ok(retries)
+    }
+
+    fn read(&self, dir: &Path, id: &str) -> Result<DeadLetter> {
+        let jam = match std::fs::read(dir.join(format!("{}.jam", id))) {
+            Ok(jam) => jam,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(DeadLetterError::NotFound(id.to_string()))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let malformed = || DeadLetterError::Malformed(id.to_string());
+        let mut slab: NounSlab = NounSlab::new();
+        let entry = slab.cue_into(Bytes::from(jam))?;
+        let part = |axis| entry.slot(axis).map_err(|_| malformed());
+        let failed_at = part(2)?.as_atom().map_err(|_| malformed())?;
+        let error = part(6)?.as_atom().map_err(|_| malformed())?;
+        let poke = part(7)?;
+        if !poke.is_cell() {
+            return Err(malformed());
+        }
+        let letter = DeadLetter {
+            id: id.to_string(),
+            failed_at: failed_at.as_u64().map_err(|_| malformed())?,
+            error: error.into_string().map_err(|_| malformed())?,
+            poke: {
+                let mut poke_slab = NounSlab::new();
+                poke_slab.copy_into(poke);
+                poke_slab
+            },
+        };
+        Ok(letter)
+    }
+
+    fn write(&self, dir: &Path, id: &str, jam: &[u8]) -> Result<()> {
+        std::fs::create_dir_all(dir)?;
+        let tmp_path = dir.join(format!(".{}.tmp", id));
+        std::fs::write(&tmp_path, jam)?;
+        std::fs::File::open(&tmp_path)?.sync_all()?;
+        std::fs::rename(&tmp_path, dir.join(format!("{}.jam", id)))?;
+        sync_dir(dir)?;
+        Ok(())
+    }
+
+    /// Drop the oldest entries beyond the limit
+    fn prune(&self) -> Result<()> {
+        let ids = ids(&self.dir)?;
+        let excess = ids.len().saturating_sub(self.max_entries);
+        for id in &ids[..excess] {
+            std::fs::remove_file(self.dir.join(format!("{}.jam", id)))?;
+        }
+        Ok(())
+    }
+}
+
+/// The ids of the entries in `dir`, oldest first
+fn ids(dir: &Path) -> Result<Vec<String>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut ids = Vec::new();
+    for entry in entries {
+        let name = entry?.file_name();
+        let Some(id) = name.to_str().and_then(|name| name.strip_suffix(".jam")) else {
+            continue;
+        };
+        if check_id(id).is_ok() {
+            ids.push(id.to_string());
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+fn check_id(id: &str) -> Result<()> {
+    let valid = !id.is_empty()
+        && !id.starts_with('.')
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(DeadLetterError::BadId(id.to_string()))
+    }
+}
+
+/// A wire's source as the `&'static str` a [`WireRepr`] holds, made once per source
+fn intern(source: String) -> &'static str {
+    static SOURCES: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+    let mut sources = SOURCES.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(interned) = sources.iter().find(|interned| **interned == source) {
+        return interned;
+    }
+    let interned: &'static str = Box::leak(source.into_boxed_str());
+    sources.push(interned);
+    interned
+}
+
+/// What made a poke fail, if its result is the serf's `[%poke %swap ...]` or `[%poke %bail ...]`
+/// for a bail
+pub fn bail_error(effects: Noun) -> Option<String> {
+    let cell = effects.as_cell().ok()?;
+    if !cell.head().eq_bytes(b"poke") {
+        return None;
+    }
+    let kind = cell.tail().as_cell().ok()?;
+    let goof = if kind.head().eq_bytes(b"swap") {
+        // [%poke %swap eve mug ovo fec], with ovo [event_num wire goof job_input]
+        effects.slot(62).ok()?.slot(14).ok()?
+    } else if kind.head().eq_bytes(b"bail") {
+        // [%poke %bail lud], with lud [goof_crud goof ~]
+        effects.slot(7).ok()?.slot(6).ok()?
+    } else {
+        return None;
+    };
+    Some(render_goof(goof).join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm_macros::tas;
+
+    use super::*;
+    use crate::noun::slab::slab_equality;
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_dead_letters() {
+        let dir = tempfile::tempdir().unwrap();
+        let dead_letters = DeadLetters::new(dir.path().join(DEAD_LETTERS_DIR)).with_max_entries(2);
+        assert!(dead_letters.list().unwrap().is_empty());
+
+        let wire = WireRepr::new("npc", 1, vec!["longer-than-a-word".into(), 7u64.into()]);
+        let mut cause: NounSlab = NounSlab::new();
+        let cause_noun = T(&mut cause, &[D(tas!(b"command")), D(42), D(0)]);
+        cause.set_root(cause_noun);
+        let ids: Vec<String> = ["bail: %exit", "timed out", "bail: %intr"]
+            .iter()
+            .map(|error| dead_letters.add(&wire, &cause, error).unwrap())
+            .collect();
+
+        // The oldest is dropped beyond the limit
+        let listed = dead_letters.list().unwrap();
+        assert_eq!(
+            listed.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(),
+            vec![ids[1].as_str(), ids[2].as_str()]
+        );
+        let entry = dead_letters.get(&ids[1]).unwrap();
+        assert_eq!(entry.error, "timed out");
+        assert_eq!(entry.source(), "npc");
+        assert_eq!(entry.wire_repr(), Some(wire.clone()));
+        assert!(slab_equality(&entry.cause_slab(), &cause));
+
+        dead_letters.retry(&ids[1]).unwrap();
+        assert_eq!(dead_letters.retrying().unwrap(), vec![ids[1].clone()]);
+        assert_eq!(dead_letters.list().unwrap().len(), 1);
+        let retries = dead_letters.take_retries().unwrap();
+        assert_eq!(retries.len(), 1);
+        assert_eq!(retries[0].1, wire);
+        assert!(dead_letters.retrying().unwrap().is_empty());
+
+        dead_letters.remove(&ids[2]).unwrap();
+        assert!(matches!(
+            dead_letters.remove(&ids[2]),
+            Err(DeadLetterError::NotFound(_))
+        ));
+        assert!(matches!(
+            dead_letters.get("../escape"),
+            Err(DeadLetterError::BadId(_))
+        ));
+        assert!(dead_letters.list().unwrap().is_empty());
+    }
+}
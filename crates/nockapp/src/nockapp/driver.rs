@@ -2,13 +2,15 @@ use std::pin::Pin;
 use std::sync::Arc;
 
 use futures::future::Future;
-use tokio::sync::{broadcast, mpsc, oneshot, Mutex};
+use tokio::sync::{broadcast, mpsc, oneshot, watch, Mutex};
 use tokio::task::JoinSet;
 use tracing::instrument;
 
 use super::error::NockAppError;
 use super::metrics::NockAppMetrics;
-use super::wire::WireRepr;
+use super::shutdown::Shutdown;
+use super::store::DriverStore;
+use super::wire::{Wire, WireRepr, WireVersions};
 use super::NockAppExit;
 use crate::noun::slab::NounSlab;
 
@@ -27,6 +29,16 @@ pub enum PokeResult {
     Nack,
 }
 
+/// A poke the app handled, for whoever asked with [`crate::NockApp::tap_pokes`]
+pub struct TappedPoke {
+    pub wire: WireRepr,
+    pub poke: NounSlab,
+    /// When the app got it
+    pub at: std::time::SystemTime,
+    /// Whether it was acked
+    pub acked: bool,
+}
+
 pub enum Operation {
     Poke,
     Peek,
@@ -46,6 +58,13 @@ pub struct NockAppHandle {
     pub effect_receiver: Mutex<EffectReceiver>,
     pub metrics: Arc<NockAppMetrics>,
     pub exit: NockAppExit,
+    pub shutdown: Shutdown,
+    /// Values the driver keeps across restarts, see [`NockAppHandle::store_get`]
+    pub store: DriverStore,
+    /// Ticks after each poke the kernel takes, which may have changed its state
+    pub state_changes: watch::Receiver<u64>,
+    /// The wire versions the kernel understands, see [`NockAppHandle::wire_version`]
+    pub wire_versions: WireVersions,
 }
 
 /// IO actions sent between [`NockAppHandle`] and [`crate::NockApp`] over channels.
@@ -60,11 +79,20 @@ pub enum IOAction {
         wire: WireRepr,
         poke: NounSlab,
         ack_channel: oneshot::Sender<PokeResult>,
+        /// The span the poke was sent in, which handling it continues
+        span: tracing::Span,
     },
     /// Peek request to [`crate::NockApp`]
     Peek {
         path: NounSlab,
         result_channel: oneshot::Sender<Option<NounSlab>>,
+        /// The span the peek was sent in, which handling it continues
+        span: tracing::Span,
+    },
+    /// Replace the running kernel with a new kernel jam, migrating its state
+    Upgrade {
+        kernel: Vec<u8>,
+        ack_channel: oneshot::Sender<PokeResult>,
     },
 }
 
@@ -81,6 +109,7 @@ impl NockAppHandle {
                 wire,
                 poke,
                 ack_channel,
+                span: tracing::Span::current(),
             })
             .await?;
         Ok(())
@@ -98,6 +127,7 @@ impl NockAppHandle {
             wire,
             poke,
             ack_channel,
+            span: tracing::Span::current(),
         })?)
     }
 
@@ -120,6 +150,20 @@ impl NockAppHandle {
         Ok(ack_future.await?)
     }
 
+    /// Hot-upgrade the kernel. The new kernel receives the current state in a `%migrate` poke
+    /// and its effects are broadcast like any poke's. On `Nack` the old kernel is still running.
+    #[tracing::instrument(name = "nockapp::NockAppHandle::upgrade", skip_all)]
+    pub async fn upgrade(&self, kernel: Vec<u8>) -> Result<PokeResult, NockAppError> {
+        let (ack_channel, ack_future) = oneshot::channel();
+        self.io_sender
+            .send(IOAction::Upgrade {
+                kernel,
+                ack_channel,
+            })
+            .await?;
+        Ok(ack_future.await?)
+    }
+
     #[tracing::instrument(name = "nockapp::NockAppHandle::try_send_peek", skip_all)]
     pub fn try_send_peek(
         &self,
@@ -129,6 +173,7 @@ impl NockAppHandle {
         Ok(self.io_sender.try_send(IOAction::Peek {
             path,
             result_channel,
+            span: tracing::Span::current(),
         })?)
     }
 
@@ -142,6 +187,7 @@ impl NockAppHandle {
             .send(IOAction::Peek {
                 path,
                 result_channel,
+                span: tracing::Span::current(),
             })
             .await?;
         Ok(())
@@ -190,6 +236,10 @@ impl NockAppHandle {
         let effect_receiver = Mutex::new(effect_sender.subscribe());
         let metrics = self.metrics.clone();
         let exit = self.exit.clone();
+        let shutdown = self.shutdown.clone();
+        let store = self.store.clone();
+        let state_changes = self.state_changes.clone();
+        let wire_versions = self.wire_versions.clone();
         (
             self,
             NockAppHandle {
@@ -198,6 +248,10 @@ impl NockAppHandle {
                 effect_receiver,
                 metrics,
                 exit,
+                shutdown,
+                store,
+                state_changes,
+                wire_versions,
             },
         )
     }
@@ -206,4 +260,27 @@ impl NockAppHandle {
     pub fn clone_io_sender(&self) -> ActionSender {
         self.io_sender.clone()
     }
+
+    /// The version of `W`'s wire to poke with, out of those the driver speaks, or an error
+    /// saying why the kernel won't take any of them. Pokes on a version the kernel doesn't
+    /// understand are nacked.
+    pub fn wire_version<W: Wire>(&self) -> Result<u64, NockAppError> {
+        Ok(self.wire_versions.negotiate::<W>()?)
+    }
+
+    /// The value the app's drivers last stored at `key`, kept across restarts in the data
+    /// directory. Keys are shared by all drivers, so prefix them with the driver's name.
+    pub async fn store_get(&self, key: &str) -> Result<Option<Vec<u8>>, NockAppError> {
+        Ok(self.store.get(key).await?)
+    }
+
+    /// Durably store `value` at `key`, see [`NockAppHandle::store_get`]
+    pub async fn store_put(&self, key: &str, value: &[u8]) -> Result<(), NockAppError> {
+        Ok(self.store.put(key, value).await?)
+    }
+
+    /// Remove `key` from the store, returning whether it was there
+    pub async fn store_delete(&self, key: &str) -> Result<bool, NockAppError> {
+        Ok(self.store.delete(key).await?)
+    }
 }
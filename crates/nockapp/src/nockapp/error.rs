@@ -4,6 +4,8 @@ use tracing::error;
 
 use super::driver::IOAction;
 use crate::nockapp::save::CheckpointError;
+use crate::nockapp::store::StoreError;
+use crate::nockapp::wire::WireVersionError;
 use crate::noun::slab::CueError;
 use crate::CrownError;
 
@@ -59,6 +61,10 @@ pub enum NockAppError {
     ConfigError(#[from] config::ConfigError),
     #[error("Checkpoing error: {0}")]
     CheckpointError(#[from] CheckpointError),
+    #[error("{0}")]
+    StoreError(#[from] StoreError),
+    #[error("Wire version error: {0}")]
+    WireVersionError(#[from] WireVersionError),
 }
 
 impl From<TrySendError<IOAction>> for NockAppError {
@@ -31,7 +31,18 @@ impl ExportedState {
     }
 
     pub fn decode(data: &[u8]) -> Result<Self, bincode::error::DecodeError> {
-        let (state, _) = decode_from_slice(data, config::standard())?;
+        let (state, _): (Self, _) = decode_from_slice(data, config::standard())?;
+        if state.magic_bytes != EXPORTED_STATE_MAGIC_BYTES {
+            return Err(bincode::error::DecodeError::Other(
+                "not an exported state: bad magic bytes",
+            ));
+        }
+        if state.version != EXPORTED_STATE_VERSION {
+            return Err(bincode::error::DecodeError::OtherString(format!(
+                "unsupported exported state version {}, expected {}",
+                state.version, EXPORTED_STATE_VERSION
+            )));
+        }
         Ok(state)
     }
 
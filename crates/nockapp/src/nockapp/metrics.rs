@@ -8,7 +8,22 @@ metrics_struct![
     (handle_exit, "nockapp.handle_exit", Count),
     (poke_during_exit, "nockapp.poke_during_exit", Count),
     (peek_during_exit, "nockapp.peek_during_exit", Count),
+    (peek_cache_hits, "nockapp.peek_cache.hits", Count),
+    (peek_cache_misses, "nockapp.peek_cache.misses", Count),
+    (poke_quota_rejected, "nockapp.poke_quota.rejected", Count),
+    (wire_version_rejected, "nockapp.wire_version.rejected", Count),
     (least_free_space_seen_in_slam, "nockapp.least_free_space_seen_in_slam", Gauge),
+    (memo_hits, "nockapp.memo.hits", Gauge),
+    (memo_misses, "nockapp.memo.misses", Gauge),
+    (memo_evictions, "nockapp.memo.evictions", Gauge),
+    (memo_entries, "nockapp.memo.entries", Gauge),
+    (nock_ops, "nockapp.nock.ops", Gauge),
+    (jet_calls, "nockapp.nock.jet_calls", Gauge),
+    (stack_allocations, "nockapp.nock.stack.allocations", Gauge),
+    (stack_allocated_words, "nockapp.nock.stack.allocated_words", Gauge),
+    (stack_copies, "nockapp.nock.stack.copies", Gauge),
+    (stack_copied_words, "nockapp.nock.stack.copied_words", Gauge),
+    (max_stack_words, "nockapp.nock.stack.max_words", Gauge),
     (save_jam_time, "nockapp.save_jam_time", TimingCount),
     (load_cue_time, "nockapp.load_cue_time", TimingCount),
     (serf_loop_blocking_recv, "nockapp.serf_loop.blocking_recv", TimingCount),
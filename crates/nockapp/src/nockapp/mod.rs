@@ -1,36 +1,51 @@
 // pub(crate) mod actors;
+pub mod dead_letter;
 pub mod driver;
 pub mod error;
 pub mod export;
 pub(crate) mod metrics;
+pub mod peek_cache;
+pub mod prometheus;
+pub mod quota;
 pub mod save;
+pub mod shutdown;
+pub mod signals;
+pub mod store;
 pub mod test;
 pub mod wire;
 
 use std::future::Future;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-use driver::{IOAction, IODriverFn, NockAppHandle, PokeResult};
+use dead_letter::{bail_error, DeadLetters, RETRY_POLL_INTERVAL};
+use driver::{IOAction, IODriverFn, NockAppHandle, PokeResult, TappedPoke};
 pub use error::NockAppError;
-use futures::stream::StreamExt;
 use futures::FutureExt;
 use metrics::*;
 use nockvm::noun::SIG;
-use signal_hook::consts::signal::*;
-use signal_hook::consts::TERM_SIGNALS;
-use signal_hook_tokio::Signals;
+use peek_cache::PeekCache;
+use quota::PokeQuotas;
+use shutdown::Shutdown;
+use signals::{Signals, SIGHUP, SIGINT, SIGQUIT, SIGTERM};
+use store::DriverStore;
 use tokio::select;
-use tokio::sync::{broadcast, mpsc, Mutex, OwnedMutexGuard};
+use tokio::sync::{broadcast, mpsc, watch, Mutex, OwnedMutexGuard};
 use tokio::time::{interval, Duration, Interval};
 use tokio_util::task::TaskTracker;
-use tracing::{debug, error, info, instrument, trace, warn};
-use wire::WireRepr;
+use tracing::{debug, error, info, info_span, instrument, trace, warn, Instrument};
+use wire::{VersionsBySource, WireRepr, WireVersions};
 
 use crate::kernel::form::Kernel;
+use crate::kernel::replica::Replica;
 use crate::noun::slab::{Jammer, NockJammer, NounSlab};
-use crate::save::{SaveableCheckpoint, Saver};
+use crate::save::{
+    CheckpointPolicy, Compression, DeltaPolicy, RetentionPolicy, SaveableCheckpoint, Saver,
+};
+use crate::utils::daemon::{self, PidFile};
+use crate::utils::scry::ScryResult;
+use crate::{CrownError, NounExt};
 
 type NockAppResult = Result<(), NockAppError>;
 
@@ -71,13 +86,55 @@ pub struct NockApp<J = NockJammer> {
     effect_broadcast: Arc<broadcast::Sender<NounSlab>>,
     /// Save interval
     save_interval: Interval,
+    /// When to write checkpoints
+    checkpoint_policy: CheckpointPolicy,
+    /// Checkpoints requested by pokes, for the event count and `%save` triggers
+    save_requests: mpsc::Receiver<SaveRequest>,
+    save_request_sender: mpsc::Sender<SaveRequest>,
     /// Mutex to ensure only one save at a time
     pub(crate) save_mutex: Arc<Mutex<Saver<J>>>,
+    /// Serves driver peeks instead of the kernel, if set
+    peek_replica: Option<Arc<Replica<J>>>,
+    /// Answers repeated driver peeks until the next poke, if set
+    peek_cache: Option<Arc<PeekCache>>,
+    /// Checked against each poke before it's sent to the kernel
+    poke_quotas: PokeQuotas,
+    /// Counts the pokes the kernel has taken, for drivers to watch
+    state_changes: watch::Sender<u64>,
     /// Shutdown oneshot sender
     pub npc_socket_path: Option<PathBuf>,
+    /// Removed once the app has shut down, if set
+    pid_file: Option<PidFile>,
     metrics: Arc<NockAppMetrics>,
     /// Signals handled by the work loop
     signals: Signals,
+    /// Tells drivers when the app is shutting down
+    shutdown: Shutdown,
+    /// How long a shutdown may take before it's forced
+    shutdown_deadline: Duration,
+    /// Forces the shutdown at the deadline
+    watchdog: Option<tokio::task::JoinHandle<()>>,
+    /// Given to each driver's handle
+    store: DriverStore,
+    /// What the kernel said it understands, checked against each poke's wire
+    wire_versions: WireVersions,
+    /// Keeps pokes that fail, if set
+    dead_letters: Option<DeadLetters>,
+    /// When to look for dead letters to retry
+    dead_letter_poll: Interval,
+    /// Gets a copy of every poke, if set
+    poke_tap: Option<mpsc::Sender<TappedPoke>>,
+    /// Whether a successful exit saves with [`NockApp::save_scrubbed`]
+    scrub_on_exit: bool,
+    /// Where the app keeps its checkpoints and other files, if set
+    data_dir: Option<PathBuf>,
+}
+
+pub(crate) enum SaveRequest {
+    /// The kernel emitted `%save`
+    Effect,
+    /// Save if this many events have passed since the last checkpoint
+    Events(u64),
 }
 
 pub enum NockAppRun {
@@ -166,6 +223,7 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
         // the Arc in the serf would result in a race condition!
 
         let (action_channel_sender, action_channel) = mpsc::channel(100);
+        let (save_request_sender, save_requests) = mpsc::channel(16);
         let (effect_broadcast_sender, _) = broadcast::channel(100);
         let effect_broadcast = Arc::new(effect_broadcast_sender);
         // let tasks = Arc::new(Mutex::new(TaskJoinSet::new()));
@@ -174,6 +232,8 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
         let tasks = TaskTracker::new();
         let mut save_interval = interval(save_interval_duration);
         save_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip); // important so we don't stack ticks when lagging
+        let mut dead_letter_poll = interval(RETRY_POLL_INTERVAL);
+        dead_letter_poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
         let exit_status = AtomicBool::new(false);
         let abort_immediately = AtomicBool::new(false);
 
@@ -182,8 +242,9 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
             .await
             .expect("Failed to provide metrics to kernel");
 
-        let signals = Signals::new(&[TERM_SIGNALS, &[SIGHUP]].concat())
-            .expect("Failed to create signal handler");
+        let signals = Signals::new().expect("Failed to create signal handler");
+        let wire_versions = WireVersions::default();
+        wire_versions.set(negotiate_wires(kernel.peek(WireVersions::peek_path())).await);
 
         let (exit, exit_recv) = NockAppExit::new();
         Ok(Self {
@@ -197,11 +258,29 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
             action_channel_sender,
             effect_broadcast,
             save_interval,
+            checkpoint_policy: CheckpointPolicy::every(save_interval_duration),
+            save_requests,
+            save_request_sender,
             save_mutex,
+            peek_replica: None,
+            peek_cache: None,
+            poke_quotas: PokeQuotas::default(),
+            state_changes: watch::Sender::new(0),
             // cancel_token,
             npc_socket_path: None,
+            pid_file: None,
             metrics,
             signals,
+            shutdown: Shutdown::default(),
+            shutdown_deadline: shutdown::DEFAULT_SHUTDOWN_DEADLINE,
+            watchdog: None,
+            store: DriverStore::default(),
+            wire_versions,
+            dead_letters: None,
+            dead_letter_poll,
+            poke_tap: None,
+            scrub_on_exit: false,
+            data_dir: None,
         })
     }
 
@@ -212,9 +291,34 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
             effect_receiver: Mutex::new(self.effect_broadcast.subscribe()),
             metrics: self.metrics.clone(),
             exit: self.exit.clone(),
+            shutdown: self.shutdown.clone(),
+            store: self.store.clone(),
+            state_changes: self.state_changes.subscribe(),
+            wire_versions: self.wire_versions.clone(),
         }
     }
 
+    /// How long a shutdown may take before the running computation is interrupted and the
+    /// process exits regardless
+    pub fn set_shutdown_deadline(&mut self, deadline: Duration) {
+        self.shutdown_deadline = deadline;
+    }
+
+    /// Where drivers added from now on keep their values, see [`NockAppHandle::store_get`]
+    pub fn set_driver_store(&mut self, dir: PathBuf) {
+        self.store = DriverStore::new(dir);
+    }
+
+    /// Set the directory the app was booted in, for drivers that keep their own files there
+    pub fn set_data_dir(&mut self, dir: PathBuf) {
+        self.data_dir = Some(dir);
+    }
+
+    /// The directory the app was booted in, if it was set
+    pub fn data_dir(&self) -> Option<&Path> {
+        self.data_dir.as_deref()
+    }
+
     /// Assume at-least-once processing and track the state necessary to know whether
     /// all critical IO actions have been performed correctly or not from the jammed state.
     #[tracing::instrument(skip(self, driver))]
@@ -224,12 +328,20 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
         let effect_receiver = Mutex::new(self.effect_broadcast.subscribe());
         let metrics = self.metrics.clone();
         let exit = self.exit.clone();
+        let shutdown = self.shutdown.clone();
+        let store = self.store.clone();
+        let state_changes = self.state_changes.subscribe();
+        let wire_versions = self.wire_versions.clone();
         let fut = driver(NockAppHandle {
             io_sender,
             effect_sender,
             effect_receiver,
             metrics,
             exit,
+            shutdown,
+            store,
+            state_changes,
+            wire_versions,
         });
         // TODO: Stop using the task tracker for user code?
         self.tasks.spawn(fut);
@@ -248,12 +360,20 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
         let effect_receiver = Mutex::new(self.effect_broadcast.subscribe());
         let metrics = self.metrics.clone();
         let exit = self.exit.clone();
+        let shutdown = self.shutdown.clone();
+        let store = self.store.clone();
+        let state_changes = self.state_changes.subscribe();
+        let wire_versions = self.wire_versions.clone();
         let fut = driver(NockAppHandle {
             io_sender,
             effect_sender,
             effect_receiver,
             metrics,
             exit,
+            shutdown,
+            store,
+            state_changes,
+            wire_versions,
         });
         // TODO: Stop using the task tracker for user code?
         self.tasks.spawn(fut);
@@ -269,17 +389,24 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
         f: impl std::future::Future<Output = ()> + Send + 'static,
         mut save_permit: OwnedMutexGuard<Saver<J>>,
     ) -> Result<tokio::task::JoinHandle<NockAppResult>, NockAppError> {
-        let checkpoint_fut = self.kernel.checkpoint();
+        let save_fut = self.kernel.save_point();
         let metrics = self.metrics.clone();
 
         trace!("Spawning save task from save_f");
         let join_handle = self.tasks.spawn(async move {
             f.await;
             trace!("Save task from save_f: f.await done");
-            let checkpoint = checkpoint_fut.await?;
-            trace!("Save task from save_f: checkpoint_fut.await done");
-            save_permit.save(checkpoint, metrics).await?;
-            trace!("Save task from save_f: save_permit.save done");
+            let save_start = std::time::Instant::now();
+            let save_point = save_fut.await?;
+            trace!("Save task from save_f: save_fut.await done");
+            save_permit.save_point(save_point, metrics).await?;
+            trace!("Save task from save_f: save_permit.save_point done");
+            prometheus::observe(
+                "nockapp_checkpoint_duration_seconds",
+                "Time taken to snapshot and write a checkpoint",
+                &[],
+                save_start.elapsed().as_secs_f64(),
+            );
 
             drop(save_permit);
             Ok::<(), NockAppError>(())
@@ -295,6 +422,78 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
         Ok(())
     }
 
+    /// Set when checkpoints are written.
+    pub fn set_checkpoint_policy(&mut self, policy: CheckpointPolicy) {
+        if let Some(period) = policy.interval {
+            self.save_interval = interval(period);
+            self.save_interval
+                .set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        }
+        self.checkpoint_policy = policy;
+    }
+
+    /// Answer peeks from IO drivers with `replica` rather than the kernel, keeping them off the
+    /// poke path. Driver peeks then see the state as of the replica's last refresh.
+    pub fn set_peek_replica(&mut self, replica: Arc<Replica<J>>) {
+        self.peek_replica = Some(replica);
+    }
+
+    /// Keep up to `max_entries` results of driver peeks and answer the same peek from them until
+    /// the next poke. Not used for peeks a replica answers, whose state changes on refresh.
+    pub fn set_peek_cache(&mut self, max_entries: usize) {
+        self.peek_cache = Some(Arc::new(PeekCache::new(max_entries)));
+    }
+
+    /// Remove `pid_file` once the app has shut down
+    pub fn set_pid_file(&mut self, pid_file: PidFile) {
+        self.pid_file = Some(pid_file);
+    }
+
+    /// Nack pokes over their source's quota instead of running them
+    pub fn set_poke_quotas(&mut self, quotas: PokeQuotas) {
+        self.poke_quotas = quotas;
+    }
+
+    /// Keep pokes from drivers that bail or time out in `dead_letters`, and poke again the ones
+    /// moved there to be retried
+    pub fn set_dead_letters(&mut self, dead_letters: DeadLetters) {
+        self.dead_letters = Some(dead_letters);
+    }
+
+    /// Send a copy of every poke from a driver, with whether it was acked, to the returned
+    /// receiver. A slow receiver holds up the copies rather than the pokes.
+    pub fn tap_pokes(&mut self, capacity: usize) -> mpsc::Receiver<TappedPoke> {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        self.poke_tap = Some(sender);
+        receiver
+    }
+
+    /// Save with [`NockApp::save_scrubbed`] when exiting with code 0, for a run whose pokes hide
+    /// secrets that earlier checkpoints and events hold in the clear.
+    pub fn scrub_on_exit(&mut self) {
+        self.scrub_on_exit = true;
+    }
+
+    /// Set how checkpoints are compressed from the next save on.
+    pub async fn set_checkpoint_compression(&self, compression: Compression) {
+        self.save_mutex.lock().await.set_compression(compression);
+    }
+
+    /// Set which past checkpoints are kept in the checkpoint history.
+    pub async fn set_checkpoint_retention(&self, retention: RetentionPolicy) {
+        self.save_mutex.lock().await.set_retention_policy(retention);
+    }
+
+    /// Set when checkpoints are written as deltas against the last full one.
+    pub async fn set_checkpoint_deltas(&self, deltas: DeltaPolicy) {
+        self.save_mutex.lock().await.set_delta_policy(deltas);
+    }
+
+    /// The paths of the live checkpoints: both full snapshots and the delta.
+    pub async fn checkpoint_paths(&self) -> Vec<PathBuf> {
+        self.save_mutex.lock().await.paths()
+    }
+
     pub async fn save_locked(&mut self) -> NockAppResult {
         trace!("save_locked: locking save_mutex");
         let guard = self.save_mutex.clone().lock_owned().await;
@@ -306,6 +505,17 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
         Ok(())
     }
 
+    /// Checkpoint over both live checkpoints, and delete the delta, the checkpoint history and the
+    /// event log, so nothing on disk holds an earlier state. For after a poke that hides secrets
+    /// the state held in the clear, like encrypting keys.
+    pub async fn save_scrubbed(&mut self) -> NockAppResult {
+        let mut saver = self.save_mutex.clone().lock_owned().await;
+        let point = self.kernel.save_point().await?;
+        saver.overwrite_point(point, self.metrics.clone()).await?;
+        self.kernel.reset_event_log().await?;
+        Ok(())
+    }
+
     /// Peek at a noun in the kernel, blocking operation
     #[tracing::instrument(skip(self, path))]
     pub fn peek_sync(&mut self, path: NounSlab) -> Result<NounSlab, NockAppError> {
@@ -374,7 +584,10 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
         // Reset NockApp for next run
         // self.reset();
         // debug!("Reset NockApp for next run");
-        loop {
+        if daemon::notify("READY=1") {
+            debug!("Told systemd the app is ready");
+        }
+        let result = loop {
             let work_res = self.work().await;
             match work_res {
                 Ok(nockapp_run) => match nockapp_run {
@@ -398,7 +611,10 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
                     break Err(e);
                 }
             };
-        }
+        };
+        // The exporter sends spans in batches, so the last ones would be lost on exit
+        let _ = tokio::task::spawn_blocking(crate::observability::flush_traces).await;
+        result
     }
 
     #[instrument(skip(socket))]
@@ -423,6 +639,7 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
     async fn work(&mut self) -> Result<NockAppRun, NockAppError> {
         // Track SIGINT (C-c) presses for immediate termination
         // Fires when there is a save interval tick *and* an available permit in the save semaphore
+        let timed_saves = self.checkpoint_policy.interval.is_some();
         let save_ready = self.save_interval.tick().then(|_| async {
             trace!("save_interval tick: locking save_mutex");
             let guard = self.save_mutex.clone().lock_owned().await;
@@ -460,10 +677,16 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
                     NockAppExitStatus::Done(res) => {
                         match res {
                             Ok(()) => {
+                                if let Some(watchdog) = self.watchdog.take() {
+                                    watchdog.abort();
+                                }
                                 debug!("Shutdown triggered, exiting");
                                 Ok(NockAppRun::Done)
                             },
                             Err(e) => {
+                                if let Some(watchdog) = self.watchdog.take() {
+                                    watchdog.abort();
+                                }
                                 error!("Shutdown triggered with error: {:?}", e);
                                 Err(e)
                             }
@@ -471,10 +694,18 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
                     },
                 }
             },
-            save_guard = save_ready => {
+            save_guard = save_ready, if timed_saves => {
                 self.metrics.handle_save_permit_res.increment();
                 self.handle_save_permit_res(save_guard).await
             },
+            Some(request) = self.save_requests.recv() => {
+                self.handle_save_request(request);
+                Ok(NockAppRun::Pending)
+            },
+            _ = self.dead_letter_poll.tick(), if self.dead_letters.is_some() => {
+                self.retry_dead_letters().await;
+                Ok(NockAppRun::Pending)
+            },
             maybe_signal = self.signals.next() => {
                 debug!("Signal received");
                 if let Some(signal) = maybe_signal {
@@ -491,11 +722,14 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
                         if !self.abort_immediately.load(Ordering::SeqCst) {
                             if self.abort_immediately.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
                                 trace!("Exiting due to signal {signal}");
+                                // The running computation finishes before the exit checkpoint,
+                                // unless it's still going at the shutdown deadline
                                 let exit_fut = self.exit.exit(code);
                                 self.tasks.spawn(exit_fut);
                                 break Ok(NockAppRun::Pending);
                             }
                         } else {
+                            warn!("Second signal {signal}, exiting without waiting for the shutdown");
                             std::process::exit(code.try_into().unwrap());
                         }
                     }
@@ -537,77 +771,301 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
         res.map(|_| NockAppRun::Pending)
     }
 
+    /// Save in the background once the save mutex is free, if the request is still due by then.
+    fn handle_save_request(&self, request: SaveRequest) -> tokio::task::JoinHandle<()> {
+        let save_fut = self.kernel.save_point();
+        let save_mutex = self.save_mutex.clone();
+        let event_number = self.kernel.serf.event_number.clone();
+        let metrics = self.metrics.clone();
+        self.tasks.spawn(async move {
+            let mut saver = save_mutex.lock_owned().await;
+            let event_num = event_number.load(Ordering::SeqCst);
+            let due = match request {
+                SaveRequest::Effect => true,
+                SaveRequest::Events(every) => event_num >= saver.last_event_num() + every,
+            };
+            if !due || !saver.save_needed(event_num) {
+                return;
+            }
+            let res = async {
+                let save_point = save_fut.await?;
+                saver.save_point(save_point, metrics).await?;
+                Ok::<(), NockAppError>(())
+            };
+            if let Err(e) = res.await {
+                error!("Failed to save on request: {:?}", e);
+            }
+        })
+    }
+
     #[instrument(skip_all)]
     async fn handle_action(&self, action: IOAction) {
         // Stop processing events if we are exiting
         if self.exit_status.load(Ordering::SeqCst) {
-            if let IOAction::Poke { .. } = action {
-                self.metrics.poke_during_exit.increment();
-                debug!("Poked during exit. Ignoring.")
-            } else {
+            if let IOAction::Peek { .. } = action {
                 self.metrics.peek_during_exit.increment();
                 debug!("Peeked during exit. Ignoring.")
+            } else {
+                self.metrics.poke_during_exit.increment();
+                debug!("Poked during exit. Ignoring.")
             }
             return;
         }
         match action {
+            // Handled in the sender's span, so a driver's trace goes on through the kernel
             IOAction::Poke {
                 wire,
                 poke,
                 ack_channel,
-            } => self.handle_poke(wire, poke, ack_channel).await,
+                span,
+            } => {
+                span.in_scope(|| self.handle_poke(wire, poke, ack_channel))
+                    .await
+            }
             IOAction::Peek {
                 path,
                 result_channel,
-            } => self.handle_peek(path, result_channel).await,
+                span,
+            } => {
+                span.in_scope(|| self.handle_peek(path, result_channel))
+                    .await
+            }
+            IOAction::Upgrade {
+                kernel,
+                ack_channel,
+            } => self.handle_upgrade(kernel, ack_channel).await,
         }
     }
 
     #[instrument(skip_all)]
-    async fn handle_poke(
+    async fn handle_upgrade(
         &self,
-        wire: WireRepr,
-        cause: NounSlab,
+        kernel: Vec<u8>,
         ack_channel: tokio::sync::oneshot::Sender<PokeResult>,
     ) {
-        let poke_future = self.kernel.poke(wire, cause);
+        if let Some(cache) = &self.peek_cache {
+            cache.invalidate();
+        }
+        let upgrade_future = self.kernel.upgrade(kernel);
+        // Sent once the upgrade is done, since the new kernel may understand other versions
+        let wires_future = self.kernel.peek(WireVersions::peek_path());
+        let wire_versions = self.wire_versions.clone();
         let effect_broadcast = self.effect_broadcast.clone();
-        let _ = self.tasks.spawn(async move {
-            let poke_result = poke_future.await;
-            match poke_result {
+        let peek_cache = self.peek_cache.clone();
+        let state_changes = self.state_changes.clone();
+        self.tasks.spawn(async move {
+            let upgrade_result = upgrade_future.await;
+            if let Some(cache) = peek_cache {
+                cache.invalidate();
+            }
+            match upgrade_result {
                 Ok(effects) => {
+                    info!("Kernel upgrade complete");
+                    wire_versions.set(negotiate_wires(wires_future).await);
+                    state_changes.send_modify(|n| *n += 1);
                     let _ = ack_channel.send(PokeResult::Ack);
                     for effect_slab in effects.to_vec() {
                         let _ = effect_broadcast.send(effect_slab);
                     }
                 }
-                Err(_) => {
+                Err(e) => {
+                    error!("Kernel upgrade failed, still running the old kernel: {}", e);
                     let _ = ack_channel.send(PokeResult::Nack);
                 }
             }
         });
     }
 
+    #[instrument(skip_all)]
+    async fn handle_poke(
+        &self,
+        wire: WireRepr,
+        cause: NounSlab,
+        ack_channel: tokio::sync::oneshot::Sender<PokeResult>,
+    ) {
+        let source = wire.source;
+        let at = std::time::SystemTime::now();
+        if let Err(e) = self.wire_versions.check(&wire) {
+            self.metrics.wire_version_rejected.increment();
+            warn!(source, version = wire.version, "Rejected a poke: {}", e);
+            let _ = ack_channel.send(PokeResult::Nack);
+            self.tap_poke(wire, cause, at, false);
+            return;
+        }
+        if let Err(violation) = self.poke_quotas.check(source, &cause) {
+            self.metrics.poke_quota_rejected.increment();
+            warn!(source, %violation, "Rejected a poke over its quota");
+            let _ = ack_channel.send(PokeResult::Nack);
+            self.tap_poke(wire, cause, at, false);
+            return;
+        }
+        // Cleared as the poke is sent and again when it's done, see `PeekCache`
+        if let Some(cache) = &self.peek_cache {
+            cache.invalidate();
+        }
+        let peek_cache = self.peek_cache.clone();
+        let state_changes = self.state_changes.clone();
+        // The poke takes the wire and cause, so keep copies in case it fails
+        let dead_letter = self
+            .dead_letters
+            .clone()
+            .filter(|_| !wire.secret)
+            .map(|dead_letters| (dead_letters, wire.clone(), cause.clone()));
+        let tap = self
+            .poke_tap
+            .clone()
+            .map(|tap| (tap, wire.clone(), cause.clone()));
+        let poke_future = self.kernel.poke(wire, cause);
+        let effect_broadcast = self.effect_broadcast.clone();
+        let save_requests = self.save_request_sender.clone();
+        let policy = self.checkpoint_policy;
+        let _ = self.tasks.spawn(
+            async move {
+                let poke_start = std::time::Instant::now();
+                let poke_result = poke_future.await;
+                if let Some(cache) = peek_cache {
+                    cache.invalidate();
+                }
+                record_poke(source, poke_result.is_ok(), poke_start.elapsed());
+                let acked = poke_result.is_ok();
+                let failure = match &poke_result {
+                    Ok(effects) => bail_error(unsafe { *effects.root() }),
+                    Err(e) => Some(e.to_string()),
+                };
+                match poke_result {
+                    Ok(effects) => {
+                        state_changes.send_modify(|n| *n += 1);
+                        let _ = ack_channel.send(PokeResult::Ack);
+                        let effects = effects.to_vec();
+                        let save_effect = policy.on_effect && effects.iter().any(is_save_effect);
+                        info_span!("effects", count = effects.len()).in_scope(|| {
+                            for effect_slab in effects {
+                                let _ = effect_broadcast.send(effect_slab);
+                            }
+                        });
+                        if save_effect {
+                            let _ = save_requests.send(SaveRequest::Effect).await;
+                        }
+                        if let Some(every) = policy.every_events {
+                            // A queued request checks the count again when it runs
+                            let _ = save_requests.try_send(SaveRequest::Events(every));
+                        }
+                    }
+                    Err(CrownError::PokeTimeout(timeout)) => {
+                        warn!("Poke from {} timed out after {:?}", source, timeout);
+                        let _ = ack_channel.send(PokeResult::Nack);
+                    }
+                    Err(CrownError::Interrupted) => {
+                        warn!("Poke from {} was interrupted", source);
+                        let _ = ack_channel.send(PokeResult::Nack);
+                    }
+                    Err(_) => {
+                        let _ = ack_channel.send(PokeResult::Nack);
+                    }
+                }
+                if let Some((tap, wire, poke)) = tap {
+                    let _ = tap
+                        .send(TappedPoke {
+                            wire,
+                            poke,
+                            at,
+                            acked,
+                        })
+                        .await;
+                }
+                if let (Some(error), Some(dead_letter)) = (failure, dead_letter) {
+                    keep_dead_letter(dead_letter, error).await;
+                }
+            }
+            .in_current_span(),
+        );
+    }
+
+    /// Send a poke that didn't reach the kernel to the tap, if there is one
+    fn tap_poke(&self, wire: WireRepr, poke: NounSlab, at: std::time::SystemTime, acked: bool) {
+        let Some(tap) = self.poke_tap.clone() else {
+            return;
+        };
+        let tapped = TappedPoke {
+            wire,
+            poke,
+            at,
+            acked,
+        };
+        self.tasks.spawn(async move {
+            let _ = tap.send(tapped).await;
+        });
+    }
+
+    /// Poke again the dead letters moved to be retried
+    async fn retry_dead_letters(&self) {
+        let Some(dead_letters) = self.dead_letters.clone() else {
+            return;
+        };
+        let retries = tokio::task::spawn_blocking(move || dead_letters.take_retries())
+            .await
+            .expect("dead letter retry read panicked");
+        let retries = match retries {
+            Ok(retries) => retries,
+            Err(e) => {
+                warn!("Could not read dead letters to retry: {}", e);
+                return;
+            }
+        };
+        for (entry, wire) in retries {
+            info!("Retrying dead letter {} from {}", entry.id, wire.source);
+            // A retry that fails is kept again, so there's nothing to do with the ack
+            let (ack_channel, _) = tokio::sync::oneshot::channel();
+            self.handle_poke(wire, entry.cause_slab(), ack_channel)
+                .await;
+        }
+    }
+
     #[instrument(skip_all)]
     async fn handle_peek(
         &self,
         path: NounSlab,
         result_channel: tokio::sync::oneshot::Sender<Option<NounSlab>>,
     ) {
-        let peek_future = self.kernel.peek(path);
-        let _ = self.tasks.spawn(async move {
-            let peek_res = peek_future.await;
-
-            match peek_res {
-                Ok(res_slab) => {
-                    let _ = result_channel.send(Some(res_slab));
+        let mut cache_entry = None;
+        let peek_future = match &self.peek_replica {
+            Some(replica) => {
+                let replica = replica.clone();
+                async move { replica.peek(path).await }.boxed()
+            }
+            None => {
+                if let Some(cache) = &self.peek_cache {
+                    let key = path.jam();
+                    if let Some(res_slab) = cache.get(&key) {
+                        self.metrics.peek_cache_hits.increment();
+                        let _ = result_channel.send(Some(res_slab));
+                        return;
+                    }
+                    self.metrics.peek_cache_misses.increment();
+                    cache_entry = Some((cache.clone(), key, cache.version()));
                 }
-                Err(e) => {
-                    error!("Peek error: {:?}", e);
-                    let _ = result_channel.send(None);
+                self.kernel.peek(path).boxed()
+            }
+        };
+        let _ = self.tasks.spawn(
+            async move {
+                let peek_res = peek_future.await;
+
+                match peek_res {
+                    Ok(res_slab) => {
+                        if let Some((cache, key, version)) = cache_entry {
+                            cache.insert(key, version, res_slab.clone());
+                        }
+                        let _ = result_channel.send(Some(res_slab));
+                    }
+                    Err(e) => {
+                        error!("Peek error: {:?}", e);
+                        let _ = result_channel.send(None);
+                    }
                 }
             }
-        });
+            .in_current_span(),
+        );
     }
 
     // TODO: We should explicitly kick off a save somehow
@@ -627,34 +1085,66 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
             }
         }
 
+        // New pokes are ignored from here on, and drivers stop taking new work
+        info!("Shutting down: finishing in-flight work");
+        self.shutdown.drain();
+        daemon::notify("STOPPING=1");
+        let deadline = self.shutdown_deadline;
+        let cancel_token = self.kernel.cancel_token();
+        self.watchdog = Some(tokio::spawn(async move {
+            tokio::time::sleep(deadline).await;
+            error!(
+                "Shutdown took longer than {:?}, interrupting the running computation",
+                deadline
+            );
+            cancel_token.cancel();
+            tokio::time::sleep(shutdown::FORCE_EXIT_AFTER).await;
+            error!("Shutdown is stuck, exiting without it");
+            std::process::exit(code.try_into().unwrap_or(1));
+        }));
+
         let exit_event_num = self.kernel.serf.event_number.load(Ordering::SeqCst);
-        debug!(
-            "Exit request received, waiting for save checkpoint with event_num {} (code {})",
-            exit_event_num, code
-        );
+        let waiter = if self.checkpoint_policy.on_shutdown {
+            debug!(
+                "Exit request received, waiting for save checkpoint with event_num {} (code {})",
+                exit_event_num, code
+            );
 
-        let waiter_mutex_arc = self.save_mutex.clone();
-        let waiter = {
-            trace!("Waiting for save event_num {}", exit_event_num);
-            let mut guard = waiter_mutex_arc.lock().await;
-            trace!("Locked save mutex for event_num {}", exit_event_num);
-            let oneshot = guard.wait_for_snapshot(exit_event_num).await;
-            trace!("Acquired the oneshot for snapshot on save event_num {}", exit_event_num);
-            drop(guard);
-            oneshot
-        };
+            let waiter_mutex_arc = self.save_mutex.clone();
+            let waiter = {
+                trace!("Waiting for save event_num {}", exit_event_num);
+                let mut guard = waiter_mutex_arc.lock().await;
+                trace!("Locked save mutex for event_num {}", exit_event_num);
+                let oneshot = guard.wait_for_snapshot(exit_event_num).await;
+                trace!("Acquired the oneshot for snapshot on save event_num {}", exit_event_num);
+                drop(guard);
+                oneshot
+            };
 
-        // Force an immediate save to ensure we have the latest state
-        info!(
-            "Exit signal received with code {}, forcing immediate save",
-            code
-        );
-        if let Err(e) = self.save_locked().await {
-            error!(
-                "Failed to save during exit: {:?} - continuing with shutdown anyway",
-                e
+            // Force an immediate save to ensure we have the latest state
+            info!(
+                "Exit signal received with code {}, forcing immediate save",
+                code
             );
-        }
+            let saved = if self.scrub_on_exit && code == EXIT_OK {
+                self.save_scrubbed().await
+            } else {
+                self.save_locked().await
+            };
+            if let Err(e) = saved {
+                error!(
+                    "Failed to save during exit: {:?} - continuing with shutdown anyway",
+                    e
+                );
+            }
+            Some(waiter)
+        } else {
+            info!(
+                "Exit signal received with code {}, not saving (checkpoint on shutdown is off)",
+                code
+            );
+            None
+        };
 
         // let cancel_token = self.cancel_token.clone();
         let exit = self.exit.clone();
@@ -663,16 +1153,25 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
         // recv from the watch channel until we reach the exit event_num, wrapped up in a future
         // that will send the shutdown result when we're done.
         let socket_path = self.npc_socket_path.clone();
+        let pid_file = self.pid_file.take();
+        let shutdown = self.shutdown.clone();
         // TODO: Break this out as a separate select! handler with no spawn
         self.tasks.spawn(async move {
-            debug!("Waiting for save event_num {}", exit_event_num);
-            let result = waiter.await;
-            if let Err(e) = result {
-                error!("Error waiting for snapshot: {e}");
-                panic!("Error waiting for snapshot: {e}");
-            };
+            if let Some(waiter) = waiter {
+                debug!("Waiting for save event_num {}", exit_event_num);
+                let result = waiter.await;
+                if let Err(e) = result {
+                    error!("Error waiting for snapshot: {e}");
+                    panic!("Error waiting for snapshot: {e}");
+                };
+            }
+            debug!("Save event_num reached, closing drivers");
+            shutdown.close().await;
             Self::cleanup_socket_(&socket_path);
-            debug!("Save event_num reached, finishing with code {}", code);
+            if let Some(pid_file) = pid_file {
+                pid_file.remove();
+            }
+            debug!("Drivers closed, finishing with code {}", code);
             let shutdown_result = if code == EXIT_OK {
                 Ok(())
             } else {
@@ -689,3 +1188,72 @@ impl<J: Jammer + Send + 'static> NockApp<J> {
         Ok(NockAppRun::Pending)
     }
 }
+
+fn is_save_effect(effect: &NounSlab) -> bool {
+    unsafe { effect.root() }
+        .as_cell()
+        .map(|cell| cell.head().eq_bytes(b"save"))
+        .unwrap_or(false)
+}
+
+/// Write a poke that failed with `error` to the dead letters
+async fn keep_dead_letter(dead_letter: (DeadLetters, WireRepr, NounSlab), error: String) {
+    let (dead_letters, wire, cause) = dead_letter;
+    let source = wire.source;
+    let kept = tokio::task::spawn_blocking(move || dead_letters.add(&wire, &cause, &error))
+        .await
+        .expect("dead letter write panicked");
+    match kept {
+        Ok(id) => warn!("Poke from {} failed, kept as dead letter {}", source, id),
+        Err(e) => error!("Poke from {} failed and could not be kept: {}", source, e),
+    }
+}
+
+/// What the kernel answered `[%wires ~]` with, or `None` if it doesn't say which wire versions
+/// it understands
+async fn negotiate_wires(
+    peek: impl Future<Output = Result<NounSlab, CrownError>>,
+) -> Option<VersionsBySource> {
+    let result = match peek.await {
+        Ok(result) => result,
+        Err(e) => {
+            debug!(
+                "The kernel doesn't say which wire versions it understands: {}",
+                e
+            );
+            return None;
+        }
+    };
+    let ScryResult::Some(noun) = ScryResult::from(unsafe { result.root() }) else {
+        debug!("The kernel doesn't say which wire versions it understands");
+        return None;
+    };
+    match WireVersions::parse(noun) {
+        Ok(understood) => {
+            debug!("The kernel understands wire versions {:?}", understood);
+            Some(understood)
+        }
+        Err(e) => {
+            warn!("Taking every wire version, since the kernel said: {}", e);
+            None
+        }
+    }
+}
+
+/// Count a poke from the driver with wire source `source` and how long it took, for the metrics
+/// endpoint
+fn record_poke(source: &str, acked: bool, elapsed: std::time::Duration) {
+    let result = if acked { "ack" } else { "nack" };
+    prometheus::inc_counter(
+        "nockapp_driver_pokes_total",
+        "Pokes handled, by the driver they came from and their result",
+        &[("driver", source), ("result", result)],
+        1.0,
+    );
+    prometheus::observe(
+        "nockapp_driver_poke_duration_seconds",
+        "Time the kernel spent on pokes, by the driver they came from",
+        &[("driver", source)],
+        elapsed.as_secs_f64(),
+    );
+}
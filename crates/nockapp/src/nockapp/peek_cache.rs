@@ -0,0 +1,112 @@
+//! Results of driver peeks, kept until the kernel's state next changes.
+//!
+//! Entries are keyed by the jam of the peek path and tagged with the state version they were
+//! computed at. Each poke or upgrade bumps the version once when it's sent to the kernel and
+//! again when it's done, clearing the cache both times, and a result is only kept if the version
+//! didn't move while it was computed, so no entry can outlive the state it was read from.
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::noun::slab::NounSlab;
+use crate::Bytes;
+
+pub struct PeekCache {
+    max_entries: usize,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    version: u64,
+    entries: HashMap<Bytes, NounSlab>,
+}
+
+impl PeekCache {
+    /// A cache holding at most `max_entries` results, flushed when it's full
+    pub fn new(max_entries: usize) -> Self {
+        PeekCache {
+            max_entries: max_entries.max(1),
+            inner: Mutex::new(Inner {
+                version: 0,
+                entries: HashMap::new(),
+            }),
+        }
+    }
+
+    /// The current state version, to pass to [`PeekCache::insert`] with the result of a peek
+    /// sent now
+    pub fn version(&self) -> u64 {
+        self.lock().version
+    }
+
+    /// The kernel's state is changing or has changed
+    pub fn invalidate(&self) {
+        let mut inner = self.lock();
+        inner.version += 1;
+        inner.entries.clear();
+    }
+
+    /// The cached result of peeking the path with this jam
+    pub fn get(&self, key: &Bytes) -> Option<NounSlab> {
+        self.lock().entries.get(key).cloned()
+    }
+
+    /// Keep `result`, computed at `version`, unless the state has changed since
+    pub fn insert(&self, key: Bytes, version: u64, result: NounSlab) {
+        let mut inner = self.lock();
+        if inner.version != version {
+            return;
+        }
+        if inner.entries.len() >= self.max_entries {
+            inner.entries.clear();
+        }
+        inner.entries.insert(key, result);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::D;
+
+    use super::*;
+    use crate::noun::slab::NockJammer;
+
+    #[test]
+    fn test_peek_cache() {
+        let cache = PeekCache::new(2);
+        let path = |n: u64| NounSlab::<NockJammer>::from(D(n)).jam();
+
+        let version = cache.version();
+        assert!(cache.get(&path(1)).is_none());
+        cache.insert(path(1), version, NounSlab::from(D(10)));
+        let hit = cache.get(&path(1)).expect("cached");
+        assert_eq!(
+            unsafe { hit.root().as_atom().unwrap().as_u64().unwrap() },
+            10
+        );
+
+        // A poke clears the cache, and a peek that ran across it isn't kept
+        let before = cache.version();
+        cache.invalidate();
+        assert!(cache.get(&path(1)).is_none());
+        cache.insert(path(2), before, NounSlab::from(D(20)));
+        assert!(cache.get(&path(2)).is_none());
+
+        let version = cache.version();
+        for n in 0..3 {
+            cache.insert(path(n), version, NounSlab::from(D(n)));
+        }
+        assert_eq!(cache.len(), 1);
+    }
+}
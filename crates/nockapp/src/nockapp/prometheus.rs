@@ -0,0 +1,167 @@
+//! Metrics in the Prometheus text exposition format.
+//!
+//! The runtime and drivers record into one process-wide registry with [`inc_counter`],
+//! [`set_gauge`] and [`observe`], and [`render`] writes out everything recorded so far, for the
+//! `/metrics` endpoint of [`crate::drivers::metrics_server`] to serve. Recording is opt-in: until
+//! [`enable`] is called every recording function returns straight away, so a node that isn't
+//! scraped pays nothing for it.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static REGISTRY: Mutex<BTreeMap<&'static str, Family>> = Mutex::new(BTreeMap::new());
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Counter,
+    Gauge,
+    Summary,
+}
+
+impl Kind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+            Kind::Summary => "summary",
+        }
+    }
+}
+
+/// A metric name and every labelled series recorded under it
+struct Family {
+    help: &'static str,
+    kind: Kind,
+    /// By rendered label set, like `{driver="http"}`
+    series: BTreeMap<String, Series>,
+}
+
+#[derive(Default)]
+struct Series {
+    value: f64,
+    /// Observations made, for summaries
+    count: u64,
+}
+
+/// Start recording metrics
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether metrics are being recorded
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Add `by` to the counter `name`
+pub fn inc_counter(name: &'static str, help: &'static str, labels: &[(&str, &str)], by: f64) {
+    record(name, help, Kind::Counter, labels, |series| {
+        series.value += by
+    });
+}
+
+/// Set the gauge `name` to `value`
+pub fn set_gauge(name: &'static str, help: &'static str, labels: &[(&str, &str)], value: f64) {
+    record(name, help, Kind::Gauge, labels, |series| {
+        series.value = value
+    });
+}
+
+/// Add an observation of `value`, usually a duration in seconds, to the summary `name`
+pub fn observe(name: &'static str, help: &'static str, labels: &[(&str, &str)], value: f64) {
+    record(name, help, Kind::Summary, labels, |series| {
+        series.value += value;
+        series.count += 1;
+    });
+}
+
+fn record(
+    name: &'static str,
+    help: &'static str,
+    kind: Kind,
+    labels: &[(&str, &str)],
+    update: impl FnOnce(&mut Series),
+) {
+    if !enabled() {
+        return;
+    }
+    let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    let family = registry.entry(name).or_insert_with(|| Family {
+        help,
+        kind,
+        series: BTreeMap::new(),
+    });
+    debug_assert_eq!(family.kind, kind, "metric {} recorded as two kinds", name);
+    update(family.series.entry(render_labels(labels)).or_default());
+}
+
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let mut out = String::from("{");
+    for (i, (key, value)) in labels.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(key);
+        out.push_str("=\"");
+        for c in value.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '"' => out.push_str("\\\""),
+                '\n' => out.push_str("\\n"),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    }
+    out.push('}');
+    out
+}
+
+/// Everything recorded so far, in the text exposition format
+pub fn render() -> String {
+    let registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    let mut out = String::new();
+    for (name, family) in registry.iter() {
+        let _ = writeln!(out, "# HELP {} {}", name, family.help);
+        let _ = writeln!(out, "# TYPE {} {}", name, family.kind.as_str());
+        for (labels, series) in &family.series {
+            match family.kind {
+                Kind::Counter | Kind::Gauge => {
+                    let _ = writeln!(out, "{}{} {}", name, labels, series.value);
+                }
+                Kind::Summary => {
+                    let _ = writeln!(out, "{}_sum{} {}", name, labels, series.value);
+                    let _ = writeln!(out, "{}_count{} {}", name, labels, series.count);
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        enable();
+        inc_counter("test_pokes_total", "Pokes", &[("driver", "a\"b")], 1.0);
+        inc_counter("test_pokes_total", "Pokes", &[("driver", "a\"b")], 2.0);
+        set_gauge("test_height", "Height", &[], 7.0);
+        observe("test_latency_seconds", "Latency", &[("kind", "block")], 0.5);
+        observe("test_latency_seconds", "Latency", &[("kind", "block")], 1.5);
+
+        let text = render();
+        assert!(text.contains("# TYPE test_pokes_total counter\n"));
+        assert!(text.contains("test_pokes_total{driver=\"a\\\"b\"} 3\n"));
+        assert!(text.contains("# HELP test_height Height\ntest_height 7\n"));
+        assert!(text.contains("test_latency_seconds_sum{kind=\"block\"} 2\n"));
+        assert!(text.contains("test_latency_seconds_count{kind=\"block\"} 2\n"));
+    }
+}
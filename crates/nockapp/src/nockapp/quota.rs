@@ -0,0 +1,179 @@
+//! Limits on the pokes each wire source may send, checked before a poke reaches the serf.
+//!
+//! A poke over its source's quota is nacked without running, and the [`QuotaViolation`] is
+//! logged and counted under `nockapp.poke_quota.rejected`. Drivers that want to tell their
+//! clients why can run [`PokeQuotas::check`] on a poke themselves first.
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use thiserror::Error;
+
+use crate::noun::slab::NounSlab;
+use crate::utils::rate::RateLimiter;
+
+/// What one source may poke
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PokeQuota {
+    /// The most bytes a poke's noun may take
+    pub max_bytes: Option<usize>,
+    /// The most pokes a second
+    pub rate: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum QuotaViolation {
+    #[error("poke from {source_name} is {bytes} bytes, over its limit of {max_bytes}")]
+    TooBig {
+        source_name: &'static str,
+        bytes: usize,
+        max_bytes: usize,
+    },
+    #[error("pokes from {source_name} are over their limit of {rate} a second")]
+    TooFast {
+        source_name: &'static str,
+        rate: u32,
+    },
+}
+
+/// A source's quota on the command line: `SOURCE:MAX_BYTES:RATE`, with either limit left empty
+/// for none and `*` as the source for the default, like `npc:1048576:50` or `*:67108864:`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceQuota {
+    pub source: String,
+    pub quota: PokeQuota,
+}
+
+impl FromStr for SourceQuota {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || format!("{} is not SOURCE:MAX_BYTES:RATE", s);
+        let mut fields = s.split(':');
+        let (Some(source), Some(max_bytes), Some(rate), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(bad());
+        };
+        if source.is_empty() {
+            return Err(bad());
+        }
+        let max_bytes = match max_bytes {
+            "" => None,
+            n => Some(n.parse().map_err(|_| bad())?),
+        };
+        let rate = match rate {
+            "" => None,
+            n => Some(n.parse().map_err(|_| bad())?),
+        };
+        Ok(SourceQuota {
+            source: source.to_string(),
+            quota: PokeQuota { max_bytes, rate },
+        })
+    }
+}
+
+/// The quota of each source, and the rate each has been poking at
+#[derive(Default)]
+pub struct PokeQuotas {
+    default: PokeQuota,
+    sources: HashMap<String, PokeQuota>,
+    buckets: Mutex<HashMap<&'static str, RateLimiter>>,
+}
+
+impl PokeQuotas {
+    /// Quotas from `SourceQuota`s, a later one for a source replacing an earlier one
+    pub fn new(quotas: impl IntoIterator<Item = SourceQuota>) -> Self {
+        let mut result = PokeQuotas::default();
+        for SourceQuota { source, quota } in quotas {
+            if source == "*" {
+                result.default = quota;
+            } else {
+                result.sources.insert(source, quota);
+            }
+        }
+        result
+    }
+
+    /// The quota of `source`: its own, or the default
+    pub fn quota(&self, source: &str) -> PokeQuota {
+        self.sources.get(source).copied().unwrap_or(self.default)
+    }
+
+    /// Whether `poke` from `source` is within its quota, counting it against the rate if so
+    pub fn check(&self, source: &'static str, poke: &NounSlab) -> Result<(), QuotaViolation> {
+        let quota = self.quota(source);
+        if let Some(max_bytes) = quota.max_bytes {
+            let bytes = poke.size_of(unsafe { *poke.root() }).bytes;
+            if bytes > max_bytes {
+                return Err(QuotaViolation::TooBig {
+                    source_name: source,
+                    bytes,
+                    max_bytes,
+                });
+            }
+        }
+        if let Some(rate) = quota.rate {
+            let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+            let bucket = buckets
+                .entry(source)
+                .or_insert_with(|| RateLimiter::new(rate));
+            if bucket.wait(Instant::now()).is_some() {
+                return Err(QuotaViolation::TooFast {
+                    source_name: source,
+                    rate,
+                });
+            }
+            bucket.take();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::{D, T};
+
+    use super::*;
+
+    #[test]
+    fn test_poke_quotas() {
+        let quotas = PokeQuotas::new(
+            ["*:1000:", "npc:64:2", "http::"]
+                .into_iter()
+                .map(|s| s.parse::<SourceQuota>().unwrap()),
+        );
+        assert!("npc:64".parse::<SourceQuota>().is_err());
+        assert!("npc:big:".parse::<SourceQuota>().is_err());
+        assert_eq!(quotas.quota("http"), PokeQuota::default());
+        assert_eq!(quotas.quota("timer").max_bytes, Some(1000));
+
+        let mut small = NounSlab::new();
+        let root = T(&mut small, &[D(1), D(2)]);
+        small.set_root(root);
+        let mut big = NounSlab::new();
+        let mut root = D(0);
+        for n in 0..10 {
+            root = T(&mut big, &[D(n), root]);
+        }
+        big.set_root(root);
+
+        assert!(matches!(
+            quotas.check("npc", &big),
+            Err(QuotaViolation::TooBig { max_bytes: 64, .. })
+        ));
+        assert_eq!(quotas.check("npc", &small), Ok(()));
+        assert_eq!(quotas.check("npc", &small), Ok(()));
+        assert_eq!(
+            quotas.check("npc", &small),
+            Err(QuotaViolation::TooFast {
+                source_name: "npc",
+                rate: 2
+            })
+        );
+        for _ in 0..10 {
+            assert_eq!(quotas.check("http", &big), Ok(()));
+        }
+    }
+}
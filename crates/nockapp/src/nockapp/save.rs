@@ -1,7 +1,8 @@
 use std::future::Future;
-use std::path::PathBuf;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime};
 
 use bincode::config::Configuration;
 use bincode::{config, encode_to_vec, Decode, Encode};
@@ -13,13 +14,248 @@ use tokio::fs::create_dir_all;
 use tokio::sync::oneshot;
 use tracing::{debug, error, trace, warn};
 
+use crate::kernel::form::SavePoint;
 use crate::metrics::NockAppMetrics;
+use crate::noun::diff::{diff, patch};
 use crate::noun::slab::{Jammer, NockJammer, NounSlab};
 use crate::JammedNoun;
 
 const JAM_MAGIC_BYTES: u64 = tas!(b"CHKJAM");
+const STREAM_MAGIC_BYTES: u64 = tas!(b"CHKSTM");
+const DELTA_MAGIC_BYTES: u64 = tas!(b"CHKDLT");
+const DELTA_VERSION_1: u32 = 1;
+const ZSTD_MAGIC_BYTES: u64 = tas!(b"CHKZST");
+const ZSTD_ENVELOPE_VERSION: u32 = 1;
+const ZSTD_HEADER_LEN: usize = 12;
+
+/// How checkpoint files are compressed on disk
+///
+/// Compressed files start with a fixed header (`%chkzst` magic bytes and an envelope version)
+/// followed by a single zstd frame holding the checkpoint. Uncompressed files are the bare
+/// checkpoint, which starts with either the `%chkstm` magic of a [`StreamedCheckpoint`] or a
+/// bincode encoding, and bincode writes `u64`s as varints, so neither can start with the `%chkzst`
+/// magic. Loading accepts either, so the compression setting can change between restarts.
+///
+/// Runtimes older than a checkpoint's format can't load it, compressed or not. To downgrade,
+/// export the state with `--export-state-jam` (`state export-state`) and import it into the older
+/// runtime with `--state-jam`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    /// zstd at the given level, from 1 (fastest) to 22 (smallest)
+    Zstd(i32),
+}
+
+impl Compression {
+    /// `0` disables compression, anything else is a zstd level.
+    pub fn from_level(level: i32) -> Self {
+        if level == 0 {
+            Compression::None
+        } else {
+            Compression::Zstd(level)
+        }
+    }
+}
+
+/// Copy a corrupt checkpoint aside so it can be inspected after the next save overwrites it.
+async fn keep_corrupt_copy(path: &PathBuf) {
+    let corrupt_path = path.with_extension("chkjam.corrupt");
+    match tokio::fs::copy(path, &corrupt_path).await {
+        Ok(_) => warn!(
+            "Copied corrupt checkpoint {} to {}",
+            path.display(),
+            corrupt_path.display()
+        ),
+        Err(e) => warn!(
+            "Failed to copy corrupt checkpoint {}: {}",
+            path.display(),
+            e
+        ),
+    }
+}
+
+/// Open a checkpoint file for reading, decompressing it as it is read if it has a zstd envelope.
+fn open_checkpoint_file(path: &Path) -> Result<Box<dyn Read>, CheckpointError> {
+    let mut file = BufReader::new(std::fs::File::open(path)?);
+    let header = file.fill_buf()?;
+    if header.len() < ZSTD_HEADER_LEN || header[..8] != ZSTD_MAGIC_BYTES.to_le_bytes() {
+        return Ok(Box::new(file));
+    }
+    let version = u32::from_le_bytes(header[8..ZSTD_HEADER_LEN].try_into().unwrap());
+    if version != ZSTD_ENVELOPE_VERSION {
+        return Err(CheckpointError::InvalidVersion(path.to_path_buf()));
+    }
+    file.consume(ZSTD_HEADER_LEN);
+    Ok(Box::new(zstd::stream::read::Decoder::with_buffer(file)?))
+}
+
+/// Read a checkpoint file, decompressing it if it has a zstd envelope.
+async fn read_checkpoint_file(path: &PathBuf) -> Result<Vec<u8>, CheckpointError> {
+    let path = path.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut bytes = Vec::new();
+        open_checkpoint_file(&path)?.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    })
+    .await?
+}
+
+/// Write a checkpoint file with `write`, compressing what it writes as configured. This blocks,
+/// so call it from a blocking task.
+///
+/// The file is written and synced under a temporary name, then renamed over `path`, so a crash
+/// mid-write leaves the previous contents of `path` intact rather than a torn file. The directory
+/// is synced after the rename, which durably commits it on Unix.
+fn write_checkpoint_file_with<T>(
+    path: &Path,
+    compression: Compression,
+    write: impl FnOnce(&mut dyn Write) -> Result<T, CheckpointError>,
+) -> Result<T, CheckpointError> {
+    let tmp_path = path.with_extension("chkjam.tmp");
+    let mut file = BufWriter::new(std::fs::File::create(&tmp_path)?);
+    let written = match compression {
+        Compression::None => write(&mut file)?,
+        Compression::Zstd(level) => {
+            file.write_all(&ZSTD_MAGIC_BYTES.to_le_bytes())?;
+            file.write_all(&ZSTD_ENVELOPE_VERSION.to_le_bytes())?;
+            let mut encoder = zstd::stream::write::Encoder::new(&mut file, level)?;
+            let written = write(&mut encoder)?;
+            encoder.finish()?;
+            written
+        }
+    };
+    let file = file.into_inner().map_err(io::IntoInnerError::into_error)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&tmp_path, path)?;
+    if let Some(dir) = path.parent() {
+        crate::utils::ipc::sync_dir(dir)?;
+    }
+    Ok(written)
+}
+
+/// Write a checkpoint file, compressing it as configured. See [`write_checkpoint_file_with`].
+async fn write_checkpoint_file(
+    path: &PathBuf,
+    bytes: Vec<u8>,
+    compression: Compression,
+) -> Result<(), CheckpointError> {
+    let path = path.clone();
+    tokio::task::spawn_blocking(move || {
+        write_checkpoint_file_with(&path, compression, |writer| Ok(writer.write_all(&bytes)?))
+    })
+    .await?
+}
+
+/// Copy the checkpoint file at `from` over `to`, as [`write_checkpoint_file_with`] would write it.
+fn copy_checkpoint_file(from: &Path, to: &Path) -> Result<(), CheckpointError> {
+    write_checkpoint_file_with(to, Compression::None, |writer| {
+        io::copy(&mut std::fs::File::open(from)?, writer)?;
+        Ok(())
+    })
+}
+
+fn read_array<const N: usize, R: Read + ?Sized>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Splits what is written to it into frames, each a little-endian `u32` length and that many
+/// bytes, and hashes the bytes on the way. An empty frame ends the stream, so a reader can find
+/// the end of a jam whose length isn't known until it has been written.
+struct FrameWriter<W: Write> {
+    writer: W,
+    hasher: Hasher,
+}
+
+impl<W: Write> FrameWriter<W> {
+    fn new(writer: W, hasher: Hasher) -> Self {
+        FrameWriter { writer, hasher }
+    }
+
+    /// Write the empty frame, returning the hasher
+    fn finish(mut self) -> io::Result<Hasher> {
+        self.writer.write_all(&0u32.to_le_bytes())?;
+        Ok(self.hasher)
+    }
+}
+
+impl<W: Write> Write for FrameWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(u32::MAX as usize);
+        if len == 0 {
+            return Ok(0);
+        }
+        self.writer.write_all(&(len as u32).to_le_bytes())?;
+        self.writer.write_all(&buf[..len])?;
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads back what a [`FrameWriter`] wrote, hashing it on the way, and ends at the empty frame.
+struct FrameReader<R: Read> {
+    reader: R,
+    hasher: Hasher,
+    /// Bytes read so far, not counting the frame lengths
+    len: usize,
+    /// Bytes left in the current frame
+    remaining: usize,
+    ended: bool,
+}
+
+impl<R: Read> FrameReader<R> {
+    fn new(reader: R, hasher: Hasher) -> Self {
+        FrameReader {
+            reader,
+            hasher,
+            len: 0,
+            remaining: 0,
+            ended: false,
+        }
+    }
+
+    /// Skip to the empty frame, returning the hasher and the number of bytes in the frames
+    fn finish(mut self) -> io::Result<(Hasher, usize)> {
+        io::copy(&mut self, &mut io::sink())?;
+        Ok((self.hasher, self.len))
+    }
+}
+
+impl<R: Read> Read for FrameReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            if self.ended || buf.is_empty() {
+                return Ok(0);
+            }
+            self.remaining = u32::from_le_bytes(read_array(&mut self.reader)?) as usize;
+            if self.remaining == 0 {
+                self.ended = true;
+                return Ok(0);
+            }
+        }
+        let len = buf.len().min(self.remaining);
+        let read = self.reader.read(&mut buf[..len])?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.hasher.update(&buf[..read]);
+        self.len += read;
+        self.remaining -= read;
+        Ok(read)
+    }
+}
+
 const SNAPSHOT_VERSION_0: u32 = 0;
 const SNAPSHOT_VERSION_1: u32 = 1;
+const SNAPSHOT_VERSION_2: u32 = 2;
+const SNAPSHOT_VERSION_3: u32 = 3;
 
 pub enum WhichSnapshot {
     Snapshot0,
@@ -35,13 +271,220 @@ impl WhichSnapshot {
     }
 }
 
+/// When to write a delta against the last full snapshot instead of a new full snapshot.
+///
+/// Deltas are off by default: while they're on, the saver keeps a copy of the base snapshot in
+/// memory to diff against, which about doubles the memory a large kernel state takes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeltaPolicy {
+    /// Write at most this many deltas against one base before compacting into a full snapshot.
+    /// Zero disables deltas.
+    pub max_deltas: usize,
+    /// Compact once a delta's jam would be larger than this fraction of the base's jam.
+    pub max_size_ratio: f64,
+}
+
+impl Default for DeltaPolicy {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+impl DeltaPolicy {
+    pub fn disabled() -> Self {
+        Self::up_to(0)
+    }
+
+    /// Write up to `max_deltas` deltas against each full snapshot
+    pub fn up_to(max_deltas: usize) -> Self {
+        DeltaPolicy {
+            max_deltas,
+            max_size_ratio: 0.5,
+        }
+    }
+}
+
+/// When the NockApp writes checkpoints.
+///
+/// The triggers combine: a checkpoint is written whenever any enabled trigger fires and there
+/// are events since the last one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CheckpointPolicy {
+    /// Save on this interval. `None` disables timed saves.
+    pub interval: Option<Duration>,
+    /// Save once this many events have been processed since the last checkpoint.
+    pub every_events: Option<u64>,
+    /// Save before exiting.
+    pub on_shutdown: bool,
+    /// Save when the kernel emits a `%save` effect.
+    pub on_effect: bool,
+}
+
+impl CheckpointPolicy {
+    /// Save on `interval`, before exiting, and on `%save`, which is the default behavior.
+    pub fn every(interval: Duration) -> Self {
+        CheckpointPolicy {
+            interval: Some(interval),
+            every_events: None,
+            on_shutdown: true,
+            on_effect: true,
+        }
+    }
+}
+
+/// How many full checkpoints to keep in `history/`, besides the live `0.chkjam` and `1.chkjam`.
+///
+/// Each full snapshot is linked into the history as `<event_num>.chkjam` when it is written,
+/// then the history is pruned. The rules combine, so a checkpoint is deleted when any of them
+/// says so. The live snapshots and the delta are never pruned. The default has no rules, and
+/// neither adds to nor prunes the history.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Keep at most this many historical checkpoints.
+    pub keep: Option<usize>,
+    /// Delete historical checkpoints older than this.
+    pub max_age: Option<Duration>,
+    /// Delete the oldest historical checkpoints until the rest take at most this many bytes.
+    pub max_bytes: Option<u64>,
+}
+
+impl RetentionPolicy {
+    /// Whether checkpoints are kept in the history at all
+    pub fn keeps_history(&self) -> bool {
+        self.keep.is_some() || self.max_age.is_some() || self.max_bytes.is_some()
+    }
+}
+
+/// A checkpoint file in `history/`
+#[derive(Clone, Debug)]
+pub struct HistoricalCheckpoint {
+    pub event_num: u64,
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+const HISTORY_DIR: &str = "history";
+
+/// Where historical checkpoints for the checkpoint directory `path` are kept
+pub fn history_dir(path: &Path) -> PathBuf {
+    path.join(HISTORY_DIR)
+}
+
+fn history_path(path: &Path, event_num: u64) -> PathBuf {
+    history_dir(path).join(format!("{:020}.chkjam", event_num))
+}
+
+/// The historical checkpoints for the checkpoint directory `path`, oldest first
+pub fn list_history(path: &Path) -> std::io::Result<Vec<HistoricalCheckpoint>> {
+    let dir = history_dir(path);
+    let mut checkpoints = Vec::new();
+    if !dir.exists() {
+        return Ok(checkpoints);
+    }
+    for entry in std::fs::read_dir(&dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let event_num = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".chkjam"))
+            .and_then(|event_num| event_num.parse::<u64>().ok());
+        let Some(event_num) = event_num else {
+            continue;
+        };
+        let metadata = entry.metadata()?;
+        checkpoints.push(HistoricalCheckpoint {
+            event_num,
+            path,
+            size: metadata.len(),
+            modified: metadata.modified()?,
+        });
+    }
+    checkpoints.sort_by_key(|c| c.event_num);
+    Ok(checkpoints)
+}
+
+/// Delete the historical checkpoints `policy` doesn't keep, returning the deleted ones.
+pub fn prune_history(
+    path: &Path,
+    policy: &RetentionPolicy,
+) -> std::io::Result<Vec<HistoricalCheckpoint>> {
+    let mut kept = list_history(path)?;
+    let mut pruned = Vec::new();
+    let now = SystemTime::now();
+    if let Some(max_age) = policy.max_age {
+        let (old, young): (Vec<_>, Vec<_>) = kept.into_iter().partition(|c| {
+            now.duration_since(c.modified)
+                .is_ok_and(|age| age > max_age)
+        });
+        pruned.extend(old);
+        kept = young;
+    }
+    if let Some(keep) = policy.keep {
+        let excess = kept.len().saturating_sub(keep);
+        pruned.extend(kept.drain(..excess));
+    }
+    if let Some(max_bytes) = policy.max_bytes {
+        let mut total: u64 = kept.iter().map(|c| c.size).sum();
+        while total > max_bytes && !kept.is_empty() {
+            let oldest = kept.remove(0);
+            total -= oldest.size;
+            pruned.push(oldest);
+        }
+    }
+    for checkpoint in &pruned {
+        debug!(
+            "Pruning historical checkpoint at event {}",
+            checkpoint.event_num
+        );
+        std::fs::remove_file(&checkpoint.path)?;
+    }
+    Ok(pruned)
+}
+
+/// Link `slot` into the history, falling back to a copy where hard links aren't supported.
+///
+/// A link stays valid when the slot is replaced later, since saves rename over the slot rather
+/// than writing into it.
+fn archive_checkpoint(path: &Path, slot: &Path, event_num: u64) -> std::io::Result<()> {
+    std::fs::create_dir_all(history_dir(path))?;
+    let archived = history_path(path, event_num);
+    if archived.exists() {
+        std::fs::remove_file(&archived)?;
+    }
+    if std::fs::hard_link(slot, &archived).is_err() {
+        std::fs::copy(slot, &archived)?;
+    }
+    Ok(())
+}
+
+/// The last full snapshot, kept in memory to diff later states against
+struct DeltaBase {
+    noun: NounSlab,
+    ker_hash: Hash,
+    checksum: Hash,
+    jam_len: usize,
+    deltas: usize,
+}
+
 /// State object which handles all NockApp saves and loads
+///
+/// Full snapshots alternate between `0.chkjam` and `1.chkjam`. Between full snapshots, each save
+/// writes `delta.chkjam`: the diff from the most recent full snapshot to the current state.
+/// Since every delta is against the base rather than the previous delta, only the latest one is
+/// kept, and a crash while writing it loses at most that delta.
 pub struct Saver<J = NockJammer> {
     path_0: PathBuf,
     path_1: PathBuf,
+    delta_path: PathBuf,
     save_to_next: WhichSnapshot,
     waiters: Vec<(u64, oneshot::Sender<()>)>,
     last_event_num: u64,
+    delta_policy: DeltaPolicy,
+    retention: RetentionPolicy,
+    base: Option<DeltaBase>,
+    compression: Compression,
     _phantom: std::marker::PhantomData<J>,
 }
 
@@ -77,19 +520,107 @@ impl<J> Saver<J> {
         futures::future::Either::Right(rx)
     }
 
+    /// The live checkpoint files, whether or not they've been written yet
+    pub fn paths(&self) -> Vec<PathBuf> {
+        vec![self.path_0.clone(), self.path_1.clone(), self.delta_path.clone()]
+    }
+
     /// Check if we need to save
     pub fn save_needed(&self, event_num: u64) -> bool {
         self.last_event_num < event_num
     }
+
+    /// The event number of the most recent checkpoint
+    pub fn last_event_num(&self) -> u64 {
+        self.last_event_num
+    }
+
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Set which historical checkpoints are kept, pruning the history right away.
+    pub fn set_retention_policy(&mut self, retention: RetentionPolicy) {
+        self.retention = retention;
+        if retention.keeps_history() {
+            self.prune_history();
+        }
+    }
+
+    fn checkpoint_dir(&self) -> &Path {
+        self.path_0
+            .parent()
+            .expect("checkpoint path has a parent directory")
+    }
+
+    fn prune_history(&self) {
+        if let Err(e) = prune_history(self.checkpoint_dir(), &self.retention) {
+            warn!("Failed to prune checkpoint history: {}", e);
+        }
+    }
+
+    pub fn set_delta_policy(&mut self, delta_policy: DeltaPolicy) {
+        self.delta_policy = delta_policy;
+        if delta_policy.max_deltas == 0 {
+            self.base = None;
+        }
+    }
 }
 
-impl<J: Jammer> Saver<J> {
+impl<J: Jammer + 'static> Saver<J> {
+    /// Cue a checkpoint, apply the delta written against it if there is one, and convert it.
+    /// Returns the checkpoint and the event number it was restored to.
+    async fn restore<C: Checkpoint>(
+        snapshot: Snapshot,
+        delta_path: &PathBuf,
+        metrics: Option<Arc<NockAppMetrics>>,
+    ) -> Result<(C, u64), CheckpointError> {
+        let (mut saveable, base_checksum) = snapshot.cue::<J>(metrics).await?;
+        trace!("After cueing snapshot");
+
+        if delta_path.exists() {
+            match JammedDelta::load_from_file(delta_path).await {
+                Ok(delta) if delta.applies_to(&saveable, &base_checksum) => {
+                    debug!(
+                        "Applying checkpoint delta at: {}, event_num: {}",
+                        delta_path.display(),
+                        delta.event_num
+                    );
+                    let delta_event_num = delta.event_num;
+                    // A bad delta only loses the events since the base, so fall back to the base
+                    if let Err(e) = saveable.apply_delta(delta) {
+                        error!(
+                            "checkpoint delta at {} (event_num {}) failed to apply: {}",
+                            delta_path.display(),
+                            delta_event_num,
+                            e
+                        );
+                    }
+                }
+                Ok(delta) => debug!(
+                    "Ignoring stale checkpoint delta at: {} (event_num {})",
+                    delta_path.display(),
+                    delta.event_num
+                ),
+                Err(e) => warn!(
+                    "checkpoint delta at {} failed to load: {}",
+                    delta_path.display(),
+                    e
+                ),
+            }
+        }
+
+        let event_num = saveable.event_num;
+        Ok((C::from_saveable(saveable)?, event_num))
+    }
+
     pub async fn try_load<C: Checkpoint>(
         path: &PathBuf,
         metrics: Option<Arc<NockAppMetrics>>,
     ) -> Result<(Self, Option<C>), CheckpointError> {
         let path_0 = path.join("0.chkjam");
         let path_1 = path.join("1.chkjam");
+        let delta_path = path.join("delta.chkjam");
         let waiters = Vec::new();
 
         // No snapshot to load
@@ -99,101 +630,154 @@ impl<J: Jammer> Saver<J> {
                 Self {
                     path_0,
                     path_1,
+                    delta_path,
                     save_to_next: WhichSnapshot::Snapshot0,
                     waiters,
                     last_event_num: 0,
+                    delta_policy: DeltaPolicy::default(),
+                    retention: RetentionPolicy::default(),
+                    base: None,
+                    compression: Compression::default(),
                     _phantom: std::marker::PhantomData,
                 },
                 None,
             ));
         }
 
-        let checkpoint_0 = match JammedCheckpointV1::load_from_file(&path_0).await {
-            Ok(c) => Ok(c),
-            Err(e_v1) => JammedCheckpointV0::load_from_file(&path_0)
-                .await
-                .map(|c0| JammedCheckpointV1::from(c0))
-                .and_then(|c| c.validate(&path_0).map(|_| c))
-                .map_err(|e_v0| CheckpointError::VersionsFailed {
-                    v1: Box::new(e_v1),
-                    v0: Box::new(e_v0),
-                }),
-        };
-
-        let checkpoint_1 = match JammedCheckpointV1::load_from_file(&path_1).await {
-            Ok(c) => Ok(c),
-            Err(e_v1) => JammedCheckpointV0::load_from_file(&path_1)
-                .await
-                .map(|c0| JammedCheckpointV1::from(c0))
-                .and_then(|c| c.validate(&path_1).map(|_| c))
-                .map_err(|e_v0| CheckpointError::VersionsFailed {
-                    v1: Box::new(e_v1),
-                    v0: Box::new(e_v0),
-                }),
-        };
+        // Try the newest checkpoint first. If it is corrupt, or fails to restore, roll back to
+        // the other one rather than refusing to boot.
+        let mut errors: [Option<CheckpointError>; 2] = [None, None];
+        let mut candidates = Vec::with_capacity(2);
+        for (index, (slot_path, slot)) in
+            [(&path_0, WhichSnapshot::Snapshot0), (&path_1, WhichSnapshot::Snapshot1)]
+                .into_iter()
+                .enumerate()
+        {
+            match Snapshot::load(slot_path).await {
+                Ok(c) => candidates.push((index, slot_path, slot, c)),
+                Err(e) => {
+                    if slot_path.exists() {
+                        error!("checkpoint at {} is corrupt: {}", slot_path.display(), e);
+                        keep_corrupt_copy(slot_path).await;
+                    } else {
+                        debug!("no checkpoint at {}", slot_path.display());
+                    }
+                    errors[index] = Some(e);
+                }
+            }
+        }
+        candidates.sort_by_key(|(index, _, _, c)| std::cmp::Reverse((c.event_num(), *index)));
 
-        let (jammed_checkpoint, save_to_next) = match (checkpoint_0, checkpoint_1) {
-            (Ok(c0), Ok(c1)) => {
-                if c0.event_num > c1.event_num {
-                    debug!(
-                        "Loading checkpoint at: {}, checksum: {}",
-                        path_0.display(),
-                        c0.checksum
-                    );
-                    (c0, WhichSnapshot::Snapshot1)
-                } else {
-                    debug!(
-                        "Loading checkpoint at: {}, checksum: {}",
-                        path_1.display(),
-                        c1.checksum
+        let mut restored = None;
+        for (index, slot_path, slot, snapshot) in candidates {
+            let event_num = snapshot.event_num();
+            debug!(
+                "Loading checkpoint at: {}, event_num: {}",
+                slot_path.display(),
+                event_num
+            );
+            match Self::restore::<C>(snapshot, &delta_path, metrics.clone()).await {
+                Ok((c, last_event_num)) => {
+                    if errors.iter().any(Option::is_some) {
+                        warn!(
+                            "Rolled back to checkpoint at {} (event_num {})",
+                            slot_path.display(),
+                            event_num
+                        );
+                    }
+                    restored = Some((c, last_event_num, slot.next()));
+                    break;
+                }
+                Err(e) => {
+                    error!(
+                        "checkpoint at {} (event_num {}) failed to restore: {}",
+                        slot_path.display(),
+                        event_num,
+                        e
                     );
-                    (c1, WhichSnapshot::Snapshot0)
+                    // A streamed checkpoint is only read past its header when it is restored
+                    if matches!(
+                        e,
+                        CheckpointError::IOError(_)
+                            | CheckpointError::CueError(_)
+                            | CheckpointError::InvalidChecksum(_)
+                    ) {
+                        keep_corrupt_copy(slot_path).await;
+                    }
+                    errors[index] = Some(e);
                 }
             }
-            (Ok(c0), Err(e)) => {
-                warn!("checkpoint at {} failed to load: {}", path_1.display(), e);
-                debug!(
-                    "Loading checkpoint at: {}, checksum: {}",
-                    path_0.display(),
-                    c0.checksum
-                );
-                (c0, WhichSnapshot::Snapshot1)
-            }
-            (Err(e), Ok(c1)) => {
-                warn!("checkpoint at {} failed to load: {}", path_0.display(), e);
-                debug!(
-                    "Loading checkpoint at: {}, checksum: {}",
-                    path_1.display(),
-                    c1.checksum
-                );
-                (c1, WhichSnapshot::Snapshot0)
-            }
-            (Err(e0), Err(e1)) => {
-                error!("checkpoint at {} failed to load: {}", path_0.display(), e0);
-                error!("checkpoint at {} failed to load: {}", path_1.display(), e1);
-                return Err(CheckpointError::BothCheckpointsFailed(
-                    Box::new(e0),
-                    Box::new(e1),
-                ));
-            }
+        }
+
+        let Some((c, last_event_num, save_to_next)) = restored else {
+            error!("No usable checkpoint in {}", path.display());
+            // Each slot that didn't restore recorded why, but don't count on it
+            return Err(match errors {
+                [Some(e0), Some(e1)] => {
+                    CheckpointError::BothCheckpointsFailed(Box::new(e0), Box::new(e1))
+                }
+                [Some(e), None] | [None, Some(e)] => e,
+                [None, None] => CheckpointError::NoUsableCheckpoint(path.clone()),
+            });
         };
-        let last_event_num = jammed_checkpoint.event_num;
-        let saveable = SaveableCheckpoint::from_jammed_checkpoint::<J>(jammed_checkpoint, metrics)?;
-        trace!("After from_jammed_checkpoint");
-        let c = C::from_saveable(saveable)?;
+        // The loaded state may already include a delta, so it can't serve as a base. The first
+        // save after loading is always a full snapshot.
         Ok((
             Self {
                 path_0,
                 path_1,
+                delta_path,
                 save_to_next,
                 waiters,
                 last_event_num,
+                delta_policy: DeltaPolicy::default(),
+                retention: RetentionPolicy::default(),
+                base: None,
+                compression: Compression::default(),
                 _phantom: std::marker::PhantomData,
             },
             Some(c),
         ))
     }
 
+    /// Load a single checkpoint file, such as one from the checkpoint history, ignoring any delta.
+    pub async fn load_file<C: Checkpoint>(
+        path: &PathBuf,
+        metrics: Option<Arc<NockAppMetrics>>,
+    ) -> Result<C, CheckpointError> {
+        let (saveable, _) = Snapshot::load(path).await?.cue::<J>(metrics).await?;
+        C::from_saveable(saveable)
+    }
+
+    /// Load the newest usable checkpoint in `path` without writing anything, for readers that
+    /// share the directory with the process saving to it. Returns `None` if there is no
+    /// checkpoint yet.
+    pub async fn load_latest<C: Checkpoint>(
+        path: &Path,
+        metrics: Option<Arc<NockAppMetrics>>,
+    ) -> Result<Option<C>, CheckpointError> {
+        let delta_path = path.join("delta.chkjam");
+        let mut candidates = Vec::with_capacity(2);
+        let mut last_error = None;
+        for slot_path in [path.join("0.chkjam"), path.join("1.chkjam")] {
+            if !slot_path.exists() {
+                continue;
+            }
+            match Snapshot::load(&slot_path).await {
+                Ok(c) => candidates.push(c),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        candidates.sort_by_key(|c| std::cmp::Reverse(c.event_num()));
+        for snapshot in candidates {
+            match Self::restore::<C>(snapshot, &delta_path, metrics.clone()).await {
+                Ok((c, _)) => return Ok(Some(c)),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        last_error.map_or(Ok(None), Err)
+    }
+
     #[tracing::instrument(skip_all)]
     pub async fn save<C: Checkpoint>(
         &mut self,
@@ -204,16 +788,161 @@ impl<J: Jammer> Saver<J> {
         trace!("Saving checkpoint at event_num {}", event_num);
         let saveable = checkpoint.to_saveable();
         trace!("Converted checkpoint to saveable");
-        let jammed = saveable.to_jammed_checkpoint::<J>(metrics);
-        trace!("Converted saveable to jammed");
+        // The delta is found before awaiting, since the borrowed checkpoint isn't Sync
+        let delta = self.delta(&saveable);
+        match delta {
+            Some((jammed, edits)) => self.save_delta(jammed, edits).await?,
+            None => self.save_full(saveable, metrics).await?,
+        }
+        self.notify_waiters(event_num);
+        Ok(())
+    }
+
+    /// Save `point`, or just record it if it is a snapshot of a persistent loom, which the
+    /// kernel has already written.
+    pub(crate) async fn save_point<C: Checkpoint>(
+        &mut self,
+        point: SavePoint<C>,
+        metrics: Arc<NockAppMetrics>,
+    ) -> Result<(), CheckpointError> {
+        match point {
+            SavePoint::Checkpoint(checkpoint) => self.save(checkpoint, metrics).await,
+            SavePoint::Snapshot(event_num) => {
+                trace!("Recorded persistent loom snapshot at event_num {}", event_num);
+                self.notify_waiters(event_num);
+                Ok(())
+            }
+        }
+    }
+
+    /// Like [`Saver::save_point`], but writes over both full snapshots and deletes the delta and
+    /// the checkpoint history, so no checkpoint file holds an earlier state.
+    pub(crate) async fn overwrite_point<C: Checkpoint>(
+        &mut self,
+        point: SavePoint<C>,
+        metrics: Arc<NockAppMetrics>,
+    ) -> Result<(), CheckpointError> {
+        let SavePoint::Checkpoint(checkpoint) = point else {
+            return self.save_point(point, metrics).await;
+        };
+        let (saveable, checksum, jam_len) = StreamedCheckpoint::save_to_file::<J>(
+            checkpoint.to_saveable(),
+            &self.path_0,
+            self.compression,
+            metrics,
+        )
+        .await?;
+        let (path_0, path_1) = (self.path_0.clone(), self.path_1.clone());
+        tokio::task::spawn_blocking(move || copy_checkpoint_file(&path_0, &path_1)).await??;
+        if self.delta_path.exists() {
+            tokio::fs::remove_file(&self.delta_path).await?;
+        }
+        let history = history_dir(self.checkpoint_dir());
+        if history.exists() {
+            tokio::fs::remove_dir_all(&history).await?;
+        }
+        debug!(
+            "Overwrote both checkpoints at event_num {}",
+            saveable.event_num
+        );
+        let event_num = saveable.event_num;
+        self.base = (self.delta_policy.max_deltas > 0).then(|| DeltaBase {
+            ker_hash: saveable.ker_hash,
+            checksum,
+            jam_len,
+            deltas: 0,
+            noun: saveable.noun,
+        });
+        self.notify_waiters(event_num);
+        Ok(())
+    }
+
+    /// The delta against the base and how many edits it makes, if the policy allows one. `None`
+    /// if a full snapshot is needed instead.
+    fn delta(&self, saveable: &SaveableCheckpoint) -> Option<(JammedDelta, usize)> {
+        let base = self.base.as_ref()?;
+        if base.deltas >= self.delta_policy.max_deltas || base.ker_hash != saveable.ker_hash {
+            return None;
+        }
+        let delta = diff(unsafe { *base.noun.root() }, unsafe {
+            *saveable.noun.root()
+        });
+        let mut delta_slab: NounSlab = NounSlab::new();
+        let encoded = delta.to_noun(&mut delta_slab);
+        let jam = J::jam(encoded);
+        if jam.len() as f64 > base.jam_len as f64 * self.delta_policy.max_size_ratio {
+            debug!(
+                "Compacting checkpoint: delta is {} bytes, base is {} bytes",
+                jam.len(),
+                base.jam_len
+            );
+            return None;
+        }
+        let jammed = JammedDelta::new(
+            saveable.ker_hash,
+            base.checksum,
+            saveable.event_num,
+            JammedNoun(jam),
+        );
+        Some((jammed, delta.edits.len()))
+    }
+
+    /// Write a delta from [`Self::delta`] against the base
+    async fn save_delta(
+        &mut self,
+        jammed: JammedDelta,
+        edits: usize,
+    ) -> Result<(), CheckpointError> {
+        jammed
+            .save_to_file(&self.delta_path, self.compression)
+            .await?;
+        if let Some(base) = &mut self.base {
+            base.deltas += 1;
+        }
+        debug!(
+            "Saved checkpoint delta with {} edits to file: {}",
+            edits,
+            self.delta_path.display()
+        );
+        Ok(())
+    }
+
+    async fn save_full(
+        &mut self,
+        saveable: SaveableCheckpoint,
+        metrics: Arc<NockAppMetrics>,
+    ) -> Result<(), CheckpointError> {
         let path = self.next_path();
-        jammed.save_to_file(&path).await?;
+        let (saveable, checksum, jam_len) =
+            StreamedCheckpoint::save_to_file::<J>(saveable, &path, self.compression, metrics)
+                .await?;
         self.save_to_next = self.save_to_next.next();
+        if self.retention.keeps_history() {
+            if let Err(e) = archive_checkpoint(self.checkpoint_dir(), &path, saveable.event_num) {
+                warn!("Failed to add checkpoint to history: {}", e);
+            }
+            self.prune_history();
+        }
+        // The old delta is against the previous base
+        if self.delta_path.exists() {
+            tokio::fs::remove_file(&self.delta_path).await?;
+        }
 
         debug!(
             "Saved checkpoint to file: {}",
             &path.as_os_str().to_str().unwrap()
         );
+        self.base = (self.delta_policy.max_deltas > 0).then(|| DeltaBase {
+            ker_hash: saveable.ker_hash,
+            checksum,
+            jam_len,
+            deltas: 0,
+            noun: saveable.noun,
+        });
+        Ok(())
+    }
+
+    fn notify_waiters(&mut self, event_num: u64) {
         let mut still_waiting = Vec::new();
         for (waiting_event_num, waiter) in self.waiters.drain(..) {
             if waiting_event_num <= event_num {
@@ -225,8 +954,6 @@ impl<J: Jammer> Saver<J> {
 
         self.last_event_num = event_num;
         self.waiters = still_waiting;
-
-        Ok(())
     }
 }
 
@@ -246,17 +973,8 @@ pub struct SaveableCheckpoint {
 }
 
 impl SaveableCheckpoint {
-    #[tracing::instrument(skip(self, metrics))]
-    fn to_jammed_checkpoint<J: Jammer>(self, metrics: Arc<NockAppMetrics>) -> JammedCheckpointV1 {
-        let jam_start = Instant::now();
-        let jam = JammedNoun(self.noun.coerce_jammer::<J>().jam());
-        metrics.save_jam_time.add_timing(&jam_start.elapsed());
-
-        JammedCheckpointV1::new(self.ker_hash, self.event_num, jam)
-    }
-
     fn from_jammed_checkpoint<'a, J: Jammer>(
-        jammed: JammedCheckpointV1,
+        jammed: JammedCheckpoint,
         metrics: Option<Arc<NockAppMetrics>>,
     ) -> Result<Self, CheckpointError> {
         let mut slab = NounSlab::new();
@@ -264,12 +982,24 @@ impl SaveableCheckpoint {
         let root = slab.cue_into(jammed.jam.0)?;
         metrics.map(|m| m.load_cue_time.add_timing(&cue_start.elapsed()));
         slab.set_root(root);
+        if !jammed.mugs.is_empty() && !slab.restore_mugs(&jammed.mugs) {
+            warn!("Checkpoint mug cache does not match its noun, mugs will be recomputed");
+        }
         Ok(Self {
             ker_hash: jammed.ker_hash,
             event_num: jammed.event_num,
             noun: slab,
         })
     }
+
+    fn apply_delta(&mut self, delta: JammedDelta) -> Result<(), CheckpointError> {
+        let encoded = self.noun.cue_into(delta.jam.0)?;
+        let old = unsafe { *self.noun.root() };
+        let root = patch(&mut self.noun, old, encoded)?;
+        self.noun.set_root(root);
+        self.event_num = delta.event_num;
+        Ok(())
+    }
 }
 
 impl Checkpoint for SaveableCheckpoint {
@@ -304,18 +1034,436 @@ pub enum CheckpointError {
     FromNounError(#[from] nockvm::jets::cold::FromNounError),
     #[error("Both checkpoints failed: {0}, {1}")]
     BothCheckpointsFailed(Box<CheckpointError>, Box<CheckpointError>),
+    #[error("No usable checkpoint in {0}")]
+    NoUsableCheckpoint(PathBuf),
     #[error("Sword interpreter error")]
     SwordInterpreterError,
     #[error("Cue error: {0}")]
     CueError(#[from] crate::noun::slab::CueError),
-    #[error("Loading at version 1 failed: {v1}\nLoading at version 0 failed: {v0}")]
+    #[error("Delta error: {0}")]
+    DeltaError(#[from] crate::noun::diff::DiffError),
+    #[error("Checkpoint task failed: {0}")]
+    TaskFailed(#[from] tokio::task::JoinError),
+    #[error("Loading at version 2 failed: {v2}\nLoading at version 1 failed: {v1}\nLoading at version 0 failed: {v0}")]
     VersionsFailed {
+        v2: Box<CheckpointError>,
         v1: Box<CheckpointError>,
         v0: Box<CheckpointError>,
     },
 }
 
-pub type JammedCheckpoint = JammedCheckpointV1;
+pub type JammedCheckpoint = JammedCheckpointV2;
+
+/// Mugs read from a [`StreamedCheckpoint`] at a time
+const MUG_CHUNK: usize = 4096;
+
+/// A full snapshot at version 3, jammed straight into its file and cued straight out of it, so
+/// its jam is never held in memory whole.
+///
+/// The file is this header (`%chkstm` magic bytes, the version, the kernel hash and the event
+/// number, little-endian), then the jam in the frames of a [`FrameWriter`], then the number of
+/// mugs and the mugs as in [`JammedCheckpointV2`], and last a checksum of the event number, the
+/// jam, its length and the mugs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamedCheckpoint {
+    /// Hash of the boot kernel
+    pub ker_hash: Hash,
+    /// Event number
+    pub event_num: u64,
+}
+
+impl StreamedCheckpoint {
+    /// Jam `saveable` into `writer`, returning the checksum and the length of the jam.
+    fn write<J: Jammer>(
+        saveable: &SaveableCheckpoint,
+        writer: &mut dyn Write,
+    ) -> Result<(Hash, usize), CheckpointError> {
+        writer.write_all(&STREAM_MAGIC_BYTES.to_le_bytes())?;
+        writer.write_all(&SNAPSHOT_VERSION_3.to_le_bytes())?;
+        writer.write_all(saveable.ker_hash.as_bytes())?;
+        writer.write_all(&saveable.event_num.to_le_bytes())?;
+
+        let mut hasher = Hasher::new();
+        hasher.update(&saveable.event_num.to_le_bytes());
+        let mut frames = FrameWriter::new(&mut *writer, hasher);
+        let (jam_len, mugs) =
+            J::jam_with_mugs_to_writer(unsafe { *saveable.noun.root() }, &mut frames)?;
+        let mut hasher = frames.finish()?;
+        hasher.update(&(jam_len as u64).to_le_bytes());
+
+        hasher.update(&(mugs.len() as u64).to_le_bytes());
+        writer.write_all(&(mugs.len() as u64).to_le_bytes())?;
+        for chunk in mugs.chunks(MUG_CHUNK) {
+            let bytes: Vec<u8> = chunk.iter().flat_map(|mug| mug.to_le_bytes()).collect();
+            hasher.update(&bytes);
+            writer.write_all(&bytes)?;
+        }
+        let checksum = hasher.finalize();
+        writer.write_all(checksum.as_bytes())?;
+        Ok((checksum, jam_len))
+    }
+
+    /// Read the header of a checkpoint, or `None` if it is at an earlier version.
+    fn read_header(reader: &mut dyn Read, path: &Path) -> Result<Option<Self>, CheckpointError> {
+        let magic = match read_array(reader) {
+            Ok(magic) => u64::from_le_bytes(magic),
+            // Too short to be a checkpoint at this version
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if magic != STREAM_MAGIC_BYTES {
+            return Ok(None);
+        }
+        if u32::from_le_bytes(read_array(reader)?) != SNAPSHOT_VERSION_3 {
+            return Err(CheckpointError::InvalidVersion(path.to_path_buf()));
+        }
+        Ok(Some(StreamedCheckpoint {
+            ker_hash: Hash::from(read_array::<32, _>(reader)?),
+            event_num: u64::from_le_bytes(read_array(reader)?),
+        }))
+    }
+
+    /// Cue the rest of a checkpoint after its header, returning it with its checksum once the
+    /// checksum matches.
+    fn read_body(
+        self,
+        reader: &mut dyn Read,
+        path: &Path,
+    ) -> Result<(SaveableCheckpoint, Hash), CheckpointError> {
+        let mut hasher = Hasher::new();
+        hasher.update(&self.event_num.to_le_bytes());
+        let mut frames = FrameReader::new(&mut *reader, hasher);
+        let mut slab: NounSlab = NounSlab::new();
+        let root = slab.cue_from_reader(&mut frames)?;
+        // The cue stops at the last bit of the jam, so the padding after it is still unread
+        let (mut hasher, jam_len) = frames.finish()?;
+        hasher.update(&(jam_len as u64).to_le_bytes());
+
+        let mug_count = u64::from_le_bytes(read_array(reader)?);
+        hasher.update(&mug_count.to_le_bytes());
+        // Grow the mugs as they are read rather than trusting the count to allocate
+        let mut mugs = Vec::new();
+        let mut chunk = vec![0; MUG_CHUNK * 4];
+        let mut left = mug_count;
+        while left > 0 {
+            let bytes = &mut chunk[..left.min(MUG_CHUNK as u64) as usize * 4];
+            reader.read_exact(bytes)?;
+            hasher.update(bytes);
+            mugs.extend(
+                bytes
+                    .chunks_exact(4)
+                    .map(|mug| u32::from_le_bytes(mug.try_into().unwrap())),
+            );
+            left -= bytes.len() as u64 / 4;
+        }
+        let checksum = hasher.finalize();
+        if Hash::from(read_array::<32, _>(reader)?) != checksum {
+            return Err(CheckpointError::InvalidChecksum(path.to_path_buf()));
+        }
+
+        slab.set_root(root);
+        if !mugs.is_empty() && !slab.restore_mugs(&mugs) {
+            warn!("Checkpoint mug cache does not match its noun, mugs will be recomputed");
+        }
+        let saveable = SaveableCheckpoint {
+            ker_hash: self.ker_hash,
+            event_num: self.event_num,
+            noun: slab,
+        };
+        Ok((saveable, checksum))
+    }
+
+    /// Read the header of the checkpoint at `path`, or `None` if it is at an earlier version.
+    async fn load_header(path: &PathBuf) -> Result<Option<Self>, CheckpointError> {
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || {
+            Self::read_header(&mut open_checkpoint_file(&path)?, &path)
+        })
+        .await?
+    }
+
+    /// Cue the checkpoint at `path`, returning it with its checksum.
+    #[tracing::instrument(skip(metrics))]
+    async fn load(
+        path: &PathBuf,
+        metrics: Option<Arc<NockAppMetrics>>,
+    ) -> Result<(SaveableCheckpoint, Hash), CheckpointError> {
+        debug!("Loading streamed checkpoint from file: {}", path.display());
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || {
+            let cue_start = Instant::now();
+            let mut reader = open_checkpoint_file(&path)?;
+            let header = Self::read_header(&mut reader, &path)?
+                .ok_or_else(|| CheckpointError::InvalidVersion(path.clone()))?;
+            let loaded = header.read_body(&mut reader, &path)?;
+            if let Some(metrics) = metrics {
+                metrics.load_cue_time.add_timing(&cue_start.elapsed());
+            }
+            Ok(loaded)
+        })
+        .await?
+    }
+
+    /// Jam `saveable` straight into the file at `path`, compressing it as configured. Returns
+    /// `saveable` back with the checksum and the length of the jam.
+    #[tracing::instrument(skip(saveable, metrics))]
+    async fn save_to_file<J: Jammer + 'static>(
+        saveable: SaveableCheckpoint,
+        path: &PathBuf,
+        compression: Compression,
+        metrics: Arc<NockAppMetrics>,
+    ) -> Result<(SaveableCheckpoint, Hash, usize), CheckpointError> {
+        trace!("Saving streamed checkpoint to file: {}", path.display());
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || {
+            let jam_start = Instant::now();
+            let (checksum, jam_len) = write_checkpoint_file_with(&path, compression, |writer| {
+                Self::write::<J>(&saveable, writer)
+            })?;
+            metrics.save_jam_time.add_timing(&jam_start.elapsed());
+            Ok((saveable, checksum, jam_len))
+        })
+        .await?
+    }
+}
+
+/// A full snapshot on disk, read as far as it takes to order it against the other one
+enum Snapshot {
+    /// At version 3, of which only the header has been read
+    Streamed(PathBuf, StreamedCheckpoint),
+    /// At an earlier version, read whole
+    Jammed(JammedCheckpoint),
+}
+
+impl Snapshot {
+    async fn load(path: &PathBuf) -> Result<Self, CheckpointError> {
+        match StreamedCheckpoint::load_header(path).await? {
+            Some(header) => Ok(Snapshot::Streamed(path.clone(), header)),
+            None => Ok(Snapshot::Jammed(
+                JammedCheckpoint::load_any_version(path).await?,
+            )),
+        }
+    }
+
+    fn event_num(&self) -> u64 {
+        match self {
+            Snapshot::Streamed(_, header) => header.event_num,
+            Snapshot::Jammed(jammed) => jammed.event_num,
+        }
+    }
+
+    /// Cue the snapshot, returning it with its checksum
+    async fn cue<J: Jammer>(
+        self,
+        metrics: Option<Arc<NockAppMetrics>>,
+    ) -> Result<(SaveableCheckpoint, Hash), CheckpointError> {
+        match self {
+            Snapshot::Streamed(path, _) => StreamedCheckpoint::load(&path, metrics).await,
+            Snapshot::Jammed(jammed) => {
+                let checksum = jammed.checksum;
+                let saveable = SaveableCheckpoint::from_jammed_checkpoint::<J>(jammed, metrics)?;
+                Ok((saveable, checksum))
+            }
+        }
+    }
+}
+
+/// The edits from a full snapshot to a later state, as a jammed `(list [axis=@ new=*])`
+#[derive(Encode, Decode, PartialEq, Debug)]
+pub struct JammedDelta {
+    /// Magic bytes to identify delta format
+    pub magic_bytes: u64,
+    /// Version of delta
+    pub version: u32,
+    /// Hash of the boot kernel
+    #[bincode(with_serde)]
+    pub ker_hash: Hash,
+    /// Checksum of the full snapshot this delta applies to
+    #[bincode(with_serde)]
+    pub base_checksum: Hash,
+    /// Checksum derived from base_checksum, event_num, and jam
+    #[bincode(with_serde)]
+    pub checksum: Hash,
+    /// Event number after applying the delta
+    pub event_num: u64,
+    /// Jammed edit list
+    pub jam: JammedNoun,
+}
+
+impl JammedDelta {
+    pub fn new(ker_hash: Hash, base_checksum: Hash, event_num: u64, jam: JammedNoun) -> Self {
+        let checksum = Self::checksum(&base_checksum, event_num, &jam.0);
+        Self {
+            magic_bytes: DELTA_MAGIC_BYTES,
+            version: DELTA_VERSION_1,
+            ker_hash,
+            base_checksum,
+            checksum,
+            event_num,
+            jam,
+        }
+    }
+
+    pub fn validate(&self, path: &Path) -> Result<(), CheckpointError> {
+        if self.magic_bytes != DELTA_MAGIC_BYTES || self.version != DELTA_VERSION_1 {
+            Err(CheckpointError::InvalidVersion(path.to_path_buf()))
+        } else if self.checksum != Self::checksum(&self.base_checksum, self.event_num, &self.jam.0)
+        {
+            Err(CheckpointError::InvalidChecksum(path.to_path_buf()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn applies_to(&self, base: &SaveableCheckpoint, base_checksum: &Hash) -> bool {
+        self.base_checksum == *base_checksum
+            && self.ker_hash == base.ker_hash
+            && self.event_num > base.event_num
+    }
+
+    fn checksum(base_checksum: &Hash, event_num: u64, jam: &Bytes) -> Hash {
+        let mut hasher = Hasher::new();
+        hasher.update(base_checksum.as_bytes());
+        hasher.update(&event_num.to_le_bytes());
+        hasher.update(&jam.len().to_le_bytes());
+        hasher.update(jam);
+        hasher.finalize()
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn load_from_file(path: &PathBuf) -> Result<Self, CheckpointError> {
+        let bytes = read_checkpoint_file(path).await?;
+        let config = bincode::config::standard();
+        let (delta, _) = bincode::decode_from_slice::<Self, Configuration>(&bytes, config)?;
+        delta.validate(path)?;
+        Ok(delta)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn save_to_file(
+        &self,
+        path: &PathBuf,
+        compression: Compression,
+    ) -> Result<(), CheckpointError> {
+        let bytes = encode_to_vec(self, config::standard())?;
+        write_checkpoint_file(path, bytes, compression).await
+    }
+}
+
+#[derive(Encode, Decode, PartialEq, Debug)]
+pub struct JammedCheckpointV2 {
+    /// Magic bytes to identify checkpoint format
+    pub magic_bytes: u64,
+    /// Version of checkpoint
+    pub version: u32,
+    /// Hash of the boot kernel
+    #[bincode(with_serde)]
+    pub ker_hash: Hash,
+    /// Checksum derived from event_num, jam, and mugs (the entries below)
+    #[bincode(with_serde)]
+    pub checksum: Hash,
+    /// Event number
+    pub event_num: u64,
+    /// Jammed noun of [kernel_state cold_state]
+    pub jam: JammedNoun,
+    /// Mugs of the cells and indirect atoms in `jam`, in the order jam writes them. Empty if the
+    /// jammer doesn't record mugs.
+    pub mugs: Vec<u32>,
+}
+
+impl JammedCheckpointV2 {
+    pub fn new(ker_hash: Hash, event_num: u64, jam: JammedNoun, mugs: Vec<u32>) -> Self {
+        let checksum = Self::checksum(event_num, &jam.0, &mugs);
+        Self {
+            magic_bytes: JAM_MAGIC_BYTES,
+            version: SNAPSHOT_VERSION_2,
+            ker_hash,
+            checksum,
+            event_num,
+            jam,
+            mugs,
+        }
+    }
+
+    pub fn validate(&self, path: &Path) -> Result<(), CheckpointError> {
+        if self.version != SNAPSHOT_VERSION_2 {
+            Err(CheckpointError::InvalidVersion(path.to_path_buf()))
+        } else if self.checksum != Self::checksum(self.event_num, &self.jam.0, &self.mugs) {
+            Err(CheckpointError::InvalidChecksum(path.to_path_buf()))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub fn encode(&self) -> Result<Vec<u8>, bincode::error::EncodeError> {
+        encode_to_vec(self, config::standard())
+    }
+
+    fn checksum(event_num: u64, jam: &Bytes, mugs: &[u32]) -> Hash {
+        let mut hasher = Hasher::new();
+        hasher.update(&event_num.to_le_bytes());
+        hasher.update(&jam.len().to_le_bytes());
+        hasher.update(jam);
+        hasher.update(&mugs.len().to_le_bytes());
+        for mug in mugs {
+            hasher.update(&mug.to_le_bytes());
+        }
+        hasher.finalize()
+    }
+
+    /// Load a checkpoint saved at this or any earlier version.
+    #[tracing::instrument(skip_all)]
+    async fn load_any_version(path: &PathBuf) -> Result<Self, CheckpointError> {
+        debug!("Loading jammed checkpoint from file: {}", path.display());
+        // Read and decompress once, then decode at each version in turn
+        let bytes = read_checkpoint_file(path).await?;
+        let e_v2 = match Self::decode(&bytes, path) {
+            Ok(c) => return Ok(c),
+            Err(e) => e,
+        };
+        let e_v1 = match JammedCheckpointV1::decode(&bytes, path) {
+            Ok(c) => return Ok(Self::from(c)),
+            Err(e) => e,
+        };
+        JammedCheckpointV0::decode(&bytes, path)
+            .map(JammedCheckpointV1::from)
+            .and_then(|c| c.validate(path).map(|_| Self::from(c)))
+            .map_err(|e_v0| CheckpointError::VersionsFailed {
+                v2: Box::new(e_v2),
+                v1: Box::new(e_v1),
+                v0: Box::new(e_v0),
+            })
+    }
+
+    /// Decode a checkpoint read from `path` at this version.
+    fn decode(bytes: &[u8], path: &Path) -> Result<Self, CheckpointError> {
+        let config = bincode::config::standard();
+        let (checkpoint, _) = bincode::decode_from_slice::<Self, Configuration>(bytes, config)?;
+        checkpoint.validate(path)?;
+        Ok(checkpoint)
+    }
+
+    #[tracing::instrument(skip(self))]
+    #[allow(dead_code)] // Preserving this for posterity
+    async fn save_to_file(
+        &self,
+        path: &PathBuf,
+        compression: Compression,
+    ) -> Result<(), CheckpointError> {
+        let bytes = self.encode()?;
+        trace!("Saving jammed checkpoint to file: {}", path.display());
+        write_checkpoint_file(path, bytes, compression).await
+    }
+}
+
+impl From<JammedCheckpointV1> for JammedCheckpointV2 {
+    /// Version 1 checkpoints have no mug cache. The checksum is recomputed for the new layout
+    /// since the old one was validated on load.
+    fn from(v1: JammedCheckpointV1) -> Self {
+        JammedCheckpointV2::new(v1.ker_hash, v1.event_num, v1.jam, Vec::new())
+    }
+}
 
 #[derive(Encode, Decode, PartialEq, Debug)]
 pub struct JammedCheckpointV1 {
@@ -375,20 +1523,16 @@ impl JammedCheckpointV1 {
         hasher.finalize()
     }
 
-    #[tracing::instrument(skip_all)]
-    async fn load_from_file(path: &PathBuf) -> Result<Self, CheckpointError> {
-        debug!(
-            "Loading jammed checkpoint from file: {}",
-            path.as_os_str().to_str().unwrap()
-        );
-        let bytes = tokio::fs::read(path).await?;
+    /// Decode a checkpoint read from `path` at this version.
+    fn decode(bytes: &[u8], path: &PathBuf) -> Result<Self, CheckpointError> {
         let config = bincode::config::standard();
-        let (checkpoint, _) = bincode::decode_from_slice::<Self, Configuration>(&bytes, config)?;
+        let (checkpoint, _) = bincode::decode_from_slice::<Self, Configuration>(bytes, config)?;
         checkpoint.validate(path)?;
         Ok(checkpoint)
     }
 
     #[tracing::instrument(skip(self))]
+    #[allow(dead_code)] // Preserving this for posterity
     async fn save_to_file(&self, path: &PathBuf) -> Result<(), CheckpointError> {
         let bytes = self.encode()?;
         trace!("Saving jammed checkpoint to file: {}", path.display());
@@ -397,7 +1541,7 @@ impl JammedCheckpointV1 {
     }
 }
 
-impl From<JammedCheckpointV0> for JammedCheckpoint {
+impl From<JammedCheckpointV0> for JammedCheckpointV1 {
     fn from(v0: JammedCheckpointV0) -> Self {
         JammedCheckpointV1 {
             magic_bytes: v0.magic_bytes,
@@ -469,15 +1613,10 @@ impl JammedCheckpointV0 {
         hasher.finalize()
     }
 
-    #[tracing::instrument(skip_all)]
-    async fn load_from_file(path: &PathBuf) -> Result<Self, CheckpointError> {
-        debug!(
-            "Loading jammed checkpoint from file: {}",
-            path.as_os_str().to_str().unwrap()
-        );
-        let bytes = tokio::fs::read(path).await?;
+    /// Decode a checkpoint read from `path` at this version.
+    fn decode(bytes: &[u8], path: &PathBuf) -> Result<Self, CheckpointError> {
         let config = bincode::config::standard();
-        let (checkpoint, _) = bincode::decode_from_slice::<Self, Configuration>(&bytes, config)?;
+        let (checkpoint, _) = bincode::decode_from_slice::<Self, Configuration>(bytes, config)?;
         checkpoint.validate(path)?;
         Ok(checkpoint)
     }
@@ -512,3 +1651,165 @@ mod tests {
     }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::{D, T};
+
+    use super::*;
+
+    fn jam() -> JammedNoun {
+        JammedNoun::new(Bytes::from(vec![0xab; 4096]))
+    }
+
+    /// A checkpoint whose jam takes many frames
+    fn saveable() -> SaveableCheckpoint {
+        let mut slab = NounSlab::new();
+        let mut list = D(0);
+        for i in 0..100_000 {
+            list = T(&mut slab, &[D(i), list]);
+        }
+        slab.set_root(list);
+        SaveableCheckpoint {
+            ker_hash: blake3::hash(b"kernel"),
+            event_num: 9,
+            noun: slab,
+        }
+    }
+
+    fn metrics() -> Arc<NockAppMetrics> {
+        Arc::new(
+            NockAppMetrics::register(gnort::global_metrics_registry())
+                .expect("Failed to register metrics!"),
+        )
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_streamed_round_trip() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("0.chkjam");
+        let expected = saveable().noun.jam();
+
+        for (compression, magic) in [
+            (Compression::None, STREAM_MAGIC_BYTES),
+            (Compression::Zstd(3), ZSTD_MAGIC_BYTES),
+        ] {
+            let (_, checksum, jam_len) = StreamedCheckpoint::save_to_file::<NockJammer>(
+                saveable(),
+                &path,
+                compression,
+                metrics(),
+            )
+            .await
+            .expect("save");
+            assert_eq!(jam_len, expected.len());
+            assert_eq!(
+                std::fs::read(&path).expect("read")[..8],
+                magic.to_le_bytes()
+            );
+
+            let snapshot = Snapshot::load(&path).await.expect("load header");
+            assert_eq!(snapshot.event_num(), 9);
+            let (loaded, loaded_checksum) = snapshot.cue::<NockJammer>(None).await.expect("cue");
+            assert_eq!(loaded_checksum, checksum);
+            assert_eq!(loaded.ker_hash, blake3::hash(b"kernel"));
+            assert_eq!(loaded.noun.jam(), expected);
+        }
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_streamed_corrupt() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("0.chkjam");
+        StreamedCheckpoint::save_to_file::<NockJammer>(
+            saveable(),
+            &path,
+            Compression::None,
+            metrics(),
+        )
+        .await
+        .expect("save");
+
+        // Flip a bit in the jam, past the header and the first frame length
+        let mut bytes = std::fs::read(&path).expect("read");
+        bytes[100] ^= 1;
+        std::fs::write(&path, &bytes).expect("write");
+        let snapshot = Snapshot::load(&path).await.expect("load header");
+        assert!(matches!(
+            snapshot.cue::<NockJammer>(None).await,
+            Err(CheckpointError::InvalidChecksum(_) | CheckpointError::CueError(_))
+        ));
+
+        // Cut off the checksum
+        bytes[100] ^= 1;
+        bytes.truncate(bytes.len() - 16);
+        std::fs::write(&path, &bytes).expect("write");
+        let snapshot = Snapshot::load(&path).await.expect("load header");
+        assert!(matches!(
+            snapshot.cue::<NockJammer>(None).await,
+            Err(CheckpointError::IOError(_))
+        ));
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_compressed_round_trip() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("0.chkjam");
+        let checkpoint = JammedCheckpoint::new(blake3::hash(b"kernel"), 7, jam(), vec![1, 2]);
+        let encoded = checkpoint.encode().expect("encode");
+
+        write_checkpoint_file(&path, encoded.clone(), Compression::Zstd(3))
+            .await
+            .expect("write");
+        let written = std::fs::read(&path).expect("read");
+        assert_eq!(written[..8], ZSTD_MAGIC_BYTES.to_le_bytes());
+        assert!(written.len() < encoded.len());
+        let loaded = JammedCheckpoint::load_any_version(&path)
+            .await
+            .expect("load");
+        assert_eq!(loaded, checkpoint);
+
+        write_checkpoint_file(&path, encoded.clone(), Compression::None)
+            .await
+            .expect("write");
+        assert_eq!(std::fs::read(&path).expect("read"), encoded);
+        let loaded = JammedCheckpoint::load_any_version(&path)
+            .await
+            .expect("load");
+        assert_eq!(loaded, checkpoint);
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_legacy_uncompressed_load() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("0.chkjam");
+        let ker_hash = blake3::hash(b"kernel");
+
+        // Written bare, as nodes did before checkpoints were compressed
+        let v1 = JammedCheckpointV1::new(ker_hash, 5, jam());
+        std::fs::write(&path, v1.encode().expect("encode")).expect("write");
+        let loaded = JammedCheckpoint::load_any_version(&path)
+            .await
+            .expect("load v1");
+        assert_eq!((loaded.event_num, loaded.ker_hash), (5, ker_hash));
+        assert_eq!(loaded.jam, jam());
+
+        let v0 = JammedCheckpointV0::new(true, ker_hash, 3, jam());
+        std::fs::write(&path, v0.encode().expect("encode")).expect("write");
+        let loaded = JammedCheckpoint::load_any_version(&path)
+            .await
+            .expect("load v0");
+        assert_eq!((loaded.event_num, loaded.ker_hash), (3, ker_hash));
+        assert_eq!(loaded.jam, jam());
+
+        std::fs::write(&path, b"not a checkpoint").expect("write");
+        assert!(matches!(
+            JammedCheckpoint::load_any_version(&path).await,
+            Err(CheckpointError::VersionsFailed { .. })
+        ));
+    }
+}
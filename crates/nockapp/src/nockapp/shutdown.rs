@@ -0,0 +1,97 @@
+//! Ordered shutdown.
+//!
+//! On a signal or an exit request, a [`crate::NockApp`] stops taking pokes and tells its drivers
+//! to stop taking new work ([`Shutdown::draining`]). Pokes already sent to the kernel finish,
+//! since the final checkpoint is taken after them rather than interrupting them, and it holds the
+//! mempool and everything else in the kernel state. Once the checkpoint is written, drivers are
+//! told to close their connections ([`Shutdown::closing`]), and the app exits when those holding a
+//! [`ShutdownGuard`] have dropped it.
+//!
+//! If all of that takes longer than the deadline ([`DEFAULT_SHUTDOWN_DEADLINE`] unless set with
+//! [`crate::NockApp::set_shutdown_deadline`]), the running computation is interrupted, and
+//! [`FORCE_EXIT_AFTER`] later the process exits regardless. Checkpoints are written under a
+//! temporary name and renamed into place, so even then the last complete one survives.
+use std::time::Duration;
+
+use tokio_util::sync::{CancellationToken, WaitForCancellationFuture};
+use tokio_util::task::task_tracker::TaskTrackerToken;
+use tokio_util::task::TaskTracker;
+
+/// How long a shutdown may take before it's forced
+pub const DEFAULT_SHUTDOWN_DEADLINE: Duration = Duration::from_secs(30);
+
+/// How long after interrupting the kernel at the deadline to exit anyway
+pub const FORCE_EXIT_AFTER: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, Default)]
+pub struct Shutdown {
+    draining: CancellationToken,
+    closing: CancellationToken,
+    guards: TaskTracker,
+}
+
+/// Held by a driver that has connections to close before the app exits
+#[derive(Debug)]
+pub struct ShutdownGuard {
+    _token: TaskTrackerToken,
+}
+
+impl Shutdown {
+    /// Resolves when the app is shutting down, and drivers should stop taking new work
+    pub fn draining(&self) -> WaitForCancellationFuture<'_> {
+        self.draining.cancelled()
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.is_cancelled()
+    }
+
+    /// Resolves when the final checkpoint is written, and drivers should close their connections
+    /// and drop their [`ShutdownGuard`]
+    pub fn closing(&self) -> WaitForCancellationFuture<'_> {
+        self.closing.cancelled()
+    }
+
+    /// Hold the exit until this is dropped
+    pub fn guard(&self) -> ShutdownGuard {
+        ShutdownGuard {
+            _token: self.guards.token(),
+        }
+    }
+
+    pub(crate) fn drain(&self) {
+        self.draining.cancel();
+    }
+
+    /// Tell drivers to close, and wait until they have
+    pub(crate) async fn close(&self) {
+        self.drain();
+        self.closing.cancel();
+        self.guards.close();
+        self.guards.wait().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_close_waits_for_guards() {
+        let shutdown = Shutdown::default();
+        let guard = shutdown.guard();
+        let driver = shutdown.clone();
+        let task = tokio::spawn(async move {
+            driver.draining().await;
+            driver.closing().await;
+            drop(guard);
+        });
+        assert!(!shutdown.is_draining());
+        shutdown.drain();
+        assert!(shutdown.is_draining());
+        tokio::time::timeout(Duration::from_secs(5), shutdown.close())
+            .await
+            .expect("close");
+        task.await.expect("driver");
+    }
+}
@@ -0,0 +1,75 @@
+//! The signals that stop a NockApp, as Unix signal numbers on every platform.
+//!
+//! Windows has console events instead: Ctrl-C is read as `SIGINT`, Ctrl-Break as `SIGQUIT`,
+//! closing the console as `SIGHUP`, and logoff or system shutdown as `SIGTERM`, so they exit with
+//! the same codes and shutdown as their Unix counterparts. Windows only waits a few seconds after
+//! a close, logoff or shutdown event before ending the process, which may cut the shutdown short.
+use std::io;
+
+#[cfg(unix)]
+pub use signal_hook::consts::signal::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
+#[cfg(windows)]
+use tokio::signal::windows::{
+    ctrl_break, ctrl_c, ctrl_close, ctrl_logoff, ctrl_shutdown, CtrlBreak, CtrlC, CtrlClose,
+    CtrlLogoff, CtrlShutdown,
+};
+
+#[cfg(windows)]
+pub const SIGHUP: i32 = 1;
+#[cfg(windows)]
+pub const SIGINT: i32 = 2;
+#[cfg(windows)]
+pub const SIGQUIT: i32 = 3;
+#[cfg(windows)]
+pub const SIGTERM: i32 = 15;
+
+pub struct Signals {
+    #[cfg(unix)]
+    signals: signal_hook_tokio::Signals,
+    #[cfg(windows)]
+    events: (CtrlC, CtrlBreak, CtrlClose, CtrlLogoff, CtrlShutdown),
+}
+
+impl Signals {
+    /// Listen for `SIGHUP`, `SIGINT`, `SIGQUIT` and `SIGTERM`, or their Windows equivalents.
+    pub fn new() -> io::Result<Self> {
+        #[cfg(unix)]
+        {
+            let signals = signal_hook_tokio::Signals::new([SIGHUP, SIGINT, SIGQUIT, SIGTERM])?;
+            Ok(Signals { signals })
+        }
+        #[cfg(windows)]
+        {
+            Ok(Signals {
+                events: (
+                    ctrl_c()?,
+                    ctrl_break()?,
+                    ctrl_close()?,
+                    ctrl_logoff()?,
+                    ctrl_shutdown()?,
+                ),
+            })
+        }
+    }
+
+    /// The next signal, or `None` if no more can arrive
+    pub async fn next(&mut self) -> Option<i32> {
+        #[cfg(unix)]
+        {
+            use futures::stream::StreamExt;
+            self.signals.next().await
+        }
+        #[cfg(windows)]
+        {
+            let (c, brk, close, logoff, shutdown) = &mut self.events;
+            tokio::select! {
+                Some(()) = c.recv() => Some(SIGINT),
+                Some(()) = brk.recv() => Some(SIGQUIT),
+                Some(()) = close.recv() => Some(SIGHUP),
+                Some(()) = logoff.recv() => Some(SIGTERM),
+                Some(()) = shutdown.recv() => Some(SIGTERM),
+                else => None,
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Small values IO drivers keep across restarts, outside the kernel's state.
+//!
+//! Each key is a file in the store's directory (`drivers` in the data directory when booted with
+//! [`crate::kernel::boot`]), written to a temporary file and renamed into place so a crash never
+//! leaves half a value. It's meant for things like connection tokens, read offsets and peer
+//! hints, which a driver can lose without the app losing anything: `--new` clears it along with
+//! the checkpoints.
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::utils::ipc::sync_dir;
+
+/// The longest key a store takes
+pub const MAX_KEY_LEN: usize = 128;
+
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("driver store: no store directory is set")]
+    NoStore,
+    #[error("driver store: bad key {0:?}, keys are letters, digits, '-', '_' and '.', not starting with '.'")]
+    BadKey(String),
+    #[error("driver store: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Where a [`NockAppHandle`](crate::nockapp::driver::NockAppHandle) keeps its drivers' values.
+/// Clones share the directory, and without one every read and write fails with
+/// [`StoreError::NoStore`].
+#[derive(Debug, Clone, Default)]
+pub struct DriverStore {
+    dir: Option<Arc<PathBuf>>,
+}
+
+impl DriverStore {
+    pub fn new(dir: PathBuf) -> Self {
+        DriverStore {
+            dir: Some(Arc::new(dir)),
+        }
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref().map(PathBuf::as_path)
+    }
+
+    /// The value last put at `key`, if there is one
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StoreError> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Durably set `key` to `value`, replacing what was there
+    pub async fn put(&self, key: &str, value: &[u8]) -> Result<(), StoreError> {
+        let path = self.path(key)?;
+        let dir = self.dir().ok_or(StoreError::NoStore)?.to_path_buf();
+        tokio::fs::create_dir_all(&dir).await?;
+        // A '.' can't start a key, so this can't be another key's file
+        let tmp_path = dir.join(format!(".{}.tmp", key));
+        tokio::fs::write(&tmp_path, value).await?;
+        tokio::fs::File::open(&tmp_path).await?.sync_all().await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        tokio::task::spawn_blocking(move || sync_dir(&dir))
+            .await
+            .expect("directory sync panicked")?;
+        Ok(())
+    }
+
+    /// Remove `key`, returning whether it was there
+    pub async fn delete(&self, key: &str) -> Result<bool, StoreError> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, StoreError> {
+        let dir = self.dir().ok_or(StoreError::NoStore)?;
+        let valid = !key.is_empty()
+            && key.len() <= MAX_KEY_LEN
+            && !key.starts_with('.')
+            && key
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'));
+        if !valid {
+            return Err(StoreError::BadKey(key.to_string()));
+        }
+        Ok(dir.join(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = DriverStore::new(dir.path().join("drivers"));
+        assert_eq!(store.get("http.token").await.unwrap(), None);
+        store.put("http.token", b"abc").await.unwrap();
+        store.put("http.token", b"def").await.unwrap();
+        assert_eq!(
+            store.get("http.token").await.unwrap(),
+            Some(b"def".to_vec())
+        );
+
+        // Another store on the same directory, as after a restart, sees it
+        let reopened = DriverStore::new(dir.path().join("drivers"));
+        assert_eq!(
+            reopened.get("http.token").await.unwrap(),
+            Some(b"def".to_vec())
+        );
+        assert!(reopened.delete("http.token").await.unwrap());
+        assert!(!reopened.delete("http.token").await.unwrap());
+        assert_eq!(store.get("http.token").await.unwrap(), None);
+
+        for key in ["", ".hidden", "../escape", "a/b", "k".repeat(MAX_KEY_LEN + 1).as_str()] {
+            assert!(matches!(
+                store.put(key, b"x").await,
+                Err(StoreError::BadKey(_))
+            ));
+        }
+        assert!(matches!(
+            DriverStore::default().get("key").await,
+            Err(StoreError::NoStore)
+        ));
+    }
+}
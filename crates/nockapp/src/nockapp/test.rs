@@ -6,9 +6,7 @@ use tempfile::TempDir;
 use super::NockApp;
 use crate::kernel::form::Kernel;
 
-pub async fn setup_nockapp(jam: &str) -> (TempDir, NockApp) {
-    let temp_dir = TempDir::new().expect("Failed to create temp directory");
-    let temp_dir_path = temp_dir.path().to_path_buf();
+pub fn read_test_jam(jam: &str) -> Vec<u8> {
     // Try multiple possible locations for the jam file
     let possible_paths = [
         Path::new(env!("CARGO_MANIFEST_DIR"))
@@ -18,11 +16,16 @@ pub async fn setup_nockapp(jam: &str) -> (TempDir, NockApp) {
         // Add other potential paths
     ];
 
-    let jam_bytes = possible_paths
+    possible_paths
         .iter()
         .find_map(|path| fs::read(path).ok())
-        .unwrap_or_else(|| panic!("Failed to read {} file from any known location", jam));
+        .unwrap_or_else(|| panic!("Failed to read {} file from any known location", jam))
+}
 
+pub async fn setup_nockapp(jam: &str) -> (TempDir, NockApp) {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let temp_dir_path = temp_dir.path().to_path_buf();
+    let jam_bytes = read_test_jam(jam);
     let kernel_f = async |checkpoint| Kernel::load(&jam_bytes, checkpoint, vec![], false).await;
     (
         temp_dir,
@@ -40,19 +43,27 @@ pub mod tests {
     use bytes::Bytes;
     use nockvm::jets::util::slot;
     use nockvm::mem::NockStack;
-    use nockvm::noun::{Noun, D, T};
+    use nockvm::noun::{Atom, Noun, D, T};
     use nockvm::serialization::{cue, jam};
     use nockvm::unifying_equality::unifying_equality;
     use nockvm_macros::tas;
     use tracing::info;
     use tracing_test::traced_test;
 
-    use super::setup_nockapp;
+    use super::{read_test_jam, setup_nockapp};
+    use crate::kernel::boot::default_boot_cli;
+    use crate::kernel::event_log;
+    use crate::kernel::form::{Kernel, StackConfig, SERF_THREAD_STACK_SIZE};
+    use crate::kernel::replica::Replica;
     use crate::nockapp::wire::{SystemWire, Wire};
+    use crate::nockapp::SaveRequest;
     use crate::noun::slab::{slab_equality, slab_noun_equality, NockJammer, NounSlab};
-    use crate::save::{SaveableCheckpoint, Saver};
+    use crate::save::{
+        history_dir, list_history, prune_history, DeltaPolicy, RetentionPolicy, SaveableCheckpoint,
+        Saver,
+    };
     use crate::utils::NOCK_STACK_SIZE;
-    use crate::{NockApp, NounExt};
+    use crate::{AtomExt, CrownError, NockApp, NounExt};
 
     async fn save_nockapp(nockapp: &mut NockApp) {
         nockapp.tasks.close();
@@ -301,78 +312,528 @@ pub mod tests {
         }
     }
 
+    async fn poke_inc(nockapp: &mut NockApp) {
+        let poke = {
+            let mut slab = NounSlab::new();
+            slab.copy_into(D(tas!(b"inc")));
+            slab
+        };
+        let wire = SystemWire.to_wire();
+        let _ = nockapp.kernel.poke(wire, poke).await.unwrap_or_else(|err| {
+            panic!(
+                "Panicked with {err:?} at {}:{} (git sha: {:?})",
+                file!(),
+                line!(),
+                option_env!("GIT_SHA")
+            )
+        });
+    }
+
+    // Tests that requested saves only happen once they are due
+    #[tokio::test]
+    #[traced_test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_nockapp_save_request() {
+        let (_temp, mut nockapp) = setup_nockapp("test-ker.jam").await;
+        poke_inc(&mut nockapp).await;
+        poke_inc(&mut nockapp).await;
+
+        let last_saved = |nockapp: &NockApp| {
+            let saver = nockapp.save_mutex.clone();
+            async move { saver.lock().await.last_event_num() }
+        };
+        nockapp
+            .handle_save_request(SaveRequest::Events(3))
+            .await
+            .expect("save task");
+        assert_eq!(last_saved(&nockapp).await, 0);
+        nockapp
+            .handle_save_request(SaveRequest::Events(2))
+            .await
+            .expect("save task");
+        assert_eq!(last_saved(&nockapp).await, 2);
+
+        poke_inc(&mut nockapp).await;
+        nockapp
+            .handle_save_request(SaveRequest::Effect)
+            .await
+            .expect("save task");
+        assert_eq!(last_saved(&nockapp).await, 3);
+    }
+
+    // Tests that a replica picks up the primary's checkpoints only once they are newer
+    #[tokio::test]
+    #[traced_test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_replica_refresh() {
+        let (temp, mut nockapp) = setup_nockapp("test-ker.jam").await;
+        poke_inc(&mut nockapp).await;
+        save_nockapp(&mut nockapp).await;
+
+        let jam = read_test_jam("test-ker.jam");
+        let replica = Replica::<NockJammer>::open(&jam, &[], temp.path().to_path_buf())
+            .await
+            .expect("Failed to open replica");
+        assert_eq!(replica.event_num(), 1);
+        assert!(!replica.refresh().await.expect("refresh"));
+
+        poke_inc(&mut nockapp).await;
+        assert!(!replica.refresh().await.expect("refresh"));
+        save_nockapp(&mut nockapp).await;
+        assert!(replica.refresh().await.expect("refresh"));
+        assert_eq!(replica.event_num(), 2);
+
+        let replica_state = replica
+            .worker_kernels()
+            .next()
+            .expect("worker")
+            .serf
+            .get_kernel_state_slab()
+            .await
+            .expect("replica state");
+        let primary_state = nockapp
+            .kernel
+            .serf
+            .get_kernel_state_slab()
+            .await
+            .expect("primary state");
+        assert!(slab_equality(&replica_state, &primary_state));
+    }
+
+    // Tests that every worker of a replica answers peeks from the same checkpoint
+    #[tokio::test]
+    #[traced_test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_replica_parallel_peeks() {
+        let (temp, mut nockapp) = setup_nockapp("test-ker.jam").await;
+        poke_inc(&mut nockapp).await;
+        poke_inc(&mut nockapp).await;
+        save_nockapp(&mut nockapp).await;
+
+        let jam = read_test_jam("test-ker.jam");
+        let replica = Replica::<NockJammer>::open_workers(&jam, &[], temp.path().to_path_buf(), 3)
+            .await
+            .expect("Failed to open replica");
+        assert_eq!(replica.workers(), 3);
+        poke_inc(&mut nockapp).await;
+        save_nockapp(&mut nockapp).await;
+        assert!(replica.refresh().await.expect("refresh"));
+
+        let state_peek = || {
+            let mut slab = NounSlab::new();
+            let path = T(&mut slab, &[D(tas!(b"state")), D(0)]);
+            slab.set_root(path);
+            slab
+        };
+        let peeks = (0..6).map(|_| replica.peek(state_peek()));
+        let results = futures::future::try_join_all(peeks)
+            .await
+            .expect("replica peeks");
+        let expected = nockapp
+            .kernel
+            .peek(state_peek())
+            .await
+            .expect("primary peek");
+        for result in &results {
+            assert!(slab_equality(result, &expected));
+        }
+    }
+
     // Tests for fallback to previous checkpoint if checkpoint is corrupt
-    // TODO: ask about this test and reframe it for 'Saver'
-    /*
     #[tokio::test]
     #[traced_test]
     #[cfg_attr(miri, ignore)]
     async fn test_nockapp_corrupt_check() {
         let (temp, mut nockapp) = setup_nockapp("test-ker.jam").await;
-        assert_eq!(nockapp.kernel.serf.event_number.load(Ordering::SeqCst), 0);
+        nockapp
+            .save_mutex
+            .lock()
+            .await
+            .set_delta_policy(DeltaPolicy::disabled());
 
-        // Save a valid checkpoint
+        // Two full checkpoints: event 1 in 0.chkjam, event 2 in 1.chkjam
+        poke_inc(&mut nockapp).await;
+        save_nockapp(&mut nockapp).await;
+        poke_inc(&mut nockapp).await;
         save_nockapp(&mut nockapp).await;
 
-        // Generate an invalid checkpoint by incrementing the event number
-        let mut invalid = nockapp
+        // Tear the newer checkpoint
+        let newer = temp.path().join("1.chkjam");
+        let mut bytes = std::fs::read(&newer).expect("Could not read checkpoint");
+        bytes.truncate(bytes.len() / 2);
+        std::fs::write(&newer, bytes).expect("Could not write checkpoint");
+
+        let (_, checkpoint_opt) = Saver::<NockJammer>::try_load(&temp.path().to_path_buf(), None)
+            .await
+            .expect("Failed to roll back to the older checkpoint");
+        let checkpoint: SaveableCheckpoint = checkpoint_opt.expect("No checkpoint");
+        assert_eq!(checkpoint.event_num, 1);
+        assert!(temp.path().join("1.chkjam.corrupt").exists());
+
+        // With both checkpoints torn, loading fails instead of starting fresh
+        std::fs::write(temp.path().join("0.chkjam"), b"torn").expect("Could not write checkpoint");
+        assert!(Saver::<NockJammer>::try_load::<SaveableCheckpoint>(
+            &temp.path().to_path_buf(),
+            None
+        )
+        .await
+        .is_err());
+    }
+
+    // Tests that replaying an event log checks the state mugs recorded at checkpoints
+    #[tokio::test]
+    #[traced_test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_replay_event_log() {
+        let (temp, mut nockapp) = setup_nockapp("test-ker.jam").await;
+        let events_dir = temp.path().join("events");
+        nockapp
+            .kernel
+            .attach_event_log(events_dir.clone())
+            .await
+            .expect("attach event log");
+        poke_inc(&mut nockapp).await;
+        poke_inc(&mut nockapp).await;
+        save_nockapp(&mut nockapp).await;
+        poke_inc(&mut nockapp).await;
+
+        let jam = read_test_jam("test-ker.jam");
+        let fresh = || Kernel::<SaveableCheckpoint>::load(&jam, None, vec![], false);
+        let report = fresh()
+            .await
+            .expect("boot")
+            .replay(events_dir.clone())
+            .await
+            .expect("replay");
+        assert_eq!((report.start, report.end, report.checked), (0, 3, 1));
+
+        std::fs::write(events_dir.join("state-mugs"), "2 00000000\n").expect("write mugs");
+        let diverged = fresh().await.expect("boot").replay(events_dir).await;
+        assert!(matches!(
+            diverged,
+            Err(CrownError::ReplayDiverged { event_num: 2, .. })
+        ));
+    }
+
+    /// A jammed kernel whose poke takes `[%migrate old-state]` and keeps `old-state`, with no
+    /// effects, or crashes if `crash`
+    fn migrating_kernel(crash: bool) -> Vec<u8> {
+        let mut slab: NounSlab = NounSlab::new();
+        let gate = if crash {
+            T(&mut slab, &[D(0), D(0)])
+        } else {
+            // [[1 0] [10 [6 0 223] 0 7]]: `~`, and the arvo core in the gate's context with
+            // the old state from the `[%migrate old-state]` cause, at axis 223 of the gate
+            let nil = T(&mut slab, &[D(1), D(0)]);
+            let old_state = T(&mut slab, &[D(6), D(0), D(223)]);
+            let arvo = T(&mut slab, &[D(0), D(7)]);
+            let edit = T(&mut slab, &[D(10), old_state, arvo]);
+            T(&mut slab, &[nil, edit])
+        };
+        // The poke arm, at axis 23, makes a gate with the arvo core as its context
+        let quoted = T(&mut slab, &[D(1), gate]);
+        let sample = T(&mut slab, &[D(1), D(0)]);
+        let context = T(&mut slab, &[D(0), D(1)]);
+        let poke = T(&mut slab, &[quoted, sample, context]);
+        let crash_arm = T(&mut slab, &[D(0), D(0)]);
+        let battery = T(&mut slab, &[crash_arm, D(0), crash_arm, poke]);
+        let arvo = T(&mut slab, &[battery, D(0), D(0)]);
+        let trap_arm = T(&mut slab, &[D(1), arvo]);
+        let trap = T(&mut slab, &[trap_arm, D(0)]);
+        slab.set_root(trap);
+        slab.jam().to_vec()
+    }
+
+    fn kernel_state(checkpoint: &SaveableCheckpoint) -> Noun {
+        let noun = unsafe { *checkpoint.noun.root() };
+        noun.as_cell().expect("checkpoint is a cell").head()
+    }
+
+    // Tests that a hot upgrade hands the live state to the new kernel, and that a failed one
+    // leaves the old kernel running
+    #[tokio::test]
+    #[traced_test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_kernel_upgrade() {
+        let (_temp, mut nockapp) = setup_nockapp("test-ker.jam").await;
+        poke_inc(&mut nockapp).await;
+        poke_inc(&mut nockapp).await;
+        let before = nockapp.kernel.checkpoint().await.expect("checkpoint");
+
+        assert!(matches!(
+            nockapp.kernel.upgrade(migrating_kernel(true)).await,
+            Err(CrownError::UpgradeFailed)
+        ));
+        let failed = nockapp.kernel.checkpoint().await.expect("checkpoint");
+        assert_eq!(failed.ker_hash, before.ker_hash);
+        assert_eq!(failed.event_num, before.event_num);
+        assert!(slab_equality(&failed.noun, &before.noun));
+        // The old kernel still takes pokes
+        poke_inc(&mut nockapp).await;
+        let live = nockapp.kernel.checkpoint().await.expect("checkpoint");
+        assert_eq!(live.event_num, before.event_num + 1);
+        assert!(!slab_noun_equality(
+            &kernel_state(&live),
+            &kernel_state(&before)
+        ));
+
+        nockapp
+            .kernel
+            .upgrade(migrating_kernel(false))
+            .await
+            .expect("upgrade");
+        let upgraded = nockapp.kernel.checkpoint().await.expect("checkpoint");
+        assert_ne!(upgraded.ker_hash, live.ker_hash);
+        assert_eq!(upgraded.event_num, live.event_num + 1);
+        assert!(slab_noun_equality(
+            &kernel_state(&upgraded),
+            &kernel_state(&live)
+        ));
+    }
+
+    // Tests that a poke that runs out of memory grows the loom up to the ceiling and runs again
+    #[tokio::test]
+    #[traced_test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_loom_grows_on_oom() {
+        let stack_config = StackConfig {
+            nock_stack_size: 1 << 21,
+            thread_stack_size: SERF_THREAD_STACK_SIZE,
+        };
+        let kernel: Kernel<SaveableCheckpoint> = Kernel::load_with_stack_config(
+            &migrating_kernel(false),
+            None,
+            &[],
+            stack_config,
+            vec![],
+            false,
+        )
+        .await
+        .expect("load kernel");
+        kernel
+            .set_loom_ceiling(Some(1 << 23))
+            .await
+            .expect("set loom ceiling");
+
+        // The new state, 3 * 2^20 words of atoms, is bigger than the loom
+        let mut cause: NounSlab = NounSlab::new();
+        let chunk = Bytes::from(vec![0xff; 8 << 10]);
+        let mut list = D(0);
+        for _ in 0..3072 {
+            let atom = Atom::from_bytes(&mut cause, &chunk).as_noun();
+            list = T(&mut cause, &[atom, list]);
+        }
+        let root = T(&mut cause, &[D(0), list]);
+        cause.set_root(root);
+        let expected = cause.clone();
+        kernel
+            .poke(SystemWire.to_wire(), cause)
+            .await
+            .expect("poke after growing the loom");
+
+        let checkpoint = kernel.checkpoint().await.expect("checkpoint");
+        assert_eq!(checkpoint.event_num, 1);
+        let expected_state = unsafe { *expected.root() }
+            .as_cell()
+            .expect("cause is a cell")
+            .tail();
+        assert!(slab_noun_equality(
+            &kernel_state(&checkpoint),
+            &expected_state
+        ));
+    }
+
+    // Tests that a full checkpoint and a delta restore the state a full checkpoint does
+    #[tokio::test]
+    #[traced_test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_delta_checkpoint() {
+        let (delta_temp, mut delta_app) = setup_nockapp("test-ker.jam").await;
+        let (full_temp, mut full_app) = setup_nockapp("test-ker.jam").await;
+        delta_app.set_checkpoint_deltas(DeltaPolicy::up_to(4)).await;
+        for nockapp in [&mut delta_app, &mut full_app] {
+            poke_inc(nockapp).await;
+            save_nockapp(nockapp).await;
+            poke_inc(nockapp).await;
+            save_nockapp(nockapp).await;
+        }
+        assert!(delta_temp.path().join("delta.chkjam").exists());
+        // Deltas are opt-in
+        assert!(!full_temp.path().join("delta.chkjam").exists());
+
+        async fn load(temp: &tempfile::TempDir) -> SaveableCheckpoint {
+            let (_, checkpoint) = Saver::<NockJammer>::try_load(&temp.path().to_path_buf(), None)
+                .await
+                .expect("Failed to load checkpoint");
+            checkpoint.expect("No checkpoint")
+        }
+        let from_delta = load(&delta_temp).await;
+        let from_full = load(&full_temp).await;
+        assert_eq!(from_delta.event_num, 2);
+        assert_eq!(from_full.event_num, 2);
+        assert!(slab_equality(&from_delta.noun, &from_full.noun));
+        let live = delta_app
             .kernel
             .checkpoint()
             .await
-            .expect("Could not get kernel checkpoint");
-        invalid.event_num += 1;
-        assert!(!invalid.validate());
+            .expect("Failed to get kernel checkpoint");
+        assert!(slab_equality(&from_delta.noun, &live.noun));
+    }
 
-        // The invalid checkpoint has a higher event number than the valid checkpoint
-        let mut checkpoint_stack = NockStack::new(NOCK_STACK_SIZE, 0);
-        let valid = jam_paths
-            .load_checkpoint(&mut checkpoint_stack)
-            .unwrap_or_else(|err| {
-                panic!(
-                    "Panicked with {err:?} at {}:{} (git sha: {:?})",
-                    file!(),
-                    line!(),
-                    option_env!("GIT_SHA")
-                )
+    // Tests that full checkpoints are kept in the history and pruned to the retention policy
+    #[tokio::test]
+    #[traced_test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_checkpoint_history() {
+        let (temp, mut nockapp) = setup_nockapp("test-ker.jam").await;
+        {
+            let mut saver = nockapp.save_mutex.lock().await;
+            saver.set_delta_policy(DeltaPolicy::disabled());
+            saver.set_retention_policy(RetentionPolicy {
+                keep: Some(3),
+                ..RetentionPolicy::default()
             });
-        assert!(valid.event_num < invalid.event_num);
+        }
+        for _ in 0..5 {
+            poke_inc(&mut nockapp).await;
+            save_nockapp(&mut nockapp).await;
+        }
+        let history = list_history(temp.path()).expect("list history");
+        let event_nums: Vec<u64> = history.iter().map(|c| c.event_num).collect();
+        assert_eq!(event_nums, vec![3, 4, 5]);
+
+        // Event 3 was written to 0.chkjam, which event 5 has since replaced
+        let restore = temp.path().join("restore");
+        std::fs::create_dir_all(&restore).expect("create restore dir");
+        std::fs::copy(&history[0].path, restore.join("0.chkjam")).expect("copy checkpoint");
+        let checkpoint = Saver::<NockJammer>::load_latest::<SaveableCheckpoint>(&restore, None)
+            .await
+            .expect("load archived checkpoint")
+            .expect("archived checkpoint");
+        assert_eq!(checkpoint.event_num, 3);
+
+        let policy = RetentionPolicy {
+            keep: Some(3),
+            max_bytes: Some(0),
+            ..RetentionPolicy::default()
+        };
+        assert_eq!(prune_history(temp.path(), &policy).expect("prune").len(), 3);
+        assert!(list_history(temp.path()).expect("list history").is_empty());
+        assert!(!history_dir(temp.path())
+            .join("00000000000000000003.chkjam")
+            .exists());
+    }
 
-        // Save the corrupted checkpoint, because of the toggle buffer, we will write to jam file 1
-        assert!(!jam_paths.1.exists());
-        let jam_path = &jam_paths.1;
-        let jam_bytes = invalid.encode().unwrap_or_else(|err| {
-            panic!(
-                "Panicked with {err:?} at {}:{} (git sha: {:?})",
-                file!(),
-                line!(),
-                option_env!("GIT_SHA")
-            )
-        });
-        tokio::fs::write(jam_path, jam_bytes)
+    // Tests that a scrubbed save leaves only the current state on disk, and that pokes on secret
+    // wires stay out of the event log
+    #[tokio::test]
+    #[traced_test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_save_scrubbed() {
+        let (temp, mut nockapp) = setup_nockapp("test-ker.jam").await;
+        let events_dir = temp.path().join("events");
+        nockapp
+            .kernel
+            .attach_event_log(events_dir.clone())
             .await
-            .unwrap_or_else(|err| {
-                panic!(
-                    "Panicked with {err:?} at {}:{} (git sha: {:?})",
-                    file!(),
-                    line!(),
-                    option_env!("GIT_SHA")
-                )
+            .expect("attach event log");
+        {
+            let mut saver = nockapp.save_mutex.lock().await;
+            saver.set_delta_policy(DeltaPolicy::up_to(4));
+            saver.set_retention_policy(RetentionPolicy {
+                keep: Some(3),
+                ..RetentionPolicy::default()
             });
+        }
+        poke_inc(&mut nockapp).await;
+        save_nockapp(&mut nockapp).await;
+        poke_inc(&mut nockapp).await;
+        save_nockapp(&mut nockapp).await;
+        assert!(temp.path().join("delta.chkjam").exists());
+        assert!(!list_history(temp.path()).expect("list history").is_empty());
 
-        // The loaded checkpoint will be the valid one
-        let chk = jam_paths
-            .load_checkpoint(&mut checkpoint_stack)
-            .unwrap_or_else(|err| {
-                panic!(
-                    "Panicked with {err:?} at {}:{} (git sha: {:?})",
-                    file!(),
-                    line!(),
-                    option_env!("GIT_SHA")
-                )
-            });
-        assert!(chk.event_num == valid.event_num);
+        let mut slab = NounSlab::new();
+        slab.copy_into(D(tas!(b"inc")));
+        nockapp
+            .kernel
+            .poke(SystemWire.to_wire().secret(), slab)
+            .await
+            .expect("secret poke");
+        assert!(event_log::read_since(&events_dir, 0)
+            .expect("read event log")
+            .is_empty());
+        poke_inc(&mut nockapp).await;
+        assert_eq!(
+            event_log::read_since(&events_dir, 0)
+                .expect("read event log")
+                .len(),
+            1
+        );
+
+        nockapp.save_scrubbed().await.expect("scrubbed save");
+        assert!(!temp.path().join("delta.chkjam").exists());
+        assert!(!history_dir(temp.path()).exists());
+        assert!(event_log::read_since(&events_dir, 0)
+            .expect("read event log")
+            .is_empty());
+        for slot in ["0.chkjam", "1.chkjam"] {
+            let checkpoint =
+                Saver::<NockJammer>::load_file::<SaveableCheckpoint>(&temp.path().join(slot), None)
+                    .await
+                    .expect("load checkpoint");
+            assert_eq!(checkpoint.event_num, 4);
+        }
+    }
+
+    // Tests that restarting without the history flags leaves the history alone, and that an age
+    // limit keeps a history on its own
+    #[tokio::test]
+    #[traced_test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_checkpoint_history_default_cli() {
+        let (temp, mut nockapp) = setup_nockapp("test-ker.jam").await;
+        nockapp
+            .set_checkpoint_retention(RetentionPolicy {
+                keep: Some(3),
+                ..RetentionPolicy::default()
+            })
+            .await;
+        for _ in 0..2 {
+            poke_inc(&mut nockapp).await;
+            save_nockapp(&mut nockapp).await;
+        }
+        drop(nockapp);
+
+        let jam_bytes = read_test_jam("test-ker.jam");
+        let kernel_f = async |checkpoint| Kernel::load(&jam_bytes, checkpoint, vec![], false).await;
+        let temp_path = temp.path().to_path_buf();
+        let mut nockapp: NockApp = NockApp::new(kernel_f, &temp_path, Duration::from_secs(1))
+            .await
+            .expect("Could not restart NockApp");
+        nockapp
+            .set_checkpoint_retention(default_boot_cli(false).retention_policy())
+            .await;
+        poke_inc(&mut nockapp).await;
+        save_nockapp(&mut nockapp).await;
+        let event_nums = |temp: &tempfile::TempDir| -> Vec<u64> {
+            list_history(temp.path())
+                .expect("list history")
+                .iter()
+                .map(|c| c.event_num)
+                .collect()
+        };
+        assert_eq!(event_nums(&temp), vec![1, 2]);
+
+        nockapp
+            .set_checkpoint_retention(RetentionPolicy {
+                max_age: Some(Duration::from_secs(60 * 60)),
+                ..RetentionPolicy::default()
+            })
+            .await;
+        poke_inc(&mut nockapp).await;
+        save_nockapp(&mut nockapp).await;
+        assert_eq!(event_nums(&temp), vec![1, 2, 4]);
     }
-    */
 
     #[tokio::test]
     #[cfg_attr(miri, ignore)]
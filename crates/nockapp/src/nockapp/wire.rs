@@ -1,6 +1,23 @@
+//! Wires name where a poke came from: `[source version tags... ~]`.
+//!
+//! A kernel that answers the peek `[%wires ~]` with `(list [source=@tas versions=(list @ud)])`
+//! says which versions of each source's wire it understands. The runtime peeks it at boot and
+//! after each upgrade, refuses pokes on any other version with a [`WireVersionError`], and lets
+//! drivers that speak several versions pick one the kernel understands with
+//! [`NockAppHandle::wire_version`](crate::nockapp::driver::NockAppHandle::wire_version). A kernel
+//! that doesn't answer gets every poke, as before.
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
 use nockvm::noun::{Noun, NounAllocator, D, T};
+use thiserror::Error;
 
+use crate::noun::slab::NounSlab;
 use crate::utils::make_tas;
+use crate::AtomExt;
+
+/// Tag of the peek a kernel answers with the wire versions it understands
+pub const WIRES_PEEK: &str = "wires";
 
 /// Standardized wire format for kernel interaction.
 pub trait Wire: Sized {
@@ -10,6 +27,9 @@ pub trait Wire: Sized {
     /// Driver/Module identifier
     const SOURCE: &'static str;
 
+    /// Every version the driver can speak, most preferred last
+    const SUPPORTED: &'static [u64] = &[Self::VERSION];
+
     /// Get wire for this driver. Specific implementations can add more tags to the wire as they wish but the default is just [`Self::SOURCE`] and [`Self::VERSION`].
     fn to_wire(&self) -> WireRepr {
         WireRepr::no_tags(Self::SOURCE, Self::VERSION)
@@ -94,6 +114,8 @@ pub struct WireRepr {
     pub source: &'static str,
     pub version: u64,
     pub tags: Vec<WireTag>,
+    /// Whether pokes on this wire carry secrets, see [`WireRepr::secret`]
+    pub secret: bool,
 }
 
 impl WireRepr {
@@ -102,6 +124,7 @@ impl WireRepr {
             source,
             version,
             tags,
+            secret: false,
         }
     }
     pub fn no_tags(source: &'static str, version: u64) -> Self {
@@ -109,8 +132,20 @@ impl WireRepr {
             source,
             version,
             tags: Vec::new(),
+            secret: false,
         }
     }
+    /// The same wire at another version, as negotiated with the kernel
+    pub fn with_version(mut self, version: u64) -> Self {
+        self.version = version;
+        self
+    }
+    /// The same wire, marked as carrying secrets like passphrases. Its pokes are kept out of the
+    /// event log, crash dumps and dead letters.
+    pub fn secret(mut self) -> Self {
+        self.secret = true;
+        self
+    }
     pub fn tags_as_csv(&self) -> String {
         let mut tags = Vec::with_capacity(self.tags.len() + 2);
         tags.push(self.source.to_string());
@@ -133,6 +168,124 @@ impl Wire for SystemWire {
     const SOURCE: &'static str = "sys";
 }
 
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum WireVersionError {
+    #[error("the kernel takes no pokes on {driver} wires")]
+    UnknownSource { driver: String },
+    #[error("the kernel understands {driver} wire versions {understood:?}, not {version}")]
+    Unsupported {
+        driver: String,
+        version: u64,
+        understood: Vec<u64>,
+    },
+    #[error("the kernel understands {driver} wire versions {understood:?}, but the driver only speaks {supported:?}")]
+    NoCommon {
+        driver: String,
+        supported: Vec<u64>,
+        understood: Vec<u64>,
+    },
+    #[error("malformed wire versions from the kernel")]
+    Malformed,
+}
+
+/// Wire versions by source
+pub type VersionsBySource = HashMap<String, Vec<u64>>;
+
+/// The wire versions the kernel says it understands, by source. Clones share them, so every
+/// handle sees what the runtime last negotiated; `None` means the kernel doesn't say.
+#[derive(Debug, Clone, Default)]
+pub struct WireVersions {
+    understood: Arc<RwLock<Option<VersionsBySource>>>,
+}
+
+impl WireVersions {
+    /// The path of the peek that asks the kernel
+    pub fn peek_path() -> NounSlab {
+        let mut path = NounSlab::new();
+        let tag = make_tas(&mut path, WIRES_PEEK).as_noun();
+        let root = T(&mut path, &[tag, D(0)]);
+        path.set_root(root);
+        path
+    }
+
+    /// Parse `(list [source=@tas versions=(list @ud)])`
+    pub fn parse(noun: Noun) -> Result<VersionsBySource, WireVersionError> {
+        let mut understood = HashMap::new();
+        let mut list = noun;
+        while let Ok(cell) = list.as_cell() {
+            let entry = cell.head().as_cell().map_err(malformed)?;
+            let source = entry.head().as_atom().map_err(malformed)?;
+            let mut versions = Vec::new();
+            let mut rest = entry.tail();
+            while let Ok(version) = rest.as_cell() {
+                let atom = version.head().as_atom().map_err(malformed)?;
+                versions.push(atom.as_u64().map_err(malformed)?);
+                rest = version.tail();
+            }
+            understood.insert(source.into_string().map_err(malformed)?, versions);
+            list = cell.tail();
+        }
+        Ok(understood)
+    }
+
+    /// Take what the kernel answered `[%wires ~]` with, `None` if it didn't
+    pub(crate) fn set(&self, understood: Option<VersionsBySource>) {
+        *self.understood.write().unwrap_or_else(|e| e.into_inner()) = understood;
+    }
+
+    /// Whether the kernel understands `wire`
+    pub fn check(&self, wire: &WireRepr) -> Result<(), WireVersionError> {
+        let understood = self.understood.read().unwrap_or_else(|e| e.into_inner());
+        let Some(understood) = understood.as_ref() else {
+            return Ok(());
+        };
+        let versions =
+            understood
+                .get(wire.source)
+                .ok_or_else(|| WireVersionError::UnknownSource {
+                    driver: wire.source.to_string(),
+                })?;
+        if versions.contains(&wire.version) {
+            Ok(())
+        } else {
+            Err(WireVersionError::Unsupported {
+                driver: wire.source.to_string(),
+                version: wire.version,
+                understood: versions.clone(),
+            })
+        }
+    }
+
+    /// The version of `W`'s wire to use: the most preferred of [`Wire::SUPPORTED`] that the
+    /// kernel understands, or [`Wire::VERSION`] if the kernel doesn't say
+    pub fn negotiate<W: Wire>(&self) -> Result<u64, WireVersionError> {
+        let understood = self.understood.read().unwrap_or_else(|e| e.into_inner());
+        let Some(understood) = understood.as_ref() else {
+            return Ok(W::VERSION);
+        };
+        let versions =
+            understood
+                .get(W::SOURCE)
+                .ok_or_else(|| WireVersionError::UnknownSource {
+                    driver: W::SOURCE.to_string(),
+                })?;
+        W::SUPPORTED
+            .iter()
+            .rev()
+            .find(|version| versions.contains(version))
+            .copied()
+            .ok_or_else(|| WireVersionError::NoCommon {
+                driver: W::SOURCE.to_string(),
+                supported: W::SUPPORTED.to_vec(),
+                understood: versions.clone(),
+            })
+    }
+}
+
+fn malformed<E>(_: E) -> WireVersionError {
+    WireVersionError::Malformed
+}
+
 #[cfg(test)]
 mod test {
     use nockvm_macros::tas;
@@ -163,6 +316,43 @@ mod test {
         }
     }
 
+    struct NewerWire;
+
+    impl Wire for NewerWire {
+        const VERSION: u64 = 2;
+        const SOURCE: &'static str = "npc";
+        const SUPPORTED: &'static [u64] = &[1, 2];
+    }
+
+    #[test]
+    fn test_wire_versions() {
+        let versions = WireVersions::default();
+        let newer = NewerWire.to_wire();
+        assert_eq!(versions.negotiate::<NewerWire>(), Ok(2));
+        assert!(versions.check(&newer).is_ok());
+
+        let mut slab: NounSlab = NounSlab::new();
+        let npc = make_tas(&mut slab, "npc").as_noun();
+        let ones = T(&mut slab, &[D(1), D(0)]);
+        let entry = T(&mut slab, &[npc, ones]);
+        let advert = T(&mut slab, &[entry, D(0)]);
+        versions.set(Some(WireVersions::parse(advert).unwrap()));
+        assert_eq!(versions.negotiate::<NewerWire>(), Ok(1));
+        assert!(versions.check(&newer.clone().with_version(1)).is_ok());
+        assert!(matches!(
+            versions.check(&newer),
+            Err(WireVersionError::Unsupported { version: 2, .. })
+        ));
+        assert!(matches!(
+            versions.check(&SystemWire.to_wire()),
+            Err(WireVersionError::UnknownSource { .. })
+        ));
+        assert_eq!(
+            WireVersions::parse(T(&mut slab, &[D(1), D(0)])),
+            Err(WireVersionError::Malformed)
+        );
+    }
+
     #[tokio::test]
     #[cfg_attr(miri, ignore)]
     async fn test_npc_wire_variants() {
@@ -0,0 +1,179 @@
+//! Random nouns for property tests, with the `quickcheck` feature.
+//!
+//! [`NounSlab`] implements [`Arbitrary`], so a property can take slabs directly:
+//!
+//! ```ignore
+//! quickcheck::quickcheck(nockapp::noun::arbitrary::jam_round_trip as fn(NounSlab) -> bool);
+//! ```
+//!
+//! The nouns mix small, direct and indirect atoms, and reuse earlier subnouns so that jam has
+//! backreferences to write. [`NounGen`] sets the depth, atom size and how much is shared, for
+//! properties that need other shapes.
+use nockvm::noun::{Atom, Noun, NounAllocator, D, T};
+use quickcheck::{Arbitrary, Gen};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::noun::serde::{from_noun, to_noun};
+use crate::noun::slab::{slab_equality, NounSlab};
+use crate::{AtomExt, Bytes};
+
+/// How [`NounGen::noun`] shapes nouns
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NounGen {
+    /// The deepest a cell may nest
+    pub depth: usize,
+    /// The most bytes in an indirect atom
+    pub atom_bytes: usize,
+    /// The percentage of subnouns that reuse an earlier one
+    pub sharing: u8,
+}
+
+impl Default for NounGen {
+    fn default() -> Self {
+        NounGen {
+            depth: 8,
+            atom_bytes: 32,
+            sharing: 20,
+        }
+    }
+}
+
+impl NounGen {
+    /// A random noun, allocated in `allocator`
+    pub fn noun<A: NounAllocator>(&self, allocator: &mut A, g: &mut Gen) -> Noun {
+        self.subnoun(allocator, g, self.depth, &mut Vec::new())
+    }
+
+    /// A slab rooted at a random noun
+    pub fn slab(&self, g: &mut Gen) -> NounSlab {
+        let mut slab = NounSlab::new();
+        let root = self.noun(&mut slab, g);
+        slab.set_root(root);
+        slab
+    }
+
+    fn subnoun<A: NounAllocator>(
+        &self,
+        allocator: &mut A,
+        g: &mut Gen,
+        depth: usize,
+        seen: &mut Vec<Noun>,
+    ) -> Noun {
+        if !seen.is_empty() && u8::arbitrary(g) % 100 < self.sharing {
+            return *g.choose(seen).expect("seen is not empty");
+        }
+        let noun = if depth > 0 && bool::arbitrary(g) {
+            let head = self.subnoun(allocator, g, depth - 1, seen);
+            let tail = self.subnoun(allocator, g, depth - 1, seen);
+            T(allocator, &[head, tail])
+        } else {
+            self.atom(allocator, g)
+        };
+        seen.push(noun);
+        noun
+    }
+
+    fn atom<A: NounAllocator>(&self, allocator: &mut A, g: &mut Gen) -> Noun {
+        match u8::arbitrary(g) % 3 {
+            0 => D(u8::arbitrary(g) as u64),
+            1 => Atom::new(allocator, u64::arbitrary(g)).as_noun(),
+            _ => {
+                let len = 1 + usize::arbitrary(g) % self.atom_bytes.max(1);
+                let mut bytes: Vec<u8> = (0..len).map(|_| u8::arbitrary(g)).collect();
+                while bytes.last() == Some(&0) {
+                    bytes.pop();
+                }
+                if bytes.is_empty() {
+                    D(0)
+                } else {
+                    Atom::from_bytes(allocator, &Bytes::from(bytes)).as_noun()
+                }
+            }
+        }
+    }
+}
+
+impl Arbitrary for NounSlab {
+    fn arbitrary(g: &mut Gen) -> Self {
+        NounGen::default().slab(g)
+    }
+
+    /// A cell shrinks to its head and tail, and an atom to 0
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let root = unsafe { *self.root() };
+        let smaller: Vec<NounSlab> = match root.as_cell() {
+            Ok(cell) => vec![NounSlab::from(cell.head()), NounSlab::from(cell.tail())],
+            Err(_) if root.as_direct().is_ok_and(|atom| atom.data() == 0) => Vec::new(),
+            Err(_) => vec![NounSlab::from(D(0))],
+        };
+        Box::new(smaller.into_iter())
+    }
+}
+
+/// Cueing the jam of `slab` gives back the same noun.
+pub fn jam_round_trip(slab: NounSlab) -> bool {
+    let mut cued = NounSlab::new();
+    let Ok(root) = cued.cue_into(slab.jam()) else {
+        return false;
+    };
+    cued.set_root(root);
+    slab_equality(&slab, &cued)
+}
+
+/// Jamming `slab` to a writer gives the bytes [`NounSlab::jam`] does, and cueing them from a
+/// reader gives back the same noun.
+pub fn jam_stream_round_trip(slab: NounSlab) -> bool {
+    let mut jammed = Vec::new();
+    if slab.jam_to_writer(&mut jammed).is_err() || jammed[..] != slab.jam()[..] {
+        return false;
+    }
+    let mut cued = NounSlab::new();
+    let Ok(root) = cued.cue_from_reader(&jammed[..]) else {
+        return false;
+    };
+    cued.set_root(root);
+    slab_equality(&slab, &cued)
+}
+
+/// `value` survives the serde bridge into a noun and back. Cords drop trailing NUL bytes, so a
+/// string ending in `'\0'` doesn't.
+pub fn serde_round_trip<T: Serialize + DeserializeOwned + PartialEq>(value: T) -> bool {
+    let mut slab: NounSlab = NounSlab::new();
+    match to_noun(&mut slab, &value) {
+        Ok(noun) => from_noun::<T>(noun).is_ok_and(|decoded| decoded == value),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickcheck::quickcheck;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trips() {
+        quickcheck(jam_round_trip as fn(NounSlab) -> bool);
+        quickcheck(jam_stream_round_trip as fn(NounSlab) -> bool);
+        quickcheck(
+            serde_round_trip::<(u64, Vec<Option<i32>>, bool, u128)>
+                as fn((u64, Vec<Option<i32>>, bool, u128)) -> bool,
+        );
+
+        let shallow = NounGen {
+            depth: 1,
+            atom_bytes: 4,
+            sharing: 0,
+        };
+        let mut g = Gen::new(10);
+        for _ in 0..100 {
+            let slab = shallow.slab(&mut g);
+            let root = unsafe { *slab.root() };
+            if let Ok(cell) = root.as_cell() {
+                assert!(cell.head().is_atom() && cell.tail().is_atom());
+            }
+            assert!(jam_round_trip(slab));
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! CBOR ([RFC 8949](https://www.rfc-editor.org/rfc/rfc8949)) encoding for nouns.
+//!
+//! This is a compact, self-describing alternative to jam for systems that don't carry a noun
+//! implementation. The mapping is:
+//!
+//! - Atoms that fit in 64 bits are unsigned integers (major type 0).
+//! - Larger atoms are positive bignums: tag 2 over a big-endian byte string.
+//! - Cells are arrays. A right-associated tuple `[a b c]` is flattened into the array
+//!   `[a, b, c]`, so arrays always have at least two elements.
+//!
+//! Decoding additionally accepts byte strings and text strings (read as little-endian atoms, the
+//! same way Hoon reads a cord), and `true`, `false`, and `null` (read as `%.y`, `%.n`, and `~`).
+//! Unlike jam, CBOR has no backreferences, so shared subtrees are written out in full.
+use either::Either;
+use nockvm::noun::{Atom, Cell, DirectAtom, IndirectAtom, Noun, NounAllocator, D, NO, YES};
+use thiserror::Error;
+
+use crate::noun::slab::NounSlab;
+
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_TAG: u8 = 6;
+const MAJOR_SIMPLE: u8 = 7;
+
+const TAG_POSITIVE_BIGNUM: u64 = 2;
+
+const SIMPLE_FALSE: u64 = 20;
+const SIMPLE_TRUE: u64 = 21;
+const SIMPLE_NULL: u64 = 22;
+
+#[derive(Debug, Error)]
+pub enum CborError {
+    #[error("cbor: truncated input")]
+    Truncated,
+    #[error("cbor: trailing bytes after value")]
+    TrailingBytes,
+    #[error("cbor: unsupported major type {0}")]
+    UnsupportedMajor(u8),
+    #[error("cbor: unsupported tag {0}")]
+    UnsupportedTag(u64),
+    #[error("cbor: unsupported simple value {0}")]
+    UnsupportedSimple(u64),
+    #[error("cbor: indefinite-length items are not supported")]
+    Indefinite,
+    #[error("cbor: reserved additional information {0}")]
+    Reserved(u8),
+    #[error("cbor: arrays must have at least two elements, found {0}")]
+    ShortArray(u64),
+    #[error("cbor: length {0} does not fit in memory")]
+    TooLong(u64),
+}
+
+pub type Result<T, E = CborError> = std::result::Result<T, E>;
+
+/// Encode `noun` as CBOR.
+pub fn to_cbor(noun: Noun) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut stack = vec![noun];
+    while let Some(noun) = stack.pop() {
+        match noun.as_either_atom_cell() {
+            Either::Left(atom) => write_atom(&mut out, atom),
+            Either::Right(cell) => {
+                let mut items = vec![cell.head()];
+                let mut tail = cell.tail();
+                while let Ok(next) = tail.as_cell() {
+                    items.push(next.head());
+                    tail = next.tail();
+                }
+                items.push(tail);
+                write_header(&mut out, MAJOR_ARRAY, items.len() as u64);
+                stack.extend(items.into_iter().rev());
+            }
+        }
+    }
+    out
+}
+
+/// Decode a CBOR item into a noun allocated in `allocator`.
+pub fn from_cbor<A: NounAllocator>(allocator: &mut A, bytes: &[u8]) -> Result<Noun> {
+    let mut reader = Reader { bytes, pos: 0 };
+    // Each frame is an array being filled in, with the number of items still expected.
+    let mut frames: Vec<(Vec<Noun>, u64)> = Vec::new();
+    loop {
+        let mut noun = match reader.item(allocator)? {
+            Item::Noun(noun) => noun,
+            Item::Array(len) => {
+                if len < 2 {
+                    return Err(CborError::ShortArray(len));
+                }
+                let capacity = usize::try_from(len).map_err(|_| CborError::TooLong(len))?;
+                frames.push((Vec::with_capacity(capacity.min(1024)), len));
+                continue;
+            }
+        };
+        loop {
+            let Some((items, remaining)) = frames.last_mut() else {
+                if reader.pos != bytes.len() {
+                    return Err(CborError::TrailingBytes);
+                }
+                return Ok(noun);
+            };
+            items.push(noun);
+            *remaining -= 1;
+            if *remaining > 0 {
+                break;
+            }
+            let (items, _) = frames.pop().unwrap_or_else(|| unreachable!());
+            let mut iter = items.into_iter().rev();
+            let mut acc = iter.next().unwrap_or_else(|| unreachable!());
+            for item in iter {
+                acc = Cell::new(allocator, item, acc).as_noun();
+            }
+            noun = acc;
+        }
+    }
+}
+
+impl<J> NounSlab<J> {
+    /// Encode the root of this slab as CBOR.
+    pub fn to_cbor(&self) -> Vec<u8> {
+        to_cbor(unsafe { *self.root() })
+    }
+
+    /// Make a new slab whose root is decoded from CBOR.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self> {
+        let mut slab = Self::new();
+        let root = from_cbor(&mut slab, bytes)?;
+        slab.set_root(root);
+        Ok(slab)
+    }
+}
+
+fn write_header(out: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    if value < 24 {
+        out.push(major | value as u8);
+    } else if value <= u8::MAX as u64 {
+        out.push(major | 24);
+        out.push(value as u8);
+    } else if value <= u16::MAX as u64 {
+        out.push(major | 25);
+        out.extend_from_slice(&(value as u16).to_be_bytes());
+    } else if value <= u32::MAX as u64 {
+        out.push(major | 26);
+        out.extend_from_slice(&(value as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn write_atom(out: &mut Vec<u8>, atom: Atom) {
+    if let Ok(value) = atom.as_u64() {
+        write_header(out, MAJOR_UNSIGNED, value);
+        return;
+    }
+    let bytes = atom.as_ne_bytes();
+    let end = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    write_header(out, MAJOR_TAG, TAG_POSITIVE_BIGNUM);
+    write_header(out, MAJOR_BYTES, end as u64);
+    out.extend(bytes[..end].iter().rev());
+}
+
+fn atom_from_le<A: NounAllocator>(allocator: &mut A, bytes: &[u8]) -> Atom {
+    if bytes.is_empty() {
+        return unsafe { DirectAtom::new_unchecked(0).as_atom() };
+    }
+    unsafe {
+        IndirectAtom::new_raw_bytes(allocator, bytes.len(), bytes.as_ptr()).normalize_as_atom()
+    }
+}
+
+enum Item {
+    Noun(Noun),
+    Array(u64),
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).ok_or(CborError::Truncated)?;
+        let slice = self.bytes.get(self.pos..end).ok_or(CborError::Truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn header(&mut self) -> Result<(u8, u64)> {
+        let initial = self.take(1)?[0];
+        let major = initial >> 5;
+        let info = initial & 0x1f;
+        let value = match info {
+            0..=23 => info as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => {
+                let mut buf = [0u8; 2];
+                buf.copy_from_slice(self.take(2)?);
+                u16::from_be_bytes(buf) as u64
+            }
+            26 => {
+                let mut buf = [0u8; 4];
+                buf.copy_from_slice(self.take(4)?);
+                u32::from_be_bytes(buf) as u64
+            }
+            27 => {
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(self.take(8)?);
+                u64::from_be_bytes(buf)
+            }
+            31 => return Err(CborError::Indefinite),
+            reserved => return Err(CborError::Reserved(reserved)),
+        };
+        Ok((major, value))
+    }
+
+    fn length(value: u64) -> Result<usize> {
+        usize::try_from(value).map_err(|_| CborError::TooLong(value))
+    }
+
+    fn item<A: NounAllocator>(&mut self, allocator: &mut A) -> Result<Item> {
+        let (major, value) = self.header()?;
+        match major {
+            MAJOR_UNSIGNED => Ok(Item::Noun(Atom::new(allocator, value).as_noun())),
+            MAJOR_BYTES | MAJOR_TEXT => {
+                let bytes = self.take(Self::length(value)?)?;
+                Ok(Item::Noun(atom_from_le(allocator, bytes).as_noun()))
+            }
+            MAJOR_ARRAY => Ok(Item::Array(value)),
+            MAJOR_TAG if value == TAG_POSITIVE_BIGNUM => {
+                let (inner, len) = self.header()?;
+                if inner != MAJOR_BYTES {
+                    return Err(CborError::UnsupportedMajor(inner));
+                }
+                let mut bytes = self.take(Self::length(len)?)?.to_vec();
+                bytes.reverse();
+                Ok(Item::Noun(atom_from_le(allocator, &bytes).as_noun()))
+            }
+            MAJOR_TAG => Err(CborError::UnsupportedTag(value)),
+            MAJOR_SIMPLE => match value {
+                SIMPLE_FALSE => Ok(Item::Noun(NO)),
+                SIMPLE_TRUE => Ok(Item::Noun(YES)),
+                SIMPLE_NULL => Ok(Item::Noun(D(0))),
+                other => Err(CborError::UnsupportedSimple(other)),
+            },
+            other => Err(CborError::UnsupportedMajor(other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::T;
+    use nockvm_macros::tas;
+
+    use super::*;
+    use crate::noun::slab::slab_noun_equality;
+
+    #[test]
+    fn test_cbor_shapes() {
+        let mut slab: NounSlab = NounSlab::new();
+        assert_eq!(to_cbor(D(0)), vec![0x00]);
+        assert_eq!(to_cbor(D(500)), vec![0x19, 0x01, 0xf4]);
+
+        let tuple = T(&mut slab, &[D(1), D(2), D(3)]);
+        assert_eq!(to_cbor(tuple), vec![0x83, 0x01, 0x02, 0x03]);
+
+        let head = T(&mut slab, &[D(1), D(2)]);
+        let nested = T(&mut slab, &[head, D(3)]);
+        assert_eq!(to_cbor(nested), vec![0x82, 0x82, 0x01, 0x02, 0x03]);
+    }
+
+    #[test]
+    fn test_cbor_roundtrip() {
+        let mut slab: NounSlab = NounSlab::new();
+        let big = atom_from_le(&mut slab, &u128::MAX.to_le_bytes()).as_noun();
+        let inner = T(&mut slab, &[D(tas!(b"inner")), big, D(0)]);
+        let noun = T(&mut slab, &[D(tas!(b"outer")), inner, D(u64::MAX >> 1)]);
+        slab.set_root(noun);
+
+        let encoded = slab.to_cbor();
+        let decoded: NounSlab = NounSlab::from_cbor(&encoded).expect("decode");
+        assert!(slab_noun_equality(unsafe { decoded.root() }, &noun));
+    }
+
+    #[test]
+    fn test_cbor_foreign_values() {
+        let mut slab: NounSlab = NounSlab::new();
+        // ["hi", true, null]
+        let noun = from_cbor(&mut slab, &[0x83, 0x62, b'h', b'i', 0xf5, 0xf6]).expect("decode");
+        let expected = T(&mut slab, &[D(tas!(b"hi")), YES, D(0)]);
+        assert!(slab_noun_equality(&noun, &expected));
+    }
+
+    #[test]
+    fn test_cbor_errors() {
+        let mut slab: NounSlab = NounSlab::new();
+        assert!(matches!(
+            from_cbor(&mut slab, &[0x81, 0x01]),
+            Err(CborError::ShortArray(1))
+        ));
+        assert!(matches!(
+            from_cbor(&mut slab, &[0x82, 0x01]),
+            Err(CborError::Truncated)
+        ));
+        assert!(matches!(
+            from_cbor(&mut slab, &[0x20]),
+            Err(CborError::UnsupportedMajor(1))
+        ));
+        assert!(matches!(
+            from_cbor(&mut slab, &[0x01, 0x01]),
+            Err(CborError::TrailingBytes)
+        ));
+    }
+}
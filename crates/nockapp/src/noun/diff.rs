@@ -0,0 +1,310 @@
+//! Structural diffs between nouns.
+//!
+//! [`diff`] walks two nouns side by side and produces the set of subtrees that must be replaced
+//! to turn the old noun into the new one. Subtrees are compared by mug first, so identical
+//! subtrees (in particular, ones that are shared between the two nouns) are skipped without
+//! being traversed. Where both sides are cells the walk descends instead of replacing, so each
+//! edit is as small as possible.
+//!
+//! A [`NounDiff`] can be applied to the old noun with [`NounDiff::apply`], encoded as a Hoon
+//! `(list [axis=@ new=*])` with [`NounDiff::to_noun`] for sending to subscribers, or printed for
+//! test failures.
+use std::fmt::{self, Display};
+
+use nockvm::noun::{Atom, Cell, IndirectAtom, Noun, NounAllocator, D, T};
+use thiserror::Error;
+
+use crate::noun::pretty::PrettyNoun;
+use crate::noun::slab::{slab_mug, slab_noun_equality};
+
+#[derive(Debug, Error)]
+pub enum DiffError {
+    #[error("diff: no cell at axis {0} to descend into")]
+    NotACell(String),
+    #[error("diff: malformed encoded diff")]
+    Malformed,
+}
+
+pub type Result<T, E = DiffError> = std::result::Result<T, E>;
+
+/// Which side of a cell a path step descends into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Head,
+    Tail,
+}
+
+/// Replace the subtree at `path` with `new`
+#[derive(Clone, Debug)]
+pub struct Edit {
+    /// The steps from the root to the replaced subtree
+    pub path: Vec<Side>,
+    /// The subtree being replaced
+    pub old: Noun,
+    /// The replacement
+    pub new: Noun,
+}
+
+impl Edit {
+    /// The Nock axis of the replaced subtree, if it fits in a `u64`.
+    pub fn axis(&self) -> Option<u64> {
+        if self.path.len() >= 64 {
+            return None;
+        }
+        Some(self.path.iter().fold(1u64, |axis, side| {
+            (axis << 1) | (*side == Side::Tail) as u64
+        }))
+    }
+
+    /// The Nock axis of the replaced subtree as little-endian bytes.
+    fn axis_bytes(&self) -> Vec<u8> {
+        let bits = self.path.len() + 1;
+        let mut bytes = vec![0u8; bits.div_ceil(8)];
+        bytes[self.path.len() >> 3] |= 1 << (self.path.len() & 7);
+        for (i, side) in self.path.iter().enumerate() {
+            if *side == Side::Tail {
+                let bit = self.path.len() - 1 - i;
+                bytes[bit >> 3] |= 1 << (bit & 7);
+            }
+        }
+        bytes
+    }
+
+    fn axis_string(&self) -> String {
+        match self.axis() {
+            Some(axis) => axis.to_string(),
+            None => {
+                let hex: String = self
+                    .axis_bytes()
+                    .iter()
+                    .rev()
+                    .map(|b| format!("{:02x}", b))
+                    .collect();
+                format!("0x{}", hex.trim_start_matches('0'))
+            }
+        }
+    }
+}
+
+/// The edits that turn one noun into another
+#[derive(Clone, Debug, Default)]
+pub struct NounDiff {
+    /// Disjoint edits, in depth-first head-first order
+    pub edits: Vec<Edit>,
+}
+
+impl NounDiff {
+    /// Whether the two nouns were equal
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Apply the edits to `old`, allocating the rebuilt spine in `allocator`.
+    ///
+    /// Subtrees that were not edited are shared with `old`, not copied.
+    pub fn apply<A: NounAllocator>(&self, allocator: &mut A, old: Noun) -> Result<Noun> {
+        let mut noun = old;
+        for edit in &self.edits {
+            noun = replace(allocator, noun, &edit.path, edit.new)?;
+        }
+        Ok(noun)
+    }
+
+    /// Encode the diff as a Hoon `(list [axis=@ new=*])`.
+    pub fn to_noun<A: NounAllocator>(&self, allocator: &mut A) -> Noun {
+        let mut list = D(0);
+        for edit in self.edits.iter().rev() {
+            let axis = match edit.axis() {
+                Some(axis) => Atom::new(allocator, axis),
+                None => {
+                    let bytes = edit.axis_bytes();
+                    unsafe {
+                        IndirectAtom::new_raw_bytes(allocator, bytes.len(), bytes.as_ptr())
+                            .normalize_as_atom()
+                    }
+                }
+            };
+            let entry = T(allocator, &[axis.as_noun(), edit.new]);
+            list = Cell::new(allocator, entry, list).as_noun();
+        }
+        list
+    }
+}
+
+impl Display for NounDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.edits.is_empty() {
+            return f.write_str("no differences");
+        }
+        for (i, edit) in self.edits.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            write!(
+                f,
+                "+{}: {} -> {}",
+                edit.axis_string(),
+                PrettyNoun::new(edit.old),
+                PrettyNoun::new(edit.new)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Compute the edits that turn `old` into `new`.
+pub fn diff(old: Noun, new: Noun) -> NounDiff {
+    let mut edits = Vec::new();
+    let mut stack = vec![(old, new, Vec::new())];
+    while let Some((old, new, path)) = stack.pop() {
+        if equal(old, new) {
+            continue;
+        }
+        match (old.as_cell(), new.as_cell()) {
+            (Ok(old), Ok(new)) => {
+                let mut tail_path = path.clone();
+                tail_path.push(Side::Tail);
+                let mut head_path = path;
+                head_path.push(Side::Head);
+                stack.push((old.tail(), new.tail(), tail_path));
+                stack.push((old.head(), new.head(), head_path));
+            }
+            _ => edits.push(Edit { path, old, new }),
+        }
+    }
+    NounDiff { edits }
+}
+
+/// Equality with a mug fast path for unequal allocated nouns.
+fn equal(a: Noun, b: Noun) -> bool {
+    if unsafe { a.raw_equals(&b) } {
+        return true;
+    }
+    if a.is_allocated() && b.is_allocated() && slab_mug(a) != slab_mug(b) {
+        return false;
+    }
+    slab_noun_equality(&a, &b)
+}
+
+/// Apply a diff encoded by [`NounDiff::to_noun`] to `old`.
+pub fn patch<A: NounAllocator>(allocator: &mut A, old: Noun, encoded: Noun) -> Result<Noun> {
+    let mut noun = old;
+    let mut list = encoded;
+    while let Ok(cell) = list.as_cell() {
+        let entry = cell.head().as_cell().map_err(|_| DiffError::Malformed)?;
+        let path = axis_to_path(entry.head().as_atom().map_err(|_| DiffError::Malformed)?)?;
+        noun = replace(allocator, noun, &path, entry.tail())?;
+        list = cell.tail();
+    }
+    if !unsafe { list.raw_equals(&D(0)) } {
+        return Err(DiffError::Malformed);
+    }
+    Ok(noun)
+}
+
+fn axis_to_path(axis: Atom) -> Result<Vec<Side>> {
+    let bits = axis.as_bitslice();
+    let top = bits.last_one().ok_or(DiffError::Malformed)?;
+    Ok(bits[..top]
+        .iter()
+        .rev()
+        .map(|bit| if *bit { Side::Tail } else { Side::Head })
+        .collect())
+}
+
+fn replace<A: NounAllocator>(
+    allocator: &mut A,
+    root: Noun,
+    path: &[Side],
+    new: Noun,
+) -> Result<Noun> {
+    let mut spine = Vec::with_capacity(path.len());
+    let mut noun = root;
+    for (depth, side) in path.iter().enumerate() {
+        let cell = noun.as_cell().map_err(|_| {
+            let partial = Edit {
+                path: path[..depth].to_vec(),
+                old: noun,
+                new: noun,
+            };
+            DiffError::NotACell(partial.axis_string())
+        })?;
+        spine.push(cell);
+        noun = match side {
+            Side::Head => cell.head(),
+            Side::Tail => cell.tail(),
+        };
+    }
+    let mut acc = new;
+    for (cell, side) in spine.into_iter().zip(path.iter()).rev() {
+        acc = match side {
+            Side::Head => Cell::new(allocator, acc, cell.tail()).as_noun(),
+            Side::Tail => Cell::new(allocator, cell.head(), acc).as_noun(),
+        };
+    }
+    Ok(acc)
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::Slots;
+    use nockvm_macros::tas;
+
+    use super::*;
+    use crate::noun::slab::NounSlab;
+
+    #[test]
+    fn test_diff_equal() {
+        let mut slab: NounSlab = NounSlab::new();
+        let a = T(&mut slab, &[D(tas!(b"state")), D(1), D(2)]);
+        let b = T(&mut slab, &[D(tas!(b"state")), D(1), D(2)]);
+        assert!(diff(a, b).is_empty());
+        assert_eq!(diff(a, b).to_string(), "no differences");
+    }
+
+    #[test]
+    fn test_diff_apply() {
+        let mut slab: NounSlab = NounSlab::new();
+        let unchanged = T(&mut slab, &[D(10), D(11), D(12)]);
+        let old_inner = T(&mut slab, &[D(1), D(2)]);
+        let new_inner = T(&mut slab, &[D(1), D(3), D(4)]);
+        let old = T(&mut slab, &[unchanged, old_inner, D(5)]);
+        let new = T(&mut slab, &[unchanged, new_inner, D(6)]);
+
+        let delta = diff(old, new);
+        let axes: Vec<_> = delta.edits.iter().map(|e| e.axis()).collect();
+        assert_eq!(axes, vec![Some(13), Some(7)]);
+        assert_eq!(delta.to_string(), "+13: 2 -> [3 4]\n+7: 5 -> 6");
+
+        let applied = delta.apply(&mut slab, old).expect("apply");
+        assert!(slab_noun_equality(&applied, &new));
+        // Untouched subtrees are shared, not copied
+        let head = applied.slot(2).expect("head");
+        assert!(unsafe { head.raw_equals(&unchanged) });
+
+        let encoded = delta.to_noun(&mut slab);
+        let first = T(&mut slab, &[D(13), new_inner.slot(3).expect("tail")]);
+        let second = T(&mut slab, &[D(7), D(6)]);
+        let expected = T(&mut slab, &[first, second, D(0)]);
+        assert!(slab_noun_equality(&encoded, &expected));
+
+        let patched = patch(&mut slab, old, encoded).expect("patch");
+        assert!(slab_noun_equality(&patched, &new));
+        assert!(matches!(
+            patch(&mut slab, old, D(5)),
+            Err(DiffError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn test_apply_to_wrong_noun() {
+        let mut slab: NounSlab = NounSlab::new();
+        let old = T(&mut slab, &[D(1), D(2), D(3)]);
+        let new = T(&mut slab, &[D(1), D(2), D(4)]);
+        let delta = diff(old, new);
+        assert!(matches!(
+            delta.apply(&mut slab, D(0)),
+            Err(DiffError::NotACell(axis)) if axis == "1"
+        ));
+    }
+}
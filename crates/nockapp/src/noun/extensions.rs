@@ -8,6 +8,7 @@ use nockvm::mem::NockStack;
 use nockvm::noun::{Atom, IndirectAtom, NounAllocator, D};
 use nockvm::serialization::{cue, jam};
 
+use crate::noun::path::{slot_path, PathError};
 use crate::noun::slab::NounSlab;
 use crate::{Noun, Result, ToBytes, ToBytesExt};
 
@@ -17,6 +18,7 @@ pub trait NounExt {
     fn jam_self(self, stack: &mut NockStack) -> JammedNoun;
     fn list_iter(self) -> impl Iterator<Item = Noun>;
     fn eq_bytes(self, bytes: impl AsRef<[u8]>) -> bool;
+    fn slot_path(self, path: &str) -> Result<Noun, PathError>;
 }
 
 impl NounExt for Noun {
@@ -49,6 +51,10 @@ impl NounExt for Noun {
             false
         }
     }
+
+    fn slot_path(self, path: &str) -> Result<Noun, PathError> {
+        slot_path(self, path)
+    }
 }
 
 // TODO: This exists largely because nockapp doesn't own the [`Atom`] type from [`nockvm`].
@@ -0,0 +1,316 @@
+//! Conversion between JSON text and the kernel-side `json` noun.
+//!
+//! The encoding matches the `json` mold from `zuse`:
+//!
+//! ```hoon
+//! +$  json
+//!   $@  ~
+//!   $%  [%a p=(list json)]
+//!       [%b p=?]
+//!       [%o p=(map @t json)]
+//!       [%n p=@ta]
+//!       [%s p=@t]
+//!   ==
+//! ```
+//!
+//! Objects are built as real Hoon maps (treaps ordered by `+gor` and balanced by `+mor`), so a
+//! noun produced here is identical to the one the kernel would get from `+de:json:html`.
+use std::cmp::Ordering;
+
+use either::Either;
+use nockvm::mug::calc_atom_mug_u32;
+use nockvm::noun::{Atom, Cell, DirectAtom, IndirectAtom, Noun, NounAllocator, D, NO, T, YES};
+use nockvm_macros::tas;
+use serde_json::{Map, Number, Value};
+use thiserror::Error;
+
+use crate::noun::slab::NounSlab;
+
+#[derive(Debug, Error)]
+pub enum JsonNounError {
+    #[error("json: parse error: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("json: expected a json noun")]
+    NotJson,
+    #[error("json: unknown tag {0:#x}")]
+    UnknownTag(u64),
+    #[error("json: invalid utf-8 in cord")]
+    InvalidUtf8,
+    #[error("json: invalid number {0:?}")]
+    InvalidNumber(String),
+    #[error("json: improper list")]
+    ImproperList,
+    #[error("json: malformed map")]
+    MalformedMap,
+}
+
+pub type Result<T, E = JsonNounError> = std::result::Result<T, E>;
+
+/// Parse JSON text into a `json` noun.
+pub fn parse_json<A: NounAllocator>(allocator: &mut A, text: &str) -> Result<Noun> {
+    let value: Value = serde_json::from_str(text)?;
+    Ok(json_to_noun(allocator, &value))
+}
+
+/// Render a `json` noun as compact JSON text.
+pub fn print_json(noun: Noun) -> Result<String> {
+    let value = noun_to_json(noun)?;
+    Ok(serde_json::to_string(&value)?)
+}
+
+/// Convert a [`serde_json::Value`] into a `json` noun.
+pub fn json_to_noun<A: NounAllocator>(allocator: &mut A, value: &Value) -> Noun {
+    match value {
+        Value::Null => D(0),
+        Value::Bool(b) => T(allocator, &[D(tas!(b"b")), if *b { YES } else { NO }]),
+        Value::Number(n) => {
+            let text = cord(allocator, n.to_string().as_bytes()).as_noun();
+            T(allocator, &[D(tas!(b"n")), text])
+        }
+        Value::String(s) => {
+            let text = cord(allocator, s.as_bytes()).as_noun();
+            T(allocator, &[D(tas!(b"s")), text])
+        }
+        Value::Array(items) => {
+            let mut list = D(0);
+            for item in items.iter().rev() {
+                let item = json_to_noun(allocator, item);
+                list = Cell::new(allocator, item, list).as_noun();
+            }
+            T(allocator, &[D(tas!(b"a")), list])
+        }
+        Value::Object(map) => {
+            let mut entries = Vec::with_capacity(map.len());
+            for (key, value) in map {
+                let key = cord(allocator, key.as_bytes());
+                let value = json_to_noun(allocator, value);
+                entries.push(MapEntry::new(key, value));
+            }
+            entries.sort_by(|a, b| a.gor(b));
+            let tree = treap(allocator, &entries);
+            T(allocator, &[D(tas!(b"o")), tree])
+        }
+    }
+}
+
+/// Convert a `json` noun into a [`serde_json::Value`].
+pub fn noun_to_json(noun: Noun) -> Result<Value> {
+    let cell = match noun.as_either_atom_cell() {
+        Either::Left(atom) if is_null(atom) => return Ok(Value::Null),
+        Either::Left(_) => return Err(JsonNounError::NotJson),
+        Either::Right(cell) => cell,
+    };
+    let tag = cell
+        .head()
+        .as_direct()
+        .map_err(|_| JsonNounError::NotJson)?
+        .data();
+    let body = cell.tail();
+    match tag {
+        tas!(b"a") => {
+            let mut items = Vec::new();
+            let mut list = body;
+            loop {
+                match list.as_either_atom_cell() {
+                    Either::Left(atom) if is_null(atom) => break,
+                    Either::Left(_) => return Err(JsonNounError::ImproperList),
+                    Either::Right(item) => {
+                        items.push(noun_to_json(item.head())?);
+                        list = item.tail();
+                    }
+                }
+            }
+            Ok(Value::Array(items))
+        }
+        tas!(b"b") => match body.as_direct().map(|d| d.data()) {
+            Ok(0) => Ok(Value::Bool(true)),
+            Ok(1) => Ok(Value::Bool(false)),
+            _ => Err(JsonNounError::NotJson),
+        },
+        tas!(b"n") => {
+            let text = text(body)?;
+            let number = text
+                .parse::<Number>()
+                .map_err(|_| JsonNounError::InvalidNumber(text.clone()))?;
+            Ok(Value::Number(number))
+        }
+        tas!(b"s") => Ok(Value::String(text(body)?)),
+        tas!(b"o") => {
+            let mut map = Map::new();
+            collect_map(body, &mut map)?;
+            Ok(Value::Object(map))
+        }
+        other => Err(JsonNounError::UnknownTag(other)),
+    }
+}
+
+impl<J> NounSlab<J> {
+    /// Make a new slab whose root is the `json` noun parsed from `text`.
+    pub fn from_json_str(text: &str) -> Result<Self> {
+        let mut slab = Self::new();
+        let root = parse_json(&mut slab, text)?;
+        slab.set_root(root);
+        Ok(slab)
+    }
+
+    /// Render the root of this slab, which must be a `json` noun, as JSON text.
+    pub fn to_json_string(&self) -> Result<String> {
+        print_json(unsafe { *self.root() })
+    }
+}
+
+fn is_null(atom: Atom) -> bool {
+    unsafe { atom.as_noun().raw_equals(&D(0)) }
+}
+
+fn cord<A: NounAllocator>(allocator: &mut A, bytes: &[u8]) -> Atom {
+    if bytes.is_empty() {
+        return unsafe { DirectAtom::new_unchecked(0).as_atom() };
+    }
+    unsafe {
+        IndirectAtom::new_raw_bytes(allocator, bytes.len(), bytes.as_ptr()).normalize_as_atom()
+    }
+}
+
+fn text(noun: Noun) -> Result<String> {
+    let atom = noun.as_atom().map_err(|_| JsonNounError::NotJson)?;
+    let bytes = significant_bytes(&atom);
+    String::from_utf8(bytes.to_vec()).map_err(|_| JsonNounError::InvalidUtf8)
+}
+
+/// The little-endian bytes of an atom without trailing zeroes.
+fn significant_bytes(atom: &Atom) -> &[u8] {
+    let bytes = atom.as_ne_bytes();
+    let end = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    &bytes[..end]
+}
+
+/// Numeric comparison of two atoms, i.e. `+dor` restricted to atoms.
+fn dor_atoms(a: Atom, b: Atom) -> Ordering {
+    let a = significant_bytes(&a);
+    let b = significant_bytes(&b);
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn collect_map(tree: Noun, map: &mut Map<String, Value>) -> Result<()> {
+    let mut stack = vec![tree];
+    while let Some(node) = stack.pop() {
+        let node = match node.as_either_atom_cell() {
+            Either::Left(atom) if is_null(atom) => continue,
+            Either::Left(_) => return Err(JsonNounError::MalformedMap),
+            Either::Right(node) => node,
+        };
+        let pair = node
+            .head()
+            .as_cell()
+            .map_err(|_| JsonNounError::MalformedMap)?;
+        let children = node
+            .tail()
+            .as_cell()
+            .map_err(|_| JsonNounError::MalformedMap)?;
+        map.insert(text(pair.head())?, noun_to_json(pair.tail())?);
+        stack.push(children.tail());
+        stack.push(children.head());
+    }
+    Ok(())
+}
+
+struct MapEntry {
+    key: Atom,
+    value: Noun,
+    mug: u32,
+    priority: u32,
+}
+
+impl MapEntry {
+    fn new(key: Atom, value: Noun) -> Self {
+        let mug = calc_atom_mug_u32(key);
+        let priority =
+            calc_atom_mug_u32(unsafe { DirectAtom::new_unchecked(mug as u64).as_atom() });
+        MapEntry {
+            key,
+            value,
+            mug,
+            priority,
+        }
+    }
+
+    /// `+gor`: order by mug, then by value.
+    fn gor(&self, other: &Self) -> Ordering {
+        self.mug
+            .cmp(&other.mug)
+            .then_with(|| dor_atoms(self.key, other.key))
+    }
+
+    /// `+mor`: order by double mug, then by value. Lesser entries sit higher in the treap.
+    fn mor(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| dor_atoms(self.key, other.key))
+    }
+}
+
+/// Build the unique treap over `entries`, which must already be sorted by `+gor`.
+fn treap<A: NounAllocator>(allocator: &mut A, entries: &[MapEntry]) -> Noun {
+    let Some((top, _)) = entries.iter().enumerate().min_by(|(_, a), (_, b)| a.mor(b)) else {
+        return D(0);
+    };
+    let left = treap(allocator, &entries[..top]);
+    let right = treap(allocator, &entries[top + 1..]);
+    let entry = &entries[top];
+    let pair = T(allocator, &[entry.key.as_noun(), entry.value]);
+    T(allocator, &[pair, left, right])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noun::slab::slab_noun_equality;
+
+    #[test]
+    fn test_json_roundtrip() {
+        let text = r#"{"name":"nockchain","height":12345,"ratio":-0.5,"ok":true,"none":null,"tags":["a","b",""],"nested":{"x":{"y":[1,2,3]}},"a":1,"b":2,"c":3,"d":4}"#;
+        let slab: NounSlab = NounSlab::from_json_str(text).expect("parse");
+        let printed = slab.to_json_string().expect("print");
+        let expected: Value = serde_json::from_str(text).expect("parse");
+        let actual: Value = serde_json::from_str(&printed).expect("parse");
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_json_shapes() {
+        let mut slab: NounSlab = NounSlab::new();
+        let noun = parse_json(&mut slab, r#"[true, "hi", 7]"#).expect("parse");
+        let b = T(&mut slab, &[D(tas!(b"b")), YES]);
+        let s = T(&mut slab, &[D(tas!(b"s")), D(tas!(b"hi"))]);
+        let n = T(&mut slab, &[D(tas!(b"n")), D(tas!(b"7"))]);
+        let expected = T(&mut slab, &[D(tas!(b"a")), b, s, n, D(0)]);
+        assert!(slab_noun_equality(&noun, &expected));
+
+        let noun = parse_json(&mut slab, r#"{"k":null}"#).expect("parse");
+        let pair = T(&mut slab, &[D(tas!(b"k")), D(0)]);
+        let expected = T(&mut slab, &[D(tas!(b"o")), pair, D(0), D(0)]);
+        assert!(slab_noun_equality(&noun, &expected));
+    }
+
+    #[test]
+    fn test_map_is_order_independent() {
+        let mut slab: NounSlab = NounSlab::new();
+        let a = parse_json(&mut slab, r#"{"one":1,"two":2,"three":3,"four":4}"#).expect("parse");
+        let b = parse_json(&mut slab, r#"{"four":4,"three":3,"two":2,"one":1}"#).expect("parse");
+        assert!(slab_noun_equality(&a, &b));
+    }
+
+    #[test]
+    fn test_bad_json_noun() {
+        assert!(matches!(noun_to_json(D(5)), Err(JsonNounError::NotJson)));
+        let mut slab: NounSlab = NounSlab::new();
+        let bad = T(&mut slab, &[D(tas!(b"q")), D(0)]);
+        assert!(matches!(
+            noun_to_json(bad),
+            Err(JsonNounError::UnknownTag(_))
+        ));
+    }
+}
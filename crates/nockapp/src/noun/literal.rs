@@ -0,0 +1,272 @@
+//! Noun literals, for typing nouns by hand.
+//!
+//! [`parse_noun`] reads the syntax [`PrettyNoun`](crate::noun::pretty::PrettyNoun) prints, so a
+//! noun copied out of a log or a REPL response can be pasted back in:
+//!
+//! | Literal              | Noun                                          |
+//! |----------------------|-----------------------------------------------|
+//! | `42`, `1.000`        | a decimal atom, with optional dot grouping    |
+//! | `0xdead.beef`        | a hex atom                                    |
+//! | `%tag`               | a `@tas`                                      |
+//! | `'text'`             | a cord; `\0a` is a byte, `\\` and `\'` escape |
+//! | `"text"`             | a tape, the null-terminated list of its bytes |
+//! | `~`                  | `0`                                           |
+//! | `%.y`, `%.n`         | the loobeans `0` and `1`                      |
+//! | `[a b c]`            | the cell `[a [b c]]`                          |
+//! | `~[a b]`             | the list `[a b ~]`                            |
+use ibig::UBig;
+use nockvm::noun::{Atom, Noun, NounAllocator, D, T};
+use thiserror::Error;
+
+use crate::{AtomExt, Bytes};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LiteralError {
+    #[error("literal: unexpected end of input")]
+    End,
+    #[error("literal: unexpected {found:?} at {at}")]
+    Unexpected { at: usize, found: char },
+    #[error("literal: bad number {0:?}")]
+    BadNumber(String),
+    #[error("literal: bad escape {0:?}")]
+    BadEscape(String),
+    #[error("literal: a cell needs at least two items")]
+    ShortCell,
+}
+
+pub type Result<T, E = LiteralError> = std::result::Result<T, E>;
+
+/// Parse a noun literal, allocating atoms and cells in `allocator`.
+pub fn parse_noun<A: NounAllocator>(allocator: &mut A, literal: &str) -> Result<Noun> {
+    let mut parser = Parser {
+        input: literal,
+        pos: 0,
+    };
+    let noun = parser.noun(allocator)?;
+    parser.skip_space();
+    match parser.peek() {
+        None => Ok(noun),
+        Some(found) => Err(LiteralError::Unexpected {
+            at: parser.pos,
+            found,
+        }),
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Result<char> {
+        let c = self.peek().ok_or(LiteralError::End)?;
+        self.pos += c.len_utf8();
+        Ok(c)
+    }
+
+    fn eat(&mut self, prefix: &str) -> bool {
+        if self.input[self.pos..].starts_with(prefix) {
+            self.pos += prefix.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_space(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    /// The run of characters matching `f`, which may be empty
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &str {
+        let start = self.pos;
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+        &self.input[start..self.pos]
+    }
+
+    fn unexpected(&self) -> LiteralError {
+        match self.peek() {
+            Some(found) => LiteralError::Unexpected {
+                at: self.pos,
+                found,
+            },
+            None => LiteralError::End,
+        }
+    }
+
+    fn noun<A: NounAllocator>(&mut self, allocator: &mut A) -> Result<Noun> {
+        self.skip_space();
+        if self.eat("~[") {
+            let mut items = self.items(allocator)?;
+            items.push(D(0));
+            return Ok(tuple(allocator, &items));
+        }
+        if self.eat("[") {
+            let items = self.items(allocator)?;
+            if items.len() < 2 {
+                return Err(LiteralError::ShortCell);
+            }
+            return Ok(tuple(allocator, &items));
+        }
+        if self.eat("%.y") {
+            return Ok(D(0));
+        }
+        if self.eat("%.n") {
+            return Ok(D(1));
+        }
+        match self.peek() {
+            Some('~') => {
+                self.pos += 1;
+                Ok(D(0))
+            }
+            Some('%') => {
+                self.pos += 1;
+                let term =
+                    self.take_while(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+                if !term.starts_with(|c: char| c.is_ascii_lowercase()) {
+                    return Err(self.unexpected());
+                }
+                Ok(atom(allocator, term.as_bytes()))
+            }
+            Some('\'') => {
+                self.pos += 1;
+                let bytes = self.text('\'')?;
+                Ok(atom(allocator, &bytes))
+            }
+            Some('"') => {
+                self.pos += 1;
+                let mut items: Vec<Noun> =
+                    self.text('"')?.into_iter().map(|b| D(b as u64)).collect();
+                items.push(D(0));
+                Ok(tuple(allocator, &items))
+            }
+            Some(c) if c.is_ascii_digit() => self.number(allocator),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Whitespace-separated nouns up to a closing `]`
+    fn items<A: NounAllocator>(&mut self, allocator: &mut A) -> Result<Vec<Noun>> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_space();
+            if self.eat("]") {
+                return Ok(items);
+            }
+            if self.peek().is_none() {
+                return Err(LiteralError::End);
+            }
+            items.push(self.noun(allocator)?);
+        }
+    }
+
+    /// The bytes of a quoted string, after its opening quote
+    fn text(&mut self, quote: char) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        loop {
+            match self.bump()? {
+                c if c == quote => return Ok(bytes),
+                '\\' => match self.bump()? {
+                    c if c == quote || c == '\\' => bytes.push(c as u8),
+                    high => {
+                        let low = self.bump()?;
+                        let hex: String = [high, low].iter().collect();
+                        let byte = u8::from_str_radix(&hex, 16)
+                            .map_err(|_| LiteralError::BadEscape(format!("\\{}", hex)))?;
+                        bytes.push(byte);
+                    }
+                },
+                c => {
+                    let mut buf = [0u8; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+    }
+
+    fn number<A: NounAllocator>(&mut self, allocator: &mut A) -> Result<Noun> {
+        let (radix, digits) = if self.eat("0x") {
+            (16, self.take_while(|c| c.is_ascii_hexdigit() || c == '.'))
+        } else {
+            (10, self.take_while(|c| c.is_ascii_digit() || c == '.'))
+        };
+        let literal = digits.to_string();
+        let digits = literal.replace('.', "");
+        if digits.is_empty() || literal.starts_with('.') || literal.ends_with('.') {
+            return Err(LiteralError::BadNumber(literal));
+        }
+        let value =
+            UBig::from_str_radix(&digits, radix).map_err(|_| LiteralError::BadNumber(literal))?;
+        Ok(atom(allocator, &value.to_le_bytes()))
+    }
+}
+
+/// The atom with little-endian `bytes`
+fn atom<A: NounAllocator>(allocator: &mut A, bytes: &[u8]) -> Noun {
+    if bytes.iter().all(|b| *b == 0) {
+        return D(0);
+    }
+    Atom::from_bytes(allocator, &Bytes::copy_from_slice(bytes)).as_noun()
+}
+
+fn tuple<A: NounAllocator>(allocator: &mut A, items: &[Noun]) -> Noun {
+    if items.len() == 1 {
+        items[0]
+    } else {
+        T(allocator, items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noun::pretty::PrettyNoun;
+    use crate::noun::slab::NounSlab;
+
+    fn round_trip(literal: &str) -> String {
+        let mut slab: NounSlab = NounSlab::new();
+        let noun = parse_noun(&mut slab, literal).unwrap();
+        PrettyNoun::new(noun).to_string()
+    }
+
+    #[test]
+    fn test_parse_noun() {
+        for literal in [
+            "0", "1.000.000", "%block", "'Hi you'", "\"hello\"", "[%req 1 2]", "~[1 2 3]",
+            "[[1 2] %a-b 3]", "[~[%ab %cd] 4]", "18.446.744.073.709.551.616",
+            "0x1.0000.0000.0000.0000.0000.0000.0000.0000", "'it\\'s\\0a'",
+        ] {
+            assert_eq!(round_trip(literal), literal);
+        }
+        assert_eq!(round_trip("[1 ~]"), "~[1]");
+        assert_eq!(round_trip("[%.y %.n]"), "[0 1]");
+        assert_eq!(round_trip("  [ 1   2 ]  "), "[1 2]");
+        assert_eq!(round_trip("0xff"), "255");
+        assert_eq!(round_trip("'a'"), "97");
+        assert_eq!(round_trip("~[]"), "0");
+
+        let mut slab: NounSlab = NounSlab::new();
+        let mut parse = |literal| parse_noun(&mut slab, literal).map(|_| ());
+        assert_eq!(parse("[1]"), Err(LiteralError::ShortCell));
+        assert_eq!(parse("[1 2"), Err(LiteralError::End));
+        assert_eq!(parse("1."), Err(LiteralError::BadNumber("1.".into())));
+        assert_eq!(
+            parse("1 2"),
+            Err(LiteralError::Unexpected { at: 2, found: '2' })
+        );
+        assert_eq!(
+            parse("%Tag"),
+            Err(LiteralError::Unexpected { at: 1, found: 'T' })
+        );
+        assert_eq!(parse("'\\zz'"), Err(LiteralError::BadEscape("\\zz".into())));
+    }
+}
@@ -1,5 +1,16 @@
+#[cfg(any(test, feature = "quickcheck"))]
+pub mod arbitrary;
+pub mod cbor;
+pub mod diff;
 mod extensions;
+pub mod json;
+pub mod literal;
 mod ops;
+pub mod path;
+pub mod pool;
+pub mod pretty;
+pub mod scry;
+pub mod serde;
 pub mod slab;
 pub use extensions::*;
 pub use ops::*;
@@ -0,0 +1,285 @@
+//! Named paths into nouns and a typed destructuring builder.
+//!
+//! [`slot_path`] looks up a subtree by a dotted path such as `"tail.head.3"`, where each segment
+//! is `head`, `tail`, or a Nock axis relative to the previous segment. [`Fields`] walks a
+//! right-associated tuple one named element at a time, converting each into a Rust type:
+//!
+//! ```ignore
+//! let mut fields = Fields::new(effect);
+//! fields.tag(b"file")?;
+//! fields.tag(b"write")?;
+//! let path: String = fields.next("path")?;
+//! let contents: Atom = fields.last("contents")?;
+//! ```
+//!
+//! Both report failures with the path to the offending element.
+use nockvm::noun::{Atom, Cell, Noun, Slots, D};
+use thiserror::Error;
+
+use crate::AtomExt;
+
+#[derive(Debug, Error)]
+pub enum PathError {
+    #[error("path {path:?}: bad segment {segment:?}, expected head, tail, or an axis")]
+    BadSegment { path: String, segment: String },
+    #[error("path {path:?}: no noun at segment {segment:?}")]
+    Missing { path: String, segment: String },
+    #[error("{at}: expected {expected}")]
+    Type { at: String, expected: &'static str },
+    #[error("{at}: expected %{expected}")]
+    Tag { at: String, expected: String },
+}
+
+pub type Result<T, E = PathError> = std::result::Result<T, E>;
+
+/// Look up the subtree of `noun` at a dotted path.
+///
+/// Segments are `head`, `tail`, or a positive axis, each applied to the result of the previous
+/// one. The empty path selects `noun` itself.
+pub fn slot_path(noun: Noun, path: &str) -> Result<Noun> {
+    let mut noun = noun;
+    if path.is_empty() {
+        return Ok(noun);
+    }
+    for segment in path.split('.') {
+        let axis = match segment {
+            "head" => 2,
+            "tail" => 3,
+            _ => match segment.parse::<u64>() {
+                Ok(axis) if axis > 0 => axis,
+                _ => {
+                    return Err(PathError::BadSegment {
+                        path: path.to_string(),
+                        segment: segment.to_string(),
+                    })
+                }
+            },
+        };
+        noun = noun.slot(axis).map_err(|_| PathError::Missing {
+            path: path.to_string(),
+            segment: segment.to_string(),
+        })?;
+    }
+    Ok(noun)
+}
+
+/// A Rust type that can be read out of a single noun field.
+pub trait FromField: Sized {
+    /// What the field must look like, for error messages
+    const EXPECTED: &'static str;
+    fn from_field(noun: Noun) -> Option<Self>;
+}
+
+impl FromField for Noun {
+    const EXPECTED: &'static str = "a noun";
+    fn from_field(noun: Noun) -> Option<Self> {
+        Some(noun)
+    }
+}
+
+impl FromField for Atom {
+    const EXPECTED: &'static str = "an atom";
+    fn from_field(noun: Noun) -> Option<Self> {
+        noun.as_atom().ok()
+    }
+}
+
+impl FromField for Cell {
+    const EXPECTED: &'static str = "a cell";
+    fn from_field(noun: Noun) -> Option<Self> {
+        noun.as_cell().ok()
+    }
+}
+
+impl FromField for u64 {
+    const EXPECTED: &'static str = "an atom of at most 64 bits";
+    fn from_field(noun: Noun) -> Option<Self> {
+        noun.as_atom().ok()?.as_u64().ok()
+    }
+}
+
+impl FromField for bool {
+    const EXPECTED: &'static str = "a loobean";
+    fn from_field(noun: Noun) -> Option<Self> {
+        match noun.as_direct().ok()?.data() {
+            0 => Some(true),
+            1 => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl FromField for String {
+    const EXPECTED: &'static str = "a utf-8 cord";
+    fn from_field(noun: Noun) -> Option<Self> {
+        let atom = noun.as_atom().ok()?;
+        let text = std::str::from_utf8(atom.as_ne_bytes()).ok()?;
+        Some(text.trim_end_matches('\0').to_string())
+    }
+}
+
+impl<T: FromField> FromField for Option<T> {
+    const EXPECTED: &'static str = "a unit";
+    fn from_field(noun: Noun) -> Option<Self> {
+        if unsafe { noun.raw_equals(&D(0)) } {
+            return Some(None);
+        }
+        let cell = noun.as_cell().ok()?;
+        if !unsafe { cell.head().raw_equals(&D(0)) } {
+            return None;
+        }
+        T::from_field(cell.tail()).map(Some)
+    }
+}
+
+/// Destructure a right-associated tuple `[a b c ...]` one named element at a time.
+pub struct Fields {
+    rest: Noun,
+    at: Vec<String>,
+}
+
+impl Fields {
+    pub fn new(noun: Noun) -> Self {
+        Fields {
+            rest: noun,
+            at: Vec::new(),
+        }
+    }
+
+    /// Prefix error paths with `name`, e.g. when destructuring a nested tuple.
+    pub fn named(mut self, name: &str) -> Self {
+        self.at.insert(0, name.to_string());
+        self
+    }
+
+    fn at(&self, name: &str) -> String {
+        let mut at = self.at.join(".");
+        if !at.is_empty() {
+            at.push('.');
+        }
+        at.push_str(name);
+        at
+    }
+
+    fn head(&mut self, name: &str) -> Result<Noun> {
+        let cell = self.rest.as_cell().map_err(|_| PathError::Type {
+            at: self.at(name),
+            expected: "another tuple element",
+        })?;
+        self.rest = cell.tail();
+        Ok(cell.head())
+    }
+
+    /// Take the next element, which must not be the last.
+    pub fn next<T: FromField>(&mut self, name: &str) -> Result<T> {
+        let noun = self.head(name)?;
+        let value = T::from_field(noun).ok_or_else(|| PathError::Type {
+            at: self.at(name),
+            expected: T::EXPECTED,
+        });
+        self.at.push(name.to_string());
+        value
+    }
+
+    /// Take the next element, which must be the `@tas` `expected`.
+    pub fn tag(&mut self, expected: &[u8]) -> Result<()> {
+        let name = String::from_utf8_lossy(expected).into_owned();
+        let noun = self.head(&name)?;
+        let matches = noun
+            .as_atom()
+            .map(|atom| atom.eq_bytes(expected))
+            .unwrap_or(false);
+        if !matches {
+            return Err(PathError::Tag {
+                at: self.at(&name),
+                expected: name,
+            });
+        }
+        self.at.push(name);
+        Ok(())
+    }
+
+    /// Take everything that remains as the final element.
+    pub fn last<T: FromField>(self, name: &str) -> Result<T> {
+        T::from_field(self.rest).ok_or_else(|| PathError::Type {
+            at: self.at(name),
+            expected: T::EXPECTED,
+        })
+    }
+
+    /// The remaining tail, without checking it.
+    pub fn rest(&self) -> Noun {
+        self.rest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::{IndirectAtom, T, YES};
+    use nockvm_macros::tas;
+
+    use super::*;
+    use crate::noun::slab::NounSlab;
+
+    #[test]
+    fn test_slot_path() {
+        let mut slab: NounSlab = NounSlab::new();
+        let inner = T(&mut slab, &[D(4), D(5)]);
+        let noun = T(&mut slab, &[D(1), inner, D(6)]);
+        let at = |path: &str| {
+            slot_path(noun, path)
+                .expect("slot_path")
+                .as_direct()
+                .expect("direct")
+                .data()
+        };
+        assert_eq!(at("head"), 1);
+        assert_eq!(at("tail.head.tail"), 5);
+        assert_eq!(at("6.2"), 4);
+        assert_eq!(at("7"), 6);
+
+        let err = slot_path(noun, "tail.tail.head").expect_err("missing");
+        assert_eq!(
+            err.to_string(),
+            "path \"tail.tail.head\": no noun at segment \"head\""
+        );
+        assert!(matches!(
+            slot_path(noun, "tail.left"),
+            Err(PathError::BadSegment { segment, .. }) if segment == "left"
+        ));
+    }
+
+    #[test]
+    fn test_fields() {
+        let mut slab: NounSlab = NounSlab::new();
+        let path = unsafe {
+            IndirectAtom::new_raw_bytes_ref(&mut slab, b"a/b.txt")
+                .normalize_as_atom()
+                .as_noun()
+        };
+        let noun = T(
+            &mut slab,
+            &[D(tas!(b"file")), D(tas!(b"write")), path, D(42), YES],
+        );
+
+        let mut fields = Fields::new(noun);
+        fields.tag(b"file").expect("file");
+        fields.tag(b"write").expect("write");
+        let path: String = fields.next("path").expect("path");
+        let contents: u64 = fields.next("contents").expect("contents");
+        let success: bool = fields.last("success").expect("success");
+        assert_eq!(path, "a/b.txt");
+        assert_eq!(contents, 42);
+        assert!(success);
+
+        let mut fields = Fields::new(noun).named("effect");
+        fields.tag(b"file").expect("file");
+        let err = fields.tag(b"read").expect_err("wrong tag");
+        assert_eq!(err.to_string(), "effect.file.read: expected %read");
+
+        let mut fields = Fields::new(noun);
+        fields.tag(b"file").expect("file");
+        let err = fields.next::<Cell>("op").expect_err("not a cell");
+        assert_eq!(err.to_string(), "file.op: expected a cell");
+    }
+}
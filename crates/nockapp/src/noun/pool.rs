@@ -0,0 +1,107 @@
+//! A pool of reusable [`NounSlab`]s.
+//!
+//! Drivers typically receive an effect slab, build a fresh poke slab, and drop both. Recycling
+//! the effect slabs into a [`SlabPool`] and renting poke slabs from it lets the arena chunks be
+//! reused instead of going back to the system allocator for every poke.
+use std::sync::Mutex;
+
+use crate::noun::slab::NounSlab;
+
+const DEFAULT_MAX_POOLED: usize = 16;
+const DEFAULT_MAX_RETAINED_BYTES: usize = 64 << 20;
+
+pub struct SlabPool {
+    slabs: Mutex<Vec<NounSlab>>,
+    max_pooled: usize,
+    max_retained_bytes: usize,
+}
+
+impl Default for SlabPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_POOLED)
+    }
+}
+
+impl SlabPool {
+    /// Make a pool holding at most `max_pooled` idle slabs.
+    pub fn new(max_pooled: usize) -> Self {
+        SlabPool {
+            slabs: Mutex::new(Vec::with_capacity(max_pooled)),
+            max_pooled,
+            max_retained_bytes: DEFAULT_MAX_RETAINED_BYTES,
+        }
+    }
+
+    /// Slabs whose retained chunk is larger than this are dropped rather than pooled, so one
+    /// huge effect doesn't pin its memory forever.
+    pub fn max_retained_bytes(mut self, max_retained_bytes: usize) -> Self {
+        self.max_retained_bytes = max_retained_bytes;
+        self
+    }
+
+    /// Take an empty slab from the pool, or make a new one if the pool is empty.
+    pub fn rent(&self) -> NounSlab {
+        self.lock().pop().unwrap_or_default()
+    }
+
+    /// Reset `slab` and return it to the pool.
+    ///
+    /// The caller must not hold on to any [`nockvm::noun::Noun`] from `slab`.
+    pub fn recycle(&self, mut slab: NounSlab) {
+        slab.reset();
+        if slab.allocated_bytes() > self.max_retained_bytes {
+            return;
+        }
+        let mut slabs = self.lock();
+        if slabs.len() < self.max_pooled {
+            slabs.push(slab);
+        }
+    }
+
+    /// Number of idle slabs in the pool
+    pub fn idle(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<NounSlab>> {
+        // A panic while holding the lock can't leave the Vec in a bad state
+        self.slabs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::{D, T};
+
+    use super::*;
+
+    #[test]
+    fn test_rent_and_recycle() {
+        let pool = SlabPool::new(1);
+        let mut slab = pool.rent();
+        let noun = T(&mut slab, &[D(1), D(2)]);
+        slab.set_root(noun);
+        let capacity = slab.allocated_bytes();
+        pool.recycle(slab);
+        assert_eq!(pool.idle(), 1);
+
+        let slab = pool.rent();
+        assert_eq!(pool.idle(), 0);
+        assert_eq!(slab.allocated_bytes(), capacity);
+        assert!(unsafe { slab.root().raw_equals(&D(0)) });
+
+        pool.recycle(slab);
+        pool.recycle(NounSlab::new());
+        assert_eq!(pool.idle(), 1);
+    }
+
+    #[test]
+    fn test_large_slabs_are_dropped() {
+        let pool = SlabPool::new(4).max_retained_bytes(0);
+        let mut slab = pool.rent();
+        let noun = T(&mut slab, &[D(1), D(2)]);
+        slab.set_root(noun);
+        pool.recycle(slab);
+        assert_eq!(pool.idle(), 0);
+    }
+}
@@ -0,0 +1,324 @@
+//! Human-readable rendering for nouns and tanks.
+//!
+//! [`PrettyNoun`] guesses at the usual auras the way a Hoon developer would read a raw noun:
+//! `%tas` tags, `'cord'` text, `"tape"` strings, `~[list]` lists, and right-associated tuples
+//! `[a b c]`. Everything else is printed as `@ud` (with Hoon's dot grouping) or `@ux` for large
+//! atoms. Depth and width limits keep huge nouns from flooding logs.
+//!
+//! [`render_tank`] flattens a `tank` onto one line the same way the slogger does, without needing
+//! a [`nockvm::mem::NockStack`].
+use std::fmt::{self, Display, Write};
+
+use either::Either;
+use nockvm::noun::{Atom, Cell, Noun, Slots, D};
+use nockvm_macros::tas;
+
+use crate::noun::slab::NounSlab;
+
+const DEFAULT_MAX_DEPTH: usize = 32;
+const DEFAULT_MAX_WIDTH: usize = 64;
+const DEFAULT_MAX_ATOM_BYTES: usize = 256;
+
+/// A [`Display`] adapter for a noun.
+#[derive(Clone, Copy)]
+pub struct PrettyNoun {
+    noun: Noun,
+    max_depth: usize,
+    max_width: usize,
+    max_atom_bytes: usize,
+}
+
+impl PrettyNoun {
+    pub fn new(noun: Noun) -> Self {
+        PrettyNoun {
+            noun,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_width: DEFAULT_MAX_WIDTH,
+            max_atom_bytes: DEFAULT_MAX_ATOM_BYTES,
+        }
+    }
+
+    /// Cells nested deeper than this are printed as `...`.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Tuples, lists, and text longer than this are cut off with `...`.
+    pub fn max_width(mut self, max_width: usize) -> Self {
+        self.max_width = max_width;
+        self
+    }
+
+    /// Atoms larger than this many bytes are elided.
+    pub fn max_atom_bytes(mut self, max_atom_bytes: usize) -> Self {
+        self.max_atom_bytes = max_atom_bytes;
+        self
+    }
+
+    fn write_noun(&self, f: &mut fmt::Formatter<'_>, noun: Noun, depth: usize) -> fmt::Result {
+        match noun.as_either_atom_cell() {
+            Either::Left(atom) => self.write_atom(f, atom),
+            Either::Right(cell) => {
+                if depth >= self.max_depth {
+                    return f.write_str("[...]");
+                }
+                if let Some(text) = as_tape(cell) {
+                    return self.write_text(f, '"', &text);
+                }
+                let (items, tail) = flatten(cell);
+                let is_list = unsafe { tail.raw_equals(&D(0)) };
+                f.write_str(if is_list { "~[" } else { "[" })?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(' ')?;
+                    }
+                    if i >= self.max_width {
+                        f.write_str("...")?;
+                        return f.write_char(']');
+                    }
+                    self.write_noun(f, *item, depth + 1)?;
+                }
+                if !is_list {
+                    f.write_char(' ')?;
+                    self.write_noun(f, tail, depth + 1)?;
+                }
+                f.write_char(']')
+            }
+        }
+    }
+
+    fn write_atom(&self, f: &mut fmt::Formatter<'_>, atom: Atom) -> fmt::Result {
+        let bytes = significant_bytes(&atom);
+        if bytes.len() > self.max_atom_bytes {
+            return write!(f, "<{} byte atom>", bytes.len());
+        }
+        if bytes.len() > 1 {
+            if is_term(bytes) {
+                f.write_char('%')?;
+                // is_term guarantees ASCII
+                return f.write_str(std::str::from_utf8(bytes).unwrap_or_default());
+            }
+            if let Ok(text) = std::str::from_utf8(bytes) {
+                if text.chars().all(|c| !c.is_control() || c == '\n') {
+                    return self.write_text(f, '\'', text);
+                }
+            }
+        }
+        if bytes.len() <= 16 {
+            let mut buf = [0u8; 16];
+            buf[..bytes.len()].copy_from_slice(bytes);
+            write_dotted(f, &u128::from_le_bytes(buf).to_string(), 3)
+        } else {
+            let hex: String = bytes.iter().rev().map(|b| format!("{:02x}", b)).collect();
+            let hex = hex.trim_start_matches('0');
+            f.write_str("0x")?;
+            write_dotted(f, hex, 4)
+        }
+    }
+
+    fn write_text(&self, f: &mut fmt::Formatter<'_>, quote: char, text: &str) -> fmt::Result {
+        f.write_char(quote)?;
+        for (i, c) in text.chars().enumerate() {
+            if i >= self.max_width {
+                f.write_str("...")?;
+                break;
+            }
+            match c {
+                '\n' => f.write_str("\\0a")?,
+                '\\' => f.write_str("\\\\")?,
+                c if c == quote => {
+                    f.write_char('\\')?;
+                    f.write_char(c)?;
+                }
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char(quote)
+    }
+}
+
+impl Display for PrettyNoun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_noun(f, self.noun, 0)
+    }
+}
+
+impl<J> Display for NounSlab<J> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        PrettyNoun::new(unsafe { *self.root() }).fmt(f)
+    }
+}
+
+/// Render a `tank` on a single line.
+///
+/// Returns `None` if the noun is not a well-formed tank.
+pub fn render_tank(tank: Noun) -> Option<String> {
+    let mut out = String::new();
+    write_tank(&mut out, tank)?;
+    Some(out)
+}
+
+fn write_tank(out: &mut String, tank: Noun) -> Option<()> {
+    match tank.as_either_atom_cell() {
+        Either::Left(cord) => {
+            out.push_str(&String::from_utf8_lossy(significant_bytes(&cord)));
+            Some(())
+        }
+        Either::Right(cell) => match cell.head().as_direct().ok()?.data() {
+            tas!(b"leaf") => write_tape(out, cell.tail()),
+            tas!(b"palm") => {
+                let style = tank.slot(6).ok()?;
+                let mid = style.slot(2).ok()?;
+                write_tape(out, style.slot(6).ok()?)?;
+                write_tape(out, style.slot(14).ok()?)?;
+                write_tanks(out, tank.slot(7).ok()?, mid)?;
+                write_tape(out, style.slot(15).ok()?)
+            }
+            tas!(b"rose") => {
+                let style = tank.slot(6).ok()?;
+                let mid = style.slot(2).ok()?;
+                write_tape(out, style.slot(6).ok()?)?;
+                write_tanks(out, tank.slot(7).ok()?, mid)?;
+                write_tape(out, style.slot(7).ok()?)
+            }
+            _ => None,
+        },
+    }
+}
+
+fn write_tanks(out: &mut String, mut tanks: Noun, mid: Noun) -> Option<()> {
+    while let Ok(cell) = tanks.as_cell() {
+        write_tank(out, cell.head())?;
+        tanks = cell.tail();
+        if tanks.is_cell() {
+            write_tape(out, mid)?;
+        }
+    }
+    Some(())
+}
+
+fn write_tape(out: &mut String, mut tape: Noun) -> Option<()> {
+    let mut bytes = Vec::new();
+    while let Ok(cell) = tape.as_cell() {
+        let byte = cell.head().as_direct().ok()?.data();
+        bytes.push(u8::try_from(byte).ok()?);
+        tape = cell.tail();
+    }
+    out.push_str(&String::from_utf8_lossy(&bytes));
+    Some(())
+}
+
+fn significant_bytes(atom: &Atom) -> &[u8] {
+    let bytes = atom.as_ne_bytes();
+    let end = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    &bytes[..end]
+}
+
+/// Whether the bytes form a valid `@tas`: lowercase letters, digits, and hyphens, starting with a
+/// letter.
+fn is_term(bytes: &[u8]) -> bool {
+    bytes[0].is_ascii_lowercase()
+        && bytes
+            .iter()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || *b == b'-')
+}
+
+/// A null-terminated list of printable bytes, at least two long.
+fn as_tape(cell: Cell) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut noun = cell.as_noun();
+    while let Ok(cell) = noun.as_cell() {
+        let byte = cell.head().as_direct().ok()?.data();
+        let byte = u8::try_from(byte).ok()?;
+        if !(byte.is_ascii_graphic() || byte == b' ') {
+            return None;
+        }
+        bytes.push(byte);
+        noun = cell.tail();
+    }
+    if bytes.len() < 2 || !unsafe { noun.raw_equals(&D(0)) } {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Split a right-associated tuple into its leading items and final tail.
+fn flatten(cell: Cell) -> (Vec<Noun>, Noun) {
+    let mut items = vec![cell.head()];
+    let mut tail = cell.tail();
+    while let Ok(next) = tail.as_cell() {
+        items.push(next.head());
+        tail = next.tail();
+    }
+    (items, tail)
+}
+
+/// Write `digits` with a `.` between every group of `group` digits, counting from the right.
+fn write_dotted(f: &mut fmt::Formatter<'_>, digits: &str, group: usize) -> fmt::Result {
+    let digits = if digits.is_empty() { "0" } else { digits };
+    let lead = digits.len() % group;
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (i + group - lead) % group == 0 {
+            f.write_char('.')?;
+        }
+        f.write_char(c)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::{tape, T};
+
+    use super::*;
+
+    fn pretty(noun: Noun) -> String {
+        PrettyNoun::new(noun).to_string()
+    }
+
+    #[test]
+    fn test_pretty_atoms() {
+        let mut slab: NounSlab = NounSlab::new();
+        assert_eq!(pretty(D(0)), "0");
+        assert_eq!(pretty(D(1_000_000)), "1.000.000");
+        assert_eq!(pretty(D(tas!(b"block"))), "%block");
+        assert_eq!(pretty(D(tas!(b"Hi you"))), "'Hi you'");
+        let big = Atom::new(&mut slab, u64::MAX).as_noun();
+        assert_eq!(pretty(big), "18.446.744.073.709.551.615");
+    }
+
+    #[test]
+    fn test_pretty_cells() {
+        let mut slab: NounSlab = NounSlab::new();
+        let tuple = T(&mut slab, &[D(tas!(b"req")), D(1), D(2)]);
+        assert_eq!(pretty(tuple), "[%req 1 2]");
+        let list = T(&mut slab, &[D(1), D(2), D(0)]);
+        assert_eq!(pretty(list), "~[1 2]");
+        let text = tape(&mut slab, "hello");
+        assert_eq!(pretty(text), "\"hello\"");
+        let nested = T(&mut slab, &[tuple, D(3)]);
+        assert_eq!(
+            PrettyNoun::new(nested).max_depth(1).to_string(),
+            "[[...] 3]"
+        );
+        let long = T(&mut slab, &[D(1), D(2), D(3), D(4), D(0)]);
+        assert_eq!(PrettyNoun::new(long).max_width(2).to_string(), "~[1 2 ...]");
+    }
+
+    #[test]
+    fn test_render_tank() {
+        let mut slab: NounSlab = NounSlab::new();
+        let a = tape(&mut slab, "a");
+        let b = tape(&mut slab, "b");
+        let leaf_a = T(&mut slab, &[D(tas!(b"leaf")), a]);
+        let leaf_b = T(&mut slab, &[D(tas!(b"leaf")), b]);
+        let mid = tape(&mut slab, " ");
+        let open = tape(&mut slab, "[");
+        let close = tape(&mut slab, "]");
+        let style = T(&mut slab, &[mid, open, close]);
+        let rose = T(&mut slab, &[D(tas!(b"rose")), style, leaf_a, leaf_b, D(0)]);
+        assert_eq!(render_tank(rose).as_deref(), Some("[a b]"));
+        assert_eq!(render_tank(D(tas!(b"oops"))).as_deref(), Some("oops"));
+    }
+}
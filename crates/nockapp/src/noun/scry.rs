@@ -0,0 +1,200 @@
+//! Text paths and response encodings for peeks made from outside the process.
+//!
+//! External tools address a peek with a slash-separated path such as `/balance/main`, which
+//! [`parse_path`] turns into the `path` noun `[%balance %main ~]`. The kernel answers with a
+//! `(unit (unit *))`: `~` when it doesn't handle the path, `[~ ~]` when it does but there is no
+//! value, and `[~ ~ value]` otherwise. [`Scry::from_peek`] classifies the answer and
+//! [`Scry::encode`] rebuilds it with the value either left as a noun ([`ScryEncoding::Jam`],
+//! jammed by the transport) or rendered as JSON text ([`ScryEncoding::Json`]).
+//!
+//! JSON encoding only works for paths where the kernel returns a `json` noun.
+use std::str::FromStr;
+
+use nockvm::noun::{Noun, NounAllocator, D, T};
+use nockvm_macros::tas;
+use thiserror::Error;
+
+use crate::noun::json::{print_json, JsonNounError};
+use crate::utils::make_tas;
+
+#[derive(Debug, Error)]
+pub enum ScryError {
+    #[error("scry: path {0:?} must start with /")]
+    Relative(String),
+    #[error("scry: bad path segment {0:?}, expected lowercase letters, digits, - . ~ _")]
+    BadSegment(String),
+    #[error("scry: unknown encoding {0:?}, expected jam or json")]
+    UnknownEncoding(String),
+    #[error("scry: kernel returned a malformed peek result")]
+    Malformed,
+    #[error("scry: {0}")]
+    Json(#[from] JsonNounError),
+}
+
+pub type Result<T, E = ScryError> = std::result::Result<T, E>;
+
+/// How the value in a peek response is encoded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScryEncoding {
+    /// The value noun itself
+    #[default]
+    Jam,
+    /// The value as a JSON cord; the kernel must return a `json` noun
+    Json,
+}
+
+impl ScryEncoding {
+    /// Read the encoding from its `@tas`, `%jam` or `%json`.
+    pub fn from_tag(tag: Noun) -> Option<Self> {
+        match tag.as_direct().ok()?.data() {
+            tas!(b"jam") => Some(ScryEncoding::Jam),
+            tas!(b"json") => Some(ScryEncoding::Json),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for ScryEncoding {
+    type Err = ScryError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jam" => Ok(ScryEncoding::Jam),
+            "json" => Ok(ScryEncoding::Json),
+            _ => Err(ScryError::UnknownEncoding(s.to_string())),
+        }
+    }
+}
+
+/// Parse a path like `/balance/main` into a `path` noun.
+///
+/// Segments must be valid `@ta`. Empty segments are skipped, so `/` is the empty path.
+pub fn parse_path<A: NounAllocator>(allocator: &mut A, path: &str) -> Result<Noun> {
+    let Some(rest) = path.strip_prefix('/') else {
+        return Err(ScryError::Relative(path.to_string()));
+    };
+    let segments: Vec<&str> = rest.split('/').filter(|s| !s.is_empty()).collect();
+    if let Some(bad) = segments.iter().find(|s| !is_knot(s)) {
+        return Err(ScryError::BadSegment(bad.to_string()));
+    }
+    let mut list = D(0);
+    for segment in segments.iter().rev() {
+        let atom = make_tas(allocator, segment).as_noun();
+        list = T(allocator, &[atom, list]);
+    }
+    Ok(list)
+}
+
+fn is_knot(segment: &str) -> bool {
+    segment
+        .bytes()
+        .all(|b| matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'~' | b'_'))
+}
+
+/// A classified peek result
+#[derive(Clone, Copy, Debug)]
+pub enum Scry {
+    /// `~`: the kernel doesn't handle the path
+    Unknown,
+    /// `[~ ~]`: the path is handled but has no value
+    Empty,
+    /// `[~ ~ value]`
+    Value(Noun),
+}
+
+impl Scry {
+    pub fn from_peek(result: Noun) -> Result<Self> {
+        if is_null(result) {
+            return Ok(Scry::Unknown);
+        }
+        let outer = result.as_cell().map_err(|_| ScryError::Malformed)?;
+        if !is_null(outer.head()) {
+            return Err(ScryError::Malformed);
+        }
+        if is_null(outer.tail()) {
+            return Ok(Scry::Empty);
+        }
+        let inner = outer.tail().as_cell().map_err(|_| ScryError::Malformed)?;
+        if !is_null(inner.head()) {
+            return Err(ScryError::Malformed);
+        }
+        Ok(Scry::Value(inner.tail()))
+    }
+
+    /// Rebuild the `(unit (unit *))` with the value in `encoding`.
+    pub fn encode<A: NounAllocator>(
+        &self,
+        allocator: &mut A,
+        encoding: ScryEncoding,
+    ) -> Result<Noun> {
+        match self {
+            Scry::Unknown => Ok(D(0)),
+            Scry::Empty => Ok(T(allocator, &[D(0), D(0)])),
+            Scry::Value(value) => {
+                let value = match encoding {
+                    ScryEncoding::Jam => *value,
+                    ScryEncoding::Json => {
+                        let text = print_json(*value)?;
+                        make_tas(allocator, &text).as_noun()
+                    }
+                };
+                Ok(T(allocator, &[D(0), D(0), value]))
+            }
+        }
+    }
+}
+
+fn is_null(noun: Noun) -> bool {
+    unsafe { noun.raw_equals(&D(0)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noun::json::parse_json;
+    use crate::noun::slab::{slab_noun_equality, NounSlab};
+
+    #[test]
+    fn test_parse_path() {
+        let mut slab: NounSlab = NounSlab::new();
+        let path = parse_path(&mut slab, "/balance//main").expect("parse");
+        let expected = T(&mut slab, &[D(tas!(b"balance")), D(tas!(b"main")), D(0)]);
+        assert!(slab_noun_equality(&path, &expected));
+        let root = parse_path(&mut slab, "/").expect("parse");
+        assert!(slab_noun_equality(&root, &D(0)));
+
+        assert!(matches!(
+            parse_path(&mut slab, "balance"),
+            Err(ScryError::Relative(_))
+        ));
+        assert!(matches!(
+            parse_path(&mut slab, "/Balance"),
+            Err(ScryError::BadSegment(s)) if s == "Balance"
+        ));
+    }
+
+    #[test]
+    fn test_encode_results() {
+        let mut slab: NounSlab = NounSlab::new();
+        let json = parse_json(&mut slab, r#"{"height":7}"#).expect("parse json");
+        let result = T(&mut slab, &[D(0), D(0), json]);
+
+        let scry = Scry::from_peek(result).expect("classify");
+        let jam = scry.encode(&mut slab, ScryEncoding::Jam).expect("jam");
+        assert!(slab_noun_equality(&jam, &result));
+
+        let encoded = scry.encode(&mut slab, ScryEncoding::Json).expect("json");
+        let text = make_tas(&mut slab, r#"{"height":7}"#).as_noun();
+        let expected = T(&mut slab, &[D(0), D(0), text]);
+        assert!(slab_noun_equality(&encoded, &expected));
+
+        let empty = T(&mut slab, &[D(0), D(0)]);
+        assert!(matches!(Scry::from_peek(empty), Ok(Scry::Empty)));
+        assert!(matches!(Scry::from_peek(D(0)), Ok(Scry::Unknown)));
+        assert!(matches!(Scry::from_peek(D(1)), Err(ScryError::Malformed)));
+        assert!(matches!(
+            Scry::Value(D(5)).encode(&mut slab, ScryEncoding::Json),
+            Err(ScryError::Json(_))
+        ));
+    }
+}
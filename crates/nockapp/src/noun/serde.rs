@@ -0,0 +1,1059 @@
+//! Serde bridge for nouns.
+//!
+//! Any [`Serialize`] type can be written into a noun allocator (usually a [`NounSlab`]) and any
+//! [`DeserializeOwned`] type can be read back out of a noun. The encoding follows the shapes a
+//! Hoon kernel would naturally use for the equivalent mold:
+//!
+//! | Rust                        | Noun                                  |
+//! |-----------------------------|---------------------------------------|
+//! | `bool`                      | loobean (`%.y` = 0, `%.n` = 1)        |
+//! | unsigned integers           | `@ud`                                 |
+//! | signed integers             | `@s` (zig-zag, as in `hoon.hoon`)     |
+//! | `f32` / `f64`               | `@rs` / `@rd` (IEEE-754 bits)         |
+//! | `char`, `&str`, `String`    | `@t` cord                             |
+//! | bytes                       | `octs`, i.e. `[p=@ud q=@]`            |
+//! | `()` / unit structs         | `~`                                   |
+//! | `Option<T>`                 | `(unit T)`: `~` or `[~ T]`            |
+//! | sequences                   | null-terminated `(list T)`            |
+//! | tuples and structs          | right-associated tuple `[a b c]`      |
+//! | maps                        | `(list (pair K V))`                   |
+//! | enum variants               | tagged union `%tag` or `[%tag ...]`   |
+//!
+//! Structs are encoded positionally in field declaration order; field names are not written.
+//! Variant tags are the variant names in kebab-case, as Hoon terms are, so `MoveTo` is `%move-to`.
+use std::fmt::Display;
+
+use either::Either;
+use nockvm::noun::{Atom, Cell, DirectAtom, IndirectAtom, Noun, NounAllocator, D, NO, T, YES};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::ser::{self, Serialize};
+use thiserror::Error;
+
+use crate::noun::slab::NounSlab;
+
+#[derive(Debug, Error)]
+pub enum NounSerdeError {
+    #[error("noun serde: {0}")]
+    Custom(String),
+    #[error("noun serde: expected an atom")]
+    ExpectedAtom,
+    #[error("noun serde: expected a cell")]
+    ExpectedCell,
+    #[error("noun serde: expected a null-terminated list")]
+    ExpectedList,
+    #[error("noun serde: expected a loobean, got {0}")]
+    ExpectedLoobean(u64),
+    #[error("noun serde: expected ~")]
+    ExpectedNull,
+    #[error("noun serde: atom too large for {0}")]
+    AtomTooLarge(&'static str),
+    #[error("noun serde: invalid utf-8 in cord")]
+    InvalidUtf8,
+    #[error("noun serde: expected a single character, got {0:?}")]
+    ExpectedChar(String),
+    #[error("noun serde: octs length {0} is shorter than its {1} bytes of data")]
+    OctsTooShort(usize, usize),
+}
+
+impl ser::Error for NounSerdeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        NounSerdeError::Custom(msg.to_string())
+    }
+}
+
+impl de::Error for NounSerdeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        NounSerdeError::Custom(msg.to_string())
+    }
+}
+
+pub type Result<T, E = NounSerdeError> = std::result::Result<T, E>;
+
+/// The longest `octs` a noun may claim to be when deserialized, since the length is allocated
+/// before any data is read
+pub const MAX_OCTS_LEN: usize = 1 << 30;
+
+/// Serialize `value` into a noun allocated in `allocator`.
+pub fn to_noun<A: NounAllocator, T: Serialize + ?Sized>(
+    allocator: &mut A,
+    value: &T,
+) -> Result<Noun> {
+    value.serialize(&mut NounSerializer { allocator })
+}
+
+/// Deserialize a `T` from `noun`.
+pub fn from_noun<T: DeserializeOwned>(noun: Noun) -> Result<T> {
+    T::deserialize(NounDeserializer::new(noun))
+}
+
+impl<J> NounSlab<J> {
+    /// Make a new slab whose root is the noun encoding of `value`.
+    pub fn from_serialize<T: Serialize + ?Sized>(value: &T) -> Result<Self> {
+        let mut slab = Self::new();
+        let root = to_noun(&mut slab, value)?;
+        slab.set_root(root);
+        Ok(slab)
+    }
+
+    /// Deserialize a `T` from the root of this slab.
+    pub fn to_deserialize<T: DeserializeOwned>(&self) -> Result<T> {
+        from_noun(unsafe { *self.root() })
+    }
+}
+
+fn bytes_to_atom<A: NounAllocator>(allocator: &mut A, bytes: &[u8]) -> Atom {
+    if bytes.is_empty() {
+        return unsafe { DirectAtom::new_unchecked(0).as_atom() };
+    }
+    unsafe {
+        IndirectAtom::new_raw_bytes(allocator, bytes.len(), bytes.as_ptr()).normalize_as_atom()
+    }
+}
+
+/// A variant name as a Hoon term: `MoveTo` is `move-to`
+fn kebab_case(name: &str) -> String {
+    let mut term = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            term.push('-');
+        }
+        term.extend(c.to_lowercase());
+    }
+    term
+}
+
+fn u128_to_noun<A: NounAllocator>(allocator: &mut A, value: u128) -> Noun {
+    if let Ok(small) = u64::try_from(value) {
+        Atom::new(allocator, small).as_noun()
+    } else {
+        bytes_to_atom(allocator, &value.to_le_bytes()).as_noun()
+    }
+}
+
+/// Encode a signed integer as `@s`: non-negative `n` is `2n`, negative `n` is `2|n| - 1`.
+fn zigzag(value: i128) -> u128 {
+    if value >= 0 {
+        (value as u128) << 1
+    } else {
+        (((-(value + 1)) as u128) << 1) | 1
+    }
+}
+
+fn unzigzag(value: u128) -> i128 {
+    if value & 1 == 0 {
+        (value >> 1) as i128
+    } else {
+        -((value >> 1) as i128) - 1
+    }
+}
+
+/// Right-associate `items` into a tuple. The empty tuple is `~`.
+fn tuple<A: NounAllocator>(allocator: &mut A, items: &[Noun]) -> Noun {
+    match items.len() {
+        0 => D(0),
+        1 => items[0],
+        _ => T(allocator, items),
+    }
+}
+
+/// Build a null-terminated list from `items`.
+fn list<A: NounAllocator>(allocator: &mut A, items: &[Noun]) -> Noun {
+    let mut res = D(0);
+    for item in items.iter().rev() {
+        res = Cell::new(allocator, *item, res).as_noun();
+    }
+    res
+}
+
+pub struct NounSerializer<'a, A: NounAllocator> {
+    allocator: &'a mut A,
+}
+
+impl<'a, A: NounAllocator> NounSerializer<'a, A> {
+    pub fn new(allocator: &'a mut A) -> Self {
+        NounSerializer { allocator }
+    }
+
+    fn cord(&mut self, text: &str) -> Noun {
+        bytes_to_atom(self.allocator, text.as_bytes()).as_noun()
+    }
+}
+
+enum CompoundKind {
+    /// A null-terminated list
+    List,
+    /// A right-associated tuple
+    Tuple,
+    /// A right-associated tuple headed by a variant tag
+    Tagged(&'static str),
+}
+
+pub struct Compound<'s, 'a, A: NounAllocator> {
+    ser: &'s mut NounSerializer<'a, A>,
+    kind: CompoundKind,
+    items: Vec<Noun>,
+}
+
+impl<A: NounAllocator> Compound<'_, '_, A> {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let noun = value.serialize(&mut *self.ser)?;
+        self.items.push(noun);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Noun> {
+        match self.kind {
+            CompoundKind::List => Ok(list(self.ser.allocator, &self.items)),
+            CompoundKind::Tuple => Ok(tuple(self.ser.allocator, &self.items)),
+            CompoundKind::Tagged(variant) => {
+                let body = tuple(self.ser.allocator, &self.items);
+                let tag = self.ser.cord(&kebab_case(variant));
+                Ok(Cell::new(self.ser.allocator, tag, body).as_noun())
+            }
+        }
+    }
+}
+
+pub struct MapCompound<'s, 'a, A: NounAllocator> {
+    ser: &'s mut NounSerializer<'a, A>,
+    key: Option<Noun>,
+    items: Vec<Noun>,
+}
+
+impl<'s, 'a, A: NounAllocator> ser::Serializer for &'s mut NounSerializer<'a, A> {
+    type Ok = Noun;
+    type Error = NounSerdeError;
+
+    type SerializeSeq = Compound<'s, 'a, A>;
+    type SerializeTuple = Compound<'s, 'a, A>;
+    type SerializeTupleStruct = Compound<'s, 'a, A>;
+    type SerializeTupleVariant = Compound<'s, 'a, A>;
+    type SerializeMap = MapCompound<'s, 'a, A>;
+    type SerializeStruct = Compound<'s, 'a, A>;
+    type SerializeStructVariant = Compound<'s, 'a, A>;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<Noun> {
+        Ok(if v { YES } else { NO })
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Noun> {
+        self.serialize_i128(v as i128)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Noun> {
+        self.serialize_i128(v as i128)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Noun> {
+        self.serialize_i128(v as i128)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Noun> {
+        self.serialize_i128(v as i128)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Noun> {
+        Ok(u128_to_noun(self.allocator, zigzag(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Noun> {
+        Ok(D(v as u64))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Noun> {
+        Ok(D(v as u64))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Noun> {
+        Ok(D(v as u64))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Noun> {
+        Ok(Atom::new(self.allocator, v).as_noun())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Noun> {
+        Ok(u128_to_noun(self.allocator, v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Noun> {
+        Ok(D(v.to_bits() as u64))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Noun> {
+        Ok(Atom::new(self.allocator, v.to_bits()).as_noun())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Noun> {
+        let mut buf = [0u8; 4];
+        Ok(self.cord(v.encode_utf8(&mut buf)))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Noun> {
+        Ok(self.cord(v))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Noun> {
+        let len = Atom::new(self.allocator, v.len() as u64).as_noun();
+        let data = bytes_to_atom(self.allocator, v).as_noun();
+        Ok(T(self.allocator, &[len, data]))
+    }
+
+    fn serialize_none(self) -> Result<Noun> {
+        Ok(D(0))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Noun> {
+        let inner = value.serialize(&mut *self)?;
+        Ok(T(self.allocator, &[D(0), inner]))
+    }
+
+    fn serialize_unit(self) -> Result<Noun> {
+        Ok(D(0))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Noun> {
+        Ok(D(0))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Noun> {
+        Ok(self.cord(&kebab_case(variant)))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Noun> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Noun> {
+        let body = value.serialize(&mut *self)?;
+        let tag = self.cord(&kebab_case(variant));
+        Ok(T(self.allocator, &[tag, body]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(Compound {
+            ser: self,
+            kind: CompoundKind::List,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        Ok(Compound {
+            ser: self,
+            kind: CompoundKind::Tuple,
+            items: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        self.serialize_tuple(len)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Ok(Compound {
+            ser: self,
+            kind: CompoundKind::Tagged(variant),
+            items: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
+        Ok(MapCompound {
+            ser: self,
+            key: None,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
+        self.serialize_tuple(len)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        self.serialize_tuple_variant(name, variant_index, variant, len)
+    }
+}
+
+impl<A: NounAllocator> ser::SerializeSeq for Compound<'_, '_, A> {
+    type Ok = Noun;
+    type Error = NounSerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Noun> {
+        self.finish()
+    }
+}
+
+impl<A: NounAllocator> ser::SerializeTuple for Compound<'_, '_, A> {
+    type Ok = Noun;
+    type Error = NounSerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Noun> {
+        self.finish()
+    }
+}
+
+impl<A: NounAllocator> ser::SerializeTupleStruct for Compound<'_, '_, A> {
+    type Ok = Noun;
+    type Error = NounSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Noun> {
+        self.finish()
+    }
+}
+
+impl<A: NounAllocator> ser::SerializeTupleVariant for Compound<'_, '_, A> {
+    type Ok = Noun;
+    type Error = NounSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Noun> {
+        self.finish()
+    }
+}
+
+impl<A: NounAllocator> ser::SerializeStruct for Compound<'_, '_, A> {
+    type Ok = Noun;
+    type Error = NounSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Noun> {
+        self.finish()
+    }
+}
+
+impl<A: NounAllocator> ser::SerializeStructVariant for Compound<'_, '_, A> {
+    type Ok = Noun;
+    type Error = NounSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Noun> {
+        self.finish()
+    }
+}
+
+impl<A: NounAllocator> ser::SerializeMap for MapCompound<'_, '_, A> {
+    type Ok = Noun;
+    type Error = NounSerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        self.key = Some(key.serialize(&mut *self.ser)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| NounSerdeError::Custom("map value without key".to_string()))?;
+        let value = value.serialize(&mut *self.ser)?;
+        let pair = T(self.ser.allocator, &[key, value]);
+        self.items.push(pair);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Noun> {
+        Ok(list(self.ser.allocator, &self.items))
+    }
+}
+
+pub struct NounDeserializer {
+    noun: Noun,
+}
+
+impl NounDeserializer {
+    pub fn new(noun: Noun) -> Self {
+        NounDeserializer { noun }
+    }
+
+    fn atom(&self) -> Result<Atom> {
+        self.noun
+            .as_atom()
+            .map_err(|_| NounSerdeError::ExpectedAtom)
+    }
+
+    fn cell(&self) -> Result<Cell> {
+        self.noun
+            .as_cell()
+            .map_err(|_| NounSerdeError::ExpectedCell)
+    }
+
+    fn u128(&self, ty: &'static str) -> Result<u128> {
+        atom_to_u128(self.atom()?).ok_or(NounSerdeError::AtomTooLarge(ty))
+    }
+
+    fn cord(&self) -> Result<String> {
+        let atom = self.atom()?;
+        let bytes = atom.as_ne_bytes();
+        let end = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+        String::from_utf8(bytes[..end].to_vec()).map_err(|_| NounSerdeError::InvalidUtf8)
+    }
+
+    fn octs(&self) -> Result<Vec<u8>> {
+        let cell = self.cell()?;
+        let len = cell
+            .head()
+            .as_atom()
+            .map_err(|_| NounSerdeError::ExpectedAtom)?
+            .as_u64()
+            .ok()
+            .and_then(|len| usize::try_from(len).ok())
+            .filter(|len| *len <= MAX_OCTS_LEN)
+            .ok_or(NounSerdeError::AtomTooLarge("octs length"))?;
+        let data = cell
+            .tail()
+            .as_atom()
+            .map_err(|_| NounSerdeError::ExpectedAtom)?;
+        let data = data.as_ne_bytes();
+        let data_len = data.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+        if len < data_len {
+            return Err(NounSerdeError::OctsTooShort(len, data_len));
+        }
+        let mut bytes = Vec::with_capacity(len);
+        bytes.extend_from_slice(&data[..data_len]);
+        bytes.resize(len, 0);
+        Ok(bytes)
+    }
+}
+
+fn atom_to_u128(atom: Atom) -> Option<u128> {
+    let bytes = atom.as_ne_bytes();
+    let end = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    if end > 16 {
+        return None;
+    }
+    let mut buf = [0u8; 16];
+    buf[..end].copy_from_slice(&bytes[..end]);
+    Some(u128::from_le_bytes(buf))
+}
+
+macro_rules! deserialize_unsigned {
+    ($method:ident, $visit:ident, $ty:ty) => {
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+            let value = self.u128(stringify!($ty))?;
+            let value = <$ty>::try_from(value)
+                .map_err(|_| NounSerdeError::AtomTooLarge(stringify!($ty)))?;
+            visitor.$visit(value)
+        }
+    };
+}
+
+macro_rules! deserialize_signed {
+    ($method:ident, $visit:ident, $ty:ty) => {
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+            let value = unzigzag(self.u128(stringify!($ty))?);
+            let value = <$ty>::try_from(value)
+                .map_err(|_| NounSerdeError::AtomTooLarge(stringify!($ty)))?;
+            visitor.$visit(value)
+        }
+    };
+}
+
+impl<'de> de::Deserializer<'de> for NounDeserializer {
+    type Error = NounSerdeError;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    /// Nouns are not self-describing, so this is a best effort: atoms that fit in a `u64` are
+    /// visited as integers, larger atoms as little-endian bytes, and cells as pairs.
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.noun.as_either_atom_cell() {
+            Either::Left(atom) => match atom.as_u64() {
+                Ok(value) => visitor.visit_u64(value),
+                Err(_) => visitor.visit_byte_buf(atom.as_ne_bytes().to_vec()),
+            },
+            Either::Right(cell) => visitor.visit_seq(TupleAccess {
+                noun: cell.as_noun(),
+                remaining: 2,
+            }),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.atom()?.as_u64() {
+            Ok(0) => visitor.visit_bool(true),
+            Ok(1) => visitor.visit_bool(false),
+            Ok(other) => Err(NounSerdeError::ExpectedLoobean(other)),
+            Err(_) => Err(NounSerdeError::AtomTooLarge("bool")),
+        }
+    }
+
+    deserialize_signed!(deserialize_i8, visit_i8, i8);
+    deserialize_signed!(deserialize_i16, visit_i16, i16);
+    deserialize_signed!(deserialize_i32, visit_i32, i32);
+    deserialize_signed!(deserialize_i64, visit_i64, i64);
+    deserialize_signed!(deserialize_i128, visit_i128, i128);
+    deserialize_unsigned!(deserialize_u8, visit_u8, u8);
+    deserialize_unsigned!(deserialize_u16, visit_u16, u16);
+    deserialize_unsigned!(deserialize_u32, visit_u32, u32);
+    deserialize_unsigned!(deserialize_u64, visit_u64, u64);
+    deserialize_unsigned!(deserialize_u128, visit_u128, u128);
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let bits =
+            u32::try_from(self.u128("f32")?).map_err(|_| NounSerdeError::AtomTooLarge("f32"))?;
+        visitor.visit_f32(f32::from_bits(bits))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let bits =
+            u64::try_from(self.u128("f64")?).map_err(|_| NounSerdeError::AtomTooLarge("f64"))?;
+        visitor.visit_f64(f64::from_bits(bits))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let text = self.cord()?;
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(NounSerdeError::ExpectedChar(text)),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_string(self.cord()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_string(self.cord()?)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_byte_buf(self.octs()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_byte_buf(self.octs()?)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.noun.as_either_atom_cell() {
+            Either::Left(atom) if atom.as_u64() == Ok(0) => visitor.visit_none(),
+            Either::Left(_) => Err(NounSerdeError::ExpectedNull),
+            Either::Right(cell) => {
+                if !unsafe { cell.head().raw_equals(&D(0)) } {
+                    return Err(NounSerdeError::ExpectedNull);
+                }
+                visitor.visit_some(NounDeserializer::new(cell.tail()))
+            }
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if unsafe { self.noun.raw_equals(&D(0)) } {
+            visitor.visit_unit()
+        } else {
+            Err(NounSerdeError::ExpectedNull)
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(ListAccess { noun: self.noun })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(TupleAccess {
+            noun: self.noun,
+            remaining: len,
+        })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_map(PairListAccess {
+            noun: self.noun,
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        let (tag, body) = match self.noun.as_either_atom_cell() {
+            Either::Left(atom) => (atom.as_noun(), None),
+            Either::Right(cell) => (cell.head(), Some(cell.tail())),
+        };
+        visitor.visit_enum(TaggedAccess {
+            tag,
+            body,
+            variants,
+        })
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+}
+
+/// Walks a null-terminated list.
+struct ListAccess {
+    noun: Noun,
+}
+
+impl<'de> SeqAccess<'de> for ListAccess {
+    type Error = NounSerdeError;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<Option<S::Value>> {
+        match self.noun.as_either_atom_cell() {
+            Either::Left(atom) if atom.as_u64() == Ok(0) => Ok(None),
+            Either::Left(_) => Err(NounSerdeError::ExpectedList),
+            Either::Right(cell) => {
+                self.noun = cell.tail();
+                seed.deserialize(NounDeserializer::new(cell.head()))
+                    .map(Some)
+            }
+        }
+    }
+}
+
+/// Walks a right-associated tuple of known length. The last element is the remaining tail.
+struct TupleAccess {
+    noun: Noun,
+    remaining: usize,
+}
+
+impl<'de> SeqAccess<'de> for TupleAccess {
+    type Error = NounSerdeError;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<Option<S::Value>> {
+        match self.remaining {
+            0 => Ok(None),
+            1 => {
+                self.remaining = 0;
+                seed.deserialize(NounDeserializer::new(self.noun)).map(Some)
+            }
+            _ => {
+                let cell = self
+                    .noun
+                    .as_cell()
+                    .map_err(|_| NounSerdeError::ExpectedCell)?;
+                self.remaining -= 1;
+                self.noun = cell.tail();
+                seed.deserialize(NounDeserializer::new(cell.head()))
+                    .map(Some)
+            }
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+/// Walks a `(list (pair k v))`.
+struct PairListAccess {
+    noun: Noun,
+    value: Option<Noun>,
+}
+
+impl<'de> MapAccess<'de> for PairListAccess {
+    type Error = NounSerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.noun.as_either_atom_cell() {
+            Either::Left(atom) if atom.as_u64() == Ok(0) => Ok(None),
+            Either::Left(_) => Err(NounSerdeError::ExpectedList),
+            Either::Right(cell) => {
+                let pair = cell
+                    .head()
+                    .as_cell()
+                    .map_err(|_| NounSerdeError::ExpectedCell)?;
+                self.noun = cell.tail();
+                self.value = Some(pair.tail());
+                seed.deserialize(NounDeserializer::new(pair.head()))
+                    .map(Some)
+            }
+        }
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value> {
+        let value = self
+            .value
+            .take()
+            .ok_or_else(|| NounSerdeError::Custom("map value without key".to_string()))?;
+        seed.deserialize(NounDeserializer::new(value))
+    }
+}
+
+/// A `%tag` or `[%tag body]` tagged union.
+struct TaggedAccess {
+    tag: Noun,
+    body: Option<Noun>,
+    /// The variant names the tag is the kebab-case of
+    variants: &'static [&'static str],
+}
+
+impl<'de> EnumAccess<'de> for TaggedAccess {
+    type Error = NounSerdeError;
+    type Variant = Self;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<(S::Value, Self)> {
+        let tag = NounDeserializer::new(self.tag).cord()?;
+        // An unknown tag is passed through for the seed to reject
+        let tag = self
+            .variants
+            .iter()
+            .find(|variant| kebab_case(variant) == tag)
+            .map_or(tag, |variant| variant.to_string());
+        let deserializer: de::value::StringDeserializer<NounSerdeError> = tag.into_deserializer();
+        let value = seed.deserialize(deserializer)?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for TaggedAccess {
+    type Error = NounSerdeError;
+
+    fn unit_variant(self) -> Result<()> {
+        match self.body {
+            None => Ok(()),
+            Some(_) => Err(NounSerdeError::ExpectedAtom),
+        }
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value> {
+        let body = self.body.ok_or(NounSerdeError::ExpectedCell)?;
+        seed.deserialize(NounDeserializer::new(body))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        let body = self.body.ok_or(NounSerdeError::ExpectedCell)?;
+        visitor.visit_seq(TupleAccess {
+            noun: body,
+            remaining: len,
+        })
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.tuple_variant(fields.len(), visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use nockvm_macros::tas;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::noun::slab::slab_noun_equality;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Command {
+        Stop,
+        Send(u64),
+        Move { x: i32, y: i32 },
+        SetLimit(u64),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        name: String,
+        port: u16,
+        verbose: bool,
+        peers: Vec<String>,
+        limit: Option<u64>,
+        offset: i64,
+        blob: serde_bytes::ByteBuf,
+        commands: Vec<Command>,
+        weights: BTreeMap<String, u128>,
+    }
+
+    #[test]
+    fn test_struct_roundtrip() {
+        let mut weights = BTreeMap::new();
+        weights.insert("a".to_string(), 1);
+        weights.insert("big".to_string(), u128::MAX);
+        let config = Config {
+            name: "nockchain".to_string(),
+            port: 3005,
+            verbose: true,
+            peers: vec!["alpha".to_string(), "beta".to_string()],
+            limit: None,
+            offset: -42,
+            blob: serde_bytes::ByteBuf::from(vec![1, 0, 0]),
+            commands: vec![
+                Command::Stop,
+                Command::Send(u64::MAX),
+                Command::Move { x: -1, y: 7 },
+                Command::SetLimit(3),
+            ],
+            weights,
+        };
+        let slab: NounSlab = NounSlab::from_serialize(&config).expect("serialize");
+        let decoded: Config = slab.to_deserialize().expect("deserialize");
+        assert_eq!(config, decoded);
+    }
+
+    #[test]
+    fn test_hoon_shapes() {
+        let mut slab: NounSlab = NounSlab::new();
+        let tagged = to_noun(&mut slab, &Command::Send(5)).expect("serialize");
+        let expected = T(&mut slab, &[D(tas!(b"send")), D(5)]);
+        assert!(slab_noun_equality(&tagged, &expected));
+
+        let tagged = to_noun(&mut slab, &Command::SetLimit(1)).expect("serialize");
+        let expected = T(&mut slab, &[D(tas!(b"set-limit")), D(1)]);
+        assert!(slab_noun_equality(&tagged, &expected));
+
+        let stop = to_noun(&mut slab, &Command::Stop).expect("serialize");
+        assert!(slab_noun_equality(&stop, &D(tas!(b"stop"))));
+
+        let unit = to_noun(&mut slab, &Some(3u8)).expect("serialize");
+        let expected = T(&mut slab, &[D(0), D(3)]);
+        assert!(slab_noun_equality(&unit, &expected));
+
+        let list = to_noun(&mut slab, &vec![1u8, 2]).expect("serialize");
+        let expected = T(&mut slab, &[D(1), D(2), D(0)]);
+        assert!(slab_noun_equality(&list, &expected));
+
+        let signed = to_noun(&mut slab, &-2i8).expect("serialize");
+        assert!(slab_noun_equality(&signed, &D(3)));
+    }
+
+    #[test]
+    fn test_deserialize_errors() {
+        assert!(matches!(
+            from_noun::<bool>(D(2)),
+            Err(NounSerdeError::ExpectedLoobean(2))
+        ));
+        assert!(matches!(
+            from_noun::<u8>(D(256)),
+            Err(NounSerdeError::AtomTooLarge(_))
+        ));
+        assert!(matches!(
+            from_noun::<Vec<u8>>(D(1)),
+            Err(NounSerdeError::ExpectedList)
+        ));
+
+        let mut slab: NounSlab = NounSlab::new();
+        let huge = T(&mut slab, &[D(1 << 40), D(0)]);
+        assert!(matches!(
+            from_noun::<serde_bytes::ByteBuf>(huge),
+            Err(NounSerdeError::AtomTooLarge(_))
+        ));
+        let short = T(&mut slab, &[D(1), D(0x100)]);
+        assert!(matches!(
+            from_noun::<serde_bytes::ByteBuf>(short),
+            Err(NounSerdeError::OctsTooShort(1, 2))
+        ));
+        assert!(matches!(
+            from_noun::<Command>(D(tas!(b"Stop"))),
+            Err(NounSerdeError::Custom(_))
+        ));
+    }
+}
@@ -1,11 +1,13 @@
 use std::alloc::Layout;
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::io::{self, Read, Write};
 use std::mem::size_of;
 use std::ptr::copy_nonoverlapping;
 
+use bitvec::bits;
 use bitvec::prelude::{BitSlice, BitVec, Lsb0};
 use bitvec::view::BitView;
-use bitvec::{bits, bitvec};
 use bytes::Bytes;
 use either::Either;
 use intmap::IntMap;
@@ -27,9 +29,51 @@ pub struct NounSlab<J = NockJammer> {
     slabs: Vec<(*mut u8, Layout)>,
     allocation_start: *mut u64,
     allocation_stop: *mut u64,
+    stats: AllocationStats,
+    /// What [`NounSlab::copy_from`] has copied in, by content, for later copies to share
+    shared: NounMap<Noun>,
     _phantom: std::marker::PhantomData<J>,
 }
 
+/// Counts of what has been allocated in a [`NounSlab`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocationStats {
+    /// Number of backing chunks allocated from the system allocator
+    pub chunks: usize,
+    /// Number of cells allocated
+    pub cells: usize,
+    /// Number of indirect atoms allocated
+    pub indirect_atoms: usize,
+    /// Total words allocated for indirect atoms, including their headers
+    pub indirect_words: usize,
+    /// Number of `alloc_struct` calls
+    pub structs: usize,
+    /// Total words allocated for structs
+    pub struct_words: usize,
+}
+
+impl AllocationStats {
+    /// Bytes handed out to nouns and structs, as opposed to reserved from the system
+    pub fn used_bytes(&self) -> usize {
+        (self.cells * CELL_MEM_WORD_SIZE + self.indirect_words + self.struct_words) << 3
+    }
+}
+
+/// The memory reachable from a noun, as computed by [`NounSlab::size_of`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NounSize {
+    /// Distinct cells reachable from the noun
+    pub cells: usize,
+    /// Distinct indirect atoms reachable from the noun
+    pub indirect_atoms: usize,
+    /// Bytes occupied by the distinct cells and indirect atoms, counting shared subtrees once
+    pub bytes: usize,
+    /// References to a cell or indirect atom that was already counted
+    pub shared_references: usize,
+    /// Distinct cells and indirect atoms that live outside the slab (e.g. in the PMA)
+    pub external: usize,
+}
+
 impl<J> Debug for NounSlab<J> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("NounSlab")
@@ -42,12 +86,14 @@ impl<J> Debug for NounSlab<J> {
 }
 
 impl<J> NounSlab<J> {
-    pub fn coerce_jammer<I>(self) -> NounSlab<I> {
+    pub fn coerce_jammer<I>(mut self) -> NounSlab<I> {
         let res = NounSlab {
             root: self.root,
             slabs: self.slabs.clone(),
             allocation_start: self.allocation_start,
             allocation_stop: self.allocation_stop,
+            stats: self.stats,
+            shared: std::mem::take(&mut self.shared),
             _phantom: std::marker::PhantomData,
         };
         // We are keeping the allocation and just reconstructing the slab struct to change type: running drop() results in use-after-free + double-free
@@ -134,12 +180,15 @@ impl<J> NounAllocator for NounSlab<J> {
             let new_slab = Self::raw_alloc(new_layout);
             let new_slab_u64 = new_slab as *mut u64;
             self.slabs[next_idx] = (new_slab, new_layout);
+            self.stats.chunks += 1;
             self.allocation_start = new_slab_u64;
             self.allocation_stop = new_slab_u64.add(new_size);
         }
 
         let new_indirect_ptr = self.allocation_start;
         self.allocation_start = self.allocation_start.add(raw_size);
+        self.stats.indirect_atoms += 1;
+        self.stats.indirect_words += raw_size;
         new_indirect_ptr
     }
     unsafe fn alloc_cell(&mut self) -> *mut CellMemory {
@@ -164,10 +213,12 @@ impl<J> NounAllocator for NounSlab<J> {
             let new_slab = Self::raw_alloc(new_layout);
             let new_slab_u64 = new_slab as *mut u64;
             self.slabs[next_idx] = (new_slab, new_layout);
+            self.stats.chunks += 1;
             self.allocation_start = new_slab_u64;
             self.allocation_stop = new_slab_u64.add(new_size);
         }
         let new_cell_ptr = self.allocation_start as *mut CellMemory;
+        self.stats.cells += 1;
         // self.allocation_start = ((self.allocation_start.expose_provenance()) + CELL_MEM_WORD_SIZE) as *mut u64;
         self.allocation_start = std::ptr::with_exposed_provenance_mut(
             self.allocation_start.expose_provenance()
@@ -198,11 +249,14 @@ impl<J> NounAllocator for NounSlab<J> {
             let new_slab = Self::raw_alloc(new_layout);
             let new_slab_u64 = new_slab as *mut u64;
             self.slabs[next_idx] = (new_slab, new_layout);
+            self.stats.chunks += 1;
             self.allocation_start = new_slab_u64;
             self.allocation_stop = new_slab_u64.add(new_size);
         }
         let new_struct_ptr = self.allocation_start as *mut T;
         self.allocation_start = self.allocation_start.add(word_size);
+        self.stats.structs += 1;
+        self.stats.struct_words += word_size;
         new_struct_ptr
     }
 }
@@ -246,10 +300,232 @@ impl<J> NounSlab<J> {
             slabs,
             allocation_start,
             allocation_stop,
+            stats: AllocationStats::default(),
+            shared: NounMap::new(),
             _phantom: std::marker::PhantomData,
         }
     }
 
+    /// Drop every noun in the slab and set the root to `D(0)`, keeping the largest chunk so
+    /// the next round of allocations doesn't have to go back to the system allocator.
+    ///
+    /// Any [`Noun`] still pointing into this slab is invalidated.
+    pub fn reset(&mut self) {
+        let largest = self.slabs.iter().rposition(|(ptr, _)| !ptr.is_null());
+        for (idx, slab) in self.slabs.iter_mut().enumerate() {
+            if Some(idx) != largest && !slab.0.is_null() {
+                unsafe { std::alloc::dealloc(slab.0, slab.1) };
+                *slab = (std::ptr::null_mut(), Layout::new::<u8>());
+            }
+        }
+        self.root = D(0);
+        self.stats = AllocationStats::default();
+        self.shared = NounMap::new();
+        match largest {
+            Some(idx) => {
+                let (ptr, layout) = self.slabs[idx];
+                self.allocation_start = ptr as *mut u64;
+                self.allocation_stop = unsafe { (ptr as *mut u64).add(layout.size() >> 3) };
+                self.stats.chunks = 1;
+            }
+            None => {
+                self.allocation_start = std::ptr::null_mut();
+                self.allocation_stop = std::ptr::null_mut();
+            }
+        }
+    }
+
+    /// Bytes reserved from the system allocator for this slab, including unused space
+    pub fn allocated_bytes(&self) -> usize {
+        self.slabs
+            .iter()
+            .filter(|(ptr, _)| !ptr.is_null())
+            .map(|(_, layout)| layout.size())
+            .sum()
+    }
+
+    /// Counts of the allocations made in this slab
+    pub fn allocation_stats(&self) -> AllocationStats {
+        self.stats
+    }
+
+    /// Jam the root with explicit control over back-references, reporting what was written.
+    ///
+    /// The output is always in the standard jam format and can be cued by any [`Jammer`].
+    pub fn jam_with_options(&self, options: JamOptions) -> (Bytes, JamStats) {
+        jam_to_bytes(self.root, options, None)
+    }
+
+    /// Re-share equal subtrees reachable from the root, so each distinct cell and indirect atom
+    /// is stored once.
+    ///
+    /// Cells in this slab are rewritten in place to point at the shared children; cells outside
+    /// the slab (e.g. in the PMA) are never written to, and are copied instead if their children
+    /// change. Memory made unreachable is not freed until the slab is dropped, so copy the root
+    /// into a fresh slab afterwards to reclaim it.
+    pub fn unify(&mut self) -> UnifyStats {
+        enum Frame {
+            Enter(Noun),
+            Exit(Cell),
+        }
+
+        let mut stats = UnifyStats::default();
+        let mut cells: HashMap<(u64, u64), Noun> = HashMap::new();
+        let mut atoms: NounMap<Noun> = NounMap::new();
+        let mut done: IntMap<u64, Noun> = IntMap::new();
+        let mut results: Vec<Noun> = Vec::new();
+        let mut stack = vec![Frame::Enter(self.root)];
+        while let Some(frame) = stack.pop() {
+            match frame {
+                Frame::Enter(noun) => {
+                    let Ok(allocated) = noun.as_allocated() else {
+                        results.push(noun);
+                        continue;
+                    };
+                    let ptr = unsafe { allocated.to_raw_pointer() } as u64;
+                    if let Some(canonical) = done.get(ptr) {
+                        results.push(*canonical);
+                        continue;
+                    }
+                    match allocated.as_either() {
+                        Either::Left(_indirect) => {
+                            let canonical = if let Some(canonical) = atoms.get(noun) {
+                                stats.atoms_merged += 1;
+                                *canonical
+                            } else {
+                                stats.atoms += 1;
+                                atoms.insert(noun, noun);
+                                noun
+                            };
+                            done.insert(ptr, canonical);
+                            results.push(canonical);
+                        }
+                        Either::Right(cell) => {
+                            stack.push(Frame::Exit(cell));
+                            stack.push(Frame::Enter(cell.tail()));
+                            stack.push(Frame::Enter(cell.head()));
+                        }
+                    }
+                }
+                Frame::Exit(mut cell) => {
+                    let tail = results.pop().expect("unify: missing tail");
+                    let head = results.pop().expect("unify: missing head");
+                    let ptr = unsafe { cell.to_raw_pointer() } as *const u64;
+                    // Children are canonical, so equal cells have identical children
+                    let key = unsafe { (head.as_raw(), tail.as_raw()) };
+                    let canonical = if let Some(canonical) = cells.get(&key) {
+                        stats.cells_merged += 1;
+                        *canonical
+                    } else {
+                        stats.cells += 1;
+                        let unchanged = unsafe {
+                            head.raw_equals(&cell.head()) && tail.raw_equals(&cell.tail())
+                        };
+                        let canonical = if unchanged {
+                            cell.as_noun()
+                        } else if self.contains(ptr) {
+                            unsafe {
+                                let memory = cell.to_raw_pointer_mut();
+                                (*memory).head = head;
+                                (*memory).tail = tail;
+                            }
+                            cell.as_noun()
+                        } else {
+                            Cell::new(self, head, tail).as_noun()
+                        };
+                        cells.insert(key, canonical);
+                        canonical
+                    };
+                    done.insert(ptr as u64, canonical);
+                    results.push(canonical);
+                }
+            }
+        }
+        self.root = results.pop().expect("unify: missing root");
+        stats
+    }
+
+    /// Cache mugs recorded by [`Jammer::jam_with_mugs`] on a freshly cued root.
+    ///
+    /// Cue turns every backreference into a shared pointer and everything else into a fresh
+    /// allocation, so a head-first walk visiting each allocation once meets the cells and
+    /// indirect atoms in the same order jam wrote them out. If the count doesn't match, nothing is
+    /// cached and this returns `false`; mugs will be computed lazily as usual.
+    pub fn restore_mugs(&mut self, mugs: &[u32]) -> bool {
+        let mut allocations = Vec::with_capacity(mugs.len());
+        let mut seen: IntMap<u64, ()> = IntMap::new();
+        let mut stack = vec![self.root];
+        while let Some(noun) = stack.pop() {
+            let Ok(allocated) = noun.as_allocated() else {
+                continue;
+            };
+            if !seen.insert_checked(unsafe { allocated.to_raw_pointer() } as u64, ()) {
+                continue;
+            }
+            if allocations.len() == mugs.len() {
+                return false;
+            }
+            allocations.push(allocated);
+            if let Some(cell) = allocated.cell() {
+                stack.push(cell.tail());
+                stack.push(cell.head());
+            }
+        }
+        if allocations.len() != mugs.len() {
+            return false;
+        }
+        for (mut allocated, mug) in allocations.into_iter().zip(mugs) {
+            unsafe { set_mug(&mut allocated, *mug) };
+        }
+        true
+    }
+
+    /// Whether `ptr` points into one of this slab's chunks
+    fn contains(&self, ptr: *const u64) -> bool {
+        self.slabs.iter().any(|(start, layout)| {
+            let start = *start as usize;
+            start != 0 && (start..start + layout.size()).contains(&(ptr as usize))
+        })
+    }
+
+    /// Measure the memory reachable from `noun`.
+    ///
+    /// Each cell and indirect atom is counted once no matter how many times it is referenced, so
+    /// `bytes` is the real footprint of the noun rather than the size of its tree. Allocations
+    /// outside this slab are counted in `external` but not in `bytes`.
+    pub fn size_of(&self, noun: Noun) -> NounSize {
+        let mut size = NounSize::default();
+        let mut seen: IntMap<u64, ()> = IntMap::new();
+        let mut stack = vec![noun];
+        while let Some(noun) = stack.pop() {
+            let Ok(allocated) = noun.as_allocated() else {
+                continue;
+            };
+            let ptr = unsafe { allocated.to_raw_pointer() };
+            if seen.insert_checked(ptr as u64, ()) {
+                if !self.contains(ptr) {
+                    size.external += 1;
+                    continue;
+                }
+                match allocated.as_either() {
+                    Either::Left(indirect) => {
+                        size.indirect_atoms += 1;
+                        size.bytes += indirect.raw_size() << 3;
+                    }
+                    Either::Right(cell) => {
+                        size.cells += 1;
+                        size.bytes += CELL_MEM_WORD_SIZE << 3;
+                        stack.push(cell.tail());
+                        stack.push(cell.head());
+                    }
+                }
+            } else {
+                size.shared_references += 1;
+            }
+        }
+        size
+    }
+
     /// Copy the root from another slab into this slab, set this slab's root to the copied root
     pub fn copy_from_slab(&mut self, other: &NounSlab) {
         self.copy_into(other.root);
@@ -314,6 +590,62 @@ impl<J> NounSlab<J> {
         self.root
     }
 
+    /// Copy `root`, a noun in `other`, into this slab and return the copy, leaving this slab's root
+    /// alone.
+    ///
+    /// Sharing within `root` is kept, as with [`NounSlab::copy_into`], and subtrees equal to any
+    /// this slab has copied from any slab with `copy_from` before are shared instead of copied
+    /// again, so nouns gathered from many effects don't each hold what they have in common.
+    /// References out of `other`, which can only be into the PMA, are left as they are.
+    pub fn copy_from<K>(&mut self, other: &NounSlab<K>, root: Noun) -> Noun {
+        let mut copied: IntMap<u64, Noun> = IntMap::new();
+        let mut fresh: Vec<Noun> = Vec::new();
+        let mut res = D(0);
+        let mut copy_stack = vec![(root, std::ptr::addr_of_mut!(res))];
+        while let Some((noun, dest)) = copy_stack.pop() {
+            let Ok(allocated) = noun.as_allocated() else {
+                unsafe { *dest = noun };
+                continue;
+            };
+            let ptr = unsafe { allocated.to_raw_pointer() };
+            if !other.contains(ptr) {
+                unsafe { *dest = noun };
+                continue;
+            }
+            if let Some(copy) = copied.get(ptr as u64) {
+                unsafe { *dest = *copy };
+                continue;
+            }
+            if let Some(shared) = self.shared.get(noun) {
+                copied.insert(ptr as u64, *shared);
+                unsafe { *dest = *shared };
+                continue;
+            }
+            let copy = match allocated.as_either() {
+                Either::Left(indirect) => unsafe {
+                    let new_mem = self.alloc_indirect(indirect.size());
+                    copy_nonoverlapping(ptr, new_mem, indirect.raw_size());
+                    IndirectAtom::from_raw_pointer(new_mem).as_atom().as_noun()
+                },
+                Either::Right(cell) => unsafe {
+                    let new_mem = self.alloc_cell();
+                    copy_nonoverlapping(cell.to_raw_pointer(), new_mem, 1);
+                    copy_stack.push((cell.tail(), std::ptr::addr_of_mut!((*new_mem).tail)));
+                    copy_stack.push((cell.head(), std::ptr::addr_of_mut!((*new_mem).head)));
+                    Cell::from_raw_pointer(new_mem).as_noun()
+                },
+            };
+            copied.insert(ptr as u64, copy);
+            fresh.push(copy);
+            unsafe { *dest = copy };
+        }
+        // Only once every copy's children are in this slab
+        for copy in fresh {
+            self.shared.insert(copy, copy);
+        }
+        res
+    }
+
     /// Copy the root noun from this slab into the given NockStack, only leaving references into the PMA
     ///
     /// Note that this consumes the slab, the slab will be freed after and the root noun returned
@@ -418,6 +750,20 @@ impl<J: Jammer> NounSlab<J> {
     pub fn cue_into(&mut self, jammed: Bytes) -> Result<Noun, CueError> {
         J::cue(self, jammed)
     }
+
+    /// Jam the root noun into a writer without materializing the whole jam in memory.
+    ///
+    /// Returns the number of bytes written. The output is byte-for-byte identical to [`Self::jam`].
+    pub fn jam_to_writer<W: Write>(&self, writer: W) -> io::Result<usize> {
+        J::jam_to_writer(unsafe { *self.root() }, writer)
+    }
+
+    /// Cue a jammed noun from a reader into this slab, without reading the whole jam into memory.
+    ///
+    /// Like [`Self::cue_into`], this does not set the root.
+    pub fn cue_from_reader<R: Read>(&mut self, reader: R) -> Result<Noun, CueError> {
+        J::cue_from_reader(self, reader)
+    }
 }
 
 impl<J> Drop for NounSlab<J> {
@@ -438,6 +784,8 @@ pub enum CueError {
     BackrefTooBig,
     #[error("cue: truncated buffer")]
     TruncatedBuffer,
+    #[error("cue: io error: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 /// Slab size from vector index, in 8-byte words
@@ -461,6 +809,12 @@ fn min_idx_for_size(sz: usize) -> usize {
 
 pub struct NounMap<V>(IntMap<u64, Vec<(Noun, V)>>);
 
+impl<V> Default for NounMap<V> {
+    fn default() -> Self {
+        NounMap::new()
+    }
+}
+
 impl<V> NounMap<V> {
     pub fn new() -> Self {
         NounMap(IntMap::new())
@@ -585,7 +939,7 @@ pub fn slab_noun_equality(a: &Noun, b: &Noun) -> bool {
     }
 }
 
-fn slab_mug(a: Noun) -> u32 {
+pub(crate) fn slab_mug(a: Noun) -> u32 {
     let mut stack = vec![a];
     while let Some(noun) = stack.pop() {
         if let Ok(mut allocated) = noun.as_allocated() {
@@ -621,76 +975,400 @@ enum CueStackEntry {
 pub trait Jammer: Sized {
     fn jam(noun: Noun) -> Bytes;
     fn cue(slab: &mut NounSlab<Self>, bytes: Bytes) -> Result<Noun, CueError>;
+
+    /// Jam a noun along with the mugs of its cells and indirect atoms, in the order
+    /// [`NounSlab::restore_mugs`] expects them.
+    ///
+    /// The default implementation records no mugs.
+    fn jam_with_mugs(noun: Noun) -> (Bytes, Vec<u32>) {
+        (Self::jam(noun), Vec::new())
+    }
+
+    /// Jam a noun into a writer, returning the number of bytes written.
+    ///
+    /// The default implementation jams into memory first; implementations should override it
+    /// to stream.
+    fn jam_to_writer<W: Write>(noun: Noun, mut writer: W) -> io::Result<usize> {
+        let bytes = Self::jam(noun);
+        writer.write_all(&bytes)?;
+        writer.flush()?;
+        Ok(bytes.len())
+    }
+
+    /// Like [`Jammer::jam_with_mugs`], but into a writer, returning the number of bytes written
+    /// along with the mugs.
+    ///
+    /// The default implementation jams into memory first.
+    fn jam_with_mugs_to_writer<W: Write>(
+        noun: Noun,
+        mut writer: W,
+    ) -> io::Result<(usize, Vec<u32>)> {
+        let (bytes, mugs) = Self::jam_with_mugs(noun);
+        writer.write_all(&bytes)?;
+        writer.flush()?;
+        Ok((bytes.len(), mugs))
+    }
+
+    /// Cue a noun from a reader into the slab.
+    ///
+    /// The default implementation reads the whole input into memory first; implementations
+    /// should override it to stream.
+    fn cue_from_reader<R: Read>(
+        slab: &mut NounSlab<Self>,
+        mut reader: R,
+    ) -> Result<Noun, CueError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::cue(slab, Bytes::from(bytes))
+    }
+}
+
+/// Bytes of jam output buffered before being flushed to a writer, and the size of reads when
+/// cueing from a reader.
+const STREAM_CHUNK_BYTES: usize = 1 << 16;
+
+/// A bit buffer that flushes whole bytes to a writer once it grows past [`STREAM_CHUNK_BYTES`].
+struct BitSink<W: Write> {
+    writer: W,
+    buffer: BitVec<u8, Lsb0>,
+    flushed_bits: usize,
+}
+
+impl<W: Write> BitSink<W> {
+    fn new(writer: W) -> Self {
+        BitSink {
+            writer,
+            buffer: BitVec::new(),
+            flushed_bits: 0,
+        }
+    }
+
+    /// Bit offset from the start of the stream
+    fn position(&self) -> usize {
+        self.flushed_bits + self.buffer.len()
+    }
+
+    fn maybe_flush(&mut self) -> io::Result<()> {
+        if self.buffer.len() < STREAM_CHUNK_BYTES << 3 {
+            return Ok(());
+        }
+        let whole_bits = self.buffer.len() & !7;
+        self.writer
+            .write_all(&self.buffer.as_raw_slice()[..whole_bits >> 3])?;
+        let rest = self.buffer[whole_bits..].to_bitvec();
+        self.buffer.clear();
+        self.buffer.extend_from_bitslice(&rest);
+        self.flushed_bits += whole_bits;
+        Ok(())
+    }
+
+    /// Write out the rest of the buffer, returning the writer
+    fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(self.buffer.as_raw_slice())?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// A bit cursor over a reader, refilled [`STREAM_CHUNK_BYTES`] at a time.
+struct BitSource<R: Read> {
+    reader: R,
+    buffer: Vec<u8>,
+    filled: usize,
+    cursor: usize,
+    consumed_bits: usize,
+}
+
+impl<R: Read> BitSource<R> {
+    fn new(reader: R) -> Self {
+        BitSource {
+            reader,
+            buffer: vec![0; STREAM_CHUNK_BYTES],
+            filled: 0,
+            cursor: 0,
+            consumed_bits: 0,
+        }
+    }
+
+    /// Bit offset from the start of the stream
+    fn position(&self) -> usize {
+        self.consumed_bits + self.cursor
+    }
+
+    fn available(&self) -> usize {
+        (self.filled << 3) - self.cursor
+    }
+
+    /// Drop consumed bytes and read more. Returns false at end of input.
+    fn refill(&mut self) -> Result<bool, CueError> {
+        let consumed_bytes = self.cursor >> 3;
+        self.buffer.copy_within(consumed_bytes..self.filled, 0);
+        self.filled -= consumed_bytes;
+        self.cursor -= consumed_bytes << 3;
+        self.consumed_bits += consumed_bytes << 3;
+        loop {
+            match self.reader.read(&mut self.buffer[self.filled..]) {
+                Ok(0) => return Ok(false),
+                Ok(n) => {
+                    self.filled += n;
+                    return Ok(true);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn ensure_available(&mut self) -> Result<(), CueError> {
+        if self.available() == 0 && !self.refill()? {
+            Err(CueError::TruncatedBuffer)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn bit(&mut self) -> Result<bool, CueError> {
+        self.ensure_available()?;
+        let bit = (self.buffer[self.cursor >> 3] >> (self.cursor & 7)) & 1 == 1;
+        self.cursor += 1;
+        Ok(bit)
+    }
+
+    /// Consume bits through the next 1, returning how many 0s preceded it.
+    fn zeros_until_one(&mut self) -> Result<usize, CueError> {
+        let mut zeros = 0;
+        while !self.bit()? {
+            zeros += 1;
+        }
+        Ok(zeros)
+    }
+
+    /// Copy the next `dest.len()` bits into `dest`.
+    fn read_into(&mut self, dest: &mut BitSlice<u64, Lsb0>) -> Result<(), CueError> {
+        let mut done = 0;
+        while done < dest.len() {
+            self.ensure_available()?;
+            let n = std::cmp::min(self.available(), dest.len() - done);
+            let src = &self.buffer[..self.filled].view_bits::<Lsb0>()[self.cursor..self.cursor + n];
+            dest[done..done + n].clone_from_bitslice(src);
+            done += n;
+            self.cursor += n;
+        }
+        Ok(())
+    }
+
+    /// Read a length-prefixed value as in `rub`: returns the bit length of the following payload.
+    fn rub_size(&mut self) -> Result<Option<usize>, CueError> {
+        let idx = self.zeros_until_one()?;
+        if idx == 0 {
+            return Ok(None);
+        }
+        if idx > 64 {
+            return Err(CueError::BackrefTooBig);
+        }
+        let mut sz = 0u64;
+        self.read_into(&mut BitSlice::<_, Lsb0>::from_element_mut(&mut sz)[0..idx - 1])?;
+        sz |= 1 << (idx - 1);
+        usize::try_from(sz)
+            .map(Some)
+            .map_err(|_| CueError::BackrefTooBig)
+    }
 }
 
 pub struct NockJammer;
 
-impl Jammer for NockJammer {
-    fn jam(noun: Noun) -> Bytes {
-        fn mat_backref(buffer: &mut BitVec<u8, Lsb0>, backref: usize) {
-            if backref == 0 {
-                buffer.extend_from_bitslice(bits![u8, Lsb0; 1, 1, 1]);
-                return;
+fn mat_backref(buffer: &mut BitVec<u8, Lsb0>, backref: usize) {
+    if backref == 0 {
+        buffer.extend_from_bitslice(bits![u8, Lsb0; 1, 1, 1]);
+        return;
+    }
+    let backref_sz = met0_u64_to_usize(backref as u64);
+    let backref_sz_sz = met0_u64_to_usize(backref_sz as u64);
+    buffer.extend_from_bitslice(bits![u8, Lsb0; 1, 1]); // backref tag
+    let buffer_len = buffer.len();
+    buffer.resize(buffer_len + backref_sz_sz, false);
+    buffer.push(true);
+    buffer.extend_from_bitslice(
+        &BitSlice::<_, Lsb0>::from_element(&backref_sz)[0..backref_sz_sz - 1],
+    );
+    buffer.extend_from_bitslice(&BitSlice::<_, Lsb0>::from_element(&backref)[0..backref_sz]);
+}
+
+fn mat_atom(buffer: &mut BitVec<u8, Lsb0>, atom: Atom) {
+    if unsafe { atom.as_noun().raw_equals(&D(0)) } {
+        buffer.extend_from_bitslice(bits![u8, Lsb0; 0, 1]);
+        return;
+    }
+    let atom_sz = met0_usize(atom);
+    let atom_sz_sz = met0_u64_to_usize(atom_sz as u64);
+    buffer.push(false); // atom tag
+    let buffer_len = buffer.len();
+    buffer.resize(buffer_len + atom_sz_sz, false);
+    buffer.push(true);
+    buffer.extend_from_bitslice(&BitSlice::<_, Lsb0>::from_element(&atom_sz)[0..atom_sz_sz - 1]);
+    buffer.extend_from_bitslice(&atom.as_bitslice()[0..atom_sz]);
+}
+
+/// Which earlier subtrees jam may refer back to instead of writing out again
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backrefs {
+    /// Back-reference any subtree equal to one already written. This finds all sharing but mugs
+    /// and compares every subtree.
+    #[default]
+    Structural,
+    /// Back-reference only subtrees that are the same allocation as one already written. Much
+    /// cheaper than [`Backrefs::Structural`], and just as compact after [`NounSlab::unify`].
+    Pointer,
+    /// Never back-reference: every subtree is written out in full.
+    Off,
+}
+
+/// Options for [`NounSlab::jam_with_options`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JamOptions {
+    pub backrefs: Backrefs,
+}
+
+/// What a jam wrote out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JamStats {
+    /// Cells written in full
+    pub cells: usize,
+    /// Atoms written in full
+    pub atoms: usize,
+    /// Back-references written in place of a repeated subtree
+    pub backrefs: usize,
+    /// Total size of the jam
+    pub bits: usize,
+}
+
+/// Statistics from [`NounSlab::unify`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UnifyStats {
+    /// Distinct cells after unifying
+    pub cells: usize,
+    /// Cells replaced by an equal cell
+    pub cells_merged: usize,
+    /// Distinct indirect atoms after unifying
+    pub atoms: usize,
+    /// Indirect atoms replaced by an equal atom
+    pub atoms_merged: usize,
+}
+
+enum BackrefTable {
+    Structural(NounMap<usize>),
+    Pointer(IntMap<u64, usize>),
+    Off,
+}
+
+impl BackrefTable {
+    fn new(backrefs: Backrefs) -> Self {
+        match backrefs {
+            Backrefs::Structural => BackrefTable::Structural(NounMap::new()),
+            Backrefs::Pointer => BackrefTable::Pointer(IntMap::new()),
+            Backrefs::Off => BackrefTable::Off,
+        }
+    }
+
+    fn get(&self, noun: Noun) -> Option<usize> {
+        match self {
+            BackrefTable::Structural(map) => map.get(noun).copied(),
+            BackrefTable::Pointer(map) => {
+                let allocated = noun.as_allocated().ok()?;
+                map.get(unsafe { allocated.to_raw_pointer() } as u64)
+                    .copied()
             }
-            let backref_sz = met0_u64_to_usize(backref as u64);
-            let backref_sz_sz = met0_u64_to_usize(backref_sz as u64);
-            buffer.extend_from_bitslice(bits![u8, Lsb0; 1, 1]); // backref tag
-            let buffer_len = buffer.len();
-            buffer.resize(buffer_len + backref_sz_sz, false);
-            buffer.push(true);
-            buffer.extend_from_bitslice(
-                &BitSlice::<_, Lsb0>::from_element(&backref_sz)[0..backref_sz_sz - 1],
-            );
-            buffer
-                .extend_from_bitslice(&BitSlice::<_, Lsb0>::from_element(&backref)[0..backref_sz]);
+            BackrefTable::Off => None,
         }
+    }
 
-        fn mat_atom(buffer: &mut BitVec<u8, Lsb0>, atom: Atom) {
-            if unsafe { atom.as_noun().raw_equals(&D(0)) } {
-                buffer.extend_from_bitslice(bits![u8, Lsb0; 0, 1]);
-                return;
+    fn insert(&mut self, noun: Noun, position: usize) {
+        match self {
+            BackrefTable::Structural(map) => map.insert(noun, position),
+            BackrefTable::Pointer(map) => {
+                if let Ok(allocated) = noun.as_allocated() {
+                    map.insert(unsafe { allocated.to_raw_pointer() } as u64, position);
+                }
             }
-            let atom_sz = met0_usize(atom);
-            let atom_sz_sz = met0_u64_to_usize(atom_sz as u64);
-            buffer.push(false); // atom tag
-            let buffer_len = buffer.len();
-            buffer.resize(buffer_len + atom_sz_sz, false);
-            buffer.push(true);
-            buffer.extend_from_bitslice(
-                &BitSlice::<_, Lsb0>::from_element(&atom_sz)[0..atom_sz_sz - 1],
-            );
-            buffer.extend_from_bitslice(&atom.as_bitslice()[0..atom_sz]);
+            BackrefTable::Off => {}
         }
+    }
+}
 
-        let mut backref_map = NounMap::<usize>::new();
-        let mut stack = vec![noun];
-        let mut buffer = bitvec![u8, Lsb0; 0; 0];
-        while let Some(noun) = stack.pop() {
-            if let Some(backref) = backref_map.get(noun) {
-                if let Ok(atom) = noun.as_atom() {
-                    if met0_u64_to_usize(*backref as u64) < met0_usize(atom) {
-                        mat_backref(&mut buffer, *backref);
-                    } else {
-                        mat_atom(&mut buffer, atom)
-                    }
+/// Jam a noun into `sink`, optionally recording the mug of every cell and indirect atom written
+/// out in full, in stream order. Structural jamming mugs everything anyway, so this costs nothing
+/// extra there.
+fn jam_inner<W: Write>(
+    noun: Noun,
+    options: JamOptions,
+    mut mugs: Option<&mut Vec<u32>>,
+    sink: &mut BitSink<W>,
+) -> io::Result<JamStats> {
+    let mut backref_map = BackrefTable::new(options.backrefs);
+    let mut stats = JamStats::default();
+    let mut stack = vec![noun];
+    while let Some(noun) = stack.pop() {
+        if let Some(backref) = backref_map.get(noun) {
+            if let Ok(atom) = noun.as_atom() {
+                if met0_u64_to_usize(backref as u64) < met0_usize(atom) {
+                    stats.backrefs += 1;
+                    mat_backref(&mut sink.buffer, backref);
                 } else {
-                    mat_backref(&mut buffer, *backref);
+                    stats.atoms += 1;
+                    record_mug(&mut mugs, noun);
+                    mat_atom(&mut sink.buffer, atom)
                 }
             } else {
-                backref_map.insert(noun, buffer.len());
-                match noun.as_either_atom_cell() {
-                    Either::Left(atom) => {
-                        mat_atom(&mut buffer, atom);
-                    }
-                    Either::Right(cell) => {
-                        buffer.extend_from_bitslice(bits![u8, Lsb0; 1, 0]); // cell tag
-                        stack.push(cell.tail());
-                        stack.push(cell.head());
-                    }
+                stats.backrefs += 1;
+                mat_backref(&mut sink.buffer, backref);
+            }
+        } else {
+            backref_map.insert(noun, sink.position());
+            record_mug(&mut mugs, noun);
+            match noun.as_either_atom_cell() {
+                Either::Left(atom) => {
+                    stats.atoms += 1;
+                    mat_atom(&mut sink.buffer, atom);
+                }
+                Either::Right(cell) => {
+                    stats.cells += 1;
+                    sink.buffer.extend_from_bitslice(bits![u8, Lsb0; 1, 0]); // cell tag
+                    stack.push(cell.tail());
+                    stack.push(cell.head());
                 }
             }
         }
-        Bytes::copy_from_slice(buffer.as_raw_slice())
+        sink.maybe_flush()?;
+    }
+    stats.bits = sink.position();
+    Ok(stats)
+}
+
+/// [`jam_inner`] into memory
+fn jam_to_bytes(noun: Noun, options: JamOptions, mugs: Option<&mut Vec<u32>>) -> (Bytes, JamStats) {
+    let mut sink = BitSink::new(Vec::new());
+    let stats = jam_inner(noun, options, mugs, &mut sink).expect("writing to a Vec cannot fail");
+    let jam = sink.finish().expect("writing to a Vec cannot fail");
+    (Bytes::from(jam), stats)
+}
+
+fn record_mug(mugs: &mut Option<&mut Vec<u32>>, noun: Noun) {
+    if let Some(mugs) = mugs {
+        if noun.is_allocated() {
+            mugs.push(slab_mug(noun));
+        }
+    }
+}
+
+impl Jammer for NockJammer {
+    fn jam(noun: Noun) -> Bytes {
+        jam_to_bytes(noun, JamOptions::default(), None).0
+    }
+
+    fn jam_with_mugs(noun: Noun) -> (Bytes, Vec<u32>) {
+        let mut mugs = Vec::new();
+        let (jam, _) = jam_to_bytes(noun, JamOptions::default(), Some(&mut mugs));
+        (jam, mugs)
     }
 
     fn cue(slab: &mut NounSlab, jammed: Bytes) -> Result<Noun, CueError> {
@@ -828,6 +1506,96 @@ impl Jammer for NockJammer {
         tracing::trace!("cue_into: noun_counter {}", noun_counter);
         Ok(res)
     }
+
+    fn jam_to_writer<W: Write>(noun: Noun, writer: W) -> io::Result<usize> {
+        let mut sink = BitSink::new(writer);
+        let stats = jam_inner(noun, JamOptions::default(), None, &mut sink)?;
+        sink.finish()?;
+        Ok(stats.bits.div_ceil(8))
+    }
+
+    fn jam_with_mugs_to_writer<W: Write>(noun: Noun, writer: W) -> io::Result<(usize, Vec<u32>)> {
+        let mut mugs = Vec::new();
+        let mut sink = BitSink::new(writer);
+        let stats = jam_inner(noun, JamOptions::default(), Some(&mut mugs), &mut sink)?;
+        sink.finish()?;
+        Ok((stats.bits.div_ceil(8), mugs))
+    }
+
+    fn cue_from_reader<R: Read>(slab: &mut NounSlab, reader: R) -> Result<Noun, CueError> {
+        let mut source = BitSource::new(reader);
+        let mut backref_map = IntMap::new();
+        let mut res = D(0);
+        let mut stack = vec![CueStackEntry::DestinationPointer(&mut res)];
+        while let Some(entry) = stack.pop() {
+            match entry {
+                CueStackEntry::DestinationPointer(dest) => {
+                    let backref = source.position() as u64;
+                    if source.bit()? {
+                        if source.bit()? {
+                            // 11 - backref
+                            let target = match source.rub_size()? {
+                                None => 0u64,
+                                Some(sz) => {
+                                    if sz > 64 {
+                                        Err(CueError::BackrefTooBig)?;
+                                    }
+                                    let mut target = 0u64;
+                                    source.read_into(
+                                        &mut BitSlice::<_, Lsb0>::from_element_mut(&mut target)
+                                            [0..sz],
+                                    )?;
+                                    target
+                                }
+                            };
+                            if let Some(noun) = backref_map.get(target) {
+                                unsafe { *dest = *noun };
+                            } else {
+                                Err(CueError::BadBackref)?
+                            }
+                        } else {
+                            // 10 - cell
+                            let (cell, cell_mem) = unsafe { Cell::new_raw_mut(slab) };
+                            unsafe {
+                                *dest = cell.as_noun();
+                                stack.push(CueStackEntry::BackRef(backref, dest as *const Noun));
+                                stack
+                                    .push(CueStackEntry::DestinationPointer(&mut (*cell_mem).tail));
+                                stack
+                                    .push(CueStackEntry::DestinationPointer(&mut (*cell_mem).head));
+                            }
+                        }
+                    } else {
+                        // 0 - atom
+                        let atom = match source.rub_size()? {
+                            None => D(0),
+                            Some(sz) if sz < 64 => {
+                                let mut data = 0u64;
+                                source.read_into(
+                                    &mut BitSlice::<_, Lsb0>::from_element_mut(&mut data)[0..sz],
+                                )?;
+                                unsafe { DirectAtom::new_unchecked(data).as_noun() }
+                            }
+                            Some(sz) => {
+                                let indirect_words = (sz + 63) >> 6;
+                                let (mut indirect, slice) = unsafe {
+                                    IndirectAtom::new_raw_mut_bitslice(slab, indirect_words)
+                                };
+                                source.read_into(&mut slice[0..sz])?;
+                                unsafe { indirect.normalize_as_atom().as_noun() }
+                            }
+                        };
+                        unsafe { *dest = atom };
+                        backref_map.insert(backref, atom);
+                    }
+                }
+                CueStackEntry::BackRef(backref, noun_ptr) => {
+                    backref_map.insert(backref, unsafe { *noun_ptr });
+                }
+            }
+        }
+        Ok(res)
+    }
 }
 
 #[cfg(test)]
@@ -930,6 +1698,185 @@ mod tests {
         );
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_streaming_jam_cue() {
+        let mut slab: NounSlab = NounSlab::new();
+        // Large enough to span several stream chunks, with shared subtrees for backrefs
+        let shared = T(&mut slab, &[D(tas!(b"shared")), D(1), D(2)]);
+        let mut list = D(0);
+        for i in 0..20_000u128 {
+            let bytes = Bytes::from((i << 64 | i).to_le_bytes().to_vec());
+            let atom = Atom::from_bytes(&mut slab, &bytes).as_noun();
+            list = T(&mut slab, &[atom, shared, list]);
+        }
+        slab.set_root(list);
+
+        let mut streamed = Vec::new();
+        let written = slab.jam_to_writer(&mut streamed).expect("jam_to_writer");
+        let jammed = slab.jam();
+        assert_eq!(written, streamed.len());
+        assert_eq!(&streamed[..], &jammed[..], "streamed jam should match jam");
+
+        let mut cued_slab: NounSlab = NounSlab::new();
+        let cued = cued_slab
+            .cue_from_reader(&streamed[..])
+            .expect("cue_from_reader");
+        assert!(slab_noun_equality(&list, &cued));
+    }
+
+    #[test]
+    fn test_cue_from_reader_truncated() {
+        let mut slab: NounSlab = NounSlab::new();
+        let noun = T(&mut slab, &[D(tas!(b"request")), D(u64::MAX >> 1), D(0)]);
+        slab.set_root(noun);
+        let jammed = slab.jam();
+
+        let mut cued_slab: NounSlab = NounSlab::new();
+        let result = cued_slab.cue_from_reader(&jammed[..jammed.len() - 2]);
+        assert!(matches!(result, Err(CueError::TruncatedBuffer)));
+    }
+
+    #[test]
+    fn test_memory_accounting() {
+        let mut slab: NounSlab = NounSlab::new();
+        assert_eq!(slab.allocated_bytes(), 0);
+        assert_eq!(slab.allocation_stats(), AllocationStats::default());
+
+        let big = Atom::from_bytes(&mut slab, &Bytes::from(vec![0xff; 16])).as_noun();
+        let shared = T(&mut slab, &[D(1), big]);
+        let noun = T(&mut slab, &[shared, shared, big]);
+        slab.set_root(noun);
+
+        let stats = slab.allocation_stats();
+        assert_eq!(stats.chunks, 1);
+        assert_eq!(stats.cells, 3);
+        assert_eq!(stats.indirect_atoms, 1);
+        assert_eq!(stats.indirect_words, 4);
+        assert_eq!(stats.used_bytes(), (3 * CELL_MEM_WORD_SIZE + 4) << 3);
+        assert_eq!(slab.allocated_bytes(), idx_to_size(0) << 3);
+
+        let size = slab.size_of(noun);
+        assert_eq!(size.cells, 3);
+        assert_eq!(size.indirect_atoms, 1);
+        assert_eq!(size.bytes, stats.used_bytes());
+        // `shared` once more, and `big` from both the second reference and the tail
+        assert_eq!(size.shared_references, 2);
+        assert_eq!(size.external, 0);
+
+        let other: NounSlab = NounSlab::new();
+        let size = other.size_of(noun);
+        assert_eq!(size.external, 1);
+        assert_eq!(size.bytes, 0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_restore_mugs() {
+        let mut slab: NounSlab = NounSlab::new();
+        let big = Atom::from_bytes(&mut slab, &Bytes::from(vec![0x5a; 24])).as_noun();
+        let shared = T(&mut slab, &[D(tas!(b"shared")), big]);
+        let noun = T(&mut slab, &[shared, big, shared, D(7)]);
+        slab.set_root(noun);
+
+        let (jammed, mugs) = NockJammer::jam_with_mugs(noun);
+        assert_eq!(jammed, slab.jam());
+
+        let mut cued_slab: NounSlab = NounSlab::new();
+        let cued = cued_slab.cue_into(jammed.clone()).expect("cue");
+        cued_slab.set_root(cued);
+        assert!(get_mug(cued).is_none());
+        assert!(cued_slab.restore_mugs(&mugs));
+        assert_eq!(get_mug(cued), Some(slab_mug(noun)));
+        let tail = cued.as_cell().expect("cell").tail();
+        assert_eq!(
+            get_mug(tail),
+            Some(slab_mug(noun.as_cell().expect("cell").tail()))
+        );
+
+        let mut short_slab: NounSlab = NounSlab::new();
+        let cued = short_slab.cue_into(jammed).expect("cue");
+        short_slab.set_root(cued);
+        assert!(!short_slab.restore_mugs(&mugs[1..]));
+        assert!(get_mug(cued).is_none());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_jam_options_and_unify() {
+        let mut slab: NounSlab = NounSlab::new();
+        let bytes = Bytes::from(vec![0xff; 16]);
+        let big_1 = Atom::from_bytes(&mut slab, &bytes).as_noun();
+        let big_2 = Atom::from_bytes(&mut slab, &bytes).as_noun();
+        let a_1 = T(&mut slab, &[D(1), D(2), D(3)]);
+        let a_2 = T(&mut slab, &[D(1), D(2), D(3)]);
+        let noun = T(&mut slab, &[a_1, a_2, big_1, big_2]);
+        slab.set_root(noun);
+
+        let structural = JamOptions {
+            backrefs: Backrefs::Structural,
+        };
+        let pointer = JamOptions {
+            backrefs: Backrefs::Pointer,
+        };
+        let off = JamOptions {
+            backrefs: Backrefs::Off,
+        };
+
+        let (structural_jam, stats) = slab.jam_with_options(structural);
+        assert_eq!(structural_jam, slab.jam());
+        assert_eq!((stats.cells, stats.backrefs), (5, 2));
+        let (_, stats) = slab.jam_with_options(off);
+        assert_eq!((stats.cells, stats.backrefs), (7, 0));
+        let (_, stats) = slab.jam_with_options(pointer);
+        assert_eq!((stats.cells, stats.backrefs), (7, 0));
+
+        let unify_stats = slab.unify();
+        assert_eq!(
+            unify_stats,
+            UnifyStats {
+                cells: 5,
+                cells_merged: 2,
+                atoms: 1,
+                atoms_merged: 1,
+            }
+        );
+        assert!(slab_noun_equality(&slab.root, &noun));
+        let (pointer_jam, stats) = slab.jam_with_options(pointer);
+        assert_eq!((stats.cells, stats.backrefs), (5, 2));
+        assert_eq!(pointer_jam, structural_jam);
+    }
+
+    #[test]
+    fn test_reset_retains_capacity() {
+        let mut slab: NounSlab = NounSlab::new();
+        slab.reset();
+        assert_eq!(slab.allocated_bytes(), 0);
+
+        let mut list = D(0);
+        for i in 0..10_000 {
+            list = T(&mut slab, &[D(i), list]);
+        }
+        slab.set_root(list);
+        let largest = slab
+            .slabs
+            .iter()
+            .map(|(_, layout)| layout.size())
+            .max()
+            .expect("chunks");
+        assert!(slab.allocation_stats().chunks > 1);
+
+        slab.reset();
+        assert!(unsafe { slab.root().raw_equals(&D(0)) });
+        assert_eq!(slab.allocated_bytes(), largest);
+        assert_eq!(slab.allocation_stats().chunks, 1);
+
+        let noun = T(&mut slab, &[D(1), D(2)]);
+        slab.set_root(noun);
+        assert_eq!(slab.allocation_stats().chunks, 1);
+        assert_eq!(slab.allocated_bytes(), largest);
+    }
+
     #[test]
     fn test_tas_macro() {
         let mut slab: NounSlab = NounSlab::new();
@@ -1059,6 +2006,39 @@ mod tests {
         copy_slab.copy_into(test_noun);
     }
 
+    #[test]
+    fn test_noun_slab_copy_from() {
+        let mut first: NounSlab = NounSlab::new();
+        let big = Atom::new(&mut first, u64::MAX).as_noun();
+        let inner = T(&mut first, &[D(1), big]);
+        let shared = T(&mut first, &[inner, inner]);
+        first.set_root(shared);
+        let mut second: NounSlab = NounSlab::new();
+        let big = Atom::new(&mut second, u64::MAX).as_noun();
+        let inner = T(&mut second, &[D(1), big]);
+        let again = T(&mut second, &[D(7), inner]);
+        second.set_root(again);
+
+        let mut gathered: NounSlab = NounSlab::new();
+        let one = gathered.copy_from(&first, shared);
+        assert_eq!(unsafe { gathered.root().as_raw() }, 0);
+        assert!(slab_noun_equality(&one, &shared));
+        // [1 big] once, and the cell holding it twice
+        assert_eq!(gathered.allocation_stats().cells, 2);
+        let two = gathered.copy_from(&second, again);
+        assert!(slab_noun_equality(&two, &again));
+        // only the new [7 ...] cell, since [1 big] was copied from the first slab
+        assert_eq!(gathered.allocation_stats().cells, 3);
+        assert_eq!(gathered.allocation_stats().indirect_atoms, 1);
+        unsafe {
+            assert!(two
+                .as_cell()
+                .unwrap()
+                .tail()
+                .raw_equals(&one.as_cell().unwrap().head()));
+        }
+    }
+
     // Fails in Miri
     // #[test]
     // fn test_alloc_cell_for_noun_slab_uninit() {
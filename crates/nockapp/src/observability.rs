@@ -68,6 +68,104 @@ pub fn init_tracing() -> Result<impl tracing::Subscriber, opentelemetry::trace::
     Ok(subscriber)
 }
 
+static TRACER_PROVIDER: std::sync::OnceLock<opentelemetry_sdk::trace::TracerProvider> =
+    std::sync::OnceLock::new();
+
+/// A tracer exporting spans over OTLP (gRPC) to `endpoint`, sampled at `OTEL_TRACES_SAMPLE_RATE`
+pub fn otlp_tracer(
+    endpoint: &str,
+    service_name: &str,
+) -> Result<opentelemetry_sdk::trace::Tracer, opentelemetry::trace::TraceError> {
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::trace::Sampler;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .with_timeout(std::time::Duration::from_secs(30))
+        .build()?;
+    let sampling_ratio = std::env::var("OTEL_TRACES_SAMPLE_RATE")
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .unwrap_or(1.0);
+    let resource = opentelemetry_sdk::Resource::new(vec![
+        opentelemetry::KeyValue::new("service.name", service_name.to_string()),
+        opentelemetry::KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+    ]);
+    let provider = opentelemetry_sdk::trace::TracerProvider::builder()
+        .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+        .with_resource(resource)
+        // Spans in a sampled trace are all kept, so a poke's trace is whole
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            sampling_ratio,
+        ))))
+        .build();
+    let tracer = provider.tracer(service_name.to_string());
+    let _ = TRACER_PROVIDER.set(provider);
+    Ok(tracer)
+}
+
+/// Sends the spans the OTLP exporter is holding, if there is one. Blocks until they're sent.
+pub fn flush_traces() {
+    if let Some(provider) = TRACER_PROVIDER.get() {
+        for result in provider.force_flush() {
+            if let Err(e) = result {
+                eprintln!("Failed to export traces: {}", e);
+            }
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LogFilterError {
+    #[error("Invalid log filter: {0}")]
+    Parse(#[from] tracing_subscriber::filter::ParseError),
+    #[error("The log filter of this process can't be changed")]
+    NotReloadable,
+    #[error("Failed to change the log filter: {0}")]
+    Reload(#[from] tracing_subscriber::reload::Error),
+}
+
+struct LogFilter {
+    handle: tracing_subscriber::reload::Handle<
+        tracing_subscriber::EnvFilter,
+        tracing_subscriber::Registry,
+    >,
+    /// Directives kept after every change, like hoonc's `slogger=off`
+    suffix: String,
+}
+
+static LOG_FILTER: std::sync::OnceLock<LogFilter> = std::sync::OnceLock::new();
+
+/// Lets [`set_log_filter`] change the filter of the log behind `handle`
+pub(crate) fn set_log_filter_handle(
+    handle: tracing_subscriber::reload::Handle<
+        tracing_subscriber::EnvFilter,
+        tracing_subscriber::Registry,
+    >,
+    suffix: &str,
+) {
+    let _ = LOG_FILTER.set(LogFilter {
+        handle,
+        suffix: suffix.to_string(),
+    });
+}
+
+/// The directives the log is filtered by, if they can be changed
+pub fn log_filter() -> Option<String> {
+    let filter = LOG_FILTER.get()?;
+    filter.handle.with_current(|f| f.to_string()).ok()
+}
+
+/// Filters the log by `directives`, like `info,nockapp::drivers::file=debug`, from now on
+pub fn set_log_filter(directives: &str) -> Result<(), LogFilterError> {
+    let filter = LOG_FILTER.get().ok_or(LogFilterError::NotReloadable)?;
+    let new = tracing_subscriber::EnvFilter::try_new(format!("{}{}", directives, filter.suffix))?;
+    filter.handle.reload(new)?;
+    Ok(())
+}
+
 // pub fn init_tracing() -> Result<impl tracing::Subscriber, opentelemetry::trace::TraceError> {
 //     use opentelemetry::trace::TracerProvider;
 //     use opentelemetry_otlp::WithExportConfig;
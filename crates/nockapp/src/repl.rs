@@ -0,0 +1,418 @@
+//! An interactive prompt for poking and peeking a running NockApp over its NPC socket.
+//!
+//! `nockapp repl --socket <path>` connects to a process serving an NPC socket (a node serves one
+//! at `--npc-socket`) and reads commands from stdin:
+//!
+//! | Command               | Does                                                             |
+//! |-----------------------|------------------------------------------------------------------|
+//! | `poke <noun>`         | sends `[pid %poke noun]`, answered by `ack` or `nack`            |
+//! | `peek /a/b`           | sends `[pid %scry %jam '/a/b']`, answered by the value or a nack |
+//! | `peek json /a/b`      | sends `[pid %scry %json '/a/b']`, for paths answered in JSON     |
+//! | `peek <noun>`         | sends `[pid %peek noun]`, for paths with non-`@ta` segments      |
+//! | `history`, `!n`, `!!` | lists the history, or reruns entry `n` or the last entry         |
+//!
+//! Nouns are written as [`literal`](crate::noun::literal)s and responses are printed with
+//! [`PrettyNoun`], so a response can be pasted into the next command. The NPC listener doesn't
+//! answer a `%peek` the kernel fails, so replies are waited on for at most `--timeout`, except on
+//! Windows, where a named pipe can't time out a read. Messages for other pids, like effects the
+//! kernel sends to its NPC clients, are printed as they arrive.
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use nockvm::noun::{Noun, D, T};
+use nockvm_macros::tas;
+use thiserror::Error;
+
+use crate::noun::literal::{parse_noun, LiteralError};
+use crate::noun::pretty::PrettyNoun;
+use crate::noun::scry::{parse_path, Scry, ScryEncoding, ScryError};
+use crate::noun::slab::{CueError, NounSlab};
+use crate::utils::ipc::{connect_blocking, BlockingIpcClient};
+use crate::utils::make_tas;
+use crate::{AtomExt, Bytes};
+
+/// How many entries are kept in the history file
+const HISTORY_LIMIT: usize = 1000;
+
+const HELP: &str = "\
+poke <noun>         poke the kernel
+peek /a/b           peek a path of @ta segments
+peek json /a/b      peek a path the kernel answers in JSON
+peek <noun>         peek a path noun, like [%block 0x1.beef ~]
+history             list the history
+!n, !!              rerun history entry n, or the last entry
+help                show this
+quit                leave";
+
+#[derive(Debug, Error)]
+pub enum ReplError {
+    #[error("repl: {0}")]
+    Io(#[from] io::Error),
+    #[error("repl: no reply within {0:?}")]
+    Timeout(Duration),
+    #[error("repl: connection closed")]
+    Closed,
+    #[error("repl: {0}")]
+    Cue(#[from] CueError),
+    #[error("repl: malformed reply {0}")]
+    Malformed(String),
+    #[error(transparent)]
+    Literal(#[from] LiteralError),
+    #[error(transparent)]
+    Scry(#[from] ScryError),
+    #[error("repl: {0}")]
+    Usage(String),
+}
+
+pub type Result<T, E = ReplError> = std::result::Result<T, E>;
+
+/// A parsed line of input
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Poke(String),
+    Peek(String),
+    Scry(ScryEncoding, String),
+    History,
+    Help,
+    Quit,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Option<Self>> {
+        let line = line.trim();
+        let (verb, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let command = match verb {
+            "" => return Ok(None),
+            "poke" if !rest.is_empty() => Command::Poke(rest.to_string()),
+            "peek" if rest.starts_with('/') => Command::Scry(ScryEncoding::Jam, rest.to_string()),
+            "peek" if rest.starts_with("json ") => {
+                Command::Scry(ScryEncoding::Json, rest["json ".len()..].trim().to_string())
+            }
+            "peek" if !rest.is_empty() => Command::Peek(rest.to_string()),
+            "poke" | "peek" => return Err(ReplError::Usage(format!("{} needs an argument", verb))),
+            "history" => Command::History,
+            "help" | "?" => Command::Help,
+            "quit" | "exit" => Command::Quit,
+            _ => {
+                return Err(ReplError::Usage(format!(
+                    "unknown command {:?}; try help",
+                    verb
+                )))
+            }
+        };
+        Ok(Some(command))
+    }
+}
+
+/// Lines entered at the prompt, kept in a file across sessions
+pub struct History {
+    entries: Vec<String>,
+    file: Option<PathBuf>,
+}
+
+impl History {
+    /// The default history file, `~/.nockapp_repl_history`
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".nockapp_repl_history"))
+    }
+
+    /// Load the history in `file`, if it exists, and append to it from now on.
+    pub fn load(file: Option<PathBuf>) -> Self {
+        let entries = file
+            .as_ref()
+            .and_then(|file| std::fs::read_to_string(file).ok())
+            .map(|text| text.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        History { entries, file }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Replace a `!n` or `!!` line with the entry it recalls.
+    pub fn expand(&self, line: &str) -> Result<String> {
+        let line = line.trim();
+        let Some(index) = line.strip_prefix('!') else {
+            return Ok(line.to_string());
+        };
+        let entry = if index == "!" {
+            self.entries.last()
+        } else {
+            index
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| n.checked_sub(1))
+                .and_then(|n| self.entries.get(n))
+        };
+        entry
+            .cloned()
+            .ok_or_else(|| ReplError::Usage(format!("no history entry {}", line)))
+    }
+
+    pub fn push(&mut self, line: &str) -> io::Result<()> {
+        if self.entries.last().map(String::as_str) == Some(line) {
+            return Ok(());
+        }
+        self.entries.push(line.to_string());
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        if self.entries.len() > HISTORY_LIMIT {
+            self.entries.drain(..self.entries.len() - HISTORY_LIMIT);
+            std::fs::write(file, self.entries.join("\n") + "\n")
+        } else {
+            let mut file = OpenOptions::new().create(true).append(true).open(file)?;
+            writeln!(file, "{}", line)
+        }
+    }
+}
+
+/// A reply to a request
+pub enum Reply {
+    Ack,
+    Nack(Option<String>),
+    /// The `(unit (unit *))` a peek answered with
+    Bind(NounSlab),
+}
+
+/// A client connection to an NPC socket
+pub struct NpcConnection {
+    stream: BlockingIpcClient,
+    next_pid: u64,
+    timeout: Duration,
+}
+
+impl NpcConnection {
+    pub fn connect(path: &Path, timeout: Duration) -> Result<Self> {
+        let stream = connect_blocking(path, timeout)?;
+        Ok(NpcConnection {
+            stream,
+            next_pid: 1,
+            timeout,
+        })
+    }
+
+    /// Send `[pid request]`, where `build` makes the request, and wait for the reply to it.
+    /// Messages for other pids are passed to `other` while waiting.
+    pub fn request<F>(&mut self, build: F, mut other: impl FnMut(NounSlab)) -> Result<Reply>
+    where
+        F: FnOnce(&mut NounSlab) -> Result<Noun>,
+    {
+        let pid = self.next_pid;
+        self.next_pid += 1;
+        let mut slab = NounSlab::new();
+        let request = build(&mut slab)?;
+        let message = T(&mut slab, &[D(pid), request]);
+        slab.set_root(message);
+        self.send(&slab)?;
+        loop {
+            let mut reply = self.receive()?;
+            let root = unsafe { *reply.root() };
+            let malformed = || ReplError::Malformed(PrettyNoun::new(root).to_string());
+            let cell = root.as_cell().map_err(|_| malformed())?;
+            if cell.head().as_direct().map(|pid| pid.data()).ok() != Some(pid) {
+                other(reply);
+                continue;
+            }
+            let directive = cell.tail().as_cell().map_err(|_| malformed())?;
+            let tag = directive.head().as_direct().map_err(|_| malformed())?;
+            return match tag.data() {
+                tas!(b"pack") => Ok(Reply::Ack),
+                tas!(b"nack") => {
+                    let message = directive
+                        .tail()
+                        .as_atom()
+                        .ok()
+                        .and_then(|message| message.into_string().ok())
+                        .filter(|message| !message.is_empty());
+                    Ok(Reply::Nack(message))
+                }
+                tas!(b"bind") => {
+                    reply.set_root(directive.tail());
+                    Ok(Reply::Bind(reply))
+                }
+                _ => Err(malformed()),
+            };
+        }
+    }
+
+    fn send(&mut self, slab: &NounSlab) -> Result<()> {
+        let bytes = slab.jam();
+        self.stream.write_all(&bytes.len().to_le_bytes())?;
+        self.stream.write_all(&bytes)?;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<NounSlab> {
+        let mut size = [0u8; 8];
+        self.read_exact(&mut size)?;
+        let mut buf = vec![0u8; usize::from_le_bytes(size)];
+        self.read_exact(&mut buf)?;
+        let mut slab = NounSlab::new();
+        let noun = slab.cue_into(Bytes::from(buf))?;
+        slab.set_root(noun);
+        Ok(slab)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.stream.read_exact(buf).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => ReplError::Closed,
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => ReplError::Timeout(self.timeout),
+            _ => ReplError::Io(e),
+        })
+    }
+}
+
+/// Read commands from `input` until it ends or `quit`, writing replies to `output`.
+pub fn run<R: BufRead, W: Write>(
+    connection: &mut NpcConnection,
+    history: &mut History,
+    mut input: R,
+    mut output: W,
+) -> Result<()> {
+    let mut line = String::new();
+    loop {
+        write!(output, "> ")?;
+        output.flush()?;
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let entry = match history.expand(&line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                writeln!(output, "{}", e)?;
+                continue;
+            }
+        };
+        if entry != line.trim() {
+            writeln!(output, "{}", entry)?;
+        }
+        let command = match Command::parse(&entry) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(e) => {
+                writeln!(output, "{}", e)?;
+                continue;
+            }
+        };
+        history.push(&entry)?;
+        match command {
+            Command::Quit => return Ok(()),
+            Command::Help => writeln!(output, "{}", HELP)?,
+            Command::History => {
+                for (i, entry) in history.entries().iter().enumerate() {
+                    writeln!(output, "{:>5}  {}", i + 1, entry)?;
+                }
+            }
+            request => match send(connection, &request, &mut output) {
+                Ok(()) => {}
+                Err(e @ (ReplError::Closed | ReplError::Io(_))) => return Err(e),
+                Err(e) => writeln!(output, "{}", e)?,
+            },
+        }
+    }
+}
+
+fn send<W: Write>(connection: &mut NpcConnection, command: &Command, output: &mut W) -> Result<()> {
+    let mut others = Vec::new();
+    let reply = connection.request(
+        |slab| match command {
+            Command::Poke(literal) => {
+                let noun = parse_noun(slab, literal)?;
+                Ok(T(slab, &[D(tas!(b"poke")), noun]))
+            }
+            Command::Peek(literal) => {
+                let path = parse_noun(slab, literal)?;
+                Ok(T(slab, &[D(tas!(b"peek")), path]))
+            }
+            Command::Scry(encoding, path) => {
+                // Check the path here, for the error, though the listener parses it again
+                parse_path(slab, path)?;
+                let encoding = match encoding {
+                    ScryEncoding::Jam => D(tas!(b"jam")),
+                    ScryEncoding::Json => D(tas!(b"json")),
+                };
+                let path = make_tas(slab, path).as_noun();
+                Ok(T(slab, &[D(tas!(b"scry")), encoding, path]))
+            }
+            _ => unreachable!("only requests are sent"),
+        },
+        |message| others.push(message),
+    );
+    for message in others {
+        writeln!(output, "npc: {}", message)?;
+    }
+    match reply? {
+        Reply::Ack => writeln!(output, "ack")?,
+        Reply::Nack(None) => writeln!(output, "nack")?,
+        Reply::Nack(Some(message)) => writeln!(output, "nack: {}", message)?,
+        Reply::Bind(slab) => {
+            let json = matches!(command, Command::Scry(ScryEncoding::Json, _));
+            match Scry::from_peek(unsafe { *slab.root() })? {
+                Scry::Unknown => writeln!(output, "~ (the kernel doesn't handle this path)")?,
+                Scry::Empty => writeln!(output, "[~ ~] (no value)")?,
+                Scry::Value(value) => match value.as_atom().ok().filter(|_| json) {
+                    Some(text) => {
+                        let text = text.into_string().unwrap_or_default();
+                        writeln!(output, "{}", text)?
+                    }
+                    None => writeln!(output, "{}", PrettyNoun::new(value))?,
+                },
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(Command::parse("  ").unwrap(), None);
+        assert_eq!(
+            Command::parse("poke [%command 1]").unwrap(),
+            Some(Command::Poke("[%command 1]".into()))
+        );
+        assert_eq!(
+            Command::parse("peek /heavy").unwrap(),
+            Some(Command::Scry(ScryEncoding::Jam, "/heavy".into()))
+        );
+        assert_eq!(
+            Command::parse("peek json  /balance/main").unwrap(),
+            Some(Command::Scry(ScryEncoding::Json, "/balance/main".into()))
+        );
+        assert_eq!(
+            Command::parse("peek [%block 1 ~]").unwrap(),
+            Some(Command::Peek("[%block 1 ~]".into()))
+        );
+        assert!(Command::parse("poke").is_err());
+        assert!(Command::parse("frobnicate").is_err());
+    }
+
+    #[test]
+    fn test_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("history");
+        let mut history = History::load(Some(file.clone()));
+        assert!(history.expand("!!").is_err());
+        history.push("peek /heavy").unwrap();
+        history.push("peek /heavy").unwrap();
+        history.push("poke [%command 1]").unwrap();
+        assert_eq!(history.entries().len(), 2);
+        assert_eq!(history.expand("!1").unwrap(), "peek /heavy");
+        assert_eq!(history.expand("!!").unwrap(), "poke [%command 1]");
+        assert!(history.expand("!3").is_err());
+        assert!(history.expand("!0").is_err());
+        assert_eq!(history.expand(" help ").unwrap(), "help");
+
+        let reloaded = History::load(Some(file));
+        assert_eq!(reloaded.entries(), history.entries());
+    }
+}
@@ -0,0 +1,414 @@
+//! Command lines layered over a TOML file and the environment
+//!
+//! [`Layers::parse`] parses a `clap` command line whose flags can also be set in a table of a
+//! TOML file, like `[node]` in `nockchain.toml`, or by environment variables. The command line
+//! wins over the environment, which wins over the file, which wins over the flag's default:
+//!
+//! ```toml
+//! [node]
+//! mine = true
+//! mining-pubkey = "..."
+//!
+//! [node.drivers]
+//! rpc-addr = "127.0.0.1:3300"
+//! peer = ["/ip4/1.2.3.4/udp/3006/quic-v1"]
+//! ```
+//!
+//! Keys are the long flags, and tables inside the section are only for grouping them. A flag
+//! without an environment variable of its own gets one from its name, like
+//! `NOCKCHAIN_MINING_PUBKEY`. The file is the one given with `--config` or `<prefix>CONFIG`, or
+//! the default file if there is one in the current directory. Only the top level flags are
+//! layered, not those of subcommands. [`Effective`] is what every flag ended up as, and where
+//! from, printed as a table the file could hold.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Parser, Subcommand};
+use config::ValueKind;
+use thiserror::Error;
+
+/// The flag that names the config file
+const CONFIG_FLAG: &str = "config";
+
+#[derive(Debug, Error)]
+pub enum SettingsError {
+    #[error(transparent)]
+    Clap(#[from] clap::Error),
+    #[error("Failed to read {0}: {1}")]
+    Config(PathBuf, config::ConfigError),
+    #[error("{file}: no flag --{key}")]
+    UnknownKey { file: PathBuf, key: String },
+    #[error("{file}: --{key} takes one value, not a list")]
+    NotAList { file: PathBuf, key: String },
+    #[error("{file}: {key} isn't a string, number, boolean or list of them")]
+    BadValue { file: PathBuf, key: String },
+}
+
+/// Where a binary's settings come from
+#[derive(Debug, Clone)]
+pub struct Layers {
+    /// The config file read without `--config` if it exists, like `nockchain.toml`
+    pub default_file: &'static str,
+    /// The table of the file that holds this binary's flags, like `node`
+    pub section: &'static str,
+    /// Prefixed to a flag's name for its environment variable, like `NOCKCHAIN_`
+    pub env_prefix: &'static str,
+}
+
+/// `config` subcommands for a binary with layered settings
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommand {
+    /// Print the value of every set flag after the config file, environment and command line
+    /// are layered, and where it came from, as TOML, and exit
+    PrintEffective,
+}
+
+/// Where a flag's value came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File,
+    Env(String),
+    CommandLine,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setting {
+    pub key: String,
+    pub values: Vec<String>,
+    pub source: Source,
+    /// A flag that takes no value, written as a bare `true` or `false`
+    flag: bool,
+    list: bool,
+    /// Left out when printing, like passphrases
+    hidden: bool,
+}
+
+/// The settings a command line was parsed with
+#[derive(Debug, Clone)]
+pub struct Effective {
+    pub section: String,
+    pub file: Option<PathBuf>,
+    pub settings: Vec<Setting>,
+}
+
+impl Layers {
+    /// Parses the process's command line, exiting with the error if it fails
+    pub fn parse<C: Parser>(&self) -> (C, Effective) {
+        match self.try_parse_from(std::env::args_os()) {
+            Ok(parsed) => parsed,
+            Err(SettingsError::Clap(e)) => e.exit(),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    pub fn try_parse_from<C, I, T>(&self, args: I) -> Result<(C, Effective), SettingsError>
+    where
+        C: Parser,
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let file = self.config_file(&args);
+        let from_file = match &file {
+            Some(path) => read_section(path, self.section)?,
+            None => BTreeMap::new(),
+        };
+
+        let mut command = C::command().arg(
+            Arg::new(CONFIG_FLAG)
+                .long(CONFIG_FLAG)
+                .value_name("FILE")
+                .global(true)
+                .help(format!(
+                    "TOML file of flags, under [{}]. Defaults to ./{} if it exists",
+                    self.section, self.default_file
+                )),
+        );
+        for (key, values) in &from_file {
+            let file = file.clone().unwrap_or_default();
+            let arg = command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(key.as_str()) && key != CONFIG_FLAG);
+            let Some(arg) = arg else {
+                return Err(SettingsError::UnknownKey {
+                    file,
+                    key: key.clone(),
+                });
+            };
+            if values.len() != 1 && !is_list(arg) {
+                return Err(SettingsError::NotAList {
+                    file,
+                    key: key.clone(),
+                });
+            }
+        }
+        command = command.mut_args(|arg| {
+            let Some(long) = arg.get_long().map(str::to_string) else {
+                return arg;
+            };
+            if long == CONFIG_FLAG {
+                return arg.env(format!("{}CONFIG", self.env_prefix));
+            }
+            let arg = match arg.get_env() {
+                Some(_) => arg,
+                None => arg.env(env_name(self.env_prefix, &long)),
+            };
+            match from_file.get(&long) {
+                Some(values) => arg.default_values(values.clone()),
+                None => arg,
+            }
+        });
+
+        let matches = command.try_get_matches_from_mut(&args)?;
+        let cli = C::from_arg_matches(&matches)?;
+        let settings = command
+            .get_arguments()
+            .filter_map(|arg| setting(arg, &matches, &from_file))
+            .collect();
+        let effective = Effective {
+            section: self.section.to_string(),
+            file,
+            settings,
+        };
+        Ok((cli, effective))
+    }
+
+    /// `--config`, `<prefix>CONFIG`, or the default file if it exists
+    fn config_file(&self, args: &[OsString]) -> Option<PathBuf> {
+        let flag = format!("--{}", CONFIG_FLAG);
+        let prefix = format!("--{}=", CONFIG_FLAG);
+        let mut args = args.iter().map(|arg| arg.to_string_lossy());
+        while let Some(arg) = args.next() {
+            if arg == flag {
+                return args.next().map(|path| PathBuf::from(path.into_owned()));
+            }
+            if let Some(path) = arg.strip_prefix(&prefix) {
+                return Some(PathBuf::from(path));
+            }
+        }
+        if let Some(path) = std::env::var_os(format!("{}CONFIG", self.env_prefix)) {
+            return Some(PathBuf::from(path));
+        }
+        let default = PathBuf::from(self.default_file);
+        default.exists().then_some(default)
+    }
+}
+
+/// `NOCKCHAIN_` and `mining-pubkey` are `NOCKCHAIN_MINING_PUBKEY`
+fn env_name(prefix: &str, long: &str) -> String {
+    format!("{}{}", prefix, long.replace('-', "_").to_uppercase())
+}
+
+fn is_list(arg: &Arg) -> bool {
+    matches!(arg.get_action(), ArgAction::Append)
+        || arg.get_num_args().is_some_and(|n| n.max_values() > 1)
+}
+
+/// The values of every key in `section` of the file, with the tables in it flattened
+fn read_section(
+    path: &Path,
+    section: &str,
+) -> Result<BTreeMap<String, Vec<String>>, SettingsError> {
+    let config = config::Config::builder()
+        .add_source(config::File::new(
+            &path.to_string_lossy(),
+            config::FileFormat::Toml,
+        ))
+        .build()
+        .map_err(|e| SettingsError::Config(path.into(), e))?;
+    let mut values = BTreeMap::new();
+    if let Ok(table) = config.get_table(section) {
+        flatten(path, table, &mut values)?;
+    }
+    Ok(values)
+}
+
+fn flatten(
+    path: &Path,
+    table: config::Map<String, config::Value>,
+    values: &mut BTreeMap<String, Vec<String>>,
+) -> Result<(), SettingsError> {
+    for (key, value) in table {
+        let key = key.replace('_', "-");
+        let bad = || SettingsError::BadValue {
+            file: path.into(),
+            key: key.clone(),
+        };
+        match value.kind {
+            ValueKind::Table(table) => flatten(path, table, values)?,
+            ValueKind::Array(items) => {
+                let items = items
+                    .into_iter()
+                    .map(|item| scalar(item.kind).ok_or_else(bad))
+                    .collect::<Result<_, _>>()?;
+                values.insert(key, items);
+            }
+            kind => {
+                let value = scalar(kind).ok_or_else(bad)?;
+                values.insert(key, vec![value]);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn scalar(kind: ValueKind) -> Option<String> {
+    match kind {
+        ValueKind::String(s) => Some(s),
+        ValueKind::Boolean(b) => Some(b.to_string()),
+        ValueKind::I64(n) => Some(n.to_string()),
+        ValueKind::I128(n) => Some(n.to_string()),
+        ValueKind::U64(n) => Some(n.to_string()),
+        ValueKind::U128(n) => Some(n.to_string()),
+        ValueKind::Float(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// What `arg` was parsed as, unless it has no value
+fn setting(
+    arg: &Arg,
+    matches: &ArgMatches,
+    from_file: &BTreeMap<String, Vec<String>>,
+) -> Option<Setting> {
+    let long = arg.get_long()?;
+    if long == CONFIG_FLAG {
+        return None;
+    }
+    let id = arg.get_id().as_str();
+    let source = match matches.value_source(id)? {
+        ValueSource::CommandLine => Source::CommandLine,
+        ValueSource::EnvVariable => Source::Env(
+            arg.get_env()
+                .map(|env| env.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        ),
+        _ if from_file.contains_key(long) => Source::File,
+        _ => Source::Default,
+    };
+    let values = matches
+        .get_raw(id)
+        .into_iter()
+        .flatten()
+        .map(|value| value.to_string_lossy().into_owned())
+        .collect();
+    Some(Setting {
+        key: long.to_string(),
+        values,
+        source,
+        flag: !arg.get_action().takes_values(),
+        list: is_list(arg),
+        hidden: arg.is_hide_env_values_set(),
+    })
+}
+
+/// A TOML basic string
+fn quote(s: &str) -> String {
+    serde_json::Value::String(s.to_string()).to_string()
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File => write!(f, "config file"),
+            Source::Env(name) => write!(f, "{}", name),
+            Source::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+impl std::fmt::Display for Effective {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            writeln!(f, "# config file: {}", file.display())?;
+        }
+        writeln!(f, "[{}]", self.section)?;
+        for setting in &self.settings {
+            if setting.hidden {
+                writeln!(f, "# {} is set, from {}", setting.key, setting.source)?;
+                continue;
+            }
+            let value = if setting.list {
+                let values: Vec<String> = setting.values.iter().map(|v| quote(v)).collect();
+                format!("[{}]", values.join(", "))
+            } else if setting.flag {
+                setting.values.join("")
+            } else {
+                quote(&setting.values.join(" "))
+            };
+            writeln!(f, "{} = {}  # {}", setting.key, value, setting.source)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Parser, Debug)]
+    struct TestCli {
+        #[arg(long, default_value = "false")]
+        mine: bool,
+        #[arg(long, default_value = "info")]
+        level: String,
+        #[arg(long)]
+        peer: Vec<String>,
+        #[arg(long)]
+        threads: Option<u64>,
+    }
+
+    const LAYERS: Layers = Layers {
+        default_file: "settings-test.toml",
+        section: "node",
+        env_prefix: "SETTINGS_TEST_",
+    };
+
+    #[test]
+    fn test_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.toml");
+        std::fs::write(
+            &path,
+            "[node]\nmine = true\nlevel = \"debug\"\nthreads = 2\n\n[node.drivers]\npeer = [\"a\", \"b\"]\n",
+        )
+        .unwrap();
+        let config = path.to_str().unwrap();
+
+        std::env::set_var("SETTINGS_TEST_THREADS", "4");
+        let (cli, effective): (TestCli, _) = LAYERS
+            .try_parse_from(["test", "--config", config, "--level", "warn"])
+            .unwrap();
+        std::env::remove_var("SETTINGS_TEST_THREADS");
+        assert!(cli.mine);
+        assert_eq!(cli.level, "warn");
+        assert_eq!(cli.peer, vec!["a", "b"]);
+        assert_eq!(cli.threads, Some(4));
+        assert_eq!(
+            effective.to_string().lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                "[node]", "mine = true  # config file", "level = \"warn\"  # command line",
+                "peer = [\"a\", \"b\"]  # config file", "threads = \"4\"  # SETTINGS_TEST_THREADS",
+            ]
+        );
+
+        std::fs::write(&path, "[node]\nminer = true\n").unwrap();
+        assert!(matches!(
+            LAYERS.try_parse_from::<TestCli, _, _>(["test", "--config", config]),
+            Err(SettingsError::UnknownKey { .. })
+        ));
+        std::fs::write(&path, "[node]\nlevel = [\"a\", \"b\"]\n").unwrap();
+        assert!(matches!(
+            LAYERS.try_parse_from::<TestCli, _, _>(["test", "--config", config]),
+            Err(SettingsError::NotAList { .. })
+        ));
+    }
+}
@@ -0,0 +1,332 @@
+//! Running under an init system: a PID file, detaching from the terminal, listening sockets
+//! passed in by systemd, and telling systemd when the app is ready and when it's stopping.
+//!
+//! Socket activation follows `sd_listen_fds(3)`: systemd passes listening sockets from fd 3 up,
+//! with `LISTEN_FDS` saying how many, `LISTEN_PID` the process they're for, and `LISTEN_FDNAMES`
+//! the `FileDescriptorName=` of each in the socket unit, which is how drivers find theirs.
+//! Readiness follows `sd_notify(3)`: datagrams to the socket named in `NOTIFY_SOCKET`. Without
+//! those variables, as when not run by systemd, neither does anything. On Windows only the PID
+//! file and detaching work.
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+#[cfg(unix)]
+use std::sync::Mutex;
+
+use thiserror::Error;
+use tracing::{debug, warn};
+
+/// Set in the environment of the process [`detach`] starts, so it doesn't detach again
+pub const DETACHED_ENV: &str = "NOCKAPP_DETACHED";
+
+#[derive(Debug, Error)]
+pub enum DaemonError {
+    #[error("{1} says process {0} is running, so this one won't start")]
+    Running(u32, PathBuf),
+    #[error("Could not use the PID file {0}: {1}")]
+    PidFile(PathBuf, io::Error),
+    #[error("Could not start a detached process: {0}")]
+    Detach(io::Error),
+}
+
+/// A file holding this process's id, removed when it's dropped
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Write this process's id to `path`, unless a running process's is there
+    pub fn create(path: &Path) -> Result<Self, DaemonError> {
+        let pid = std::process::id();
+        let error = |e| DaemonError::PidFile(path.to_path_buf(), e);
+        match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                if let Ok(other) = contents.trim().parse::<u32>() {
+                    if other != pid && is_running(other) {
+                        return Err(DaemonError::Running(other, path.to_path_buf()));
+                    }
+                }
+                debug!("Replacing the stale PID file {}", path.display());
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(error(e)),
+        }
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(error)?;
+        }
+        let tmp_path = path.with_extension("pid.tmp");
+        std::fs::write(&tmp_path, format!("{}\n", pid)).map_err(error)?;
+        std::fs::rename(&tmp_path, path).map_err(error)?;
+        Ok(PidFile {
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Remove the file, if it still holds this process's id
+    pub fn remove(&self) {
+        let ours = std::fs::read_to_string(&self.path)
+            .is_ok_and(|contents| contents.trim() == std::process::id().to_string());
+        if ours {
+            if let Err(e) = std::fs::remove_file(&self.path) {
+                warn!(
+                    "Could not remove the PID file {}: {}",
+                    self.path.display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 only checks the process exists; EPERM means it does but isn't ours
+    let alive = unsafe { libc::kill(pid, 0) } == 0;
+    alive || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    false
+}
+
+/// Run this program again in the background with the same arguments, in its own session with
+/// no terminal, and its output appended to `log` or thrown away. Returns the new process's id,
+/// for this one to print and exit, or `None` if this is the detached process.
+pub fn detach(log: Option<&Path>) -> Result<Option<u32>, DaemonError> {
+    if std::env::var_os(DETACHED_ENV).is_some() {
+        return Ok(None);
+    }
+    let exe = std::env::current_exe().map_err(DaemonError::Detach)?;
+    let args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    let output = || -> io::Result<Stdio> {
+        Ok(match log {
+            Some(log) => std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log)?
+                .into(),
+            None => Stdio::null(),
+        })
+    };
+    let mut command = Command::new(exe);
+    command
+        .args(args)
+        .env(DETACHED_ENV, "1")
+        .stdin(Stdio::null())
+        .stdout(output().map_err(DaemonError::Detach)?)
+        .stderr(output().map_err(DaemonError::Detach)?);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const DETACHED_PROCESS: u32 = 0x0000_0008;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        command.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+    }
+    let child = command.spawn().map_err(DaemonError::Detach)?;
+    Ok(Some(child.id()))
+}
+
+/// The sockets systemd passed in that no driver has taken yet, by name
+#[cfg(unix)]
+static ACTIVATED: Mutex<Option<Vec<(String, std::os::fd::OwnedFd)>>> = Mutex::new(None);
+
+/// Take the listening socket systemd passed in as `name`, if it did
+#[cfg(unix)]
+pub fn take_activated(name: &str) -> Option<std::os::fd::OwnedFd> {
+    let mut activated = ACTIVATED.lock().unwrap_or_else(|e| e.into_inner());
+    let sockets = activated.get_or_insert_with(activated_from_env);
+    let index = sockets.iter().position(|(n, _)| n == name)?;
+    Some(sockets.remove(index).1)
+}
+
+/// Read and clear the `LISTEN_*` variables, so processes this one starts don't take the sockets
+#[cfg(unix)]
+fn activated_from_env() -> Vec<(String, std::os::fd::OwnedFd)> {
+    use std::os::fd::FromRawFd;
+
+    /// systemd's first passed fd, `SD_LISTEN_FDS_START`
+    const LISTEN_FDS_START: i32 = 3;
+
+    let pid = std::env::var("LISTEN_PID").ok();
+    let count = std::env::var("LISTEN_FDS").ok();
+    let names = std::env::var("LISTEN_FDNAMES").unwrap_or_default();
+    for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(var);
+    }
+    if pid.and_then(|pid| pid.parse::<u32>().ok()) != Some(std::process::id()) {
+        return Vec::new();
+    }
+    let count = count
+        .and_then(|count| count.parse::<i32>().ok())
+        .unwrap_or(0);
+    let mut names = names.split(':');
+    (0..count)
+        .map(|i| {
+            let name = names.next().unwrap_or("unknown").to_string();
+            // systemd hands these to us, and nothing else in the process has them
+            let fd = unsafe { std::os::fd::OwnedFd::from_raw_fd(LISTEN_FDS_START + i) };
+            (name, fd)
+        })
+        .collect()
+}
+
+/// The Unix socket systemd passed in as `name`, ready for tokio
+#[cfg(unix)]
+pub fn activated_unix_listener(name: &str) -> io::Result<Option<tokio::net::UnixListener>> {
+    let Some(fd) = take_activated(name) else {
+        return Ok(None);
+    };
+    let listener = std::os::unix::net::UnixListener::from(fd);
+    listener.set_nonblocking(true)?;
+    tokio::net::UnixListener::from_std(listener).map(Some)
+}
+
+/// The TCP socket systemd passed in as `name`, ready for tokio
+pub fn activated_tcp_listener(name: &str) -> io::Result<Option<tokio::net::TcpListener>> {
+    #[cfg(unix)]
+    {
+        let Some(fd) = take_activated(name) else {
+            return Ok(None);
+        };
+        let listener = std::net::TcpListener::from(fd);
+        listener.set_nonblocking(true)?;
+        tokio::net::TcpListener::from_std(listener).map(Some)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = name;
+        Ok(None)
+    }
+}
+
+/// The TCP socket systemd passed in as `name`, or else one bound to `addr`
+pub async fn activated_or_bind_tcp(
+    name: &str,
+    addr: impl tokio::net::ToSocketAddrs,
+) -> io::Result<tokio::net::TcpListener> {
+    match activated_tcp_listener(name)? {
+        Some(listener) => Ok(listener),
+        None => tokio::net::TcpListener::bind(addr).await,
+    }
+}
+
+/// Send `state`, like `READY=1` or `STOPPING=1`, to systemd, returning whether there was anyone
+/// to send it to
+pub fn notify(state: &str) -> bool {
+    #[cfg(unix)]
+    {
+        let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+            return false;
+        };
+        match send_notify(&socket, state) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Could not notify systemd of {}: {}", state, e);
+                false
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = state;
+        false
+    }
+}
+
+#[cfg(unix)]
+fn send_notify(socket: &std::ffi::OsStr, state: &str) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let datagram = UnixDatagram::unbound()?;
+    let bytes = socket.as_bytes();
+    // A leading '@' names a socket in the abstract namespace
+    if let Some(name) = bytes.strip_prefix(b"@") {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            datagram.send_to_addr(state.as_bytes(), &addr)?;
+            return Ok(());
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        {
+            let _ = name;
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract sockets are Linux only",
+            ));
+        }
+    }
+    datagram.send_to(state.as_bytes(), Path::new(socket))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_pid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run").join("app.pid");
+        let pid_file = PidFile::create(&path).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(written.trim(), std::process::id().to_string());
+
+        // This process may make it again, as when booting twice
+        let again = PidFile::create(&path).unwrap();
+        drop(again);
+        assert!(!path.exists());
+        drop(pid_file);
+
+        // A file naming a process that's gone is replaced
+        std::fs::write(&path, format!("{}\n", u32::MAX)).unwrap();
+        let pid_file = PidFile::create(&path).unwrap();
+        assert!(path.exists());
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_notify() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify.sock");
+        let receiver = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+        send_notify(path.as_os_str(), "READY=1").unwrap();
+        let mut buf = [0u8; 16];
+        let read = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..read], b"READY=1");
+    }
+}
@@ -24,7 +24,17 @@ pub enum CrownError<T = ExternalError> {
     #[error("Crown NounError: {0}")]
     Noun(#[from] NounError),
     #[error("{0}")]
+    NounSerde(#[from] crate::noun::serde::NounSerdeError),
+    #[error("{0}")]
+    Path(#[from] crate::noun::path::PathError),
+    #[error("{0}")]
+    EventLog(#[from] crate::kernel::event_log::EventLogError),
+    #[error("{0}")]
+    Checkpoint(#[from] crate::nockapp::save::CheckpointError),
+    #[error("{0}")]
     InterpreterError(#[from] SwordError),
+    #[error("{0}")]
+    Pma(#[from] nockvm::pma::PmaError),
     #[error("kernel error")]
     KernelError(Option<nockvm::noun::Noun>),
     #[error("{0}")]
@@ -39,6 +49,20 @@ pub enum CrownError<T = ExternalError> {
     BootError,
     #[error("Serf load error")]
     SerfLoadError,
+    #[error("kernel upgrade failed: the new kernel crashed on %migrate")]
+    UpgradeFailed,
+    #[error("%poke-timeout: poke interrupted after {0:?}")]
+    PokeTimeout(std::time::Duration),
+    #[error("%intr: computation interrupted")]
+    Interrupted,
+    #[error(
+        "replay diverged at event {event_num}: state mug {actual:08x}, recorded {expected:08x}"
+    )]
+    ReplayDiverged {
+        event_num: u64,
+        expected: u32,
+        actual: u32,
+    },
     #[error("work bail")]
     WorkBail,
     #[error("peek bail")]
@@ -0,0 +1,217 @@
+//! Local sockets for the NPC protocol: Unix domain sockets, or named pipes on Windows.
+//!
+//! Either way a socket is named by a path, like `.socket/nockchain_npc.sock`. Windows keeps
+//! pipes in their own namespace rather than the filesystem, so there the path names the pipe
+//! [`pipe_name`] makes from it, and nothing is created at the path itself.
+use std::io;
+use std::path::{Component, Path};
+use std::time::Duration;
+
+#[cfg(windows)]
+use tokio::net::windows::named_pipe::{
+    ClientOptions, NamedPipeClient, NamedPipeServer, ServerOptions,
+};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+
+/// A connection accepted by an [`IpcListener`]
+#[cfg(unix)]
+pub type IpcStream = UnixStream;
+#[cfg(windows)]
+pub type IpcStream = NamedPipeServer;
+
+/// A connection made by [`connect`]
+#[cfg(unix)]
+pub type IpcClient = UnixStream;
+#[cfg(windows)]
+pub type IpcClient = NamedPipeClient;
+
+/// A connection made by [`connect_blocking`]
+#[cfg(unix)]
+pub type BlockingIpcClient = std::os::unix::net::UnixStream;
+#[cfg(windows)]
+pub type BlockingIpcClient = std::fs::File;
+
+/// How long [`connect`] waits between tries while every instance of a pipe is busy
+#[cfg(windows)]
+const PIPE_BUSY_RETRY: Duration = Duration::from_millis(50);
+#[cfg(windows)]
+const ERROR_PIPE_BUSY: i32 = 231;
+
+pub struct IpcListener {
+    #[cfg(unix)]
+    listener: UnixListener,
+    #[cfg(windows)]
+    name: String,
+    /// The instance the next client connects to
+    #[cfg(windows)]
+    next: NamedPipeServer,
+}
+
+impl IpcListener {
+    /// Listen at `path`
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        #[cfg(unix)]
+        {
+            Ok(IpcListener {
+                listener: UnixListener::bind(path)?,
+            })
+        }
+        #[cfg(windows)]
+        {
+            let name = pipe_name(path);
+            let next = ServerOptions::new()
+                .first_pipe_instance(true)
+                .create(&name)?;
+            Ok(IpcListener { name, next })
+        }
+    }
+
+    /// The socket systemd passed in as `name`, or else listen at `path`. Also returns whether it
+    /// listened at `path` itself, so the caller knows whether to clean it up.
+    pub fn activated_or_bind(name: &str, path: &Path) -> io::Result<(Self, bool)> {
+        #[cfg(unix)]
+        if let Some(listener) = crate::utils::daemon::activated_unix_listener(name)? {
+            return Ok((IpcListener { listener }, false));
+        }
+        #[cfg(windows)]
+        let _ = name;
+        Ok((Self::bind(path)?, true))
+    }
+
+    /// Wait for the next client. Cancel safe, so it can be raced in a `select!`.
+    pub async fn accept(&mut self) -> io::Result<IpcStream> {
+        #[cfg(unix)]
+        {
+            self.listener.accept().await.map(|(stream, _)| stream)
+        }
+        #[cfg(windows)]
+        {
+            self.next.connect().await?;
+            // A pipe instance serves one client, so the next one needs its own
+            let next = ServerOptions::new().create(&self.name)?;
+            Ok(std::mem::replace(&mut self.next, next))
+        }
+    }
+}
+
+/// Connect to the listener at `path`.
+pub async fn connect(path: &Path) -> io::Result<IpcClient> {
+    #[cfg(unix)]
+    {
+        UnixStream::connect(path).await
+    }
+    #[cfg(windows)]
+    {
+        let name = pipe_name(path);
+        loop {
+            match ClientOptions::new().open(&name) {
+                Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) => {
+                    tokio::time::sleep(PIPE_BUSY_RETRY).await
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Connect to the listener at `path` without a runtime, with reads waiting at most `timeout`.
+/// Windows pipes opened this way can't time out, so there reads wait as long as they must.
+pub fn connect_blocking(path: &Path, timeout: Duration) -> io::Result<BlockingIpcClient> {
+    #[cfg(unix)]
+    {
+        let stream = std::os::unix::net::UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(timeout))?;
+        Ok(stream)
+    }
+    #[cfg(windows)]
+    {
+        let _ = timeout;
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(pipe_name(path))
+    }
+}
+
+/// The Windows pipe for the socket path `path`: `\\.\pipe\` and the path's components joined
+/// by `-`, without the drive's colon, so `C:\nock\.socket\npc.sock` is
+/// `\\.\pipe\c-nock-.socket-npc.sock`. It's lowercased, since the path isn't case-sensitive.
+pub fn pipe_name(path: &Path) -> String {
+    let parts: Vec<String> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Prefix(prefix) => Some(
+                prefix
+                    .as_os_str()
+                    .to_string_lossy()
+                    .trim_end_matches(':')
+                    .to_string(),
+            ),
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            Component::ParentDir => Some("..".to_string()),
+            Component::RootDir | Component::CurDir => None,
+        })
+        .collect();
+    format!(r"\\.\pipe\{}", parts.join("-").to_lowercase())
+}
+
+/// Ensure a rename into `dir` survives a crash. Unix only makes the rename durable once the
+/// directory is synced; NTFS journals renames itself, and directories can't be opened to sync
+/// there, so this does nothing on Windows. An empty `dir` is the current directory, as the
+/// parent of a bare file name is.
+pub fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        std::fs::File::open(dir)?.sync_all()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = dir;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipe_name() {
+        assert_eq!(
+            pipe_name(Path::new(".socket/nockchain_npc.sock")),
+            r"\\.\pipe\.socket-nockchain_npc.sock"
+        );
+        assert_eq!(
+            pipe_name(Path::new("./a/../NPC.sock")),
+            r"\\.\pipe\a-..-npc.sock"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_listener() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("npc.sock");
+        let mut listener = IpcListener::bind(&path).unwrap();
+        let client = tokio::spawn({
+            let path = path.clone();
+            async move {
+                let mut client = connect(&path).await.unwrap();
+                client.write_all(b"hi").await.unwrap();
+            }
+        });
+        let mut stream = listener.accept().await.unwrap();
+        let mut buf = [0u8; 2];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hi");
+        client.await.unwrap();
+    }
+}
@@ -1,7 +1,11 @@
 pub mod bytes;
+pub mod daemon;
 pub mod error;
+pub mod ipc;
+pub(crate) mod rate;
 pub mod scry;
 pub mod slogger;
+pub mod tls;
 
 use std::ptr::copy_nonoverlapping;
 use std::slice::from_raw_parts_mut;
@@ -19,6 +23,7 @@ use nockvm::jets::cold::Cold;
 use nockvm::jets::hot::{Hot, HotEntry};
 use nockvm::jets::warm::Warm;
 use nockvm::mem::NockStack;
+use nockvm::memo::{MemoCache, MemoConfig, MemoStats};
 use nockvm::noun::{Atom, IndirectAtom, Noun, NounAllocator, D};
 use nockvm::serialization::jam;
 use nockvm::trace::TraceInfo;
@@ -52,6 +57,49 @@ pub const NOCK_STACK_SIZE_LARGE: usize = (NOCK_STACK_1KB << 10 << 10) * 32; // 3
 // HUGE nock stack size
 pub const NOCK_STACK_SIZE_HUGE: usize = (NOCK_STACK_1KB << 10 << 10) * 64; // 64GB
 
+/// The preset nock stack sizes, smallest first
+pub const NOCK_STACK_SIZES: [usize; 6] = [
+    NOCK_STACK_SIZE_TINY, NOCK_STACK_SIZE_SMALL, NOCK_STACK_SIZE, NOCK_STACK_SIZE_MEDIUM,
+    NOCK_STACK_SIZE_LARGE, NOCK_STACK_SIZE_HUGE,
+];
+
+/// Pick the largest preset nock stack size that fits in physical memory.
+///
+/// The stack is mapped lazily, so a larger one only costs memory once a computation touches it,
+/// but a computation that outgrows physical memory swaps instead of bailing with `%meme`. Falls
+/// back to [`NOCK_STACK_SIZE`] when the amount of memory can't be read, and never goes below
+/// [`NOCK_STACK_SIZE_TINY`].
+pub fn autodetect_nock_stack_size() -> usize {
+    match total_memory() {
+        Some(bytes) => nock_stack_size_for(bytes),
+        None => NOCK_STACK_SIZE,
+    }
+}
+
+fn nock_stack_size_for(memory_bytes: u64) -> usize {
+    NOCK_STACK_SIZES
+        .into_iter()
+        .rev()
+        .find(|words| (*words as u64) << 3 <= memory_bytes)
+        .unwrap_or(NOCK_STACK_SIZE_TINY)
+}
+
+/// Physical memory in bytes, from `/proc/meminfo`
+fn total_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    parse_mem_total(&meminfo)
+}
+
+fn parse_mem_total(meminfo: &str) -> Option<u64> {
+    let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+    let kb = line
+        .trim_start_matches("MemTotal:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim();
+    kb.parse::<u64>().ok().map(|kb| kb << 10)
+}
+
 /**
  *   ::  +from-unix: unix seconds to @da
  *   ::
@@ -163,7 +211,7 @@ pub fn create_context(
     trace_info: Option<TraceInfo>,
     test_jets: Vec<NounSlab>,
 ) -> Context {
-    let cache = Hamt::<Noun>::new(&mut stack);
+    let cache = MemoCache::new(&mut stack);
     let test_jets = {
         let mut hamt = Hamt::<()>::new(&mut stack);
         for jet in test_jets {
@@ -185,9 +233,33 @@ pub fn create_context(
         warm,
         hot,
         cache,
+        memo_config: MemoConfig::default(),
+        memo_stats: MemoStats::default(),
         scry_stack: D(0),
         trace_info,
         test_jets,
         running_status: cancel,
+        fuel: None,
+        profiler: None,
+        bytecode: None,
+        shadow: None,
+        stats: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nock_stack_size_for_memory() {
+        let meminfo = "MemTotal:       16318360 kB\nMemFree:         1290412 kB\n";
+        let bytes = parse_mem_total(meminfo).expect("MemTotal");
+        assert_eq!(bytes, 16318360 << 10);
+        // Just under 16GB of RAM gets the 8GB stack
+        assert_eq!(nock_stack_size_for(bytes), NOCK_STACK_SIZE);
+        assert_eq!(nock_stack_size_for(1 << 30), NOCK_STACK_SIZE_TINY);
+        assert_eq!(nock_stack_size_for(1 << 40), NOCK_STACK_SIZE_HUGE);
+        assert_eq!(parse_mem_total("MemFree: 12 kB"), None);
     }
 }
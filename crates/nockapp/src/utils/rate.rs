@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+
+/// A token bucket refilling `rate` tokens a second and holding at most a second's worth
+pub(crate) struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rate: u32) -> Self {
+        RateLimiter {
+            rate: rate.max(1) as f64,
+            tokens: rate.max(1) as f64,
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+    }
+
+    /// How long until a token is free, or `None` if one is now
+    pub(crate) fn wait(&mut self, now: Instant) -> Option<Duration> {
+        self.refill(now);
+        (self.tokens < 1.0).then(|| Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+    }
+
+    pub(crate) fn take(&mut self) {
+        self.tokens -= 1.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(2);
+        let start = limiter.last;
+        for _ in 0..2 {
+            assert_eq!(limiter.wait(start), None);
+            limiter.take();
+        }
+        assert_eq!(limiter.wait(start), Some(Duration::from_millis(500)));
+        assert_eq!(limiter.wait(start + Duration::from_millis(500)), None);
+    }
+}
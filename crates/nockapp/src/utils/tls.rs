@@ -0,0 +1,247 @@
+//! Mutually authenticated TLS over TCP for the NPC protocol, for managing a NockApp remotely.
+//!
+//! Both ends have a certificate from a CA the other trusts, and a connection is refused unless
+//! both certificates check out, so only holders of a key the CA signed can poke or peek. The
+//! CA can be private: `openssl` or any other tool can make one and sign a certificate for the
+//! server's host name and one for each client. The files are PEM.
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, ServerConfig, StreamOwned};
+use thiserror::Error;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{client, server, TlsAcceptor, TlsConnector};
+
+use crate::drivers::npc::NpcTransport;
+
+/// How long a client has to finish the handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("TLS: {0}: {1}")]
+    Read(PathBuf, io::Error),
+    #[error("TLS: no certificates in {0}")]
+    NoCertificates(PathBuf),
+    #[error("TLS: no private key in {0}")]
+    NoKey(PathBuf),
+    #[error("TLS: {0}")]
+    Rustls(#[from] rustls::Error),
+    #[error("TLS: {0}")]
+    Verifier(#[from] rustls::server::VerifierBuilderError),
+}
+
+/// A certificate and key to present, and the CA the other end's certificate must be signed by
+#[derive(Debug, Clone)]
+pub struct TlsIdentity {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub ca: PathBuf,
+}
+
+impl TlsIdentity {
+    /// A server that only accepts clients with a certificate from `ca`
+    pub fn server_config(&self) -> Result<Arc<ServerConfig>, TlsError> {
+        install_provider();
+        let verifier = WebPkiClientVerifier::builder(Arc::new(self.roots()?)).build()?;
+        let config = ServerConfig::builder()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(certs(&self.cert)?, key(&self.key)?)?;
+        Ok(Arc::new(config))
+    }
+
+    /// A client that only talks to servers with a certificate from `ca`
+    pub fn client_config(&self) -> Result<Arc<ClientConfig>, TlsError> {
+        install_provider();
+        let config = ClientConfig::builder()
+            .with_root_certificates(self.roots()?)
+            .with_client_auth_cert(certs(&self.cert)?, key(&self.key)?)?;
+        Ok(Arc::new(config))
+    }
+
+    fn roots(&self) -> Result<RootCertStore, TlsError> {
+        let mut roots = RootCertStore::empty();
+        for cert in certs(&self.ca)? {
+            roots.add(cert)?;
+        }
+        Ok(roots)
+    }
+}
+
+fn install_provider() {
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+}
+
+fn certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let file = File::open(path).map_err(|e| TlsError::Read(path.into(), e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| TlsError::Read(path.into(), e))?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificates(path.into()));
+    }
+    Ok(certs)
+}
+
+fn key(path: &Path) -> Result<PrivateKeyDer<'static>, TlsError> {
+    let file = File::open(path).map_err(|e| TlsError::Read(path.into(), e))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| TlsError::Read(path.into(), e))?
+        .ok_or_else(|| TlsError::NoKey(path.into()))
+}
+
+/// Serves NPC over TLS on a TCP port, see [`npc_listener_with_limits`](crate::drivers::npc::npc_listener_with_limits)
+pub struct TlsListener {
+    tcp: TcpListener,
+    acceptor: TlsAcceptor,
+}
+
+impl TlsListener {
+    pub async fn bind(addr: SocketAddr, config: Arc<ServerConfig>) -> io::Result<Self> {
+        Ok(TlsListener {
+            tcp: TcpListener::bind(addr).await?,
+            acceptor: TlsAcceptor::from(config),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp.local_addr()
+    }
+}
+
+impl NpcTransport for TlsListener {
+    type Connection = TcpStream;
+    type Stream = server::TlsStream<TcpStream>;
+
+    async fn accept(&mut self) -> io::Result<TcpStream> {
+        self.tcp.accept().await.map(|(stream, _)| stream)
+    }
+
+    fn establish(
+        &self,
+        connection: TcpStream,
+    ) -> impl Future<Output = io::Result<Self::Stream>> + Send + 'static {
+        let handshake = self.acceptor.accept(connection);
+        async move {
+            tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake)
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out"))?
+        }
+    }
+}
+
+/// Connect to the NPC server at `addr`, whose certificate must be for `server_name`.
+pub async fn connect(
+    addr: &str,
+    server_name: &str,
+    config: Arc<ClientConfig>,
+) -> io::Result<client::TlsStream<TcpStream>> {
+    let name = server_name_from(server_name)?;
+    let tcp = TcpStream::connect(addr).await?;
+    TlsConnector::from(config).connect(name, tcp).await
+}
+
+/// Connect to the NPC server at `addr` without a runtime, with reads waiting at most `timeout`.
+pub fn connect_blocking(
+    addr: &str,
+    server_name: &str,
+    config: Arc<ClientConfig>,
+    timeout: Duration,
+) -> io::Result<StreamOwned<ClientConnection, std::net::TcpStream>> {
+    let name = server_name_from(server_name)?;
+    let tcp = std::net::TcpStream::connect(addr)?;
+    tcp.set_read_timeout(Some(timeout))?;
+    let connection = ClientConnection::new(config, name).map_err(io::Error::other)?;
+    Ok(StreamOwned::new(connection, tcp))
+}
+
+fn server_name_from(name: &str) -> io::Result<ServerName<'static>> {
+    ServerName::try_from(name.to_string())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    /// A CA, and server and client identities it signed, in `dir`
+    fn identities(dir: &Path, ca_name: &str) -> (TlsIdentity, TlsIdentity) {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(vec![ca_name.to_string()]).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let ca_path = dir.join(format!("{}.pem", ca_name));
+        std::fs::write(&ca_path, ca.pem()).unwrap();
+
+        let identity = |name: &str| {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec![name.to_string()])
+                .unwrap()
+                .signed_by(&key, &ca, &ca_key)
+                .unwrap();
+            let cert_path = dir.join(format!("{}-{}.pem", ca_name, name));
+            let key_path = dir.join(format!("{}-{}.key", ca_name, name));
+            std::fs::write(&cert_path, cert.pem()).unwrap();
+            std::fs::write(&key_path, key.serialize_pem()).unwrap();
+            TlsIdentity {
+                cert: cert_path,
+                key: key_path,
+                ca: ca_path.clone(),
+            }
+        };
+        (identity("localhost"), identity("client"))
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_mutual_auth() {
+        let dir = tempfile::tempdir().unwrap();
+        let (server, client) = identities(dir.path(), "ca");
+        let (_, stranger) = identities(dir.path(), "other-ca");
+
+        let mut listener = TlsListener::bind(
+            "127.0.0.1:0".parse().unwrap(),
+            server.server_config().unwrap(),
+        )
+        .await
+        .unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let serve = tokio::spawn(async move {
+            let mut results = Vec::new();
+            for _ in 0..2 {
+                let connection = listener.accept().await.unwrap();
+                let result = match listener.establish(connection).await {
+                    Ok(mut stream) => stream.write_all(b"hi").await.is_ok(),
+                    Err(_) => false,
+                };
+                results.push(result);
+            }
+            results
+        });
+
+        let mut stream = connect(&addr, "localhost", client.client_config().unwrap())
+            .await
+            .unwrap();
+        let mut buf = [0u8; 2];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hi");
+
+        // A client whose certificate the server's CA didn't sign is turned away
+        let refused = match connect(&addr, "localhost", stranger.client_config().unwrap()).await {
+            Ok(mut stream) => stream.read_exact(&mut buf).await.is_err(),
+            Err(_) => true,
+        };
+        assert!(refused);
+        assert_eq!(serve.await.unwrap(), vec![true, false]);
+    }
+}
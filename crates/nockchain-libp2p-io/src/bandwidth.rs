@@ -0,0 +1,250 @@
+//! Upload and download rate limits.
+//!
+//! Each limit is a token bucket of bytes that refills at the limit's rate, up to [`BURST`] worth
+//! of it. There is a global bucket each way and one per peer, and a transfer has to fit in both.
+//! A transfer bigger than what's in a bucket still goes, leaving it in debt, so a block bigger than
+//! the burst isn't stuck forever; the next transfer waits for the debt to be paid off.
+//!
+//! Uploads are the responses we serve: a response waits until the buckets allow it, and is dropped
+//! if that would take longer than the requester waits for it. Downloads are the responses we get,
+//! which can't be refused once they're here, so instead our block requests wait until the buckets
+//! are out of debt. Gossip isn't limited either way, so blocks and transactions still propagate
+//! on a saturated link.
+//!
+//! There are no limits by default. They're set in KiB per second with
+//! `NOCKCHAIN_LIBP2P_UPLOAD_LIMIT_KIB_PER_SEC`, `NOCKCHAIN_LIBP2P_DOWNLOAD_LIMIT_KIB_PER_SEC`, and
+//! `NOCKCHAIN_LIBP2P_PEER_UPLOAD_LIMIT_KIB_PER_SEC` and
+//! `NOCKCHAIN_LIBP2P_PEER_DOWNLOAD_LIMIT_KIB_PER_SEC` for each peer.
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+
+use crate::config::LibP2PConfig;
+
+/// How many seconds of a limit's rate a bucket holds
+pub const BURST: u64 = 2;
+
+/// Rates in bytes per second, or `None` for no limit
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub upload: Option<u64>,
+    pub download: Option<u64>,
+    pub peer_upload: Option<u64>,
+    pub peer_download: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate: u64,
+    /// Bytes we can send now, negative when in debt
+    tokens: i64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        Self {
+            rate,
+            tokens: (rate * BURST) as i64,
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last);
+        let refill = (elapsed.as_secs_f64() * self.rate as f64) as i64;
+        if refill > 0 {
+            self.tokens = (self.tokens + refill).min((self.rate * BURST) as i64);
+            self.last = now;
+        }
+    }
+
+    fn full(&self) -> bool {
+        self.tokens >= (self.rate * BURST) as i64
+    }
+
+    /// How long until the bucket is out of debt
+    fn wait(&self) -> Duration {
+        if self.tokens >= 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens as f64 / self.rate as f64)
+        }
+    }
+
+    fn take(&mut self, bytes: usize) {
+        self.tokens -= bytes as i64;
+    }
+}
+
+/// One direction's global bucket and per-peer buckets
+#[derive(Debug)]
+struct Direction {
+    global: Option<Bucket>,
+    peer_rate: Option<u64>,
+    peers: HashMap<PeerId, Bucket>,
+}
+
+impl Direction {
+    fn new(global: Option<u64>, peer_rate: Option<u64>, now: Instant) -> Self {
+        Self {
+            global: global.map(|rate| Bucket::new(rate, now)),
+            peer_rate,
+            peers: HashMap::new(),
+        }
+    }
+
+    fn wait(&mut self, peer: PeerId, now: Instant) -> Duration {
+        let global = self.global.as_mut().map_or(Duration::ZERO, |bucket| {
+            bucket.refill(now);
+            bucket.wait()
+        });
+        let per_peer = self.peer_rate.map_or(Duration::ZERO, |rate| {
+            let bucket = self
+                .peers
+                .entry(peer)
+                .or_insert_with(|| Bucket::new(rate, now));
+            bucket.refill(now);
+            bucket.wait()
+        });
+        global.max(per_peer)
+    }
+
+    fn take(&mut self, peer: PeerId, bytes: usize, now: Instant) {
+        if let Some(bucket) = self.global.as_mut() {
+            bucket.refill(now);
+            bucket.take(bytes);
+        }
+        if let Some(rate) = self.peer_rate {
+            let bucket = self
+                .peers
+                .entry(peer)
+                .or_insert_with(|| Bucket::new(rate, now));
+            bucket.refill(now);
+            bucket.take(bytes);
+        }
+    }
+
+    fn tick(&mut self, now: Instant) {
+        self.peers.retain(|_, bucket| {
+            bucket.refill(now);
+            !bucket.full()
+        });
+    }
+}
+
+#[derive(Debug)]
+pub struct Bandwidth {
+    upload: Direction,
+    download: Direction,
+    /// Longest a response may wait to be sent
+    max_wait: Duration,
+}
+
+impl Default for Bandwidth {
+    fn default() -> Self {
+        let config = LibP2PConfig::default();
+        Self::new(config.bandwidth_limits(), config.request_response_timeout())
+    }
+}
+
+impl Bandwidth {
+    pub fn new(limits: Limits, max_wait: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            upload: Direction::new(limits.upload, limits.peer_upload, now),
+            download: Direction::new(limits.download, limits.peer_download, now),
+            max_wait,
+        }
+    }
+
+    /// Make room to send `peer` a response of `bytes`. Returns how long to wait before sending
+    /// it, or `None` if that would be too long and it shouldn't be sent at all.
+    pub fn upload(&mut self, peer: PeerId, bytes: usize, now: Instant) -> Option<Duration> {
+        let wait = self.upload.wait(peer, now);
+        if wait > self.max_wait {
+            return None;
+        }
+        self.upload.take(peer, bytes, now);
+        Some(wait)
+    }
+
+    /// We got a response of `bytes` from `peer`
+    pub fn downloaded(&mut self, peer: PeerId, bytes: usize, now: Instant) {
+        self.download.take(peer, bytes, now);
+    }
+
+    /// How long to wait before asking `peer` for more
+    pub fn download_wait(&mut self, peer: PeerId, now: Instant) -> Duration {
+        self.download.wait(peer, now)
+    }
+
+    /// Forget the buckets of peers that have refilled, which are no different from new ones
+    pub fn tick(&mut self, now: Instant) {
+        self.upload.tick(now);
+        self.download.tick(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_limits() {
+        let (fast, slow) = (PeerId::random(), PeerId::random());
+        let limits = Limits {
+            upload: Some(1000),
+            peer_upload: Some(500),
+            ..Limits::default()
+        };
+        let mut bandwidth = Bandwidth::new(limits, Duration::from_secs(5));
+        let now = Instant::now();
+
+        // within the burst, then in debt
+        assert_eq!(bandwidth.upload(slow, 1000, now), Some(Duration::ZERO));
+        assert_eq!(bandwidth.upload(slow, 1000, now), Some(Duration::ZERO));
+        assert_eq!(
+            bandwidth.upload(slow, 1000, now),
+            Some(Duration::from_secs(2))
+        );
+        // the global bucket is in debt too
+        assert_eq!(
+            bandwidth.upload(fast, 10, now),
+            Some(Duration::from_secs(1))
+        );
+        // the slow peer's debt would take too long
+        assert_eq!(
+            bandwidth.upload(slow, 1000, now),
+            Some(Duration::from_secs(4))
+        );
+        assert_eq!(bandwidth.upload(slow, 1000, now), None);
+
+        let later = now + Duration::from_secs(10);
+        assert_eq!(bandwidth.upload(fast, 10, later), Some(Duration::ZERO));
+        bandwidth.tick(later + Duration::from_secs(10));
+        assert!(bandwidth.upload.peers.is_empty());
+    }
+
+    #[test]
+    fn test_download_wait() {
+        let peer = PeerId::random();
+        let limits = Limits {
+            download: Some(1000),
+            ..Limits::default()
+        };
+        let mut bandwidth = Bandwidth::new(limits, Duration::from_secs(5));
+        let now = Instant::now();
+
+        assert_eq!(bandwidth.download_wait(peer, now), Duration::ZERO);
+        bandwidth.downloaded(peer, 3000, now);
+        assert_eq!(bandwidth.download_wait(peer, now), Duration::from_secs(1));
+        assert_eq!(
+            bandwidth.download_wait(peer, now + Duration::from_secs(1)),
+            Duration::ZERO
+        );
+        // no per-peer limit, so no per-peer buckets
+        assert!(bandwidth.download.peers.is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+//! Compact block relay.
+//!
+//! A page names its transactions by their tx ids, five belts each, and a miner regossips every
+//! transaction of a block it finds, though its peers have almost always heard them already. Peers
+//! that advertise the compact protocol (see [`crate::config::LibP2PConfig::compact_protocol`])
+//! instead get `[%heard-compact-block page]`, the page with each id in its `tx-ids` set swapped for
+//! a [`short_id`]: six bytes of a hash keyed by the block, so nobody can make transactions whose
+//! short ids collide in every block. The receiver swaps them back from the ids of transactions its
+//! kernel has seen, and if any is unknown or ambiguous asks the sender for the full block instead.
+//!
+//! A block's transactions aren't regossiped to peers that got it compactly. One that lacks some
+//! gets the full block, and its kernel asks for the missing transactions by id as usual.
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use libp2p::{identify, PeerId, StreamProtocol};
+use nockapp::noun::slab::NounSlab;
+use nockapp::utils::make_tas;
+use nockapp::NounExt;
+use nockvm::noun::{Atom, Noun, Slots, D, T};
+
+use crate::config::LibP2PConfig;
+
+/// Most transaction ids to remember for reconstructing blocks
+pub const MAX_KNOWN_TXS: usize = 100_000;
+
+/// A tip5 hash, as a block or tx id
+pub type Id = [u64; 5];
+
+/// The id in a tip5 hash noun
+pub fn id_of(noun: Noun) -> Option<Id> {
+    let mut id = [0; 5];
+    let mut rest = noun;
+    for (i, belt) in id.iter_mut().enumerate() {
+        let atom = if i < 4 {
+            let cell = rest.as_cell().ok()?;
+            rest = cell.tail();
+            cell.head()
+        } else {
+            rest
+        };
+        *belt = atom.as_atom().ok()?.as_u64().ok()?;
+    }
+    Some(id)
+}
+
+fn id_bytes(id: &Id) -> [u8; 40] {
+    let mut bytes = [0; 40];
+    for (chunk, belt) in bytes.chunks_exact_mut(8).zip(id) {
+        chunk.copy_from_slice(&belt.to_le_bytes());
+    }
+    bytes
+}
+
+/// The short id of transaction `tx` in block `block`
+pub fn short_id(block: &Id, tx: &Id) -> u64 {
+    let key = blake3::hash(&id_bytes(block));
+    let hash = blake3::keyed_hash(key.as_bytes(), &id_bytes(tx));
+    let mut short = [0; 8];
+    short[..6].copy_from_slice(&hash.as_bytes()[..6]);
+    u64::from_le_bytes(short)
+}
+
+/// Rebuild the z-set `set` with each of its elements mapped by `f`, keeping the tree's shape,
+/// which the kernel's ordering fixes. `None` if `f` fails on any.
+fn map_set(
+    slab: &mut NounSlab,
+    set: Noun,
+    f: &mut impl FnMut(&mut NounSlab, Noun) -> Option<Noun>,
+) -> Option<Noun> {
+    let Ok(node) = set.as_cell() else {
+        return Some(D(0));
+    };
+    let branches = node.tail().as_cell().ok()?;
+    let n = f(slab, node.head())?;
+    let l = map_set(slab, branches.head(), f)?;
+    let r = map_set(slab, branches.tail(), f)?;
+    Some(T(slab, &[n, l, r]))
+}
+
+/// `[tag page]` with the page's `tx-ids` mapped by `f`
+fn map_tx_ids(
+    fact: &NounSlab,
+    tag: &str,
+    f: &mut impl FnMut(&mut NounSlab, Noun) -> Option<Noun>,
+) -> Option<NounSlab> {
+    let mut slab = NounSlab::new();
+    slab.copy_from_slab(fact);
+    let page = unsafe { slab.root() }.as_cell().ok()?.tail();
+    let digest = page.slot(2).ok()?;
+    let pow = page.slot(6).ok()?;
+    let parent = page.slot(14).ok()?;
+    let tx_ids = page.slot(30).ok()?;
+    let rest = page.slot(31).ok()?;
+    let tx_ids = map_set(&mut slab, tx_ids, f)?;
+    let tag = make_tas(&mut slab, tag).as_noun();
+    let root = T(&mut slab, &[tag, digest, pow, parent, tx_ids, rest]);
+    slab.set_root(root);
+    Some(slab)
+}
+
+/// The tx ids of the page in `[%heard-block page]`
+pub fn tx_ids(fact: &NounSlab) -> Option<Vec<Id>> {
+    let mut ids = Vec::new();
+    map_tx_ids(fact, "heard-block", &mut |_, tx| {
+        ids.push(id_of(tx)?);
+        Some(D(0))
+    })?;
+    Some(ids)
+}
+
+/// Turn `[%heard-block page]` into `[%heard-compact-block page]`
+pub fn compact(fact: &NounSlab) -> Option<NounSlab> {
+    let page = unsafe { fact.root() }.as_cell().ok()?.tail();
+    let block = id_of(page.slot(2).ok()?)?;
+    map_tx_ids(fact, "heard-compact-block", &mut |_, tx| {
+        Some(D(short_id(&block, &id_of(tx)?)))
+    })
+}
+
+pub fn is_compact(fact: &NounSlab) -> bool {
+    unsafe { fact.root() }
+        .as_cell()
+        .is_ok_and(|cell| cell.head().eq_bytes(b"heard-compact-block"))
+}
+
+#[derive(Debug)]
+pub struct CompactRelay {
+    protocol: StreamProtocol,
+    /// Connected peers that take compact blocks
+    peers: BTreeSet<PeerId>,
+    /// Ids of transactions the kernel has seen, oldest first
+    known_order: VecDeque<Id>,
+    known: HashSet<Id>,
+    /// Transactions of the block we last gossiped
+    last_block_txs: HashSet<Id>,
+}
+
+impl Default for CompactRelay {
+    fn default() -> Self {
+        Self::new(LibP2PConfig::default().compact_protocol())
+    }
+}
+
+impl CompactRelay {
+    pub fn new(protocol: StreamProtocol) -> Self {
+        Self {
+            protocol,
+            peers: BTreeSet::new(),
+            known_order: VecDeque::new(),
+            known: HashSet::new(),
+            last_block_txs: HashSet::new(),
+        }
+    }
+
+    /// Note whether a peer that identified itself takes compact blocks
+    pub fn identified(&mut self, peer: PeerId, info: &identify::Info) {
+        if info.protocols.contains(&self.protocol) {
+            self.peers.insert(peer);
+        } else {
+            self.peers.remove(&peer);
+        }
+    }
+
+    pub fn lost(&mut self, peer: &PeerId) {
+        self.peers.remove(peer);
+    }
+
+    pub fn supports(&self, peer: &PeerId) -> bool {
+        self.peers.contains(peer)
+    }
+
+    /// The kernel has seen the transaction `id`
+    pub fn saw_tx(&mut self, id: Id) {
+        if !self.known.insert(id) {
+            return;
+        }
+        self.known_order.push_back(id);
+        if self.known_order.len() > MAX_KNOWN_TXS {
+            if let Some(oldest) = self.known_order.pop_front() {
+                self.known.remove(&oldest);
+            }
+        }
+    }
+
+    /// We're gossiping a block with these transactions
+    pub fn gossiped_block(&mut self, txs: Vec<Id>) {
+        self.last_block_txs = txs.into_iter().collect();
+    }
+
+    /// Whether the transaction `id` is in the block we last gossiped, so peers that got it
+    /// compactly don't need it again
+    pub fn in_last_block(&self, id: &Id) -> bool {
+        self.last_block_txs.contains(id)
+    }
+
+    /// Turn `[%heard-compact-block page]` back into `[%heard-block page]`, or `None` if some short
+    /// id isn't the short id of exactly one transaction we know
+    pub fn reconstruct(&self, fact: &NounSlab) -> Option<NounSlab> {
+        let page = unsafe { fact.root() }.as_cell().ok()?.tail();
+        let block = id_of(page.slot(2).ok()?)?;
+        let mut by_short: HashMap<u64, Option<Id>> = HashMap::new();
+        for tx in &self.known {
+            by_short
+                .entry(short_id(&block, tx))
+                .and_modify(|known| *known = None)
+                .or_insert(Some(*tx));
+        }
+        map_tx_ids(fact, "heard-block", &mut |slab, short| {
+            let short = short.as_atom().ok()?.as_u64().ok()?;
+            let tx = (*by_short.get(&short)?)?;
+            let belts: Vec<Noun> = tx
+                .iter()
+                .map(|belt| Atom::new(&mut *slab, *belt).as_noun())
+                .collect();
+            Some(T(slab, &belts))
+        })
+    }
+}
+
+/// The height of the page in a `[%heard-block page]` or `[%heard-compact-block page]`
+pub fn height(fact: &NounSlab) -> Option<u64> {
+    let page = unsafe { fact.root() }.as_cell().ok()?.tail();
+    crate::sync::page_height(page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id_noun(slab: &mut NounSlab, id: &Id) -> Noun {
+        let belts: Vec<Noun> = id
+            .iter()
+            .map(|belt| Atom::new(&mut *slab, *belt).as_noun())
+            .collect();
+        T(slab, &belts)
+    }
+
+    /// `[%heard-block page]` with the given txs, as a right-leaning tree
+    fn heard_block(digest: &Id, txs: &[Id]) -> NounSlab {
+        let mut slab = NounSlab::new();
+        let mut set = D(0);
+        for tx in txs.iter().rev() {
+            let n = id_noun(&mut slab, tx);
+            set = T(&mut slab, &[n, D(0), set]);
+        }
+        let digest = id_noun(&mut slab, digest);
+        let parent = id_noun(&mut slab, &[9; 5]);
+        let tag = make_tas(&mut slab, "heard-block").as_noun();
+        let root = T(&mut slab, &[tag, digest, D(0), parent, set, D(42)]);
+        slab.set_root(root);
+        slab
+    }
+
+    #[test]
+    fn test_reconstruct() {
+        let digest = [1, 2, 3, 4, u64::MAX - 5];
+        let txs = [[5; 5], [6; 5], [7, 7, 7, 7, u64::MAX - 7]];
+        let block = heard_block(&digest, &txs);
+        assert_eq!(tx_ids(&block), Some(txs.to_vec()));
+
+        let compact = compact(&block).expect("compact");
+        assert!(is_compact(&compact) && !is_compact(&block));
+        assert_eq!(short_id(&digest, &txs[0]) >> 48, 0);
+        assert_ne!(short_id(&digest, &txs[0]), short_id(&[0; 5], &txs[0]));
+
+        let mut relay = CompactRelay::new(StreamProtocol::new("/compact"));
+        relay.saw_tx(txs[0]);
+        relay.saw_tx(txs[1]);
+        assert!(relay.reconstruct(&compact).is_none());
+        relay.saw_tx(txs[2]);
+        relay.saw_tx([8; 5]);
+        let rebuilt = relay.reconstruct(&compact).expect("reconstruct");
+        assert!(unsafe { rebuilt.root().raw_equals(block.root()) });
+    }
+}
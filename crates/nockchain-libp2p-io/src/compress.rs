@@ -0,0 +1,114 @@
+//! zstd compression of large messages.
+//!
+//! Blocks are most of what a node uploads, and they compress well. Peers that advertise the
+//! compression protocol (see [`crate::config::LibP2PConfig::compression_protocol`]) get gossip and
+//! responses of at least `NOCKCHAIN_LIBP2P_COMPRESSION_THRESHOLD_BYTES` compressed at
+//! `NOCKCHAIN_LIBP2P_COMPRESSION_LEVEL`, flagged `compressed` so they know to decompress them.
+//! Other peers get every message as before. A message that doesn't get any smaller is sent as it
+//! is, and block requests never are compressed, since they're small and their proof of work
+//! covers the bytes as sent.
+//!
+//! A compressed message is decompressed to at most [`MAX_DECOMPRESSED`] bytes, the most an
+//! uncompressed response may be, so a small message can't make us allocate an unbounded buffer.
+//! A threshold of 0 turns compression off, though we still take compressed messages.
+use std::collections::BTreeSet;
+
+use libp2p::{identify, PeerId, StreamProtocol};
+
+use crate::config::LibP2PConfig;
+
+/// Most bytes a compressed message may decompress to
+pub const MAX_DECOMPRESSED: usize = 10 * 1024 * 1024;
+
+#[derive(Debug)]
+pub struct Compression {
+    protocol: StreamProtocol,
+    /// Connected peers that take compressed messages
+    peers: BTreeSet<PeerId>,
+    threshold: usize,
+    level: i32,
+    /// Bytes not sent thanks to compression
+    saved: u64,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        let config = LibP2PConfig::default();
+        Self::new(
+            config.compression_protocol(),
+            config.compression_threshold_bytes,
+            config.compression_level,
+        )
+    }
+}
+
+impl Compression {
+    pub fn new(protocol: StreamProtocol, threshold: usize, level: i32) -> Self {
+        Self {
+            protocol,
+            peers: BTreeSet::new(),
+            threshold,
+            level,
+            saved: 0,
+        }
+    }
+
+    /// Note whether a peer that identified itself takes compressed messages
+    pub fn identified(&mut self, peer: PeerId, info: &identify::Info) {
+        if info.protocols.contains(&self.protocol) {
+            self.peers.insert(peer);
+        } else {
+            self.peers.remove(&peer);
+        }
+    }
+
+    pub fn lost(&mut self, peer: &PeerId) {
+        self.peers.remove(peer);
+    }
+
+    pub fn supports(&self, peer: &PeerId) -> bool {
+        self.peers.contains(peer)
+    }
+
+    /// Note that sending a compressed message saved `bytes`, and return the total saved
+    pub fn saved(&mut self, bytes: usize) -> u64 {
+        self.saved += bytes as u64;
+        self.saved
+    }
+
+    /// `message` compressed, or `None` if it's too small to bother or doesn't get smaller
+    pub fn compress(&self, message: &[u8]) -> Option<Vec<u8>> {
+        if self.threshold == 0 || message.len() < self.threshold {
+            return None;
+        }
+        zstd::bulk::compress(message, self.level)
+            .ok()
+            .filter(|compressed| compressed.len() < message.len())
+    }
+}
+
+pub fn decompress(message: &[u8]) -> std::io::Result<Vec<u8>> {
+    zstd::bulk::decompress(message, MAX_DECOMPRESSED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress() {
+        let compression = Compression::new(StreamProtocol::new("/zstd"), 1024, 3);
+        let block = vec![7u8; 100_000];
+        assert!(compression.compress(&block[..1000]).is_none());
+        let compressed = compression.compress(&block).expect("compress");
+        assert!(compressed.len() < block.len());
+        assert_eq!(decompress(&compressed).expect("decompress"), block);
+
+        // a message may not decompress to more than the limit
+        let bomb = zstd::bulk::compress(&vec![0u8; MAX_DECOMPRESSED + 1], 3).expect("compress");
+        assert!(decompress(&bomb).is_err());
+        assert!(Compression::new(StreamProtocol::new("/zstd"), 0, 3)
+            .compress(&block)
+            .is_none());
+    }
+}
@@ -2,8 +2,11 @@ use std::num::NonZero;
 use std::time::Duration;
 
 use config::{Config, ConfigError, Environment};
+use libp2p::StreamProtocol;
 use serde::Deserialize;
 
+use crate::bandwidth::Limits;
+
 // Kademlia constants
 /** How often we should run a kademlia bootstrap to keep our peer table fresh */
 const KADEMLIA_BOOTSTRAP_INTERVAL: Duration = Duration::from_secs(300);
@@ -59,8 +62,34 @@ const ELDERS_DEBOUNCE_RESET: Duration = Duration::from_secs(60);
 // Cache clear interval of seen_tx cache handled in libp2p driver
 const SEEN_TX_CLEAR_INTERVAL: u64 = 1;
 
+// Block sync: how many heights to download at once, and how long a peer has to send one
+const BLOCK_SYNC_WINDOW: u64 = 32;
+const BLOCK_SYNC_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How many blocks to keep rebroadcasting a submitted transaction that no block includes
+const REBROADCAST_DEPTH: u64 = 100;
+
+// Gossip and responses at least this big go compressed to peers that take it, at this zstd level
+const COMPRESSION_THRESHOLD_BYTES: usize = 4096;
+const COMPRESSION_LEVEL: i32 = 3;
+
+// Snapshot requests served a second to one peer, and to all of them
+const SNAPSHOT_PEER_REQUESTS_PER_SEC: u32 = 8;
+const SNAPSHOT_REQUESTS_PER_SEC: u32 = 64;
+
+// How long a peer stays blocked once its reputation runs out
+const PEER_BLOCK_DURATION: Duration = Duration::from_secs(3600);
+
 // ALL PROTOCOLS MUST HAVE UNIQUE VERSIONS
 const REQ_RES_PROTOCOL_VERSION: &str = "/nockchain-1-req-res";
+// Advertises that we take compact blocks, over the same messages as req-res
+const COMPACT_PROTOCOL_VERSION: &str = "/nockchain-1-compact-blocks";
+// Advertises that we take zstd-compressed messages
+const COMPRESSION_PROTOCOL_VERSION: &str = "/nockchain-1-zstd";
+// Serves chain snapshots to peers that fast sync, only advertised by nodes that opt in
+const SNAPSHOT_PROTOCOL_VERSION: &str = "/nockchain-1-snapshots";
+// Relays memos between wallets, only advertised by nodes that opt in
+const MEMO_PROTOCOL_VERSION: &str = "/nockchain-1-memos";
 const KAD_PROTOCOL_VERSION: &str = "/nockchain-1-kad";
 const IDENTIFY_PROTOCOL_VERSION: &str = "/nockchain-1-identify";
 
@@ -164,6 +193,76 @@ pub struct LibP2PConfig {
     /// added to the heaviest chain.
     #[serde(default = "default_seen_tx_clear_interval")]
     pub seen_tx_clear_interval: u64,
+
+    /// How many block heights to request from peers at once while catching up
+    #[serde(default = "default_block_sync_window")]
+    pub block_sync_window: u64,
+
+    /// How long a peer has to answer a block request before it goes to another peer
+    #[serde(default = "default_block_sync_timeout_secs")]
+    pub block_sync_timeout_secs: u64,
+
+    /// How many blocks to keep rebroadcasting a transaction submitted to this node before giving
+    /// up on it
+    #[serde(default = "default_rebroadcast_depth")]
+    pub rebroadcast_depth: u64,
+
+    /// Smallest gossip or response to compress for peers that take it, or 0 to never compress
+    #[serde(default = "default_compression_threshold_bytes")]
+    pub compression_threshold_bytes: usize,
+
+    /// zstd level to compress messages at
+    #[serde(default = "default_compression_level")]
+    pub compression_level: i32,
+
+    /// Whether to serve chain snapshots to peers that fast sync from them
+    #[serde(default)]
+    pub serve_snapshots: bool,
+
+    /// Most snapshot requests a second to serve to any one peer
+    #[serde(default = "default_snapshot_peer_requests_per_sec")]
+    pub snapshot_peer_requests_per_sec: u32,
+
+    /// Most snapshot requests a second to serve in all
+    #[serde(default = "default_snapshot_requests_per_sec")]
+    pub snapshot_requests_per_sec: u32,
+
+    /// Whether to relay memos between wallets, holding them for wallets to fetch
+    #[serde(default)]
+    pub relay_memos: bool,
+
+    /// How long a peer whose score reaches the block threshold stays blocked
+    #[serde(default = "default_peer_block_secs")]
+    pub peer_block_secs: u64,
+
+    /// Whether to ask the router to forward our listen ports over UPnP
+    #[serde(default = "default_true")]
+    pub upnp: bool,
+
+    /// Whether to relay connections for peers behind NATs
+    #[serde(default = "default_true")]
+    pub relay_server: bool,
+
+    /// Most KiB per second to upload serving requests, or 0 for no limit
+    #[serde(default)]
+    pub upload_limit_kib_per_sec: u64,
+
+    /// Most KiB per second to download in responses, or 0 for no limit
+    #[serde(default)]
+    pub download_limit_kib_per_sec: u64,
+
+    /// Most KiB per second to upload to any one peer, or 0 for no limit
+    #[serde(default)]
+    pub peer_upload_limit_kib_per_sec: u64,
+
+    /// Most KiB per second to download from any one peer, or 0 for no limit
+    #[serde(default)]
+    pub peer_download_limit_kib_per_sec: u64,
+
+    /// Name of a private network, added to every protocol name so its nodes only talk to each
+    /// other
+    #[serde(default)]
+    pub network: Option<String>,
 }
 
 // Default value functions
@@ -238,6 +337,42 @@ fn default_seen_tx_clear_interval() -> u64 {
     SEEN_TX_CLEAR_INTERVAL // By default, clear seen_tx cache after every new block on heaviest chain
 }
 
+fn default_block_sync_window() -> u64 {
+    BLOCK_SYNC_WINDOW
+}
+
+fn default_block_sync_timeout_secs() -> u64 {
+    BLOCK_SYNC_TIMEOUT.as_secs()
+}
+
+fn default_rebroadcast_depth() -> u64 {
+    REBROADCAST_DEPTH
+}
+
+fn default_compression_threshold_bytes() -> usize {
+    COMPRESSION_THRESHOLD_BYTES
+}
+
+fn default_compression_level() -> i32 {
+    COMPRESSION_LEVEL
+}
+
+fn default_snapshot_peer_requests_per_sec() -> u32 {
+    SNAPSHOT_PEER_REQUESTS_PER_SEC
+}
+
+fn default_snapshot_requests_per_sec() -> u32 {
+    SNAPSHOT_REQUESTS_PER_SEC
+}
+
+fn default_peer_block_secs() -> u64 {
+    PEER_BLOCK_DURATION.as_secs()
+}
+
+fn default_true() -> bool {
+    true
+}
+
 // Do _not_ use this default implementation in production code. It's just a fallback.
 // Use from_env() to load from environment variables with sensible defaults.
 impl Default for LibP2PConfig {
@@ -264,6 +399,23 @@ impl Default for LibP2PConfig {
             peer_status_log_interval_secs: default_peer_status_log_interval_secs(),
             elders_debounce_reset_secs: default_elders_debounce_reset_secs(),
             seen_tx_clear_interval: default_seen_tx_clear_interval(),
+            block_sync_window: default_block_sync_window(),
+            block_sync_timeout_secs: default_block_sync_timeout_secs(),
+            rebroadcast_depth: default_rebroadcast_depth(),
+            compression_threshold_bytes: default_compression_threshold_bytes(),
+            compression_level: default_compression_level(),
+            serve_snapshots: false,
+            snapshot_peer_requests_per_sec: default_snapshot_peer_requests_per_sec(),
+            snapshot_requests_per_sec: default_snapshot_requests_per_sec(),
+            relay_memos: false,
+            peer_block_secs: default_peer_block_secs(),
+            upnp: default_true(),
+            relay_server: default_true(),
+            upload_limit_kib_per_sec: 0,
+            download_limit_kib_per_sec: 0,
+            peer_upload_limit_kib_per_sec: 0,
+            peer_download_limit_kib_per_sec: 0,
+            network: None,
         }
     }
 }
@@ -283,12 +435,43 @@ impl LibP2PConfig {
         Self::from_env().unwrap_or_default()
     }
 
-    pub fn kad_protocol_version() -> &'static str {
-        KAD_PROTOCOL_VERSION
+    pub fn kad_protocol(&self) -> StreamProtocol {
+        self.network_protocol(KAD_PROTOCOL_VERSION)
+    }
+
+    pub fn req_res_protocol(&self) -> StreamProtocol {
+        self.network_protocol(REQ_RES_PROTOCOL_VERSION)
+    }
+
+    pub fn compact_protocol(&self) -> StreamProtocol {
+        self.network_protocol(COMPACT_PROTOCOL_VERSION)
+    }
+
+    pub fn compression_protocol(&self) -> StreamProtocol {
+        self.network_protocol(COMPRESSION_PROTOCOL_VERSION)
     }
 
-    pub fn req_res_protocol_version() -> &'static str {
-        REQ_RES_PROTOCOL_VERSION
+    pub fn snapshot_protocol(&self) -> StreamProtocol {
+        self.network_protocol(SNAPSHOT_PROTOCOL_VERSION)
+    }
+
+    pub fn memo_protocol(&self) -> StreamProtocol {
+        self.network_protocol(MEMO_PROTOCOL_VERSION)
+    }
+
+    pub fn identify_protocol(&self) -> String {
+        match &self.network {
+            Some(network) => format!("{}/{network}", self.identify_protocol_version),
+            None => self.identify_protocol_version.clone(),
+        }
+    }
+
+    fn network_protocol(&self, protocol: &'static str) -> StreamProtocol {
+        match &self.network {
+            Some(network) => StreamProtocol::try_from_owned(format!("{protocol}/{network}"))
+                .expect("protocol names start with /"),
+            None => StreamProtocol::new(protocol),
+        }
     }
 
     /// Get kademlia bootstrap interval as Duration
@@ -357,4 +540,22 @@ impl LibP2PConfig {
     pub fn seen_tx_clear_interval(&self) -> u64 {
         self.seen_tx_clear_interval
     }
+
+    pub fn block_sync_timeout(&self) -> std::time::Duration {
+        Duration::from_secs(self.block_sync_timeout_secs)
+    }
+
+    pub fn peer_block_duration(&self) -> std::time::Duration {
+        Duration::from_secs(self.peer_block_secs)
+    }
+
+    pub fn bandwidth_limits(&self) -> Limits {
+        let bytes_per_sec = |kib: u64| (kib > 0).then(|| kib * 1024);
+        Limits {
+            upload: bytes_per_sec(self.upload_limit_kib_per_sec),
+            download: bytes_per_sec(self.download_limit_kib_per_sec),
+            peer_upload: bytes_per_sec(self.peer_upload_limit_kib_per_sec),
+            peer_download: bytes_per_sec(self.peer_download_limit_kib_per_sec),
+        }
+    }
 }
@@ -1,6 +1,18 @@
+pub mod bandwidth;
+pub mod compact;
+pub mod compress;
 pub mod config;
+pub mod memo;
 pub mod metrics;
+pub mod nat;
 pub mod nc;
+pub mod orphans;
 pub mod p2p;
 pub mod p2p_util;
+pub mod peer_store;
+pub mod rebroadcast;
+pub mod reputation;
+pub mod seeds;
+pub mod snapshot;
+pub mod sync;
 pub mod tip5_util;
@@ -0,0 +1,455 @@
+//! Relaying memos, small signed notes like payment requests from one wallet to another.
+//!
+//! Only nodes that opt in with `NOCKCHAIN_LIBP2P_RELAY_MEMOS` (nockchain's `--relay-memos`) speak
+//! the memo protocol (see [`crate::config::LibP2PConfig::memo_protocol`]). A [`Memo`] is the
+//! base58 public key it's addressed to and the jam of the wallet's `signed-memo`. A relay keeps
+//! each memo it hasn't seen in its [`MemoBox`] for [`MEMO_TTL`], filed under the key, and passes
+//! it on to its other peers that speak the protocol, so a memo floods the relays once and waits at
+//! every one of them for the wallet to fetch it over the node's JSON-RPC.
+//!
+//! A relay only keeps a memo that's signed by its sender and addressed to the key it's filed
+//! under, checked as the wallet it's for checks it, so no one can fill a key's share of the box
+//! with memos that wallet would throw away.
+//!
+//! Memos over [`MAX_MEMO_BYTES`] aren't relayed. A relay takes [`PEER_MEMOS_PER_SEC`] memos a
+//! second from a peer, past which it drops them and answers [`MemoResponse::Busy`], and keeps at
+//! most [`MAX_MEMOS_PER_KEY`] for a key and [`MAX_MEMOS`] in all, dropping the oldest past either.
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use libp2p::{identify, PeerId, StreamProtocol};
+use nockapp::noun::slab::NounSlab;
+use nockapp::Bytes;
+use nockvm::mem::NockStack;
+use nockvm::noun::{Noun, Slots, D, T};
+use nockvm_macros::tas;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use zkvm_jetpack::form::Belt;
+use zkvm_jetpack::jets::cheetah_jets::belt_schnorr_verify;
+use zkvm_jetpack::jets::tip5_jets::hash_hashable;
+
+use crate::snapshot::{Hash, Rate};
+
+/// Biggest signed memo relayed
+pub const MAX_MEMO_BYTES: usize = 4096;
+/// How long a relay keeps a memo
+pub const MEMO_TTL: Duration = Duration::from_secs(7 * 24 * 3600);
+pub const MAX_MEMOS_PER_KEY: usize = 64;
+pub const MAX_MEMOS: usize = 10_000;
+/// Memos a relay takes a second from one peer
+pub const PEER_MEMOS_PER_SEC: u32 = 4;
+/// Longest base58 key a memo can be addressed to
+const MAX_KEY_LEN: usize = 256;
+/// Words of stack to check a memo's signature with
+const VERIFY_STACK_SIZE: usize = 1 << 20;
+/// Peers a relay keeps rate limits for before forgetting idle ones
+const MAX_RATED_PEERS: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Memo {
+    /// Base58 public key of the wallet it's for
+    pub to: String,
+    /// The jammed signed memo
+    pub memo: ByteBuf,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemoResponse {
+    Ack,
+    /// Over the rate limit, so the memo was dropped
+    Busy,
+}
+
+impl Memo {
+    pub fn new(to: String, memo: Vec<u8>) -> Self {
+        Memo {
+            to,
+            memo: ByteBuf::from(memo),
+        }
+    }
+
+    pub fn id(&self) -> Hash {
+        blake3::Hasher::new()
+            .update(self.to.as_bytes())
+            .update(&[0])
+            .update(&self.memo)
+            .finalize()
+            .into()
+    }
+
+    /// Whether it's small enough to relay, and addressed to something that could be a key
+    pub fn is_relayable(&self) -> bool {
+        !self.memo.is_empty()
+            && self.memo.len() <= MAX_MEMO_BYTES
+            && !self.to.is_empty()
+            && self.to.len() <= MAX_KEY_LEN
+            && bs58::decode(&self.to).into_vec().is_ok()
+    }
+
+    /// Whether it's a `signed-memo` to the key it's addressed to, signed by the key it's from
+    pub fn is_signed(&self) -> bool {
+        let mut slab = NounSlab::new();
+        let Ok(memo) = slab.cue_into(Bytes::from(self.memo.to_vec())) else {
+            return false;
+        };
+        let mut stack = NockStack::new(VERIFY_STACK_SIZE, 0);
+        verify(&mut stack, memo, &self.to) == Some(true)
+    }
+}
+
+/// Check `[to from sent payload sig]` as the wallet does: that `to` is `key` and `sig` is
+/// `from`'s signature of the memo's message. `None` where the wallet would crash.
+fn verify(stack: &mut NockStack, memo: Noun, key: &str) -> Option<bool> {
+    let to = memo.slot(2).ok()?;
+    let from = memo.slot(6).ok()?;
+    let sent = memo.slot(14).ok()?;
+    let payload = memo.slot(30).ok()?;
+    let sig = memo.slot(31).ok()?;
+    if pubkey_to_base58(to)? != key {
+        return Some(false);
+    }
+    let m = memo_message(stack, to, from, sent, payload)?;
+    belt_schnorr_verify(stack, from, &m, sig)
+}
+
+/// +memo-message: the hash of `[to from sent (rip 5 payload)]`
+fn memo_message(
+    stack: &mut NockStack,
+    to: Noun,
+    from: Noun,
+    sent: Noun,
+    payload: Noun,
+) -> Option<Vec<Belt>> {
+    sent.as_atom().ok()?;
+    let payload = payload.as_atom().ok()?.as_ubig(stack).to_le_bytes();
+    let pieces: Vec<Noun> = payload
+        .chunks(4)
+        .map(|piece| {
+            let mut word = [0; 4];
+            word[..piece.len()].copy_from_slice(piece);
+            D(u32::from_le_bytes(word) as u64)
+        })
+        .collect();
+    let pieces = pieces
+        .into_iter()
+        .rev()
+        .fold(D(0), |list, piece| T(stack, &[piece, list]));
+    let message = T(stack, &[to, from, sent, pieces]);
+    let leaf = T(stack, &[D(tas!(b"leaf")), message]);
+    let mut digest = hash_hashable(stack, leaf).ok()?;
+    let mut m = Vec::with_capacity(5);
+    for _ in 0..4 {
+        let cell = digest.as_cell().ok()?;
+        m.push(Belt(cell.head().as_atom().ok()?.as_u64().ok()?));
+        digest = cell.tail();
+    }
+    m.push(Belt(digest.as_atom().ok()?.as_u64().ok()?));
+    Some(m)
+}
+
+/// to-b58:schnorr-pubkey: the base58 of the point's coordinates as 64-bit words, little end
+/// first, under a leading 1
+fn pubkey_to_base58(pubkey: Noun) -> Option<String> {
+    if pubkey.slot(7).ok()?.as_atom().ok()?.as_u64().ok()? != 1 {
+        return None;
+    }
+    let mut words = Vec::with_capacity(13);
+    for axis in [2, 6] {
+        let mut coordinate = pubkey.slot(axis).ok()?;
+        for _ in 0..5 {
+            let cell = coordinate.as_cell().ok()?;
+            words.push(cell.head().as_atom().ok()?.as_u64().ok()?);
+            coordinate = cell.tail();
+        }
+        words.push(coordinate.as_atom().ok()?.as_u64().ok()?);
+    }
+    words.push(1);
+    let bytes: Vec<u8> = words
+        .iter()
+        .rev()
+        .flat_map(|word| word.to_be_bytes())
+        .collect();
+    let start = bytes.iter().position(|byte| *byte != 0)?;
+    Some(bs58::encode(&bytes[start..]).into_string())
+}
+
+#[derive(Debug, Default)]
+struct Held {
+    /// Oldest first
+    memos: VecDeque<(Instant, Hash, Memo)>,
+    ids: HashSet<Hash>,
+}
+
+impl Held {
+    fn expire(&mut self, now: Instant) {
+        while let Some((at, id, _)) = self.memos.front() {
+            if now.saturating_duration_since(*at) < MEMO_TTL {
+                break;
+            }
+            self.ids.remove(id);
+            self.memos.pop_front();
+        }
+    }
+
+    fn remove(&mut self, index: usize) {
+        if let Some((_, id, _)) = self.memos.remove(index) {
+            self.ids.remove(&id);
+        }
+    }
+}
+
+/// The memos a relay holds, shared with the JSON-RPC server that hands them to wallets
+#[derive(Debug)]
+pub struct MemoBox {
+    held: Mutex<Held>,
+    /// Whether a memo is signed, [`Memo::is_signed`] but in tests
+    signed: fn(&Memo) -> bool,
+}
+
+impl Default for MemoBox {
+    fn default() -> Self {
+        MemoBox {
+            held: Mutex::default(),
+            signed: Memo::is_signed,
+        }
+    }
+}
+
+impl MemoBox {
+    /// Keep `memo` if it's relayable, signed and not held already, returning whether it was kept
+    pub fn insert(&self, memo: Memo, now: Instant) -> bool {
+        if !memo.is_relayable() || !(self.signed)(&memo) {
+            return false;
+        }
+        let mut held = self.held.lock().expect("memo box lock poisoned");
+        held.expire(now);
+        let id = memo.id();
+        if held.ids.contains(&id) {
+            return false;
+        }
+        let for_key: Vec<usize> = held
+            .memos
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, _, other))| other.to == memo.to)
+            .map(|(index, _)| index)
+            .collect();
+        if for_key.len() >= MAX_MEMOS_PER_KEY {
+            held.remove(for_key[0]);
+        }
+        if held.memos.len() >= MAX_MEMOS {
+            held.remove(0);
+        }
+        held.ids.insert(id);
+        held.memos.push_back((now, id, memo));
+        true
+    }
+
+    /// The memos held for `to`, oldest first
+    pub fn inbox(&self, to: &str, now: Instant) -> Vec<Memo> {
+        let mut held = self.held.lock().expect("memo box lock poisoned");
+        held.expire(now);
+        held.memos
+            .iter()
+            .filter(|(_, _, memo)| memo.to == to)
+            .map(|(_, _, memo)| memo.clone())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.held
+            .lock()
+            .expect("memo box lock poisoned")
+            .memos
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// What the libp2p driver does with memos
+#[derive(Clone, Debug, Default)]
+pub struct Memos {
+    /// Relay memos, holding them here for wallets to fetch
+    pub inbox: Option<Arc<MemoBox>>,
+}
+
+/// The relay's view of its peers: which speak the memo protocol, and how fast they send
+#[derive(Debug)]
+pub struct MemoRelay {
+    protocol: StreamProtocol,
+    inbox: Arc<MemoBox>,
+    peers: BTreeSet<PeerId>,
+    rates: HashMap<PeerId, Rate>,
+}
+
+impl MemoRelay {
+    pub fn new(protocol: StreamProtocol, inbox: Arc<MemoBox>) -> Self {
+        MemoRelay {
+            protocol,
+            inbox,
+            peers: BTreeSet::new(),
+            rates: HashMap::new(),
+        }
+    }
+
+    pub fn identified(&mut self, peer: PeerId, info: &identify::Info) {
+        if info.protocols.contains(&self.protocol) {
+            self.peers.insert(peer);
+        } else {
+            self.peers.remove(&peer);
+        }
+    }
+
+    pub fn lost(&mut self, peer: &PeerId) {
+        self.peers.remove(peer);
+        self.rates.remove(peer);
+    }
+
+    /// Whether a memo from `peer` is within its rate, counting it if so
+    pub fn allow(&mut self, peer: PeerId, now: Instant) -> bool {
+        if self.rates.len() >= MAX_RATED_PEERS {
+            self.rates.retain(|_, rate| !rate.full(now));
+        }
+        self.rates
+            .entry(peer)
+            .or_insert_with(|| Rate::new(PEER_MEMOS_PER_SEC, now))
+            .take(now)
+    }
+
+    /// Keep a memo from `from`, or submitted here if `None`, returning the peers to pass it on to
+    /// if it's new
+    pub fn keep(&mut self, from: Option<PeerId>, memo: Memo, now: Instant) -> Option<Vec<PeerId>> {
+        if !self.inbox.insert(memo, now) {
+            return None;
+        }
+        let onward = self
+            .peers
+            .iter()
+            .filter(|peer| Some(**peer) != from)
+            .copied()
+            .collect();
+        Some(onward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ibig::UBig;
+    use nockapp::utils::make_tas;
+    use nockvm::noun::NO;
+    use zkvm_jetpack::jets::cheetah_jets::{belt_schnorr_pubkey, belt_schnorr_sign_with_nonce};
+
+    use super::*;
+
+    /// A box that takes memos without checking their signatures
+    fn unchecked() -> MemoBox {
+        MemoBox {
+            held: Mutex::default(),
+            signed: |_| true,
+        }
+    }
+
+    /// A `signed-memo` of `payload` to a key whose first belt is `to`, from the key `sk`, with
+    /// the signature of `signed`
+    fn signed_memo(to: u64, sk: u64, payload: &str, signed: &str) -> Memo {
+        let mut stack = NockStack::new(VERIFY_STACK_SIZE, 0);
+        let mut slab = NounSlab::new();
+        let x = T(&mut slab, &[D(to), D(0), D(0), D(0), D(0), D(0)]);
+        let y = T(&mut slab, &[D(0), D(0), D(0), D(0), D(0), D(0)]);
+        let to = T(&mut slab, &[x, y, NO]);
+        let sk = UBig::from(sk);
+        let from = belt_schnorr_pubkey(&mut stack, &sk).expect("pubkey");
+        let from = slab.copy_into(from);
+        let sent = D(1_700_000_000);
+        let signed = make_tas(&mut slab, signed).as_noun();
+        let m = memo_message(&mut stack, to, from, sent, signed).expect("message");
+        let nonce = &sk * UBig::from(31u8) + UBig::from(7u8);
+        let sig = belt_schnorr_sign_with_nonce(&mut stack, &sk, &nonce, &m).expect("sign");
+        let sig = slab.copy_into(sig);
+        let payload = make_tas(&mut slab, payload).as_noun();
+        let memo = T(&mut slab, &[to, from, sent, payload, sig]);
+        slab.set_root(memo);
+        Memo::new(pubkey_to_base58(to).expect("key"), slab.jam().to_vec())
+    }
+
+    fn memo(to: &str, body: &[u8]) -> Memo {
+        Memo {
+            to: to.to_string(),
+            memo: ByteBuf::from(body.to_vec()),
+        }
+    }
+
+    #[test]
+    fn test_memo_box() {
+        let inbox = unchecked();
+        let now = Instant::now();
+        assert!(inbox.insert(memo("abc", b"hi"), now));
+        assert!(!inbox.insert(memo("abc", b"hi"), now));
+        assert!(inbox.insert(memo("abd", b"hi"), now));
+        // Not base58, empty, or too big
+        assert!(!inbox.insert(memo("0OIl", b"hi"), now));
+        assert!(!inbox.insert(memo("abc", b""), now));
+        assert!(!inbox.insert(memo("abc", &[0; MAX_MEMO_BYTES + 1]), now));
+        assert_eq!(inbox.inbox("abc", now), vec![memo("abc", b"hi")]);
+
+        for i in 0..MAX_MEMOS_PER_KEY as u32 {
+            assert!(inbox.insert(memo("abc", &i.to_le_bytes()), now));
+        }
+        let held = inbox.inbox("abc", now);
+        assert_eq!(held.len(), MAX_MEMOS_PER_KEY);
+        assert_eq!(held[0], memo("abc", &0u32.to_le_bytes()));
+        assert_eq!(inbox.inbox("abd", now).len(), 1);
+
+        assert!(inbox.inbox("abc", now + MEMO_TTL).is_empty());
+        assert!(inbox.is_empty());
+    }
+
+    #[test]
+    fn test_memo_relay() {
+        let protocol = StreamProtocol::new("/nockchain-1-memos");
+        let mut relay = MemoRelay::new(protocol, Arc::new(unchecked()));
+        let (a, b) = (PeerId::random(), PeerId::random());
+        relay.peers.extend([a, b]);
+        let now = Instant::now();
+
+        assert_eq!(relay.keep(Some(a), memo("abc", b"hi"), now), Some(vec![b]));
+        assert_eq!(relay.keep(Some(b), memo("abc", b"hi"), now), None);
+        assert_eq!(
+            relay
+                .keep(None, memo("abc", b"ho"), now)
+                .map(|onward| onward.len()),
+            Some(2)
+        );
+
+        let allowed = (0..=PEER_MEMOS_PER_SEC)
+            .filter(|_| relay.allow(a, now))
+            .count();
+        assert_eq!(allowed, PEER_MEMOS_PER_SEC as usize);
+        assert!(relay.allow(b, now));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_signed() {
+        let now = Instant::now();
+        let inbox = MemoBox::default();
+        let real = signed_memo(3, 7919, "invoice 42", "invoice 42");
+        assert!(real.is_signed());
+        assert!(inbox.insert(real.clone(), now));
+
+        // filed under another key, signed for another payload, or not a memo at all
+        let elsewhere = Memo::new(signed_memo(4, 7919, "hi", "hi").to, real.memo.to_vec());
+        assert!(!elsewhere.is_signed());
+        assert!(!inbox.insert(elsewhere, now));
+        let forged = signed_memo(3, 7919, "invoice 43", "invoice 42");
+        assert!(!forged.is_signed());
+        assert!(!inbox.insert(forged, now));
+        assert!(!inbox.insert(memo(&real.to, b"hi"), now));
+        assert_eq!(inbox.len(), 1);
+    }
+}
@@ -56,12 +56,30 @@ metrics_struct![
     (blocks_received_by_height, "nockchain-libp2p-io.blocks_received_by_height", Count),
     (block_request_timeouts, "nockchain-libp2p-io.block_request_timeouts", Count),
     (last_block_height_received, "nockchain-libp2p-io.last_block_height_received", Gauge),
+    (orphans_held, "nockchain-libp2p-io.orphans_held", Count),
+    (orphans_duplicate, "nockchain-libp2p-io.orphans_duplicate", Count),
+    (orphans_connected, "nockchain-libp2p-io.orphans_connected", Count),
+    (orphans_expired, "nockchain-libp2p-io.orphans_expired", Count),
+    (orphan_pool_size, "nockchain-libp2p-io.orphan_pool_size", Gauge),
+    (compact_blocks_sent, "nockchain-libp2p-io.compact_blocks_sent", Count),
+    (compact_blocks_reconstructed, "nockchain-libp2p-io.compact_blocks_reconstructed", Count),
+    (compact_blocks_failed, "nockchain-libp2p-io.compact_blocks_failed", Count),
+    (compact_txs_not_regossiped, "nockchain-libp2p-io.compact_txs_not_regossiped", Count),
+    (messages_compressed, "nockchain-libp2p-io.messages_compressed", Count),
+    (compression_bytes_saved, "nockchain-libp2p-io.compression_bytes_saved", Gauge),
+    (decompression_failed, "nockchain-libp2p-io.decompression_failed", Count),
+    (submitted_txs_rebroadcast, "nockchain-libp2p-io.submitted_txs_rebroadcast", Count),
+    (submitted_txs_left_mempool, "nockchain-libp2p-io.submitted_txs_left_mempool", Count),
+    (submitted_txs_abandoned, "nockchain-libp2p-io.submitted_txs_abandoned", Count),
+    (submitted_txs_tracked, "nockchain-libp2p-io.submitted_txs_tracked", Gauge),
     // Request/response patterns
     (
         request_response_active_streams, "nockchain-libp2p-io.request_response_active_streams",
         Gauge
     ),
     (peer_request_rate_limited, "nockchain-libp2p-io.peer_request_rate_limited", Count),
+    (responses_delayed_by_bandwidth, "nockchain-libp2p-io.responses_delayed_by_bandwidth", Count),
+    (responses_dropped_by_bandwidth, "nockchain-libp2p-io.responses_dropped_by_bandwidth", Count),
     (request_failed, "nockchain-libp2p-io.request_failed", Count),
     (response_failed_not_dropped, "nockchain-libp2p-io.response_failed_not_dropped", Count),
     (response_dropped, "nockchain-libp2p-io.response_dropped", Count)
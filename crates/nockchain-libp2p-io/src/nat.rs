@@ -0,0 +1,112 @@
+//! Getting dialable from behind a NAT.
+//!
+//! Three things work together. UPnP asks the router to forward our listen ports, which is all a
+//! home miner needs when the router allows it. AutoNAT has peers dial us back, to learn whether
+//! anyone can reach us. If not, [`Relays`] picks up to [`MAX_RELAYS`] connected peers that run a
+//! circuit relay and listens through them, so other peers can reach us by a `/p2p-circuit`
+//! address, and DCUtR then tries to punch a direct connection through both NATs.
+//!
+//! Nodes serve as relays for others, within libp2p's default limits on reservations, circuit
+//! length and bytes relayed. `NOCKCHAIN_LIBP2P_RELAY_SERVER=false` turns that off, and
+//! `NOCKCHAIN_LIBP2P_UPNP=false` turns off port mapping.
+use std::collections::{BTreeMap, BTreeSet};
+
+use libp2p::autonat::NatStatus;
+use libp2p::multiaddr::Protocol;
+use libp2p::{identify, relay, Multiaddr, PeerId, StreamProtocol};
+
+/// Most relays to listen through at once
+pub const MAX_RELAYS: usize = 2;
+
+#[derive(Debug, Default)]
+pub struct Relays {
+    /// Connected peers that run a relay, and an address to reach each on
+    candidates: BTreeMap<PeerId, Multiaddr>,
+    /// Relays we listen through
+    listening: BTreeSet<PeerId>,
+    /// Whether AutoNAT found us unreachable
+    private: bool,
+}
+
+impl Relays {
+    /// Note whether a peer that identified itself runs a relay
+    pub fn identified(&mut self, peer: PeerId, info: &identify::Info) {
+        self.supports(peer, &info.protocols, &info.listen_addrs);
+    }
+
+    fn supports(&mut self, peer: PeerId, protocols: &[StreamProtocol], listen_addrs: &[Multiaddr]) {
+        let hop = protocols
+            .iter()
+            .any(|protocol| *protocol == relay::HOP_PROTOCOL_NAME);
+        match listen_addrs.first() {
+            Some(addr) if hop => {
+                self.candidates.insert(peer, addr.clone());
+            }
+            _ => {
+                self.candidates.remove(&peer);
+            }
+        }
+    }
+
+    pub fn nat_status(&mut self, status: &NatStatus) {
+        self.private = matches!(status, NatStatus::Private);
+    }
+
+    /// A peer disconnected, so we can't listen through it any more
+    pub fn lost(&mut self, peer: &PeerId) {
+        self.candidates.remove(peer);
+        self.listening.remove(peer);
+    }
+
+    /// Circuit addresses to start listening on, if we're unreachable and short of relays
+    pub fn to_listen(&mut self) -> Vec<Multiaddr> {
+        if !self.private {
+            return Vec::new();
+        }
+        let wanted = MAX_RELAYS.saturating_sub(self.listening.len());
+        let picked: Vec<(PeerId, Multiaddr)> = self
+            .candidates
+            .iter()
+            .filter(|(peer, _)| !self.listening.contains(peer))
+            .take(wanted)
+            .map(|(peer, addr)| (*peer, addr.clone()))
+            .collect();
+        picked
+            .into_iter()
+            .map(|(peer, addr)| {
+                self.listening.insert(peer);
+                addr.with(Protocol::P2p(peer)).with(Protocol::P2pCircuit)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_listen() {
+        let peers: Vec<PeerId> = (0..4).map(|_| PeerId::random()).collect();
+        let mut relays = Relays::default();
+        let addr: Multiaddr = "/ip4/1.2.3.4/udp/3006/quic-v1".parse().expect("addr");
+        relays.supports(peers[0], &[], &[addr.clone()]);
+        for peer in &peers[1..] {
+            relays.supports(*peer, &[relay::HOP_PROTOCOL_NAME], &[addr.clone()]);
+        }
+        // reachable, so no relays
+        assert!(relays.to_listen().is_empty());
+
+        relays.nat_status(&NatStatus::Private);
+        let circuits = relays.to_listen();
+        assert_eq!(circuits.len(), MAX_RELAYS);
+        assert!(circuits
+            .iter()
+            .all(|addr| addr.iter().last() == Some(Protocol::P2pCircuit)));
+        assert!(relays.to_listen().is_empty());
+
+        let lost = *relays.listening.iter().next().expect("a relay");
+        relays.lost(&lost);
+        assert_eq!(relays.to_listen().len(), 1);
+    }
+}
@@ -1,11 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::mem::size_of;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{Instant, SystemTime};
 
 use bytes::Bytes;
 use either::{Either, Left, Right};
 use futures::{Future, StreamExt};
+use libp2p::core::ConnectedPoint;
 use libp2p::identify::Event::Received;
 use libp2p::identity::Keypair;
 use libp2p::kad::NoKnownPeers;
@@ -16,16 +19,17 @@ use libp2p::request_response::Message::*;
 use libp2p::request_response::{self};
 use libp2p::swarm::{ConnectionId, DialError, ListenError, SwarmEvent};
 use libp2p::{
-    allow_block_list, connection_limits, memory_connection_limits, Multiaddr, PeerId, Swarm,
+    allow_block_list, autonat, connection_limits, memory_connection_limits, relay, upnp, Multiaddr,
+    PeerId, Swarm,
 };
 use nockapp::driver::{IODriverFn, NockAppHandle, PokeResult};
 use nockapp::noun::slab::NounSlab;
 use nockapp::noun::FromAtom;
 use nockapp::utils::make_tas;
 use nockapp::utils::scry::*;
-use nockapp::wire::{Wire, WireRepr};
-use nockapp::{AtomExt, NockAppError, NounExt};
-use nockvm::noun::{Atom, Noun, D, T};
+use nockapp::wire::{SystemWire, Wire, WireRepr};
+use nockapp::{prometheus, AtomExt, NockAppError, NounExt};
+use nockvm::noun::{Atom, Noun, Slots, D, T};
 use nockvm_macros::tas;
 use rand::seq::SliceRandom;
 use serde_bytes::ByteBuf;
@@ -34,13 +38,24 @@ use tokio::task::{AbortHandle, JoinError, JoinSet};
 use tokio::time::{Duration, MissedTickBehavior};
 use tracing::{debug, error, info, instrument, trace, warn};
 
+use crate::bandwidth::Bandwidth;
+use crate::compact::{self, CompactRelay};
+use crate::compress::{self, Compression};
 use crate::config::LibP2PConfig;
+use crate::memo::{Memo, MemoRelay, MemoResponse, Memos};
 use crate::metrics::NockchainP2PMetrics;
+use crate::nat::Relays;
 use crate::p2p::*;
 use crate::p2p_util::{
     log_fail2ban_ipv4, log_fail2ban_ipv6, CacheResponse, MessageTracker, NockchainDataRequest,
     NockchainFact, PeerIdExt,
 };
+use crate::peer_store::{PeerStore, BOOTSTRAP_FALLBACK, REDIAL_COUNT, SAVE_INTERVAL};
+use crate::rebroadcast::{Rebroadcast, REBROADCAST_INTERVAL};
+use crate::reputation::{Offense, Reputation};
+use crate::seeds::{self, DnsSeed};
+use crate::snapshot::{Fetcher, SnapshotRequest, SnapshotResponse, SnapshotServer, Snapshots};
+use crate::sync::{by_height_request, page_height, Arrival, BlockSync};
 use crate::tip5_util::tip5_hash_to_base58;
 
 //TODO This wire is a placeholder for now. The libp2p driver is entangled with the other types of nockchain pokes
@@ -93,6 +108,7 @@ enum EffectType {
     LiarBlockId,
     Track,
     Seen,
+    Submitted,
     Unknown,
 }
 
@@ -117,6 +133,7 @@ impl EffectType {
             b"liar-block-id" => EffectType::LiarBlockId,
             b"track" => EffectType::Track,
             b"seen" => EffectType::Seen,
+            b"submitted" => EffectType::Submitted,
             _ => EffectType::Unknown,
         }
     }
@@ -159,10 +176,11 @@ impl<T: 'static> TrackedJoinSet<T> {
     }
 }
 
-#[instrument(skip(keypair, bind, allowed, limits, memory_limits, equix_builder))]
+#[instrument(skip(keypair, bind, external, allowed, limits, memory_limits, equix_builder))]
 pub fn make_libp2p_driver(
     keypair: Keypair,
     bind: Vec<Multiaddr>,
+    external: Vec<Multiaddr>,
     allowed: Option<allow_block_list::Behaviour<allow_block_list::AllowedPeers>>,
     limits: connection_limits::ConnectionLimits,
     memory_limits: Option<memory_connection_limits::Behaviour>,
@@ -172,6 +190,12 @@ pub fn make_libp2p_driver(
     equix_builder: equix::EquiXBuilder,
     chain_interval: Duration,
     init_complete_tx: Option<tokio::sync::oneshot::Sender<()>>,
+    mut peer_commands: Option<mpsc::Receiver<PeerCommand>>,
+    peer_store_path: Option<PathBuf>,
+    dns_seeds: Vec<DnsSeed>,
+    network: Option<String>,
+    snapshots: Snapshots,
+    memos: Memos,
 ) -> IODriverFn {
     let initial_peers = Vec::from(initial_peers);
     let force_peers = Vec::from(force_peers);
@@ -182,7 +206,16 @@ pub fn make_libp2p_driver(
         );
 
         Box::pin(async move {
-            let libp2p_config = LibP2PConfig::from_env()?;
+            let mut libp2p_config = LibP2PConfig::from_env()?;
+            if network.is_some() {
+                libp2p_config.network = network;
+            }
+            if snapshots.serve {
+                libp2p_config.serve_snapshots = true;
+            }
+            if memos.inbox.is_some() {
+                libp2p_config.relay_memos = true;
+            }
             debug!("Libp2p config: {:?}", libp2p_config);
             let kademlia_bootstrap_interval = libp2p_config.kademlia_bootstrap_interval();
             let force_peer_dial_interval = libp2p_config.force_peer_dial_interval();
@@ -192,8 +225,33 @@ pub fn make_libp2p_driver(
             let peer_status_log_interval = libp2p_config.peer_status_log_interval_secs();
             let elders_debounce_reset = libp2p_config.elders_debounce_reset();
             let seen_tx_clear_interval = libp2p_config.seen_tx_clear_interval();
+            let block_sync_window = libp2p_config.block_sync_window;
+            let block_sync_timeout = libp2p_config.block_sync_timeout();
+            let peer_block_duration = libp2p_config.peer_block_duration();
+            let bandwidth_limits = libp2p_config.bandwidth_limits();
+            let request_response_timeout = libp2p_config.request_response_timeout();
+            let compact_protocol = libp2p_config.compact_protocol();
+            let compression = Compression::new(
+                libp2p_config.compression_protocol(),
+                libp2p_config.compression_threshold_bytes,
+                libp2p_config.compression_level,
+            );
+            let rebroadcast_depth = libp2p_config.rebroadcast_depth;
+            let snapshot_protocol = libp2p_config.snapshot_protocol();
+            let snapshot_server = libp2p_config.serve_snapshots.then(|| {
+                Arc::new(Mutex::new(SnapshotServer::new(
+                    libp2p_config.snapshot_peer_requests_per_sec,
+                    libp2p_config.snapshot_requests_per_sec,
+                )))
+            });
+            let mut memo_relay = libp2p_config.relay_memos.then(|| {
+                MemoRelay::new(
+                    libp2p_config.memo_protocol(),
+                    memos.inbox.clone().unwrap_or_default(),
+                )
+            });
             let mut swarm = match crate::p2p::start_swarm(
-                libp2p_config, keypair, bind, allowed, limits, memory_limits,
+                libp2p_config, keypair, bind, external, allowed, limits, memory_limits,
             ) {
                 Ok(swarm) => swarm,
                 Err(e) => {
@@ -213,6 +271,22 @@ pub fn make_libp2p_driver(
                 metrics.clone(),
                 seen_tx_clear_interval,
             )));
+            message_tracker.lock().await.block_sync =
+                BlockSync::new(block_sync_window, block_sync_timeout);
+            message_tracker.lock().await.reputation = Reputation::new(peer_block_duration);
+            message_tracker.lock().await.bandwidth =
+                Bandwidth::new(bandwidth_limits, request_response_timeout);
+            message_tracker.lock().await.compact = CompactRelay::new(compact_protocol);
+            message_tracker.lock().await.compression = compression;
+            message_tracker.lock().await.rebroadcast = Rebroadcast::new(rebroadcast_depth);
+            let mut bandwidth_tick = tokio::time::interval(Duration::from_secs(60));
+            bandwidth_tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            let mut reputation_tick = tokio::time::interval(Duration::from_secs(60));
+            reputation_tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            let mut rebroadcast_tick = tokio::time::interval(REBROADCAST_INTERVAL);
+            rebroadcast_tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            let mut block_sync_tick = tokio::time::interval(Duration::from_secs(1));
+            block_sync_tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
             let mut kad_bootstrap = tokio::time::interval(kademlia_bootstrap_interval);
             kad_bootstrap.set_missed_tick_behavior(MissedTickBehavior::Skip);
             let mut force_peer_dial = tokio::time::interval(force_peer_dial_interval);
@@ -224,14 +298,54 @@ pub fn make_libp2p_driver(
             let mut nockchain_timer = tokio::time::interval(chain_interval);
             nockchain_timer.set_missed_tick_behavior(MissedTickBehavior::Skip);
             let nockchain_timer_mutex = Arc::new(Mutex::new(()));
+            let shutdown = handle.shutdown.clone();
+            let shutdown_guard = shutdown.guard();
+            let mut draining = false;
             let (traffic_handle, effect_handle) = handle.dup();
             let traffic_cop = traffic_cop::TrafficCop::new(traffic_handle, &mut join_set);
 
             let mut initial_peer_retries_remaining = initial_peer_retries;
-            dial_peers(&mut swarm, &initial_peers)?;
-            if let Some(tx) = init_complete_tx {
-                let _ = tx.send(());
-                debug!("libp2p driver initialization complete signal sent");
+            let mut peer_store = peer_store_path.map(PeerStore::load).unwrap_or_default();
+            let remembered = peer_store.best(REDIAL_COUNT, SystemTime::now());
+            // Dial the bootstrap peers only if none of the peers we remember answer in time
+            let mut bootstrap_pending = !remembered.is_empty();
+            let bootstrap_fallback = tokio::time::sleep(BOOTSTRAP_FALLBACK);
+            tokio::pin!(bootstrap_fallback);
+            if bootstrap_pending {
+                info!("Dialing {} remembered peers", remembered.len());
+                dial_peers(&mut swarm, &remembered)?;
+            } else {
+                dial_peers(&mut swarm, &initial_peers)?;
+            }
+            let mut relays = Relays::default();
+            let mut seed_peers: Vec<Multiaddr> = Vec::new();
+            let (seed_tx, mut seed_rx) = mpsc::channel::<Vec<Multiaddr>>(1);
+            let mut resolve_seeds = tokio::time::interval(seeds::RESOLVE_INTERVAL);
+            resolve_seeds.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            let mut save_peer_store = tokio::time::interval(SAVE_INTERVAL);
+            save_peer_store.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            let mut fetcher = snapshots
+                .fetch
+                .map(|fetch| Fetcher::new(fetch, snapshot_protocol));
+            if let Some(height) = fetcher.as_ref().map(Fetcher::height) {
+                if heaviest_height(&traffic_cop).await?.is_some() {
+                    info!("Already have blocks, not fetching a snapshot from peers");
+                    fetcher = None;
+                } else {
+                    info!("Fetching the snapshot at height {height} from peers before syncing");
+                }
+            }
+            let (snapshot_loaded_tx, mut snapshot_loaded_rx) =
+                mpsc::channel::<Result<bool, NockAppError>>(1);
+            let mut snapshot_tick = tokio::time::interval(Duration::from_secs(1));
+            snapshot_tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            // Kernel startup waits on this, so a snapshot being fetched is loaded first
+            let mut init_complete_tx = init_complete_tx;
+            if fetcher.is_none() {
+                if let Some(tx) = init_complete_tx.take() {
+                    let _ = tx.send(());
+                    debug!("libp2p driver initialization complete signal sent");
+                }
             }
             let mut peer_status_log = tokio::time::interval(peer_status_log_interval);
             loop {
@@ -240,7 +354,20 @@ pub fn make_libp2p_driver(
                     nockchain_timer_mutex.clone().lock_owned().await
                 };
                 tokio::select! {
-                    guard = timer_fut => {
+                    _ = shutdown.draining(), if !draining => {
+                        info!("Shutting down, no longer taking blocks or transactions from peers");
+                        draining = true;
+                    },
+                    _ = shutdown.closing() => {
+                        if let Err(e) = peer_store.save(SystemTime::now()) {
+                            warn!("Could not save the peer store: {e}");
+                        }
+                        info!("Closing connections to {} peers", swarm.connected_peers().count());
+                        drop(swarm);
+                        drop(shutdown_guard);
+                        return Ok(());
+                    },
+                    guard = timer_fut, if !draining && fetcher.is_none() => {
                         join_set.spawn("timer".to_string(), send_timer_poke(guard, traffic_cop.clone()))
                     }
                     _ = peer_status_log.tick() => {
@@ -258,10 +385,20 @@ pub fn make_libp2p_driver(
                             handle_effect(noun_slab, swarm_tx_clone, equix_builder_clone, local_peer_id, connected_peers, message_tracker_clone, metrics_clone).await
                         });
                     },
-                    Some(event) = swarm.next() => {
+                    Some(event) = swarm.next(), if !draining => {
                         match event {
                             SwarmEvent::NewListenAddr { address, .. } => {
                                 info!("SEvent: Listening on {address:?}");
+                                // Identify only tells peers our external addresses
+                                if address.iter().any(|protocol| protocol == Protocol::P2pCircuit)
+                                    || crate::p2p::is_public_addr(&address)
+                                {
+                                    swarm.add_external_address(address);
+                                }
+                            },
+                            SwarmEvent::ExpiredListenAddr { address, .. } => {
+                                info!("SEvent: No longer listening on {address:?}");
+                                swarm.remove_external_address(&address);
                             },
                             SwarmEvent::ListenerError { error, .. } => {
                                 error!("SEvent: Listener error: {error:?}");
@@ -275,14 +412,45 @@ pub fn make_libp2p_driver(
                             },
                             SwarmEvent::Behaviour(NockchainEvent::Identify(Received { connection_id: _, peer_id, info })) => {
                                 trace!("SEvent: identify_received");
+                                relays.identified(peer_id, &info);
+                                {
+                                    let mut tracker = message_tracker.lock().await;
+                                    tracker.compact.identified(peer_id, &info);
+                                    tracker.compression.identified(peer_id, &info);
+                                }
+                                if let Some(fetcher) = fetcher.as_mut() {
+                                    fetcher.identified(peer_id, &info);
+                                }
+                                if let Some(memo_relay) = memo_relay.as_mut() {
+                                    memo_relay.identified(peer_id, &info);
+                                }
                                 identify_received(&mut swarm, peer_id, info)?;
+                                listen_via_relays(&mut swarm, &mut relays);
                             },
                             SwarmEvent::ConnectionEstablished { connection_id, peer_id, endpoint, .. } => {
+                                if let ConnectedPoint::Dialer { address, .. } = &endpoint {
+                                    peer_store.connected(peer_id, address, SystemTime::now());
+                                }
                                 message_tracker.lock().await.track_connection(connection_id, peer_id, endpoint.get_remote_address(), endpoint.clone());
                                 debug!("SEvent: {peer_id} is new friend via: {endpoint:?}");
                             },
-                            SwarmEvent::ConnectionClosed { connection_id, peer_id, endpoint, cause, .. } => {
+                            SwarmEvent::ConnectionClosed { connection_id, peer_id, endpoint, cause, num_established, .. } => {
                                 message_tracker.lock().await.lost_connection(connection_id);
+                                if num_established == 0 {
+                                    relays.lost(&peer_id);
+                                    {
+                                        let mut tracker = message_tracker.lock().await;
+                                        tracker.compact.lost(&peer_id);
+                                        tracker.compression.lost(&peer_id);
+                                    }
+                                    if let Some(fetcher) = fetcher.as_mut() {
+                                        fetcher.lost(&peer_id);
+                                    }
+                                    if let Some(memo_relay) = memo_relay.as_mut() {
+                                        memo_relay.lost(&peer_id);
+                                    }
+                                    listen_via_relays(&mut swarm, &mut relays);
+                                }
                                 debug!("SEvent: friendship ended with {peer_id} via: {endpoint:?}. cause: {cause:?}");
                             },
                             SwarmEvent::IncomingConnectionError { local_addr, send_back_addr, error, .. } => {
@@ -302,6 +470,12 @@ pub fn make_libp2p_driver(
                                    _ => {}
                                }
                             },
+                            SwarmEvent::Behaviour(NockchainEvent::RequestResponse(Message { message, .. })) if fetcher.is_some() => {
+                                // Blocks and transactions wait until the snapshot is loaded
+                                if let Request { channel, .. } = message {
+                                    let _ = swarm.behaviour_mut().request_response.send_response(channel, NockchainResponse::Ack);
+                                }
+                            },
                             SwarmEvent::Behaviour(NockchainEvent::RequestResponse(Message { connection_id , peer, message })) => {
                                 trace!("SEvent: received RequestResponse");
                                 let _span = tracing::debug_span!("SwarmEvent::Behavior(NockchainEvent::RequestResponse(…))").entered();
@@ -317,12 +491,122 @@ pub fn make_libp2p_driver(
                                 });
                             },
                             SwarmEvent::Behaviour(NockchainEvent::RequestResponse(OutboundFailure { peer, error, ..})) => {
+                                if let request_response::OutboundFailure::Timeout = error {
+                                    let swarm_tx = swarm_tx.clone();
+                                    let message_tracker_clone = Arc::clone(&message_tracker);
+                                    join_set.spawn("offend".to_string(), async move {
+                                        offend(peer, Offense::Timeout, &message_tracker_clone, &swarm_tx).await
+                                    });
+                                }
                                 log_outbound_failure(peer, error, metrics.clone());
                             }
                             SwarmEvent::Behaviour(NockchainEvent::RequestResponse(InboundFailure { peer, error, .. })) => {
                                 log_inbound_failure(peer, error, metrics.clone());
                             }
-                            SwarmEvent::OutgoingConnectionError { error, .. } => {
+                            SwarmEvent::Behaviour(NockchainEvent::Snapshot(event)) => match event {
+                                Message { peer, message: Request { request, channel, .. }, .. } => {
+                                    if let Some(server) = snapshot_server.clone() {
+                                        let traffic_clone = traffic_cop.clone();
+                                        let swarm_tx_clone = swarm_tx.clone();
+                                        join_set.spawn("serve_snapshot".to_string(), async move {
+                                            serve_snapshot(peer, request, channel, server, traffic_clone, swarm_tx_clone).await
+                                        });
+                                    }
+                                }
+                                Message { message: Response { request_id, response }, .. } => {
+                                    let Some(fetcher) = fetcher.as_mut() else {
+                                        continue;
+                                    };
+                                    if let Some(liar) = fetcher.response(request_id, response, Instant::now()) {
+                                        warn!("{liar} sent a snapshot answer that doesn't check out");
+                                        let swarm_tx = swarm_tx.clone();
+                                        let message_tracker_clone = Arc::clone(&message_tracker);
+                                        join_set.spawn("offend".to_string(), async move {
+                                            offend(liar, Offense::ProtocolViolation, &message_tracker_clone, &swarm_tx).await
+                                        });
+                                    }
+                                    match fetcher.assembled() {
+                                        Some(Ok(jam)) => {
+                                            let height = fetcher.height();
+                                            let traffic_clone = traffic_cop.clone();
+                                            let snapshot_loaded_tx = snapshot_loaded_tx.clone();
+                                            join_set.spawn("load_snapshot".to_string(), async move {
+                                                let loaded = load_snapshot(&traffic_clone, jam, height).await;
+                                                snapshot_loaded_tx.send(loaded).await.map_err(|_| NockAppError::OtherError)
+                                            });
+                                        }
+                                        Some(Err(e)) => {
+                                            error!("Could not read the snapshot fetched from peers: {e}");
+                                            let exit = effect_handle.exit.clone();
+                                            tokio::spawn(async move { exit.exit(1).await });
+                                            return Err(NockAppError::OtherError);
+                                        }
+                                        None => {}
+                                    }
+                                }
+                                OutboundFailure { request_id, peer, error, .. } => {
+                                    if let Some(fetcher) = fetcher.as_mut() {
+                                        fetcher.failed(request_id);
+                                    }
+                                    debug!("SEvent: Snapshot request to {peer} failed: {error}");
+                                }
+                                InboundFailure { peer, error, .. } => {
+                                    debug!("SEvent: Snapshot request from {peer} failed: {error}");
+                                }
+                                ResponseSent { .. } => {}
+                            },
+                            SwarmEvent::Behaviour(NockchainEvent::Memo(event)) => match event {
+                                Message { peer, message: Request { request, channel, .. }, .. } => {
+                                    let Some(memo_relay) = memo_relay.as_mut() else {
+                                        continue;
+                                    };
+                                    let now = Instant::now();
+                                    let response = if memo_relay.allow(peer, now) {
+                                        if let Some(onward) = memo_relay.keep(Some(peer), request.clone(), now) {
+                                            relay_memo(&mut swarm, onward, &request);
+                                        }
+                                        MemoResponse::Ack
+                                    } else {
+                                        MemoResponse::Busy
+                                    };
+                                    if let Some(memos) = swarm.behaviour_mut().memos.as_mut() {
+                                        let _ = memos.send_response(channel, response);
+                                    }
+                                }
+                                Message { peer, message: Response { response: MemoResponse::Busy, .. }, .. } => {
+                                    debug!("SEvent: {peer} was too busy to take a memo");
+                                }
+                                OutboundFailure { peer, error, .. } => {
+                                    debug!("SEvent: Memo to {peer} failed: {error}");
+                                }
+                                InboundFailure { peer, error, .. } => {
+                                    debug!("SEvent: Memo from {peer} failed: {error}");
+                                }
+                                Message { .. } | ResponseSent { .. } => {}
+                            },
+                            SwarmEvent::Behaviour(NockchainEvent::Autonat(autonat::Event::StatusChanged { old, new })) => {
+                                info!("SEvent: Reachability changed from {old:?} to {new:?}");
+                                relays.nat_status(&new);
+                                listen_via_relays(&mut swarm, &mut relays);
+                            }
+                            SwarmEvent::Behaviour(NockchainEvent::RelayClient(relay::client::Event::ReservationReqAccepted { relay_peer_id, renewal, .. })) => {
+                                if !renewal {
+                                    info!("SEvent: Reserved a circuit through relay {relay_peer_id}");
+                                }
+                            }
+                            SwarmEvent::Behaviour(NockchainEvent::Upnp(upnp::Event::NewExternalAddr(addr))) => {
+                                info!("SEvent: Router forwards {addr} to us");
+                            }
+                            SwarmEvent::Behaviour(NockchainEvent::Upnp(event)) => {
+                                debug!("SEvent: UPnP {event:?}");
+                            }
+                            SwarmEvent::Behaviour(NockchainEvent::Dcutr(event)) => {
+                                debug!("SEvent: Hole punch {event:?}");
+                            }
+                            SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                                if let Some(peer_id) = peer_id {
+                                    peer_store.dial_failed(peer_id);
+                                }
                                 log_dial_error(error);
                             },
                             SwarmEvent::IncomingConnection {
@@ -342,6 +626,54 @@ pub fn make_libp2p_driver(
                             }
                         }
                     },
+                    Some(command) = next_peer_command(&mut peer_commands) => {
+                        match command {
+                            PeerCommand::List { result } => {
+                                let tracker = message_tracker.lock().await;
+                                let peers = tracker
+                                    .peer_addresses()
+                                    .into_iter()
+                                    .map(|(peer_id, addrs)| (peer_id, addrs, tracker.reputation.score(&peer_id)))
+                                    .collect();
+                                let _ = result.send(peers);
+                            }
+                            PeerCommand::Dial { addr } => {
+                                info!("PCommand: Dialing {addr}");
+                                dial_peers(&mut swarm, &[addr])?;
+                            }
+                            PeerCommand::Block { peer_id, duration } => {
+                                message_tracker.lock().await.reputation.block(peer_id, duration, "blocked by operator".to_string(), SystemTime::now());
+                                let swarm_tx = swarm_tx.clone();
+                                join_set.spawn("block_peer".to_string(), async move {
+                                    swarm_tx
+                                        .send(SwarmAction::BlockPeer { peer_id })
+                                        .await
+                                        .map_err(|_| NockAppError::OtherError)
+                                });
+                            }
+                            PeerCommand::Unblock { peer_id, result } => {
+                                let was_blocked = message_tracker.lock().await.reputation.unblock(&peer_id);
+                                info!("PCommand: Unblocking {peer_id}");
+                                swarm.behaviour_mut().allow_block_list.unblock_peer(peer_id);
+                                let _ = result.send(was_blocked);
+                            }
+                            PeerCommand::ListBlocked { result } => {
+                                let _ = result.send(message_tracker.lock().await.reputation.blocked());
+                            }
+                            PeerCommand::Memo { memo, result } => {
+                                let Some(memo_relay) = memo_relay.as_mut() else {
+                                    let _ = result.send(None);
+                                    continue;
+                                };
+                                let onward = memo_relay.keep(None, memo.clone(), Instant::now());
+                                let was_new = onward.is_some();
+                                if let Some(onward) = onward {
+                                    relay_memo(&mut swarm, onward, &memo);
+                                }
+                                let _ = result.send(Some(was_new));
+                            }
+                        }
+                    },
                     Some(swarm_action) = swarm_rx.recv() => {
                         // We do this because Swarm doesn't implement Send, and so we can't pass it into the tasks
                         // being spawned in the match cases above.
@@ -354,8 +686,13 @@ pub fn make_libp2p_driver(
                                 trace!("SAction: SendResponse");
                                 let _ = swarm.behaviour_mut().request_response.send_response(channel, response);
                             },
+                            SwarmAction::SendSnapshotResponse { channel, response } => {
+                                trace!("SAction: SendSnapshotResponse");
+                                let _ = swarm.behaviour_mut().snapshots.send_response(channel, response);
+                            },
                             SwarmAction::BlockPeer { peer_id } => {
                                 warn!("SAction: Blocking peer {peer_id}");
+                                peer_store.forget(peer_id);
                                 // Block the peer in the allow_block_list
                                 swarm.behaviour_mut().allow_block_list.block_peer(peer_id);
                                 {
@@ -393,6 +730,7 @@ pub fn make_libp2p_driver(
                                 info!("Failed to bootstrap: {}", NoKnownPeers());
                                 initial_peer_retries_remaining -= 1;
                                 dial_peers(&mut swarm, &initial_peers)?;
+                                dial_peers(&mut swarm, &seed_peers)?;
                             } else {
                                 warn!("Failed to bootstrap after {} retries, will not attempt to redial initial peers.", initial_peer_retries);
                             }
@@ -406,6 +744,132 @@ pub fn make_libp2p_driver(
                         trace!("Resetting request counts");
                         message_tracker.lock().await.reset_requests();
                     },
+                    _ = block_sync_tick.tick(), if !draining && fetcher.is_none() => {
+                        let connected_peers: Vec<PeerId> = swarm.connected_peers().cloned().collect();
+                        let (tick, orphans_ready) = {
+                            let mut tracker = message_tracker.lock().await;
+                            let now = Instant::now();
+                            for _ in 0..tracker.orphans.tick(now) {
+                                metrics.orphans_expired.increment();
+                            }
+                            metrics.orphan_pool_size.swap(tracker.orphans.len() as f64);
+                            (tracker.block_sync.tick(&connected_peers, now), tracker.orphans.has_ready())
+                        };
+                        for _ in 0..tick.timed_out {
+                            metrics.block_request_timeouts.increment();
+                        }
+                        if orphans_ready {
+                            let traffic_clone = traffic_cop.clone();
+                            let metrics_clone = metrics.clone();
+                            let message_tracker_clone = Arc::clone(&message_tracker);
+                            join_set.spawn("deliver_orphans".to_string(), async move {
+                                deliver_orphans(message_tracker_clone, traffic_clone, metrics_clone).await
+                            });
+                        }
+                        if !tick.requests.is_empty() {
+                            let swarm_tx_clone = swarm_tx.clone();
+                            let equix_builder_clone = equix_builder.clone();
+                            let local_peer_id = *swarm.local_peer_id();
+                            let metrics_clone = metrics.clone();
+                            let message_tracker_clone = Arc::clone(&message_tracker);
+                            join_set.spawn("block_sync_requests".to_string(), async move {
+                                send_block_requests(tick.requests, swarm_tx_clone, equix_builder_clone, local_peer_id, message_tracker_clone, metrics_clone).await
+                            });
+                        }
+                        if tick.deliver {
+                            let traffic_clone = traffic_cop.clone();
+                            let metrics_clone = metrics.clone();
+                            let message_tracker_clone = Arc::clone(&message_tracker);
+                            join_set.spawn("block_sync_deliver".to_string(), async move {
+                                deliver_blocks(message_tracker_clone, traffic_clone, metrics_clone).await
+                            });
+                        }
+                    },
+                    _ = snapshot_tick.tick(), if !draining && fetcher.is_some() => {
+                        if let Some(fetcher) = fetcher.as_mut() {
+                            for (peer, request) in fetcher.requests(Instant::now()) {
+                                let id = swarm.behaviour_mut().snapshots.send_request(&peer, request.clone());
+                                fetcher.sent(id, peer, request);
+                            }
+                        }
+                    },
+                    Some(loaded) = snapshot_loaded_rx.recv() => {
+                        let Some(fetched) = fetcher.take() else {
+                            continue;
+                        };
+                        match loaded {
+                            Ok(true) => {
+                                info!("Fast synced from peers to height {}", fetched.height());
+                                fetched.finish();
+                                if let Some(tx) = init_complete_tx.take() {
+                                    let _ = tx.send(());
+                                    debug!("libp2p driver initialization complete signal sent");
+                                }
+                            }
+                            Ok(false) => {
+                                error!("The kernel rejected the snapshot fetched from peers; its log says why");
+                                let exit = effect_handle.exit.clone();
+                                tokio::spawn(async move { exit.exit(1).await });
+                                return Err(NockAppError::OtherError);
+                            }
+                            Err(e) => {
+                                error!("Could not load the snapshot fetched from peers: {e}");
+                                let exit = effect_handle.exit.clone();
+                                tokio::spawn(async move { exit.exit(1).await });
+                                return Err(NockAppError::OtherError);
+                            }
+                        }
+                    },
+                    _ = rebroadcast_tick.tick(), if !draining => {
+                        let connected_peers: Vec<PeerId> = swarm.connected_peers().cloned().collect();
+                        if !connected_peers.is_empty() && !message_tracker.lock().await.rebroadcast.is_empty() {
+                            let swarm_tx_clone = swarm_tx.clone();
+                            let traffic_clone = traffic_cop.clone();
+                            let metrics_clone = metrics.clone();
+                            let message_tracker_clone = Arc::clone(&message_tracker);
+                            join_set.spawn("rebroadcast".to_string(), async move {
+                                rebroadcast_txs(connected_peers, swarm_tx_clone, message_tracker_clone, traffic_clone, metrics_clone).await
+                            });
+                        }
+                    },
+                    _ = &mut bootstrap_fallback, if bootstrap_pending => {
+                        bootstrap_pending = false;
+                        if swarm.connected_peers().next().is_none() {
+                            info!("No remembered peer answered, dialing bootstrap peers");
+                            dial_peers(&mut swarm, &initial_peers)?;
+                            dial_peers(&mut swarm, &seed_peers)?;
+                        }
+                    },
+                    _ = resolve_seeds.tick(), if !dns_seeds.is_empty() => {
+                        let dns_seeds = dns_seeds.clone();
+                        let seed_tx = seed_tx.clone();
+                        join_set.spawn("resolve_seeds".to_string(), async move {
+                            let _ = seed_tx.send(seeds::resolve(&dns_seeds).await).await;
+                            Ok(())
+                        });
+                    },
+                    Some(resolved) = seed_rx.recv() => {
+                        debug!("DNS seeds resolved to {resolved:?}");
+                        seed_peers = resolved;
+                        if !bootstrap_pending && swarm.connected_peers().count() < seeds::DIAL_BELOW {
+                            dial_peers(&mut swarm, &seed_peers)?;
+                        }
+                    },
+                    _ = save_peer_store.tick() => {
+                        if let Err(e) = peer_store.save(SystemTime::now()) {
+                            warn!("Could not save the peer store: {e}");
+                        }
+                    },
+                    _ = reputation_tick.tick() => {
+                        let ended = message_tracker.lock().await.reputation.tick(SystemTime::now());
+                        for peer_id in ended {
+                            info!("Block on {peer_id} ended");
+                            swarm.behaviour_mut().allow_block_list.unblock_peer(peer_id);
+                        }
+                    },
+                    _ = bandwidth_tick.tick() => {
+                        message_tracker.lock().await.bandwidth.tick(Instant::now());
+                    },
                     _ = reset_elders_debounce.tick() => {
                         trace!("Resetting elders debounce");
                         let mut tracker = message_tracker.lock().await;
@@ -432,7 +896,12 @@ pub enum NockchainRequest {
         message: ByteBuf,
     },
     /// Gossip a block or TX to another node
-    Gossip { message: ByteBuf },
+    Gossip {
+        message: ByteBuf,
+        /// zstd compressed, for a peer that takes it (see [`crate::compress`])
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        compressed: bool,
+    },
 }
 
 impl NockchainRequest {
@@ -441,9 +910,30 @@ impl NockchainRequest {
         let message_bytes = ByteBuf::from(message.jam().as_ref());
         NockchainRequest::Gossip {
             message: message_bytes,
+            compressed: false,
         }
     }
 
+    /// This gossip compressed, and how many bytes that saves, if it's worth compressing
+    fn compressed(&self, compression: &Compression) -> Option<(NockchainRequest, usize)> {
+        let NockchainRequest::Gossip {
+            message,
+            compressed: false,
+        } = self
+        else {
+            return None;
+        };
+        let compressed = compression.compress(message)?;
+        let saved = message.len() - compressed.len();
+        Some((
+            NockchainRequest::Gossip {
+                message: ByteBuf::from(compressed),
+                compressed: true,
+            },
+            saved,
+        ))
+    }
+
     /// Make a new request for a block or a TX
     fn new_request(
         builder: &mut equix::EquiXBuilder,
@@ -516,7 +1006,7 @@ impl NockchainRequest {
                 pow_buf.extend_from_slice(&message[..]);
                 builder.verify_bytes(&pow_buf[..], pow)
             }
-            NockchainRequest::Gossip { message: _ } => Ok(()),
+            NockchainRequest::Gossip { .. } => Ok(()),
         }
     }
 }
@@ -525,7 +1015,12 @@ impl NockchainRequest {
 /// Responses to Nockchain requests
 pub enum NockchainResponse {
     /// The requested block or raw-tx
-    Result { message: ByteBuf },
+    Result {
+        message: ByteBuf,
+        /// zstd compressed, for a peer that takes it (see [`crate::compress`])
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        compressed: bool,
+    },
     /// If the request was a gossip, no actual response is needed
     Ack,
 }
@@ -536,8 +1031,38 @@ impl NockchainResponse {
         let message_bytebuf = ByteBuf::from(message_bytes.to_vec());
         NockchainResponse::Result {
             message: message_bytebuf,
+            compressed: false,
         }
     }
+
+    /// This response compressed, and how many bytes that saves, if it's worth compressing
+    fn compressed(&self, compression: &Compression) -> Option<(NockchainResponse, usize)> {
+        let NockchainResponse::Result {
+            message,
+            compressed: false,
+        } = self
+        else {
+            return None;
+        };
+        let compressed = compression.compress(message)?;
+        let saved = message.len() - compressed.len();
+        Some((
+            NockchainResponse::Result {
+                message: ByteBuf::from(compressed),
+                compressed: true,
+            },
+            saved,
+        ))
+    }
+}
+
+/// The jammed noun in a received message, decompressed if it was compressed
+fn message_bytes(message: &ByteBuf, compressed: bool) -> std::io::Result<Bytes> {
+    if compressed {
+        Ok(Bytes::from(compress::decompress(message)?))
+    } else {
+        Ok(Bytes::from(message.to_vec()))
+    }
 }
 
 // fn emit_fail2ban(peer_ip: u128) -> Result<(), NockAppError> {
@@ -578,32 +1103,109 @@ async fn handle_effect(
             let data_cell = gossip_cell.as_cell()?.tail();
             tail_slab.copy_into(data_cell);
 
+            let gossip_request = NockchainRequest::new_gossip(&tail_slab);
+            // Peers that take compact blocks get a block compactly, and not the transactions of
+            // the block we last gossiped
+            let mut compact_request = Some(gossip_request.clone());
+            let mut compact_block = false;
+
             // Check if this is a heard-block gossip
             let gossip_noun = unsafe { tail_slab.root() };
+            let mut tracker = message_tracker.lock().await;
             if let Ok(data_cell) = gossip_noun.as_cell() {
                 if data_cell.head().eq_bytes(b"heard-block") {
                     trace!("Gossip effect for heard-block, clearing block and elders cache");
-                    let mut tracker = message_tracker.lock().await;
                     tracker.block_cache.clear();
                     tracker.elders_cache.clear();
                     tracker.elders_negative_cache.clear();
+                    if let (Some(block), Some(txs)) =
+                        (compact::compact(&tail_slab), compact::tx_ids(&tail_slab))
+                    {
+                        tracker.compact.gossiped_block(txs);
+                        compact_request = Some(NockchainRequest::new_gossip(&block));
+                        compact_block = true;
+                    }
+                } else if data_cell.head().eq_bytes(b"heard-tx") {
+                    let tx_id = data_cell.tail().as_cell()?.head();
+                    if compact::id_of(tx_id).is_some_and(|id| tracker.compact.in_last_block(&id)) {
+                        compact_request = None;
+                    }
                 }
             }
+            // Each compressed once, for the peers that take compressed messages
+            let compressed_gossip = gossip_request.compressed(&tracker.compression);
+            let compressed_compact = compact_request
+                .as_ref()
+                .and_then(|request| request.compressed(&tracker.compression));
+            let peer_support: Vec<(bool, bool)> = connected_peers
+                .iter()
+                .map(|peer_id| {
+                    (
+                        tracker.compact.supports(peer_id),
+                        tracker.compression.supports(peer_id),
+                    )
+                })
+                .collect();
+            drop(tracker);
 
-            let gossip_request = NockchainRequest::new_gossip(&tail_slab);
             debug!("Gossiping to {} peers", connected_peers.len());
-            for peer_id in connected_peers.clone() {
-                let gossip_request_clone = gossip_request.clone();
+            let mut saved = 0;
+            for (peer_id, (takes_compact, takes_compressed)) in
+                connected_peers.iter().zip(peer_support)
+            {
+                let (request, compressed) = match (takes_compact, &compact_request) {
+                    (false, _) => (&gossip_request, &compressed_gossip),
+                    (true, Some(request)) => {
+                        if compact_block {
+                            metrics.compact_blocks_sent.increment();
+                        }
+                        (request, &compressed_compact)
+                    }
+                    (true, None) => {
+                        metrics.compact_txs_not_regossiped.increment();
+                        continue;
+                    }
+                };
+                let request = match compressed {
+                    Some((compressed, bytes)) if takes_compressed => {
+                        metrics.messages_compressed.increment();
+                        saved += bytes;
+                        compressed.clone()
+                    }
+                    _ => request.clone(),
+                };
                 swarm_tx
                     .send(SwarmAction::SendRequest {
-                        peer_id,
-                        request: gossip_request_clone,
+                        peer_id: *peer_id,
+                        request,
                     })
                     .await
                     .map_err(|_e| NockAppError::OtherError)?;
             }
+            if saved > 0 {
+                let total = message_tracker.lock().await.compression.saved(saved);
+                metrics.compression_bytes_saved.swap(total as f64);
+            }
         }
         EffectType::Request => {
+            if let Ok(NockchainDataRequest::BlockByHeight(height)) =
+                NockchainDataRequest::from_noun(unsafe { *noun_slab.root() })
+            {
+                let requests = message_tracker.lock().await.block_sync.request(
+                    height,
+                    &connected_peers,
+                    Instant::now(),
+                );
+                debug!(
+                    "Sending {} block requests from height {height}",
+                    requests.len()
+                );
+                return send_block_requests(
+                    requests, swarm_tx, equix_builder, local_peer_id, message_tracker, metrics,
+                )
+                .await;
+            }
+
             // Extract request details to check if it's a peer-specific request
             let request_cell = unsafe { noun_slab.root().as_cell()? };
             let request_body = request_cell.tail().as_cell()?;
@@ -682,10 +1284,7 @@ async fn handle_effect(
                 ))
             })?;
 
-            swarm_tx
-                .send(SwarmAction::BlockPeer { peer_id })
-                .await
-                .map_err(|_| NockAppError::OtherError)?;
+            offend(peer_id, Offense::InvalidBlock, &message_tracker, &swarm_tx).await?;
         }
         EffectType::LiarBlockId => {
             let effect_cell = unsafe { noun_slab.root().as_cell()? };
@@ -697,6 +1296,12 @@ async fn handle_effect(
 
             // Ban each peer that sent this block
             for peer_id in peers_to_ban {
+                if !tracker
+                    .reputation
+                    .offend(peer_id, Offense::InvalidBlock, SystemTime::now())
+                {
+                    continue;
+                }
                 swarm_tx
                     .send(SwarmAction::BlockPeer { peer_id })
                     .await
@@ -748,12 +1353,22 @@ async fn handle_effect(
                 let block_id_str = tip5_hash_to_base58(block_id.as_noun())
                     .expect("failed to convert block ID to base58");
                 trace!("seen block id: {:?}", &block_id_str);
+                let connected = tracker.orphans.connected(&block_id_str);
+                if connected > 0 {
+                    debug!("{connected} orphans of {block_id_str} connected");
+                }
                 tracker.seen_blocks.insert(block_id_str);
 
                 if let Ok(block_height_unit_cell) = seen_pq.tail().as_cell() {
                     let block_height = block_height_unit_cell.tail().as_atom()?.as_u64()?;
                     if tracker.first_negative <= block_height {
                         metrics.highest_block_height_seen.swap(block_height as f64);
+                        prometheus::set_gauge(
+                            "nockchain_height",
+                            "Height of the highest block the kernel has seen",
+                            &[],
+                            block_height as f64,
+                        );
                         tracker.first_negative = block_height + 1;
                         trace!("Setting tracker.first_negative to {:?}", tracker.first_negative);
 
@@ -775,8 +1390,29 @@ async fn handle_effect(
                     .expect("failed to convert tx ID to base58");
                 trace!("seen tx id: {:?}", &tx_id_str);
                 tracker.seen_txs.insert(tx_id_str);
+                if let Some(id) = compact::id_of(tx_id.as_noun()) {
+                    tracker.compact.saw_tx(id);
+                }
             }
         }
+        EffectType::Submitted => {
+            // [%submitted raw-tx], a transaction submitted to this node
+            let raw_tx = unsafe { noun_slab.root() }.as_cell()?.tail();
+            let id = tip5_hash_to_base58(raw_tx.as_cell()?.head())?;
+            let mut fact = NounSlab::new();
+            let raw_tx = fact.copy_into(raw_tx);
+            let heard_tx = T(&mut fact, &[D(tas!(b"heard-tx")), raw_tx]);
+            fact.set_root(heard_tx);
+            let mut tracker = message_tracker.lock().await;
+            debug!("Tracking submitted tx {id} to rebroadcast");
+            let height = tracker.first_negative.saturating_sub(1);
+            tracker
+                .rebroadcast
+                .submitted(id, fact, height, Instant::now());
+            metrics
+                .submitted_txs_tracked
+                .swap(tracker.rebroadcast.len() as f64);
+        }
         EffectType::Unknown => {
             //  This isn't unexpected - any effect that this driver doesn't handle
             //  will hit this case.
@@ -785,6 +1421,21 @@ async fn handle_effect(
     Ok(())
 }
 
+/// Record how long the kernel took to validate a fact heard from a peer
+fn record_validation(fact: &NockchainFact, elapsed: std::time::Duration) {
+    let kind = match fact {
+        NockchainFact::HeardBlock(..) => "block",
+        NockchainFact::HeardTx(..) => "tx",
+        NockchainFact::HeardElders(..) => "elders",
+    };
+    prometheus::observe(
+        "nockchain_validation_duration_seconds",
+        "Time the kernel took to validate blocks, transactions and elders heard from peers",
+        &[("fact", kind)],
+        elapsed.as_secs_f64(),
+    );
+}
+
 // TODO: Wrap some of this up.
 #[allow(clippy::too_many_arguments)]
 async fn handle_request_response(
@@ -806,10 +1457,7 @@ async fn handle_request_response(
         } => {
             let Ok(()) = request.verify_pow(equix_builder, &local_peer_id, &peer) else {
                 warn!("bad libp2p powork from {peer}, blocking!");
-                swarm_tx
-                    .send(SwarmAction::BlockPeer { peer_id: peer })
-                    .await
-                    .map_err(|_| NockAppError::OtherError)?;
+                offend(peer, Offense::BadPow, &message_tracker, &swarm_tx).await?;
                 return Ok(());
             };
             trace!("handle_request_response: powork verified");
@@ -836,6 +1484,13 @@ async fn handle_request_response(
                         .requested(ip4, request_high_threshold);
                     if let Some(count) = threshold_exceeded {
                         warn!("IP address {ip4} exceeded the request-per-interval threshold with {count} requests");
+                        offend(
+                            peer,
+                            Offense::ProtocolViolation,
+                            &message_tracker,
+                            &swarm_tx,
+                        )
+                        .await?;
                     }
                 }
             } else {
@@ -980,18 +1635,68 @@ async fn handle_request_response(
                             }
                         }
                     };
+                    let response = {
+                        let mut tracker = message_tracker.lock().await;
+                        match response.compressed(&tracker.compression) {
+                            Some((compressed, saved)) if tracker.compression.supports(&peer) => {
+                                metrics.messages_compressed.increment();
+                                let total = tracker.compression.saved(saved);
+                                metrics.compression_bytes_saved.swap(total as f64);
+                                compressed
+                            }
+                            _ => response,
+                        }
+                    };
+                    if let NockchainResponse::Result { ref message, .. } = response {
+                        let wait = message_tracker.lock().await.bandwidth.upload(
+                            peer,
+                            message.len(),
+                            Instant::now(),
+                        );
+                        match wait {
+                            None => {
+                                debug!("Not enough upload bandwidth to answer {peer} in time");
+                                metrics.responses_dropped_by_bandwidth.increment();
+                                return Ok(());
+                            }
+                            Some(wait) if !wait.is_zero() => {
+                                metrics.responses_delayed_by_bandwidth.increment();
+                                tokio::time::sleep(wait).await;
+                            }
+                            Some(_) => {}
+                        }
+                    }
                     swarm_tx
                         .send(SwarmAction::SendResponse { channel, response })
                         .await
                         .map_err(|_| NockAppError::OtherError)?;
                 }
-                NockchainRequest::Gossip { message } => {
+                NockchainRequest::Gossip {
+                    message,
+                    compressed,
+                } => {
                     trace!("handle_request_response: Gossip received");
-                    let message_bytes = Bytes::from(message.to_vec());
+                    let message_bytes = match message_bytes(&message, compressed) {
+                        Ok(bytes) => bytes,
+                        Err(e) => {
+                            warn!("Could not decompress gossip from {peer}: {e}");
+                            metrics.decompression_failed.increment();
+                            offend(
+                                peer,
+                                Offense::ProtocolViolation,
+                                &message_tracker,
+                                &swarm_tx,
+                            )
+                            .await?;
+                            return Ok(());
+                        }
+                    };
                     let request_noun = request_slab.cue_into(message_bytes)?;
                     request_slab.set_root(request_noun);
                     trace!("handle_request_response: Gossip noun parsed");
 
+                    let gossip_swarm_tx = swarm_tx.clone();
+                    let gossip_equix_builder = equix_builder.clone();
                     let send_response: tokio::task::JoinHandle<Result<(), NockAppError>> =
                         tokio::spawn(async move {
                             let response = NockchainResponse::Ack;
@@ -1003,6 +1708,35 @@ async fn handle_request_response(
                         });
 
                     let poke_kernel = tokio::task::spawn(async move {
+                        if compact::is_compact(&request_slab) {
+                            let page = unsafe { request_slab.root() }.as_cell()?.tail();
+                            let id = tip5_hash_to_base58(page.as_cell()?.head())?;
+                            let tracker = message_tracker.lock().await;
+                            if tracker.seen_blocks.contains(&id) {
+                                metrics.block_seen_cache_hits.increment();
+                                return Ok(());
+                            }
+                            let Some(block) = tracker.compact.reconstruct(&request_slab) else {
+                                drop(tracker);
+                                debug!("Could not reconstruct compact block {id} from {peer}, asking for all of it");
+                                metrics.compact_blocks_failed.increment();
+                                let Some(height) = compact::height(&request_slab) else {
+                                    return Ok(());
+                                };
+                                return send_block_requests(
+                                    vec![(peer, height)],
+                                    gossip_swarm_tx,
+                                    gossip_equix_builder,
+                                    local_peer_id,
+                                    message_tracker.clone(),
+                                    metrics.clone(),
+                                )
+                                .await;
+                            };
+                            metrics.compact_blocks_reconstructed.increment();
+                            request_slab = block;
+                        }
+
                         let gossip = NockchainFact::from_noun_slab(&request_slab)?;
 
                         match gossip {
@@ -1031,6 +1765,17 @@ async fn handle_request_response(
                             NockchainFact::HeardElders(..) => (),
                         }
 
+                        if let NockchainFact::HeardBlock(..) = gossip {
+                            let held = hold_orphan(
+                                peer, true, &request_slab, gossip_swarm_tx, gossip_equix_builder,
+                                local_peer_id, &message_tracker, &traffic, &metrics,
+                            )
+                            .await?;
+                            if !held {
+                                return Ok(());
+                            }
+                        }
+
                         let wire = Libp2pWire::Gossip(peer);
 
                         trace!(
@@ -1040,13 +1785,19 @@ async fn handle_request_response(
                         );
 
                         let poke = gossip.fact_poke();
-                        match traffic
+                        let validate_start = Instant::now();
+                        let poke_result = traffic
                             .poke_high_priority(wire.to_wire(), poke.clone())
-                            .await
-                        {
+                            .await;
+                        record_validation(&gossip, validate_start.elapsed());
+                        match poke_result {
                             Ok(PokeResult::Ack) => match gossip {
                                 NockchainFact::HeardBlock(..) => {
                                     metrics.gossip_acked_heard_block.increment();
+                                    let page = unsafe { request_slab.root() }.as_cell()?.tail();
+                                    if let Some(height) = page_height(page) {
+                                        message_tracker.lock().await.block_sync.heard_of(height);
+                                    }
                                 }
                                 NockchainFact::HeardTx(..) => {
                                     metrics.gossip_acked_heard_tx.increment();
@@ -1120,10 +1871,32 @@ async fn handle_request_response(
             }
         }
         Response { response, .. } => match response {
-            NockchainResponse::Result { message } => {
+            NockchainResponse::Result {
+                message,
+                compressed,
+            } => {
                 trace!("handle_request_response: Response result received");
+                message_tracker.lock().await.bandwidth.downloaded(
+                    peer,
+                    message.len(),
+                    Instant::now(),
+                );
                 let mut response_slab = NounSlab::new();
-                let message_bytes = Bytes::from(message.to_vec());
+                let message_bytes = match message_bytes(&message, compressed) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        warn!("Could not decompress response from {peer}: {e}");
+                        metrics.decompression_failed.increment();
+                        offend(
+                            peer,
+                            Offense::ProtocolViolation,
+                            &message_tracker,
+                            &swarm_tx,
+                        )
+                        .await?;
+                        return Ok(());
+                    }
+                };
                 let response_noun = response_slab.cue_into(message_bytes)?;
                 response_slab.set_root(response_noun);
 
@@ -1148,14 +1921,67 @@ async fn handle_request_response(
                     nockvm::noun::FullDebugCell(&response_noun.as_cell()?)
                 );
 
-                let response = NockchainFact::from_noun_slab(&response_slab)?;
+                if response_cell.head().eq_bytes(b"heard-block") {
+                    if let Some(height) = page_height(response_cell.tail()) {
+                        metrics.blocks_received_by_height.increment();
+                        let _ = metrics.last_block_height_received.swap(height as f64);
+                        let arrival = message_tracker.lock().await.block_sync.arrived(
+                            peer,
+                            height,
+                            response_slab.clone(),
+                            Instant::now(),
+                        );
+                        match arrival {
+                            Arrival::PassThrough => {
+                                let held = hold_orphan(
+                                    peer,
+                                    false,
+                                    &response_slab,
+                                    swarm_tx.clone(),
+                                    equix_builder.clone(),
+                                    local_peer_id,
+                                    &message_tracker,
+                                    &traffic,
+                                    &metrics,
+                                )
+                                .await?;
+                                if !held {
+                                    return Ok(());
+                                }
+                            }
+                            Arrival::Duplicate | Arrival::Buffered { deliver: false } => {
+                                return Ok(());
+                            }
+                            Arrival::Buffered { deliver: true } => {
+                                return deliver_blocks(message_tracker, traffic, metrics).await;
+                            }
+                        }
+                    }
+                }
+
+                let response = match NockchainFact::from_noun_slab(&response_slab) {
+                    Ok(response) => response,
+                    Err(err) => {
+                        debug!("Malformed response from {peer}");
+                        offend(
+                            peer,
+                            Offense::ProtocolViolation,
+                            &message_tracker,
+                            &swarm_tx,
+                        )
+                        .await?;
+                        return Err(err);
+                    }
+                };
                 let wire = Libp2pWire::Response(peer);
                 let poke_slab = response.fact_poke();
 
-                match traffic
+                let validate_start = Instant::now();
+                let poke_result = traffic
                     .poke_high_priority(wire.to_wire(), poke_slab.clone())
-                    .await
-                {
+                    .await;
+                record_validation(&response, validate_start.elapsed());
+                match poke_result {
                     Ok(PokeResult::Ack) => match response {
                         NockchainFact::HeardBlock(..) => {
                             metrics.responses_acked_heard_block.increment();
@@ -1231,6 +2057,382 @@ async fn handle_request_response(
     Ok(())
 }
 
+/// Pass a memo on to the relays in `peers`
+fn relay_memo(swarm: &mut Swarm<NockchainBehaviour>, peers: Vec<PeerId>, memo: &Memo) {
+    let Some(memos) = swarm.behaviour_mut().memos.as_mut() else {
+        return;
+    };
+    for peer in peers {
+        memos.send_request(&peer, memo.clone());
+    }
+}
+
+/// Listen through more relays if we're unreachable and there are relays to use
+fn listen_via_relays(swarm: &mut Swarm<NockchainBehaviour>, relays: &mut Relays) {
+    for circuit in relays.to_listen() {
+        info!("Listening through relay at {circuit}");
+        if let Err(e) = swarm.listen_on(circuit.clone()) {
+            warn!("Could not listen through relay at {circuit}: {e}");
+        }
+    }
+}
+
+/// Count an offense against `peer`, and block it if that brings its score too low
+async fn offend(
+    peer: PeerId,
+    offense: Offense,
+    message_tracker: &Arc<Mutex<MessageTracker>>,
+    swarm_tx: &mpsc::Sender<SwarmAction>,
+) -> Result<(), NockAppError> {
+    let blocked = message_tracker
+        .lock()
+        .await
+        .reputation
+        .offend(peer, offense, SystemTime::now());
+    if blocked {
+        swarm_tx
+            .send(SwarmAction::BlockPeer { peer_id: peer })
+            .await
+            .map_err(|_| NockAppError::OtherError)?;
+    }
+    Ok(())
+}
+
+/// Answer a peer's snapshot request, if it's within the limits
+async fn serve_snapshot(
+    peer: PeerId,
+    request: SnapshotRequest,
+    channel: request_response::ResponseChannel<SnapshotResponse>,
+    server: Arc<Mutex<SnapshotServer>>,
+    traffic: traffic_cop::TrafficCop,
+    swarm_tx: mpsc::Sender<SwarmAction>,
+) -> Result<(), NockAppError> {
+    let response = {
+        let mut server = server.lock().await;
+        if server.allow(peer, Instant::now()) {
+            let height = request.height();
+            let served = match server.cached(height) {
+                Some(served) => Some(served),
+                None => snapshot_jam(&traffic, height)
+                    .await?
+                    .map(|jam| server.cache(height, jam)),
+            };
+            served.map_or(SnapshotResponse::Missing, |served| served.answer(&request))
+        } else {
+            SnapshotResponse::Busy
+        }
+    };
+    swarm_tx
+        .send(SwarmAction::SendSnapshotResponse { channel, response })
+        .await
+        .map_err(|_| NockAppError::OtherError)
+}
+
+/// The jammed snapshot of the heaviest chain up to `height`, as the JSON-RPC server serves it
+async fn snapshot_jam(
+    traffic: &traffic_cop::TrafficCop,
+    height: u64,
+) -> Result<Option<Bytes>, NockAppError> {
+    if height >= 1 << 63 {
+        return Ok(None);
+    }
+    let mut path = NounSlab::new();
+    let root = T(&mut path, &[D(tas!(b"snapshot")), D(height), D(0)]);
+    path.set_root(root);
+    let Some(result) = traffic.peek(path).await? else {
+        return Ok(None);
+    };
+    let ScryResult::Some(snapshot) = ScryResult::from(unsafe { result.root() }) else {
+        return Ok(None);
+    };
+    let mut slab = NounSlab::new();
+    let snapshot = slab.copy_into(snapshot);
+    slab.set_root(snapshot);
+    Ok(Some(slab.jam()))
+}
+
+/// Load a snapshot fetched from peers, returning whether the kernel took it
+async fn load_snapshot(
+    traffic: &traffic_cop::TrafficCop,
+    jam: Bytes,
+    height: u64,
+) -> Result<bool, NockAppError> {
+    let mut slab = NounSlab::new();
+    let snapshot = slab.cue_into(jam).map_err(|_| NockAppError::OtherError)?;
+    let tag = make_tas(&mut slab, "load-snapshot").as_noun();
+    let poke = T(&mut slab, &[D(tas!(b"command")), tag, snapshot]);
+    slab.set_root(poke);
+    if let PokeResult::Nack = traffic
+        .poke_high_priority(SystemWire.to_wire(), slab)
+        .await?
+    {
+        return Ok(false);
+    }
+    Ok(heaviest_height(traffic).await? == Some(height))
+}
+
+async fn heaviest_height(traffic: &traffic_cop::TrafficCop) -> Result<Option<u64>, NockAppError> {
+    let mut path = NounSlab::new();
+    let tag = make_tas(&mut path, "heaviest-block").as_noun();
+    let root = T(&mut path, &[tag, D(0)]);
+    path.set_root(root);
+    let Some(result) = traffic.peek(path).await? else {
+        return Ok(None);
+    };
+    match ScryResult::from(unsafe { result.root() }) {
+        ScryResult::Some(page) => Ok(page_height(page)),
+        _ => Ok(None),
+    }
+}
+
+/// Ask each peer for the block at its height, once the download limits allow it
+async fn send_block_requests(
+    requests: Vec<(PeerId, u64)>,
+    swarm_tx: mpsc::Sender<SwarmAction>,
+    mut equix_builder: equix::EquiXBuilder,
+    local_peer_id: PeerId,
+    message_tracker: Arc<Mutex<MessageTracker>>,
+    metrics: Arc<NockchainP2PMetrics>,
+) -> Result<(), NockAppError> {
+    for (peer_id, height) in requests {
+        let wait = message_tracker
+            .lock()
+            .await
+            .bandwidth
+            .download_wait(peer_id, Instant::now());
+        if !wait.is_zero() {
+            trace!("Waiting {wait:?} for download bandwidth to ask {peer_id} for {height}");
+            tokio::time::sleep(wait).await;
+        }
+        let request = NockchainRequest::new_request(
+            &mut equix_builder,
+            &local_peer_id,
+            &peer_id,
+            &by_height_request(height),
+        );
+        metrics.blocks_requested_by_height.increment();
+        swarm_tx
+            .send(SwarmAction::SendRequest { peer_id, request })
+            .await
+            .map_err(|_e| NockAppError::OtherError)?;
+    }
+    Ok(())
+}
+
+/// Poke buffered blocks into the kernel lowest first, for as long as the next one is here
+async fn deliver_blocks(
+    message_tracker: Arc<Mutex<MessageTracker>>,
+    traffic: traffic_cop::TrafficCop,
+    metrics: Arc<NockchainP2PMetrics>,
+) -> Result<(), NockAppError> {
+    loop {
+        let Some((height, peer, block)) = message_tracker.lock().await.block_sync.take_ready()
+        else {
+            return Ok(());
+        };
+        let poke_result = async {
+            let fact = NockchainFact::from_noun_slab(&block)?;
+            let validate_start = Instant::now();
+            let poke_result = traffic
+                .poke_high_priority(
+                    Libp2pWire::Response(peer).to_wire(),
+                    fact.fact_poke().clone(),
+                )
+                .await;
+            record_validation(&fact, validate_start.elapsed());
+            poke_result
+        }
+        .await;
+        let mut tracker = message_tracker.lock().await;
+        match poke_result {
+            Ok(PokeResult::Ack) => {
+                metrics.responses_acked_heard_block.increment();
+                tracker.block_sync.delivered(height);
+            }
+            Ok(PokeResult::Nack) => {
+                debug!("Poke response nacked for heard-block at height: {height}");
+                metrics.responses_nacked_heard_block.increment();
+                tracker.block_sync.refused(peer, height, Instant::now());
+            }
+            Err(err) => {
+                if let NockAppError::MPSCFullError(_) = err {
+                    metrics.responses_dropped.increment();
+                } else {
+                    metrics.responses_erred_heard_block.increment();
+                }
+                tracker.block_sync.interrupted();
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// The id, parent and height of a `page:dt` whose parent the kernel doesn't have, so that the
+/// kernel would drop it
+async fn orphan_of(
+    page: Noun,
+    message_tracker: &Arc<Mutex<MessageTracker>>,
+    traffic: &traffic_cop::TrafficCop,
+) -> Option<(String, String, u64)> {
+    let height = page_height(page).filter(|height| *height > 0)?;
+    let id = tip5_hash_to_base58(page.slot(2).ok()?).ok()?;
+    let parent = tip5_hash_to_base58(page.slot(14).ok()?).ok()?;
+    if message_tracker.lock().await.seen_blocks.contains(&parent) {
+        return None;
+    }
+    let mut path = NounSlab::new();
+    let parent_atom = Atom::from_value(&mut path, parent.as_str()).ok()?;
+    let root = T(&mut path, &[D(tas!(b"block")), parent_atom.as_noun(), D(0)]);
+    path.set_root(root);
+    let result = traffic.peek(path).await.ok()??;
+    match ScryResult::from(unsafe { result.root() }) {
+        ScryResult::Nothing => Some((id, parent, height)),
+        _ => None,
+    }
+}
+
+/// Hold a `[%heard-block page]` from `peer` in the orphan pool if the kernel doesn't have its
+/// parent, and ask `peer` for the parent if nothing else is waiting on it. Returns false if the
+/// pool already held it, so the kernel has already heard it.
+#[allow(clippy::too_many_arguments)]
+async fn hold_orphan(
+    peer: PeerId,
+    gossip: bool,
+    block: &NounSlab,
+    swarm_tx: mpsc::Sender<SwarmAction>,
+    equix_builder: equix::EquiXBuilder,
+    local_peer_id: PeerId,
+    message_tracker: &Arc<Mutex<MessageTracker>>,
+    traffic: &traffic_cop::TrafficCop,
+    metrics: &Arc<NockchainP2PMetrics>,
+) -> Result<bool, NockAppError> {
+    let page = unsafe { block.root() }.as_cell()?.tail();
+    let Some((id, parent, height)) = orphan_of(page, message_tracker, traffic).await else {
+        return Ok(true);
+    };
+    let held = {
+        let mut tracker = message_tracker.lock().await;
+        let held = tracker
+            .orphans
+            .insert(id, parent, peer, gossip, block.clone(), Instant::now());
+        metrics.orphan_pool_size.swap(tracker.orphans.len() as f64);
+        held
+    };
+    match held {
+        None => {
+            metrics.orphans_duplicate.increment();
+            Ok(false)
+        }
+        Some(ask) => {
+            debug!("Holding orphan block at height {height} from {peer}");
+            metrics.orphans_held.increment();
+            if ask {
+                send_block_requests(
+                    vec![(peer, height - 1)],
+                    swarm_tx,
+                    equix_builder,
+                    local_peer_id,
+                    Arc::clone(message_tracker),
+                    Arc::clone(metrics),
+                )
+                .await?;
+            }
+            Ok(true)
+        }
+    }
+}
+
+/// Gossip the transactions submitted here that are due to go out again, dropping the ones that
+/// have left the mempool
+async fn rebroadcast_txs(
+    connected_peers: Vec<PeerId>,
+    swarm_tx: mpsc::Sender<SwarmAction>,
+    message_tracker: Arc<Mutex<MessageTracker>>,
+    traffic: traffic_cop::TrafficCop,
+    metrics: Arc<NockchainP2PMetrics>,
+) -> Result<(), NockAppError> {
+    let mut path = NounSlab::new();
+    let mempool_tag = make_tas(&mut path, "mempool").as_noun();
+    let root = T(&mut path, &[mempool_tag, D(0)]);
+    path.set_root(root);
+    let Some(result) = traffic.peek(path).await? else {
+        return Ok(());
+    };
+    // (list [id=@t fee size heard-at candidate=?])
+    let ScryResult::Some(txs) = ScryResult::from(unsafe { result.root() }) else {
+        return Ok(());
+    };
+    let mempool: BTreeSet<String> = txs
+        .list_iter()
+        .filter_map(|tx| tx.as_cell().ok()?.head().as_atom().ok()?.into_string().ok())
+        .collect();
+
+    let due = {
+        let mut tracker = message_tracker.lock().await;
+        for _ in 0..tracker.rebroadcast.retain(&mempool) {
+            metrics.submitted_txs_left_mempool.increment();
+        }
+        let height = tracker.first_negative.saturating_sub(1);
+        let (due, abandoned) = tracker.rebroadcast.due(height, Instant::now());
+        for _ in 0..abandoned {
+            metrics.submitted_txs_abandoned.increment();
+        }
+        metrics
+            .submitted_txs_tracked
+            .swap(tracker.rebroadcast.len() as f64);
+        due
+    };
+    if !due.is_empty() {
+        debug!(
+            "Rebroadcasting {} submitted txs to {} peers",
+            due.len(),
+            connected_peers.len()
+        );
+    }
+    for fact in due {
+        metrics.submitted_txs_rebroadcast.increment();
+        let request = NockchainRequest::new_gossip(&fact);
+        for peer_id in &connected_peers {
+            swarm_tx
+                .send(SwarmAction::SendRequest {
+                    peer_id: *peer_id,
+                    request: request.clone(),
+                })
+                .await
+                .map_err(|_e| NockAppError::OtherError)?;
+        }
+    }
+    Ok(())
+}
+
+/// Poke orphans whose parents the kernel has now back into it
+async fn deliver_orphans(
+    message_tracker: Arc<Mutex<MessageTracker>>,
+    traffic: traffic_cop::TrafficCop,
+    metrics: Arc<NockchainP2PMetrics>,
+) -> Result<(), NockAppError> {
+    let ready = message_tracker.lock().await.orphans.take_ready();
+    for orphan in ready {
+        metrics.orphans_connected.increment();
+        let wire = if orphan.gossip {
+            Libp2pWire::Gossip(orphan.peer)
+        } else {
+            Libp2pWire::Response(orphan.peer)
+        };
+        let fact = NockchainFact::from_noun_slab(&orphan.block)?;
+        let validate_start = Instant::now();
+        let poke_result = traffic
+            .poke_high_priority(wire.to_wire(), fact.fact_poke().clone())
+            .await;
+        record_validation(&fact, validate_start.elapsed());
+        if let Ok(PokeResult::Nack) = poke_result {
+            debug!("Poke nacked for orphan from {}", orphan.peer);
+        }
+        poke_result?;
+    }
+    Ok(())
+}
+
 async fn log_peer_status(swarm: &mut Swarm<NockchainBehaviour>, metrics: &NockchainP2PMetrics) {
     {
         info!("Logging current peer status...");
@@ -1684,6 +2886,7 @@ mod tests {
         // Test that gossip requests always verify successfully
         let gossip_request = NockchainRequest::Gossip {
             message: message.clone(),
+            compressed: false,
         };
         let result = gossip_request.verify_pow(&mut builder, &remote_peer_id, &local_peer_id);
         assert!(
@@ -2235,6 +3438,16 @@ mod tests {
     }
 }
 
+/// The next peer command, or never if the driver wasn't given a command channel
+async fn next_peer_command(
+    peer_commands: &mut Option<mpsc::Receiver<PeerCommand>>,
+) -> Option<PeerCommand> {
+    match peer_commands {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
 fn dial_peers(
     swarm: &mut Swarm<NockchainBehaviour>,
     peers: &[Multiaddr],
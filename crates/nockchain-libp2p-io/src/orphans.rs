@@ -0,0 +1,221 @@
+//! Blocks that arrived before their parents.
+//!
+//! The kernel drops a block whose parent it doesn't have, so a block gossiped or sent out of order
+//! used to be downloaded again once its parent was in. Instead the driver still pokes it into the
+//! kernel, so the kernel can catch up as before, but also holds it in the [`OrphanPool`] and asks
+//! the peer that sent it for the block below. When the kernel has seen a block, the orphans
+//! waiting on it go back to the kernel with the next block sync tick, so a chain of orphans
+//! connects from the bottom up.
+//!
+//! The pool holds up to [`MAX_ORPHANS`] blocks, dropping the oldest beyond that, and for up to
+//! [`ORPHAN_TTL`] each, after which the missing parent isn't coming.
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+use nockapp::noun::slab::NounSlab;
+use tracing::debug;
+
+/// Most orphans to hold at once
+pub const MAX_ORPHANS: usize = 256;
+
+/// How long an orphan waits for its parent
+pub const ORPHAN_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug)]
+struct Orphan {
+    parent: String,
+    peer: PeerId,
+    /// Whether it was gossiped rather than sent in response
+    gossip: bool,
+    block: NounSlab,
+    arrived: Instant,
+}
+
+/// An orphan whose parent the kernel has now
+#[derive(Debug)]
+pub struct Connected {
+    pub peer: PeerId,
+    pub gossip: bool,
+    pub block: NounSlab,
+}
+
+#[derive(Debug, Default)]
+pub struct OrphanPool {
+    /// By block id
+    orphans: BTreeMap<String, Orphan>,
+    /// Ids of the orphans waiting on each parent
+    children: BTreeMap<String, BTreeSet<String>>,
+    ready: Vec<Connected>,
+}
+
+impl OrphanPool {
+    pub fn len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty()
+    }
+
+    /// Hold `block`, whose parent the kernel doesn't have. Returns `None` if it's already held,
+    /// and otherwise whether its parent needs asking for, since nothing else is waiting on it.
+    pub fn insert(
+        &mut self,
+        id: String,
+        parent: String,
+        peer: PeerId,
+        gossip: bool,
+        block: NounSlab,
+        now: Instant,
+    ) -> Option<bool> {
+        if self.orphans.contains_key(&id) {
+            return None;
+        }
+        if self.orphans.len() >= MAX_ORPHANS {
+            let oldest = self
+                .orphans
+                .iter()
+                .min_by_key(|(_, orphan)| orphan.arrived)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                debug!("Orphan pool full, dropping {oldest}");
+                self.remove(&oldest);
+            }
+        }
+        let siblings = self.children.entry(parent.clone()).or_default();
+        let ask = siblings.is_empty() && !self.orphans.contains_key(&parent);
+        siblings.insert(id.clone());
+        self.orphans.insert(
+            id,
+            Orphan {
+                parent,
+                peer,
+                gossip,
+                block,
+                arrived: now,
+            },
+        );
+        Some(ask)
+    }
+
+    /// The kernel has seen the block `id`, so the orphans waiting on it can go back to it.
+    /// Returns how many there are.
+    pub fn connected(&mut self, id: &str) -> usize {
+        self.remove(id);
+        let Some(children) = self.children.remove(id) else {
+            return 0;
+        };
+        let mut count = 0;
+        for child in children {
+            if let Some(orphan) = self.orphans.remove(&child) {
+                self.ready.push(Connected {
+                    peer: orphan.peer,
+                    gossip: orphan.gossip,
+                    block: orphan.block,
+                });
+                count += 1;
+            }
+        }
+        count
+    }
+
+    pub fn has_ready(&self) -> bool {
+        !self.ready.is_empty()
+    }
+
+    /// The orphans to deliver to the kernel
+    pub fn take_ready(&mut self) -> Vec<Connected> {
+        std::mem::take(&mut self.ready)
+    }
+
+    /// Drop orphans that have waited too long. Returns how many.
+    pub fn tick(&mut self, now: Instant) -> usize {
+        let expired: Vec<String> = self
+            .orphans
+            .iter()
+            .filter(|(_, orphan)| now.saturating_duration_since(orphan.arrived) >= ORPHAN_TTL)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            self.remove(id);
+        }
+        expired.len()
+    }
+
+    fn remove(&mut self, id: &str) {
+        let Some(orphan) = self.orphans.remove(id) else {
+            return;
+        };
+        if let Some(siblings) = self.children.get_mut(&orphan.parent) {
+            siblings.remove(id);
+            if siblings.is_empty() {
+                self.children.remove(&orphan.parent);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::D;
+
+    use super::*;
+
+    fn block() -> NounSlab {
+        let mut slab = NounSlab::new();
+        slab.set_root(D(0));
+        slab
+    }
+
+    #[test]
+    fn test_connects_chains() {
+        let peer = PeerId::random();
+        let now = Instant::now();
+        let mut pool = OrphanPool::default();
+
+        // 3 and then 2 arrive before 1: only 2's parent needs asking for
+        let insert = |pool: &mut OrphanPool, id: &str, parent: &str| {
+            pool.insert(id.into(), parent.into(), peer, true, block(), now)
+        };
+        assert_eq!(insert(&mut pool, "3", "2"), Some(true));
+        assert_eq!(insert(&mut pool, "2", "1"), Some(true));
+        assert_eq!(insert(&mut pool, "3", "2"), None);
+        assert_eq!(insert(&mut pool, "3b", "2"), Some(false));
+        assert_eq!(pool.len(), 3);
+
+        assert_eq!(pool.connected("0"), 0);
+        assert_eq!(pool.connected("1"), 1);
+        assert!(pool.has_ready());
+        assert_eq!(pool.take_ready().len(), 1);
+        assert_eq!(pool.connected("2"), 2);
+        assert_eq!(pool.take_ready().len(), 2);
+        assert!(pool.is_empty() && !pool.has_ready());
+    }
+
+    #[test]
+    fn test_expires_and_evicts() {
+        let peer = PeerId::random();
+        let now = Instant::now();
+        let mut pool = OrphanPool::default();
+        for i in 0..MAX_ORPHANS + 1 {
+            let arrived = now + Duration::from_secs(i as u64);
+            pool.insert(
+                i.to_string(),
+                "parent".into(),
+                peer,
+                false,
+                block(),
+                arrived,
+            );
+        }
+        // the first went to make room
+        assert_eq!(pool.len(), MAX_ORPHANS);
+        assert!(!pool.orphans.contains_key("0"));
+
+        assert_eq!(pool.tick(now + ORPHAN_TTL + Duration::from_secs(10)), 10);
+        assert_eq!(pool.len(), MAX_ORPHANS - 10);
+        assert_eq!(pool.tick(now + ORPHAN_TTL * 2), MAX_ORPHANS - 10);
+        assert!(pool.children.is_empty());
+    }
+}
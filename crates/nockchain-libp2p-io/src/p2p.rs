@@ -1,21 +1,26 @@
 use std::convert::Infallible;
 use std::error::Error;
+use std::time::Duration;
 
 use hickory_resolver::config::{ResolverConfig, ResolverOpts};
 use libp2p::identity::Keypair;
-use libp2p::multiaddr::Multiaddr;
+use libp2p::multiaddr::{Multiaddr, Protocol};
 use libp2p::request_response::{self, cbor, ResponseChannel};
 use libp2p::swarm::behaviour::toggle::Toggle;
 use libp2p::swarm::NetworkBehaviour;
 use libp2p::{
-    allow_block_list, connection_limits, identify, kad, memory_connection_limits, ping, PeerId,
-    Swarm,
+    allow_block_list, autonat, connection_limits, dcutr, identify, kad, memory_connection_limits,
+    ping, relay, upnp, PeerId, Swarm,
 };
 use nockapp::NockAppError;
-use tracing::{debug, error, trace};
+use tokio::sync::oneshot;
+use tracing::{debug, error, info, trace};
 
 use crate::config::LibP2PConfig;
+use crate::memo::{Memo, MemoResponse};
 use crate::nc::*;
+use crate::reputation::BlockedPeer;
+use crate::snapshot::{SnapshotRequest, SnapshotResponse};
 
 #[derive(Debug)]
 pub enum SwarmAction {
@@ -27,11 +32,45 @@ pub enum SwarmAction {
         peer_id: PeerId,
         request: NockchainRequest,
     },
+    SendSnapshotResponse {
+        channel: ResponseChannel<SnapshotResponse>,
+        response: SnapshotResponse,
+    },
     BlockPeer {
         peer_id: PeerId,
     },
 }
 
+/// Peer management asked of the driver from outside it, like an operator's RPC call
+#[derive(Debug)]
+pub enum PeerCommand {
+    /// Send back the connected peers, the addresses they are connected on and their scores
+    List {
+        result: oneshot::Sender<Vec<(PeerId, Vec<Multiaddr>, i64)>>,
+    },
+    /// Dial a peer
+    Dial { addr: Multiaddr },
+    /// Block and disconnect a peer for a while, or for good if `duration` is `None`
+    Block {
+        peer_id: PeerId,
+        duration: Option<Duration>,
+    },
+    /// Unblock a peer, sending back whether it was blocked
+    Unblock {
+        peer_id: PeerId,
+        result: oneshot::Sender<bool>,
+    },
+    /// Send back the blocked peers
+    ListBlocked {
+        result: oneshot::Sender<Vec<(PeerId, BlockedPeer)>>,
+    },
+    /// Relay a memo submitted here, sending back whether it was new, or `None` if we don't relay
+    Memo {
+        memo: Memo,
+        result: oneshot::Sender<Option<bool>>,
+    },
+}
+
 #[derive(NetworkBehaviour)]
 #[behaviour(to_swarm = "NockchainEvent")]
 /** Composed [NetworkBehaviour] implementation for Nockchain */
@@ -54,6 +93,20 @@ pub struct NockchainBehaviour {
     pub peer_store: libp2p::peer_store::Behaviour<libp2p::peer_store::memory_store::MemoryStore>,
     /// Actual comms
     pub request_response: cbor::Behaviour<NockchainRequest, NockchainResponse>,
+    /// Snapshots for fast sync, served only if we opt in (see [`crate::snapshot`])
+    pub snapshots: cbor::Behaviour<SnapshotRequest, SnapshotResponse>,
+    /// Memos between wallets, only if we opt in to relaying them (see [`crate::memo`])
+    pub memos: Toggle<cbor::Behaviour<Memo, MemoResponse>>,
+    /// Router port mapping
+    upnp: Toggle<upnp::tokio::Behaviour>,
+    /// Learning whether peers can dial us
+    autonat: autonat::Behaviour,
+    /// Listening through relays when they can't
+    relay_client: relay::client::Behaviour,
+    /// Relaying for peers behind NATs
+    relay_server: Toggle<relay::Behaviour>,
+    /// Hole punching through relayed connections
+    dcutr: dcutr::Behaviour,
 }
 
 impl NockchainBehaviour {
@@ -62,23 +115,19 @@ impl NockchainBehaviour {
         allowed: Option<allow_block_list::Behaviour<allow_block_list::AllowedPeers>>,
         limits: connection_limits::ConnectionLimits,
         memory_limits: Option<memory_connection_limits::Behaviour>,
-    ) -> impl FnOnce(&libp2p::identity::Keypair) -> Self {
-        move |keypair: &libp2p::identity::Keypair| {
+    ) -> impl FnOnce(&libp2p::identity::Keypair, relay::client::Behaviour) -> Self {
+        move |keypair: &libp2p::identity::Keypair, relay_client: relay::client::Behaviour| {
             let peer_id = libp2p::identity::PeerId::from_public_key(&keypair.public());
 
-            let identify_config = identify::Config::new(
-                libp2p_config.identify_protocol_version.clone(),
-                keypair.public(),
-            )
-            .with_interval(libp2p_config.identify_interval())
-            .with_hide_listen_addrs(true); // Only send externally confirmed addresses so we don't send loopback addresses
+            let identify_config =
+                identify::Config::new(libp2p_config.identify_protocol(), keypair.public())
+                    .with_interval(libp2p_config.identify_interval())
+                    .with_hide_listen_addrs(true); // Only send externally confirmed addresses so we don't send loopback addresses
             let identify_behaviour = identify::Behaviour::new(identify_config);
 
             let memory_store = kad::store::MemoryStore::new(peer_id);
 
-            let kad_config = kad::Config::new(libp2p::StreamProtocol::new(
-                LibP2PConfig::kad_protocol_version(),
-            ));
+            let kad_config = kad::Config::new(libp2p_config.kad_protocol());
             let kad_behaviour = kad::Behaviour::with_config(peer_id, memory_store, kad_config);
 
             let request_response_config = request_response::Config::default()
@@ -88,12 +137,40 @@ impl NockchainBehaviour {
                 .with_request_timeout(libp2p_config.request_response_timeout());
 
             let request_response_behaviour = cbor::Behaviour::new(
-                [(
-                    libp2p::StreamProtocol::new(LibP2PConfig::req_res_protocol_version()),
-                    request_response::ProtocolSupport::Full,
-                )],
-                request_response_config,
+                [
+                    (
+                        libp2p_config.req_res_protocol(),
+                        request_response::ProtocolSupport::Full,
+                    ),
+                    (
+                        libp2p_config.compact_protocol(),
+                        request_response::ProtocolSupport::Full,
+                    ),
+                    (
+                        libp2p_config.compression_protocol(),
+                        request_response::ProtocolSupport::Full,
+                    ),
+                ],
+                request_response_config.clone(),
+            );
+            let snapshot_support = if libp2p_config.serve_snapshots {
+                request_response::ProtocolSupport::Full
+            } else {
+                request_response::ProtocolSupport::Outbound
+            };
+            let snapshot_behaviour = cbor::Behaviour::new(
+                [(libp2p_config.snapshot_protocol(), snapshot_support)],
+                request_response_config.clone(),
             );
+            let memo_behaviour = Toggle::from(libp2p_config.relay_memos.then(|| {
+                cbor::Behaviour::new(
+                    [(
+                        libp2p_config.memo_protocol(),
+                        request_response::ProtocolSupport::Full,
+                    )],
+                    request_response_config,
+                )
+            }));
             let connection_limits_behaviour = connection_limits::Behaviour::new(limits);
             let memory_connection_limits =
                 Toggle::<memory_connection_limits::Behaviour>::from(memory_limits);
@@ -109,6 +186,13 @@ impl NockchainBehaviour {
                 libp2p::peer_store::memory_store::MemoryStore::new(peer_store_config);
 
             let peer_store_behaviour = libp2p::peer_store::Behaviour::new(peer_store_memory);
+            let upnp = Toggle::from(libp2p_config.upnp.then(upnp::tokio::Behaviour::default));
+            let autonat = autonat::Behaviour::new(peer_id, autonat::Config::default());
+            let relay_server = Toggle::from(
+                libp2p_config
+                    .relay_server
+                    .then(|| relay::Behaviour::new(peer_id, relay::Config::default())),
+            );
             NockchainBehaviour {
                 ping: ping::Behaviour::default(),
                 identify: identify_behaviour,
@@ -116,9 +200,16 @@ impl NockchainBehaviour {
                 allow_block_list: allow_block_list::Behaviour::default(),
                 allow_peers,
                 request_response: request_response_behaviour,
+                snapshots: snapshot_behaviour,
+                memos: memo_behaviour,
                 connection_limits: connection_limits_behaviour,
                 memory_connection_limits,
                 peer_store: peer_store_behaviour,
+                upnp,
+                autonat,
+                relay_client,
+                relay_server,
+                dcutr: dcutr::Behaviour::new(peer_id),
             }
         }
     }
@@ -131,7 +222,9 @@ impl NockchainBehaviour {
 ///
 /// # Arguments
 /// * `keypair` - The keypair for the node's identity
-/// * `bind` - A vector of multiaddresses specifying the network interfaces to bind to
+/// * `bind` - A vector of multiaddresses specifying the network interfaces to bind to, over QUIC
+///   (`/udp/<port>/quic-v1`) or TCP (`/tcp/<port>`)
+/// * `external` - Addresses peers can reach us on that aren't among `bind`, like a forwarded port
 ///
 /// # Returns
 /// A Result containing the Swarm instance or an error if any operation fails
@@ -139,6 +232,7 @@ pub fn start_swarm(
     libp2p_config: LibP2PConfig,
     keypair: Keypair,
     bind: Vec<Multiaddr>,
+    external: Vec<Multiaddr>,
     allowed: Option<allow_block_list::Behaviour<allow_block_list::AllowedPeers>>,
     limits: connection_limits::ConnectionLimits,
     memory_limits: Option<memory_connection_limits::Behaviour>,
@@ -158,6 +252,11 @@ pub fn start_swarm(
     let swarm_idle_timeout = libp2p_config.swarm_idle_timeout();
     let mut swarm = libp2p::SwarmBuilder::with_existing_identity(keypair)
         .with_tokio()
+        .with_tcp(
+            libp2p::tcp::Config::default().nodelay(true),
+            libp2p::tls::Config::new,
+            libp2p::yamux::Config::default,
+        )?
         .with_quic_config(|mut cfg| {
             cfg.max_idle_timeout = max_idle_timeout_millisecs;
             cfg.keep_alive_interval = keep_alive_interval;
@@ -165,6 +264,7 @@ pub fn start_swarm(
             cfg
         })
         .with_dns_config(resolver_config, resolver_opts)
+        .with_relay_client(libp2p::tls::Config::new, libp2p::yamux::Config::default)?
         .with_behaviour(NockchainBehaviour::pre_new(
             libp2p_config, allowed, limits, memory_limits,
         ))?
@@ -178,9 +278,38 @@ pub fn start_swarm(
             e
         })?;
     }
+    for addr in external {
+        info!("Advertising external address {addr}");
+        swarm.add_external_address(addr);
+    }
     Ok(swarm)
 }
 
+/// Whether peers elsewhere on the internet could dial `addr`, so it's worth advertising as soon as
+/// we listen on it. Private, loopback, link-local and unspecified IPs aren't; DNS names are
+/// assumed to be.
+pub fn is_public_addr(addr: &Multiaddr) -> bool {
+    match addr.iter().next() {
+        Some(Protocol::Ip4(ip)) => {
+            let shared = ip.octets()[0] == 100 && (ip.octets()[1] & 0b1100_0000) == 64;
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || shared)
+        }
+        Some(Protocol::Ip6(ip)) => {
+            let unique_local = (ip.segments()[0] & 0xfe00) == 0xfc00;
+            let link_local = (ip.segments()[0] & 0xffc0) == 0xfe80;
+            !(ip.is_loopback() || ip.is_unspecified() || unique_local || link_local)
+        }
+        Some(Protocol::Dns(_)) | Some(Protocol::Dns4(_)) | Some(Protocol::Dns6(_)) => true,
+        _ => false,
+    }
+}
+
 // TODO: We need to box identify::Event but we are on stable so no boxed patterns.
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -195,8 +324,52 @@ pub enum NockchainEvent {
     Kad(kad::Event),
     /// Request or response received from peer
     RequestResponse(request_response::Event<NockchainRequest, NockchainResponse>),
+    /// Snapshot request or response received from peer
+    Snapshot(request_response::Event<SnapshotRequest, SnapshotResponse>),
+    /// Memo relayed to us, or our relaying's outcome
+    Memo(request_response::Event<Memo, MemoResponse>),
     /// Peer store events
     PeerStore(libp2p::peer_store::memory_store::Event),
+    /// Port mapping found or lost
+    Upnp(upnp::Event),
+    /// Our reachability changed
+    Autonat(autonat::Event),
+    /// Relay reservations of ours
+    RelayClient(relay::client::Event),
+    /// Relaying for others
+    RelayServer(relay::Event),
+    /// Hole punch results
+    Dcutr(dcutr::Event),
+}
+
+impl From<upnp::Event> for NockchainEvent {
+    fn from(event: upnp::Event) -> Self {
+        Self::Upnp(event)
+    }
+}
+
+impl From<autonat::Event> for NockchainEvent {
+    fn from(event: autonat::Event) -> Self {
+        Self::Autonat(event)
+    }
+}
+
+impl From<relay::client::Event> for NockchainEvent {
+    fn from(event: relay::client::Event) -> Self {
+        Self::RelayClient(event)
+    }
+}
+
+impl From<relay::Event> for NockchainEvent {
+    fn from(event: relay::Event) -> Self {
+        Self::RelayServer(event)
+    }
+}
+
+impl From<dcutr::Event> for NockchainEvent {
+    fn from(event: dcutr::Event) -> Self {
+        Self::Dcutr(event)
+    }
 }
 
 impl From<identify::Event> for NockchainEvent {
@@ -229,6 +402,18 @@ impl From<request_response::Event<NockchainRequest, NockchainResponse>> for Nock
     }
 }
 
+impl From<request_response::Event<SnapshotRequest, SnapshotResponse>> for NockchainEvent {
+    fn from(event: request_response::Event<SnapshotRequest, SnapshotResponse>) -> Self {
+        Self::Snapshot(event)
+    }
+}
+
+impl From<request_response::Event<Memo, MemoResponse>> for NockchainEvent {
+    fn from(event: request_response::Event<Memo, MemoResponse>) -> Self {
+        Self::Memo(event)
+    }
+}
+
 impl From<libp2p::peer_store::memory_store::Event> for NockchainEvent {
     fn from(event: libp2p::peer_store::memory_store::Event) -> Self {
         Self::PeerStore(event)
@@ -254,3 +439,22 @@ pub fn identify_received(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_public_addr() {
+        let public = |addr: &str| is_public_addr(&addr.parse().expect("addr"));
+        assert!(public("/ip4/1.2.3.4/udp/3006/quic-v1"));
+        assert!(public("/ip6/2600::1/tcp/3006"));
+        assert!(public("/dns4/node.example.org/tcp/3006"));
+        assert!(!public("/ip4/0.0.0.0/udp/3006/quic-v1"));
+        assert!(!public("/ip4/192.168.1.5/tcp/3006"));
+        assert!(!public("/ip4/100.64.0.1/tcp/3006"));
+        assert!(!public("/ip4/127.0.0.1/udp/3006/quic-v1"));
+        assert!(!public("/ip6/fe80::1/udp/3006/quic-v1"));
+        assert!(!public("/ip6/fd00::1/tcp/3006"));
+    }
+}
@@ -7,13 +7,20 @@ use libp2p::core::ConnectedPoint;
 use libp2p::swarm::ConnectionId;
 use libp2p::{Multiaddr, PeerId, Swarm};
 use nockapp::noun::slab::NounSlab;
-use nockapp::{AtomExt, NockAppError, NounExt};
+use nockapp::{prometheus, AtomExt, NockAppError, NounExt};
 use nockvm::noun::{Noun, D};
 use nockvm_macros::tas;
 use rand::prelude::SliceRandom;
 use tracing::{info, trace, warn};
 
+use crate::bandwidth::Bandwidth;
+use crate::compact::CompactRelay;
+use crate::compress::Compression;
 use crate::metrics::NockchainP2PMetrics;
+use crate::orphans::OrphanPool;
+use crate::rebroadcast::Rebroadcast;
+use crate::reputation::Reputation;
+use crate::sync::BlockSync;
 use crate::tip5_util::tip5_hash_to_base58;
 
 // The warn logs are specifically constructed for fail2ban
@@ -70,6 +77,13 @@ pub struct MessageTracker {
     pub first_negative: u64,
     pub seen_tx_clear_interval: u64,
     pub last_tx_cache_clear_height: u64,
+    pub block_sync: BlockSync,
+    pub reputation: Reputation,
+    pub bandwidth: Bandwidth,
+    pub orphans: OrphanPool,
+    pub compact: CompactRelay,
+    pub compression: Compression,
+    pub rebroadcast: Rebroadcast,
 }
 
 impl MessageTracker {
@@ -92,6 +106,13 @@ impl MessageTracker {
             first_negative: 0,
             seen_tx_clear_interval,
             last_tx_cache_clear_height: 0,
+            block_sync: BlockSync::default(),
+            reputation: Reputation::default(),
+            bandwidth: Bandwidth::default(),
+            orphans: OrphanPool::default(),
+            compact: CompactRelay::default(),
+            compression: Compression::default(),
+            rebroadcast: Rebroadcast::default(),
         }
     }
 
@@ -115,6 +136,15 @@ impl MessageTracker {
         }
         let peer_count = self.peer_connections.len() as f64;
         let _ = self.metrics.peer_count.swap(peer_count);
+        prometheus::set_gauge("nockchain_peers", "Connected peers", &[], peer_count);
+    }
+
+    /// The connected peers and the addresses of their connections
+    pub fn peer_addresses(&self) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        self.peer_connections
+            .iter()
+            .map(|(peer_id, connections)| (*peer_id, connections.values().cloned().collect()))
+            .collect()
     }
 
     pub(crate) fn lost_connection(&mut self, connection_id: ConnectionId) {
@@ -130,6 +160,7 @@ impl MessageTracker {
         }
         let peer_count = self.peer_connections.len() as f64;
         let _ = self.metrics.peer_count.swap(peer_count);
+        prometheus::set_gauge("nockchain_peers", "Connected peers", &[], peer_count);
     }
 
     pub(crate) fn prune_inbound_connections(
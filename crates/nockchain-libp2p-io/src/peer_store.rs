@@ -0,0 +1,206 @@
+//! Peers worth dialing again after a restart.
+//!
+//! The driver remembers every peer it has dialed successfully: the addresses it reached it on,
+//! when it last did, and how many dials of it have succeeded and failed since. The list is saved
+//! as JSON every few minutes, and loaded when the driver starts, which dials the best
+//! [`REDIAL_COUNT`] of them first and only dials the bootstrap peers if none of those answer.
+//! Peers that fail [`MAX_FAILURES`] dials in a row, that haven't been reached in
+//! [`FORGET_AFTER`], or that get blocked are forgotten.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+/// How many remembered peers to dial at startup
+pub const REDIAL_COUNT: usize = 16;
+
+/// Failed dials in a row after which a peer is forgotten
+pub const MAX_FAILURES: u32 = 5;
+
+/// How long a peer is remembered without being reached
+pub const FORGET_AFTER: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Most peers to remember, dropping the worst beyond it
+pub const MAX_PEERS: usize = 256;
+
+/// How long to wait for a remembered peer to answer before dialing the bootstrap peers
+pub const BOOTSTRAP_FALLBACK: Duration = Duration::from_secs(15);
+
+/// How often to save the store
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Most addresses to remember for one peer, keeping the latest
+const MAX_ADDRESSES: usize = 4;
+
+/// What we know of one peer
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownPeer {
+    /// Addresses we reached it on, latest first
+    pub addresses: Vec<String>,
+    /// Unix seconds when we last connected to it
+    pub last_seen: u64,
+    pub successes: u64,
+    /// Failed dials since the last success
+    pub failures: u32,
+}
+
+#[derive(Default)]
+pub struct PeerStore {
+    /// Where to save, or `None` to only remember until the node stops
+    path: Option<PathBuf>,
+    /// By base58 peer id
+    peers: BTreeMap<String, KnownPeer>,
+    dirty: bool,
+}
+
+impl PeerStore {
+    /// Load the peers saved at `path`, or start with none if there aren't any
+    pub fn load(path: PathBuf) -> Self {
+        let peers = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable peer store {}: {e}", path.display());
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Self {
+            path: Some(path),
+            peers,
+            dirty: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// We dialed `peer` on `address`
+    pub fn connected(&mut self, peer: PeerId, address: &Multiaddr, now: SystemTime) {
+        let known = self.peers.entry(peer.to_base58()).or_default();
+        let address = address.to_string();
+        known.addresses.retain(|known| *known != address);
+        known.addresses.insert(0, address);
+        known.addresses.truncate(MAX_ADDRESSES);
+        known.last_seen = unix_secs(now);
+        known.successes += 1;
+        known.failures = 0;
+        self.dirty = true;
+    }
+
+    /// A dial of `peer` failed
+    pub fn dial_failed(&mut self, peer: PeerId) {
+        let id = peer.to_base58();
+        let Some(known) = self.peers.get_mut(&id) else {
+            return;
+        };
+        known.failures += 1;
+        if known.failures >= MAX_FAILURES {
+            debug!("Forgetting {id} after {} failed dials", known.failures);
+            self.peers.remove(&id);
+        }
+        self.dirty = true;
+    }
+
+    pub fn forget(&mut self, peer: PeerId) {
+        self.dirty |= self.peers.remove(&peer.to_base58()).is_some();
+    }
+
+    /// Addresses of the `count` best peers to dial: fewest recent failures, then latest seen,
+    /// then most often reached
+    pub fn best(&self, count: usize, now: SystemTime) -> Vec<Multiaddr> {
+        let oldest = unix_secs(now).saturating_sub(FORGET_AFTER.as_secs());
+        let mut peers: Vec<&KnownPeer> = self
+            .peers
+            .values()
+            .filter(|known| known.last_seen >= oldest)
+            .collect();
+        peers.sort_by(|a, b| rank(a).cmp(&rank(b)));
+        peers
+            .into_iter()
+            .take(count)
+            .flat_map(|known| known.addresses.first())
+            .filter_map(|address| Multiaddr::from_str(address).ok())
+            .collect()
+    }
+
+    /// Write the store out if anything changed, forgetting old and surplus peers first
+    pub fn save(&mut self, now: SystemTime) -> std::io::Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        if !self.dirty {
+            return Ok(());
+        }
+        let oldest = unix_secs(now).saturating_sub(FORGET_AFTER.as_secs());
+        self.peers.retain(|_, known| known.last_seen >= oldest);
+        if self.peers.len() > MAX_PEERS {
+            let mut ranked: Vec<(String, KnownPeer)> =
+                std::mem::take(&mut self.peers).into_iter().collect();
+            ranked.sort_by(|(_, a), (_, b)| rank(a).cmp(&rank(b)));
+            ranked.truncate(MAX_PEERS);
+            self.peers = ranked.into_iter().collect();
+        }
+        let contents = serde_json::to_string_pretty(&self.peers)?;
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, contents)?;
+        fs::rename(&temp, path)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// Lower is better
+fn rank(known: &KnownPeer) -> (u32, std::cmp::Reverse<u64>, std::cmp::Reverse<u64>) {
+    (
+        known.failures,
+        std::cmp::Reverse(known.last_seen),
+        std::cmp::Reverse(known.successes),
+    )
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remember_and_reload() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("peers");
+        let now = SystemTime::now();
+        let (good, flaky) = (PeerId::random(), PeerId::random());
+        let good_addr: Multiaddr = "/ip4/10.0.0.1/udp/3006/quic-v1".parse().expect("addr");
+        let flaky_addr: Multiaddr = "/ip4/10.0.0.2/udp/3006/quic-v1".parse().expect("addr");
+
+        let mut store = PeerStore::load(path.clone());
+        assert!(store.is_empty());
+        store.connected(flaky, &flaky_addr, now);
+        store.connected(good, &good_addr, now - Duration::from_secs(60));
+        store.dial_failed(flaky);
+        assert_eq!(store.best(1, now), vec![good_addr.clone()]);
+        store.save(now).expect("save");
+
+        let mut store = PeerStore::load(path);
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.best(2, now), vec![good_addr, flaky_addr]);
+        for _ in 1..MAX_FAILURES {
+            store.dial_failed(flaky);
+        }
+        assert_eq!(store.len(), 1);
+        // too long ago to dial
+        assert!(store.best(2, now + FORGET_AFTER * 2).is_empty());
+    }
+}
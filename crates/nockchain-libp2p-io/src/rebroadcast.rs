@@ -0,0 +1,145 @@
+//! Rebroadcasting transactions submitted to this node.
+//!
+//! A transaction submitted while the node has no peers, or just before it loses them, reaches
+//! nobody, and the kernel only regossips its mempool when a new block comes in. So the kernel tells
+//! the driver about each transaction it accepts that didn't come from a peer with `%submitted`,
+//! and [`Rebroadcast`] gossips it again [`FIRST_DELAY`] later, then after twice as long each time
+//! up to [`MAX_DELAY`], for as long as it's in the mempool. Nothing is rebroadcast while there are
+//! no peers to send it to. A transaction still in the mempool
+//! `NOCKCHAIN_LIBP2P_REBROADCAST_DEPTH` blocks after it was submitted is given up on, since the
+//! network isn't taking it, though it stays in the mempool.
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+use nockapp::noun::slab::NounSlab;
+use tracing::debug;
+
+use crate::config::LibP2PConfig;
+
+/// How often to check for transactions to rebroadcast
+pub const REBROADCAST_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How long after it's submitted a transaction is first rebroadcast
+pub const FIRST_DELAY: Duration = Duration::from_secs(30);
+
+/// Longest to wait between rebroadcasts
+pub const MAX_DELAY: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Debug)]
+struct Submitted {
+    /// `[%heard-tx raw-tx]`, to gossip
+    fact: NounSlab,
+    /// Height of the highest block when it was submitted
+    height: u64,
+    delay: Duration,
+    next: Instant,
+}
+
+#[derive(Debug)]
+pub struct Rebroadcast {
+    depth: u64,
+    /// By tx id
+    txs: BTreeMap<String, Submitted>,
+}
+
+impl Default for Rebroadcast {
+    fn default() -> Self {
+        Self::new(LibP2PConfig::default().rebroadcast_depth)
+    }
+}
+
+impl Rebroadcast {
+    pub fn new(depth: u64) -> Self {
+        Self {
+            depth,
+            txs: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+
+    /// The kernel accepted the transaction `id`, submitted to this node at `height`
+    pub fn submitted(&mut self, id: String, fact: NounSlab, height: u64, now: Instant) {
+        self.txs.entry(id).or_insert(Submitted {
+            fact,
+            height,
+            delay: FIRST_DELAY,
+            next: now + FIRST_DELAY,
+        });
+    }
+
+    /// Forget the transactions that aren't in the `mempool` any more, because a block includes
+    /// them or they were evicted. Returns how many.
+    pub fn retain(&mut self, mempool: &BTreeSet<String>) -> usize {
+        let before = self.txs.len();
+        self.txs.retain(|id, _| mempool.contains(id));
+        before - self.txs.len()
+    }
+
+    /// The transactions to gossip again now that the highest block is at `height`, and how many
+    /// were given up on
+    pub fn due(&mut self, height: u64, now: Instant) -> (Vec<NounSlab>, usize) {
+        let before = self.txs.len();
+        let depth = self.depth;
+        self.txs.retain(|id, tx| {
+            let keep = height.saturating_sub(tx.height) < depth;
+            if !keep {
+                debug!("Giving up on rebroadcasting {id}, {depth} blocks after it was submitted");
+            }
+            keep
+        });
+        let abandoned = before - self.txs.len();
+        let due = self
+            .txs
+            .values_mut()
+            .filter(|tx| tx.next <= now)
+            .map(|tx| {
+                tx.delay = (tx.delay * 2).min(MAX_DELAY);
+                tx.next = now + tx.delay;
+                tx.fact.clone()
+            })
+            .collect();
+        (due, abandoned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::D;
+
+    use super::*;
+
+    fn fact() -> NounSlab {
+        let mut slab = NounSlab::new();
+        slab.set_root(D(0));
+        slab
+    }
+
+    #[test]
+    fn test_backoff_and_give_up() {
+        let now = Instant::now();
+        let mut rebroadcast = Rebroadcast::new(10);
+        rebroadcast.submitted("a".into(), fact(), 100, now);
+        rebroadcast.submitted("b".into(), fact(), 105, now);
+
+        assert_eq!(rebroadcast.due(100, now).0.len(), 0);
+        assert_eq!(rebroadcast.due(100, now + FIRST_DELAY).0.len(), 2);
+        // the next is twice as long after
+        let later = now + FIRST_DELAY * 2;
+        assert_eq!(rebroadcast.due(100, later).0.len(), 0);
+        assert_eq!(rebroadcast.due(100, now + FIRST_DELAY * 3).0.len(), 2);
+
+        // a has been in the mempool for 10 blocks
+        let (due, abandoned) = rebroadcast.due(110, now + MAX_DELAY * 2);
+        assert_eq!((due.len(), abandoned), (1, 1));
+
+        assert_eq!(rebroadcast.retain(&BTreeSet::new()), 1);
+        assert!(rebroadcast.is_empty());
+    }
+}
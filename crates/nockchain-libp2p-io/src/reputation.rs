@@ -0,0 +1,197 @@
+//! Peer reputation: scoring misbehaviour and blocking peers for it.
+//!
+//! Every peer starts at a score of zero. Each [`Offense`] takes points off, and a peer that
+//! reaches [`BLOCK_THRESHOLD`] is disconnected and blocked for the configured block duration.
+//! Scores recover by [`RECOVERY`] points a minute, so a peer that times out now and then is never
+//! blocked, while one that keeps sending garbage is. Offenses the kernel is sure of, like an
+//! invalid block, block a peer at once.
+//!
+//! Operators can block a peer for a while or forever, and unblock it, over JSON-RPC. The block
+//! list lasts until the node restarts.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use libp2p::PeerId;
+use tracing::warn;
+
+use crate::config::LibP2PConfig;
+
+/// The score at which a peer is blocked
+pub const BLOCK_THRESHOLD: i64 = -100;
+
+/// Points a peer's score recovers each minute, up to zero
+pub const RECOVERY: i64 = 2;
+
+/// Something a peer did wrong
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Offense {
+    /// The kernel called it a liar, for a block or transaction that doesn't validate
+    InvalidBlock,
+    /// A request without valid proof of work
+    BadPow,
+    /// A message that doesn't parse, or too many requests
+    ProtocolViolation,
+    /// No answer to a request in time
+    Timeout,
+}
+
+impl Offense {
+    pub fn penalty(self) -> i64 {
+        match self {
+            Offense::InvalidBlock => 100,
+            Offense::BadPow => 100,
+            Offense::ProtocolViolation => 25,
+            Offense::Timeout => 5,
+        }
+    }
+}
+
+impl fmt::Display for Offense {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Offense::InvalidBlock => "invalid block",
+            Offense::BadPow => "bad proof of work",
+            Offense::ProtocolViolation => "protocol violation",
+            Offense::Timeout => "timeouts",
+        })
+    }
+}
+
+/// Why and until when a peer is blocked
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockedPeer {
+    pub reason: String,
+    /// When the block ends, or `None` if it doesn't
+    pub until: Option<SystemTime>,
+}
+
+pub struct Reputation {
+    block_duration: Duration,
+    scores: HashMap<PeerId, i64>,
+    blocked: BTreeMap<PeerId, BlockedPeer>,
+}
+
+impl Default for Reputation {
+    fn default() -> Self {
+        Self::new(LibP2PConfig::default().peer_block_duration())
+    }
+}
+
+impl Reputation {
+    pub fn new(block_duration: Duration) -> Self {
+        Self {
+            block_duration,
+            scores: HashMap::new(),
+            blocked: BTreeMap::new(),
+        }
+    }
+
+    pub fn score(&self, peer: &PeerId) -> i64 {
+        self.scores.get(peer).copied().unwrap_or(0)
+    }
+
+    /// Count `offense` against `peer`. Returns whether that got it blocked, in which case the
+    /// caller should disconnect and block it in the swarm.
+    pub fn offend(&mut self, peer: PeerId, offense: Offense, now: SystemTime) -> bool {
+        if self.blocked.contains_key(&peer) {
+            return false;
+        }
+        let score = self.scores.entry(peer).or_insert(0);
+        *score -= offense.penalty();
+        if *score > BLOCK_THRESHOLD {
+            return false;
+        }
+        warn!("Blocking {peer} for {offense}, score {score}");
+        self.block(peer, Some(self.block_duration), offense.to_string(), now);
+        true
+    }
+
+    /// Block `peer` for `duration`, or for good if `None`
+    pub fn block(
+        &mut self,
+        peer: PeerId,
+        duration: Option<Duration>,
+        reason: String,
+        now: SystemTime,
+    ) {
+        self.scores.remove(&peer);
+        let until = duration.map(|duration| now + duration);
+        self.blocked.insert(peer, BlockedPeer { reason, until });
+    }
+
+    /// Returns whether `peer` was blocked
+    pub fn unblock(&mut self, peer: &PeerId) -> bool {
+        self.blocked.remove(peer).is_some()
+    }
+
+    pub fn blocked(&self) -> Vec<(PeerId, BlockedPeer)> {
+        self.blocked
+            .iter()
+            .map(|(peer, blocked)| (*peer, blocked.clone()))
+            .collect()
+    }
+
+    /// Let a minute's worth of scores recover, and return the peers whose blocks have ended
+    pub fn tick(&mut self, now: SystemTime) -> Vec<PeerId> {
+        self.scores.retain(|_, score| {
+            *score = (*score + RECOVERY).min(0);
+            *score < 0
+        });
+        let ended: Vec<PeerId> = self
+            .blocked
+            .iter()
+            .filter(|(_, blocked)| blocked.until.is_some_and(|until| until <= now))
+            .map(|(peer, _)| *peer)
+            .collect();
+        for peer in &ended {
+            self.blocked.remove(peer);
+        }
+        ended
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offend_and_recover() {
+        let peer = PeerId::random();
+        let now = SystemTime::now();
+        let mut reputation = Reputation::new(Duration::from_secs(60));
+
+        assert!(!reputation.offend(peer, Offense::Timeout, now));
+        assert_eq!(reputation.score(&peer), -5);
+        reputation.tick(now);
+        assert_eq!(reputation.score(&peer), -3);
+        reputation.tick(now);
+        reputation.tick(now);
+        assert_eq!(reputation.score(&peer), 0);
+
+        for _ in 0..3 {
+            assert!(!reputation.offend(peer, Offense::ProtocolViolation, now));
+        }
+        assert!(reputation.offend(peer, Offense::ProtocolViolation, now));
+        // already blocked
+        assert!(!reputation.offend(peer, Offense::InvalidBlock, now));
+        assert_eq!(reputation.blocked()[0].1.reason, "protocol violation");
+
+        assert!(reputation.tick(now + Duration::from_secs(30)).is_empty());
+        assert_eq!(reputation.tick(now + Duration::from_secs(60)), vec![peer]);
+        assert!(reputation.blocked().is_empty());
+    }
+
+    #[test]
+    fn test_operator_blocks() {
+        let peer = PeerId::random();
+        let now = SystemTime::now();
+        let mut reputation = Reputation::new(Duration::from_secs(60));
+
+        assert!(reputation.offend(peer, Offense::InvalidBlock, now));
+        reputation.block(peer, None, "operator".into(), now);
+        assert!(reputation.tick(now + Duration::from_secs(3600)).is_empty());
+        assert!(reputation.unblock(&peer));
+        assert!(!reputation.unblock(&peer));
+    }
+}
@@ -0,0 +1,104 @@
+//! Bootstrap peers from DNS seeds.
+//!
+//! A DNS seed is a hostname whose A and AAAA records are the addresses of nodes taking new
+//! peers, all listening for QUIC on the same UDP port, written like `seed.example.org:3006`.
+//! Anyone can run one, and the records behind it can change without a new release, so new nodes
+//! don't depend on the built-in backbone peers alone. The driver resolves its seeds when it
+//! starts and every [`RESOLVE_INTERVAL`] after, and dials what they resolve to whenever it has
+//! fewer than [`DIAL_BELOW`] peers, or has to fall back on bootstrap peers.
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use tracing::warn;
+
+/// How often to resolve the seeds again
+pub const RESOLVE_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Dial the seeds' addresses when connected to fewer peers than this
+pub const DIAL_BELOW: usize = 8;
+
+/// A hostname and the UDP port its nodes listen on
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DnsSeed {
+    pub host: String,
+    pub port: u16,
+}
+
+impl FromStr for DnsSeed {
+    type Err = String;
+
+    fn from_str(seed: &str) -> Result<Self, Self::Err> {
+        let (host, port) = seed
+            .rsplit_once(':')
+            .ok_or_else(|| format!("DNS seed {seed} needs a port, like {seed}:3006"))?;
+        let port = port
+            .parse()
+            .map_err(|_| format!("DNS seed {seed} has an invalid port"))?;
+        if host.is_empty() {
+            return Err(format!("DNS seed {seed} has no hostname"));
+        }
+        Ok(DnsSeed {
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+impl fmt::Display for DnsSeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+/// The QUIC addresses the seeds resolve to. Seeds that don't resolve are logged and skipped.
+pub async fn resolve(seeds: &[DnsSeed]) -> Vec<Multiaddr> {
+    let mut addrs = Vec::new();
+    for seed in seeds {
+        match tokio::net::lookup_host((seed.host.as_str(), seed.port)).await {
+            Ok(resolved) => addrs.extend(resolved.map(quic_multiaddr)),
+            Err(e) => warn!("Could not resolve DNS seed {seed}: {e}"),
+        }
+    }
+    addrs.sort();
+    addrs.dedup();
+    addrs
+}
+
+fn quic_multiaddr(addr: SocketAddr) -> Multiaddr {
+    Multiaddr::from(addr.ip())
+        .with(Protocol::Udp(addr.port()))
+        .with(Protocol::QuicV1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_seed() {
+        let seed: DnsSeed = "seed.example.org:3006".parse().expect("seed");
+        assert_eq!(seed.host, "seed.example.org");
+        assert_eq!(seed.port, 3006);
+        assert_eq!(seed.to_string(), "seed.example.org:3006");
+        assert!("seed.example.org".parse::<DnsSeed>().is_err());
+        assert!("seed.example.org:quic".parse::<DnsSeed>().is_err());
+        assert!(":3006".parse::<DnsSeed>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve() {
+        let seeds =
+            vec!["127.0.0.1:3006".parse().expect("seed"), "127.0.0.1:3006".parse().expect("seed")];
+        let addrs = resolve(&seeds).await;
+        assert_eq!(
+            addrs,
+            vec!["/ip4/127.0.0.1/udp/3006/quic-v1"
+                .parse::<Multiaddr>()
+                .expect("addr")]
+        );
+    }
+}
@@ -0,0 +1,715 @@
+//! Serving chain snapshots to peers that fast sync, and fetching them from peers.
+//!
+//! Only nodes that opt in with `NOCKCHAIN_LIBP2P_SERVE_SNAPSHOTS` (nockchain's
+//! `--serve-snapshots`) answer the snapshot protocol (see
+//! [`crate::config::LibP2PConfig::snapshot_protocol`]), so only they advertise it. The snapshot
+//! at a height is the same jam the JSON-RPC server's `GET /snapshot/{height}` serves, cut into
+//! [`CHUNK_SIZE`] chunks. Its [`Manifest`] lists the hash of each chunk, and [`Manifest::root`]
+//! is the root of a binary hash tree over them and the length, so two providers serving the same
+//! snapshot agree on the root.
+//!
+//! A [`Fetcher`] asks every provider it meets for the manifest at its height, picks the root the
+//! most providers offered, and downloads chunks from those providers a few at a time, checking each
+//! against the manifest as it arrives. A provider that sends a chunk that doesn't match is
+//! penalized and not asked again. Chunks are kept on disk under the root, so a fetch cut short by a
+//! restart picks up where it stopped. Providers that agree could still all be lying, so the whole
+//! snapshot must also have the blake3 hash it was fetched for, as the JSON-RPC fast sync checks;
+//! if it doesn't, the root is given up on and the next best one tried.
+//!
+//! A provider keeps the last [`MAX_CACHED`] snapshots it was asked for in memory, and answers at
+//! most `NOCKCHAIN_LIBP2P_SNAPSHOT_PEER_REQUESTS_PER_SEC` requests a second from one peer and
+//! `NOCKCHAIN_LIBP2P_SNAPSHOT_REQUESTS_PER_SEC` from all of them, past which it answers
+//! [`SnapshotResponse::Busy`].
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use libp2p::request_response::OutboundRequestId;
+use libp2p::{identify, PeerId, StreamProtocol};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use tracing::{info, warn};
+
+/// Bytes of a snapshot in each chunk but the last
+pub const CHUNK_SIZE: usize = 1 << 20;
+/// Snapshots a provider keeps in memory
+pub const MAX_CACHED: usize = 2;
+/// Biggest snapshot a fetcher takes a manifest for
+pub const MAX_SNAPSHOT_BYTES: u64 = 1 << 36;
+/// Chunk requests a fetcher has out at once, and to one provider
+const MAX_IN_FLIGHT: usize = 8;
+const PEER_IN_FLIGHT: usize = 2;
+/// How long a fetcher waits for more manifests after the first before picking a root
+const MANIFEST_WAIT: Duration = Duration::from_secs(10);
+/// How often, in chunks, a fetcher logs its progress
+const PROGRESS_CHUNKS: usize = 64;
+/// Peers a provider keeps rate limits for before forgetting idle ones
+const MAX_RATED_PEERS: usize = 1024;
+
+/// A blake3 hash in the hash tree
+pub type Hash = [u8; 32];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SnapshotRequest {
+    /// The manifest of the snapshot at a height
+    Manifest { height: u64 },
+    /// A chunk of the snapshot at a height with a root
+    Chunk { height: u64, root: Hash, index: u32 },
+}
+
+impl SnapshotRequest {
+    pub fn height(&self) -> u64 {
+        match self {
+            SnapshotRequest::Manifest { height } | SnapshotRequest::Chunk { height, .. } => *height,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SnapshotResponse {
+    Manifest(Manifest),
+    Chunk {
+        data: ByteBuf,
+    },
+    /// No snapshot at that height, or none with that root
+    Missing,
+    /// Over a rate limit, so ask again later
+    Busy,
+}
+
+/// What a snapshot is made of
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub height: u64,
+    /// Bytes in the whole jam
+    pub length: u64,
+    /// The hash of each chunk
+    pub chunks: Vec<Hash>,
+}
+
+fn leaf_hash(data: &[u8]) -> Hash {
+    blake3::Hasher::new()
+        .update(&[0])
+        .update(data)
+        .finalize()
+        .into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    blake3::Hasher::new()
+        .update(&[1])
+        .update(left)
+        .update(right)
+        .finalize()
+        .into()
+}
+
+impl Manifest {
+    /// The manifest of `jam`, the snapshot at `height`
+    pub fn new(height: u64, jam: &[u8]) -> Self {
+        Manifest {
+            height,
+            length: jam.len() as u64,
+            chunks: jam.chunks(CHUNK_SIZE).map(leaf_hash).collect(),
+        }
+    }
+
+    /// The root of the hash tree over the chunks, committing to the length too
+    pub fn root(&self) -> Hash {
+        let mut level = self.chunks.clone();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [odd] => *odd,
+                    _ => unreachable!("chunks(2) yields one or two"),
+                })
+                .collect();
+        }
+        let tree = level.first().copied().unwrap_or_else(|| leaf_hash(&[]));
+        blake3::Hasher::new()
+            .update(&[2])
+            .update(&self.length.to_le_bytes())
+            .update(&tree)
+            .finalize()
+            .into()
+    }
+
+    /// Whether there are as many chunks as the length needs, and not too many
+    pub fn is_consistent(&self) -> bool {
+        self.length > 0
+            && self.length <= MAX_SNAPSHOT_BYTES
+            && self.chunks.len() as u64 == self.length.div_ceil(CHUNK_SIZE as u64)
+    }
+
+    fn chunk_len(&self, index: u32) -> usize {
+        let start = index as u64 * CHUNK_SIZE as u64;
+        (self.length.saturating_sub(start)).min(CHUNK_SIZE as u64) as usize
+    }
+
+    /// Whether `data` is the chunk at `index`
+    pub fn verify(&self, index: u32, data: &[u8]) -> bool {
+        self.chunks.get(index as usize) == Some(&leaf_hash(data))
+            && data.len() == self.chunk_len(index)
+    }
+}
+
+fn hex(hash: &Hash) -> String {
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// A token bucket of requests, holding a second's worth
+#[derive(Debug)]
+pub struct Rate {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Rate {
+    pub fn new(rate: u32, now: Instant) -> Self {
+        let rate = rate.max(1) as f64;
+        Rate {
+            rate,
+            tokens: rate,
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+    }
+
+    pub fn take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    pub(crate) fn full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.rate
+    }
+}
+
+/// A snapshot a provider is serving
+#[derive(Debug)]
+pub struct Served {
+    pub manifest: Manifest,
+    pub root: Hash,
+    pub jam: Bytes,
+}
+
+impl Served {
+    pub fn new(height: u64, jam: Bytes) -> Self {
+        let manifest = Manifest::new(height, &jam);
+        let root = manifest.root();
+        Served {
+            manifest,
+            root,
+            jam,
+        }
+    }
+
+    /// The answer to `request`, which is for this snapshot's height
+    pub fn answer(&self, request: &SnapshotRequest) -> SnapshotResponse {
+        match request {
+            SnapshotRequest::Manifest { .. } => SnapshotResponse::Manifest(self.manifest.clone()),
+            SnapshotRequest::Chunk { root, index, .. } => {
+                let start = *index as usize * CHUNK_SIZE;
+                if *root != self.root || start >= self.jam.len() {
+                    return SnapshotResponse::Missing;
+                }
+                let end = (start + CHUNK_SIZE).min(self.jam.len());
+                SnapshotResponse::Chunk {
+                    data: ByteBuf::from(self.jam[start..end].to_vec()),
+                }
+            }
+        }
+    }
+}
+
+/// A provider's rate limits and the snapshots it has ready
+#[derive(Debug)]
+pub struct SnapshotServer {
+    peer_rate: u32,
+    global: Rate,
+    peers: HashMap<PeerId, Rate>,
+    cache: VecDeque<Arc<Served>>,
+}
+
+impl SnapshotServer {
+    /// Serve `peer_rate` requests a second to each peer and `global_rate` in all
+    pub fn new(peer_rate: u32, global_rate: u32) -> Self {
+        SnapshotServer {
+            peer_rate,
+            global: Rate::new(global_rate, Instant::now()),
+            peers: HashMap::new(),
+            cache: VecDeque::new(),
+        }
+    }
+
+    /// Whether a request from `peer` is within the limits, counting it if so
+    pub fn allow(&mut self, peer: PeerId, now: Instant) -> bool {
+        if self.peers.len() >= MAX_RATED_PEERS {
+            self.peers.retain(|_, rate| !rate.full(now));
+        }
+        let peer_rate = self.peer_rate;
+        let rate = self
+            .peers
+            .entry(peer)
+            .or_insert_with(|| Rate::new(peer_rate, now));
+        // A peer over its own limit doesn't use up everyone else's
+        rate.take(now) && self.global.take(now)
+    }
+
+    pub fn cached(&self, height: u64) -> Option<Arc<Served>> {
+        self.cache
+            .iter()
+            .find(|served| served.manifest.height == height)
+            .cloned()
+    }
+
+    /// Keep the snapshot `jam` at `height`, dropping the oldest past [`MAX_CACHED`]
+    pub fn cache(&mut self, height: u64, jam: Bytes) -> Arc<Served> {
+        let served = Arc::new(Served::new(height, jam));
+        if self.cache.len() >= MAX_CACHED {
+            self.cache.pop_front();
+        }
+        self.cache.push_back(served.clone());
+        served
+    }
+}
+
+/// The snapshot to fetch, and where to keep it while fetching
+#[derive(Clone, Debug)]
+pub struct SnapshotFetch {
+    pub height: u64,
+    /// Hex blake3 hash the whole snapshot must have
+    pub hash: String,
+    pub dir: PathBuf,
+}
+
+/// What the libp2p driver does with snapshots
+#[derive(Clone, Debug, Default)]
+pub struct Snapshots {
+    /// Serve snapshots to peers
+    pub serve: bool,
+    /// Fetch a snapshot from peers before syncing
+    pub fetch: Option<SnapshotFetch>,
+}
+
+/// A root being downloaded
+#[derive(Debug)]
+struct Download {
+    root: Hash,
+    manifest: Manifest,
+    dir: PathBuf,
+    have: Vec<bool>,
+    missing: usize,
+    in_flight: HashMap<u32, PeerId>,
+    /// Providers of this root not to ask again
+    shunned: HashSet<PeerId>,
+}
+
+impl Download {
+    /// Start downloading `manifest` into `dir`, keeping the chunks already there
+    fn start(root: Hash, manifest: Manifest, dir: PathBuf) -> io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        let have: Vec<bool> = (0..manifest.chunks.len() as u32)
+            .map(|index| {
+                std::fs::read(dir.join(index.to_string()))
+                    .is_ok_and(|data| manifest.verify(index, &data))
+            })
+            .collect();
+        let missing = have.iter().filter(|have| !**have).count();
+        Ok(Download {
+            root,
+            manifest,
+            dir,
+            have,
+            missing,
+            in_flight: HashMap::new(),
+            shunned: HashSet::new(),
+        })
+    }
+
+    fn done(&self) -> usize {
+        self.have.len() - self.missing
+    }
+
+    fn assemble(&self) -> io::Result<Vec<u8>> {
+        let mut jam = Vec::with_capacity(self.manifest.length as usize);
+        for index in 0..self.have.len() {
+            jam.extend(std::fs::read(self.dir.join(index.to_string()))?);
+        }
+        Ok(jam)
+    }
+}
+
+/// Fetches a snapshot from the providers among the connected peers
+#[derive(Debug)]
+pub struct Fetcher {
+    fetch: SnapshotFetch,
+    protocol: StreamProtocol,
+    /// Connected peers that serve snapshots
+    providers: BTreeSet<PeerId>,
+    /// Providers asked for the manifest
+    asked: HashSet<PeerId>,
+    /// Manifests offered, by root, and who offered each
+    offers: HashMap<Hash, (Manifest, BTreeSet<PeerId>)>,
+    first_offer: Option<Instant>,
+    bad_roots: HashSet<Hash>,
+    download: Option<Download>,
+    pending: HashMap<OutboundRequestId, (PeerId, SnapshotRequest)>,
+    /// The whole snapshot has been handed over
+    assembled: bool,
+}
+
+impl Fetcher {
+    pub fn new(fetch: SnapshotFetch, protocol: StreamProtocol) -> Self {
+        Fetcher {
+            fetch,
+            protocol,
+            providers: BTreeSet::new(),
+            asked: HashSet::new(),
+            offers: HashMap::new(),
+            first_offer: None,
+            bad_roots: HashSet::new(),
+            download: None,
+            pending: HashMap::new(),
+            assembled: false,
+        }
+    }
+
+    pub fn height(&self) -> u64 {
+        self.fetch.height
+    }
+
+    /// Note whether a peer that identified itself serves snapshots
+    pub fn identified(&mut self, peer: PeerId, info: &identify::Info) {
+        if info.protocols.contains(&self.protocol) {
+            self.providers.insert(peer);
+        } else {
+            self.providers.remove(&peer);
+        }
+    }
+
+    pub fn lost(&mut self, peer: &PeerId) {
+        self.providers.remove(peer);
+        self.asked.remove(peer);
+    }
+
+    /// The requests to send now
+    pub fn requests(&mut self, now: Instant) -> Vec<(PeerId, SnapshotRequest)> {
+        if self.assembled {
+            return Vec::new();
+        }
+        let height = self.fetch.height;
+        let mut requests: Vec<(PeerId, SnapshotRequest)> = self
+            .providers
+            .iter()
+            .filter(|peer| !self.asked.contains(peer))
+            .map(|peer| (*peer, SnapshotRequest::Manifest { height }))
+            .collect();
+        self.asked.extend(requests.iter().map(|(peer, _)| *peer));
+
+        if self.download.is_none() {
+            let answered = self.offers.values().map(|(_, by)| by.len()).sum::<usize>();
+            let waited = self
+                .first_offer
+                .is_some_and(|first| now.saturating_duration_since(first) >= MANIFEST_WAIT);
+            if waited || (answered > 0 && answered >= self.providers.len()) {
+                self.choose();
+            }
+        }
+        let Some(download) = self.download.as_mut() else {
+            return requests;
+        };
+        let Some((_, offered_by)) = self.offers.get(&download.root) else {
+            return requests;
+        };
+        let mut load: HashMap<PeerId, usize> = HashMap::new();
+        for peer in download.in_flight.values() {
+            *load.entry(*peer).or_default() += 1;
+        }
+        let mut sources = offered_by
+            .iter()
+            .filter(|peer| self.providers.contains(peer) && !download.shunned.contains(peer))
+            .cycle()
+            .take(offered_by.len() * PEER_IN_FLIGHT);
+        for index in 0..download.have.len() as u32 {
+            if download.in_flight.len() >= MAX_IN_FLIGHT {
+                break;
+            }
+            if download.have[index as usize] || download.in_flight.contains_key(&index) {
+                continue;
+            }
+            let Some(peer) =
+                sources.find(|peer| load.get(*peer).copied().unwrap_or(0) < PEER_IN_FLIGHT)
+            else {
+                break;
+            };
+            *load.entry(*peer).or_default() += 1;
+            download.in_flight.insert(index, *peer);
+            requests.push((
+                *peer,
+                SnapshotRequest::Chunk {
+                    height,
+                    root: download.root,
+                    index,
+                },
+            ));
+        }
+        requests
+    }
+
+    /// Start downloading the root the most providers offered
+    fn choose(&mut self) {
+        let Some((root, manifest)) = self
+            .offers
+            .iter()
+            .filter(|(root, _)| !self.bad_roots.contains(*root))
+            .max_by_key(|(_, (_, by))| by.len())
+            .map(|(root, (manifest, _))| (*root, manifest.clone()))
+        else {
+            return;
+        };
+        let dir = self.fetch.dir.join(hex(&root));
+        match Download::start(root, manifest, dir) {
+            Ok(download) => {
+                info!(
+                    "Fetching the snapshot at height {} with root {} from {} peers, {} of {} chunks already here",
+                    self.fetch.height,
+                    hex(&root),
+                    self.offers[&root].1.len(),
+                    download.done(),
+                    download.have.len()
+                );
+                self.download = Some(download);
+            }
+            Err(e) => warn!(
+                "Could not keep snapshot chunks in {:?}: {e}",
+                self.fetch.dir
+            ),
+        }
+    }
+
+    pub fn sent(&mut self, id: OutboundRequestId, peer: PeerId, request: SnapshotRequest) {
+        self.pending.insert(id, (peer, request));
+    }
+
+    /// A request got no answer
+    pub fn failed(&mut self, id: OutboundRequestId) {
+        let Some((peer, request)) = self.pending.remove(&id) else {
+            return;
+        };
+        match request {
+            SnapshotRequest::Manifest { .. } => {
+                self.asked.remove(&peer);
+            }
+            SnapshotRequest::Chunk { index, .. } => {
+                if let Some(download) = self.download.as_mut() {
+                    download.in_flight.remove(&index);
+                }
+            }
+        }
+    }
+
+    /// Take the answer to a request, returning the peer that sent it if it lied
+    pub fn response(
+        &mut self,
+        id: OutboundRequestId,
+        response: SnapshotResponse,
+        now: Instant,
+    ) -> Option<PeerId> {
+        let (peer, request) = self.pending.remove(&id)?;
+        match (request, response) {
+            (SnapshotRequest::Manifest { height }, SnapshotResponse::Manifest(manifest)) => {
+                if manifest.height != height || !manifest.is_consistent() {
+                    return Some(peer);
+                }
+                let root = manifest.root();
+                if !self.bad_roots.contains(&root) {
+                    self.first_offer.get_or_insert(now);
+                    self.offers
+                        .entry(root)
+                        .or_insert_with(|| (manifest, BTreeSet::new()))
+                        .1
+                        .insert(peer);
+                }
+                None
+            }
+            (SnapshotRequest::Manifest { .. }, SnapshotResponse::Busy) => {
+                self.asked.remove(&peer);
+                None
+            }
+            (SnapshotRequest::Manifest { .. }, SnapshotResponse::Missing) => None,
+            (SnapshotRequest::Chunk { root, index, .. }, response) => {
+                let download = self.download.as_mut().filter(|d| d.root == root)?;
+                download.in_flight.remove(&index);
+                match response {
+                    SnapshotResponse::Chunk { data } => {
+                        if !download.manifest.verify(index, &data) {
+                            download.shunned.insert(peer);
+                            return Some(peer);
+                        }
+                        if download.have[index as usize] {
+                            return None;
+                        }
+                        if let Err(e) = std::fs::write(download.dir.join(index.to_string()), &data)
+                        {
+                            warn!("Could not keep snapshot chunk {index}: {e}");
+                            return None;
+                        }
+                        download.have[index as usize] = true;
+                        download.missing -= 1;
+                        if download.done() % PROGRESS_CHUNKS == 0 {
+                            info!(
+                                "Fetched {} of {} snapshot chunks",
+                                download.done(),
+                                download.have.len()
+                            );
+                        }
+                        None
+                    }
+                    SnapshotResponse::Missing => {
+                        download.shunned.insert(peer);
+                        None
+                    }
+                    SnapshotResponse::Busy => None,
+                    SnapshotResponse::Manifest(_) => Some(peer),
+                }
+            }
+            (SnapshotRequest::Manifest { .. }, SnapshotResponse::Chunk { .. }) => Some(peer),
+        }
+    }
+
+    /// The whole snapshot, once every chunk is in and it has the hash asked for. A snapshot that
+    /// doesn't is thrown away and its root given up on.
+    pub fn assembled(&mut self) -> Option<io::Result<Bytes>> {
+        let download = self.download.as_ref().filter(|d| d.missing == 0)?;
+        if self.assembled {
+            return None;
+        }
+        let jam = match download.assemble() {
+            Ok(jam) => jam,
+            Err(e) => return Some(Err(e)),
+        };
+        let hash = blake3::hash(&jam).to_hex();
+        if hash.eq_ignore_ascii_case(&self.fetch.hash) {
+            self.assembled = true;
+            return Some(Ok(Bytes::from(jam)));
+        }
+        let root = download.root;
+        warn!(
+            "The snapshot with root {} hashes to {hash}, not {}, trying another",
+            hex(&root),
+            self.fetch.hash
+        );
+        if let Err(e) = std::fs::remove_dir_all(&download.dir) {
+            warn!("Could not remove {:?}: {e}", download.dir);
+        }
+        self.bad_roots.insert(root);
+        self.offers.remove(&root);
+        self.download = None;
+        None
+    }
+
+    /// The snapshot is loaded, so its chunks can go
+    pub fn finish(self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.fetch.dir) {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!("Could not remove {:?}: {e}", self.fetch.dir);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jam(len: usize) -> Bytes {
+        Bytes::from((0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_manifest() {
+        let jam = jam(2 * CHUNK_SIZE + 10);
+        let manifest = Manifest::new(7, &jam);
+        assert_eq!(manifest.chunks.len(), 3);
+        assert!(manifest.is_consistent());
+        assert!(manifest.verify(2, &jam[2 * CHUNK_SIZE..]));
+        assert!(!manifest.verify(1, &jam[2 * CHUNK_SIZE..]));
+        assert!(!manifest.verify(3, &[]));
+
+        let mut other = jam.to_vec();
+        other[5] ^= 1;
+        assert_ne!(Manifest::new(7, &other).root(), manifest.root());
+        let mut short = manifest.clone();
+        short.length -= 1;
+        assert_ne!(short.root(), manifest.root());
+        short.chunks.pop();
+        assert!(!short.is_consistent());
+    }
+
+    #[test]
+    fn test_server_limits() {
+        let mut server = SnapshotServer::new(2, 3);
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let now = Instant::now();
+        assert!(server.allow(a, now));
+        assert!(server.allow(a, now));
+        assert!(!server.allow(a, now));
+        assert!(server.allow(b, now));
+        assert!(!server.allow(b, now));
+        assert!(server.allow(a, now + Duration::from_secs(1)));
+
+        for height in 0..3 {
+            server.cache(height, jam(10));
+        }
+        assert!(server.cached(0).is_none());
+        let served = server.cached(2).expect("cached");
+        let chunk = SnapshotRequest::Chunk {
+            height: 2,
+            root: served.root,
+            index: 0,
+        };
+        assert!(
+            matches!(served.answer(&chunk), SnapshotResponse::Chunk { data } if data.len() == 10)
+        );
+        let wrong = SnapshotRequest::Chunk {
+            height: 2,
+            root: [0; 32],
+            index: 0,
+        };
+        assert!(matches!(served.answer(&wrong), SnapshotResponse::Missing));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_download_resumes() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let jam = jam(CHUNK_SIZE + 1);
+        let manifest = Manifest::new(1, &jam);
+        let root = manifest.root();
+        let chunk_dir = dir.path().join(hex(&root));
+        std::fs::create_dir_all(&chunk_dir).expect("mkdir");
+        std::fs::write(chunk_dir.join("0"), &jam[..CHUNK_SIZE]).expect("write");
+        std::fs::write(chunk_dir.join("1"), b"corrupt").expect("write");
+
+        let download = Download::start(root, manifest, chunk_dir).expect("start");
+        assert_eq!(download.have, vec![true, false]);
+        assert_eq!(download.missing, 1);
+    }
+}
@@ -0,0 +1,364 @@
+//! Downloading blocks from several peers at once.
+//!
+//! The kernel asks for one block at a time: the one after its heaviest, by height. Sending each
+//! of those to every peer downloads every block once per peer and syncs no faster than one round
+//! trip per block. [`BlockSync`] instead keeps a window of heights in flight, each asked of one
+//! peer, as long as some peer has shown us a block at least that high. Peers that answer quickly
+//! and have the fewest requests outstanding are asked first.
+//!
+//! Blocks arrive in any order, so they wait in a reorder buffer and go to the kernel lowest
+//! first, each after its parent, and the kernel validates them as usual. A request that isn't
+//! answered within the timeout, or whose block the kernel refuses, is a strike against the peer
+//! that had it, and goes to another peer. A peer with [`MAX_STRIKES`] strikes gets no block
+//! requests for [`BENCH_TIME`]. Peers that send invalid blocks are still banned outright by the
+//! kernel's `%liar-peer` effect.
+//!
+//! A height beyond anything we've heard of is still asked of every peer, as is one no peer can be
+//! asked alone because they're all benched, since there's no telling who has it.
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+use nockapp::noun::slab::NounSlab;
+use nockapp::utils::make_tas;
+use nockapp::AtomExt;
+use nockvm::noun::{Noun, Slots, D, T};
+use nockvm_macros::tas;
+use tracing::{debug, warn};
+
+use crate::config::LibP2PConfig;
+
+/// Strikes a peer can take before it gets no block requests for a while
+pub const MAX_STRIKES: u32 = 3;
+
+/// How long a peer with too many strikes goes without block requests
+pub const BENCH_TIME: Duration = Duration::from_secs(60);
+
+/// What became of a block that arrived
+#[derive(Debug, PartialEq, Eq)]
+pub enum Arrival {
+    /// Not part of the window, so it goes straight to the kernel as before
+    PassThrough,
+    /// We already have this height buffered from another peer
+    Duplicate,
+    /// Buffered. If `deliver`, the caller delivers the buffer with [`BlockSync::take_ready`],
+    /// since nobody else is.
+    Buffered { deliver: bool },
+}
+
+/// What to do after [`BlockSync::tick`]
+#[derive(Debug, Default)]
+pub struct Tick {
+    /// Heights to ask peers for
+    pub requests: Vec<(PeerId, u64)>,
+    /// How many requests it gave up waiting for
+    pub timed_out: usize,
+    /// Whether the caller should deliver the buffer, as after [`Arrival::Buffered`]
+    pub deliver: bool,
+}
+
+#[derive(Debug, Default)]
+struct PeerRecord {
+    strikes: u32,
+    benched_until: Option<Instant>,
+    /// Moving average of how long the peer takes to send a block
+    latency: Option<Duration>,
+}
+
+pub struct BlockSync {
+    window: u64,
+    timeout: Duration,
+    /// The height the kernel wants next
+    next: u64,
+    /// The highest block any peer has shown us
+    best_known: u64,
+    in_flight: BTreeMap<u64, (PeerId, Instant)>,
+    ready: BTreeMap<u64, (PeerId, NounSlab)>,
+    /// Whether some task is delivering the buffer to the kernel
+    delivering: bool,
+    peers: HashMap<PeerId, PeerRecord>,
+}
+
+impl Default for BlockSync {
+    fn default() -> Self {
+        let config = LibP2PConfig::default();
+        Self::new(config.block_sync_window, config.block_sync_timeout())
+    }
+}
+
+impl BlockSync {
+    pub fn new(window: u64, timeout: Duration) -> Self {
+        Self {
+            window: window.max(1),
+            timeout,
+            next: 0,
+            best_known: 0,
+            in_flight: BTreeMap::new(),
+            ready: BTreeMap::new(),
+            delivering: false,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// The kernel wants the block at `height`. Returns the requests to send.
+    pub fn request(&mut self, height: u64, peers: &[PeerId], now: Instant) -> Vec<(PeerId, u64)> {
+        self.next = height;
+        self.in_flight.retain(|h, _| *h >= height);
+        self.ready.retain(|h, _| *h >= height);
+        let mut requests = self.fill(peers, now);
+        if !self.in_flight.contains_key(&height) && !self.ready.contains_key(&height) {
+            requests.extend(peers.iter().map(|peer| (*peer, height)));
+        }
+        requests
+    }
+
+    /// A peer showed us a block at `height`, so others likely have the ones below it
+    pub fn heard_of(&mut self, height: u64) {
+        self.best_known = self.best_known.max(height);
+    }
+
+    /// `peer` sent the block at `height`
+    pub fn arrived(&mut self, peer: PeerId, height: u64, block: NounSlab, now: Instant) -> Arrival {
+        self.heard_of(height);
+        if let Some((asked, sent)) = self.in_flight.get(&height).copied() {
+            if asked == peer {
+                self.in_flight.remove(&height);
+                let record = self.peers.entry(peer).or_default();
+                let took = now.saturating_duration_since(sent);
+                record.latency = Some(match record.latency {
+                    Some(latency) => (latency * 3 + took) / 4,
+                    None => took,
+                });
+                record.strikes = record.strikes.saturating_sub(1);
+            }
+        }
+        if height < self.next || height - self.next >= self.window {
+            return Arrival::PassThrough;
+        }
+        if self.ready.contains_key(&height) {
+            return Arrival::Duplicate;
+        }
+        self.ready.insert(height, (peer, block));
+        Arrival::Buffered {
+            deliver: self.start_delivering(),
+        }
+    }
+
+    /// The next block to deliver to the kernel, if it's here. Once this returns `None`, delivery
+    /// is over until [`Arrival::Buffered`] or [`Tick`] says otherwise.
+    pub fn take_ready(&mut self) -> Option<(u64, PeerId, NounSlab)> {
+        match self.ready.remove(&self.next) {
+            Some((peer, block)) => Some((self.next, peer, block)),
+            None => {
+                self.delivering = false;
+                None
+            }
+        }
+    }
+
+    /// The kernel accepted the block at `height`
+    pub fn delivered(&mut self, height: u64) {
+        self.next = self.next.max(height + 1);
+    }
+
+    /// The kernel refused the block `peer` sent for `height`, which will be asked of another
+    pub fn refused(&mut self, peer: PeerId, height: u64, now: Instant) {
+        debug!("Kernel refused block {height} from {peer}");
+        self.strike(peer, now);
+    }
+
+    /// Delivery stopped short of the end of the buffer
+    pub fn interrupted(&mut self) {
+        self.delivering = false;
+    }
+
+    /// Give up on requests that took too long, and ask for what's missing from the window
+    pub fn tick(&mut self, peers: &[PeerId], now: Instant) -> Tick {
+        self.peers.retain(|peer, record| {
+            peers.contains(peer) || record.benched_until.is_some_and(|until| now < until)
+        });
+        let expired: Vec<(u64, PeerId)> = self
+            .in_flight
+            .iter()
+            .filter(|(_, (_, sent))| now.saturating_duration_since(*sent) >= self.timeout)
+            .map(|(height, (peer, _))| (*height, *peer))
+            .collect();
+        let mut tick = Tick {
+            timed_out: expired.len(),
+            ..Tick::default()
+        };
+        for (height, slow) in expired {
+            debug!("{slow} took too long to send block {height}");
+            self.in_flight.remove(&height);
+            self.strike(slow, now);
+            let peer = self
+                .pick(peers, now, Some(slow))
+                .or_else(|| self.pick(peers, now, None));
+            if let Some(peer) = peer {
+                self.in_flight.insert(height, (peer, now));
+                tick.requests.push((peer, height));
+            }
+        }
+        tick.requests.extend(self.fill(peers, now));
+        tick.deliver = self.start_delivering();
+        tick
+    }
+
+    /// Ask for every height in the window that isn't in flight or buffered
+    fn fill(&mut self, peers: &[PeerId], now: Instant) -> Vec<(PeerId, u64)> {
+        let last = self
+            .best_known
+            .min(self.next.saturating_add(self.window - 1));
+        let mut requests = Vec::new();
+        for height in self.next..=last {
+            if self.in_flight.contains_key(&height) || self.ready.contains_key(&height) {
+                continue;
+            }
+            let Some(peer) = self.pick(peers, now, None) else {
+                break;
+            };
+            self.in_flight.insert(height, (peer, now));
+            requests.push((peer, height));
+        }
+        requests
+    }
+
+    /// The peer expected to send a block soonest: its latency times the requests it already has
+    fn pick(&self, peers: &[PeerId], now: Instant, except: Option<PeerId>) -> Option<PeerId> {
+        peers
+            .iter()
+            .copied()
+            .filter(|peer| Some(*peer) != except && !self.benched(peer, now))
+            .min_by_key(|peer| {
+                let load = self
+                    .in_flight
+                    .values()
+                    .filter(|(asked, _)| asked == peer)
+                    .count() as u32;
+                let latency = self
+                    .peers
+                    .get(peer)
+                    .and_then(|record| record.latency)
+                    .unwrap_or(self.timeout / 2);
+                latency * (load + 1)
+            })
+    }
+
+    fn benched(&self, peer: &PeerId, now: Instant) -> bool {
+        self.peers
+            .get(peer)
+            .and_then(|record| record.benched_until)
+            .is_some_and(|until| now < until)
+    }
+
+    fn strike(&mut self, peer: PeerId, now: Instant) {
+        let record = self.peers.entry(peer).or_default();
+        record.strikes += 1;
+        if record.strikes >= MAX_STRIKES {
+            warn!(
+                "Not asking {peer} for blocks for {}s after {} strikes",
+                BENCH_TIME.as_secs(),
+                record.strikes
+            );
+            record.strikes = 0;
+            record.benched_until = Some(now + BENCH_TIME);
+        }
+    }
+
+    fn start_delivering(&mut self) -> bool {
+        let start = !self.delivering && self.ready.contains_key(&self.next);
+        self.delivering |= start;
+        start
+    }
+}
+
+/// The height of a `page:dt`
+pub fn page_height(page: Noun) -> Option<u64> {
+    page.slot(2046).ok()?.as_atom().ok()?.as_u64().ok()
+}
+
+/// The effect `[%request %block %by-height height]`, as the kernel would emit it
+pub fn by_height_request(height: u64) -> NounSlab {
+    let mut slab = NounSlab::new();
+    let by_height = make_tas(&mut slab, "by-height").as_noun();
+    let request = T(
+        &mut slab,
+        &[D(tas!(b"request")), D(tas!(b"block")), by_height, D(height)],
+    );
+    slab.set_root(request);
+    slab
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block() -> NounSlab {
+        let mut slab = NounSlab::new();
+        slab.set_root(D(0));
+        slab
+    }
+
+    fn heights(requests: &[(PeerId, u64)]) -> Vec<u64> {
+        requests.iter().map(|(_, height)| *height).collect()
+    }
+
+    #[test]
+    fn test_spreads_and_reorders() {
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let now = Instant::now();
+        let mut sync = BlockSync::new(4, Duration::from_secs(10));
+
+        // nothing heard of yet, so ask everyone
+        assert_eq!(sync.request(1, &[a, b], now), vec![(a, 1), (b, 1)]);
+        sync.heard_of(100);
+        let requests = sync.request(1, &[a, b], now);
+        assert_eq!(heights(&requests), vec![1, 2, 3, 4]);
+        assert_eq!(requests.iter().filter(|(peer, _)| *peer == a).count(), 2);
+
+        let peer_of = |height| requests.iter().find(|(_, h)| *h == height).unwrap().0;
+        assert_eq!(
+            sync.arrived(peer_of(2), 2, block(), now),
+            Arrival::Buffered { deliver: false }
+        );
+        assert_eq!(
+            sync.arrived(peer_of(2), 2, block(), now),
+            Arrival::Duplicate
+        );
+        assert_eq!(
+            sync.arrived(peer_of(1), 1, block(), now),
+            Arrival::Buffered { deliver: true }
+        );
+        let (height, ..) = sync.take_ready().expect("block 1");
+        assert_eq!(height, 1);
+        sync.delivered(1);
+        let (height, ..) = sync.take_ready().expect("block 2");
+        sync.delivered(height);
+        assert!(sync.take_ready().is_none());
+        // beyond the window
+        assert_eq!(sync.arrived(a, 50, block(), now), Arrival::PassThrough);
+    }
+
+    #[test]
+    fn test_timeouts_and_strikes() {
+        let (slow, fast) = (PeerId::random(), PeerId::random());
+        let now = Instant::now();
+        let mut sync = BlockSync::new(1, Duration::from_secs(10));
+        sync.heard_of(100);
+
+        for round in 1..=MAX_STRIKES {
+            let later = now + Duration::from_secs(20 * round as u64);
+            sync.next = round as u64;
+            sync.in_flight.clear();
+            sync.in_flight.insert(round as u64, (slow, later));
+            let tick = sync.tick(&[slow, fast], later + Duration::from_secs(11));
+            assert_eq!(tick.timed_out, 1);
+            assert_eq!(tick.requests, vec![(fast, round as u64)]);
+        }
+        // the slow peer is benched, so everything goes to the fast one
+        let later = now + Duration::from_secs(100);
+        assert!(sync.benched(&slow, later));
+        assert_eq!(sync.request(10, &[slow, fast], later), vec![(fast, 10)]);
+        assert!(!sync.benched(&slow, later + BENCH_TIME));
+    }
+}
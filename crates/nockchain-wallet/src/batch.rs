@@ -0,0 +1,165 @@
+//! Payment files for `send-batch`
+//!
+//! A payment file lists the recipients of one transaction. A `.json` file is a list of
+//! `{"recipient": ..., "amount": ..., "memo": ...}` objects, with `memo` optional. Anything else
+//! is CSV, one `recipient,amount[,memo]` row per payment, with fields that hold commas, like a
+//! shared lock `"[2 pk1,pk2]"`, in double quotes. A first row whose amount isn't a number is a
+//! header, and is skipped.
+//!
+//! A recipient is anything `simple-spend --recipients` takes: a pubkey, a contact's name or an
+//! m-of-n lock. Transactions have no room for memos, so the wallet keeps them, and labels the
+//! transaction with them in the history once it's sent.
+
+use std::path::Path;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum BatchError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Line {0}: expected recipient,amount[,memo]")]
+    Row(usize),
+    #[error("Line {0}: amount {1} isn't a number of nicks")]
+    Amount(usize, String),
+    #[error("Line {0}: unterminated quote")]
+    Quote(usize),
+    #[error("No payments in the file")]
+    Empty,
+}
+
+/// One output of a batch
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Payment {
+    pub recipient: String,
+    pub amount: u64,
+    #[serde(default)]
+    pub memo: Option<String>,
+}
+
+/// Reads the payments in `path`, as JSON if it ends in `.json` and CSV otherwise
+pub fn read_payments(path: &Path) -> Result<Vec<Payment>, BatchError> {
+    let contents = std::fs::read_to_string(path)?;
+    let payments = if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str(&contents)?
+    } else {
+        parse_csv(&contents)?
+    };
+    if payments.is_empty() {
+        return Err(BatchError::Empty);
+    }
+    Ok(payments)
+}
+
+fn parse_csv(contents: &str) -> Result<Vec<Payment>, BatchError> {
+    let mut payments = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let number = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields = split_row(line).ok_or(BatchError::Quote(number))?;
+        let (recipient, amount, memo) = match fields.as_slice() {
+            [recipient, amount] => (recipient, amount, None),
+            [recipient, amount, memo] => (recipient, amount, Some(memo)),
+            _ => return Err(BatchError::Row(number)),
+        };
+        let Ok(amount) = amount.trim().parse() else {
+            if i == 0 {
+                continue;
+            }
+            return Err(BatchError::Amount(number, amount.clone()));
+        };
+        payments.push(Payment {
+            recipient: recipient.trim().to_string(),
+            amount,
+            memo: memo.filter(|memo| !memo.is_empty()).cloned(),
+        });
+    }
+    Ok(payments)
+}
+
+/// The fields of a CSV row, where `""` in a quoted field is a quote
+fn split_row(line: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    fields.push(field);
+    Some(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let payments = parse_csv(
+            "recipient,amount,memo\nalice,5,March\n\"[2 pk1,pk2]\",7\nbob,1,\"a \"\"b\"\"\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            payments,
+            vec![
+                Payment {
+                    recipient: "alice".into(),
+                    amount: 5,
+                    memo: Some("March".into())
+                },
+                Payment {
+                    recipient: "[2 pk1,pk2]".into(),
+                    amount: 7,
+                    memo: None
+                },
+                Payment {
+                    recipient: "bob".into(),
+                    amount: 1,
+                    memo: Some("a \"b\"".into())
+                },
+            ]
+        );
+        // A first row without an amount is a header
+        assert_eq!(parse_csv("alice,five").unwrap(), vec![]);
+        assert!(matches!(
+            parse_csv("alice,5\nbob,five"),
+            Err(BatchError::Amount(2, _))
+        ));
+        assert!(matches!(parse_csv("alice"), Err(BatchError::Row(1))));
+        assert!(matches!(parse_csv("\"alice,5"), Err(BatchError::Quote(1))));
+    }
+
+    #[test]
+    fn test_read_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("payments.json");
+        std::fs::write(
+            &path,
+            r#"[{"recipient": "alice", "amount": 5, "memo": "March"}, {"recipient": "bob", "amount": 2}]"#,
+        )
+        .unwrap();
+        let payments = read_payments(&path).unwrap();
+        assert_eq!(payments.len(), 2);
+        assert_eq!(payments[1].memo, None);
+
+        std::fs::write(&path, "[]").unwrap();
+        assert!(matches!(read_payments(&path), Err(BatchError::Empty)));
+    }
+}
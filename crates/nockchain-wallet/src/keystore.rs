@@ -0,0 +1,308 @@
+//! Passphrases for the wallet's encrypted keystore
+//!
+//! After `encrypt-keys`, the kernel keeps private keys and seed phrases sealed with AES-256-SIV
+//! under a key it derives from the passphrase with argon2id. A command that needs them is held
+//! back with a `[%passphrase pid]` effect, which [`keystore_driver`] answers by poking
+//! `[%unlock pid passphrase]`. The passphrase comes from `--keystore-passphrase` (or
+//! `NOCKCHAIN_KEYSTORE_PASSPHRASE`), then a running `keystore-agent`, then a prompt. The unlock
+//! wire is secret, so the passphrase is kept out of the event log and crash dumps.
+//!
+//! The agent relies on a socket file only its owner can open, which named pipes have no
+//! equivalent of, so it is Unix only. The socket is bound inside a directory only its owner can
+//! enter, so nobody else can connect in the moment before its own permissions are set.
+
+use std::io::{self, IsTerminal, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use nockapp::driver::{make_driver, IODriverFn, PokeResult};
+use nockapp::noun::slab::NounSlab;
+use nockapp::utils::make_tas;
+use nockapp::wire::{Wire, WireRepr};
+use nockapp::NounExt;
+use nockvm::noun::{Noun, D, T};
+use thiserror::Error;
+#[cfg(unix)]
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{error, info};
+
+/// The directory holding the agent's socket, in the wallet's data directory
+pub const AGENT_DIR: &str = "keystore-agent";
+
+/// The agent's socket, in [`AGENT_DIR`]
+pub const AGENT_SOCKET: &str = "keystore-agent.sock";
+
+#[derive(Debug, Error)]
+pub enum KeystoreError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("No passphrase given, and no terminal to ask for one on")]
+    NoTerminal,
+    #[error("Interrupted")]
+    Interrupted,
+    #[error("Passphrases don't match")]
+    Mismatch,
+    #[error("Passphrase can't be empty")]
+    Empty,
+    #[cfg_attr(unix, allow(dead_code))]
+    #[error("The keystore agent is only supported on Unix")]
+    NoAgent,
+}
+
+pub enum KeystoreWire {
+    Unlock,
+}
+
+impl Wire for KeystoreWire {
+    const VERSION: u64 = 1;
+    const SOURCE: &'static str = "keystore";
+
+    fn to_wire(&self) -> WireRepr {
+        WireRepr::new(
+            KeystoreWire::SOURCE,
+            KeystoreWire::VERSION,
+            vec!["unlock".into()],
+        )
+        .secret()
+    }
+}
+
+/// Where the keystore passphrase comes from
+#[derive(Debug, Clone)]
+pub struct Passphrase {
+    /// Given on the command line or in the environment
+    pub given: Option<String>,
+    /// The agent's socket
+    pub agent: PathBuf,
+}
+
+impl Passphrase {
+    /// The passphrase as given, from the agent, or from a prompt, in that order
+    pub async fn get(&self) -> Result<String, KeystoreError> {
+        if let Some(given) = &self.given {
+            return Ok(given.clone());
+        }
+        if let Some(passphrase) = from_agent(&self.agent).await {
+            return Ok(passphrase);
+        }
+        tokio::task::spawn_blocking(|| prompt("Keystore passphrase: "))
+            .await
+            .map_err(|e| io::Error::other(e.to_string()))?
+    }
+
+    /// A new passphrase, as given or typed twice
+    pub fn new_passphrase(given: Option<String>) -> Result<String, KeystoreError> {
+        let passphrase = match given {
+            Some(given) => given,
+            None => {
+                let first = prompt("New keystore passphrase: ")?;
+                if prompt("Repeat it: ")? != first {
+                    return Err(KeystoreError::Mismatch);
+                }
+                first
+            }
+        };
+        if passphrase.is_empty() {
+            return Err(KeystoreError::Empty);
+        }
+        Ok(passphrase)
+    }
+}
+
+/// Reads a line from the terminal without echoing it
+pub fn prompt(prompt: &str) -> Result<String, KeystoreError> {
+    if !io::stdin().is_terminal() {
+        return Err(KeystoreError::NoTerminal);
+    }
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    terminal::enable_raw_mode()?;
+    let read = read_hidden();
+    terminal::disable_raw_mode()?;
+    eprintln!();
+    read
+}
+
+fn read_hidden() -> Result<String, KeystoreError> {
+    let mut line = String::new();
+    loop {
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Enter => return Ok(line),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Err(KeystoreError::Interrupted)
+            }
+            KeyCode::Char(c) => line.push(c),
+            KeyCode::Backspace => {
+                line.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The passphrase a running agent holds, if there is one
+#[cfg(unix)]
+async fn from_agent(path: &Path) -> Option<String> {
+    let mut stream = UnixStream::connect(path).await.ok()?;
+    let mut passphrase = String::new();
+    stream.read_to_string(&mut passphrase).await.ok()?;
+    Some(passphrase)
+}
+
+#[cfg(not(unix))]
+async fn from_agent(_path: &Path) -> Option<String> {
+    None
+}
+
+/// Serves `passphrase` to this user's wallet commands on `path` until `timeout` passes
+#[cfg(unix)]
+pub async fn run_agent(
+    path: &Path,
+    passphrase: String,
+    timeout: Duration,
+) -> Result<(), KeystoreError> {
+    if let Some(dir) = path.parent() {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+        // An existing directory keeps its mode, so set it before binding
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    info!(
+        "Holding the keystore passphrase at {} for {}s",
+        path.display(),
+        timeout.as_secs()
+    );
+    let serve = async {
+        loop {
+            match listener.accept().await {
+                Ok((mut stream, _)) => {
+                    if let Err(e) = stream.write_all(passphrase.as_bytes()).await {
+                        error!("Failed to send the passphrase: {}", e);
+                    }
+                }
+                Err(e) => error!("Failed to accept a connection: {}", e),
+            }
+        }
+    };
+    let _ = tokio::time::timeout(timeout, serve).await;
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub async fn run_agent(
+    _path: &Path,
+    _passphrase: String,
+    _timeout: Duration,
+) -> Result<(), KeystoreError> {
+    Err(KeystoreError::NoAgent)
+}
+
+/// The pid of a `[%passphrase pid]` effect
+fn passphrase_request(effect: Noun) -> Option<u64> {
+    let cell = effect.as_cell().ok()?;
+    if !cell.head().eq_bytes(b"passphrase") {
+        return None;
+    }
+    cell.tail().as_atom().ok()?.as_u64().ok()
+}
+
+/// `[%unlock pid passphrase]`
+fn unlock(pid: u64, passphrase: &str) -> NounSlab {
+    let mut slab = NounSlab::new();
+    let tag = make_tas(&mut slab, "unlock").as_noun();
+    let passphrase = make_tas(&mut slab, passphrase).as_noun();
+    let poke = T(&mut slab, &[tag, D(pid), passphrase]);
+    slab.set_root(poke);
+    slab
+}
+
+/// Answers the kernel's `%passphrase` effects
+pub fn keystore_driver(passphrase: Passphrase) -> IODriverFn {
+    make_driver(move |handle| async move {
+        loop {
+            let effect = match handle.next_effect().await {
+                Ok(effect) => effect,
+                Err(e) => {
+                    error!("Error in keystore driver: {:?}", e);
+                    continue;
+                }
+            };
+            let Some(pid) = passphrase_request(unsafe { *effect.root() }) else {
+                continue;
+            };
+            match passphrase.get().await {
+                Ok(given) => {
+                    let wire = KeystoreWire::Unlock.to_wire();
+                    if let PokeResult::Nack = handle.poke(wire, unlock(pid, &given)).await? {
+                        error!("The wallet could not run the command with the keystore open");
+                        handle.exit.exit(1).await?;
+                    }
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    handle.exit.exit(1).await?;
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passphrase_request() {
+        let mut slab: NounSlab = NounSlab::new();
+        let tag = make_tas(&mut slab, "passphrase").as_noun();
+        let effect = T(&mut slab, &[tag, D(7)]);
+        assert_eq!(passphrase_request(effect), Some(7));
+
+        let tag = make_tas(&mut slab, "markdown").as_noun();
+        let effect = T(&mut slab, &[tag, D(7)]);
+        assert_eq!(passphrase_request(effect), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_agent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(AGENT_DIR).join(AGENT_SOCKET);
+        let agent = tokio::spawn({
+            let path = path.clone();
+            async move { run_agent(&path, "hunter2".into(), Duration::from_millis(500)).await }
+        });
+        while !path.exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(from_agent(&path).await.as_deref(), Some("hunter2"));
+        let mode = std::fs::metadata(path.parent().unwrap())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+        agent.await.unwrap().unwrap();
+        assert!(!path.exists());
+        assert_eq!(from_agent(&path).await, None);
+    }
+}
@@ -0,0 +1,5 @@
+//! The wallet as a library, for services that pay from their own process instead of running
+//! `nockchain-wallet` and reading what it prints. See [`tx`].
+pub mod tx;
+
+pub use tx::{CoinSelection, Draft, Recipient, Transaction, TxBuilder, TxError, WalletKernel};
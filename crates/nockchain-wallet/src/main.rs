@@ -2,27 +2,47 @@
 
 use std::fs;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use getrandom::getrandom;
 use nockapp::utils::bytes::Byts;
+use nockapp::utils::tls::{self, TlsIdentity};
 use nockapp::{system_data_dir, CrownError, NockApp, NockAppError, ToBytesExt};
 use nockvm::jets::cold::Nounable;
 use nockvm::noun::{Atom, Cell, IndirectAtom, Noun, D, NO, SIG, T, YES};
 use tokio::fs as tokio_fs;
-use tokio::net::UnixStream;
 use tracing::{error, info};
 use zkvm_jetpack::hot::produce_prover_hot_state;
 
+mod batch;
 mod error;
+mod keystore;
+mod memo;
+mod signer;
 
 use kernels::wallet::KERNEL;
+use keystore::Passphrase;
 use nockapp::driver::*;
 use nockapp::kernel::boot::{self, Cli as BootCli};
 use nockapp::noun::slab::NounSlab;
+use nockapp::settings::{ConfigCommand, Layers};
 use nockapp::utils::make_tas;
 use nockapp::wire::{Wire, WireRepr};
-use nockapp::{exit_driver, file_driver, markdown_driver, one_punch_driver};
+use nockapp::{
+    exit_driver, file_driver, markdown_driver, one_punch_driver, one_punch_secret_driver,
+};
+use nockchain_wallet::{CoinSelection, Recipient, TxBuilder};
+use signer::{DerivationPath, Device};
+
+/// The wallet's settings: the `[wallet]` table of `nockchain.toml`, and `NOCKCHAIN_WALLET_`
+/// variables
+const LAYERS: Layers = Layers {
+    default_file: "nockchain.toml",
+    section: "wallet",
+    env_prefix: "NOCKCHAIN_WALLET_",
+};
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
@@ -33,8 +53,72 @@ struct WalletCli {
     #[command(subcommand)]
     command: Commands,
 
-    #[arg(long, value_name = "PATH")]
+    #[arg(long, value_name = "PATH", conflicts_with = "nockchain_tls_addr")]
     nockchain_socket: Option<PathBuf>,
+
+    /// Talk to a node's NPC TLS listener at this host:port instead of its socket
+    #[arg(long, value_name = "ADDR", requires_all = ["tls_cert", "tls_key", "tls_ca"])]
+    nockchain_tls_addr: Option<String>,
+
+    /// PEM client certificate for --nockchain-tls-addr
+    #[arg(long, value_name = "PATH", requires = "nockchain_tls_addr")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, value_name = "PATH", requires = "nockchain_tls_addr")]
+    tls_key: Option<PathBuf>,
+
+    /// PEM CA certificates the node's certificate must be signed by
+    #[arg(long, value_name = "PATH", requires = "nockchain_tls_addr")]
+    tls_ca: Option<PathBuf>,
+
+    /// Name the node's certificate is for, if not the host in --nockchain-tls-addr
+    #[arg(long, requires = "nockchain_tls_addr")]
+    tls_server_name: Option<String>,
+
+    /// Passphrase of the encrypted keystore, asked for when a command needs it if not given
+    #[arg(
+        long,
+        global = true,
+        env = "NOCKCHAIN_KEYSTORE_PASSPHRASE",
+        hide_env_values = true
+    )]
+    keystore_passphrase: Option<String>,
+}
+
+/// What a spend pays: a number of nicks, or `auto` for the node's estimate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fee {
+    Nicks(u64),
+    Auto,
+}
+
+impl FromStr for Fee {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "auto" {
+            return Ok(Fee::Auto);
+        }
+        s.parse()
+            .map(Fee::Nicks)
+            .map_err(|_| format!("Fee {} must be a number of nicks or auto", s))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HistoryFormat {
+    Csv,
+    Json,
+}
+
+impl HistoryFormat {
+    fn as_tas(self) -> &'static str {
+        match self {
+            HistoryFormat::Csv => "csv",
+            HistoryFormat::Json => "json",
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -67,6 +151,80 @@ impl Wire for WalletWire {
 /// Represents a Noun that the wallet kernel can handle
 type CommandNoun<T> = Result<(T, Operation), NockAppError>;
 
+/// `s` as a cord, which is 0 when it's empty
+fn cord(slab: &mut NounSlab, s: &str) -> Noun {
+    if s.is_empty() {
+        D(0)
+    } else {
+        make_tas(slab, s).as_noun()
+    }
+}
+
+/// Parses recipients like "[1 pk1],[2 pk2,pk3,pk4]", or "pk1,pk2" for single signers. A
+/// contact's name is taken like a single signer's pubkey, and the kernel looks it up.
+fn parse_recipients(recipients: &str) -> Vec<(u64, Vec<String>)> {
+    if recipients.contains('[') {
+        split_outside_brackets(recipients)
+            .into_iter()
+            .filter_map(|pair| {
+                let pair = pair.trim();
+                if pair.starts_with('[') && pair.ends_with(']') {
+                    let inner = &pair[1..pair.len() - 1];
+                    let mut parts = inner.splitn(2, ' ');
+
+                    // Parse the number
+                    let number = parts.next()?.parse().ok()?;
+
+                    // Parse the pubkeys
+                    let pubkeys = parts
+                        .next()?
+                        .split(',')
+                        .map(|s| s.trim().to_string())
+                        .collect();
+
+                    Some((number, pubkeys))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    } else {
+        recipients
+            .split(',')
+            .map(|addr| (1, vec![addr.trim().to_string()]))
+            .collect()
+    }
+}
+
+/// `[m pubkeys]`, the way the kernel takes a recipient
+fn recipient_noun(slab: &mut NounSlab, (m, pubkeys): (u64, Vec<String>)) -> Noun {
+    let pubkeys_noun = pubkeys.into_iter().rev().fold(D(0), |acc, pubkey| {
+        let pubkey_noun = make_tas(slab, &pubkey).as_noun();
+        Cell::new(slab, pubkey_noun, acc).as_noun()
+    });
+    T(slab, &[D(m), pubkeys_noun])
+}
+
+/// Splits `s` on the commas that aren't inside brackets, so "[1 a],[2 b,c]" is two items
+fn split_outside_brackets(s: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                items.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&s[start..]);
+    items
+}
+
 fn validate_label(s: &str) -> Result<String, String> {
     if s.chars()
         .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
@@ -79,8 +237,22 @@ fn validate_label(s: &str) -> Result<String, String> {
 
 #[derive(Subcommand, Debug, Clone)]
 pub enum Commands {
-    /// Generate a new key pair
-    Keygen,
+    /// Generate a new key pair, and the 24-word mnemonic to restore it from
+    Keygen {
+        /// Optional BIP39 passphrase, needed with the mnemonic to restore the key
+        #[arg(long, env = "NOCKCHAIN_WALLET_PASSPHRASE", hide_env_values = true)]
+        passphrase: Option<String>,
+    },
+
+    /// Restore the master key from a BIP39 mnemonic
+    Restore {
+        /// The mnemonic's words, separated by spaces
+        #[arg(long)]
+        mnemonic: String,
+        /// The passphrase the key was generated with, if any
+        #[arg(long, env = "NOCKCHAIN_WALLET_PASSPHRASE", hide_env_values = true)]
+        passphrase: Option<String>,
+    },
 
     /// Derive child key (pub, private or both) from the current master key
     DeriveChild {
@@ -97,6 +269,98 @@ pub enum Commands {
         label: Option<String>,
     },
 
+    /// Derive the key(s) at a derivation path, like m/0'/1, from the current master key
+    DerivePath {
+        /// The derivation path, with ' marking hardened indices
+        #[arg(short, long)]
+        path: String,
+
+        /// Label for the derived key
+        #[arg(short, long, value_parser = validate_label, default_value = None)]
+        label: Option<String>,
+    },
+
+    /// Derive a fresh address, m/<account>/0/<index>, and receive to it from now on
+    NewAddress {
+        /// Account to derive the address in
+        #[arg(short, long, default_value_t = 0, value_parser = clap::value_parser!(u64).range(0..(1 << 31)))]
+        account: u64,
+
+        /// Derive a change address, m/<account>/1/<index>, without receiving to it
+        #[arg(short, long)]
+        change: bool,
+
+        /// Label for the address
+        #[arg(short, long, value_parser = validate_label, default_value = None)]
+        label: Option<String>,
+    },
+
+    /// List every address derived along a path
+    ListAddresses,
+
+    /// Watch an address without its private key, to track its balance and build unsigned
+    /// transactions from it
+    WatchAddress {
+        /// Base58-encoded public key to watch
+        #[arg(short, long)]
+        pubkey: String,
+
+        /// Label for the address
+        #[arg(short, long, value_parser = validate_label, default_value = None)]
+        label: Option<String>,
+    },
+
+    /// List watched addresses and the notes each holds
+    ListWatched,
+
+    /// Name a recipient, so it can be sent to by name
+    AddContact {
+        /// Name of the contact
+        #[arg(value_parser = validate_label)]
+        name: String,
+        /// Address of the contact, a pubkey or a lock like "[2 pk1,pk2,pk3]"
+        recipient: String,
+    },
+
+    /// Forget a contact
+    RemoveContact {
+        /// Name of the contact
+        name: String,
+    },
+
+    /// List contacts
+    ListContacts,
+
+    /// Label a transaction in the history
+    LabelTransaction {
+        /// Id of the transaction, as the history shows it
+        id: String,
+        /// The label, or "" to remove it
+        label: String,
+    },
+
+    /// Create a shared m-of-n lock to pay a treasury to, and watch it
+    CreateMultisig {
+        /// Number of signatures needed to spend
+        #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..=255))]
+        m: u64,
+
+        /// Base58-encoded public keys of the signers (comma-separated)
+        #[arg(short, long, value_delimiter = ',', required = true)]
+        pubkeys: Vec<String>,
+
+        /// Label for the lock
+        #[arg(short, long, value_parser = validate_label, default_value = None)]
+        label: Option<String>,
+    },
+
+    /// Merge the signatures on copies of a draft signed by different signers
+    MergeDrafts {
+        /// Signed copies of the same draft
+        #[arg(short, long, num_args = 1.., required = true)]
+        drafts: Vec<String>,
+    },
+
     /// Import keys from a file
     ImportKeys {
         /// Path to the jammed keys file
@@ -116,6 +380,48 @@ pub enum Commands {
         /// Optional key index to use for signing (0-255)
         #[arg(short, long, value_parser = clap::value_parser!(u64).range(0..=255))]
         index: Option<u64>,
+
+        /// Sign on a hardware wallet instead of with the wallet's keys
+        #[arg(long, value_enum, conflicts_with = "index")]
+        device: Option<Device>,
+
+        /// Derivation path of the device's key to sign with, like m/44'/0/1
+        #[arg(long, default_value = "m", requires = "device")]
+        device_path: DerivationPath,
+
+        /// USB vendor id of the device, in hex, for --device hid
+        #[arg(long, value_parser = parse_usb_id, requires = "device")]
+        vendor_id: Option<u16>,
+
+        /// USB product id of the device, in hex
+        #[arg(long, value_parser = parse_usb_id, requires = "device")]
+        product_id: Option<u16>,
+    },
+
+    /// Sign a memo, like a payment request, to a public key and send it through a node
+    SendMemo {
+        /// Base58-encoded public key to send it to
+        #[arg(long)]
+        to: String,
+
+        /// What the memo says
+        #[arg(short, long)]
+        message: String,
+
+        /// Optional key index to sign with (0-255)
+        #[arg(short, long, value_parser = clap::value_parser!(u64).range(0..=255))]
+        index: Option<u64>,
+
+        /// JSON-RPC server of a node run with --relay-memos
+        #[arg(long, default_value = "http://127.0.0.1:8545")]
+        node_rpc: String,
+    },
+
+    /// Show the memos sent to the wallet's public keys
+    Memos {
+        /// JSON-RPC server of a node run with --relay-memos
+        #[arg(long, default_value = "http://127.0.0.1:8545")]
+        node_rpc: String,
     },
 
     /// Generate a master private key from a seed phrase
@@ -168,26 +474,109 @@ pub enum Commands {
         pubkey: String,
     },
 
+    /// Export sent and received transactions to history.csv or history.json
+    History {
+        /// Format to export in
+        #[arg(long, value_enum, default_value_t = HistoryFormat::Csv)]
+        format: HistoryFormat,
+    },
+
+    /// Replay the node's balances from a height, to find what imported keys were paid
+    Rescan {
+        /// Height to replay from
+        #[arg(long)]
+        from_height: u64,
+    },
+
+    /// Pay everyone in a CSV or JSON payment file in one transaction, picking the notes to spend
+    SendBatch {
+        /// Payment file, rows of recipient,amount[,memo] or a .json list of
+        /// {"recipient", "amount", "memo"}
+        #[arg(long)]
+        file: PathBuf,
+        /// Transaction fee, or auto to pay what the node estimates
+        #[arg(long)]
+        fee: Fee,
+        /// With --fee auto, how many blocks to get into one of
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u64).range(1..=1008))]
+        fee_target: u64,
+        /// Optional key index to use for signing (0-255)
+        #[arg(short, long, value_parser = clap::value_parser!(u64).range(0..=255))]
+        index: Option<u64>,
+        /// How to pick notes
+        #[arg(long, value_enum, default_value_t = CoinSelection::Bnb)]
+        selection: CoinSelection,
+    },
+
+    /// Send to a contact or pubkey, picking the notes to spend
+    Send {
+        /// A contact's name or a pubkey
+        to: String,
+        /// Amount to send
+        amount: u64,
+        /// Transaction fee, or auto to pay what the node estimates
+        #[arg(long)]
+        fee: Fee,
+        /// With --fee auto, how many blocks to get into one of
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u64).range(1..=1008))]
+        fee_target: u64,
+        /// Optional key index to use for signing (0-255)
+        #[arg(short, long, value_parser = clap::value_parser!(u64).range(0..=255))]
+        index: Option<u64>,
+        /// How to pick notes
+        #[arg(long, value_enum, default_value_t = CoinSelection::Bnb)]
+        selection: CoinSelection,
+    },
+
     /// Perform a simple spend operation
     SimpleSpend {
-        /// Names of notes to spend (comma-separated)
+        /// Names of notes to spend (comma-separated), or leave out to pick them with --selection
         #[arg(long)]
-        names: String,
+        names: Option<String>,
         /// Recipient addresses (comma-separated)
         #[arg(long)]
         recipients: String,
         /// Amounts to send (comma-separated)
         #[arg(long)]
         gifts: String,
-        /// Transaction fee
+        /// Transaction fee, or auto to pay what the node estimates
         #[arg(long)]
-        fee: u64,
+        fee: Fee,
+        /// With --fee auto, how many blocks to get into one of
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u64).range(1..=1008))]
+        fee_target: u64,
         /// Optional key index to use for signing (0-255)
         #[arg(short, long, value_parser = clap::value_parser!(u64).range(0..=255))]
         index: Option<u64>,
+        /// How to pick notes when --names is left out
+        #[arg(long, value_enum, default_value_t = CoinSelection::Bnb)]
+        selection: CoinSelection,
     },
 
-    /// Create a transaction from a draft file
+    /// Build an unsigned draft to sign offline with sign-tx, spending the wallet's watched notes
+    BuildTx {
+        /// Names of notes to spend (comma-separated), or leave out to pick them with --selection
+        #[arg(long)]
+        names: Option<String>,
+        /// Recipient addresses (comma-separated)
+        #[arg(long)]
+        recipients: String,
+        /// Amounts to send (comma-separated)
+        #[arg(long)]
+        gifts: String,
+        /// Transaction fee, or auto to pay what the node estimates
+        #[arg(long)]
+        fee: Fee,
+        /// With --fee auto, how many blocks to get into one of
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u64).range(1..=1008))]
+        fee_target: u64,
+        /// How to pick notes when --names is left out
+        #[arg(long, value_enum, default_value_t = CoinSelection::Bnb)]
+        selection: CoinSelection,
+    },
+
+    /// Create a transaction from a draft file and send it
+    #[command(alias = "broadcast-tx")]
     SendTx {
         /// Draft file to create transaction from
         #[arg(short, long)]
@@ -200,6 +589,41 @@ pub enum Commands {
     /// Export a master public key
     ExportMasterPubkey,
 
+    /// Encrypt the private keys and seed phrase with a passphrase
+    EncryptKeys {
+        /// The passphrase, asked for twice if not given
+        #[arg(
+            long,
+            env = "NOCKCHAIN_KEYSTORE_NEW_PASSPHRASE",
+            hide_env_values = true
+        )]
+        new_passphrase: Option<String>,
+    },
+
+    /// Change the keystore passphrase
+    ChangePassphrase {
+        /// The new passphrase, asked for twice if not given
+        #[arg(
+            long,
+            env = "NOCKCHAIN_KEYSTORE_NEW_PASSPHRASE",
+            hide_env_values = true
+        )]
+        new_passphrase: Option<String>,
+    },
+
+    /// Hold the keystore passphrase for other wallet commands for a while
+    KeystoreAgent {
+        /// Seconds to hold it for
+        #[arg(long, default_value_t = 900)]
+        timeout: u64,
+    },
+
+    /// Show the settings layered from `nockchain.toml`, the environment and the command line
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+
     /// Import a master public key
     ImportMasterPubkey {
         // Path to keys file generated from export-master-pubkey
@@ -223,21 +647,44 @@ pub enum Commands {
 impl Commands {
     fn as_wire_tag(&self) -> &'static str {
         match self {
-            Commands::Keygen => "keygen",
+            Commands::Keygen { .. } => "keygen",
+            Commands::Restore { .. } => "restore",
             Commands::DeriveChild { .. } => "derive-child",
+            Commands::DerivePath { .. } => "derive-path",
+            Commands::NewAddress { .. } => "new-address",
+            Commands::ListAddresses => "list-addresses",
+            Commands::WatchAddress { .. } => "watch-address",
+            Commands::ListWatched => "list-watched",
+            Commands::AddContact { .. } => "add-contact",
+            Commands::RemoveContact { .. } => "remove-contact",
+            Commands::ListContacts => "list-contacts",
+            Commands::LabelTransaction { .. } => "label-transaction",
+            Commands::Send { .. } => "send",
+            Commands::SendBatch { .. } => "send-batch",
+            Commands::CreateMultisig { .. } => "create-multisig",
+            Commands::MergeDrafts { .. } => "merge-drafts",
             Commands::ImportKeys { .. } => "import-keys",
             Commands::ExportKeys => "export-keys",
             Commands::SignTx { .. } => "sign-tx",
+            Commands::SendMemo { .. } => "send-memo",
+            Commands::Memos { .. } => "memos",
             Commands::GenMasterPrivkey { .. } => "gen-master-privkey",
             Commands::GenMasterPubkey { .. } => "gen-master-pubkey",
             Commands::Scan { .. } => "scan",
             Commands::ListNotes => "list-notes",
             Commands::ListNotesByPubkey { .. } => "list-notes-by-pubkey",
             Commands::ListNotesByPubkeyCsv { .. } => "list-notes-by-pubkey-csv",
+            Commands::History { .. } => "history",
+            Commands::Rescan { .. } => "rescan",
             Commands::SimpleSpend { .. } => "simple-spend",
+            Commands::BuildTx { .. } => "build-tx",
             Commands::SendTx { .. } => "send-tx",
             Commands::UpdateBalance => "update-balance",
             Commands::ExportMasterPubkey => "export-master-pubkey",
+            Commands::EncryptKeys { .. } => "encrypt-keys",
+            Commands::ChangePassphrase { .. } => "change-passphrase",
+            Commands::KeystoreAgent { .. } => "keystore-agent",
+            Commands::Config { .. } => "config",
             Commands::ImportMasterPubkey { .. } => "import-master-pubkey",
             Commands::ListPubkeys => "list-pubkeys",
             Commands::ShowSeedphrase => "show-seedphrase",
@@ -344,13 +791,44 @@ impl Wallet {
     /// # Arguments
     ///
     /// * `entropy` - The entropy to use for key generation.
-    fn keygen(entropy: &[u8; 32], sal: &[u8; 16]) -> CommandNoun<NounSlab> {
+    /// * `sal` - The salt to stretch the entropy with.
+    /// * `passphrase` - The BIP39 passphrase, or empty for none.
+    fn keygen(entropy: &[u8; 32], sal: &[u8; 16], passphrase: &str) -> CommandNoun<NounSlab> {
         let mut slab = NounSlab::new();
         let ent: Byts = Byts::new(entropy.to_vec());
         let ent_noun = ent.into_noun(&mut slab);
         let sal: Byts = Byts::new(sal.to_vec());
         let sal_noun = sal.into_noun(&mut slab);
-        Self::wallet("keygen", &[ent_noun, sal_noun], Operation::Poke, &mut slab)
+        let passphrase_noun = cord(&mut slab, passphrase);
+        Self::wallet(
+            "keygen",
+            &[ent_noun, sal_noun, passphrase_noun],
+            Operation::Poke,
+            &mut slab,
+        )
+    }
+
+    /// Restores the master key from a BIP39 mnemonic.
+    ///
+    /// # Arguments
+    ///
+    /// * `mnemonic` - The mnemonic's words, in any case and spacing.
+    /// * `passphrase` - The BIP39 passphrase, or empty for none.
+    fn restore(mnemonic: &str, passphrase: &str) -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        let words = mnemonic
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mnemonic_noun = make_tas(&mut slab, &words).as_noun();
+        let passphrase_noun = cord(&mut slab, passphrase);
+        Self::wallet(
+            "restore",
+            &[mnemonic_noun, passphrase_noun],
+            Operation::Poke,
+            &mut slab,
+        )
     }
 
     // Derives a child key from current master key.
@@ -377,6 +855,185 @@ impl Wallet {
         )
     }
 
+    /// Derives the key(s) at a derivation path from the current master key.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The derivation path, like m/0'/1
+    /// * `label` - Optional label for the derived key
+    fn derive_path(path: &str, label: &Option<String>) -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        let path_noun = make_tas(&mut slab, path.trim()).as_noun();
+        let label_noun = label.as_ref().map_or(SIG, |l| {
+            let label_noun = l.into_noun(&mut slab);
+            T(&mut slab, &[SIG, label_noun])
+        });
+
+        Self::wallet(
+            "derive-path",
+            &[path_noun, label_noun],
+            Operation::Poke,
+            &mut slab,
+        )
+    }
+
+    /// Derives the next address of an account.
+    ///
+    /// # Arguments
+    ///
+    /// * `account` - The account to derive the address in
+    /// * `change` - Whether to derive a change address rather than a receive address
+    /// * `label` - Optional label for the address
+    fn new_address(account: u64, change: bool, label: &Option<String>) -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        let change_noun = if change { YES } else { NO };
+        let label_noun = label.as_ref().map_or(SIG, |l| {
+            let label_noun = l.into_noun(&mut slab);
+            T(&mut slab, &[SIG, label_noun])
+        });
+
+        Self::wallet(
+            "new-address",
+            &[D(account), change_noun, label_noun],
+            Operation::Poke,
+            &mut slab,
+        )
+    }
+
+    /// Lists every address derived along a path.
+    fn list_addresses() -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        Self::wallet("list-addresses", &[], Operation::Poke, &mut slab)
+    }
+
+    /// Watches an address without its private key.
+    ///
+    /// # Arguments
+    ///
+    /// * `pubkey` - Base58-encoded public key to watch
+    /// * `label` - Optional label for the address
+    fn watch_address(pubkey: &str, label: &Option<String>) -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        let pubkey_noun = make_tas(&mut slab, pubkey.trim()).as_noun();
+        let label_noun = label.as_ref().map_or(SIG, |l| {
+            let label_noun = l.into_noun(&mut slab);
+            T(&mut slab, &[SIG, label_noun])
+        });
+
+        Self::wallet(
+            "watch-address",
+            &[pubkey_noun, label_noun],
+            Operation::Poke,
+            &mut slab,
+        )
+    }
+
+    /// Lists watched addresses and the notes each holds.
+    fn list_watched() -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        Self::wallet("list-watched", &[], Operation::Poke, &mut slab)
+    }
+
+    /// Names a recipient.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the contact
+    /// * `recipient` - A base58-encoded pubkey, or a lock like "[2 pk1,pk2,pk3]"
+    fn add_contact(name: &str, recipient: &str) -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        let mut recipients = parse_recipients(recipient);
+        if recipients.len() != 1 {
+            return Err(CrownError::Unknown(format!("Invalid recipient {}", recipient)).into());
+        }
+        let name_noun = make_tas(&mut slab, name).as_noun();
+        let recipient_noun = recipient_noun(&mut slab, recipients.remove(0));
+        Self::wallet(
+            "add-contact",
+            &[name_noun, recipient_noun],
+            Operation::Poke,
+            &mut slab,
+        )
+    }
+
+    fn remove_contact(name: &str) -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        let name_noun = make_tas(&mut slab, name).as_noun();
+        Self::wallet("remove-contact", &[name_noun], Operation::Poke, &mut slab)
+    }
+
+    fn list_contacts() -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        Self::wallet("list-contacts", &[], Operation::Poke, &mut slab)
+    }
+
+    /// Labels the transaction `id` in the history, or removes its label if `label` is empty.
+    fn label_transaction(id: &str, label: &str) -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        let id_noun = make_tas(&mut slab, id).as_noun();
+        let label_noun = cord(&mut slab, label);
+        Self::wallet(
+            "label-transaction",
+            &[id_noun, label_noun],
+            Operation::Poke,
+            &mut slab,
+        )
+    }
+
+    /// Creates a shared m-of-n lock.
+    ///
+    /// # Arguments
+    ///
+    /// * `m` - Number of signatures needed to spend
+    /// * `pubkeys` - Base58-encoded public keys of the signers
+    /// * `label` - Optional label for the lock
+    fn create_multisig(
+        m: u64,
+        pubkeys: &[String],
+        label: &Option<String>,
+    ) -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        let pubkeys_noun = pubkeys.iter().rev().fold(D(0), |acc, pubkey| {
+            let pubkey_noun = make_tas(&mut slab, pubkey.trim()).as_noun();
+            Cell::new(&mut slab, pubkey_noun, acc).as_noun()
+        });
+        let label_noun = label.as_ref().map_or(SIG, |l| {
+            let label_noun = l.into_noun(&mut slab);
+            T(&mut slab, &[SIG, label_noun])
+        });
+
+        Self::wallet(
+            "create-multisig",
+            &[D(m), pubkeys_noun, label_noun],
+            Operation::Poke,
+            &mut slab,
+        )
+    }
+
+    /// Merges the signatures on copies of one draft.
+    ///
+    /// # Arguments
+    ///
+    /// * `draft_paths` - Paths to the signed copies
+    fn merge_drafts(draft_paths: &[String]) -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        let mut drafts = Vec::with_capacity(draft_paths.len());
+        for path in draft_paths {
+            let draft_data = fs::read(path).map_err(|e| {
+                CrownError::Unknown(format!("Failed to read draft {}: {}", path, e))
+            })?;
+            let draft_noun = slab.cue_into(draft_data.as_bytes()?).map_err(|e| {
+                CrownError::Unknown(format!("Failed to decode draft {}: {}", path, e))
+            })?;
+            drafts.push(draft_noun);
+        }
+        let drafts_noun = drafts.into_iter().rev().fold(D(0), |acc, draft| {
+            Cell::new(&mut slab, draft, acc).as_noun()
+        });
+
+        Self::wallet("merge-drafts", &[drafts_noun], Operation::Poke, &mut slab)
+    }
+
     /// Signs a transaction.
     ///
     /// # Arguments
@@ -413,13 +1070,58 @@ impl Wallet {
         let entropy = from_bytes(&mut slab, &entropy_bytes).as_noun();
 
         Self::wallet(
-            "sign-tx",
-            &[draft_noun, index_noun, entropy],
+            "sign-tx",
+            &[draft_noun, index_noun, entropy],
+            Operation::Poke,
+            &mut slab,
+        )
+    }
+
+    /// Asks the wallet what a hardware wallet should sign for a draft.
+    ///
+    /// # Arguments
+    ///
+    /// * `draft_path` - Path to the draft file
+    fn sign_requests(draft_path: &str) -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        let draft_data = fs::read(draft_path)
+            .map_err(|e| CrownError::Unknown(format!("Failed to read draft: {}", e)))?;
+        let draft_noun = slab
+            .cue_into(draft_data.as_bytes()?)
+            .map_err(|e| CrownError::Unknown(format!("Failed to decode draft: {}", e)))?;
+
+        Self::wallet("sign-requests", &[draft_noun], Operation::Poke, &mut slab)
+    }
+
+    /// Signs a memo to a public key, for the memo driver to send.
+    ///
+    /// # Arguments
+    ///
+    /// * `to` - Base58-encoded public key to send it to
+    /// * `message` - What the memo says
+    /// * `index` - Optional index of the key to sign with
+    fn sign_memo(to: &str, message: &str, index: Option<u64>) -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        let to_noun = make_tas(&mut slab, to).as_noun();
+        let message_noun = make_tas(&mut slab, message).as_noun();
+        let index_noun = match index {
+            Some(i) => T(&mut slab, &[D(0), D(i)]),
+            None => D(0),
+        };
+        Self::wallet(
+            "sign-memo",
+            &[to_noun, message_noun, index_noun],
             Operation::Poke,
             &mut slab,
         )
     }
 
+    /// Lists the wallet's public keys, for the memo driver to fetch memos for.
+    fn fetch_memos() -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        Self::wallet("fetch-memos", &[], Operation::Poke, &mut slab)
+    }
+
     /// Generates a master private key from a seed phrase.
     ///
     /// # Arguments
@@ -517,6 +1219,7 @@ impl Wallet {
     ///
     /// * `names` - Comma-separated list of note name pairs in format "[first last]"
     ///             Example: "[first1 last1],[first2 last2]"
+    ///             With `None`, the wallet picks notes using `selection`
     ///
     /// * `recipients` - Comma-separated list of recipient $locks
     ///                 Example: "[1 pk1],[2 pk2,pk3,pk4]"
@@ -529,6 +1232,13 @@ impl Wallet {
     ///
     /// * `fee` - Transaction fee to be subtracted from one of the input notes
     ///
+    /// * `selection` - How the wallet picks notes when `names` is `None`
+    ///
+    /// * `sign` - Whether to sign with the wallet's keys, or leave the draft for sign-tx
+    ///
+    /// * `memos` - A memo for each recipient, or none. The wallet keeps them, and labels the
+    ///             transaction with them once it's sent
+    ///
     /// # Returns
     ///
     /// Returns a `CommandNoun` containing:
@@ -549,20 +1259,24 @@ impl Wallet {
     /// let recipients = "[1 pk1],[2 pk2,pk3,pk4]";
     /// let gifts = "100,200";
     /// let fee = 10;
-    /// wallet.simple_spend(names.to_string(), recipients.to_string(), gifts.to_string(), fee)?;
+    /// wallet.simple_spend(Some(names.to_string()), recipients.to_string(), gifts.to_string(), fee, None, CoinSelection::Bnb, true, &[])?;
     /// ```
+    #[allow(clippy::too_many_arguments)]
     fn simple_spend(
-        names: String,
+        names: Option<String>,
         recipients: String,
         gifts: String,
         fee: u64,
         index: Option<u64>,
+        selection: CoinSelection,
+        sign: bool,
+        memos: &[String],
     ) -> CommandNoun<NounSlab> {
-        let mut slab = NounSlab::new();
-
         // Split the comma-separated inputs
         // Each name should be in format "[first last]"
         let names_vec: Vec<(String, String)> = names
+            .as_deref()
+            .unwrap_or_default()
             .split(',')
             .filter_map(|pair| {
                 let pair = pair.trim();
@@ -580,45 +1294,14 @@ impl Wallet {
             })
             .collect();
 
-        // Convert recipients to list of [number pubkeys] pairs
-        let recipients_vec: Vec<(u64, Vec<String>)> = if recipients.contains('[') {
-            // Parse complex format: "[1 pk1],[2 pk2,pk3,pk4]"
-            recipients
-                .split(',')
-                .filter_map(|pair| {
-                    let pair = pair.trim();
-                    if pair.starts_with('[') && pair.ends_with(']') {
-                        let inner = &pair[1..pair.len() - 1];
-                        let mut parts = inner.splitn(2, ' ');
-
-                        // Parse the number
-                        let number = parts.next()?.parse().ok()?;
-
-                        // Parse the pubkeys
-                        let pubkeys = parts
-                            .next()?
-                            .split(',')
-                            .map(|s| s.trim().to_string())
-                            .collect();
-
-                        Some((number, pubkeys))
-                    } else {
-                        None
-                    }
-                })
-                .collect()
-        } else {
-            // Parse simple format: "pk1,pk2,pk3"
-            recipients
-                .split(',')
-                .map(|addr| (1, vec![addr.trim().to_string()]))
-                .collect()
-        };
+        let recipients_vec = parse_recipients(&recipients);
 
         let gifts_vec: Vec<u64> = gifts.split(',').filter_map(|s| s.parse().ok()).collect();
 
         // Verify equal lengths
-        if names_vec.len() != recipients_vec.len() || names_vec.len() != gifts_vec.len() {
+        if (names.is_some() && names_vec.len() != recipients_vec.len())
+            || recipients_vec.len() != gifts_vec.len()
+        {
             return Err(CrownError::Unknown(
                 "Invalid input - names, recipients, and gifts must have the same length"
                     .to_string(),
@@ -626,56 +1309,74 @@ impl Wallet {
             .into());
         }
 
-        // Convert names to list of pairs
-        let names_noun = names_vec
-            .into_iter()
-            .rev()
-            .fold(D(0), |acc, (first, last)| {
-                // Create a tuple [first_name last_name] for each name pair
-                let first_noun = make_tas(&mut slab, &first).as_noun();
-                let last_noun = make_tas(&mut slab, &last).as_noun();
-                let name_pair = T(&mut slab, &[first_noun, last_noun]);
-                Cell::new(&mut slab, name_pair, acc).as_noun()
-            });
-
-        // Convert recipients to list
-        let recipients_noun = recipients_vec
-            .into_iter()
-            .rev()
-            .fold(D(0), |acc, (num, pubkeys)| {
-                // Create the inner list of pubkeys
-                let pubkeys_noun = pubkeys.into_iter().rev().fold(D(0), |acc, pubkey| {
-                    let pubkey_noun = make_tas(&mut slab, &pubkey).as_noun();
-                    Cell::new(&mut slab, pubkey_noun, acc).as_noun()
-                });
-
-                // Create the pair of [number pubkeys_list]
-                let pair = T(&mut slab, &[D(num), pubkeys_noun]);
-                Cell::new(&mut slab, pair, acc).as_noun()
-            });
-
-        // Convert gifts to list
-        let gifts_noun = gifts_vec.into_iter().rev().fold(D(0), |acc, amount| {
-            Cell::new(&mut slab, D(amount), acc).as_noun()
-        });
+        let mut builder = TxBuilder::new().fee(fee).selection(selection);
+        for (first, last) in names_vec {
+            builder = builder.spend_note(first, last);
+        }
+        for (i, ((m, pubkeys), gift)) in recipients_vec.into_iter().zip(gifts_vec).enumerate() {
+            let memo = memos.get(i).map_or("", String::as_str);
+            builder = builder.pay_with_memo(Recipient { m, pubkeys }, gift, memo);
+        }
+        if let Some(index) = index {
+            builder = builder.key_index(index);
+        }
+        if !sign {
+            builder = builder.unsigned();
+        }
+        let slab = builder
+            .to_poke()
+            .map_err(|e| CrownError::Unknown(e.to_string()))?;
+        Ok((slab, Operation::Poke))
+    }
 
-        let fee_noun = D(fee);
-        let index_noun = match index {
-            Some(i) => {
-                let inner = D(i);
-                T(&mut slab, &[D(0), inner])
-            }
-            None => D(0),
-        };
+    /// Makes a simple-spend pay what the node estimates it takes to be in one of the next
+    /// `target` blocks.
+    ///
+    /// # Arguments
+    ///
+    /// * `spend` - The simple-spend, with any fee
+    /// * `target` - How many blocks to get into one of
+    fn auto_fee(spend: (NounSlab, Operation), target: u64) -> CommandNoun<NounSlab> {
+        let (mut slab, operation) = spend;
+        let tag = make_tas(&mut slab, "auto-fee").as_noun();
+        slab.modify(move |spend| vec![tag, D(target), spend]);
+        Ok((slab, operation))
+    }
 
+    /// Encrypts the private keys and seed phrase under `passphrase`.
+    fn encrypt_keys(passphrase: &str) -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        let passphrase_noun = make_tas(&mut slab, passphrase).as_noun();
+        let salt = Self::keystore_salt(&mut slab)?;
+        Self::wallet(
+            "encrypt-keys",
+            &[passphrase_noun, salt],
+            Operation::Poke,
+            &mut slab,
+        )
+    }
+
+    /// Reseals the keystore under `new`, given its passphrase `old`.
+    fn change_passphrase(old: &str, new: &str) -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        let old_noun = make_tas(&mut slab, old).as_noun();
+        let new_noun = make_tas(&mut slab, new).as_noun();
+        let salt = Self::keystore_salt(&mut slab)?;
         Self::wallet(
-            "simple-spend",
-            &[names_noun, recipients_noun, gifts_noun, fee_noun, index_noun],
+            "change-passphrase",
+            &[old_noun, new_noun, salt],
             Operation::Poke,
             &mut slab,
         )
     }
 
+    /// 16 random bytes to derive a keystore key with
+    fn keystore_salt(slab: &mut NounSlab) -> Result<Noun, NockAppError> {
+        let mut salt = [0u8; 16];
+        getrandom(&mut salt).map_err(|e| CrownError::Unknown(e.to_string()))?;
+        Ok(from_bytes(slab, &salt).as_noun())
+    }
+
     fn update_balance() -> CommandNoun<NounSlab> {
         let mut slab = NounSlab::new();
         Self::wallet("update-balance", &[], Operation::Poke, &mut slab)
@@ -771,6 +1472,27 @@ impl Wallet {
         )
     }
 
+    /// Exports the transaction history.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - Whether to write history.csv or history.json
+    fn history(format: HistoryFormat) -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        let format_noun = make_tas(&mut slab, format.as_tas()).as_noun();
+        Self::wallet("history", &[format_noun], Operation::Poke, &mut slab)
+    }
+
+    /// Rebuilds the balance and history by replaying the node's balances.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_height` - Height to replay from
+    fn rescan(from_height: u64) -> CommandNoun<NounSlab> {
+        let mut slab = NounSlab::new();
+        Self::wallet("rescan", &[D(from_height)], Operation::Poke, &mut slab)
+    }
+
     /// Shows the seed phrase for the current master key.
     fn show_seedphrase() -> CommandNoun<NounSlab> {
         let mut slab = NounSlab::new();
@@ -804,11 +1526,36 @@ pub async fn wallet_data_dir() -> Result<PathBuf, NockAppError> {
 
 #[tokio::main]
 async fn main() -> Result<(), NockAppError> {
-    let cli = WalletCli::parse();
+    let (cli, effective) = LAYERS.parse::<WalletCli>();
+    if let Commands::Config { command } = &cli.command {
+        match command {
+            ConfigCommand::PrintEffective => print!("{}", effective),
+        }
+        return Ok(());
+    }
     boot::init_default_tracing(&cli.boot.clone()); // Init tracing early
 
-    let prover_hot_state = produce_prover_hot_state();
     let data_dir = wallet_data_dir().await?;
+    let passphrase = Passphrase {
+        given: cli.keystore_passphrase.clone(),
+        agent: data_dir
+            .join(keystore::AGENT_DIR)
+            .join(keystore::AGENT_SOCKET),
+    };
+
+    // The agent only holds the passphrase, and never boots the kernel
+    if let Commands::KeystoreAgent { timeout } = &cli.command {
+        let given = passphrase
+            .get()
+            .await
+            .map_err(|e| CrownError::Unknown(e.to_string()))?;
+        keystore::run_agent(&passphrase.agent, given, Duration::from_secs(*timeout))
+            .await
+            .map_err(|e| CrownError::Unknown(e.to_string()))?;
+        return Ok(());
+    }
+
+    let prover_hot_state = produce_prover_hot_state();
 
     let kernel = boot::setup(
         KERNEL,
@@ -827,47 +1574,103 @@ async fn main() -> Result<(), NockAppError> {
     let requires_socket = match &cli.command {
         // Commands that DON'T need socket either because they don't sync
         // or they don't interact with the chain
-        Commands::Keygen
+        Commands::Keygen { .. }
+        | Commands::Restore { .. }
         | Commands::DeriveChild { .. }
+        | Commands::DerivePath { .. }
+        | Commands::NewAddress { .. }
+        | Commands::ListAddresses
+        | Commands::WatchAddress { .. }
+        | Commands::AddContact { .. }
+        | Commands::RemoveContact { .. }
+        | Commands::ListContacts
+        | Commands::LabelTransaction { .. }
+        | Commands::CreateMultisig { .. }
+        | Commands::MergeDrafts { .. }
         | Commands::ImportKeys { .. }
         | Commands::ExportKeys
         | Commands::SignTx { .. }
+        | Commands::SendMemo { .. }
+        | Commands::Memos { .. }
         | Commands::GenMasterPrivkey { .. }
         | Commands::GenMasterPubkey { .. }
         | Commands::ExportMasterPubkey
+        | Commands::EncryptKeys { .. }
+        | Commands::ChangePassphrase { .. }
+        | Commands::KeystoreAgent { .. }
+        | Commands::Config { .. }
         | Commands::ImportMasterPubkey { .. }
         | Commands::ListPubkeys
         | Commands::ShowSeedphrase
         | Commands::ShowMasterPubkey
         | Commands::ShowMasterPrivkey
-        | Commands::SimpleSpend { .. } => false,
+        | Commands::SimpleSpend {
+            fee: Fee::Nicks(_), ..
+        }
+        | Commands::Send {
+            fee: Fee::Nicks(_), ..
+        }
+        | Commands::SendBatch {
+            fee: Fee::Nicks(_), ..
+        } => false,
 
         // All other commands DO need sync
         _ => true,
     };
     // Check if we need sync but don't have a socket
-    if requires_socket && cli.nockchain_socket.is_none() {
+    let has_node = cli.nockchain_socket.is_some() || cli.nockchain_tls_addr.is_some();
+    if requires_socket && !has_node {
         return Err(CrownError::Unknown(
-            "This command requires connection to a nockchain node. Please provide --nockchain-socket"
+            "This command requires connection to a nockchain node. Please provide --nockchain-socket or --nockchain-tls-addr"
             .to_string()
         ).into());
     }
 
     // Generate the command noun and operation
     let poke = match &cli.command {
-        Commands::Keygen => {
+        Commands::Keygen { passphrase } => {
             let mut entropy = [0u8; 32];
             let mut salt = [0u8; 16];
             getrandom(&mut entropy).map_err(|e| CrownError::Unknown(e.to_string()))?;
             getrandom(&mut salt).map_err(|e| CrownError::Unknown(e.to_string()))?;
-            Wallet::keygen(&entropy, &salt)
+            Wallet::keygen(&entropy, &salt, passphrase.as_deref().unwrap_or_default())
         }
+        Commands::Restore {
+            mnemonic,
+            passphrase,
+        } => Wallet::restore(mnemonic, passphrase.as_deref().unwrap_or_default()),
         Commands::DeriveChild {
             index,
             hardened,
             label,
         } => Wallet::derive_child(*index, *hardened, label),
-        Commands::SignTx { draft, index } => Wallet::sign_tx(draft, *index),
+        Commands::DerivePath { path, label } => Wallet::derive_path(path, label),
+        Commands::NewAddress {
+            account,
+            change,
+            label,
+        } => Wallet::new_address(*account, *change, label),
+        Commands::ListAddresses => Wallet::list_addresses(),
+        Commands::WatchAddress { pubkey, label } => Wallet::watch_address(pubkey, label),
+        Commands::ListWatched => Wallet::list_watched(),
+        Commands::AddContact { name, recipient } => Wallet::add_contact(name, recipient),
+        Commands::RemoveContact { name } => Wallet::remove_contact(name),
+        Commands::ListContacts => Wallet::list_contacts(),
+        Commands::LabelTransaction { id, label } => Wallet::label_transaction(id, label),
+        Commands::CreateMultisig { m, pubkeys, label } => {
+            Wallet::create_multisig(*m, pubkeys, label)
+        }
+        Commands::MergeDrafts { drafts } => Wallet::merge_drafts(drafts),
+        Commands::SignTx {
+            draft,
+            device: Some(_),
+            ..
+        } => Wallet::sign_requests(draft),
+        Commands::SignTx { draft, index, .. } => Wallet::sign_tx(draft, *index),
+        Commands::SendMemo {
+            to, message, index, ..
+        } => Wallet::sign_memo(to, message, *index),
+        Commands::Memos { .. } => Wallet::fetch_memos(),
         Commands::ImportKeys { input } => Wallet::import_keys(input),
         Commands::ExportKeys => Wallet::export_keys(),
         Commands::GenMasterPrivkey { seedphrase } => Wallet::gen_master_privkey(seedphrase),
@@ -892,22 +1695,164 @@ async fn main() -> Result<(), NockAppError> {
             }
         }
         Commands::ListNotesByPubkeyCsv { pubkey } => Wallet::list_notes_by_pubkey_csv(pubkey),
+        Commands::History { format } => Wallet::history(*format),
+        Commands::Rescan { from_height } => Wallet::rescan(*from_height),
         Commands::SimpleSpend {
             names,
             recipients,
             gifts,
-            fee,
+            fee: Fee::Nicks(fee),
             index,
+            selection,
+            ..
         } => Wallet::simple_spend(
             names.clone(),
             recipients.clone(),
             gifts.clone(),
             *fee,
             *index,
+            *selection,
+            true,
+            &[],
+        ),
+        Commands::SimpleSpend {
+            names,
+            recipients,
+            gifts,
+            fee: Fee::Auto,
+            fee_target,
+            index,
+            selection,
+        } => Wallet::auto_fee(
+            Wallet::simple_spend(
+                names.clone(),
+                recipients.clone(),
+                gifts.clone(),
+                0,
+                *index,
+                *selection,
+                true,
+                &[],
+            )?,
+            *fee_target,
         ),
+        Commands::BuildTx {
+            names,
+            recipients,
+            gifts,
+            fee,
+            fee_target,
+            selection,
+        } => {
+            let paying = match fee {
+                Fee::Nicks(nicks) => *nicks,
+                Fee::Auto => 0,
+            };
+            let spend = Wallet::simple_spend(
+                names.clone(),
+                recipients.clone(),
+                gifts.clone(),
+                paying,
+                None,
+                *selection,
+                false,
+                &[],
+            );
+            match fee {
+                Fee::Nicks(_) => spend,
+                Fee::Auto => Wallet::auto_fee(spend?, *fee_target),
+            }
+        }
+        Commands::Send {
+            to,
+            amount,
+            fee,
+            fee_target,
+            index,
+            selection,
+        } => {
+            let paying = match fee {
+                Fee::Nicks(nicks) => *nicks,
+                Fee::Auto => 0,
+            };
+            let spend = Wallet::simple_spend(
+                None,
+                to.clone(),
+                amount.to_string(),
+                paying,
+                *index,
+                *selection,
+                true,
+                &[],
+            );
+            match fee {
+                Fee::Nicks(_) => spend,
+                Fee::Auto => Wallet::auto_fee(spend?, *fee_target),
+            }
+        }
+        Commands::SendBatch {
+            file,
+            fee,
+            fee_target,
+            index,
+            selection,
+        } => {
+            let payments =
+                batch::read_payments(file).map_err(|e| CrownError::Unknown(e.to_string()))?;
+            // Bracketed, so pubkeys and contacts can sit alongside m-of-n locks
+            let recipients: Vec<String> = payments
+                .iter()
+                .map(|p| {
+                    if p.recipient.starts_with('[') {
+                        p.recipient.clone()
+                    } else {
+                        format!("[1 {}]", p.recipient)
+                    }
+                })
+                .collect();
+            let gifts: Vec<String> = payments.iter().map(|p| p.amount.to_string()).collect();
+            let memos: Vec<String> = payments
+                .into_iter()
+                .map(|p| p.memo.unwrap_or_default())
+                .collect();
+            let paying = match fee {
+                Fee::Nicks(nicks) => *nicks,
+                Fee::Auto => 0,
+            };
+            let spend = Wallet::simple_spend(
+                None,
+                recipients.join(","),
+                gifts.join(","),
+                paying,
+                *index,
+                *selection,
+                true,
+                &memos,
+            );
+            match fee {
+                Fee::Nicks(_) => spend,
+                Fee::Auto => Wallet::auto_fee(spend?, *fee_target),
+            }
+        }
         Commands::SendTx { draft } => Wallet::send_tx(draft),
         Commands::UpdateBalance => Wallet::update_balance(),
         Commands::ExportMasterPubkey => Wallet::export_master_pubkey(),
+        Commands::EncryptKeys { new_passphrase } => {
+            let new = Passphrase::new_passphrase(new_passphrase.clone())
+                .map_err(|e| CrownError::Unknown(e.to_string()))?;
+            Wallet::encrypt_keys(&new)
+        }
+        Commands::ChangePassphrase { new_passphrase } => {
+            let old = passphrase
+                .get()
+                .await
+                .map_err(|e| CrownError::Unknown(e.to_string()))?;
+            let new = Passphrase::new_passphrase(new_passphrase.clone())
+                .map_err(|e| CrownError::Unknown(e.to_string()))?;
+            Wallet::change_passphrase(&old, &new)
+        }
+        Commands::KeystoreAgent { .. } => unreachable!("the agent runs before the kernel boots"),
+        Commands::Config { .. } => unreachable!("settings are printed before the kernel boots"),
         Commands::ImportMasterPubkey { key_path } => Wallet::import_master_pubkey(key_path),
         Commands::ListPubkeys => Wallet::list_pubkeys(),
         Commands::ShowSeedphrase => Wallet::show_seedphrase(),
@@ -916,20 +1861,63 @@ async fn main() -> Result<(), NockAppError> {
     }?;
 
     // If this command requires sync and we have a socket, wrap it with sync-run
-    let final_poke = if requires_socket && cli.nockchain_socket.is_some() {
+    let final_poke = if requires_socket && has_node {
         Wallet::wrap_with_sync_run(poke.0, poke.1)?
     } else {
         poke
     };
 
-    wallet
-        .app
-        .add_io_driver(one_punch_driver(final_poke.0, final_poke.1))
-        .await;
+    // These carry passphrases, and leave the keys in the clear or under the old passphrase in
+    // every earlier checkpoint and event
+    if matches!(
+        cli.command,
+        Commands::EncryptKeys { .. } | Commands::ChangePassphrase { .. }
+    ) {
+        wallet.app.scrub_on_exit();
+        wallet
+            .app
+            .add_io_driver(one_punch_secret_driver(final_poke.0))
+            .await;
+    } else {
+        wallet
+            .app
+            .add_io_driver(one_punch_driver(final_poke.0, final_poke.1))
+            .await;
+    }
+
+    if let Commands::SignTx {
+        draft,
+        device: Some(device),
+        device_path,
+        vendor_id,
+        product_id,
+        ..
+    } = &cli.command
+    {
+        let backend = signer::open(*device, *vendor_id, *product_id)
+            .map_err(|e| CrownError::Unknown(e.to_string()))?;
+        let draft_data = fs::read(draft)
+            .map_err(|e| CrownError::Unknown(format!("Failed to read draft: {}", e)))?;
+        wallet
+            .app
+            .add_io_driver(signer::signing_driver(
+                backend,
+                device_path.clone(),
+                draft_data.as_bytes()?,
+            ))
+            .await;
+    }
+
+    if let Commands::SendMemo { node_rpc, .. } | Commands::Memos { node_rpc } = &cli.command {
+        wallet
+            .app
+            .add_io_driver(memo::memo_driver(node_rpc.clone()))
+            .await;
+    }
 
     {
         if let Some(socket_path) = cli.nockchain_socket {
-            match UnixStream::connect(&socket_path).await {
+            match nockapp::utils::ipc::connect(&socket_path).await {
                 Ok(stream) => {
                     info!("Connected to nockchain NPC socket at {:?}", socket_path);
                     wallet
@@ -950,7 +1938,45 @@ async fn main() -> Result<(), NockAppError> {
                 }
             }
         }
+        if let Some(addr) = &cli.nockchain_tls_addr {
+            let (Some(cert), Some(key), Some(ca)) = (
+                cli.tls_cert.clone(),
+                cli.tls_key.clone(),
+                cli.tls_ca.clone(),
+            ) else {
+                return Err(CrownError::Unknown(
+                    "--nockchain-tls-addr needs --tls-cert, --tls-key and --tls-ca".to_string(),
+                )
+                .into());
+            };
+            let config = TlsIdentity { cert, key, ca }
+                .client_config()
+                .map_err(|e| CrownError::Unknown(e.to_string()))?;
+            let server_name = match &cli.tls_server_name {
+                Some(name) => name.clone(),
+                None => tls_host(addr).to_string(),
+            };
+            match tls::connect(addr, &server_name, config).await {
+                Ok(stream) => {
+                    info!("Connected to nockchain NPC over TLS at {}", addr);
+                    wallet
+                        .app
+                        .add_io_driver(nockapp::npc_client_driver(stream))
+                        .await;
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to connect to nockchain NPC over TLS at {}: {}",
+                        addr, e
+                    );
+                }
+            }
+        }
 
+        wallet
+            .app
+            .add_io_driver(keystore::keystore_driver(passphrase))
+            .await;
         wallet.app.add_io_driver(file_driver()).await;
         wallet.app.add_io_driver(markdown_driver()).await;
         wallet.app.add_io_driver(exit_driver()).await;
@@ -960,6 +1986,18 @@ async fn main() -> Result<(), NockAppError> {
     }
 }
 
+/// The host part of a host:port, without the brackets of an IPv6 address
+fn tls_host(addr: &str) -> &str {
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+/// Parses a USB vendor or product id, like 2c97 or 0x2c97
+fn parse_usb_id(s: &str) -> Result<u16, String> {
+    let hex = s.strip_prefix("0x").unwrap_or(s);
+    u16::from_str_radix(hex, 16).map_err(|e| format!("Invalid USB id {}: {}", s, e))
+}
+
 pub fn from_bytes(stack: &mut NounSlab, bytes: &[u8]) -> Atom {
     unsafe {
         let mut tas_atom = IndirectAtom::new_raw_bytes(stack, bytes.len(), bytes.as_ptr());
@@ -976,7 +2014,7 @@ mod tests {
 
     use nockapp::kernel::boot::{self, Cli as BootCli};
     use nockapp::wire::SystemWire;
-    use nockapp::{exit_driver, AtomExt, Bytes};
+    use nockapp::{exit_driver, AtomExt, Bytes, NounExt};
     use tokio::sync::mpsc;
 
     use super::*;
@@ -1011,9 +2049,9 @@ mod tests {
         let mut salt = [0u8; 16];
         getrandom(&mut entropy).map_err(|e| CrownError::Unknown(e.to_string()))?;
         getrandom(&mut salt).map_err(|e| CrownError::Unknown(e.to_string()))?;
-        let (noun, op) = Wallet::keygen(&entropy, &salt)?;
+        let (noun, op) = Wallet::keygen(&entropy, &salt, "")?;
 
-        let wire = WalletWire::Command(Commands::Keygen).to_wire();
+        let wire = WalletWire::Command(Commands::Keygen { passphrase: None }).to_wire();
 
         let keygen_result = wallet.app.poke(wire, noun.clone()).await?;
 
@@ -1051,8 +2089,8 @@ mod tests {
         // Generate a new key pair
         let mut entropy = [0u8; 32];
         let mut salt = [0u8; 16];
-        let (noun, op) = Wallet::keygen(&entropy, &salt)?;
-        let wire = WalletWire::Command(Commands::Keygen).to_wire();
+        let (noun, op) = Wallet::keygen(&entropy, &salt, "")?;
+        let wire = WalletWire::Command(Commands::Keygen { passphrase: None }).to_wire();
         let _ = wallet.app.poke(wire, noun.clone()).await?;
 
         // Derive a child key
@@ -1082,6 +2120,50 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_new_address() -> Result<(), NockAppError> {
+        init_tracing();
+        let cli = BootCli::parse_from(&["--new"]);
+        let nockapp = boot::setup(KERNEL, Some(cli.clone()), &[], "wallet", None)
+            .await
+            .map_err(|e| CrownError::Unknown(e.to_string()))?;
+        let mut wallet = Wallet::new(nockapp);
+        let entropy = [0u8; 32];
+        let salt = [0u8; 16];
+        let (noun, _) = Wallet::keygen(&entropy, &salt, "")?;
+        let wire = WalletWire::Command(Commands::Keygen { passphrase: None }).to_wire();
+        let _ = wallet.app.poke(wire, noun).await?;
+
+        // Two receive addresses and a change address in account 0, then a hardened path
+        for change in [false, false, true] {
+            let (noun, _) = Wallet::new_address(0, change, &None)?;
+            let wire = WalletWire::Command(Commands::NewAddress {
+                account: 0,
+                change,
+                label: None,
+            })
+            .to_wire();
+            let result = wallet.app.poke(wire, noun).await?;
+            let exit_cause = unsafe { result[1].root() };
+            let code = exit_cause.as_cell()?.tail();
+            assert!(unsafe { code.raw_equals(&D(0)) }, "Expected exit code 0");
+        }
+        let path = "m/44'/0/3".to_string();
+        let (noun, _) = Wallet::derive_path(&path, &None)?;
+        let wire = WalletWire::Command(Commands::DerivePath { path, label: None }).to_wire();
+        let result = wallet.app.poke(wire, noun).await?;
+        let exit_cause = unsafe { result[1].root() };
+        let code = exit_cause.as_cell()?.tail();
+        assert!(unsafe { code.raw_equals(&D(0)) }, "Expected exit code 0");
+
+        let (noun, _) = Wallet::list_addresses()?;
+        let wire = WalletWire::Command(Commands::ListAddresses).to_wire();
+        let result = wallet.app.poke(wire, noun).await?;
+        assert_eq!(result.len(), 2, "Expected the addresses and an exit");
+        Ok(())
+    }
+
     // TODO make this a real test by creating and signing a real draft
     #[tokio::test]
     #[ignore]
@@ -1101,6 +2183,10 @@ mod tests {
         let wire = WalletWire::Command(Commands::SignTx {
             draft: bundle_path.to_string(),
             index: None,
+            device: None,
+            device_path: DerivationPath::default(),
+            vendor_id: None,
+            product_id: None,
         })
         .to_wire();
 
@@ -1113,6 +2199,10 @@ mod tests {
         let wire = WalletWire::Command(Commands::SignTx {
             draft: bundle_path.to_string(),
             index: Some(1),
+            device: None,
+            device_path: DerivationPath::default(),
+            vendor_id: None,
+            product_id: None,
         })
         .to_wire();
 
@@ -1124,6 +2214,10 @@ mod tests {
         let wire = WalletWire::Command(Commands::SignTx {
             draft: bundle_path.to_string(),
             index: Some(255),
+            device: None,
+            device_path: DerivationPath::default(),
+            vendor_id: None,
+            product_id: None,
         })
         .to_wire();
 
@@ -1138,6 +2232,37 @@ mod tests {
     }
 
     // Tests for Cold Side Commands
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_restore() -> Result<(), NockAppError> {
+        init_tracing();
+        let cli = BootCli::parse_from(&["--new"]);
+        let nockapp = boot::setup(KERNEL, Some(cli.clone()), &[], "wallet", None)
+            .await
+            .map_err(|e| CrownError::Unknown(e.to_string()))?;
+        let mut wallet = Wallet::new(nockapp);
+        let valid = format!("{} art", vec!["Abandon"; 23].join("  "));
+        // the last word carries the checksum
+        let invalid = format!("{} zoo", vec!["abandon"; 23].join(" "));
+        for (mnemonic, expected) in [(valid, 0), (invalid, 1)] {
+            let (noun, _) = Wallet::restore(&mnemonic, "TREZOR")?;
+            let wire = WalletWire::Command(Commands::Restore {
+                mnemonic,
+                passphrase: Some("TREZOR".to_string()),
+            })
+            .to_wire();
+            let result = wallet.app.poke(wire, noun).await?;
+            let exit_cause = unsafe { result[1].root() };
+            let code = exit_cause.as_cell()?.tail();
+            assert!(
+                unsafe { code.raw_equals(&D(expected)) },
+                "Expected exit code {}",
+                expected
+            );
+        }
+        Ok(())
+    }
+
     #[tokio::test]
     #[cfg_attr(miri, ignore)]
     async fn test_gen_master_privkey() -> Result<(), NockAppError> {
@@ -1331,14 +2456,24 @@ mod tests {
         let gifts = "1,2".to_string();
         let fee = 1;
 
-        let (noun, op) =
-            Wallet::simple_spend(names.clone(), recipients.clone(), gifts.clone(), fee, None)?;
+        let (noun, op) = Wallet::simple_spend(
+            Some(names.clone()),
+            recipients.clone(),
+            gifts.clone(),
+            fee,
+            None,
+            CoinSelection::Bnb,
+            true,
+            &[],
+        )?;
         let wire = WalletWire::Command(Commands::SimpleSpend {
-            names: names.clone(),
+            names: Some(names.clone()),
             recipients: recipients.clone(),
             gifts: gifts.clone(),
-            fee: fee.clone(),
+            fee: Fee::Nicks(fee),
+            fee_target: 3,
             index: None,
+            selection: CoinSelection::Bnb,
         })
         .to_wire();
         let spend_result = wallet.app.poke(wire, noun.clone()).await?;
@@ -1365,8 +2500,16 @@ mod tests {
 
         // generate keys
         let (genkey_noun, genkey_op) = Wallet::gen_master_privkey("correct horse battery staple")?;
-        let (spend_noun, spend_op) =
-            Wallet::simple_spend(names.clone(), recipients.clone(), gifts.clone(), fee, None)?;
+        let (spend_noun, spend_op) = Wallet::simple_spend(
+            Some(names.clone()),
+            recipients.clone(),
+            gifts.clone(),
+            fee,
+            None,
+            CoinSelection::Bnb,
+            true,
+            &[],
+        )?;
 
         let wire1 = WalletWire::Command(Commands::GenMasterPrivkey {
             seedphrase: "correct horse battery staple".to_string(),
@@ -1376,11 +2519,13 @@ mod tests {
         println!("genkey_result: {:?}", genkey_result);
 
         let wire2 = WalletWire::Command(Commands::SimpleSpend {
-            names: names.clone(),
+            names: Some(names.clone()),
             recipients: recipients.clone(),
             gifts: gifts.clone(),
-            fee: fee.clone(),
+            fee: Fee::Nicks(fee),
+            fee_target: 3,
             index: None,
+            selection: CoinSelection::Bnb,
         })
         .to_wire();
         let spend_result = wallet.app.poke(wire2, spend_noun.clone()).await?;
@@ -1389,6 +2534,197 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_encrypt_keys() -> Result<(), NockAppError> {
+        init_tracing();
+        let cli = BootCli::parse_from(&["--new"]);
+        let nockapp = boot::setup(KERNEL, Some(cli.clone()), &[], "wallet", None)
+            .await
+            .map_err(|e| CrownError::Unknown(e.to_string()))?;
+        let mut wallet = Wallet::new(nockapp);
+
+        let seedphrase = "correct horse battery staple".to_string();
+        let (noun, _) = Wallet::gen_master_privkey(&seedphrase)?;
+        let wire = WalletWire::Command(Commands::GenMasterPrivkey { seedphrase }).to_wire();
+        wallet.app.poke(wire, noun).await?;
+
+        let (noun, _) = Wallet::encrypt_keys("hunter2")?;
+        let wire = WalletWire::Command(Commands::EncryptKeys {
+            new_passphrase: None,
+        })
+        .to_wire();
+        let result = wallet.app.poke(wire, noun).await?;
+        let exit_cause = unsafe { result[1].root() };
+        let code = exit_cause.as_cell()?.tail();
+        assert!(unsafe { code.raw_equals(&D(0)) }, "Expected exit code 0");
+
+        // The seed phrase is sealed, so showing it waits for the passphrase
+        let (noun, _) = Wallet::show_seedphrase()?;
+        let wire = WalletWire::Command(Commands::ShowSeedphrase).to_wire();
+        let result = wallet.app.poke(wire, noun).await?;
+        assert_eq!(result.len(), 1, "Expected only a passphrase request");
+        let request = unsafe { result[0].root() }.as_cell()?;
+        assert!(request.head().eq_bytes(b"passphrase"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_watch_address() -> Result<(), NockAppError> {
+        init_tracing();
+        let cli = BootCli::parse_from(&["--new"]);
+        let nockapp = boot::setup(KERNEL, Some(cli.clone()), &[], "wallet", None)
+            .await
+            .map_err(|e| CrownError::Unknown(e.to_string()))?;
+        let mut wallet = Wallet::new(nockapp);
+
+        // A wallet with no keys at all can watch an address
+        let pubkey = "3HKKp7xZgCw1mhzk4iw735S2ZTavCLHc8YDGRP6G9sSTrRGsaPBu1AqJ8cBDiw2LwhRFnQG7S3N9N9okc28uBda6oSAUCBfMSg5uC9cefhrFrvXVGomoGcRvcFZTWuJzm3ch".to_string();
+        let label = Some("exchange".to_string());
+        let (noun, _) = Wallet::watch_address(&pubkey, &label)?;
+        let wire = WalletWire::Command(Commands::WatchAddress { pubkey, label }).to_wire();
+        let result = wallet.app.poke(wire, noun).await?;
+        let exit_cause = unsafe { result[1].root() };
+        let code = exit_cause.as_cell()?.tail();
+        assert!(unsafe { code.raw_equals(&D(0)) }, "Expected exit code 0");
+
+        let (noun, _) = Wallet::list_watched()?;
+        let wire = WalletWire::Command(Commands::ListWatched).to_wire();
+        let result = wallet.app.poke(wire, noun).await?;
+        assert_eq!(
+            result.len(),
+            2,
+            "Expected the watched addresses and an exit"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_contacts() -> Result<(), NockAppError> {
+        init_tracing();
+        let cli = BootCli::parse_from(&["--new"]);
+        let nockapp = boot::setup(KERNEL, Some(cli.clone()), &[], "wallet", None)
+            .await
+            .map_err(|e| CrownError::Unknown(e.to_string()))?;
+        let mut wallet = Wallet::new(nockapp);
+
+        let name = "alice".to_string();
+        let recipient = "3HKKp7xZgCw1mhzk4iw735S2ZTavCLHc8YDGRP6G9sSTrRGsaPBu1AqJ8cBDiw2LwhRFnQG7S3N9N9okc28uBda6oSAUCBfMSg5uC9cefhrFrvXVGomoGcRvcFZTWuJzm3ch".to_string();
+        let (noun, _) = Wallet::add_contact(&name, &recipient)?;
+        let wire = WalletWire::Command(Commands::AddContact {
+            name: name.clone(),
+            recipient,
+        })
+        .to_wire();
+        let result = wallet.app.poke(wire, noun).await?;
+        assert_eq!(result.len(), 2, "Expected the contact and an exit");
+
+        let (noun, _) = Wallet::list_contacts()?;
+        let wire = WalletWire::Command(Commands::ListContacts).to_wire();
+        let result = wallet.app.poke(wire, noun).await?;
+        assert_eq!(result.len(), 2, "Expected the contacts and an exit");
+
+        let (noun, _) = Wallet::remove_contact(&name)?;
+        let wire = WalletWire::Command(Commands::RemoveContact { name }).to_wire();
+        let result = wallet.app.poke(wire, noun).await?;
+        let code = unsafe { result[0].root() }.as_cell()?.tail();
+        assert!(unsafe { code.raw_equals(&D(0)) }, "Expected exit code 0");
+
+        // Only transactions in the history can be labeled
+        let (noun, _) = Wallet::label_transaction("nope", "rent")?;
+        let wire = WalletWire::Command(Commands::LabelTransaction {
+            id: "nope".into(),
+            label: "rent".into(),
+        })
+        .to_wire();
+        let result = wallet.app.poke(wire, noun).await?;
+        let code = unsafe { result[1].root() }.as_cell()?.tail();
+        assert!(unsafe { code.raw_equals(&D(1)) }, "Expected exit code 1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_recipients() {
+        assert_eq!(
+            parse_recipients("[1 pk1],[2 pk2,pk3]"),
+            vec![(1, vec!["pk1".to_string()]), (2, vec!["pk2".to_string(), "pk3".to_string()])]
+        );
+        assert_eq!(
+            parse_recipients("alice"),
+            vec![(1, vec!["alice".to_string()])]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_history() -> Result<(), NockAppError> {
+        init_tracing();
+        let cli = BootCli::parse_from(&["--new"]);
+        let nockapp = boot::setup(KERNEL, Some(cli.clone()), &[], "wallet", None)
+            .await
+            .map_err(|e| CrownError::Unknown(e.to_string()))?;
+        let mut wallet = Wallet::new(nockapp);
+
+        // An empty history is still written
+        for format in [HistoryFormat::Csv, HistoryFormat::Json] {
+            let (noun, _) = Wallet::history(format)?;
+            let wire = WalletWire::Command(Commands::History { format }).to_wire();
+            let result = wallet.app.poke(wire, noun).await?;
+            assert_eq!(result.len(), 2, "Expected the history file and an exit");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_fee() {
+        assert_eq!(Fee::from_str("auto"), Ok(Fee::Auto));
+        assert_eq!(Fee::from_str("10"), Ok(Fee::Nicks(10)));
+        assert!(Fee::from_str("ten").is_err());
+    }
+
+    #[test]
+    fn test_selection() {
+        let cli = WalletCli::try_parse_from([
+            "wallet", "simple-spend", "--recipients", "pk1", "--gifts", "1", "--fee", "1",
+        ])
+        .expect("names are optional");
+        match cli.command {
+            Commands::SimpleSpend {
+                names, selection, ..
+            } => {
+                assert_eq!(names, None);
+                assert_eq!(selection, CoinSelection::Bnb);
+            }
+            _ => panic!("Expected simple-spend"),
+        }
+        assert_eq!(CoinSelection::LargestFirst.as_tas(), "largest-first");
+    }
+
+    #[test]
+    fn test_offline_signing_commands() {
+        let cli = WalletCli::try_parse_from([
+            "wallet", "build-tx", "--recipients", "pk1", "--gifts", "1", "--fee", "auto",
+        ])
+        .expect("build-tx takes simple-spend's arguments");
+        assert!(matches!(
+            cli.command,
+            Commands::BuildTx { fee: Fee::Auto, .. }
+        ));
+        let cli = WalletCli::try_parse_from(["wallet", "broadcast-tx", "--draft", "a.draft"])
+            .expect("broadcast-tx is send-tx");
+        assert!(matches!(cli.command, Commands::SendTx { .. }));
+    }
+
+    #[test]
+    fn test_split_outside_brackets() {
+        assert_eq!(
+            split_outside_brackets("[1 pk1],[2 pk2,pk3,pk4]"),
+            vec!["[1 pk1]", "[2 pk2,pk3,pk4]"]
+        );
+        assert_eq!(split_outside_brackets("pk1,pk2"), vec!["pk1", "pk2"]);
+    }
+
     #[tokio::test]
     #[cfg_attr(miri, ignore)]
     async fn test_update_balance() -> Result<(), NockAppError> {
@@ -1408,6 +2744,26 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_rescan() -> Result<(), NockAppError> {
+        init_tracing();
+        let cli = BootCli::parse_from(&["--new"]);
+        let nockapp = boot::setup(KERNEL, Some(cli.clone()), &[], "wallet", None)
+            .await
+            .map_err(|e| CrownError::Unknown(e.to_string()))?;
+        let mut wallet = Wallet::new(nockapp);
+
+        // The replay starts by asking the node for the balance before the height
+        let (noun, _) = Wallet::rescan(10)?;
+        let wire = WalletWire::Command(Commands::Rescan { from_height: 10 }).to_wire();
+        let result = wallet.app.poke(wire, noun).await?;
+        assert_eq!(result.len(), 1, "Expected a peek at the node");
+        let effect = unsafe { result[0].root() }.as_cell()?;
+        assert!(effect.head().eq_bytes(b"npc"));
+        Ok(())
+    }
+
     #[tokio::test]
     #[cfg_attr(miri, ignore)]
     async fn test_list_notes() -> Result<(), NockAppError> {
@@ -0,0 +1,225 @@
+//! Sending memos to other wallets, and reading the ones sent to ours, through a node's JSON-RPC.
+//!
+//! `send-memo` has the kernel sign a `[%memo to jam]` effect, which is handed to a node run with
+//! `--relay-memos` over `memo_send`, and the node's relays pass it on to each other. `memos` has
+//! the kernel list our public keys in a `[%fetch-memos pubkeys]` effect, asks the node for what
+//! it holds for each over `memo_getInbox`, and pokes the jams back as `[%read-memos jams]` for the
+//! kernel to check and show. Memos aren't encrypted, so relays can read them.
+use nockapp::driver::{make_driver, IODriverFn, PokeResult};
+use nockapp::noun::slab::NounSlab;
+use nockapp::utils::make_tas;
+use nockapp::wire::{Wire, WireRepr};
+use nockapp::{AtomExt, Bytes, NounExt};
+use nockvm::noun::{Atom, Cell, Noun, D, T};
+use reqwest::Client;
+use serde_json::{json, Value};
+use thiserror::Error;
+use tracing::{error, info};
+
+#[derive(Debug, Error)]
+pub enum MemoError {
+    #[error("Could not reach the node at {0}: {1}")]
+    Http(String, reqwest::Error),
+    #[error("The node at {0} answered: {1}")]
+    Rpc(String, String),
+    #[error("Malformed answer from the node: {0}")]
+    Malformed(String),
+}
+
+pub enum MemoWire {
+    Inbox,
+}
+
+impl Wire for MemoWire {
+    const VERSION: u64 = 1;
+    const SOURCE: &'static str = "memo";
+
+    fn to_wire(&self) -> WireRepr {
+        WireRepr::new(MemoWire::SOURCE, MemoWire::VERSION, vec!["inbox".into()])
+    }
+}
+
+/// An effect the memo driver handles
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MemoEffect {
+    /// `[%memo to jam]`
+    Send { to: String, jam: Vec<u8> },
+    /// `[%fetch-memos pubkeys]`
+    Fetch { pubkeys: Vec<String> },
+}
+
+fn memo_effect(effect: Noun) -> Option<MemoEffect> {
+    let cell = effect.as_cell().ok()?;
+    let text = |noun: Noun| noun.as_atom().ok()?.into_string().ok();
+    if cell.head().eq_bytes(b"memo") {
+        let rest = cell.tail().as_cell().ok()?;
+        let jam = rest.tail().as_atom().ok()?;
+        Some(MemoEffect::Send {
+            to: text(rest.head())?,
+            jam: atom_bytes(jam),
+        })
+    } else if cell.head().eq_bytes(b"fetch-memos") {
+        let pubkeys = cell
+            .tail()
+            .list_iter()
+            .map(text)
+            .collect::<Option<Vec<String>>>()?;
+        Some(MemoEffect::Fetch { pubkeys })
+    } else {
+        None
+    }
+}
+
+/// The bytes of an atom, without the zeros past its end
+fn atom_bytes(atom: Atom) -> Vec<u8> {
+    let mut bytes = atom.as_ne_bytes().to_vec();
+    while bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+    bytes
+}
+
+/// Builds the `[%read-memos jams]` poke
+fn read_memos(jams: &[Vec<u8>]) -> NounSlab {
+    let mut slab = NounSlab::new();
+    let list = jams.iter().rev().fold(D(0), |acc, jam| {
+        let atom = Atom::from_bytes(&mut slab, &Bytes::from(jam.clone())).as_noun();
+        Cell::new(&mut slab, atom, acc).as_noun()
+    });
+    let tag = make_tas(&mut slab, "read-memos").as_noun();
+    let poke = T(&mut slab, &[tag, list]);
+    slab.set_root(poke);
+    slab
+}
+
+async fn call(client: &Client, url: &str, method: &str, params: Value) -> Result<Value, MemoError> {
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let reply: Value = client
+        .post(url)
+        .json(&request)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| MemoError::Http(url.to_string(), e))?
+        .json()
+        .await
+        .map_err(|e| MemoError::Http(url.to_string(), e))?;
+    if let Some(error) = reply.get("error") {
+        let message = error["message"].as_str().unwrap_or("unknown error");
+        return Err(MemoError::Rpc(url.to_string(), message.to_string()));
+    }
+    Ok(reply["result"].clone())
+}
+
+/// The jams the node at `url` holds for each of `pubkeys`
+async fn fetch(client: &Client, url: &str, pubkeys: &[String]) -> Result<Vec<Vec<u8>>, MemoError> {
+    let mut jams = Vec::new();
+    for pubkey in pubkeys {
+        let held = call(client, url, "memo_getInbox", json!([pubkey])).await?;
+        let held = held
+            .as_array()
+            .ok_or_else(|| MemoError::Malformed("memo_getInbox is not a list".into()))?;
+        for jam in held {
+            let jam = jam.as_str().and_then(from_hex).ok_or_else(|| {
+                MemoError::Malformed("memo_getInbox has a jam that isn't hex".into())
+            })?;
+            jams.push(jam);
+        }
+    }
+    Ok(jams)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Hands the memo the kernel signs to the node's JSON-RPC at `url`, or fetches the memos for our
+/// keys from it and pokes them back
+pub fn memo_driver(url: String) -> IODriverFn {
+    make_driver(move |handle| async move {
+        let client = Client::new();
+        let effect = loop {
+            match handle.next_effect().await {
+                Ok(effect) => {
+                    if let Some(effect) = memo_effect(unsafe { *effect.root() }) {
+                        break effect;
+                    }
+                }
+                Err(e) => {
+                    error!("Error in memo driver: {:?}", e);
+                }
+            }
+        };
+        match effect {
+            MemoEffect::Send { to, jam } => {
+                match call(&client, &url, "memo_send", json!([to, to_hex(&jam)])).await {
+                    Ok(Value::Bool(true)) => println!("Sent a memo to {}", to),
+                    Ok(_) => println!("The node already had that memo"),
+                    Err(e) => {
+                        error!("{}", e);
+                        handle.exit.exit(1).await?;
+                        return Ok(());
+                    }
+                }
+                handle.exit.exit(0).await?;
+            }
+            MemoEffect::Fetch { pubkeys } => match fetch(&client, &url, &pubkeys).await {
+                Ok(jams) => {
+                    info!("The node holds {} memos for our keys", jams.len());
+                    let wire = MemoWire::Inbox.to_wire();
+                    if let PokeResult::Nack = handle.poke(wire, read_memos(&jams)).await? {
+                        error!("The wallet did not accept the memos");
+                        handle.exit.exit(1).await?;
+                    }
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    handle.exit.exit(1).await?;
+                }
+            },
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm_macros::tas;
+
+    use super::*;
+
+    #[test]
+    fn test_memo_effect() {
+        let mut slab: NounSlab = NounSlab::new();
+        let to = Atom::from_bytes(&mut slab, &Bytes::from_static(b"abc")).as_noun();
+        let jam = Atom::new(&mut slab, 0x0102).as_noun();
+        let effect = T(&mut slab, &[D(tas!(b"memo")), to, jam]);
+        assert_eq!(
+            memo_effect(effect),
+            Some(MemoEffect::Send {
+                to: "abc".into(),
+                jam: vec![2, 1],
+            })
+        );
+        let pubkeys = T(&mut slab, &[to, to, D(0)]);
+        let tag = make_tas(&mut slab, "fetch-memos").as_noun();
+        let effect = T(&mut slab, &[tag, pubkeys]);
+        assert_eq!(
+            memo_effect(effect),
+            Some(MemoEffect::Fetch {
+                pubkeys: vec!["abc".into(), "abc".into()],
+            })
+        );
+        assert_eq!(from_hex(&to_hex(&[0, 255, 16])), Some(vec![0, 255, 16]));
+    }
+}
@@ -0,0 +1,675 @@
+//! Signing drafts on a hardware wallet.
+//!
+//! With `sign-tx --device`, the wallet kernel describes each input of the draft along with the
+//! hash of its spend. A [`SigningBackend`] shows the description on the device and signs the
+//! hash once the signer confirms it there. The kernel then checks every signature against the
+//! note's lock before adding it to the draft, so a faulty device can't corrupt a draft.
+//!
+//! Devices speak APDUs, framed into 64-byte HID reports the way Ledger devices frame them.
+//! `ledger` finds a Ledger with the Nockchain app open, and `hid` is any other device that
+//! speaks the same protocol, found by `--vendor-id` and `--product-id`. Talking to HID devices
+//! needs the wallet built with the `hid` feature.
+//!
+//! To sign, the wallet sends `INS_SIGN` with the payload
+//! `path-len:u8 path:u32be* summary-len:u16be summary message-len:u8 message:u64le*`, chunked into
+//! APDUs of at most 255 bytes, with `P1_MORE` on every chunk after the first and `P2_MORE` on
+//! every chunk before the last. The device answers
+//! `pubkey:[u8; 97] chal:u64le*8 sig:u64le*8`, followed by the status word.
+use std::fmt;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use nockapp::driver::{make_driver, IODriverFn, PokeResult};
+use nockapp::noun::slab::NounSlab;
+use nockapp::utils::make_tas;
+use nockapp::wire::{Wire, WireRepr};
+use nockapp::{AtomExt, Bytes, NounExt};
+use nockvm::noun::{Atom, Cell, Noun, D, T};
+use thiserror::Error;
+use tracing::{error, info};
+
+#[cfg_attr(not(feature = "hid"), allow(dead_code))]
+pub const LEDGER_VENDOR_ID: u16 = 0x2c97;
+/// The HID usage page of a Ledger's APDU interface
+#[cfg_attr(not(feature = "hid"), allow(dead_code))]
+const LEDGER_USAGE_PAGE: u16 = 0xffa0;
+
+const CLA: u8 = 0x4e;
+const INS_SIGN: u8 = 0x04;
+const P1_MORE: u8 = 0x80;
+const P2_MORE: u8 = 0x80;
+const MAX_APDU_DATA: usize = 255;
+
+const SW_OK: u16 = 0x9000;
+const SW_REJECTED: u16 = 0x6985;
+
+#[cfg_attr(not(feature = "hid"), allow(dead_code))]
+const REPORT_LEN: usize = 64;
+#[cfg_attr(not(feature = "hid"), allow(dead_code))]
+const TAG_APDU: u8 = 0x05;
+/// The channel Ledger devices expect APDUs on
+#[cfg_attr(not(feature = "hid"), allow(dead_code))]
+const CHANNEL: u16 = 0x0101;
+
+pub const PUBKEY_LEN: usize = 97;
+const BELTS_PER_HALF: usize = 8;
+
+#[derive(Debug, Error)]
+pub enum SignerError {
+    #[cfg_attr(not(feature = "hid"), allow(dead_code))]
+    #[error("No {0} found, is it plugged in and unlocked with the Nockchain app open?")]
+    NotFound(String),
+    #[error(
+        "The wallet was built without hardware wallet support, rebuild it with --features hid"
+    )]
+    Unsupported,
+    #[cfg_attr(not(feature = "hid"), allow(dead_code))]
+    #[error("Could not talk to the device: {0}")]
+    Transport(String),
+    #[error("The signature was rejected on the device")]
+    Rejected,
+    #[error("The device answered with status {0:#06x}")]
+    Status(u16),
+    #[error("Malformed answer from the device: {0}")]
+    Malformed(String),
+}
+
+/// The devices `sign-tx --device` can sign on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Device {
+    /// A Ledger with the Nockchain app open
+    Ledger,
+    /// Any HID device speaking the same protocol, found by vendor and product id
+    Hid,
+}
+
+/// What a device signs for one input of a draft
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignRequest {
+    /// The base58 first name of the input's note
+    pub input: String,
+    /// The hash of the input's spend, as belts
+    pub message: Vec<u64>,
+    /// What the spend does, to confirm on the device
+    pub summary: String,
+}
+
+/// A signature made on a device, by the key at the path it was asked to sign with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceSignature {
+    /// The serialized public key, most significant byte first
+    pub pubkey: Vec<u8>,
+    pub chal: [u64; BELTS_PER_HALF],
+    pub sig: [u64; BELTS_PER_HALF],
+}
+
+/// Something that can sign spends without the wallet seeing the private key
+pub trait SigningBackend: Send {
+    /// What to call the device when asking the signer to confirm on it
+    fn name(&self) -> String;
+
+    /// Signs `request` with the key at `path`, once the signer confirms it
+    fn sign(&mut self, path: &[u32], request: &SignRequest)
+        -> Result<DeviceSignature, SignerError>;
+}
+
+/// Sends an APDU to a device and returns its answer, status word included
+pub trait Transport: Send {
+    fn exchange(&mut self, apdu: &Apdu) -> Result<Vec<u8>, SignerError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Apdu {
+    pub cla: u8,
+    pub ins: u8,
+    pub p1: u8,
+    pub p2: u8,
+    pub data: Vec<u8>,
+}
+
+impl Apdu {
+    #[cfg_attr(not(feature = "hid"), allow(dead_code))]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoded = vec![self.cla, self.ins, self.p1, self.p2, self.data.len() as u8];
+        encoded.extend_from_slice(&self.data);
+        encoded
+    }
+}
+
+/// A derivation path like m/44'/0/1, with ' marking hardened indices
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DerivationPath(pub Vec<u32>);
+
+impl FromStr for DerivationPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let rest = match s.strip_prefix('m') {
+            Some(rest) => rest,
+            None => return Err(format!("Derivation path {} must start with m", s)),
+        };
+        let mut path = Vec::new();
+        for part in rest.split('/').skip(1) {
+            let (index, hardened) = match part.strip_suffix('\'') {
+                Some(index) => (index, true),
+                None => (part, false),
+            };
+            let index: u32 = index
+                .parse()
+                .map_err(|e| format!("Invalid index {} in {}: {}", part, s, e))?;
+            if index >= 1 << 31 {
+                return Err(format!("Index {} in {} is too large", part, s));
+            }
+            path.push(if hardened { index | 1 << 31 } else { index });
+        }
+        if !rest.is_empty() && path.is_empty() {
+            return Err(format!("Invalid derivation path {}", s));
+        }
+        Ok(DerivationPath(path))
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            if index & 1 << 31 == 0 {
+                write!(f, "/{}", index)?;
+            } else {
+                write!(f, "/{}'", index & !(1 << 31))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Signs over any [`Transport`] speaking the protocol in the module docs
+pub struct ApduSigner<T: Transport> {
+    transport: T,
+    name: String,
+}
+
+impl<T: Transport> ApduSigner<T> {
+    #[cfg_attr(not(feature = "hid"), allow(dead_code))]
+    pub fn new(transport: T, name: impl Into<String>) -> Self {
+        ApduSigner {
+            transport,
+            name: name.into(),
+        }
+    }
+}
+
+impl<T: Transport> SigningBackend for ApduSigner<T> {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn sign(
+        &mut self,
+        path: &[u32],
+        request: &SignRequest,
+    ) -> Result<DeviceSignature, SignerError> {
+        let apdus = sign_apdus(path, request);
+        let last = apdus.len() - 1;
+        for (i, apdu) in apdus.iter().enumerate() {
+            let answer = self.transport.exchange(apdu)?;
+            if i < last {
+                status(&answer)?;
+            } else {
+                return parse_signature(&answer);
+            }
+        }
+        unreachable!("there is always at least one sign APDU")
+    }
+}
+
+fn sign_payload(path: &[u32], request: &SignRequest) -> Vec<u8> {
+    let mut payload = vec![path.len() as u8];
+    for index in path {
+        payload.extend_from_slice(&index.to_be_bytes());
+    }
+    let summary = request.summary.as_bytes();
+    payload.extend_from_slice(&(summary.len() as u16).to_be_bytes());
+    payload.extend_from_slice(summary);
+    payload.push(request.message.len() as u8);
+    for belt in &request.message {
+        payload.extend_from_slice(&belt.to_le_bytes());
+    }
+    payload
+}
+
+fn sign_apdus(path: &[u32], request: &SignRequest) -> Vec<Apdu> {
+    let payload = sign_payload(path, request);
+    let chunks: Vec<&[u8]> = payload.chunks(MAX_APDU_DATA).collect();
+    let last = chunks.len() - 1;
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| Apdu {
+            cla: CLA,
+            ins: INS_SIGN,
+            p1: if i == 0 { 0 } else { P1_MORE },
+            p2: if i == last { 0 } else { P2_MORE },
+            data: chunk.to_vec(),
+        })
+        .collect()
+}
+
+/// Splits the status word off an answer, failing unless it's [`SW_OK`]
+fn status(answer: &[u8]) -> Result<&[u8], SignerError> {
+    if answer.len() < 2 {
+        return Err(SignerError::Malformed("no status word".into()));
+    }
+    let (data, sw) = answer.split_at(answer.len() - 2);
+    match u16::from_be_bytes([sw[0], sw[1]]) {
+        SW_OK => Ok(data),
+        SW_REJECTED => Err(SignerError::Rejected),
+        sw => Err(SignerError::Status(sw)),
+    }
+}
+
+fn parse_signature(answer: &[u8]) -> Result<DeviceSignature, SignerError> {
+    let data = status(answer)?;
+    let expected = PUBKEY_LEN + 2 * BELTS_PER_HALF * 8;
+    if data.len() != expected {
+        return Err(SignerError::Malformed(format!(
+            "{} bytes of signature, expected {}",
+            data.len(),
+            expected
+        )));
+    }
+    let (pubkey, rest) = data.split_at(PUBKEY_LEN);
+    let belts: Vec<u64> = rest
+        .chunks(8)
+        .map(|b| u64::from_le_bytes(b.try_into().expect("8 byte chunks")))
+        .collect();
+    let mut chal = [0; BELTS_PER_HALF];
+    let mut sig = [0; BELTS_PER_HALF];
+    chal.copy_from_slice(&belts[..BELTS_PER_HALF]);
+    sig.copy_from_slice(&belts[BELTS_PER_HALF..]);
+    Ok(DeviceSignature {
+        pubkey: pubkey.to_vec(),
+        chal,
+        sig,
+    })
+}
+
+/// Frames an APDU into HID reports: channel, tag, sequence number, and on the first report the
+/// APDU's length, then as much of the APDU as fits, zero padded
+#[cfg_attr(not(feature = "hid"), allow(dead_code))]
+pub fn frame(channel: u16, apdu: &[u8]) -> Vec<[u8; REPORT_LEN]> {
+    let mut data = (apdu.len() as u16).to_be_bytes().to_vec();
+    data.extend_from_slice(apdu);
+    data.chunks(REPORT_LEN - 5)
+        .enumerate()
+        .map(|(seq, chunk)| {
+            let mut report = [0; REPORT_LEN];
+            report[..2].copy_from_slice(&channel.to_be_bytes());
+            report[2] = TAG_APDU;
+            report[3..5].copy_from_slice(&(seq as u16).to_be_bytes());
+            report[5..5 + chunk.len()].copy_from_slice(chunk);
+            report
+        })
+        .collect()
+}
+
+/// Reassembles an answer from the HID reports [`frame`] splits it into
+#[cfg_attr(not(feature = "hid"), allow(dead_code))]
+pub struct Unframer {
+    channel: u16,
+    seq: u16,
+    len: Option<usize>,
+    data: Vec<u8>,
+}
+
+#[cfg_attr(not(feature = "hid"), allow(dead_code))]
+impl Unframer {
+    pub fn new(channel: u16) -> Self {
+        Unframer {
+            channel,
+            seq: 0,
+            len: None,
+            data: Vec::new(),
+        }
+    }
+
+    /// Adds a report, returning the answer once it's whole
+    pub fn push(&mut self, report: &[u8]) -> Result<Option<Vec<u8>>, SignerError> {
+        if report.len() < 5 {
+            return Err(SignerError::Malformed("short HID report".into()));
+        }
+        if u16::from_be_bytes([report[0], report[1]]) != self.channel || report[2] != TAG_APDU {
+            return Err(SignerError::Malformed(
+                "HID report on the wrong channel".into(),
+            ));
+        }
+        if u16::from_be_bytes([report[3], report[4]]) != self.seq {
+            return Err(SignerError::Malformed("HID report out of sequence".into()));
+        }
+        self.seq += 1;
+        let mut chunk = &report[5..];
+        let len = match self.len {
+            Some(len) => len,
+            None => {
+                if chunk.len() < 2 {
+                    return Err(SignerError::Malformed("short HID report".into()));
+                }
+                let len = u16::from_be_bytes([chunk[0], chunk[1]]) as usize;
+                chunk = &chunk[2..];
+                self.len = Some(len);
+                len
+            }
+        };
+        let wanted = (len - self.data.len()).min(chunk.len());
+        self.data.extend_from_slice(&chunk[..wanted]);
+        if self.data.len() == len {
+            Ok(Some(std::mem::take(&mut self.data)))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(feature = "hid")]
+mod hid {
+    use super::*;
+
+    /// APDUs over HID, framed as Ledger devices frame them
+    pub struct HidTransport {
+        device: hidapi::HidDevice,
+    }
+
+    impl HidTransport {
+        pub fn open(vendor_id: u16, product_id: Option<u16>) -> Result<Self, SignerError> {
+            let api = hidapi::HidApi::new().map_err(|e| SignerError::Transport(e.to_string()))?;
+            let info = api
+                .device_list()
+                .find(|d| {
+                    d.vendor_id() == vendor_id
+                        && product_id.is_none_or(|p| d.product_id() == p)
+                        // Ledgers have several interfaces, and report which is which on most
+                        // platforms
+                        && (vendor_id != LEDGER_VENDOR_ID
+                            || d.usage_page() == 0
+                            || d.usage_page() == LEDGER_USAGE_PAGE)
+                })
+                .ok_or_else(|| SignerError::NotFound(format!("device {:04x}", vendor_id)))?;
+            let device = info
+                .open_device(&api)
+                .map_err(|e| SignerError::Transport(e.to_string()))?;
+            Ok(HidTransport { device })
+        }
+    }
+
+    impl Transport for HidTransport {
+        fn exchange(&mut self, apdu: &Apdu) -> Result<Vec<u8>, SignerError> {
+            for report in frame(CHANNEL, &apdu.encode()) {
+                // hidapi wants the report id first, which is always 0
+                let mut with_id = vec![0];
+                with_id.extend_from_slice(&report);
+                self.device
+                    .write(&with_id)
+                    .map_err(|e| SignerError::Transport(e.to_string()))?;
+            }
+            let mut unframer = Unframer::new(CHANNEL);
+            loop {
+                let mut report = [0; REPORT_LEN];
+                // Blocks until the signer confirms or rejects on the device
+                let read = self
+                    .device
+                    .read(&mut report)
+                    .map_err(|e| SignerError::Transport(e.to_string()))?;
+                if let Some(answer) = unframer.push(&report[..read])? {
+                    return Ok(answer);
+                }
+            }
+        }
+    }
+}
+
+/// Finds `device` and opens it for signing
+pub fn open(
+    device: Device,
+    vendor_id: Option<u16>,
+    product_id: Option<u16>,
+) -> Result<Box<dyn SigningBackend>, SignerError> {
+    #[cfg(feature = "hid")]
+    {
+        let (vendor_id, name) = match device {
+            Device::Ledger => (vendor_id.unwrap_or(LEDGER_VENDOR_ID), "Ledger"),
+            Device::Hid => match vendor_id {
+                Some(vendor_id) => (vendor_id, "device"),
+                None => {
+                    return Err(SignerError::NotFound(
+                        "device without --vendor-id".to_string(),
+                    ))
+                }
+            },
+        };
+        let transport = hid::HidTransport::open(vendor_id, product_id)?;
+        Ok(Box::new(ApduSigner::new(transport, name)))
+    }
+    #[cfg(not(feature = "hid"))]
+    {
+        let _ = (device, vendor_id, product_id);
+        Err(SignerError::Unsupported)
+    }
+}
+
+pub enum SignerWire {
+    Signatures,
+}
+
+impl Wire for SignerWire {
+    const VERSION: u64 = 1;
+    const SOURCE: &'static str = "signer";
+
+    fn to_wire(&self) -> WireRepr {
+        WireRepr::new(
+            SignerWire::SOURCE,
+            SignerWire::VERSION,
+            vec!["signatures".into()],
+        )
+    }
+}
+
+/// Parses a `[%sign-requests name requests]` effect
+fn sign_requests(effect: Noun) -> Option<Vec<SignRequest>> {
+    let cell = effect.as_cell().ok()?;
+    if !cell.head().eq_bytes(b"sign-requests") {
+        return None;
+    }
+    let requests = cell.tail().as_cell().ok()?.tail();
+    let text = |noun: Noun| noun.as_atom().ok()?.into_string().ok();
+    requests
+        .list_iter()
+        .map(|request| {
+            let request = request.as_cell().ok()?;
+            let rest = request.tail().as_cell().ok()?;
+            let message = rest
+                .head()
+                .list_iter()
+                .map(|belt| belt.as_atom().ok()?.as_u64().ok())
+                .collect::<Option<Vec<u64>>>()?;
+            Some(SignRequest {
+                input: text(request.head())?,
+                message,
+                summary: text(rest.tail())?,
+            })
+        })
+        .collect()
+}
+
+/// Builds the `[%add-signatures draft sigs]` poke
+fn add_signatures(
+    draft: &Bytes,
+    signed: &[(SignRequest, DeviceSignature)],
+) -> Result<NounSlab, SignerError> {
+    let mut slab = NounSlab::new();
+    let draft_noun = slab
+        .cue_into(draft.clone())
+        .map_err(|e| SignerError::Malformed(format!("Failed to decode draft: {}", e)))?;
+    let sigs = signed.iter().rev().fold(D(0), |acc, (request, signature)| {
+        let input = make_tas(&mut slab, &request.input).as_noun();
+        // Devices send the key most significant byte first, atoms are little endian
+        let pubkey: Vec<u8> = signature.pubkey.iter().rev().copied().collect();
+        let pubkey = Atom::from_bytes(&mut slab, &Bytes::from(pubkey)).as_noun();
+        let belts = |slab: &mut NounSlab, belts: &[u64; BELTS_PER_HALF]| {
+            let belts: Vec<Noun> = belts
+                .iter()
+                .map(|belt| Atom::new(slab, *belt).as_noun())
+                .collect();
+            T(slab, &belts)
+        };
+        let chal = belts(&mut slab, &signature.chal);
+        let sig = belts(&mut slab, &signature.sig);
+        let entry = T(&mut slab, &[input, pubkey, chal, sig]);
+        Cell::new(&mut slab, entry, acc).as_noun()
+    });
+    let tag = make_tas(&mut slab, "add-signatures").as_noun();
+    let poke = T(&mut slab, &[tag, draft_noun, sigs]);
+    slab.set_root(poke);
+    Ok(slab)
+}
+
+/// Signs the requests the kernel makes for `draft` on `backend`, with the key at `path`, and
+/// pokes the signatures back
+pub fn signing_driver(
+    backend: Box<dyn SigningBackend>,
+    path: DerivationPath,
+    draft: Bytes,
+) -> IODriverFn {
+    make_driver(move |handle| async move {
+        let requests = loop {
+            match handle.next_effect().await {
+                Ok(effect) => {
+                    if let Some(requests) = sign_requests(unsafe { *effect.root() }) {
+                        break requests;
+                    }
+                }
+                Err(e) => {
+                    error!("Error in signing driver: {:?}", e);
+                }
+            }
+        };
+        let signed = tokio::task::spawn_blocking(move || {
+            let mut backend = backend;
+            let mut signed = Vec::with_capacity(requests.len());
+            for request in requests {
+                println!(
+                    "Confirm on your {} with key {}: {}",
+                    backend.name(),
+                    path,
+                    request.summary
+                );
+                let signature = backend.sign(&path.0, &request)?;
+                signed.push((request, signature));
+            }
+            Ok::<_, SignerError>(signed)
+        })
+        .await
+        .map_err(|e| nockapp::CrownError::Unknown(e.to_string()))?;
+        match signed.and_then(|signed| add_signatures(&draft, &signed)) {
+            Ok(poke) => {
+                info!("Adding the device's signatures to the draft");
+                let wire = SignerWire::Signatures.to_wire();
+                if let PokeResult::Nack = handle.poke(wire, poke).await? {
+                    error!("The wallet did not accept the device's signatures");
+                    handle.exit.exit(1).await?;
+                }
+            }
+            Err(e) => {
+                error!("{}", e);
+                handle.exit.exit(1).await?;
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers every APDU with a canned signature, keeping what it was sent
+    struct Echo {
+        sent: Vec<Apdu>,
+    }
+
+    impl Transport for Echo {
+        fn exchange(&mut self, apdu: &Apdu) -> Result<Vec<u8>, SignerError> {
+            self.sent.push(apdu.clone());
+            let mut answer = vec![];
+            if apdu.p2 == 0 {
+                answer.extend_from_slice(&[7; PUBKEY_LEN]);
+                for belt in 0..16u64 {
+                    answer.extend_from_slice(&belt.to_le_bytes());
+                }
+            }
+            answer.extend_from_slice(&SW_OK.to_be_bytes());
+            Ok(answer)
+        }
+    }
+
+    #[test]
+    fn test_sign() {
+        let request = SignRequest {
+            input: "first".into(),
+            message: vec![1, 2, 3, 4, 5],
+            summary: "x".repeat(300),
+        };
+        let mut signer = ApduSigner::new(Echo { sent: vec![] }, "test");
+        let signature = signer.sign(&[1, 1 << 31], &request).expect("signature");
+        assert_eq!(signature.pubkey, vec![7; PUBKEY_LEN]);
+        assert_eq!(signature.chal, [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(signature.sig, [8, 9, 10, 11, 12, 13, 14, 15]);
+        // The 300 byte summary doesn't fit in one APDU
+        let sent = &signer.transport.sent;
+        assert_eq!(sent.len(), 2);
+        assert_eq!((sent[0].p1, sent[0].p2), (0, P2_MORE));
+        assert_eq!((sent[1].p1, sent[1].p2), (P1_MORE, 0));
+        let payload: Vec<u8> = sent.iter().flat_map(|apdu| apdu.data.clone()).collect();
+        assert_eq!(payload, sign_payload(&[1, 1 << 31], &request));
+    }
+
+    #[test]
+    fn test_status() {
+        assert!(matches!(status(&[0x69, 0x85]), Err(SignerError::Rejected)));
+        assert!(matches!(
+            status(&[0x6a, 0x80]),
+            Err(SignerError::Status(0x6a80))
+        ));
+        assert!(matches!(
+            parse_signature(&[1, 0x90, 0x00]),
+            Err(SignerError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_frame() {
+        let apdu: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        let reports = frame(CHANNEL, &apdu);
+        assert_eq!(reports.len(), 4);
+        let mut unframer = Unframer::new(CHANNEL);
+        let mut answer = None;
+        for report in &reports {
+            answer = unframer.push(report).expect("report");
+        }
+        assert_eq!(answer, Some(apdu));
+        assert!(Unframer::new(0x0202).push(&reports[0]).is_err());
+    }
+
+    #[test]
+    fn test_derivation_path() {
+        let path = DerivationPath::from_str("m/44'/0/1").expect("path");
+        assert_eq!(path.0, vec![44 | 1 << 31, 0, 1]);
+        assert_eq!(path.to_string(), "m/44'/0/1");
+        assert_eq!(
+            DerivationPath::from_str("m").expect("path").0,
+            Vec::<u32>::new()
+        );
+        assert!(DerivationPath::from_str("44/0").is_err());
+        assert!(DerivationPath::from_str("m/x").is_err());
+    }
+}
@@ -0,0 +1,599 @@
+//! Building, signing and serializing transactions without the CLI.
+//!
+//! A [`TxBuilder`] holds what `simple-spend` takes, and [`TxBuilder::build`] has the wallet's
+//! kernel, booted in this process as a [`WalletKernel`], make a [`Draft`] of it, signed unless
+//! the builder is [`TxBuilder::unsigned`]. [`WalletKernel::sign`] signs a draft made elsewhere,
+//! like an offline machine's, and [`WalletKernel::transaction`] makes the [`Transaction`] to
+//! broadcast, whose [`Transaction::to_hex`] is what a node's `mempool_submitTransaction` takes.
+//!
+//! ```no_run
+//! # async fn pay() -> Result<(), nockchain_wallet::TxError> {
+//! use nockchain_wallet::{Recipient, TxBuilder, WalletKernel};
+//!
+//! let mut wallet = WalletKernel::open().await?;
+//! let draft = TxBuilder::new()
+//!     .pay(Recipient::pubkey("<pk>"), 1_000)
+//!     .fee(10)
+//!     .build(&mut wallet)
+//!     .await?;
+//! let tx = wallet.transaction(&draft).await?;
+//! println!("{}", tx.to_hex());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The kernel spends the notes it saw when the wallet last synced, so run `nockchain-wallet
+//! update-balance` against a node first. Drafts are handed back rather than written to
+//! `drafts/`. The wallet's state is saved after each call, so the CLI shouldn't use the same data
+//! directory at the same time. A wallet with encrypted keys needs [`WalletKernel::passphrase`]
+//! to sign.
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use getrandom::getrandom;
+use kernels::wallet::KERNEL;
+use nockapp::kernel::boot;
+use nockapp::noun::slab::NounSlab;
+use nockapp::utils::make_tas;
+use nockapp::wire::{Wire, WireRepr};
+use nockapp::{system_data_dir, AtomExt, Bytes, NockApp, NockAppError, NounExt};
+use nockvm::noun::{Atom, Cell, Noun, D, NO, T, YES};
+use thiserror::Error;
+use zkvm_jetpack::hot::produce_prover_hot_state;
+
+/// Highest key index the wallet signs with
+const MAX_KEY_INDEX: u64 = 255;
+
+#[derive(Debug, Error)]
+pub enum TxError {
+    #[error("Kernel setup failed: {0}")]
+    Boot(String),
+    #[error("Kernel error: {0}")]
+    Kernel(#[from] NockAppError),
+    #[error("A transaction needs at least one payment")]
+    NoPayments,
+    #[error("{0} notes named for {1} payments")]
+    Notes(usize, usize),
+    #[error("Key index {0} is over 255")]
+    KeyIndex(u64),
+    #[error("The keys are encrypted, and no passphrase was given")]
+    Locked,
+    #[error("The wallet refused: {0}")]
+    Refused(String),
+    #[error("The wallet gave no {0}")]
+    Missing(&'static str),
+    #[error("Not a draft: {0}")]
+    Draft(String),
+    #[error("No entropy: {0}")]
+    Entropy(String),
+}
+
+/// How simple-spend picks notes when it isn't given names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CoinSelection {
+    /// Look for notes that need no change, then fall back to largest-first
+    Bnb,
+    /// Spend the fewest notes
+    LargestFirst,
+    /// Spend notes in a random order
+    Random,
+}
+
+impl CoinSelection {
+    pub fn as_tas(self) -> &'static str {
+        match self {
+            CoinSelection::Bnb => "bnb",
+            CoinSelection::LargestFirst => "largest-first",
+            CoinSelection::Random => "random",
+        }
+    }
+}
+
+/// Who a payment goes to: a lock that any `m` of `pubkeys` can spend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipient {
+    pub m: u64,
+    /// Base58 public keys, or names from the wallet's address book
+    pub pubkeys: Vec<String>,
+}
+
+impl Recipient {
+    pub fn pubkey(pubkey: impl Into<String>) -> Self {
+        Recipient {
+            m: 1,
+            pubkeys: vec![pubkey.into()],
+        }
+    }
+
+    pub fn multisig(m: u64, pubkeys: Vec<String>) -> Self {
+        Recipient { m, pubkeys }
+    }
+
+    /// `[m pubkeys]`, the way the kernel takes a recipient
+    fn to_noun(&self, slab: &mut NounSlab) -> Noun {
+        let pubkeys = list(slab, &self.pubkeys, |slab, pubkey| {
+            make_tas(slab, pubkey).as_noun()
+        });
+        T(slab, &[D(self.m), pubkeys])
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Payment {
+    to: Recipient,
+    nicks: u64,
+    /// Kept by the wallet to label the transaction with, or empty
+    memo: String,
+}
+
+/// A transaction to build, paying one output per payment
+#[derive(Debug, Clone)]
+pub struct TxBuilder {
+    payments: Vec<Payment>,
+    /// `[first last]` names of the notes to spend, one per payment, or none to let the wallet
+    /// pick
+    notes: Vec<(String, String)>,
+    fee: u64,
+    key_index: Option<u64>,
+    selection: CoinSelection,
+    sign: bool,
+}
+
+impl Default for TxBuilder {
+    fn default() -> Self {
+        TxBuilder {
+            payments: Vec::new(),
+            notes: Vec::new(),
+            fee: 0,
+            key_index: None,
+            selection: CoinSelection::Bnb,
+            sign: true,
+        }
+    }
+}
+
+impl TxBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pay(self, to: Recipient, nicks: u64) -> Self {
+        self.pay_with_memo(to, nicks, "")
+    }
+
+    /// Pays `to`, labelling the transaction with `memo` in the wallet's history once it's sent.
+    /// Memos don't go on chain.
+    pub fn pay_with_memo(mut self, to: Recipient, nicks: u64, memo: &str) -> Self {
+        self.payments.push(Payment {
+            to,
+            nicks,
+            memo: memo.to_string(),
+        });
+        self
+    }
+
+    /// Spends the note named `[first last]`. Naming notes at all takes one for each payment.
+    pub fn spend_note(mut self, first: impl Into<String>, last: impl Into<String>) -> Self {
+        self.notes.push((first.into(), last.into()));
+        self
+    }
+
+    pub fn fee(mut self, nicks: u64) -> Self {
+        self.fee = nicks;
+        self
+    }
+
+    /// Spends from and signs with the key at `index`, rather than the first
+    pub fn key_index(mut self, index: u64) -> Self {
+        self.key_index = Some(index);
+        self
+    }
+
+    pub fn selection(mut self, selection: CoinSelection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// Leaves the draft unsigned, for signing on another machine
+    pub fn unsigned(mut self) -> Self {
+        self.sign = false;
+        self
+    }
+
+    /// The `%simple-spend` poke for the kernel
+    pub fn to_poke(&self) -> Result<NounSlab, TxError> {
+        if self.payments.is_empty() {
+            return Err(TxError::NoPayments);
+        }
+        if !self.notes.is_empty() && self.notes.len() != self.payments.len() {
+            return Err(TxError::Notes(self.notes.len(), self.payments.len()));
+        }
+        check_key_index(self.key_index)?;
+
+        let mut slab = NounSlab::new();
+        let names = list(&mut slab, &self.notes, |slab, (first, last)| {
+            let first = make_tas(slab, first).as_noun();
+            let last = make_tas(slab, last).as_noun();
+            T(slab, &[first, last])
+        });
+        let recipients = list(&mut slab, &self.payments, |slab, payment| {
+            payment.to.to_noun(slab)
+        });
+        let gifts = list(&mut slab, &self.payments, |_, payment| D(payment.nicks));
+        let memos = if self.payments.iter().all(|payment| payment.memo.is_empty()) {
+            D(0)
+        } else {
+            list(&mut slab, &self.payments, |slab, payment| {
+                make_tas(slab, &payment.memo).as_noun()
+            })
+        };
+        let index = unit(&mut slab, self.key_index);
+        let selection = make_tas(&mut slab, self.selection.as_tas()).as_noun();
+        let entropy = entropy(&mut slab)?;
+        let tag = make_tas(&mut slab, "simple-spend").as_noun();
+        let sign = if self.sign { YES } else { NO };
+        let poke = T(
+            &mut slab,
+            &[
+                tag,
+                names,
+                recipients,
+                gifts,
+                D(self.fee),
+                index,
+                selection,
+                entropy,
+                sign,
+                memos,
+            ],
+        );
+        slab.set_root(poke);
+        Ok(slab)
+    }
+
+    /// Has `wallet` make the draft
+    pub async fn build(&self, wallet: &mut WalletKernel) -> Result<Draft, TxError> {
+        let effects = wallet.run("simple-spend", self.to_poke()?).await?;
+        draft_written(&effects)
+    }
+}
+
+/// A transaction's inputs and their signatures so far, jammed like a `.draft` file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Draft {
+    name: String,
+    jam: Vec<u8>,
+}
+
+impl Draft {
+    /// The draft in `jam`, like the contents of a `.draft` file
+    pub fn from_bytes(jam: Vec<u8>) -> Result<Self, TxError> {
+        let mut slab: NounSlab = NounSlab::new();
+        let draft = slab
+            .cue_into(Bytes::from(jam.clone()))
+            .map_err(|e| TxError::Draft(e.to_string()))?;
+        let name = draft
+            .as_cell()
+            .ok()
+            .and_then(|draft| draft.head().as_atom().ok())
+            .and_then(|name| name.into_string().ok())
+            .ok_or_else(|| TxError::Draft("no name".into()))?;
+        Ok(Draft { name, jam })
+    }
+
+    /// The name the CLI files it under in `drafts/`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.jam
+    }
+
+    fn to_noun(&self, slab: &mut NounSlab) -> Result<Noun, TxError> {
+        slab.cue_into(Bytes::from(self.jam.clone()))
+            .map_err(|e| TxError::Draft(e.to_string()))
+    }
+}
+
+/// A signed raw transaction, jammed the way nodes take it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    jam: Vec<u8>,
+}
+
+impl Transaction {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.jam
+    }
+
+    /// The jam in hex, for a node's `mempool_submitTransaction`
+    pub fn to_hex(&self) -> String {
+        self.jam
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+enum TxWire {
+    Command(&'static str),
+    Unlock,
+}
+
+impl Wire for TxWire {
+    const VERSION: u64 = 1;
+    const SOURCE: &'static str = "wallet";
+
+    fn to_wire(&self) -> WireRepr {
+        let tags = match self {
+            TxWire::Command(command) => vec!["command".into(), (*command).into()],
+            TxWire::Unlock => vec!["unlock".into()],
+        };
+        let wire = WireRepr::new(TxWire::SOURCE, TxWire::VERSION, tags);
+        match self {
+            TxWire::Unlock => wire.secret(),
+            TxWire::Command(_) => wire,
+        }
+    }
+}
+
+/// The wallet's kernel, booted in this process
+pub struct WalletKernel {
+    app: NockApp,
+    passphrase: Option<String>,
+}
+
+impl WalletKernel {
+    /// Boots the wallet the CLI uses, in `~/.nockapp/wallet`
+    pub async fn open() -> Result<Self, TxError> {
+        Self::open_in(system_data_dir().join("wallet")).await
+    }
+
+    /// Boots the wallet whose state is under `dir`, like the CLI's data directory
+    pub async fn open_in(dir: PathBuf) -> Result<Self, TxError> {
+        let hot_state = produce_prover_hot_state();
+        let app = boot::setup(KERNEL, None, hot_state.as_slice(), "wallet", Some(dir))
+            .await
+            .map_err(|e| TxError::Boot(e.to_string()))?;
+        Ok(Self::new(app))
+    }
+
+    pub fn new(app: NockApp) -> Self {
+        WalletKernel {
+            app,
+            passphrase: None,
+        }
+    }
+
+    /// The keystore passphrase, for a wallet whose keys are encrypted
+    pub fn passphrase(mut self, passphrase: impl Into<String>) -> Self {
+        self.passphrase = Some(passphrase.into());
+        self
+    }
+
+    /// Signs `draft` with the first key, or the one at `key_index`
+    pub async fn sign(&mut self, draft: &Draft, key_index: Option<u64>) -> Result<Draft, TxError> {
+        check_key_index(key_index)?;
+        let mut slab = NounSlab::new();
+        let draft = draft.to_noun(&mut slab)?;
+        let index = unit(&mut slab, key_index);
+        let entropy = entropy(&mut slab)?;
+        let tag = make_tas(&mut slab, "sign-tx").as_noun();
+        let poke = T(&mut slab, &[tag, draft, index, entropy]);
+        slab.set_root(poke);
+        let effects = self.run("sign-tx", slab).await?;
+        draft_written(&effects)
+    }
+
+    /// The transaction to broadcast for a signed `draft`. The wallet records it in its history as
+    /// sent, as it does for `send-tx`, and refuses a draft that's missing signatures.
+    pub async fn transaction(&mut self, draft: &Draft) -> Result<Transaction, TxError> {
+        let mut slab = NounSlab::new();
+        let draft = draft.to_noun(&mut slab)?;
+        let tag = make_tas(&mut slab, "send-tx").as_noun();
+        let poke = T(&mut slab, &[tag, draft]);
+        slab.set_root(poke);
+        let effects = self.run("send-tx", slab).await?;
+        let raw = effects
+            .iter()
+            .find_map(|effect| heard_tx(unsafe { *effect.root() }))
+            .ok_or(TxError::Missing("transaction"))?;
+        let mut tx: NounSlab = NounSlab::new();
+        let raw = tx.copy_into(raw);
+        tx.set_root(raw);
+        Ok(Transaction {
+            jam: tx.jam().to_vec(),
+        })
+    }
+
+    /// Pokes `command`, opening the keystore if the kernel asks, and saves if it succeeded
+    async fn run(
+        &mut self,
+        command: &'static str,
+        poke: NounSlab,
+    ) -> Result<Vec<NounSlab>, TxError> {
+        let mut effects = self
+            .app
+            .poke(TxWire::Command(command).to_wire(), poke)
+            .await?;
+        let locked = effects.iter().find_map(|effect| {
+            let pid = effect_tail(unsafe { *effect.root() }, "passphrase")?;
+            pid.as_atom().ok()?.as_u64().ok()
+        });
+        if let Some(pid) = locked {
+            let passphrase = self.passphrase.as_deref().ok_or(TxError::Locked)?;
+            effects = self
+                .app
+                .poke(TxWire::Unlock.to_wire(), unlock(pid, passphrase))
+                .await?;
+        }
+        let failed = effects.iter().any(|effect| {
+            effect_tail(unsafe { *effect.root() }, "exit")
+                .and_then(|code| code.as_atom().ok()?.as_u64().ok())
+                .is_some_and(|code| code != 0)
+        });
+        if failed {
+            let said: Vec<String> = effects
+                .iter()
+                .filter_map(|effect| {
+                    let text = effect_tail(unsafe { *effect.root() }, "markdown")?;
+                    text.as_atom().ok()?.into_string().ok()
+                })
+                .collect();
+            return Err(TxError::Refused(said.join("\n")));
+        }
+        self.app.save_locked().await?;
+        Ok(effects)
+    }
+}
+
+fn check_key_index(index: Option<u64>) -> Result<(), TxError> {
+    match index {
+        Some(index) if index > MAX_KEY_INDEX => Err(TxError::KeyIndex(index)),
+        _ => Ok(()),
+    }
+}
+
+/// `[%unlock pid passphrase]`
+fn unlock(pid: u64, passphrase: &str) -> NounSlab {
+    let mut slab = NounSlab::new();
+    let tag = make_tas(&mut slab, "unlock").as_noun();
+    let passphrase = make_tas(&mut slab, passphrase).as_noun();
+    let poke = T(&mut slab, &[tag, D(pid), passphrase]);
+    slab.set_root(poke);
+    slab
+}
+
+/// The tail of `[tag tail]`
+fn effect_tail(noun: Noun, tag: &str) -> Option<Noun> {
+    let cell = noun.as_cell().ok()?;
+    cell.head().eq_bytes(tag).then(|| cell.tail())
+}
+
+/// The draft in the `[%file %write path jam]` effect
+fn draft_written(effects: &[NounSlab]) -> Result<Draft, TxError> {
+    let jam = effects
+        .iter()
+        .find_map(|effect| {
+            let write = effect_tail(unsafe { *effect.root() }, "file")?;
+            let file = effect_tail(write, "write")?.as_cell().ok()?;
+            file.tail().as_atom().ok()
+        })
+        .ok_or(TxError::Missing("draft"))?;
+    Draft::from_bytes(atom_bytes(jam))
+}
+
+/// The raw transaction in `[%npc pid %poke %fact %0 %heard-tx raw]`
+fn heard_tx(effect: Noun) -> Option<Noun> {
+    let npc = effect_tail(effect, "npc")?.as_cell().ok()?;
+    let fact = effect_tail(effect_tail(npc.tail(), "poke")?, "fact")?;
+    effect_tail(fact.as_cell().ok()?.tail(), "heard-tx")
+}
+
+/// The bytes of an atom, without the zeros past its end
+fn atom_bytes(atom: Atom) -> Vec<u8> {
+    let mut bytes = atom.as_ne_bytes().to_vec();
+    while bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+    bytes
+}
+
+/// `[~ value]`, or `~`
+fn unit(slab: &mut NounSlab, value: Option<u64>) -> Noun {
+    match value {
+        Some(value) => T(slab, &[D(0), D(value)]),
+        None => D(0),
+    }
+}
+
+/// 32 random bytes, for the kernel's signatures
+fn entropy(slab: &mut NounSlab) -> Result<Noun, TxError> {
+    let mut bytes = [0u8; 32];
+    getrandom(&mut bytes).map_err(|e| TxError::Entropy(e.to_string()))?;
+    Ok(Atom::from_bytes(slab, &Bytes::copy_from_slice(&bytes)).as_noun())
+}
+
+/// A list of `items`, each made a noun by `noun`
+fn list<I>(slab: &mut NounSlab, items: &[I], noun: impl Fn(&mut NounSlab, &I) -> Noun) -> Noun {
+    items.iter().rev().fold(D(0), |acc, item| {
+        let item = noun(slab, item);
+        Cell::new(slab, item, acc).as_noun()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::Slots;
+
+    use super::*;
+
+    /// The noun at `axis` of `noun`, counting from 2 for the head
+    fn at(noun: Noun, axis: u64) -> Noun {
+        noun.slot(axis).expect("no such axis")
+    }
+
+    #[test]
+    fn test_to_poke() {
+        assert!(matches!(
+            TxBuilder::new().fee(1).to_poke(),
+            Err(TxError::NoPayments)
+        ));
+        assert!(matches!(
+            TxBuilder::new()
+                .pay(Recipient::pubkey("pk1"), 1)
+                .pay(Recipient::pubkey("pk2"), 2)
+                .spend_note("first", "last")
+                .to_poke(),
+            Err(TxError::Notes(1, 2))
+        ));
+        assert!(matches!(
+            TxBuilder::new()
+                .pay(Recipient::pubkey("pk1"), 1)
+                .key_index(256)
+                .to_poke(),
+            Err(TxError::KeyIndex(256))
+        ));
+
+        let slab = TxBuilder::new()
+            .pay(
+                Recipient::multisig(2, vec!["pk1".into(), "pk2".into()]),
+                100,
+            )
+            .fee(10)
+            .key_index(3)
+            .unsigned()
+            .to_poke()
+            .expect("a payment is enough");
+        let poke = unsafe { *slab.root() };
+        assert!(at(poke, 2).eq_bytes("simple-spend"));
+        // No names, for the wallet to pick notes
+        assert!(at(poke, 6).is_atom());
+        let recipient = at(at(poke, 14), 2);
+        assert_eq!(at(recipient, 2).as_atom().unwrap().as_u64().unwrap(), 2);
+        assert!(at(at(recipient, 3), 6).eq_bytes("pk2"));
+        assert_eq!(
+            at(at(poke, 30), 2).as_atom().unwrap().as_u64().unwrap(),
+            100
+        );
+        assert_eq!(at(poke, 62).as_atom().unwrap().as_u64().unwrap(), 10);
+        assert_eq!(at(at(poke, 126), 3).as_atom().unwrap().as_u64().unwrap(), 3);
+        assert!(at(poke, 254).eq_bytes("bnb"));
+        assert_eq!(at(poke, 1022).as_atom().unwrap().as_u64().unwrap(), 1);
+        // No memos
+        assert!(at(poke, 1023).is_atom());
+    }
+
+    #[test]
+    fn test_draft_from_bytes() {
+        let mut slab: NounSlab = NounSlab::new();
+        let name = make_tas(&mut slab, "draft-name").as_noun();
+        let draft = T(&mut slab, &[name, D(0)]);
+        slab.set_root(draft);
+        let draft = Draft::from_bytes(slab.jam().to_vec()).expect("a draft");
+        assert_eq!(draft.name(), "draft-name");
+        assert!(Draft::from_bytes(vec![]).is_err());
+        assert_eq!(Transaction { jam: vec![0, 255] }.to_hex(), "00ff");
+    }
+}
@@ -0,0 +1,168 @@
+//! Where mining threads run, and how urgently.
+//!
+//! A node that mines and serves peers can keep mining off the cores its consensus and networking
+//! threads run on. `--mining-cores` pins mining thread `i` to the `i`th of the cores listed,
+//! wrapping around, and lets the proving pool run on any of them. `--mining-priority` schedules
+//! the same threads as `batch`, so they never preempt the node's other threads, or `idle`, so
+//! they only get cores nothing else wants. Neither needs privileges. Both are Linux only, and
+//! elsewhere they're ignored with a warning.
+use std::fmt;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use tracing::warn;
+
+/// A set of cores, like `0,2,4-7`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cores(pub Vec<usize>);
+
+impl FromStr for Cores {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cores = Vec::new();
+        for part in s.split(',') {
+            let core = |n: &str| {
+                n.trim()
+                    .parse::<usize>()
+                    .map_err(|e| format!("Invalid core {}: {}", n, e))
+            };
+            match part.split_once('-') {
+                Some((from, to)) => {
+                    let (from, to) = (core(from)?, core(to)?);
+                    if from > to {
+                        return Err(format!("Invalid core range {}", part));
+                    }
+                    cores.extend(from..=to);
+                }
+                None => cores.push(core(part)?),
+            }
+        }
+        cores.sort_unstable();
+        cores.dedup();
+        Ok(Cores(cores))
+    }
+}
+
+impl fmt::Display for Cores {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cores: Vec<String> = self.0.iter().map(usize::to_string).collect();
+        write!(f, "{}", cores.join(","))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Priority {
+    /// Like any other thread
+    #[default]
+    Normal,
+    /// CPU bound, so never preempting other threads
+    Batch,
+    /// Only on cores that would otherwise be idle
+    Idle,
+}
+
+/// How mining threads are placed
+#[derive(Debug, Clone, Default)]
+pub struct Placement {
+    pub cores: Option<Cores>,
+    pub priority: Priority,
+}
+
+impl Placement {
+    pub fn is_default(&self) -> bool {
+        self.cores.is_none() && self.priority == Priority::Normal
+    }
+
+    /// Pin mining thread `worker` to its core and set its priority
+    pub fn place_worker(&self, thread: &std::thread::JoinHandle<()>, worker: usize) {
+        let core = self
+            .cores
+            .as_ref()
+            .filter(|cores| !cores.0.is_empty())
+            .map(|cores| cores.0[worker % cores.0.len()]);
+        place(thread, core.as_slice(), self.priority);
+    }
+
+    /// Let the calling thread, one of the proving pool's, run on any of the cores, at the
+    /// priority
+    pub fn place_current(&self) {
+        let cores = self.cores.as_ref().map_or(&[][..], |cores| &cores.0[..]);
+        place_current(cores, self.priority);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn place(thread: &std::thread::JoinHandle<()>, cores: &[usize], priority: Priority) {
+    use std::os::unix::thread::JoinHandleExt;
+
+    place_pthread(thread.as_pthread_t(), cores, priority);
+}
+
+#[cfg(target_os = "linux")]
+fn place_current(cores: &[usize], priority: Priority) {
+    place_pthread(unsafe { libc::pthread_self() }, cores, priority);
+}
+
+#[cfg(target_os = "linux")]
+fn place_pthread(thread: libc::pthread_t, cores: &[usize], priority: Priority) {
+    if !cores.is_empty() {
+        let set = unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            libc::CPU_ZERO(&mut set);
+            for &core in cores {
+                libc::CPU_SET(core, &mut set);
+            }
+            set
+        };
+        let result = unsafe {
+            libc::pthread_setaffinity_np(thread, std::mem::size_of::<libc::cpu_set_t>(), &set)
+        };
+        if result != 0 {
+            warn!(
+                "Could not pin a mining thread to cores {}: {}",
+                Cores(cores.to_vec()),
+                std::io::Error::from_raw_os_error(result)
+            );
+        }
+    }
+    let policy = match priority {
+        Priority::Normal => return,
+        Priority::Batch => libc::SCHED_BATCH,
+        Priority::Idle => libc::SCHED_IDLE,
+    };
+    let param = libc::sched_param { sched_priority: 0 };
+    let result = unsafe { libc::pthread_setschedparam(thread, policy, &param) };
+    if result != 0 {
+        warn!(
+            "Could not lower the priority of a mining thread: {}",
+            std::io::Error::from_raw_os_error(result)
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn place(_thread: &std::thread::JoinHandle<()>, cores: &[usize], priority: Priority) {
+    place_current(cores, priority);
+}
+
+#[cfg(not(target_os = "linux"))]
+fn place_current(cores: &[usize], priority: Priority) {
+    if !cores.is_empty() || priority != Priority::Normal {
+        warn!("Mining thread affinity and priority are only supported on Linux, ignoring them");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cores() {
+        let cores = Cores::from_str("6-7,0,2,4-6").expect("cores");
+        assert_eq!(cores.0, vec![0, 2, 4, 5, 6, 7]);
+        assert_eq!(cores.to_string(), "0,2,4,5,6,7");
+        assert!(Cores::from_str("3-1").is_err());
+        assert!(Cores::from_str("a").is_err());
+    }
+}
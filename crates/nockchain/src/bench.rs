@@ -0,0 +1,301 @@
+//! Benchmarking the proof of work.
+//!
+//! `nockchain bench-pow` proves the mining puzzle for a random block on every mining thread for
+//! `--duration-secs`, without a kernel for the chain or any peers, and reports how many proofs a
+//! second the machine does. It takes the same `--num-threads`, `--prover-threads`,
+//! `--mining-cores` and `--mining-priority` as mining does, so settings can be compared before
+//! using them. The target is never met, so every attempt is a whole proof.
+//!
+//! Time is split between the stages [`zkvm_jetpack::stages`] measures, averaged over proofs, with
+//! what's left as `other`. A stage's jets may run on the proving pool, so with several mining
+//! threads a stage's time is how long each proof waited on it, not the CPU time it took. Memory
+//! is the peak resident size of the process, which includes each thread's loom.
+use std::time::{Duration, Instant};
+
+use clap::Args;
+use nockapp::noun::slab::NounSlab;
+use nockapp::noun::AtomExt;
+use nockapp::utils::NOCK_STACK_SIZE_TINY;
+use nockapp::wire::Wire;
+use nockvm::noun::{Atom, D, T};
+use nockvm_macros::tas;
+use rand::Rng;
+use serde_json::{json, Value};
+use thiserror::Error;
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+use zkvm_jetpack::form::PRIME;
+use zkvm_jetpack::stages::{self, Stage};
+
+use crate::config::NockchainCli;
+use crate::mining::{create_poke, load_miner, MiningData, MiningWire, NonceRange};
+use crate::setup::BlockchainConstants;
+
+#[derive(Args, Debug, Clone)]
+pub struct BenchArgs {
+    #[arg(long, default_value_t = 60, help = "How long to prove for")]
+    pub duration_secs: u64,
+    #[arg(
+        long,
+        default_value_t = BlockchainConstants::DEFAULT_POW_LEN,
+        help = "Length of the puzzle, as on mainnet by default"
+    )]
+    pub pow_len: u64,
+    #[arg(long, default_value_t = 2, help = "Proof version to prove")]
+    pub proof_version: u64,
+    #[arg(long, help = "Print the report as JSON")]
+    pub json: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum BenchError {
+    #[error("Could not set up the mining threads: {0}")]
+    Setup(String),
+    #[error("Proof version {0} does not exist")]
+    Version(u64),
+    #[error("No proof finished in {0:?}, try a longer --duration-secs")]
+    NoProofs(Duration),
+}
+
+/// What one thread did
+#[derive(Debug, Default)]
+struct Proofs {
+    count: u64,
+    total: Duration,
+    fastest: Option<Duration>,
+    slowest: Duration,
+    failed: u64,
+}
+
+impl Proofs {
+    fn add(&mut self, took: Duration) {
+        self.count += 1;
+        self.total += took;
+        self.fastest = Some(self.fastest.map_or(took, |fastest| fastest.min(took)));
+        self.slowest = self.slowest.max(took);
+    }
+
+    fn merge(&mut self, other: Proofs) {
+        self.count += other.count;
+        self.total += other.total;
+        self.fastest = match (self.fastest, other.fastest) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.slowest = self.slowest.max(other.slowest);
+        self.failed += other.failed;
+    }
+}
+
+pub async fn run(args: &BenchArgs, cli: &NockchainCli) -> Result<(), BenchError> {
+    if args.proof_version > 2 {
+        return Err(BenchError::Version(args.proof_version));
+    }
+    cli.validate().map_err(BenchError::Setup)?;
+    let (threads, placement) =
+        crate::prepare_mining_threads(Some(cli)).map_err(|e| BenchError::Setup(e.to_string()))?;
+    let threads = threads.max(1) as usize;
+    let duration = Duration::from_secs(args.duration_secs);
+    info!(
+        "Proving for {:?} on {} threads with pow-len {}",
+        duration, threads, args.pow_len
+    );
+
+    let hot_state = zkvm_jetpack::hot::produce_prover_hot_state();
+    let test_jets_str = std::env::var("NOCK_TEST_JETS").unwrap_or_default();
+    let test_jets = nockapp::kernel::boot::parse_test_jets(test_jets_str.as_str());
+    let mut serfs = Vec::with_capacity(threads);
+    for worker in 0..threads {
+        serfs.push(load_miner(&hot_state, &test_jets, &placement, worker).await);
+    }
+
+    let stages_before = stages::totals();
+    let start = Instant::now();
+    let deadline = start + duration;
+    let mut attempts = JoinSet::new();
+    for (serf, mut nonces) in serfs.into_iter().zip(NonceRange::split(threads)) {
+        let data = bench_data(args.proof_version, args.pow_len);
+        attempts.spawn(async move {
+            let mut proofs = Proofs::default();
+            while Instant::now() < deadline {
+                let poke = create_poke(&data, &nonces.next());
+                let attempt = Instant::now();
+                match serf.poke(MiningWire::Candidate.to_wire(), poke).await {
+                    Ok(_) => proofs.add(attempt.elapsed()),
+                    Err(e) => {
+                        warn!("A proof failed: {e}");
+                        proofs.failed += 1;
+                    }
+                }
+            }
+            proofs
+        });
+    }
+    let mut proofs = Proofs::default();
+    while let Some(thread) = attempts.join_next().await {
+        if let Ok(thread) = thread {
+            proofs.merge(thread);
+        }
+    }
+    let elapsed = start.elapsed();
+    if proofs.count == 0 {
+        return Err(BenchError::NoProofs(elapsed));
+    }
+
+    let stage_times: Vec<(&str, Duration)> = stages_before
+        .into_iter()
+        .zip(stages::totals())
+        .map(|((stage, before), (_, after))| (stage.name(), (after - before) / proofs.count as u32))
+        .collect();
+    let prover_threads = zkvm_jetpack::pool::prover_threads();
+    let report = report(&proofs, elapsed, threads, prover_threads, &stage_times);
+    if args.json {
+        println!("{}", report);
+    } else {
+        print_report(&report);
+    }
+    Ok(())
+}
+
+/// A candidate to prove, for a random block that can't be mined
+fn bench_data(version: u64, pow_len: u64) -> MiningData {
+    let mut rng = rand::thread_rng();
+    let mut block_header = NounSlab::new();
+    let belts = [0; 5].map(|_| {
+        Atom::from_value(&mut block_header, rng.gen::<u64>() % PRIME)
+            .expect("Failed to create header atom")
+            .as_noun()
+    });
+    let header = T(&mut block_header, &belts);
+    block_header.set_root(header);
+    let mut version_slab = NounSlab::new();
+    version_slab.set_root(D(version));
+    // A target of 0, which no proof's hash is under
+    let mut target = NounSlab::new();
+    let bignum = T(&mut target, &[D(tas!(b"bn")), D(0)]);
+    target.set_root(bignum);
+    MiningData {
+        block_header,
+        version: version_slab,
+        target,
+        pow_len,
+    }
+}
+
+fn report(
+    proofs: &Proofs,
+    elapsed: Duration,
+    threads: usize,
+    prover_threads: usize,
+    stage_times: &[(&str, Duration)],
+) -> Value {
+    let average = proofs.total / proofs.count as u32;
+    let staged: Duration = stage_times.iter().map(|(_, took)| *took).sum();
+    let mut stages: serde_json::Map<String, Value> = stage_times
+        .iter()
+        .map(|(name, took)| (name.to_string(), json!(took.as_secs_f64())))
+        .collect();
+    stages.insert(
+        "other".into(),
+        json!(average.saturating_sub(staged).as_secs_f64()),
+    );
+    json!({
+        "threads": threads,
+        "proverThreads": prover_threads,
+        "seconds": elapsed.as_secs_f64(),
+        "proofs": proofs.count,
+        "failed": proofs.failed,
+        "proofsPerSec": proofs.count as f64 / elapsed.as_secs_f64(),
+        "averageProofSecs": average.as_secs_f64(),
+        "fastestProofSecs": proofs.fastest.unwrap_or_default().as_secs_f64(),
+        "slowestProofSecs": proofs.slowest.as_secs_f64(),
+        "stageSecs": stages,
+        "peakRssBytes": peak_rss(),
+        "loomBytes": threads * NOCK_STACK_SIZE_TINY * 8,
+    })
+}
+
+fn print_report(report: &Value) {
+    let secs = |key: &str| report[key].as_f64().unwrap_or_default();
+    println!(
+        "{} proofs in {:.1}s on {} threads ({} proving): {:.3} proofs/sec",
+        report["proofs"],
+        secs("seconds"),
+        report["threads"],
+        report["proverThreads"],
+        secs("proofsPerSec")
+    );
+    println!(
+        "proof time: {:.2}s average, {:.2}s fastest, {:.2}s slowest",
+        secs("averageProofSecs"),
+        secs("fastestProofSecs"),
+        secs("slowestProofSecs")
+    );
+    let average = secs("averageProofSecs");
+    println!("per proof:");
+    let stages = report["stageSecs"].as_object().cloned().unwrap_or_default();
+    for name in Stage::ALL.iter().map(|stage| stage.name()).chain(["other"]) {
+        let took = stages.get(name).and_then(Value::as_f64).unwrap_or_default();
+        let share = if average > 0.0 {
+            took / average * 100.0
+        } else {
+            0.0
+        };
+        println!("  {:<12} {:>8.3}s {:>5.1}%", name, took, share);
+    }
+    if let Some(rss) = report["peakRssBytes"].as_u64() {
+        println!(
+            "peak memory: {} MiB, {} MiB of it looms",
+            rss >> 20,
+            report["loomBytes"].as_u64().unwrap_or_default() >> 20
+        );
+    }
+    if report["failed"].as_u64().unwrap_or_default() > 0 {
+        println!("{} proofs failed", report["failed"]);
+    }
+}
+
+/// Peak resident memory of the process, in bytes
+#[cfg(unix)]
+fn peak_rss() -> Option<u64> {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    // Kilobytes on Linux, bytes on macOS
+    let rss = usage.ru_maxrss as u64;
+    if cfg!(target_os = "macos") {
+        Some(rss)
+    } else {
+        Some(rss * 1024)
+    }
+}
+
+#[cfg(not(unix))]
+fn peak_rss() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut proofs = Proofs::default();
+        proofs.add(Duration::from_secs(2));
+        let mut other = Proofs::default();
+        other.add(Duration::from_secs(4));
+        other.failed = 1;
+        proofs.merge(other);
+        let stage_times = [("lde", Duration::from_secs(1))];
+        let report = report(&proofs, Duration::from_secs(4), 2, 8, &stage_times);
+        assert_eq!(report["proofs"], json!(2));
+        assert_eq!(report["failed"], json!(1));
+        assert_eq!(report["proofsPerSec"], json!(0.5));
+        assert_eq!(report["averageProofSecs"], json!(3.0));
+        assert_eq!(report["fastestProofSecs"], json!(2.0));
+        assert_eq!(report["stageSecs"]["lde"], json!(1.0));
+        assert_eq!(report["stageSecs"]["other"], json!(2.0));
+    }
+}
@@ -0,0 +1,307 @@
+//! Exporting the chain to a file and importing it again, for archiving and bootstrapping nodes.
+//!
+//! `nockchain export-blocks PATH` writes every block of the stored heaviest chain, with its
+//! transactions, from genesis up. `nockchain import-blocks PATH` boots the node without any
+//! networking, hears each block of the file in order, and exits. Every block is validated as if
+//! it had just arrived from a peer, so a file can bootstrap a node but can't make it accept a
+//! chain it wouldn't have synced anyway.
+//!
+//! The file is a header and then a record for each block, with integers little-endian:
+//!
+//! ```text
+//! file   = magic:"NOCKBLKS" version:u32 record*
+//! record = length:u64 jam:[u8; length]
+//! ```
+//!
+//! Version 1 is the only one. The jam in the record at index `n` is `[page txs=(list raw-tx)]`
+//! for the block at height `n`, and the file ends after the last record.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use clap::Args;
+use nockapp::driver::{make_driver, IODriverFn, NockAppHandle};
+use nockapp::kernel::boot;
+use nockapp::noun::slab::{Jammer, NounSlab};
+use nockapp::{Bytes, NockAppError};
+use nockvm::jets::hot::HotEntry;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::oneshot;
+use tracing::{error, info};
+
+use crate::indexer::{self, AddressIndex, IndexError};
+use crate::reindex::{self, ReindexError};
+use crate::NockchainCli;
+
+/// First bytes of a block file
+pub const MAGIC: &[u8; 8] = b"NOCKBLKS";
+/// Version of the format written
+pub const VERSION: u32 = 1;
+/// The longest record read, well over any block that fits `max_block_size`
+const MAX_RECORD_LEN: u64 = 1 << 32;
+/// How often to log progress, in blocks
+const PROGRESS_INTERVAL: u64 = 1000;
+
+#[derive(Args, Debug, Clone)]
+pub struct BlocksArgs {
+    /// Block file to write or read
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Error)]
+pub enum BlocksError {
+    #[error("Could not boot the stored chain: {0}")]
+    Boot(String),
+    #[error(transparent)]
+    Chain(#[from] ReindexError),
+    #[error("Could not hear the blocks: {0}")]
+    Kernel(#[from] NockAppError),
+    #[error("Could not update the address index: {0}")]
+    Index(#[from] IndexError),
+    #[error("Could not use {0}: {1}")]
+    Io(PathBuf, io::Error),
+    #[error("Could not read the block file: {0}")]
+    Read(io::Error),
+    #[error("Not a block file")]
+    BadMagic,
+    #[error("Block file version {0} is not supported")]
+    Version(u32),
+    #[error("Record {0} is {1} bytes, more than a block can be")]
+    TooLong(u64, u64),
+    #[error("Record {0} is cut off")]
+    Truncated(u64),
+    #[error("Record {0} is not a block")]
+    Malformed(u64),
+}
+
+/// Writes blocks in order to a block file
+pub struct BlockWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> BlockWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        Ok(BlockWriter { writer })
+    }
+
+    /// Write the block `[page txs]` at the next height
+    pub fn write(&mut self, block: &NounSlab) -> io::Result<()> {
+        let jam = block.jam();
+        self.writer.write_all(&(jam.len() as u64).to_le_bytes())?;
+        self.writer.write_all(&jam)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads the blocks of a block file in order
+pub struct BlockReader<R: AsyncRead + Unpin> {
+    reader: R,
+    records: u64,
+}
+
+impl<R: AsyncRead + Unpin> BlockReader<R> {
+    /// Check the header of the file `reader` reads
+    pub async fn open(mut reader: R) -> Result<Self, BlocksError> {
+        let mut header = [0u8; 12];
+        match reader.read_exact(&mut header).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(BlocksError::BadMagic)
+            }
+            Err(e) => return Err(BlocksError::Read(e)),
+        }
+        if &header[..8] != MAGIC {
+            return Err(BlocksError::BadMagic);
+        }
+        let version = u32::from_le_bytes(header[8..].try_into().expect("4 bytes"));
+        if version != VERSION {
+            return Err(BlocksError::Version(version));
+        }
+        Ok(BlockReader { reader, records: 0 })
+    }
+
+    /// The next block, or `None` at the end of the file
+    pub async fn next(&mut self) -> Result<Option<NounSlab>, BlocksError> {
+        let record = self.records;
+        let mut length = [0u8; 8];
+        let read = self
+            .reader
+            .read(&mut length)
+            .await
+            .map_err(BlocksError::Read)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        self.read_rest(record, &mut length[read..]).await?;
+        let length = u64::from_le_bytes(length);
+        if length > MAX_RECORD_LEN {
+            return Err(BlocksError::TooLong(record, length));
+        }
+        let mut jam = vec![0u8; length as usize];
+        self.read_rest(record, &mut jam).await?;
+
+        let mut block = NounSlab::new();
+        let root = block
+            .cue_into(Bytes::from(jam))
+            .map_err(|_| BlocksError::Malformed(record))?;
+        if !root.is_cell() {
+            return Err(BlocksError::Malformed(record));
+        }
+        block.set_root(root);
+        self.records += 1;
+        Ok(Some(block))
+    }
+
+    async fn read_rest(&mut self, record: u64, buf: &mut [u8]) -> Result<(), BlocksError> {
+        match self.reader.read_exact(buf).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                Err(BlocksError::Truncated(record))
+            }
+            Err(e) => Err(BlocksError::Read(e)),
+        }
+    }
+}
+
+/// Write the stored heaviest chain to `args.path`
+pub async fn export<J: Jammer + Send + 'static>(
+    args: &BlocksArgs,
+    cli: &NockchainCli,
+    kernel_jam: &[u8],
+    hot_state: &[HotEntry],
+) -> Result<(), BlocksError> {
+    let mut nockapp = boot::setup::<J>(
+        kernel_jam,
+        Some(cli.nockapp_cli.clone()),
+        hot_state,
+        "nockchain",
+        None,
+    )
+    .await
+    .map_err(|e| BlocksError::Boot(e.to_string()))?;
+
+    let io_error = |e| ReindexError::Io(args.path.clone(), e);
+    let file = File::create(&args.path).map_err(io_error)?;
+    let mut writer = BlockWriter::new(BufWriter::new(file)).map_err(io_error)?;
+    let count = reindex::for_each_block(&mut nockapp, |_, block| {
+        writer.write(&block).map_err(io_error)
+    })
+    .await?;
+    writer
+        .finish()
+        .and_then(|w| w.into_inner().map_err(|e| e.into_error()))
+        .and_then(|file| file.sync_all())
+        .map_err(io_error)?;
+    info!("Exported {} blocks to {}", count, args.path.display());
+    Ok(())
+}
+
+/// Once `born_rx` fires, hear each block of the file at `path`, bring `index` up to date, and
+/// exit
+pub fn make_import_driver(
+    path: PathBuf,
+    born_rx: oneshot::Receiver<()>,
+    index: Option<Arc<AddressIndex>>,
+) -> IODriverFn {
+    make_driver(move |handle| async move {
+        let _ = born_rx.await;
+        match import(&handle, &path, index.as_deref()).await {
+            Ok(count) => {
+                info!("Imported {} blocks from {}", count, path.display());
+                handle.exit.exit(0).await
+            }
+            Err(e) => {
+                error!("Import from {} failed: {}", path.display(), e);
+                handle.exit.exit(1).await
+            }
+        }
+    })
+}
+
+async fn import(
+    handle: &NockAppHandle,
+    path: &Path,
+    index: Option<&AddressIndex>,
+) -> Result<u64, BlocksError> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| BlocksError::Io(path.into(), e))?;
+    let mut reader = BlockReader::open(tokio::io::BufReader::new(file)).await?;
+    let mut height = 0;
+    while let Some(block) = reader.next().await? {
+        let block = unsafe { *block.root() }
+            .as_cell()
+            .map_err(|_| BlocksError::Malformed(height))?;
+        reindex::replay_block(handle, height, block.head(), block.tail()).await?;
+        height += 1;
+        if height % PROGRESS_INTERVAL == 0 {
+            info!("Imported {} blocks", height);
+        }
+    }
+    if let Some(index) = index {
+        indexer::sync(handle, index).await?;
+    }
+    Ok(height)
+}
+
+#[cfg(test)]
+mod tests {
+    use nockapp::noun::slab::slab_equality;
+    use nockvm::noun::{D, T};
+
+    use super::*;
+
+    fn block(n: u64) -> NounSlab {
+        let mut slab = NounSlab::new();
+        let txs = T(&mut slab, &[D(n + 1), D(0)]);
+        let root = T(&mut slab, &[D(n), txs]);
+        slab.set_root(root);
+        slab
+    }
+
+    #[tokio::test]
+    async fn test_block_file() {
+        let mut writer = BlockWriter::new(Vec::new()).unwrap();
+        writer.write(&block(0)).unwrap();
+        writer.write(&block(1)).unwrap();
+        let file = writer.finish().unwrap();
+        assert_eq!(&file[..8], MAGIC);
+
+        let mut reader = BlockReader::open(file.as_slice()).await.unwrap();
+        assert!(slab_equality(
+            &reader.next().await.unwrap().unwrap(),
+            &block(0)
+        ));
+        assert!(slab_equality(
+            &reader.next().await.unwrap().unwrap(),
+            &block(1)
+        ));
+        assert!(reader.next().await.unwrap().is_none());
+
+        let mut reader = BlockReader::open(&file[..file.len() - 1]).await.unwrap();
+        reader.next().await.unwrap();
+        assert!(matches!(
+            reader.next().await,
+            Err(BlocksError::Truncated(1))
+        ));
+
+        let mut newer = file.clone();
+        newer[8] = 2;
+        assert!(matches!(
+            BlockReader::open(newer.as_slice()).await,
+            Err(BlocksError::Version(2))
+        ));
+        assert!(matches!(
+            BlockReader::open(&b"NOCK"[..]).await,
+            Err(BlocksError::BadMagic)
+        ));
+    }
+}
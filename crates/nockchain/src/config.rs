@@ -1,14 +1,32 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-use clap::{arg, command, value_parser, ArgAction, Parser};
+use clap::{arg, command, value_parser, ArgAction, Parser, Subcommand};
+use nockapp::drivers::npc::LimitAction;
+use nockapp::settings::{ConfigCommand, Layers};
+use nockchain_libp2p_io::seeds::DnsSeed;
 
-use crate::mining::MiningKeyConfig;
+use crate::affinity::{Cores, Priority};
+use crate::backup::{BackupArgs, RestoreArgs};
+use crate::bench::BenchArgs;
+use crate::blocks::BlocksArgs;
+use crate::devnet::DevnetArgs;
+use crate::genesis::VerifyGenesisArgs;
+use crate::light::LightArgs;
+use crate::mempool::RelayPolicy;
+use crate::mining::{check_split, MiningKeyConfig};
+use crate::status::StatusArgs;
 
 // TODO: command-line/configure
 /** Path to read current node's identity from */
 pub const IDENTITY_PATH: &str = ".nockchain_identity";
 
+/** Path to remember peers in across restarts */
+pub const PEER_STORE_PATH: &str = ".nockchain_peers";
+
+/** Path to keep the chunks of a snapshot being fetched from peers in, so a restart resumes it */
+pub const SNAPSHOT_FETCH_PATH: &str = ".nockchain_snapshot_fetch";
+
 /** Path to read current node's peer ID from */
 pub const PEER_ID_EXTENSION: &str = ".peer_id";
 
@@ -26,6 +44,12 @@ pub const TESTNET_BACKBONE_NODES: &[&str] = &[];
 #[allow(dead_code)]
 pub const REALNET_BACKBONE_NODES: &[&str] = &["/dnsaddr/nockchain-backbone.zorp.io"];
 
+/** DNS seeds for our testnet, as `host:port` */
+pub const TESTNET_DNS_SEEDS: &[&str] = &[];
+
+/** DNS seeds for our realnet, as `host:port` */
+pub const REALNET_DNS_SEEDS: &[&str] = &[];
+
 /** How often we should affirmatively ask other nodes for their heaviest chain */
 pub const CHAIN_INTERVAL: Duration = Duration::from_secs(20);
 
@@ -34,6 +58,18 @@ pub const CHAIN_INTERVAL: Duration = Duration::from_secs(20);
 /// switched to a future block for launch.
 pub const GENESIS_HEIGHT: u64 = 897767;
 
+/// The shallowest `--prune-depth` we accept. A pruned node can't follow a reorg deeper than its
+/// prune depth, so this keeps it well clear of any reorg seen in practice.
+pub const MIN_PRUNE_DEPTH: u64 = 100;
+
+/// Where the node's settings are read from, as [`nockapp::settings`] explains: the `[node]`
+/// table of `nockchain.toml`, and `NOCKCHAIN_` variables
+pub const LAYERS: Layers = Layers {
+    default_file: "nockchain.toml",
+    section: "node",
+    env_prefix: "NOCKCHAIN_",
+};
+
 /// Command line arguments
 #[derive(Parser, Debug, Clone)]
 #[command(name = "nockchain")]
@@ -46,6 +82,64 @@ pub struct NockchainCli {
         default_value = ".socket/nockchain_npc.sock"
     )]
     pub npc_socket: String,
+    #[arg(
+        long,
+        default_value_t = 64,
+        help = "Most NPC connections open at once, or 0 for no limit"
+    )]
+    pub npc_max_connections: usize,
+    #[arg(
+        long,
+        default_value_t = 64 << 20,
+        help = "Most bytes in one NPC message; a connection sending more is closed. 0 for no limit."
+    )]
+    pub npc_max_message_bytes: usize,
+    #[arg(
+        long,
+        help = "Most messages a second from each NPC connection. Unlimited by default."
+    )]
+    pub npc_rate_limit: Option<u32>,
+    #[arg(
+        long,
+        help = "Most messages a second from all NPC connections together. Unlimited by default."
+    )]
+    pub npc_global_rate_limit: Option<u32>,
+    #[arg(
+        long,
+        help = "Most NPC peeks waiting on the kernel at once. Unlimited by default."
+    )]
+    pub npc_max_concurrent_peeks: Option<usize>,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = LimitAction::Delay,
+        help = "What an NPC client over a rate or peek limit gets: delay holds its request until it fits, reject nacks it, disconnect closes the connection"
+    )]
+    pub npc_on_limit: LimitAction,
+    #[arg(
+        long,
+        requires_all = ["npc_tls_cert", "npc_tls_key", "npc_tls_client_ca"],
+        help = "Also serve NPC over mutually authenticated TLS on this TCP address, for managing the node remotely"
+    )]
+    pub npc_tls_addr: Option<std::net::SocketAddr>,
+    #[arg(
+        long,
+        requires = "npc_tls_addr",
+        help = "PEM certificate chain the NPC TLS listener presents"
+    )]
+    pub npc_tls_cert: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "npc_tls_addr",
+        help = "PEM private key for --npc-tls-cert"
+    )]
+    pub npc_tls_key: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "npc_tls_addr",
+        help = "PEM CA certificates; only NPC TLS clients with a certificate one of them signed are let in"
+    )]
+    pub npc_tls_client_ca: Option<PathBuf>,
     #[arg(long, help = "Mine in-kernel", default_value = "false")]
     pub mine: bool,
     #[arg(
@@ -55,30 +149,63 @@ pub struct NockchainCli {
     pub mining_pubkey: Option<String>,
     #[arg(
         long,
-        help = "Advanced mining key configuration (mutually exclusive with --mining-pubkey). Format: share,m:key1,key2,key3",
+        help = "Advanced mining key configuration (mutually exclusive with --mining-pubkey), one per lock the coinbase is split between. Format: share,m:key1,key2,key3, with the share a proportion or a percentage like 40%",
         value_parser = value_parser!(MiningKeyConfig),
         num_args = 1..,
-        value_delimiter = ',',
     )]
     pub mining_key_adv: Option<Vec<MiningKeyConfig>>,
     #[arg(long, help = "Whether to run as fakenet", default_value_t = false)]
     pub fakenet: bool,
+    #[arg(
+        long,
+        help = "Run a local fakenet for integration tests: no default peers, and blocks only when the regtest_generate JSON-RPC call asks for them, or all the time with --mine",
+        default_value_t = false
+    )]
+    pub regtest: bool,
     #[arg(long, short, help = "Initial peer", action = ArgAction::Append)]
     pub peer: Vec<String>,
     #[arg(long, short, help = "Force peer", action = ArgAction::Append)]
     pub force_peer: Vec<String>,
+    #[arg(
+        long,
+        help = "DNS seed as host:port, whose A and AAAA records are peers listening for QUIC on that port",
+        action = ArgAction::Append
+    )]
+    pub dns_seed: Vec<String>,
     #[arg(long, help = "Allowed peer IDs file")]
     pub allowed_peers_path: Option<String>,
-    #[arg(long, help = "Don't dial default peers")]
+    #[arg(long, help = "Don't dial default peers or use default DNS seeds")]
     pub no_default_peers: bool,
-    #[arg(long, help = "Bind address", action = ArgAction::Append)]
+    #[arg(
+        long,
+        help = "Address to listen on, over QUIC like /ip4/0.0.0.0/udp/3006/quic-v1 or TCP like /ip4/0.0.0.0/tcp/3006",
+        action = ArgAction::Append
+    )]
     pub bind: Vec<String>,
+    #[arg(
+        long,
+        help = "Address peers can reach us on, advertised to them, when it isn't a bind address (e.g. a forwarded port)",
+        action = ArgAction::Append
+    )]
+    pub external_address: Vec<String>,
     #[arg(
         long,
         help = "Generate a new peer ID, discarding the existing one",
         default_value = "false"
     )]
     pub new_peer_id: bool,
+    #[arg(
+        long,
+        help = "Remember peers in this file and dial them first on restart",
+        default_value = PEER_STORE_PATH
+    )]
+    pub peer_store: PathBuf,
+    #[arg(
+        long,
+        help = "Don't remember peers across restarts",
+        default_value = "false"
+    )]
+    pub no_peer_store: bool,
     #[arg(long, help = "Maximum established incoming connections")]
     pub max_established_incoming: Option<u32>,
     #[arg(long, help = "Maximum established outgoing connections")]
@@ -100,8 +227,176 @@ pub struct NockchainCli {
     pub max_system_memory_fraction: Option<f64>,
     #[arg(long, help = "Maximum process memory for connection limits (bytes)")]
     pub max_system_memory_bytes: Option<usize>,
-    #[arg(long, help = "Number of threads to mine with defaults to one less than the number of cpus available.", default_value = None)]
+    #[arg(
+        long,
+        help = "Number of threads to mine with, each trying its own nonces. Defaults to one less than the number of physical cores."
+    )]
     pub num_threads: Option<u64>,
+    #[arg(
+        long,
+        help = "Number of threads the proving jets share across all mining threads. Defaults to one per cpu."
+    )]
+    pub prover_threads: Option<usize>,
+    #[arg(
+        long,
+        help = "Cores to run the mining and proving threads on, e.g. 2-7 or 0,2,4. Each mining thread is pinned to one of them, and --num-threads and --prover-threads default to how many there are. Linux only."
+    )]
+    pub mining_cores: Option<Cores>,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Priority::Normal,
+        help = "Scheduling priority of the mining and proving threads: batch never preempts the node's other threads, and idle only runs on cores nothing else wants. Linux only."
+    )]
+    pub mining_priority: Priority,
+    #[arg(
+        long,
+        help = "Serve Prometheus metrics at /metrics on this address, e.g. 127.0.0.1:9100. Off by default."
+    )]
+    pub metrics_addr: Option<std::net::SocketAddr>,
+    #[arg(
+        long,
+        help = "Serve JSON-RPC over HTTP and WebSocket on this address, e.g. 127.0.0.1:8545. Off by default."
+    )]
+    pub rpc_addr: Option<std::net::SocketAddr>,
+    #[arg(
+        long,
+        help = "Serve gRPC, with peeks and streams of new heads, reorgs and pending transactions, on this address, e.g. 127.0.0.1:50051. Off by default."
+    )]
+    pub grpc_addr: Option<std::net::SocketAddr>,
+    #[arg(
+        long,
+        help = "Serve the read-only REST explorer API under /api on this address, e.g. 0.0.0.0:8080. Off by default."
+    )]
+    pub explorer_addr: Option<std::net::SocketAddr>,
+    #[arg(
+        long,
+        help = "Serve /healthz and /readyz probes on this address, e.g. 0.0.0.0:8081. Off by default."
+    )]
+    pub health_addr: Option<std::net::SocketAddr>,
+    #[arg(
+        long,
+        default_value_t = 60,
+        help = "Seconds the kernel may go without answering a peek before /healthz fails"
+    )]
+    pub health_serf_timeout: u64,
+    #[arg(
+        long,
+        default_value_t = 3600,
+        help = "Seconds old the heaviest block may be before /readyz fails, or 0 not to check"
+    )]
+    pub ready_max_block_age: u64,
+    #[arg(
+        long,
+        default_value_t = 1,
+        help = "Fewest connected peers for /readyz to pass"
+    )]
+    pub ready_min_peers: usize,
+    #[arg(
+        long,
+        default_value_t = 3600,
+        help = "Seconds old the latest checkpoint may be before /readyz fails, or 0 not to check"
+    )]
+    pub ready_max_checkpoint_age: u64,
+    #[arg(
+        long,
+        help = "Hand out mining work to miners in other processes or on other machines over a stratum-style protocol on this address, e.g. 0.0.0.0:3333. Needs --mining-pubkey. Off by default."
+    )]
+    pub stratum_addr: Option<std::net::SocketAddr>,
+    #[arg(
+        long,
+        help = "Keep an index from addresses to the transactions that touch them in this database file, for index_getAddressTransactions and the explorer. Off by default."
+    )]
+    pub address_index: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Most bytes of jammed transactions to keep in the mempool, evicting those paying the least per byte beyond it. 0 for no limit. Defaults to 300000000."
+    )]
+    pub mempool_max_bytes: Option<u64>,
+    #[arg(
+        long,
+        help = "Least fee, in nicks per thousand bytes of jammed transaction, for the mempool to admit and relay a transaction. Defaults to 0."
+    )]
+    pub min_relay_fee: Option<u64>,
+    #[arg(
+        long,
+        help = "Most bytes of jammed transaction the mempool admits and relays. No limit by default."
+    )]
+    pub max_relay_tx_bytes: Option<u64>,
+    #[arg(
+        long,
+        help = "Least nicks each output of a transaction must receive for the mempool to admit and relay it. Defaults to 0."
+    )]
+    pub dust_threshold: Option<u64>,
+    #[arg(
+        long,
+        help = "Most outputs a transaction may pay for the mempool to admit and relay it. No limit by default."
+    )]
+    pub max_tx_outputs: Option<u64>,
+    #[arg(
+        long,
+        help = "Most pubkeys in the lock of each output of a transaction the mempool admits and relays. Keys no one holds can carry arbitrary data. No limit by default."
+    )]
+    pub max_lock_keys: Option<u64>,
+    #[arg(
+        long,
+        default_value_t = crate::mempool::DEFAULT_SAVE_SECS,
+        help = "How often to save the mempool to the data directory, to restore it on the next start. It's also saved on shutdown. 0 to neither save nor restore it."
+    )]
+    pub mempool_save_secs: u64,
+    #[arg(
+        long,
+        help = "Run a pruned node: discard the balances and transactions of blocks more than this many blocks below the heaviest, keeping their headers. 0 to stop pruning, though what was pruned stays gone. Kept between runs. Archival by default."
+    )]
+    pub prune_depth: Option<u64>,
+    #[arg(
+        long,
+        help = "While pruning, keep the balance of every block at a height divisible by this, so chain_getStateAt can still answer for old heights. 0 to keep none. Kept between runs. An unpruned node keeps every balance regardless."
+    )]
+    pub snapshot_interval: Option<u64>,
+    #[arg(
+        long,
+        help = "Fast sync a node with no blocks from a snapshot served by the JSON-RPC server at this URL, instead of validating every block from genesis. Needs --fast-sync-hash or --fast-sync-peer to trust its balances."
+    )]
+    pub fast_sync: Option<String>,
+    #[arg(
+        long,
+        help = "Height of the snapshot to fast sync from. Defaults to 10 below the source's heaviest block."
+    )]
+    pub fast_sync_height: Option<u64>,
+    #[arg(
+        long,
+        help = "Blake3 hash, in hex, that the fast sync snapshot must have"
+    )]
+    pub fast_sync_hash: Option<String>,
+    #[arg(
+        long,
+        help = "JSON-RPC server of another node whose snapshot at the same height must hash the same. May be given more than once.",
+        action = ArgAction::Append
+    )]
+    pub fast_sync_peer: Vec<String>,
+    #[arg(
+        long,
+        help = "Fast sync a node with no blocks from the snapshot peers serve at --fast-sync-height, checked against --fast-sync-hash, instead of from a JSON-RPC server",
+        default_value = "false",
+        conflicts_with = "fast_sync",
+        requires_all = ["fast_sync_height", "fast_sync_hash"]
+    )]
+    pub fast_sync_from_peers: bool,
+    #[arg(
+        long,
+        help = "Serve snapshots of the chain to peers that fast sync from peers, and over the JSON-RPC server if it's on. Pruned nodes can only serve heights they kept balances for.",
+        default_value = "false"
+    )]
+    pub serve_snapshots: bool,
+    #[arg(
+        long,
+        help = "Relay memos, small signed notes like payment requests, between wallets, and hold them for wallets to fetch over the JSON-RPC server.",
+        default_value = "false"
+    )]
+    pub relay_memos: bool,
+    #[command(flatten)]
+    pub backup: BackupArgs,
     #[arg(
         long,
         help = "Size of Proof of Work puzzle for mining on fakenet. Mainnet uses 64. Must be a power of 2. Defaults to 2. Ignored on mainnet.",
@@ -114,11 +409,97 @@ pub struct NockchainCli {
         default_value = "1"
     )]
     pub fakenet_log_difficulty: Option<u64>,
+    #[arg(
+        long,
+        help = "Blocks before a coinbase can be spent on fakenet. Defaults to 0. Ignored on mainnet.",
+        default_value = "0"
+    )]
+    pub fakenet_coinbase_timelock: Option<u64>,
     #[arg(long, help = "Path to fake genesis block jam file")]
     pub fakenet_genesis_jam_path: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "JSON file describing a private network to run a fakenet node on: its name, genesis block and seal, peers and constants",
+        conflicts_with = "fakenet_genesis_jam_path"
+    )]
+    pub genesis_file: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<NockchainCommand>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum NockchainCommand {
+    #[command(flatten)]
+    State(nockapp::kernel::boot::StateCommand),
+    /// Replay and revalidate the stored chain in a fresh kernel, rebuild the address index if
+    /// there is one, and exit, without downloading anything
+    Reindex,
+    /// Write every block of the stored heaviest chain, with its transactions, to a block file
+    ExportBlocks(BlocksArgs),
+    /// Hear every block of a block file, as if from a peer, without any networking, and exit
+    ImportBlocks(BlocksArgs),
+    /// Sync only block headers from a node's JSON-RPC server, without booting a kernel, and check
+    /// transaction inclusion proofs against them
+    Light(LightArgs),
+    /// Prove the mining puzzle for a while without joining a network, and report proofs per
+    /// second, where the time went, and the memory used
+    BenchPow(BenchArgs),
+    /// Report on a running node over its JSON-RPC server: the chain tip, sync, peers, mempool,
+    /// mining and the last checkpoint
+    Status(StatusArgs),
+    /// Run a local network of nodes in their own directories, one of them mining, for
+    /// integration tests
+    Devnet(DevnetArgs),
+    /// Check the stored genesis block against the network's published genesis, and print an
+    /// attestation of the result signed with the node's identity
+    VerifyGenesis(VerifyGenesisArgs),
+    /// Download a checkpoint backup from `--backup-url` into the data directory to boot from, or
+    /// list the backups there
+    RestoreFromRemote(RestoreArgs),
+    /// Show the settings layered from `nockchain.toml`, the environment and the command line
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
 }
 
 impl NockchainCli {
+    /// `--regtest` is a fakenet that doesn't dial the default peers, and `--genesis-file` a
+    /// fakenet that dials the file's peers instead
+    pub fn with_implied_flags(mut self) -> Self {
+        if self.regtest {
+            self.fakenet = true;
+            self.no_default_peers = true;
+        }
+        if self.genesis_file.is_some() {
+            self.fakenet = true;
+        }
+        self
+    }
+
+    pub fn reindexing(&self) -> bool {
+        matches!(self.command, Some(NockchainCommand::Reindex))
+    }
+
+    /// What the mempool admits beyond validity, from the relay flags
+    pub fn relay_policy(&self) -> RelayPolicy {
+        RelayPolicy {
+            min_fee_per_kb: self.min_relay_fee.unwrap_or(0),
+            max_tx_bytes: self.max_relay_tx_bytes,
+            dust: self.dust_threshold.unwrap_or(0),
+            max_seeds: self.max_tx_outputs,
+            max_lock_keys: self.max_lock_keys,
+        }
+    }
+
+    /// The block file `import-blocks` reads, if that's the command
+    pub fn importing(&self) -> Option<&Path> {
+        match &self.command {
+            Some(NockchainCommand::ImportBlocks(args)) => Some(&args.path),
+            _ => None,
+        }
+    }
+
     pub fn validate(&self) -> Result<(), String> {
         if self.mine && !(self.mining_pubkey.is_some() || self.mining_key_adv.is_some()) {
             return Err(
@@ -132,6 +513,91 @@ impl NockchainCli {
             );
         }
 
+        if let Some(cores) = &self.mining_cores {
+            let available = num_cpus::get();
+            if let Some(core) = cores.0.iter().find(|&&core| core >= available) {
+                return Err(format!(
+                    "Cannot mine on core {}, there are only {}",
+                    core, available
+                ));
+            }
+        }
+
+        if let Some(configs) = &self.mining_key_adv {
+            check_split(configs)?;
+        }
+
+        if self.regtest && !(self.mining_pubkey.is_some() || self.mining_key_adv.is_some()) {
+            return Err(
+                "Cannot run regtest without either mining_pubkey or mining_key_adv to mine to"
+                    .to_string(),
+            );
+        }
+
+        if self.regtest && !self.mine && self.rpc_addr.is_none() {
+            return Err(
+                "Cannot run regtest without mine or an rpc_addr to ask for blocks over".to_string(),
+            );
+        }
+
+        if self.stratum_addr.is_some()
+            && !(self.mining_pubkey.is_some() || self.mining_key_adv.is_some())
+        {
+            return Err(
+                "Cannot serve stratum without either mining_pubkey or mining_key_adv to mine to"
+                    .to_string(),
+            );
+        }
+
+        for seed in &self.dns_seed {
+            seed.parse::<DnsSeed>()?;
+        }
+
+        if self.fast_sync.is_some()
+            && self.fast_sync_hash.is_none()
+            && self.fast_sync_peer.is_empty()
+        {
+            return Err(
+                "Cannot fast_sync without a fast_sync_hash or fast_sync_peer to check the snapshot against"
+                    .to_string(),
+            );
+        }
+
+        if self.reindexing() && self.fast_sync.is_some() {
+            return Err("Cannot fast_sync while reindexing the stored chain".to_string());
+        }
+        if self.importing().is_some() && self.fast_sync.is_some() {
+            return Err("Cannot fast_sync while importing blocks".to_string());
+        }
+        if (self.reindexing() || self.importing().is_some()) && self.fast_sync_from_peers {
+            return Err(
+                "Cannot fast_sync_from_peers while reindexing or importing blocks".to_string(),
+            );
+        }
+
+        if self.backup.backup_url.is_some() {
+            if self.backup.backup_key_file.is_none() {
+                return Err("Cannot back up without a backup_key_file to encrypt with".to_string());
+            }
+            if self.backup.backup_interval == 0 {
+                return Err("backup_interval must be at least 1".to_string());
+            }
+        }
+
+        if let Some(depth) = self.prune_depth {
+            if depth > 0 && depth < MIN_PRUNE_DEPTH {
+                return Err(format!(
+                    "prune_depth must be 0 or at least {MIN_PRUNE_DEPTH}"
+                ));
+            }
+            if depth > 0 && self.address_index.is_some() {
+                return Err(
+                    "Cannot keep an address_index on a pruned node, since it indexes every block"
+                        .to_string(),
+                );
+            }
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,429 @@
+//! `nockchain devnet`: a local network of nodes for integration tests.
+//!
+//! `devnet up -n 4` runs that many nodes of this binary, each in its own directory
+//! `<dir>/node-<i>`, so each has its own data, peer id and NPC socket. Node `i` listens for peers
+//! on `127.0.0.1` UDP port `--port` + `i` and serves JSON-RPC on port `--rpc-port` + `i`, every
+//! node dials every other, and only node 0 mines, paying `--mining-pubkey`. The nodes are
+//! `--regtest`, so they share the fakenet genesis block and dial nobody else. Each logs to
+//! `node.log` in its directory, and arguments after `--` are given to every node.
+//!
+//! `up` waits for every node to answer `node_status`, then runs until it's interrupted or a node
+//! exits, and stops them all. `devnet down` stops the nodes of a devnet that's `up` in another
+//! shell, from the pids they leave in their directories, and `--clean` deletes the directories.
+//! Nodes are sent SIGTERM, to shut down as they do on Ctrl-C, and killed if they haven't after
+//! [`STOP_TIMEOUT`].
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use clap::{Args, Subcommand};
+use reqwest::Client;
+use serde_json::json;
+use thiserror::Error;
+use tokio::process::{Child, Command};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{info, warn};
+
+use crate::snapshot::call;
+
+/// How long a node has to shut down before it's killed
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long to wait for the nodes to answer once started
+pub const READY_TIMEOUT: Duration = Duration::from_secs(300);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+const DEFAULT_DIR: &str = ".devnet";
+const PID_FILE: &str = "pid";
+const LOG_FILE: &str = "node.log";
+const NPC_SOCKET: &str = "nockchain.sock";
+
+#[derive(Args, Debug, Clone)]
+pub struct DevnetArgs {
+    #[command(subcommand)]
+    pub command: DevnetCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum DevnetCommand {
+    /// Start the nodes and run them until interrupted
+    Up(UpArgs),
+    /// Stop the nodes of a devnet running in another shell
+    Down(DownArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct UpArgs {
+    #[arg(
+        short = 'n',
+        long,
+        default_value_t = 4,
+        value_parser = clap::value_parser!(u16).range(1..=64),
+        help = "How many nodes to run"
+    )]
+    pub nodes: u16,
+    #[arg(long, default_value = DEFAULT_DIR, help = "Where to keep the nodes' directories")]
+    pub dir: PathBuf,
+    #[arg(long, help = "Public key the mining node pays")]
+    pub mining_pubkey: String,
+    #[arg(
+        long,
+        default_value_t = 3006,
+        help = "UDP port the first node listens for peers on, with the others on the ports after it"
+    )]
+    pub port: u16,
+    #[arg(
+        long,
+        default_value_t = 8545,
+        help = "Port the first node serves JSON-RPC on, with the others on the ports after it"
+    )]
+    pub rpc_port: u16,
+    #[arg(
+        long,
+        help = "Delete the nodes' directories first, to start from genesis"
+    )]
+    pub fresh: bool,
+    #[arg(last = true, help = "Arguments for every node")]
+    pub node_args: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DownArgs {
+    #[arg(long, default_value = DEFAULT_DIR, help = "Where the nodes' directories are")]
+    pub dir: PathBuf,
+    #[arg(long, help = "Delete the nodes' directories once they've stopped")]
+    pub clean: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum DevnetError {
+    #[error("Could not {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error("A devnet is already running in {0}")]
+    AlreadyUp(PathBuf),
+    #[error("{0} nodes from port {1} run past port 65535")]
+    Ports(u16, u16),
+    #[error("Node {0} exited with {1}, see {2}")]
+    NodeExited(usize, ExitStatus, PathBuf),
+}
+
+fn io(what: impl Into<String>) -> impl FnOnce(std::io::Error) -> DevnetError {
+    let what = what.into();
+    move |e| DevnetError::Io(what, e)
+}
+
+struct Node {
+    index: usize,
+    dir: PathBuf,
+    rpc: String,
+    child: Child,
+}
+
+fn node_dir(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("node-{index}"))
+}
+
+fn peer_addr(port: u16) -> String {
+    format!("/ip4/127.0.0.1/udp/{port}/quic-v1")
+}
+
+/// Run `nockchain devnet`
+pub async fn run(args: &DevnetArgs) -> Result<(), DevnetError> {
+    match &args.command {
+        DevnetCommand::Up(up_args) => up(up_args).await,
+        DevnetCommand::Down(down_args) => down(down_args).await,
+    }
+}
+
+async fn up(args: &UpArgs) -> Result<(), DevnetError> {
+    if !running(&args.dir).is_empty() {
+        return Err(DevnetError::AlreadyUp(args.dir.clone()));
+    }
+    for first in [args.port, args.rpc_port] {
+        if first.checked_add(args.nodes - 1).is_none() {
+            return Err(DevnetError::Ports(args.nodes, first));
+        }
+    }
+    if args.fresh && args.dir.exists() {
+        fs::remove_dir_all(&args.dir).map_err(io(format!("delete {}", args.dir.display())))?;
+    }
+    let exe = std::env::current_exe().map_err(io("find the nockchain binary"))?;
+    let peers: Vec<String> = (0..args.nodes).map(|i| peer_addr(args.port + i)).collect();
+    let mut nodes = Vec::with_capacity(peers.len());
+    for index in 0..peers.len() {
+        match spawn_node(args, &exe, &peers, index) {
+            Ok(node) => nodes.push(node),
+            Err(e) => {
+                stop(&mut nodes).await;
+                return Err(e);
+            }
+        }
+    }
+    for (node, peer) in nodes.iter().zip(&peers) {
+        let role = if node.index == 0 { "miner" } else { "node" };
+        println!(
+            "{} {}  {}  {}  {}",
+            role,
+            node.index,
+            peer,
+            node.rpc,
+            node.dir.join(LOG_FILE).display()
+        );
+    }
+
+    let result = tokio::select! {
+        ready = wait_ready(&mut nodes) => match ready {
+            Ok(()) => {
+                info!("Devnet of {} nodes is up, Ctrl-C to stop it", nodes.len());
+                tokio::select! {
+                    exited = watch(&mut nodes) => exited,
+                    interrupted = interrupted() => interrupted,
+                }
+            }
+            Err(e) => Err(e),
+        },
+        interrupted = interrupted() => interrupted,
+    };
+    info!("Stopping the devnet");
+    stop(&mut nodes).await;
+    result
+}
+
+fn spawn_node(
+    args: &UpArgs,
+    exe: &Path,
+    peers: &[String],
+    index: usize,
+) -> Result<Node, DevnetError> {
+    let dir = node_dir(&args.dir, index);
+    fs::create_dir_all(&dir).map_err(io(format!("create {}", dir.display())))?;
+    let log_path = dir.join(LOG_FILE);
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .map_err(io(format!("open {}", log_path.display())))?;
+    let stderr = log
+        .try_clone()
+        .map_err(io(format!("open {}", log_path.display())))?;
+    // A node that was killed leaves its socket behind
+    let _ = fs::remove_file(dir.join(NPC_SOCKET));
+
+    let rpc_port = args.rpc_port + index as u16;
+    let mut command = Command::new(exe);
+    command
+        .current_dir(&dir)
+        .arg("--regtest")
+        .args(["--mining-pubkey", &args.mining_pubkey])
+        .args(["--npc-socket", NPC_SOCKET])
+        .args(["--bind", &peers[index]])
+        .args(["--rpc-addr", &format!("127.0.0.1:{rpc_port}")]);
+    for (other, peer) in peers.iter().enumerate() {
+        if other != index {
+            command.args(["--peer", peer]);
+        }
+    }
+    if index == 0 {
+        command.arg("--mine");
+    }
+    let child = command
+        .args(&args.node_args)
+        .stdin(Stdio::null())
+        .stdout(log)
+        .stderr(stderr)
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(io(format!("start node {index}")))?;
+    if let Some(pid) = child.id() {
+        fs::write(dir.join(PID_FILE), pid.to_string())
+            .map_err(io(format!("write the pid of node {index}")))?;
+    }
+    Ok(Node {
+        index,
+        dir,
+        rpc: format!("http://127.0.0.1:{rpc_port}"),
+        child,
+    })
+}
+
+/// Wait for every node to answer `node_status`, or for [`READY_TIMEOUT`]
+async fn wait_ready(nodes: &mut [Node]) -> Result<(), DevnetError> {
+    let client = Client::new();
+    let deadline = Instant::now() + READY_TIMEOUT;
+    for node in nodes.iter_mut() {
+        loop {
+            if call(&client, &node.rpc, "node_status", json!([]))
+                .await
+                .is_ok()
+            {
+                info!("Node {} is up", node.index);
+                break;
+            }
+            exited(node)?;
+            if Instant::now() > deadline {
+                warn!(
+                    "Node {} isn't answering yet, see {}",
+                    node.index,
+                    node.dir.join(LOG_FILE).display()
+                );
+                break;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+    Ok(())
+}
+
+/// Wait for a node to exit. One that stopped cleanly, as `devnet down` stops them, stops the rest.
+async fn watch(nodes: &mut [Node]) -> Result<(), DevnetError> {
+    loop {
+        for node in nodes.iter_mut() {
+            match exited(node) {
+                Ok(true) => {
+                    info!("Node {} stopped", node.index);
+                    return Ok(());
+                }
+                Ok(false) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Whether the node has exited cleanly, or an error if it exited otherwise
+fn exited(node: &mut Node) -> Result<bool, DevnetError> {
+    match node
+        .child
+        .try_wait()
+        .map_err(io(format!("check on node {}", node.index)))?
+    {
+        Some(status) if status.success() => Ok(true),
+        Some(status) => Err(DevnetError::NodeExited(
+            node.index,
+            status,
+            node.dir.join(LOG_FILE),
+        )),
+        None => Ok(false),
+    }
+}
+
+async fn interrupted() -> Result<(), DevnetError> {
+    let mut term = signal(SignalKind::terminate()).map_err(io("listen for SIGTERM"))?;
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = term.recv() => {}
+    }
+    Ok(())
+}
+
+/// Ask every node to shut down, and kill those that haven't after [`STOP_TIMEOUT`]
+async fn stop(nodes: &mut [Node]) {
+    for node in nodes.iter() {
+        if let Some(pid) = node.child.id() {
+            terminate(pid as libc::pid_t);
+        }
+    }
+    let deadline = tokio::time::Instant::now() + STOP_TIMEOUT;
+    for node in nodes.iter_mut() {
+        match tokio::time::timeout_at(deadline, node.child.wait()).await {
+            Ok(Ok(status)) if !status.success() => {
+                warn!("Node {} exited with {}", node.index, status)
+            }
+            Ok(_) => {}
+            Err(_) => {
+                warn!("Node {} didn't stop, killing it", node.index);
+                let _ = node.child.kill().await;
+            }
+        }
+        let _ = fs::remove_file(node.dir.join(PID_FILE));
+    }
+}
+
+fn terminate(pid: libc::pid_t) {
+    unsafe {
+        libc::kill(pid, libc::SIGTERM);
+    }
+}
+
+fn alive(pid: libc::pid_t) -> bool {
+    unsafe { libc::kill(pid, 0) == 0 }
+}
+
+/// The node directories in `dir` with a running node's pid in them
+fn running(dir: &Path) -> Vec<(PathBuf, libc::pid_t)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut nodes: Vec<(PathBuf, libc::pid_t)> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("node-"))
+        })
+        .filter_map(|path| {
+            let pid = fs::read_to_string(path.join(PID_FILE))
+                .ok()?
+                .trim()
+                .parse()
+                .ok()?;
+            Some((path, pid))
+        })
+        .filter(|(_, pid)| alive(*pid))
+        .collect();
+    nodes.sort();
+    nodes
+}
+
+async fn down(args: &DownArgs) -> Result<(), DevnetError> {
+    let nodes = running(&args.dir);
+    if nodes.is_empty() {
+        info!("No devnet is running in {}", args.dir.display());
+    }
+    for (_, pid) in &nodes {
+        terminate(*pid);
+    }
+    let deadline = Instant::now() + STOP_TIMEOUT;
+    while nodes.iter().any(|(_, pid)| alive(*pid)) && Instant::now() < deadline {
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    for (dir, pid) in &nodes {
+        if alive(*pid) {
+            warn!("{} didn't stop, killing it", dir.display());
+            unsafe {
+                libc::kill(*pid, libc::SIGKILL);
+            }
+        }
+        let _ = fs::remove_file(dir.join(PID_FILE));
+        info!("Stopped {}", dir.display());
+    }
+    if args.clean && args.dir.exists() {
+        fs::remove_dir_all(&args.dir).map_err(io(format!("delete {}", args.dir.display())))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_running() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(running(dir.path()).is_empty());
+        let node = node_dir(dir.path(), 0);
+        fs::create_dir_all(&node).unwrap();
+        fs::write(node.join(PID_FILE), std::process::id().to_string()).unwrap();
+        // a pid file that isn't a node's, and one whose process is gone
+        fs::write(dir.path().join(PID_FILE), std::process::id().to_string()).unwrap();
+        let gone = node_dir(dir.path(), 1);
+        fs::create_dir_all(&gone).unwrap();
+        fs::write(gone.join(PID_FILE), i32::MAX.to_string()).unwrap();
+        assert_eq!(
+            running(dir.path()),
+            vec![(node, std::process::id() as libc::pid_t)]
+        );
+    }
+}
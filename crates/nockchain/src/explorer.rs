@@ -0,0 +1,494 @@
+//! Read-only REST API for block explorers.
+//!
+//! Every route answers `GET` with JSON and allows any origin, so a static web explorer can be
+//! served from anywhere and query the node directly. Ids and public keys are base58, as the
+//! wallet prints them, and amounts are in the smallest unit.
+//!
+//! | Route                                      | Result                                        |
+//! |--------------------------------------------|-----------------------------------------------|
+//! | `/api/tip`                                 | the heaviest block                            |
+//! | `/api/blocks/{id}`                         | a block by id                                 |
+//! | `/api/blocks/height/{height}`              | the block at a height on the heaviest chain   |
+//! | `/api/transactions/{id}`                   | `{id, jam}` of a raw transaction              |
+//! | `/api/addresses/{pubkey}?height`           | the notes a public key can spend, and their total |
+//! | `/api/addresses/{pubkey}/history?from&to`  | notes the key received and spent, by height   |
+//! | `/api/addresses/{pubkey}/transactions?from&limit` | `[{height, blockId, txId}]` from the index |
+//! | `/api/richlist?limit`                      | owners by total assets, largest first         |
+//! | `/api/supply`                              | assets and notes in existence at the tip      |
+//!
+//! Blocks are `{id, parent, height, txIds}`, as in [`crate::rpc`]. Owners are locks,
+//! `{m, pubkeys}`, where `m` of the `pubkeys` must sign. History is worked out by comparing the
+//! balance at each height in the range, so it covers at most [`MAX_HISTORY_BLOCKS`] blocks per
+//! request and defaults to the last ten. An address at a `height` is as of the nearest balance
+//! at or below it, as `chain_getStateAt` finds it, and says which height that was. The
+//! transactions route needs the address index of
+//! [`crate::indexer`], and answers 404 without it.
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::{FromRef, Path, Query, State};
+use axum::http::header::ACCESS_CONTROL_ALLOW_ORIGIN;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{serve, Json, Router};
+use nockapp::driver::{make_driver, IODriverFn, NockAppHandle};
+use nockapp::noun::slab::NounSlab;
+use nockapp::utils::make_tas;
+use nockapp::{AtomExt, NockAppError, NounExt};
+use nockvm::noun::{Noun, Slots, D, T};
+use nockvm_macros::tas;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::indexer::{entry_json, AddressIndex};
+use crate::rpc::{block_json, peek, state_at, to_hex, RpcError, MAX_INDEX_ENTRIES};
+
+/// The most heights one history request walks
+pub const MAX_HISTORY_BLOCKS: u64 = 100;
+
+type Handle = Arc<NockAppHandle>;
+
+#[derive(Clone)]
+struct ExplorerState {
+    handle: Handle,
+    index: Option<Arc<AddressIndex>>,
+}
+
+impl FromRef<ExplorerState> for Handle {
+    fn from_ref(state: &ExplorerState) -> Self {
+        state.handle.clone()
+    }
+}
+
+/// Serve the explorer API on `addr`, with address transactions from `index` if there is one
+pub fn make_explorer_driver(addr: SocketAddr, index: Option<Arc<AddressIndex>>) -> IODriverFn {
+    make_driver(move |handle| async move {
+        let app = Router::new()
+            .route("/api/tip", get(tip))
+            .route("/api/blocks/{id}", get(block))
+            .route("/api/blocks/height/{height}", get(block_at))
+            .route("/api/transactions/{id}", get(transaction))
+            .route("/api/addresses/{pubkey}", get(address))
+            .route("/api/addresses/{pubkey}/history", get(history))
+            .route("/api/addresses/{pubkey}/transactions", get(transactions))
+            .route("/api/richlist", get(richlist))
+            .route("/api/supply", get(supply))
+            .with_state(ExplorerState {
+                handle: Arc::new(handle),
+                index,
+            });
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(NockAppError::IoError)?;
+        let local_addr = listener.local_addr().map_err(NockAppError::IoError)?;
+        info!("Serving the explorer API on http://{}/api", local_addr);
+        serve(listener, app.into_make_service())
+            .await
+            .map_err(NockAppError::IoError)
+    })
+}
+
+/// A JSON body or an error, with the CORS header either way
+fn respond(result: Result<Option<Value>, RpcError>) -> Response {
+    let (status, body) = match result {
+        Ok(Some(value)) => (StatusCode::OK, value),
+        Ok(None) => (StatusCode::NOT_FOUND, json!({ "error": "not found" })),
+        Err(e @ RpcError::InvalidParams(_)) => {
+            (StatusCode::BAD_REQUEST, json!({ "error": e.to_string() }))
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            json!({ "error": e.to_string() }),
+        ),
+    };
+    (status, [(ACCESS_CONTROL_ALLOW_ORIGIN, "*")], Json(body)).into_response()
+}
+
+async fn tip(State(handle): State<Handle>) -> Response {
+    respond(tip_block(&handle).await)
+}
+
+async fn block(State(handle): State<Handle>, Path(id): Path<String>) -> Response {
+    let page = peek(&handle, |slab| {
+        let id = make_tas(slab, &id).as_noun();
+        T(slab, &[D(tas!(b"block")), id, D(0)])
+    })
+    .await;
+    respond(page.and_then(|page| {
+        page.map(|page| block_json(unsafe { *page.root() }))
+            .transpose()
+    }))
+}
+
+async fn block_at(State(handle): State<Handle>, Path(height): Path<u64>) -> Response {
+    respond(block_at_height(&handle, height).await)
+}
+
+async fn transaction(State(handle): State<Handle>, Path(id): Path<String>) -> Response {
+    let raw_tx = peek(&handle, |slab| {
+        let tag = make_tas(slab, "raw-transaction").as_noun();
+        let id = make_tas(slab, &id).as_noun();
+        T(slab, &[tag, id, D(0)])
+    })
+    .await;
+    respond(
+        raw_tx.map(|raw_tx| raw_tx.map(|raw_tx| json!({ "id": id, "jam": to_hex(&raw_tx.jam()) }))),
+    )
+}
+
+#[derive(Deserialize)]
+struct AtHeight {
+    height: Option<u64>,
+}
+
+async fn address(
+    State(handle): State<Handle>,
+    Path(pubkey): Path<String>,
+    Query(at): Query<AtHeight>,
+) -> Response {
+    let result = async {
+        let (height, block_id) = match at.height {
+            Some(height) => match state_at(&handle, height).await? {
+                Some((height, block_id, _)) => (json!(height), block_id),
+                None => return Ok(None),
+            },
+            None => match tip_block(&handle).await? {
+                Some(tip) => (tip["height"].clone(), block_id(&tip)?.to_string()),
+                None => return Ok(None),
+            },
+        };
+        let notes: Vec<Note> = balance_notes(&handle, &block_id)
+            .await?
+            .into_iter()
+            .filter(|note| note.pks.contains(&pubkey))
+            .collect();
+        let balance: u64 = notes.iter().map(|note| note.assets).sum();
+        Ok(Some(json!({
+            "address": pubkey,
+            "height": height,
+            "balance": balance,
+            "notes": notes.iter().map(Note::to_json).collect::<Vec<_>>(),
+        })))
+    }
+    .await;
+    respond(result)
+}
+
+#[derive(Deserialize)]
+struct HistoryRange {
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+async fn history(
+    State(handle): State<Handle>,
+    Path(pubkey): Path<String>,
+    Query(range): Query<HistoryRange>,
+) -> Response {
+    let result = async {
+        let Some(tip) = tip_block(&handle).await? else {
+            return Ok(None);
+        };
+        let tip_height = tip["height"].as_u64().unwrap_or(0);
+        let to = range.to.unwrap_or(tip_height).min(tip_height);
+        let from = range.from.unwrap_or(to.saturating_sub(9));
+        if from > to || to - from >= MAX_HISTORY_BLOCKS {
+            return Err(RpcError::InvalidParams(format!(
+                "from must be at most to, and at most {} blocks before it",
+                MAX_HISTORY_BLOCKS - 1
+            )));
+        }
+        // The key's notes before the range, to compare the first height with
+        let mut held: HashMap<(String, String), Note> = match from.checked_sub(1) {
+            Some(before) => notes_of_at(&handle, &pubkey, before).await?,
+            None => HashMap::new(),
+        };
+        let mut changes = Vec::new();
+        for height in from..=to {
+            let Some(block) = block_at_height(&handle, height).await? else {
+                break;
+            };
+            let now = notes_of(&handle, &pubkey, block_id(&block)?).await?;
+            let received: Vec<Value> = now
+                .iter()
+                .filter(|(name, _)| !held.contains_key(*name))
+                .map(|(_, note)| note.to_json())
+                .collect();
+            let spent: Vec<Value> = held
+                .iter()
+                .filter(|(name, _)| !now.contains_key(*name))
+                .map(|(_, note)| note.to_json())
+                .collect();
+            if !received.is_empty() || !spent.is_empty() {
+                changes.push(json!({
+                    "height": height,
+                    "blockId": block["id"],
+                    "received": received,
+                    "spent": spent,
+                }));
+            }
+            held = now;
+        }
+        Ok(Some(
+            json!({ "address": pubkey, "from": from, "to": to, "changes": changes }),
+        ))
+    }
+    .await;
+    respond(result)
+}
+
+#[derive(Deserialize)]
+struct Page {
+    from: Option<u64>,
+    limit: Option<u64>,
+}
+
+async fn transactions(
+    State(state): State<ExplorerState>,
+    Path(pubkey): Path<String>,
+    Query(page): Query<Page>,
+) -> Response {
+    let Some(index) = state.index.as_ref() else {
+        return respond(Ok(None));
+    };
+    let limit = page
+        .limit
+        .unwrap_or(MAX_INDEX_ENTRIES)
+        .min(MAX_INDEX_ENTRIES);
+    let result = index
+        .entries(&pubkey, page.from.unwrap_or(0), limit as usize)
+        .and_then(|entries| {
+            entries
+                .iter()
+                .map(|entry| entry_json(index, entry))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map(|entries| Some(json!({ "address": pubkey, "transactions": entries })))
+        .map_err(RpcError::from);
+    respond(result)
+}
+
+#[derive(Deserialize)]
+struct Limit {
+    limit: Option<usize>,
+}
+
+async fn richlist(State(handle): State<Handle>, Query(limit): Query<Limit>) -> Response {
+    let result = async {
+        let Some(tip) = tip_block(&handle).await? else {
+            return Ok(None);
+        };
+        let notes = balance_notes(&handle, block_id(&tip)?).await?;
+        let mut owners: Vec<((u64, Vec<String>), (u64, usize))> =
+            owner_totals(&notes).into_iter().collect();
+        owners.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then_with(|| a.0.cmp(&b.0)));
+        owners.truncate(limit.limit.unwrap_or(100));
+        let owners: Vec<Value> = owners
+            .into_iter()
+            .map(|((m, pubkeys), (assets, notes))| {
+                json!({ "m": m, "pubkeys": pubkeys, "assets": assets, "notes": notes })
+            })
+            .collect();
+        Ok(Some(json!({ "height": tip["height"], "owners": owners })))
+    }
+    .await;
+    respond(result)
+}
+
+async fn supply(State(handle): State<Handle>) -> Response {
+    let result = async {
+        let Some(tip) = tip_block(&handle).await? else {
+            return Ok(None);
+        };
+        let notes = balance_notes(&handle, block_id(&tip)?).await?;
+        Ok(Some(json!({
+            "height": tip["height"],
+            "blockId": tip["id"],
+            "supply": notes.iter().map(|note| note.assets).sum::<u64>(),
+            "notes": notes.len(),
+            "owners": owner_totals(&notes).len(),
+        })))
+    }
+    .await;
+    respond(result)
+}
+
+/// A note in a balance, as the `%balance-notes` peek describes it
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Note {
+    first: String,
+    last: String,
+    m: u64,
+    pks: Vec<String>,
+    origin: u64,
+    assets: u64,
+}
+
+impl Note {
+    /// Read one `[name=[first last] owners=[m pks] origin assets]`
+    fn from_noun(noun: Noun) -> Option<Self> {
+        let cord = |noun: Noun| noun.as_atom().ok()?.into_string().ok();
+        let number = |noun: Noun| noun.as_atom().ok()?.as_u64().ok();
+        let mut pks: Vec<String> = noun
+            .slot(13)
+            .ok()?
+            .list_iter()
+            .map(cord)
+            .collect::<Option<_>>()?;
+        pks.sort();
+        Some(Note {
+            first: cord(noun.slot(4).ok()?)?,
+            last: cord(noun.slot(5).ok()?)?,
+            m: number(noun.slot(12).ok()?)?,
+            pks,
+            origin: number(noun.slot(14).ok()?)?,
+            assets: number(noun.slot(15).ok()?)?,
+        })
+    }
+
+    fn name(&self) -> (String, String) {
+        (self.first.clone(), self.last.clone())
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "name": { "first": self.first, "last": self.last },
+            "owners": { "m": self.m, "pubkeys": self.pks },
+            "origin": self.origin,
+            "assets": self.assets,
+        })
+    }
+}
+
+/// Assets and note count by owner
+fn owner_totals(notes: &[Note]) -> BTreeMap<(u64, Vec<String>), (u64, usize)> {
+    let mut owners: BTreeMap<(u64, Vec<String>), (u64, usize)> = BTreeMap::new();
+    for note in notes {
+        let total = owners.entry((note.m, note.pks.clone())).or_default();
+        total.0 += note.assets;
+        total.1 += 1;
+    }
+    owners
+}
+
+fn block_id(block: &Value) -> Result<&str, RpcError> {
+    block["id"]
+        .as_str()
+        .ok_or_else(|| RpcError::Internal("block without an id".into()))
+}
+
+pub(crate) async fn tip_block(handle: &NockAppHandle) -> Result<Option<Value>, RpcError> {
+    let page = peek(handle, |slab| {
+        let path = make_tas(slab, "heaviest-block").as_noun();
+        T(slab, &[path, D(0)])
+    })
+    .await?;
+    page.map(|page| block_json(unsafe { *page.root() }))
+        .transpose()
+}
+
+pub(crate) async fn block_at_height(
+    handle: &NockAppHandle,
+    height: u64,
+) -> Result<Option<Value>, RpcError> {
+    if height >= 1 << 63 {
+        return Ok(None);
+    }
+    let page = peek(handle, |slab| {
+        T(slab, &[D(tas!(b"heavy-n")), D(height), D(0)])
+    })
+    .await?;
+    page.map(|page| block_json(unsafe { *page.root() }))
+        .transpose()
+}
+
+async fn balance_notes(handle: &NockAppHandle, block_id: &str) -> Result<Vec<Note>, RpcError> {
+    let notes = peek(handle, |slab| {
+        let tag = make_tas(slab, "balance-notes").as_noun();
+        let id = make_tas(slab, block_id).as_noun();
+        T(slab, &[tag, id, D(0)])
+    })
+    .await?;
+    let Some(notes) = notes else {
+        return Ok(Vec::new());
+    };
+    unsafe { *notes.root() }
+        .list_iter()
+        .map(Note::from_noun)
+        .collect::<Option<_>>()
+        .ok_or_else(|| RpcError::Internal("malformed balance notes".into()))
+}
+
+/// The notes `pubkey` can sign for in the balance at `block_id`, by name
+async fn notes_of(
+    handle: &NockAppHandle,
+    pubkey: &str,
+    block_id: &str,
+) -> Result<HashMap<(String, String), Note>, RpcError> {
+    Ok(balance_notes(handle, block_id)
+        .await?
+        .into_iter()
+        .filter(|note| note.pks.iter().any(|pk| pk == pubkey))
+        .map(|note| (note.name(), note))
+        .collect())
+}
+
+async fn notes_of_at(
+    handle: &NockAppHandle,
+    pubkey: &str,
+    height: u64,
+) -> Result<HashMap<(String, String), Note>, RpcError> {
+    match block_at_height(handle, height).await? {
+        Some(block) => notes_of(handle, pubkey, block_id(&block)?).await,
+        None => Ok(HashMap::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::Atom;
+
+    use super::*;
+
+    fn note(slab: &mut NounSlab, first: &str, pks: &[&str], assets: u64) -> Noun {
+        let first = make_tas(slab, first).as_noun();
+        let last = make_tas(slab, "last").as_noun();
+        let name = T(slab, &[first, last]);
+        let mut list = D(0);
+        for pk in pks.iter().rev() {
+            let pk = make_tas(slab, pk).as_noun();
+            list = T(slab, &[pk, list]);
+        }
+        let owners = T(slab, &[D(1), list]);
+        let assets = Atom::new(slab, assets).as_noun();
+        T(slab, &[name, owners, D(7), assets])
+    }
+
+    #[test]
+    fn test_notes() {
+        let mut slab: NounSlab = NounSlab::new();
+        let a = note(&mut slab, "a", &["pk2", "pk1"], 10);
+        let b = note(&mut slab, "b", &["pk1", "pk2"], 5);
+        let c = note(&mut slab, "c", &["pk3"], 20);
+        let notes: Vec<Note> = [a, b, c]
+            .into_iter()
+            .map(|n| Note::from_noun(n).expect("note"))
+            .collect();
+        assert_eq!(notes[0].first, "a");
+        assert_eq!(notes[0].pks, vec!["pk1", "pk2"]);
+        assert_eq!(notes[0].origin, 7);
+        assert_eq!(notes[2].assets, 20);
+        assert!(Note::from_noun(D(0)).is_none());
+
+        let owners = owner_totals(&notes);
+        assert_eq!(owners.len(), 2);
+        assert_eq!(
+            owners[&(1, vec!["pk1".to_string(), "pk2".to_string()])],
+            (15, 2)
+        );
+        assert_eq!(owners[&(1, vec!["pk3".to_string()])], (20, 1));
+    }
+}
@@ -0,0 +1,464 @@
+//! Genesis files, for private networks and testnets.
+//!
+//! `--genesis-file` starts a fakenet node on a network described by a JSON file, so a new network
+//! needs only that file and a genesis block, not a rebuilt kernel:
+//!
+//! ```json
+//! {
+//!   "network": "devnet-3",
+//!   "seal": "3WNP3WtcQJYtP5PCvFHDQVEeiZEznsULEY5Lc4vUKV64Ge8feBxAkYp",
+//!   "block": "devnet-3-genesis.jam",
+//!   "peers": ["/ip4/10.0.0.1/udp/3006/quic-v1"],
+//!   "constants": { "pow_len": 2, "log_difficulty": 1, "coinbase_timelock": 0 }
+//! }
+//! ```
+//!
+//! `network` goes into the names of the p2p protocols, so nodes of the network only ever talk to
+//! each other. `seal` is the base58 hash of the genesis block's message, which nodes check any
+//! genesis block they hear against, and `block` is the jam of the mined genesis block, relative to
+//! the file, which carries the network's timestamp. `peers` replaces the default peers, and
+//! `constants` overrides the fakenet's, with the initial difficulty as `log_difficulty` and the
+//! most locks a coinbase may be split between as `max_coinbase_split`. The
+//! kernel only accepts a genesis block with an empty coinbase, so a network can't start with a
+//! premine: its first coins are mined like any others.
+//!
+//! `nockchain verify-genesis` checks the genesis block the node has stored against the network's
+//! published configuration, the genesis file or the built-in fakenet or mainnet seal, and prints
+//! an attestation of the result signed with the node's identity key. The block has to hash to its
+//! id, with its message hashing to the seal, no transactions and no premine: nothing in its
+//! coinbase. Where the configuration publishes the block itself, as the fakenet and genesis files
+//! do, the stored one has to have its id. A mainnet genesis block's parent commits to a bitcoin
+//! block, which isn't known offline, so it's printed to check by hand. [`check_attestation`]
+//! checks the signature on one.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::Args;
+use libp2p::identity::{Keypair, PublicKey};
+use nockapp::kernel::boot;
+use nockapp::noun::slab::{Jammer, NounSlab};
+use nockapp::utils::make_tas;
+use nockapp::{Bytes, NockApp, NockAppError};
+use nockchain_libp2p_io::tip5_util::tip5_hash_to_base58;
+use nockvm::jets::hot::HotEntry;
+use nockvm::mem::NockStack;
+use nockvm::noun::{Noun, Slots, D, T};
+use nockvm_macros::tas;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use thiserror::Error;
+use tracing::info;
+use zkvm_jetpack::jets::tip5_jets::hash_hashable;
+
+use crate::light::{Header, HASH_STACK_SIZE};
+use crate::rpc::{from_hex, to_hex, treap_nodes};
+use crate::setup::{self, BlockchainConstants, Seconds};
+use crate::{config, NockchainCli};
+
+#[derive(Debug, Error)]
+pub enum GenesisError {
+    #[error("Could not read genesis file {0}: {1}")]
+    Read(PathBuf, std::io::Error),
+    #[error("Could not parse genesis file {0}: {1}")]
+    Parse(PathBuf, serde_json::Error),
+    #[error("Network name {0} must be lowercase letters, digits and dashes")]
+    Network(String),
+    #[error("Could not boot the stored chain: {0}")]
+    Boot(String),
+    #[error("Could not read the stored chain: {0}")]
+    Kernel(#[from] NockAppError),
+    #[error("The node has no genesis block")]
+    Missing,
+    #[error("Malformed {0}")]
+    Malformed(&'static str),
+    #[error("Could not hash")]
+    Hash,
+    #[error("Could not read identity {0}: {1}")]
+    Identity(PathBuf, String),
+    #[error("Could not sign the attestation: {0}")]
+    Sign(String),
+    #[error("Genesis block {0} does not match the published configuration")]
+    Mismatch(String),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct VerifyGenesisArgs {
+    #[arg(
+        long,
+        default_value = config::IDENTITY_PATH,
+        help = "Identity key to sign the attestation with"
+    )]
+    pub identity: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisConfig {
+    pub network: String,
+    pub seal: String,
+    pub block: PathBuf,
+    #[serde(default)]
+    pub peers: Vec<String>,
+    #[serde(default)]
+    pub constants: GenesisConstants,
+}
+
+/// Blockchain constants to change from the fakenet's
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisConstants {
+    pub pow_len: Option<u64>,
+    pub log_difficulty: Option<u64>,
+    pub coinbase_timelock: Option<u64>,
+    pub max_block_size: Option<u64>,
+    pub blocks_per_epoch: Option<u64>,
+    pub target_epoch_duration_secs: Option<u64>,
+    pub first_month_coinbase_min: Option<u64>,
+    pub max_coinbase_split: Option<u64>,
+}
+
+impl GenesisConfig {
+    pub fn load(path: &Path) -> Result<Self, GenesisError> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| GenesisError::Read(path.to_path_buf(), e))?;
+        let mut config: GenesisConfig = serde_json::from_str(&contents)
+            .map_err(|e| GenesisError::Parse(path.to_path_buf(), e))?;
+        let valid = !config.network.is_empty()
+            && config
+                .network
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if !valid {
+            return Err(GenesisError::Network(config.network));
+        }
+        if let Some(dir) = path.parent() {
+            config.block = dir.join(&config.block);
+        }
+        Ok(config)
+    }
+
+    pub fn constants(&self) -> BlockchainConstants {
+        let overrides = &self.constants;
+        let mut constants = setup::fakenet_blockchain_constaints(
+            overrides.pow_len.unwrap_or(2),
+            overrides.log_difficulty.unwrap_or(1),
+            overrides.coinbase_timelock.unwrap_or(0),
+        );
+        if let Some(max_block_size) = overrides.max_block_size {
+            constants.max_block_size = max_block_size;
+        }
+        if let Some(blocks_per_epoch) = overrides.blocks_per_epoch {
+            constants.blocks_per_epoch = blocks_per_epoch;
+        }
+        if let Some(duration) = overrides.target_epoch_duration_secs {
+            constants.target_epoch_duration = Seconds::new(duration);
+        }
+        if let Some(coinbase_min) = overrides.first_month_coinbase_min {
+            constants.first_month_coinbase_min = coinbase_min;
+        }
+        if let Some(split) = overrides.max_coinbase_split {
+            constants.max_coinbase_split = split;
+        }
+        constants
+    }
+}
+
+/// What the stored genesis block was checked against
+pub struct Published {
+    pub network: String,
+    pub seal: String,
+    /// The jammed genesis block, where the network publishes it
+    pub block: Option<Vec<u8>>,
+}
+
+impl Published {
+    /// The genesis file, or the built-in fakenet or mainnet genesis
+    pub fn from_cli(cli: &NockchainCli) -> Result<Self, GenesisError> {
+        if let Some(path) = &cli.genesis_file {
+            let genesis = GenesisConfig::load(path)?;
+            let block =
+                fs::read(&genesis.block).map_err(|e| GenesisError::Read(genesis.block, e))?;
+            return Ok(Published {
+                network: genesis.network,
+                seal: genesis.seal,
+                block: Some(block),
+            });
+        }
+        if !cli.fakenet {
+            return Ok(Published {
+                network: "mainnet".into(),
+                seal: setup::REALNET_GENESIS_MESSAGE.into(),
+                block: None,
+            });
+        }
+        let block = match &cli.fakenet_genesis_jam_path {
+            Some(path) => fs::read(path).map_err(|e| GenesisError::Read(path.clone(), e))?,
+            None => setup::FAKENET_GENESIS_BLOCK.to_vec(),
+        };
+        Ok(Published {
+            network: "fakenet".into(),
+            seal: setup::FAKENET_GENESIS_MESSAGE.into(),
+            block: Some(block),
+        })
+    }
+}
+
+/// The stored genesis block and how it compares to the published configuration
+#[derive(Debug)]
+pub struct GenesisReport {
+    pub id: String,
+    pub parent: String,
+    pub timestamp: u64,
+    pub msg_hash: String,
+    /// Allocations in the coinbase
+    pub premine: usize,
+    /// The block hashes to its id
+    pub digest: bool,
+    /// Its message hashes to the seal
+    pub seal: bool,
+    /// It's at height 0 with no transactions
+    pub empty: bool,
+    /// It has the published block's id, where there is one
+    pub published: Option<bool>,
+}
+
+impl GenesisReport {
+    /// Check `page`, with its `header`, against `published`
+    pub fn check(page: Noun, header: &Header, published: &Published) -> Result<Self, GenesisError> {
+        let slot = |axis| {
+            page.slot(axis)
+                .map_err(|_| GenesisError::Malformed("genesis block"))
+        };
+        let base58 =
+            |noun| tip5_hash_to_base58(noun).map_err(|_| GenesisError::Malformed("genesis block"));
+        let id = base58(slot(2)?)?;
+        let msg_hash = message_hash(slot(2047)?)?;
+        let height = slot(2046)?.as_atom().ok().and_then(|h| h.as_u64().ok());
+        let published_id = match &published.block {
+            Some(jam) => {
+                let mut block: NounSlab = NounSlab::new();
+                let root = block
+                    .cue_into(Bytes::copy_from_slice(jam))
+                    .map_err(|_| GenesisError::Malformed("published genesis block"))?;
+                let digest = root
+                    .slot(2)
+                    .map_err(|_| GenesisError::Malformed("published genesis block"))?;
+                Some(base58(digest)?)
+            }
+            None => None,
+        };
+        Ok(GenesisReport {
+            parent: base58(slot(14)?)?,
+            timestamp: slot(126)?
+                .as_atom()
+                .ok()
+                .and_then(|t| t.as_u64().ok())
+                .ok_or(GenesisError::Malformed("genesis block"))?,
+            premine: treap_nodes(slot(62)?).len(),
+            digest: header.check_digest().is_ok() && header.id_base58().ok().as_ref() == Some(&id),
+            seal: msg_hash == published.seal,
+            empty: height == Some(0) && treap_nodes(slot(30)?).is_empty(),
+            published: published_id.map(|published_id| published_id == id),
+            id,
+            msg_hash,
+        })
+    }
+
+    pub fn verified(&self) -> bool {
+        self.digest && self.seal && self.empty && self.premine == 0 && self.published != Some(false)
+    }
+
+    /// The statement an attestation signs
+    pub fn statement(&self, published: &Published, at: u64) -> Value {
+        json!({
+            "network": published.network,
+            "seal": published.seal,
+            "genesis": {
+                "id": self.id,
+                "parent": self.parent,
+                "timestamp": self.timestamp,
+                "msgHash": self.msg_hash,
+            },
+            "premine": self.premine,
+            "checks": {
+                "digest": self.digest,
+                "seal": self.seal,
+                "empty": self.empty,
+                "publishedBlock": self.published,
+            },
+            "verified": self.verified(),
+            "attestedAt": at,
+        })
+    }
+}
+
+/// The base58 hash of a page's `msg`, as its genesis seal has it
+pub fn message_hash(msg: Noun) -> Result<String, GenesisError> {
+    let mut stack = NockStack::new(HASH_STACK_SIZE, 0);
+    let leaf = T(&mut stack, &[D(tas!(b"leaf")), msg]);
+    let hash = hash_hashable(&mut stack, leaf).map_err(|_| GenesisError::Hash)?;
+    tip5_hash_to_base58(hash).map_err(|_| GenesisError::Hash)
+}
+
+/// Sign `statement` with `keypair`
+pub fn attest(statement: Value, keypair: &Keypair) -> Result<Value, GenesisError> {
+    let signature = keypair
+        .sign(statement.to_string().as_bytes())
+        .map_err(|e| GenesisError::Sign(e.to_string()))?;
+    Ok(json!({
+        "statement": statement,
+        "peerId": keypair.public().to_peer_id().to_base58(),
+        "publicKey": to_hex(&keypair.public().encode_protobuf()),
+        "signature": to_hex(&signature),
+    }))
+}
+
+/// Whether `attestation` is signed by the key it names, and that key is its peer's
+pub fn check_attestation(attestation: &Value) -> bool {
+    let key = attestation["publicKey"]
+        .as_str()
+        .and_then(from_hex)
+        .and_then(|key| PublicKey::try_decode_protobuf(&key).ok());
+    let signature = attestation["signature"].as_str().and_then(from_hex);
+    let (Some(key), Some(signature)) = (key, signature) else {
+        return false;
+    };
+    attestation["peerId"].as_str() == Some(key.to_peer_id().to_base58().as_str())
+        && key.verify(attestation["statement"].to_string().as_bytes(), &signature)
+}
+
+/// Check the stored genesis block against the published configuration and print a signed
+/// attestation, failing if it doesn't match
+pub async fn verify<J: Jammer + Send + 'static>(
+    args: &VerifyGenesisArgs,
+    cli: &NockchainCli,
+    kernel_jam: &[u8],
+    hot_state: &[HotEntry],
+) -> Result<(), GenesisError> {
+    let cli = cli.clone().with_implied_flags();
+    let published = Published::from_cli(&cli)?;
+    let keypair = fs::read(&args.identity)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| Keypair::from_protobuf_encoding(&bytes).map_err(|e| e.to_string()))
+        .map_err(|e| GenesisError::Identity(args.identity.clone(), e))?;
+    let mut nockapp = boot::setup::<J>(
+        kernel_jam,
+        Some(cli.nockapp_cli.clone()),
+        hot_state,
+        "nockchain",
+        None,
+    )
+    .await
+    .map_err(|e| GenesisError::Boot(e.to_string()))?;
+
+    let page = peek(&mut nockapp, |slab| {
+        T(slab, &[D(tas!(b"heavy-n")), D(0), D(0)])
+    })
+    .await?
+    .ok_or(GenesisError::Missing)?;
+    let header = peek(&mut nockapp, |slab| {
+        let tag = make_tas(slab, "header").as_noun();
+        T(slab, &[tag, D(0), D(0)])
+    })
+    .await?
+    .ok_or(GenesisError::Missing)?;
+    let header =
+        Header::from_jam(header.jam()).map_err(|_| GenesisError::Malformed("genesis header"))?;
+    let report = GenesisReport::check(unsafe { *page.root() }, &header, &published)?;
+
+    let at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let attestation = attest(report.statement(&published, at), &keypair)?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&attestation).expect("json serializes")
+    );
+    if !report.verified() {
+        return Err(GenesisError::Mismatch(report.id));
+    }
+    info!(
+        "Genesis block {} matches the {} configuration",
+        report.id, published.network
+    );
+    Ok(())
+}
+
+async fn peek<J: Jammer + Send + 'static>(
+    nockapp: &mut NockApp<J>,
+    build: impl FnOnce(&mut NounSlab) -> Noun,
+) -> Result<Option<NounSlab>, NockAppError> {
+    let mut path = NounSlab::new();
+    let root = build(&mut path);
+    path.set_root(root);
+    nockapp.peek_handle(path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("genesis.json");
+        fs::write(
+            &path,
+            r#"{"network": "devnet-3", "seal": "abc", "block": "genesis.jam",
+                "constants": {"pow_len": 4, "blocks_per_epoch": 10, "max_coinbase_split": 5}}"#,
+        )
+        .expect("write");
+        let config = GenesisConfig::load(&path).expect("load");
+        assert_eq!(config.block, dir.path().join("genesis.jam"));
+        assert!(config.peers.is_empty());
+        let constants = config.constants();
+        assert_eq!(constants.pow_len, 4);
+        assert_eq!(constants.blocks_per_epoch, 10);
+        assert_eq!(constants.coinbase_timelock_min, 0);
+        assert_eq!(constants.max_coinbase_split, 5);
+
+        fs::write(
+            &path, r#"{"network": "Dev Net", "seal": "abc", "block": "g.jam"}"#,
+        )
+        .expect("write");
+        assert!(matches!(
+            GenesisConfig::load(&path),
+            Err(GenesisError::Network(_))
+        ));
+        fs::write(
+            &path, r#"{"network": "dev", "seal": "abc", "block": "g.jam", "premine": 1}"#,
+        )
+        .expect("write");
+        assert!(matches!(
+            GenesisConfig::load(&path),
+            Err(GenesisError::Parse(..))
+        ));
+    }
+
+    #[test]
+    fn test_fakenet_seal() {
+        let mut block: NounSlab = NounSlab::new();
+        let page = block
+            .cue_into(Bytes::from_static(setup::FAKENET_GENESIS_BLOCK))
+            .expect("cue");
+        let msg_hash = message_hash(page.slot(2047).expect("msg")).expect("hash");
+        assert_eq!(msg_hash, setup::FAKENET_GENESIS_MESSAGE);
+    }
+
+    #[test]
+    fn test_attestation() {
+        let keypair = Keypair::generate_ed25519();
+        let mut attestation =
+            attest(json!({"network": "fakenet", "verified": true}), &keypair).expect("attest");
+        assert!(check_attestation(&attestation));
+        attestation["statement"]["verified"] = json!(false);
+        assert!(!check_attestation(&attestation));
+        attestation["statement"]["verified"] = json!(true);
+        attestation["peerId"] = json!(Keypair::generate_ed25519()
+            .public()
+            .to_peer_id()
+            .to_base58());
+        assert!(!check_attestation(&attestation));
+    }
+}
@@ -0,0 +1,344 @@
+//! gRPC streams of chain events, for indexers and wallets.
+//!
+//! A node started with `--grpc-addr` serves the `nockchain.v1.Chain` service in
+//! `proto/chain.proto` beside nockapp's `nockapp.v1.NockApp`, whose `Peek` answers read-only
+//! queries. `SubscribeHeads`, `SubscribeReorgs` and `SubscribePendingTransactions` each stream
+//! the events of one of the websocket topics in [`crate::rpc`], from when the call is made, with
+//! the same ordering: a reorg arrives before the heads of the blocks it applies. A subscriber that
+//! falls too far behind skips the events it missed, as a websocket does.
+//!
+//! The messages and the service in [`pb`] are written out the way `tonic-build` would generate
+//! them from the proto, so keep the two in step.
+use serde_json::Value;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::StreamExt;
+use tonic::codegen::BoxStream;
+use tonic::service::Routes;
+use tracing::warn;
+
+use crate::rpc::{from_hex, ChainEvents, Topic};
+
+/// The `Chain` service, streaming what is published on `events`, for the gRPC driver to serve
+pub fn chain_routes(events: ChainEvents) -> Routes {
+    Routes::new(pb::ChainServer::new(events))
+}
+
+/// A stream of the events on `events` that `event` turns into messages
+fn subscribe<T: Send + 'static>(
+    events: &ChainEvents,
+    event: fn(Topic, &Value) -> Option<T>,
+) -> BoxStream<T> {
+    let stream =
+        BroadcastStream::new(events.subscribe()).filter_map(move |received| match received {
+            Ok((topic, value)) => event(topic, &value).map(Ok),
+            Err(BroadcastStreamRecvError::Lagged(n)) => {
+                warn!("gRPC subscriber missed {} events", n);
+                None
+            }
+        });
+    Box::pin(stream)
+}
+
+fn head(topic: Topic, value: &Value) -> Option<pb::Block> {
+    (topic == Topic::NewHeads).then(|| block(value))
+}
+
+fn reorg(topic: Topic, value: &Value) -> Option<pb::Reorg> {
+    if topic != Topic::Reorgs {
+        return None;
+    }
+    let blocks = |key: &str| {
+        value[key]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(block)
+            .collect()
+    };
+    let ancestor = &value["commonAncestor"];
+    Some(pb::Reorg {
+        old_tip: string(&value["oldTip"]),
+        new_tip: string(&value["newTip"]),
+        height: value["height"].as_u64().unwrap_or(0),
+        common_ancestor: ancestor.is_object().then(|| pb::BlockRef {
+            id: string(&ancestor["id"]),
+            height: ancestor["height"].as_u64().unwrap_or(0),
+        }),
+        rolled_back: blocks("rolledBack"),
+        applied: blocks("applied"),
+    })
+}
+
+fn pending_transaction(topic: Topic, value: &Value) -> Option<pb::PendingTransaction> {
+    if topic != Topic::PendingTransactions {
+        return None;
+    }
+    Some(pb::PendingTransaction {
+        id: string(&value["id"]),
+        jam: value["jam"].as_str().and_then(from_hex).unwrap_or_default(),
+    })
+}
+
+/// A block as `rpc::block_json` has it
+fn block(value: &Value) -> pb::Block {
+    pb::Block {
+        id: string(&value["id"]),
+        parent: string(&value["parent"]),
+        height: value["height"].as_u64().unwrap_or(0),
+        tx_ids: value["txIds"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(string)
+            .collect(),
+    }
+}
+
+fn string(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+/// The messages and server of `nockchain.v1`
+pub mod pb {
+    use std::convert::Infallible;
+
+    use tonic::codegen::{
+        empty_body, http, Body, BoxFuture, BoxStream, Context, Poll, Service, StdError,
+    };
+    use tonic::server::{Grpc, NamedService, ServerStreamingService};
+    use tonic::Status;
+
+    use super::*;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeRequest {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Block {
+        /// Base58 block id
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(string, tag = "2")]
+        pub parent: String,
+        #[prost(uint64, tag = "3")]
+        pub height: u64,
+        #[prost(string, repeated, tag = "4")]
+        pub tx_ids: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct BlockRef {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(uint64, tag = "2")]
+        pub height: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Reorg {
+        #[prost(string, tag = "1")]
+        pub old_tip: String,
+        #[prost(string, tag = "2")]
+        pub new_tip: String,
+        #[prost(uint64, tag = "3")]
+        pub height: u64,
+        /// The highest block both chains share, `None` if the reorg is deeper than the node
+        /// remembers
+        #[prost(message, optional, tag = "4")]
+        pub common_ancestor: Option<BlockRef>,
+        /// The blocks that left the heaviest chain, highest first
+        #[prost(message, repeated, tag = "5")]
+        pub rolled_back: Vec<Block>,
+        /// The blocks that replaced them, lowest first, ending with `new_tip`
+        #[prost(message, repeated, tag = "6")]
+        pub applied: Vec<Block>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PendingTransaction {
+        /// Base58 transaction id
+        #[prost(string, tag = "1")]
+        pub id: String,
+        /// The jam of the raw transaction
+        #[prost(bytes = "vec", tag = "2")]
+        pub jam: Vec<u8>,
+    }
+
+    /// The `nockchain.v1.Chain` service, streaming from a node's chain events
+    #[derive(Clone)]
+    pub struct ChainServer {
+        events: ChainEvents,
+    }
+
+    impl ChainServer {
+        pub fn new(events: ChainEvents) -> Self {
+            ChainServer { events }
+        }
+    }
+
+    impl NamedService for ChainServer {
+        const NAME: &'static str = "nockchain.v1.Chain";
+    }
+
+    struct SubscribeSvc<T> {
+        events: ChainEvents,
+        event: fn(Topic, &Value) -> Option<T>,
+    }
+
+    impl<T: Send + 'static> ServerStreamingService<SubscribeRequest> for SubscribeSvc<T> {
+        type Response = T;
+        type ResponseStream = BoxStream<T>;
+        type Future = BoxFuture<tonic::Response<BoxStream<T>>, Status>;
+
+        fn call(&mut self, _request: tonic::Request<SubscribeRequest>) -> Self::Future {
+            let stream = subscribe(&self.events, self.event);
+            Box::pin(async move { Ok(tonic::Response::new(stream)) })
+        }
+    }
+
+    /// Answer `request` with the stream `event` picks out of `events`
+    fn stream<T, B>(
+        events: ChainEvents,
+        event: fn(Topic, &Value) -> Option<T>,
+        request: http::Request<B>,
+    ) -> BoxFuture<http::Response<tonic::body::BoxBody>, Infallible>
+    where
+        T: prost::Message + Send + 'static,
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        let svc = SubscribeSvc { events, event };
+        Box::pin(async move {
+            let mut grpc = Grpc::new(tonic::codec::ProstCodec::<T, SubscribeRequest>::default());
+            Ok(grpc.server_streaming(svc, request).await)
+        })
+    }
+
+    impl<B> Service<http::Request<B>> for ChainServer
+    where
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<B>) -> Self::Future {
+            let events = self.events.clone();
+            match request.uri().path() {
+                "/nockchain.v1.Chain/SubscribeHeads" => stream(events, head, request),
+                "/nockchain.v1.Chain/SubscribeReorgs" => stream(events, reorg, request),
+                "/nockchain.v1.Chain/SubscribePendingTransactions" => {
+                    stream(events, pending_transaction, request)
+                }
+                _ => Box::pin(async move {
+                    let mut response = http::Response::new(empty_body());
+                    let headers = response.headers_mut();
+                    headers.insert(
+                        Status::GRPC_STATUS,
+                        (tonic::Code::Unimplemented as i32).into(),
+                    );
+                    headers.insert(
+                        http::header::CONTENT_TYPE,
+                        tonic::metadata::GRPC_CONTENT_TYPE,
+                    );
+                    Ok(response)
+                }),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::rpc::chain_events;
+
+    fn block_json(height: u64) -> Value {
+        json!({
+            "id": format!("id{}", height),
+            "parent": format!("id{}", height - 1),
+            "height": height,
+            "txIds": [format!("tx{}", height)],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let events = chain_events();
+        let mut heads = subscribe(&events, head);
+        let mut reorgs = subscribe(&events, reorg);
+        let mut pending = subscribe(&events, pending_transaction);
+
+        let reorg_json = json!({
+            "oldTip": "old",
+            "newTip": "id6",
+            "height": 6,
+            "commonAncestor": null,
+            "rolledBack": [block_json(5)],
+            "applied": [block_json(5), block_json(6)],
+        });
+        events
+            .send((Topic::Reorgs, reorg_json))
+            .expect("subscribers");
+        events
+            .send((Topic::NewHeads, block_json(5)))
+            .expect("subscribers");
+        events
+            .send((
+                Topic::PendingTransactions,
+                json!({ "id": "tx", "jam": "0102ff" }),
+            ))
+            .expect("subscribers");
+
+        let block = heads.next().await.expect("head").expect("ok");
+        assert_eq!(
+            block,
+            pb::Block {
+                id: "id5".into(),
+                parent: "id4".into(),
+                height: 5,
+                tx_ids: vec!["tx5".into()],
+            }
+        );
+
+        let reorg = reorgs.next().await.expect("reorg").expect("ok");
+        assert_eq!(reorg.old_tip, "old");
+        assert_eq!(reorg.new_tip, "id6");
+        assert_eq!(reorg.common_ancestor, None);
+        assert_eq!(reorg.rolled_back.len(), 1);
+        assert_eq!(reorg.applied.last().map(|b| b.height), Some(6));
+
+        let tx = pending.next().await.expect("tx").expect("ok");
+        assert_eq!(tx.id, "tx");
+        assert_eq!(tx.jam, vec![0x01, 0x02, 0xff]);
+    }
+
+    #[test]
+    fn test_reorg_ancestor() {
+        let value = json!({
+            "oldTip": "a",
+            "newTip": "b",
+            "height": 3,
+            "commonAncestor": { "id": "c", "height": 1 },
+            "rolledBack": [],
+            "applied": [],
+        });
+        let reorg = reorg(Topic::Reorgs, &value).expect("reorg");
+        assert_eq!(
+            reorg.common_ancestor,
+            Some(pb::BlockRef {
+                id: "c".into(),
+                height: 1,
+            })
+        );
+        assert!(head(Topic::Reorgs, &value).is_none());
+    }
+}
@@ -0,0 +1,295 @@
+//! Health and readiness probes for Kubernetes and load balancers.
+//!
+//! `--health-addr` serves `GET /healthz` and `GET /readyz`. Each answers 200 when its checks pass
+//! and 503 when one fails, with the checks as JSON either way:
+//!
+//! | Check        | Passes when                                                   | On        |
+//! |--------------|---------------------------------------------------------------|-----------|
+//! | `serf`       | the kernel answered a peek within `--health-serf-timeout`     | both      |
+//! | `sync`       | the heaviest block is at most `--ready-max-block-age` old     | `/readyz` |
+//! | `peers`      | at least `--ready-min-peers` peers are connected              | `/readyz` |
+//! | `checkpoint` | a checkpoint was written within `--ready-max-checkpoint-age`  | `/readyz` |
+//!
+//! Liveness only asks whether the kernel is responding, so a node that's catching up or has lost
+//! its peers isn't restarted for it, only taken out of rotation. The kernel is peeked every
+//! [`PROBE_INTERVAL`] in the background, since a peek waits behind the poke being run, and a long
+//! block validation shouldn't fail a probe on its own. A block's age is from its timestamp, so a
+//! node replaying old blocks isn't ready until it reaches the present. A max age of 0 skips that
+//! check, like on a regtest node whose blocks only come when asked for. Checkpoints older than
+//! the node's start count from the start, so a restarted node has the max age to write one.
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{serve, Json, Router};
+use nockapp::driver::{make_driver, IODriverFn};
+use nockapp::utils::{da_to_unix_ms, make_tas, DA};
+use nockapp::{AtomExt, NockAppError};
+use nockchain_libp2p_io::p2p::PeerCommand;
+use nockvm::noun::{Noun, Slots, D, T};
+use serde_json::{json, Value};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error, info};
+
+use crate::rpc::peek;
+
+/// How often the kernel is peeked for the `serf` and `sync` checks
+pub const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What the checks are held to
+#[derive(Clone, Debug)]
+pub struct Thresholds {
+    pub serf_timeout: Duration,
+    pub max_block_age: Duration,
+    pub min_peers: usize,
+    pub max_checkpoint_age: Duration,
+}
+
+/// What the background peeks found
+#[derive(Clone, Debug)]
+struct Probe {
+    started: Instant,
+    answered: Option<Instant>,
+    height: Option<u64>,
+    /// Unix seconds
+    block_time: Option<u64>,
+}
+
+/// The state each check looks at
+#[derive(Clone, Debug)]
+struct Observed {
+    since_answer: Option<Duration>,
+    height: Option<u64>,
+    block_age: Option<Duration>,
+    peers: usize,
+    checkpoint_age: Duration,
+}
+
+#[derive(Clone)]
+struct HealthState {
+    thresholds: Thresholds,
+    probe: Arc<Mutex<Probe>>,
+    peers: Option<mpsc::Sender<PeerCommand>>,
+    checkpoints: Arc<Vec<PathBuf>>,
+}
+
+pub fn make_health_driver(
+    addr: SocketAddr,
+    thresholds: Thresholds,
+    peers: Option<mpsc::Sender<PeerCommand>>,
+    checkpoints: Vec<PathBuf>,
+) -> IODriverFn {
+    make_driver(move |handle| async move {
+        let state = HealthState {
+            thresholds,
+            probe: Arc::new(Mutex::new(Probe {
+                started: Instant::now(),
+                answered: None,
+                height: None,
+                block_time: None,
+            })),
+            peers,
+            checkpoints: Arc::new(checkpoints),
+        };
+        let probe = state.probe.clone();
+        let app = Router::new()
+            .route("/healthz", get(healthz))
+            .route("/readyz", get(readyz))
+            .with_state(state);
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(NockAppError::IoError)?;
+        let local_addr = listener.local_addr().map_err(NockAppError::IoError)?;
+        info!(
+            "Serving health checks on http://{}/healthz and /readyz",
+            local_addr
+        );
+        tokio::spawn(async move {
+            if let Err(e) = serve(listener, app.into_make_service()).await {
+                error!("Health server error: {}", e);
+            }
+        });
+
+        let mut ticks = tokio::time::interval(PROBE_INTERVAL);
+        loop {
+            ticks.tick().await;
+            let page = match peek(&handle, |slab| {
+                let path = make_tas(slab, "heaviest-block").as_noun();
+                T(slab, &[path, D(0)])
+            })
+            .await
+            {
+                Ok(page) => page,
+                Err(e) => {
+                    debug!("Health probe failed: {}", e);
+                    continue;
+                }
+            };
+            let tip = page.and_then(|page| tip(unsafe { *page.root() }));
+            let mut probe = probe.lock().unwrap_or_else(|e| e.into_inner());
+            probe.answered = Some(Instant::now());
+            probe.height = tip.map(|(height, _)| height);
+            probe.block_time = tip.map(|(_, time)| time);
+        }
+    })
+}
+
+/// The height and Unix timestamp of a page
+pub(crate) fn tip(page: Noun) -> Option<(u64, u64)> {
+    let height = page.slot(2046).ok()?.as_atom().ok()?.as_u64().ok()?;
+    // Block timestamps are whole seconds of an `@da`
+    let seconds = page.slot(126).ok()?.as_atom().ok()?.as_u64().ok()?;
+    let unix_ms = da_to_unix_ms(DA((seconds as u128) << 64));
+    Some((height, (unix_ms / 1000) as u64))
+}
+
+async fn observe(state: &HealthState) -> Observed {
+    let probe = state
+        .probe
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let since_start = probe.started.elapsed();
+    let checkpoint = checkpoint_age(&state.checkpoints).await;
+    Observed {
+        since_answer: probe.answered.map(|answered| answered.elapsed()),
+        height: probe.height,
+        block_age: probe
+            .block_time
+            .map(|time| Duration::from_secs(now.saturating_sub(time))),
+        peers: peer_count(state.peers.as_ref()).await,
+        // A restarted node has the max age to write its first checkpoint
+        checkpoint_age: checkpoint.map_or(since_start, |age| age.min(since_start)),
+    }
+}
+
+/// How long ago the newest of the checkpoints was written, if any was
+pub(crate) async fn checkpoint_age(paths: &[PathBuf]) -> Option<Duration> {
+    let mut newest = None;
+    for path in paths {
+        let Ok(modified) = tokio::fs::metadata(path).await.and_then(|m| m.modified()) else {
+            continue;
+        };
+        let age = modified.elapsed().unwrap_or_default();
+        newest = Some(newest.map_or(age, |newest: Duration| newest.min(age)));
+    }
+    newest
+}
+
+async fn peer_count(peers: Option<&mpsc::Sender<PeerCommand>>) -> usize {
+    let Some(peers) = peers else {
+        return 0;
+    };
+    let (result, list) = oneshot::channel();
+    if peers.send(PeerCommand::List { result }).await.is_err() {
+        return 0;
+    }
+    list.await.map_or(0, |list| list.len())
+}
+
+/// Whether every check passed, and the checks as JSON
+fn report(thresholds: &Thresholds, observed: &Observed, ready: bool) -> (bool, Value) {
+    let within =
+        |age: Option<Duration>, max: Duration| max.is_zero() || age.is_some_and(|age| age <= max);
+    let secs = |age: Option<Duration>| age.map(|age| age.as_secs());
+    let serf = observed
+        .since_answer
+        .is_some_and(|since| since <= thresholds.serf_timeout);
+    let mut checks = json!({
+        "serf": { "ok": serf, "lastAnswer": secs(observed.since_answer) },
+    });
+    let mut ok = serf;
+    if ready {
+        let sync =
+            observed.height.is_some() && within(observed.block_age, thresholds.max_block_age);
+        let peers = observed.peers >= thresholds.min_peers;
+        let checkpoint = within(Some(observed.checkpoint_age), thresholds.max_checkpoint_age);
+        checks["sync"] = json!({
+            "ok": sync,
+            "height": observed.height,
+            "blockAge": secs(observed.block_age),
+        });
+        checks["peers"] = json!({
+            "ok": peers,
+            "count": observed.peers,
+            "min": thresholds.min_peers,
+        });
+        checks["checkpoint"] = json!({
+            "ok": checkpoint,
+            "age": observed.checkpoint_age.as_secs(),
+        });
+        ok = ok && sync && peers && checkpoint;
+    }
+    let status = if ok { "ok" } else { "unavailable" };
+    (ok, json!({ "status": status, "checks": checks }))
+}
+
+async fn respond(state: &HealthState, ready: bool) -> Response {
+    let observed = observe(state).await;
+    let (ok, body) = report(&state.thresholds, &observed, ready);
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(body)).into_response()
+}
+
+async fn healthz(State(state): State<HealthState>) -> Response {
+    respond(&state, false).await
+}
+
+async fn readyz(State(state): State<HealthState>) -> Response {
+    respond(&state, true).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let thresholds = Thresholds {
+            serf_timeout: Duration::from_secs(60),
+            max_block_age: Duration::from_secs(3600),
+            min_peers: 2,
+            max_checkpoint_age: Duration::from_secs(3600),
+        };
+        let mut observed = Observed {
+            since_answer: Some(Duration::from_secs(3)),
+            height: Some(100),
+            block_age: Some(Duration::from_secs(600)),
+            peers: 1,
+            checkpoint_age: Duration::from_secs(120),
+        };
+        assert!(report(&thresholds, &observed, false).0);
+        let (ok, body) = report(&thresholds, &observed, true);
+        assert!(!ok);
+        assert_eq!(body["status"], "unavailable");
+        assert_eq!(body["checks"]["peers"]["ok"], false);
+        assert_eq!(body["checks"]["sync"]["ok"], true);
+
+        observed.peers = 2;
+        assert!(report(&thresholds, &observed, true).0);
+        observed.block_age = Some(Duration::from_secs(7200));
+        assert!(!report(&thresholds, &observed, true).0);
+        let no_block_age = Thresholds {
+            max_block_age: Duration::ZERO,
+            ..thresholds.clone()
+        };
+        assert!(report(&no_block_age, &observed, true).0);
+
+        observed.since_answer = None;
+        let (ok, body) = report(&thresholds, &observed, false);
+        assert!(!ok);
+        assert!(body["checks"].get("sync").is_none());
+    }
+}
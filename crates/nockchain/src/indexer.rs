@@ -0,0 +1,441 @@
+//! An index from addresses to the transactions that touch them.
+//!
+//! Without it, finding an address's history means walking every block. With `--address-index`,
+//! the node keeps an embedded database beside its checkpoints that maps each public key to the
+//! heights and ids of the transactions that spend from it or pay to it, and the coinbases that
+//! pay it. The JSON-RPC `index_getAddressTransactions` call and the explorer's
+//! `/api/addresses/{pubkey}/transactions` read from it.
+//!
+//! The index follows the heaviest chain. Each time the kernel accepts a block, the driver compares
+//! the blocks it has indexed with the heaviest chain, disconnects the ones that are no longer on
+//! it, highest first, and connects the new ones. A node that starts with an empty index catches up
+//! from genesis the same way. The kernel's `%block-addresses` peek says which public keys each
+//! block touches, so the node never decodes transactions itself.
+use std::path::Path;
+use std::sync::Arc;
+
+use nockapp::driver::{make_driver, IODriverFn, NockAppHandle};
+use nockapp::noun::slab::NounSlab;
+use nockapp::utils::make_tas;
+use nockapp::{AtomExt, NockAppError, NounExt};
+use nockvm::noun::{Noun, Slots, D, T};
+use redb::{
+    Database, MultimapTableDefinition, ReadableMultimapTable, ReadableTable, TableDefinition,
+};
+use serde_json::{json, Value};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use crate::explorer::{block_at_height, tip_block};
+use crate::rpc::{gossip_fact, peek, RpcError};
+
+/// Public key to entries, each the height, a kind byte and then the transaction id
+const ADDRESS_ENTRIES: MultimapTableDefinition<&str, &[u8]> =
+    MultimapTableDefinition::new("address_entries");
+/// Height to the id of the block indexed there
+const BLOCKS: TableDefinition<u64, &str> = TableDefinition::new("blocks");
+
+const TRANSACTION: u8 = 0;
+const COINBASE: u8 = 1;
+
+#[derive(Debug, Error)]
+pub enum IndexError {
+    #[error("Address index database error: {0}")]
+    Database(#[from] redb::Error),
+    #[error("Could not read the chain: {0}")]
+    Kernel(#[from] RpcError),
+}
+
+/// Something that happened to an address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub height: u64,
+    /// The transaction, or `None` for the block's coinbase
+    pub tx_id: Option<String>,
+}
+
+impl Entry {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.height.to_be_bytes().to_vec();
+        match &self.tx_id {
+            Some(id) => {
+                bytes.push(TRANSACTION);
+                bytes.extend_from_slice(id.as_bytes());
+            }
+            None => bytes.push(COINBASE),
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let height = u64::from_be_bytes(bytes.get(..8)?.try_into().ok()?);
+        let tx_id = match *bytes.get(8)? {
+            TRANSACTION => Some(String::from_utf8(bytes[9..].to_vec()).ok()?),
+            COINBASE if bytes.len() == 9 => None,
+            _ => return None,
+        };
+        Some(Entry { height, tx_id })
+    }
+}
+
+/// The public keys a block touches, as the `%block-addresses` peek gives them
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct BlockAddresses {
+    coinbase: Vec<String>,
+    txs: Vec<(String, Vec<String>)>,
+}
+
+impl BlockAddresses {
+    /// Read `[coinbase=(list @t) txs=(list [id=@t pks=(list @t)])]`
+    fn from_noun(noun: Noun) -> Option<Self> {
+        let cords = |list: Noun| -> Option<Vec<String>> {
+            list.list_iter()
+                .map(|cord| cord.as_atom().ok()?.into_string().ok())
+                .collect()
+        };
+        let coinbase = cords(noun.slot(2).ok()?)?;
+        let txs = noun
+            .slot(3)
+            .ok()?
+            .list_iter()
+            .map(|tx| {
+                let id = tx.slot(2).ok()?.as_atom().ok()?.into_string().ok()?;
+                Some((id, cords(tx.slot(3).ok()?)?))
+            })
+            .collect::<Option<_>>()?;
+        Some(BlockAddresses { coinbase, txs })
+    }
+
+    /// Every public key and what happened to it in a block at `height`
+    fn entries(&self, height: u64) -> Vec<(&str, Entry)> {
+        let coinbase = self.coinbase.iter().map(|pk| {
+            (
+                pk.as_str(),
+                Entry {
+                    height,
+                    tx_id: None,
+                },
+            )
+        });
+        let txs = self.txs.iter().flat_map(|(id, pks)| {
+            pks.iter().map(move |pk| {
+                (
+                    pk.as_str(),
+                    Entry {
+                        height,
+                        tx_id: Some(id.clone()),
+                    },
+                )
+            })
+        });
+        coinbase.chain(txs).collect()
+    }
+}
+
+pub struct AddressIndex {
+    db: Database,
+}
+
+impl AddressIndex {
+    /// Open the index at `path`, creating it if it doesn't exist
+    pub fn open(path: &Path) -> Result<Self, IndexError> {
+        let open = || -> Result<_, redb::Error> {
+            let db = Database::create(path)?;
+            // create the tables, so that reads never find them missing
+            let txn = db.begin_write()?;
+            txn.open_multimap_table(ADDRESS_ENTRIES)?;
+            txn.open_table(BLOCKS)?;
+            txn.commit()?;
+            Ok(db)
+        };
+        Ok(AddressIndex { db: open()? })
+    }
+
+    /// The highest block indexed, as its height and id
+    pub fn tip(&self) -> Result<Option<(u64, String)>, IndexError> {
+        let read = || -> Result<_, redb::Error> {
+            let txn = self.db.begin_read()?;
+            let blocks = txn.open_table(BLOCKS)?;
+            let tip = blocks
+                .last()?
+                .map(|(height, id)| (height.value(), id.value().to_string()));
+            Ok(tip)
+        };
+        Ok(read()?)
+    }
+
+    /// The id of the block indexed at `height`
+    pub fn block_at(&self, height: u64) -> Result<Option<String>, IndexError> {
+        let read = || -> Result<_, redb::Error> {
+            let txn = self.db.begin_read()?;
+            let blocks = txn.open_table(BLOCKS)?;
+            let id = blocks.get(height)?.map(|id| id.value().to_string());
+            Ok(id)
+        };
+        Ok(read()?)
+    }
+
+    /// Up to `limit` entries for `pubkey` at or above `from`, lowest first
+    pub fn entries(&self, pubkey: &str, from: u64, limit: usize) -> Result<Vec<Entry>, IndexError> {
+        let read = || -> Result<_, redb::Error> {
+            let txn = self.db.begin_read()?;
+            let table = txn.open_multimap_table(ADDRESS_ENTRIES)?;
+            let mut entries = Vec::new();
+            for value in table.get(pubkey)? {
+                let Some(entry) = Entry::from_bytes(value?.value()) else {
+                    continue;
+                };
+                if entry.height < from {
+                    continue;
+                }
+                if entries.len() == limit {
+                    break;
+                }
+                entries.push(entry);
+            }
+            Ok(entries)
+        };
+        Ok(read()?)
+    }
+
+    /// Empty the index, so it's rebuilt from genesis
+    pub fn clear(&self) -> Result<(), IndexError> {
+        let write = || -> Result<(), redb::Error> {
+            let txn = self.db.begin_write()?;
+            txn.delete_multimap_table(ADDRESS_ENTRIES)?;
+            txn.delete_table(BLOCKS)?;
+            txn.open_multimap_table(ADDRESS_ENTRIES)?;
+            txn.open_table(BLOCKS)?;
+            txn.commit()?;
+            Ok(())
+        };
+        Ok(write()?)
+    }
+
+    fn connect(&self, height: u64, id: &str, addresses: &BlockAddresses) -> Result<(), IndexError> {
+        let write = || -> Result<(), redb::Error> {
+            let txn = self.db.begin_write()?;
+            {
+                let mut table = txn.open_multimap_table(ADDRESS_ENTRIES)?;
+                for (pk, entry) in addresses.entries(height) {
+                    table.insert(pk, entry.to_bytes().as_slice())?;
+                }
+                txn.open_table(BLOCKS)?.insert(height, id)?;
+            }
+            txn.commit()?;
+            Ok(())
+        };
+        Ok(write()?)
+    }
+
+    fn disconnect(&self, height: u64, addresses: &BlockAddresses) -> Result<(), IndexError> {
+        let write = || -> Result<(), redb::Error> {
+            let txn = self.db.begin_write()?;
+            {
+                let mut table = txn.open_multimap_table(ADDRESS_ENTRIES)?;
+                for (pk, entry) in addresses.entries(height) {
+                    table.remove(pk, entry.to_bytes().as_slice())?;
+                }
+                txn.open_table(BLOCKS)?.remove(height)?;
+            }
+            txn.commit()?;
+            Ok(())
+        };
+        Ok(write()?)
+    }
+}
+
+/// `{height, blockId, txId}`, where `txId` is `null` for a coinbase
+pub(crate) fn entry_json(index: &AddressIndex, entry: &Entry) -> Result<Value, IndexError> {
+    Ok(json!({
+        "height": entry.height,
+        "blockId": index.block_at(entry.height)?,
+        "txId": entry.tx_id,
+    }))
+}
+
+/// Keep `index` on the heaviest chain
+pub fn make_indexer_driver(index: Arc<AddressIndex>) -> IODriverFn {
+    make_driver(move |handle| async move {
+        if let Err(e) = sync(&handle, &index).await {
+            warn!("Could not update the address index: {}", e);
+        }
+        loop {
+            let effect = match handle.next_effect().await {
+                Ok(effect) => effect,
+                Err(NockAppError::BroadcastRecvLaggedError(n)) => {
+                    debug!("Address indexer missed {} effects", n);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if !gossip_fact(&effect).is_some_and(|fact| fact.eq_bytes(b"heard-block")) {
+                continue;
+            }
+            if let Err(e) = sync(&handle, &index).await {
+                warn!("Could not update the address index: {}", e);
+            }
+        }
+    })
+}
+
+/// Disconnect indexed blocks that are no longer on the heaviest chain and connect the ones that
+/// are new on it
+pub(crate) async fn sync(handle: &NockAppHandle, index: &AddressIndex) -> Result<(), IndexError> {
+    let Some(tip) = tip_block(handle).await? else {
+        return Ok(());
+    };
+    let tip_height = tip["height"].as_u64().unwrap_or(0);
+
+    let mut height = index.tip()?.map(|(height, _)| height);
+    while let Some(h) = height {
+        let Some(indexed) = index.block_at(h)? else {
+            break;
+        };
+        if h <= tip_height {
+            let current = block_at_height(handle, h).await?;
+            if current.as_ref().and_then(|block| block["id"].as_str()) == Some(indexed.as_str()) {
+                break;
+            }
+        }
+        let addresses = block_addresses(handle, &indexed).await?;
+        index.disconnect(h, &addresses)?;
+        info!(
+            "Address index disconnected block {} at height {}",
+            indexed, h
+        );
+        height = h.checked_sub(1);
+    }
+
+    let start = height.map_or(0, |h| h + 1);
+    for h in start..=tip_height {
+        let Some(block) = block_at_height(handle, h).await? else {
+            break;
+        };
+        let Some(id) = block["id"].as_str() else {
+            break;
+        };
+        let addresses = block_addresses(handle, id).await?;
+        index.connect(h, id, &addresses)?;
+    }
+    if start <= tip_height {
+        debug!("Address index is at height {}", tip_height);
+    }
+    Ok(())
+}
+
+async fn block_addresses(handle: &NockAppHandle, id: &str) -> Result<BlockAddresses, IndexError> {
+    let addresses = peek(handle, |slab: &mut NounSlab| {
+        let tag = make_tas(slab, "block-addresses").as_noun();
+        let id = make_tas(slab, id).as_noun();
+        T(slab, &[tag, id, D(0)])
+    })
+    .await?;
+    let Some(addresses) = addresses else {
+        return Ok(BlockAddresses::default());
+    };
+    BlockAddresses::from_noun(unsafe { *addresses.root() })
+        .ok_or_else(|| RpcError::Internal("malformed block addresses".into()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(slab: &mut NounSlab, items: &[Noun]) -> Noun {
+        items
+            .iter()
+            .rev()
+            .fold(D(0), |tail, item| T(slab, &[*item, tail]))
+    }
+
+    fn cords(slab: &mut NounSlab, items: &[&str]) -> Noun {
+        let items: Vec<Noun> = items
+            .iter()
+            .map(|item| make_tas(slab, item).as_noun())
+            .collect();
+        list(slab, &items)
+    }
+
+    #[test]
+    fn test_entry_bytes() {
+        let tx = Entry {
+            height: 300,
+            tx_id: Some("tx1".into()),
+        };
+        let coinbase = Entry {
+            height: 2,
+            tx_id: None,
+        };
+        assert_eq!(Entry::from_bytes(&tx.to_bytes()), Some(tx.clone()));
+        assert_eq!(
+            Entry::from_bytes(&coinbase.to_bytes()),
+            Some(coinbase.clone())
+        );
+        // entries sort by height
+        assert!(coinbase.to_bytes() < tx.to_bytes());
+        assert_eq!(Entry::from_bytes(&[0; 4]), None);
+    }
+
+    #[test]
+    fn test_block_addresses() {
+        let mut slab: NounSlab = NounSlab::new();
+        let coinbase = cords(&mut slab, &["miner"]);
+        let id = make_tas(&mut slab, "tx1").as_noun();
+        let pks = cords(&mut slab, &["alice", "bob"]);
+        let tx = T(&mut slab, &[id, pks]);
+        let txs = list(&mut slab, &[tx]);
+        let noun = T(&mut slab, &[coinbase, txs]);
+
+        let addresses = BlockAddresses::from_noun(noun).expect("addresses");
+        assert_eq!(addresses.coinbase, vec!["miner"]);
+        assert_eq!(
+            addresses.txs,
+            vec![(
+                "tx1".to_string(),
+                vec!["alice".to_string(), "bob".to_string()]
+            )]
+        );
+        let entries = addresses.entries(5);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[0],
+            (
+                "miner",
+                Entry {
+                    height: 5,
+                    tx_id: None
+                }
+            )
+        );
+        assert_eq!(entries[2].0, "bob");
+        assert_eq!(entries[2].1.tx_id.as_deref(), Some("tx1"));
+    }
+
+    #[test]
+    fn test_index() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let index = AddressIndex::open(&dir.path().join("index.redb")).expect("open");
+        let addresses = BlockAddresses {
+            coinbase: vec!["miner".into()],
+            txs: vec![("tx1".into(), vec!["alice".into(), "miner".into()])],
+        };
+        index
+            .connect(0, "genesis", &BlockAddresses::default())
+            .expect("connect");
+        index.connect(1, "one", &addresses).expect("connect");
+        assert_eq!(index.tip().expect("tip"), Some((1, "one".into())));
+        assert_eq!(index.entries("miner", 0, 10).expect("entries").len(), 2);
+        assert_eq!(index.entries("miner", 0, 1).expect("entries").len(), 1);
+        assert_eq!(index.entries("miner", 2, 10).expect("entries"), vec![]);
+
+        index.disconnect(1, &addresses).expect("disconnect");
+        assert_eq!(index.tip().expect("tip"), Some((0, "genesis".into())));
+        assert_eq!(index.entries("alice", 0, 10).expect("entries"), vec![]);
+
+        index.connect(1, "one", &addresses).expect("connect");
+        index.clear().expect("clear");
+        assert_eq!(index.tip().expect("tip"), None);
+        assert_eq!(index.entries("miner", 0, 10).expect("entries"), vec![]);
+    }
+}
@@ -1,20 +1,43 @@
+pub mod affinity;
+pub mod backup;
+pub mod bench;
+pub mod blocks;
 pub mod config;
+pub mod devnet;
+pub mod explorer;
+pub mod genesis;
+pub mod grpc;
+pub mod health;
+pub mod indexer;
+pub mod light;
+pub mod mempool;
 pub mod mining;
+pub mod reindex;
+pub mod rpc;
 pub mod setup;
+pub mod snapshot;
+pub mod status;
+pub mod stratum;
 
 use std::error::Error;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 pub use config::NockchainCli;
 use libp2p::identity::Keypair;
 use libp2p::multiaddr::Multiaddr;
 use libp2p::{allow_block_list, connection_limits, memory_connection_limits, PeerId};
+use nockapp::drivers::npc::{npc_listener_with_limits, NpcLimits};
 use nockapp::kernel::boot;
+use nockapp::utils::ipc::IpcListener;
 use nockapp::utils::make_tas;
-use nockapp::NockApp;
+use nockapp::utils::scry::ScryResult;
+use nockapp::utils::tls::{TlsIdentity, TlsListener};
+use nockapp::{NockApp, NounExt};
+use nockchain_libp2p_io::seeds::DnsSeed;
 use termcolor::{ColorChoice, StandardStream};
-use tokio::net::UnixListener;
 pub mod colors;
 
 use colors::*;
@@ -24,6 +47,7 @@ use nockvm::noun::{D, T, YES};
 use nockvm_macros::tas;
 use tracing::{debug, info, instrument};
 
+use crate::affinity::{Placement, Priority};
 use crate::mining::MiningKeyConfig;
 
 /// Module for handling driver initialization signals
@@ -177,6 +201,35 @@ fn load_keypair(keypair_path: &Path, force_new: bool) -> Result<Keypair, Box<dyn
     }
 }
 
+/// How many threads to mine with, and where, with the proving pool set up to match
+pub fn prepare_mining_threads(
+    cli: Option<&config::NockchainCli>,
+) -> Result<(u64, Placement), Box<dyn Error>> {
+    let placement = Placement {
+        cores: cli.and_then(|c| c.mining_cores.clone()),
+        priority: cli.map_or(Priority::Normal, |c| c.mining_priority),
+    };
+    let pinned = placement.cores.as_ref().map(|cores| cores.0.len());
+    // Leave a core for the node itself, unless told which to mine on
+    let threads = cli
+        .and_then(|c| c.num_threads)
+        .or(pinned.map(|cores| cores as u64))
+        .unwrap_or_else(|| num_cpus::get_physical().saturating_sub(1).max(1) as u64);
+
+    let prover_threads = cli.and_then(|c| c.prover_threads).or(pinned);
+    if prover_threads.is_some() || !placement.is_default() {
+        let pool_placement = placement.clone();
+        zkvm_jetpack::pool::set_prover_pool(prover_threads.unwrap_or(0), move |_| {
+            pool_placement.place_current()
+        })?;
+        info!(
+            "Proving with {} threads",
+            zkvm_jetpack::pool::prover_threads()
+        );
+    }
+    Ok((threads, placement))
+}
+
 #[instrument(skip(kernel_jam, hot_state))]
 pub async fn init_with_kernel<J: Jammer + Send + 'static>(
     cli: Option<config::NockchainCli>,
@@ -188,15 +241,48 @@ pub async fn init_with_kernel<J: Jammer + Send + 'static>(
     if let Some(cli) = &cli {
         cli.validate()?;
     }
+    let cli = cli.map(config::NockchainCli::with_implied_flags);
+    let genesis = cli
+        .as_ref()
+        .and_then(|c| c.genesis_file.as_deref())
+        .map(genesis::GenesisConfig::load)
+        .transpose()?;
+    if let Some(genesis) = &genesis {
+        info!("Running on private network {}", genesis.network);
+    }
 
-    let mut nockapp = boot::setup::<J>(
-        kernel_jam,
-        cli.as_ref().map(|c| c.nockapp_cli.clone()),
-        hot_state,
-        "nockchain",
-        None,
-    )
-    .await?;
+    let mut nockapp_cli = cli.as_ref().map(|c| {
+        let mut nockapp_cli = c.nockapp_cli.clone();
+        if let Some(config::NockchainCommand::State(state_command)) = c.command.clone() {
+            state_command.apply(&mut nockapp_cli);
+        }
+        nockapp_cli
+    });
+    // A reindex reads the stored chain out before booting a fresh kernel to replay it into
+    let reindex_chain = if cli.as_ref().is_some_and(|c| c.reindexing()) {
+        let chain = match reindex::load_chain()? {
+            Some(chain) => chain,
+            None => {
+                let mut stored = boot::setup::<J>(
+                    kernel_jam,
+                    nockapp_cli.clone(),
+                    hot_state,
+                    "nockchain",
+                    None,
+                )
+                .await?;
+                reindex::export_chain(&mut stored).await?
+            }
+        };
+        if let Some(nockapp_cli) = nockapp_cli.as_mut() {
+            nockapp_cli.new = true;
+        }
+        Some(chain)
+    } else {
+        None
+    };
+    let mut nockapp =
+        boot::setup::<J>(kernel_jam, nockapp_cli, hot_state, "nockchain", None).await?;
 
     let keypair = {
         let keypair_path = Path::new(config::IDENTITY_PATH);
@@ -240,6 +326,17 @@ pub async fn init_with_kernel<J: Jammer + Send + 'static>(
                 .collect()
         });
 
+    let external_multiaddrs: Vec<Multiaddr> = cli.as_ref().map_or(Vec::new(), |c| {
+        c.external_address
+            .iter()
+            .map(|addr_str| {
+                addr_str
+                    .parse()
+                    .expect("could not parse external address multiaddr")
+            })
+            .collect()
+    });
+
     let libp2p_config = nockchain_libp2p_io::config::LibP2PConfig::from_env()?;
     debug!("Using libp2p config: {:?}", libp2p_config);
     let limits = connection_limits::ConnectionLimits::default()
@@ -280,10 +377,12 @@ pub async fn init_with_kernel<J: Jammer + Send + 'static>(
         } else { c.max_system_memory_fraction.map(memory_connection_limits::Behaviour::with_max_percentage) }
     });
 
-    let default_backbone_peers = if cli.as_ref().map(|c| c.fakenet).unwrap_or(false) {
-        config::TESTNET_BACKBONE_NODES
+    let default_backbone_peers: Vec<&str> = if let Some(genesis) = &genesis {
+        genesis.peers.iter().map(String::as_str).collect()
+    } else if cli.as_ref().map(|c| c.fakenet).unwrap_or(false) {
+        config::TESTNET_BACKBONE_NODES.to_vec()
     } else {
-        config::REALNET_BACKBONE_NODES
+        config::REALNET_BACKBONE_NODES.to_vec()
     };
 
     let backbone_peers = default_backbone_peers
@@ -291,7 +390,7 @@ pub async fn init_with_kernel<J: Jammer + Send + 'static>(
         .map(|multiaddr_str| {
             multiaddr_str
                 .parse()
-                .expect("could not parse multiaddr from built-in string")
+                .expect("could not parse default peer multiaddr")
         })
         .collect();
 
@@ -333,6 +432,29 @@ pub async fn init_with_kernel<J: Jammer + Send + 'static>(
         initial_peer_multiaddrs.push(multiaddr.clone());
     }
 
+    let default_dns_seeds: &[&str] = if genesis.is_some() {
+        &[]
+    } else if cli.as_ref().map(|c| c.fakenet).unwrap_or(false) {
+        config::TESTNET_DNS_SEEDS
+    } else {
+        config::REALNET_DNS_SEEDS
+    };
+    let mut dns_seeds: Vec<String> = if cli.as_ref().is_some_and(|c| c.no_default_peers) {
+        Vec::new()
+    } else {
+        default_dns_seeds
+            .iter()
+            .map(|seed| seed.to_string())
+            .collect()
+    };
+    if let Some(c) = cli.as_ref() {
+        dns_seeds.extend(c.dns_seed.iter().cloned());
+    }
+    let dns_seeds: Vec<DnsSeed> = dns_seeds
+        .iter()
+        .map(|seed| seed.parse().expect("could not parse DNS seed"))
+        .collect();
+
     debug!("initial_peer_multiaddrs: {:?}", initial_peer_multiaddrs);
     debug!("force_peer_multiaddrs: {:?}", force_peers);
 
@@ -382,6 +504,58 @@ pub async fn init_with_kernel<J: Jammer + Send + 'static>(
         }
     };
 
+    if let Some(max_bytes) = cli.as_ref().and_then(|c| c.mempool_max_bytes) {
+        let max_bytes = Some(max_bytes).filter(|max_bytes| *max_bytes > 0);
+        setup::poke(
+            &mut nockapp,
+            setup::SetupCommand::PokeSetMempoolMaxBytes(max_bytes),
+        )
+        .await?;
+    }
+
+    // The relay policy isn't kept between runs, so leaving a flag out lifts its limit
+    if let Some(c) = cli.as_ref() {
+        let policy = c.relay_policy();
+        if policy != mempool::RelayPolicy::default() {
+            info!("Relaying only transactions that meet {:?}", policy);
+        }
+        setup::poke(
+            &mut nockapp,
+            setup::SetupCommand::PokeSetRelayPolicy(policy),
+        )
+        .await?;
+    }
+
+    if let Some(depth) = cli.as_ref().and_then(|c| c.prune_depth) {
+        let depth = Some(depth).filter(|depth| *depth > 0);
+        match depth {
+            Some(depth) => info!("Pruning blocks more than {depth} below the heaviest"),
+            None => info!("Not pruning blocks"),
+        }
+        setup::poke(&mut nockapp, setup::SetupCommand::PokeSetPruneDepth(depth)).await?;
+    }
+
+    if let Some(interval) = cli.as_ref().and_then(|c| c.snapshot_interval) {
+        let interval = Some(interval).filter(|interval| *interval > 0);
+        setup::poke(
+            &mut nockapp,
+            setup::SetupCommand::PokeSetSnapshotInterval(interval),
+        )
+        .await?;
+    }
+
+    if let Some(c) = cli.as_ref() {
+        if let Some(source) = c.fast_sync.clone() {
+            let sync = snapshot::FastSync {
+                source,
+                height: c.fast_sync_height,
+                hash: c.fast_sync_hash.clone(),
+                peers: c.fast_sync_peer.clone(),
+            };
+            snapshot::fast_sync(&mut nockapp, &sync).await?;
+        }
+    }
+
     let born_init_tx = if cli.as_ref().map(|c| c.fakenet).unwrap_or(false) {
         let pow_len = cli
             .as_ref()
@@ -391,11 +565,17 @@ pub async fn init_with_kernel<J: Jammer + Send + 'static>(
             .as_ref()
             .map(|c| c.fakenet_log_difficulty.unwrap_or(1))
             .unwrap_or(1);
+        let coinbase_timelock = cli
+            .as_ref()
+            .and_then(|c| c.fakenet_coinbase_timelock)
+            .unwrap_or(0);
+        let constants = match &genesis {
+            Some(genesis) => genesis.constants(),
+            None => setup::fakenet_blockchain_constaints(pow_len, target, coinbase_timelock),
+        };
         setup::poke(
             &mut nockapp,
-            setup::SetupCommand::PokeFakenetConstants(setup::fakenet_blockchain_constaints(
-                pow_len, target,
-            )),
+            setup::SetupCommand::PokeFakenetConstants(constants),
         )
         .await?;
         if let Some(true) = is_kernel_mainnet {
@@ -403,30 +583,39 @@ pub async fn init_with_kernel<J: Jammer + Send + 'static>(
         } else if !genesis_seal_set {
             setup::poke(
                 &mut nockapp,
-                setup::SetupCommand::PokeSetGenesisSeal(setup::FAKENET_GENESIS_MESSAGE.to_string()),
+                setup::SetupCommand::PokeSetGenesisSeal(genesis.as_ref().map_or_else(
+                    || setup::FAKENET_GENESIS_MESSAGE.to_string(),
+                    |genesis| genesis.seal.clone(),
+                )),
             )
             .await?;
         }
 
-        // Create driver initialization signals for fakenet
-        let mut fake_genesis_signals = driver_init::DriverInitSignals::new();
-        let born_init_tx = fake_genesis_signals.register_driver("born");
-        let _ = fake_genesis_signals.create_task();
-
-        // Check if custom genesis path is provided, read file if so
-        let genesis_data = if let Some(genesis_path) = cli
-            .as_ref()
-            .and_then(|c| c.fakenet_genesis_jam_path.as_ref())
-        {
-            Some(fs::read(genesis_path)?)
-        } else {
+        if reindex_chain.is_some() || cli.as_ref().is_some_and(|c| c.importing().is_some()) {
+            // the stored chain or block file starts with its genesis block
             None
-        };
+        } else {
+            // Create driver initialization signals for fakenet
+            let mut fake_genesis_signals = driver_init::DriverInitSignals::new();
+            let born_init_tx = fake_genesis_signals.register_driver("born");
+            let _ = fake_genesis_signals.create_task();
+
+            // Check if custom genesis path is provided, read file if so
+            let genesis_data = if let Some(genesis_path) =
+                genesis.as_ref().map(|g| &g.block).or(cli
+                    .as_ref()
+                    .and_then(|c| c.fakenet_genesis_jam_path.as_ref()))
+            {
+                Some(fs::read(genesis_path)?)
+            } else {
+                None
+            };
 
-        let poke = setup::heard_fake_genesis_block(genesis_data)?;
-        let fakenet_driver = fake_genesis_signals.create_driver(poke, None);
-        nockapp.add_io_driver(fakenet_driver).await;
-        Some(born_init_tx)
+            let poke = setup::heard_fake_genesis_block(genesis_data)?;
+            let fakenet_driver = fake_genesis_signals.create_driver(poke, None);
+            nockapp.add_io_driver(fakenet_driver).await;
+            Some(born_init_tx)
+        }
     } else {
         if let Some(false) = is_kernel_mainnet {
             panic!("Fatal: attemped to boot fakenet kernel without fakenet flag!")
@@ -441,12 +630,57 @@ pub async fn init_with_kernel<J: Jammer + Send + 'static>(
     };
     setup::poke(&mut nockapp, setup::SetupCommand::PokeSetBtcData).await?;
 
+    // A reindex runs no networking or mining, just the replay once the kernel is born
+    if let Some(chain) = reindex_chain {
+        let _ = mining_init_tx.send(());
+        let _ = libp2p_init_tx.send(());
+        let address_index = match cli.as_ref().and_then(|c| c.address_index.as_ref()) {
+            Some(path) => Some(Arc::new(indexer::AddressIndex::open(path)?)),
+            None => None,
+        };
+        let (replay_tx, replay_rx) = tokio::sync::oneshot::channel();
+        nockapp
+            .add_io_driver(born_driver_signals.create_driver(born_poke(), Some(replay_tx)))
+            .await;
+        nockapp
+            .add_io_driver(reindex::make_reindex_driver(
+                chain, replay_rx, address_index,
+            ))
+            .await;
+        nockapp.add_io_driver(nockapp::exit_driver()).await;
+        return Ok(nockapp);
+    }
+
+    // So does an import, which hears the block file instead
+    if let Some(path) = cli.as_ref().and_then(|c| c.importing()) {
+        let _ = mining_init_tx.send(());
+        let _ = libp2p_init_tx.send(());
+        let address_index = match cli.as_ref().and_then(|c| c.address_index.as_ref()) {
+            Some(index_path) => Some(Arc::new(indexer::AddressIndex::open(index_path)?)),
+            None => None,
+        };
+        let (import_tx, import_rx) = tokio::sync::oneshot::channel();
+        nockapp
+            .add_io_driver(born_driver_signals.create_driver(born_poke(), Some(import_tx)))
+            .await;
+        nockapp
+            .add_io_driver(blocks::make_import_driver(
+                path.to_path_buf(),
+                import_rx,
+                address_index,
+            ))
+            .await;
+        nockapp.add_io_driver(nockapp::exit_driver()).await;
+        return Ok(nockapp);
+    }
+
     let mining_config = cli.as_ref().and_then(|c| {
         if let Some(pubkey) = &c.mining_pubkey {
             Some(vec![MiningKeyConfig {
                 share: 1,
                 m: 1,
                 keys: vec![pubkey.clone()],
+                percent: false,
             }])
         } else if let Some(mining_key_adv) = &c.mining_key_adv {
             Some(mining_key_adv.clone())
@@ -458,25 +692,61 @@ pub async fn init_with_kernel<J: Jammer + Send + 'static>(
     let prune_inbound = cli.as_ref().and_then(|c| c.prune_inbound);
 
     let mine = cli.as_ref().map_or(false, |c| c.mine);
+    // Without --mine, a regtest node mines only when regtest_generate asks it to
+    let regtest = cli.as_ref().is_some_and(|c| c.regtest);
+    let on_demand = regtest && !mine;
 
-    let threads = cli
-        .as_ref()
-        .and_then(|c| {
-            if let Some(num_threads) = &c.num_threads {
-                Some(*num_threads)
-            } else {
-                Some(1)
-            }
-        })
-        .expect("Failed to get number of threads for mining");
+    let (threads, placement) = prepare_mining_threads(cli.as_ref())?;
 
-    let mining_driver =
-        crate::mining::create_mining_driver(mining_config, mine, threads, Some(mining_init_tx));
+    let mining_stats = Arc::new(crate::mining::MiningStats::default());
+    let mining_driver = crate::mining::create_mining_driver(
+        mining_config,
+        mine || on_demand,
+        on_demand,
+        threads,
+        placement,
+        mining_stats.clone(),
+        Some(mining_init_tx),
+    );
     nockapp.add_io_driver(mining_driver).await;
 
+    let rpc_addr = cli.as_ref().and_then(|c| c.rpc_addr);
+    let health_addr = cli.as_ref().and_then(|c| c.health_addr);
+    let (peer_control, peer_commands) = if rpc_addr.is_some() || health_addr.is_some() {
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
+        (Some(tx), Some(rx))
+    } else {
+        (None, None)
+    };
+    let peer_store = cli
+        .as_ref()
+        .filter(|c| !c.no_peer_store)
+        .map(|c| c.peer_store.clone());
+    let snapshots = nockchain_libp2p_io::snapshot::Snapshots {
+        serve: cli.as_ref().is_some_and(|c| c.serve_snapshots),
+        fetch: cli
+            .as_ref()
+            .filter(|c| c.fast_sync_from_peers)
+            .and_then(|c| c.fast_sync_height.zip(c.fast_sync_hash.clone()))
+            .map(
+                |(height, hash)| nockchain_libp2p_io::snapshot::SnapshotFetch {
+                    height,
+                    hash,
+                    dir: PathBuf::from(config::SNAPSHOT_FETCH_PATH),
+                },
+            ),
+    };
+    let memo_box = cli
+        .as_ref()
+        .filter(|c| c.relay_memos)
+        .map(|_| Arc::new(nockchain_libp2p_io::memo::MemoBox::default()));
+    let memos = nockchain_libp2p_io::memo::Memos {
+        inbox: memo_box.clone(),
+    };
     let libp2p_driver = nockchain_libp2p_io::nc::make_libp2p_driver(
         keypair,
         bind_multiaddrs,
+        external_multiaddrs,
         allowed,
         limits,
         memory_limits,
@@ -486,18 +756,139 @@ pub async fn init_with_kernel<J: Jammer + Send + 'static>(
         equix_builder,
         config::CHAIN_INTERVAL,
         Some(libp2p_init_tx),
+        peer_commands,
+        peer_store,
+        dns_seeds,
+        genesis.as_ref().map(|genesis| genesis.network.clone()),
+        snapshots,
+        memos,
     );
     nockapp.add_io_driver(libp2p_driver).await;
 
+    let address_index = match cli.as_ref().and_then(|c| c.address_index.as_ref()) {
+        Some(path) => {
+            info!("Keeping an address index in {}", path.display());
+            Some(Arc::new(indexer::AddressIndex::open(path)?))
+        }
+        None => None,
+    };
+    if let Some(index) = address_index.clone() {
+        nockapp
+            .add_io_driver(indexer::make_indexer_driver(index))
+            .await;
+    }
+
+    if let (Some(health_addr), Some(c)) = (health_addr, cli.as_ref()) {
+        let thresholds = health::Thresholds {
+            serf_timeout: Duration::from_secs(c.health_serf_timeout),
+            max_block_age: Duration::from_secs(c.ready_max_block_age),
+            min_peers: c.ready_min_peers,
+            max_checkpoint_age: Duration::from_secs(c.ready_max_checkpoint_age),
+        };
+        let checkpoints = nockapp.checkpoint_paths().await;
+        nockapp
+            .add_io_driver(health::make_health_driver(
+                health_addr,
+                thresholds,
+                peer_control.clone(),
+                checkpoints,
+            ))
+            .await;
+    }
+
+    let grpc_addr = cli.as_ref().and_then(|c| c.grpc_addr);
+    let chain_events = rpc::chain_events();
+    if rpc_addr.is_some() || grpc_addr.is_some() {
+        nockapp
+            .add_io_driver(rpc::make_events_driver(chain_events.clone()))
+            .await;
+    }
+
+    if let Some(rpc_addr) = rpc_addr {
+        let checkpoints = nockapp.checkpoint_paths().await;
+        let data_dir = nockapp
+            .data_dir()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| nockapp::default_data_dir("nockchain"));
+        nockapp
+            .add_io_driver(rpc::make_rpc_driver(
+                rpc_addr,
+                rpc::cookie_path(&data_dir),
+                chain_events.clone(),
+                peer_control,
+                address_index.clone(),
+                mining_stats,
+                regtest,
+                checkpoints,
+                memo_box,
+                cli.as_ref().is_some_and(|c| c.serve_snapshots),
+            ))
+            .await;
+    }
+
+    if let Some(grpc_addr) = grpc_addr {
+        nockapp
+            .add_io_driver(nockapp::drivers::grpc_driver(
+                grpc_addr,
+                grpc::chain_routes(chain_events),
+            ))
+            .await;
+    }
+
+    if let Some(stratum_addr) = cli.as_ref().and_then(|c| c.stratum_addr) {
+        nockapp
+            .add_io_driver(stratum::make_stratum_driver(stratum_addr))
+            .await;
+    }
+
+    if let Some(explorer_addr) = cli.as_ref().and_then(|c| c.explorer_addr) {
+        nockapp
+            .add_io_driver(explorer::make_explorer_driver(explorer_addr, address_index))
+            .await;
+    }
+
+    if let Some(args) = cli.as_ref().map(|c| &c.backup) {
+        if args.backup_url.is_some() {
+            let checkpoints = nockapp.checkpoint_paths().await;
+            nockapp
+                .add_io_driver(backup::make_backup_driver(
+                    args.backups()?,
+                    args.retention(),
+                    Duration::from_secs(args.backup_interval),
+                    checkpoints,
+                ))
+                .await;
+        }
+    }
+
+    // The fakenet genesis block and the saved mempool are both heard once the kernel is born
+    let (born_done_tx, born_done_rx) = tokio::sync::oneshot::channel();
+    let (mempool_born_tx, mempool_born_rx) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        if born_done_rx.await.is_ok() {
+            if let Some(tx) = born_init_tx {
+                let _ = tx.send(());
+            }
+            let _ = mempool_born_tx.send(());
+        }
+    });
+    let mempool_save_secs = cli.as_ref().map_or(0, |c| c.mempool_save_secs);
+    if mempool_save_secs > 0 {
+        let data_dir = nockapp
+            .data_dir()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| nockapp::default_data_dir("nockchain"));
+        nockapp
+            .add_io_driver(mempool::make_persist_driver(
+                mempool::save_path(&data_dir),
+                Duration::from_secs(mempool_save_secs),
+                mempool_born_rx,
+            ))
+            .await;
+    }
+
     // Create the born driver that waits for the born signal
-    // Make the born poke
-    let mut born_slab = NounSlab::new();
-    let born = T(
-        &mut born_slab,
-        &[D(tas!(b"command")), D(tas!(b"born")), D(0)],
-    );
-    born_slab.set_root(born);
-    let born_driver = born_driver_signals.create_driver(born_slab, born_init_tx);
+    let born_driver = born_driver_signals.create_driver(born_poke(), Some(born_done_tx));
 
     // Add the born driver to the nockapp
     nockapp.add_io_driver(born_driver).await;
@@ -515,22 +906,88 @@ pub async fn init_with_kernel<J: Jammer + Send + 'static>(
             })
             .npc_socket,
     );
-    nockapp.npc_socket_path = Some(socket_path.to_path_buf());
-
     if let Some(parent) = socket_path.parent() {
         fs::create_dir_all(parent)?;
     }
-    let listener = UnixListener::bind(socket_path)?;
+    let (listener, bound) = IpcListener::activated_or_bind("npc", socket_path)?;
+    if bound {
+        nockapp.npc_socket_path = Some(socket_path.to_path_buf());
+    } else {
+        info!("serving NPC on the socket systemd passed in");
+    }
+    let npc_limits = cli
+        .as_ref()
+        .map(|c| NpcLimits {
+            max_connections: Some(c.npc_max_connections).filter(|max| *max > 0),
+            max_message_bytes: Some(c.npc_max_message_bytes).filter(|max| *max > 0),
+            client_rate: c.npc_rate_limit,
+            global_rate: c.npc_global_rate_limit,
+            max_concurrent_peeks: c.npc_max_concurrent_peeks,
+            on_limit: c.npc_on_limit,
+        })
+        .unwrap_or_default();
 
     nockapp
-        .add_io_driver(nockapp::npc_listener_driver(listener))
+        .add_io_driver(npc_listener_with_limits(listener, npc_limits.clone()))
         .await;
 
+    if let Some(c) = cli.as_ref().filter(|c| c.npc_tls_addr.is_some()) {
+        let (Some(addr), Some(cert), Some(key), Some(ca)) = (
+            c.npc_tls_addr,
+            c.npc_tls_cert.clone(),
+            c.npc_tls_key.clone(),
+            c.npc_tls_client_ca.clone(),
+        ) else {
+            return Err(
+                "--npc-tls-addr needs --npc-tls-cert, --npc-tls-key and --npc-tls-client-ca".into(),
+            );
+        };
+        let config = TlsIdentity { cert, key, ca }.server_config()?;
+        let tls_listener = TlsListener::bind(addr, config).await?;
+        info!("serving NPC over TLS on {}", addr);
+        nockapp
+            .add_io_driver(npc_listener_with_limits(tls_listener, npc_limits))
+            .await;
+    }
+
+    if let Some(metrics_addr) = cli.as_ref().and_then(|c| c.metrics_addr) {
+        let mut path = NounSlab::new();
+        let mempool = T(&mut path, &[D(tas!(b"mempool")), D(0)]);
+        path.set_root(mempool);
+        let probes = vec![nockapp::drivers::metrics_server::MetricsProbe {
+            name: "nockchain_mempool_transactions",
+            help: "Transactions in the mempool",
+            path,
+            read: |result| match ScryResult::from(&result) {
+                ScryResult::Some(txs) => Some(txs.list_iter().count() as f64),
+                _ => None,
+            },
+        }];
+        nockapp
+            .add_io_driver(nockapp::metrics_driver(
+                metrics_addr, probes, METRICS_PROBE_INTERVAL,
+            ))
+            .await;
+    }
+
     nockapp.add_io_driver(nockapp::exit_driver()).await;
 
     Ok(nockapp)
 }
 
+fn born_poke() -> NounSlab {
+    let mut born_slab = NounSlab::new();
+    let born = T(
+        &mut born_slab,
+        &[D(tas!(b"command")), D(tas!(b"born")), D(0)],
+    );
+    born_slab.set_root(born);
+    born_slab
+}
+
+/// How often the metrics driver peeks the kernel for its gauges
+const METRICS_PROBE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+
 fn welcome() {
     let mut stdout = StandardStream::stdout(ColorChoice::Auto);
 
@@ -0,0 +1,576 @@
+//! Light client mode.
+//!
+//! A phone or an embedded wallet can't keep every block and replay it. `nockchain light` boots no
+//! kernel: it downloads only the headers of the heaviest chain from a node's JSON-RPC server with
+//! `chain_getHeaders`, and keeps them in a small database. A header is the kernel's
+//! `page-header`, a page with its proof of work, transaction ids and coinbase hashed, which is
+//! enough to recompute the block's id, so a header that doesn't hash to its id or doesn't follow
+//! the one below it is refused. When the node's heaviest chain no longer has our highest header,
+//! headers are dropped until it does, and the sync carries on from there. With `--follow` it
+//! keeps polling every [`POLL_INTERVAL`] for new blocks.
+//!
+//! `chain_getTxProof` shows that a block includes a transaction: the hashes from the
+//! transaction's node in the block's `tx-ids` up to the root its header commits to.
+//! [`verify_tx_proof`] checks one against a header, and `--verify-tx` with `--block` checks one
+//! against the synced headers.
+//!
+//! Proofs of work aren't checked, since that takes the STARK verifier. So a light client trusts
+//! the node to serve the heaviest chain: `--checkpoint` names a block the chain must include, and
+//! it's best to sync from a node you run, or to check the tip against another.
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::Args;
+use nockapp::noun::slab::NounSlab;
+use nockapp::{Bytes, NounExt};
+use nockchain_libp2p_io::compact::{id_of, Id};
+use nockchain_libp2p_io::tip5_util::tip5_hash_to_base58;
+use nockvm::mem::NockStack;
+use nockvm::noun::{Noun, Slots, D, T};
+use nockvm_macros::tas;
+use redb::{Database, ReadableTable, TableDefinition};
+use reqwest::Client;
+use serde_json::{json, Value};
+use thiserror::Error;
+use tracing::{info, warn};
+use zkvm_jetpack::jets::tip5_jets::hash_hashable;
+
+use crate::rpc::{from_hex, MAX_HEADERS};
+use crate::snapshot::{call, SnapshotError};
+
+/// How often `--follow` asks the node for new headers
+pub const POLL_INTERVAL: Duration = Duration::from_secs(20);
+
+/// Words of stack to hash with
+pub(crate) const HASH_STACK_SIZE: usize = 1 << 20;
+
+/// Height to the jammed header there
+const HEADERS: TableDefinition<u64, &[u8]> = TableDefinition::new("headers");
+/// Block id to its height
+const HEIGHTS: TableDefinition<&str, u64> = TableDefinition::new("heights");
+
+#[derive(Args, Debug, Clone)]
+pub struct LightArgs {
+    #[arg(long, help = "JSON-RPC server of the node to sync headers from")]
+    pub node: String,
+    #[arg(
+        long,
+        help = "Where to keep the headers, by default light.redb in the data directory"
+    )]
+    pub headers_path: Option<PathBuf>,
+    #[arg(long, help = "A block the chain must include, as height:id")]
+    pub checkpoint: Option<String>,
+    #[arg(long, help = "Keep following the node's heaviest chain")]
+    pub follow: bool,
+    #[arg(
+        long,
+        help = "Once synced, check that --block includes this transaction",
+        requires = "block"
+    )]
+    pub verify_tx: Option<String>,
+    #[arg(
+        long,
+        help = "Block to check --verify-tx against",
+        requires = "verify_tx"
+    )]
+    pub block: Option<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum LightError {
+    #[error("Header database error: {0}")]
+    Database(#[from] redb::Error),
+    #[error(transparent)]
+    Node(#[from] SnapshotError),
+    #[error("Malformed {0} from the node")]
+    Malformed(&'static str),
+    #[error("Header {1} at height {0} does not hash to its id")]
+    BadDigest(u64, String),
+    #[error("Header {1} at height {0} does not follow the header below it")]
+    Unlinked(u64, String),
+    #[error("Checkpoint must be height:id, not {0}")]
+    BadCheckpoint(String),
+    #[error("The chain has {got} at height {height}, not the checkpoint {expected}")]
+    Checkpoint {
+        height: u64,
+        expected: String,
+        got: String,
+    },
+    #[error("The node's heaviest chain no longer includes the checkpoint {1} at height {0}")]
+    LeftCheckpoint(u64, String),
+    #[error("No synced header has id {0}")]
+    UnknownBlock(String),
+    #[error("The node has no proof that block {0} includes transaction {1}")]
+    NoProof(String, String),
+    #[error("The proof does not show that block {0} includes transaction {1}")]
+    BadProof(String, String),
+    #[error("Could not hash")]
+    Hash,
+}
+
+/// `[digest proof-hash=(unit hash) parent tx-root coinbase-hash timestamp epoch-counter target
+/// accumulated-work height msg]`
+pub struct Header {
+    slab: NounSlab,
+}
+
+impl Header {
+    pub fn from_jam(jam: Bytes) -> Result<Self, LightError> {
+        let mut slab = NounSlab::new();
+        let root = slab
+            .cue_into(jam)
+            .map_err(|_| LightError::Malformed("header"))?;
+        slab.set_root(root);
+        let header = Header { slab };
+        header.id()?;
+        header.parent()?;
+        header.tx_root()?;
+        header.height()?;
+        Ok(header)
+    }
+
+    fn slot(&self, axis: u64) -> Result<Noun, LightError> {
+        unsafe { self.slab.root() }
+            .slot(axis)
+            .map_err(|_| LightError::Malformed("header"))
+    }
+
+    fn hash(&self, axis: u64) -> Result<Id, LightError> {
+        id_of(self.slot(axis)?).ok_or(LightError::Malformed("header"))
+    }
+
+    pub fn id(&self) -> Result<Id, LightError> {
+        self.hash(2)
+    }
+
+    /// The id in base58
+    pub fn id_base58(&self) -> Result<String, LightError> {
+        tip5_hash_to_base58(self.slot(2)?).map_err(|_| LightError::Malformed("header"))
+    }
+
+    pub fn parent(&self) -> Result<Id, LightError> {
+        self.hash(14)
+    }
+
+    /// The hash of the block's `tx-ids`
+    pub fn tx_root(&self) -> Result<Id, LightError> {
+        self.hash(30)
+    }
+
+    pub fn height(&self) -> Result<u64, LightError> {
+        self.slot(2046)?
+            .as_atom()
+            .ok()
+            .and_then(|height| height.as_u64().ok())
+            .ok_or(LightError::Malformed("header"))
+    }
+
+    pub fn jam(&self) -> Bytes {
+        self.slab.jam()
+    }
+
+    /// Recompute the block's id the way `compute-digest:page` does, and check it's the header's
+    pub fn check_digest(&self) -> Result<(), LightError> {
+        let mut stack = NockStack::new(HASH_STACK_SIZE, 0);
+        let leaf = |stack: &mut NockStack, noun: Noun| T(stack, &[D(tas!(b"leaf")), noun]);
+        let hash = |stack: &mut NockStack, noun: Noun| T(stack, &[D(tas!(b"hash")), noun]);
+        let pow = match self.slot(6)?.as_cell() {
+            Ok(proof_hash) => {
+                let empty = leaf(&mut stack, D(0));
+                let proof_hash = hash(&mut stack, proof_hash.tail());
+                T(&mut stack, &[empty, proof_hash])
+            }
+            Err(_) => leaf(&mut stack, D(0)),
+        };
+        let mut commitment = Vec::new();
+        for axis in [14, 30, 62] {
+            commitment.push(hash(&mut stack, self.slot(axis)?));
+        }
+        for axis in [126, 254, 510, 1022, 2046, 2047] {
+            commitment.push(leaf(&mut stack, self.slot(axis)?));
+        }
+        let commitment = T(&mut stack, &commitment);
+        let hashable = T(&mut stack, &[pow, commitment]);
+        let digest = hash_hashable(&mut stack, hashable).map_err(|_| LightError::Hash)?;
+        if id_of(digest) == Some(self.id()?) {
+            Ok(())
+        } else {
+            Err(LightError::BadDigest(self.height()?, self.id_base58()?))
+        }
+    }
+}
+
+/// The hash of `[hash+a hash+b]`
+fn hash_pair(stack: &mut NockStack, a: Noun, b: Noun) -> Result<Noun, LightError> {
+    let a = T(stack, &[D(tas!(b"hash")), a]);
+    let b = T(stack, &[D(tas!(b"hash")), b]);
+    let pair = T(stack, &[a, b]);
+    hash_hashable(stack, pair).map_err(|_| LightError::Hash)
+}
+
+/// Check that `proof`, the kernel's `tx-proof`, shows that the block of `header` includes the
+/// transaction `tx_id`
+pub fn verify_tx_proof(header: &Header, tx_id: &str, proof: &NounSlab) -> Result<(), LightError> {
+    let bad = || LightError::BadProof(header.id_base58().unwrap_or_default(), tx_id.to_string());
+    let root = unsafe { *proof.root() };
+    let slot = |noun: Noun, axis: u64| noun.slot(axis).map_err(|_| bad());
+    let id = slot(root, 2)?;
+    if tip5_hash_to_base58(id).ok().as_deref() != Some(tx_id) {
+        return Err(bad());
+    }
+    let mut stack = NockStack::new(HASH_STACK_SIZE, 0);
+    let children = hash_pair(&mut stack, slot(root, 12)?, slot(root, 13)?)?;
+    let mut node = hash_pair(&mut stack, id, children)?;
+    for step in slot(root, 7)?.list_iter() {
+        let left = slot(step, 2)?
+            .as_atom()
+            .map_err(|_| bad())?
+            .as_u64()
+            .map_err(|_| bad())?;
+        let other = slot(step, 7)?;
+        let children = match left {
+            0 => hash_pair(&mut stack, node, other)?,
+            1 => hash_pair(&mut stack, other, node)?,
+            _ => return Err(bad()),
+        };
+        node = hash_pair(&mut stack, slot(step, 6)?, children)?;
+    }
+    if id_of(node) == Some(header.tx_root()?) {
+        Ok(())
+    } else {
+        Err(bad())
+    }
+}
+
+pub struct HeaderStore {
+    db: Database,
+}
+
+impl HeaderStore {
+    /// Open the store at `path`, creating it if it doesn't exist
+    pub fn open(path: &Path) -> Result<Self, LightError> {
+        let open = || -> Result<_, redb::Error> {
+            let db = Database::create(path)?;
+            let txn = db.begin_write()?;
+            txn.open_table(HEADERS)?;
+            txn.open_table(HEIGHTS)?;
+            txn.commit()?;
+            Ok(db)
+        };
+        Ok(HeaderStore { db: open()? })
+    }
+
+    /// The highest header
+    pub fn tip(&self) -> Result<Option<Header>, LightError> {
+        let read = || -> Result<_, redb::Error> {
+            let txn = self.db.begin_read()?;
+            let headers = txn.open_table(HEADERS)?;
+            let tip = headers.last()?.map(|(_, jam)| jam.value().to_vec());
+            Ok(tip)
+        };
+        read()?
+            .map(|jam| Header::from_jam(Bytes::from(jam)))
+            .transpose()
+    }
+
+    /// The header of the block `id`
+    pub fn get(&self, id: &str) -> Result<Option<Header>, LightError> {
+        let read = || -> Result<_, redb::Error> {
+            let txn = self.db.begin_read()?;
+            let Some(height) = txn.open_table(HEIGHTS)?.get(id)?.map(|h| h.value()) else {
+                return Ok(None);
+            };
+            let jam = txn
+                .open_table(HEADERS)?
+                .get(height)?
+                .map(|jam| jam.value().to_vec());
+            Ok(jam)
+        };
+        read()?
+            .map(|jam| Header::from_jam(Bytes::from(jam)))
+            .transpose()
+    }
+
+    /// Add headers above the tip
+    pub fn append(&self, headers: &[Header]) -> Result<(), LightError> {
+        let mut rows = Vec::new();
+        for header in headers {
+            rows.push((header.height()?, header.id_base58()?, header.jam()));
+        }
+        let write = || -> Result<_, redb::Error> {
+            let txn = self.db.begin_write()?;
+            {
+                let mut by_height = txn.open_table(HEADERS)?;
+                let mut heights = txn.open_table(HEIGHTS)?;
+                for (height, id, jam) in &rows {
+                    by_height.insert(*height, jam.as_ref())?;
+                    heights.insert(id.as_str(), *height)?;
+                }
+            }
+            txn.commit()?;
+            Ok(())
+        };
+        Ok(write()?)
+    }
+
+    /// Drop the highest header
+    pub fn pop(&self) -> Result<(), LightError> {
+        let Some(tip) = self.tip()? else {
+            return Ok(());
+        };
+        let (height, id) = (tip.height()?, tip.id_base58()?);
+        let write = || -> Result<_, redb::Error> {
+            let txn = self.db.begin_write()?;
+            txn.open_table(HEADERS)?.remove(height)?;
+            txn.open_table(HEIGHTS)?.remove(id.as_str())?;
+            txn.commit()?;
+            Ok(())
+        };
+        Ok(write()?)
+    }
+}
+
+pub struct LightClient {
+    node: String,
+    client: Client,
+    store: HeaderStore,
+    /// Height and id of a block the chain must include
+    checkpoint: Option<(u64, String)>,
+}
+
+impl LightClient {
+    pub fn new(node: String, store: HeaderStore, checkpoint: Option<(u64, String)>) -> Self {
+        LightClient {
+            node,
+            client: Client::new(),
+            store,
+            checkpoint,
+        }
+    }
+
+    pub fn store(&self) -> &HeaderStore {
+        &self.store
+    }
+
+    async fn headers(&self, from: u64) -> Result<Vec<Header>, LightError> {
+        let headers = call(
+            &self.client,
+            &self.node,
+            "chain_getHeaders",
+            json!([from, MAX_HEADERS]),
+        )
+        .await?;
+        let Value::Array(headers) = headers else {
+            return Err(LightError::Malformed("headers"));
+        };
+        headers
+            .iter()
+            .map(|header| {
+                let jam = header["jam"]
+                    .as_str()
+                    .and_then(from_hex)
+                    .ok_or(LightError::Malformed("header"))?;
+                Header::from_jam(Bytes::from(jam))
+            })
+            .collect()
+    }
+
+    fn check_checkpoint(&self, header: &Header) -> Result<(), LightError> {
+        let Some((height, expected)) = &self.checkpoint else {
+            return Ok(());
+        };
+        let got = header.id_base58()?;
+        if header.height()? == *height && got != *expected {
+            return Err(LightError::Checkpoint {
+                height: *height,
+                expected: expected.clone(),
+                got,
+            });
+        }
+        Ok(())
+    }
+
+    /// Sync headers up to the node's heaviest block, and return its height
+    pub async fn sync(&self) -> Result<Option<u64>, LightError> {
+        loop {
+            let tip = self.store.tip()?;
+            let from = tip.as_ref().map_or(Ok(0), Header::height)?;
+            let mut headers = self.headers(from).await?.into_iter();
+            if let Some(tip) = &tip {
+                // Build on our tip only if the node's chain still has it
+                let on_chain = match headers.next() {
+                    Some(header) => header.id()? == tip.id()?,
+                    None => false,
+                };
+                if !on_chain {
+                    let id = tip.id_base58()?;
+                    if self
+                        .checkpoint
+                        .as_ref()
+                        .is_some_and(|(height, _)| *height == from)
+                    {
+                        return Err(LightError::LeftCheckpoint(from, id));
+                    }
+                    warn!("Dropping header {id} at height {from}, which left the heaviest chain");
+                    self.store.pop()?;
+                    continue;
+                }
+            }
+            let mut fresh: Vec<Header> = Vec::new();
+            for header in headers {
+                header.check_digest()?;
+                let height = header.height()?;
+                if let Some(below) = fresh.last().or(tip.as_ref()) {
+                    if height != below.height()? + 1 || header.parent()? != below.id()? {
+                        return Err(LightError::Unlinked(height, header.id_base58()?));
+                    }
+                }
+                self.check_checkpoint(&header)?;
+                fresh.push(header);
+            }
+            let Some(highest) = fresh.last() else {
+                return Ok(tip.map(|_| from));
+            };
+            info!("Synced headers up to height {}", highest.height()?);
+            self.store.append(&fresh)?;
+        }
+    }
+
+    /// Check with the node's proof that block `block_id`, whose header we have, includes the
+    /// transaction `tx_id`
+    pub async fn verify_tx(&self, block_id: &str, tx_id: &str) -> Result<(), LightError> {
+        let header = self
+            .store
+            .get(block_id)?
+            .ok_or_else(|| LightError::UnknownBlock(block_id.to_string()))?;
+        let proof = call(
+            &self.client,
+            &self.node,
+            "chain_getTxProof",
+            json!([block_id, tx_id]),
+        )
+        .await?;
+        if proof.is_null() {
+            return Err(LightError::NoProof(block_id.to_string(), tx_id.to_string()));
+        }
+        let jam = proof["jam"]
+            .as_str()
+            .and_then(from_hex)
+            .ok_or(LightError::Malformed("proof"))?;
+        let mut slab = NounSlab::new();
+        let root = slab
+            .cue_into(Bytes::from(jam))
+            .map_err(|_| LightError::Malformed("proof"))?;
+        slab.set_root(root);
+        verify_tx_proof(&header, tx_id, &slab)
+    }
+}
+
+fn parse_checkpoint(checkpoint: &str) -> Result<(u64, String), LightError> {
+    let bad = || LightError::BadCheckpoint(checkpoint.to_string());
+    let (height, id) = checkpoint.split_once(':').ok_or_else(bad)?;
+    Ok((height.parse().map_err(|_| bad())?, id.to_string()))
+}
+
+/// Run `nockchain light`
+pub async fn run(args: &LightArgs) -> Result<(), LightError> {
+    let path = args
+        .headers_path
+        .clone()
+        .unwrap_or_else(|| nockapp::default_data_dir("nockchain").join("light.redb"));
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let checkpoint = args
+        .checkpoint
+        .as_deref()
+        .map(parse_checkpoint)
+        .transpose()?;
+    let light = LightClient::new(args.node.clone(), HeaderStore::open(&path)?, checkpoint);
+    loop {
+        match light.sync().await? {
+            Some(height) => info!("Headers synced to height {height}"),
+            None => info!("The node has no blocks yet"),
+        }
+        if let (Some(tx_id), Some(block_id)) = (&args.verify_tx, &args.block) {
+            light.verify_tx(block_id, tx_id).await?;
+            info!("Block {block_id} includes transaction {tx_id}");
+        }
+        if !args.follow {
+            return Ok(());
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::{Atom, NO, YES};
+
+    use super::*;
+
+    fn id_noun(slab: &mut NounSlab, id: &Id) -> Noun {
+        let belts: Vec<Noun> = id
+            .iter()
+            .map(|belt| Atom::new(&mut *slab, *belt).as_noun())
+            .collect();
+        T(slab, &belts)
+    }
+
+    #[test]
+    fn test_verify_tx_proof() {
+        let mut stack = NockStack::new(HASH_STACK_SIZE, 0);
+        let mut slab = NounSlab::new();
+        let (a, b, c) = (
+            id_noun(&mut slab, &[1; 5]),
+            id_noun(&mut slab, &[2; 5]),
+            id_noun(&mut slab, &[3; 5]),
+        );
+        // tx-ids of [n=a l=[b ~ ~] r=[c ~ ~]], hashed as +hash-tx-ids does
+        let leaf = T(&mut stack, &[D(tas!(b"leaf")), D(0)]);
+        let empty = hash_hashable(&mut stack, leaf).expect("hash");
+        let no_children = hash_pair(&mut stack, empty, empty).expect("hash");
+        let left = hash_pair(&mut stack, b, no_children).expect("hash");
+        let right = hash_pair(&mut stack, c, no_children).expect("hash");
+        let children = hash_pair(&mut stack, left, right).expect("hash");
+        let tx_root = hash_pair(&mut stack, a, children).expect("hash");
+
+        let tx_root = slab.copy_into(tx_root);
+        let empty = slab.copy_into(empty);
+        let right = slab.copy_into(right);
+        let digest = id_noun(&mut slab, &[4; 5]);
+        let header = T(
+            &mut slab,
+            &[digest, D(0), digest, tx_root, digest, D(0), D(0), D(0), D(0), D(7), D(0)],
+        );
+        slab.set_root(header);
+        let header = Header::from_jam(slab.jam()).expect("header");
+        assert_eq!(header.height().expect("height"), 7);
+
+        let b_id = tip5_hash_to_base58(b).expect("base58");
+        let step = T(&mut slab, &[YES, a, right]);
+        let path = T(&mut slab, &[step, D(0)]);
+        let proof = T(&mut slab, &[b, empty, empty, path]);
+        slab.set_root(proof);
+        assert!(verify_tx_proof(&header, &b_id, &slab).is_ok());
+
+        // the wrong transaction, or the wrong side
+        let c_id = tip5_hash_to_base58(c).expect("base58");
+        assert!(verify_tx_proof(&header, &c_id, &slab).is_err());
+        let step = T(&mut slab, &[NO, a, right]);
+        let path = T(&mut slab, &[step, D(0)]);
+        let proof = T(&mut slab, &[b, empty, empty, path]);
+        slab.set_root(proof);
+        assert!(verify_tx_proof(&header, &b_id, &slab).is_err());
+    }
+
+    #[test]
+    fn test_parse_checkpoint() {
+        assert_eq!(
+            parse_checkpoint("12:abc").expect("checkpoint"),
+            (12, "abc".to_string())
+        );
+        assert!(parse_checkpoint("abc").is_err());
+        assert!(parse_checkpoint("x:abc").is_err());
+    }
+}
@@ -1,20 +1,57 @@
 use std::error::Error;
 
-use clap::Parser;
 use kernels::dumb::KERNEL;
 use nockapp::kernel::boot;
+use nockapp::noun::slab::NockJammer;
+use nockapp::settings::ConfigCommand;
 use nockapp::NockApp;
-use zkvm_jetpack::hot::produce_prover_hot_state;
+use nockvm::jets::pack::JetRegistry;
+use zkvm_jetpack::hot::ProverJets;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     nockvm::check_endian();
-    let cli = nockchain::NockchainCli::parse();
+    let (cli, effective) = nockchain::config::LAYERS.parse::<nockchain::NockchainCli>();
+    if let Some(nockchain::config::NockchainCommand::Config { command }) = &cli.command {
+        match command {
+            ConfigCommand::PrintEffective => print!("{}", effective),
+        }
+        return Ok(());
+    }
+    if let Some(nockchain::config::NockchainCommand::Status(args)) = &cli.command {
+        nockchain::status::run(args).await?;
+        return Ok(());
+    }
     boot::init_default_tracing(&cli.nockapp_cli);
+    if let Some(nockchain::config::NockchainCommand::Light(args)) = &cli.command {
+        nockchain::light::run(args).await?;
+        return Ok(());
+    }
+    if let Some(nockchain::config::NockchainCommand::Devnet(args)) = &cli.command {
+        nockchain::devnet::run(args).await?;
+        return Ok(());
+    }
+    if let Some(nockchain::config::NockchainCommand::RestoreFromRemote(args)) = &cli.command {
+        nockchain::backup::restore(args, &cli).await?;
+        return Ok(());
+    }
+    if let Some(nockchain::config::NockchainCommand::BenchPow(args)) = &cli.command {
+        nockchain::bench::run(args, &cli).await?;
+        return Ok(());
+    }
 
-    let prover_hot_state = produce_prover_hot_state();
+    let mut jets = JetRegistry::new();
+    jets.register(&ProverJets)?;
+    if let Some(nockchain::config::NockchainCommand::ExportBlocks(args)) = &cli.command {
+        nockchain::blocks::export::<NockJammer>(args, &cli, KERNEL, jets.hot_state()).await?;
+        return Ok(());
+    }
+    if let Some(nockchain::config::NockchainCommand::VerifyGenesis(args)) = &cli.command {
+        nockchain::genesis::verify::<NockJammer>(args, &cli, KERNEL, jets.hot_state()).await?;
+        return Ok(());
+    }
     let mut nockchain: NockApp =
-        nockchain::init_with_kernel(Some(cli), KERNEL, prover_hot_state.as_slice()).await?;
+        nockchain::init_with_kernel(Some(cli), KERNEL, jets.hot_state()).await?;
     nockchain.run().await?;
     Ok(())
 }
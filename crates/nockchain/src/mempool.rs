@@ -0,0 +1,496 @@
+//! Inspecting and trimming the mempool, for the JSON-RPC `mempool_*` calls.
+//!
+//! The mempool is every raw transaction the kernel has validated that no block includes yet. The
+//! kernel's `%mempool` peek describes each one, and its `%drop-txs` and `%flush-mempool` commands
+//! evict them. Transactions in the node's candidate block are never evicted, since the miner
+//! still needs them, and neither is anything a pending block is waiting for, since that isn't in
+//! the mempool.
+//!
+//! The kernel caps the mempool in bytes, as `--mempool-max-bytes` sets, and admits a
+//! transaction over the cap only by evicting ones that pay less per byte. `mempool_setSizeLimit`
+//! pokes the same `%set-mempool-max-bytes` command, and lowering the cap evicts the transactions
+//! paying the least per byte until the mempool fits under it. The cap is in the kernel state, so
+//! it lasts across restarts, until `--mempool-max-bytes` sets it again.
+//!
+//! `mempool_estimateFee` asks the kernel's `%fee-estimate` peek what to pay, per bit of jammed
+//! raw transaction, to be in one of the next `target` blocks, as a fee and a size to scale it by.
+//! It's the higher of what outbids the mempool for room in those blocks and what recent full
+//! blocks took. Wallets peek the same path to pay `--fee auto`.
+//!
+//! Beyond validity, the kernel admits only transactions that meet the node's [`RelayPolicy`],
+//! and it only gossips what it admits, so the policy keeps spam out of both. It doesn't apply to
+//! a transaction a pending block is waiting for.
+//!
+//! The mempool is in the kernel state, but a crash loses what arrived since the last checkpoint,
+//! and `--new` or a reindex starts from an empty one. So it's also saved to `mempool.jam` in the
+//! data directory every `--mempool-save-secs` and when the node shuts down, as a
+//! `(list [id=@t raw-tx])`. Once the kernel is born, each saved transaction is heard again, as if
+//! from a peer, so those no longer valid against the chain are dropped, and the rest are back.
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use nockapp::driver::{make_driver, IODriverFn, NockAppHandle, PokeResult};
+use nockapp::noun::slab::NounSlab;
+use nockapp::utils::make_tas;
+use nockapp::wire::Wire;
+use nockapp::{AtomExt, Bytes, NounExt};
+use nockvm::noun::{Atom, Noun, Slots, D, T};
+use nockvm_macros::tas;
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+use crate::reindex::hear;
+use crate::rpc::{peek, RpcError, RpcWire};
+
+/// How often the mempool is saved by default, in seconds
+pub const DEFAULT_SAVE_SECS: u64 = 60;
+
+/// Where the mempool of a node booted in `data_dir` is saved
+pub fn save_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("mempool.jam")
+}
+
+/// A transaction waiting for a block, as the `%mempool` peek describes it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MempoolTx {
+    pub id: String,
+    pub fee: u64,
+    /// Bytes in the jammed raw transaction
+    pub size: u64,
+    /// Height of the heaviest block when the node heard it
+    pub heard_at: u64,
+    /// Whether the candidate block includes it, which keeps it from being evicted
+    pub in_candidate: bool,
+}
+
+impl MempoolTx {
+    /// Read one `[id=@t fee size heard-at candidate=?]`
+    fn from_noun(noun: Noun) -> Option<Self> {
+        let number = |noun: Noun| noun.as_atom().ok()?.as_u64().ok();
+        let in_candidate = noun.slot(31).ok()?.as_atom().ok()?.as_u64().ok()?;
+        Some(MempoolTx {
+            id: noun.slot(2).ok()?.as_atom().ok()?.into_string().ok()?,
+            fee: number(noun.slot(6).ok()?)?,
+            size: number(noun.slot(14).ok()?)?,
+            heard_at: number(noun.slot(30).ok()?)?,
+            in_candidate: in_candidate == 0,
+        })
+    }
+
+    /// `{id, fee, size, heardAt, age, inCandidate}`, with the age in blocks below `height`
+    pub fn to_json(&self, height: u64) -> Value {
+        json!({
+            "id": self.id,
+            "fee": self.fee,
+            "size": self.size,
+            "heardAt": self.heard_at,
+            "age": height.saturating_sub(self.heard_at),
+            "inCandidate": self.in_candidate,
+        })
+    }
+}
+
+/// What the mempool admits, and so relays, beyond what's valid
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RelayPolicy {
+    /// Least fee for every thousand bytes of jammed raw transaction
+    pub min_fee_per_kb: u64,
+    /// Most bytes of jammed raw transaction
+    pub max_tx_bytes: Option<u64>,
+    /// Least nicks each seed may carry to its output
+    pub dust: u64,
+    /// Most seeds, so outputs, in a transaction
+    pub max_seeds: Option<u64>,
+    /// Most pubkeys in the lock a seed pays to. Keys no one holds carry arbitrary data.
+    pub max_lock_keys: Option<u64>,
+}
+
+impl RelayPolicy {
+    /// The kernel's `relay-policy`: `[min-fee-rate=[fee size] max-tx-bytes dust max-seeds
+    /// max-lock-keys]`, with the fee rate per bit as the `%fee-estimate` peek gives it
+    pub fn to_noun(&self, slab: &mut NounSlab) -> Noun {
+        let atom = |slab: &mut NounSlab, n: u64| Atom::new(slab, n).as_noun();
+        let unit = |slab: &mut NounSlab, n: Option<u64>| match n {
+            Some(n) => {
+                let n = Atom::new(slab, n).as_noun();
+                T(slab, &[D(0), n])
+            }
+            None => D(0),
+        };
+        let fee = atom(slab, self.min_fee_per_kb);
+        let rate = T(slab, &[fee, D(8000)]);
+        let max_tx_bytes = unit(slab, self.max_tx_bytes);
+        let dust = atom(slab, self.dust);
+        let max_seeds = unit(slab, self.max_seeds);
+        let max_lock_keys = unit(slab, self.max_lock_keys);
+        T(slab, &[rate, max_tx_bytes, dust, max_seeds, max_lock_keys])
+    }
+}
+
+/// What to pay to be in one of the next `target` blocks: `fee` nicks for every `size` bits
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeeEstimate {
+    pub target: u64,
+    pub fee: u64,
+    pub size: u64,
+}
+
+impl FeeEstimate {
+    /// Read one `[fee size]`
+    fn from_noun(target: u64, noun: Noun) -> Option<Self> {
+        let number = |noun: Noun| noun.as_atom().ok()?.as_u64().ok();
+        Some(FeeEstimate {
+            target,
+            fee: number(noun.slot(2).ok()?)?,
+            size: number(noun.slot(3).ok()?)?.max(1),
+        })
+    }
+
+    /// The fee for a transaction of `bits` bits, rounded up
+    pub fn fee_for(&self, bits: u64) -> u64 {
+        (self.fee as u128 * bits as u128).div_ceil(self.size as u128) as u64
+    }
+
+    /// `{target, fee, size, perKilobyte}`, with the fee for a thousand bytes
+    pub fn to_json(&self) -> Value {
+        json!({
+            "target": self.target,
+            "fee": self.fee,
+            "size": self.size,
+            "perKilobyte": self.fee_for(8000),
+        })
+    }
+}
+
+/// What to pay to be in one of the next `target` blocks
+pub async fn estimate_fee(handle: &NockAppHandle, target: u64) -> Result<FeeEstimate, RpcError> {
+    let estimate = peek(handle, |slab| {
+        let target = make_tas(slab, &target.to_string()).as_noun();
+        let tag = make_tas(slab, "fee-estimate").as_noun();
+        T(slab, &[tag, target, D(0)])
+    })
+    .await?
+    .ok_or_else(|| RpcError::Internal("no fee estimate".into()))?;
+    FeeEstimate::from_noun(target, unsafe { *estimate.root() })
+        .ok_or_else(|| RpcError::Internal("malformed fee estimate".into()))
+}
+
+/// Every transaction in the mempool, oldest first
+pub async fn mempool(handle: &NockAppHandle) -> Result<Vec<MempoolTx>, RpcError> {
+    let txs = peek(handle, |slab| T(slab, &[D(tas!(b"mempool")), D(0)])).await?;
+    let Some(txs) = txs else {
+        return Ok(Vec::new());
+    };
+    let mut txs = unsafe { *txs.root() }
+        .list_iter()
+        .map(MempoolTx::from_noun)
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| RpcError::Internal("malformed mempool".into()))?;
+    txs.sort_by(|a, b| a.heard_at.cmp(&b.heard_at).then_with(|| a.id.cmp(&b.id)));
+    Ok(txs)
+}
+
+/// Evict `ids` from the mempool, and count how many left
+pub async fn drop_txs(handle: &NockAppHandle, ids: &[String]) -> Result<usize, RpcError> {
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    command(handle, "drop-txs", |slab| {
+        ids.iter().rev().fold(D(0), |list, id| {
+            let id = make_tas(slab, id).as_noun();
+            T(slab, &[id, list])
+        })
+    })
+    .await
+}
+
+/// Evict everything the mempool can spare, and count how many left
+pub async fn flush(handle: &NockAppHandle) -> Result<usize, RpcError> {
+    command(handle, "flush-mempool", |_| D(0)).await
+}
+
+/// Cap the mempool at `max_bytes` of jam, or lift the cap with `None`, and count how many
+/// transactions left to fit under it
+pub async fn set_max_bytes(
+    handle: &NockAppHandle,
+    max_bytes: Option<u64>,
+) -> Result<usize, RpcError> {
+    command(handle, "set-mempool-max-bytes", |slab| match max_bytes {
+        Some(max_bytes) => {
+            let max_bytes = Atom::new(slab, max_bytes).as_noun();
+            T(slab, &[D(0), max_bytes])
+        }
+        None => D(0),
+    })
+    .await
+}
+
+/// The kernel's cap on the mempool, in bytes of jam, if it has one
+pub async fn max_bytes(handle: &NockAppHandle) -> Result<Option<u64>, RpcError> {
+    let cap = peek(handle, |slab| {
+        let tag = make_tas(slab, "mempool-max-bytes").as_noun();
+        T(slab, &[tag, D(0)])
+    })
+    .await?
+    .ok_or_else(|| RpcError::Internal("no mempool cap".into()))?;
+    let cap = unsafe { *cap.root() };
+    if cap.is_atom() {
+        return Ok(None);
+    }
+    cap.slot(3)
+        .ok()
+        .and_then(|max_bytes| max_bytes.as_atom().ok())
+        .and_then(|max_bytes| max_bytes.as_u64().ok())
+        .map(Some)
+        .ok_or_else(|| RpcError::Internal("malformed mempool cap".into()))
+}
+
+/// Poke `[%command tag arg]` and count the transactions that left the mempool
+async fn command(
+    handle: &NockAppHandle,
+    tag: &str,
+    arg: impl FnOnce(&mut NounSlab) -> Noun,
+) -> Result<usize, RpcError> {
+    let before = mempool(handle).await?.len();
+    let mut slab = NounSlab::new();
+    let tag = make_tas(&mut slab, tag).as_noun();
+    let arg = arg(&mut slab);
+    let poke = T(&mut slab, &[D(tas!(b"command")), tag, arg]);
+    slab.set_root(poke);
+    let result = handle.poke(RpcWire::Mempool.to_wire(), slab).await?;
+    if !matches!(result, PokeResult::Ack) {
+        return Err(RpcError::Internal("the kernel refused the command".into()));
+    }
+    Ok(before.saturating_sub(mempool(handle).await?.len()))
+}
+
+/// Every transaction in the mempool as a `(list [id=@t raw-tx])`, oldest first
+pub async fn raw_txs(handle: &NockAppHandle) -> Result<NounSlab, RpcError> {
+    let mut saved = NounSlab::new();
+    let mut entries = Vec::new();
+    for tx in mempool(handle).await? {
+        // one evicted since the listing is just left out
+        let Some(raw_tx) = peek(handle, |slab| {
+            let tag = make_tas(slab, "raw-transaction").as_noun();
+            let id = make_tas(slab, &tx.id).as_noun();
+            T(slab, &[tag, id, D(0)])
+        })
+        .await?
+        else {
+            continue;
+        };
+        let id = make_tas(&mut saved, &tx.id).as_noun();
+        let raw_tx = saved.copy_into(unsafe { *raw_tx.root() });
+        entries.push(T(&mut saved, &[id, raw_tx]));
+    }
+    let root = entries
+        .into_iter()
+        .rev()
+        .fold(D(0), |tail, entry| T(&mut saved, &[entry, tail]));
+    saved.set_root(root);
+    Ok(saved)
+}
+
+/// Write the mempool to `path`, replacing what was saved, and count the transactions
+pub async fn save(handle: &NockAppHandle, path: &Path) -> Result<usize, RpcError> {
+    let saved = raw_txs(handle).await?;
+    let count = unsafe { *saved.root() }.list_iter().count();
+    let tmp_path = path.with_extension("jam.tmp");
+    let write = async {
+        tokio::fs::write(&tmp_path, saved.jam()).await?;
+        tokio::fs::rename(&tmp_path, path).await
+    };
+    write
+        .await
+        .map_err(|e| RpcError::Internal(format!("could not write {}: {}", path.display(), e)))?;
+    Ok(count)
+}
+
+/// Hear each transaction saved at `path` again, and count those the mempool then has and the
+/// ones saved, or `None` if nothing was saved
+pub async fn restore(
+    handle: &NockAppHandle,
+    path: &Path,
+) -> Result<Option<(usize, usize)>, RpcError> {
+    let jam = match tokio::fs::read(path).await {
+        Ok(jam) => jam,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(RpcError::Internal(format!(
+                "could not read {}: {}",
+                path.display(),
+                e
+            )))
+        }
+    };
+    let mut saved = NounSlab::new();
+    let root = saved
+        .cue_into(Bytes::from(jam))
+        .map_err(|_| RpcError::Internal(format!("{} is not a saved mempool", path.display())))?;
+    saved.set_root(root);
+
+    let mut ids = Vec::new();
+    for (id, raw_tx) in saved_txs(root) {
+        // one the kernel won't take is left out, like one no longer valid
+        if let Err(e) = hear(handle, "heard-tx", raw_tx).await {
+            warn!("Could not restore mempool transaction {}: {}", id, e);
+        }
+        ids.push(id);
+    }
+    let kept: HashSet<String> = mempool(handle).await?.into_iter().map(|tx| tx.id).collect();
+    let restored = ids.iter().filter(|id| kept.contains(*id)).count();
+    Ok(Some((restored, ids.len())))
+}
+
+/// Each `[id=@t raw-tx]` in a saved `(list [id=@t raw-tx])`, skipping malformed entries
+fn saved_txs(saved: Noun) -> Vec<(String, Noun)> {
+    saved
+        .list_iter()
+        .filter_map(|entry| {
+            let entry = entry.as_cell().ok()?;
+            let id = entry.head().as_atom().ok()?.into_string().ok()?;
+            Some((id, entry.tail()))
+        })
+        .collect()
+}
+
+/// Once `born_rx` fires, restore the mempool saved at `path`, then save it there every
+/// `interval` and once more when the node shuts down
+pub fn make_persist_driver(
+    path: PathBuf,
+    interval: Duration,
+    born_rx: oneshot::Receiver<()>,
+) -> IODriverFn {
+    make_driver(move |handle| async move {
+        let shutdown = handle.shutdown.clone();
+        let _guard = shutdown.guard();
+        let _ = born_rx.await;
+        match restore(&handle, &path).await {
+            Ok(Some((restored, saved))) => info!(
+                "Restored {} of {} saved mempool transactions, dropping those no longer valid",
+                restored, saved
+            ),
+            Ok(None) => {}
+            Err(e) => warn!("Could not restore the mempool: {}", e),
+        }
+
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        ticker.tick().await;
+        loop {
+            tokio::select! {
+                _ = shutdown.draining() => break,
+                _ = ticker.tick() => {
+                    if let Err(e) = save(&handle, &path).await {
+                        warn!("Could not save the mempool: {}", e);
+                    }
+                }
+            }
+        }
+        match save(&handle, &path).await {
+            Ok(count) => info!("Saved {} mempool transactions", count),
+            Err(e) => warn!("Could not save the mempool: {}", e),
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::noun::{NO, YES};
+
+    use super::*;
+
+    fn tx(id: &str, fee: u64, size: u64, heard_at: u64, in_candidate: bool) -> MempoolTx {
+        MempoolTx {
+            id: id.into(),
+            fee,
+            size,
+            heard_at,
+            in_candidate,
+        }
+    }
+
+    #[test]
+    fn test_from_noun() {
+        let mut slab: NounSlab = NounSlab::new();
+        let id = make_tas(&mut slab, "tx1").as_noun();
+        let noun = T(&mut slab, &[id, D(10), D(200), D(3), YES]);
+        assert_eq!(
+            MempoolTx::from_noun(noun),
+            Some(tx("tx1", 10, 200, 3, true))
+        );
+        let noun = T(&mut slab, &[id, D(10), D(200), D(3), NO]);
+        assert!(!MempoolTx::from_noun(noun).expect("tx").in_candidate);
+        assert_eq!(MempoolTx::from_noun(D(0)), None);
+        assert_eq!(tx("tx1", 10, 200, 3, true).to_json(5)["age"], json!(2));
+    }
+
+    #[test]
+    fn test_fee_estimate() {
+        let mut slab: NounSlab = NounSlab::new();
+        let noun = T(&mut slab, &[D(3), D(8)]);
+        let estimate = FeeEstimate::from_noun(2, noun).expect("estimate");
+        assert_eq!(estimate.fee_for(8), 3);
+        assert_eq!(estimate.fee_for(9), 4);
+        assert_eq!(estimate.to_json()["perKilobyte"], json!(3000));
+        let free = FeeEstimate::from_noun(1, T(&mut slab, &[D(0), D(0)])).expect("estimate");
+        assert_eq!(free.fee_for(1000), 0);
+    }
+
+    #[test]
+    fn test_relay_policy() {
+        let mut slab: NounSlab = NounSlab::new();
+        let policy = RelayPolicy {
+            min_fee_per_kb: 5,
+            max_seeds: Some(10),
+            ..RelayPolicy::default()
+        };
+        let noun = policy.to_noun(&mut slab);
+        let number = |axis| {
+            noun.slot(axis)
+                .unwrap()
+                .as_atom()
+                .unwrap()
+                .as_u64()
+                .unwrap()
+        };
+        assert_eq!((number(4), number(5)), (5, 8000));
+        assert_eq!(number(6), 0);
+        assert_eq!(number(14), 0);
+        assert_eq!((number(60), number(61)), (0, 10));
+        assert_eq!(number(31), 0);
+    }
+
+    #[test]
+    fn test_save_path() {
+        let dir = Path::new("/tmp/node/nockchain");
+        assert_eq!(save_path(dir), dir.join("mempool.jam"));
+    }
+
+    #[test]
+    fn test_saved_txs() {
+        let mut slab: NounSlab = NounSlab::new();
+        let first = make_tas(&mut slab, "tx1").as_noun();
+        let second = make_tas(&mut slab, "tx2").as_noun();
+        let first = T(&mut slab, &[first, D(1)]);
+        let second = T(&mut slab, &[second, D(2)]);
+        // an id that isn't a cord, and an entry that isn't a cell, are skipped
+        let unnamed = T(&mut slab, &[D(0), D(3)]);
+        let unnamed = T(&mut slab, &[unnamed, D(3)]);
+        let saved = T(&mut slab, &[first, D(7), unnamed, second, D(0)]);
+        slab.set_root(saved);
+
+        let mut copy = NounSlab::new();
+        let root = copy.cue_into(slab.jam()).expect("cue");
+        let txs: Vec<(String, u64)> = saved_txs(root)
+            .into_iter()
+            .map(|(id, tx)| (id, tx.as_atom().unwrap().as_u64().unwrap()))
+            .collect();
+        assert_eq!(txs, vec![("tx1".into(), 1), ("tx2".into(), 2)]);
+        assert!(saved_txs(D(0)).is_empty());
+    }
+}
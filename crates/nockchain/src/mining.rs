@@ -1,4 +1,7 @@
+use std::collections::{HashMap, VecDeque};
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use kernels::miner::KERNEL;
 use nockapp::kernel::form::SerfThread;
@@ -9,15 +12,24 @@ use nockapp::noun::slab::NounSlab;
 use nockapp::noun::{AtomExt, NounExt};
 use nockapp::save::SaveableCheckpoint;
 use nockapp::utils::NOCK_STACK_SIZE_TINY;
-use nockapp::CrownError;
-use nockvm::noun::{Atom, D, NO, T, YES};
+use nockapp::{prometheus, CrownError};
+use nockvm::interpreter::NockCancelToken;
+use nockvm::jets::hot::HotEntry;
+use nockvm::noun::{Atom, Slots, D, NO, T, YES};
 use nockvm_macros::tas;
 use rand::Rng;
-use tokio::sync::Mutex;
+use serde_json::{json, Value};
+use tokio::task::{Id, JoinSet};
 use tracing::{debug, info, instrument, warn};
 use zkvm_jetpack::form::PRIME;
 use zkvm_jetpack::noun::noun_ext::NounExt as OtherNounExt;
 
+use crate::affinity::Placement;
+use crate::explorer::tip_block;
+
+/// How far back the hashrate looks
+pub const HASHRATE_WINDOW: Duration = Duration::from_secs(10 * 60);
+
 pub enum MiningWire {
     Mined,
     Candidate,
@@ -46,11 +58,14 @@ impl Wire for MiningWire {
     }
 }
 
+/// One lock the coinbase pays, and its share of it
 #[derive(Debug, Clone)]
 pub struct MiningKeyConfig {
     pub share: u64,
     pub m: u64,
     pub keys: Vec<String>,
+    /// Whether the share was given as a percentage, like `40%`
+    pub percent: bool,
 }
 
 impl FromStr for MiningKeyConfig {
@@ -68,25 +83,63 @@ impl FromStr for MiningKeyConfig {
             return Err("Invalid share,m format".to_string());
         }
 
-        let share = share_m[0].parse::<u64>().map_err(|e| e.to_string())?;
+        let (share, percent) = match share_m[0].strip_suffix('%') {
+            Some(share) => (share, true),
+            None => (share_m[0], false),
+        };
+        let share = share.parse::<u64>().map_err(|e| e.to_string())?;
+        if share == 0 {
+            return Err("A share must be more than 0".to_string());
+        }
         let m = share_m[1].parse::<u64>().map_err(|e| e.to_string())?;
         let keys: Vec<String> = parts[1].split(',').map(String::from).collect();
+        if m == 0 || m as usize > keys.len() {
+            return Err(format!("m must be between 1 and {}", keys.len()));
+        }
 
-        Ok(MiningKeyConfig { share, m, keys })
+        Ok(MiningKeyConfig {
+            share,
+            m,
+            keys,
+            percent,
+        })
     }
 }
 
-struct MiningData {
+/// Check that the shares of a coinbase split can be paid: shares are proportions, unless they're
+/// percentages, in which case they all have to be and they have to add up to 100
+pub fn check_split(configs: &[MiningKeyConfig]) -> Result<(), String> {
+    if configs.is_empty() {
+        return Err("The coinbase has to pay at least one lock".to_string());
+    }
+    if configs.iter().any(|config| config.percent) {
+        if !configs.iter().all(|config| config.percent) {
+            return Err("Either all shares or none may be percentages".to_string());
+        }
+        let total: u64 = configs.iter().map(|config| config.share).sum();
+        if total != 100 {
+            return Err(format!("Percentage shares add up to {}%, not 100%", total));
+        }
+    }
+    Ok(())
+}
+
+pub(crate) struct MiningData {
     pub block_header: NounSlab,
     pub version: NounSlab,
     pub target: NounSlab,
     pub pow_len: u64,
 }
 
+/// With `on_demand`, the miner is ready to mine but the kernel starts with mining disabled, for
+/// a regtest node that only mines when asked to. What the miner does is counted in `stats`.
 pub fn create_mining_driver(
     mining_config: Option<Vec<MiningKeyConfig>>,
     mine: bool,
+    on_demand: bool,
     num_threads: u64,
+    placement: Placement,
+    stats: Arc<MiningStats>,
     init_complete_tx: Option<tokio::sync::oneshot::Sender<()>>,
 ) -> IODriverFn {
     Box::new(move |handle| {
@@ -112,7 +165,7 @@ pub fn create_mining_driver(
             } else {
                 set_mining_key_advanced(&handle, configs).await?;
             }
-            enable_mining(&handle, mine).await?;
+            enable_mining(&handle, mine && !on_demand).await?;
 
             if let Some(tx) = init_complete_tx {
                 tx.send(()).map_err(|_| {
@@ -125,67 +178,70 @@ pub fn create_mining_driver(
                 return Ok(());
             }
 
+            let num_threads = num_threads.max(1) as usize;
             info!("Starting mining driver with {} threads", num_threads);
+            stats.started(num_threads as u64, Instant::now());
 
-            let mut mining_attempts = tokio::task::JoinSet::new();
             let hot_state = zkvm_jetpack::hot::produce_prover_hot_state();
             let test_jets_str = std::env::var("NOCK_TEST_JETS").unwrap_or_default();
             let test_jets = nockapp::kernel::boot::parse_test_jets(test_jets_str.as_str());
 
-            let mining_data: Mutex<Option<MiningData>> = Mutex::new(None);
+            let mut mining_data: Option<MiningData> = None;
+            let mut workers = Workers::new(num_threads);
 
             loop {
                 tokio::select! {
-                        mining_result = mining_attempts.join_next(), if !mining_attempts.is_empty() => {
-                            if mining_result.as_ref().is_none() || mining_result.as_ref().expect("error").is_err() {
-                                // restart mining thread
-                                debug!("Mining task failed, restarting mining thread");
-                                let kernel = Vec::from(KERNEL);
-                                let serf = SerfThread::<SaveableCheckpoint>::new(
-                                    kernel,
-                                    None,
-                                    hot_state.clone(),
-                                    NOCK_STACK_SIZE_TINY,
-                                    test_jets.clone(),
-                                    false,
-                                )
-                                .await
-                                .expect("Could not load mining kernel");
-
-                                start_mining_attempt(serf, mining_data.lock().await, &mut mining_attempts, None).await;
-                            } else {
-                                let (serf, slab_res) = mining_result.expect("mining task failed").expect("mining result failed");
-                                let result = slab_res.expect("Failed to get mining result");
-
-                                //  there should only be one effect
-                                let effects = unsafe { result.root() };
-                                let effect = effects.as_cell().expect("Expected result to be a cell").head();
-                                let [head, res, tail] = effect.uncell().expect("Expected three elements in mining result");
-                                if head.eq_bytes("mine-result") {
-                                    if unsafe { res.raw_equals(&D(0)) } {
-                                        // success
+                    attempt = workers.attempts.join_next_with_id(), if !workers.attempts.is_empty() => {
+                        let (worker, serf, result) = match attempt {
+                            Some(Ok((id, (worker, serf, result, took)))) => {
+                                workers.ids.remove(&id);
+                                if result.is_ok() {
+                                    stats.attempted(took, Instant::now());
+                                }
+                                (worker, serf, Some(result))
+                            }
+                            Some(Err(e)) => {
+                                let Some(worker) = workers.ids.remove(&e.id()) else {
+                                    continue;
+                                };
+                                debug!("Mining thread {worker} failed, restarting it: {e}");
+                                (worker, load_miner(&hot_state, &test_jets, &placement, worker).await, None)
+                            }
+                            None => continue,
+                        };
+                        let data = mining_data.as_ref().expect("Mining data should already be initialized");
+                        match result {
+                            Some(Ok(result)) => {
+                                if let Some(poke) = mined(&result) {
+                                    if !mined_on(&poke, data) {
+                                        info!("Found a block for an old candidate, dropping it");
+                                        stats.found(Solution::Stale);
+                                    } else {
                                         // poke main kernel with mined block and start a new attempt
                                         info!("Found block!");
-                                        let [hash, poke] = tail.uncell().expect("Expected two elements in tail");
-                                        let mut poke_slab = NounSlab::new();
-                                        poke_slab.copy_into(poke);
-                                        handle.poke(MiningWire::Mined.to_wire(), poke_slab).await.expect("Could not poke nockchain with mined PoW");
-
-                                        // launch new attempt
-                                        let mut nonce_slab = NounSlab::new();
-                                        nonce_slab.copy_into(hash);
-                                        start_mining_attempt(serf, mining_data.lock().await, &mut mining_attempts, Some(nonce_slab)).await;
-                                    } else {
-                                        // failure
-                                        //  launch new attempt, using hash as new nonce
-                                        //  nonce is tail
-                                        let mut nonce_slab = NounSlab::new();
-                                        nonce_slab.copy_into(tail);
-                                        start_mining_attempt(serf, mining_data.lock().await, &mut mining_attempts, Some(nonce_slab)).await;
+                                        let tip = heaviest(&handle).await;
+                                        handle.poke(MiningWire::Mined.to_wire(), poke).await.expect("Could not poke nockchain with mined PoW");
+                                        if heaviest(&handle).await != tip {
+                                            stats.found(Solution::Accepted);
+                                        } else {
+                                            warn!("The kernel did not take the block found");
+                                            stats.found(Solution::Rejected);
+                                        }
                                     }
                                 }
                             }
+                            Some(Err(CrownError::Interrupted)) => {
+                                debug!("Mining thread {worker} moved on to the new candidate");
+                                stats.interrupted();
+                            }
+                            Some(Err(e)) => {
+                                warn!("Mining thread {worker} failed an attempt: {e}");
+                                stats.failed();
+                            }
+                            None => stats.failed(),
                         }
+                        workers.start(worker, serf, data);
+                    }
 
                     effect_res = handle.next_effect() => {
                         let Ok(effect) = effect_res else {
@@ -216,30 +272,23 @@ pub fn create_mining_driver(
                                         .expect("Expected pow-len to be a u64");
                                 (version_slab, header_slab, target_slab, pow_len)
                             };
-                            *(mining_data.lock().await) = Some(MiningData {
+                            let data = mining_data.insert(MiningData {
                                 block_header: header_slab,
                                 version: version_slab,
                                 target: target_slab,
                                 pow_len: pow_len
                             });
 
-                            // Mining hasn't started yet, so start it
-                            if mining_attempts.is_empty() {
-                                for _ in 0..num_threads {
-                                    let kernel = Vec::from(KERNEL);
-                                    let serf = SerfThread::<SaveableCheckpoint>::new(
-                                        kernel,
-                                        None,
-                                        hot_state.clone(),
-                                        NOCK_STACK_SIZE_TINY,
-                                        test_jets.clone(),
-                                        false,
-                                    )
-                                    .await
-                                    .expect("Could not load mining kernel");
-
-                                    start_mining_attempt(serf, mining_data.lock().await, &mut mining_attempts, None).await;
+                            if workers.attempts.is_empty() {
+                                // Mining hasn't started yet, so start it
+                                for worker in 0..num_threads {
+                                    let serf = load_miner(&hot_state, &test_jets, &placement, worker).await;
+                                    workers.start(worker, serf, data);
                                 }
+                            } else {
+                                // The candidate changed, so a proof of the old one would be
+                                // turned away
+                                workers.interrupt();
                             }
                         }
                     }
@@ -249,7 +298,323 @@ pub fn create_mining_driver(
     })
 }
 
-fn create_poke(mining_data: &MiningData, nonce: &NounSlab) -> NounSlab {
+/// The thread, its kernel, the result and how long it took
+type Attempt = (
+    usize,
+    SerfThread<SaveableCheckpoint>,
+    Result<NounSlab, CrownError>,
+    Duration,
+);
+
+/// The mining threads, each a miner kernel trying the nonces of its own [`NonceRange`]
+struct Workers {
+    attempts: JoinSet<Attempt>,
+    /// Which thread each attempt is on
+    ids: HashMap<Id, usize>,
+    /// Interrupt each thread's kernel
+    cancel_tokens: Vec<Option<NockCancelToken>>,
+    nonces: Vec<NonceRange>,
+}
+
+impl Workers {
+    fn new(threads: usize) -> Self {
+        Self {
+            attempts: JoinSet::new(),
+            ids: HashMap::new(),
+            cancel_tokens: vec![None; threads],
+            nonces: NonceRange::split(threads),
+        }
+    }
+
+    /// Have thread `worker` try its next nonce on `mining_data`
+    fn start(
+        &mut self,
+        worker: usize,
+        serf: SerfThread<SaveableCheckpoint>,
+        mining_data: &MiningData,
+    ) {
+        let poke_slab = create_poke(mining_data, &self.nonces[worker].next());
+        self.cancel_tokens[worker] = Some(serf.cancel_token.clone());
+        let task = self.attempts.spawn(async move {
+            let start = Instant::now();
+            let result = serf.poke(MiningWire::Candidate.to_wire(), poke_slab).await;
+            (worker, serf, result, start.elapsed())
+        });
+        self.ids.insert(task.id(), worker);
+    }
+
+    /// Stop every attempt, so each thread starts again on the latest candidate
+    fn interrupt(&self) {
+        for token in self.cancel_tokens.iter().flatten() {
+            token.cancel();
+        }
+    }
+}
+
+/// The nonces one thread tries, `[thread salt salt salt n]` for `n` counting up, so no two threads
+/// try the same one, nor do two nodes mining to the same keys, which pick different salts
+pub(crate) struct NonceRange {
+    thread: u64,
+    salt: [u64; 3],
+    next: u64,
+}
+
+impl NonceRange {
+    /// A range for each of `threads`
+    pub(crate) fn split(threads: usize) -> Vec<Self> {
+        let salt = rand::thread_rng()
+            .gen::<[u64; 3]>()
+            .map(|belt| belt % PRIME);
+        (0..threads as u64)
+            .map(|thread| NonceRange {
+                thread,
+                salt,
+                next: 0,
+            })
+            .collect()
+    }
+
+    pub(crate) fn next(&mut self) -> NounSlab {
+        let mut slab = NounSlab::new();
+        let n = self.next;
+        self.next += 1;
+        let belts = [self.thread, self.salt[0], self.salt[1], self.salt[2], n].map(|belt| {
+            Atom::from_value(&mut slab, belt)
+                .expect("Failed to create nonce atom")
+                .as_noun()
+        });
+        let nonce = T(&mut slab, &belts);
+        slab.set_root(nonce);
+        slab
+    }
+}
+
+/// Boot a miner kernel for thread `worker`, placed on its core
+pub(crate) async fn load_miner(
+    hot_state: &[HotEntry],
+    test_jets: &[NounSlab],
+    placement: &Placement,
+    worker: usize,
+) -> SerfThread<SaveableCheckpoint> {
+    let serf = SerfThread::<SaveableCheckpoint>::new(
+        Vec::from(KERNEL),
+        None,
+        hot_state.to_vec(),
+        NOCK_STACK_SIZE_TINY,
+        test_jets.to_vec(),
+        false,
+    )
+    .await
+    .expect("Could not load mining kernel");
+    if let Some(thread) = serf.thread() {
+        placement.place_worker(thread, worker);
+    }
+    serf
+}
+
+/// Whether the `[%command %pow prf dig header nonce]` poke of a block found is for the latest
+/// candidate, rather than one it replaced before the proof finished
+fn mined_on(poke: &NounSlab, mining_data: &MiningData) -> bool {
+    let Ok(header) = unsafe { poke.root() }.slot(62) else {
+        return false;
+    };
+    let mut slab = NounSlab::new();
+    let header = slab.copy_into(header);
+    slab.set_root(header);
+    slab.jam() == mining_data.block_header.jam()
+}
+
+/// The id of the heaviest block, to tell whether a block found joined the chain
+async fn heaviest(handle: &NockAppHandle) -> Option<Value> {
+    tip_block(handle)
+        .await
+        .ok()
+        .flatten()
+        .map(|tip| tip["id"].clone())
+}
+
+/// The `[%command %pow ...]` poke for the main kernel if a mining result found a block
+fn mined(result: &NounSlab) -> Option<NounSlab> {
+    //  there should only be one effect
+    let effects = unsafe { result.root() };
+    let effect = effects
+        .as_cell()
+        .expect("Expected result to be a cell")
+        .head();
+    let [head, res, tail] = effect
+        .uncell()
+        .expect("Expected three elements in mining result");
+    if !head.eq_bytes("mine-result") || !unsafe { res.raw_equals(&D(0)) } {
+        return None;
+    }
+    let [_hash, poke] = tail.uncell().expect("Expected two elements in tail");
+    let mut poke_slab = NounSlab::new();
+    poke_slab.copy_into(poke);
+    Some(poke_slab)
+}
+
+/// What became of a block the miner found
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Solution {
+    /// It joined the heaviest chain
+    Accepted,
+    /// The candidate changed while it was being proven
+    Stale,
+    /// The kernel turned it away
+    Rejected,
+}
+
+impl Solution {
+    fn label(self) -> &'static str {
+        match self {
+            Solution::Accepted => "accepted",
+            Solution::Stale => "stale",
+            Solution::Rejected => "rejected",
+        }
+    }
+}
+
+/// What the miner has done since it started, for `mining_getStats` and `/metrics`. A proof
+/// attempt tries one nonce, so the hashrate is attempts a second, over the last
+/// [`HASHRATE_WINDOW`].
+#[derive(Debug, Default)]
+pub struct MiningStats(std::sync::Mutex<Stats>);
+
+#[derive(Debug, Default)]
+struct Stats {
+    started: Option<Instant>,
+    threads: u64,
+    attempts: u64,
+    interrupted: u64,
+    failed: u64,
+    accepted: u64,
+    stale: u64,
+    rejected: u64,
+    proof_time: Duration,
+    /// When each attempt within the window finished
+    recent: VecDeque<Instant>,
+}
+
+impl MiningStats {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Stats> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn started(&self, threads: u64, now: Instant) {
+        let mut stats = self.lock();
+        stats.started = Some(now);
+        stats.threads = threads;
+    }
+
+    /// A thread finished proving one nonce, which took `took`
+    fn attempted(&self, took: Duration, now: Instant) {
+        let hashrate = {
+            let mut stats = self.lock();
+            stats.attempts += 1;
+            stats.proof_time += took;
+            stats.recent.push_back(now);
+            stats.hashrate(now)
+        };
+        prometheus::inc_counter(
+            "nockchain_mining_attempts_total",
+            "Nonces the miner has proven",
+            &[],
+            1.0,
+        );
+        prometheus::observe(
+            "nockchain_mining_proof_duration_seconds",
+            "Time one mining thread took to prove a nonce",
+            &[],
+            took.as_secs_f64(),
+        );
+        prometheus::set_gauge(
+            "nockchain_mining_hashrate",
+            "Nonces proven a second across all mining threads",
+            &[],
+            hashrate,
+        );
+    }
+
+    fn interrupted(&self) {
+        self.lock().interrupted += 1;
+        prometheus::inc_counter(
+            "nockchain_mining_interrupted_total",
+            "Mining attempts dropped because the candidate block changed",
+            &[],
+            1.0,
+        );
+    }
+
+    fn failed(&self) {
+        self.lock().failed += 1;
+    }
+
+    fn found(&self, solution: Solution) {
+        {
+            let mut stats = self.lock();
+            match solution {
+                Solution::Accepted => stats.accepted += 1,
+                Solution::Stale => stats.stale += 1,
+                Solution::Rejected => stats.rejected += 1,
+            }
+        }
+        prometheus::inc_counter(
+            "nockchain_mining_solutions_total",
+            "Blocks the miner found, by what became of them",
+            &[("result", solution.label())],
+            1.0,
+        );
+    }
+
+    /// `{threads, uptimeSecs, attempts, interrupted, failed, accepted, stale, rejected,
+    /// averageProofSecs, hashrate}`, or `null` if the miner hasn't started
+    pub fn to_json(&self, now: Instant) -> Value {
+        let mut stats = self.lock();
+        let Some(started) = stats.started else {
+            return Value::Null;
+        };
+        let average = match stats.attempts {
+            0 => 0.0,
+            n => stats.proof_time.as_secs_f64() / n as f64,
+        };
+        json!({
+            "threads": stats.threads,
+            "uptimeSecs": now.duration_since(started).as_secs(),
+            "attempts": stats.attempts,
+            "interrupted": stats.interrupted,
+            "failed": stats.failed,
+            "accepted": stats.accepted,
+            "stale": stats.stale,
+            "rejected": stats.rejected,
+            "averageProofSecs": average,
+            "hashrate": stats.hashrate(now),
+        })
+    }
+}
+
+impl Stats {
+    /// Attempts a second over the last [`HASHRATE_WINDOW`], or since the miner started if that's
+    /// more recent
+    fn hashrate(&mut self, now: Instant) -> f64 {
+        while self
+            .recent
+            .front()
+            .is_some_and(|at| now.duration_since(*at) > HASHRATE_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+        let window = self
+            .started
+            .map_or(HASHRATE_WINDOW, |started| now.duration_since(started))
+            .min(HASHRATE_WINDOW);
+        if window.is_zero() {
+            return 0.0;
+        }
+        self.recent.len() as f64 / window.as_secs_f64()
+    }
+}
+
+pub(crate) fn create_poke(mining_data: &MiningData, nonce: &NounSlab) -> NounSlab {
     let mut slab = NounSlab::new();
     let header = slab.copy_into(unsafe { *(mining_data.block_header.root()) });
     let version = slab.copy_into(unsafe { *(mining_data.version.root()) });
@@ -288,9 +653,14 @@ async fn set_mining_key_advanced(
     handle: &NockAppHandle,
     configs: Vec<MiningKeyConfig>,
 ) -> Result<PokeResult, NockAppError> {
-    let mut set_mining_key_slab = NounSlab::new();
-    let set_mining_key_adv = Atom::from_value(&mut set_mining_key_slab, "set-mining-key-advanced")
-        .expect("Failed to create set-mining-key-advanced atom");
+    let poke = mining_keys_poke("set-mining-key-advanced", configs);
+    handle.poke(MiningWire::SetPubKey.to_wire(), poke).await
+}
+
+/// `[%command tag configs]`, for `%set-mining-key-advanced` and `%rotate-mining-key`
+pub(crate) fn mining_keys_poke(tag: &str, configs: Vec<MiningKeyConfig>) -> NounSlab {
+    let mut slab = NounSlab::new();
+    let tag = Atom::from_value(&mut slab, tag).expect("Failed to create tag atom");
 
     // Create the list of configs
     let mut configs_list = D(0);
@@ -298,29 +668,22 @@ async fn set_mining_key_advanced(
         // Create the list of keys
         let mut keys_noun = D(0);
         for key in config.keys {
-            let key_atom =
-                Atom::from_value(&mut set_mining_key_slab, key).expect("Failed to create key atom");
-            keys_noun = T(&mut set_mining_key_slab, &[key_atom.as_noun(), keys_noun]);
+            let key_atom = Atom::from_value(&mut slab, key).expect("Failed to create key atom");
+            keys_noun = T(&mut slab, &[key_atom.as_noun(), keys_noun]);
         }
 
         // Create the config tuple [share m keys]
-        let config_tuple = T(
-            &mut set_mining_key_slab,
-            &[D(config.share), D(config.m), keys_noun],
-        );
+        let config_tuple = T(&mut slab, &[D(config.share), D(config.m), keys_noun]);
 
-        configs_list = T(&mut set_mining_key_slab, &[config_tuple, configs_list]);
+        configs_list = T(&mut slab, &[config_tuple, configs_list]);
     }
 
-    let set_mining_key_poke = T(
-        &mut set_mining_key_slab,
-        &[D(tas!(b"command")), set_mining_key_adv.as_noun(), configs_list],
+    let poke = T(
+        &mut slab,
+        &[D(tas!(b"command")), tag.as_noun(), configs_list],
     );
-    set_mining_key_slab.set_root(set_mining_key_poke);
-
-    handle
-        .poke(MiningWire::SetPubKey.to_wire(), set_mining_key_slab)
-        .await
+    slab.set_root(poke);
+    slab
 }
 
 //TODO add %set-mining-key-multisig poke
@@ -339,36 +702,53 @@ async fn enable_mining(handle: &NockAppHandle, enable: bool) -> Result<PokeResul
         .await
 }
 
-async fn start_mining_attempt(
-    serf: SerfThread<SaveableCheckpoint>,
-    mining_data: tokio::sync::MutexGuard<'_, Option<MiningData>>,
-    mining_attempts: &mut tokio::task::JoinSet<(
-        SerfThread<SaveableCheckpoint>,
-        Result<NounSlab, CrownError>,
-    )>,
-    nonce: Option<NounSlab>,
-) {
-    let nonce = nonce.unwrap_or_else(|| {
-        let mut rng = rand::thread_rng();
-        let mut nonce_slab = NounSlab::new();
-        let mut nonce_cell = Atom::from_value(&mut nonce_slab, rng.gen::<u64>() % PRIME)
-            .expect("Failed to create nonce atom")
-            .as_noun();
-        for _ in 1..5 {
-            let nonce_atom = Atom::from_value(&mut nonce_slab, rng.gen::<u64>() % PRIME)
-                .expect("Failed to create nonce atom")
-                .as_noun();
-            nonce_cell = T(&mut nonce_slab, &[nonce_atom, nonce_cell]);
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_hashrate() {
+        let stats = MiningStats::default();
+        let start = Instant::now();
+        assert_eq!(stats.to_json(start), Value::Null);
+        stats.started(2, start);
+        for second in 1..=10 {
+            stats.attempted(Duration::from_secs(2), start + Duration::from_secs(second));
         }
-        nonce_slab.set_root(nonce_cell);
-        nonce_slab
-    });
-    let mining_data_ref = mining_data
-        .as_ref()
-        .expect("Mining data should already be initialized");
-    let poke_slab = create_poke(mining_data_ref, &nonce);
-    mining_attempts.spawn(async move {
-        let result = serf.poke(MiningWire::Candidate.to_wire(), poke_slab).await;
-        (serf, result)
-    });
+        let now = start + Duration::from_secs(10);
+        let json = stats.to_json(now);
+        assert_eq!(json["attempts"], json!(10));
+        assert_eq!(json["averageProofSecs"], json!(2.0));
+        assert_eq!(json["hashrate"], json!(1.0));
+
+        // only the window counts
+        let later = now + HASHRATE_WINDOW + Duration::from_secs(1);
+        assert_eq!(stats.to_json(later)["hashrate"], json!(0.0));
+    }
+
+    #[test]
+    fn test_split() {
+        let config = |s: &str| MiningKeyConfig::from_str(s).expect("config");
+        let halves = [config("50%,1:a"), config("50%,2:b,c")];
+        assert!(halves[0].percent);
+        assert_eq!(halves[1].keys, vec!["b", "c"]);
+        assert!(check_split(&halves).is_ok());
+        assert!(check_split(&[config("3,1:a"), config("1,1:b")]).is_ok());
+        assert!(check_split(&[config("60%,1:a"), config("30%,1:b")]).is_err());
+        assert!(check_split(&[config("60%,1:a"), config("40,1:b")]).is_err());
+        assert!(MiningKeyConfig::from_str("0,1:a").is_err());
+        assert!(MiningKeyConfig::from_str("1,2:a").is_err());
+    }
+
+    #[test]
+    fn test_nonce_ranges_are_disjoint() {
+        let mut seen = HashSet::new();
+        for range in NonceRange::split(4).iter_mut() {
+            for _ in 0..100 {
+                assert!(seen.insert(range.next().jam()));
+            }
+        }
+    }
 }
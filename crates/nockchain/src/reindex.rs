@@ -0,0 +1,258 @@
+//! Rebuilding the chain state and indices from the blocks stored locally.
+//!
+//! `nockchain reindex` reads every block of the heaviest chain and its transactions out of the
+//! node's checkpoint, boots a fresh kernel, and hears them again in order, so each block is
+//! validated as if it had just arrived from a peer, and the heaviest block has to come out the
+//! same. With `--address-index`, the index is emptied and rebuilt from genesis once the chain is
+//! back. Nothing comes from the network, and the node exits when it's done.
+//!
+//! The chain is written to `reindex.jam` in the data directory before the old state is replaced,
+//! and removed when the reindex succeeds. If it's interrupted, running it again starts over from
+//! that file. Only the heaviest chain is replayed, so side chains are forgotten, and a node that
+//! pruned or fast synced has blocks or transactions missing and can't be reindexed.
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use nockapp::driver::{make_driver, IODriverFn, NockAppHandle};
+use nockapp::noun::slab::{Jammer, NounSlab};
+use nockapp::utils::make_tas;
+use nockapp::wire::{SystemWire, Wire};
+use nockapp::{Bytes, NockApp, NockAppError, NounExt};
+use nockvm::noun::{Noun, D, T};
+use nockvm_macros::tas;
+use thiserror::Error;
+use tokio::sync::oneshot;
+use tracing::{error, info};
+
+use crate::explorer::{block_at_height, tip_block};
+use crate::indexer::{self, AddressIndex, IndexError};
+use crate::rpc::{block_json, RpcError};
+
+/// How often to log progress, in blocks
+const PROGRESS_INTERVAL: u64 = 1000;
+
+#[derive(Debug, Error)]
+pub enum ReindexError {
+    #[error("Could not read the stored chain: {0}")]
+    Kernel(#[from] NockAppError),
+    #[error("Could not read the chain: {0}")]
+    Rpc(#[from] RpcError),
+    #[error("Could not rebuild the address index: {0}")]
+    Index(#[from] IndexError),
+    #[error("Could not use {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("The stored chain has no block at height {0}, so it was pruned or fast synced")]
+    MissingBlock(u64),
+    #[error("The stored chain is missing transaction {1} of block {0}")]
+    MissingTransaction(String, String),
+    #[error("{0} is not a stored chain")]
+    Malformed(PathBuf),
+    #[error("Block {1} at height {0} was not accepted")]
+    Rejected(u64, String),
+    #[error("The heaviest block is {got}, but was {expected}")]
+    Mismatch { expected: String, got: String },
+}
+
+/// Where the chain is kept while it's replayed
+pub fn chain_path() -> PathBuf {
+    nockapp::default_data_dir("nockchain").join("reindex.jam")
+}
+
+/// The chain saved by an interrupted reindex, if there is one
+pub fn load_chain() -> Result<Option<NounSlab>, ReindexError> {
+    let path = chain_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+    let jam = fs::read(&path).map_err(|e| ReindexError::Io(path.clone(), e))?;
+    let mut chain = NounSlab::new();
+    let root = chain
+        .cue_into(Bytes::from(jam))
+        .map_err(|_| ReindexError::Malformed(path.clone()))?;
+    chain.set_root(root);
+    info!("Resuming the reindex from {}", path.display());
+    Ok(Some(chain))
+}
+
+/// Read the heaviest chain out of `nockapp`, as a list of `[page txs=(list raw-tx)]` from
+/// genesis up, and save it to [`chain_path`]
+pub async fn export_chain<J: Jammer + Send + 'static>(
+    nockapp: &mut NockApp<J>,
+) -> Result<NounSlab, ReindexError> {
+    let mut chain = NounSlab::new();
+    let mut blocks = Vec::new();
+    for_each_block(nockapp, |_, block| {
+        blocks.push(chain.copy_into(unsafe { *block.root() }));
+        Ok(())
+    })
+    .await?;
+    let root = blocks
+        .into_iter()
+        .rev()
+        .fold(D(0), |tail, block| T(&mut chain, &[block, tail]));
+    chain.set_root(root);
+
+    let path = chain_path();
+    fs::write(&path, chain.jam()).map_err(|e| ReindexError::Io(path.clone(), e))?;
+    Ok(chain)
+}
+
+/// Read each block of the heaviest chain out of `nockapp` from genesis up, passing it to `each`
+/// with its height as `[page txs=(list raw-tx)]`, and return how many there were
+pub async fn for_each_block<J: Jammer + Send + 'static>(
+    nockapp: &mut NockApp<J>,
+    mut each: impl FnMut(u64, NounSlab) -> Result<(), ReindexError>,
+) -> Result<u64, ReindexError> {
+    let mut height = 0;
+    loop {
+        let Some(page) = peek(nockapp, |slab| {
+            T(slab, &[D(tas!(b"heavy-n")), D(height), D(0)])
+        })
+        .await?
+        else {
+            if height == 0 {
+                return Err(ReindexError::MissingBlock(0));
+            }
+            break;
+        };
+        let header = block_json(unsafe { *page.root() })?;
+        let id = header["id"].as_str().unwrap_or_default().to_string();
+        let mut block = NounSlab::new();
+        let mut txs = D(0);
+        for tx_id in header["txIds"].as_array().into_iter().flatten().rev() {
+            let tx_id = tx_id.as_str().unwrap_or_default();
+            let Some(raw_tx) = peek(nockapp, |slab| {
+                let tag = make_tas(slab, "raw-transaction").as_noun();
+                let tx_id = make_tas(slab, tx_id).as_noun();
+                T(slab, &[tag, tx_id, D(0)])
+            })
+            .await?
+            else {
+                return Err(ReindexError::MissingTransaction(id, tx_id.to_string()));
+            };
+            let raw_tx = block.copy_into(unsafe { *raw_tx.root() });
+            txs = T(&mut block, &[raw_tx, txs]);
+        }
+        let page = block.copy_into(unsafe { *page.root() });
+        let root = T(&mut block, &[page, txs]);
+        block.set_root(root);
+        each(height, block)?;
+        height += 1;
+        if height % PROGRESS_INTERVAL == 0 {
+            info!("Read {} blocks of the stored chain", height);
+        }
+    }
+    info!("Read {} blocks of the stored chain", height);
+    Ok(height)
+}
+
+async fn peek<J: Jammer + Send + 'static>(
+    nockapp: &mut NockApp<J>,
+    build: impl FnOnce(&mut NounSlab) -> Noun,
+) -> Result<Option<NounSlab>, NockAppError> {
+    let mut path = NounSlab::new();
+    let root = build(&mut path);
+    path.set_root(root);
+    nockapp.peek_handle(path).await
+}
+
+/// Once `born_rx` fires, hear each block of `chain` and then its transactions, check that the
+/// heaviest block comes out the same, rebuild `index` from scratch, and exit
+pub fn make_reindex_driver(
+    chain: NounSlab,
+    born_rx: oneshot::Receiver<()>,
+    index: Option<Arc<AddressIndex>>,
+) -> IODriverFn {
+    make_driver(move |handle| async move {
+        let _ = born_rx.await;
+        match replay(&handle, &chain, index.as_deref()).await {
+            Ok(()) => {
+                let path = chain_path();
+                if let Err(e) = fs::remove_file(&path) {
+                    error!("Could not remove {}: {}", path.display(), e);
+                }
+                info!("Reindex complete");
+                handle.exit.exit(0).await
+            }
+            Err(e) => {
+                error!("Reindex failed: {}", e);
+                handle.exit.exit(1).await
+            }
+        }
+    })
+}
+
+async fn replay(
+    handle: &NockAppHandle,
+    chain: &NounSlab,
+    index: Option<&AddressIndex>,
+) -> Result<(), ReindexError> {
+    let mut expected = None;
+    for (height, block) in unsafe { *chain.root() }.list_iter().enumerate() {
+        let height = height as u64;
+        let block = block
+            .as_cell()
+            .map_err(|_| ReindexError::Malformed(chain_path()))?;
+        let id = replay_block(handle, height, block.head(), block.tail()).await?;
+        if (height + 1) % PROGRESS_INTERVAL == 0 {
+            info!("Revalidated {} blocks", height + 1);
+        }
+        expected = Some(id);
+    }
+
+    let got = tip_block(handle)
+        .await?
+        .and_then(|tip| tip["id"].as_str().map(str::to_string))
+        .unwrap_or_default();
+    let expected = expected.unwrap_or_default();
+    if got != expected {
+        return Err(ReindexError::Mismatch { expected, got });
+    }
+    info!("Revalidated the chain up to {}", got);
+
+    if let Some(index) = index {
+        index.clear()?;
+        indexer::sync(handle, index).await?;
+        info!("Rebuilt the address index");
+    }
+    Ok(())
+}
+
+/// Hear `page` and then its transactions `txs`, and return its id if it's then the block at
+/// `height` of the heaviest chain
+pub(crate) async fn replay_block(
+    handle: &NockAppHandle,
+    height: u64,
+    page: Noun,
+    txs: Noun,
+) -> Result<String, ReindexError> {
+    let id = block_json(page)?["id"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    hear(handle, "heard-block", page).await?;
+    for raw_tx in txs.list_iter() {
+        hear(handle, "heard-tx", raw_tx).await?;
+    }
+    let accepted = block_at_height(handle, height).await?;
+    if accepted.as_ref().and_then(|block| block["id"].as_str()) != Some(id.as_str()) {
+        return Err(ReindexError::Rejected(height, id));
+    }
+    Ok(id)
+}
+
+/// Poke `[%fact %0 tag noun]`
+pub(crate) async fn hear(
+    handle: &NockAppHandle,
+    tag: &str,
+    noun: Noun,
+) -> Result<(), NockAppError> {
+    let mut poke = NounSlab::new();
+    let noun = poke.copy_into(noun);
+    let tag = make_tas(&mut poke, tag).as_noun();
+    let root = T(&mut poke, &[D(tas!(b"fact")), D(0), tag, noun]);
+    poke.set_root(root);
+    handle.poke(SystemWire.to_wire(), poke).await?;
+    Ok(())
+}
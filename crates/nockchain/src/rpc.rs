@@ -0,0 +1,1600 @@
+//! JSON-RPC 2.0 server for querying and controlling the node.
+//!
+//! Calls are POSTed to `/`, singly or in batches, or sent as text frames over a WebSocket opened
+//! at `/ws`. Params are positional. Block and transaction ids are base58 strings, and nouns with
+//! no obvious JSON shape, like raw transactions and balances, are handed over as hex-encoded jams.
+//!
+//! ## Methods
+//!
+//! Each method is listed with its params, if it takes any, and what it answers with.
+//!
+//! - `chain_getHeaviestBlock`: block, or `null` before genesis
+//! - `chain_getBlock [id]`: block or `null`
+//! - `chain_getBlockByHeight [height]`: block on the heaviest chain or `null`
+//! - `chain_getTransaction [id]`: `{id, jam}` of the raw tx or `null`
+//! - `chain_getBalance [blockId]`: `{notes, jam}` of the balance or `null`
+//! - `chain_getStateAt [height]`: `{height, blockId, notes, jam}` or `null`
+//! - `chain_getHeaders [from, count?]`: `[{id, parent, height, jam}]` of headers
+//! - `chain_getTxProof [blockId, txId]`: `{blockId, txId, jam}` of the proof or `null`
+//! - `mempool_size`: number of raw txs waiting for a block
+//! - `mempool_getTransactions`: `[{id, fee, size, heardAt, age, inCandidate}]`
+//! - `mempool_submitTransaction [jam]`: `{id, accepted}`
+//! - `mempool_evict [[id]]`: number evicted
+//! - `mempool_flush`: number evicted
+//! - `mempool_setSizeLimit [bytes or null]`: number evicted to fit
+//! - `mempool_getSizeLimit`: the cap in bytes or `null`
+//! - `mempool_estimateFee [target?]`: `{target, fee, size, perKilobyte}`
+//! - `mining_setEnabled [bool]`: whether the kernel accepted it
+//! - `mining_setKey [pubkey or [config]]`: whether the kernel accepted it
+//! - `mining_getStats`: the miner's statistics or `null`
+//! - `mining_getBlockTemplate`: the candidate block or `null`
+//! - `mining_submitBlock [jam]`: `{accepted, id, stale}`
+//! - `regtest_generate [count?]`: ids of the blocks mined, lowest first
+//! - `node_peers`: `[{peerId, addresses, score}]`
+//! - `node_dialPeer [multiaddr]`: `true`
+//! - `node_blockPeer [peerId, seconds?]`: `true`
+//! - `node_unblockPeer [peerId]`: whether the peer was blocked
+//! - `node_blockedPeers`: `[{peerId, reason, until}]`
+//! - `node_getSnapshotHash [height]`: `{height, hash}` or `null`
+//! - `node_getLogFilter`: the log's filter directives
+//! - `node_setLogFilter [directives]`: `true`
+//! - `node_status`: `{tip, peers, mempool, mining, checkpointAge, uptime}`
+//! - `index_getAddressTransactions [pubkey, from?, limit?]`: `[{height, blockId, txId}]`
+//! - `memo_send [pubkey, jam]`: whether the memo was new
+//! - `memo_getInbox [pubkey]`: jams of the memos held for the key
+//!
+//! A block is `{id, parent, height, txIds}`. Mempool sizes are bytes of jam, ages are blocks
+//! since the transaction was heard, and which transactions can be evicted is explained in
+//! [`crate::mempool`], as is `mempool_estimateFee`, whose `size` is in bits, not bytes, and whose
+//! `target` defaults to the next block. `index_getAddressTransactions` needs the address
+//! index of [`crate::indexer`], and lists what touched a public key from height `from`, lowest
+//! first, with a `null` `txId` for a coinbase. `chain_getStateAt` answers with the balance at a
+//! height on the heaviest chain; a pruned node only keeps balances every `--snapshot-interval`
+//! heights, so it answers with the nearest one below, and says which height that is.
+//! `chain_getHeaders` lists the headers of the heaviest chain from height `from`, lowest first, at
+//! most `count` and [`MAX_HEADERS`], and `chain_getTxProof` shows that a block includes a
+//! transaction, both for light clients, as [`crate::light`] explains.
+//! `mining_setEnabled` only pauses and resumes a node started with `--mine`, since a node without
+//! mining keys has no miner to resume. `mining_setKey` changes who the node's blocks pay, starting
+//! with the block being mined: a pubkey gets all of the coinbase, or each config, as in
+//! `--mining-key-adv`, is `"share,m:key1,key2"`, as many as the network's `max-coinbase-split`.
+//! A key that doesn't parse is refused and the old ones stay. The new keys last until the node
+//! restarts, when the command line sets them again, so change those as well. NPC clients can poke
+//! the same `[%command %enable-mining ?]` and `[%command %rotate-mining-key configs]` directly.
+//! `mining_getStats` reports what the node's own miner has done since it started, as
+//! [`MiningStats::to_json`] describes, and is `null` on a node that doesn't mine.
+//! `mining_getBlockTemplate` hands a miner outside the node the block it would mine next:
+//! `{version, parent, height, timestamp, txIds, coinbase, target, powLen, commitment, jam}`, with
+//! the coinbase as `[{owners: {m, pubkeys}, amount}]`, the target as a decimal string, and `jam`
+//! the `[version commitment target pow-len]` the miner kernel proves with, given a nonce. It's
+//! `null` on a node without `--mining-pubkey`, which has nobody to pay. `mining_submitBlock`
+//! takes the jam of the `[%pow proof digest commitment nonce]` found, and a block that joins the
+//! heaviest chain is `accepted` with its `id`. The template changes whenever a block or
+//! transaction comes in, and a solution for an old one is `stale`. A peer's score and why it might
+//! be blocked are explained in [`nockchain_libp2p_io::reputation`]; `node_blockPeer` blocks for
+//! good without `seconds`, and `until` is in Unix seconds, or `null` for a block that doesn't end.
+//! `regtest_generate` only works on a `--regtest` node, turning mining on until `count` more
+//! blocks (1 by default, at most [`MAX_GENERATE`]) join the heaviest chain and off again; the
+//! miner can find one more before it stops.
+//! `node_getSnapshotHash` hashes the snapshot that `GET /snapshot/{height}` serves, for new
+//! nodes to fast sync from, as [`crate::snapshot`] explains. Both need `--serve-snapshots`.
+//! `node_setLogFilter` takes `RUST_LOG` directives, like `info,nockapp::drivers::file=debug`,
+//! and filters the log by them until the node restarts or they're set again. Exported spans keep
+//! the filter the node started with.
+//! `node_status` gathers what `nockchain status` prints: the heaviest block as
+//! `{id, height, timestamp, age}` or `null`, the peer count or `null` without a libp2p driver,
+//! the mempool as `{count, bytes}`, `mining_getStats`, and the seconds since the newest checkpoint
+//! was written (`null` before the first) and since the node started.
+//! `memo_send` and `memo_getInbox` only work on a `--relay-memos` node, which passes the memos
+//! wallets send on to its peers and holds them for the wallets they're for, as
+//! [`nockchain_libp2p_io::memo`] explains.
+//!
+//! ## Admin calls
+//!
+//! The calls in [`ADMIN_METHODS`] change what the node does rather than read it, so anyone who
+//! can reach the port mustn't be able to make them. Each time it starts, the node writes a new
+//! random token to `rpc.cookie` in its data directory, readable only by the user it runs as, and
+//! refuses those calls unless the request, or the WebSocket upgrade, carries it as
+//! `Authorization: Bearer <token>`.
+//!
+//! ## Subscriptions
+//!
+//! Over the WebSocket, `chain_subscribe` with one of the topics below returns a subscription id,
+//! and every event on the topic then arrives as a `chain_subscription` notification with params
+//! `{subscription, result}`, until `chain_unsubscribe` with the id.
+//!
+//! - `newHeads`: each block that joins the heaviest chain, lowest first
+//! - `reorgs`: `{oldTip, newTip, height, commonAncestor, rolledBack, applied}` when blocks leave
+//!   the heaviest chain
+//! - `pendingTransactions`: `{id, jam}` of each raw tx the kernel accepts
+//!
+//! The same events are streamed over gRPC on a node started with `--grpc-addr`, as
+//! [`crate::grpc`] explains. Events come from the kernel's gossip effects, so a node only reports
+//! blocks and transactions it has validated. A reorg arrives before the `newHeads` of the blocks
+//! it applies. Its `commonAncestor` is the `{id, height}` of the highest block both chains share,
+//! `rolledBack` holds the blocks that left the heaviest chain, highest first, and `applied` the
+//! ones that replaced them, lowest first, ending with `newTip`. Only the latest [`REORG_WINDOW`]
+//! blocks are remembered, so a deeper reorg has a `null` `commonAncestor` and only the blocks
+//! remembered in `rolledBack`. A node that jumps ahead many blocks at once, like while catching
+//! up, reports at most that many of them.
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{serve, Router};
+use ibig::UBig;
+use libp2p::{Multiaddr, PeerId};
+use nockapp::driver::{make_driver, IODriverFn, NockAppHandle, PokeResult};
+use nockapp::noun::slab::NounSlab;
+use nockapp::observability::{self, LogFilterError};
+use nockapp::utils::scry::ScryResult;
+use nockapp::utils::{daemon, make_tas};
+use nockapp::wire::{Wire, WireRepr};
+use nockapp::{Bytes, NockAppError, NounExt};
+use nockchain_libp2p_io::memo::{Memo, MemoBox};
+use nockchain_libp2p_io::p2p::PeerCommand;
+use nockchain_libp2p_io::p2p_util::NockchainFact;
+use nockchain_libp2p_io::snapshot::Rate;
+use nockchain_libp2p_io::tip5_util::tip5_hash_to_base58;
+use nockvm::noun::{Noun, Slots, D, NO, T, YES};
+use nockvm_macros::tas;
+use serde_json::{json, Value};
+use thiserror::Error;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tracing::{debug, error, info, warn};
+
+use crate::explorer::{block_at_height, tip_block};
+use crate::indexer::{entry_json, AddressIndex, IndexError};
+use crate::mining::{check_split, mining_keys_poke, MiningKeyConfig, MiningStats};
+use crate::{health, mempool, snapshot};
+
+/// The most blocks `mempool_estimateFee` targets
+const MAX_FEE_TARGET: u64 = 1008;
+
+/// The most entries one `index_getAddressTransactions` call returns
+pub const MAX_INDEX_ENTRIES: u64 = 1000;
+
+/// The most headers one `chain_getHeaders` call returns
+pub const MAX_HEADERS: u64 = 500;
+
+/// The most blocks one `regtest_generate` call mines
+pub const MAX_GENERATE: u64 = 100;
+
+/// How long `regtest_generate` waits for each block before giving up
+pub const GENERATE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How many of the latest blocks on the heaviest chain subscriptions remember, the deepest reorg
+/// whose rolled-back blocks they can report
+pub const REORG_WINDOW: u64 = 256;
+
+/// The calls that need the token in the node's cookie file
+pub const ADMIN_METHODS: &[&str] = &[
+    "mempool_evict", "mempool_flush", "mempool_setSizeLimit", "mining_setEnabled", "mining_setKey",
+    "regtest_generate", "node_dialPeer", "node_blockPeer", "node_unblockPeer", "node_setLogFilter",
+];
+
+/// Where a node booted in `data_dir` writes the token for admin calls
+pub fn cookie_path(data_dir: &std::path::Path) -> PathBuf {
+    data_dir.join("rpc.cookie")
+}
+
+/// Write a new random token to `path`, readable only by this user, and return it
+pub fn write_cookie(path: &std::path::Path) -> std::io::Result<String> {
+    let token = to_hex(&rand::random::<[u8; 32]>());
+    // Replace the file rather than truncate it, so an old one's mode doesn't carry over
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
+pub enum RpcWire {
+    Submit,
+    Mining,
+    Mempool,
+}
+
+impl Wire for RpcWire {
+    const VERSION: u64 = 1;
+    const SOURCE: &'static str = "rpc";
+
+    fn to_wire(&self) -> WireRepr {
+        let tags = match self {
+            RpcWire::Submit => vec!["submit".into()],
+            RpcWire::Mining => vec!["mining".into()],
+            RpcWire::Mempool => vec!["mempool".into()],
+        };
+        WireRepr::new(RpcWire::SOURCE, RpcWire::VERSION, tags)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RpcError {
+    #[error("Parse error: {0}")]
+    Parse(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(&'static str),
+    #[error("Method not found: {0}")]
+    MethodNotFound(String),
+    #[error("Invalid params: {0}")]
+    InvalidParams(String),
+    #[error("Peer management is not available on this node")]
+    NoPeerControl,
+    #[error("Subscriptions are only available over the websocket")]
+    NoSubscriptions,
+    #[error("The address index is not enabled on this node")]
+    NoIndex,
+    #[error("Only available on a --regtest node")]
+    NotRegtest,
+    #[error("Only available on a --relay-memos node")]
+    NoMemos,
+    #[error("Only available on a --serve-snapshots node")]
+    NoSnapshots,
+    #[error("Too many snapshot requests, try again later")]
+    RateLimited,
+    #[error("{0} needs the token in the node's rpc.cookie")]
+    Unauthorized(String),
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl RpcError {
+    fn code(&self) -> i64 {
+        match self {
+            RpcError::Parse(_) => -32700,
+            RpcError::InvalidRequest(_) => -32600,
+            RpcError::MethodNotFound(_) => -32601,
+            RpcError::InvalidParams(_) => -32602,
+            RpcError::NoPeerControl
+            | RpcError::NoSubscriptions
+            | RpcError::NoIndex
+            | RpcError::NotRegtest
+            | RpcError::NoMemos
+            | RpcError::NoSnapshots
+            | RpcError::RateLimited
+            | RpcError::Unauthorized(_)
+            | RpcError::Internal(_) => -32603,
+        }
+    }
+
+    pub(crate) fn to_json(&self, id: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": self.code(), "message": self.to_string() },
+        })
+    }
+}
+
+impl From<NockAppError> for RpcError {
+    fn from(e: NockAppError) -> Self {
+        RpcError::Internal(e.to_string())
+    }
+}
+
+impl From<IndexError> for RpcError {
+    fn from(e: IndexError) -> Self {
+        match e {
+            IndexError::Kernel(e) => e,
+            e => RpcError::Internal(e.to_string()),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct RpcState {
+    handle: Arc<NockAppHandle>,
+    peers: Option<mpsc::Sender<PeerCommand>>,
+    index: Option<Arc<AddressIndex>>,
+    mining: Arc<MiningStats>,
+    events: ChainEvents,
+    /// Held while `regtest_generate` mines, on a regtest node
+    regtest: Option<Arc<tokio::sync::Mutex<()>>>,
+    /// Where the node writes its checkpoints, for `node_status`
+    checkpoints: Arc<Vec<PathBuf>>,
+    started: Instant,
+    /// The memos held for wallets, on a `--relay-memos` node
+    memos: Option<Arc<MemoBox>>,
+    /// Snapshots left to build this second, on a `--serve-snapshots` node
+    snapshots: Option<Arc<Mutex<Rate>>>,
+    /// What a request presents to make admin calls
+    token: Arc<String>,
+}
+
+/// What a subscription is for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Topic {
+    NewHeads,
+    Reorgs,
+    PendingTransactions,
+}
+
+impl FromStr for Topic {
+    type Err = RpcError;
+
+    fn from_str(topic: &str) -> Result<Self, Self::Err> {
+        match topic {
+            "newHeads" => Ok(Topic::NewHeads),
+            "reorgs" => Ok(Topic::Reorgs),
+            "pendingTransactions" => Ok(Topic::PendingTransactions),
+            _ => Err(RpcError::InvalidParams(format!("unknown topic {}", topic))),
+        }
+    }
+}
+
+/// Where [`make_events_driver`] publishes each event, with its topic, for websocket and gRPC
+/// subscribers
+pub type ChainEvents = broadcast::Sender<(Topic, Value)>;
+
+/// A new channel for [`make_events_driver`] to publish on
+pub fn chain_events() -> ChainEvents {
+    broadcast::channel(256).0
+}
+
+/// A websocket's subscriptions, by id
+#[derive(Default)]
+struct Subscriptions {
+    next_id: u64,
+    topics: HashMap<String, Topic>,
+}
+
+/// Serve JSON-RPC on `addr`, with the token for admin calls written to `cookie`. Peer
+/// management calls go to the libp2p driver over `peers`, and index calls read `index`, and each
+/// fail if theirs is `None`. `mining_getStats` reads `mining`, `regtest_generate` fails unless
+/// `regtest`, `node_status` dates the newest of `checkpoints`, and snapshots are only served if
+/// `serve_snapshots`. Websocket subscriptions get what [`make_events_driver`] publishes on
+/// `events`.
+pub fn make_rpc_driver(
+    addr: SocketAddr,
+    cookie: PathBuf,
+    events: ChainEvents,
+    peers: Option<mpsc::Sender<PeerCommand>>,
+    index: Option<Arc<AddressIndex>>,
+    mining: Arc<MiningStats>,
+    regtest: bool,
+    checkpoints: Vec<PathBuf>,
+    memos: Option<Arc<MemoBox>>,
+    serve_snapshots: bool,
+) -> IODriverFn {
+    make_driver(move |handle| async move {
+        let token = write_cookie(&cookie).map_err(NockAppError::IoError)?;
+        info!(
+            "Admin JSON-RPC calls need the token in {}",
+            cookie.display()
+        );
+        let state = RpcState {
+            handle: Arc::new(handle),
+            peers,
+            index,
+            mining,
+            events,
+            regtest: regtest.then(|| Arc::new(tokio::sync::Mutex::new(()))),
+            checkpoints: Arc::new(checkpoints),
+            started: Instant::now(),
+            memos,
+            snapshots: serve_snapshots.then(|| {
+                Arc::new(Mutex::new(Rate::new(
+                    snapshot::RPC_SNAPSHOTS_PER_SEC,
+                    Instant::now(),
+                )))
+            }),
+            token: Arc::new(token),
+        };
+        let app = Router::new()
+            .route("/", post(http_handler))
+            .route("/ws", get(ws_handler))
+            .route("/snapshot/{height}", get(snapshot_handler))
+            .with_state(state);
+        let listener = daemon::activated_or_bind_tcp("rpc", addr)
+            .await
+            .map_err(NockAppError::IoError)?;
+        let local_addr = listener.local_addr().map_err(NockAppError::IoError)?;
+        info!(
+            "Serving JSON-RPC on http://{} and ws://{}/ws",
+            local_addr, local_addr
+        );
+        serve(listener, app.into_make_service())
+            .await
+            .map_err(NockAppError::IoError)
+    })
+}
+
+/// Publish new heads, reorgs and pending transactions on `events`, from the kernel's gossip
+/// effects
+pub fn make_events_driver(events: ChainEvents) -> IODriverFn {
+    make_driver(move |handle| async move {
+        // The latest blocks on the heaviest chain, by height
+        let mut chain: BTreeMap<u64, Value> = BTreeMap::new();
+        loop {
+            let effect = match handle.next_effect().await {
+                Ok(effect) => effect,
+                Err(NockAppError::BroadcastRecvLaggedError(n)) => {
+                    warn!("Chain subscriptions missed {} effects", n);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let Some(fact) = gossip_fact(&effect) else {
+                continue;
+            };
+            if fact.eq_bytes(b"heard-block") {
+                if let Err(e) = publish_heads(&handle, &events, &mut chain).await {
+                    debug!("Could not look up the heaviest block: {}", e);
+                }
+            } else if fact.eq_bytes(b"heard-tx") {
+                // [%gossip %0 %heard-tx raw-tx], where the raw tx starts with its id
+                let Ok(raw_tx) = unsafe { effect.root() }.slot(15) else {
+                    continue;
+                };
+                let Some(id) = raw_tx
+                    .slot(2)
+                    .ok()
+                    .and_then(|id| tip5_hash_to_base58(id).ok())
+                else {
+                    continue;
+                };
+                let mut raw_tx_slab = NounSlab::new();
+                let raw_tx = raw_tx_slab.copy_into(raw_tx);
+                raw_tx_slab.set_root(raw_tx);
+                let event = json!({ "id": id, "jam": to_hex(&raw_tx_slab.jam()) });
+                let _ = events.send((Topic::PendingTransactions, event));
+            }
+        }
+    })
+}
+
+/// The tag of the fact in a `[%gossip %0 fact]` effect
+pub(crate) fn gossip_fact(effect: &NounSlab) -> Option<Noun> {
+    let effect = unsafe { effect.root() };
+    if !effect.as_cell().ok()?.head().eq_bytes(b"gossip") {
+        return None;
+    }
+    effect.slot(14).ok()
+}
+
+/// Look up the heaviest block after a new block was accepted. Publish each block that is new on
+/// the heaviest chain, lowest first, and a reorg first if blocks `chain` had are no longer on it.
+async fn publish_heads(
+    handle: &NockAppHandle,
+    events: &ChainEvents,
+    chain: &mut BTreeMap<u64, Value>,
+) -> Result<(), RpcError> {
+    let Some(tip) = tip_block(handle).await? else {
+        return Ok(());
+    };
+    let tip_height = tip["height"].as_u64().unwrap_or(0);
+    if chain
+        .last_key_value()
+        .is_some_and(|(_, block)| block["id"] == tip["id"])
+    {
+        return Ok(());
+    }
+
+    // Roll back what we have until it meets the heaviest chain
+    let mut rolled_back = Vec::new();
+    let mut ancestor = None;
+    while let Some((&height, block)) = chain.last_key_value() {
+        let on_chain = if height + 1 == tip_height {
+            block["id"] == tip["parent"]
+        } else if height < tip_height {
+            block_at_height(handle, height)
+                .await?
+                .is_some_and(|current| current["id"] == block["id"])
+        } else {
+            false
+        };
+        if on_chain {
+            ancestor = Some(block.clone());
+            break;
+        }
+        if let Some((_, block)) = chain.pop_last() {
+            rolled_back.push(block);
+        }
+    }
+
+    let start = match (&ancestor, rolled_back.last()) {
+        (Some(ancestor), _) => ancestor["height"].as_u64().unwrap_or(0) + 1,
+        (None, Some(lowest)) => lowest["height"].as_u64().unwrap_or(0),
+        (None, None) => tip_height,
+    };
+    let start = start.max(tip_height.saturating_sub(REORG_WINDOW - 1));
+    let mut applied = Vec::new();
+    for height in start..tip_height {
+        match block_at_height(handle, height).await? {
+            Some(block) => applied.push(block),
+            None => break,
+        }
+    }
+    applied.push(tip.clone());
+
+    if !rolled_back.is_empty() {
+        let reorg = json!({
+            "oldTip": rolled_back[0]["id"],
+            "newTip": tip["id"],
+            "height": tip["height"],
+            "commonAncestor": ancestor.map(|ancestor| json!({
+                "id": ancestor["id"],
+                "height": ancestor["height"],
+            })),
+            "rolledBack": rolled_back,
+            "applied": applied,
+        });
+        let _ = events.send((Topic::Reorgs, reorg));
+    }
+    for block in applied {
+        let height = block["height"].as_u64().unwrap_or(0);
+        let _ = events.send((Topic::NewHeads, block.clone()));
+        chain.insert(height, block);
+    }
+    while chain.len() as u64 > REORG_WINDOW {
+        chain.pop_first();
+    }
+    Ok(())
+}
+
+/// Whether a request carries `token` as `Authorization: Bearer <token>`
+fn is_admin(token: &str, headers: &HeaderMap) -> bool {
+    let Some(presented) = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    // Compare every byte, so how long the comparison takes says nothing about the token
+    presented.len() == token.len()
+        && presented
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn http_handler(State(state): State<RpcState>, headers: HeaderMap, body: String) -> Response {
+    let admin = is_admin(&state.token, &headers);
+    match handle_body(&state, admin, None, &body).await {
+        Some(reply) => ([(CONTENT_TYPE, "application/json")], reply).into_response(),
+        // Only notifications, which get no reply
+        None => StatusCode::NO_CONTENT.into_response(),
+    }
+}
+
+async fn snapshot_handler(State(state): State<RpcState>, Path(height): Path<u64>) -> Response {
+    let Some(rate) = &state.snapshots else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match snapshot::limited_snapshot_jam(&state.handle, rate, height).await {
+        Ok(Some(jam)) => ([(CONTENT_TYPE, "application/octet-stream")], jam).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(RpcError::RateLimited) => StatusCode::TOO_MANY_REQUESTS.into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn ws_handler(
+    State(state): State<RpcState>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let admin = is_admin(&state.token, &headers);
+    ws.on_upgrade(move |socket| ws_session(state, admin, socket))
+}
+
+/// Answer calls over a websocket, and send it the events it subscribes to. Admin calls are
+/// refused unless `admin`.
+async fn ws_session(state: RpcState, admin: bool, mut socket: WebSocket) {
+    let mut subscriptions = Subscriptions::default();
+    let mut events = state.events.subscribe();
+    loop {
+        let reply = tokio::select! {
+            message = socket.recv() => {
+                let body = match message {
+                    Some(Ok(Message::Text(text))) => text.as_str().to_owned(),
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                handle_body(&state, admin, Some(&mut subscriptions), &body).await
+            }
+            event = events.recv() => {
+                let (topic, result) = match event {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("JSON-RPC websocket missed {} events", n);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let notifications: Vec<Value> = subscriptions
+                    .topics
+                    .iter()
+                    .filter(|(_, subscribed)| **subscribed == topic)
+                    .map(|(id, _)| {
+                        json!({
+                            "jsonrpc": "2.0",
+                            "method": "chain_subscription",
+                            "params": { "subscription": id, "result": result },
+                        })
+                    })
+                    .collect();
+                match notifications.len() {
+                    0 => None,
+                    1 => notifications.into_iter().next().map(|n| n.to_string()),
+                    _ => Some(Value::Array(notifications).to_string()),
+                }
+            }
+        };
+        if let Some(reply) = reply {
+            if socket.send(Message::Text(reply.into())).await.is_err() {
+                break;
+            }
+        }
+    }
+    debug!("JSON-RPC websocket closed");
+}
+
+/// Answer a request body, which holds one call or a batch, making admin calls only if `admin`.
+/// `None` if nothing needs a reply.
+async fn handle_body(
+    state: &RpcState,
+    admin: bool,
+    mut subscriptions: Option<&mut Subscriptions>,
+    body: &str,
+) -> Option<String> {
+    let value: Value = match serde_json::from_str(body) {
+        Ok(value) => value,
+        Err(e) => {
+            return Some(
+                RpcError::Parse(e.to_string())
+                    .to_json(Value::Null)
+                    .to_string(),
+            )
+        }
+    };
+    match value {
+        Value::Array(calls) if calls.is_empty() => Some(
+            RpcError::InvalidRequest("empty batch")
+                .to_json(Value::Null)
+                .to_string(),
+        ),
+        Value::Array(calls) => {
+            let mut replies = Vec::new();
+            for call in calls {
+                replies.extend(handle_call(state, admin, subscriptions.as_deref_mut(), call).await);
+            }
+            (!replies.is_empty()).then(|| Value::Array(replies).to_string())
+        }
+        call => handle_call(state, admin, subscriptions, call)
+            .await
+            .map(|reply| reply.to_string()),
+    }
+}
+
+/// Answer one call, or `None` for a notification
+async fn handle_call(
+    state: &RpcState,
+    admin: bool,
+    subscriptions: Option<&mut Subscriptions>,
+    call: Value,
+) -> Option<Value> {
+    let (id, method, params) = match parse_call(&call) {
+        Ok(parsed) => parsed,
+        Err(e) => return Some(e.to_json(Value::Null)),
+    };
+    let result = match method {
+        "chain_subscribe" | "chain_unsubscribe" => {
+            subscription_call(subscriptions, method, &params)
+        }
+        _ if !admin && ADMIN_METHODS.contains(&method) => {
+            Err(RpcError::Unauthorized(method.to_string()))
+        }
+        _ => dispatch(state, method, &params).await,
+    };
+    let id = id?;
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => e.to_json(id),
+    })
+}
+
+/// The id, if the call isn't a notification, method and positional params of a call
+pub(crate) fn parse_call(call: &Value) -> Result<(Option<Value>, &str, Vec<Value>), RpcError> {
+    let Value::Object(call) = call else {
+        return Err(RpcError::InvalidRequest("call must be an object"));
+    };
+    if call.get("jsonrpc") != Some(&json!("2.0")) {
+        return Err(RpcError::InvalidRequest("jsonrpc must be \"2.0\""));
+    }
+    let Some(Value::String(method)) = call.get("method") else {
+        return Err(RpcError::InvalidRequest("method must be a string"));
+    };
+    let params = match call.get("params") {
+        None => Vec::new(),
+        Some(Value::Array(params)) => params.clone(),
+        Some(_) => return Err(RpcError::InvalidRequest("params must be an array")),
+    };
+    let id = match call.get("id") {
+        None => None,
+        Some(id @ (Value::Null | Value::Number(_) | Value::String(_))) => Some(id.clone()),
+        Some(_) => {
+            return Err(RpcError::InvalidRequest(
+                "id must be a number, string or null",
+            ))
+        }
+    };
+    Ok((id, method, params))
+}
+
+async fn dispatch(state: &RpcState, method: &str, params: &[Value]) -> Result<Value, RpcError> {
+    match method {
+        "chain_getHeaviestBlock" => {
+            let page = peek(&state.handle, |slab| {
+                let path = make_tas(slab, "heaviest-block").as_noun();
+                T(slab, &[path, D(0)])
+            })
+            .await?;
+            page.map_or(Ok(Value::Null), |page| block_json(unsafe { *page.root() }))
+        }
+        "chain_getBlock" => {
+            let id = string_param(params, 0, "id")?;
+            let page = peek(&state.handle, |slab| {
+                let id = make_tas(slab, id).as_noun();
+                T(slab, &[D(tas!(b"block")), id, D(0)])
+            })
+            .await?;
+            page.map_or(Ok(Value::Null), |page| block_json(unsafe { *page.root() }))
+        }
+        "chain_getBlockByHeight" => {
+            let height = param(params, 0, "height")?
+                .as_u64()
+                .filter(|height| *height < (1 << 63))
+                .ok_or_else(|| RpcError::InvalidParams("height must be a number".into()))?;
+            let page = peek(&state.handle, |slab| {
+                T(slab, &[D(tas!(b"heavy-n")), D(height), D(0)])
+            })
+            .await?;
+            page.map_or(Ok(Value::Null), |page| block_json(unsafe { *page.root() }))
+        }
+        "chain_getTransaction" => {
+            let id = string_param(params, 0, "id")?;
+            let raw_tx = peek(&state.handle, |slab| {
+                let tag = make_tas(slab, "raw-transaction").as_noun();
+                let id = make_tas(slab, id).as_noun();
+                T(slab, &[tag, id, D(0)])
+            })
+            .await?;
+            Ok(raw_tx.map_or(
+                Value::Null,
+                |raw_tx| json!({ "id": id, "jam": to_hex(&raw_tx.jam()) }),
+            ))
+        }
+        "chain_getBalance" => {
+            let id = string_param(params, 0, "blockId")?;
+            let balance = peek(&state.handle, |slab| {
+                let id = make_tas(slab, id).as_noun();
+                T(slab, &[D(tas!(b"balance")), id, D(0)])
+            })
+            .await?;
+            Ok(balance.map_or(Value::Null, |balance| {
+                json!({
+                    "notes": treap_nodes(unsafe { *balance.root() }).len(),
+                    "jam": to_hex(&balance.jam()),
+                })
+            }))
+        }
+        "chain_getStateAt" => {
+            let height = param(params, 0, "height")?
+                .as_u64()
+                .filter(|height| *height < (1 << 63))
+                .ok_or_else(|| RpcError::InvalidParams("height must be a number".into()))?;
+            let Some((height, block_id, balance)) = state_at(&state.handle, height).await? else {
+                return Ok(Value::Null);
+            };
+            Ok(json!({
+                "height": height,
+                "blockId": block_id,
+                "notes": treap_nodes(unsafe { *balance.root() }).len(),
+                "jam": to_hex(&balance.jam()),
+            }))
+        }
+        "chain_getHeaders" => {
+            let from = param(params, 0, "from")?
+                .as_u64()
+                .filter(|from| *from < (1 << 63))
+                .ok_or_else(|| RpcError::InvalidParams("from must be a number".into()))?;
+            let count = optional_u64_param(params, 1, "count")?
+                .unwrap_or(MAX_HEADERS)
+                .min(MAX_HEADERS);
+            let mut headers = Vec::new();
+            for height in from..(from + count).min(1 << 63) {
+                let Some(header) = peek(&state.handle, |slab| {
+                    T(slab, &[D(tas!(b"header")), D(height), D(0)])
+                })
+                .await?
+                else {
+                    break;
+                };
+                headers.push(header_json(&header)?);
+            }
+            Ok(Value::Array(headers))
+        }
+        "chain_getTxProof" => {
+            let block_id = string_param(params, 0, "blockId")?;
+            let tx_id = string_param(params, 1, "txId")?;
+            let proof = peek(&state.handle, |slab| {
+                let tag = make_tas(slab, "tx-proof").as_noun();
+                let block_id = make_tas(slab, block_id).as_noun();
+                let tx_id = make_tas(slab, tx_id).as_noun();
+                T(slab, &[tag, block_id, tx_id, D(0)])
+            })
+            .await?;
+            Ok(proof.map_or(
+                Value::Null,
+                |proof| json!({ "blockId": block_id, "txId": tx_id, "jam": to_hex(&proof.jam()) }),
+            ))
+        }
+        "mempool_size" => Ok(json!(mempool::mempool(&state.handle).await?.len())),
+        "mempool_getTransactions" => {
+            let height = tip_block(&state.handle)
+                .await?
+                .and_then(|tip| tip["height"].as_u64())
+                .unwrap_or(0);
+            let txs = mempool::mempool(&state.handle).await?;
+            Ok(Value::Array(
+                txs.iter().map(|tx| tx.to_json(height)).collect(),
+            ))
+        }
+        "mempool_submitTransaction" => {
+            let jam = from_hex(string_param(params, 0, "jam")?)
+                .ok_or_else(|| RpcError::InvalidParams("jam must be hex".into()))?;
+            let mut slab = NounSlab::new();
+            let raw_tx = slab
+                .cue_into(Bytes::from(jam))
+                .map_err(|e| RpcError::InvalidParams(format!("jam does not cue: {}", e)))?;
+            let heard_tx = T(&mut slab, &[D(tas!(b"heard-tx")), raw_tx]);
+            slab.set_root(heard_tx);
+            let NockchainFact::HeardTx(id, poke) = NockchainFact::from_noun_slab(&slab)
+                .map_err(|_| RpcError::InvalidParams("not a raw transaction".into()))?
+            else {
+                return Err(RpcError::InvalidParams("not a raw transaction".into()));
+            };
+            let result = state.handle.poke(RpcWire::Submit.to_wire(), poke).await?;
+            Ok(json!({ "id": id, "accepted": matches!(result, PokeResult::Ack) }))
+        }
+        "mempool_evict" => {
+            let ids = param(params, 0, "ids")?
+                .as_array()
+                .and_then(|ids| {
+                    ids.iter()
+                        .map(|id| id.as_str().map(str::to_string))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .ok_or_else(|| RpcError::InvalidParams("ids must be a list of strings".into()))?;
+            Ok(json!(mempool::drop_txs(&state.handle, &ids).await?))
+        }
+        "mempool_flush" => Ok(json!(mempool::flush(&state.handle).await?)),
+        "mempool_setSizeLimit" => {
+            let max_bytes = optional_u64_param(params, 0, "maxBytes")?;
+            Ok(json!(
+                mempool::set_max_bytes(&state.handle, max_bytes).await?
+            ))
+        }
+        "mempool_estimateFee" => {
+            let target = optional_u64_param(params, 0, "target")?
+                .unwrap_or(1)
+                .clamp(1, MAX_FEE_TARGET);
+            Ok(mempool::estimate_fee(&state.handle, target)
+                .await?
+                .to_json())
+        }
+        "mempool_getSizeLimit" => Ok(json!(mempool::max_bytes(&state.handle).await?)),
+        "mining_setEnabled" => {
+            let enable = param(params, 0, "enabled")?
+                .as_bool()
+                .ok_or_else(|| RpcError::InvalidParams("enabled must be a boolean".into()))?;
+            Ok(json!(set_mining(state, enable).await?))
+        }
+        "mining_setKey" => {
+            let configs = match param(params, 0, "keys")? {
+                Value::String(pubkey) => vec![MiningKeyConfig {
+                    share: 1,
+                    m: 1,
+                    keys: vec![pubkey.clone()],
+                    percent: false,
+                }],
+                Value::Array(configs) => configs
+                    .iter()
+                    .map(|config| {
+                        config
+                            .as_str()
+                            .ok_or_else(|| "a config must be a string".to_string())
+                            .and_then(MiningKeyConfig::from_str)
+                    })
+                    .collect::<Result<_, _>>()
+                    .map_err(RpcError::InvalidParams)?,
+                _ => {
+                    return Err(RpcError::InvalidParams(
+                        "keys must be a pubkey or a list of configs".into(),
+                    ))
+                }
+            };
+            check_split(&configs).map_err(RpcError::InvalidParams)?;
+            Ok(json!(set_mining_key(state, configs).await?))
+        }
+        "mining_getStats" => Ok(state.mining.to_json(Instant::now())),
+        "mining_getBlockTemplate" => Ok(block_template(&state.handle)
+            .await?
+            .map_or(Value::Null, |(template, _)| template)),
+        "mining_submitBlock" => {
+            let jam = from_hex(string_param(params, 0, "jam")?)
+                .ok_or_else(|| RpcError::InvalidParams("jam must be hex".into()))?;
+            submit_block(&state.handle, jam).await
+        }
+        "regtest_generate" => {
+            let generating = state.regtest.as_ref().ok_or(RpcError::NotRegtest)?;
+            let count = match params.first() {
+                None => 1,
+                Some(count) => count
+                    .as_u64()
+                    .filter(|count| (1..=MAX_GENERATE).contains(count))
+                    .ok_or_else(|| {
+                        RpcError::InvalidParams(format!(
+                            "count must be a number from 1 to {MAX_GENERATE}"
+                        ))
+                    })?,
+            };
+            let _generating = generating.lock().await;
+            generate(state, count).await
+        }
+        "node_peers" => {
+            let (result, peers) = oneshot::channel();
+            peer_command(state, PeerCommand::List { result }).await?;
+            let peers = peers
+                .await
+                .map_err(|_| RpcError::Internal("libp2p driver stopped".into()))?;
+            Ok(Value::Array(
+                peers
+                    .into_iter()
+                    .map(|(peer_id, addresses, score)| {
+                        let addresses: Vec<String> =
+                            addresses.iter().map(|addr| addr.to_string()).collect();
+                        json!({
+                            "peerId": peer_id.to_base58(),
+                            "addresses": addresses,
+                            "score": score,
+                        })
+                    })
+                    .collect(),
+            ))
+        }
+        "node_dialPeer" => {
+            let addr = Multiaddr::from_str(string_param(params, 0, "multiaddr")?)
+                .map_err(|e| RpcError::InvalidParams(e.to_string()))?;
+            peer_command(state, PeerCommand::Dial { addr }).await?;
+            Ok(json!(true))
+        }
+        "node_blockPeer" => {
+            let peer_id = PeerId::from_str(string_param(params, 0, "peerId")?)
+                .map_err(|e| RpcError::InvalidParams(e.to_string()))?;
+            let duration = optional_u64_param(params, 1, "seconds")?.map(Duration::from_secs);
+            peer_command(state, PeerCommand::Block { peer_id, duration }).await?;
+            Ok(json!(true))
+        }
+        "node_unblockPeer" => {
+            let peer_id = PeerId::from_str(string_param(params, 0, "peerId")?)
+                .map_err(|e| RpcError::InvalidParams(e.to_string()))?;
+            let (result, was_blocked) = oneshot::channel();
+            peer_command(state, PeerCommand::Unblock { peer_id, result }).await?;
+            let was_blocked = was_blocked
+                .await
+                .map_err(|_| RpcError::Internal("libp2p driver stopped".into()))?;
+            Ok(json!(was_blocked))
+        }
+        "node_blockedPeers" => {
+            let (result, blocked) = oneshot::channel();
+            peer_command(state, PeerCommand::ListBlocked { result }).await?;
+            let blocked = blocked
+                .await
+                .map_err(|_| RpcError::Internal("libp2p driver stopped".into()))?;
+            Ok(Value::Array(
+                blocked
+                    .into_iter()
+                    .map(|(peer_id, blocked)| {
+                        let until = blocked.until.map(|until| {
+                            until
+                                .duration_since(UNIX_EPOCH)
+                                .map_or(0, |since| since.as_secs())
+                        });
+                        json!({
+                            "peerId": peer_id.to_base58(),
+                            "reason": blocked.reason,
+                            "until": until,
+                        })
+                    })
+                    .collect(),
+            ))
+        }
+        "node_getSnapshotHash" => {
+            let height = param(params, 0, "height")?
+                .as_u64()
+                .ok_or_else(|| RpcError::InvalidParams("height must be a number".into()))?;
+            let rate = state.snapshots.as_ref().ok_or(RpcError::NoSnapshots)?;
+            let jam = snapshot::limited_snapshot_jam(&state.handle, rate, height).await?;
+            Ok(jam.map_or(
+                Value::Null,
+                |jam| json!({ "height": height, "hash": snapshot::snapshot_hash(&jam) }),
+            ))
+        }
+        "node_getLogFilter" => Ok(observability::log_filter().map_or(Value::Null, Value::String)),
+        "node_setLogFilter" => {
+            let directives = string_param(params, 0, "directives")?;
+            observability::set_log_filter(directives).map_err(|e| match e {
+                LogFilterError::Parse(_) => RpcError::InvalidParams(e.to_string()),
+                e => RpcError::Internal(e.to_string()),
+            })?;
+            info!("Log filter set to {}", directives);
+            Ok(json!(true))
+        }
+        "node_status" => node_status(state).await,
+        "index_getAddressTransactions" => {
+            let index = state.index.as_ref().ok_or(RpcError::NoIndex)?;
+            let pubkey = string_param(params, 0, "pubkey")?;
+            let from = optional_u64_param(params, 1, "from")?.unwrap_or(0);
+            let limit = optional_u64_param(params, 2, "limit")?
+                .unwrap_or(MAX_INDEX_ENTRIES)
+                .min(MAX_INDEX_ENTRIES);
+            let entries = index
+                .entries(pubkey, from, limit as usize)?
+                .iter()
+                .map(|entry| entry_json(index, entry))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(json!(entries))
+        }
+        "memo_send" => {
+            if state.memos.is_none() {
+                return Err(RpcError::NoMemos);
+            }
+            let to = string_param(params, 0, "pubkey")?.to_string();
+            let jam = from_hex(string_param(params, 1, "jam")?)
+                .ok_or_else(|| RpcError::InvalidParams("jam must be hex".into()))?;
+            let memo = Memo::new(to, jam);
+            if !memo.is_relayable() {
+                return Err(RpcError::InvalidParams(
+                    "memo too big or pubkey invalid".into(),
+                ));
+            }
+            let (result, was_new) = oneshot::channel();
+            peer_command(state, PeerCommand::Memo { memo, result }).await?;
+            let was_new = was_new
+                .await
+                .map_err(|_| RpcError::Internal("libp2p driver stopped".into()))?
+                .ok_or(RpcError::NoMemos)?;
+            Ok(json!(was_new))
+        }
+        "memo_getInbox" => {
+            let memos = state.memos.as_ref().ok_or(RpcError::NoMemos)?;
+            let pubkey = string_param(params, 0, "pubkey")?;
+            let jams: Vec<String> = memos
+                .inbox(pubkey, Instant::now())
+                .iter()
+                .map(|memo| to_hex(&memo.memo))
+                .collect();
+            Ok(json!(jams))
+        }
+        _ => Err(RpcError::MethodNotFound(method.to_string())),
+    }
+}
+
+fn subscription_call(
+    subscriptions: Option<&mut Subscriptions>,
+    method: &str,
+    params: &[Value],
+) -> Result<Value, RpcError> {
+    let subscriptions = subscriptions.ok_or(RpcError::NoSubscriptions)?;
+    if method == "chain_subscribe" {
+        let topic = Topic::from_str(string_param(params, 0, "topic")?)?;
+        let id = format!("{:#x}", subscriptions.next_id);
+        subscriptions.next_id += 1;
+        subscriptions.topics.insert(id.clone(), topic);
+        Ok(json!(id))
+    } else {
+        let id = string_param(params, 0, "subscription")?;
+        Ok(json!(subscriptions.topics.remove(id).is_some()))
+    }
+}
+
+fn param<'a>(params: &'a [Value], index: usize, name: &str) -> Result<&'a Value, RpcError> {
+    params
+        .get(index)
+        .ok_or_else(|| RpcError::InvalidParams(format!("missing {}", name)))
+}
+
+fn optional_u64_param(params: &[Value], index: usize, name: &str) -> Result<Option<u64>, RpcError> {
+    match params.get(index) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .map(Some)
+            .ok_or_else(|| RpcError::InvalidParams(format!("{} must be a number", name))),
+    }
+}
+
+pub(crate) fn string_param<'a>(
+    params: &'a [Value],
+    index: usize,
+    name: &str,
+) -> Result<&'a str, RpcError> {
+    param(params, index, name)?
+        .as_str()
+        .ok_or_else(|| RpcError::InvalidParams(format!("{} must be a string", name)))
+}
+
+/// Turn mining on or off, returning whether the kernel accepted it
+async fn set_mining(state: &RpcState, enable: bool) -> Result<bool, RpcError> {
+    let mut slab = NounSlab::new();
+    let tag = make_tas(&mut slab, "enable-mining").as_noun();
+    let poke = T(
+        &mut slab,
+        &[D(tas!(b"command")), tag, if enable { YES } else { NO }],
+    );
+    slab.set_root(poke);
+    let result = state.handle.poke(RpcWire::Mining.to_wire(), slab).await?;
+    Ok(matches!(result, PokeResult::Ack))
+}
+
+/// Replace the keys mined blocks pay, returning whether the kernel accepted them
+async fn set_mining_key(state: &RpcState, configs: Vec<MiningKeyConfig>) -> Result<bool, RpcError> {
+    let poke = mining_keys_poke("rotate-mining-key", configs);
+    let result = state.handle.poke(RpcWire::Mining.to_wire(), poke).await?;
+    Ok(matches!(result, PokeResult::Ack))
+}
+
+/// Mine until `count` more blocks join the heaviest chain, and return their ids
+async fn generate(state: &RpcState, count: u64) -> Result<Value, RpcError> {
+    let mut events = state.events.subscribe();
+    let start = tip_block(&state.handle)
+        .await?
+        .and_then(|tip| tip["height"].as_u64());
+    if !set_mining(state, true).await? {
+        return Err(RpcError::Internal(
+            "the kernel would not start mining".into(),
+        ));
+    }
+    let mut mined = Vec::new();
+    let waited = async {
+        while (mined.len() as u64) < count {
+            let event = tokio::time::timeout(GENERATE_TIMEOUT, events.recv())
+                .await
+                .map_err(|_| RpcError::Internal("timed out waiting for a block".into()))?;
+            match event {
+                Ok((Topic::NewHeads, block)) => {
+                    if start.is_none_or(|start| block["height"].as_u64() > Some(start)) {
+                        mined.push(block["id"].clone());
+                    }
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => {
+                    return Err(RpcError::Internal("the node is shutting down".into()));
+                }
+            }
+        }
+        Ok(())
+    }
+    .await;
+    set_mining(state, false).await?;
+    waited.map(|()| json!(mined))
+}
+
+/// The candidate block as `mining_getBlockTemplate` describes it, and its commitment, or `None`
+/// without mining keys
+pub(crate) async fn block_template(
+    handle: &NockAppHandle,
+) -> Result<Option<(Value, String)>, RpcError> {
+    let Some(template) = peek(handle, |slab| {
+        let tag = make_tas(slab, "block-template").as_noun();
+        T(slab, &[tag, D(0)])
+    })
+    .await?
+    else {
+        return Ok(None);
+    };
+    let root = unsafe { *template.root() };
+    let malformed = || RpcError::Internal("malformed block template".into());
+    let slot = |axis| root.slot(axis).map_err(|_| malformed());
+    let number = |noun: Noun| noun.as_atom().ok()?.as_u64().ok();
+    let cord = |noun: Noun| noun.as_atom().ok()?.into_string().ok();
+
+    // a bignum is 32-bit chunks, least significant first
+    let chunks = slot(29)?
+        .list_iter()
+        .map(number)
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(malformed)?;
+    let target = chunks.iter().rev().fold(UBig::from(0u8), |target, chunk| {
+        (target << 32) + UBig::from(*chunk)
+    });
+    let tx_ids = treap_nodes(slot(510)?)
+        .into_iter()
+        .map(tip5_hash_to_base58)
+        .collect::<Result<Vec<_>, _>>()?;
+    let coinbase = slot(511)?
+        .list_iter()
+        .map(|split| {
+            let pks = split
+                .slot(5)
+                .ok()?
+                .list_iter()
+                .map(cord)
+                .collect::<Option<Vec<_>>>()?;
+            Some(json!({
+                "owners": { "m": number(split.slot(4).ok()?)?, "pubkeys": pks },
+                "amount": number(split.slot(3).ok()?)?,
+            }))
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(malformed)?;
+    let commitment = tip5_hash_to_base58(slot(6)?)?;
+
+    // what the miner kernel takes, less the nonce
+    let mut mine = NounSlab::new();
+    let version = mine.copy_into(slot(2)?);
+    let commit = mine.copy_into(slot(6)?);
+    let bignum = mine.copy_into(slot(14)?);
+    let pow_len = mine.copy_into(slot(30)?);
+    let root = T(&mut mine, &[version, commit, bignum, pow_len]);
+    mine.set_root(root);
+
+    Ok(Some((
+        json!({
+            "version": number(slot(2)?).ok_or_else(malformed)?,
+            "parent": tip5_hash_to_base58(slot(62)?)?,
+            "height": number(slot(126)?).ok_or_else(malformed)?,
+            "timestamp": number(slot(254)?).ok_or_else(malformed)?,
+            "txIds": tx_ids,
+            "coinbase": coinbase,
+            "target": target.to_string(),
+            "powLen": number(slot(30)?).ok_or_else(malformed)?,
+            "commitment": commitment,
+            "jam": to_hex(&mine.jam()),
+        }),
+        commitment,
+    )))
+}
+
+/// Hand the kernel `[%pow proof digest commitment nonce]` mined outside the node, and say
+/// whether the block joined the heaviest chain, or was mined on a template that's out of date
+pub(crate) async fn submit_block(handle: &NockAppHandle, jam: Vec<u8>) -> Result<Value, RpcError> {
+    let mut slab = NounSlab::new();
+    let pow = slab
+        .cue_into(Bytes::from(jam))
+        .map_err(|e| RpcError::InvalidParams(format!("jam does not cue: {}", e)))?;
+    let not_pow = || RpcError::InvalidParams("not a [%pow proof digest commitment nonce]".into());
+    if !pow.slot(2).is_ok_and(|tag| tag.eq_bytes("pow")) {
+        return Err(not_pow());
+    }
+    let commitment = pow
+        .slot(30)
+        .map_err(|_| not_pow())
+        .and_then(|commit| tip5_hash_to_base58(commit).map_err(|_| not_pow()))?;
+    let current = block_template(handle)
+        .await?
+        .map(|(_, commitment)| commitment);
+    if current.as_deref() != Some(commitment.as_str()) {
+        return Ok(json!({ "accepted": false, "id": null, "stale": true }));
+    }
+
+    let tip = tip_block(handle).await?.map(|tip| tip["id"].clone());
+    let poke = T(&mut slab, &[D(tas!(b"command")), pow]);
+    slab.set_root(poke);
+    let result = handle.poke(RpcWire::Mining.to_wire(), slab).await?;
+    let id = tip_block(handle)
+        .await?
+        .map(|tip| tip["id"].clone())
+        .filter(|id| matches!(result, PokeResult::Ack) && Some(id) != tip.as_ref());
+    Ok(json!({ "accepted": id.is_some(), "id": id, "stale": false }))
+}
+
+/// Peek the path `build` makes, with the value if there is one
+pub(crate) async fn peek(
+    handle: &NockAppHandle,
+    build: impl FnOnce(&mut NounSlab) -> Noun,
+) -> Result<Option<NounSlab>, RpcError> {
+    let mut path = NounSlab::new();
+    let root = build(&mut path);
+    path.set_root(root);
+    let Some(result) = handle.peek(path).await? else {
+        return Err(RpcError::Internal("peek failed".into()));
+    };
+    match ScryResult::from(unsafe { result.root() }) {
+        ScryResult::Some(value) => {
+            let mut slab = NounSlab::new();
+            let value = slab.copy_into(value);
+            slab.set_root(value);
+            Ok(Some(slab))
+        }
+        ScryResult::Nothing => Ok(None),
+        ScryResult::BadPath => Err(RpcError::InvalidParams(
+            "the kernel has no such path".into(),
+        )),
+        ScryResult::Invalid => Err(RpcError::Internal("malformed peek result".into())),
+    }
+}
+
+async fn node_status(state: &RpcState) -> Result<Value, RpcError> {
+    let page = peek(&state.handle, |slab| {
+        let path = make_tas(slab, "heaviest-block").as_noun();
+        T(slab, &[path, D(0)])
+    })
+    .await?;
+    let tip = match page {
+        Some(page) => {
+            let page = unsafe { *page.root() };
+            let block = block_json(page)?;
+            let timestamp = health::tip(page).map(|(_, timestamp)| timestamp);
+            let now = UNIX_EPOCH.elapsed().map_or(0, |since| since.as_secs());
+            json!({
+                "id": block["id"],
+                "height": block["height"],
+                "timestamp": timestamp,
+                "age": timestamp.map(|timestamp| now.saturating_sub(timestamp)),
+            })
+        }
+        None => Value::Null,
+    };
+    let peers = match &state.peers {
+        Some(_) => {
+            let (result, peers) = oneshot::channel();
+            peer_command(state, PeerCommand::List { result }).await?;
+            let peers = peers
+                .await
+                .map_err(|_| RpcError::Internal("libp2p driver stopped".into()))?;
+            json!(peers.len())
+        }
+        None => Value::Null,
+    };
+    let mempool = mempool::mempool(&state.handle).await?;
+    let checkpoint_age = health::checkpoint_age(&state.checkpoints).await;
+    Ok(json!({
+        "tip": tip,
+        "peers": peers,
+        "mempool": {
+            "count": mempool.len(),
+            "bytes": mempool.iter().map(|tx| tx.size).sum::<u64>(),
+        },
+        "mining": state.mining.to_json(Instant::now()),
+        "checkpointAge": checkpoint_age.map(|age| age.as_secs()),
+        "uptime": state.started.elapsed().as_secs(),
+    }))
+}
+
+async fn peer_command(state: &RpcState, command: PeerCommand) -> Result<(), RpcError> {
+    let peers = state.peers.as_ref().ok_or(RpcError::NoPeerControl)?;
+    peers.send(command).await.map_err(|e| {
+        error!("libp2p driver is not taking peer commands: {}", e);
+        RpcError::Internal("libp2p driver stopped".into())
+    })
+}
+
+/// `{id, parent, height, txIds}` of a `page`
+pub(crate) fn block_json(page: Noun) -> Result<Value, RpcError> {
+    let malformed = |_| RpcError::Internal("malformed block".into());
+    let id = tip5_hash_to_base58(page.slot(2).map_err(malformed)?)?;
+    let parent = tip5_hash_to_base58(page.slot(14).map_err(malformed)?)?;
+    let height = page
+        .slot(2046)
+        .and_then(|height| Ok(height.as_atom()?.as_u64()?))
+        .map_err(malformed)?;
+    let tx_ids = treap_nodes(page.slot(30).map_err(malformed)?)
+        .into_iter()
+        .map(tip5_hash_to_base58)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(json!({ "id": id, "parent": parent, "height": height, "txIds": tx_ids }))
+}
+
+/// `{id, parent, height, jam}` of a `page-header`
+pub(crate) fn header_json(header: &NounSlab) -> Result<Value, RpcError> {
+    let root = unsafe { *header.root() };
+    let malformed = |_| RpcError::Internal("malformed header".into());
+    let id = tip5_hash_to_base58(root.slot(2).map_err(malformed)?)?;
+    let parent = tip5_hash_to_base58(root.slot(14).map_err(malformed)?)?;
+    let height = root
+        .slot(2046)
+        .and_then(|height| Ok(height.as_atom()?.as_u64()?))
+        .map_err(malformed)?;
+    Ok(json!({ "id": id, "parent": parent, "height": height, "jam": to_hex(&header.jam()) }))
+}
+
+/// The height and block id of the nearest balance at or below `height` on the heaviest chain,
+/// and the balance
+pub(crate) async fn state_at(
+    handle: &NockAppHandle,
+    height: u64,
+) -> Result<Option<(u64, String, NounSlab)>, RpcError> {
+    if height >= 1 << 63 {
+        return Ok(None);
+    }
+    let state = peek(handle, |slab| {
+        let tag = make_tas(slab, "state-at").as_noun();
+        T(slab, &[tag, D(height), D(0)])
+    })
+    .await?;
+    let Some(mut state) = state else {
+        return Ok(None);
+    };
+    let root = unsafe { *state.root() };
+    let malformed = |_| RpcError::Internal("malformed state".into());
+    let height = root
+        .slot(2)
+        .and_then(|height| Ok(height.as_atom()?.as_u64()?))
+        .map_err(malformed)?;
+    let block_id = tip5_hash_to_base58(root.slot(6).map_err(malformed)?)?;
+    let balance = root.slot(7).map_err(malformed)?;
+    state.set_root(balance);
+    Ok(Some((height, block_id, state)))
+}
+
+/// The values at the nodes of a `z-set` or `z-map`, a treap of `[n l r]` nodes
+pub(crate) fn treap_nodes(tree: Noun) -> Vec<Noun> {
+    let mut values = Vec::new();
+    let mut nodes = vec![tree];
+    while let Some(node) = nodes.pop() {
+        let Ok(cell) = node.as_cell() else {
+            continue;
+        };
+        let Ok(children) = cell.tail().as_cell() else {
+            continue;
+        };
+        values.push(cell.head());
+        nodes.push(children.head());
+        nodes.push(children.tail());
+    }
+    values
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_call() {
+        let (id, method, params) = parse_call(&json!({
+            "jsonrpc": "2.0", "id": 1, "method": "chain_getBlock", "params": ["abc"],
+        }))
+        .expect("call");
+        assert_eq!(id, Some(json!(1)));
+        assert_eq!(method, "chain_getBlock");
+        assert_eq!(params, vec![json!("abc")]);
+
+        let (id, _, params) =
+            parse_call(&json!({ "jsonrpc": "2.0", "method": "mempool_size" })).expect("call");
+        assert_eq!(id, None);
+        assert!(params.is_empty());
+
+        for bad in [
+            json!([]),
+            json!({ "jsonrpc": "1.0", "id": 1, "method": "mempool_size" }),
+            json!({ "jsonrpc": "2.0", "id": 1, "method": 5 }),
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "m", "params": {} }),
+            json!({ "jsonrpc": "2.0", "id": [1], "method": "m" }),
+        ] {
+            assert_eq!(parse_call(&bad).err().map(|e| e.code()), Some(-32600));
+        }
+    }
+
+    #[test]
+    fn test_admin_token() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = cookie_path(dir.path());
+        let first = write_cookie(&path).expect("cookie");
+        let token = write_cookie(&path).expect("cookie");
+        assert_ne!(first, token);
+        assert_eq!(std::fs::read_to_string(&path).expect("read"), token);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path)
+                .expect("metadata")
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let mut headers = HeaderMap::new();
+        assert!(!is_admin(&token, &headers));
+        headers.insert(AUTHORIZATION, format!("Bearer {}", first).parse().unwrap());
+        assert!(!is_admin(&token, &headers));
+        headers.insert(AUTHORIZATION, token.parse().unwrap());
+        assert!(!is_admin(&token, &headers));
+        headers.insert(AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        assert!(is_admin(&token, &headers));
+    }
+
+    #[test]
+    fn test_subscription_call() {
+        let mut subscriptions = Subscriptions::default();
+        let id = subscription_call(
+            Some(&mut subscriptions),
+            "chain_subscribe",
+            &[json!("newHeads")],
+        )
+        .expect("subscribe");
+        assert_eq!(
+            subscriptions.topics.get(id.as_str().expect("id")),
+            Some(&Topic::NewHeads)
+        );
+        assert!(subscription_call(
+            Some(&mut subscriptions),
+            "chain_subscribe",
+            &[json!("blocks")]
+        )
+        .is_err());
+        assert_eq!(
+            subscription_call(Some(&mut subscriptions), "chain_unsubscribe", &[id.clone()])
+                .expect("unsubscribe"),
+            json!(true)
+        );
+        assert_eq!(
+            subscription_call(Some(&mut subscriptions), "chain_unsubscribe", &[id])
+                .expect("unsubscribe"),
+            json!(false)
+        );
+        assert!(matches!(
+            subscription_call(None, "chain_subscribe", &[json!("newHeads")]),
+            Err(RpcError::NoSubscriptions)
+        ));
+    }
+
+    #[test]
+    fn test_hex() {
+        let bytes = vec![0x00, 0x1f, 0xa0, 0xff];
+        assert_eq!(to_hex(&bytes), "001fa0ff");
+        assert_eq!(from_hex("001fa0ff"), Some(bytes.clone()));
+        assert_eq!(from_hex("0x001FA0FF"), Some(bytes));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+
+    #[test]
+    fn test_treap_nodes() {
+        let mut slab: NounSlab = NounSlab::new();
+        // [n=1 l=[n=2 ~ ~] r=~]
+        let left = T(&mut slab, &[D(2), D(0), D(0)]);
+        let tree = T(&mut slab, &[D(1), left, D(0)]);
+        let values: Vec<u64> = treap_nodes(tree)
+            .into_iter()
+            .map(|n| n.as_atom().expect("atom").as_u64().expect("u64"))
+            .collect();
+        assert_eq!(values, vec![1, 2]);
+        assert!(treap_nodes(D(0)).is_empty());
+    }
+}
@@ -10,6 +10,7 @@ use nockapp::{AtomExt, Bytes, NockApp, NockAppError, ToBytes};
 use nockvm::noun::{Atom, D, NO, T, YES};
 use nockvm_macros::tas;
 
+use crate::mempool::RelayPolicy;
 use crate::NounSlab;
 
 #[cfg(feature = "bazel_build")]
@@ -30,62 +31,129 @@ pub enum SetupCommand {
     PokeFakenetConstants(BlockchainConstants),
     PokeSetGenesisSeal(String),
     PokeSetBtcData,
+    /// Cap the mempool at this many jammed bytes, or lift the cap with `None`
+    PokeSetMempoolMaxBytes(Option<u64>),
+    /// Prune blocks this far below the heaviest, or stop pruning with `None`
+    PokeSetPruneDepth(Option<u64>),
+    /// Keep balances at heights divisible by this while pruning, or none with `None`
+    PokeSetSnapshotInterval(Option<u64>),
+    /// Admit only transactions that meet this policy to the mempool
+    PokeSetRelayPolicy(RelayPolicy),
 }
 
-pub fn fakenet_blockchain_constaints(pow_len: u64, target_bex: u64) -> BlockchainConstants {
+pub fn fakenet_blockchain_constaints(
+    pow_len: u64,
+    target_bex: u64,
+    coinbase_timelock: u64,
+) -> BlockchainConstants {
     BlockchainConstants::new()
         .with_pow_len(pow_len)
         .with_genesis_target_atom_bex(target_bex as u128)
         .with_update_candidate_timestamp_interval(Seconds(15 * 60))
-        .with_coinbase_timelock_min(0)
+        .with_coinbase_timelock_min(coinbase_timelock)
         .with_first_month_coinbase_min(0)
 }
 
+impl SetupCommand {
+    /// The `%command` poke that makes this setting
+    pub fn into_poke(self) -> NounSlab {
+        match self {
+            SetupCommand::PokeFakenetConstants(constants) => {
+                let mut poke_slab = NounSlab::new();
+                let tag = make_tas(&mut poke_slab, "set-constants").as_noun();
+
+                let constants_slab = constants.into_slab();
+                poke_slab.copy_from_slab(&constants_slab);
+                poke_slab.modify(|constants| vec![D(tas!(b"command")), tag, constants]);
+                poke_slab
+            }
+            SetupCommand::PokeSetGenesisSeal(seal) => {
+                let mut poke_slab = NounSlab::new();
+                let block_height_noun =
+                    Atom::new(&mut poke_slab, DEFAULT_GENESIS_BLOCK_HEIGHT).as_noun();
+                let seal_byts = Bytes::from(
+                    seal.to_bytes()
+                        .expect("Failed to convert seal message to bytes"),
+                );
+                let seal_noun = Atom::from_bytes(&mut poke_slab, &seal_byts).as_noun();
+                let tag = Bytes::from(b"set-genesis-seal".to_vec());
+                let set_genesis_seal = Atom::from_bytes(&mut poke_slab, &tag).as_noun();
+                let poke_noun = T(
+                    &mut poke_slab,
+                    &[D(tas!(b"command")), set_genesis_seal, block_height_noun, seal_noun],
+                );
+                poke_slab.set_root(poke_noun);
+                poke_slab
+            }
+            SetupCommand::PokeSetBtcData => {
+                let mut poke_slab = NounSlab::new();
+                let poke_noun = T(
+                    &mut poke_slab,
+                    &[D(tas!(b"command")), D(tas!(b"btc-data")), D(0)],
+                );
+                poke_slab.set_root(poke_noun);
+                poke_slab
+            }
+            SetupCommand::PokeSetMempoolMaxBytes(max_bytes) => {
+                let mut poke_slab = NounSlab::new();
+                let tag = make_tas(&mut poke_slab, "set-mempool-max-bytes").as_noun();
+                let max_bytes = match max_bytes {
+                    Some(max_bytes) => {
+                        let max_bytes = Atom::new(&mut poke_slab, max_bytes).as_noun();
+                        T(&mut poke_slab, &[D(0), max_bytes])
+                    }
+                    None => D(0),
+                };
+                let poke_noun = T(&mut poke_slab, &[D(tas!(b"command")), tag, max_bytes]);
+                poke_slab.set_root(poke_noun);
+                poke_slab
+            }
+            SetupCommand::PokeSetPruneDepth(depth) => {
+                let mut poke_slab = NounSlab::new();
+                let tag = make_tas(&mut poke_slab, "set-prune-depth").as_noun();
+                let depth = match depth {
+                    Some(depth) => {
+                        let depth = Atom::new(&mut poke_slab, depth).as_noun();
+                        T(&mut poke_slab, &[D(0), depth])
+                    }
+                    None => D(0),
+                };
+                let poke_noun = T(&mut poke_slab, &[D(tas!(b"command")), tag, depth]);
+                poke_slab.set_root(poke_noun);
+                poke_slab
+            }
+            SetupCommand::PokeSetSnapshotInterval(interval) => {
+                let mut poke_slab = NounSlab::new();
+                let tag = make_tas(&mut poke_slab, "set-snapshot-interval").as_noun();
+                let interval = match interval {
+                    Some(interval) => {
+                        let interval = Atom::new(&mut poke_slab, interval).as_noun();
+                        T(&mut poke_slab, &[D(0), interval])
+                    }
+                    None => D(0),
+                };
+                let poke_noun = T(&mut poke_slab, &[D(tas!(b"command")), tag, interval]);
+                poke_slab.set_root(poke_noun);
+                poke_slab
+            }
+            SetupCommand::PokeSetRelayPolicy(policy) => {
+                let mut poke_slab = NounSlab::new();
+                let tag = make_tas(&mut poke_slab, "set-relay-policy").as_noun();
+                let policy = policy.to_noun(&mut poke_slab);
+                let poke_noun = T(&mut poke_slab, &[D(tas!(b"command")), tag, policy]);
+                poke_slab.set_root(poke_noun);
+                poke_slab
+            }
+        }
+    }
+}
+
 pub async fn poke<J: Jammer + Send + 'static>(
     nockapp: &mut NockApp<J>,
     command: SetupCommand,
 ) -> Result<(), Box<dyn Error>> {
-    let poke: NounSlab = match command {
-        SetupCommand::PokeFakenetConstants(constants) => {
-            let mut poke_slab = NounSlab::new();
-            let tag = make_tas(&mut poke_slab, "set-constants").as_noun();
-
-            let constants_slab = constants.into_slab();
-            poke_slab.copy_from_slab(&constants_slab);
-            poke_slab.modify(|constants| vec![D(tas!(b"command")), tag, constants]);
-            poke_slab
-        }
-        SetupCommand::PokeSetGenesisSeal(seal) => {
-            let mut poke_slab = NounSlab::new();
-            let block_height_noun =
-                Atom::new(&mut poke_slab, DEFAULT_GENESIS_BLOCK_HEIGHT).as_noun();
-            let seal_byts = Bytes::from(
-                seal.to_bytes()
-                    .expect("Failed to convert seal message to bytes"),
-            );
-            let seal_noun = Atom::from_bytes(&mut poke_slab, &seal_byts).as_noun();
-            let tag = Bytes::from(b"set-genesis-seal".to_vec());
-            let set_genesis_seal = Atom::from_bytes(&mut poke_slab, &tag).as_noun();
-            let poke_noun = T(
-                &mut poke_slab,
-                &[D(tas!(b"command")), set_genesis_seal, block_height_noun, seal_noun],
-            );
-            poke_slab.set_root(poke_noun);
-            poke_slab
-        }
-        SetupCommand::PokeSetBtcData => {
-            let mut poke_slab = NounSlab::new();
-            let poke_noun = T(
-                &mut poke_slab,
-                &[D(tas!(b"command")), D(tas!(b"btc-data")), D(0)],
-            );
-            poke_slab.set_root(poke_noun);
-            poke_slab
-        }
-    };
-
     nockapp
-        .poke(nockapp::wire::SystemWire.to_wire(), poke)
+        .poke(nockapp::wire::SystemWire.to_wire(), command.into_poke())
         .await?;
     Ok(())
 }
@@ -274,3 +342,25 @@ impl IntoSlab for BlockchainConstants {
         slab
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use nockapp::noun::slab::slab_noun_equality;
+
+    use super::*;
+
+    #[test]
+    fn test_mempool_max_bytes_poke() {
+        let mut expected: NounSlab = NounSlab::new();
+        let tag = make_tas(&mut expected, "set-mempool-max-bytes").as_noun();
+        let max_bytes = Atom::new(&mut expected, 300_000_000).as_noun();
+        let capped = T(&mut expected, &[D(0), max_bytes]);
+        let capped = T(&mut expected, &[D(tas!(b"command")), tag, capped]);
+        let uncapped = T(&mut expected, &[D(tas!(b"command")), tag, D(0)]);
+
+        let poke = SetupCommand::PokeSetMempoolMaxBytes(Some(300_000_000)).into_poke();
+        assert!(slab_noun_equality(unsafe { poke.root() }, &capped));
+        let poke = SetupCommand::PokeSetMempoolMaxBytes(None).into_poke();
+        assert!(slab_noun_equality(unsafe { poke.root() }, &uncapped));
+    }
+}
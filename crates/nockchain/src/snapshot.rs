@@ -0,0 +1,285 @@
+//! Fast sync from a snapshot of another node's chain.
+//!
+//! A snapshot is the kernel's `chain-snapshot`: every page of the heaviest chain up to some
+//! height, the balance there, and what it takes to validate the blocks after it, but no
+//! transactions. Nodes serve theirs as a jam from `GET /snapshot/{height}` on the JSON-RPC
+//! server, and its blake3 hash from `node_getSnapshotHash`. Z-maps are canonical, so two
+//! nodes agreeing on the chain up to a height serve byte-identical snapshots of it.
+//!
+//! Only a `--serve-snapshots` node serves them, at most [`RPC_SNAPSHOTS_PER_SEC`] a second,
+//! since each one walks the whole chain in the kernel.
+//!
+//! The kernel checks that a snapshot's pages hash to their ids, chain up to its tip, add up
+//! their work and match the mainnet checkpoints, and verifies the tip's powork, but it can't
+//! check the balance without replaying every transaction, which is what fast sync exists to
+//! avoid. The balance is trusted because the snapshot's hash matches one given on the command
+//! line, or the hashes of every other node asked, or both. A node fast syncs only if it has no
+//! blocks yet, and then syncs the blocks after the snapshot from peers as usual. It can't follow
+//! a reorg below the snapshot.
+//!
+//! The download is spooled to a temporary file and hashed as it arrives, and given up on past
+//! [`MAX_SNAPSHOT_BYTES`], so a source can't make the node buffer an arbitrary amount in memory.
+use std::io::{BufReader, Seek, SeekFrom};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use nockapp::driver::NockAppHandle;
+use nockapp::noun::slab::{Jammer, NounSlab};
+use nockapp::utils::make_tas;
+use nockapp::wire::{SystemWire, Wire};
+use nockapp::{Bytes, NockApp, NockAppError};
+use nockchain_libp2p_io::snapshot::Rate;
+use nockvm::noun::{D, T};
+use nockvm_macros::tas;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+use crate::rpc::{block_json, peek, RpcError};
+
+/// How far below the source's heaviest block to fast sync to, when no height is given. Peers
+/// asked for their hash must have the same block there, so it shouldn't be one a reorg can
+/// still replace.
+pub const FAST_SYNC_CONFIRMATIONS: u64 = 10;
+
+/// Snapshots a second the JSON-RPC server builds, for `GET /snapshot/{height}` and
+/// `node_getSnapshotHash` together
+pub const RPC_SNAPSHOTS_PER_SEC: u32 = 1;
+
+/// The largest snapshot fast sync downloads
+pub const MAX_SNAPSHOT_BYTES: u64 = 4 << 30;
+
+/// Where to fast sync from, and how to trust what arrives
+#[derive(Clone, Debug)]
+pub struct FastSync {
+    /// JSON-RPC server of the node to download the snapshot from
+    pub source: String,
+    /// Height to sync to, or the source's heaviest less [`FAST_SYNC_CONFIRMATIONS`]
+    pub height: Option<u64>,
+    /// Hex blake3 hash the snapshot must have
+    pub hash: Option<String>,
+    /// Other JSON-RPC servers whose snapshots at the same height must hash the same
+    pub peers: Vec<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("Request to {0} failed: {1}")]
+    Http(String, reqwest::Error),
+    #[error("{0} answered with an error: {1}")]
+    Rpc(String, String),
+    #[error("{0} has no snapshot at height {1}")]
+    Missing(String, u64),
+    #[error("Snapshot hashes to {got}, but {by} says {expected}")]
+    Mismatch {
+        by: String,
+        expected: String,
+        got: String,
+    },
+    #[error("Snapshot from {0} is over {MAX_SNAPSHOT_BYTES} bytes")]
+    TooLarge(String),
+    #[error("Cannot spool the snapshot to disk: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Snapshot is not a valid jam")]
+    Cue,
+    #[error("The kernel rejected the snapshot; its log says why")]
+    Rejected,
+    #[error(transparent)]
+    NockApp(#[from] NockAppError),
+}
+
+/// The jammed snapshot of the heaviest chain up to `height`, if there's a balance there
+pub(crate) async fn snapshot_jam(
+    handle: &NockAppHandle,
+    height: u64,
+) -> Result<Option<Bytes>, RpcError> {
+    if height >= 1 << 63 {
+        return Ok(None);
+    }
+    let snapshot = peek(handle, |slab| {
+        T(slab, &[D(tas!(b"snapshot")), D(height), D(0)])
+    })
+    .await?;
+    Ok(snapshot.map(|snapshot| snapshot.jam()))
+}
+
+/// Like [`snapshot_jam`], but [`RpcError::RateLimited`] if `rate` has run out
+pub(crate) async fn limited_snapshot_jam(
+    handle: &NockAppHandle,
+    rate: &Mutex<Rate>,
+    height: u64,
+) -> Result<Option<Bytes>, RpcError> {
+    if !rate
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take(Instant::now())
+    {
+        return Err(RpcError::RateLimited);
+    }
+    snapshot_jam(handle, height).await
+}
+
+/// Hex blake3 hash of a jammed snapshot
+pub fn snapshot_hash(jam: &[u8]) -> String {
+    blake3::hash(jam).to_hex().to_string()
+}
+
+/// Load a snapshot from `sync.source` into a node with no blocks, once it's checked out
+pub async fn fast_sync<J: Jammer + Send + 'static>(
+    nockapp: &mut NockApp<J>,
+    sync: &FastSync,
+) -> Result<(), SnapshotError> {
+    if let Some(height) = heaviest_height(nockapp).await? {
+        info!("Already have blocks up to height {height}, not fast syncing");
+        return Ok(());
+    }
+    let client = Client::new();
+    let height = match sync.height {
+        Some(height) => height,
+        None => {
+            let tip = call(&client, &sync.source, "chain_getHeaviestBlock", json!([])).await?;
+            let Some(tip_height) = tip["height"].as_u64() else {
+                return Err(SnapshotError::Missing(sync.source.clone(), 0));
+            };
+            tip_height.saturating_sub(FAST_SYNC_CONFIRMATIONS)
+        }
+    };
+
+    let url = format!("{}/snapshot/{height}", sync.source.trim_end_matches('/'));
+    info!("Downloading a snapshot at height {height} from {url}");
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| SnapshotError::Http(url.clone(), e))?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Err(SnapshotError::Missing(sync.source.clone(), height));
+    }
+    let mut response = response
+        .error_for_status()
+        .map_err(|e| SnapshotError::Http(url.clone(), e))?;
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_SNAPSHOT_BYTES)
+    {
+        return Err(SnapshotError::TooLarge(url));
+    }
+    let mut file = tokio::fs::File::from_std(tempfile::tempfile()?);
+    let mut hasher = blake3::Hasher::new();
+    let mut len = 0;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| SnapshotError::Http(url.clone(), e))?
+    {
+        len += chunk.len() as u64;
+        if len > MAX_SNAPSHOT_BYTES {
+            return Err(SnapshotError::TooLarge(url));
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    let hash = hasher.finalize().to_hex().to_string();
+
+    if let Some(expected) = &sync.hash {
+        agree("--fast-sync-hash", expected, &hash)?;
+    }
+    for peer in &sync.peers {
+        let answer = call(&client, peer, "node_getSnapshotHash", json!([height])).await?;
+        let Some(expected) = answer["hash"].as_str() else {
+            return Err(SnapshotError::Missing(peer.clone(), height));
+        };
+        agree(peer, expected, &hash)?;
+    }
+    info!(
+        "Snapshot {hash} checks out against {} hashes",
+        sync.peers.len() + usize::from(sync.hash.is_some())
+    );
+
+    let mut file = file.into_std().await;
+    file.seek(SeekFrom::Start(0))?;
+    let mut slab = NounSlab::new();
+    let snapshot = slab
+        .cue_from_reader(BufReader::new(file))
+        .map_err(|_| SnapshotError::Cue)?;
+    let tag = make_tas(&mut slab, "load-snapshot").as_noun();
+    let poke = T(&mut slab, &[D(tas!(b"command")), tag, snapshot]);
+    slab.set_root(poke);
+    nockapp.poke(SystemWire.to_wire(), slab).await?;
+    if heaviest_height(nockapp).await? != Some(height) {
+        return Err(SnapshotError::Rejected);
+    }
+    info!("Fast synced to height {height}");
+    Ok(())
+}
+
+fn agree(by: &str, expected: &str, got: &str) -> Result<(), SnapshotError> {
+    if expected.eq_ignore_ascii_case(got) {
+        Ok(())
+    } else {
+        Err(SnapshotError::Mismatch {
+            by: by.to_string(),
+            expected: expected.to_string(),
+            got: got.to_string(),
+        })
+    }
+}
+
+/// Make a JSON-RPC call and take its result
+pub(crate) async fn call(
+    client: &Client,
+    url: &str,
+    method: &str,
+    params: Value,
+) -> Result<Value, SnapshotError> {
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let reply: Value = client
+        .post(url)
+        .json(&request)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| SnapshotError::Http(url.to_string(), e))?
+        .json()
+        .await
+        .map_err(|e| SnapshotError::Http(url.to_string(), e))?;
+    if let Some(error) = reply.get("error") {
+        let message = error["message"].as_str().unwrap_or("unknown error");
+        return Err(SnapshotError::Rpc(url.to_string(), message.to_string()));
+    }
+    Ok(reply["result"].clone())
+}
+
+async fn heaviest_height<J: Jammer + Send + 'static>(
+    nockapp: &mut NockApp<J>,
+) -> Result<Option<u64>, SnapshotError> {
+    let mut path = NounSlab::new();
+    let tag = make_tas(&mut path, "heaviest-block").as_noun();
+    let root = T(&mut path, &[tag, D(0)]);
+    path.set_root(root);
+    let Some(page) = nockapp.peek_handle(path).await? else {
+        return Ok(None);
+    };
+    Ok(block_json(unsafe { *page.root() })
+        .ok()
+        .and_then(|block| block["height"].as_u64()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agree() {
+        let hash = snapshot_hash(b"snapshot");
+        assert_eq!(hash.len(), 64);
+        assert!(agree("peer", &hash.to_uppercase(), &hash).is_ok());
+        assert!(matches!(
+            agree("peer", &snapshot_hash(b"other"), &hash),
+            Err(SnapshotError::Mismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,170 @@
+//! `nockchain status`: a one-screen report on a running node.
+//!
+//! The report comes from the node's `node_status` JSON-RPC method, so the node must be serving
+//! `--rpc-addr`. `--json` prints the method's result as it is, for scripts. The node counts as
+//! synced when its heaviest block is at most `--max-block-age` old, as `/readyz` does by default.
+use clap::Args;
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use crate::snapshot::{call, SnapshotError};
+
+#[derive(Args, Debug, Clone)]
+pub struct StatusArgs {
+    #[arg(
+        long,
+        default_value = "http://127.0.0.1:8545",
+        help = "JSON-RPC server of the node"
+    )]
+    pub rpc: String,
+    #[arg(long, help = "Print the report as JSON")]
+    pub json: bool,
+    #[arg(
+        long,
+        default_value_t = 3600,
+        help = "Seconds old the heaviest block may be for the node to count as synced"
+    )]
+    pub max_block_age: u64,
+}
+
+pub async fn run(args: &StatusArgs) -> Result<(), SnapshotError> {
+    let status = call(&Client::new(), &args.rpc, "node_status", json!([])).await?;
+    if args.json {
+        println!("{}", status);
+    } else {
+        print!("{}", report(&status, args.max_block_age));
+    }
+    Ok(())
+}
+
+/// The `node_status` result as aligned lines
+fn report(status: &Value, max_block_age: u64) -> String {
+    let tip = &status["tip"];
+    let (tip_line, sync_line) = match tip["height"].as_u64() {
+        Some(height) => {
+            let id = tip["id"].as_str().unwrap_or("?");
+            match tip["age"].as_u64() {
+                Some(age) if age <= max_block_age => (
+                    format!("height {} {} ({} ago)", height, id, duration(age)),
+                    "synced".to_string(),
+                ),
+                Some(age) => (
+                    format!("height {} {} ({} ago)", height, id, duration(age)),
+                    format!("catching up, the tip is {} old", duration(age)),
+                ),
+                None => (format!("height {} {}", height, id), "unknown".to_string()),
+            }
+        }
+        None => (
+            "no blocks yet".to_string(),
+            "waiting for genesis".to_string(),
+        ),
+    };
+    let peers = match status["peers"].as_u64() {
+        Some(peers) => peers.to_string(),
+        None => "no libp2p driver".to_string(),
+    };
+    let mempool = &status["mempool"];
+    let mempool = format!(
+        "{} transactions, {}",
+        mempool["count"].as_u64().unwrap_or(0),
+        bytes(mempool["bytes"].as_u64().unwrap_or(0))
+    );
+    let mining = &status["mining"];
+    let mining = if mining.is_null() {
+        "off".to_string()
+    } else {
+        format!(
+            "{} threads, {:.2} attempts/s, {} accepted, {} stale",
+            mining["threads"].as_u64().unwrap_or(0),
+            mining["hashrate"].as_f64().unwrap_or(0.0),
+            mining["accepted"].as_u64().unwrap_or(0),
+            mining["stale"].as_u64().unwrap_or(0)
+        )
+    };
+    let checkpoint = match status["checkpointAge"].as_u64() {
+        Some(age) => format!("written {} ago", duration(age)),
+        None => "none yet".to_string(),
+    };
+    let uptime = duration(status["uptime"].as_u64().unwrap_or(0));
+    [
+        ("tip", tip_line),
+        ("sync", sync_line),
+        ("peers", peers),
+        ("mempool", mempool),
+        ("mining", mining),
+        ("checkpoint", checkpoint),
+        ("uptime", uptime),
+    ]
+    .iter()
+    .map(|(label, value)| format!("{:<12}{}\n", label, value))
+    .collect()
+}
+
+/// `secs` in its two largest units, like `3h 5m`
+fn duration(secs: u64) -> String {
+    let units = [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)];
+    let Some(first) = units.iter().position(|(_, size)| secs >= *size) else {
+        return "0s".to_string();
+    };
+    let (unit, size) = units[first];
+    let mut out = format!("{}{}", secs / size, unit);
+    if let Some((next, next_size)) = units.get(first + 1) {
+        let rest = secs % size / next_size;
+        if rest > 0 {
+            out.push_str(&format!(" {}{}", rest, next));
+        }
+    }
+    out
+}
+
+fn bytes(bytes: u64) -> String {
+    if bytes < 1 << 10 {
+        format!("{} B", bytes)
+    } else if bytes < 1 << 20 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / 1048576.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        assert_eq!(duration(0), "0s");
+        assert_eq!(duration(59), "59s");
+        assert_eq!(duration(3600 * 3 + 300 + 7), "3h 5m");
+        assert_eq!(duration(86400 * 2), "2d");
+        assert_eq!(bytes(35_328), "34.5 KiB");
+
+        let status = json!({
+            "tip": { "id": "9xQe", "height": 1200, "timestamp": 1_750_000_000u64, "age": 90 },
+            "peers": 8,
+            "mempool": { "count": 3, "bytes": 35_328 },
+            "mining": null,
+            "checkpointAge": 240,
+            "uptime": 90_000,
+        });
+        assert_eq!(
+            report(&status, 3600),
+            "tip         height 1200 9xQe (1m 30s ago)\n\
+             sync        synced\n\
+             peers       8\n\
+             mempool     3 transactions, 34.5 KiB\n\
+             mining      off\n\
+             checkpoint  written 4m ago\n\
+             uptime      1d 1h\n"
+        );
+        assert!(report(&status, 60).contains("catching up, the tip is 1m 30s old"));
+
+        let empty = json!({ "tip": null, "peers": null, "mempool": { "count": 0, "bytes": 0 },
+            "mining": null, "checkpointAge": null, "uptime": 5 });
+        let lines = report(&empty, 3600);
+        assert!(lines.contains("no blocks yet"));
+        assert!(lines.contains("no libp2p driver"));
+        assert!(lines.contains("none yet"));
+    }
+}
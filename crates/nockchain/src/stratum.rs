@@ -0,0 +1,380 @@
+//! Stratum-style mining server.
+//!
+//! With `--stratum-addr`, the node hands out work over TCP to miners in other processes or on
+//! other machines, and takes back what they find, so one node can coordinate many of them. As in
+//! stratum, each line is a JSON-RPC 2.0 message, and the miner starts the conversation:
+//!
+//! | Method             | Params         | Result                                     |
+//! |--------------------|----------------|--------------------------------------------|
+//! | `mining.subscribe` | `[agent?]`     | `{session, noncePrefix}`                   |
+//! | `mining.authorize` | `[worker]`     | `true`                                     |
+//! | `mining.submit`    | `[jobId, jam]` | `{share, block, stale}`                    |
+//!
+//! Once subscribed, a miner gets a `mining.notify` notification with params `[job]` for the
+//! current candidate block and for each that replaces it. A job is what `mining_getBlockTemplate`
+//! returns (see [`crate::rpc`]) with a `jobId` and a `shareTarget`, and replaces any job before it,
+//! since the kernel only takes a proof of its latest candidate. Each session gets its own
+//! `noncePrefix`, the first belt of every nonce it tries, so no two sessions repeat work.
+//!
+//! A miner submits the jam of each `[%pow proof digest commitment nonce]` whose digest meets
+//! `shareTarget`, [`SHARE_FACTOR`] times the block target. It's a share, logged under the worker's
+//! name, and one that meets `target` as well is a block, which goes to the kernel to be checked
+//! like any other. Shares are counted on the miner's word, since checking a proof takes as long
+//! as checking a block, so they show how much work a worker does, but aren't yet fit to pay out
+//! on. The node needs `--mining-pubkey` for there to be work, and no work is handed out until
+//! then.
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ibig::UBig;
+use nockapp::driver::{make_driver, IODriverFn, NockAppHandle};
+use nockapp::noun::slab::NounSlab;
+use nockapp::{Bytes, NockAppError, NounExt};
+use nockchain_libp2p_io::tip5_util::tip5_hash_to_base58;
+use nockvm::noun::Slots;
+use rand::Rng;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, info, warn};
+use zkvm_jetpack::form::PRIME;
+
+use crate::rpc::{
+    block_template, from_hex, gossip_fact, parse_call, string_param, submit_block, RpcError,
+};
+
+/// How many times easier a share is to find than a block
+pub const SHARE_FACTOR: u64 = 256;
+
+/// How often to look for a new candidate block, besides whenever a block or transaction comes in
+pub const JOB_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Longest line a miner may send, enough for a jammed proof
+pub const MAX_LINE: usize = 16 * 1024 * 1024;
+
+/// A candidate block to mine
+#[derive(Debug)]
+struct Job {
+    id: String,
+    commitment: String,
+    target: UBig,
+    share_target: UBig,
+    /// The `mining.notify` params
+    notify: Value,
+}
+
+impl Job {
+    fn new(id: String, mut template: Value, commitment: String) -> Option<Self> {
+        let target = UBig::from_str(template["target"].as_str()?).ok()?;
+        let share_target = &target * UBig::from(SHARE_FACTOR);
+        template["jobId"] = json!(id);
+        template["shareTarget"] = json!(share_target.to_string());
+        Some(Job {
+            id,
+            commitment,
+            target,
+            share_target,
+            notify: json!([template]),
+        })
+    }
+}
+
+/// Serve miners on `addr`
+pub fn make_stratum_driver(addr: SocketAddr) -> IODriverFn {
+    make_driver(move |handle| async move {
+        let handle = Arc::new(handle);
+        let listener = TcpListener::bind(addr)
+            .await
+            .map_err(NockAppError::IoError)?;
+        let local_addr = listener.local_addr().map_err(NockAppError::IoError)?;
+        info!("Serving stratum mining on {}", local_addr);
+
+        let (jobs, _) = watch::channel(None);
+        let mut next_job = 0;
+        let mut interval = tokio::time::interval(JOB_INTERVAL);
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, peer)) => {
+                        tokio::spawn(session(handle.clone(), jobs.subscribe(), stream, peer));
+                    }
+                    Err(e) => warn!("Could not accept a stratum connection: {}", e),
+                },
+                effect = handle.next_effect() => match effect {
+                    Ok(effect) if gossip_fact(&effect).is_some() => {
+                        refresh(&handle, &jobs, &mut next_job).await;
+                    }
+                    Ok(_) | Err(NockAppError::BroadcastRecvLaggedError(_)) => {}
+                    Err(e) => return Err(e),
+                },
+                _ = interval.tick() => refresh(&handle, &jobs, &mut next_job).await,
+            }
+        }
+    })
+}
+
+/// Make a new job if the candidate block changed
+async fn refresh(
+    handle: &NockAppHandle,
+    jobs: &watch::Sender<Option<Arc<Job>>>,
+    next_job: &mut u64,
+) {
+    let template = match block_template(handle).await {
+        Ok(template) => template,
+        Err(e) => {
+            debug!("Could not get a block template: {}", e);
+            return;
+        }
+    };
+    let Some((template, commitment)) = template else {
+        jobs.send_if_modified(|job| job.take().is_some());
+        return;
+    };
+    if jobs
+        .borrow()
+        .as_ref()
+        .is_some_and(|job| job.commitment == commitment)
+    {
+        return;
+    }
+    *next_job += 1;
+    let Some(job) = Job::new(next_job.to_string(), template, commitment) else {
+        warn!("Could not read the block template");
+        return;
+    };
+    debug!("New stratum job {}", job.id);
+    jobs.send_replace(Some(Arc::new(job)));
+}
+
+/// What a miner's connection has told us
+struct Session {
+    peer: SocketAddr,
+    /// The first belt of the nonces it tries, once it subscribes
+    prefix: Option<u64>,
+    worker: String,
+    shares: u64,
+    blocks: u64,
+}
+
+async fn session(
+    handle: Arc<NockAppHandle>,
+    mut jobs: watch::Receiver<Option<Arc<Job>>>,
+    stream: TcpStream,
+    peer: SocketAddr,
+) {
+    debug!("Stratum miner connected from {}", peer);
+    let (reader, mut writer) = stream.into_split();
+    let (lines_tx, mut lines) = mpsc::channel(16);
+    let reading = tokio::spawn(read_lines(reader, peer, lines_tx));
+    let mut session = Session {
+        peer,
+        prefix: None,
+        worker: peer.to_string(),
+        shares: 0,
+        blocks: 0,
+    };
+    loop {
+        let reply = tokio::select! {
+            line = lines.recv() => {
+                let Some(line) = line else {
+                    break;
+                };
+                let subscribed = session.prefix.is_some();
+                let reply = handle_line(&handle, &mut session, &jobs, &line).await;
+                if !subscribed && session.prefix.is_some() {
+                    // a new subscriber gets the current job right after its subscription
+                    jobs.mark_changed();
+                }
+                reply
+            }
+            changed = jobs.changed(), if session.prefix.is_some() => {
+                if changed.is_err() {
+                    break;
+                }
+                let job = jobs.borrow_and_update().clone();
+                job.map(|job| {
+                    json!({ "jsonrpc": "2.0", "method": "mining.notify", "params": job.notify })
+                })
+            }
+        };
+        if let Some(reply) = reply {
+            let mut reply = reply.to_string();
+            reply.push('\n');
+            if writer.write_all(reply.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    }
+    reading.abort();
+    info!(
+        "Stratum miner {} disconnected after {} shares and {} blocks",
+        session.worker, session.shares, session.blocks
+    );
+}
+
+/// Send on each line `reader` reads, until it closes or sends one over [`MAX_LINE`]
+async fn read_lines(reader: OwnedReadHalf, peer: SocketAddr, lines: mpsc::Sender<Vec<u8>>) {
+    let mut reader = BufReader::new(reader);
+    loop {
+        let mut line = Vec::new();
+        match (&mut reader)
+            .take(MAX_LINE as u64)
+            .read_until(b'\n', &mut line)
+            .await
+        {
+            Ok(_) if line.ends_with(b"\n") => {
+                if lines.send(line).await.is_err() {
+                    return;
+                }
+            }
+            Ok(_) => {
+                if line.len() >= MAX_LINE {
+                    warn!("Stratum miner {} sent a line over {} bytes", peer, MAX_LINE);
+                }
+                return;
+            }
+            Err(e) => {
+                debug!("Stratum connection from {} failed: {}", peer, e);
+                return;
+            }
+        }
+    }
+}
+
+/// Answer one line, or `None` for a notification
+async fn handle_line(
+    handle: &NockAppHandle,
+    session: &mut Session,
+    jobs: &watch::Receiver<Option<Arc<Job>>>,
+    line: &[u8],
+) -> Option<Value> {
+    let (id, result) = match serde_json::from_slice::<Value>(line) {
+        Err(e) => (Some(Value::Null), Err(RpcError::Parse(e.to_string()))),
+        Ok(call) => match parse_call(&call) {
+            Err(e) => (Some(Value::Null), Err(e)),
+            Ok((id, method, params)) => {
+                (id, dispatch(handle, session, jobs, method, &params).await)
+            }
+        },
+    };
+    let id = id?;
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => e.to_json(id),
+    })
+}
+
+async fn dispatch(
+    handle: &NockAppHandle,
+    session: &mut Session,
+    jobs: &watch::Receiver<Option<Arc<Job>>>,
+    method: &str,
+    params: &[Value],
+) -> Result<Value, RpcError> {
+    match method {
+        "mining.subscribe" => {
+            let prefix = *session
+                .prefix
+                .get_or_insert_with(|| rand::thread_rng().gen::<u64>() % PRIME);
+            Ok(json!({ "session": session.peer.to_string(), "noncePrefix": prefix.to_string() }))
+        }
+        "mining.authorize" => {
+            session.worker = string_param(params, 0, "worker")?.to_string();
+            info!("Stratum miner {} is {}", session.peer, session.worker);
+            Ok(json!(true))
+        }
+        "mining.submit" => {
+            let prefix = session
+                .prefix
+                .ok_or(RpcError::InvalidRequest("subscribe first"))?;
+            let job_id = string_param(params, 0, "jobId")?;
+            let jam = from_hex(string_param(params, 1, "jam")?)
+                .ok_or_else(|| RpcError::InvalidParams("jam must be hex".into()))?;
+            let job = jobs.borrow().clone();
+            let Some(job) = job.filter(|job| job.id == job_id) else {
+                return Ok(json!({ "share": false, "block": false, "stale": true }));
+            };
+            let digest = check_share(&job, prefix, &jam)?;
+            if digest > job.share_target {
+                return Ok(json!({ "share": false, "block": false, "stale": false }));
+            }
+            session.shares += 1;
+            debug!(
+                "Share {} from stratum miner {}",
+                session.shares, session.worker
+            );
+            if digest > job.target {
+                return Ok(json!({ "share": true, "block": false, "stale": false }));
+            }
+            let result = submit_block(handle, jam).await?;
+            let block = result["accepted"].as_bool().unwrap_or(false);
+            if block {
+                session.blocks += 1;
+                info!(
+                    "Stratum miner {} found block {}",
+                    session.worker, result["id"]
+                );
+            }
+            Ok(json!({ "share": true, "block": block, "stale": result["stale"] }))
+        }
+        _ => Err(RpcError::MethodNotFound(method.to_string())),
+    }
+}
+
+/// The digest of a `[%pow proof digest commitment nonce]` for `job`, with a nonce from the
+/// session's range
+fn check_share(job: &Job, prefix: u64, jam: &[u8]) -> Result<UBig, RpcError> {
+    let invalid = |reason: &str| RpcError::InvalidParams(reason.to_string());
+    let mut slab = NounSlab::new();
+    let pow = slab
+        .cue_into(Bytes::copy_from_slice(jam))
+        .map_err(|e| invalid(&format!("jam does not cue: {}", e)))?;
+    let not_pow = || invalid("not a [%pow proof digest commitment nonce]");
+    if !pow.slot(2).is_ok_and(|tag| tag.eq_bytes("pow")) {
+        return Err(not_pow());
+    }
+    let commitment = pow
+        .slot(30)
+        .ok()
+        .and_then(|commit| tip5_hash_to_base58(commit).ok())
+        .ok_or_else(not_pow)?;
+    if commitment != job.commitment {
+        return Err(invalid("the commitment is not the job's"));
+    }
+    let first_belt = pow
+        .slot(62)
+        .ok()
+        .and_then(|belt| belt.as_atom().ok()?.as_u64().ok())
+        .ok_or_else(not_pow)?;
+    if first_belt != prefix {
+        return Err(invalid(
+            "the nonce does not start with the session's noncePrefix",
+        ));
+    }
+    let digest = pow
+        .slot(14)
+        .ok()
+        .and_then(|digest| digest.as_atom().ok())
+        .ok_or_else(not_pow)?;
+    Ok(UBig::from_le_bytes(&digest.to_le_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_targets() {
+        let template = json!({ "target": "1000", "height": 7 });
+        let job = Job::new("3".into(), template, "commit".into()).expect("job");
+        assert_eq!(job.target, UBig::from(1000u64));
+        assert_eq!(job.share_target, UBig::from(1000 * SHARE_FACTOR));
+        assert_eq!(job.notify[0]["jobId"], json!("3"));
+        assert_eq!(job.notify[0]["height"], json!(7));
+        assert!(Job::new("4".into(), json!({}), "commit".into()).is_none());
+    }
+}
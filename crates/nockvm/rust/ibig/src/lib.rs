@@ -99,6 +99,7 @@ mod shift;
 mod shift_ops;
 mod sign;
 mod ubig;
+pub mod words;
 
 #[cfg(feature = "rand")]
 pub mod rand;
@@ -4,10 +4,12 @@ use alloc::alloc::Layout;
 use core::marker::PhantomData;
 use core::{mem, slice};
 
-/// Chunk of memory directly allocated from the global allocator.
+/// Chunk of memory directly allocated from the global allocator, or from a [`Stack`].
 pub(crate) struct MemoryAllocation {
     layout: Layout,
     start: *mut u8,
+    /// Whether the memory belongs to a stack, which frees it itself
+    on_stack: bool,
 }
 
 pub trait Stack: Sized {
@@ -44,7 +46,11 @@ impl MemoryAllocation {
             ptr
         };
 
-        MemoryAllocation { layout, start }
+        MemoryAllocation {
+            layout,
+            start,
+            on_stack: true,
+        }
     }
 
     /// Allocate memory.
@@ -63,7 +69,11 @@ impl MemoryAllocation {
             ptr
         };
 
-        MemoryAllocation { layout, start }
+        MemoryAllocation {
+            layout,
+            start,
+            on_stack: false,
+        }
     }
 
     /// Get memory.
@@ -79,7 +89,7 @@ impl MemoryAllocation {
 
 impl Drop for MemoryAllocation {
     fn drop(&mut self) {
-        if self.layout.size() != 0 {
+        if self.layout.size() != 0 && !self.on_stack {
             // Safe because the memory was allocated with the same layout.
             unsafe { alloc::alloc::dealloc(self.start, self.layout) };
         }
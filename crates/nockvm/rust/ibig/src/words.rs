@@ -0,0 +1,98 @@
+//! Arithmetic on borrowed word slices.
+//!
+//! These operate on little-endian slices of words that the caller owns, so a caller that keeps
+//! its numbers in its own memory can compute on them without copying them into a [`UBig`] and
+//! the result back out. Inputs may have zero high words. Outputs are written in full, so they may
+//! have zero high words, but their contents on entry don't matter.
+//!
+//! [`UBig`]: crate::UBig
+
+use crate::memory::{self, MemoryAllocation, Stack};
+use crate::sign::Sign::Positive;
+use crate::{add, arch, div, mul, shift};
+
+/// A machine word, the unit the slices are made of
+pub type Word = arch::word::Word;
+
+/// `words` without its zero high words.
+fn trim(words: &[Word]) -> &[Word] {
+    let len = words
+        .iter()
+        .rposition(|word| *word != 0)
+        .map_or(0, |i| i + 1);
+    &words[..len]
+}
+
+/// `out = a + b`
+///
+/// `out` must be longer than both `a` and `b`.
+pub fn add(out: &mut [Word], a: &[Word], b: &[Word]) {
+    let (a, b) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    assert!(out.len() > a.len());
+    let (low, high) = out.split_at_mut(a.len());
+    low.copy_from_slice(a);
+    high.fill(0);
+    if add::add_in_place(low, b) {
+        high[0] = 1;
+    }
+}
+
+/// `out = a * b`, allocating scratch space on `stack`
+///
+/// `out` must be at least `a.len() + b.len()` words long.
+pub fn mul_stack<S: Stack>(stack: &mut S, out: &mut [Word], a: &[Word], b: &[Word]) {
+    assert!(out.len() >= a.len() + b.len());
+    let (a, b) = (trim(a), trim(b));
+    let (a, b) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    out.fill(0);
+    match b.len() {
+        0 => {}
+        1 => {
+            out[..a.len()].copy_from_slice(a);
+            out[a.len()] = mul::mul_word_in_place(&mut out[..a.len()], b[0]);
+        }
+        _ => {
+            let res_len = a.len() + b.len();
+            let mut allocation =
+                MemoryAllocation::new_stack(stack, mul::memory_requirement_exact(res_len, b.len()));
+            let mut memory = allocation.memory();
+            let overflow = mul::add_signed_mul(&mut out[..res_len], Positive, a, b, &mut memory);
+            assert!(overflow == 0);
+        }
+    }
+}
+
+/// `out = a % b`, allocating scratch space on `stack`
+///
+/// `b` must not be zero, and `out` must be at least as long as `b` without its zero high words.
+pub fn rem_stack<S: Stack>(stack: &mut S, out: &mut [Word], a: &[Word], b: &[Word]) {
+    let (a, b) = (trim(a), trim(b));
+    assert!(!b.is_empty(), "divide by zero");
+    assert!(out.len() >= b.len());
+    out.fill(0);
+    if a.len() < b.len() {
+        out[..a.len()].copy_from_slice(a);
+    } else if b.len() == 1 {
+        out[0] = div::rem_by_word(a, b[0]);
+    } else {
+        // Division happens in place on a normalized copy of both operands, with room for the
+        // shifted-out high bits of the dividend
+        let layout = memory::add_layout(
+            memory::array_layout::<Word>(a.len() + 1 + b.len()),
+            div::memory_requirement_exact(a.len() + 1, b.len()),
+        );
+        let mut allocation = MemoryAllocation::new_stack(stack, layout);
+        let mut memory = allocation.memory();
+        let (rhs, mut memory) = memory.allocate_slice_copy(b);
+        let (shift, fast_div_rhs_top) = div::normalize_large(rhs);
+        let (lhs, mut memory) = memory.allocate_slice_copy_fill(a.len() + 1, a, 0);
+        let carry = shift::shl_in_place(&mut lhs[..a.len()], shift);
+        lhs[a.len()] = carry;
+        // Only the remainder is wanted, not the quotient or its carry
+        let _overflow = div::div_rem_in_place(lhs, rhs, fast_div_rhs_top, &mut memory);
+        let rem = &mut out[..b.len()];
+        rem.copy_from_slice(&lhs[..b.len()]);
+        let low_bits = shift::shr_in_place(rem, shift);
+        debug_assert!(low_bits == 0);
+    }
+}
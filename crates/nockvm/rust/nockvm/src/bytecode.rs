@@ -0,0 +1,491 @@
+//! A bytecode tier for hot formulas.
+//!
+//! While a [`Bytecode`] tier is set on the context, the interpreter counts how often each formula
+//! is called by Nock 2 or 9, keyed by the formula's mug. Once a formula has been called
+//! [`Bytecode::threshold`] times it is compiled into a linear [`Program`], and later calls run the
+//! program in place of walking the formula.
+//!
+//! Only formulas made of Nock 0, 1, 3 through 8, 10 and autocons compile. Their programs never
+//! call, hint or scry, so a program runs to completion in time linear in its length, without
+//! pushing interpreter frames. Formulas that do anything else stay with the tree-walking
+//! interpreter, as does everything while a computation is metered, traced or profiled, so those
+//! see every step.
+//!
+//! Compiled programs are looked up in a [`Hamt`] keyed by the formula itself, so two formulas
+//! with the same mug never share a program, and a formula that doesn't compile is recorded there
+//! too, so it doesn't keep a formula with the same mug from compiling. The table lives on the
+//! NockStack, like the memo cache, and is restored along with it when a computation bails, which
+//! drops the programs compiled since.
+use std::collections::HashMap;
+
+use bitvec::prelude::{BitSlice, Lsb0};
+
+use crate::hamt::Hamt;
+use crate::interpreter::{edit, inc, Error, Mote, Result};
+use crate::mem::{NockStack, Preserve};
+use crate::mug::mug_u32;
+use crate::noun::{Noun, Slots, D, T};
+use crate::unifying_equality::unifying_equality;
+
+crate::gdb!();
+
+/// Formulas nested deeper than this don't compile, so the axis of every part fits in a u64
+const MAX_DEPTH: usize = 62;
+
+/// The longest program compiled
+const MAX_OPS: usize = 4096;
+
+/// The most programs compiled. Past this, cold formulas stay cold.
+const MAX_PROGRAMS: usize = 1 << 16;
+
+/// The most formulas recorded as not compiling. Past this, they are tried again once hot again.
+const MAX_COLD: usize = 1 << 16;
+
+/// The most mugs whose calls are counted. Past this, the counts start over.
+const MAX_HEAT: usize = 1 << 20;
+
+/// The table entry of a formula that doesn't compile
+const COLD: u32 = u32::MAX;
+
+const BAIL_EXIT: Result = Err(Error::Deterministic(Mote::Exit, D(0)));
+const BAIL_FAIL: Result = Err(Error::NonDeterministic(Mote::Fail, D(0)));
+
+/// An instruction of a compiled formula, working on a stack of nouns and the current subject
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Op {
+    /// Push the subject at an axis
+    Slot(u64),
+    /// Push the formula at an axis, for the constant of a Nock 1
+    Quote(u64),
+    /// Pop a tail and a head and push their cell
+    Cons,
+    IsCell,
+    Inc,
+    Eq,
+    /// Pop a patch and a tree and push the tree, edited at an axis
+    Edit(u64),
+    /// Pop a loobean, jumping to an instruction if it's no
+    Branch(usize),
+    Jump(usize),
+    /// Pop a noun and make it the subject, saving the subject
+    Push,
+    /// Pop a noun and pin it to the subject, saving the subject
+    Pin,
+    /// Restore the last subject saved
+    Pop,
+}
+
+pub struct Program {
+    ops: Vec<Op>,
+}
+
+impl Program {
+    /// Compile `formula`, or `None` if it calls, hints, scries or is too big.
+    pub fn compile(formula: Noun) -> Option<Program> {
+        let mut program = Program { ops: Vec::new() };
+        program.emit(formula, 1, 0)?;
+        Some(program)
+    }
+
+    fn op(&mut self, op: Op) -> Option<()> {
+        if self.ops.len() >= MAX_OPS {
+            return None;
+        }
+        self.ops.push(op);
+        Some(())
+    }
+
+    /// Emit `formula`, found at `axis` of the compiled formula
+    fn emit(&mut self, formula: Noun, axis: u64, depth: usize) -> Option<()> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let cell = formula.as_cell().ok()?;
+        let head_axis = axis << 1;
+        let tail_axis = (axis << 1) | 1;
+        let arg = |n: u64| (tail_axis << 1) | n;
+        let head = match cell.head().as_atom() {
+            Ok(atom) => atom.as_direct().ok()?.data(),
+            Err(_) => {
+                self.emit(cell.head(), head_axis, depth + 1)?;
+                self.emit(cell.tail(), tail_axis, depth + 1)?;
+                return self.op(Op::Cons);
+            }
+        };
+        match head {
+            0 => {
+                let axis = cell.tail().as_atom().ok()?.as_direct().ok()?.data();
+                self.op(Op::Slot(axis))
+            }
+            1 => self.op(Op::Quote(tail_axis)),
+            3 | 4 => {
+                self.emit(cell.tail(), tail_axis, depth + 1)?;
+                self.op(if head == 3 { Op::IsCell } else { Op::Inc })
+            }
+            5 => {
+                let args = cell.tail().as_cell().ok()?;
+                self.emit(args.head(), arg(0), depth + 2)?;
+                self.emit(args.tail(), arg(1), depth + 2)?;
+                self.op(Op::Eq)
+            }
+            6 => {
+                let args = cell.tail().as_cell().ok()?;
+                let branches = args.tail().as_cell().ok()?;
+                self.emit(args.head(), arg(0), depth + 2)?;
+                let branch = self.ops.len();
+                self.op(Op::Branch(0))?;
+                self.emit(branches.head(), arg(1) << 1, depth + 3)?;
+                let jump = self.ops.len();
+                self.op(Op::Jump(0))?;
+                self.ops[branch] = Op::Branch(self.ops.len());
+                self.emit(branches.tail(), (arg(1) << 1) | 1, depth + 3)?;
+                self.ops[jump] = Op::Jump(self.ops.len());
+                Some(())
+            }
+            7 | 8 => {
+                let args = cell.tail().as_cell().ok()?;
+                self.emit(args.head(), arg(0), depth + 2)?;
+                self.op(if head == 7 { Op::Push } else { Op::Pin })?;
+                self.emit(args.tail(), arg(1), depth + 2)?;
+                self.op(Op::Pop)
+            }
+            10 => {
+                let args = cell.tail().as_cell().ok()?;
+                let patch = args.head().as_cell().ok()?;
+                let edit_axis = patch.head().as_atom().ok()?.as_direct().ok()?.data();
+                if edit_axis == 0 {
+                    return None;
+                }
+                self.emit(args.tail(), arg(1), depth + 2)?;
+                self.emit(patch.tail(), (arg(0) << 1) | 1, depth + 3)?;
+                self.op(Op::Edit(edit_axis))
+            }
+            _ => None,
+        }
+    }
+
+    /// Run the program compiled from `formula` against `subject`.
+    pub fn run(&self, stack: &mut NockStack, mut subject: Noun, formula: Noun) -> Result {
+        let mut values: Vec<Noun> = Vec::new();
+        let mut subjects: Vec<Noun> = Vec::new();
+        let mut pc = 0;
+        while let Some(op) = self.ops.get(pc) {
+            pc += 1;
+            match *op {
+                Op::Slot(axis) => values.push(subject.slot(axis)?),
+                Op::Quote(axis) => values.push(formula.slot(axis)?),
+                Op::Cons => {
+                    let tail = pop(&mut values)?;
+                    let head = pop(&mut values)?;
+                    values.push(T(stack, &[head, tail]));
+                }
+                Op::IsCell => {
+                    let noun = pop(&mut values)?;
+                    values.push(if noun.is_cell() { D(0) } else { D(1) });
+                }
+                Op::Inc => {
+                    // Cannot increment (Nock 4) a cell
+                    let atom = pop(&mut values)?.as_atom()?;
+                    values.push(inc(stack, atom).as_noun());
+                }
+                Op::Eq => {
+                    let mut right = pop(&mut values)?;
+                    let mut left = pop(&mut values)?;
+                    let equal = unsafe { unifying_equality(stack, &mut right, &mut left) };
+                    values.push(if equal { D(0) } else { D(1) });
+                }
+                Op::Edit(axis) => {
+                    let patch = pop(&mut values)?;
+                    let tree = pop(&mut values)?;
+                    let axis = BitSlice::<u64, Lsb0>::from_element(&axis);
+                    values.push(edit(stack, axis, patch, tree));
+                }
+                Op::Branch(target) => {
+                    // Test branch of Nock 6 must return 0 or 1
+                    match pop(&mut values)?.as_atom()?.as_direct()?.data() {
+                        0 => {}
+                        1 => pc = target,
+                        _ => return BAIL_EXIT,
+                    }
+                }
+                Op::Jump(target) => pc = target,
+                Op::Push => {
+                    subjects.push(subject);
+                    subject = pop(&mut values)?;
+                }
+                Op::Pin => {
+                    subjects.push(subject);
+                    let pin = pop(&mut values)?;
+                    subject = T(stack, &[pin, subject]);
+                }
+                Op::Pop => subject = pop(&mut subjects)?,
+            }
+        }
+        pop(&mut values)
+    }
+}
+
+/// Compiled programs never pop more than they pushed, so running out is a bug in the compiler,
+/// which fails the computation rather than the process.
+fn pop(values: &mut Vec<Noun>) -> Result {
+    values.pop().map_or(BAIL_FAIL, Ok)
+}
+
+#[derive(Copy, Clone)]
+enum Heat {
+    /// Called this many times without compiling
+    Warming(u32),
+    /// Compiled, or found not to compile, so look the formula up in the table
+    Decided,
+}
+
+pub struct Bytecode {
+    /// How many calls make a formula hot
+    pub threshold: u32,
+    /// Heat by mug, at most [`MAX_HEAT`] entries
+    heat: HashMap<u32, Heat>,
+    /// Index into `programs` of each compiled formula, or [`COLD`] if it doesn't compile
+    compiled: Hamt<u32>,
+    programs: Vec<Program>,
+    /// Formulas recorded as [`COLD`]
+    cold: usize,
+}
+
+impl Bytecode {
+    /// The table is allocated on `stack`, and must be preserved with the context's caches.
+    pub fn new(stack: &mut NockStack, threshold: u32) -> Self {
+        Bytecode {
+            threshold,
+            heat: HashMap::new(),
+            compiled: Hamt::new(stack),
+            programs: Vec::new(),
+            cold: 0,
+        }
+    }
+
+    pub fn programs(&self) -> usize {
+        self.programs.len()
+    }
+
+    /// Record a call of `formula`, returning its program if it's hot.
+    pub(crate) fn call(&mut self, stack: &mut NockStack, mut formula: Noun) -> Option<&Program> {
+        // Atoms and trivial formulas aren't worth the lookup
+        formula.as_cell().ok()?.tail().as_cell().ok()?;
+        let mug = mug_u32(stack, formula);
+        if self.heat.len() >= MAX_HEAT && !self.heat.contains_key(&mug) {
+            // Decided formulas are still in the table, so only the counts are lost
+            self.heat.clear();
+        }
+        let heat = self.heat.entry(mug).or_insert(Heat::Warming(0));
+        match *heat {
+            Heat::Decided => match self.compiled.lookup(stack, &mut formula) {
+                Some(COLD) => None,
+                Some(index) => self.programs.get(index as usize),
+                None => {
+                    // Decided by a computation that bailed, or another formula with this mug
+                    *heat = Heat::Warming(0);
+                    None
+                }
+            },
+            Heat::Warming(calls) if calls + 1 < self.threshold => {
+                *heat = Heat::Warming(calls + 1);
+                None
+            }
+            Heat::Warming(_) => {
+                *heat = Heat::Decided;
+                if let Some(index) = self.compiled.lookup(stack, &mut formula) {
+                    // Decided before its count was lost
+                    return self.programs.get(index as usize);
+                }
+                let program = match Program::compile(formula) {
+                    Some(program) if self.programs.len() < MAX_PROGRAMS => program,
+                    _ => {
+                        if self.cold < MAX_COLD {
+                            self.cold += 1;
+                            self.compiled = self.compiled.insert(stack, &mut formula, COLD);
+                        } else {
+                            *heat = Heat::Warming(0);
+                        }
+                        return None;
+                    }
+                };
+                let index = self.programs.len();
+                self.programs.push(program);
+                self.compiled = self.compiled.insert(stack, &mut formula, index as u32);
+                self.programs.last()
+            }
+        }
+    }
+
+    pub(crate) fn save(&self) -> SavedBytecode {
+        SavedBytecode {
+            compiled: self.compiled,
+            programs: self.programs.len(),
+            cold: self.cold,
+        }
+    }
+
+    /// Go back to a saved table, dropping the programs compiled since, which it doesn't index
+    pub(crate) fn restore(&mut self, saved: &SavedBytecode) {
+        self.compiled = saved.compiled;
+        self.programs.truncate(saved.programs);
+        self.cold = saved.cold;
+    }
+}
+
+/// The table and how many programs it indexes, saved with the context's caches
+#[derive(Clone, Copy)]
+pub(crate) struct SavedBytecode {
+    compiled: Hamt<u32>,
+    programs: usize,
+    cold: usize,
+}
+
+impl Preserve for Bytecode {
+    unsafe fn preserve(&mut self, stack: &mut NockStack) {
+        self.compiled.preserve(stack);
+    }
+
+    unsafe fn assert_in_stack(&self, stack: &NockStack) {
+        self.compiled.assert_in_stack(stack);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::interpret;
+    use crate::jets::util::test::init_context;
+
+    /// Run `formula` both ways and check they agree
+    fn check(stack: &mut NockStack, subject: Noun, formula: Noun, expected: Noun) {
+        let program = Program::compile(formula).expect("compiles");
+        let mut res = program.run(stack, subject, formula).expect("runs");
+        let mut expected = expected;
+        assert!(unsafe { unifying_equality(stack, &mut res, &mut expected) });
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_compile_runs_like_the_interpreter() {
+        let c = &mut init_context();
+        let s = &mut c.stack;
+        let subject = T(s, &[D(40), D(2)]);
+
+        // [4 4 0 2]
+        let inner = T(s, &[D(4), D(0), D(2)]);
+        let formula = T(s, &[D(4), inner]);
+        check(s, subject, formula, D(42));
+
+        // [6 [5 [0 3] [1 2]] [1 7] [1 9]]
+        let left = T(s, &[D(0), D(3)]);
+        let right = T(s, &[D(1), D(2)]);
+        let test = T(s, &[D(5), left, right]);
+        let yes = T(s, &[D(1), D(7)]);
+        let no = T(s, &[D(1), D(9)]);
+        let formula = T(s, &[D(6), test, yes, no]);
+        check(s, subject, formula, D(7));
+        let other = T(s, &[D(40), D(3)]);
+        check(s, other, formula, D(9));
+
+        // [8 [1 5] [[0 2] [10 [2 [1 6]] 0 3]]]
+        let pin = T(s, &[D(1), D(5)]);
+        let head = T(s, &[D(0), D(2)]);
+        let patch = T(s, &[D(1), D(6)]);
+        let tree = T(s, &[D(0), D(3)]);
+        let pair = T(s, &[D(2), patch]);
+        let edit_formula = T(s, &[D(10), pair, tree]);
+        let body = T(s, &[head, edit_formula]);
+        let formula = T(s, &[D(8), pin, body]);
+        let expected = T(s, &[D(5), D(6), D(2)]);
+        check(s, subject, formula, expected);
+
+        // [7 [0 3] 3 0 1]
+        let first = T(s, &[D(0), D(3)]);
+        let second = T(s, &[D(3), D(0), D(1)]);
+        let formula = T(s, &[D(7), first, second]);
+        check(s, subject, formula, D(1));
+
+        // Calls don't compile
+        let call = T(s, &[D(9), D(2), D(0), D(1)]);
+        assert!(Program::compile(call).is_none());
+
+        // Neither does a crash, but it crashes the same way when run
+        let formula = T(s, &[D(4), D(0), D(1)]);
+        let program = Program::compile(formula).expect("compiles");
+        assert!(program.run(s, subject, formula).is_err());
+        assert!(interpret(c, subject, formula).is_err());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_hot_formulas_compile() {
+        let c = &mut init_context();
+        let mut tier = Bytecode::new(&mut c.stack, 3);
+        let formula = T(&mut c.stack, &[D(4), D(0), D(1)]);
+        let same = T(&mut c.stack, &[D(4), D(0), D(1)]);
+
+        assert!(tier.call(&mut c.stack, formula).is_none());
+        assert!(tier.call(&mut c.stack, same).is_none());
+        assert!(tier.call(&mut c.stack, formula).is_some());
+        assert!(tier.call(&mut c.stack, same).is_some());
+        assert_eq!(tier.programs(), 1);
+
+        let call = T(&mut c.stack, &[D(9), D(2), D(0), D(1)]);
+        for _ in 0..5 {
+            assert!(tier.call(&mut c.stack, call).is_none());
+        }
+        assert_eq!(tier.programs(), 1);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_cold_formula_keeps_its_mug_warm() {
+        let c = &mut init_context();
+        let mut tier = Bytecode::new(&mut c.stack, 2);
+        let call = T(&mut c.stack, &[D(9), D(2), D(0), D(1)]);
+        let formula = T(&mut c.stack, &[D(4), D(0), D(1)]);
+        assert!(tier.call(&mut c.stack, call).is_none());
+        assert!(tier.call(&mut c.stack, call).is_none());
+
+        // Stand in for a formula that shares the cold formula's mug
+        let mug = mug_u32(&mut c.stack, call);
+        let heat = tier.heat.remove(&mug).expect("heat");
+        tier.heat.insert(mug_u32(&mut c.stack, formula), heat);
+        assert!(tier.call(&mut c.stack, formula).is_none());
+        assert!(tier.call(&mut c.stack, formula).is_none());
+        assert!(tier.call(&mut c.stack, formula).is_some());
+        assert_eq!((tier.programs(), tier.cold), (1, 1));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_restore_drops_programs() {
+        let c = &mut init_context();
+        let mut tier = Bytecode::new(&mut c.stack, 1);
+        let formula = T(&mut c.stack, &[D(4), D(0), D(1)]);
+        let saved = tier.save();
+        assert!(tier.call(&mut c.stack, formula).is_some());
+        assert_eq!(tier.programs(), 1);
+
+        // A bailed computation's programs go with it, and the formula compiles again once hot
+        tier.restore(&saved);
+        assert_eq!(tier.programs(), 0);
+        assert!(tier.call(&mut c.stack, formula).is_none());
+        assert!(tier.call(&mut c.stack, formula).is_some());
+        assert_eq!(tier.programs(), 1);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_underflow_fails() {
+        let c = &mut init_context();
+        let program = Program {
+            ops: vec![Op::Cons],
+        };
+        assert!(matches!(
+            program.run(&mut c.stack, D(0), D(0)),
+            Err(Error::NonDeterministic(Mote::Fail, _))
+        ));
+    }
+}
@@ -0,0 +1,194 @@
+//! Fuel-metered interpretation, for evaluating untrusted formulas.
+//!
+//! A metered computation is given a budget of fuel. Every step of the interpreter burns one unit,
+//! and a jet burns the cost listed for its path in the jet cost table. Once the budget is spent the
+//! computation bails with `%fuel`, a deterministic error, so every node evaluating the same formula
+//! with the same budget agrees on whether and where it ran out.
+//!
+//! Jets that aren't in the cost table don't run while metered: their formulas are interpreted
+//! instead, so they are charged step by step. Only list a jet whose work is bounded by its cost no
+//! matter the sample, since a single call is charged that cost and nothing more. For the same
+//! reason `%memo` cache hits are skipped while metered, as they would make the fuel burned depend
+//! on what earlier computations left in the cache.
+use either::Either;
+
+use crate::hamt::Hamt;
+use crate::interpreter::{interpret, Context, Error, Mote, Result};
+use crate::jets::hot::hot_path;
+use crate::mem::{NockStack, Preserve};
+use crate::noun::{Noun, D};
+
+crate::gdb!();
+
+/// A hot state path and the fuel a call of its jet burns
+pub type JetCost = (&'static [Either<&'static [u8], (u64, u64)>], u64);
+
+pub(crate) const BAIL_FUEL: Result = Err(Error::Deterministic(Mote::Fuel, D(0)));
+
+#[derive(Copy, Clone)]
+pub struct Fuel {
+    remaining: u64,
+    jet_costs: Hamt<u64>,
+}
+
+impl Fuel {
+    /// A budget of `budget` units, with jets priced by `jet_costs`.
+    ///
+    /// The table is allocated on `stack`, so the fuel must not outlive the current frame.
+    pub fn new(stack: &mut NockStack, budget: u64, jet_costs: &[JetCost]) -> Self {
+        let mut costs = Hamt::new(stack);
+        for (htap, cost) in jet_costs {
+            let mut path = hot_path(stack, htap);
+            costs = costs.insert(stack, &mut path, *cost);
+        }
+        Fuel {
+            remaining: budget,
+            jet_costs: costs,
+        }
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Burn `units`, returning false without burning any if there isn't enough left.
+    #[inline(always)]
+    pub(crate) fn burn(&mut self, units: u64) -> bool {
+        match self.remaining.checked_sub(units) {
+            Some(remaining) => {
+                self.remaining = remaining;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The cost of calling the jet at cold state `path`, if it may run while metered.
+    pub(crate) fn jet_cost(&self, stack: &mut NockStack, mut path: Noun) -> Option<u64> {
+        self.jet_costs.lookup(stack, &mut path)
+    }
+}
+
+impl Preserve for Fuel {
+    unsafe fn preserve(&mut self, stack: &mut NockStack) {
+        self.jet_costs.preserve(stack);
+    }
+
+    unsafe fn assert_in_stack(&self, stack: &NockStack) {
+        self.jet_costs.assert_in_stack(stack);
+    }
+}
+
+/// Whether the jet at `path` may run in `context`, burning its cost if the computation is metered.
+///
+/// `Ok(false)` means the jet has no cost and its formula should be interpreted instead.
+#[inline(always)]
+pub(crate) fn burn_jet(context: &mut Context, path: Noun) -> std::result::Result<bool, Error> {
+    let Some(fuel) = context.fuel.as_mut() else {
+        return Ok(true);
+    };
+    match fuel.jet_cost(&mut context.stack, path) {
+        Some(cost) if fuel.burn(cost) => Ok(true),
+        Some(_) => Err(Error::Deterministic(Mote::Fuel, D(0))),
+        None => Ok(false),
+    }
+}
+
+/// Interpret `formula` against `subject` with a fuel budget, returning the result and the fuel left.
+///
+/// Whatever metering `context` already had is restored afterwards, so metered computations can
+/// nest.
+pub fn interpret_metered(
+    context: &mut Context,
+    subject: Noun,
+    formula: Noun,
+    fuel: Fuel,
+) -> (Result, u64) {
+    let outer = context.fuel.replace(fuel);
+    let res = interpret(context, subject, formula);
+    let remaining = context.fuel.map_or(0, |fuel| fuel.remaining());
+    context.fuel = outer;
+    (res, remaining)
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm_macros::tas;
+
+    use super::*;
+    use crate::jets::hot::K_138;
+    use crate::jets::util::test::init_context;
+    use crate::noun::T;
+
+    /// `[2 [0 1] [0 1]]` evaluated against itself calls itself forever
+    fn forever(stack: &mut NockStack) -> Noun {
+        let axis = T(stack, &[D(0), D(1)]);
+        T(stack, &[D(2), axis, axis])
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_fuel_runs_out() {
+        let c = &mut init_context();
+        let formula = forever(&mut c.stack);
+        let fuel = Fuel::new(&mut c.stack, 1000, &[]);
+        let (res, remaining) = interpret_metered(c, formula, formula, fuel);
+        assert!(matches!(res, Err(Error::Deterministic(Mote::Fuel, _))));
+        assert_eq!(remaining, 0);
+        assert!(c.fuel.is_none());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_fuel_is_deterministic() {
+        let c = &mut init_context();
+        // [4 4 0 1] increments its subject twice
+        let inner = T(&mut c.stack, &[D(0), D(1)]);
+        let inner = T(&mut c.stack, &[D(4), inner]);
+        let formula = T(&mut c.stack, &[D(4), inner]);
+
+        let fuel = Fuel::new(&mut c.stack, 100, &[]);
+        let (res, first) = interpret_metered(c, D(40), formula, fuel);
+        assert!(unsafe { res.expect("runs").raw_equals(&D(42)) });
+        assert!(first < 100);
+
+        let fuel = Fuel::new(&mut c.stack, 100, &[]);
+        let (_, second) = interpret_metered(c, D(40), formula, fuel);
+        assert_eq!(first, second);
+
+        // Exactly the fuel it took is enough, one less isn't
+        let fuel = Fuel::new(&mut c.stack, 100 - first, &[]);
+        assert!(interpret_metered(c, D(40), formula, fuel).0.is_ok());
+        let fuel = Fuel::new(&mut c.stack, 100 - first - 1, &[]);
+        assert!(interpret_metered(c, D(40), formula, fuel).0.is_err());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_jet_costs() {
+        let c = &mut init_context();
+        let fuel = Fuel::new(
+            &mut c.stack,
+            7,
+            &[(&[K_138, Either::Left(b"one"), Either::Left(b"dec")], 3)],
+        );
+        c.fuel = Some(fuel);
+
+        let k = T(&mut c.stack, &[D(tas!(b"k")), D(138)]);
+        let dec = T(&mut c.stack, &[D(tas!(b"dec")), D(tas!(b"one")), k, D(0)]);
+        let add = T(&mut c.stack, &[D(tas!(b"add")), D(tas!(b"one")), k, D(0)]);
+
+        assert!(matches!(burn_jet(c, dec), Ok(true)));
+        assert!(matches!(burn_jet(c, add), Ok(false)));
+        assert!(matches!(burn_jet(c, dec), Ok(true)));
+        assert_eq!(c.fuel.map(|fuel| fuel.remaining()), Some(1));
+        assert!(matches!(
+            burn_jet(c, dec),
+            Err(Error::Deterministic(Mote::Fuel, _))
+        ));
+
+        // Unmetered, every jet runs
+        c.fuel = None;
+        assert!(matches!(burn_jet(c, add), Ok(true)));
+    }
+}
@@ -10,14 +10,20 @@ use either::*;
 use nockvm_macros::tas;
 use tracing::trace;
 
+use crate::bytecode::{Bytecode, SavedBytecode};
+use crate::fuel::{burn_jet, Fuel, BAIL_FUEL};
 use crate::hamt::Hamt;
 use crate::jets::cold::Cold;
 use crate::jets::hot::Hot;
 use crate::jets::list::util::weld;
 use crate::jets::warm::Warm;
-use crate::jets::{cold, JetErr};
+use crate::jets::{cold, Jet, JetErr};
 use crate::mem::{NockStack, Preserve};
+use crate::memo::{MemoCache, MemoConfig, MemoStats};
 use crate::noun::{Atom, Cell, IndirectAtom, Noun, Slots, D, T};
+use crate::profile::Profiler;
+use crate::shadow::{shadow_check, ShadowCheck};
+use crate::stats::RuntimeStats;
 use crate::trace::{write_nock_trace, TraceInfo, TraceStack};
 use crate::unifying_equality::unifying_equality;
 use crate::{assert_acyclic, assert_no_forwarding_pointers, assert_no_junior_pointers, flog, noun};
@@ -285,7 +291,8 @@ where
 pub struct ContextSnapshot {
     cold: Cold,
     warm: Warm,
-    cache: Hamt<Noun>,
+    cache: MemoCache,
+    bytecode: Option<SavedBytecode>,
 }
 
 pub struct Context {
@@ -294,11 +301,24 @@ pub struct Context {
     pub cold: Cold,
     pub warm: Warm,
     pub hot: Hot,
-    pub cache: Hamt<Noun>,
+    pub cache: MemoCache,
+    /// Size limit and eviction policy of the `%memo` cache
+    pub memo_config: MemoConfig,
+    pub memo_stats: MemoStats,
     pub scry_stack: Noun,
     pub trace_info: Option<TraceInfo>,
     pub running_status: Arc<AtomicIsize>,
     pub test_jets: Hamt<()>,
+    /// The fuel budget of a metered computation, see [`crate::fuel`]
+    pub fuel: Option<Fuel>,
+    /// Call stack timings while profiling, see [`crate::profile`]
+    pub profiler: Option<Profiler>,
+    /// Compiled hot formulas, see [`crate::bytecode`]
+    pub bytecode: Option<Bytecode>,
+    /// Jet calls sampled for checking against their Nock, see [`crate::shadow`]
+    pub shadow: Option<ShadowCheck>,
+    /// Nock step and jet hit counters, see [`crate::stats`]
+    pub stats: Option<RuntimeStats>,
 }
 
 #[derive(Debug, Clone)]
@@ -339,6 +359,7 @@ impl Context {
             cold: self.cold,
             warm: self.warm,
             cache: self.cache,
+            bytecode: self.bytecode.as_ref().map(Bytecode::save),
         }
     }
 
@@ -346,6 +367,27 @@ impl Context {
         self.cold = saved.cold;
         self.warm = saved.warm;
         self.cache = saved.cache;
+        if let (Some(bytecode), Some(saved)) = (self.bytecode.as_mut(), &saved.bytecode) {
+            bytecode.restore(saved);
+        }
+    }
+
+    /// Look up a memoized result, counting the hit or miss.
+    pub fn memo_lookup(&mut self, key: &mut Noun) -> Option<Noun> {
+        let res = self.cache.lookup(&mut self.stack, key);
+        if res.is_some() {
+            self.memo_stats.hits += 1;
+        } else {
+            self.memo_stats.misses += 1;
+        }
+        res
+    }
+
+    /// Memoize a result, evicting according to the cache configuration.
+    pub fn memo_insert(&mut self, key: &mut Noun, value: Noun) {
+        self.cache = self.cache.insert(
+            &mut self.stack, key, value, &self.memo_config, &mut self.memo_stats,
+        );
     }
 
     pub fn cancel_token(&self) -> NockCancelToken {
@@ -371,6 +413,7 @@ impl Context {
         let mut ret = f(self);
         ret.preserve(&mut self.stack);
         self.cache.preserve(&mut self.stack);
+        self.bytecode.preserve(&mut self.stack);
         self.cold.preserve(&mut self.stack);
         self.warm.preserve(&mut self.stack);
         self.stack.frame_pop();
@@ -385,6 +428,7 @@ pub enum Mote {
     Intr = tas!(b"intr") as isize,
     Meme = tas!(b"meme") as isize,
     Jest = tas!(b"jest") as isize,
+    Fuel = tas!(b"fuel") as isize,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -434,6 +478,32 @@ const BAIL_FAIL: Result = Err(Error::NonDeterministic(Mote::Fail, D(0)));
 const BAIL_INTR: Result = Err(Error::NonDeterministic(Mote::Intr, D(0)));
 pub(crate) const BAIL_JEST: Result = Err(Error::NonDeterministic(Mote::Jest, D(0)));
 
+/// Whether the computation has been cancelled through a [`NockCancelToken`].
+///
+/// Checked before every Nock 2 and 9, since any unbounded computation has to go through one.
+#[inline(always)]
+fn cancelled(context: &Context) -> bool {
+    context.running_status.load(Ordering::Relaxed) < NockCancelToken::RUNNING_IDLE
+}
+
+/// Run the bytecode of a hot formula, if it has any. Metered, traced, profiled and counted
+/// computations always walk the formula, so they see every step.
+#[inline(always)]
+fn run_compiled(context: &mut Context, subject: Noun, formula: Noun) -> Option<Result> {
+    if context.fuel.is_some()
+        || context.trace_info.is_some()
+        || context.profiler.is_some()
+        || context.stats.is_some()
+    {
+        return None;
+    }
+    let program = context
+        .bytecode
+        .as_mut()?
+        .call(&mut context.stack, formula)?;
+    Some(program.run(&mut context.stack, subject, formula))
+}
+
 #[allow(unused_variables)]
 #[inline(always)]
 fn debug_assertions(stack: &mut NockStack, noun: Noun) {
@@ -443,7 +513,18 @@ fn debug_assertions(stack: &mut NockStack, noun: Noun) {
 }
 
 /** Interpret nock */
-pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Result {
+pub fn interpret(context: &mut Context, subject: Noun, formula: Noun) -> Result {
+    let Some(depth) = context.profiler.as_ref().map(Profiler::depth) else {
+        return interpret_frames(context, subject, formula);
+    };
+    let res = interpret_frames(context, subject, formula);
+    if let Some(profiler) = context.profiler.as_mut() {
+        profiler.unwind(depth);
+    }
+    res
+}
+
+fn interpret_frames(context: &mut Context, mut subject: Noun, formula: Noun) -> Result {
     let orig_subject = subject; // for debugging
     let snapshot = context.save();
     let virtual_frame: *const u64 = context.stack.get_frame_pointer();
@@ -501,6 +582,14 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
 
         loop {
             let work: NockWork = *context.stack.top();
+            if let Some(fuel) = context.fuel.as_mut() {
+                if !fuel.burn(1) {
+                    break BAIL_FUEL;
+                }
+            }
+            if let Some(stats) = context.stats.as_mut() {
+                stats.count_op();
+            }
             match work {
                 NockWork::Done => {
                     write_trace(context);
@@ -511,6 +600,7 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                     debug_assertions(stack, res);
 
                     stack.preserve(&mut context.cache);
+                    stack.preserve(&mut context.bytecode);
                     stack.preserve(&mut context.cold);
                     stack.preserve(&mut context.warm);
                     stack.preserve(&mut res);
@@ -523,6 +613,9 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                 }
                 NockWork::Ret => {
                     write_trace(context);
+                    if let Some(profiler) = context.profiler.as_mut() {
+                        profiler.leave();
+                    }
 
                     let stack = &mut context.stack;
                     debug_assertions(stack, orig_subject);
@@ -530,6 +623,7 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                     debug_assertions(stack, res);
 
                     stack.preserve(&mut context.cache);
+                    stack.preserve(&mut context.bytecode);
                     stack.preserve(&mut context.cold);
                     stack.preserve(&mut context.warm);
                     stack.preserve(&mut res);
@@ -582,6 +676,18 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                         push_formula(&mut context.stack, vale.formula, false)?;
                     }
                     Todo2::ComputeResult => {
+                        if cancelled(context) {
+                            break BAIL_INTR;
+                        }
+                        match run_compiled(context, vale.subject, res) {
+                            Some(Ok(compiled)) => {
+                                res = compiled;
+                                context.stack.pop::<NockWork>();
+                                continue;
+                            }
+                            Some(Err(err)) => break Err(err),
+                            None => {}
+                        }
                         let stack = &mut context.stack;
                         if vale.tail {
                             stack.pop::<NockWork>();
@@ -599,6 +705,9 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                             mean_frame_push(stack, 0);
                             *stack.push() = NockWork::Ret;
                             push_formula(stack, res, true)?;
+                            if let Some(profiler) = context.profiler.as_mut() {
+                                profiler.enter(&mut context.stack, None, false);
+                            }
                         }
                     }
                     Todo2::RestoreSubject => {
@@ -744,14 +853,22 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                             push_formula(&mut context.stack, kale.core, false)?;
                         }
                         Todo9::ComputeResult => {
+                            if cancelled(context) {
+                                break BAIL_INTR;
+                            }
                             if let Ok(mut formula) = res.slot_atom(kale.axis) {
                                 if !cfg!(feature = "sham_hints") {
-                                    if let Some((jet, _path, test)) = context
+                                    if let Some((jet, path, test)) = context
                                         .warm
                                         .find_jet(&mut context.stack, &mut res, &mut formula)
                                         .next()
                                     {
-                                        match jet(context, res) {
+                                        let jet_res = match burn_jet(context, path) {
+                                            Ok(true) => run_jet(context, jet, path, res),
+                                            Ok(false) => Err(JetErr::Punt),
+                                            Err(err) => break Err(err),
+                                        };
+                                        match jet_res {
                                             Ok(mut jet_res) => {
                                                 if test {
                                                     let mut test_res =
@@ -762,6 +879,13 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                                                     ) {
                                                         break BAIL_JEST;
                                                     }
+                                                } else {
+                                                    jet_res = match shadow_check(
+                                                        context, path, res, formula, jet_res,
+                                                    ) {
+                                                        Ok(checked) => checked,
+                                                        Err(err) => break Err(err),
+                                                    };
                                                 }
                                                 res = jet_res;
                                                 context.stack.pop::<NockWork>();
@@ -775,6 +899,16 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                                     }
                                 };
 
+                                match run_compiled(context, res, formula) {
+                                    Some(Ok(compiled)) => {
+                                        res = compiled;
+                                        context.stack.pop::<NockWork>();
+                                        continue;
+                                    }
+                                    Some(Err(err)) => break Err(err),
+                                    None => {}
+                                }
+
                                 let stack = &mut context.stack;
                                 if kale.tail {
                                     stack.pop::<NockWork>();
@@ -782,9 +916,14 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                                     // We could trace on 2 as well, but 2 only comes from Hoon via
                                     // '.*', so we can assume it's never directly used to invoke
                                     // jetted code.
-                                    if context.trace_info.is_some() {
+                                    if context.trace_info.is_some() || context.profiler.is_some() {
                                         if let Some(path) = context.cold.matches(stack, &mut res) {
-                                            append_trace(stack, path);
+                                            if context.trace_info.is_some() {
+                                                append_trace(stack, path);
+                                            }
+                                            if let Some(profiler) = context.profiler.as_mut() {
+                                                profiler.replace(stack, path);
+                                            }
                                         };
                                     };
 
@@ -807,10 +946,16 @@ pub fn interpret(context: &mut Context, mut subject: Noun, formula: Noun) -> Res
                                     // We could trace on 2 as well, but 2 only comes from Hoon via
                                     // '.*', so we can assume it's never directly used to invoke
                                     // jetted code.
-                                    if context.trace_info.is_some() {
-                                        if let Some(path) = context.cold.matches(stack, &mut res) {
-                                            append_trace(stack, path);
-                                        };
+                                    if context.trace_info.is_some() || context.profiler.is_some() {
+                                        let path = context.cold.matches(stack, &mut res);
+                                        if context.trace_info.is_some() {
+                                            if let Some(path) = path {
+                                                append_trace(stack, path);
+                                            }
+                                        }
+                                        if let Some(profiler) = context.profiler.as_mut() {
+                                            profiler.enter(stack, path, false);
+                                        }
                                     };
                                 }
                             } else {
@@ -1348,7 +1493,7 @@ fn mean_pop(stack: &mut NockStack) {
     }
 }
 
-fn edit(
+pub(crate) fn edit(
     stack: &mut NockStack,
     edit_axis: &BitSlice<u64, Lsb0>,
     patch: Noun,
@@ -1432,6 +1577,35 @@ fn append_trace(stack: &mut NockStack, path: Noun) {
     }
 }
 
+/// Run a jet, in a profiler frame of its own when profiling
+fn run_jet(context: &mut Context, jet: Jet, path: Noun, subject: Noun) -> crate::jets::Result {
+    let Some(profiler) = context.profiler.as_mut() else {
+        let res = jet(context, subject);
+        return count_jet(context, path, res);
+    };
+    profiler.enter(&mut context.stack, Some(path), true);
+    let res = jet(context, subject);
+    if let Some(profiler) = context.profiler.as_mut() {
+        profiler.leave();
+    }
+    count_jet(context, path, res)
+}
+
+/// Count a jet hit if the jet returned a result and hits are being counted
+#[inline(always)]
+pub(crate) fn count_jet(
+    context: &mut Context,
+    path: Noun,
+    res: crate::jets::Result,
+) -> crate::jets::Result {
+    if res.is_ok() {
+        if let Some(stats) = context.stats.as_mut() {
+            stats.count_jet(&mut context.stack, path);
+        }
+    }
+    res
+}
+
 /// Write fast-hinted traces to trace file
 unsafe fn write_trace(context: &mut Context) {
     if let Some(ref mut info) = &mut context.trace_info {
@@ -1479,7 +1653,7 @@ mod hint {
         //  XX: handle IndirectAtom tags
         match tag.direct()?.data() {
             tas!(b"sham") => {
-                if cfg!(feature = "sham_hints") {
+                if cfg!(feature = "sham_hints") && context.fuel.is_none() {
                     let jet_formula = hint.cell()?;
                     // XX: what is the head here?
                     let jet_name = jet_formula.tail();
@@ -1545,10 +1719,9 @@ mod hint {
                     None
                 }
             }
-            tas!(b"memo") => {
-                let stack = &mut context.stack;
-                let mut key = Cell::new(stack, subject, body).as_noun();
-                context.cache.lookup(stack, &mut key).map(Ok)
+            tas!(b"memo") if context.fuel.is_none() => {
+                let mut key = Cell::new(&mut context.stack, subject, body).as_noun();
+                context.memo_lookup(&mut key).map(Ok)
             }
             _ => None,
         }
@@ -1661,13 +1834,12 @@ mod hint {
         let slogger = &mut context.slogger;
         let cold = &mut context.cold;
         let hot = &context.hot;
-        let cache = &mut context.cache;
 
         //  XX: handle IndirectAtom tags
         match tag.direct()?.data() {
             tas!(b"memo") => {
                 let mut key = Cell::new(stack, subject, body).as_noun();
-                context.cache = cache.insert(stack, &mut key, res);
+                context.memo_insert(&mut key, res);
             }
             tas!(b"hand") | tas!(b"hunk") | tas!(b"lose") | tas!(b"mean") | tas!(b"spot") => {
                 mean_pop(stack);
@@ -10,6 +10,7 @@ pub mod lock;
 pub mod lute;
 pub mod math;
 pub mod nock;
+pub mod pack;
 pub mod parse;
 pub mod serial;
 pub mod sort;
@@ -198,10 +199,11 @@ pub mod util {
 
     use super::*;
     use crate::interpreter::interpret;
-    use crate::noun::{Noun, D, T};
+    use crate::noun::{Noun, D, NO, T, YES};
 
     pub const BAIL_EXIT: JetErr = JetErr::Fail(Error::Deterministic(Mote::Exit, D(0)));
     pub const BAIL_FAIL: JetErr = JetErr::Fail(Error::NonDeterministic(Mote::Fail, D(0)));
+    pub const BAIL_MEME: JetErr = JetErr::Fail(Error::NonDeterministic(Mote::Meme, D(0)));
 
     /**
      * Address-based size checks.
@@ -311,6 +313,23 @@ pub mod util {
         kick(context, core, D(2))
     }
 
+    /// Whether `f` holds for every item of `list`, stopping at the first it doesn't, like +levy
+    pub fn levy_by(
+        mut list: Noun,
+        mut f: impl FnMut(Noun) -> result::Result<bool, JetErr>,
+    ) -> Result {
+        while let Ok(cell) = list.as_cell() {
+            if !f(cell.head())? {
+                return Ok(NO);
+            }
+            list = cell.tail();
+        }
+        if unsafe { !list.raw_equals(&D(0)) } {
+            return Err(BAIL_EXIT);
+        }
+        Ok(YES)
+    }
+
     pub mod test {
         use std::sync::atomic::AtomicIsize;
         use std::sync::Arc;
@@ -321,6 +340,7 @@ pub mod util {
         use crate::hamt::Hamt;
         use crate::interpreter::{NockCancelToken, Slogger};
         use crate::mem::NockStack;
+        use crate::memo::{MemoCache, MemoConfig, MemoStats};
         use crate::noun::{Atom, Noun, D, T};
         use crate::unifying_equality::unifying_equality;
 
@@ -341,7 +361,7 @@ pub mod util {
             let cold = Cold::new(&mut stack);
             let warm = Warm::new(&mut stack);
             let hot = Hot::init(&mut stack, URBIT_HOT_STATE);
-            let cache = Hamt::<Noun>::new(&mut stack);
+            let cache = MemoCache::new(&mut stack);
             let slogger = std::boxed::Box::pin(TestSlogger {});
             let cancel = Arc::new(AtomicIsize::new(NockCancelToken::RUNNING_IDLE));
             let test_jets = Hamt::<()>::new(&mut stack);
@@ -353,10 +373,17 @@ pub mod util {
                 warm,
                 hot,
                 cache,
+                memo_config: MemoConfig::default(),
+                memo_stats: MemoStats::default(),
                 scry_stack: D(0),
                 trace_info: None,
                 running_status: cancel,
                 test_jets,
+                fuel: None,
+                profiler: None,
+                bytecode: None,
+                shadow: None,
+                stats: None,
             }
         }
 
@@ -2,8 +2,8 @@
  */
 use crate::interpreter::Context;
 use crate::jets::util::slot;
-use crate::jets::Result;
-use crate::noun::Noun;
+use crate::jets::{JetErr, Result};
+use crate::noun::{IndirectAtom, Noun};
 
 crate::gdb!();
 
@@ -13,6 +13,25 @@ pub fn jet_scow(context: &mut Context, subject: Noun) -> Result {
     util::scow(&mut context.stack, aura, atom)
 }
 
+pub fn jet_scot(context: &mut Context, subject: Noun) -> Result {
+    // An aura too long for a direct atom is none the jet knows, so it's left to the Hoon
+    let Ok(aura) = slot(subject, 12)?.as_direct() else {
+        return Err(JetErr::Punt);
+    };
+    let atom = slot(subject, 13)?.as_atom()?;
+    let mut tape = util::scow(&mut context.stack, aura, atom)?;
+    let mut bytes = Vec::new();
+    while let Ok(cell) = tape.as_cell() {
+        bytes.push(cell.head().as_direct()?.data() as u8);
+        tape = cell.tail();
+    }
+    Ok(unsafe {
+        IndirectAtom::new_raw_bytes_ref(&mut context.stack, &bytes)
+            .normalize_as_atom()
+            .as_noun()
+    })
+}
+
 pub mod util {
     use nockvm_macros::tas;
     use num_traits::identities::Zero;
@@ -147,4 +166,26 @@ mod tests {
         let sam = T(&mut c.stack, &[bad_aura, D(0)]);
         assert_jet_err(c, jet_scow, sam, JetErr::Punt);
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_scot() {
+        let c = &mut init_context();
+
+        let aura = D(tas!(b"ud"));
+        let sam = T(&mut c.stack, &[aura, D(0)]);
+        assert_jet(c, jet_scot, sam, D(b'0' as u64));
+        let sam = T(&mut c.stack, &[aura, D(1000)]);
+        assert_jet(c, jet_scot, sam, D(u64::from_le_bytes(*b"1.000\0\0\0")));
+        let big = A(&mut c.stack, &ubig!(9876543210));
+        let sam = T(&mut c.stack, &[aura, big]);
+        let res = A(&mut c.stack, &ibig::UBig::from_le_bytes(b"9.876.543.210"));
+        assert_jet(c, jet_scot, sam, res);
+        let bad_aura = D(tas!(b"ux"));
+        let sam = T(&mut c.stack, &[bad_aura, D(0)]);
+        assert_jet_err(c, jet_scot, sam, JetErr::Punt);
+        let long_aura = A(&mut c.stack, &ibig::UBig::from_le_bytes(b"udududududud"));
+        let sam = T(&mut c.stack, &[long_aura, D(0)]);
+        assert_jet_err(c, jet_scot, sam, JetErr::Punt);
+    }
 }
@@ -131,6 +131,18 @@ pub const URBIT_HOT_STATE: &[HotEntry] = &[
         1,
         jet_find,
     ),
+    (
+        &[K_138, Left(b"one"), Left(b"two"), Left(b"fand")],
+        1,
+        jet_fand,
+    ),
+    //
+    (
+        &[K_138, Left(b"one"), Left(b"two"), Left(b"slag")],
+        1,
+        jet_slag,
+    ),
+    //
     (
         &[K_138, Left(b"one"), Left(b"two"), Left(b"scag")],
         1,
@@ -245,6 +257,12 @@ pub const URBIT_HOT_STATE: &[HotEntry] = &[
         jet_mor,
     ),
     //
+    (
+        &[K_138, Left(b"one"), Left(b"two"), Left(b"pow")],
+        1,
+        jet_pow,
+    ),
+    //
     (
         &[K_138, Left(b"one"), Left(b"two"), Left(b"cue")],
         1,
@@ -292,6 +310,18 @@ pub const URBIT_HOT_STATE: &[HotEntry] = &[
         jet_trip,
     ),
     //
+    (
+        &[K_138, Left(b"one"), Left(b"two"), Left(b"tri"), Left(b"qua"), Left(b"cass")],
+        1,
+        jet_cass,
+    ),
+    //
+    (
+        &[K_138, Left(b"one"), Left(b"two"), Left(b"tri"), Left(b"qua"), Left(b"cuss")],
+        1,
+        jet_cuss,
+    ),
+    //
     (
         &[K_138, Left(b"one"), Left(b"two"), Left(b"tri"), Left(b"qua"), Left(b"last")],
         1,
@@ -504,12 +534,32 @@ pub const URBIT_HOT_STATE: &[HotEntry] = &[
         jet_stir,
     ),
     //
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"stun"),
+            Left(b"fun"),
+        ],
+        1,
+        jet_stun,
+    ),
+    //
     (
         &[K_138, Left(b"one"), Left(b"two"), Left(b"tri"), Left(b"qua"), Left(b"scow")],
         1,
         jet_scow,
     ),
     //
+    (
+        &[K_138, Left(b"one"), Left(b"two"), Left(b"tri"), Left(b"qua"), Left(b"scot")],
+        1,
+        jet_scot,
+    ),
+    //
     (
         &[K_138, Left(b"one"), Left(b"two"), Left(b"tri"), Left(b"qua"), Left(b"mink")],
         1,
@@ -816,27 +866,7 @@ impl Hot {
         unsafe {
             let mut next = Hot(null_mut());
             for (htap, axe, jet) in constant_hot_state {
-                let mut a_path = D(0);
-                for i in *htap {
-                    match i {
-                        Left(tas) => {
-                            let chum = IndirectAtom::new_raw_bytes_ref(stack, tas)
-                                .normalize_as_atom()
-                                .as_noun();
-                            a_path = T(stack, &[chum, a_path]);
-                        }
-                        Right((tas, ver)) => {
-                            let chum = T(
-                                stack,
-                                &[
-                                    DirectAtom::new_panic(*tas).as_atom().as_noun(),
-                                    DirectAtom::new_panic(*ver).as_atom().as_noun(),
-                                ],
-                            );
-                            a_path = T(stack, &[chum, a_path]);
-                        }
-                    };
-                }
+                let a_path = hot_path(stack, htap);
                 let axis = DirectAtom::new_panic(*axe).as_atom();
                 let hot_mem_ptr: *mut HotMem = stack.struct_alloc(1);
                 *hot_mem_ptr = HotMem {
@@ -852,6 +882,34 @@ impl Hot {
     }
 }
 
+/// Build the cold state path of a hot state entry, innermost core first.
+pub(crate) fn hot_path(stack: &mut NockStack, htap: &[Either<&[u8], (u64, u64)>]) -> Noun {
+    let mut a_path = D(0);
+    for i in htap {
+        match i {
+            Left(tas) => {
+                let chum = unsafe {
+                    IndirectAtom::new_raw_bytes_ref(stack, tas)
+                        .normalize_as_atom()
+                        .as_noun()
+                };
+                a_path = T(stack, &[chum, a_path]);
+            }
+            Right((tas, ver)) => {
+                let chum = T(
+                    stack,
+                    &[
+                        DirectAtom::new_panic(*tas).as_atom().as_noun(),
+                        DirectAtom::new_panic(*ver).as_atom().as_noun(),
+                    ],
+                );
+                a_path = T(stack, &[chum, a_path]);
+            }
+        };
+    }
+    a_path
+}
+
 impl Iterator for Hot {
     type Item = (Noun, Atom, Jet); // path,axis,jet
     fn next(&mut self) -> Option<Self::Item> {
@@ -122,6 +122,22 @@ pub fn jet_find(context: &mut Context, subject: Noun) -> Result {
     util::find(context, nedl, hstk)
 }
 
+pub fn jet_fand(context: &mut Context, subject: Noun) -> Result {
+    let sam = slot(subject, 6)?;
+    let nedl = slot(sam, 2)?;
+    let hstk = slot(sam, 3)?;
+
+    util::fand(context, nedl, hstk)
+}
+
+pub fn jet_slag(_context: &mut Context, subject: Noun) -> Result {
+    let sam = slot(subject, 6)?;
+    let a = sam.as_cell()?.head().as_atom()?;
+    let b = sam.as_cell()?.tail();
+
+    util::slag(a, b)
+}
+
 pub fn jet_scag(context: &mut Context, subject: Noun) -> Result {
     let sam = slot(subject, 6)?;
     let a = sam.as_cell()?.head().as_atom()?;
@@ -139,6 +155,7 @@ pub mod util {
     use crate::mem::NockStack;
     use crate::noun::{Atom, Cell, Noun, D, NO, T, YES};
     use crate::site::{site_slam, Site};
+    use crate::unifying_equality::unifying_equality;
 
     /// Reverse order of list
     pub fn flop(stack: &mut NockStack, noun: Noun) -> Result {
@@ -335,9 +352,64 @@ pub mod util {
         }
     }
 
+    pub fn fand(context: &mut Context, nedl: Noun, hstk: Noun) -> Result {
+        // Every index in hstk that nedl starts at, in order
+        let mut found: Vec<u64> = vec![];
+        let mut hstk = hstk;
+        let mut i = 0;
+        loop {
+            let mut n = nedl;
+            let mut h = hstk;
+            loop {
+                if unsafe { n.raw_equals(&D(0)) || h.raw_equals(&D(0)) } {
+                    let mut res = D(0);
+                    while let Some(i) = found.pop() {
+                        res = T(&mut context.stack, &[D(i), res]);
+                    }
+                    return Ok(res);
+                }
+
+                let mut n_head = n.as_cell()?.head();
+                let mut h_head = h.as_cell()?.head();
+                if unsafe { unifying_equality(&mut context.stack, &mut n_head, &mut h_head) } {
+                    if unsafe { n.as_cell()?.tail().raw_equals(&D(0)) } {
+                        found.push(i);
+                    } else {
+                        n = n.as_cell()?.tail();
+                        h = h.as_cell()?.tail();
+                        continue;
+                    }
+                }
+
+                // try next position
+                hstk = hstk.as_cell()?.tail();
+                i += 1;
+                break;
+            }
+        }
+    }
+
+    pub fn slag(a: Atom, b: Noun) -> Result {
+        // Accepts an atom a and list b, producing the list without its first a elements.
+        // An index of 2^64 or more is past the end of any list.
+        let Ok(mut a) = a.as_u64() else {
+            return Ok(D(0));
+        };
+        let mut list = b;
+        while a > 0 {
+            if unsafe { list.raw_equals(&D(0)) } {
+                break;
+            }
+            list = list.as_cell()?.tail();
+            a -= 1;
+        }
+        Ok(list)
+    }
+
     pub fn scag(context: &mut Context, a: Atom, b: Noun) -> Result {
         // Accepts an atom a and list b, producing the first a elements of the front of the list.
-        let a = a.as_u64()?;
+        // An index of 2^64 or more is past the end of any list, so takes all of it.
+        let a = a.as_u64().unwrap_or(u64::MAX);
         let mut res: Vec<Noun> = vec![];
         let mut list = b;
         let mut pos = 0;
@@ -364,8 +436,10 @@ pub mod util {
 
 #[cfg(test)]
 mod tests {
+    use ibig::ubig;
+
     use super::*;
-    use crate::jets::util::test::{assert_jet, assert_jet_err, init_context};
+    use crate::jets::util::test::{assert_jet, assert_jet_err, init_context, A};
     use crate::jets::util::BAIL_EXIT;
     use crate::noun::{D, T};
 
@@ -436,6 +510,11 @@ mod tests {
 
         let sam = T(&mut c.stack, &[D(0), D(0)]);
         assert_jet_err(c, jet_snag, sam, BAIL_EXIT);
+
+        // past the end, as +snag fails for any index the list doesn't reach
+        let big = A(&mut c.stack, &ubig!(_18446744073709551616));
+        let sam = T(&mut c.stack, &[big, list1]);
+        assert_jet_err(c, jet_snag, sam, BAIL_EXIT);
     }
 
     #[test]
@@ -576,6 +655,66 @@ mod tests {
         assert_jet(c, jet_find, sam, res);
     }
 
+    #[test]
+    fn test_fand() {
+        let c = &mut init_context();
+
+        let c3 = T(&mut c.stack, &[D(3), D(0)]);
+        let c13 = T(&mut c.stack, &[D(1), D(3), D(0)]);
+        let c13413 = T(&mut c.stack, &[D(1), D(3), D(4), D(1), D(3), D(0)]);
+
+        let sam = T(&mut c.stack, &[D(0), c13413]);
+        assert_jet(c, jet_fand, sam, D(0));
+
+        let sam = T(&mut c.stack, &[c3, D(0)]);
+        assert_jet(c, jet_fand, sam, D(0));
+
+        let sam = T(&mut c.stack, &[c3, c13413]);
+        let res = T(&mut c.stack, &[D(1), D(4), D(0)]);
+        assert_jet(c, jet_fand, sam, res);
+
+        let sam = T(&mut c.stack, &[c13, c13413]);
+        let res = T(&mut c.stack, &[D(0), D(3), D(0)]);
+        assert_jet(c, jet_fand, sam, res);
+
+        // overlapping matches all count
+        let c33 = T(&mut c.stack, &[D(3), D(3), D(0)]);
+        let c333 = T(&mut c.stack, &[D(3), D(3), D(3), D(0)]);
+        let sam = T(&mut c.stack, &[c33, c333]);
+        let res = T(&mut c.stack, &[D(0), D(1), D(0)]);
+        assert_jet(c, jet_fand, sam, res);
+    }
+
+    #[test]
+    fn test_slag() {
+        let c = &mut init_context();
+
+        let c1341342 = T(
+            &mut c.stack,
+            &[D(1), D(3), D(4), D(1), D(3), D(4), D(2), D(0)],
+        );
+
+        let sam = T(&mut c.stack, &[D(0), c1341342]);
+        assert_jet(c, jet_slag, sam, c1341342);
+
+        let sam = T(&mut c.stack, &[D(5), c1341342]);
+        let res = T(&mut c.stack, &[D(4), D(2), D(0)]);
+        assert_jet(c, jet_slag, sam, res);
+
+        let sam = T(&mut c.stack, &[D(7), c1341342]);
+        assert_jet(c, jet_slag, sam, D(0));
+
+        let sam = T(&mut c.stack, &[D(99), c1341342]);
+        assert_jet(c, jet_slag, sam, D(0));
+
+        let sam = T(&mut c.stack, &[D(2), D(0)]);
+        assert_jet(c, jet_slag, sam, D(0));
+
+        let big = A(&mut c.stack, &ubig!(_18446744073709551616));
+        let sam = T(&mut c.stack, &[big, c1341342]);
+        assert_jet(c, jet_slag, sam, D(0));
+    }
+
     #[test]
     fn test_scag() {
         let c = &mut init_context();
@@ -609,5 +748,9 @@ mod tests {
         let sam = T(&mut c.stack, &[D(99), c1341342]);
         let res = c1341342;
         assert_jet(c, jet_scag, sam, res);
+
+        let big = A(&mut c.stack, &ubig!(_18446744073709551616));
+        let sam = T(&mut c.stack, &[big, c1341342]);
+        assert_jet(c, jet_scag, sam, c1341342);
     }
 }
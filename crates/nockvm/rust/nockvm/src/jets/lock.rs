@@ -1,3 +1,6 @@
 pub mod aes;
+pub mod blake;
 pub mod ed;
+pub mod keccak;
+pub mod secp;
 pub mod sha;
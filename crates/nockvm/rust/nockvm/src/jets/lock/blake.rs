@@ -0,0 +1,158 @@
+use nockvm_crypto::blake::ac_blake3;
+
+use crate::interpreter::Context;
+use crate::jets::bits::util::met;
+use crate::jets::util::{slot, BAIL_FAIL};
+use crate::jets::{JetErr, Result};
+use crate::noun::{IndirectAtom, Noun, D};
+
+crate::gdb!();
+
+const IV: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+const F_KEYEDHASH: u64 = 1 << 4;
+
+//  Note:   +kdf:blake3 also goes through +hash, with the chaining value and flags of
+//          key derivation. The BLAKE3 implementation has no way to start from those,
+//          so only plain and keyed hashing are jetted and everything else punts.
+
+pub fn jet_blake3_hash(context: &mut Context, subject: Noun) -> Result {
+    let stack = &mut context.stack;
+    let out = slot(subject, 12)?.as_atom()?;
+    let len = slot(subject, 26)?.as_atom()?;
+    let dat = slot(subject, 27)?.as_atom()?;
+    let cv = slot(subject, 60)?.as_atom()?;
+    let flags = slot(subject, 61)?.as_atom()?;
+
+    let (Ok(out), Ok(len)) = (out.as_direct(), len.as_direct()) else {
+        return Err(BAIL_FAIL);
+    };
+    let (out, length) = (out.data() as usize, len.data() as usize);
+    let msg_len = met(3, dat);
+    if msg_len > length {
+        // +split-octs doesn't trim the message to its length
+        return Err(JetErr::Punt);
+    }
+
+    let cv_len = met(3, cv);
+    if cv_len > 32 {
+        return Err(JetErr::Punt);
+    }
+    let mut cv_bytes = [0u8; 32];
+    cv_bytes[0..cv_len].copy_from_slice(&(cv.as_ne_bytes())[0..cv_len]);
+    let key = match flags.as_direct().map(|flags| flags.data()) {
+        Ok(0) => {
+            let words = cv_bytes
+                .chunks_exact(4)
+                .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]));
+            if !words.eq(IV) {
+                return Err(JetErr::Punt);
+            }
+            None
+        }
+        Ok(F_KEYEDHASH) => Some(&cv_bytes),
+        _ => return Err(JetErr::Punt),
+    };
+
+    if out == 0 {
+        return Ok(D(0));
+    }
+    unsafe {
+        let (mut out_ida, out_bytes) = IndirectAtom::new_raw_mut_bytes(stack, out);
+        // Past the end of [dat] the message is zeros, hashed without a buffer for them
+        ac_blake3(
+            &(dat.as_ne_bytes())[0..msg_len],
+            length - msg_len,
+            key,
+            out_bytes,
+        );
+
+        Ok(out_ida.normalize_as_atom().as_noun())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ibig::ubig;
+
+    use super::*;
+    use crate::jets::util::test::{assert_jet_door, init_context, A};
+    use crate::noun::T;
+
+    /// A +blake3 core, whose payload is `[[cv flags] impl-core]`
+    fn blake3_core(c: &mut Context, cv: Noun, flags: u64) -> Noun {
+        let state = T(&mut c.stack, &[cv, D(flags)]);
+        T(&mut c.stack, &[D(0), state, D(0)])
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_blake3_hash() {
+        let c = &mut init_context();
+        let iv = A(
+            &mut c.stack,
+            &ubig!(_0x5be0cd191f83d9ab9b05688c510e527fa54ff53a3c6ef372bb67ae856a09e667),
+        );
+        let pay = blake3_core(c, iv, 0);
+
+        let sam = T(&mut c.stack, &[D(32), D(0), D(0)]);
+        let res = A(
+            &mut c.stack,
+            &ubig!(_0x62321fe4ca939accb712c1adc925cb9b49c9dc36ea4d40a0a6a1f9f5b94913af),
+        );
+        assert_jet_door(c, jet_blake3_hash, sam, pay, res);
+
+        let sam = T(&mut c.stack, &[D(32), D(3), D(0x636261)]); // [32 3 'abc']
+        let res = A(
+            &mut c.stack,
+            &ubig!(_0x859dbdd56c9c35fd03db795d4658c548b58d3a27753bb6ff33514638acb33764),
+        );
+        assert_jet_door(c, jet_blake3_hash, sam, pay, res);
+
+        // Output lengths past one block extend the output stream
+        let sam = T(&mut c.stack, &[D(64), D(3), D(0x636261)]);
+        let res = A(
+            &mut c.stack,
+            &ubig!(_0x0bbf7433f200d9ff4c7fcef79ca09b2d490d1a525db61328d0f59373ae50b21f859dbdd56c9c35fd03db795d4658c548b58d3a27753bb6ff33514638acb33764),
+        );
+        assert_jet_door(c, jet_blake3_hash, sam, pay, res);
+
+        // Messages shorter than their length are zero-padded
+        let sam = T(&mut c.stack, &[D(32), D(4), D(0x636261)]);
+        let res = A(
+            &mut c.stack,
+            &ubig!(_0x5be4c2f39a64adacd99ca4ff12c213c31c199315c4bb182965b6e492df080c96),
+        );
+        assert_jet_door(c, jet_blake3_hash, sam, pay, res);
+
+        let key = A(
+            &mut c.stack,
+            &ubig!(_0x1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100),
+        );
+        let pay = blake3_core(c, key, F_KEYEDHASH);
+        let sam = T(&mut c.stack, &[D(32), D(3), D(0x636261)]);
+        let res = A(
+            &mut c.stack,
+            &ubig!(_0x32558b2b85d27b4c5fed48446bb6cd0109f72d28d77ba8cb2b2f15d89544a56d),
+        );
+        assert_jet_door(c, jet_blake3_hash, sam, pay, res);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_blake3_hash_punts() {
+        let c = &mut init_context();
+
+        // Key derivation flags
+        let pay = blake3_core(c, D(0), 1 << 6);
+        let sam = T(&mut c.stack, &[D(32), D(3), D(0x636261)]);
+        let subject = T(&mut c.stack, &[D(0), sam, pay]);
+        assert!(matches!(jet_blake3_hash(c, subject), Err(JetErr::Punt)));
+
+        // Unkeyed, but not starting from the IV
+        let pay = blake3_core(c, D(1), 0);
+        let subject = T(&mut c.stack, &[D(0), sam, pay]);
+        assert!(matches!(jet_blake3_hash(c, subject), Err(JetErr::Punt)));
+    }
+}
@@ -2,7 +2,7 @@ use nockvm_crypto::ed25519::{ac_ed_puck, ac_ed_shar, ac_ed_sign, ac_ed_veri};
 
 use crate::interpreter::Context;
 use crate::jets::bits::util::met;
-use crate::jets::util::{slot, BAIL_EXIT};
+use crate::jets::util::{levy_by, slot, BAIL_EXIT};
 use crate::jets::{JetErr, Result};
 use crate::mem::NockStack;
 use crate::noun::{IndirectAtom, Noun, NO, YES};
@@ -92,30 +92,38 @@ pub fn jet_sign(context: &mut Context, subject: Noun) -> Result {
 }
 
 pub fn jet_veri(_context: &mut Context, subject: Noun) -> Result {
-    let sig = slot(subject, 12)?.as_atom()?;
-    let msg = slot(subject, 26)?.as_atom()?;
-    let puk = slot(subject, 27)?.as_atom()?;
+    let sam = slot(subject, 6)?;
+    Ok(if veri(sam)? { YES } else { NO })
+}
+
+pub fn jet_veri_batch(_context: &mut Context, subject: Noun) -> Result {
+    levy_by(slot(subject, 6)?, veri)
+}
+
+/// Verify a `[s=@ m=@ pk=@]` sample
+fn veri(sam: Noun) -> std::result::Result<bool, JetErr> {
+    let sig = slot(sam, 2)?.as_atom()?;
+    let msg = slot(sam, 6)?.as_atom()?;
+    let puk = slot(sam, 7)?.as_atom()?;
 
     // Both are size checked by Hoon, but without crashing
     let sig_bytes = sig.as_ne_bytes();
     if sig_bytes.len() > 64 {
-        return Ok(NO);
+        return Ok(false);
     };
     let signature = &mut [0u8; 64];
     signature[0..sig_bytes.len()].copy_from_slice(sig_bytes);
 
     let pub_bytes = puk.as_ne_bytes();
     if pub_bytes.len() > 32 {
-        return Ok(NO);
+        return Ok(false);
     };
     let public_key = &mut [0u8; 32];
     public_key[0..pub_bytes.len()].copy_from_slice(pub_bytes);
 
     let message = &(msg.as_ne_bytes())[0..met(3, msg)]; // drop trailing zeros
 
-    let valid = ac_ed_veri(message, public_key, signature);
-
-    Ok(if valid { YES } else { NO })
+    Ok(ac_ed_veri(message, public_key, signature))
 }
 
 #[cfg(test)]
@@ -267,4 +275,35 @@ mod tests {
             assert_jet(c, jet_veri, sam, YES);
         }
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_veri_batch() {
+        let c = &mut init_context();
+
+        unsafe {
+            let sig_ubig = ubig!(_0x92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00);
+            let sig_bytes = sig_ubig.to_be_bytes();
+            let signature =
+                IndirectAtom::new_raw_bytes(&mut c.stack, sig_bytes.len(), sig_bytes.as_ptr())
+                    .as_noun();
+            let pub_ubig =
+                ubig!(_0x3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c);
+            let pub_bytes = pub_ubig.to_be_bytes();
+            let public_key =
+                IndirectAtom::new_raw_bytes(&mut c.stack, pub_bytes.len(), pub_bytes.as_ptr())
+                    .as_noun();
+            let good = T(&mut c.stack, &[signature, D(0x72), public_key]);
+            let bad = T(&mut c.stack, &[signature, D(0x73), public_key]);
+
+            assert_jet(c, jet_veri_batch, D(0), YES);
+            let sam = T(&mut c.stack, &[good, good, D(0)]);
+            assert_jet(c, jet_veri_batch, sam, YES);
+            let sam = T(&mut c.stack, &[good, bad, good, D(0)]);
+            assert_jet(c, jet_veri_batch, sam, NO);
+            // An improper list crashes like +levy's, once every signature before its end verifies
+            let sam = T(&mut c.stack, &[good, good, D(1)]);
+            assert_jet_err(c, jet_veri_batch, sam, BAIL_EXIT);
+        }
+    }
 }
@@ -0,0 +1,79 @@
+use nockvm_crypto::keccak::ac_keccak_256;
+
+use crate::interpreter::Context;
+use crate::jets::bits::util::met;
+use crate::jets::util::{slot, BAIL_FAIL};
+use crate::jets::Result;
+use crate::noun::{IndirectAtom, Noun};
+
+crate::gdb!();
+
+pub fn jet_keccak_256(context: &mut Context, subject: Noun) -> Result {
+    let stack = &mut context.stack;
+    let len = slot(subject, 12)?.as_atom()?;
+    let dat = slot(subject, 13)?.as_atom()?;
+
+    let length = match len.as_direct() {
+        Ok(direct) => direct.data() as usize,
+        Err(_) => return Err(BAIL_FAIL),
+    };
+    let msg_len = met(3, dat);
+
+    unsafe {
+        let (mut out_ida, out) = IndirectAtom::new_raw_mut_bytes(stack, 32);
+        // Past the end of [dat] the message is zeros, hashed without a buffer for them
+        let used = msg_len.min(length);
+        ac_keccak_256(&(dat.as_ne_bytes())[0..used], length - used, out);
+        // +keccak-256 produces the digest as a big-endian number
+        out.reverse();
+
+        Ok(out_ida.normalize_as_atom().as_noun())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ibig::ubig;
+
+    use super::*;
+    use crate::jets::util::test::{assert_jet_err, assert_jet_ubig, init_context, A};
+    use crate::noun::{D, T};
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_keccak_256() {
+        let c = &mut init_context();
+
+        let sam = T(&mut c.stack, &[D(0), D(0)]);
+        assert_jet_ubig(
+            c,
+            jet_keccak_256,
+            sam,
+            ubig!(_0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470),
+        );
+
+        let sam = T(&mut c.stack, &[D(3), D(0x636261)]); // [3 'abc']
+        assert_jet_ubig(
+            c,
+            jet_keccak_256,
+            sam,
+            ubig!(_0x4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45),
+        );
+
+        // Bytes past the length are ignored
+        let sam = T(&mut c.stack, &[D(0), D(0x636261)]);
+        assert_jet_ubig(
+            c,
+            jet_keccak_256,
+            sam,
+            ubig!(_0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470),
+        );
+
+        let wid = A(
+            &mut c.stack,
+            &ubig!(_0xa1d6eb6ef33f233ae6980ca7c4fc65f90fe1bdee11c730d41607b4747c83de72),
+        );
+        let sam = T(&mut c.stack, &[wid, D(1)]);
+        assert_jet_err(c, jet_keccak_256, sam, BAIL_FAIL);
+    }
+}
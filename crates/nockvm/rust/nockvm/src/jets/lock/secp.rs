@@ -0,0 +1,204 @@
+use nockvm_crypto::secp::{
+    ac_secp_priv_to_pub, ac_secp_reco, ac_secp_schnorr_sign, ac_secp_schnorr_veri, ac_secp_sign,
+};
+
+use crate::interpreter::Context;
+use crate::jets::bits::util::met;
+use crate::jets::util::{levy_by, slot, BAIL_EXIT};
+use crate::jets::{JetErr, Result};
+use crate::mem::NockStack;
+use crate::noun::{Atom, IndirectAtom, Noun, D, NO, T, YES};
+
+crate::gdb!();
+
+//  Note:   Every size and range check below is an assertion in the Hoon, so
+//          inputs that fail them crash rather than produce a value.
+
+/// Copy an atom into a fixed-size little-endian array, or `None` if it doesn't fit.
+fn fixed_bytes<const N: usize>(atom: Atom) -> Option<[u8; N]> {
+    let len = met(3, atom);
+    if len > N {
+        return None;
+    }
+    let mut out = [0u8; N];
+    out[0..len].copy_from_slice(&(atom.as_ne_bytes())[0..len]);
+    Some(out)
+}
+
+fn bytes_to_atom<const N: usize>(stack: &mut NockStack, bytes: &[u8; N]) -> Noun {
+    unsafe {
+        let (mut ida, out) = IndirectAtom::new_raw_mut_bytearray::<N, NockStack>(stack);
+        out.copy_from_slice(bytes);
+        ida.normalize_as_atom().as_noun()
+    }
+}
+
+fn point(stack: &mut NockStack, x: &[u8; 32], y: &[u8; 32]) -> Noun {
+    let x = bytes_to_atom(stack, x);
+    let y = bytes_to_atom(stack, y);
+    T(stack, &[x, y])
+}
+
+pub fn jet_secp_priv_to_pub(context: &mut Context, subject: Noun) -> Result {
+    let private_key = slot(subject, 6)?.as_atom()?;
+    let private_key = fixed_bytes::<32>(private_key).ok_or(BAIL_EXIT)?;
+
+    let (mut x, mut y) = ([0u8; 32], [0u8; 32]);
+    if !ac_secp_priv_to_pub(&private_key, &mut x, &mut y) {
+        return Err(BAIL_EXIT);
+    }
+    Ok(point(&mut context.stack, &x, &y))
+}
+
+pub fn jet_secp_sign(context: &mut Context, subject: Noun) -> Result {
+    let hash = fixed_bytes::<32>(slot(subject, 12)?.as_atom()?).ok_or(BAIL_EXIT)?;
+    let private_key = fixed_bytes::<32>(slot(subject, 13)?.as_atom()?).ok_or(BAIL_EXIT)?;
+
+    let (mut r, mut s) = ([0u8; 32], [0u8; 32]);
+    let v = ac_secp_sign(&hash, &private_key, &mut r, &mut s).ok_or(BAIL_EXIT)?;
+    let stack = &mut context.stack;
+    let r = bytes_to_atom(stack, &r);
+    let s = bytes_to_atom(stack, &s);
+    Ok(T(stack, &[D(v as u64), r, s]))
+}
+
+pub fn jet_secp_reco(context: &mut Context, subject: Noun) -> Result {
+    let hash = fixed_bytes::<32>(slot(subject, 12)?.as_atom()?).ok_or(BAIL_EXIT)?;
+    let v = slot(subject, 26)?.as_atom()?;
+    let r = fixed_bytes::<32>(slot(subject, 54)?.as_atom()?).ok_or(BAIL_EXIT)?;
+    let s = fixed_bytes::<32>(slot(subject, 55)?.as_atom()?).ok_or(BAIL_EXIT)?;
+
+    let v = match v.as_direct() {
+        Ok(v) if v.data() <= 3 => v.data() as u8,
+        _ => return Err(BAIL_EXIT),
+    };
+    if v >= 2 {
+        // The Hoon recovers from x = r + n without reducing it mod p, which libsecp256k1
+        // rejects. Honest signatures almost never have an x past the group order.
+        return Err(JetErr::Punt);
+    }
+
+    let (mut x, mut y) = ([0u8; 32], [0u8; 32]);
+    if !ac_secp_reco(&hash, v, &r, &s, &mut x, &mut y) {
+        return Err(BAIL_EXIT);
+    }
+    Ok(point(&mut context.stack, &x, &y))
+}
+
+pub fn jet_secp_schnorr_sign(context: &mut Context, subject: Noun) -> Result {
+    let private_key = fixed_bytes::<32>(slot(subject, 12)?.as_atom()?).ok_or(BAIL_EXIT)?;
+    let message = fixed_bytes::<32>(slot(subject, 26)?.as_atom()?).ok_or(BAIL_EXIT)?;
+    let aux = fixed_bytes::<32>(slot(subject, 27)?.as_atom()?).ok_or(BAIL_EXIT)?;
+
+    let mut signature = [0u8; 64];
+    if !ac_secp_schnorr_sign(&private_key, &message, &aux, &mut signature) {
+        return Err(BAIL_EXIT);
+    }
+    Ok(bytes_to_atom(&mut context.stack, &signature))
+}
+
+pub fn jet_secp_schnorr_veri(_context: &mut Context, subject: Noun) -> Result {
+    let sam = slot(subject, 6)?;
+    Ok(if schnorr_veri(sam)? { YES } else { NO })
+}
+
+pub fn jet_secp_schnorr_veri_batch(_context: &mut Context, subject: Noun) -> Result {
+    levy_by(slot(subject, 6)?, schnorr_veri)
+}
+
+/// Verify a `[pk=@I m=@I sig=@J]` sample
+fn schnorr_veri(sam: Noun) -> std::result::Result<bool, JetErr> {
+    let public_key = fixed_bytes::<32>(slot(sam, 2)?.as_atom()?).ok_or(BAIL_EXIT)?;
+    let message = fixed_bytes::<32>(slot(sam, 6)?.as_atom()?).ok_or(BAIL_EXIT)?;
+    let signature = fixed_bytes::<64>(slot(sam, 7)?.as_atom()?).ok_or(BAIL_EXIT)?;
+    Ok(ac_secp_schnorr_veri(&public_key, &message, &signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use ibig::ubig;
+
+    use super::*;
+    use crate::jets::util::test::{assert_jet, assert_jet_err, init_context, A};
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_secp_priv_to_pub() {
+        let c = &mut init_context();
+
+        let x = A(
+            &mut c.stack,
+            &ubig!(_0x79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798),
+        );
+        let y = A(
+            &mut c.stack,
+            &ubig!(_0x483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8),
+        );
+        let ret = T(&mut c.stack, &[x, y]);
+        assert_jet(c, jet_secp_priv_to_pub, D(1), ret);
+
+        assert_jet_err(c, jet_secp_priv_to_pub, D(0), BAIL_EXIT);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_secp_sign_reco() {
+        let c = &mut init_context();
+        let hash = A(
+            &mut c.stack,
+            &ubig!(_0xa1d6eb6ef33f233ae6980ca7c4fc65f90fe1bdee11c730d41607b4747c83de72),
+        );
+        let sam = T(&mut c.stack, &[hash, D(0xdeadbeef)]);
+        let sig = T(&mut c.stack, &[D(0), sam, D(0)]);
+        let sig = jet_secp_sign(c, sig).expect("sign");
+
+        let sam = T(&mut c.stack, &[hash, sig]);
+        let pub_key = T(&mut c.stack, &[D(0), D(0xdeadbeef), D(0)]);
+        let pub_key = jet_secp_priv_to_pub(c, pub_key).expect("priv-to-pub");
+        assert_jet(c, jet_secp_reco, sam, pub_key);
+
+        let bad = T(&mut c.stack, &[D(4), D(1), D(1)]);
+        let sam = T(&mut c.stack, &[hash, bad]);
+        assert_jet_err(c, jet_secp_reco, sam, BAIL_EXIT);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_secp_schnorr() {
+        let c = &mut init_context();
+
+        // BIP-340 test vector 0
+        let public_key = A(
+            &mut c.stack,
+            &ubig!(_0xf9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9),
+        );
+        let signature = A(
+            &mut c.stack,
+            &ubig!(_0xe907831f80848d1069a5371b402410364bdf1c5f8307b0084c55f1ce2dca821525f66a4a85ea8b71e482a74f382d2ce5ebeee8fdb2172f477df4900d310536c0),
+        );
+        let sam = T(&mut c.stack, &[D(3), D(0), D(0)]);
+        assert_jet(c, jet_secp_schnorr_sign, sam, signature);
+
+        let good = T(&mut c.stack, &[public_key, D(0), signature]);
+        assert_jet(c, jet_secp_schnorr_veri, good, YES);
+        let forged = T(&mut c.stack, &[public_key, D(1), signature]);
+        assert_jet(c, jet_secp_schnorr_veri, forged, NO);
+
+        let all = T(&mut c.stack, &[good, good, D(0)]);
+        assert_jet(c, jet_secp_schnorr_veri_batch, all, YES);
+        let one_bad = T(&mut c.stack, &[good, forged, D(0)]);
+        assert_jet(c, jet_secp_schnorr_veri_batch, one_bad, NO);
+        assert_jet(c, jet_secp_schnorr_veri_batch, D(0), YES);
+
+        // An oversized message crashes, unless an earlier signature already failed
+        let big = A(&mut c.stack, &(ubig!(1) << 256));
+        let oversized = T(&mut c.stack, &[public_key, big, signature]);
+        let sam = T(&mut c.stack, &[good, oversized, D(0)]);
+        assert_jet_err(c, jet_secp_schnorr_veri_batch, sam, BAIL_EXIT);
+        let sam = T(&mut c.stack, &[forged, oversized, D(0)]);
+        assert_jet(c, jet_secp_schnorr_veri_batch, sam, NO);
+
+        let sam = T(&mut c.stack, &[good, good, D(1)]);
+        assert_jet_err(c, jet_secp_schnorr_veri_batch, sam, BAIL_EXIT);
+    }
+}
@@ -28,11 +28,11 @@ pub fn jet_ut_crop(context: &mut Context, subject: Noun) -> Result {
     let fun = 141 + tas!(b"crop") + (flag << 8);
     let mut key = T(&mut context.stack, &[D(fun), sut, rff, bat]);
 
-    match context.cache.lookup(&mut context.stack, &mut key) {
+    match context.memo_lookup(&mut key) {
         Some(pro) => Ok(pro),
         None => {
             let pro = interpret(context, subject, slot(subject, 2)?)?;
-            context.cache = context.cache.insert(&mut context.stack, &mut key, pro);
+            context.memo_insert(&mut key, pro);
             Ok(pro)
         }
     }
@@ -58,11 +58,11 @@ pub fn jet_ut_fish(context: &mut Context, subject: Noun) -> Result {
     let fun = 141 + tas!(b"fish") + (flag << 8);
     let mut key = T(&mut context.stack, &[D(fun), sut, axe.as_noun(), bat]);
 
-    match context.cache.lookup(&mut context.stack, &mut key) {
+    match context.memo_lookup(&mut key) {
         Some(pro) => Ok(pro),
         None => {
             let pro = interpret(context, subject, slot(subject, 2)?)?;
-            context.cache = context.cache.insert(&mut context.stack, &mut key, pro);
+            context.memo_insert(&mut key, pro);
             Ok(pro)
         }
     }
@@ -87,11 +87,11 @@ pub fn jet_ut_fuse(context: &mut Context, subject: Noun) -> Result {
     let fun = 141 + tas!(b"fuse") + (flag << 8);
     let mut key = T(&mut context.stack, &[D(fun), sut, rff, bat]);
 
-    match context.cache.lookup(&mut context.stack, &mut key) {
+    match context.memo_lookup(&mut key) {
         Some(pro) => Ok(pro),
         None => {
             let pro = interpret(context, subject, slot(subject, 2)?)?;
-            context.cache = context.cache.insert(&mut context.stack, &mut key, pro);
+            context.memo_insert(&mut key, pro);
             Ok(pro)
         }
     }
@@ -109,11 +109,11 @@ pub fn jet_ut_mint(context: &mut Context, subject: Noun) -> Result {
     let vet = slot(van, 59).map_or(NONE, |x| x);
     let mut key = T(&mut context.stack, &[D(fun), vet, sut, gol, gen, bat]);
 
-    match context.cache.lookup(&mut context.stack, &mut key) {
+    match context.memo_lookup(&mut key) {
         Some(pro) => Ok(pro),
         None => {
             let pro = interpret(context, subject, slot(subject, 2)?)?;
-            context.cache = context.cache.insert(&mut context.stack, &mut key, pro);
+            context.memo_insert(&mut key, pro);
             Ok(pro)
         }
     }
@@ -140,11 +140,11 @@ pub fn jet_ut_mull(context: &mut Context, subject: Noun) -> Result {
     let fun = 141 + tas!(b"mull") + (flag << 8);
     let mut key = T(&mut context.stack, &[D(fun), sut, gol, dox, gen, bat]);
 
-    match context.cache.lookup(&mut context.stack, &mut key) {
+    match context.memo_lookup(&mut key) {
         Some(pro) => Ok(pro),
         None => {
             let pro = interpret(context, subject, slot(subject, 2)?)?;
-            context.cache = context.cache.insert(&mut context.stack, &mut key, pro);
+            context.memo_insert(&mut key, pro);
             Ok(pro)
         }
     }
@@ -175,14 +175,14 @@ pub fn jet_ut_nest_dext(context: &mut Context, subject: Noun) -> Result {
     let fun = (141 + tas!(b"dext")) + (flag << 8);
     let mut key = T(&mut context.stack, &[D(fun), sut, rff, bat]);
 
-    match context.cache.lookup(&mut context.stack, &mut key) {
+    match context.memo_lookup(&mut key) {
         Some(pro) => Ok(pro),
         None => {
             let pro = interpret(context, subject, slot(subject, 2)?)?;
             if unsafe { pro.raw_equals(&YES) && reg.raw_equals(&D(0)) }
                 || unsafe { pro.raw_equals(&NO) && seg.raw_equals(&D(0)) }
             {
-                context.cache = context.cache.insert(&mut context.stack, &mut key, pro);
+                context.memo_insert(&mut key, pro);
             }
             Ok(pro)
         }
@@ -208,11 +208,11 @@ pub fn jet_ut_rest(context: &mut Context, subject: Noun) -> Result {
     let fun = 141 + tas!(b"rest") + (flag << 8);
     let mut key = T(&mut context.stack, &[D(fun), sut, leg, bat]);
 
-    match context.cache.lookup(&mut context.stack, &mut key) {
+    match context.memo_lookup(&mut key) {
         Some(pro) => Ok(pro),
         None => {
             let pro = interpret(context, subject, slot(subject, 2)?)?;
-            context.cache = context.cache.insert(&mut context.stack, &mut key, pro);
+            context.memo_insert(&mut key, pro);
             Ok(pro)
         }
     }
@@ -13,13 +13,18 @@ use ibig::UBig;
  * patching it, we might even be able to avoid copying the input and output at all, which might
  * give a greater performance advantage than using gmp anyway.
  *
+ * Our vendored ibig is patched to allocate on the nock stack, and its `words` module computes
+ * directly on word slices.  The add, mul, mod and pow jets use it on views of the atoms' words
+ * in the loom, writing their results straight into a new indirect atom, so big atoms are never
+ * copied in or out.
+ *
  * Another approach is use a global custom allocator.  This is fairly involved, but it would allow
  * us to use any library without worrying whether it allocates.
  */
 use crate::interpreter::Context;
 use crate::jets::util::*;
 use crate::jets::Result;
-use crate::noun::{Atom, DirectAtom, IndirectAtom, Noun, D, DIRECT_MAX, T};
+use crate::noun::{Atom, DirectAtom, IndirectAtom, Noun, D, T};
 
 crate::gdb!();
 
@@ -208,37 +213,27 @@ pub fn jet_mod(context: &mut Context, subject: Noun) -> Result {
     } else if let (Ok(a), Ok(b)) = (a.as_direct(), b.as_direct()) {
         Ok(unsafe { DirectAtom::new_unchecked(a.data() % b.data()) }.as_noun())
     } else {
-        let res = a.as_ubig(stack) % b.as_ubig(stack);
-        Ok(Atom::from_ubig(stack, &res).as_noun())
+        Ok(util::rem(stack, a, b).as_noun())
     }
 }
 
 pub fn jet_mul(context: &mut Context, subject: Noun) -> Result {
-    let stack = &mut context.stack;
     let arg = slot(subject, 6)?;
     let a = slot(arg, 2)?.as_atom()?;
     let b = slot(arg, 3)?.as_atom()?;
 
-    if let (Ok(a), Ok(b)) = (a.as_direct(), b.as_direct()) {
-        let res = a.data() as u128 * b.data() as u128;
-        if res < DIRECT_MAX as u128 {
-            Ok(Atom::new(stack, res as u64).as_noun())
-        } else {
-            Ok(unsafe {
-                IndirectAtom::new_raw_bytes(
-                    stack,
-                    if res < u64::MAX as u128 { 8 } else { 16 },
-                    &res as *const u128 as *const u8,
-                )
-            }
-            .as_noun())
-        }
-    } else {
-        let a_big = a.as_ubig(stack);
-        let b_big = b.as_ubig(stack);
-        let res = UBig::mul_stack(stack, a_big, b_big);
-        Ok(Atom::from_ubig(stack, &res).as_noun())
-    }
+    Ok(util::mul(&mut context.stack, a, b).as_noun())
+}
+
+pub fn jet_pow(context: &mut Context, subject: Noun) -> Result {
+    let arg = slot(subject, 6)?;
+    let a = slot(arg, 2)?.as_atom()?;
+    let b = slot(arg, 3)?.as_atom()?;
+
+    // Anything but 0 or 1 to a power that doesn't fit in a word would not fit in memory
+    util::pow(&mut context.stack, a, b)
+        .ok_or(BAIL_MEME)
+        .map(|res| res.as_noun())
 }
 
 pub fn jet_sub(context: &mut Context, subject: Noun) -> Result {
@@ -250,23 +245,92 @@ pub fn jet_sub(context: &mut Context, subject: Noun) -> Result {
 }
 
 pub mod util {
-    use ibig::UBig;
+    use std::slice::from_raw_parts_mut;
+
+    use ibig::{words, UBig};
 
     use crate::mem::NockStack;
-    use crate::noun::{Atom, Error, Noun, Result, NO, YES};
+    use crate::noun::{Atom, Error, IndirectAtom, Noun, Result, DIRECT_MAX, NO, YES};
+
+    /// Compute an atom of at most `size` words by writing its words straight into the loom
+    fn from_words<F>(stack: &mut NockStack, size: usize, f: F) -> Atom
+    where
+        F: FnOnce(&mut NockStack, &mut [u64]),
+    {
+        unsafe {
+            let (mut atom, buffer) = IndirectAtom::new_raw_mut(stack, size);
+            f(stack, from_raw_parts_mut(buffer, size));
+            atom.normalize_as_atom()
+        }
+    }
 
     /// Addition
     pub fn add(stack: &mut NockStack, a: Atom, b: Atom) -> Atom {
         if let (Ok(a), Ok(b)) = (a.as_direct(), b.as_direct()) {
             Atom::new(stack, a.data() + b.data())
         } else {
-            let a_big = a.as_ubig(stack);
-            let b_big = b.as_ubig(stack);
-            let res = UBig::add_stack(stack, a_big, b_big);
-            Atom::from_ubig(stack, &res)
+            let size = a.size().max(b.size()) + 1;
+            from_words(stack, size, |_stack, out| {
+                words::add(out, a.as_slice(), b.as_slice())
+            })
+        }
+    }
+
+    /// Multiplication
+    pub fn mul(stack: &mut NockStack, a: Atom, b: Atom) -> Atom {
+        if let (Ok(a), Ok(b)) = (a.as_direct(), b.as_direct()) {
+            let res = a.data() as u128 * b.data() as u128;
+            if res <= DIRECT_MAX as u128 {
+                Atom::new(stack, res as u64)
+            } else {
+                from_words(stack, 2, |_stack, out| {
+                    out.copy_from_slice(&[res as u64, (res >> 64) as u64])
+                })
+            }
+        } else {
+            let size = a.size() + b.size();
+            from_words(stack, size, |stack, out| {
+                words::mul_stack(stack, out, a.as_slice(), b.as_slice())
+            })
         }
     }
 
+    /// Modulus. Panics if `b` is 0.
+    pub fn rem(stack: &mut NockStack, a: Atom, b: Atom) -> Atom {
+        if let (Ok(a), Ok(b)) = (a.as_direct(), b.as_direct()) {
+            Atom::new(stack, a.data() % b.data())
+        } else {
+            from_words(stack, b.size(), |stack, out| {
+                words::rem_stack(stack, out, a.as_slice(), b.as_slice())
+            })
+        }
+    }
+
+    /// Exponentiation, or None if the result can't fit in memory
+    pub fn pow(stack: &mut NockStack, a: Atom, b: Atom) -> Option<Atom> {
+        if a.bit_size() <= 1 || b.bit_size() == 0 {
+            // 0 or 1 to any power, or anything to the power of 0
+            return Some(if b.bit_size() == 0 {
+                Atom::new(stack, 1)
+            } else {
+                a
+            });
+        }
+        let exp = b.as_u64().ok()?;
+        let bits = (a.bit_size() as u64).checked_mul(exp)?;
+        if bits > (stack.size() as u64) << 6 {
+            return None;
+        }
+        let mut res = a;
+        for bit in (0..63 - exp.leading_zeros()).rev() {
+            res = mul(stack, res, res);
+            if (exp >> bit) & 1 == 1 {
+                res = mul(stack, res, a);
+            }
+        }
+        Some(res)
+    }
+
     /// Greater than or equal to (boolean)
     pub fn gte_b(stack: &mut NockStack, a: Atom, b: Atom) -> bool {
         if let (Ok(a), Ok(b)) = (a.as_direct(), b.as_direct()) {
@@ -729,6 +793,92 @@ mod tests {
         assert_common_jet(c, jet_mul, &[atom_24, atom_24], ubig!(0x479bf4b7ef89));
     }
 
+    fn words_atom(stack: &mut NockStack, words: &[u64]) -> Atom {
+        unsafe { IndirectAtom::new_raw(stack, words.len(), words.as_ptr()).normalize_as_atom() }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_pow() {
+        let c = &mut init_context();
+        fn atom_5(_stack: &mut NockStack) -> Noun {
+            D(5)
+        }
+        fn atom_1(_stack: &mut NockStack) -> Noun {
+            D(1)
+        }
+
+        assert_common_jet(
+            c,
+            jet_pow,
+            &[atom_24, atom_5],
+            UBig::from(0x876543u128.pow(5)),
+        );
+        assert_common_jet(c, jet_pow, &[atom_63, atom_1], ubig!(0x7fffffffffffffff));
+        assert_common_jet(c, jet_pow, &[atom_96, atom_0], ubig!(1));
+        assert_common_jet(c, jet_pow, &[atom_0, atom_0], ubig!(1));
+        assert_common_jet(c, jet_pow, &[atom_0, atom_128], ubig!(0));
+        assert_common_jet(c, jet_pow, &[atom_1, atom_128], ubig!(1));
+        assert_common_jet_err(c, jet_pow, &[atom_24, atom_128], BAIL_MEME);
+
+        let sam = T(&mut c.stack, &[D(2), D(200)]);
+        let res = words_atom(&mut c.stack, &[0, 0, 0, 1 << 8]).as_noun();
+        assert_jet(c, jet_pow, sam, res);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_big_atom_words() {
+        let c = &mut init_context();
+        let s = &mut c.stack;
+
+        // Big enough for karatsuba multiplication and divide and conquer division:
+        // a = 2^(64*140) - 1 and b = 2^(64*70) + 1
+        let a = words_atom(s, &[u64::MAX; 140]);
+        let mut b_words = [0; 71];
+        b_words[0] = 1;
+        b_words[70] = 1;
+        let b = words_atom(s, &b_words);
+        let small = D(0x876543).as_atom().expect("atom");
+
+        let mut sum = [0; 141];
+        sum[70] = 1;
+        sum[140] = 1;
+        let sum = words_atom(s, &sum).as_noun();
+        let a_b = util::add(s, a, b);
+        assert_noun_eq(s, a_b.as_noun(), sum);
+
+        // a * b = 2^(64*210) + 2^(64*140) - 2^(64*70) - 1
+        let mut product = [u64::MAX; 211];
+        product[70] = u64::MAX - 1;
+        product[140..].fill(0);
+        product[210] = 1;
+        let product = words_atom(s, &product).as_noun();
+        let ab = util::mul(s, a, b);
+        assert_noun_eq(s, ab.as_noun(), product);
+        let ba = util::mul(s, b, a);
+        assert_noun_eq(s, ba.as_noun(), product);
+
+        let rem = util::rem(s, ab, b);
+        assert_noun_eq(s, rem.as_noun(), D(0));
+        let ab_small = util::add(s, ab, small);
+        let rem = util::rem(s, ab_small, b);
+        assert_noun_eq(s, rem.as_noun(), small.as_noun());
+        let b_less = util::sub(s, b, D(1).as_atom().expect("atom")).expect("sub");
+        let ab_big = util::add(s, ab, b_less);
+        let rem = util::rem(s, ab_big, b);
+        assert_noun_eq(s, rem.as_noun(), b_less.as_noun());
+        let rem = util::rem(s, b, a);
+        assert_noun_eq(s, rem.as_noun(), b.as_noun());
+
+        let a_small = util::mul(s, a, small);
+        let small_a = util::mul(s, small, a);
+        assert_noun_eq(s, a_small.as_noun(), small_a.as_noun());
+        let a_small_5 = util::add(s, a_small, D(5).as_atom().expect("atom"));
+        let rem = util::rem(s, a_small_5, small);
+        assert_noun_eq(s, rem.as_noun(), D(5));
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn test_sub() {
@@ -77,12 +77,12 @@ pub mod util {
     use either::{Left, Right};
     use nockvm_macros::tas;
 
-    use crate::hamt::Hamt;
     use crate::interpreter::{interpret, Context, Error, Mote};
     use crate::jets;
     use crate::jets::bits::util::rip;
     use crate::jets::form::util::scow;
     use crate::mem::NockStack;
+    use crate::memo::MemoCache;
     use crate::noun::{tape, Cell, Noun, D, T};
 
     pub const LEAF: Noun = D(tas!(b"leaf"));
@@ -153,7 +153,7 @@ pub mod util {
         let cache_snapshot = context.cache;
         let scry_snapshot = context.scry_stack;
 
-        context.cache = Hamt::<Noun>::new(&mut context.stack);
+        context.cache = MemoCache::new(&mut context.stack);
         context.scry_stack = T(&mut context.stack, &[scry, context.scry_stack]);
 
         match interpret(context, subject, formula) {
@@ -168,6 +168,13 @@ pub mod util {
                     context.scry_stack = scry_snapshot;
                     Ok(T(&mut context.stack, &[D(1), path]))
                 }
+                Error::Deterministic(Mote::Fuel, _) => {
+                    // Running out of fuel ends the whole metered computation, rather than
+                    // crashing the virtualized one where it could be caught.
+                    context.cache = cache_snapshot;
+                    context.scry_stack = scry_snapshot;
+                    Err(err)
+                }
                 Error::Deterministic(_, trace) => {
                     context.cache = cache_snapshot;
                     context.scry_stack = scry_snapshot;
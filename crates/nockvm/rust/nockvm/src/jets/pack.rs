@@ -0,0 +1,188 @@
+//! Jet packs: sets of jets that crates outside nockvm contribute to the hot state.
+//!
+//! A crate implements [`JetPack`] for its jets and an application registers the packs it wants
+//! with a [`JetRegistry`], which hands the interpreter their combined hot state. Registration
+//! fails if a pack would jet an arm that nockvm or an earlier pack already jets with a different
+//! function, rather than leaving it up to the order of the hot state which one runs.
+use either::Either::{self, Left, Right};
+use thiserror::Error;
+
+use crate::jets::hot::{HotEntry, URBIT_HOT_STATE};
+
+/// A set of jets contributed to the hot state
+pub trait JetPack {
+    /// A short name for the pack, used in errors and logs
+    fn name(&self) -> &'static str;
+
+    /// The jets in the pack, as `(path, axis in battery, jet)` hot state entries
+    fn jets(&self) -> Vec<HotEntry>;
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum JetPackError {
+    #[error("jet pack {pack} jets {path} at axis {axis}, which {other} already jets differently")]
+    Conflict {
+        pack: &'static str,
+        other: &'static str,
+        path: String,
+        axis: u64,
+    },
+    #[error("jet pack {0} is already registered")]
+    Duplicate(&'static str),
+}
+
+/// The jet packs an application runs with, on top of nockvm's own jets
+#[derive(Default)]
+pub struct JetRegistry {
+    entries: Vec<HotEntry>,
+    /// The pack each entry came from
+    owners: Vec<&'static str>,
+    packs: Vec<&'static str>,
+}
+
+impl JetRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the jets of `pack`. Jets it shares with nockvm or an earlier pack, down to the jet
+    /// function, are only added once.
+    pub fn register(&mut self, pack: &dyn JetPack) -> Result<(), JetPackError> {
+        let name = pack.name();
+        if self.packs.contains(&name) {
+            return Err(JetPackError::Duplicate(name));
+        }
+        let mut added: Vec<HotEntry> = Vec::new();
+        for entry in pack.jets() {
+            let builtin = URBIT_HOT_STATE.iter().map(|old| (old, "nockvm"));
+            let registered = self.entries.iter().zip(self.owners.iter().copied());
+            let earlier = added.iter().map(|old| (old, name));
+            let existing = builtin
+                .chain(registered)
+                .chain(earlier)
+                .find(|(old, _)| old.0 == entry.0 && old.1 == entry.1)
+                .map(|(old, owner)| (old.2 as usize, owner));
+            match existing {
+                Some((jet, _)) if jet == entry.2 as usize => {}
+                Some((_, other)) => {
+                    return Err(JetPackError::Conflict {
+                        pack: name,
+                        other,
+                        path: render_path(entry.0),
+                        axis: entry.1,
+                    });
+                }
+                None => added.push(entry),
+            }
+        }
+        self.owners.extend(added.iter().map(|_| name));
+        self.entries.extend(added);
+        self.packs.push(name);
+        Ok(())
+    }
+
+    /// The names of the registered packs, in registration order
+    pub fn packs(&self) -> &[&'static str] {
+        &self.packs
+    }
+
+    /// The hot state entries of every registered pack, to run alongside nockvm's own
+    pub fn hot_state(&self) -> &[HotEntry] {
+        &self.entries
+    }
+}
+
+/// Render a hot state path like `k.138/one/add`
+fn render_path(path: &[Either<&[u8], (u64, u64)>]) -> String {
+    path.iter()
+        .map(|step| match step {
+            Left(name) => String::from_utf8_lossy(name).into_owned(),
+            Right((name, version)) => {
+                let bytes = name.to_le_bytes();
+                let len = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+                format!("{}.{}", String::from_utf8_lossy(&bytes[..len]), version)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Context;
+    use crate::jets::hot::K_138;
+    use crate::jets::{Jet, Result};
+    use crate::noun::{Noun, D};
+
+    fn jet_one(_context: &mut Context, _subject: Noun) -> Result {
+        Ok(D(1))
+    }
+
+    fn jet_two(_context: &mut Context, _subject: Noun) -> Result {
+        Ok(D(2))
+    }
+
+    const FROB: &[Either<&[u8], (u64, u64)>] = &[K_138, Left(b"one"), Left(b"frob")];
+    const ADD: &[Either<&[u8], (u64, u64)>] = &[K_138, Left(b"one"), Left(b"add")];
+
+    fn entry(path: &'static [Either<&'static [u8], (u64, u64)>], axis: u64, jet: Jet) -> HotEntry {
+        (path, axis, jet)
+    }
+
+    struct Pack(&'static str, Vec<HotEntry>);
+
+    impl JetPack for Pack {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn jets(&self) -> Vec<HotEntry> {
+            self.1.clone()
+        }
+    }
+
+    #[test]
+    fn test_register_jet_packs() {
+        let mut registry = JetRegistry::new();
+        let first = Pack(
+            "first",
+            vec![entry(FROB, 1, jet_one), entry(FROB, 1, jet_one)],
+        );
+        registry.register(&first).expect("register");
+        assert_eq!(registry.hot_state().len(), 1);
+        assert_eq!(
+            registry.register(&first),
+            Err(JetPackError::Duplicate("first"))
+        );
+
+        // Sharing a jet is fine, overriding one isn't
+        let second = Pack(
+            "second",
+            vec![entry(FROB, 1, jet_one), entry(FROB, 2, jet_two)],
+        );
+        registry.register(&second).expect("register");
+        assert_eq!(registry.hot_state().len(), 2);
+
+        let third = Pack("third", vec![entry(FROB, 2, jet_one)]);
+        assert_eq!(
+            registry.register(&third),
+            Err(JetPackError::Conflict {
+                pack: "third",
+                other: "second",
+                path: "k.138/one/frob".to_string(),
+                axis: 2,
+            })
+        );
+
+        let builtin = Pack("builtin", vec![entry(ADD, 1, jet_one)]);
+        assert!(matches!(
+            registry.register(&builtin),
+            Err(JetPackError::Conflict {
+                other: "nockvm",
+                ..
+            })
+        ));
+        assert_eq!(registry.packs(), &["first", "second"]);
+    }
+}
@@ -6,7 +6,7 @@ use crate::interpreter::Context;
 use crate::jets::bits::util::met;
 use crate::jets::math::util::{gte_b, lte_b, lth_b};
 use crate::jets::util::{kick, slam, slot, BAIL_FAIL};
-use crate::jets::Result;
+use crate::jets::{JetErr, Result};
 use crate::noun::{Cell, Noun, D, T};
 
 crate::gdb!();
@@ -41,6 +41,28 @@ pub fn jet_trip(context: &mut Context, subject: Noun) -> Result {
     Ok(result)
 }
 
+pub fn jet_cass(context: &mut Context, subject: Noun) -> Result {
+    let vib = slot(subject, 6)?;
+    util::map_tape(&mut context.stack, vib, |chr| {
+        if (b'A' as u64..=b'Z' as u64).contains(&chr) {
+            chr + 32
+        } else {
+            chr
+        }
+    })
+}
+
+pub fn jet_cuss(context: &mut Context, subject: Noun) -> Result {
+    let vib = slot(subject, 6)?;
+    util::map_tape(&mut context.stack, vib, |chr| {
+        if (b'a' as u64..=b'z' as u64).contains(&chr) {
+            chr - 32
+        } else {
+            chr
+        }
+    })
+}
+
 //
 //  Tracing
 //
@@ -598,13 +620,72 @@ pub fn jet_stir(context: &mut Context, subject: Noun) -> Result {
     }
 }
 
+pub fn jet_stun(context: &mut Context, subject: Noun) -> Result {
+    let mut tub = slot(subject, 6)?;
+    let van = slot(subject, 7)?;
+    let lig = slot(van, 12)?.as_cell()?;
+    let fel = slot(van, 13)?;
+    // Bounds too big for a direct atom are left to the Hoon
+    let (Ok(min), Ok(max)) = (lig.head().as_direct(), lig.tail().as_direct()) else {
+        return Err(JetErr::Punt);
+    };
+    let mut min = min.data();
+    let mut max = max.data();
+
+    // successful [fel] parse results, first first
+    let mut res: Vec<Noun> = vec![];
+    let p_wag = loop {
+        if max == 0 {
+            break tub.as_cell()?.head();
+        }
+        let vex = slam(context, fel, tub)?.as_cell()?;
+        let q_vex = vex.tail();
+        if unsafe { q_vex.raw_equals(&D(0)) } {
+            if min == 0 {
+                break vex.head();
+            }
+            return Ok(vex.as_noun());
+        }
+        res.push(slot(q_vex, 6)?);
+        tub = slot(q_vex, 7)?;
+        min = min.saturating_sub(1);
+        max = max.saturating_sub(1);
+    };
+
+    let mut puq_wag = D(0);
+    while let Some(n) = res.pop() {
+        puq_wag = T(&mut context.stack, &[n, puq_wag]);
+    }
+    Ok(T(&mut context.stack, &[p_wag, D(0), puq_wag, tub]))
+}
+
 pub mod util {
     use std::cmp::Ordering;
 
     use crate::interpreter::{inc, Context};
     use crate::jets::Result;
+    use crate::mem::NockStack;
     use crate::noun::{Noun, D, T};
 
+    /// The tape with `f` applied to every character that's a direct atom, as +turn would
+    pub fn map_tape(stack: &mut NockStack, mut vib: Noun, f: impl Fn(u64) -> u64) -> Result {
+        let mut chrs: Vec<Noun> = vec![];
+        while !unsafe { vib.raw_equals(&D(0)) } {
+            let cell = vib.as_cell()?;
+            let chr = cell.head();
+            chrs.push(match chr.as_direct() {
+                Ok(direct) => D(f(direct.data())),
+                Err(_) => chr.as_atom()?.as_noun(),
+            });
+            vib = cell.tail();
+        }
+        let mut res = D(0);
+        while let Some(chr) = chrs.pop() {
+            res = T(stack, &[chr, res]);
+        }
+        Ok(res)
+    }
+
     pub fn last(zyc: Noun, naz: Noun) -> Result {
         let zyl = zyc.as_cell()?;
         let nal = naz.as_cell()?;
@@ -684,6 +765,27 @@ mod tests {
     //      +mask
     //      +stag
 
+    #[test]
+    fn test_cass_cuss() {
+        let c = &mut init_context();
+
+        let sam = T(
+            &mut c.stack,
+            &[D(b'a' as u64), D(b'Z' as u64), D(b'-' as u64), D(0)],
+        );
+        let res = T(
+            &mut c.stack,
+            &[D(b'a' as u64), D(b'z' as u64), D(b'-' as u64), D(0)],
+        );
+        assert_jet(c, jet_cass, sam, res);
+        let res = T(
+            &mut c.stack,
+            &[D(b'A' as u64), D(b'Z' as u64), D(b'-' as u64), D(0)],
+        );
+        assert_jet(c, jet_cuss, sam, res);
+        assert_jet(c, jet_cass, D(0), D(0));
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn test_easy() {
@@ -779,4 +881,75 @@ mod tests {
         let ctx = T(&mut c.stack, &[D(0), D(0x6f6f66), D(0)]);
         assert_jet_door(c, jet_easy, sam, ctx, ans);
     }
+
+    /// The +stun door for `lig` over a rule taking one `'a'`, without moving the hair
+    fn stun_door(c: &mut Context, min: Noun, max: Noun) -> Noun {
+        // ?~  q.tub  [p.tub ~]
+        // ?.  =('a' i.q.tub)  [p.tub ~]
+        // [p.tub ~ 'a' p.tub t.q.tub]
+        let p_tub = T(&mut c.stack, &[D(0), D(12)]);
+        let sig = T(&mut c.stack, &[D(1), D(0)]);
+        let fail = T(&mut c.stack, &[p_tub, sig]);
+        let chr = T(&mut c.stack, &[D(1), D(97)]);
+        let rest = T(&mut c.stack, &[D(0), D(27)]);
+        let take = T(&mut c.stack, &[p_tub, sig, chr, p_tub, rest]);
+        let i_tub = T(&mut c.stack, &[D(0), D(26)]);
+        let same = T(&mut c.stack, &[D(5), chr, i_tub]);
+        let check = T(&mut c.stack, &[D(6), same, take, fail]);
+        let q_tub = T(&mut c.stack, &[D(0), D(13)]);
+        let is_cell = T(&mut c.stack, &[D(3), q_tub]);
+        let rule = T(&mut c.stack, &[D(6), is_cell, check, fail]);
+        let fel = T(&mut c.stack, &[rule, D(0), D(0)]);
+        let lig = T(&mut c.stack, &[min, max]);
+        let sam = T(&mut c.stack, &[lig, fel]);
+        T(&mut c.stack, &[D(0), sam, D(0)])
+    }
+
+    fn tape(c: &mut Context, s: &str) -> Noun {
+        let mut res = D(0);
+        for chr in s.bytes().rev() {
+            res = T(&mut c.stack, &[D(chr as u64), res]);
+        }
+        res
+    }
+
+    #[test]
+    fn test_stun() {
+        let c = &mut init_context();
+        let hair = T(&mut c.stack, &[D(1), D(1)]);
+        let aaab = tape(c, "aaab");
+        let sam = T(&mut c.stack, &[hair, aaab]);
+
+        // Parsing nothing
+        let door = stun_door(c, D(0), D(0));
+        let res = T(&mut c.stack, &[hair, D(0), D(0), sam]);
+        assert_jet_door(c, jet_stun, sam, door, res);
+
+        // Failing before the minimum
+        let door = stun_door(c, D(4), D(9));
+        let res = T(&mut c.stack, &[hair, D(0)]);
+        assert_jet_door(c, jet_stun, sam, door, res);
+
+        // Failing after the minimum
+        let door = stun_door(c, D(2), D(9));
+        let aaa = tape(c, "aaa");
+        let b = tape(c, "b");
+        let res = T(&mut c.stack, &[hair, D(0), aaa, hair, b]);
+        assert_jet_door(c, jet_stun, sam, door, res);
+
+        // Reaching the maximum
+        let door = stun_door(c, D(0), D(2));
+        let aa = tape(c, "aa");
+        let ab = tape(c, "ab");
+        let res = T(&mut c.stack, &[hair, D(0), aa, hair, ab]);
+        assert_jet_door(c, jet_stun, sam, door, res);
+
+        // Bounds too big for the jet
+        let big = A(&mut c.stack, &ubig!(18446744073709551616));
+        for (min, max) in [(big, D(2)), (D(0), big)] {
+            let door = stun_door(c, min, max);
+            let subject = T(&mut c.stack, &[D(0), sam, door]);
+            assert!(matches!(jet_stun(c, subject), Err(JetErr::Punt)));
+        }
+    }
 }
@@ -5,15 +5,22 @@ extern crate lazy_static;
 extern crate num_derive;
 #[macro_use]
 extern crate static_assertions;
+pub mod bytecode;
 mod flog;
+pub mod fuel;
 pub mod hamt;
 pub mod interpreter;
 pub mod jets;
 pub mod mem;
+pub mod memo;
 pub mod mug;
 pub mod noun;
+pub mod pma;
+pub mod profile;
 pub mod serialization;
+pub mod shadow;
 mod site;
+pub mod stats;
 pub mod substantive;
 pub mod trace;
 pub mod unifying_equality;
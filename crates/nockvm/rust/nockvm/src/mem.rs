@@ -2,6 +2,7 @@
 use std::alloc::Layout;
 use std::ops::{Deref, DerefMut};
 use std::panic::panic_any;
+use std::path::Path;
 use std::ptr::copy_nonoverlapping;
 use std::{mem, ptr};
 
@@ -11,6 +12,7 @@ use memmap2::MmapMut;
 use thiserror::Error;
 
 use crate::noun::{Atom, Cell, CellMemory, IndirectAtom, Noun, NounAllocator};
+use crate::pma::{Pma, PmaError, PmaSnapshot, PmaState};
 use crate::{assert_acyclic, assert_no_forwarding_pointers, assert_no_junior_pointers};
 
 crate::gdb!();
@@ -162,6 +164,8 @@ pub enum AllocType {
 pub enum Memory {
     Mmap(MmapMut),
     Malloc(*mut u8, usize),
+    /// A loom persisted in a file, see [`crate::pma`]
+    Pma(Pma),
 }
 
 impl Deref for Memory {
@@ -172,6 +176,7 @@ impl Deref for Memory {
         match self {
             Memory::Mmap(mmap) => mmap.deref(),
             Memory::Malloc(ptr, size) => unsafe { core::slice::from_raw_parts(*ptr, *size) },
+            Memory::Pma(pma) => unsafe { core::slice::from_raw_parts(pma.as_ptr(), pma.len()) },
         }
     }
 }
@@ -182,6 +187,7 @@ impl DerefMut for Memory {
         match self {
             Memory::Mmap(mmap) => mmap.deref_mut(),
             Memory::Malloc(ptr, size) => unsafe { core::slice::from_raw_parts_mut(*ptr, *size) },
+            Memory::Pma(pma) => unsafe { core::slice::from_raw_parts_mut(pma.as_ptr(), pma.len()) },
         }
     }
 }
@@ -225,6 +231,18 @@ impl Memory {
     }
 }
 
+/// Counts of the allocation and copying a [`NockStack`] has done since it was created
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StackStats {
+    /// Allocations in the current frame
+    pub allocations: u64,
+    pub allocated_words: u64,
+    /// Nouns copied to the previous frame by [`NockStack::preserve`]
+    pub copies: u64,
+    /// Words allocated in the previous frame to copy nouns to
+    pub copied_words: u64,
+}
+
 /// A stack for Nock computation, which supports stack allocation and delimited copying collection
 /// for returned nouns
 #[allow(dead_code)] // We need the memory field to keep our memory from being unmapped
@@ -245,6 +263,7 @@ pub struct NockStack {
     memory: Memory,
     /// Whether or not [`Self::pre_copy()`] has been called on the current stack frame.
     pc: bool,
+    stats: StackStats,
 }
 
 impl NockStack {
@@ -312,9 +331,86 @@ impl NockStack {
         }
         let free = size - (top_slots + RESERVED);
         #[cfg(feature = "mmap")]
-        let mut memory = Memory::allocate(AllocType::Mmap, size)?;
+        let memory = Memory::allocate(AllocType::Mmap, size)?;
         #[cfg(feature = "malloc")]
-        let mut memory = Memory::allocate(AllocType::Malloc, size)?;
+        let memory = Memory::allocate(AllocType::Malloc, size)?;
+        Ok((Self::with_memory(memory, size, top_slots), free))
+    }
+
+    /// Like [`NockStack::new_`], but with the loom in a new persistent memory arena at `path`,
+    /// replacing any there. See [`crate::pma`].
+    pub fn new_pma(path: &Path, size: usize, top_slots: usize) -> Result<NockStack, PmaError> {
+        if top_slots + RESERVED > size {
+            return Err(PmaError::StackTooSmall);
+        }
+        let pma = Pma::create(path, size)?;
+        Ok(Self::with_memory(Memory::Pma(pma), size, top_slots))
+    }
+
+    /// Reopen the persistent memory arena at `path` as it was at its last snapshot, with the roots
+    /// passed to [`NockStack::snapshot`].
+    pub fn open_pma(path: &Path) -> Result<(NockStack, Vec<Noun>), PmaError> {
+        let (pma, state) = Pma::open(path)?;
+        let size = pma.len() >> 3;
+        let start = pma.as_ptr() as *const u64;
+        let roots = state
+            .roots
+            .iter()
+            .map(|raw| unsafe { Noun::from_raw(*raw) })
+            .collect();
+        let stack = NockStack {
+            start,
+            size,
+            frame_offset: state.frame_offset,
+            stack_offset: state.stack_offset,
+            alloc_offset: state.alloc_offset,
+            least_space: state.least_space,
+            memory: Memory::Pma(pma),
+            pc: state.pc,
+            stats: StackStats::default(),
+        };
+        Ok((stack, roots))
+    }
+
+    /// Persist the loom with `roots` to its arena, writing only the pages dirtied since the last
+    /// snapshot. Anything the roots point to must be preserved to the top frame first.
+    pub fn snapshot(&mut self, roots: &[Noun]) -> Result<PmaSnapshot, PmaError> {
+        let state = PmaState {
+            frame_offset: self.frame_offset,
+            stack_offset: self.stack_offset,
+            alloc_offset: self.alloc_offset,
+            least_space: self.least_space,
+            pc: self.pc,
+            roots: roots.iter().map(|root| unsafe { root.as_raw() }).collect(),
+        };
+        match &mut self.memory {
+            Memory::Pma(pma) => pma.snapshot(state),
+            _ => Err(PmaError::NotPersistent),
+        }
+    }
+
+    /// Like [`NockStack::snapshot`], with roots allocated by `roots` just for the snapshot. Their
+    /// allocations are freed once it's written, so the roots must not be kept.
+    pub fn snapshot_with<F>(&mut self, roots: F) -> Result<PmaSnapshot, PmaError>
+    where
+        F: FnOnce(&mut NockStack) -> Vec<Noun>,
+    {
+        let alloc_offset = self.alloc_offset;
+        let roots = roots(self);
+        let snapshot = self.snapshot(&roots);
+        self.alloc_offset = alloc_offset;
+        snapshot
+    }
+
+    /// The persistent memory arena the loom is in, if any
+    pub fn pma(&self) -> Option<&Pma> {
+        match &self.memory {
+            Memory::Pma(pma) => Some(pma),
+            _ => None,
+        }
+    }
+
+    fn with_memory(mut memory: Memory, size: usize, top_slots: usize) -> NockStack {
         let start = memory.as_mut_ptr() as *mut u64;
 
         // Here, frame_offset < alloc_offset, so the initial frame is West
@@ -337,20 +433,17 @@ impl NockStack {
             *(start.add(prev_alloc_slot)) = start as u64; // "alloc pointer" from "previous" frame
         };
 
-        assert_eq!(alloc_offset - stack_offset, free);
-        Ok((
-            NockStack {
-                start: start as *const u64,
-                size,
-                frame_offset,
-                stack_offset,
-                alloc_offset,
-                least_space,
-                memory,
-                pc: false,
-            },
-            free,
-        ))
+        NockStack {
+            start: start as *const u64,
+            size,
+            frame_offset,
+            stack_offset,
+            alloc_offset,
+            least_space,
+            memory,
+            pc: false,
+            stats: StackStats::default(),
+        }
     }
 
     fn memory_state(&self, words: Option<usize>) -> MemoryState {
@@ -739,7 +832,7 @@ impl NockStack {
     }
 
     /** Size **in 64-bit words** of this NockStack */
-    pub(crate) fn size(&self) -> usize {
+    pub fn size(&self) -> usize {
         self.size
     }
 
@@ -748,6 +841,11 @@ impl NockStack {
         self.least_space
     }
 
+    /** Allocation and copying counters for this nockstack */
+    pub fn stats(&self) -> StackStats {
+        self.stats
+    }
+
     /** Check to see if an allocation is in frame */
     #[inline]
     pub(crate) unsafe fn is_in_frame<T>(&self, ptr: *const T) -> bool {
@@ -1032,6 +1130,8 @@ impl NockStack {
             .checked_sub(self.stack_offset)
             .expect("Uncaught OOM in raw_alloc_west");
         self.least_space = new_space.min(self.least_space);
+        self.stats.allocations += 1;
+        self.stats.allocated_words += words as u64;
 
         // Derive pointer from the new offset
         let alloc_ptr = self.derive_ptr(new_alloc_offset);
@@ -1065,6 +1165,8 @@ impl NockStack {
             .checked_sub(new_alloc_offset)
             .expect("Uncaught OOM in raw_alloc_east");
         self.least_space = new_space.min(self.least_space);
+        self.stats.allocations += 1;
+        self.stats.allocated_words += words as u64;
 
         // Check that the new offset is within bounds
         if new_alloc_offset > self.size {
@@ -1199,6 +1301,7 @@ impl NockStack {
      * frame. */
     unsafe fn raw_alloc_in_previous_frame(&mut self, words: usize) -> *mut u64 {
         self.pre_copy();
+        self.stats.copied_words += words as u64;
         if self.is_west() {
             self.raw_alloc_in_previous_frame_west(words)
         } else {
@@ -1287,6 +1390,7 @@ impl NockStack {
 
         self.pre_copy();
         assert!(self.stack_is_empty());
+        self.stats.copies += 1;
         let noun_ptr = noun as *mut Noun;
         // Add two slots to the lightweight stack
         // Set the first new slot to the noun to be copied
@@ -2079,6 +2183,20 @@ impl<T: Preserve, E: Preserve> Preserve for Result<T, E> {
     }
 }
 
+impl<T: Preserve> Preserve for Option<T> {
+    unsafe fn preserve(&mut self, stack: &mut NockStack) {
+        if let Some(t_ref) = self.as_mut() {
+            t_ref.preserve(stack);
+        }
+    }
+
+    unsafe fn assert_in_stack(&self, stack: &NockStack) {
+        if let Some(t_ref) = self.as_ref() {
+            t_ref.assert_in_stack(stack);
+        }
+    }
+}
+
 impl Preserve for bool {
     unsafe fn preserve(&mut self, _: &mut NockStack) {}
 
@@ -2091,6 +2209,12 @@ impl Preserve for u32 {
     unsafe fn assert_in_stack(&self, _: &NockStack) {}
 }
 
+impl Preserve for u64 {
+    unsafe fn preserve(&mut self, _: &mut NockStack) {}
+
+    unsafe fn assert_in_stack(&self, _: &NockStack) {}
+}
+
 impl Preserve for usize {
     unsafe fn preserve(&mut self, _: &mut NockStack) {}
 
@@ -0,0 +1,165 @@
+//! The `%memo` cache, with a configurable size limit and hit, miss and eviction counters.
+//!
+//! The cache is a persistent [`Hamt`], so single entries can't be dropped from it. Once it holds
+//! [`MemoConfig::max_entries`] entries, the [`MemoEviction`] policy decides whether to flush it
+//! and start over, or to keep it and stop caching new results until the next reset.
+use crate::hamt::Hamt;
+use crate::mem::{NockStack, Preserve};
+use crate::noun::Noun;
+
+crate::gdb!();
+
+/// What to do with a new result once the cache is full
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MemoEviction {
+    /// Drop every entry, then cache the new result
+    #[default]
+    Flush,
+    /// Keep the entries already cached and don't cache the new result
+    Retain,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoConfig {
+    /// The most entries the cache may hold, or `None` for no limit
+    pub max_entries: Option<usize>,
+    pub eviction: MemoEviction,
+}
+
+/// Cumulative counters, kept across cache resets
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries dropped by a flush, plus results not cached because the cache was full
+    pub evictions: u64,
+}
+
+#[derive(Copy, Clone)]
+pub struct MemoCache {
+    table: Hamt<Noun>,
+    entries: usize,
+}
+
+impl MemoCache {
+    pub fn new(stack: &mut NockStack) -> Self {
+        MemoCache {
+            table: Hamt::new(stack),
+            entries: 0,
+        }
+    }
+
+    pub fn entries(&self) -> usize {
+        self.entries
+    }
+
+    pub fn lookup(&self, stack: &mut NockStack, key: &mut Noun) -> Option<Noun> {
+        self.table.lookup(stack, key)
+    }
+
+    /// Cache `value` under `key`, making room as `config` says and counting evictions in `stats`.
+    pub fn insert(
+        &self,
+        stack: &mut NockStack,
+        key: &mut Noun,
+        value: Noun,
+        config: &MemoConfig,
+        stats: &mut MemoStats,
+    ) -> MemoCache {
+        let mut cache = *self;
+        if config.max_entries.is_some_and(|max| cache.entries >= max) {
+            match config.eviction {
+                MemoEviction::Flush => {
+                    stats.evictions += cache.entries as u64;
+                    cache = MemoCache::new(stack);
+                }
+                MemoEviction::Retain => {
+                    stats.evictions += 1;
+                    return cache;
+                }
+            }
+            // A limit of zero caches nothing
+            if config.max_entries == Some(0) {
+                stats.evictions += 1;
+                return cache;
+            }
+        }
+        MemoCache {
+            table: cache.table.insert(stack, key, value),
+            entries: cache.entries + 1,
+        }
+    }
+}
+
+impl Preserve for MemoCache {
+    unsafe fn preserve(&mut self, stack: &mut NockStack) {
+        self.table.preserve(stack);
+    }
+
+    unsafe fn assert_in_stack(&self, stack: &NockStack) {
+        self.table.assert_in_stack(stack);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noun::D;
+
+    fn fill(
+        stack: &mut NockStack,
+        config: &MemoConfig,
+        stats: &mut MemoStats,
+        n: u64,
+    ) -> MemoCache {
+        let mut cache = MemoCache::new(stack);
+        for i in 0..n {
+            cache = cache.insert(stack, &mut D(i), D(i + 100), config, stats);
+        }
+        cache
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_memo_eviction() {
+        let stack = &mut NockStack::new(1 << 20, 0);
+
+        let unbounded = MemoConfig::default();
+        let mut stats = MemoStats::default();
+        let cache = fill(stack, &unbounded, &mut stats, 10);
+        assert_eq!(cache.entries(), 10);
+        assert_eq!(stats.evictions, 0);
+
+        let flush = MemoConfig {
+            max_entries: Some(4),
+            eviction: MemoEviction::Flush,
+        };
+        let mut stats = MemoStats::default();
+        let cache = fill(stack, &flush, &mut stats, 10);
+        // Flushed after the 4th and 8th entries
+        assert_eq!(cache.entries(), 2);
+        assert_eq!(stats.evictions, 8);
+        assert!(cache.lookup(stack, &mut D(9)).is_some());
+        assert!(cache.lookup(stack, &mut D(0)).is_none());
+
+        let retain = MemoConfig {
+            max_entries: Some(4),
+            eviction: MemoEviction::Retain,
+        };
+        let mut stats = MemoStats::default();
+        let cache = fill(stack, &retain, &mut stats, 10);
+        assert_eq!(cache.entries(), 4);
+        assert_eq!(stats.evictions, 6);
+        assert!(cache.lookup(stack, &mut D(0)).is_some());
+        assert!(cache.lookup(stack, &mut D(9)).is_none());
+
+        let disabled = MemoConfig {
+            max_entries: Some(0),
+            eviction: MemoEviction::Flush,
+        };
+        let mut stats = MemoStats::default();
+        let cache = fill(stack, &disabled, &mut stats, 3);
+        assert_eq!(cache.entries(), 0);
+        assert_eq!(stats.evictions, 3);
+    }
+}
@@ -256,6 +256,10 @@ impl DirectAtom {
         self.0
     }
 
+    pub fn as_slice(&self) -> &[u64] {
+        std::slice::from_ref(&self.0)
+    }
+
     pub fn as_bitslice(&self) -> &BitSlice<u64, Lsb0> {
         BitSlice::from_element(&self.0)
     }
@@ -1027,6 +1031,15 @@ impl Atom {
         }
     }
 
+    /// View of the atom's words, least significant first, without copying an indirect atom
+    pub fn as_slice(&self) -> &[u64] {
+        if self.is_indirect() {
+            unsafe { self.indirect.as_slice() }
+        } else {
+            unsafe { self.direct.as_slice() }
+        }
+    }
+
     pub fn as_bitslice(&self) -> &BitSlice<u64, Lsb0> {
         if self.is_indirect() {
             unsafe { self.indirect.as_bitslice() }
@@ -0,0 +1,645 @@
+//! A persistent memory arena (PMA): a loom backed by a file, snapshotted by flushing dirty pages.
+//!
+//! The arena is a private, copy-on-write mapping of the file at a fixed address. Pages the
+//! interpreter writes become private copies, so the file keeps the last snapshot until the next
+//! one, however the loom is mutated in between. [`Pma::snapshot`] finds the copied pages in
+//! `/proc/self/pagemap`, writes them and a header with the stack's offsets and roots to a journal,
+//! applies the journal to the file and maps the written pages back from it. A snapshot costs time
+//! proportional to the pages dirtied since the last one rather than to the size of the state.
+//!
+//! Nouns point into the loom by absolute address, so [`Pma::open`] maps the arena back at the
+//! address it was created at, and fails if something else is mapped there. A journal left by a
+//! crash mid-snapshot is replayed on open, and a torn one is discarded, so the file always opens
+//! to the last complete snapshot.
+//!
+//! The arena needs `mmap`, so outside Unix creating or opening one fails with
+//! [`PmaError::Unsupported`]. Without a readable pagemap, as outside Linux or where `/proc` isn't
+//! mounted, there is no telling which pages to write, so creating or opening one fails with
+//! [`PmaError::Pagemap`] rather than snapshotting the whole loom every time.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use sys::{map, page_size, read_exact_at, remap, unmap, write_all_at};
+use thiserror::Error;
+
+crate::gdb!();
+
+/// Bytes reserved for the header at the start of the file, a multiple of any page size
+const HEADER_BYTES: usize = 1 << 16;
+const MAGIC: u64 = u64::from_le_bytes(*b"NOCKPMA1");
+const JOURNAL_MAGIC: u64 = u64::from_le_bytes(*b"NOCKJRN1");
+const VERSION: u64 = 1;
+/// The most roots a snapshot can record
+pub const MAX_ROOTS: usize = 16;
+const HEADER_WORDS: usize = 12 + MAX_ROOTS;
+/// Where arenas are mapped if the address is free, far from where the kernel puts mappings
+const PMA_BASE: usize = 0x1000_0000_0000;
+
+#[derive(Debug, Error)]
+pub enum PmaError {
+    #[error("PMA I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("{0} is not a PMA or is corrupt")]
+    BadHeader(PathBuf),
+    #[error("{0} has no snapshot")]
+    NoSnapshot(PathBuf),
+    #[error("cannot map the PMA at {0:#x}, where it was created")]
+    AddressUnavailable(usize),
+    #[error("a snapshot records at most {MAX_ROOTS} roots")]
+    TooManyRoots,
+    #[error("stack too small")]
+    StackTooSmall,
+    #[error("the stack is not backed by a PMA")]
+    NotPersistent,
+    #[error("the PMA is not supported on this platform")]
+    Unsupported,
+    #[error("cannot read /proc/self/pagemap to find dirty pages, so the PMA is unavailable: {0}")]
+    Pagemap(io::Error),
+}
+
+/// The stack state a snapshot records alongside the loom
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct PmaState {
+    pub frame_offset: usize,
+    pub stack_offset: usize,
+    pub alloc_offset: usize,
+    pub least_space: usize,
+    pub pc: bool,
+    pub roots: Vec<u64>,
+}
+
+/// What a snapshot wrote
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PmaSnapshot {
+    /// The number of snapshots taken of the arena, this one included
+    pub generation: u64,
+    /// Pages written to the file
+    pub dirty_pages: usize,
+    pub bytes: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Header {
+    base: usize,
+    size: usize,
+    generation: u64,
+    state: PmaState,
+}
+
+impl Header {
+    fn encode(&self) -> Vec<u8> {
+        let mut words = [0u64; HEADER_WORDS - 1];
+        words[0] = MAGIC;
+        words[1] = VERSION;
+        words[2] = self.base as u64;
+        words[3] = self.size as u64;
+        words[4] = self.generation;
+        words[5] = self.state.frame_offset as u64;
+        words[6] = self.state.stack_offset as u64;
+        words[7] = self.state.alloc_offset as u64;
+        words[8] = self.state.least_space as u64;
+        words[9] = self.state.pc as u64;
+        words[10] = self.state.roots.len() as u64;
+        words[11..11 + self.state.roots.len()].copy_from_slice(&self.state.roots);
+        let mut bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let hash = checksum(&bytes);
+        bytes.extend_from_slice(&hash.to_le_bytes());
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Header> {
+        if bytes.len() < HEADER_WORDS * 8 {
+            return None;
+        }
+        let words: Vec<u64> = bytes[..HEADER_WORDS * 8]
+            .chunks_exact(8)
+            .map(|word| u64::from_le_bytes(word.try_into().expect("8 bytes")))
+            .collect();
+        let roots = words[10] as usize;
+        if words[0] != MAGIC
+            || words[1] != VERSION
+            || roots > MAX_ROOTS
+            || words[HEADER_WORDS - 1] != checksum(&bytes[..(HEADER_WORDS - 1) * 8])
+        {
+            return None;
+        }
+        Some(Header {
+            base: words[2] as usize,
+            size: words[3] as usize,
+            generation: words[4],
+            state: PmaState {
+                frame_offset: words[5] as usize,
+                stack_offset: words[6] as usize,
+                alloc_offset: words[7] as usize,
+                least_space: words[8] as usize,
+                pc: words[9] != 0,
+                roots: words[11..11 + roots].to_vec(),
+            },
+        })
+    }
+}
+
+/// A loom of `size` words mapped from a file
+pub struct Pma {
+    path: PathBuf,
+    file: File,
+    base: *mut u8,
+    /// Size of the loom in words
+    size: usize,
+    generation: u64,
+    page_size: usize,
+}
+
+impl Pma {
+    /// Create an arena of `size` words at `path`, replacing any arena there. It has no snapshot
+    /// until the first [`Pma::snapshot`].
+    pub(crate) fn create(path: &Path, size: usize) -> Result<Pma, PmaError> {
+        if !sys::SUPPORTED {
+            return Err(PmaError::Unsupported);
+        }
+        let _ = fs::remove_file(journal_path(path));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len((HEADER_BYTES + (size << 3)) as u64)?;
+        let base = map(&file, PMA_BASE, size << 3).or_else(|_| map(&file, 0, size << 3))?;
+        let pma = Pma {
+            path: path.to_path_buf(),
+            file,
+            base,
+            size,
+            generation: 0,
+            page_size: page_size(),
+        };
+        pma.check_pagemap()?;
+        write_all_at(&pma.file, &pma.header(PmaState::default()).encode(), 0)?;
+        pma.file.sync_all()?;
+        Ok(pma)
+    }
+
+    /// Open the arena at `path` at its last complete snapshot, returning the stack state it
+    /// recorded.
+    pub(crate) fn open(path: &Path) -> Result<(Pma, PmaState), PmaError> {
+        if !sys::SUPPORTED {
+            return Err(PmaError::Unsupported);
+        }
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        replay_journal(path, &file)?;
+        let mut bytes = vec![0u8; HEADER_WORDS * 8];
+        read_exact_at(&file, &mut bytes, 0)?;
+        let header = Header::decode(&bytes).ok_or_else(|| PmaError::BadHeader(path.into()))?;
+        if header.generation == 0 {
+            return Err(PmaError::NoSnapshot(path.into()));
+        }
+        if file.metadata()?.len() < (HEADER_BYTES + (header.size << 3)) as u64 {
+            return Err(PmaError::BadHeader(path.into()));
+        }
+        let base = map(&file, header.base, header.size << 3)
+            .map_err(|_| PmaError::AddressUnavailable(header.base))?;
+        let pma = Pma {
+            path: path.to_path_buf(),
+            file,
+            base,
+            size: header.size,
+            generation: header.generation,
+            page_size: page_size(),
+        };
+        pma.check_pagemap()?;
+        Ok((pma, header.state))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of snapshots taken
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut u8 {
+        self.base
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.size << 3
+    }
+
+    fn header(&self, state: PmaState) -> Header {
+        Header {
+            base: self.base as usize,
+            size: self.size,
+            generation: self.generation,
+            state,
+        }
+    }
+
+    /// Persist the loom and `state`, writing only the pages dirtied since the last snapshot.
+    pub(crate) fn snapshot(&mut self, state: PmaState) -> Result<PmaSnapshot, PmaError> {
+        if state.roots.len() > MAX_ROOTS {
+            return Err(PmaError::TooManyRoots);
+        }
+        let dirty = self.dirty_pages().map_err(PmaError::Pagemap)?;
+        self.generation += 1;
+        let header = self.header(state).encode();
+
+        // The journal makes the snapshot atomic: the file is only written once it is durable
+        let journal = journal_path(&self.path);
+        {
+            let mut out = BufWriter::new(File::create(&journal)?);
+            let mut hash = Checksum::new();
+            let mut put = |out: &mut BufWriter<File>, bytes: &[u8]| -> io::Result<()> {
+                hash.update(bytes);
+                out.write_all(bytes)
+            };
+            put(&mut out, &JOURNAL_MAGIC.to_le_bytes())?;
+            put(&mut out, &(self.page_size as u64).to_le_bytes())?;
+            put(&mut out, &(dirty.len() as u64).to_le_bytes())?;
+            for page in &dirty {
+                put(&mut out, &(*page as u64).to_le_bytes())?;
+                put(&mut out, self.page(*page))?;
+            }
+            put(&mut out, &header)?;
+            out.write_all(&hash.finish().to_le_bytes())?;
+            out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+
+        for page in &dirty {
+            write_all_at(
+                &self.file,
+                self.page(*page),
+                (HEADER_BYTES + page * self.page_size) as u64,
+            )?;
+        }
+        write_all_at(&self.file, &header, 0)?;
+        self.file.sync_all()?;
+        fs::remove_file(&journal)?;
+
+        // Drop the private copies of the written pages, which now match the file
+        for (start, count) in runs(&dirty) {
+            let offset = start * self.page_size;
+            let addr = unsafe { self.base.add(offset) };
+            unsafe { remap(&self.file, addr, count * self.page_size, offset)? };
+        }
+
+        Ok(PmaSnapshot {
+            generation: self.generation,
+            dirty_pages: dirty.len(),
+            bytes: dirty.len() * self.page_size,
+        })
+    }
+
+    fn pages(&self) -> usize {
+        self.len().div_ceil(self.page_size)
+    }
+
+    fn page(&self, page: usize) -> &[u8] {
+        let start = page * self.page_size;
+        let len = self.page_size.min(self.len() - start);
+        unsafe { std::slice::from_raw_parts(self.base.add(start), len) }
+    }
+
+    /// Fail now if the pagemap can't be read, rather than at the first snapshot
+    fn check_pagemap(&self) -> Result<(), PmaError> {
+        let pagemap = File::open("/proc/self/pagemap").map_err(PmaError::Pagemap)?;
+        let first = self.base as usize / self.page_size;
+        read_exact_at(&pagemap, &mut [0u8; 8], (first * 8) as u64).map_err(PmaError::Pagemap)
+    }
+
+    /// The pages that are private copies
+    fn dirty_pages(&self) -> io::Result<Vec<usize>> {
+        const PRESENT: u64 = 1 << 63;
+        const SWAPPED: u64 = 1 << 62;
+        const FILE_PAGE: u64 = 1 << 61;
+        let pagemap = File::open("/proc/self/pagemap")?;
+        let first = self.base as usize / self.page_size;
+        let mut dirty = Vec::new();
+        let mut buf = vec![0u8; 8 * 4096];
+        let mut page = 0;
+        while page < self.pages() {
+            let count = (self.pages() - page).min(4096);
+            let bytes = &mut buf[..count * 8];
+            read_exact_at(&pagemap, bytes, ((first + page) * 8) as u64)?;
+            for (i, entry) in bytes.chunks_exact(8).enumerate() {
+                let entry = u64::from_le_bytes(entry.try_into().expect("8 bytes"));
+                if (entry & PRESENT != 0 && entry & FILE_PAGE == 0) || entry & SWAPPED != 0 {
+                    dirty.push(page + i);
+                }
+            }
+            page += count;
+        }
+        Ok(dirty)
+    }
+}
+
+impl Drop for Pma {
+    fn drop(&mut self) {
+        unsafe { unmap(self.base, self.len()) };
+    }
+}
+
+fn journal_path(path: &Path) -> PathBuf {
+    let mut journal = path.as_os_str().to_owned();
+    journal.push(".journal");
+    PathBuf::from(journal)
+}
+
+/// Apply a complete journal to the file and remove it. A torn journal is removed unapplied,
+/// since the file wasn't touched before the journal was complete.
+fn replay_journal(path: &Path, file: &File) -> Result<(), PmaError> {
+    let journal = journal_path(path);
+    let mut bytes = Vec::new();
+    match File::open(&journal) {
+        Ok(mut f) => f.read_to_end(&mut bytes)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    if let Some((pages, header)) = parse_journal(&bytes) {
+        for (offset, page) in pages {
+            write_all_at(file, page, (HEADER_BYTES + offset) as u64)?;
+        }
+        write_all_at(file, header, 0)?;
+        file.sync_all()?;
+    }
+    fs::remove_file(&journal)?;
+    Ok(())
+}
+
+/// A page in a journal: its byte offset in the loom and its contents
+type JournalPage<'a> = (usize, &'a [u8]);
+
+/// The pages in a journal, and its header
+fn parse_journal(bytes: &[u8]) -> Option<(Vec<JournalPage<'_>>, &[u8])> {
+    let (body, hash) = bytes.split_at(bytes.len().checked_sub(8)?);
+    if u64::from_le_bytes(hash.try_into().ok()?) != checksum(body) {
+        return None;
+    }
+    let word = |at: usize| -> Option<usize> {
+        Some(u64::from_le_bytes(body.get(at..at + 8)?.try_into().ok()?) as usize)
+    };
+    if word(0)? as u64 != JOURNAL_MAGIC {
+        return None;
+    }
+    let page_size = word(8)?;
+    let count = word(16)?;
+    let mut at = 24;
+    let mut pages = Vec::with_capacity(count);
+    for _ in 0..count {
+        let offset = word(at)? * page_size;
+        let page = body.get(at + 8..at + 8 + page_size)?;
+        pages.push((offset, page));
+        at += 8 + page_size;
+    }
+    Some((pages, body.get(at..at + HEADER_WORDS * 8)?))
+}
+
+/// Runs of consecutive pages, as `(first page, count)`
+fn runs(pages: &[usize]) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for page in pages {
+        match runs.last_mut() {
+            Some((start, count)) if *start + *count == *page => *count += 1,
+            _ => runs.push((*page, 1)),
+        }
+    }
+    runs
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::fs::File;
+    use std::os::unix::fs::FileExt;
+    use std::os::unix::io::AsRawFd;
+    use std::{io, ptr};
+
+    use super::HEADER_BYTES;
+
+    pub(super) const SUPPORTED: bool = true;
+
+    /// Map `len` bytes of the loom in `file` privately at `addr`, or where the kernel likes if 0
+    pub(super) fn map(file: &File, addr: usize, len: usize) -> io::Result<*mut u8> {
+        #[cfg(target_os = "linux")]
+        let fixed = if addr != 0 {
+            libc::MAP_FIXED_NOREPLACE
+        } else {
+            0
+        };
+        #[cfg(not(target_os = "linux"))]
+        let fixed = 0;
+        let base = unsafe {
+            libc::mmap(
+                if addr == 0 {
+                    ptr::null_mut()
+                } else {
+                    addr as *mut libc::c_void
+                },
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | fixed,
+                file.as_raw_fd(),
+                HEADER_BYTES as libc::off_t,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        if addr != 0 && base as usize != addr {
+            unsafe { libc::munmap(base, len) };
+            return Err(io::Error::from(io::ErrorKind::AddrInUse));
+        }
+        Ok(base as *mut u8)
+    }
+
+    /// Map the `len` bytes of the loom at `offset` in `file` over `addr`, dropping the private
+    /// copies of its pages.
+    ///
+    /// # Safety
+    /// `addr` must be `offset` bytes into the arena mapped from `file`, and the range in bounds.
+    pub(super) unsafe fn remap(
+        file: &File,
+        addr: *mut u8,
+        len: usize,
+        offset: usize,
+    ) -> io::Result<()> {
+        let mapped = libc::mmap(
+            addr as *mut libc::c_void,
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_FIXED,
+            file.as_raw_fd(),
+            (HEADER_BYTES + offset) as libc::off_t,
+        );
+        if mapped == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// # Safety
+    /// `base` and `len` must be a mapping made by [`map`], which nothing uses afterwards.
+    pub(super) unsafe fn unmap(base: *mut u8, len: usize) {
+        libc::munmap(base as *mut libc::c_void, len);
+    }
+
+    pub(super) fn page_size() -> usize {
+        unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+    }
+
+    pub(super) fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        file.read_exact_at(buf, offset)
+    }
+
+    pub(super) fn write_all_at(file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+        file.write_all_at(buf, offset)
+    }
+}
+
+/// Without `mmap` no arena can be created or opened, so only the positional I/O is real.
+#[cfg(not(unix))]
+mod sys {
+    use std::fs::File;
+    use std::io::{self, Read, Seek, SeekFrom, Write};
+
+    pub(super) const SUPPORTED: bool = false;
+
+    pub(super) fn map(_file: &File, _addr: usize, _len: usize) -> io::Result<*mut u8> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    pub(super) unsafe fn remap(
+        _file: &File,
+        _addr: *mut u8,
+        _len: usize,
+        _offset: usize,
+    ) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    pub(super) unsafe fn unmap(_base: *mut u8, _len: usize) {}
+
+    pub(super) fn page_size() -> usize {
+        4096
+    }
+
+    pub(super) fn read_exact_at(mut file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(buf)
+    }
+
+    pub(super) fn write_all_at(mut file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(buf)
+    }
+}
+
+/// FNV-1a, to tell a torn header or journal from a complete one
+struct Checksum(u64);
+
+impl Checksum {
+    fn new() -> Self {
+        Checksum(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash = Checksum::new();
+    hash.update(bytes);
+    hash.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::NockStack;
+    use crate::noun::{D, T};
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    #[cfg_attr(not(target_os = "linux"), ignore = "the PMA needs /proc/self/pagemap")]
+    fn test_pma_snapshot_and_reopen() {
+        let dir = std::env::temp_dir().join(format!("nockvm-pma-{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("temp dir");
+        let path = dir.join("loom");
+
+        let mut stack = NockStack::new_pma(&path, 1 << 16, 0).expect("create");
+        assert!(matches!(
+            NockStack::open_pma(&path),
+            Err(PmaError::NoSnapshot(_))
+        ));
+        let root = T(&mut stack, &[D(1), D(2), D(3)]);
+        let first = stack.snapshot(&[root, D(7)]).expect("snapshot");
+        assert_eq!(first.generation, 1);
+        assert!(first.dirty_pages > 0);
+
+        // Only pages written since are flushed, and mutations after the last snapshot are lost
+        // on reopening
+        let second = stack.snapshot(&[root, D(7)]).expect("snapshot");
+        assert_eq!(second.generation, 2);
+        assert!(second.dirty_pages <= first.dirty_pages);
+        let _lost = T(&mut stack, &[D(4), D(5)]);
+        drop(stack);
+
+        let (mut stack, roots) = NockStack::open_pma(&path).expect("open");
+        assert_eq!(roots.len(), 2);
+        assert_eq!(stack.pma().expect("pma").generation(), 2);
+        let mut expected = T(&mut stack, &[D(1), D(2), D(3)]);
+        let mut found = roots[0];
+        assert!(unsafe {
+            crate::unifying_equality::unifying_equality(&mut stack, &mut found, &mut expected)
+        });
+        assert!(unsafe { roots[1].raw_equals(&D(7)) });
+
+        // A torn journal is discarded
+        fs::write(journal_path(&path), b"torn").expect("journal");
+        drop(stack);
+        let (_stack, roots) = NockStack::open_pma(&path).expect("open");
+        assert_eq!(roots.len(), 2);
+        assert!(!journal_path(&path).exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    #[cfg_attr(not(target_os = "linux"), ignore = "the PMA needs /proc/self/pagemap")]
+    fn test_pma_snapshot_with_frees_roots() {
+        let dir = std::env::temp_dir().join(format!("nockvm-pma-with-{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("temp dir");
+        let path = dir.join("loom");
+
+        let mut stack = NockStack::new_pma(&path, 1 << 16, 0).expect("create");
+        let mut root_raw = 0;
+        stack
+            .snapshot_with(|stack| {
+                let root = T(stack, &[D(1), D(2)]);
+                root_raw = unsafe { root.as_raw() };
+                vec![root]
+            })
+            .expect("snapshot");
+        // The root's cell is free again
+        let reused = T(&mut stack, &[D(3), D(4)]);
+        assert_eq!(unsafe { reused.as_raw() }, root_raw);
+        drop(stack);
+
+        let (mut stack, roots) = NockStack::open_pma(&path).expect("open");
+        let mut expected = T(&mut stack, &[D(1), D(2)]);
+        let mut found = roots[0];
+        assert!(unsafe {
+            crate::unifying_equality::unifying_equality(&mut stack, &mut found, &mut expected)
+        });
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
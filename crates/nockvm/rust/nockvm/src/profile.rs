@@ -0,0 +1,260 @@
+//! Profiling of Nock computations, exported as folded stacks for flamegraphs.
+//!
+//! While a [`Profiler`] is set on the context, the interpreter keeps a call stack of the arms it
+//! is running, labelled with their cold state paths, and charges the time between calls to the
+//! arm on top. Only arms registered with a `%fast` hint have a path. A call to any other core
+//! counts as part of its caller, and a tail call to one keeps the caller's label. Jets get frames
+//! of their own, named after their path with a ` (jet)` suffix.
+//!
+//! Every labelled call is timed rather than sampled, so time spent inside a jet, where the
+//! interpreter never gets a chance to look at its stack, is attributed exactly.
+//!
+//! [`Profiler::write_folded`] writes one `caller;callee weight` line per call stack, with weights
+//! in microseconds, the input format of `flamegraph.pl` and `inferno-flamegraph`.
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::io::{Result, Write};
+use std::time::Instant;
+
+use either::Either::*;
+
+use crate::mem::NockStack;
+use crate::mug::met3_usize;
+use crate::noun::{Atom, Noun};
+use crate::trace::path_to_cord;
+
+crate::gdb!();
+
+const ROOT: usize = 0;
+
+struct Node {
+    parent: usize,
+    label: Option<u32>,
+    children: HashMap<u32, usize>,
+    nanos: u64,
+}
+
+pub struct Profiler {
+    /// Rendered paths, indexed by label
+    labels: Vec<String>,
+    /// Label of each path, by a hash of the path and whether it's a jet
+    label_ids: HashMap<u64, u32>,
+    /// The call tree, rooted at `nodes[ROOT]`
+    nodes: Vec<Node>,
+    /// The call tree node of each interpreter frame
+    frames: Vec<usize>,
+    last: Instant,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            labels: Vec::new(),
+            label_ids: HashMap::new(),
+            nodes: vec![Node {
+                parent: ROOT,
+                label: None,
+                children: HashMap::new(),
+                nanos: 0,
+            }],
+            frames: Vec::new(),
+            last: Instant::now(),
+        }
+    }
+
+    fn current(&self) -> usize {
+        self.frames.last().copied().unwrap_or(ROOT)
+    }
+
+    /// Charge the time since the last call or return to the running arm
+    fn charge(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(self.last).as_nanos() as u64;
+        let current = self.current();
+        self.nodes[current].nanos += elapsed;
+        self.last = now;
+    }
+
+    fn label(&mut self, stack: &mut NockStack, path: Noun, jet: bool) -> u32 {
+        let key = path_hash(path, jet);
+        if let Some(label) = self.label_ids.get(&key) {
+            return *label;
+        }
+        let cord = path_to_cord(stack, path);
+        let bytes = &cord.as_ne_bytes()[0..met3_usize(cord)];
+        let mut name = String::from_utf8_lossy(bytes).into_owned();
+        if jet {
+            name.push_str(" (jet)");
+        }
+        let label = self.labels.len() as u32;
+        self.labels.push(name);
+        self.label_ids.insert(key, label);
+        label
+    }
+
+    fn child(&mut self, parent: usize, label: u32) -> usize {
+        if let Some(child) = self.nodes[parent].children.get(&label) {
+            return *child;
+        }
+        let child = self.nodes.len();
+        self.nodes.push(Node {
+            parent,
+            label: Some(label),
+            children: HashMap::new(),
+            nanos: 0,
+        });
+        self.nodes[parent].children.insert(label, child);
+        child
+    }
+
+    /// Enter a new frame, running the arm at cold state `path`, or more of the caller if `None`.
+    pub(crate) fn enter(&mut self, stack: &mut NockStack, path: Option<Noun>, jet: bool) {
+        self.charge();
+        let current = self.current();
+        let node = match path {
+            Some(path) => {
+                let label = self.label(stack, path, jet);
+                self.child(current, label)
+            }
+            None => current,
+        };
+        self.frames.push(node);
+    }
+
+    /// Return from the innermost frame
+    pub(crate) fn leave(&mut self) {
+        self.charge();
+        self.frames.pop();
+    }
+
+    /// Tail call the arm at `path` from the innermost frame.
+    pub(crate) fn replace(&mut self, stack: &mut NockStack, path: Noun) {
+        self.charge();
+        let caller = match self.frames.len() {
+            0 | 1 => ROOT,
+            len => self.frames[len - 2],
+        };
+        let label = self.label(stack, path, false);
+        let node = self.child(caller, label);
+        match self.frames.last_mut() {
+            Some(top) => *top = node,
+            None => self.frames.push(node),
+        }
+    }
+
+    pub(crate) fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Drop the frames above `depth`, left behind by a computation that crashed.
+    pub(crate) fn unwind(&mut self, depth: usize) {
+        if self.frames.len() > depth {
+            self.charge();
+            self.frames.truncate(depth);
+        }
+    }
+
+    /// Write the profile as folded stacks, one line per call stack that ran for at least a
+    /// microsecond.
+    pub fn write_folded<W: Write>(&self, out: &mut W) -> Result<()> {
+        for (index, node) in self.nodes.iter().enumerate() {
+            let micros = node.nanos / 1000;
+            if index == ROOT || micros == 0 {
+                continue;
+            }
+            let mut names = Vec::new();
+            let mut cursor = index;
+            while cursor != ROOT {
+                if let Some(label) = self.nodes[cursor].label {
+                    names.push(self.labels[label as usize].as_str());
+                }
+                cursor = self.nodes[cursor].parent;
+            }
+            names.reverse();
+            writeln!(out, "{} {}", names.join(";"), micros)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hash a cold state path, a list of atoms and `[name version]` cells.
+pub(crate) fn path_hash(path: Noun, jet: bool) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write_u8(jet as u8);
+    let mut cursor = path;
+    while let Ok(cell) = cursor.as_cell() {
+        match cell.head().as_either_atom_cell() {
+            Left(atom) => hash_atom(&mut hasher, atom),
+            Right(version) => {
+                hasher.write_u8(b'.');
+                if let (Ok(name), Ok(number)) = (version.head().as_atom(), version.tail().as_atom())
+                {
+                    hash_atom(&mut hasher, name);
+                    hash_atom(&mut hasher, number);
+                }
+            }
+        }
+        cursor = cell.tail();
+    }
+    hasher.finish()
+}
+
+fn hash_atom(hasher: &mut DefaultHasher, atom: Atom) {
+    let bytes = &atom.as_ne_bytes()[0..met3_usize(atom)];
+    hasher.write_usize(bytes.len());
+    hasher.write(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm_macros::tas;
+
+    use super::*;
+    use crate::noun::{D, T};
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_folded_stacks() {
+        let mut stack = NockStack::new(1 << 20, 0);
+        let outer = T(&mut stack, &[D(tas!(b"outer")), D(0)]);
+        let inner = T(&mut stack, &[D(tas!(b"inner")), D(0)]);
+        let other = T(&mut stack, &[D(tas!(b"other")), D(0)]);
+
+        let mut profiler = Profiler::new();
+        let spin = |profiler: &mut Profiler| {
+            profiler.last -= std::time::Duration::from_millis(1);
+        };
+        profiler.enter(&mut stack, Some(outer), false);
+        spin(&mut profiler);
+        // An unlabelled call is charged to its caller
+        profiler.enter(&mut stack, None, false);
+        spin(&mut profiler);
+        profiler.enter(&mut stack, Some(inner), true);
+        spin(&mut profiler);
+        profiler.leave();
+        profiler.replace(&mut stack, other);
+        spin(&mut profiler);
+        profiler.unwind(0);
+        assert_eq!(profiler.depth(), 0);
+
+        let mut out = Vec::new();
+        profiler.write_folded(&mut out).expect("write");
+        let mut lines: Vec<String> = String::from_utf8(out)
+            .expect("utf8")
+            .lines()
+            .map(|line| line.split(' ').next().expect("stack").to_string())
+            .collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec!["/outer", "/outer;/inner (jet)", "/outer;/other"]
+        );
+    }
+}
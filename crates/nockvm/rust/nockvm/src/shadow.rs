@@ -0,0 +1,175 @@
+//! Shadow checking jets against the Nock they replace.
+//!
+//! While a [`ShadowCheck`] is set on the context, one in every [`ShadowCheck::new`] `every` jet
+//! calls that succeed is also computed by interpreting the jetted arm, and the two results are
+//! compared. A mismatch is passed to the [`ShadowReport`] with the jet's path and the offending
+//! subject, and the computation carries on with the result of the Nock, which is correct by
+//! definition.
+//!
+//! Checks run with shadow checking suspended, so jets called by the Nock of a checked arm aren't
+//! checked in turn. Metered computations aren't checked, since the check would burn their fuel.
+use crate::interpreter::{interpret, Context, Error};
+use crate::mem::NockStack;
+use crate::noun::Noun;
+use crate::unifying_equality::unifying_equality;
+
+crate::gdb!();
+
+/// A jet that disagreed with its Nock
+pub struct ShadowMismatch {
+    /// The cold state path of the jetted arm
+    pub path: Noun,
+    /// The core the arm was called on
+    pub subject: Noun,
+    /// What the jet computed
+    pub jet: Noun,
+    /// What the Nock computed, or how it crashed
+    pub nock: Result<Noun, Error>,
+}
+
+/// Called with every mismatch found. The nouns are only valid for the duration of the call.
+pub type ShadowReport = Box<dyn FnMut(&mut NockStack, &ShadowMismatch)>;
+
+pub struct ShadowCheck {
+    every: u64,
+    calls: u64,
+    checked: u64,
+    mismatches: u64,
+    report: ShadowReport,
+}
+
+impl ShadowCheck {
+    /// Check one in every `every` jet calls, or every call if `every` is 0 or 1.
+    pub fn new(every: u64, report: ShadowReport) -> Self {
+        ShadowCheck {
+            every: every.max(1),
+            calls: 0,
+            checked: 0,
+            mismatches: 0,
+            report,
+        }
+    }
+
+    /// How many jet calls have been checked
+    pub fn checked(&self) -> u64 {
+        self.checked
+    }
+
+    /// How many checked jet calls disagreed with their Nock
+    pub fn mismatches(&self) -> u64 {
+        self.mismatches
+    }
+
+    fn sample(&mut self) -> bool {
+        self.calls += 1;
+        self.calls % self.every == 0
+    }
+}
+
+/// Check `jet_res`, the result of the jet at `path` on `subject`, against interpreting `formula`
+/// if this call is sampled, returning the result to carry on with.
+pub(crate) fn shadow_check(
+    context: &mut Context,
+    path: Noun,
+    subject: Noun,
+    formula: Noun,
+    mut jet_res: Noun,
+) -> Result<Noun, Error> {
+    if context.fuel.is_some() {
+        return Ok(jet_res);
+    }
+    let Some(check) = context.shadow.as_mut() else {
+        return Ok(jet_res);
+    };
+    if !check.sample() {
+        return Ok(jet_res);
+    }
+    let mut check = context.shadow.take().expect("shadow check");
+    let nock = interpret(context, subject, formula);
+    check.checked += 1;
+    let res = match nock {
+        Ok(mut nock_res) => {
+            if unsafe { unifying_equality(&mut context.stack, &mut nock_res, &mut jet_res) } {
+                Ok(jet_res)
+            } else {
+                check.mismatches += 1;
+                let mismatch = ShadowMismatch {
+                    path,
+                    subject,
+                    jet: jet_res,
+                    nock: Ok(nock_res),
+                };
+                (check.report)(&mut context.stack, &mismatch);
+                Ok(nock_res)
+            }
+        }
+        Err(err @ Error::Deterministic(..)) => {
+            check.mismatches += 1;
+            let mismatch = ShadowMismatch {
+                path,
+                subject,
+                jet: jet_res,
+                nock: Err(err),
+            };
+            (check.report)(&mut context.stack, &mismatch);
+            Err(err)
+        }
+        // Interrupted, out of memory or blocked on a scry: not the jet's fault
+        Err(err) => Err(err),
+    };
+    context.shadow = Some(check);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use nockvm_macros::tas;
+
+    use super::*;
+    use crate::jets::util::test::init_context;
+    use crate::noun::{D, T};
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_shadow_check() {
+        let c = &mut init_context();
+        let found = Rc::new(RefCell::new(Vec::new()));
+        let report_found = found.clone();
+        c.shadow = Some(ShadowCheck::new(
+            2,
+            Box::new(move |_stack, mismatch| {
+                let jet = mismatch.jet.as_atom().expect("atom").as_u64().expect("u64");
+                report_found.borrow_mut().push(jet);
+            }),
+        ));
+
+        let path = T(&mut c.stack, &[D(tas!(b"inc")), D(0)]);
+        // [4 0 1] increments the subject
+        let formula = T(&mut c.stack, &[D(4), D(0), D(1)]);
+
+        // Every other call is checked
+        let res = shadow_check(c, path, D(41), formula, D(7)).expect("unchecked");
+        assert!(unsafe { res.raw_equals(&D(7)) });
+        let res = shadow_check(c, path, D(41), formula, D(42)).expect("agrees");
+        assert!(unsafe { res.raw_equals(&D(42)) });
+        assert!(found.borrow().is_empty());
+
+        // A wrong jet is reported, and the Nock wins
+        shadow_check(c, path, D(41), formula, D(7)).expect("unchecked");
+        let res = shadow_check(c, path, D(41), formula, D(7)).expect("disagrees");
+        assert!(unsafe { res.raw_equals(&D(42)) });
+        assert_eq!(*found.borrow(), vec![7]);
+
+        // So is a jet that succeeds where the Nock crashes
+        let cell = T(&mut c.stack, &[D(1), D(2)]);
+        shadow_check(c, path, cell, formula, D(7)).expect("unchecked");
+        assert!(shadow_check(c, path, cell, formula, D(7)).is_err());
+
+        let check = c.shadow.as_ref().expect("shadow check");
+        assert_eq!(check.checked(), 3);
+        assert_eq!(check.mismatches(), 2);
+    }
+}
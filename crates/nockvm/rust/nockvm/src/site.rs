@@ -2,10 +2,12 @@
 use bitvec::order::Lsb0;
 use bitvec::slice::BitSlice;
 
-use crate::interpreter::{interpret, Context, Mote};
+use crate::fuel::burn_jet;
+use crate::interpreter::{count_jet, interpret, Context, Mote};
 use crate::jets::util::slot;
 use crate::jets::{Jet, JetErr};
 use crate::noun::{Noun, D, T};
+use crate::shadow::shadow_check;
 use crate::unifying_equality::unifying_equality;
 
 /// Return Err if the computation crashed or should punt to Nock
@@ -81,8 +83,13 @@ impl Site {
 pub(crate) fn site_slam(ctx: &mut Context, site: &Site, sample: Noun) -> Result {
     let subject = T(&mut ctx.stack, &[site.battery, sample, site.context]);
     // TODO run test if necessary
-    if let Some((jet, test)) = site.jet {
+    let jet = match site.jet {
+        Some(jet) if burn_jet(ctx, site.path)? => Some(jet),
+        _ => None,
+    };
+    if let Some((jet, test)) = jet {
         let jet_res = jet(ctx, subject);
+        let jet_res = count_jet(ctx, site.path, jet_res);
         match jet_res {
             Ok(mut jet_res) => {
                 if test {
@@ -93,8 +100,12 @@ pub(crate) fn site_slam(ctx: &mut Context, site: &Site, sample: Noun) -> Result
                             D(0),
                         )));
                     }
+                    Ok(jet_res)
+                } else {
+                    Ok(shadow_check(
+                        ctx, site.path, subject, site.battery, jet_res,
+                    )?)
                 }
-                Ok(jet_res)
             }
             Err(err) => Err(err),
         }
@@ -0,0 +1,137 @@
+//! Runtime statistics, for spotting performance regressions without timing anything.
+//!
+//! While [`RuntimeStats`] are set on the context, the interpreter counts every Nock step it takes
+//! and every jet call that returns a result, by the jet's cold state path. Hot formulas are
+//! walked rather than run from their bytecode, so every step is counted. The stack keeps its own
+//! allocation and copying counters all the time, see [`StackStats`].
+//!
+//! [`report`] combines both into a [`StatsReport`] the runtime can poll and export. The counts
+//! are deterministic for a given computation, unlike its wall clock time.
+use std::collections::HashMap;
+
+use crate::interpreter::Context;
+use crate::mem::{NockStack, StackStats};
+use crate::mug::met3_usize;
+use crate::noun::Noun;
+use crate::profile::path_hash;
+use crate::trace::path_to_cord;
+
+crate::gdb!();
+
+#[derive(Default)]
+pub struct RuntimeStats {
+    nock_ops: u64,
+    /// Rendered jet paths and how often each was hit
+    jets: Vec<(String, u64)>,
+    /// Index into `jets` of each path, by a hash of the path
+    jet_ids: HashMap<u64, usize>,
+}
+
+impl RuntimeStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline(always)]
+    pub(crate) fn count_op(&mut self) {
+        self.nock_ops += 1;
+    }
+
+    /// Count a hit of the jet at cold state `path`
+    pub(crate) fn count_jet(&mut self, stack: &mut NockStack, path: Noun) {
+        let key = path_hash(path, true);
+        let id = match self.jet_ids.get(&key) {
+            Some(id) => *id,
+            None => {
+                let cord = path_to_cord(stack, path);
+                let bytes = &cord.as_ne_bytes()[0..met3_usize(cord)];
+                self.jets
+                    .push((String::from_utf8_lossy(bytes).into_owned(), 0));
+                self.jet_ids.insert(key, self.jets.len() - 1);
+                self.jets.len() - 1
+            }
+        };
+        self.jets[id].1 += 1;
+    }
+
+    /// Nock steps taken
+    pub fn nock_ops(&self) -> u64 {
+        self.nock_ops
+    }
+
+    /// Jet calls that returned a result, summed over every jet
+    pub fn jet_calls(&self) -> u64 {
+        self.jets.iter().map(|(_, hits)| hits).sum()
+    }
+}
+
+/// Everything counted so far, polled from a [`Context`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StatsReport {
+    pub nock_ops: u64,
+    pub jet_calls: u64,
+    /// Hits of each jet by path, most hit first
+    pub jet_hits: Vec<(String, u64)>,
+    pub stack: StackStats,
+    /// The most words of the loom in use at once since the last reset of the stack's low-water
+    /// mark
+    pub max_stack_words: u64,
+}
+
+/// Report the statistics counted on `context`, or `None` if it isn't counting
+pub fn report(context: &Context) -> Option<StatsReport> {
+    let stats = context.stats.as_ref()?;
+    let mut jet_hits = stats.jets.clone();
+    jet_hits.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Some(StatsReport {
+        nock_ops: stats.nock_ops,
+        jet_calls: stats.jet_calls(),
+        jet_hits,
+        stack: context.stack.stats(),
+        max_stack_words: (context.stack.size() - context.stack.least_space()) as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm_macros::tas;
+
+    use super::*;
+    use crate::interpreter::interpret;
+    use crate::jets::util::test::init_context;
+    use crate::noun::{D, T};
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_runtime_stats() {
+        let c = &mut init_context();
+        assert!(report(c).is_none());
+        c.stats = Some(RuntimeStats::new());
+
+        // [8 [1 5] 4 0 2]: push 5, then increment it
+        let push = T(&mut c.stack, &[D(1), D(5)]);
+        let formula = T(&mut c.stack, &[D(8), push, D(4), D(0), D(2)]);
+        let res = interpret(c, D(0), formula).expect("interpret");
+        assert!(unsafe { res.raw_equals(&D(6)) });
+        let first = report(c).expect("report");
+        assert!(first.nock_ops > 0);
+        assert!(first.stack.allocations > 0);
+        assert!(first.max_stack_words > 0);
+
+        // The same computation takes the same steps
+        interpret(c, D(0), formula).expect("interpret");
+        let second = report(c).expect("report");
+        assert_eq!(second.nock_ops, 2 * first.nock_ops);
+
+        let path = T(&mut c.stack, &[D(tas!(b"add")), D(0)]);
+        let other = T(&mut c.stack, &[D(tas!(b"mul")), D(0)]);
+        let stats = c.stats.as_mut().expect("stats");
+        stats.count_jet(&mut c.stack, path);
+        stats.count_jet(&mut c.stack, other);
+        stats.count_jet(&mut c.stack, path);
+        let third = report(c).expect("report");
+        assert_eq!(third.jet_calls, 3);
+        assert_eq!(third.jet_hits[0].1, 2);
+        assert_eq!(third.jet_hits[1].1, 1);
+    }
+}
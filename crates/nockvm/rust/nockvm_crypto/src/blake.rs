@@ -0,0 +1,33 @@
+use crate::update_zeros;
+
+/// Hashes a message followed by `zeros` zero bytes using BLAKE3, keyed if `key` is given, filling
+/// `out` from the output stream.
+pub fn ac_blake3(message: &[u8], zeros: usize, key: Option<&[u8; 32]>, out: &mut [u8]) {
+    let mut hasher = match key {
+        Some(key) => blake3::Hasher::new_keyed(key),
+        None => blake3::Hasher::new(),
+    };
+    hasher.update(message);
+    update_zeros(zeros, |block| {
+        hasher.update(block);
+    });
+    hasher.finalize_xof().fill(out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ac_blake3;
+
+    #[test]
+    fn test_zeros() {
+        // Across the zero blocks and a partial one
+        let mut padded = vec![0u8; 10000];
+        padded[0..3].copy_from_slice(b"abc");
+        let mut want = [0u8; 64];
+        ac_blake3(&padded, 0, None, &mut want);
+
+        let mut out = [0u8; 64];
+        ac_blake3(b"abc", padded.len() - 3, None, &mut out);
+        assert_eq!(out, want);
+    }
+}
@@ -0,0 +1,33 @@
+use sha3::{Digest, Keccak256};
+
+use crate::update_zeros;
+
+/// Hashes a message followed by `zeros` zero bytes using Keccak-256, the pre-standard SHA-3
+/// padding used by Ethereum.
+pub fn ac_keccak_256(message: &[u8], zeros: usize, out: &mut [u8]) {
+    let mut hasher = Keccak256::new();
+    hasher.update(message);
+    update_zeros(zeros, |block| {
+        hasher.update(block);
+    });
+    let result = hasher.finalize();
+    out.copy_from_slice(&result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ac_keccak_256;
+
+    #[test]
+    fn test_zeros() {
+        // Across the zero blocks and a partial one
+        let mut padded = vec![0u8; 10000];
+        padded[0..3].copy_from_slice(b"abc");
+        let mut want = [0u8; 32];
+        ac_keccak_256(&padded, 0, &mut want);
+
+        let mut out = [0u8; 32];
+        ac_keccak_256(b"abc", padded.len() - 3, &mut out);
+        assert_eq!(out, want);
+    }
+}
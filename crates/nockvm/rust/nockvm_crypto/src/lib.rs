@@ -1,8 +1,29 @@
 #[cfg(feature = "aes_siv")]
 pub mod aes_siv;
 
+#[cfg(feature = "blake")]
+pub mod blake;
+
 #[cfg(feature = "ed25519")]
 pub mod ed25519;
 
+#[cfg(feature = "keccak")]
+pub mod keccak;
+
+#[cfg(feature = "secp")]
+pub mod secp;
+
 #[cfg(feature = "sha")]
 pub mod sha;
+
+/// Feed `len` zero bytes to `update` a block at a time, so padding needs no buffer of its own
+#[cfg(any(feature = "blake", feature = "keccak"))]
+fn update_zeros(len: usize, mut update: impl FnMut(&[u8])) {
+    const ZEROS: [u8; 4096] = [0; 4096];
+    let mut left = len;
+    while left > 0 {
+        let n = left.min(ZEROS.len());
+        update(&ZEROS[0..n]);
+        left -= n;
+    }
+}
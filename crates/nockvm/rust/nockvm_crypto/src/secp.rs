@@ -0,0 +1,193 @@
+//! secp256k1 ECDSA and BIP-340 Schnorr signatures.
+//!
+//! Scalars, hashes, coordinates and signatures are passed as little-endian byte arrays, the way
+//! they are laid out in atoms, and reversed into the big-endian encoding libsecp256k1 expects.
+
+use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use secp256k1::{schnorr, Keypair, Message, PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+
+fn reversed<const N: usize>(bytes: &[u8; N]) -> [u8; N] {
+    let mut out = *bytes;
+    out.reverse();
+    out
+}
+
+/// Split an uncompressed public key into little-endian coordinates.
+fn point(public_key: &PublicKey, x: &mut [u8; 32], y: &mut [u8; 32]) {
+    let serialized = public_key.serialize_uncompressed();
+    x.copy_from_slice(&serialized[1..33]);
+    y.copy_from_slice(&serialized[33..65]);
+    x.reverse();
+    y.reverse();
+}
+
+/// Compute the public key point of a private key, returning false if the key isn't in the
+/// group order.
+pub fn ac_secp_priv_to_pub(private_key: &[u8; 32], x: &mut [u8; 32], y: &mut [u8; 32]) -> bool {
+    let secp = Secp256k1::signing_only();
+    let Ok(secret_key) = SecretKey::from_slice(&reversed(private_key)) else {
+        return false;
+    };
+    point(&PublicKey::from_secret_key(&secp, &secret_key), x, y);
+    true
+}
+
+/// Sign a hash with an RFC 6979 nonce, producing the recovery id and a low-s signature `r`, `s`.
+pub fn ac_secp_sign(
+    hash: &[u8; 32],
+    private_key: &[u8; 32],
+    r: &mut [u8; 32],
+    s: &mut [u8; 32],
+) -> Option<u8> {
+    let secp = Secp256k1::signing_only();
+    let secret_key = SecretKey::from_slice(&reversed(private_key)).ok()?;
+    let message = Message::from_digest(reversed(hash));
+    let (recovery_id, compact) = secp
+        .sign_ecdsa_recoverable(&message, &secret_key)
+        .serialize_compact();
+    r.copy_from_slice(&compact[0..32]);
+    s.copy_from_slice(&compact[32..64]);
+    r.reverse();
+    s.reverse();
+    Some(recovery_id.to_i32() as u8)
+}
+
+/// Recover the public key point that signed a hash, returning false if there isn't one.
+pub fn ac_secp_reco(
+    hash: &[u8; 32],
+    v: u8,
+    r: &[u8; 32],
+    s: &[u8; 32],
+    x: &mut [u8; 32],
+    y: &mut [u8; 32],
+) -> bool {
+    let secp = Secp256k1::verification_only();
+    let Ok(recovery_id) = RecoveryId::from_i32(v as i32) else {
+        return false;
+    };
+    let mut compact = [0u8; 64];
+    compact[0..32].copy_from_slice(&reversed(r));
+    compact[32..64].copy_from_slice(&reversed(s));
+    let Ok(signature) = RecoverableSignature::from_compact(&compact, recovery_id) else {
+        return false;
+    };
+    let message = Message::from_digest(reversed(hash));
+    match secp.recover_ecdsa(&message, &signature) {
+        Ok(public_key) => {
+            point(&public_key, x, y);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Sign a message with BIP-340 Schnorr and the given auxiliary randomness, writing the 64-byte
+/// signature to `out`. Returns false if the private key isn't in the group order.
+pub fn ac_secp_schnorr_sign(
+    private_key: &[u8; 32],
+    message: &[u8; 32],
+    aux: &[u8; 32],
+    out: &mut [u8; 64],
+) -> bool {
+    let secp = Secp256k1::signing_only();
+    let Ok(keypair) = Keypair::from_seckey_slice(&secp, &reversed(private_key)) else {
+        return false;
+    };
+    let message = Message::from_digest(reversed(message));
+    let signature = secp.sign_schnorr_with_aux_rand(&message, &keypair, &reversed(aux));
+    *out = reversed(&signature.serialize());
+    true
+}
+
+/// Verify a BIP-340 Schnorr signature of a message by an x-only public key.
+pub fn ac_secp_schnorr_veri(
+    public_key: &[u8; 32],
+    message: &[u8; 32],
+    signature: &[u8; 64],
+) -> bool {
+    let secp = Secp256k1::verification_only();
+    let Ok(public_key) = XOnlyPublicKey::from_slice(&reversed(public_key)) else {
+        return false;
+    };
+    let Ok(signature) = schnorr::Signature::from_slice(&reversed(signature)) else {
+        return false;
+    };
+    let message = Message::from_digest(reversed(message));
+    secp.verify_schnorr(&signature, &message, &public_key)
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use ibig::ubig;
+
+    use super::*;
+
+    fn le<const N: usize>(n: ibig::UBig) -> [u8; N] {
+        let mut out = [0u8; N];
+        let bytes = n.to_le_bytes();
+        out[0..bytes.len()].copy_from_slice(&bytes);
+        out
+    }
+
+    #[test]
+    fn test_secp_priv_to_pub() {
+        let (mut x, mut y) = ([0u8; 32], [0u8; 32]);
+        assert!(ac_secp_priv_to_pub(&le(ubig!(1)), &mut x, &mut y));
+        assert_eq!(
+            x,
+            le(ubig!(
+                _0x79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798
+            ))
+        );
+        assert_eq!(
+            y,
+            le(ubig!(
+                _0x483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8
+            ))
+        );
+        assert!(!ac_secp_priv_to_pub(&[0u8; 32], &mut x, &mut y));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_secp_sign_reco() {
+        let hash = le(ubig!(
+            _0xa1d6eb6ef33f233ae6980ca7c4fc65f90fe1bdee11c730d41607b4747c83de72
+        ));
+        let private_key = le(ubig!(_0xdeadbeef));
+        let (mut r, mut s) = ([0u8; 32], [0u8; 32]);
+        let v = ac_secp_sign(&hash, &private_key, &mut r, &mut s).expect("sign");
+
+        let (mut x, mut y) = ([0u8; 32], [0u8; 32]);
+        assert!(ac_secp_reco(&hash, v, &r, &s, &mut x, &mut y));
+        let (mut px, mut py) = ([0u8; 32], [0u8; 32]);
+        assert!(ac_secp_priv_to_pub(&private_key, &mut px, &mut py));
+        assert_eq!((x, y), (px, py));
+
+        assert!(!ac_secp_reco(&hash, v, &[0u8; 32], &s, &mut x, &mut y));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_secp_schnorr() {
+        // BIP-340 test vector 0
+        let private_key = le(ubig!(3));
+        let public_key = le(ubig!(
+            _0xf9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9
+        ));
+        let signature: [u8; 64] = le(ubig!(_0xe907831f80848d1069a5371b402410364bdf1c5f8307b0084c55f1ce2dca821525f66a4a85ea8b71e482a74f382d2ce5ebeee8fdb2172f477df4900d310536c0));
+        let message = [0u8; 32];
+
+        let mut out = [0u8; 64];
+        assert!(ac_secp_schnorr_sign(
+            &private_key, &message, &[0u8; 32], &mut out
+        ));
+        assert_eq!(out, signature);
+        assert!(ac_secp_schnorr_veri(&public_key, &message, &signature));
+
+        let mut forged = signature;
+        forged[0] ^= 1;
+        assert!(!ac_secp_schnorr_veri(&public_key, &message, &forged));
+    }
+}
@@ -1,3 +1,5 @@
+use rayon::prelude::*;
+
 use crate::form::bpoly::*;
 use crate::form::fext::*;
 use crate::form::fpoly::*;
@@ -6,6 +8,7 @@ use crate::form::math::mary::snag_as_bpoly;
 use crate::form::math::*;
 use crate::form::{Belt, FPolySlice, Felt};
 use crate::hand::structs::HoonList;
+use crate::pool;
 
 pub fn precompute_ntts(
     polys: MarySlice,
@@ -14,15 +17,24 @@ pub fn precompute_ntts(
     res: &mut [Belt],
 ) -> Result<(), FieldError> {
     let new_len = height * max_ntt_len;
-
-    for i in 0..polys.len as usize {
-        let bp = snag_as_bpoly(polys, i);
-        let mut extended = vec![Belt::zero(); new_len];
-        bpoly_zero_extend(bp, &mut extended);
-        let fft = bp_fft(&extended)?;
-        res[i * new_len..(i + 1) * new_len].copy_from_slice(&fft);
+    if new_len == 0 {
+        return Ok(());
     }
-    Ok(())
+
+    //  every column is extended on its own, so they're spread across the prover pool
+    pool::install(|| {
+        res[..polys.len as usize * new_len]
+            .par_chunks_mut(new_len)
+            .enumerate()
+            .try_for_each(|(i, out)| {
+                let bp = snag_as_bpoly(polys, i);
+                let mut extended = vec![Belt::zero(); new_len];
+                bpoly_zero_extend(bp, &mut extended);
+                let fft = bp_fft(&extended)?;
+                out.copy_from_slice(&fft);
+                Ok(())
+            })
+    })
 }
 
 pub fn compute_deep(
@@ -1,5 +1,13 @@
 use either::Either::*;
 use nockvm::jets::hot::{HotEntry, K_138};
+use nockvm::jets::lock::blake::jet_blake3_hash;
+use nockvm::jets::lock::ed::{jet_puck, jet_shar, jet_sign, jet_veri, jet_veri_batch};
+use nockvm::jets::lock::keccak::jet_keccak_256;
+use nockvm::jets::lock::secp::{
+    jet_secp_priv_to_pub, jet_secp_reco, jet_secp_schnorr_sign, jet_secp_schnorr_veri,
+    jet_secp_schnorr_veri_batch, jet_secp_sign,
+};
+use nockvm::jets::pack::JetPack;
 
 use crate::jets::base_jets::*;
 use crate::jets::bp_jets::*;
@@ -19,6 +27,20 @@ use crate::jets::tip5_sponge::*;
 use crate::jets::trace_gen_jets::*;
 use crate::jets::verifier_jets::*;
 
+/// The jets of the STARK prover and verifier, and of the cryptography nockchain uses, as a pack
+/// to register with a [`nockvm::jets::pack::JetRegistry`]
+pub struct ProverJets;
+
+impl JetPack for ProverJets {
+    fn name(&self) -> &'static str {
+        "zkvm-jetpack"
+    }
+
+    fn jets(&self) -> Vec<HotEntry> {
+        produce_prover_hot_state()
+    }
+}
+
 pub fn produce_prover_hot_state() -> Vec<HotEntry> {
     let mut jets: Vec<HotEntry> = Vec::new();
     jets.extend(BASE_FIELD_JETS);
@@ -26,6 +48,8 @@ pub fn produce_prover_hot_state() -> Vec<HotEntry> {
     jets.extend(CURVE_JETS);
     jets.extend(ZTD_JETS);
     jets.extend(KEYGEN_JETS);
+    jets.extend(HASH_JETS);
+    jets.extend(SIGNATURE_JETS);
     jets.extend(XTRA_JETS);
     jets.extend(EXTENSION_FIELD_JETS);
     jets.extend(ZKVM_TABLE_JETS_V2);
@@ -153,6 +177,32 @@ pub const XTRA_JETS: &[HotEntry] = &[
         1,
         evaluate_deep_jet,
     ),
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"proof-lib"),
+            Left(b"utils"),
+            Left(b"fri"),
+            Left(b"table-lib"),
+            Left(b"stark-core"),
+            Left(b"fock-core"),
+            Left(b"pow"),
+            Left(b"stark-engine"),
+            Left(b"stark-verifier"),
+            Left(b"verify-door"),
+            Left(b"verify-merk-proofs"),
+        ],
+        1,
+        verify_merk_proofs_jet,
+    ),
     (
         &[
             K_138,
@@ -1200,22 +1250,422 @@ pub const KEYGEN_JETS: &[HotEntry] = &[(
     argon2_jet,
 )];
 
-pub const CURVE_JETS: &[HotEntry] = &[(
-    &[
-        K_138,
-        Left(b"one"),
-        Left(b"two"),
-        Left(b"tri"),
-        Left(b"qua"),
-        Left(b"pen"),
-        Left(b"zeke"),
-        Left(b"ext-field"),
-        Left(b"misc-lib"),
-        Left(b"cheetah"),
-        Left(b"curve"),
-        Left(b"affine"),
-        Left(b"ch-scal"),
-    ],
-    1,
-    ch_scal_jet,
-)];
+pub const HASH_JETS: &[HotEntry] = &[
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"proof-lib"),
+            Left(b"utils"),
+            Left(b"fri"),
+            Left(b"table-lib"),
+            Left(b"stark-core"),
+            Left(b"fock-core"),
+            Left(b"pow"),
+            Left(b"stark-engine"),
+            Left(b"zose"),
+            Left(b"blake"),
+            Left(b"blake3-impl"),
+            Left(b"blake3"),
+            Left(b"hash"),
+        ],
+        1,
+        jet_blake3_hash,
+    ),
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"proof-lib"),
+            Left(b"utils"),
+            Left(b"fri"),
+            Left(b"table-lib"),
+            Left(b"stark-core"),
+            Left(b"fock-core"),
+            Left(b"pow"),
+            Left(b"stark-engine"),
+            Left(b"zose"),
+            Left(b"kecc"),
+            Left(b"k256"),
+        ],
+        1,
+        jet_keccak_256,
+    ),
+];
+
+pub const SIGNATURE_JETS: &[HotEntry] = &[
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"proof-lib"),
+            Left(b"utils"),
+            Left(b"fri"),
+            Left(b"table-lib"),
+            Left(b"stark-core"),
+            Left(b"fock-core"),
+            Left(b"pow"),
+            Left(b"stark-engine"),
+            Left(b"zose"),
+            Left(b"coed"),
+            Left(b"ed"),
+            Left(b"puck"),
+        ],
+        1,
+        jet_puck,
+    ),
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"proof-lib"),
+            Left(b"utils"),
+            Left(b"fri"),
+            Left(b"table-lib"),
+            Left(b"stark-core"),
+            Left(b"fock-core"),
+            Left(b"pow"),
+            Left(b"stark-engine"),
+            Left(b"zose"),
+            Left(b"coed"),
+            Left(b"ed"),
+            Left(b"shar"),
+        ],
+        1,
+        jet_shar,
+    ),
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"proof-lib"),
+            Left(b"utils"),
+            Left(b"fri"),
+            Left(b"table-lib"),
+            Left(b"stark-core"),
+            Left(b"fock-core"),
+            Left(b"pow"),
+            Left(b"stark-engine"),
+            Left(b"zose"),
+            Left(b"coed"),
+            Left(b"ed"),
+            Left(b"sign"),
+        ],
+        1,
+        jet_sign,
+    ),
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"proof-lib"),
+            Left(b"utils"),
+            Left(b"fri"),
+            Left(b"table-lib"),
+            Left(b"stark-core"),
+            Left(b"fock-core"),
+            Left(b"pow"),
+            Left(b"stark-engine"),
+            Left(b"zose"),
+            Left(b"coed"),
+            Left(b"ed"),
+            Left(b"veri"),
+        ],
+        1,
+        jet_veri,
+    ),
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"proof-lib"),
+            Left(b"utils"),
+            Left(b"fri"),
+            Left(b"table-lib"),
+            Left(b"stark-core"),
+            Left(b"fock-core"),
+            Left(b"pow"),
+            Left(b"stark-engine"),
+            Left(b"zose"),
+            Left(b"coed"),
+            Left(b"ed"),
+            Left(b"veri-batch"),
+        ],
+        1,
+        jet_veri_batch,
+    ),
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"proof-lib"),
+            Left(b"utils"),
+            Left(b"fri"),
+            Left(b"table-lib"),
+            Left(b"stark-core"),
+            Left(b"fock-core"),
+            Left(b"pow"),
+            Left(b"stark-engine"),
+            Left(b"zose"),
+            Left(b"secp"),
+            Left(b"secp256k1"),
+            Left(b"priv"),
+        ],
+        1,
+        jet_secp_priv_to_pub,
+    ),
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"proof-lib"),
+            Left(b"utils"),
+            Left(b"fri"),
+            Left(b"table-lib"),
+            Left(b"stark-core"),
+            Left(b"fock-core"),
+            Left(b"pow"),
+            Left(b"stark-engine"),
+            Left(b"zose"),
+            Left(b"secp"),
+            Left(b"secp256k1"),
+            Left(b"sign"),
+        ],
+        1,
+        jet_secp_sign,
+    ),
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"proof-lib"),
+            Left(b"utils"),
+            Left(b"fri"),
+            Left(b"table-lib"),
+            Left(b"stark-core"),
+            Left(b"fock-core"),
+            Left(b"pow"),
+            Left(b"stark-engine"),
+            Left(b"zose"),
+            Left(b"secp"),
+            Left(b"secp256k1"),
+            Left(b"reco"),
+        ],
+        1,
+        jet_secp_reco,
+    ),
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"proof-lib"),
+            Left(b"utils"),
+            Left(b"fri"),
+            Left(b"table-lib"),
+            Left(b"stark-core"),
+            Left(b"fock-core"),
+            Left(b"pow"),
+            Left(b"stark-engine"),
+            Left(b"zose"),
+            Left(b"secp"),
+            Left(b"secp256k1"),
+            Left(b"schnorr"),
+            Left(b"sosi"),
+        ],
+        1,
+        jet_secp_schnorr_sign,
+    ),
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"proof-lib"),
+            Left(b"utils"),
+            Left(b"fri"),
+            Left(b"table-lib"),
+            Left(b"stark-core"),
+            Left(b"fock-core"),
+            Left(b"pow"),
+            Left(b"stark-engine"),
+            Left(b"zose"),
+            Left(b"secp"),
+            Left(b"secp256k1"),
+            Left(b"schnorr"),
+            Left(b"sove"),
+        ],
+        1,
+        jet_secp_schnorr_veri,
+    ),
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"proof-lib"),
+            Left(b"utils"),
+            Left(b"fri"),
+            Left(b"table-lib"),
+            Left(b"stark-core"),
+            Left(b"fock-core"),
+            Left(b"pow"),
+            Left(b"stark-engine"),
+            Left(b"zose"),
+            Left(b"secp"),
+            Left(b"secp256k1"),
+            Left(b"schnorr"),
+            Left(b"sove-batch"),
+        ],
+        1,
+        jet_secp_schnorr_veri_batch,
+    ),
+];
+
+pub const CURVE_JETS: &[HotEntry] = &[
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"cheetah"),
+            Left(b"curve"),
+            Left(b"affine"),
+            Left(b"ch-scal"),
+        ],
+        1,
+        ch_scal_jet,
+    ),
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"cheetah"),
+            Left(b"schnorr"),
+            Left(b"affine"),
+            Left(b"verify"),
+        ],
+        1,
+        schnorr_verify_jet,
+    ),
+    (
+        &[
+            K_138,
+            Left(b"one"),
+            Left(b"two"),
+            Left(b"tri"),
+            Left(b"qua"),
+            Left(b"pen"),
+            Left(b"zeke"),
+            Left(b"ext-field"),
+            Left(b"misc-lib"),
+            Left(b"cheetah"),
+            Left(b"schnorr"),
+            Left(b"affine"),
+            Left(b"verify-batch"),
+        ],
+        1,
+        schnorr_verify_batch_jet,
+    ),
+];
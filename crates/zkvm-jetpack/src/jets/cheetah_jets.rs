@@ -1,14 +1,19 @@
-use ibig::UBig;
+use ibig::{ubig, UBig};
 use nockvm::interpreter::Context;
 use nockvm::jets::cold::{FromNounError, Nounable, NounableResult};
 use nockvm::jets::util::{slot, BAIL_FAIL};
 use nockvm::jets::JetErr;
-use nockvm::noun::{Atom, Noun, NounAllocator, NO, T, YES};
+use nockvm::mem::NockStack;
+use nockvm::noun::{Atom, Noun, NounAllocator, D, NO, T, YES};
+use rayon::prelude::*;
 
-use crate::form::math::base::bneg;
+use crate::form::math::base::{based_check, bneg, PRIME};
 use crate::form::math::bpoly::{bpegcd, bpscal};
 use crate::form::Belt;
+use crate::jets::tip5_jets::hash_varlen;
 use crate::noun::noun_ext::AtomExt;
+use crate::pool;
+use crate::utils::hoon_list_to_vecbelt;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) struct CheetahPoint {
@@ -303,3 +308,301 @@ pub(crate) fn ch_scal_big(n: &UBig, p: &CheetahPoint) -> Result<CheetahPoint, Je
     }
     Ok(acc)
 }
+
+/// a-gen:curve
+pub(crate) const A_GEN: CheetahPoint = CheetahPoint {
+    x: F6lt([
+        Belt(2754611494552410273),
+        Belt(8599518745794843693),
+        Belt(10526511002404673680),
+        Belt(4830863958577994148),
+        Belt(375185138577093320),
+        Belt(12938930721685970739),
+    ]),
+    y: F6lt([
+        Belt(15384029202802550068),
+        Belt(2774812795997841935),
+        Belt(14375303400746062753),
+        Belt(10708493419890101954),
+        Belt(13187678623570541764),
+        Belt(9990732138772505951),
+    ]),
+    inf: false,
+};
+
+/// g-order:curve
+fn g_order() -> UBig {
+    ubig!(_0x7af2599b3b3f22d0563fbf0f990a37b5327aa72330157722d443623eaed4accf)
+}
+
+/// trunc-g-order
+fn trunc_g_order(a: &[u64]) -> UBig {
+    let p = UBig::from(PRIME);
+    let n = UBig::from(a[0])
+        + &p * UBig::from(a[1])
+        + &p * &p * UBig::from(a[2])
+        + &p * &p * &p * UBig::from(a[3]);
+    n % g_order()
+}
+
+/// The sample of verify:affine:schnorr, read out of the loom
+#[derive(Debug, Clone)]
+pub(crate) struct SchnorrSig {
+    pub pubkey: CheetahPoint,
+    pub m: Vec<Belt>,
+    pub chal: UBig,
+    pub sig: UBig,
+}
+
+impl SchnorrSig {
+    /// Read `[pubkey m chal sig]`, or `None` to leave it to the Hoon, which crashes hashing
+    /// anything outside the field
+    fn from_noun(context: &mut Context, noun: Noun) -> Option<Self> {
+        let pubkey = CheetahPoint::from_noun(&mut context.stack, &slot(noun, 2).ok()?).ok()?;
+        let m = hoon_list_to_vecbelt(slot(noun, 6).ok()?).ok()?;
+        let chal = slot(noun, 14).ok()?.as_atom().ok()?;
+        let sig = slot(noun, 15).ok()?.as_atom().ok()?;
+        let chal = chal.as_ubig(&mut context.stack);
+        let sig = sig.as_ubig(&mut context.stack);
+        SchnorrSig::new(pubkey, m, chal, sig)
+    }
+
+    fn new(pubkey: CheetahPoint, m: Vec<Belt>, chal: UBig, sig: UBig) -> Option<Self> {
+        let based = pubkey
+            .x
+            .0
+            .iter()
+            .chain(&pubkey.y.0)
+            .chain(&m)
+            .all(|belt| based_check(belt.0));
+        if !based {
+            return None;
+        }
+        Some(SchnorrSig {
+            pubkey,
+            m,
+            chal,
+            sig,
+        })
+    }
+}
+
+/// verify:affine:schnorr. `None` where the Hoon would crash.
+pub(crate) fn schnorr_verify(s: &SchnorrSig) -> Option<bool> {
+    let zero = ubig!(0);
+    let order = g_order();
+    if s.m.len() != 5 || s.chal == zero || s.chal >= order || s.sig == zero || s.sig >= order {
+        return Some(false);
+    }
+    let scalar = ch_add(
+        &ch_scal_big(&s.sig, &A_GEN).ok()?,
+        &ch_neg(&ch_scal_big(&s.chal, &s.pubkey).ok()?),
+    )
+    .ok()?;
+    let mut transcript: Vec<Belt> = [scalar.x, scalar.y, s.pubkey.x, s.pubkey.y]
+        .iter()
+        .flat_map(|f| f.0)
+        .chain(s.m.iter().copied())
+        .collect();
+    Some(s.chal == trunc_g_order(&hash_varlen(&mut transcript)))
+}
+
+/// t8-to-atom:belt-schnorr: the pieces of an 8-tuple put together with +rap, so each takes as many
+/// 32-bit blocks as it needs, and a zero takes none
+fn t8_to_atom(stack: &mut NockStack, t8: Noun) -> Option<UBig> {
+    let mut pieces = Vec::with_capacity(8);
+    let mut rest = t8;
+    for _ in 0..7 {
+        let cell = rest.as_cell().ok()?;
+        pieces.push(cell.head().as_atom().ok()?.as_ubig(stack));
+        rest = cell.tail();
+    }
+    pieces.push(rest.as_atom().ok()?.as_ubig(stack));
+    Some(pieces.into_iter().rev().fold(ubig!(0), |above, piece| {
+        let blocks = piece.bit_len().div_ceil(32);
+        (above << (blocks * 32)) + piece
+    }))
+}
+
+/// atom-to-t8:belt-schnorr, for an atom of at most 256 bits
+fn atom_to_t8(stack: &mut NockStack, atom: &UBig) -> Noun {
+    let mask = ubig!(0xffffffff);
+    let pieces: Vec<Noun> = (0..8)
+        .map(|i| {
+            let piece = u64::try_from((atom >> (32 * i)) & &mask).expect("32 bits");
+            Atom::new(stack, piece).as_noun()
+        })
+        .collect();
+    T(stack, &pieces)
+}
+
+/// verify:affine:belt-schnorr, for checking a signature outside the kernel: whether `sig`, a
+/// `schnorr-signature` of two 8-tuples, is `pubkey`'s signature of `m`. `None` where the Hoon
+/// would crash.
+pub fn belt_schnorr_verify(
+    stack: &mut NockStack,
+    pubkey: Noun,
+    m: &[Belt],
+    sig: Noun,
+) -> Option<bool> {
+    let pubkey = CheetahPoint::from_noun(stack, &pubkey).ok()?;
+    let chal = t8_to_atom(stack, slot(sig, 2).ok()?)?;
+    let sig = t8_to_atom(stack, slot(sig, 3).ok()?)?;
+    schnorr_verify(&SchnorrSig::new(pubkey, m.to_vec(), chal, sig)?)
+}
+
+/// The public key of the secret key `sk`, as a noun
+pub fn belt_schnorr_pubkey(stack: &mut NockStack, sk: &UBig) -> Option<Noun> {
+    Some(ch_scal_big(sk, &A_GEN).ok()?.into_noun(stack))
+}
+
+/// sign:affine:belt-schnorr with the nonce given rather than derived from the key and message,
+/// for tests that need signatures outside the kernel. Signing twice with a nonce gives the key
+/// away.
+pub fn belt_schnorr_sign_with_nonce(
+    stack: &mut NockStack,
+    sk: &UBig,
+    nonce: &UBig,
+    m: &[Belt],
+) -> Option<Noun> {
+    let (_, chal, sig) = sign_with_nonce(sk, nonce, m)?;
+    let chal = atom_to_t8(stack, &chal);
+    let sig = atom_to_t8(stack, &sig);
+    Some(T(stack, &[chal, sig]))
+}
+
+/// sign:affine:schnorr with a fixed nonce
+fn sign_with_nonce(sk: &UBig, nonce: &UBig, m: &[Belt]) -> Option<(CheetahPoint, UBig, UBig)> {
+    let pubkey = ch_scal_big(sk, &A_GEN).ok()?;
+    let scalar = ch_scal_big(nonce, &A_GEN).ok()?;
+    let mut transcript: Vec<Belt> = [scalar.x, scalar.y, pubkey.x, pubkey.y]
+        .iter()
+        .flat_map(|f| f.0)
+        .chain(m.iter().copied())
+        .collect();
+    let chal = trunc_g_order(&hash_varlen(&mut transcript));
+    let sig = (nonce + &chal * sk) % g_order();
+    Some((pubkey, chal, sig))
+}
+
+pub fn schnorr_verify_jet(context: &mut Context, subject: Noun) -> Result<Noun, JetErr> {
+    let sam = slot(subject, 6)?;
+    let sig = SchnorrSig::from_noun(context, sam).ok_or(JetErr::Punt)?;
+    let valid = schnorr_verify(&sig).ok_or(JetErr::Punt)?;
+    Ok(if valid { YES } else { NO })
+}
+
+/// verify-batch:affine:schnorr, checking every signature across the prover pool.
+///
+/// Signatures are independent, so the ones of a whole block can be checked at once. Anything
+/// the Hoon would crash on is left to the Hoon.
+pub fn schnorr_verify_batch_jet(context: &mut Context, subject: Noun) -> Result<Noun, JetErr> {
+    let mut list = slot(subject, 6)?;
+
+    //  nouns can't leave this thread, so read the signatures out first
+    let mut sigs = Vec::new();
+    while let Ok(item) = list.as_cell() {
+        sigs.push(SchnorrSig::from_noun(context, item.head()).ok_or(JetErr::Punt)?);
+        list = item.tail();
+    }
+    if unsafe { !list.raw_equals(&D(0)) } {
+        return Err(JetErr::Punt);
+    }
+
+    let results: Option<Vec<bool>> =
+        pool::install(|| sigs.par_iter().map(schnorr_verify).collect());
+    let valid = results.ok_or(JetErr::Punt)?.into_iter().all(|valid| valid);
+    Ok(if valid { YES } else { NO })
+}
+
+#[cfg(test)]
+mod tests {
+    use nockvm::jets::util::test::*;
+
+    use super::*;
+
+    fn sign(sk: &UBig, nonce: &UBig, m: &[Belt]) -> (CheetahPoint, UBig, UBig) {
+        sign_with_nonce(sk, nonce, m).expect("sign")
+    }
+
+    fn sig_noun(
+        c: &mut Context,
+        pubkey: CheetahPoint,
+        m: &[Belt],
+        chal: &UBig,
+        sig: &UBig,
+    ) -> Noun {
+        let pubkey = pubkey.into_noun(&mut c.stack);
+        let mut m: Vec<Noun> = m
+            .iter()
+            .map(|belt| Atom::new(&mut c.stack, belt.0).as_noun())
+            .collect();
+        m.push(D(0));
+        let m = T(&mut c.stack, &m);
+        let chal = Atom::from_ubig(&mut c.stack, chal).as_noun();
+        let sig = Atom::from_ubig(&mut c.stack, sig).as_noun();
+        T(&mut c.stack, &[pubkey, m, chal, sig])
+    }
+
+    #[test]
+    fn test_schnorr_verify_batch() {
+        let c = &mut init_context();
+        let m: Vec<Belt> = (1..=5).map(Belt).collect();
+
+        let mut sigs = Vec::new();
+        for i in 1..4u64 {
+            let (pubkey, chal, sig) = sign(&UBig::from(i * 7919), &UBig::from(i * 104729), &m);
+            let s = SchnorrSig {
+                pubkey,
+                m: m.clone(),
+                chal,
+                sig,
+            };
+            assert_eq!(schnorr_verify(&s), Some(true));
+            sigs.push(sig_noun(c, s.pubkey, &m, &s.chal, &s.sig));
+        }
+        let all = T(&mut c.stack, &[sigs[0], sigs[1], sigs[2], D(0)]);
+        assert_jet(c, schnorr_verify_batch_jet, all, YES);
+
+        // one bad signature fails the batch
+        let (pubkey, chal, sig) = sign(&ubig!(5), &ubig!(11), &m);
+        let bad = sig_noun(c, pubkey, &m, &chal, &(sig + ubig!(1)));
+        assert_jet(c, schnorr_verify_jet, bad, NO);
+        let batch = T(&mut c.stack, &[sigs[0], bad, D(0)]);
+        assert_jet(c, schnorr_verify_batch_jet, batch, NO);
+        assert_jet(c, schnorr_verify_batch_jet, D(0), YES);
+    }
+
+    #[test]
+    fn test_belt_schnorr() {
+        let c = &mut init_context();
+        let m: Vec<Belt> = (1..=5).map(Belt).collect();
+        let (sk, nonce) = (ubig!(7919), ubig!(104729));
+        let pubkey = belt_schnorr_pubkey(&mut c.stack, &sk).expect("pubkey");
+        let sig = belt_schnorr_sign_with_nonce(&mut c.stack, &sk, &nonce, &m).expect("sign");
+        assert_eq!(
+            belt_schnorr_verify(&mut c.stack, pubkey, &m, sig),
+            Some(true)
+        );
+        let other: Vec<Belt> = (2..=6).map(Belt).collect();
+        assert_eq!(
+            belt_schnorr_verify(&mut c.stack, pubkey, &other, sig),
+            Some(false)
+        );
+
+        // +rap skips the blocks of zero pieces
+        let t8 = T(
+            &mut c.stack,
+            &[D(5), D(0), D(7), D(0), D(0), D(0), D(0), D(0)],
+        );
+        assert_eq!(
+            t8_to_atom(&mut c.stack, t8),
+            Some(ubig!(5) + (ubig!(7) << 32))
+        );
+        let t8 = atom_to_t8(&mut c.stack, &(ubig!(5) + (ubig!(7) << 64)));
+        assert_eq!(
+            t8_to_atom(&mut c.stack, t8),
+            Some(ubig!(5) + (ubig!(7) << 32))
+        );
+    }
+}
@@ -15,8 +15,10 @@ use crate::hand::handle::{finalize_mary, new_handle_mut_mary};
 use crate::hand::structs::HoonList;
 use crate::jets::table_utils::*;
 use crate::jets::utils::jet_err;
+use crate::stages::{self, Stage};
 
 pub fn compute_v2_mega_extend_jet(context: &mut Context, subject: Noun) -> Result<Noun, JetErr> {
+    let _timer = stages::time(Stage::Extension);
     let sam = slot(subject, 6)?;
     let table_mary = slot(sam, 2)?;
     let all_chals = slot(sam, 6)?;
@@ -500,6 +502,7 @@ fn compress_ion(ion: &Ion, a: &Felt, b: &Felt, c: &Felt) -> Felt {
 }
 
 pub fn compute_v2_extend_jet(context: &mut Context, subject: Noun) -> Result<Noun, JetErr> {
+    let _timer = stages::time(Stage::Extension);
     let sam = slot(subject, 6)?;
     let table_mary = slot(sam, 2)?;
     let chals_rd1 = slot(sam, 6)?;
@@ -8,6 +8,7 @@ use nockvm::jets::JetErr;
 use nockvm::mem::NockStack;
 use nockvm::noun::{Atom, IndirectAtom, Noun, D, NO, T, YES};
 use nockvm_macros::tas;
+use rayon::prelude::*;
 use tracing::{debug, error};
 
 use crate::form::mary::*;
@@ -21,9 +22,11 @@ use crate::hand::structs::HoonList;
 use crate::jets::base_jets::{levy_based, rip_correct};
 use crate::jets::bp_jets::init_bpoly;
 use crate::jets::shape_jets::leaf_sequence;
-use crate::jets::tip5_jets::{digest_to_noundigest, hash_hashable, hash_pairs};
+use crate::jets::tip5_jets::{digest_to_noundigest, hash_hashable, hash_pairs, hash_varlen};
 use crate::jets::utils::jet_err;
 use crate::noun::noun_ext::{AtomExt, NounExt};
+use crate::pool;
+use crate::stages::{self, Stage};
 use crate::utils::vecnoun_to_hoon_list;
 
 pub fn mary_swag_jet(context: &mut Context, subject: Noun) -> Result<Noun, JetErr> {
@@ -286,6 +289,7 @@ pub fn change_step(
 }
 
 pub fn bp_build_merk_heap_jet(context: &mut Context, subject: Noun) -> Result<Noun, JetErr> {
+    let _timer = stages::time(Stage::Merkle);
     let stack = &mut context.stack;
     let mary_noun = slot(subject, 6)?;
 
@@ -314,6 +318,16 @@ pub fn get_mary_fields(p: Noun) -> Result<(Atom, Atom, Noun), JetErr> {
     Ok((ma_step.as_atom()?, ma_array_len.as_atom()?, ma_array_dat))
 }
 
+/// The hash-hashable of a mary row as a one-row mary, from the varlen hash of its `step` belts
+fn row_leaf_hash(stack: &mut NockStack, step: u32, digest: [u64; 5]) -> Result<Noun, JetErr> {
+    let leaf_step = T(stack, &[D(tas!(b"leaf")), D(1)]);
+    let leaf_len = T(stack, &[D(tas!(b"leaf")), D(step as u64)]);
+    let digest = digest_to_noundigest(stack, digest);
+    let row_hash = T(stack, &[D(tas!(b"hash")), digest]);
+    let hashable = T(stack, &[leaf_step, leaf_len, row_hash]);
+    hash_hashable(stack, hashable)
+}
+
 fn heapify_mary(stack: &mut NockStack, m_noun: Noun) -> Result<Noun, JetErr> {
     let (_ma_step, ma_array_len, _ma_array_dat) = get_mary_fields(m_noun)?;
     let size = bex(simple_xeb(ma_array_len.as_u64()? as usize)) - 1;
@@ -321,12 +335,21 @@ fn heapify_mary(stack: &mut NockStack, m_noun: Noun) -> Result<Noun, JetErr> {
     // calc high-bit
     let high_bit = lsh(stack, 6, size * 5, D(1).as_atom()?)?.as_atom()?;
 
-    // make leaves
+    // make leaves. each is the hash-hashable of a row as a one-row mary, which is the varlen
+    // hash of the row's belts under its shape. the belts are most of the work, so those are
+    // hashed across the prover pool.
+    let Ok(rows) = MarySlice::try_from(m_noun) else {
+        return jet_err();
+    };
+    let row_digests: Vec<[u64; 5]> = pool::install(|| {
+        (0..rows.len as usize)
+            .into_par_iter()
+            .map(|i| hash_varlen(&mut crate::form::math::mary::snag_as_bpoly(rows, i).to_vec()))
+            .collect()
+    });
     let mut res_vec: Vec<Noun> = Vec::new();
-    for i in 0..ma_array_len.as_u64()? {
-        let t = snag_as_bpoly(stack, m_noun, i as usize)?;
-        let hashable_bpoly = T(stack, &[D(tas!(b"mary")), D(1), t]);
-        let hash = hash_hashable(stack, hashable_bpoly)?;
+    for digest in row_digests {
+        let hash = row_leaf_hash(stack, rows.step, digest)?;
         let leafs = leaf_sequence(stack, hash)?;
         res_vec.push(leafs);
     }
@@ -417,3 +440,46 @@ pub fn mary_to_list_fields(
 
     Ok(vecnoun_to_hoon_list(stack, res_turn.as_slice()))
 }
+
+#[cfg(test)]
+mod tests {
+    use nockvm::jets::util::test::*;
+    use nockvm::unifying_equality::unifying_equality;
+
+    use super::*;
+    use crate::form::math::base::PRIME;
+    use crate::hand::handle::{finalize_mary, new_handle_mut_mary};
+
+    #[test]
+    fn test_row_leaf_hash_matches_hash_hashable() {
+        let c = &mut init_context();
+
+        // xorshift, for rows of arbitrary belts
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        for step in [1usize, 2, 3, 7] {
+            let len = 5;
+            let (res, mary) = new_handle_mut_mary(&mut c.stack, step, len);
+            for belt in mary.dat.iter_mut() {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                *belt = seed % PRIME;
+            }
+            let m_noun = finalize_mary(&mut c.stack, step, len, res);
+            let rows = MarySlice::try_from(m_noun).expect("mary");
+
+            // every leaf is the hash-hashable of the row as a one-row mary, as it was before
+            // the rows were hashed on their own
+            for i in 0..len {
+                let row = snag_as_bpoly(&mut c.stack, m_noun, i).expect("row");
+                let hashable = T(&mut c.stack, &[D(tas!(b"mary")), D(1), row]);
+                let mut expected = hash_hashable(&mut c.stack, hashable).expect("hash-hashable");
+                let digest =
+                    hash_varlen(&mut crate::form::math::mary::snag_as_bpoly(rows, i).to_vec());
+                let mut actual =
+                    row_leaf_hash(&mut c.stack, rows.step, digest).expect("row leaf hash");
+                assert!(unsafe { unifying_equality(&mut c.stack, &mut expected, &mut actual) });
+            }
+        }
+    }
+}
@@ -11,6 +11,7 @@ use crate::hand::handle::*;
 use crate::hand::structs::{HoonMap, HoonMapIter};
 use crate::jets::utils::jet_err;
 use crate::noun::noun_ext::NounExt;
+use crate::stages::{self, Stage};
 
 fn zero_bpoly() -> BPolyVec {
     BPolyVec::from(vec![0u64])
@@ -21,6 +22,7 @@ fn lagrange_one_bpoly(len: usize) -> BPolyVec {
 }
 
 pub fn mp_substitute_mega_jet(context: &mut Context, subject: Noun) -> Result {
+    let _timer = stages::time(Stage::Constraints);
     let sam = slot(subject, 6)?;
     let stack = &mut context.stack;
 
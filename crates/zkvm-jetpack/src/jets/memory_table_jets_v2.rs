@@ -18,8 +18,10 @@ use crate::form::{Belt, Felt};
 use crate::hand::handle::{finalize_mary, new_handle_mut_mary};
 use crate::jets::table_utils::*;
 use crate::jets::utils::jet_err;
+use crate::stages::{self, Stage};
 
 pub fn memory_v2_extend_jet(context: &mut Context, subject: Noun) -> Result<Noun, JetErr> {
+    let _timer = stages::time(Stage::Extension);
     let sam = slot(subject, 6)?;
     let table_mary = slot(sam, 2)?;
     let chals_rd1 = slot(sam, 6)?;
@@ -252,6 +254,7 @@ struct MemoryBankEx {
 }
 
 pub fn memory_v2_mega_extend_jet(context: &mut Context, subject: Noun) -> Result<Noun, JetErr> {
+    let _timer = stages::time(Stage::Extension);
     let sam = slot(subject, 6)?;
     let table_mary = slot(sam, 2)?;
     let all_chals = slot(sam, 6)?;
@@ -11,8 +11,10 @@ use crate::hand::handle::{finalize_poly, new_handle_mut_slice};
 use crate::hand::structs::HoonList;
 use crate::jets::utils::jet_err;
 use crate::noun::noun_ext::NounExt;
+use crate::stages::{self, Stage};
 
 pub fn precompute_ntts_jet(context: &mut Context, subject: Noun) -> Result<Noun, JetErr> {
+    let _timer = stages::time(Stage::Lde);
     let sam = slot(subject, 6)?;
     let polys = slot(sam, 2)?;
     let height = slot(sam, 6)?.as_atom()?.as_u64()? as usize;
@@ -38,6 +40,7 @@ pub fn precompute_ntts_jet(context: &mut Context, subject: Noun) -> Result<Noun,
 }
 
 pub fn compute_deep_jet(context: &mut Context, subject: Noun) -> Result<Noun, JetErr> {
+    let _timer = stages::time(Stage::Deep);
     let sam = slot(subject, 6)?;
     let trace_polys = slot(sam, 2)?;
     let trace_openings = slot(sam, 6)?;
@@ -6,8 +6,8 @@ use nockvm::jets::JetErr;
 use nockvm::mem::NockStack;
 use nockvm::noun::{Atom, Noun, D, T};
 use nockvm_macros::tas;
+use rayon::prelude::*;
 
-use crate::based;
 use crate::form::math::tip5::*;
 use crate::form::{Belt, Poly};
 use crate::hand::structs::HoonList;
@@ -20,6 +20,7 @@ use crate::utils::{
     belt_as_noun, bitslice_to_u128, fits_in_u128, hoon_list_to_vecbelt, hoon_list_to_vecnoun,
     vec_to_hoon_list, vecnoun_to_hoon_list,
 };
+use crate::{based, pool};
 
 pub fn hoon_list_to_sponge(list: Noun) -> Result<[u64; STATE_SIZE], JetErr> {
     if list.is_atom() {
@@ -128,7 +129,7 @@ pub fn hash_varlen_jet(context: &mut Context, subject: Noun) -> Result<Noun, Jet
     Ok(vec_to_hoon_list(stack, &digest))
 }
 
-fn hash_varlen(mut input_vec: &mut Vec<Belt>) -> [u64; 5] {
+pub(crate) fn hash_varlen(mut input_vec: &mut Vec<Belt>) -> [u64; 5] {
     let mut sponge = create_init_sponge_variable();
 
     // assert that input is made of base field elements
@@ -269,7 +270,7 @@ pub fn hash_10_jet(context: &mut Context, subject: Noun) -> Result<Noun, JetErr>
     Ok(vec_to_hoon_list(stack, &digest))
 }
 
-fn hash_10(mut input_vec: &mut Vec<Belt>) -> [u64; 5] {
+pub(crate) fn hash_10(mut input_vec: &mut Vec<Belt>) -> [u64; 5] {
     // check input
     let (q, r) = tip5_calc_q_r(&input_vec);
     assert_eq!(q, 1);
@@ -297,28 +298,35 @@ pub fn hash_pairs_jet(context: &mut Context, subject: Noun) -> Result<Noun, JetE
     hash_pairs(stack, lis_noun)
 }
 
+/// Layers with at least this many pairs are hashed across the prover pool
+const PARALLEL_HASH_PAIRS: usize = 256;
+
 pub fn hash_pairs(stack: &mut NockStack, lis_noun: Noun) -> Result<Noun, JetErr> {
     let lis = hoon_list_to_vecnoun(lis_noun)?;
     let lent_lis = lis.len();
     assert!(lent_lis > 0);
 
-    let mut res: Vec<Noun> = Vec::new();
-
+    //  nouns can't leave this thread, so read the pairs out first
+    let mut pairs: Vec<Vec<Belt>> = Vec::with_capacity(lent_lis / 2);
     for i in 0..lent_lis / 2 {
         let b = i * 2;
-        if (b + 1) == lent_lis {
-            res.push(lis[b]);
-        } else {
-            let b0 = hoon_list_to_vecbelt(lis[b])?;
-            let mut b1 = hoon_list_to_vecbelt(lis[b + 1])?;
-            let mut pair = b0;
-            pair.append(&mut b1);
-            let digest = hash_10(&mut pair);
-            let digest_noun = vec_to_hoon_list(stack, &digest);
-            res.push(digest_noun);
-        }
+        let mut pair = hoon_list_to_vecbelt(lis[b])?;
+        pair.append(&mut hoon_list_to_vecbelt(lis[b + 1])?);
+        pairs.push(pair);
     }
 
+    //  collect keeps the digests in order, so the result doesn't depend on scheduling
+    let digests: Vec<[u64; 5]> = if pairs.len() >= PARALLEL_HASH_PAIRS {
+        pool::install(|| pairs.par_iter_mut().map(hash_10).collect())
+    } else {
+        pairs.iter_mut().map(hash_10).collect()
+    };
+
+    let res: Vec<Noun> = digests
+        .iter()
+        .map(|digest| vec_to_hoon_list(stack, digest))
+        .collect();
+
     Ok(vecnoun_to_hoon_list(stack, res.as_slice()))
 }
 
@@ -513,6 +521,36 @@ mod tests {
         assert_jet(c, montify_jet, sam, res);
     }
 
+    #[test]
+    fn test_hash_pairs_parallel() {
+        let c = &mut init_context();
+
+        // enough pairs to be split across the pool
+        let digests: Vec<Vec<u64>> = (0..2 * PARALLEL_HASH_PAIRS as u64 + 1)
+            .map(|i| (0..5).map(|j| i * 5 + j).collect())
+            .collect();
+        let lists: Vec<Noun> = digests
+            .iter()
+            .map(|digest| vec_to_hoon_list(&mut c.stack, digest))
+            .collect();
+        let lis = vecnoun_to_hoon_list(&mut c.stack, &lists);
+        let res = hash_pairs(&mut c.stack, lis).expect("hash-pairs");
+        let res = hoon_list_to_vecnoun(res).expect("list");
+        assert_eq!(res.len(), PARALLEL_HASH_PAIRS);
+
+        // every digest is the one hashed on its own
+        for (i, digest) in res.iter().enumerate() {
+            let mut pair: Vec<Belt> = digests[2 * i]
+                .iter()
+                .chain(&digests[2 * i + 1])
+                .map(|b| Belt(*b))
+                .collect();
+            let expected = hash_10(&mut pair);
+            let digest = hoon_list_to_vecbelt(*digest).expect("digest");
+            assert_eq!(digest.iter().map(|b| b.0).collect::<Vec<u64>>(), expected);
+        }
+    }
+
     #[test]
     fn test_hash_varlen_jet() {
         let c = &mut init_context();
@@ -9,8 +9,10 @@ use crate::form::Felt;
 use crate::hand::handle::new_handle_mut_felt;
 use crate::jets::utils::jet_err;
 use crate::noun::noun_ext::NounExt;
+use crate::stages::{self, Stage};
 
 pub fn build_tree_data_jet(context: &mut Context, subject: Noun) -> Result<Noun, JetErr> {
+    let _timer = stages::time(Stage::Trace);
     let sam = slot(subject, 6)?;
     let t = slot(sam, 2)?;
     let alf_noun = slot(sam, 3)?;
@@ -4,15 +4,19 @@ use nockvm::jets::JetErr;
 use nockvm::mem::NockStack;
 use nockvm::noun::{Atom, Cell, IndirectAtom, Noun, D};
 use nockvm_macros::tas;
+use rayon::prelude::*;
 use tracing::debug;
 
+use crate::form::math::base::based_check;
 use crate::form::math::fext::*;
 use crate::form::poly::Poly;
 use crate::form::{bpow, brek, BPolySlice, Belt, Element, FPolySlice, Felt, MegaTyp, PolySlice};
 use crate::hand::handle::new_handle_mut_felt;
 use crate::hand::structs::{HoonList, HoonMap, HoonMapIter};
+use crate::jets::tip5_jets::hash_10;
 use crate::jets::utils::jet_err;
 use crate::noun::noun_ext::NounExt;
+use crate::pool;
 
 pub fn evaluate_deep_jet(context: &mut Context, subject: Noun) -> Result<Noun, JetErr> {
     let sam = slot(subject, 6)?;
@@ -179,6 +183,120 @@ fn process_belt(
     (acc, num)
 }
 
+/// A tip5 digest as its five belts
+type Digest = [u64; 5];
+
+/// An opening to check, leaf first: `[leaf=noun-digest axis=@ root=noun-digest path=(list noun-digest)]`
+struct MerkData {
+    leaf: Digest,
+    axis: u64,
+    root: Digest,
+    path: Vec<Digest>,
+}
+
+/// Read a `noun-digest`, or `None` if it isn't five based belts
+fn noun_to_digest(noun: Noun) -> Option<Digest> {
+    let mut digest = [0u64; 5];
+    let mut cur = noun;
+    for (i, belt) in digest.iter_mut().enumerate() {
+        let elem = if i < 4 {
+            let cell = cur.as_cell().ok()?;
+            cur = cell.tail();
+            cell.head()
+        } else {
+            cur
+        };
+        *belt = elem.as_atom().ok()?.as_u64().ok()?;
+        if !based_check(*belt) {
+            return None;
+        }
+    }
+    Some(digest)
+}
+
+fn noun_to_merk_data(noun: Noun) -> Option<MerkData> {
+    let cell = noun.as_cell().ok()?;
+    let leaf = noun_to_digest(cell.head())?;
+    let cell = cell.tail().as_cell().ok()?;
+    let axis = cell.head().as_atom().ok()?.as_u64().ok()?;
+    let cell = cell.tail().as_cell().ok()?;
+    let root = noun_to_digest(cell.head())?;
+    let mut path = Vec::new();
+    let mut list = cell.tail();
+    while let Ok(item) = list.as_cell() {
+        path.push(noun_to_digest(item.head())?);
+        list = item.tail();
+    }
+    if unsafe { !list.raw_equals(&D(0)) } {
+        return None;
+    }
+    Some(MerkData {
+        leaf,
+        axis,
+        root,
+        path,
+    })
+}
+
+/// hash-ten-cell:tip5
+fn hash_ten_digests(l: &Digest, r: &Digest) -> Digest {
+    let mut input: Vec<Belt> = l.iter().chain(r).map(|x| Belt(*x)).collect();
+    hash_10(&mut input)
+}
+
+/// verify-merk-proof:merkle
+fn verify_merk_proof(data: &MerkData) -> bool {
+    if data.axis == 0 {
+        return false;
+    }
+    let mut leaf = data.leaf;
+    let mut axis = data.axis;
+    let mut path = data.path.iter();
+    loop {
+        if axis == 1 {
+            return leaf == data.root && path.next().is_none();
+        }
+        let Some(sib) = path.next() else {
+            return false;
+        };
+        leaf = if axis % 2 == 0 {
+            hash_ten_digests(&leaf, sib)
+        } else {
+            hash_ten_digests(sib, &leaf)
+        };
+        axis /= 2;
+    }
+}
+
+/// verify-merk-proofs:verify-door, checking every opening across the prover pool.
+///
+/// The Hoon checks the openings one at a time in an order drawn from `eny`, so that a prover
+/// can't make a verifier hash its way down a long list before reaching a bad opening. The order
+/// doesn't change the answer, and checking them all at once bounds that work just as well, so
+/// `eny` is unused here. A batch of proofs pools all of its openings into one call. Anything that
+/// isn't a well-formed list of openings is left to the Hoon.
+pub fn verify_merk_proofs_jet(_context: &mut Context, subject: Noun) -> Result<Noun, JetErr> {
+    let sam = slot(subject, 6)?;
+    let ps = slot(sam, 2)?;
+
+    //  nouns can't leave this thread, so read the openings out first
+    let mut openings = Vec::new();
+    let mut list = ps;
+    while let Ok(item) = list.as_cell() {
+        let Some(data) = noun_to_merk_data(item.head()) else {
+            return Err(JetErr::Punt);
+        };
+        openings.push(data);
+        list = item.tail();
+    }
+    if unsafe { !list.raw_equals(&D(0)) } {
+        return Err(JetErr::Punt);
+    }
+
+    let valid = pool::install(|| openings.par_iter().all(verify_merk_proof));
+    Ok(if valid { D(0) } else { D(1) })
+}
+
 // =/  add-op   ?:(=(field %base) badd fadd)
 // =/  mul-op   ?:(=(field %base) bmul fmul)
 // =/  aop-door   ?:(=(field %base) bop fop)
@@ -380,3 +498,79 @@ where
         Ok(acc + (coeff * res))
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use nockvm::jets::util::test::*;
+    use nockvm::noun::T;
+
+    use super::*;
+    use crate::form::math::base::PRIME;
+
+    fn digest_noun(stack: &mut NockStack, digest: &Digest) -> Noun {
+        let belts: Vec<Noun> = digest
+            .iter()
+            .map(|x| Atom::new(stack, *x).as_noun())
+            .collect();
+        T(stack, &belts)
+    }
+
+    fn opening(
+        stack: &mut NockStack,
+        leaf: &Digest,
+        axis: u64,
+        root: &Digest,
+        path: &[Digest],
+    ) -> Noun {
+        let mut list = D(0);
+        for sib in path.iter().rev() {
+            let sib = digest_noun(stack, sib);
+            list = T(stack, &[sib, list]);
+        }
+        let leaf = digest_noun(stack, leaf);
+        let root = digest_noun(stack, root);
+        T(stack, &[leaf, D(axis), root, list])
+    }
+
+    #[test]
+    fn test_verify_merk_proofs_jet() {
+        let c = &mut init_context();
+
+        // a tree of four leaves, opened at its second leaf
+        let leaves: Vec<Digest> = (0..4u64).map(|i| [i, i + 1, i + 2, i + 3, i + 4]).collect();
+        let left = hash_ten_digests(&leaves[0], &leaves[1]);
+        let right = hash_ten_digests(&leaves[2], &leaves[3]);
+        let root = hash_ten_digests(&left, &right);
+
+        let good = opening(&mut c.stack, &leaves[1], 5, &root, &[leaves[0], right]);
+        let other = opening(&mut c.stack, &leaves[2], 6, &root, &[leaves[3], left]);
+        let ps = T(&mut c.stack, &[good, other, D(0)]);
+        let sam = T(&mut c.stack, &[ps, D(0)]);
+        assert_jet(c, verify_merk_proofs_jet, sam, D(0));
+
+        // one bad opening in the batch fails all of it
+        let bad = opening(&mut c.stack, &leaves[1], 4, &root, &[leaves[0], right]);
+        let ps = T(&mut c.stack, &[good, bad, D(0)]);
+        let sam = T(&mut c.stack, &[ps, D(0)]);
+        assert_jet(c, verify_merk_proofs_jet, sam, D(1));
+
+        // so does a path that runs out, or one that is too long
+        let short = opening(&mut c.stack, &leaves[1], 5, &root, &[leaves[0]]);
+        let long = opening(&mut c.stack, &root, 1, &root, &[root]);
+        for p in [short, long] {
+            let ps = T(&mut c.stack, &[p, D(0)]);
+            let sam = T(&mut c.stack, &[ps, D(0)]);
+            assert_jet(c, verify_merk_proofs_jet, sam, D(1));
+        }
+
+        // digests outside the field are left to the Hoon
+        let unbased = opening(&mut c.stack, &[PRIME, 0, 0, 0, 0], 1, &root, &[]);
+        let ps = T(&mut c.stack, &[unbased, D(0)]);
+        let sam = T(&mut c.stack, &[ps, D(0)]);
+        let subject = T(&mut c.stack, &[D(0), sam, D(0)]);
+        assert!(matches!(
+            verify_merk_proofs_jet(c, subject),
+            Err(JetErr::Punt)
+        ));
+    }
+}
@@ -3,6 +3,9 @@ pub mod hand;
 pub mod hot;
 pub mod jets;
 pub mod noun;
+pub mod pool;
+pub mod proof;
+pub mod stages;
 pub mod utils;
 
 #[macro_use]
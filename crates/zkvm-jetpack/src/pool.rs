@@ -0,0 +1,73 @@
+//! The thread pool the proving jets spread their work across.
+//!
+//! Jets with enough independent work, like the low degree extension of every trace column or the
+//! hashing of every Merkle leaf, run it on this pool rather than on the interpreter's thread. The
+//! work is split so that results are collected in order, so a proof doesn't depend on the number
+//! of threads. Nouns can't leave the interpreter's thread, so jets read what they need out of
+//! the loom first and write results back after.
+//!
+//! The pool has one thread per core unless [`set_prover_threads`] sizes it before the first proof.
+use std::sync::OnceLock;
+
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+use tracing::warn;
+
+static PROVER_POOL: OnceLock<ThreadPool> = OnceLock::new();
+
+fn build_pool(
+    threads: usize,
+    start: impl Fn(usize) + Send + Sync + 'static,
+) -> Result<ThreadPool, ThreadPoolBuildError> {
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|i| format!("prover-{i}"))
+        .start_handler(start)
+        .build()
+}
+
+/// Size the proving pool to `threads` threads, or one per core if `threads` is 0.
+///
+/// The pool is built once, so this has no effect after it has been sized or used.
+pub fn set_prover_threads(threads: usize) -> Result<(), ThreadPoolBuildError> {
+    set_prover_pool(threads, |_| {})
+}
+
+/// Like [`set_prover_threads`], but each thread runs `start` with its index as it starts, to set
+/// its affinity or priority
+pub fn set_prover_pool(
+    threads: usize,
+    start: impl Fn(usize) + Send + Sync + 'static,
+) -> Result<(), ThreadPoolBuildError> {
+    let pool = build_pool(threads, start)?;
+    if PROVER_POOL.set(pool).is_err() {
+        warn!("prover thread pool is already running, not resizing it to {threads} threads");
+    }
+    Ok(())
+}
+
+/// Threads in the proving pool
+pub fn prover_threads() -> usize {
+    prover_pool().current_num_threads()
+}
+
+fn prover_pool() -> &'static ThreadPool {
+    PROVER_POOL.get_or_init(|| {
+        build_pool(0, |_| {}).unwrap_or_else(|err| {
+            panic!(
+                "Panicked with {err:?} at {}:{} (git sha: {:?})",
+                file!(),
+                line!(),
+                option_env!("GIT_SHA")
+            )
+        })
+    })
+}
+
+/// Run `op` on the proving pool, so that any parallel iterators in it are split across the pool
+pub fn install<OP, R>(op: OP) -> R
+where
+    OP: FnOnce() -> R + Send,
+    R: Send,
+{
+    prover_pool().install(op)
+}
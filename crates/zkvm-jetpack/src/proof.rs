@@ -0,0 +1,614 @@
+//! A versioned byte format for STARK proofs, for storing them and handing them to verifiers that
+//! don't run Nock.
+//!
+//! Every encoded proof starts with a fixed 24-byte header, all integers little-endian:
+//!
+//! | bytes  | field                                          |
+//! |--------|------------------------------------------------|
+//! | 0..4   | magic, `NKPF`                                  |
+//! | 4..6   | format version, [`PROOF_FORMAT_VERSION`]       |
+//! | 6..8   | proof version, the `version` of the proof noun |
+//! | 8..12  | number of proof objects                        |
+//! | 12..16 | number of hashes                               |
+//! | 16..24 | read index                                     |
+//!
+//! The proof objects follow in order, each a one-byte tag and its fields, then the hashes. Belts
+//! and other integers are 8 bytes, a digest is its 5 belts, and a felt is its 3 belts. A polynomial
+//! is its length followed by its coefficients, and a list is a 4-byte count followed by its items.
+//! The puzzle's subject is an arbitrary noun, so it's written as an 8-byte length followed by its
+//! [CBOR](nockapp::noun::cbor) encoding.
+//!
+//! | tag | object      | fields                                           |
+//! |-----|-------------|--------------------------------------------------|
+//! | 0   | `%m-root`   | digest                                           |
+//! | 1   | `%puzzle`   | commitment digest, nonce digest, length, subject |
+//! | 2   | `%codeword` | felt polynomial                                  |
+//! | 3   | `%terms`    | belt polynomial                                  |
+//! | 4   | `%m-paths`  | three paths, each a felt polynomial and digests  |
+//! | 5   | `%m-path`   | a path, a felt polynomial and digests            |
+//! | 6   | `%m-pathbf` | a path, a belt polynomial and digests            |
+//! | 7   | `%comp-m`   | digest, count                                    |
+//! | 8   | `%evals`    | felt polynomial                                  |
+//! | 9   | `%heights`  | list of integers                                 |
+//! | 10  | `%poly`     | belt polynomial                                  |
+//!
+//! A format version change is needed for any change to this layout; new proof versions and new
+//! tags can be added without one, since older decoders reject what they don't know.
+use nockapp::noun::cbor::{from_cbor, to_cbor, CborError};
+use nockvm::noun::{Atom, IndirectAtom, Noun, NounAllocator, D, T};
+use nockvm_macros::tas;
+use thiserror::Error;
+
+use crate::noun::noun_ext::NounExt;
+
+/// The first four bytes of every encoded proof
+pub const PROOF_MAGIC: [u8; 4] = *b"NKPF";
+
+/// The version of the byte format written by [`encode_proof`]
+pub const PROOF_FORMAT_VERSION: u16 = 1;
+
+/// Proof versions this format can carry
+const PROOF_VERSIONS: [u64; 3] = [0, 1, 2];
+
+const HEADER_LEN: usize = 24;
+
+const TAG_M_ROOT: u8 = 0;
+const TAG_PUZZLE: u8 = 1;
+const TAG_CODEWORD: u8 = 2;
+const TAG_TERMS: u8 = 3;
+const TAG_M_PATHS: u8 = 4;
+const TAG_M_PATH: u8 = 5;
+const TAG_M_PATHBF: u8 = 6;
+const TAG_COMP_M: u8 = 7;
+const TAG_EVALS: u8 = 8;
+const TAG_HEIGHTS: u8 = 9;
+const TAG_POLY: u8 = 10;
+
+/// Words in a felt
+const FELT_WORDS: usize = 3;
+
+#[derive(Debug, Error)]
+pub enum ProofFormatError {
+    #[error("proof format: bad magic")]
+    BadMagic,
+    #[error("proof format: unsupported format version {0}")]
+    UnsupportedFormat(u16),
+    #[error("proof format: unsupported proof version {0}")]
+    UnsupportedVersion(u64),
+    #[error("proof format: unknown object tag {0}")]
+    UnknownTag(u8),
+    #[error("proof format: truncated input")]
+    Truncated,
+    #[error("proof format: trailing bytes after proof")]
+    TrailingBytes,
+    #[error("proof format: malformed proof noun: {0}")]
+    Malformed(&'static str),
+    #[error("proof format: puzzle noun: {0}")]
+    Puzzle(#[from] CborError),
+}
+
+pub type Result<T, E = ProofFormatError> = std::result::Result<T, E>;
+
+/// The fixed header of an encoded proof, readable without decoding the rest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProofHeader {
+    pub format_version: u16,
+    pub proof_version: u16,
+    pub num_objects: u32,
+    pub num_hashes: u32,
+    pub read_index: u64,
+}
+
+/// Read the header of an encoded proof, checking its magic and format version.
+pub fn read_header(bytes: &[u8]) -> Result<ProofHeader> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(PROOF_MAGIC.len())? != PROOF_MAGIC {
+        return Err(ProofFormatError::BadMagic);
+    }
+    let format_version = reader.u16()?;
+    if format_version != PROOF_FORMAT_VERSION {
+        return Err(ProofFormatError::UnsupportedFormat(format_version));
+    }
+    let proof_version = reader.u16()?;
+    if !PROOF_VERSIONS.contains(&(proof_version as u64)) {
+        return Err(ProofFormatError::UnsupportedVersion(proof_version as u64));
+    }
+    Ok(ProofHeader {
+        format_version,
+        proof_version,
+        num_objects: reader.u32()?,
+        num_hashes: reader.u32()?,
+        read_index: reader.u64()?,
+    })
+}
+
+/// Encode a `proof` noun, `[version objects hashes read-index]`.
+pub fn encode_proof(proof: Noun) -> Result<Vec<u8>> {
+    let [version, objects, hashes, read_index] = proof
+        .uncell()
+        .map_err(|_| ProofFormatError::Malformed("proof is not a 4-tuple"))?;
+    let version = word(version, "version")?;
+    if !PROOF_VERSIONS.contains(&version) {
+        return Err(ProofFormatError::UnsupportedVersion(version));
+    }
+    let objects = list(objects, "objects")?;
+    let hashes = list(hashes, "hashes")?;
+
+    let mut out = Vec::with_capacity(HEADER_LEN);
+    out.extend_from_slice(&PROOF_MAGIC);
+    out.extend_from_slice(&PROOF_FORMAT_VERSION.to_le_bytes());
+    out.extend_from_slice(&(version as u16).to_le_bytes());
+    out.extend_from_slice(&count(objects.len())?.to_le_bytes());
+    out.extend_from_slice(&count(hashes.len())?.to_le_bytes());
+    out.extend_from_slice(&word(read_index, "read-index")?.to_le_bytes());
+    for object in objects {
+        write_object(&mut out, object)?;
+    }
+    for hash in hashes {
+        write_digest(&mut out, hash)?;
+    }
+    Ok(out)
+}
+
+/// Decode an encoded proof into a `proof` noun allocated in `allocator`.
+pub fn decode_proof<A: NounAllocator>(allocator: &mut A, bytes: &[u8]) -> Result<Noun> {
+    let header = read_header(bytes)?;
+    let mut reader = Reader {
+        bytes,
+        pos: HEADER_LEN,
+    };
+    let mut objects = Vec::new();
+    for _ in 0..header.num_objects {
+        objects.push(reader.object(allocator)?);
+    }
+    let mut hashes = Vec::new();
+    for _ in 0..header.num_hashes {
+        hashes.push(reader.digest(allocator)?);
+    }
+    if reader.pos != bytes.len() {
+        return Err(ProofFormatError::TrailingBytes);
+    }
+    let objects = make_list(allocator, objects);
+    let hashes = make_list(allocator, hashes);
+    let read_index = Atom::new(allocator, header.read_index).as_noun();
+    Ok(T(
+        allocator,
+        &[D(header.proof_version as u64), objects, hashes, read_index],
+    ))
+}
+
+fn word(noun: Noun, what: &'static str) -> Result<u64> {
+    noun.as_atom()
+        .and_then(|atom| atom.as_u64())
+        .map_err(|_| ProofFormatError::Malformed(what))
+}
+
+fn count(len: usize) -> Result<u32> {
+    u32::try_from(len).map_err(|_| ProofFormatError::Malformed("list too long"))
+}
+
+fn list(mut noun: Noun, what: &'static str) -> Result<Vec<Noun>> {
+    let mut items = Vec::new();
+    while let Ok(cell) = noun.as_cell() {
+        items.push(cell.head());
+        noun = cell.tail();
+    }
+    if unsafe { !noun.raw_equals(&D(0)) } {
+        return Err(ProofFormatError::Malformed(what));
+    }
+    Ok(items)
+}
+
+fn make_list<A: NounAllocator>(allocator: &mut A, items: Vec<Noun>) -> Noun {
+    items
+        .into_iter()
+        .rev()
+        .fold(D(0), |tail, item| T(allocator, &[item, tail]))
+}
+
+fn write_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_digest(out: &mut Vec<u8>, digest: Noun) -> Result<()> {
+    let belts: [Noun; 5] = digest
+        .uncell()
+        .map_err(|_| ProofFormatError::Malformed("digest is not a 5-tuple"))?;
+    for belt in belts {
+        write_u64(out, word(belt, "digest")?);
+    }
+    Ok(())
+}
+
+fn write_digests(out: &mut Vec<u8>, digests: Noun) -> Result<()> {
+    let digests = list(digests, "digests")?;
+    out.extend_from_slice(&count(digests.len())?.to_le_bytes());
+    for digest in digests {
+        write_digest(out, digest)?;
+    }
+    Ok(())
+}
+
+/// Write a polynomial `[len dat]` of `len` elements of `elem_words` words each
+fn write_poly(out: &mut Vec<u8>, poly: Noun, elem_words: usize) -> Result<()> {
+    let [len, dat] = poly
+        .uncell()
+        .map_err(|_| ProofFormatError::Malformed("polynomial is not a cell"))?;
+    let len = word(len, "polynomial length")?;
+    let dat = dat
+        .as_atom()
+        .map_err(|_| ProofFormatError::Malformed("polynomial data"))?;
+    let num_words = usize::try_from(len)
+        .ok()
+        .and_then(|len| len.checked_mul(elem_words))
+        .ok_or(ProofFormatError::Malformed("polynomial length"))?;
+    // The data is the coefficients' words with a 1 word above them, so that zero high
+    // coefficients aren't lost
+    let bytes = dat.as_ne_bytes();
+    let mut words: Vec<u64> = bytes
+        .chunks(8)
+        .map(|chunk| {
+            let mut buf = [0u8; 8];
+            buf[..chunk.len()].copy_from_slice(chunk);
+            u64::from_le_bytes(buf)
+        })
+        .collect();
+    while words.last() == Some(&0) {
+        words.pop();
+    }
+    if words.len() != num_words + 1 || words[num_words] != 1 {
+        return Err(ProofFormatError::Malformed("polynomial data"));
+    }
+    write_u64(out, len);
+    for w in &words[..num_words] {
+        write_u64(out, *w);
+    }
+    Ok(())
+}
+
+fn write_path(out: &mut Vec<u8>, path: Noun, elem_words: usize) -> Result<()> {
+    let [leaf, digests] = path
+        .uncell()
+        .map_err(|_| ProofFormatError::Malformed("merkle path is not a cell"))?;
+    write_poly(out, leaf, elem_words)?;
+    write_digests(out, digests)
+}
+
+fn write_object(out: &mut Vec<u8>, object: Noun) -> Result<()> {
+    let object = object
+        .as_cell()
+        .map_err(|_| ProofFormatError::Malformed("proof object is not a cell"))?;
+    let p = object.tail();
+    let tag = object
+        .head()
+        .as_atom()
+        .and_then(|atom| atom.as_u64())
+        .map_err(|_| ProofFormatError::Malformed("proof object tag"))?;
+    match tag {
+        tas!(b"m-root") => {
+            out.push(TAG_M_ROOT);
+            write_digest(out, p)?;
+        }
+        tas!(b"puzzle") => {
+            let [commitment, nonce, len, subject] = p
+                .uncell()
+                .map_err(|_| ProofFormatError::Malformed("puzzle is not a 4-tuple"))?;
+            out.push(TAG_PUZZLE);
+            write_digest(out, commitment)?;
+            write_digest(out, nonce)?;
+            write_u64(out, word(len, "puzzle length")?);
+            let subject = to_cbor(subject);
+            write_u64(out, subject.len() as u64);
+            out.extend_from_slice(&subject);
+        }
+        tas!(b"codeword") => {
+            out.push(TAG_CODEWORD);
+            write_poly(out, p, FELT_WORDS)?;
+        }
+        tas!(b"terms") => {
+            out.push(TAG_TERMS);
+            write_poly(out, p, 1)?;
+        }
+        tas!(b"m-paths") => {
+            let paths: [Noun; 3] = p
+                .uncell()
+                .map_err(|_| ProofFormatError::Malformed("m-paths is not a 3-tuple"))?;
+            out.push(TAG_M_PATHS);
+            for path in paths {
+                write_path(out, path, FELT_WORDS)?;
+            }
+        }
+        tas!(b"m-path") => {
+            out.push(TAG_M_PATH);
+            write_path(out, p, FELT_WORDS)?;
+        }
+        tas!(b"m-pathbf") => {
+            out.push(TAG_M_PATHBF);
+            write_path(out, p, 1)?;
+        }
+        tas!(b"comp-m") => {
+            let [digest, num] = p
+                .uncell()
+                .map_err(|_| ProofFormatError::Malformed("comp-m is not a cell"))?;
+            out.push(TAG_COMP_M);
+            write_digest(out, digest)?;
+            write_u64(out, word(num, "comp-m count")?);
+        }
+        tas!(b"evals") => {
+            out.push(TAG_EVALS);
+            write_poly(out, p, FELT_WORDS)?;
+        }
+        tas!(b"heights") => {
+            let heights = list(p, "heights")?;
+            out.push(TAG_HEIGHTS);
+            out.extend_from_slice(&count(heights.len())?.to_le_bytes());
+            for height in heights {
+                write_u64(out, word(height, "height")?);
+            }
+        }
+        tas!(b"poly") => {
+            out.push(TAG_POLY);
+            write_poly(out, p, 1)?;
+        }
+        _ => return Err(ProofFormatError::Malformed("unknown proof object")),
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .ok_or(ProofFormatError::Truncated)?;
+        let slice = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or(ProofFormatError::Truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(*array_ref![self.take(2)?, 0, 2]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(*array_ref![self.take(4)?, 0, 4]))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(*array_ref![self.take(8)?, 0, 8]))
+    }
+
+    fn atom<A: NounAllocator>(&mut self, allocator: &mut A) -> Result<Noun> {
+        let value = self.u64()?;
+        Ok(Atom::new(allocator, value).as_noun())
+    }
+
+    fn digest<A: NounAllocator>(&mut self, allocator: &mut A) -> Result<Noun> {
+        let mut belts = [D(0); 5];
+        for belt in belts.iter_mut() {
+            *belt = self.atom(allocator)?;
+        }
+        Ok(T(allocator, &belts))
+    }
+
+    fn digests<A: NounAllocator>(&mut self, allocator: &mut A) -> Result<Noun> {
+        let num = self.u32()?;
+        let mut digests = Vec::new();
+        for _ in 0..num {
+            digests.push(self.digest(allocator)?);
+        }
+        Ok(make_list(allocator, digests))
+    }
+
+    fn poly<A: NounAllocator>(&mut self, allocator: &mut A, elem_words: usize) -> Result<Noun> {
+        let len = self.u64()?;
+        let num_bytes = usize::try_from(len)
+            .ok()
+            .and_then(|len| len.checked_mul(elem_words * 8))
+            .ok_or(ProofFormatError::Truncated)?;
+        let mut dat = self.take(num_bytes)?.to_vec();
+        dat.extend_from_slice(&1u64.to_le_bytes());
+        let dat = unsafe {
+            IndirectAtom::new_raw_bytes(allocator, dat.len(), dat.as_ptr()).normalize_as_atom()
+        };
+        let len = Atom::new(allocator, len).as_noun();
+        Ok(T(allocator, &[len, dat.as_noun()]))
+    }
+
+    fn path<A: NounAllocator>(&mut self, allocator: &mut A, elem_words: usize) -> Result<Noun> {
+        let leaf = self.poly(allocator, elem_words)?;
+        let digests = self.digests(allocator)?;
+        Ok(T(allocator, &[leaf, digests]))
+    }
+
+    fn object<A: NounAllocator>(&mut self, allocator: &mut A) -> Result<Noun> {
+        let tag = self.take(1)?[0];
+        let (name, p) = match tag {
+            TAG_M_ROOT => (tas!(b"m-root"), self.digest(allocator)?),
+            TAG_PUZZLE => {
+                let commitment = self.digest(allocator)?;
+                let nonce = self.digest(allocator)?;
+                let len = self.atom(allocator)?;
+                let subject_len = self.u64()?;
+                let subject_len =
+                    usize::try_from(subject_len).map_err(|_| ProofFormatError::Truncated)?;
+                let subject = from_cbor(allocator, self.take(subject_len)?)?;
+                (
+                    tas!(b"puzzle"),
+                    T(allocator, &[commitment, nonce, len, subject]),
+                )
+            }
+            TAG_CODEWORD => (tas!(b"codeword"), self.poly(allocator, FELT_WORDS)?),
+            TAG_TERMS => (tas!(b"terms"), self.poly(allocator, 1)?),
+            TAG_M_PATHS => {
+                let a = self.path(allocator, FELT_WORDS)?;
+                let b = self.path(allocator, FELT_WORDS)?;
+                let c = self.path(allocator, FELT_WORDS)?;
+                (tas!(b"m-paths"), T(allocator, &[a, b, c]))
+            }
+            TAG_M_PATH => (tas!(b"m-path"), self.path(allocator, FELT_WORDS)?),
+            TAG_M_PATHBF => (tas!(b"m-pathbf"), self.path(allocator, 1)?),
+            TAG_COMP_M => {
+                let digest = self.digest(allocator)?;
+                let num = self.atom(allocator)?;
+                (tas!(b"comp-m"), T(allocator, &[digest, num]))
+            }
+            TAG_EVALS => (tas!(b"evals"), self.poly(allocator, FELT_WORDS)?),
+            TAG_HEIGHTS => {
+                let num = self.u32()?;
+                let mut heights = Vec::new();
+                for _ in 0..num {
+                    heights.push(self.atom(allocator)?);
+                }
+                (tas!(b"heights"), make_list(allocator, heights))
+            }
+            TAG_POLY => (tas!(b"poly"), self.poly(allocator, 1)?),
+            other => return Err(ProofFormatError::UnknownTag(other)),
+        };
+        let name = Atom::new(allocator, name).as_noun();
+        Ok(T(allocator, &[name, p]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nockapp::noun::slab::{slab_noun_equality, NounSlab};
+
+    use super::*;
+
+    fn digest(slab: &mut NounSlab, seed: u64) -> Noun {
+        let belts: Vec<Noun> = (0..5).map(|i| D(seed + i)).collect();
+        T(slab, &belts)
+    }
+
+    fn poly(slab: &mut NounSlab, words: &[u64], elem_words: usize) -> Noun {
+        let mut dat: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        dat.extend_from_slice(&1u64.to_le_bytes());
+        let dat = unsafe {
+            IndirectAtom::new_raw_bytes(slab, dat.len(), dat.as_ptr()).normalize_as_atom()
+        };
+        T(slab, &[D((words.len() / elem_words) as u64), dat.as_noun()])
+    }
+
+    fn tagged(slab: &mut NounSlab, tag: u64, p: Noun) -> Noun {
+        let tag = Atom::new(slab, tag).as_noun();
+        T(slab, &[tag, p])
+    }
+
+    fn sample_proof(slab: &mut NounSlab) -> Noun {
+        let root = digest(slab, 1);
+        let m_root = tagged(slab, tas!(b"m-root"), root);
+
+        let commitment = digest(slab, 10);
+        let nonce = digest(slab, 20);
+        let subject = T(slab, &[D(1), D(2), D(3)]);
+        let puzzle = T(slab, &[commitment, nonce, D(64), subject]);
+        let puzzle = tagged(slab, tas!(b"puzzle"), puzzle);
+
+        let heights = T(slab, &[D(3), D(4), D(0)]);
+        let heights = tagged(slab, tas!(b"heights"), heights);
+
+        // a big coefficient, and a zero high one that only the sentinel word keeps
+        let terms = poly(slab, &[u64::MAX - 1, 7, 0], 1);
+        let terms = tagged(slab, tas!(b"terms"), terms);
+        let evals = poly(slab, &[1, 2, 3, 4, 5, 6], FELT_WORDS);
+        let evals = tagged(slab, tas!(b"evals"), evals);
+        let empty = poly(slab, &[], 1);
+        let empty = tagged(slab, tas!(b"poly"), empty);
+
+        let leaf = poly(slab, &[9, 8, 7], FELT_WORDS);
+        let sib = digest(slab, 30);
+        let sibs = T(slab, &[sib, D(0)]);
+        let path = T(slab, &[leaf, sibs]);
+        let paths = T(slab, &[path, path, path]);
+        let m_paths = tagged(slab, tas!(b"m-paths"), paths);
+        let leaf = poly(slab, &[5], 1);
+        let path = T(slab, &[leaf, D(0)]);
+        let m_pathbf = tagged(slab, tas!(b"m-pathbf"), path);
+
+        let comp_root = digest(slab, 40);
+        let comp = T(slab, &[comp_root, D(2)]);
+        let comp_m = tagged(slab, tas!(b"comp-m"), comp);
+
+        let objects = T(
+            slab,
+            &[m_root, puzzle, heights, terms, evals, empty, m_paths, m_pathbf, comp_m, D(0)],
+        );
+        T(slab, &[D(2), objects, D(0), D(0)])
+    }
+
+    #[test]
+    fn test_proof_roundtrip() {
+        let mut slab: NounSlab = NounSlab::new();
+        let proof = sample_proof(&mut slab);
+        let bytes = encode_proof(proof).expect("encode");
+
+        let header = read_header(&bytes).expect("header");
+        assert_eq!(
+            header,
+            ProofHeader {
+                format_version: PROOF_FORMAT_VERSION,
+                proof_version: 2,
+                num_objects: 9,
+                num_hashes: 0,
+                read_index: 0,
+            }
+        );
+
+        let decoded = decode_proof(&mut slab, &bytes).expect("decode");
+        assert!(slab_noun_equality(&decoded, &proof));
+        // and the encoding is canonical
+        assert_eq!(encode_proof(decoded).expect("encode"), bytes);
+    }
+
+    #[test]
+    fn test_proof_format_errors() {
+        let mut slab: NounSlab = NounSlab::new();
+        let proof = sample_proof(&mut slab);
+        let bytes = encode_proof(proof).expect("encode");
+
+        let mut bad = bytes.clone();
+        bad[0] = b'X';
+        assert!(matches!(read_header(&bad), Err(ProofFormatError::BadMagic)));
+
+        let mut bad = bytes.clone();
+        bad[4] = 2;
+        assert!(matches!(
+            decode_proof(&mut slab, &bad),
+            Err(ProofFormatError::UnsupportedFormat(2))
+        ));
+
+        assert!(matches!(
+            decode_proof(&mut slab, &bytes[..bytes.len() - 1]),
+            Err(ProofFormatError::Truncated)
+        ));
+
+        let mut bad = bytes.clone();
+        bad.push(0);
+        assert!(matches!(
+            decode_proof(&mut slab, &bad),
+            Err(ProofFormatError::TrailingBytes)
+        ));
+
+        let mut bad = bytes.clone();
+        bad[HEADER_LEN] = 200;
+        assert!(matches!(
+            decode_proof(&mut slab, &bad),
+            Err(ProofFormatError::UnknownTag(200))
+        ));
+
+        let unversioned = T(&mut slab, &[D(7), D(0), D(0), D(0)]);
+        assert!(matches!(
+            encode_proof(unversioned),
+            Err(ProofFormatError::UnsupportedVersion(7))
+        ));
+    }
+}
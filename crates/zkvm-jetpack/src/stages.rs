@@ -0,0 +1,96 @@
+//! Time spent in each stage of proving.
+//!
+//! The jets doing the bulk of each stage add how long they took to a running total, across every
+//! thread proving, so a benchmark can tell where a proof's time goes. What's left of a proof's
+//! time is spent in the interpreter and smaller jets, mostly running the puzzle and FRI.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Building the memory table's tree data from the puzzle's trace
+    Trace,
+    /// Extending the tables with the verifier's challenges
+    Extension,
+    /// Low degree extension of every column
+    Lde,
+    /// Merkle commitments to the extended columns
+    Merkle,
+    /// Evaluating the constraints into the composition polynomial
+    Constraints,
+    /// The DEEP polynomial, before FRI
+    Deep,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::Trace,
+        Stage::Extension,
+        Stage::Lde,
+        Stage::Merkle,
+        Stage::Constraints,
+        Stage::Deep,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Trace => "trace",
+            Stage::Extension => "extension",
+            Stage::Lde => "lde",
+            Stage::Merkle => "merkle",
+            Stage::Constraints => "constraints",
+            Stage::Deep => "deep",
+        }
+    }
+}
+
+/// Nanoseconds spent in each of [`Stage::ALL`]
+static TOTALS: [AtomicU64; 6] = [const { AtomicU64::new(0) }; 6];
+
+/// Adds the time until it's dropped to its stage
+pub struct Timer {
+    stage: Stage,
+    start: Instant,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let nanos = self.start.elapsed().as_nanos() as u64;
+        TOTALS[self.stage as usize].fetch_add(nanos, Ordering::Relaxed);
+    }
+}
+
+/// Time the rest of the caller's scope as part of `stage`
+pub fn time(stage: Stage) -> Timer {
+    Timer {
+        stage,
+        start: Instant::now(),
+    }
+}
+
+/// Total time spent in each stage so far
+pub fn totals() -> Vec<(Stage, Duration)> {
+    Stage::ALL
+        .iter()
+        .map(|&stage| {
+            let nanos = TOTALS[stage as usize].load(Ordering::Relaxed);
+            (stage, Duration::from_nanos(nanos))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time() {
+        let before = totals()[Stage::Deep as usize].1;
+        {
+            let _timer = time(Stage::Deep);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let after = totals()[Stage::Deep as usize].1;
+        assert!(after - before >= Duration::from_millis(5));
+    }
+}